@@ -16,6 +16,11 @@ pub const IAPC_BOOT_ARG_FLAGS_MSI_NOT_PRESENT: u16 = 3;
 #[cfg(target_arch = "x86_64")]
 pub const IAPC_BOOT_ARG_FLAGS_PCI_ASPM: u16 = 4;
 
+#[cfg(target_arch = "aarch64")]
+pub const ARM_BOOT_ARCH_FLAGS_PSCI_COMPLIANT: u16 = 0;
+#[cfg(target_arch = "aarch64")]
+pub const ARM_BOOT_ARCH_FLAGS_PSCI_USE_HVC: u16 = 1;
+
 // ACPI Flags. Reading from the specification here:
 // https://uefi.org/specs/ACPI/6.5/05_ACPI_Software_Programming_Model.html#fixed-acpi-description-table-fixed-feature-flags
 
@@ -142,6 +147,11 @@ impl Fadt {
         self.iapc_boot_arch = U16::new(flags);
     }
 
+    /// Set the ARM specific flags
+    pub fn setup_arm_flags(&mut self, flags: u16) {
+        self.arm_boot_arch = U16::new(flags);
+    }
+
     /// Set the hypervisor vendor ID
     pub fn set_hypervisor_vendor_id(&mut self, hypervisor_vendor_id: [u8; 8]) {
         self.hypervisor_vendor_id = hypervisor_vendor_id;
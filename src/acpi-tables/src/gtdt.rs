@@ -0,0 +1,106 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use vm_memory::{Bytes, GuestAddress, GuestMemory};
+use zerocopy::little_endian::{U32, U64};
+use zerocopy::{Immutable, IntoBytes};
+
+use crate::{checksum, Result, Sdt, SdtHeader};
+
+/// Flag for timers whose interrupt is edge triggered, rather than level triggered.
+pub const GTDT_F_EDGE_TRIGGERED: u32 = 0;
+/// Flag for timers whose interrupt is active low, rather than active high.
+pub const GTDT_F_ACTIVE_LOW: u32 = 1;
+/// Flag for timers which keep running in all power states.
+pub const GTDT_F_ALWAYS_ON: u32 = 2;
+
+/// Description of the interrupt of a generic timer.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GtdtTimer {
+    /// GSIV of the timer interrupt
+    pub gsiv: u32,
+    /// Timer flags
+    pub flags: u32,
+}
+
+// clippy doesn't understand that we actually "use" the fields of this struct when we serialize
+// them as bytes in guest memory, so here we just ignore dead code to avoid having to name
+// everything with an underscore prefix
+#[allow(dead_code)]
+/// Generic Timer Description Table (GTDT)
+///
+/// This table describes the interrupts of the per-processor ARM generic timers. We don't expose
+/// memory-mapped platform timers, nor a memory-mapped counter.
+/// More information about this table can be found in the ACPI specification:
+/// https://uefi.org/specs/ACPI/6.5/05_ACPI_Software_Programming_Model.html#generic-timer-description-table-gtdt
+#[repr(packed)]
+#[derive(Debug, Copy, Clone, Default, IntoBytes, Immutable)]
+pub struct Gtdt {
+    header: SdtHeader,
+    cnt_control_base: U64,
+    reserved: U32,
+    secure_el1_timer_gsiv: U32,
+    secure_el1_timer_flags: U32,
+    non_secure_el1_timer_gsiv: U32,
+    non_secure_el1_timer_flags: U32,
+    virtual_el1_timer_gsiv: U32,
+    virtual_el1_timer_flags: U32,
+    el2_timer_gsiv: U32,
+    el2_timer_flags: U32,
+    cnt_read_base: U64,
+    platform_timer_count: U32,
+    platform_timer_offset: U32,
+    virtual_el2_timer_gsiv: U32,
+    virtual_el2_timer_flags: U32,
+}
+
+impl Gtdt {
+    pub fn new(
+        oem_id: [u8; 6],
+        oem_table_id: [u8; 8],
+        oem_revision: u32,
+        secure_el1_timer: GtdtTimer,
+        non_secure_el1_timer: GtdtTimer,
+        virtual_el1_timer: GtdtTimer,
+        el2_timer: GtdtTimer,
+    ) -> Self {
+        let header = SdtHeader::new(
+            *b"GTDT",
+            // It's fine to unwrap here, we know that the size of the Gtdt structure fits in 32
+            // bits.
+            std::mem::size_of::<Self>().try_into().unwrap(),
+            3, // revision 3
+            oem_id,
+            oem_table_id,
+            oem_revision,
+        );
+
+        Gtdt {
+            header,
+            // There is no memory-mapped counter, so these are set to all ones.
+            cnt_control_base: U64::new(u64::MAX),
+            secure_el1_timer_gsiv: U32::new(secure_el1_timer.gsiv),
+            secure_el1_timer_flags: U32::new(secure_el1_timer.flags),
+            non_secure_el1_timer_gsiv: U32::new(non_secure_el1_timer.gsiv),
+            non_secure_el1_timer_flags: U32::new(non_secure_el1_timer.flags),
+            virtual_el1_timer_gsiv: U32::new(virtual_el1_timer.gsiv),
+            virtual_el1_timer_flags: U32::new(virtual_el1_timer.flags),
+            el2_timer_gsiv: U32::new(el2_timer.gsiv),
+            el2_timer_flags: U32::new(el2_timer.flags),
+            cnt_read_base: U64::new(u64::MAX),
+            ..Default::default()
+        }
+    }
+}
+
+impl Sdt for Gtdt {
+    fn len(&self) -> usize {
+        self.header.length.get().try_into().unwrap()
+    }
+
+    fn write_to_guest<M: GuestMemory>(&mut self, mem: &M, address: GuestAddress) -> Result<()> {
+        self.header.checksum = checksum(&[self.as_bytes()]);
+        mem.write_slice(self.as_bytes(), address)?;
+        Ok(())
+    }
+}
@@ -9,15 +9,25 @@ use vm_memory::{GuestAddress, GuestMemory, GuestMemoryError};
 pub mod aml;
 pub mod dsdt;
 pub mod fadt;
+pub mod gtdt;
 pub mod madt;
+pub mod pptt;
 pub mod rsdp;
+pub mod spcr;
+pub mod srat;
+pub mod tpm2;
 pub mod xsdt;
 
 pub use aml::Aml;
 pub use dsdt::Dsdt;
 pub use fadt::Fadt;
+pub use gtdt::Gtdt;
 pub use madt::Madt;
+pub use pptt::Pptt;
 pub use rsdp::Rsdp;
+pub use spcr::Spcr;
+pub use srat::Srat;
+pub use tpm2::Tpm2;
 pub use xsdt::Xsdt;
 use zerocopy::little_endian::{U32, U64};
 use zerocopy::{Immutable, IntoBytes};
@@ -6,7 +6,7 @@
 use std::mem::size_of;
 
 use vm_memory::{Address, Bytes, GuestAddress, GuestMemory};
-use zerocopy::little_endian::U32;
+use zerocopy::little_endian::{U16, U32, U64};
 use zerocopy::{Immutable, IntoBytes};
 
 use crate::{checksum, AcpiError, Result, Sdt, SdtHeader};
@@ -67,6 +67,143 @@ impl IoAPIC {
     }
 }
 
+// clippy doesn't understand that we actually "use" the fields of this struct when we serialize
+// them as bytes in guest memory, so here we just ignore dead code to avoid having to name
+// everything with an underscore prefix
+#[allow(dead_code)]
+#[repr(packed)]
+#[derive(Copy, Clone, Debug, Default, IntoBytes, Immutable)]
+pub struct Gicc {
+    r#type: u8,
+    length: u8,
+    reserved: U16,
+    cpu_interface_number: U32,
+    acpi_processor_uid: U32,
+    flags: U32,
+    parking_protocol_version: U32,
+    performance_interrupt_gsiv: U32,
+    parked_address: U64,
+    physical_base_address: U64,
+    gicv: U64,
+    gich: U64,
+    vgic_maintenance_interrupt: U32,
+    gicr_base_address: U64,
+    mpidr: U64,
+    processor_power_efficiency_class: u8,
+    reserved_1: u8,
+    spe_overflow_interrupt: U16,
+    trbe_interrupt: U16,
+}
+
+impl Gicc {
+    /// Creates the GIC CPU interface structure of a vCPU.
+    ///
+    /// The redistributor of a GICv3 is described by a separate [`Gicr`] structure, so
+    /// `physical_base_address` is only set for a GICv2 CPU interface.
+    pub fn new(
+        cpu_id: u32,
+        mpidr: u64,
+        physical_base_address: u64,
+        vgic_maintenance_interrupt: u32,
+        performance_interrupt_gsiv: u32,
+    ) -> Self {
+        Self {
+            r#type: 0xb,
+            length: 82,
+            cpu_interface_number: U32::new(cpu_id),
+            acpi_processor_uid: U32::new(cpu_id),
+            flags: U32::new(1u32 << MADT_CPU_ENABLE_FLAG),
+            performance_interrupt_gsiv: U32::new(performance_interrupt_gsiv),
+            physical_base_address: U64::new(physical_base_address),
+            vgic_maintenance_interrupt: U32::new(vgic_maintenance_interrupt),
+            mpidr: U64::new(mpidr),
+            ..Default::default()
+        }
+    }
+}
+
+// clippy doesn't understand that we actually "use" the fields of this struct when we serialize
+// them as bytes in guest memory, so here we just ignore dead code to avoid having to name
+// everything with an underscore prefix
+#[allow(dead_code)]
+#[repr(packed)]
+#[derive(Copy, Clone, Debug, Default, IntoBytes, Immutable)]
+pub struct Gicd {
+    r#type: u8,
+    length: u8,
+    reserved: U16,
+    gic_id: U32,
+    physical_base_address: U64,
+    system_vector_base: U32,
+    gic_version: u8,
+    reserved_1: [u8; 3],
+}
+
+impl Gicd {
+    pub fn new(physical_base_address: u64, gic_version: u8) -> Self {
+        Self {
+            r#type: 0xc,
+            length: 24,
+            physical_base_address: U64::new(physical_base_address),
+            gic_version,
+            ..Default::default()
+        }
+    }
+}
+
+// clippy doesn't understand that we actually "use" the fields of this struct when we serialize
+// them as bytes in guest memory, so here we just ignore dead code to avoid having to name
+// everything with an underscore prefix
+#[allow(dead_code)]
+#[repr(packed)]
+#[derive(Copy, Clone, Debug, Default, IntoBytes, Immutable)]
+pub struct Gicr {
+    r#type: u8,
+    length: u8,
+    reserved: U16,
+    discovery_range_base_address: U64,
+    discovery_range_length: U32,
+}
+
+impl Gicr {
+    pub fn new(discovery_range_base_address: u64, discovery_range_length: u32) -> Self {
+        Self {
+            r#type: 0xe,
+            length: 16,
+            reserved: U16::ZERO,
+            discovery_range_base_address: U64::new(discovery_range_base_address),
+            discovery_range_length: U32::new(discovery_range_length),
+        }
+    }
+}
+
+// clippy doesn't understand that we actually "use" the fields of this struct when we serialize
+// them as bytes in guest memory, so here we just ignore dead code to avoid having to name
+// everything with an underscore prefix
+#[allow(dead_code)]
+#[repr(packed)]
+#[derive(Copy, Clone, Debug, Default, IntoBytes, Immutable)]
+pub struct GicIts {
+    r#type: u8,
+    length: u8,
+    reserved: U16,
+    its_id: U32,
+    physical_base_address: U64,
+    reserved_1: U32,
+}
+
+impl GicIts {
+    pub fn new(its_id: u32, physical_base_address: u64) -> Self {
+        Self {
+            r#type: 0xf,
+            length: 20,
+            its_id: U32::new(its_id),
+            physical_base_address: U64::new(physical_base_address),
+            ..Default::default()
+        }
+    }
+}
+
 // clippy doesn't understand that we actually "use" the fields of this struct when we serialize
 // them as bytes in guest memory, so here we just ignore dead code to avoid having to name
 // everything with an underscore prefix
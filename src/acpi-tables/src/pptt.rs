@@ -0,0 +1,96 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::mem::size_of;
+
+use vm_memory::{Address, Bytes, GuestAddress, GuestMemory};
+use zerocopy::little_endian::{U16, U32};
+use zerocopy::{Immutable, IntoBytes};
+
+use crate::{checksum, AcpiError, Result, Sdt, SdtHeader};
+
+/// Flag for nodes representing the boundary of a physical package.
+pub const PPTT_F_PHYSICAL_PACKAGE: u32 = 0;
+/// Flag for nodes whose ACPI processor ID matches the UID of a processor in the MADT.
+pub const PPTT_F_ACPI_PROCESSOR_ID_VALID: u32 = 1;
+/// Flag for leaf nodes, i.e. nodes representing processors.
+pub const PPTT_F_LEAF: u32 = 3;
+
+/// Offset of the first node in the PPTT, to be used when computing the offset of parent nodes.
+pub const PPTT_NODES_OFFSET: usize = size_of::<SdtHeader>();
+
+// clippy doesn't understand that we actually "use" the fields of this struct when we serialize
+// them as bytes in guest memory, so here we just ignore dead code to avoid having to name
+// everything with an underscore prefix
+#[allow(dead_code)]
+#[repr(packed)]
+#[derive(Copy, Clone, Debug, Default, IntoBytes, Immutable)]
+pub struct ProcessorHierarchyNode {
+    r#type: u8,
+    length: u8,
+    reserved: U16,
+    flags: U32,
+    parent: U32,
+    acpi_processor_id: U32,
+    number_of_private_resources: U32,
+}
+
+impl ProcessorHierarchyNode {
+    /// Creates a node of the processor hierarchy, without private resources.
+    ///
+    /// `parent` is the offset of the parent node from the start of the table, or 0 for the root.
+    pub fn new(flags: u32, parent: u32, acpi_processor_id: u32) -> Self {
+        Self {
+            r#type: 0,
+            length: 20,
+            flags: U32::new(flags),
+            parent: U32::new(parent),
+            acpi_processor_id: U32::new(acpi_processor_id),
+            ..Default::default()
+        }
+    }
+}
+
+/// Processor Properties Topology Table (PPTT)
+///
+/// This table describes the topology of the processors of the platform.
+/// More information about this table can be found in the ACPI specification:
+/// https://uefi.org/specs/ACPI/6.5/05_ACPI_Software_Programming_Model.html#processor-properties-topology-table-pptt
+#[derive(Debug)]
+pub struct Pptt {
+    header: SdtHeader,
+    nodes: Vec<u8>,
+}
+
+impl Pptt {
+    pub fn new(oem_id: [u8; 6], oem_table_id: [u8; 8], oem_revision: u32, nodes: Vec<u8>) -> Self {
+        let mut header = SdtHeader::new(
+            *b"PPTT",
+            (PPTT_NODES_OFFSET + nodes.len()).try_into().unwrap(),
+            2,
+            oem_id,
+            oem_table_id,
+            oem_revision,
+        );
+
+        header.checksum = checksum(&[header.as_bytes(), nodes.as_bytes()]);
+
+        Pptt { header, nodes }
+    }
+}
+
+impl Sdt for Pptt {
+    fn len(&self) -> usize {
+        self.header.length.get().try_into().unwrap()
+    }
+
+    fn write_to_guest<M: GuestMemory>(&mut self, mem: &M, address: GuestAddress) -> Result<()> {
+        mem.write_slice(self.header.as_bytes(), address)?;
+        let address = address
+            .checked_add(PPTT_NODES_OFFSET as u64)
+            .ok_or(AcpiError::InvalidGuestAddress)?;
+        mem.write_slice(self.nodes.as_slice(), address)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,101 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use vm_memory::{Bytes, GuestAddress, GuestMemory};
+use zerocopy::little_endian::{U16, U32};
+use zerocopy::{Immutable, IntoBytes};
+
+use crate::{checksum, GenericAddressStructure, Result, Sdt, SdtHeader};
+
+/// Interface type of a full 16550 compatible UART.
+pub const SPCR_INTERFACE_TYPE_16550: u8 = 0;
+/// Interrupt type of an interrupt routed through the ARM GIC.
+pub const SPCR_INTERRUPT_TYPE_ARM_GIC: u8 = 1 << 3;
+
+// clippy doesn't understand that we actually "use" the fields of this struct when we serialize
+// them as bytes in guest memory, so here we just ignore dead code to avoid having to name
+// everything with an underscore prefix
+#[allow(dead_code)]
+/// Serial Port Console Redirection Table (SPCR)
+///
+/// This table describes the serial port the guest should use as its console.
+/// More information about this table can be found in the Microsoft specification:
+/// https://learn.microsoft.com/en-us/windows-hardware/drivers/serports/serial-port-console-redirection-table
+#[repr(packed)]
+#[derive(Debug, Copy, Clone, Default, IntoBytes, Immutable)]
+pub struct Spcr {
+    header: SdtHeader,
+    interface_type: u8,
+    reserved: [u8; 3],
+    base_address: GenericAddressStructure,
+    interrupt_type: u8,
+    irq: u8,
+    global_system_interrupt: U32,
+    baud_rate: u8,
+    parity: u8,
+    stop_bits: u8,
+    flow_control: u8,
+    terminal_type: u8,
+    language: u8,
+    pci_device_id: U16,
+    pci_vendor_id: U16,
+    pci_bus_number: u8,
+    pci_device_number: u8,
+    pci_function_number: u8,
+    pci_flags: U32,
+    pci_segment: u8,
+    reserved_1: U32,
+}
+
+impl Spcr {
+    pub fn new(
+        oem_id: [u8; 6],
+        oem_table_id: [u8; 8],
+        oem_revision: u32,
+        interface_type: u8,
+        base_address: GenericAddressStructure,
+        interrupt_type: u8,
+        global_system_interrupt: u32,
+    ) -> Self {
+        let header = SdtHeader::new(
+            *b"SPCR",
+            // It's fine to unwrap here, we know that the size of the Spcr structure fits in 32
+            // bits.
+            std::mem::size_of::<Self>().try_into().unwrap(),
+            2, // revision 2
+            oem_id,
+            oem_table_id,
+            oem_revision,
+        );
+
+        Spcr {
+            header,
+            interface_type,
+            base_address,
+            interrupt_type,
+            global_system_interrupt: U32::new(global_system_interrupt),
+            // The baud rate is left as is, since the UART is emulated.
+            baud_rate: 0,
+            // One stop bit.
+            stop_bits: 1,
+            // ANSI terminal.
+            terminal_type: 3,
+            // Not a PCI device.
+            pci_device_id: U16::new(0xffff),
+            pci_vendor_id: U16::new(0xffff),
+            ..Default::default()
+        }
+    }
+}
+
+impl Sdt for Spcr {
+    fn len(&self) -> usize {
+        self.header.length.get().try_into().unwrap()
+    }
+
+    fn write_to_guest<M: GuestMemory>(&mut self, mem: &M, address: GuestAddress) -> Result<()> {
+        self.header.checksum = checksum(&[self.as_bytes()]);
+        mem.write_slice(self.as_bytes(), address)?;
+        Ok(())
+    }
+}
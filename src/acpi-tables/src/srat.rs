@@ -0,0 +1,182 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::mem::size_of;
+
+use vm_memory::{Address, Bytes, GuestAddress, GuestMemory};
+use zerocopy::little_endian::{U16, U32, U64};
+use zerocopy::{Immutable, IntoBytes};
+
+use crate::{checksum, AcpiError, Result, Sdt, SdtHeader};
+
+/// Flag for enabled affinity structures.
+pub const SRAT_F_ENABLED: u32 = 0;
+/// Flag for non-volatile memory ranges.
+pub const SRAT_F_NON_VOLATILE: u32 = 2;
+
+// clippy doesn't understand that we actually "use" the fields of this struct when we serialize
+// them as bytes in guest memory, so here we just ignore dead code to avoid having to name
+// everything with an underscore prefix
+#[allow(dead_code)]
+#[repr(packed)]
+#[derive(Copy, Clone, Debug, Default, IntoBytes, Immutable)]
+pub struct LocalApicAffinity {
+    r#type: u8,
+    length: u8,
+    proximity_domain_low: u8,
+    apic_id: u8,
+    flags: U32,
+    local_sapic_eid: u8,
+    proximity_domain_high: [u8; 3],
+    clock_domain: U32,
+}
+
+impl LocalApicAffinity {
+    /// Associates the local APIC `apic_id` with the `proximity_domain`.
+    pub fn new(apic_id: u8, proximity_domain: u32) -> Self {
+        let domain = proximity_domain.to_le_bytes();
+        Self {
+            r#type: 0,
+            length: 16,
+            proximity_domain_low: domain[0],
+            apic_id,
+            flags: U32::new(1 << SRAT_F_ENABLED),
+            proximity_domain_high: [domain[1], domain[2], domain[3]],
+            ..Default::default()
+        }
+    }
+}
+
+// clippy doesn't understand that we actually "use" the fields of this struct when we serialize
+// them as bytes in guest memory, so here we just ignore dead code to avoid having to name
+// everything with an underscore prefix
+#[allow(dead_code)]
+#[repr(packed)]
+#[derive(Copy, Clone, Debug, Default, IntoBytes, Immutable)]
+pub struct MemoryAffinity {
+    r#type: u8,
+    length: u8,
+    proximity_domain: U32,
+    reserved: U16,
+    base_address: U64,
+    range_length: U64,
+    reserved_1: U32,
+    flags: U32,
+    reserved_2: U64,
+}
+
+impl MemoryAffinity {
+    /// Associates the memory range [`base_address`, `base_address + range_length`) with the
+    /// `proximity_domain`.
+    pub fn new(proximity_domain: u32, base_address: u64, range_length: u64, flags: u32) -> Self {
+        Self {
+            r#type: 1,
+            length: 40,
+            proximity_domain: U32::new(proximity_domain),
+            base_address: U64::new(base_address),
+            range_length: U64::new(range_length),
+            flags: U32::new(flags | 1 << SRAT_F_ENABLED),
+            ..Default::default()
+        }
+    }
+}
+
+// clippy doesn't understand that we actually "use" the fields of this struct when we serialize
+// them as bytes in guest memory, so here we just ignore dead code to avoid having to name
+// everything with an underscore prefix
+#[allow(dead_code)]
+#[repr(packed)]
+#[derive(Copy, Clone, Debug, Default, IntoBytes, Immutable)]
+pub struct GiccAffinity {
+    r#type: u8,
+    length: u8,
+    proximity_domain: U32,
+    acpi_processor_uid: U32,
+    flags: U32,
+    clock_domain: U32,
+}
+
+impl GiccAffinity {
+    /// Associates the processor `acpi_processor_uid`, as described by a GICC structure of the
+    /// MADT, with the `proximity_domain`.
+    pub fn new(acpi_processor_uid: u32, proximity_domain: u32) -> Self {
+        Self {
+            r#type: 3,
+            length: 18,
+            proximity_domain: U32::new(proximity_domain),
+            acpi_processor_uid: U32::new(acpi_processor_uid),
+            flags: U32::new(1 << SRAT_F_ENABLED),
+            clock_domain: U32::ZERO,
+        }
+    }
+}
+
+// clippy doesn't understand that we actually "use" the fields of this struct when we serialize
+// them as bytes in guest memory, so here we just ignore dead code to avoid having to name
+// everything with an underscore prefix
+#[allow(dead_code)]
+#[repr(packed)]
+#[derive(Debug, IntoBytes, Immutable)]
+struct SratHeader {
+    sdt: SdtHeader,
+    reserved: U32,
+    reserved_1: U64,
+}
+
+/// System Resource Affinity Table (SRAT)
+///
+/// This table associates the processors and memory ranges of the platform with proximity
+/// domains, i.e. NUMA nodes.
+/// More information about this table can be found in the ACPI specification:
+/// https://uefi.org/specs/ACPI/6.5/05_ACPI_Software_Programming_Model.html#system-resource-affinity-table-srat
+#[derive(Debug)]
+pub struct Srat {
+    header: SratHeader,
+    affinities: Vec<u8>,
+}
+
+impl Srat {
+    pub fn new(
+        oem_id: [u8; 6],
+        oem_table_id: [u8; 8],
+        oem_revision: u32,
+        affinities: Vec<u8>,
+    ) -> Self {
+        let length = size_of::<SratHeader>() + affinities.len();
+        let sdt_header = SdtHeader::new(
+            *b"SRAT",
+            length.try_into().unwrap(),
+            3,
+            oem_id,
+            oem_table_id,
+            oem_revision,
+        );
+
+        let mut header = SratHeader {
+            sdt: sdt_header,
+            // Reserved, must be 1 for backward compatibility.
+            reserved: U32::new(1),
+            reserved_1: U64::ZERO,
+        };
+
+        header.sdt.checksum = checksum(&[header.as_bytes(), affinities.as_bytes()]);
+
+        Srat { header, affinities }
+    }
+}
+
+impl Sdt for Srat {
+    fn len(&self) -> usize {
+        self.header.sdt.length.get().try_into().unwrap()
+    }
+
+    fn write_to_guest<M: GuestMemory>(&mut self, mem: &M, address: GuestAddress) -> Result<()> {
+        mem.write_slice(self.header.as_bytes(), address)?;
+        let address = address
+            .checked_add(size_of::<SratHeader>() as u64)
+            .ok_or(AcpiError::InvalidGuestAddress)?;
+        mem.write_slice(self.affinities.as_bytes(), address)?;
+
+        Ok(())
+    }
+}
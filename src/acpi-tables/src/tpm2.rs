@@ -0,0 +1,73 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use vm_memory::{Bytes, GuestAddress, GuestMemory};
+use zerocopy::little_endian::{U16, U32, U64};
+use zerocopy::{Immutable, IntoBytes};
+
+use crate::{checksum, Result, Sdt, SdtHeader};
+
+/// Start method of a TPM using the Command Response Buffer interface.
+pub const TPM2_START_METHOD_CRB: u32 = 7;
+
+// clippy doesn't understand that we actually "use" the fields of this struct when we serialize
+// them as bytes in guest memory, so here we just ignore dead code to avoid having to name
+// everything with an underscore prefix
+#[allow(dead_code)]
+/// Trusted Platform Module 2 Table (TPM2)
+///
+/// This table describes the interface of the TPM 2.0 device of the guest.
+/// More information about this table can be found in the TCG ACPI specification:
+/// https://trustedcomputinggroup.org/resource/tcg-acpi-specification/
+#[repr(packed)]
+#[derive(Debug, Copy, Clone, Default, IntoBytes, Immutable)]
+pub struct Tpm2 {
+    header: SdtHeader,
+    platform_class: U16,
+    reserved: U16,
+    control_area_address: U64,
+    start_method: U32,
+    start_method_parameters: [u8; 12],
+}
+
+impl Tpm2 {
+    pub fn new(
+        oem_id: [u8; 6],
+        oem_table_id: [u8; 8],
+        oem_revision: u32,
+        control_area_address: u64,
+        start_method: u32,
+    ) -> Self {
+        let header = SdtHeader::new(
+            *b"TPM2",
+            // It's fine to unwrap here, we know that the size of the Tpm2 structure fits in 32
+            // bits.
+            std::mem::size_of::<Self>().try_into().unwrap(),
+            4, // revision 4
+            oem_id,
+            oem_table_id,
+            oem_revision,
+        );
+
+        Tpm2 {
+            header,
+            // Client platform.
+            platform_class: U16::new(0),
+            control_area_address: U64::new(control_area_address),
+            start_method: U32::new(start_method),
+            ..Default::default()
+        }
+    }
+}
+
+impl Sdt for Tpm2 {
+    fn len(&self) -> usize {
+        self.header.length.get().try_into().unwrap()
+    }
+
+    fn write_to_guest<M: GuestMemory>(&mut self, mem: &M, address: GuestAddress) -> Result<()> {
+        self.header.checksum = checksum(&[self.as_bytes()]);
+        mem.write_slice(self.as_bytes(), address)?;
+        Ok(())
+    }
+}
@@ -82,6 +82,24 @@ enum TemplateOperation {
         #[arg(short, long, value_name = "PATH")]
         template: Option<PathBuf>,
     },
+    /// Annotate the given CPU template with the names of the features it modifies and its
+    /// provenance.
+    Annotate {
+        /// Path of the CPU template to annotate.
+        #[arg(short, long, value_name = "PATH")]
+        template: PathBuf,
+        /// Provenance of the CPU template, e.g. who created it and from which host.
+        #[arg(short, long)]
+        provenance: Option<String>,
+        /// Path of output file.
+        #[arg(
+            short,
+            long,
+            value_name = "PATH",
+            default_value = "annotated_template.json"
+        )]
+        output: PathBuf,
+    },
 }
 
 #[derive(Debug, Subcommand)]
@@ -169,6 +187,18 @@ fn run(cli: Cli) -> Result<(), HelperError> {
 
                 template::verify::verify(cpu_template, cpu_config)?;
             }
+            TemplateOperation::Annotate {
+                template,
+                provenance,
+                output,
+            } => {
+                let template = utils::load_cpu_template(&template)?;
+
+                let annotations = template::annotate::annotate(&template, provenance);
+
+                let template_json = template::annotate::to_annotated_json(&template, &annotations)?;
+                write(output, template_json)?;
+            }
         },
         Command::Fingerprint(op) => match op {
             FingerprintOperation::Dump {
@@ -342,6 +372,31 @@ mod tests {
         run(cli).unwrap();
     }
 
+    #[test]
+    fn test_template_annotate_command() {
+        let template_file = generate_sample_template();
+        let output_file = TempFile::new().unwrap();
+        let args = vec![
+            "cpu-template-helper",
+            "template",
+            "annotate",
+            "--template",
+            template_file.as_path().to_str().unwrap(),
+            "--provenance",
+            "Sample template",
+            "--output",
+            output_file.as_path().to_str().unwrap(),
+        ];
+        let cli = Cli::parse_from(args);
+
+        run(cli).unwrap();
+
+        // The annotated template can be loaded again.
+        let annotated_json = read_to_string(output_file.as_path()).unwrap();
+        assert!(annotated_json.contains("Sample template"));
+        utils::load_cpu_template(&output_file.as_path().to_path_buf()).unwrap();
+    }
+
     #[test]
     fn test_fingerprint_dump_command() {
         let output_file = TempFile::new().unwrap();
@@ -0,0 +1,132 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use vmm::arch::aarch64::regs::{
+    ID_AA64ISAR0_EL1, ID_AA64ISAR1_EL1, ID_AA64MMFR2_EL1, ID_AA64PFR0_EL1,
+};
+
+// Names of the 4-bit feature fields of the ID registers, annotated on the lowest bit of each
+// field, as in the Arm Architecture Registers documentation:
+// https://developer.arm.com/documentation/ddi0595/2021-12/AArch64-Registers?lang=en
+
+const ID_AA64PFR0_EL1_FIELDS: &[(u8, &str)] = &[
+    (0, "el0"),
+    (4, "el1"),
+    (8, "el2"),
+    (12, "el3"),
+    (16, "fp"),
+    (20, "advsimd"),
+    (24, "gic"),
+    (28, "ras"),
+    (32, "sve"),
+    (36, "sel2"),
+    (40, "mpam"),
+    (44, "amu"),
+    (48, "dit"),
+    (52, "rme"),
+    (56, "csv2"),
+    (60, "csv3"),
+];
+
+const ID_AA64ISAR0_EL1_FIELDS: &[(u8, &str)] = &[
+    (4, "aes"),
+    (8, "sha1"),
+    (12, "sha2"),
+    (16, "crc32"),
+    (20, "atomic"),
+    (24, "tme"),
+    (28, "rdm"),
+    (32, "sha3"),
+    (36, "sm3"),
+    (40, "sm4"),
+    (44, "dp"),
+    (48, "fhm"),
+    (52, "ts"),
+    (56, "tlb"),
+    (60, "rndr"),
+];
+
+const ID_AA64ISAR1_EL1_FIELDS: &[(u8, &str)] = &[
+    (0, "dpb"),
+    (4, "apa"),
+    (8, "api"),
+    (12, "jscvt"),
+    (16, "fcma"),
+    (20, "lrcpc"),
+    (24, "gpa"),
+    (28, "gpi"),
+    (32, "frintts"),
+    (36, "sb"),
+    (40, "specres"),
+    (44, "bf16"),
+    (48, "dgh"),
+    (52, "i8mm"),
+    (56, "xs"),
+    (60, "ls64"),
+];
+
+const ID_AA64MMFR2_EL1_FIELDS: &[(u8, &str)] = &[
+    (0, "cnp"),
+    (4, "uao"),
+    (8, "lsm"),
+    (12, "iesb"),
+    (16, "varange"),
+    (20, "ccidx"),
+    (24, "nv"),
+    (28, "st"),
+    (32, "at"),
+    (36, "ids"),
+    (40, "fwb"),
+    (48, "ttl"),
+    (52, "bbm"),
+    (56, "evt"),
+    (60, "e0pd"),
+];
+
+/// Get the names of the known features controlled by the bits of a register, identified as in
+/// the template annotations.
+pub fn feature_names(register: &str) -> &'static [(u8, &'static str)] {
+    let fields = [
+        (ID_AA64PFR0_EL1, ID_AA64PFR0_EL1_FIELDS),
+        (ID_AA64ISAR0_EL1, ID_AA64ISAR0_EL1_FIELDS),
+        (ID_AA64ISAR1_EL1, ID_AA64ISAR1_EL1_FIELDS),
+        (ID_AA64MMFR2_EL1, ID_AA64MMFR2_EL1_FIELDS),
+    ];
+    fields
+        .into_iter()
+        .find(|(id, _)| register == format!("reg:{id:#x}"))
+        .map(|(_, fields)| fields)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use vmm::cpu_config::aarch64::custom_cpu_template::RegisterModifier;
+    use vmm::cpu_config::templates::{CustomCpuTemplate, RegisterValueFilter};
+
+    use super::*;
+    use crate::template::annotate::annotate;
+
+    #[test]
+    fn test_annotate_id_registers() {
+        let template = CustomCpuTemplate {
+            reg_modifiers: vec![RegisterModifier {
+                addr: ID_AA64PFR0_EL1,
+                bitmap: RegisterValueFilter {
+                    filter: 0x000F_000F_0000_0000,
+                    value: 0,
+                },
+            }],
+            ..Default::default()
+        };
+
+        let annotations = annotate(&template, None);
+        let names: Vec<_> = annotations
+            .features
+            .iter()
+            .map(|feature| (feature.bit, feature.name.as_str()))
+            .collect();
+        assert_eq!(names, vec![(32, "sve"), (48, "dit")]);
+        annotations.validate(&template).unwrap();
+    }
+}
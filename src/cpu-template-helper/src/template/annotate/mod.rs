@@ -0,0 +1,81 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use vmm::cpu_config::templates::{
+    CustomCpuTemplate, FeatureAnnotation, TemplateAnnotations, ANNOTATIONS_KEY,
+};
+
+#[cfg(target_arch = "aarch64")]
+mod aarch64;
+#[cfg(target_arch = "aarch64")]
+use aarch64::feature_names;
+
+#[cfg(target_arch = "x86_64")]
+mod x86_64;
+#[cfg(target_arch = "x86_64")]
+use x86_64::feature_names;
+
+/// Build the annotations of a CPU template, naming the known features controlled by the bits it
+/// modifies.
+pub fn annotate(template: &CustomCpuTemplate, provenance: Option<String>) -> TemplateAnnotations {
+    let features = template
+        .annotated_registers()
+        .into_iter()
+        .flat_map(|(register, filter)| {
+            feature_names(&register)
+                .iter()
+                .filter(move |(bit, _)| filter & (1u128 << bit) != 0)
+                .map(move |(bit, name)| FeatureAnnotation {
+                    register: register.clone(),
+                    bit: *bit,
+                    name: name.to_string(),
+                })
+        })
+        .collect();
+
+    TemplateAnnotations {
+        provenance: Some(provenance.unwrap_or_else(|| {
+            format!(
+                "Annotated by cpu-template-helper v{}",
+                crate::utils::CPU_TEMPLATE_HELPER_VERSION
+            )
+        })),
+        features,
+    }
+}
+
+/// Serialize a CPU template along with its annotations.
+pub fn to_annotated_json(
+    template: &CustomCpuTemplate,
+    annotations: &TemplateAnnotations,
+) -> Result<String, serde_json::Error> {
+    let mut json = serde_json::to_value(template)?;
+    // A CPU template is always serialized as a JSON object.
+    json.as_object_mut().unwrap().insert(
+        ANNOTATIONS_KEY.to_string(),
+        serde_json::to_value(annotations)?,
+    );
+    serde_json::to_string_pretty(&json)
+}
+
+#[cfg(test)]
+mod tests {
+    use vmm::cpu_config::templates::test_utils::build_test_template;
+
+    use super::*;
+
+    #[test]
+    fn test_annotate() {
+        let template = build_test_template();
+        let annotations = annotate(&template, Some("Test template".to_string()));
+        assert_eq!(annotations.provenance.as_deref(), Some("Test template"));
+        // The annotations are valid for the template they were built from.
+        annotations.validate(&template).unwrap();
+
+        let json = to_annotated_json(&template, &annotations).unwrap();
+        assert_eq!(
+            CustomCpuTemplate::try_from(json.as_str()).unwrap(),
+            template
+        );
+    }
+}
@@ -0,0 +1,262 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+// Names of the CPUID feature flags as reported in `/proc/cpuinfo` by Linux, and of the
+// IA32_ARCH_CAPABILITIES MSR bits as in the Intel SDM.
+
+const CPUID_1_ECX: &[(u8, &str)] = &[
+    (0, "pni"),
+    (1, "pclmulqdq"),
+    (2, "dtes64"),
+    (3, "monitor"),
+    (4, "ds_cpl"),
+    (5, "vmx"),
+    (6, "smx"),
+    (7, "est"),
+    (8, "tm2"),
+    (9, "ssse3"),
+    (10, "cid"),
+    (11, "sdbg"),
+    (12, "fma"),
+    (13, "cx16"),
+    (14, "xtpr"),
+    (15, "pdcm"),
+    (17, "pcid"),
+    (18, "dca"),
+    (19, "sse4_1"),
+    (20, "sse4_2"),
+    (21, "x2apic"),
+    (22, "movbe"),
+    (23, "popcnt"),
+    (24, "tsc_deadline_timer"),
+    (25, "aes"),
+    (26, "xsave"),
+    (27, "osxsave"),
+    (28, "avx"),
+    (29, "f16c"),
+    (30, "rdrand"),
+    (31, "hypervisor"),
+];
+
+const CPUID_1_EDX: &[(u8, &str)] = &[
+    (0, "fpu"),
+    (1, "vme"),
+    (2, "de"),
+    (3, "pse"),
+    (4, "tsc"),
+    (5, "msr"),
+    (6, "pae"),
+    (7, "mce"),
+    (8, "cx8"),
+    (9, "apic"),
+    (11, "sep"),
+    (12, "mtrr"),
+    (13, "pge"),
+    (14, "mca"),
+    (15, "cmov"),
+    (16, "pat"),
+    (17, "pse36"),
+    (18, "pn"),
+    (19, "clflush"),
+    (21, "dts"),
+    (22, "acpi"),
+    (23, "mmx"),
+    (24, "fxsr"),
+    (25, "sse"),
+    (26, "sse2"),
+    (27, "ss"),
+    (28, "ht"),
+    (29, "tm"),
+    (31, "pbe"),
+];
+
+const CPUID_7_0_EBX: &[(u8, &str)] = &[
+    (0, "fsgsbase"),
+    (1, "tsc_adjust"),
+    (2, "sgx"),
+    (3, "bmi1"),
+    (4, "hle"),
+    (5, "avx2"),
+    (7, "smep"),
+    (8, "bmi2"),
+    (9, "erms"),
+    (10, "invpcid"),
+    (11, "rtm"),
+    (12, "cqm"),
+    (14, "mpx"),
+    (15, "rdt_a"),
+    (16, "avx512f"),
+    (17, "avx512dq"),
+    (18, "rdseed"),
+    (19, "adx"),
+    (20, "smap"),
+    (21, "avx512ifma"),
+    (23, "clflushopt"),
+    (24, "clwb"),
+    (25, "intel_pt"),
+    (26, "avx512pf"),
+    (27, "avx512er"),
+    (28, "avx512cd"),
+    (29, "sha_ni"),
+    (30, "avx512bw"),
+    (31, "avx512vl"),
+];
+
+const CPUID_7_0_ECX: &[(u8, &str)] = &[
+    (1, "avx512vbmi"),
+    (2, "umip"),
+    (3, "pku"),
+    (4, "ospke"),
+    (5, "waitpkg"),
+    (6, "avx512_vbmi2"),
+    (8, "gfni"),
+    (9, "vaes"),
+    (10, "vpclmulqdq"),
+    (11, "avx512_vnni"),
+    (12, "avx512_bitalg"),
+    (14, "avx512_vpopcntdq"),
+    (16, "la57"),
+    (22, "rdpid"),
+    (25, "cldemote"),
+    (27, "movdiri"),
+    (28, "movdir64b"),
+];
+
+const CPUID_7_0_EDX: &[(u8, &str)] = &[
+    (2, "avx512_4vnniw"),
+    (3, "avx512_4fmaps"),
+    (4, "fsrm"),
+    (8, "avx512_vp2intersect"),
+    (10, "md_clear"),
+    (14, "serialize"),
+    (16, "tsxldtrk"),
+    (18, "pconfig"),
+    (20, "ibt"),
+    (22, "amx_bf16"),
+    (23, "avx512_fp16"),
+    (24, "amx_tile"),
+    (25, "amx_int8"),
+    (26, "spec_ctrl"),
+    (27, "intel_stibp"),
+    (28, "flush_l1d"),
+    (29, "arch_capabilities"),
+    (30, "core_capabilities"),
+    (31, "spec_ctrl_ssbd"),
+];
+
+const CPUID_80000001_ECX: &[(u8, &str)] = &[
+    (0, "lahf_lm"),
+    (1, "cmp_legacy"),
+    (2, "svm"),
+    (3, "extapic"),
+    (4, "cr8_legacy"),
+    (5, "abm"),
+    (6, "sse4a"),
+    (7, "misalignsse"),
+    (8, "3dnowprefetch"),
+    (9, "osvw"),
+    (10, "ibs"),
+    (11, "xop"),
+    (12, "skinit"),
+    (13, "wdt"),
+    (15, "lwp"),
+    (16, "fma4"),
+    (17, "tce"),
+    (19, "nodeid_msr"),
+    (21, "tbm"),
+    (22, "topoext"),
+    (23, "perfctr_core"),
+    (24, "perfctr_nb"),
+    (26, "bpext"),
+    (27, "ptsc"),
+    (28, "perfctr_llc"),
+    (29, "mwaitx"),
+];
+
+const CPUID_80000001_EDX: &[(u8, &str)] = &[
+    (11, "syscall"),
+    (19, "mp"),
+    (20, "nx"),
+    (22, "mmxext"),
+    (25, "fxsr_opt"),
+    (26, "pdpe1gb"),
+    (27, "rdtscp"),
+    (29, "lm"),
+    (30, "3dnowext"),
+    (31, "3dnow"),
+];
+
+const MSR_IA32_ARCH_CAPABILITIES: &[(u8, &str)] = &[
+    (0, "rdcl_no"),
+    (1, "ibrs_all"),
+    (2, "rsba"),
+    (3, "skip_l1dfl_vmentry"),
+    (4, "ssb_no"),
+    (5, "mds_no"),
+    (6, "if_pschange_mc_no"),
+    (7, "tsx_ctrl"),
+    (8, "taa_no"),
+    (13, "sbdr_ssdp_no"),
+    (14, "fbsdp_no"),
+    (15, "psdp_no"),
+    (17, "fb_clear"),
+    (19, "rrsba"),
+    (20, "bhi_no"),
+    (24, "pbrsb_no"),
+    (26, "gds_no"),
+    (27, "rfds_no"),
+];
+
+/// Get the names of the known features controlled by the bits of a register, identified as in
+/// the template annotations.
+pub fn feature_names(register: &str) -> &'static [(u8, &'static str)] {
+    match register {
+        "cpuid:0x1:0x0:ecx" => CPUID_1_ECX,
+        "cpuid:0x1:0x0:edx" => CPUID_1_EDX,
+        "cpuid:0x7:0x0:ebx" => CPUID_7_0_EBX,
+        "cpuid:0x7:0x0:ecx" => CPUID_7_0_ECX,
+        "cpuid:0x7:0x0:edx" => CPUID_7_0_EDX,
+        "cpuid:0x80000001:0x0:ecx" => CPUID_80000001_ECX,
+        "cpuid:0x80000001:0x0:edx" => CPUID_80000001_EDX,
+        "msr:0x10a" => MSR_IA32_ARCH_CAPABILITIES,
+        _ => &[],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use vmm::cpu_config::templates::{CustomCpuTemplate, RegisterValueFilter};
+    use vmm::cpu_config::x86_64::cpuid::KvmCpuidFlags;
+    use vmm::cpu_config::x86_64::custom_cpu_template::{
+        CpuidLeafModifier, CpuidRegister, CpuidRegisterModifier,
+    };
+
+    use super::*;
+    use crate::template::annotate::annotate;
+
+    #[test]
+    fn test_annotate_cpuid() {
+        let template = CustomCpuTemplate {
+            cpuid_modifiers: vec![CpuidLeafModifier {
+                leaf: 0x7,
+                subleaf: 0x0,
+                flags: KvmCpuidFlags::SIGNIFICANT_INDEX,
+                modifiers: vec![CpuidRegisterModifier {
+                    register: CpuidRegister::Ebx,
+                    bitmap: RegisterValueFilter {
+                        filter: 1 << 16 | 1 << 6,
+                        value: 0,
+                    },
+                }],
+            }],
+            ..Default::default()
+        };
+
+        let annotations = annotate(&template, None);
+        assert_eq!(annotations.features.len(), 1);
+        assert_eq!(annotations.features[0].register, "cpuid:0x7:0x0:ebx");
+        assert_eq!(annotations.features[0].bit, 16);
+        assert_eq!(annotations.features[0].name, "avx512f");
+        annotations.validate(&template).unwrap();
+    }
+}
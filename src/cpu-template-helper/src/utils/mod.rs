@@ -75,9 +75,10 @@ pub enum UtilsError {
     Serde(#[from] serde_json::Error),
 }
 
+// Annotated templates are accepted: the annotations are validated, then dropped.
 pub fn load_cpu_template(path: &PathBuf) -> Result<CustomCpuTemplate, UtilsError> {
     let template_json = read_to_string(path)?;
-    let template = serde_json::from_str(&template_json)?;
+    let template = CustomCpuTemplate::try_from(template_json.as_str())?;
     Ok(template)
 }
 
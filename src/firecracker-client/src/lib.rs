@@ -0,0 +1,472 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+#![warn(missing_docs)]
+
+//! Typed async client for the Firecracker API.
+//!
+//! The client sends the requests described in `firecracker.yaml` to the API server of a
+//! Firecracker process, over its Unix domain socket. The bodies of the requests and responses
+//! are the types of [`models`], which are those of the VMM, so that the client follows the API.
+
+pub mod models;
+mod transport;
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+pub use transport::Method;
+
+use crate::models::*;
+
+/// Errors associated with requests to the Firecracker API.
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum ClientError {
+    /// Failed to exchange with the API server: {0}
+    Io(#[from] io::Error),
+    /// Failed to serialize the request or to deserialize the response: {0}
+    Json(#[from] serde_json::Error),
+    /// The request failed with status {status}: {fault_message}
+    Api {
+        /// HTTP status of the response.
+        status: u16,
+        /// Description of the failure, sent by the API server.
+        fault_message: String,
+    },
+}
+
+/// Body of the responses to the requests which failed.
+#[derive(Debug, Deserialize)]
+struct Fault {
+    fault_message: String,
+}
+
+/// Client of the API server of a Firecracker process.
+#[derive(Clone, Debug)]
+pub struct Client {
+    socket_path: PathBuf,
+}
+
+impl Client {
+    /// Creates a client of the API server listening on `socket_path`. The socket is connected to
+    /// for each request.
+    pub fn new<P: Into<PathBuf>>(socket_path: P) -> Self {
+        Client {
+            socket_path: socket_path.into(),
+        }
+    }
+
+    /// Returns the path of the socket of the API server.
+    pub fn socket_path(&self) -> &Path {
+        &self.socket_path
+    }
+
+    /// Sends a request on `path`, and returns the body of the response, if any.
+    pub async fn request<B>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&B>,
+    ) -> Result<Vec<u8>, ClientError>
+    where
+        B: Serialize + ?Sized,
+    {
+        let body = body.map(serde_json::to_vec).transpose()?;
+        let response = transport::send(&self.socket_path, method, path, body.as_deref()).await?;
+        if (200..300).contains(&response.status) {
+            return Ok(response.body);
+        }
+        let fault_message = serde_json::from_slice::<Fault>(&response.body)
+            .map(|fault| fault.fault_message)
+            .unwrap_or_else(|_| String::from_utf8_lossy(&response.body).into_owned());
+        Err(ClientError::Api {
+            status: response.status,
+            fault_message,
+        })
+    }
+
+    /// Sends a GET request on `path`, and deserializes the body of the response.
+    pub async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T, ClientError> {
+        let body = self.request::<()>(Method::Get, path, None).await?;
+        Ok(serde_json::from_slice(&body)?)
+    }
+
+    /// Sends a PUT request on `path`.
+    pub async fn put<B: Serialize + ?Sized>(
+        &self,
+        path: &str,
+        body: &B,
+    ) -> Result<(), ClientError> {
+        self.request(Method::Put, path, Some(body)).await?;
+        Ok(())
+    }
+
+    /// Sends a PATCH request on `path`.
+    pub async fn patch<B: Serialize + ?Sized>(
+        &self,
+        path: &str,
+        body: &B,
+    ) -> Result<(), ClientError> {
+        self.request(Method::Patch, path, Some(body)).await?;
+        Ok(())
+    }
+
+    /// Returns the general information about the microVM.
+    pub async fn describe_instance(&self) -> Result<InstanceInfo, ClientError> {
+        self.get("/").await
+    }
+
+    /// Returns the version of Firecracker.
+    pub async fn version(&self) -> Result<FirecrackerVersion, ClientError> {
+        self.get("/version").await
+    }
+
+    /// Returns the full configuration of the microVM, in the format of the configuration file.
+    pub async fn vm_config(&self) -> Result<serde_json::Value, ClientError> {
+        self.get("/vm/config").await
+    }
+
+    /// Performs an action on the microVM.
+    pub async fn action(&self, action_type: ActionType) -> Result<(), ClientError> {
+        self.put(
+            "/actions",
+            &InstanceActionInfo {
+                action_type,
+                shutdown_timeout_ms: None,
+            },
+        )
+        .await
+    }
+
+    /// Asks the guest to shut down gracefully, stopping the microVM after `shutdown_timeout_ms`
+    /// if the guest did not shut down by then.
+    pub async fn send_shutdown(&self, shutdown_timeout_ms: Option<u64>) -> Result<(), ClientError> {
+        self.put(
+            "/actions",
+            &InstanceActionInfo {
+                action_type: ActionType::SendShutdown,
+                shutdown_timeout_ms,
+            },
+        )
+        .await
+    }
+
+    /// Starts the microVM.
+    pub async fn start_instance(&self) -> Result<(), ClientError> {
+        self.action(ActionType::InstanceStart).await
+    }
+
+    /// Pauses the microVM.
+    pub async fn pause(&self) -> Result<(), ClientError> {
+        self.patch(
+            "/vm",
+            &Vm {
+                state: VmStateUpdate::Paused,
+            },
+        )
+        .await
+    }
+
+    /// Resumes the microVM.
+    pub async fn resume(&self) -> Result<(), ClientError> {
+        self.patch(
+            "/vm",
+            &Vm {
+                state: VmStateUpdate::Resumed,
+            },
+        )
+        .await
+    }
+
+    /// Sets the boot source of the microVM.
+    pub async fn put_boot_source(&self, config: &BootSourceConfig) -> Result<(), ClientError> {
+        self.put("/boot-source", config).await
+    }
+
+    /// Returns the machine configuration of the microVM.
+    pub async fn machine_config(&self) -> Result<MachineConfig, ClientError> {
+        self.get("/machine-config").await
+    }
+
+    /// Sets the machine configuration of the microVM.
+    pub async fn put_machine_config(&self, config: &MachineConfig) -> Result<(), ClientError> {
+        self.put("/machine-config", config).await
+    }
+
+    /// Updates the machine configuration of the microVM.
+    pub async fn patch_machine_config(
+        &self,
+        update: &MachineConfigUpdate,
+    ) -> Result<(), ClientError> {
+        self.patch("/machine-config", update).await
+    }
+
+    /// Adds a block device, or replaces the block device with the same ID.
+    pub async fn put_drive(&self, config: &BlockDeviceConfig) -> Result<(), ClientError> {
+        self.put(&format!("/drives/{}", config.drive_id), config)
+            .await
+    }
+
+    /// Updates a block device.
+    pub async fn patch_drive(&self, update: &BlockDeviceUpdateConfig) -> Result<(), ClientError> {
+        self.patch(&format!("/drives/{}", update.drive_id), update)
+            .await
+    }
+
+    /// Adds a network interface, or replaces the network interface with the same ID.
+    pub async fn put_network_interface(
+        &self,
+        config: &NetworkInterfaceConfig,
+    ) -> Result<(), ClientError> {
+        self.put(&format!("/network-interfaces/{}", config.iface_id), config)
+            .await
+    }
+
+    /// Updates the rate limiters of a network interface.
+    pub async fn patch_network_interface(
+        &self,
+        update: &NetworkInterfaceUpdateConfig,
+    ) -> Result<(), ClientError> {
+        self.patch(&format!("/network-interfaces/{}", update.iface_id), update)
+            .await
+    }
+
+    /// Starts or stops the packet capture of a network interface.
+    pub async fn put_network_capture(
+        &self,
+        iface_id: &str,
+        config: &NetworkCaptureConfig,
+    ) -> Result<(), ClientError> {
+        self.put(&format!("/network-interfaces/{}/capture", iface_id), config)
+            .await
+    }
+
+    /// Sets the vsock device of the microVM.
+    pub async fn put_vsock(&self, config: &VsockDeviceConfig) -> Result<(), ClientError> {
+        self.put("/vsock", config).await
+    }
+
+    /// Sets the entropy device of the microVM.
+    pub async fn put_entropy(&self, config: &EntropyDeviceConfig) -> Result<(), ClientError> {
+        self.put("/entropy", config).await
+    }
+
+    /// Returns the configuration of the balloon device.
+    pub async fn balloon(&self) -> Result<BalloonDeviceConfig, ClientError> {
+        self.get("/balloon").await
+    }
+
+    /// Sets the balloon device of the microVM.
+    pub async fn put_balloon(&self, config: &BalloonDeviceConfig) -> Result<(), ClientError> {
+        self.put("/balloon", config).await
+    }
+
+    /// Updates the target size of the balloon.
+    pub async fn patch_balloon(&self, update: &BalloonUpdateConfig) -> Result<(), ClientError> {
+        self.patch("/balloon", update).await
+    }
+
+    /// Returns the latest statistics of the balloon device.
+    pub async fn balloon_statistics(&self) -> Result<BalloonStats, ClientError> {
+        self.get("/balloon/statistics").await
+    }
+
+    /// Updates the statistics configuration of the balloon device.
+    pub async fn patch_balloon_statistics(
+        &self,
+        update: &BalloonUpdateStatsConfig,
+    ) -> Result<(), ClientError> {
+        self.patch("/balloon/statistics", update).await
+    }
+
+    /// Returns the progress of the memory available to the guest towards its target size.
+    pub async fn memory_target(&self) -> Result<MemoryTargetStatus, ClientError> {
+        self.get("/memory/target").await
+    }
+
+    /// Resizes the memory available to the guest.
+    pub async fn put_memory_target(&self, config: &MemoryTargetConfig) -> Result<(), ClientError> {
+        self.put("/memory/target", config).await
+    }
+
+    /// Sets the logger.
+    pub async fn put_logger(&self, config: &LoggerConfig) -> Result<(), ClientError> {
+        self.put("/logger", config).await
+    }
+
+    /// Sets the metrics system.
+    pub async fn put_metrics(&self, config: &MetricsConfig) -> Result<(), ClientError> {
+        self.put("/metrics", config).await
+    }
+
+    /// Sets the hooks run at the lifecycle events of the microVM.
+    pub async fn put_lifecycle_hooks(
+        &self,
+        config: &LifecycleHooksConfig,
+    ) -> Result<(), ClientError> {
+        self.put("/lifecycle-hooks", config).await
+    }
+
+    /// Sets the MMDS configuration.
+    pub async fn put_mmds_config(&self, config: &MmdsConfig) -> Result<(), ClientError> {
+        self.put("/mmds/config", config).await
+    }
+
+    /// Returns the contents of the MMDS data store.
+    pub async fn mmds(&self) -> Result<serde_json::Value, ClientError> {
+        self.get("/mmds").await
+    }
+
+    /// Replaces the contents of the MMDS data store.
+    pub async fn put_mmds(&self, data: &serde_json::Value) -> Result<(), ClientError> {
+        self.put("/mmds", data).await
+    }
+
+    /// Merges `patch` into the contents of the MMDS data store.
+    pub async fn patch_mmds(&self, patch: &serde_json::Value) -> Result<(), ClientError> {
+        self.patch("/mmds", patch).await
+    }
+
+    /// Returns the outstanding MMDS sessions opened by the guest.
+    pub async fn mmds_sessions(&self) -> Result<Vec<MmdsSession>, ClientError> {
+        self.get("/mmds/sessions").await
+    }
+
+    /// Revokes an outstanding MMDS session, invalidating its token.
+    pub async fn revoke_mmds_session(
+        &self,
+        revocation: &MmdsSessionRevocation,
+    ) -> Result<(), ClientError> {
+        self.put("/mmds/sessions/revoke", revocation).await
+    }
+
+    /// Creates a snapshot of the paused microVM.
+    pub async fn create_snapshot(&self, params: &CreateSnapshotParams) -> Result<(), ClientError> {
+        self.put("/snapshot/create", params).await
+    }
+
+    /// Loads the microVM from a snapshot.
+    pub async fn load_snapshot(&self, config: &LoadSnapshotConfig) -> Result<(), ClientError> {
+        self.put("/snapshot/load", config).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::UnixListener;
+    use vmm_sys_util::tempdir::TempDir;
+
+    use super::*;
+
+    /// Accepts a connection on `listener`, replies `response` to the request read from it, and
+    /// returns the request line and body.
+    async fn serve(listener: &UnixListener, response: &str) -> (String, String) {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line).await.unwrap();
+        let mut content_length = 0;
+        loop {
+            let mut header = String::new();
+            reader.read_line(&mut header).await.unwrap();
+            if header == "\r\n" {
+                break;
+            }
+            if let Some(len) = header.strip_prefix("Content-Length: ") {
+                content_length = len.trim().parse().unwrap();
+            }
+        }
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body).await.unwrap();
+        reader
+            .into_inner()
+            .write_all(response.as_bytes())
+            .await
+            .unwrap();
+        (
+            request_line.trim_end().to_string(),
+            String::from_utf8(body).unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_client() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.as_path().join("api.sock");
+        let listener = UnixListener::bind(&path).unwrap();
+        let client = Client::new(&path);
+        assert_eq!(client.socket_path(), path);
+
+        let (request, result) = tokio::join!(
+            serve(&listener, "HTTP/1.1 204 \r\n\r\n"),
+            client.patch_drive(&BlockDeviceUpdateConfig {
+                drive_id: "rootfs".to_string(),
+                path_on_host: Some("/tmp/rootfs.ext4".to_string()),
+                rate_limiter: None,
+                state: None,
+            })
+        );
+        result.unwrap();
+        assert_eq!(request.0, "PATCH /drives/rootfs HTTP/1.1");
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&request.1).unwrap(),
+            serde_json::json!({
+                "drive_id": "rootfs",
+                "path_on_host": "/tmp/rootfs.ext4",
+                "rate_limiter": null,
+                "state": null
+            })
+        );
+
+        let body =
+            r#"{"id":"vm0","state":"Running","vmm_version":"1.11.0","app_name":"Firecracker"}"#;
+        let response = format!(
+            "HTTP/1.1 200 \r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        );
+        let (request, info) = tokio::join!(serve(&listener, &response), client.describe_instance());
+        assert_eq!(request.0, "GET / HTTP/1.1");
+        assert_eq!(
+            info.unwrap(),
+            InstanceInfo {
+                id: "vm0".to_string(),
+                state: VmState::Running,
+                vmm_version: "1.11.0".to_string(),
+                app_name: "Firecracker".to_string(),
+            }
+        );
+
+        let body = r#"{"fault_message":"The microVM is not paused."}"#;
+        let response = format!(
+            "HTTP/1.1 400 \r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        );
+        let (request, result) = tokio::join!(serve(&listener, &response), client.resume());
+        assert_eq!(
+            request,
+            (
+                "PATCH /vm HTTP/1.1".to_string(),
+                r#"{"state":"Resumed"}"#.to_string()
+            )
+        );
+        match result.unwrap_err() {
+            ClientError::Api {
+                status,
+                fault_message,
+            } => {
+                assert_eq!(status, 400);
+                assert_eq!(fault_message, "The microVM is not paused.");
+            }
+            err => panic!("unexpected error: {err}"),
+        }
+
+        drop(listener);
+        assert!(matches!(
+            client.start_instance().await.unwrap_err(),
+            ClientError::Io(_)
+        ));
+    }
+}
@@ -0,0 +1,68 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Bodies of the requests to, and of the responses from, the Firecracker API.
+//!
+//! The models are the types the VMM deserializes the requests into, so that they cannot drift from
+//! the API. The few bodies parsed by the API server itself are defined here.
+
+use serde::{Deserialize, Serialize};
+pub use vmm::logger::{LevelFilter, LoggerConfig};
+pub use vmm::mmds::data_store::MmdsSession;
+pub use vmm::vmm_config::balloon::{
+    BalloonAutoTargetConfig, BalloonDeviceConfig, BalloonStats, BalloonStatsPushConfig,
+    BalloonUpdateConfig, BalloonUpdateStatsConfig,
+};
+pub use vmm::vmm_config::boot_source::BootSourceConfig;
+pub use vmm::vmm_config::drive::{BlockDeviceConfig, BlockDeviceUpdateConfig};
+pub use vmm::vmm_config::entropy::EntropyDeviceConfig;
+pub use vmm::vmm_config::instance_info::{InstanceInfo, VmState};
+pub use vmm::vmm_config::lifecycle_hooks::{
+    LifecycleEvent, LifecycleHookConfig, LifecycleHooksConfig,
+};
+pub use vmm::vmm_config::machine_config::{MachineConfig, MachineConfigUpdate};
+pub use vmm::vmm_config::memory_target::{
+    MemoryTargetConfig, MemoryTargetMechanism, MemoryTargetStatus,
+};
+pub use vmm::vmm_config::metrics::MetricsConfig;
+pub use vmm::vmm_config::mmds::{MmdsConfig, MmdsSessionRevocation};
+pub use vmm::vmm_config::net::{
+    NetworkCaptureConfig, NetworkInterfaceConfig, NetworkInterfaceUpdateConfig,
+};
+pub use vmm::vmm_config::snapshot::{
+    CheckSnapshotParams, CreateSnapshotParams, LoadSnapshotConfig, MemBackendConfig,
+    MemBackendType, SnapshotEncryptionConfig, SnapshotType, Vm, VmState as VmStateUpdate,
+};
+pub use vmm::vmm_config::vsock::VsockDeviceConfig;
+pub use vmm::vmm_config::{DeviceQueuesState, RateLimiterConfig};
+
+/// Actions which can be requested on `/actions`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ActionType {
+    /// Flushes the metrics.
+    FlushMetrics,
+    /// Starts the microVM.
+    InstanceStart,
+    /// Sends CTRL+ALT+DEL to the microVM. Only supported on x86_64.
+    SendCtrlAltDel,
+    /// Presses the ACPI power button of the microVM.
+    SendShutdown,
+}
+
+/// Body of a request on `/actions`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct InstanceActionInfo {
+    /// Action to perform.
+    pub action_type: ActionType,
+    /// Time in milliseconds after which the microVM is stopped if the guest did not shut down.
+    /// Only allowed for [`ActionType::SendShutdown`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shutdown_timeout_ms: Option<u64>,
+}
+
+/// Body of the response to a request on `/version`.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct FirecrackerVersion {
+    /// Version of the Firecracker binary.
+    pub firecracker_version: String,
+}
@@ -0,0 +1,208 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! HTTP/1.1 exchanges with the API server, over its Unix domain socket.
+//!
+//! Each request is sent on a new connection, so that requests issued concurrently do not wait on
+//! each other, and that a client outlives the restarts of the API server.
+
+use std::io;
+use std::path::Path;
+
+use tokio::io::{
+    AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader,
+};
+use tokio::net::UnixStream;
+
+/// Maximum length of the status line and of each header of a response.
+const MAX_LINE_LEN: u64 = 8192;
+
+/// HTTP methods used by the API.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Method {
+    /// GET method.
+    Get,
+    /// PATCH method.
+    Patch,
+    /// PUT method.
+    Put,
+}
+
+impl Method {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Method::Get => "GET",
+            Method::Patch => "PATCH",
+            Method::Put => "PUT",
+        }
+    }
+}
+
+/// Response received from the API server.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Response {
+    pub status: u16,
+    pub body: Vec<u8>,
+}
+
+/// Sends a request to the API server listening on `socket_path`, and reads its response.
+pub(crate) async fn send(
+    socket_path: &Path,
+    method: Method,
+    path: &str,
+    body: Option<&[u8]>,
+) -> io::Result<Response> {
+    let stream = UnixStream::connect(socket_path).await?;
+    exchange(stream, method, path, body).await
+}
+
+async fn exchange<S>(
+    mut stream: S,
+    method: Method,
+    path: &str,
+    body: Option<&[u8]>,
+) -> io::Result<Response>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut request = format!(
+        "{} {path} HTTP/1.1\r\nHost: localhost\r\nAccept: application/json\r\n",
+        method.as_str()
+    );
+    if let Some(body) = body {
+        request.push_str(&format!(
+            "Content-Type: application/json\r\nContent-Length: {}\r\n",
+            body.len()
+        ));
+    }
+    request.push_str("\r\n");
+
+    let mut message = request.into_bytes();
+    message.extend_from_slice(body.unwrap_or_default());
+    stream.write_all(&message).await?;
+    stream.flush().await?;
+
+    read_response(BufReader::new(stream)).await
+}
+
+async fn read_response<R>(mut reader: R) -> io::Result<Response>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let status_line = read_line(&mut reader).await?;
+    let status = status_line
+        .strip_prefix("HTTP/1.1 ")
+        .and_then(|status| status.get(..3))
+        .and_then(|status| status.parse::<u16>().ok())
+        .ok_or_else(|| invalid_data(format!("invalid status line: {status_line:?}")))?;
+
+    let mut content_length = None;
+    loop {
+        let line = read_line(&mut reader).await?;
+        if line.is_empty() {
+            break;
+        }
+        let (name, value) = line
+            .split_once(':')
+            .ok_or_else(|| invalid_data(format!("invalid header: {line:?}")))?;
+        if name.trim().eq_ignore_ascii_case("content-length") {
+            let len = value
+                .trim()
+                .parse::<usize>()
+                .map_err(|_| invalid_data(format!("invalid header: {line:?}")))?;
+            content_length = Some(len);
+        }
+    }
+
+    // The API server sets the length of all the responses with a body.
+    let mut body = vec![0; content_length.unwrap_or(0)];
+    reader.read_exact(&mut body).await?;
+    Ok(Response { status, body })
+}
+
+/// Reads a line terminated by CRLF, and returns it without the terminator.
+async fn read_line<R>(reader: &mut R) -> io::Result<String>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let mut line = Vec::new();
+    let len = (&mut *reader)
+        .take(MAX_LINE_LEN)
+        .read_until(b'\n', &mut line)
+        .await?;
+    if len == 0 {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+    }
+    if !line.ends_with(b"\r\n") {
+        return Err(invalid_data("line not terminated by CRLF".to_string()));
+    }
+    line.truncate(line.len() - 2);
+    String::from_utf8(line).map_err(|_| invalid_data("line is not valid UTF-8".to_string()))
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::duplex;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_exchange() {
+        let (client, mut server) = duplex(4096);
+        let server = tokio::spawn(async move {
+            let mut request = vec![0; 4096];
+            let len = server.read(&mut request).await.unwrap();
+            request.truncate(len);
+            server
+                .write_all(
+                    b"HTTP/1.1 200 \r\nServer: Firecracker API\r\nContent-Length: 2\r\n\r\n{}",
+                )
+                .await
+                .unwrap();
+            String::from_utf8(request).unwrap()
+        });
+
+        let response = exchange(client, Method::Put, "/mmds", Some(&b"{}"[..]))
+            .await
+            .unwrap();
+        assert_eq!(
+            response,
+            Response {
+                status: 200,
+                body: b"{}".to_vec()
+            }
+        );
+        assert_eq!(
+            server.await.unwrap(),
+            "PUT /mmds HTTP/1.1\r\nHost: localhost\r\nAccept: application/json\r\nContent-Type: \
+             application/json\r\nContent-Length: 2\r\n\r\n{}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_read_response() {
+        let response = read_response(&b"HTTP/1.1 204 \r\n\r\n"[..]).await.unwrap();
+        assert_eq!(response.status, 204);
+        assert!(response.body.is_empty());
+
+        let response = read_response(&b"HTTP/1.1 400 \r\ncontent-length: 3\r\n\r\nabc"[..])
+            .await
+            .unwrap();
+        assert_eq!(response.status, 400);
+        assert_eq!(response.body, b"abc");
+
+        for invalid in [
+            &b"HTTP/1.1 204 \n\n"[..],
+            &b"HTTP/1.0 204 \r\n\r\n"[..],
+            &b"HTTP/1.1 204 \r\nServer\r\n\r\n"[..],
+            &b"HTTP/1.1 200 \r\nContent-Length: 3\r\n\r\nab"[..],
+            &b""[..],
+        ] {
+            read_response(invalid).await.unwrap_err();
+        }
+    }
+}
@@ -12,6 +12,10 @@ const ADVANCED_BINARY_FILTER_FILE_NAME: &str = "seccomp_filter.bpf";
 
 const JSON_DIR: &str = "../../resources/seccomp";
 const SECCOMPILER_SRC_DIR: &str = "../seccompiler/src";
+#[cfg(feature = "grpc")]
+const GRPC_JSON_DIR: &str = "../../resources/seccomp/grpc";
+#[cfg(feature = "grpc")]
+const GRPC_PROTO_FILE: &str = "proto/firecracker.proto";
 
 // This script is run on every modification in the target-specific JSON file in `resources/seccomp`.
 // It compiles the JSON seccomp policies into a serializable BPF format, using seccompiler-bin.
@@ -45,7 +49,23 @@ fn main() {
     println!("cargo:rerun-if-changed={}", SECCOMPILER_SRC_DIR);
 
     let input = std::fs::read_to_string(seccomp_json_path).expect("Correct input file");
-    let filters: JsonFile = serde_json::from_str(&input).expect("Input read");
+    #[allow(unused_mut)]
+    let mut filters: serde_json::Value = serde_json::from_str(&input).expect("Input read");
+
+    // The gRPC server runs with the filter of the API thread, which only allows the syscalls of
+    // the server when it is compiled in.
+    #[cfg(feature = "grpc")]
+    {
+        let grpc_json_path = format!("{}/{}.json", GRPC_JSON_DIR, target);
+        if Path::new(&grpc_json_path).exists() {
+            println!("cargo:rerun-if-changed={}", grpc_json_path);
+            let input = std::fs::read_to_string(grpc_json_path).expect("Correct gRPC input file");
+            let rules: serde_json::Value = serde_json::from_str(&input).expect("gRPC input read");
+            merge_rules(&mut filters, &rules);
+        }
+    }
+
+    let filters: JsonFile = serde_json::from_value(filters).expect("Input read");
 
     let arch = target_arch.as_str().try_into().expect("Target");
     let compiler = Compiler::new(arch);
@@ -59,4 +79,28 @@ fn main() {
     let out_path = format!("{}/{}", out_dir, ADVANCED_BINARY_FILTER_FILE_NAME);
     let output_file = File::create(out_path).expect("Create seccompiler output path");
     bincode::serialize_into(output_file, &bpf_data).expect("Seccompiler serialization");
+
+    // Generate the gRPC service from its definition. This needs `protoc`, see `PROTOC` in the
+    // prost-build documentation.
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed={}", GRPC_PROTO_FILE);
+        tonic_build::configure()
+            .build_client(false)
+            .compile_protos(&[GRPC_PROTO_FILE], &["proto"])
+            .expect("Failed to compile the gRPC service definition");
+    }
+}
+
+/// Appends the rules of each thread category of `rules` to the filter of the same category.
+#[cfg(feature = "grpc")]
+fn merge_rules(filters: &mut serde_json::Value, rules: &serde_json::Value) {
+    let rules = rules.as_object().expect("A map of thread categories");
+    for (thread, filter) in rules {
+        let extra = filter["filter"].as_array().expect("A list of rules");
+        filters[thread.as_str()]["filter"]
+            .as_array_mut()
+            .unwrap_or_else(|| panic!("No filter for thread category {}", thread))
+            .extend(extra.iter().cloned());
+    }
 }
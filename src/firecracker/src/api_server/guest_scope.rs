@@ -0,0 +1,118 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Restricts the requests served by the guest API.
+//!
+//! The guest API is a second API listener reachable by the guest through vsock, so that in-guest
+//! agents can interact with the VMM without a host-side proxy. Since the guest is not trusted,
+//! only the endpoints which cannot alter the configuration of the microVM are served, and MMDS
+//! updates are limited to the [`GUEST_WRITABLE_MMDS_KEY`] subtree.
+
+use micro_http::{Method, Request};
+use serde_json::Value;
+
+/// Top level MMDS key under which the guest can write.
+pub const GUEST_WRITABLE_MMDS_KEY: &str = "guest";
+
+/// Errors associated with requests outside of the guest API scope.
+#[derive(Debug, PartialEq, Eq, thiserror::Error, displaydoc::Display)]
+pub enum GuestScopeError {
+    /// The {0:?} request on {1:?} is not allowed on the guest API.
+    NotAllowed(Method, String),
+    /// The guest API only allows MMDS updates of the `guest` subtree.
+    MmdsSubtree,
+}
+
+/// Checks that `request` is allowed on the guest API.
+///
+/// The guest can get the instance information and the balloon statistics, and patch the MMDS
+/// with a JSON object whose only key is [`GUEST_WRITABLE_MMDS_KEY`].
+pub fn check_guest_request(request: &Request) -> Result<(), GuestScopeError> {
+    let path = request.uri().get_abs_path();
+    let path = path.split_once('?').map_or(path, |(path, _)| path);
+    match (request.method(), path.trim_end_matches('/')) {
+        (Method::Get, "") | (Method::Get, "/balloon/statistics") => Ok(()),
+        (Method::Patch, "/mmds") => {
+            let value = request
+                .body
+                .as_ref()
+                .and_then(|body| serde_json::from_slice::<Value>(body.raw()).ok());
+            match value {
+                Some(Value::Object(map))
+                    if !map.is_empty() && map.keys().all(|key| key == GUEST_WRITABLE_MMDS_KEY) =>
+                {
+                    Ok(())
+                }
+                _ => Err(GuestScopeError::MmdsSubtree),
+            }
+        }
+        (method, _) => Err(GuestScopeError::NotAllowed(method, path.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(method: &str, path: &str, body: Option<&str>) -> Request {
+        let raw = match body {
+            Some(body) => format!(
+                "{method} {path} HTTP/1.1\r\nContent-Type: application/json\r\nContent-Length: \
+                 {}\r\n\r\n{body}",
+                body.len()
+            ),
+            None => format!("{method} {path} HTTP/1.1\r\n\r\n"),
+        };
+        Request::try_from(raw.as_bytes(), None).unwrap()
+    }
+
+    #[test]
+    fn test_check_guest_request() {
+        check_guest_request(&request("GET", "/", None)).unwrap();
+        check_guest_request(&request("GET", "/balloon/statistics", None)).unwrap();
+        check_guest_request(&request(
+            "PATCH",
+            "/mmds",
+            Some(r#"{"guest": {"ready": true}}"#),
+        ))
+        .unwrap();
+        check_guest_request(&request("PATCH", "/mmds", Some(r#"{"guest": null}"#))).unwrap();
+
+        assert_eq!(
+            check_guest_request(&request("GET", "/balloon", None)).unwrap_err(),
+            GuestScopeError::NotAllowed(Method::Get, "/balloon".to_string())
+        );
+        assert_eq!(
+            check_guest_request(&request("GET", "/mmds", None)).unwrap_err(),
+            GuestScopeError::NotAllowed(Method::Get, "/mmds".to_string())
+        );
+        assert_eq!(
+            check_guest_request(&request("PUT", "/actions", Some("{}"))).unwrap_err(),
+            GuestScopeError::NotAllowed(Method::Put, "/actions".to_string())
+        );
+        assert_eq!(
+            check_guest_request(&request("PATCH", "/vm", Some(r#"{"state": "Paused"}"#)))
+                .unwrap_err(),
+            GuestScopeError::NotAllowed(Method::Patch, "/vm".to_string())
+        );
+
+        // MMDS updates outside of the guest subtree are rejected.
+        for body in [
+            r#"{"guest": {}, "host": {}}"#,
+            r#"{"host": {}}"#,
+            "{}",
+            r#"["guest"]"#,
+            "not json",
+        ] {
+            assert_eq!(
+                check_guest_request(&request("PATCH", "/mmds", Some(body))).unwrap_err(),
+                GuestScopeError::MmdsSubtree
+            );
+        }
+        assert_eq!(
+            check_guest_request(&request("PATCH", "/mmds/config", Some(r#"{"guest": {}}"#)))
+                .unwrap_err(),
+            GuestScopeError::NotAllowed(Method::Patch, "/mmds/config".to_string())
+        );
+    }
+}
@@ -0,0 +1,1171 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! HTTP/1.1 server of the API sockets.
+//!
+//! Requests and responses are the `micro_http` ones, but the connections are handled here so that
+//! request bodies can use the chunked transfer coding, several requests can be pipelined on a
+//! connection, and large response bodies are sent with the chunked transfer coding. Responses to
+//! pipelined requests are sent in the order the requests were received.
+
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Read, Write};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+#[cfg(feature = "grpc")]
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use micro_http::{Body, Request, Response, StatusCode, Version};
+#[cfg(feature = "grpc")]
+use tokio::sync::oneshot;
+use vmm_sys_util::epoll::{ControlOperation, Epoll, EpollEvent, EventSet};
+use vmm_sys_util::eventfd::EventFd;
+
+/// Maximum number of connections served at the same time.
+const MAX_CONNECTIONS: usize = 10;
+/// Maximum number of epoll events handled on each call of `HttpServer::requests()`.
+const MAX_EVENTS: usize = MAX_CONNECTIONS + 2;
+/// Maximum size of the request line and headers, and of the trailer fields of chunked bodies.
+const MAX_HEAD_SIZE: usize = 16 * 1024;
+/// Size of the buffer used to read from connections.
+const READ_BUFFER_SIZE: usize = 4096;
+/// Response bodies larger than this are sent in chunks of this size to HTTP/1.1 clients.
+pub const RESPONSE_CHUNK_SIZE: usize = 16 * 1024;
+/// How long `HttpServer::flush_outgoing_writes()` waits for the clients to accept the queued
+/// responses.
+const FLUSH_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Epoll data of the listening socket.
+const LISTENER_TOKEN: u64 = 0;
+/// Epoll data of the kill switch.
+const KILL_SWITCH_TOKEN: u64 = 1;
+/// Epoll data of the event fd signaling local requests.
+#[cfg(feature = "grpc")]
+const LOCAL_REQUESTS_TOKEN: u64 = 2;
+/// Epoll data of the first connection, the following ones get increasing values.
+const FIRST_CONNECTION_TOKEN: u64 = 3;
+
+/// Errors of the API HTTP server.
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum ServerError {
+    /// IO error: {0}
+    IOError(io::Error),
+    /// Shutdown requested.
+    ShutdownEvent,
+}
+
+/// Errors which make it impossible to find the boundaries of a request.
+#[derive(Debug, PartialEq, Eq, thiserror::Error, displaydoc::Display)]
+enum FramingError {
+    /// Request line and headers are larger than the limit of {0} bytes.
+    HeadTooLarge(usize),
+    /// Chunk size line is larger than the limit of {0} bytes.
+    ChunkLineTooLarge(usize),
+    /// Request payload with size {0} is larger than the limit of {1} allowed by server.
+    PayloadTooLarge(usize, usize),
+    /// Invalid Content-Length header.
+    ContentLength,
+    /// Invalid chunked request body.
+    ChunkedBody,
+    /// Requests can't carry both the Content-Length and Transfer-Encoding headers.
+    LengthAndEncoding,
+    /// Unsupported transfer coding, only chunked and identity are supported.
+    TransferEncoding,
+    /// {0}
+    Request(String),
+}
+
+/// A request received on a connection of the server.
+#[derive(Debug)]
+pub struct ServerRequest {
+    /// The parsed request.
+    pub request: Request,
+    id: u64,
+}
+
+impl ServerRequest {
+    /// Builds the response to the request with `callable`.
+    pub fn process<F>(&self, callable: F) -> ServerResponse
+    where
+        F: FnOnce(&Request) -> Response,
+    {
+        ServerResponse {
+            response: callable(&self.request),
+            id: self.id,
+        }
+    }
+}
+
+/// A response to send on the connection a request was received on.
+#[derive(Debug)]
+pub struct ServerResponse {
+    /// The response to send.
+    pub response: Response,
+    id: u64,
+}
+
+/// A request submitted by another thread of the process, e.g. the gRPC server, along with the
+/// channel on which to send its response.
+#[cfg(feature = "grpc")]
+#[derive(Debug)]
+pub struct LocalRequest {
+    /// The request to serve.
+    pub request: Request,
+    /// Receives the response to the request. The submitter can await it without blocking its
+    /// runtime.
+    pub response_sender: oneshot::Sender<Response>,
+}
+
+/// Queue of the requests submitted by another thread of the process, which are served along with
+/// the requests of the socket.
+#[cfg(feature = "grpc")]
+#[derive(Debug)]
+pub struct LocalRequests {
+    receiver: mpsc::Receiver<LocalRequest>,
+    /// Written by the submitter after each request.
+    event_fd: EventFd,
+    /// Response channels of the requests handed out by `HttpServer::requests()`, in order.
+    pending: VecDeque<oneshot::Sender<Response>>,
+}
+
+#[cfg(feature = "grpc")]
+impl LocalRequests {
+    /// Creates a queue receiving requests from `receiver`, whose sender writes `event_fd` after
+    /// each request.
+    pub fn new(receiver: mpsc::Receiver<LocalRequest>, event_fd: EventFd) -> Self {
+        LocalRequests {
+            receiver,
+            event_fd,
+            pending: VecDeque::new(),
+        }
+    }
+}
+
+/// How to send the response of a request.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct ResponseFraming {
+    /// The client asked for the connection to be closed after the response.
+    close: bool,
+    /// The client understands chunked response bodies.
+    chunked: bool,
+}
+
+/// A connection of the server.
+#[derive(Debug)]
+struct ClientConnection {
+    stream: UnixStream,
+    /// Received bytes which don't make up a complete request yet.
+    input: Vec<u8>,
+    /// Progress made decoding the chunked body of the first request in `input`.
+    decoder: ChunkedDecoder,
+    /// Encoded responses, of which the first `written` bytes were sent.
+    output: Vec<u8>,
+    written: usize,
+    /// Framing of the responses to the requests handed out by `HttpServer::requests()`, in the
+    /// order the requests were received.
+    pending: VecDeque<ResponseFraming>,
+    /// Error to send once the pending requests are answered, after which the connection is closed.
+    error: Option<FramingError>,
+    /// Set once no more requests are accepted on the connection.
+    stop_reading: bool,
+    /// Set once the client shut down its side of the connection.
+    eof: bool,
+    /// Set when the connection can't be used anymore.
+    broken: bool,
+    /// The events the connection is registered for.
+    registered: EventSet,
+}
+
+impl ClientConnection {
+    fn new(stream: UnixStream) -> Self {
+        ClientConnection {
+            stream,
+            input: Vec::new(),
+            decoder: ChunkedDecoder::default(),
+            output: Vec::new(),
+            written: 0,
+            pending: VecDeque::new(),
+            error: None,
+            stop_reading: false,
+            eof: false,
+            broken: false,
+            registered: EventSet::empty(),
+        }
+    }
+
+    /// Reads the bytes available on the connection, up to the size of the largest request which
+    /// can be accepted. The bytes left in the connection are read once the buffered requests are
+    /// handed out.
+    fn read(&mut self, payload_max_size: usize) {
+        let mut buf = [0u8; READ_BUFFER_SIZE];
+        // Past this size, the buffered bytes are either complete requests, or a request which gets
+        // rejected by `frame_request()`.
+        let max_input = MAX_HEAD_SIZE.saturating_add(payload_max_size);
+        while self.stop_reading || self.input.len() <= max_input {
+            match self.stream.read(&mut buf) {
+                Ok(0) => {
+                    self.eof = true;
+                    return;
+                }
+                // Bytes received after the last accepted request are dropped.
+                Ok(_) if self.stop_reading => (),
+                Ok(len) => self.input.extend_from_slice(&buf[..len]),
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => return,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
+                Err(_) => {
+                    self.broken = true;
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Hands out the complete requests received on the connection, in order.
+    fn take_requests(&mut self, id: u64, payload_max_size: usize, out: &mut Vec<ServerRequest>) {
+        while !self.stop_reading {
+            let framed = frame_request(&self.input, payload_max_size, &mut self.decoder);
+            let (len, request, framing) = match framed {
+                Ok(Some((len, bytes, framing))) => {
+                    let request = Request::try_from(&bytes, Some(payload_max_size))
+                        .map_err(|err| FramingError::Request(err.to_string()));
+                    (len, request, framing)
+                }
+                Ok(None) => break,
+                Err(err) => (self.input.len(), Err(err), ResponseFraming::default()),
+            };
+            self.input.drain(..len);
+            self.decoder = ChunkedDecoder::default();
+            match request {
+                Ok(request) => {
+                    self.stop_reading = framing.close;
+                    self.pending.push_back(framing);
+                    out.push(ServerRequest { request, id });
+                }
+                Err(err) => {
+                    // We can't tell where the next request begins, so we answer with an error and
+                    // close the connection once the previous requests are answered.
+                    self.error = Some(err);
+                    self.stop_reading = true;
+                    self.input.clear();
+                    self.queue_error();
+                }
+            }
+        }
+    }
+
+    /// Queues the response to the oldest pending request.
+    fn queue_response(&mut self, response: &Response) {
+        let framing = self.pending.pop_front().unwrap_or_default();
+        encode_response(response, framing, &mut self.output);
+        self.queue_error();
+    }
+
+    /// Queues the error response once the requests received before the error are answered.
+    fn queue_error(&mut self) {
+        if !self.pending.is_empty() {
+            return;
+        }
+        if let Some(err) = self.error.take() {
+            let status = match err {
+                FramingError::PayloadTooLarge(..) => StatusCode::PayloadTooLarge,
+                _ => StatusCode::BadRequest,
+            };
+            let mut response = Response::new(Version::Http11, status);
+            response.set_body(Body::new(format!(
+                "{{ \"error\": {} }}",
+                serde_json::Value::from(err.to_string())
+            )));
+            let framing = ResponseFraming {
+                close: true,
+                chunked: false,
+            };
+            encode_response(&response, framing, &mut self.output);
+        }
+    }
+
+    /// Writes as much of the queued responses as the connection accepts.
+    fn write(&mut self) {
+        while self.written < self.output.len() {
+            match self.stream.write(&self.output[self.written..]) {
+                Ok(0) => self.broken = true,
+                Ok(len) => self.written += len,
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
+                Err(_) => self.broken = true,
+            }
+            if self.broken {
+                return;
+            }
+        }
+        if self.written == self.output.len() {
+            self.output.clear();
+            self.written = 0;
+        }
+    }
+
+    /// Whether the connection has nothing left to do.
+    fn is_done(&self) -> bool {
+        self.broken
+            || ((self.stop_reading || self.eof)
+                && self.pending.is_empty()
+                && self.error.is_none()
+                && self.output.is_empty())
+    }
+
+    /// The events the connection waits for.
+    fn event_set(&self) -> EventSet {
+        let mut events = EventSet::empty();
+        if !self.eof {
+            events |= EventSet::IN | EventSet::READ_HANG_UP;
+        }
+        if !self.output.is_empty() {
+            events |= EventSet::OUT;
+        }
+        events
+    }
+}
+
+/// HTTP server listening on a Unix domain socket.
+#[derive(Debug)]
+pub struct HttpServer {
+    listener: UnixListener,
+    epoll: Epoll,
+    kill_switch: Option<EventFd>,
+    connections: HashMap<u64, ClientConnection>,
+    next_token: u64,
+    payload_max_size: usize,
+    #[cfg(feature = "grpc")]
+    local_requests: Option<LocalRequests>,
+}
+
+impl HttpServer {
+    /// Creates a server bound to the Unix domain socket at `path_to_socket`.
+    pub fn new<P: AsRef<Path>>(path_to_socket: P) -> Result<Self, ServerError> {
+        let listener = UnixListener::bind(path_to_socket).map_err(ServerError::IOError)?;
+        Ok(HttpServer {
+            listener,
+            epoll: Epoll::new().map_err(ServerError::IOError)?,
+            kill_switch: None,
+            connections: HashMap::new(),
+            next_token: FIRST_CONNECTION_TOKEN,
+            payload_max_size: vmm::HTTP_MAX_PAYLOAD_SIZE,
+            #[cfg(feature = "grpc")]
+            local_requests: None,
+        })
+    }
+
+    /// Sets the maximum size of request bodies.
+    pub fn set_payload_max_size(&mut self, payload_max_size: usize) {
+        self.payload_max_size = payload_max_size;
+    }
+
+    /// Makes `requests()` return `ServerError::ShutdownEvent` once `kill_switch` is readable.
+    pub fn add_kill_switch(&mut self, kill_switch: EventFd) -> Result<(), ServerError> {
+        self.epoll
+            .ctl(
+                ControlOperation::Add,
+                kill_switch.as_raw_fd(),
+                EpollEvent::new(EventSet::IN, KILL_SWITCH_TOKEN),
+            )
+            .map_err(ServerError::IOError)?;
+        self.kill_switch = Some(kill_switch);
+        Ok(())
+    }
+
+    /// Serves the requests of `local_requests` along with the requests of the socket.
+    #[cfg(feature = "grpc")]
+    pub fn add_local_requests(&mut self, local_requests: LocalRequests) -> Result<(), ServerError> {
+        self.epoll
+            .ctl(
+                ControlOperation::Add,
+                local_requests.event_fd.as_raw_fd(),
+                EpollEvent::new(EventSet::IN, LOCAL_REQUESTS_TOKEN),
+            )
+            .map_err(ServerError::IOError)?;
+        self.local_requests = Some(local_requests);
+        Ok(())
+    }
+
+    /// Stops serving the local requests, e.g. to hand them over to another server.
+    #[cfg(feature = "grpc")]
+    pub fn take_local_requests(&mut self) -> Option<LocalRequests> {
+        let local_requests = self.local_requests.take()?;
+        let _ = self.epoll.ctl(
+            ControlOperation::Delete,
+            local_requests.event_fd.as_raw_fd(),
+            EpollEvent::default(),
+        );
+        Some(local_requests)
+    }
+
+    /// Starts accepting connections.
+    pub fn start_server(&mut self) -> Result<(), ServerError> {
+        self.listener
+            .set_nonblocking(true)
+            .map_err(ServerError::IOError)?;
+        self.epoll
+            .ctl(
+                ControlOperation::Add,
+                self.listener.as_raw_fd(),
+                EpollEvent::new(EventSet::IN, LISTENER_TOKEN),
+            )
+            .map_err(ServerError::IOError)
+    }
+
+    /// Waits for connection events and returns the complete requests received.
+    ///
+    /// The requests received on a connection are returned in order, and must be answered in the
+    /// same order.
+    pub fn requests(&mut self) -> Result<Vec<ServerRequest>, ServerError> {
+        let mut events = [EpollEvent::default(); MAX_EVENTS];
+        let count = match self.epoll.wait(-1, &mut events) {
+            Ok(count) => count,
+            // A signal, e.g. a socket rebind request, interrupted the wait.
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => return Ok(vec![]),
+            Err(err) => return Err(ServerError::IOError(err)),
+        };
+        let events = &events[..count];
+        if events.iter().any(|event| event.data() == KILL_SWITCH_TOKEN) {
+            return Err(ServerError::ShutdownEvent);
+        }
+
+        let mut requests = Vec::new();
+        for event in events {
+            match event.data() {
+                LISTENER_TOKEN => self.accept_connections()?,
+                #[cfg(feature = "grpc")]
+                LOCAL_REQUESTS_TOKEN => self.receive_local_requests(&mut requests),
+                token => {
+                    let Some(connection) = self.connections.get_mut(&token) else {
+                        continue;
+                    };
+                    let event_set = event.event_set();
+                    if event_set.contains(EventSet::OUT) {
+                        connection.write();
+                    }
+                    if !event_set.difference(EventSet::OUT).is_empty() {
+                        connection.read(self.payload_max_size);
+                    }
+                    connection.take_requests(token, self.payload_max_size, &mut requests);
+                    connection.write();
+                    self.update_connection(token);
+                }
+            }
+        }
+        Ok(requests)
+    }
+
+    /// Sends the response to a request returned by `requests()`.
+    pub fn respond(&mut self, response: ServerResponse) -> Result<(), ServerError> {
+        #[cfg(feature = "grpc")]
+        if response.id == LOCAL_REQUESTS_TOKEN {
+            let sender = self
+                .local_requests
+                .as_mut()
+                .and_then(|local_requests| local_requests.pending.pop_front());
+            // The submitter may have given up on the request in the meantime.
+            if let Some(sender) = sender {
+                let _ = sender.send(response.response);
+            }
+            return Ok(());
+        }
+        // The client may have gone away in the meantime, in which case there's nobody to answer.
+        if let Some(connection) = self.connections.get_mut(&response.id) {
+            connection.queue_response(&response.response);
+            connection.write();
+            self.update_connection(response.id);
+        }
+        Ok(())
+    }
+
+    /// Writes the queued responses, waiting up to `FLUSH_TIMEOUT` for the clients to accept them.
+    /// The connections stay non-blocking, and are written to as they become writable, so that a
+    /// client which doesn't read its responses can't hold up the caller.
+    pub fn flush_outgoing_writes(&mut self) {
+        // Only the connections with queued responses are waited for, the other events of the
+        // server are left for the next call of `requests()`.
+        let Ok(epoll) = Epoll::new() else {
+            return;
+        };
+        let mut waiting = 0;
+        for (token, connection) in &mut self.connections {
+            connection.write();
+            if connection.broken || connection.output.is_empty() {
+                continue;
+            }
+            let event = EpollEvent::new(EventSet::OUT, *token);
+            if epoll
+                .ctl(ControlOperation::Add, connection.stream.as_raw_fd(), event)
+                .is_ok()
+            {
+                waiting += 1;
+            }
+        }
+
+        let deadline = Instant::now() + FLUSH_TIMEOUT;
+        let mut events = [EpollEvent::default(); MAX_CONNECTIONS];
+        while waiting > 0 {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            if timeout.is_zero() {
+                break;
+            }
+            // Rounded up, so that the wait doesn't return early.
+            let timeout_ms = i32::try_from(timeout.as_millis() + 1).unwrap_or(i32::MAX);
+            let count = match epoll.wait(timeout_ms, &mut events) {
+                Ok(count) => count,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(_) => break,
+            };
+            for event in &events[..count] {
+                let Some(connection) = self.connections.get_mut(&event.data()) else {
+                    continue;
+                };
+                connection.write();
+                if connection.broken || connection.output.is_empty() {
+                    let _ = epoll.ctl(
+                        ControlOperation::Delete,
+                        connection.stream.as_raw_fd(),
+                        EpollEvent::default(),
+                    );
+                    waiting -= 1;
+                }
+            }
+        }
+
+        let tokens: Vec<u64> = self.connections.keys().copied().collect();
+        for token in tokens {
+            self.update_connection(token);
+        }
+    }
+
+    #[cfg(feature = "grpc")]
+    fn receive_local_requests(&mut self, requests: &mut Vec<ServerRequest>) {
+        let Some(local_requests) = &mut self.local_requests else {
+            return;
+        };
+        let _ = local_requests.event_fd.read();
+        while let Ok(local_request) = local_requests.receiver.try_recv() {
+            local_requests
+                .pending
+                .push_back(local_request.response_sender);
+            requests.push(ServerRequest {
+                request: local_request.request,
+                id: LOCAL_REQUESTS_TOKEN,
+            });
+        }
+    }
+
+    fn accept_connections(&mut self) -> Result<(), ServerError> {
+        loop {
+            let stream = match self.listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(ServerError::IOError(err)),
+            };
+            if self.connections.len() >= MAX_CONNECTIONS {
+                // Dropping the stream closes the connection.
+                continue;
+            }
+            stream.set_nonblocking(true).map_err(ServerError::IOError)?;
+            let token = self.next_token;
+            self.next_token += 1;
+            let mut connection = ClientConnection::new(stream);
+            connection.registered = connection.event_set();
+            self.epoll
+                .ctl(
+                    ControlOperation::Add,
+                    connection.stream.as_raw_fd(),
+                    EpollEvent::new(connection.registered, token),
+                )
+                .map_err(ServerError::IOError)?;
+            self.connections.insert(token, connection);
+        }
+    }
+
+    /// Closes the connection if it has nothing left to do, or updates the events it waits for.
+    fn update_connection(&mut self, token: u64) {
+        let Some(connection) = self.connections.get_mut(&token) else {
+            return;
+        };
+        if connection.is_done() {
+            // Dropping the stream closes the connection and removes it from the epoll set.
+            self.connections.remove(&token);
+            return;
+        }
+        let event_set = connection.event_set();
+        if event_set == connection.registered {
+            return;
+        }
+        let result = self.epoll.ctl(
+            ControlOperation::Modify,
+            connection.stream.as_raw_fd(),
+            EpollEvent::new(event_set, token),
+        );
+        if result.is_ok() {
+            connection.registered = event_set;
+        } else {
+            self.connections.remove(&token);
+        }
+    }
+}
+
+/// Appends `response` to `out`, with a chunked body if it is large and the client supports it.
+fn encode_response(response: &Response, framing: ResponseFraming, out: &mut Vec<u8>) {
+    let mut bytes = Vec::new();
+    response
+        .write_all(&mut bytes)
+        .expect("Writing a response to memory cannot fail");
+    // The unwrap is safe because responses always end their headers with an empty line.
+    let head_end = find_head_end(&bytes).unwrap();
+    let (head, body) = bytes.split_at(head_end);
+    let chunked = framing.chunked && body.len() > RESPONSE_CHUNK_SIZE;
+
+    let mut pos = 0;
+    while let Some((line, line_len)) = next_line(&head[pos..]) {
+        pos += line_len;
+        let name = line
+            .iter()
+            .position(|c| *c == b':')
+            .map(|colon| &line[..colon]);
+        let skip = line.is_empty()
+            || name.is_some_and(|name| {
+                (chunked && name.eq_ignore_ascii_case(b"content-length"))
+                    || (framing.close && name.eq_ignore_ascii_case(b"connection"))
+            });
+        if !skip {
+            out.extend_from_slice(line);
+            out.extend_from_slice(b"\r\n");
+        }
+    }
+    if framing.close {
+        out.extend_from_slice(b"Connection: close\r\n");
+    }
+    if !chunked {
+        out.extend_from_slice(b"\r\n");
+        out.extend_from_slice(body);
+        return;
+    }
+    out.extend_from_slice(b"Transfer-Encoding: chunked\r\n\r\n");
+    for chunk in body.chunks(RESPONSE_CHUNK_SIZE) {
+        out.extend_from_slice(format!("{:X}\r\n", chunk.len()).as_bytes());
+        out.extend_from_slice(chunk);
+        out.extend_from_slice(b"\r\n");
+    }
+    out.extend_from_slice(b"0\r\n\r\n");
+}
+
+// Returns the line starting at the beginning of `b` without its line terminator, along with the
+// number of bytes it takes up including the terminator, or None if the line is incomplete.
+fn next_line(b: &[u8]) -> Option<(&[u8], usize)> {
+    let len = b.iter().position(|c| *c == b'\n')?;
+    let line = &b[..len];
+    Some((line.strip_suffix(b"\r").unwrap_or(line), len + 1))
+}
+
+// Returns the length of the request line and headers found at the beginning of `b`, including the
+// empty line which ends them, or None if they are incomplete.
+fn find_head_end(b: &[u8]) -> Option<usize> {
+    // We're basically looking for a double new line, which can only appear at the end of the
+    // request line and headers.
+    for i in 0..b.len().saturating_sub(1) {
+        if b[i] == b'\n' {
+            if b[i + 1] == b'\n' {
+                return Some(i + 2);
+            } else if i + 3 <= b.len() && &b[i + 1..i + 3] == b"\r\n" {
+                return Some(i + 3);
+            }
+        }
+    }
+    None
+}
+
+/// Decoder of a chunked request body, which picks up where it left off as more bytes of the body
+/// are received. Chunk extensions and trailer fields are ignored.
+#[derive(Debug, Default)]
+struct ChunkedDecoder {
+    /// The chunks decoded so far.
+    body: Vec<u8>,
+    /// Number of encoded bytes decoded so far.
+    pos: usize,
+    /// Where the trailer fields begin, once the last chunk was decoded.
+    trailer_start: Option<usize>,
+}
+
+impl ChunkedDecoder {
+    /// Decodes the chunked body found at the beginning of `b`, which holds the bytes given to the
+    /// previous calls and the newly received ones. Returns the decoded body along with the number
+    /// of bytes the encoded body takes up, or None if it is incomplete. The encoded body counts
+    /// against `payload_max_size` while it is incomplete, so that a client can't make the server
+    /// buffer an unbounded amount of chunk framing.
+    fn decode(
+        &mut self,
+        b: &[u8],
+        payload_max_size: usize,
+    ) -> Result<Option<(Vec<u8>, usize)>, FramingError> {
+        loop {
+            if let Some(trailer_start) = self.trailer_start {
+                // Skip the trailer fields, up to the empty line which ends the body.
+                let Some((line, line_len)) = next_line(&b[self.pos..]) else {
+                    if b.len() - trailer_start > MAX_HEAD_SIZE {
+                        return Err(FramingError::HeadTooLarge(MAX_HEAD_SIZE));
+                    }
+                    return Self::incomplete(b, payload_max_size);
+                };
+                self.pos += line_len;
+                if line.is_empty() {
+                    return Ok(Some((std::mem::take(&mut self.body), self.pos)));
+                }
+                continue;
+            }
+
+            let Some((line, line_len)) = next_line(&b[self.pos..]) else {
+                if b.len() - self.pos > MAX_HEAD_SIZE {
+                    return Err(FramingError::ChunkLineTooLarge(MAX_HEAD_SIZE));
+                }
+                return Self::incomplete(b, payload_max_size);
+            };
+            if line_len > MAX_HEAD_SIZE {
+                return Err(FramingError::ChunkLineTooLarge(MAX_HEAD_SIZE));
+            }
+            // The unwrap is safe because split() yields at least one item.
+            let size = line.split(|c| *c == b';').next().unwrap();
+            let size = std::str::from_utf8(size)
+                .ok()
+                .and_then(|size| usize::from_str_radix(size.trim(), 16).ok())
+                .ok_or(FramingError::ChunkedBody)?;
+
+            if size == 0 {
+                self.pos += line_len;
+                self.trailer_start = Some(self.pos);
+                continue;
+            }
+
+            let body_len = self.body.len().saturating_add(size);
+            if body_len > payload_max_size {
+                return Err(FramingError::PayloadTooLarge(body_len, payload_max_size));
+            }
+            // The chunk is only consumed once it is complete, so that the next call starts again
+            // from its size line.
+            let data_start = self.pos + line_len;
+            let data_end = data_start
+                .checked_add(size)
+                .ok_or(FramingError::ChunkedBody)?;
+            if b.len() <= data_end {
+                return Self::incomplete(b, payload_max_size);
+            }
+            // The chunk data must be followed by a line terminator.
+            match next_line(&b[data_end..]) {
+                Some((line, line_len)) if line.is_empty() => {
+                    self.body.extend_from_slice(&b[data_start..data_end]);
+                    self.pos = data_end + line_len;
+                }
+                None if b[data_end..] == *b"\r" => return Self::incomplete(b, payload_max_size),
+                _ => return Err(FramingError::ChunkedBody),
+            }
+        }
+    }
+
+    /// Checks the size of the encoded body received so far, which isn't complete yet.
+    fn incomplete(
+        b: &[u8],
+        payload_max_size: usize,
+    ) -> Result<Option<(Vec<u8>, usize)>, FramingError> {
+        if b.len() > payload_max_size {
+            return Err(FramingError::PayloadTooLarge(b.len(), payload_max_size));
+        }
+        Ok(None)
+    }
+}
+
+// Looks for a complete request at the beginning of `b`, whose body is delimited either by the
+// Content-Length header, or by the chunked transfer coding. Returns the number of bytes the request
+// takes up, the request bytes to parse and how to send the response, or None if the request is
+// incomplete. Chunked requests are rewritten to carry their decoded body and a Content-Length
+// header instead, since that's what the HTTP parser understands. `decoder` keeps the progress made
+// on a chunked body between the calls made for the same request.
+fn frame_request<'a>(
+    b: &'a [u8],
+    payload_max_size: usize,
+    decoder: &mut ChunkedDecoder,
+) -> Result<Option<(usize, Cow<'a, [u8]>, ResponseFraming)>, FramingError> {
+    let Some(head_end) = find_head_end(b) else {
+        if b.len() > MAX_HEAD_SIZE {
+            return Err(FramingError::HeadTooLarge(MAX_HEAD_SIZE));
+        }
+        return Ok(None);
+    };
+    if head_end > MAX_HEAD_SIZE {
+        return Err(FramingError::HeadTooLarge(MAX_HEAD_SIZE));
+    }
+    let head = &b[..head_end];
+
+    // The unwrap is safe because the head ends with a line terminator.
+    let (request_line, mut pos) = next_line(head).unwrap();
+    let mut framing = ResponseFraming {
+        close: false,
+        chunked: !request_line.ends_with(b"HTTP/1.0"),
+    };
+    let mut content_length = None;
+    let mut chunked = false;
+    while let Some((line, line_len)) = next_line(&head[pos..]) {
+        pos += line_len;
+        let Some(colon) = line.iter().position(|c| *c == b':') else {
+            continue;
+        };
+        let (name, value) = (&line[..colon], line[colon + 1..].trim_ascii());
+        if name.eq_ignore_ascii_case(b"content-length") {
+            let length = std::str::from_utf8(value)
+                .ok()
+                .and_then(|value| value.parse::<usize>().ok())
+                .ok_or(FramingError::ContentLength)?;
+            if content_length.is_some_and(|current| current != length) {
+                return Err(FramingError::ContentLength);
+            }
+            content_length = Some(length);
+        } else if name.eq_ignore_ascii_case(b"transfer-encoding") {
+            for coding in value.split(|c| *c == b',') {
+                // The unwrap is safe because split() yields at least one item.
+                let coding = coding.split(|c| *c == b';').next().unwrap().trim_ascii();
+                if coding.eq_ignore_ascii_case(b"chunked") {
+                    chunked = true;
+                } else if !coding.eq_ignore_ascii_case(b"identity") {
+                    return Err(FramingError::TransferEncoding);
+                }
+            }
+        } else if name.eq_ignore_ascii_case(b"connection") {
+            framing.close |= value
+                .split(|c| *c == b',')
+                .any(|option| option.trim_ascii().eq_ignore_ascii_case(b"close"));
+        }
+    }
+
+    match (content_length, chunked) {
+        (Some(_), true) => Err(FramingError::LengthAndEncoding),
+        (Some(length), false) => {
+            if length > payload_max_size {
+                return Err(FramingError::PayloadTooLarge(length, payload_max_size));
+            }
+            let end = head_end + length;
+            Ok((end <= b.len()).then(|| (end, Cow::Borrowed(&b[..end]), framing)))
+        }
+        (None, true) => {
+            let Some((body, body_len)) = decoder.decode(&b[head_end..], payload_max_size)? else {
+                return Ok(None);
+            };
+            let mut request = Vec::with_capacity(head_end + body.len() + 32);
+            let mut pos = 0;
+            while let Some((line, line_len)) = next_line(&head[pos..]) {
+                pos += line_len;
+                let is_encoding = line
+                    .iter()
+                    .position(|c| *c == b':')
+                    .is_some_and(|colon| line[..colon].eq_ignore_ascii_case(b"transfer-encoding"));
+                if !line.is_empty() && !is_encoding {
+                    request.extend_from_slice(line);
+                    request.extend_from_slice(b"\r\n");
+                }
+            }
+            request.extend_from_slice(format!("Content-Length: {}\r\n\r\n", body.len()).as_bytes());
+            request.extend_from_slice(&body);
+            Ok(Some((head_end + body_len, Cow::Owned(request), framing)))
+        }
+        (None, false) => Ok(Some((head_end, Cow::Borrowed(head), framing))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use vmm_sys_util::tempdir::TempDir;
+
+    use super::*;
+
+    const LIMIT: usize = 64;
+
+    type Framed<'a> = Result<Option<(usize, Cow<'a, [u8]>, ResponseFraming)>, FramingError>;
+
+    // Frames the request at the beginning of `b` in one go.
+    fn frame(b: &[u8]) -> Framed<'_> {
+        frame_request(b, LIMIT, &mut ChunkedDecoder::default())
+    }
+
+    #[test]
+    fn test_frame_request() {
+        // The request line and headers are incomplete.
+        assert_eq!(frame(b"GET / HTTP/1.1\r\n"), Ok(None));
+
+        // Requests without a body end with the headers.
+        let request = b"GET / HTTP/1.1\r\nAccept: */*\r\n\r\nGET";
+        let (len, bytes, framing) = frame(request).unwrap().unwrap();
+        assert_eq!(len, request.len() - 3);
+        assert_eq!(bytes.as_ref(), &request[..len]);
+        assert_eq!(
+            framing,
+            ResponseFraming {
+                close: false,
+                chunked: true
+            }
+        );
+
+        // HTTP/1.0 clients don't get chunked responses, and clients may ask to close the
+        // connection.
+        let request = b"GET / HTTP/1.0\r\nConnection: keep-alive, Close\r\n\r\n";
+        let (_, _, framing) = frame(request).unwrap().unwrap();
+        assert_eq!(
+            framing,
+            ResponseFraming {
+                close: true,
+                chunked: false
+            }
+        );
+
+        // The body is delimited by the Content-Length header.
+        let request = b"PUT /snapshot/create HTTP/1.1\r\ncontent-length: 5\r\n\r\nhello";
+        for len in request.len() - 5..request.len() {
+            assert_eq!(frame(&request[..len]), Ok(None));
+        }
+        let pipelined = [request.as_ref(), b"GET".as_ref()].concat();
+        let (len, bytes, _) = frame(&pipelined).unwrap().unwrap();
+        assert_eq!(len, request.len());
+        assert_eq!(bytes.as_ref(), request.as_ref());
+
+        // Chunked bodies are decoded as they are received, and the request gets a Content-Length
+        // header instead.
+        let request = b"PUT /snapshot/create HTTP/1.1\r\n\
+                        Transfer-Encoding: identity, Chunked\r\n\
+                        Accept: */*\r\n\r\n\
+                        5;name=value\r\nhello\r\n6\n world\r\n0\r\nTrailer: x\r\n\r\n";
+        let head_len = request.len() - 48;
+        let mut decoder = ChunkedDecoder::default();
+        for len in head_len..request.len() {
+            assert_eq!(
+                frame_request(&request[..len], LIMIT, &mut decoder),
+                Ok(None),
+                "{}",
+                len
+            );
+        }
+        // The chunks received so far aren't decoded again.
+        assert_eq!(decoder.body, b"hello world");
+        assert_eq!(decoder.trailer_start, Some(34));
+        let pipelined = [request.as_ref(), b"GET".as_ref()].concat();
+        let (len, bytes, _) = frame_request(&pipelined, LIMIT, &mut decoder)
+            .unwrap()
+            .unwrap();
+        assert_eq!(len, request.len());
+        assert_eq!(
+            bytes.as_ref(),
+            b"PUT /snapshot/create HTTP/1.1\r\nAccept: */*\r\nContent-Length: 11\r\n\r\nhello world"
+        );
+        let request = Request::try_from(&bytes, Some(LIMIT)).unwrap();
+        assert_eq!(request.body.unwrap().raw(), b"hello world");
+
+        // Let's look at the requests which can't be framed.
+        for (request, err) in [
+            (
+                b"PUT / HTTP/1.1\r\nContent-Length: alpha\r\n\r\n".as_ref(),
+                FramingError::ContentLength,
+            ),
+            (
+                b"PUT / HTTP/1.1\r\nContent-Length: 1\r\nContent-Length: 2\r\n\r\n".as_ref(),
+                FramingError::ContentLength,
+            ),
+            (
+                b"PUT / HTTP/1.1\r\nContent-Length: 1\r\nTransfer-Encoding: chunked\r\n\r\n"
+                    .as_ref(),
+                FramingError::LengthAndEncoding,
+            ),
+            (
+                b"PUT / HTTP/1.1\r\nTransfer-Encoding: gzip, chunked\r\n\r\n".as_ref(),
+                FramingError::TransferEncoding,
+            ),
+            (
+                b"PUT / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n".as_ref(),
+                FramingError::ChunkedBody,
+            ),
+            (
+                b"PUT / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n1\r\nab\r\n".as_ref(),
+                FramingError::ChunkedBody,
+            ),
+            (
+                b"PUT / HTTP/1.1\r\nContent-Length: 65\r\n\r\n".as_ref(),
+                FramingError::PayloadTooLarge(65, LIMIT),
+            ),
+        ] {
+            assert_eq!(frame(request), Err(err));
+        }
+
+        // Chunked bodies are checked against the limit as they are decoded. The first chunk fits,
+        // the second one doesn't.
+        let request = [
+            b"PUT / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n20\r\n".as_ref(),
+            &[b'a'; 32],
+            b"\r\n21\r\n",
+        ]
+        .concat();
+        assert_eq!(
+            frame(&request),
+            Err(FramingError::PayloadTooLarge(65, LIMIT))
+        );
+
+        // The chunk framing counts against the limit while the body is incomplete, as the server
+        // has to buffer it.
+        let request = [
+            b"PUT / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n".as_ref(),
+            &b"1;extension\r\na\r\n".repeat(5),
+        ]
+        .concat();
+        assert_eq!(
+            frame(&request),
+            Err(FramingError::PayloadTooLarge(80, LIMIT))
+        );
+
+        // The chunk size lines are limited, whether they are complete or not.
+        let line = [b"1;".as_ref(), &[b'a'; MAX_HEAD_SIZE]].concat();
+        let head = b"PUT / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n".as_ref();
+        for request in [[head, &line].concat(), [head, &line, b"\r\na"].concat()] {
+            assert_eq!(
+                frame_request(&request, usize::MAX, &mut ChunkedDecoder::default()),
+                Err(FramingError::ChunkLineTooLarge(MAX_HEAD_SIZE))
+            );
+        }
+
+        // The request line and headers are limited too.
+        assert_eq!(
+            frame(&[b'a'; MAX_HEAD_SIZE + 1]),
+            Err(FramingError::HeadTooLarge(MAX_HEAD_SIZE))
+        );
+    }
+
+    #[test]
+    fn test_encode_response() {
+        let mut response = Response::new(Version::Http11, StatusCode::OK);
+        response.set_body(Body::new(vec![b'a'; RESPONSE_CHUNK_SIZE + 1]));
+        let mut expected = Vec::new();
+        response.write_all(&mut expected).unwrap();
+
+        // Small bodies, and bodies sent to HTTP/1.0 clients, are not chunked.
+        let mut out = Vec::new();
+        encode_response(&response, ResponseFraming::default(), &mut out);
+        assert_eq!(out, expected);
+
+        let mut out = Vec::new();
+        let framing = ResponseFraming {
+            close: true,
+            chunked: true,
+        };
+        encode_response(&response, framing, &mut out);
+        let head_end = find_head_end(&out).unwrap();
+        let head = std::str::from_utf8(&out[..head_end]).unwrap();
+        assert!(head.contains("Connection: close\r\n"));
+        assert!(head.contains("Transfer-Encoding: chunked\r\n"));
+        assert!(!head.contains("keep-alive"));
+        assert!(!head.contains("Content-Length"));
+        let (body, len) = ChunkedDecoder::default()
+            .decode(&out[head_end..], usize::MAX)
+            .unwrap()
+            .unwrap();
+        assert_eq!(head_end + len, out.len());
+        assert_eq!(body, vec![b'a'; RESPONSE_CHUNK_SIZE + 1]);
+    }
+
+    #[test]
+    fn test_pipelined_requests() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = tmp_dir.as_path().join("api.socket");
+        let mut server = HttpServer::new(&path).unwrap();
+        server.set_payload_max_size(LIMIT);
+        server.start_server().unwrap();
+
+        let mut client = UnixStream::connect(&path).unwrap();
+        let client_thread = thread::spawn(move || {
+            client
+                .write_all(
+                    b"PUT /snapshot/create HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n\
+                      5\r\nhello\r\n0\r\n\r\n\
+                      GET / HTTP/1.1\r\n\r\n\
+                      GET / HTTP/1.1\r\nContent-Length: 1000\r\n\r\n",
+                )
+                .unwrap();
+            let mut responses = Vec::new();
+            client.read_to_end(&mut responses).unwrap();
+            responses
+        });
+
+        let mut requests = Vec::new();
+        while requests.len() < 2 {
+            requests.extend(server.requests().unwrap());
+        }
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].request.body.as_ref().unwrap().raw(), b"hello");
+        for (request, status) in requests.iter().zip([StatusCode::NoContent, StatusCode::OK]) {
+            let response = request.process(|_| Response::new(Version::Http11, status));
+            server.respond(response).unwrap();
+        }
+        // The connection is closed once the error response is written.
+        while !server.connections.is_empty() {
+            server.requests().unwrap();
+        }
+
+        let responses = String::from_utf8(client_thread.join().unwrap()).unwrap();
+        let statuses: Vec<_> = responses
+            .match_indices("HTTP/1.1 ")
+            .map(|(pos, _)| &responses[pos + 9..pos + 12])
+            .collect();
+        assert_eq!(statuses, ["204", "200", "413"]);
+        assert!(responses.ends_with(
+            "{ \"error\": \"Request payload with size 1000 is larger than the limit of 64 allowed \
+             by server.\" }"
+        ));
+    }
+
+    #[cfg(feature = "grpc")]
+    #[test]
+    fn test_local_requests() {
+        let tmp_dir = TempDir::new().unwrap();
+        let mut server = HttpServer::new(tmp_dir.as_path().join("api.socket")).unwrap();
+        server.start_server().unwrap();
+        let (sender, receiver) = mpsc::channel();
+        let event_fd = EventFd::new(libc::EFD_NONBLOCK).unwrap();
+        server
+            .add_local_requests(LocalRequests::new(receiver, event_fd.try_clone().unwrap()))
+            .unwrap();
+
+        let mut response_receivers = Vec::new();
+        for _ in 0..2 {
+            let (response_sender, response_receiver) = oneshot::channel();
+            let request = Request::try_from(b"GET / HTTP/1.1\r\n\r\n", None).unwrap();
+            sender
+                .send(LocalRequest {
+                    request,
+                    response_sender,
+                })
+                .unwrap();
+            response_receivers.push(response_receiver);
+        }
+        event_fd.write(1).unwrap();
+
+        // The local requests are answered in order, on their own channel.
+        let requests = server.requests().unwrap();
+        assert_eq!(requests.len(), 2);
+        for (request, status) in requests.iter().zip([StatusCode::NoContent, StatusCode::OK]) {
+            let response = request.process(|_| Response::new(Version::Http11, status));
+            server.respond(response).unwrap();
+        }
+        for (receiver, status) in response_receivers
+            .into_iter()
+            .zip([StatusCode::NoContent, StatusCode::OK])
+        {
+            assert_eq!(receiver.blocking_recv().unwrap().status(), status);
+        }
+
+        // Once taken, the local requests are no longer served by the server.
+        assert!(server.take_local_requests().is_some());
+        assert!(server.take_local_requests().is_none());
+    }
+}
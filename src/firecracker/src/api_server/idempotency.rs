@@ -0,0 +1,189 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Replays the responses to retried API requests.
+//!
+//! `PUT` and `PATCH` requests can carry an `Idempotency-Key` header. The responses to the last
+//! [`IDEMPOTENCY_CACHE_SIZE`] such requests are kept, and a later request with the same key is
+//! answered with the same response instead of being handled again. Clients which retry requests
+//! whose response was lost therefore do not apply their action twice.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::VecDeque;
+use std::hash::{Hash, Hasher};
+
+use micro_http::{Body, Method, Request, Response, StatusCode, Version};
+
+/// Header holding the idempotency key of a request.
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+/// Maximum length of an idempotency key.
+pub const MAX_IDEMPOTENCY_KEY_LEN: usize = 255;
+/// Number of requests whose response is kept.
+pub const IDEMPOTENCY_CACHE_SIZE: usize = 64;
+
+/// Errors associated with idempotency keys.
+#[derive(Debug, PartialEq, Eq, thiserror::Error, displaydoc::Display)]
+pub enum IdempotencyKeyError {
+    /// Invalid idempotency key {0:?}, expected 1 to 255 printable ASCII characters.
+    InvalidKey(String),
+}
+
+/// Returns the idempotency key of `request`, if it is a `PUT` or `PATCH` request carrying one.
+pub fn idempotency_key(request: &Request) -> Result<Option<String>, IdempotencyKeyError> {
+    if !matches!(request.method(), Method::Put | Method::Patch) {
+        return Ok(None);
+    }
+    let Some(key) = request
+        .headers
+        .custom_entries()
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(IDEMPOTENCY_KEY_HEADER))
+        .map(|(_, key)| key)
+    else {
+        return Ok(None);
+    };
+    if key.is_empty()
+        || key.len() > MAX_IDEMPOTENCY_KEY_LEN
+        || !key.bytes().all(|byte| byte.is_ascii_graphic())
+    {
+        return Err(IdempotencyKeyError::InvalidKey(key.clone()));
+    }
+    Ok(Some(key.clone()))
+}
+
+/// Returns a fingerprint of the method, path and body of `request`, telling apart different
+/// requests sent with the same idempotency key.
+pub fn fingerprint(request: &Request) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    (request.method() == Method::Put).hash(&mut hasher);
+    request.uri().get_abs_path().hash(&mut hasher);
+    request.body.as_ref().map(Body::raw).hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Outcome of looking up a request in the [`IdempotencyCache`].
+#[derive(Debug)]
+pub enum Lookup {
+    /// The key was not used before: the request must be handled, and its response recorded
+    /// with [`IdempotencyCache::finish`].
+    New,
+    /// The request was already handled, and was answered with this response.
+    Replay(Response),
+    /// The request with the same key is still being handled.
+    InFlight,
+    /// The key was used for a different request.
+    Mismatch,
+}
+
+#[derive(Debug)]
+struct Entry {
+    key: String,
+    fingerprint: u64,
+    response: Option<(StatusCode, Option<Body>)>,
+}
+
+/// Responses to the last requests carrying an idempotency key.
+#[derive(Debug)]
+pub struct IdempotencyCache {
+    entries: VecDeque<Entry>,
+    capacity: usize,
+}
+
+impl Default for IdempotencyCache {
+    fn default() -> Self {
+        Self::new(IDEMPOTENCY_CACHE_SIZE)
+    }
+}
+
+impl IdempotencyCache {
+    /// Creates a cache keeping the responses to the last `capacity` requests.
+    pub fn new(capacity: usize) -> Self {
+        IdempotencyCache {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Looks up the request with the given key and fingerprint. A new request is recorded as
+    /// in flight, evicting the oldest request if the cache is full.
+    pub fn start(&mut self, key: &str, fingerprint: u64) -> Lookup {
+        if let Some(entry) = self.entries.iter().find(|entry| entry.key == key) {
+            return match &entry.response {
+                _ if entry.fingerprint != fingerprint => Lookup::Mismatch,
+                Some((status, body)) => {
+                    let mut response = Response::new(Version::Http11, *status);
+                    if let Some(body) = body {
+                        response.set_body(body.clone());
+                    }
+                    Lookup::Replay(response)
+                }
+                None => Lookup::InFlight,
+            };
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(Entry {
+            key: key.to_string(),
+            fingerprint,
+            response: None,
+        });
+        Lookup::New
+    }
+
+    /// Records the response to the in flight request with the given key.
+    pub fn finish(&mut self, key: &str, response: &Response) {
+        if let Some(entry) = self.entries.iter_mut().find(|entry| entry.key == key) {
+            entry.response = Some((response.status(), response.body()));
+        }
+    }
+
+    /// Forgets the in flight request with the given key, which was not handled.
+    pub fn forget(&mut self, key: &str) {
+        self.entries
+            .retain(|entry| entry.key != key || entry.response.is_some());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(status: StatusCode, body: &str) -> Response {
+        let mut response = Response::new(Version::Http11, status);
+        response.set_body(Body::new(body));
+        response
+    }
+
+    #[test]
+    fn test_idempotency_cache() {
+        let mut cache = IdempotencyCache::new(2);
+
+        assert!(matches!(cache.start("a", 1), Lookup::New));
+        assert!(matches!(cache.start("a", 1), Lookup::InFlight));
+        assert!(matches!(cache.start("a", 2), Lookup::Mismatch));
+        cache.finish("a", &response(StatusCode::BadRequest, "error"));
+        match cache.start("a", 1) {
+            Lookup::Replay(replay) => {
+                assert_eq!(replay.status(), StatusCode::BadRequest);
+                assert_eq!(replay.body().unwrap().raw(), b"error");
+            }
+            lookup => panic!("unexpected lookup: {lookup:?}"),
+        }
+        assert!(matches!(cache.start("a", 2), Lookup::Mismatch));
+
+        // Requests which were not handled can be sent again.
+        assert!(matches!(cache.start("b", 1), Lookup::New));
+        cache.forget("b");
+        assert!(matches!(cache.start("b", 1), Lookup::New));
+        cache.finish("b", &response(StatusCode::NoContent, ""));
+        // Handled requests are not forgotten.
+        cache.forget("b");
+        assert!(matches!(cache.start("b", 1), Lookup::Replay(_)));
+
+        // The oldest request is evicted once the cache is full.
+        assert!(matches!(cache.start("c", 1), Lookup::New));
+        assert!(matches!(cache.start("a", 1), Lookup::New));
+        assert!(matches!(cache.start("c", 1), Lookup::InFlight));
+    }
+}
@@ -0,0 +1,134 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Defines how long the API server waits for the VMM to handle a request.
+//!
+//! Requests taking longer than the timeout of their endpoint are answered with
+//! `503 Service Unavailable`, while the VMM keeps handling them in the background. Up to
+//! `max_pending_requests` such requests can be pending on the VMM: further requests are answered
+//! with `429 Too Many Requests` until the VMM catches up.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Default number of timed out requests which can be pending on the VMM.
+pub const DEFAULT_MAX_PENDING_REQUESTS: usize = 1;
+/// Delay after which clients are advised to retry requests rejected because the VMM is busy.
+pub const RETRY_AFTER_SECS: u64 = 1;
+
+/// Errors associated with parsing the API request timeouts.
+#[derive(Debug, PartialEq, Eq, thiserror::Error, displaydoc::Display)]
+pub enum RequestTimeoutsError {
+    /// Invalid API request timeout {0:?}, expected `[<endpoint>=]<milliseconds>`.
+    InvalidTimeout(String),
+    /// Duplicate API request timeout for endpoint {0:?}.
+    DuplicateEndpoint(String),
+}
+
+/// Maximum time the API server waits for the VMM to handle a request, per endpoint.
+///
+/// Parsed from a comma separated list of `[<endpoint>=]<milliseconds>` entries, where the
+/// endpoint is the first segment of the request path, e.g. `500,snapshot=60000`. The entry
+/// without endpoint applies to all the other endpoints. Requests to endpoints without timeout
+/// wait for the VMM indefinitely.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RequestTimeouts {
+    default: Option<Duration>,
+    endpoints: HashMap<String, Duration>,
+}
+
+impl RequestTimeouts {
+    /// Returns the timeout of requests to the endpoint `endpoint`, if any.
+    pub fn timeout(&self, endpoint: &str) -> Option<Duration> {
+        self.endpoints.get(endpoint).copied().or(self.default)
+    }
+}
+
+impl FromStr for RequestTimeouts {
+    type Err = RequestTimeoutsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut timeouts = RequestTimeouts::default();
+        for entry in s.split(',').map(str::trim) {
+            let (endpoint, millis) = match entry.split_once('=') {
+                Some((endpoint, millis)) => (Some(endpoint.trim()), millis.trim()),
+                None => (None, entry),
+            };
+            let timeout = millis
+                .parse::<u64>()
+                .ok()
+                .filter(|millis| *millis > 0)
+                .map(Duration::from_millis)
+                .ok_or_else(|| RequestTimeoutsError::InvalidTimeout(entry.to_string()))?;
+            let previous = match endpoint {
+                Some(endpoint) if endpoint.is_empty() || endpoint.contains('/') => {
+                    return Err(RequestTimeoutsError::InvalidTimeout(entry.to_string()));
+                }
+                Some(endpoint) => timeouts.endpoints.insert(endpoint.to_string(), timeout),
+                None => timeouts.default.replace(timeout),
+            };
+            if previous.is_some() {
+                return Err(RequestTimeoutsError::DuplicateEndpoint(
+                    endpoint.unwrap_or_default().to_string(),
+                ));
+            }
+        }
+        Ok(timeouts)
+    }
+}
+
+/// Latency budget of the requests forwarded to the VMM.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LatencyBudget {
+    /// Timeouts of the requests, per endpoint.
+    pub timeouts: RequestTimeouts,
+    /// Maximum number of timed out requests which can be pending on the VMM.
+    pub max_pending_requests: usize,
+}
+
+impl Default for LatencyBudget {
+    fn default() -> Self {
+        LatencyBudget {
+            timeouts: RequestTimeouts::default(),
+            max_pending_requests: DEFAULT_MAX_PENDING_REQUESTS,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_request_timeouts() {
+        let timeouts = RequestTimeouts::from_str("500, snapshot=60000,actions = 2000").unwrap();
+        assert_eq!(timeouts.timeout("drives"), Some(Duration::from_millis(500)));
+        assert_eq!(
+            timeouts.timeout("snapshot"),
+            Some(Duration::from_millis(60000))
+        );
+        assert_eq!(
+            timeouts.timeout("actions"),
+            Some(Duration::from_millis(2000))
+        );
+
+        let timeouts = RequestTimeouts::from_str("snapshot=60000").unwrap();
+        assert_eq!(timeouts.timeout("drives"), None);
+
+        for invalid in ["", "0", "snapshot=", "=100", "snapshot/create=100", "1s"] {
+            assert_eq!(
+                RequestTimeouts::from_str(invalid),
+                Err(RequestTimeoutsError::InvalidTimeout(invalid.to_string()))
+            );
+        }
+        assert_eq!(
+            RequestTimeouts::from_str("100,vm=10,vm=20"),
+            Err(RequestTimeoutsError::DuplicateEndpoint("vm".to_string()))
+        );
+        assert_eq!(
+            RequestTimeouts::from_str("100,200"),
+            Err(RequestTimeoutsError::DuplicateEndpoint(String::new()))
+        );
+    }
+}
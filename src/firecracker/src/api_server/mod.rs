@@ -6,24 +6,76 @@
 //! It is constructed on top of an HTTP Server that uses Unix Domain Sockets and `EPOLL` to
 //! handle multiple connections on the same thread.
 
+pub mod guest_scope;
+pub mod http_server;
+pub mod idempotency;
+pub mod latency_budget;
 pub mod parsed_request;
 pub mod request;
 
+use std::collections::VecDeque;
 use std::fmt::Debug;
-use std::sync::mpsc;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant};
 
-pub use micro_http::{Body, HttpServer, Request, Response, ServerError, StatusCode, Version};
+use guest_scope::check_guest_request;
+pub use http_server::{HttpServer, ServerError};
+use idempotency::{fingerprint, idempotency_key, IdempotencyCache, Lookup};
+use latency_budget::{LatencyBudget, RETRY_AFTER_SECS};
+use micro_http::Method;
+pub use micro_http::{Body, Request, Response, StatusCode, Version};
 use parsed_request::{ParsedRequest, RequestAction};
 use seccompiler::BpfProgramRef;
 use serde_json::json;
 use utils::time::{get_time_us, ClockType};
 use vmm::logger::{
-    debug, error, info, update_metric_with_elapsed_time, warn, ProcessTimeReporter, METRICS,
+    debug, error, info, update_metric_with_elapsed_time, warn, IncMetric, ProcessTimeReporter,
+    METRICS,
 };
-use vmm::rpc_interface::{ApiRequest, ApiResponse, VmmAction};
+use vmm::rpc_interface::{ApiRequest, ApiResponse, VmmAction, VmmData};
+use vmm::vmm_config::seccomp::SeccompFiltersError;
 use vmm::vmm_config::snapshot::SnapshotType;
 use vmm_sys_util::eventfd::EventFd;
 
+/// Set when the API socket should be re-created on the next kill switch event.
+static SOCKET_REBIND_REQUESTED: AtomicBool = AtomicBool::new(false);
+/// Set when the API thread should stop. Takes precedence over rebind requests.
+static SHUTDOWN_REQUESTED: AtomicBool = AtomicBool::new(false);
+/// Kill switch of the API server which supports socket rebinds, or -1 if there is none.
+static REBIND_KILL_SWITCH_FD: AtomicI32 = AtomicI32::new(-1);
+
+/// Requests the API server to re-create its socket.
+///
+/// Only performs async-signal-safe operations so it can be called from a signal handler.
+pub fn request_socket_rebind() {
+    let fd = REBIND_KILL_SWITCH_FD.load(Ordering::SeqCst);
+    if fd < 0 {
+        return;
+    }
+    SOCKET_REBIND_REQUESTED.store(true, Ordering::SeqCst);
+    let val: u64 = 1;
+    // SAFETY: `fd` is the kill switch eventfd of the running API server and `val` is a valid
+    // 8 byte buffer. A failed write only means that the request is lost.
+    unsafe {
+        libc::write(
+            fd,
+            std::ptr::addr_of!(val).cast(),
+            std::mem::size_of::<u64>(),
+        )
+    };
+}
+
+/// Stops the API server by writing to its kill switch.
+///
+/// Unlike writing to the kill switch directly, this cannot be mistaken for a rebind request.
+pub fn request_shutdown(kill_switch: &EventFd) -> std::io::Result<()> {
+    SHUTDOWN_REQUESTED.store(true, Ordering::SeqCst);
+    kill_switch.write(1)
+}
+
 /// Structure associated with the API server implementation.
 #[derive(Debug)]
 pub struct ApiServer {
@@ -34,6 +86,17 @@ pub struct ApiServer {
     /// FD on which we notify the VMM that we have sent at least one
     /// `VmmRequest`.
     to_vmm_fd: EventFd,
+    /// Socket path and kill switch used to re-create the API socket.
+    socket_rebind: Option<(PathBuf, EventFd)>,
+    /// How long requests can wait for the VMM.
+    latency_budget: LatencyBudget,
+    /// Idempotency keys of the timed out requests whose response has not been received from the
+    /// VMM yet, oldest first.
+    pending_requests: VecDeque<Option<String>>,
+    /// Responses to the last requests carrying an idempotency key.
+    idempotency_cache: IdempotencyCache,
+    /// Whether only the requests allowed on the guest API are served.
+    guest_scope: bool,
 }
 
 impl ApiServer {
@@ -49,9 +112,36 @@ impl ApiServer {
             api_request_sender,
             vmm_response_receiver,
             to_vmm_fd,
+            socket_rebind: None,
+            latency_budget: LatencyBudget::default(),
+            pending_requests: VecDeque::new(),
+            idempotency_cache: IdempotencyCache::default(),
+            guest_scope: false,
         }
     }
 
+    /// Bounds the time requests wait for the VMM, and the number of timed out requests which can
+    /// be pending on it.
+    pub fn with_latency_budget(mut self, latency_budget: LatencyBudget) -> Self {
+        self.latency_budget = latency_budget;
+        self
+    }
+
+    /// Allows the API socket to be re-created at `bind_path`, either on
+    /// [`request_socket_rebind`] or when the listener errors after the socket file is gone.
+    ///
+    /// `kill_switch` must be a clone of the kill switch of the server passed to `run`.
+    pub fn with_socket_rebind(mut self, bind_path: PathBuf, kill_switch: EventFd) -> Self {
+        self.socket_rebind = Some((bind_path, kill_switch));
+        self
+    }
+
+    /// Only serves the requests allowed on the guest API, see [`guest_scope`].
+    pub fn with_guest_scope(mut self) -> Self {
+        self.guest_scope = true;
+        self
+    }
+
     /// Runs the Api Server.
     ///
     /// # Arguments
@@ -87,10 +177,21 @@ impl ApiServer {
 
         server.start_server().expect("Cannot start HTTP server");
 
+        if let Some((_, kill_switch)) = &self.socket_rebind {
+            REBIND_KILL_SWITCH_FD.store(kill_switch.as_raw_fd(), Ordering::SeqCst);
+        }
+
         loop {
             let request_vec = match server.requests() {
                 Ok(vec) => vec,
                 Err(ServerError::ShutdownEvent) => {
+                    if self.take_rebind_request() {
+                        self.rebind_socket(&mut server, api_payload_limit);
+                        continue;
+                    }
+                    if self.socket_rebind.is_some() {
+                        REBIND_KILL_SWITCH_FD.store(-1, Ordering::SeqCst);
+                    }
                     server.flush_outgoing_writes();
                     debug!("shutdown request received, API server thread ending.");
                     return;
@@ -98,6 +199,9 @@ impl ApiServer {
                 Err(err) => {
                     // print request error, but keep server running
                     error!("API Server error on retrieving incoming request: {}", err);
+                    if self.socket_lost() {
+                        self.rebind_socket(&mut server, api_payload_limit);
+                    }
                     continue;
                 }
             };
@@ -116,19 +220,159 @@ impl ApiServer {
         }
     }
 
+    /// Checks whether the last kill switch event was a rebind request.
+    fn take_rebind_request(&self) -> bool {
+        let Some((_, kill_switch)) = &self.socket_rebind else {
+            return false;
+        };
+        if SHUTDOWN_REQUESTED.load(Ordering::SeqCst)
+            || !SOCKET_REBIND_REQUESTED.swap(false, Ordering::SeqCst)
+        {
+            return false;
+        }
+        // Drain the kill switch so that the new server does not see this request again.
+        let _ = kill_switch.read();
+        // The drain may have consumed a shutdown request issued in the meantime.
+        !SHUTDOWN_REQUESTED.load(Ordering::SeqCst)
+    }
+
+    /// Checks whether the API socket file has disappeared.
+    fn socket_lost(&self) -> bool {
+        self.socket_rebind
+            .as_ref()
+            .is_some_and(|(bind_path, _)| !bind_path.exists())
+    }
+
+    /// Replaces `server` with a new one listening on a freshly created socket.
+    fn rebind_socket(&self, server: &mut HttpServer, api_payload_limit: usize) {
+        let Some((bind_path, kill_switch)) = &self.socket_rebind else {
+            return;
+        };
+        match Self::bind_server(bind_path, kill_switch, api_payload_limit) {
+            Ok(new_server) => {
+                server.flush_outgoing_writes();
+                // The gRPC requests keep going through the new server.
+                #[cfg(feature = "grpc")]
+                let new_server = {
+                    let mut new_server = new_server;
+                    if let Some(local_requests) = server.take_local_requests() {
+                        if let Err(err) = new_server.add_local_requests(local_requests) {
+                            error!("Failed to keep serving the gRPC requests: {}", err);
+                        }
+                    }
+                    new_server
+                };
+                // Dropping the previous server closes its listener and connections.
+                *server = new_server;
+                METRICS.api_server.socket_rebinds.inc();
+                info!("API socket re-created at {}.", bind_path.display());
+            }
+            Err(err) => {
+                METRICS.api_server.socket_rebind_fails.inc();
+                error!(
+                    "Failed to re-create the API socket at {}: {}",
+                    bind_path.display(),
+                    err
+                );
+            }
+        }
+    }
+
+    fn bind_server(
+        bind_path: &Path,
+        kill_switch: &EventFd,
+        api_payload_limit: usize,
+    ) -> Result<HttpServer, ServerError> {
+        if let Some(parent) = bind_path.parent() {
+            std::fs::create_dir_all(parent).map_err(ServerError::IOError)?;
+        }
+        // Remove the previous socket file, if any, so that the path can be bound again.
+        match std::fs::remove_file(bind_path) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                return Err(ServerError::IOError(err));
+            }
+            _ => (),
+        }
+        let mut server = HttpServer::new(bind_path)?;
+        server.set_payload_max_size(api_payload_limit);
+        server.add_kill_switch(kill_switch.try_clone().map_err(ServerError::IOError)?)?;
+        server.start_server()?;
+        Ok(server)
+    }
+
     /// Handles an API request received through the associated socket.
     pub fn handle_request(
         &mut self,
         request: &Request,
         request_processing_start_us: u64,
     ) -> Response {
+        if self.guest_scope {
+            if let Err(err) = check_guest_request(request) {
+                METRICS.api_server.guest_rejections.inc();
+                warn!("{}", err);
+                return Self::json_response(
+                    StatusCode::NotFound,
+                    Self::json_fault_message(err.to_string()),
+                );
+            }
+        }
         match ParsedRequest::try_from(request).map(|r| r.into_parts()) {
             Ok((req_action, mut parsing_info)) => {
-                let mut response = match req_action {
-                    RequestAction::Sync(vmm_action) => {
-                        self.serve_vmm_action_request(vmm_action, request_processing_start_us)
+                let key = match idempotency_key(request) {
+                    Ok(key) => key,
+                    Err(err) => {
+                        error!("{}", err);
+                        return Self::json_response(
+                            StatusCode::BadRequest,
+                            Self::json_fault_message(err.to_string()),
+                        );
                     }
                 };
+                if let Some(key) = &key {
+                    match self.idempotency_cache.start(key, fingerprint(request)) {
+                        Lookup::New => (),
+                        Lookup::Replay(response) => {
+                            METRICS.api_server.idempotent_replays.inc();
+                            info!(
+                                "Replaying the response to the request with idempotency key \
+                                 {key:?}."
+                            );
+                            return response;
+                        }
+                        Lookup::InFlight => {
+                            return Self::busy_response(
+                                StatusCode::ServiceUnavailable,
+                                "The request with the same idempotency key is still being handled.",
+                            );
+                        }
+                        Lookup::Mismatch => {
+                            return Self::json_response(
+                                StatusCode::BadRequest,
+                                Self::json_fault_message(
+                                    "The idempotency key was already used for a different request.",
+                                ),
+                            );
+                        }
+                    }
+                }
+                let endpoint = request
+                    .uri()
+                    .get_abs_path()
+                    .trim_start_matches('/')
+                    .split('/')
+                    .next()
+                    .unwrap_or("");
+                let timeout = self.latency_budget.timeouts.timeout(endpoint);
+                let read_only = request.method() == Method::Get;
+                let mut response = match req_action {
+                    RequestAction::Sync(vmm_action) => self.serve_vmm_action_request(
+                        vmm_action,
+                        request_processing_start_us,
+                        timeout,
+                        key.as_deref(),
+                        read_only,
+                    ),
+                };
                 if let Some(message) = parsing_info.take_deprecation_message() {
                     warn!("{}", message);
                     response.set_deprecation();
@@ -146,7 +390,28 @@ impl ApiServer {
         &mut self,
         vmm_action: Box<VmmAction>,
         request_processing_start_us: u64,
+        timeout: Option<Duration>,
+        idempotency_key: Option<&str>,
+        read_only: bool,
     ) -> Response {
+        self.discard_late_responses();
+        if !self.pending_requests.is_empty()
+            && self.pending_requests.len() >= self.latency_budget.max_pending_requests
+        {
+            METRICS.api_server.vmm_busy_rejections.inc();
+            warn!(
+                "Rejecting API request, {} timed out requests are still pending on the VMM.",
+                self.pending_requests.len()
+            );
+            if let Some(key) = idempotency_key {
+                self.idempotency_cache.forget(key);
+            }
+            return Self::busy_response(
+                StatusCode::TooManyRequests,
+                "The VMM is busy handling previous requests.",
+            );
+        }
+
         let metric_with_action = match *vmm_action {
             VmmAction::CreateSnapshot(ref params) => match params.snapshot_type {
                 SnapshotType::Full => Some((
@@ -167,11 +432,30 @@ impl ApiServer {
         };
 
         self.api_request_sender
-            .send(vmm_action)
+            .send(ApiRequest::new(vmm_action))
             .expect("Failed to send VMM message");
         self.to_vmm_fd.write(1).expect("Cannot update send VMM fd");
-        let vmm_outcome = *(self.vmm_response_receiver.recv().expect("VMM disconnected"));
+        let Some(vmm_outcome) = self.recv_vmm_response(timeout, idempotency_key) else {
+            METRICS.api_server.sync_vmm_send_timeout_count.inc();
+            warn!("API request timed out, the VMM keeps handling it in the background.");
+            // Retrying a mutating request could apply it twice, unless its idempotency key
+            // makes the retry replay the outcome instead.
+            if read_only || idempotency_key.is_some() {
+                return Self::busy_response(
+                    StatusCode::ServiceUnavailable,
+                    "The VMM did not handle the request in time, it may still be applied.",
+                );
+            }
+            return Self::outcome_unknown_response(
+                "The VMM did not handle the request in time, it keeps handling it in the \
+                 background so its outcome is unknown.",
+            );
+        };
+        let vmm_outcome = *Self::install_api_seccomp_filter(vmm_outcome);
         let response = ParsedRequest::convert_to_response(&vmm_outcome);
+        if let Some(key) = idempotency_key {
+            self.idempotency_cache.finish(key, &response);
+        }
 
         if vmm_outcome.is_ok() {
             if let Some((metric, action)) = metric_with_action {
@@ -183,6 +467,92 @@ impl ApiServer {
         response
     }
 
+    /// Waits up to `timeout` for the VMM to answer the last request, skipping the responses to
+    /// the requests which timed out before it.
+    ///
+    /// Returns `None`, and counts the request as pending along with its `idempotency_key`, if it
+    /// times out.
+    fn recv_vmm_response(
+        &mut self,
+        timeout: Option<Duration>,
+        idempotency_key: Option<&str>,
+    ) -> Option<ApiResponse> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            let response = match deadline {
+                None => self.vmm_response_receiver.recv().expect("VMM disconnected"),
+                Some(deadline) => match self
+                    .vmm_response_receiver
+                    .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                {
+                    Ok(response) => response,
+                    Err(RecvTimeoutError::Timeout) => {
+                        self.pending_requests
+                            .push_back(idempotency_key.map(str::to_string));
+                        return None;
+                    }
+                    Err(RecvTimeoutError::Disconnected) => panic!("VMM disconnected"),
+                },
+            };
+            if self.pending_requests.is_empty() {
+                return Some(response);
+            }
+            self.discard_late_response(response);
+        }
+    }
+
+    /// Drops the responses to timed out requests already sent by the VMM.
+    fn discard_late_responses(&mut self) {
+        while !self.pending_requests.is_empty() {
+            let Ok(response) = self.vmm_response_receiver.try_recv() else {
+                break;
+            };
+            self.discard_late_response(response);
+        }
+    }
+
+    /// Drops the response to the oldest timed out request, recording it if the request carried
+    /// an idempotency key so that retries of the request get it.
+    fn discard_late_response(&mut self, response: ApiResponse) {
+        let response = Self::install_api_seccomp_filter(response);
+        if let Some(Some(key)) = self.pending_requests.pop_front() {
+            self.idempotency_cache
+                .finish(&key, &ParsedRequest::convert_to_response(&response));
+        }
+        debug!("Discarding the response to a timed out API request.");
+    }
+
+    /// Installs on the API thread the seccomp filter the VMM returned for it, if any, since the
+    /// filters of a thread can only be installed by the thread itself.
+    fn install_api_seccomp_filter(response: ApiResponse) -> ApiResponse {
+        match *response {
+            Ok(VmmData::ApiSeccompFilter(filter)) => Box::new(
+                seccompiler::apply_filter(&filter)
+                    .map(|()| VmmData::Empty)
+                    .map_err(|err| SeccompFiltersError::ApiThread(err).into()),
+            ),
+            response => Box::new(response),
+        }
+    }
+
+    /// A response to a request which was not handled because the VMM is busy.
+    fn busy_response(status: StatusCode, msg: &str) -> Response {
+        let body = json!({
+            "fault_message": msg,
+            "retry_after_secs": RETRY_AFTER_SECS,
+        });
+        Self::json_response(status, body.to_string())
+    }
+
+    /// A response to a mutating request which timed out, and which must not be retried blindly.
+    fn outcome_unknown_response(msg: &str) -> Response {
+        let body = json!({
+            "fault_message": msg,
+            "outcome_unknown": true,
+        });
+        Self::json_response(StatusCode::ServiceUnavailable, body.to_string())
+    }
+
     /// An HTTP response which also includes a body.
     pub(crate) fn json_response<T: Into<String> + Debug>(status: StatusCode, body: T) -> Response {
         let mut response = Response::new(Version::Http11, status);
@@ -207,10 +577,12 @@ mod tests {
     use utils::time::ClockType;
     use vmm::builder::StartMicrovmError;
     use vmm::logger::StoreMetric;
-    use vmm::rpc_interface::{VmmActionError, VmmData};
+    use vmm::rpc_interface::VmmActionError;
     use vmm::seccomp_filters::get_empty_filters;
     use vmm::vmm_config::instance_info::InstanceInfo;
+    use vmm::vmm_config::seccomp::SeccompFiltersConfig;
     use vmm::vmm_config::snapshot::CreateSnapshotParams;
+    use vmm_sys_util::tempdir::TempDir;
     use vmm_sys_util::tempfile::TempFile;
 
     use super::request::cpu_configuration::parse_put_cpu_config;
@@ -250,7 +622,13 @@ mod tests {
                 StartMicrovmError::MissingKernelConfig,
             ))))
             .unwrap();
-        let response = api_server.serve_vmm_action_request(Box::new(VmmAction::StartMicroVm), 0);
+        let response = api_server.serve_vmm_action_request(
+            Box::new(VmmAction::StartMicroVm),
+            0,
+            None,
+            None,
+            false,
+        );
         assert_eq!(response.status(), StatusCode::BadRequest);
 
         // Since the vmm side is mocked out in this test, the call to serve_vmm_action_request can
@@ -261,8 +639,13 @@ mod tests {
         let start_time_us = get_time_us(ClockType::Monotonic) - 1;
         assert_eq!(METRICS.latencies_us.pause_vm.fetch(), 0);
         to_api.send(Box::new(Ok(VmmData::Empty))).unwrap();
-        let response =
-            api_server.serve_vmm_action_request(Box::new(VmmAction::Pause), start_time_us);
+        let response = api_server.serve_vmm_action_request(
+            Box::new(VmmAction::Pause),
+            start_time_us,
+            None,
+            None,
+            false,
+        );
         assert_eq!(response.status(), StatusCode::NoContent);
         assert_ne!(METRICS.latencies_us.pause_vm.fetch(), 0);
 
@@ -275,8 +658,14 @@ mod tests {
                 snapshot_type: SnapshotType::Diff,
                 snapshot_path: PathBuf::new(),
                 mem_file_path: PathBuf::new(),
+                labels: Default::default(),
+                encryption: None,
+                background: false,
             })),
             start_time_us,
+            None,
+            None,
+            false,
         );
         assert_eq!(response.status(), StatusCode::BadRequest);
         // The metric should not be updated if the request wasn't successful.
@@ -288,12 +677,111 @@ mod tests {
                 snapshot_type: SnapshotType::Diff,
                 snapshot_path: PathBuf::new(),
                 mem_file_path: PathBuf::new(),
+                labels: Default::default(),
+                encryption: None,
+                background: false,
             })),
             start_time_us,
+            None,
+            None,
+            false,
         );
         assert_eq!(response.status(), StatusCode::NoContent);
         assert_ne!(METRICS.latencies_us.diff_create_snapshot.fetch(), 0);
         assert_eq!(METRICS.latencies_us.full_create_snapshot.fetch(), 0);
+
+        // The filter of the API thread is installed by the API server, which answers with no
+        // content. An empty filter leaves the filters of the thread unchanged.
+        to_api
+            .send(Box::new(Ok(VmmData::ApiSeccompFilter(Default::default()))))
+            .unwrap();
+        let response = api_server.serve_vmm_action_request(
+            Box::new(VmmAction::InstallSeccompFilters(SeccompFiltersConfig {
+                filter_path: PathBuf::new(),
+            })),
+            0,
+            None,
+            None,
+            false,
+        );
+        assert_eq!(response.status(), StatusCode::NoContent);
+    }
+
+    #[test]
+    fn test_serve_vmm_action_request_latency_budget() {
+        let to_vmm_fd = EventFd::new(libc::EFD_NONBLOCK).unwrap();
+        let (api_request_sender, _from_api) = channel();
+        let (to_api, vmm_response_receiver) = channel();
+
+        let mut api_server = ApiServer::new(api_request_sender, vmm_response_receiver, to_vmm_fd)
+            .with_latency_budget(LatencyBudget {
+                timeouts: "1".parse().unwrap(),
+                max_pending_requests: 1,
+            });
+        let timeout = Some(Duration::from_millis(1));
+
+        // The VMM does not answer in time.
+        let timeouts = METRICS.api_server.sync_vmm_send_timeout_count.count();
+        let response = api_server.serve_vmm_action_request(
+            Box::new(VmmAction::Pause),
+            0,
+            timeout,
+            None,
+            false,
+        );
+        assert_eq!(response.status(), StatusCode::ServiceUnavailable);
+        // Retrying the mutating request could apply it twice, so no retry is advised.
+        let body: serde_json::Value =
+            serde_json::from_slice(response.body().unwrap().raw()).unwrap();
+        assert_eq!(body["outcome_unknown"], true);
+        assert!(body.get("retry_after_secs").is_none());
+        assert_eq!(
+            METRICS.api_server.sync_vmm_send_timeout_count.count(),
+            timeouts + 1
+        );
+
+        // Requests are rejected while the timed out request is pending on the VMM.
+        let rejections = METRICS.api_server.vmm_busy_rejections.count();
+        let response = api_server.serve_vmm_action_request(
+            Box::new(VmmAction::Resume),
+            0,
+            timeout,
+            None,
+            false,
+        );
+        assert_eq!(response.status(), StatusCode::TooManyRequests);
+        assert_eq!(
+            METRICS.api_server.vmm_busy_rejections.count(),
+            rejections + 1
+        );
+
+        // The late response is discarded once the VMM sends it.
+        to_api
+            .send(Box::new(Err(VmmActionError::OperationNotSupportedPreBoot)))
+            .unwrap();
+        to_api.send(Box::new(Ok(VmmData::Empty))).unwrap();
+        let response = api_server.serve_vmm_action_request(
+            Box::new(VmmAction::Resume),
+            0,
+            timeout,
+            None,
+            false,
+        );
+        assert_eq!(response.status(), StatusCode::NoContent);
+
+        // Read-only requests which time out can be retried.
+        let response = api_server.serve_vmm_action_request(
+            Box::new(VmmAction::GetVmMachineConfig),
+            0,
+            timeout,
+            None,
+            true,
+        );
+        assert_eq!(response.status(), StatusCode::ServiceUnavailable);
+        let body: serde_json::Value =
+            serde_json::from_slice(response.body().unwrap().raw()).unwrap();
+        assert_eq!(body["retry_after_secs"], RETRY_AFTER_SECS);
+        assert!(body.get("outcome_unknown").is_none());
     }
 
     #[test]
@@ -348,6 +836,54 @@ mod tests {
         assert_eq!(response.status(), StatusCode::BadRequest);
     }
 
+    #[test]
+    fn test_handle_request_idempotency_key() {
+        let to_vmm_fd = EventFd::new(libc::EFD_NONBLOCK).unwrap();
+        let (api_request_sender, from_api) = channel();
+        let (to_api, vmm_response_receiver) = channel();
+
+        // Requests which are wrongly forwarded to the VMM time out instead of blocking the test.
+        let mut api_server = ApiServer::new(api_request_sender, vmm_response_receiver, to_vmm_fd)
+            .with_latency_budget(LatencyBudget {
+                timeouts: "1000".parse().unwrap(),
+                max_pending_requests: 1,
+            });
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        let mut send = |key: &str, body: &str| {
+            sender
+                .write_all(
+                    format!(
+                        "PUT /actions HTTP/1.1\r\nIdempotency-Key: {key}\r\nContent-Type: \
+                         application/json\r\nContent-Length: {}\r\n\r\n{body}",
+                        body.len()
+                    )
+                    .as_bytes(),
+                )
+                .unwrap();
+            connection.try_read().unwrap();
+            let req = connection.pop_parsed_request().unwrap();
+            api_server.handle_request(&req, 0)
+        };
+        let start = r#"{"action_type": "InstanceStart"}"#;
+
+        to_api.send(Box::new(Ok(VmmData::Empty))).unwrap();
+        assert_eq!(send("start-1", start).status(), StatusCode::NoContent);
+        // The retried request is answered without reaching the VMM.
+        let replays = METRICS.api_server.idempotent_replays.count();
+        assert_eq!(send("start-1", start).status(), StatusCode::NoContent);
+        assert_eq!(METRICS.api_server.idempotent_replays.count(), replays + 1);
+        assert_eq!(from_api.try_iter().count(), 1);
+
+        // The key can not be reused for a different request.
+        assert_eq!(
+            send("start-1", r#"{"action_type": "FlushMetrics"}"#).status(),
+            StatusCode::BadRequest
+        );
+        assert_eq!(send("start 2", start).status(), StatusCode::BadRequest);
+        assert_eq!(from_api.try_iter().count(), 0);
+    }
+
     #[test]
     fn test_handle_request_logging() {
         let cpu_template_json = TEST_UNESCAPED_JSON_TEMPLATE;
@@ -446,16 +982,15 @@ mod tests {
                   Content-Length: 50000\r\n\r\naaaaaa",
         )
         .unwrap();
-        let mut buf: [u8; 265] = [0; 265];
-        assert!(sock.read(&mut buf[..]).unwrap() > 0);
+        let mut buf = Vec::new();
+        sock.read_to_end(&mut buf).unwrap();
         let error_message = b"HTTP/1.1 400 \r\n\
                               Server: Firecracker API\r\n\
-                              Connection: keep-alive\r\n\
                               Content-Type: application/json\r\n\
-                              Content-Length: 146\r\n\r\n{ \"error\": \"\
+                              Content-Length: 96\r\n\
+                              Connection: close\r\n\r\n{ \"error\": \"\
                               Request payload with size 50000 is larger than \
-                              the limit of 50 allowed by server.\nAll previous \
-                              unanswered requests will be dropped.\" }";
+                              the limit of 50 allowed by server.\" }";
         assert_eq!(&buf[..], &error_message[..]);
     }
 
@@ -492,4 +1027,66 @@ mod tests {
         // Verify API thread was brought down.
         api_thread.join().unwrap();
     }
+
+    #[test]
+    fn test_socket_rebind() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path_to_socket = tmp_dir.as_path().join("api").join("api.socket");
+        std::fs::create_dir(path_to_socket.parent().unwrap()).unwrap();
+
+        let to_vmm_fd = EventFd::new(libc::EFD_NONBLOCK).unwrap();
+        let (api_request_sender, _from_api) = channel();
+        let (to_api, vmm_response_receiver) = channel();
+        let seccomp_filters = get_empty_filters();
+
+        let api_kill_switch = EventFd::new(libc::EFD_NONBLOCK).unwrap();
+        let mut server = HttpServer::new(&path_to_socket).unwrap();
+        server
+            .add_kill_switch(api_kill_switch.try_clone().unwrap())
+            .unwrap();
+
+        let api_thread_path_to_socket = path_to_socket.clone();
+        let kill_switch = api_kill_switch.try_clone().unwrap();
+        let api_thread = thread::Builder::new()
+            .name("fc_api_test".to_owned())
+            .spawn(move || {
+                ApiServer::new(api_request_sender, vmm_response_receiver, to_vmm_fd)
+                    .with_socket_rebind(api_thread_path_to_socket, kill_switch)
+                    .run(
+                        server,
+                        ProcessTimeReporter::new(Some(1), Some(1), Some(1)),
+                        seccomp_filters.get("api").unwrap(),
+                        vmm::HTTP_MAX_PAYLOAD_SIZE,
+                    )
+            })
+            .unwrap();
+
+        let get_instance_info = |path: &PathBuf| {
+            to_api
+                .send(Box::new(Ok(VmmData::InstanceInformation(
+                    InstanceInfo::default(),
+                ))))
+                .unwrap();
+            let mut sock = UnixStream::connect(path).unwrap();
+            sock.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+            let mut buf: [u8; 100] = [0; 100];
+            assert!(sock.read(&mut buf[..]).unwrap() > 0);
+        };
+        // The server is up and running.
+        get_instance_info(&path_to_socket);
+
+        // Remove the socket together with its parent directory.
+        std::fs::remove_dir_all(path_to_socket.parent().unwrap()).unwrap();
+        UnixStream::connect(&path_to_socket).unwrap_err();
+
+        let rebinds = METRICS.api_server.socket_rebinds.count();
+        request_socket_rebind();
+        while METRICS.api_server.socket_rebinds.count() == rebinds {
+            thread::sleep(std::time::Duration::from_millis(10));
+        }
+        get_instance_info(&path_to_socket);
+
+        request_shutdown(&api_kill_switch).unwrap();
+        api_thread.join().unwrap();
+    }
 }
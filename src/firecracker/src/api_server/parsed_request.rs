@@ -13,17 +13,27 @@ use super::request::actions::parse_put_actions;
 use super::request::balloon::{parse_get_balloon, parse_patch_balloon, parse_put_balloon};
 use super::request::boot_source::parse_put_boot_source;
 use super::request::cpu_configuration::parse_put_cpu_config;
+use super::request::cpu_limiter::{parse_patch_cpu_limiter, parse_put_cpu_limiter};
 use super::request::drive::{parse_patch_drive, parse_put_drive};
 use super::request::entropy::parse_put_entropy;
 use super::request::instance_info::parse_get_instance_info;
+use super::request::lifecycle_hooks::parse_put_lifecycle_hooks;
 use super::request::logger::parse_put_logger;
 use super::request::machine_configuration::{
     parse_get_machine_config, parse_patch_machine_config, parse_put_machine_config,
 };
+use super::request::memory::{parse_get_memory, parse_put_memory};
 use super::request::metrics::parse_put_metrics;
 use super::request::mmds::{parse_get_mmds, parse_patch_mmds, parse_put_mmds};
-use super::request::net::{parse_patch_net, parse_put_net};
-use super::request::snapshot::{parse_patch_vm_state, parse_put_snapshot};
+use super::request::net::{parse_get_net, parse_patch_net, parse_put_net};
+use super::request::pvpanic::parse_put_pvpanic;
+use super::request::rate_limiters::{parse_get_rate_limiters, parse_put_rate_limiter};
+use super::request::seccomp::parse_put_seccomp;
+use super::request::serial::{parse_get_serial, parse_put_serial};
+use super::request::shared_memory::parse_put_shared_memory;
+use super::request::smbios::parse_put_smbios;
+use super::request::snapshot::{parse_get_snapshot, parse_patch_vm_state, parse_put_snapshot};
+use super::request::tpm::parse_put_tpm;
 use super::request::version::parse_get_version;
 use super::request::vsock::parse_put_vsock;
 use super::ApiServer;
@@ -61,46 +71,86 @@ impl TryFrom<&Request> for ParsedRequest {
     type Error = RequestError;
     fn try_from(request: &Request) -> Result<Self, Self::Error> {
         let request_uri = request.uri().get_abs_path().to_string();
-        let description = describe(
-            request.method(),
-            request_uri.as_str(),
-            request.body.as_ref(),
-        );
+        // The query string is only supported by `PUT /cpu-config`.
+        let (request_path, query) = match request_uri.split_once('?') {
+            Some((request_path, query)) => (request_path, Some(query)),
+            None => (request_uri.as_str(), None),
+        };
+        let description = describe(request.method(), request_path, request.body.as_ref());
         info!("The API server received a {description}.");
 
-        // Split request uri by '/' by doing:
+        // Split request path by '/' by doing:
         // 1. Trim starting '/' characters
         // 2. Splitting by '/'
-        let mut path_tokens = request_uri.trim_start_matches('/').split_terminator('/');
+        let mut path_tokens = request_path.trim_start_matches('/').split_terminator('/');
         let path = path_tokens.next().unwrap_or("");
+        if query.is_some() && path != "cpu-config" {
+            return Err(RequestError::InvalidPathMethod(
+                request_uri.clone(),
+                request.method(),
+            ));
+        }
 
         match (request.method(), path, request.body.as_ref()) {
             (Method::Get, "", None) => parse_get_instance_info(),
             (Method::Get, "balloon", None) => parse_get_balloon(path_tokens.next()),
-            (Method::Get, "version", None) => parse_get_version(),
-            (Method::Get, "vm", None) if path_tokens.next() == Some("config") => {
-                Ok(ParsedRequest::new_sync(VmmAction::GetFullVmConfig))
+            (Method::Get, "boot-timings", None) => {
+                Ok(ParsedRequest::new_sync(VmmAction::GetBootTimings))
             }
+            (Method::Get, "version", None) => parse_get_version(),
+            (Method::Get, "vm", None) => match path_tokens.next() {
+                Some("config") => Ok(ParsedRequest::new_sync(VmmAction::GetFullVmConfig)),
+                Some("memory-slots") => Ok(ParsedRequest::new_sync(VmmAction::GetMemorySlots)),
+                Some("info") => Ok(ParsedRequest::new_sync(VmmAction::GetProcessInfo)),
+                _ => Err(RequestError::InvalidPathMethod(
+                    path.to_string(),
+                    Method::Get,
+                )),
+            },
             (Method::Get, "machine-config", None) => parse_get_machine_config(),
-            (Method::Get, "mmds", None) => parse_get_mmds(),
+            (Method::Get, "memory", None) => parse_get_memory(path_tokens.next()),
+            (Method::Get, "mmds", None) => parse_get_mmds(path_tokens.next()),
+            (Method::Get, "network-interfaces", None) => {
+                parse_get_net(path_tokens.next(), path_tokens.next())
+            }
+            (Method::Get, "rate-limiters", None) => parse_get_rate_limiters(path_tokens.next()),
+            (Method::Get, "serial", None) => parse_get_serial(path_tokens.next()),
+            (Method::Get, "snapshot", None) => parse_get_snapshot(path_tokens.next()),
             (Method::Get, _, Some(_)) => method_to_error(Method::Get),
             (Method::Put, "actions", Some(body)) => parse_put_actions(body),
             (Method::Put, "balloon", Some(body)) => parse_put_balloon(body),
             (Method::Put, "boot-source", Some(body)) => parse_put_boot_source(body),
-            (Method::Put, "cpu-config", Some(body)) => parse_put_cpu_config(body),
+            (Method::Put, "cpu-config", Some(body)) => parse_put_cpu_config(body, query),
+            (Method::Put, "cpu-limiter", Some(body)) => parse_put_cpu_limiter(body),
             (Method::Put, "drives", Some(body)) => parse_put_drive(body, path_tokens.next()),
+            (Method::Put, "lifecycle-hooks", Some(body)) => parse_put_lifecycle_hooks(body),
             (Method::Put, "logger", Some(body)) => parse_put_logger(body),
             (Method::Put, "machine-config", Some(body)) => parse_put_machine_config(body),
+            (Method::Put, "memory", Some(body)) => parse_put_memory(body, path_tokens.next()),
             (Method::Put, "metrics", Some(body)) => parse_put_metrics(body),
-            (Method::Put, "mmds", Some(body)) => parse_put_mmds(body, path_tokens.next()),
+            (Method::Put, "mmds", Some(body)) => {
+                parse_put_mmds(body, path_tokens.next(), path_tokens.next())
+            }
             (Method::Put, "network-interfaces", Some(body)) => {
-                parse_put_net(body, path_tokens.next())
+                parse_put_net(body, path_tokens.next(), path_tokens.next())
+            }
+            (Method::Put, "pvpanic", Some(body)) => parse_put_pvpanic(body),
+            (Method::Put, "rate-limiters", Some(body)) => {
+                parse_put_rate_limiter(body, path_tokens.next())
             }
+            (Method::Put, "seccomp", Some(body)) => parse_put_seccomp(body),
+            (Method::Put, "serial", Some(body)) => parse_put_serial(body),
+            (Method::Put, "shared-memory", Some(body)) => {
+                parse_put_shared_memory(body, path_tokens.next())
+            }
+            (Method::Put, "smbios", Some(body)) => parse_put_smbios(body),
+            (Method::Put, "tpm", Some(body)) => parse_put_tpm(body),
             (Method::Put, "snapshot", Some(body)) => parse_put_snapshot(body, path_tokens.next()),
             (Method::Put, "vsock", Some(body)) => parse_put_vsock(body),
             (Method::Put, "entropy", Some(body)) => parse_put_entropy(body),
             (Method::Put, _, None) => method_to_error(Method::Put),
             (Method::Patch, "balloon", Some(body)) => parse_patch_balloon(body, path_tokens.next()),
+            (Method::Patch, "cpu-limiter", Some(body)) => parse_patch_cpu_limiter(body),
             (Method::Patch, "drives", Some(body)) => parse_patch_drive(body, path_tokens.next()),
             (Method::Patch, "machine-config", Some(body)) => parse_patch_machine_config(body),
             (Method::Patch, "mmds", Some(body)) => parse_patch_mmds(body),
@@ -159,23 +209,36 @@ impl ParsedRequest {
     ) -> Response {
         match request_outcome {
             Ok(vmm_data) => match vmm_data {
-                VmmData::Empty => {
+                VmmData::Empty | VmmData::ApiSeccompFilter(_) => {
                     info!("The request was executed successfully. Status code: 204 No Content.");
                     Response::new(Version::Http11, StatusCode::NoContent)
                 }
                 VmmData::MachineConfiguration(vm_config) => {
                     Self::success_response_with_data(vm_config)
                 }
+                VmmData::MemorySlots(usage) => Self::success_response_with_data(usage),
+                VmmData::MemoryTarget(status) => Self::success_response_with_data(status),
+                VmmData::ProcessInfo(info) => Self::success_response_with_data(info),
+                VmmData::MmdsSessions(sessions) => Self::success_response_with_data(sessions),
                 VmmData::MmdsValue(value) => Self::success_response_with_mmds_value(value),
                 VmmData::BalloonConfig(balloon_config) => {
                     Self::success_response_with_data(balloon_config)
                 }
                 VmmData::BalloonStats(stats) => Self::success_response_with_data(stats),
+                VmmData::BootTimings(timings) => Self::success_response_with_data(timings),
+                VmmData::CpuTemplateReport(report) => Self::success_response_with_data(report),
+                VmmData::SnapshotCompatReport(report) => Self::success_response_with_data(report),
                 VmmData::InstanceInformation(info) => Self::success_response_with_data(info),
                 VmmData::VmmVersion(version) => Self::success_response_with_data(
                     &serde_json::json!({ "firecracker_version": version.as_str() }),
                 ),
                 VmmData::FullVmConfig(config) => Self::success_response_with_data(config),
+                VmmData::NetworkFlows(flows) => Self::success_response_with_data(flows),
+                VmmData::SerialLog(log) => Self::success_response_with_data(log),
+                VmmData::SharedRateLimiters(statuses) => Self::success_response_with_data(statuses),
+                VmmData::BackgroundSnapshotStatus(status) => {
+                    Self::success_response_with_data(status)
+                }
             },
             Err(vmm_action_error) => {
                 let mut response = match vmm_action_error {
@@ -316,16 +379,25 @@ pub(crate) fn checked_id(id: &str) -> Result<&str, RequestError> {
 pub mod tests {
     use std::io::{Cursor, Write};
     use std::os::unix::net::UnixStream;
+    use std::path::PathBuf;
     use std::str::FromStr;
 
     use micro_http::HttpConnection;
+    use vmm::background_snapshot::BackgroundSnapshotStatus;
+    use vmm::boot_timings::BootTimingsInfo;
     use vmm::builder::StartMicrovmError;
     use vmm::cpu_config::templates::test_utils::build_test_template;
+    use vmm::cpu_config::templates::CpuTemplateReport;
+    use vmm::devices::legacy::serial::SerialLogContent;
+    use vmm::devices::virtio::net::flows::NetFlows;
+    use vmm::persist::SnapshotCompatReport;
     use vmm::resources::VmmConfig;
     use vmm::rpc_interface::VmmActionError;
     use vmm::vmm_config::balloon::{BalloonDeviceConfig, BalloonStats};
-    use vmm::vmm_config::instance_info::InstanceInfo;
+    use vmm::vmm_config::instance_info::{InstanceInfo, ProcessInfo};
     use vmm::vmm_config::machine_config::MachineConfig;
+    use vmm::vmm_config::memory_target::MemoryTargetStatus;
+    use vmm::vstate::memory::MemorySlotsUsage;
 
     use super::*;
 
@@ -555,16 +627,47 @@ pub mod tests {
                 VmmData::BalloonStats(stats) => {
                     http_response(&serde_json::to_string(stats).unwrap(), 200)
                 }
-                VmmData::Empty => http_response("", 204),
+                VmmData::BootTimings(timings) => {
+                    http_response(&serde_json::to_string(timings).unwrap(), 200)
+                }
+                VmmData::CpuTemplateReport(report) => {
+                    http_response(&serde_json::to_string(report).unwrap(), 200)
+                }
+                VmmData::SnapshotCompatReport(report) => {
+                    http_response(&serde_json::to_string(report).unwrap(), 200)
+                }
+                VmmData::Empty | VmmData::ApiSeccompFilter(_) => http_response("", 204),
                 VmmData::FullVmConfig(cfg) => {
                     http_response(&serde_json::to_string(cfg).unwrap(), 200)
                 }
                 VmmData::MachineConfiguration(cfg) => {
                     http_response(&serde_json::to_string(cfg).unwrap(), 200)
                 }
+                VmmData::MemorySlots(usage) => {
+                    http_response(&serde_json::to_string(usage).unwrap(), 200)
+                }
+                VmmData::MemoryTarget(status) => {
+                    http_response(&serde_json::to_string(status).unwrap(), 200)
+                }
+                VmmData::ProcessInfo(info) => {
+                    http_response(&serde_json::to_string(info).unwrap(), 200)
+                }
+                VmmData::MmdsSessions(sessions) => {
+                    http_response(&serde_json::to_string(sessions).unwrap(), 200)
+                }
                 VmmData::MmdsValue(value) => {
                     http_response(&serde_json::to_string(value).unwrap(), 200)
                 }
+                VmmData::NetworkFlows(flows) => {
+                    http_response(&serde_json::to_string(flows).unwrap(), 200)
+                }
+                VmmData::SerialLog(log) => http_response(&serde_json::to_string(log).unwrap(), 200),
+                VmmData::SharedRateLimiters(statuses) => {
+                    http_response(&serde_json::to_string(statuses).unwrap(), 200)
+                }
+                VmmData::BackgroundSnapshotStatus(status) => {
+                    http_response(&serde_json::to_string(status).unwrap(), 200)
+                }
                 VmmData::InstanceInformation(info) => {
                     http_response(&serde_json::to_string(info).unwrap(), 200)
                 }
@@ -584,10 +687,39 @@ pub mod tests {
             swap_out: Some(1),
             ..Default::default()
         }));
+        verify_ok_response_with(VmmData::BootTimings(BootTimingsInfo {
+            config_done_us: Some(1000),
+            kernel_loaded_us: Some(2000),
+            ..Default::default()
+        }));
+        verify_ok_response_with(VmmData::CpuTemplateReport(CpuTemplateReport::default()));
+        verify_ok_response_with(VmmData::SnapshotCompatReport(
+            SnapshotCompatReport::default(),
+        ));
         verify_ok_response_with(VmmData::Empty);
+        verify_ok_response_with(VmmData::ApiSeccompFilter(Default::default()));
         verify_ok_response_with(VmmData::FullVmConfig(VmmConfig::default()));
         verify_ok_response_with(VmmData::MachineConfiguration(MachineConfig::default()));
+        verify_ok_response_with(VmmData::MemorySlots(MemorySlotsUsage::default()));
+        verify_ok_response_with(VmmData::MemoryTarget(MemoryTargetStatus::from_balloon(
+            1024, 512, 256,
+        )));
+        verify_ok_response_with(VmmData::ProcessInfo(ProcessInfo {
+            pid: 1234,
+            vcpu_tids: vec![1235, 1236],
+            chroot_dir: Some(PathBuf::from("/srv/jailer/firecracker/vm0/root")),
+        }));
+        verify_ok_response_with(VmmData::MmdsSessions(Vec::new()));
         verify_ok_response_with(VmmData::MmdsValue(serde_json::from_str("{}").unwrap()));
+        verify_ok_response_with(VmmData::NetworkFlows(NetFlows::default()));
+        verify_ok_response_with(VmmData::SerialLog(SerialLogContent {
+            log: "login: ".to_string(),
+            dropped_bytes: 0,
+        }));
+        verify_ok_response_with(VmmData::SharedRateLimiters(Vec::new()));
+        verify_ok_response_with(VmmData::BackgroundSnapshotStatus(
+            BackgroundSnapshotStatus::default(),
+        ));
         verify_ok_response_with(VmmData::InstanceInformation(InstanceInfo::default()));
         verify_ok_response_with(VmmData::VmmVersion(String::default()));
 
@@ -638,6 +770,97 @@ pub mod tests {
         ParsedRequest::try_from(&req).unwrap();
     }
 
+    #[test]
+    fn test_try_from_get_net_flows() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        sender
+            .write_all(http_request("GET", "/network-interfaces/string/flows", None).as_bytes())
+            .unwrap();
+        connection.try_read().unwrap();
+        let req = connection.pop_parsed_request().unwrap();
+        ParsedRequest::try_from(&req).unwrap();
+    }
+
+    #[test]
+    fn test_try_from_get_memory_slots() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        sender
+            .write_all(http_request("GET", "/vm/memory-slots", None).as_bytes())
+            .unwrap();
+        connection.try_read().unwrap();
+        let req = connection.pop_parsed_request().unwrap();
+        assert_eq!(
+            vmm_action_from_request(ParsedRequest::try_from(&req).unwrap()),
+            VmmAction::GetMemorySlots
+        );
+
+        sender
+            .write_all(http_request("GET", "/vm/foo", None).as_bytes())
+            .unwrap();
+        connection.try_read().unwrap();
+        let req = connection.pop_parsed_request().unwrap();
+        ParsedRequest::try_from(&req).unwrap_err();
+    }
+
+    #[test]
+    fn test_try_from_get_boot_timings() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        sender
+            .write_all(http_request("GET", "/boot-timings", None).as_bytes())
+            .unwrap();
+        connection.try_read().unwrap();
+        let req = connection.pop_parsed_request().unwrap();
+        assert_eq!(
+            vmm_action_from_request(ParsedRequest::try_from(&req).unwrap()),
+            VmmAction::GetBootTimings
+        );
+    }
+
+    #[test]
+    fn test_try_from_get_process_info() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        sender
+            .write_all(http_request("GET", "/vm/info", None).as_bytes())
+            .unwrap();
+        connection.try_read().unwrap();
+        let req = connection.pop_parsed_request().unwrap();
+        assert_eq!(
+            vmm_action_from_request(ParsedRequest::try_from(&req).unwrap()),
+            VmmAction::GetProcessInfo
+        );
+    }
+
+    #[test]
+    fn test_try_from_get_memory_target() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        sender
+            .write_all(http_request("GET", "/memory/target", None).as_bytes())
+            .unwrap();
+        connection.try_read().unwrap();
+        let req = connection.pop_parsed_request().unwrap();
+        assert_eq!(
+            vmm_action_from_request(ParsedRequest::try_from(&req).unwrap()),
+            VmmAction::GetMemoryTarget
+        );
+    }
+
+    #[test]
+    fn test_try_from_get_serial_log() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        sender
+            .write_all(http_request("GET", "/serial/log", None).as_bytes())
+            .unwrap();
+        connection.try_read().unwrap();
+        let req = connection.pop_parsed_request().unwrap();
+        ParsedRequest::try_from(&req).unwrap();
+    }
+
     #[test]
     fn test_try_from_get_machine_config() {
         let (mut sender, receiver) = UnixStream::pair().unwrap();
@@ -660,6 +883,20 @@ pub mod tests {
         connection.try_read().unwrap();
         let req = connection.pop_parsed_request().unwrap();
         ParsedRequest::try_from(&req).unwrap();
+
+        sender
+            .write_all(http_request("GET", "/mmds/guest-data", None).as_bytes())
+            .unwrap();
+        connection.try_read().unwrap();
+        let req = connection.pop_parsed_request().unwrap();
+        ParsedRequest::try_from(&req).unwrap();
+
+        sender
+            .write_all(http_request("GET", "/mmds/sessions", None).as_bytes())
+            .unwrap();
+        connection.try_read().unwrap();
+        let req = connection.pop_parsed_request().unwrap();
+        ParsedRequest::try_from(&req).unwrap();
     }
 
     #[test]
@@ -716,6 +953,164 @@ pub mod tests {
         ParsedRequest::try_from(&req).unwrap();
     }
 
+    #[test]
+    fn test_try_from_put_shared_memory() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        let body = "{ \"segment_id\": \"runtime\", \"path_on_host\": \"string\", \"guest_addr\": \
+                    4294967296 }";
+        sender
+            .write_all(http_request("PUT", "/shared-memory/runtime", Some(body)).as_bytes())
+            .unwrap();
+        connection.try_read().unwrap();
+        let req = connection.pop_parsed_request().unwrap();
+        ParsedRequest::try_from(&req).unwrap();
+    }
+
+    #[test]
+    fn test_try_from_get_rate_limiters() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        sender
+            .write_all(http_request("GET", "/rate-limiters", None).as_bytes())
+            .unwrap();
+        connection.try_read().unwrap();
+        let req = connection.pop_parsed_request().unwrap();
+        assert_eq!(
+            vmm_action_from_request(ParsedRequest::try_from(&req).unwrap()),
+            VmmAction::GetSharedRateLimiters
+        );
+    }
+
+    #[test]
+    fn test_try_from_put_rate_limiter() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        let body = "{ \"id\": \"drives\", \"ops\": { \"size\": 1000, \"refill_time\": 1000 } }";
+        sender
+            .write_all(http_request("PUT", "/rate-limiters/drives", Some(body)).as_bytes())
+            .unwrap();
+        connection.try_read().unwrap();
+        let req = connection.pop_parsed_request().unwrap();
+        ParsedRequest::try_from(&req).unwrap();
+    }
+
+    #[test]
+    fn test_try_from_put_seccomp() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        let body = "{ \"filter_path\": \"/tmp/filters.bpf\" }";
+        sender
+            .write_all(http_request("PUT", "/seccomp", Some(body)).as_bytes())
+            .unwrap();
+        connection.try_read().unwrap();
+        let req = connection.pop_parsed_request().unwrap();
+        ParsedRequest::try_from(&req).unwrap();
+    }
+
+    #[test]
+    fn test_try_from_put_serial() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        let body = "{ \"mode\": \"pty\", \"path\": \"/tmp/console\" }";
+        sender
+            .write_all(http_request("PUT", "/serial", Some(body)).as_bytes())
+            .unwrap();
+        connection.try_read().unwrap();
+        let req = connection.pop_parsed_request().unwrap();
+        ParsedRequest::try_from(&req).unwrap();
+    }
+
+    #[test]
+    fn test_try_from_put_memory_target() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        let body = "{ \"target_mib\": 512 }";
+        sender
+            .write_all(http_request("PUT", "/memory/target", Some(body)).as_bytes())
+            .unwrap();
+        connection.try_read().unwrap();
+        let req = connection.pop_parsed_request().unwrap();
+        ParsedRequest::try_from(&req).unwrap();
+    }
+
+    #[test]
+    fn test_try_from_put_smbios() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        let body = "{ \"system_serial_number\": \"serial\", \"oem_strings\": [\"oem\"] }";
+        sender
+            .write_all(http_request("PUT", "/smbios", Some(body)).as_bytes())
+            .unwrap();
+        connection.try_read().unwrap();
+        let req = connection.pop_parsed_request().unwrap();
+        ParsedRequest::try_from(&req).unwrap();
+    }
+
+    #[test]
+    fn test_try_from_put_lifecycle_hooks() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        let body = "{ \"hooks\": [{ \"event\": \"pre_boot\", \"command\": [\"true\"] }] }";
+        sender
+            .write_all(http_request("PUT", "/lifecycle-hooks", Some(body)).as_bytes())
+            .unwrap();
+        connection.try_read().unwrap();
+        let req = connection.pop_parsed_request().unwrap();
+        ParsedRequest::try_from(&req).unwrap();
+    }
+
+    #[test]
+    fn test_try_from_put_cpu_limiter() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        let body = "{ \"millicpus\": 1500, \"burst_ms\": 10 }";
+        sender
+            .write_all(http_request("PUT", "/cpu-limiter", Some(body)).as_bytes())
+            .unwrap();
+        connection.try_read().unwrap();
+        let req = connection.pop_parsed_request().unwrap();
+        ParsedRequest::try_from(&req).unwrap();
+    }
+
+    #[test]
+    fn test_try_from_patch_cpu_limiter() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        let body = "{ \"millicpus\": 500 }";
+        sender
+            .write_all(http_request("PATCH", "/cpu-limiter", Some(body)).as_bytes())
+            .unwrap();
+        connection.try_read().unwrap();
+        let req = connection.pop_parsed_request().unwrap();
+        ParsedRequest::try_from(&req).unwrap();
+    }
+
+    #[test]
+    fn test_try_from_put_tpm() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        let body = "{ \"socket\": \"/tmp/swtpm.sock\" }";
+        sender
+            .write_all(http_request("PUT", "/tpm", Some(body)).as_bytes())
+            .unwrap();
+        connection.try_read().unwrap();
+        let req = connection.pop_parsed_request().unwrap();
+        ParsedRequest::try_from(&req).unwrap();
+    }
+
+    #[test]
+    fn test_try_from_put_pvpanic() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        sender
+            .write_all(http_request("PUT", "/pvpanic", Some("{}")).as_bytes())
+            .unwrap();
+        connection.try_read().unwrap();
+        let req = connection.pop_parsed_request().unwrap();
+        ParsedRequest::try_from(&req).unwrap();
+    }
+
     #[test]
     fn test_try_from_put_boot() {
         let (mut sender, receiver) = UnixStream::pair().unwrap();
@@ -815,6 +1210,15 @@ pub mod tests {
         connection.try_read().unwrap();
         let req = connection.pop_parsed_request().unwrap();
         ParsedRequest::try_from(&req).unwrap();
+
+        // `/mmds/sessions/revoke`
+        let body = "{ \"session_id\": \"0123456789abcdef01234567\" }";
+        sender
+            .write_all(http_request("PUT", "/mmds/sessions/revoke", Some(body)).as_bytes())
+            .unwrap();
+        connection.try_read().unwrap();
+        let req = connection.pop_parsed_request().unwrap();
+        ParsedRequest::try_from(&req).unwrap();
     }
 
     #[test]
@@ -836,6 +1240,21 @@ pub mod tests {
         ParsedRequest::try_from(&req).unwrap();
     }
 
+    #[test]
+    fn test_try_from_put_netif_capture() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        let body = "{ \"enabled\": true, \"path\": \"/tmp/string.pcap\" }";
+        sender
+            .write_all(
+                http_request("PUT", "/network-interfaces/string/capture", Some(body)).as_bytes(),
+            )
+            .unwrap();
+        connection.try_read().unwrap();
+        let req = connection.pop_parsed_request().unwrap();
+        ParsedRequest::try_from(&req).unwrap();
+    }
+
     #[test]
     fn test_try_from_put_snapshot() {
         let (mut sender, receiver) = UnixStream::pair().unwrap();
@@ -971,6 +1390,26 @@ pub mod tests {
         let req = connection.pop_parsed_request().unwrap();
         let request_result = ParsedRequest::try_from(&req);
         assert!(request_result.is_ok(), "{}", request_result.err().unwrap());
+
+        sender
+            .write_all(
+                http_request("PUT", "/cpu-config?dry_run=true", Some(&cpu_config_json)).as_bytes(),
+            )
+            .unwrap();
+        connection.try_read().unwrap();
+        let req = connection.pop_parsed_request().unwrap();
+        assert_eq!(
+            vmm_action_from_request(ParsedRequest::try_from(&req).unwrap()),
+            VmmAction::ValidateCpuConfiguration(cpu_template)
+        );
+
+        // The query string is not supported by other endpoints.
+        sender
+            .write_all(http_request("PUT", "/machine-config?dry_run=true", Some("{}")).as_bytes())
+            .unwrap();
+        connection.try_read().unwrap();
+        let req = connection.pop_parsed_request().unwrap();
+        ParsedRequest::try_from(&req).unwrap_err();
     }
 
     #[test]
@@ -4,11 +4,10 @@
 use serde::{Deserialize, Serialize};
 use vmm::logger::{IncMetric, METRICS};
 use vmm::rpc_interface::VmmAction;
+use vmm::vmm_config::shutdown::ShutdownConfig;
 
 use super::super::parsed_request::{ParsedRequest, RequestError};
-use super::Body;
-#[cfg(target_arch = "aarch64")]
-use super::StatusCode;
+use super::{Body, StatusCode};
 
 // The names of the members from this enum must precisely correspond (as a string) to the possible
 // values of "action_type" from the json request body. This is useful to get a strongly typed
@@ -18,6 +17,7 @@ enum ActionType {
     FlushMetrics,
     InstanceStart,
     SendCtrlAltDel,
+    SendShutdown,
 }
 
 // The model of the json body from a sync request. We use Serde to transform each associated
@@ -26,6 +26,8 @@ enum ActionType {
 #[serde(deny_unknown_fields)]
 struct ActionBody {
     action_type: ActionType,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    shutdown_timeout_ms: Option<u64>,
 }
 
 pub(crate) fn parse_put_actions(body: &Body) -> Result<ParsedRequest, RequestError> {
@@ -34,6 +36,16 @@ pub(crate) fn parse_put_actions(body: &Body) -> Result<ParsedRequest, RequestErr
         METRICS.put_api_requests.actions_fails.inc();
     })?;
 
+    if action_body.shutdown_timeout_ms.is_some()
+        && !matches!(action_body.action_type, ActionType::SendShutdown)
+    {
+        METRICS.put_api_requests.actions_fails.inc();
+        return Err(RequestError::Generic(
+            StatusCode::BadRequest,
+            "The shutdown timeout is only allowed for the SendShutdown action.".to_string(),
+        ));
+    }
+
     match action_body.action_type {
         ActionType::FlushMetrics => Ok(ParsedRequest::new_sync(VmmAction::FlushMetrics)),
         ActionType::InstanceStart => Ok(ParsedRequest::new_sync(VmmAction::StartMicroVm)),
@@ -48,6 +60,11 @@ pub(crate) fn parse_put_actions(body: &Body) -> Result<ParsedRequest, RequestErr
             #[cfg(target_arch = "x86_64")]
             Ok(ParsedRequest::new_sync(VmmAction::SendCtrlAltDel))
         }
+        ActionType::SendShutdown => Ok(ParsedRequest::new_sync(VmmAction::SendShutdown(
+            ShutdownConfig {
+                shutdown_timeout_ms: action_body.shutdown_timeout_ms,
+            },
+        ))),
     }
 }
 
@@ -99,5 +116,38 @@ mod tests {
             let result = parse_put_actions(&Body::new(json));
             assert_eq!(result.unwrap(), req);
         }
+
+        {
+            let json = r#"{
+                "action_type": "SendShutdown"
+            }"#;
+
+            let req: ParsedRequest =
+                ParsedRequest::new_sync(VmmAction::SendShutdown(ShutdownConfig::default()));
+            let result = parse_put_actions(&Body::new(json));
+            assert_eq!(result.unwrap(), req);
+
+            let json = r#"{
+                "action_type": "SendShutdown",
+                "shutdown_timeout_ms": 5000
+            }"#;
+
+            let req: ParsedRequest =
+                ParsedRequest::new_sync(VmmAction::SendShutdown(ShutdownConfig {
+                    shutdown_timeout_ms: Some(5000),
+                }));
+            let result = parse_put_actions(&Body::new(json));
+            assert_eq!(result.unwrap(), req);
+        }
+
+        {
+            // The shutdown timeout is only allowed for the SendShutdown action.
+            let json = r#"{
+                "action_type": "FlushMetrics",
+                "shutdown_timeout_ms": 5000
+            }"#;
+
+            parse_put_actions(&Body::new(json)).unwrap_err();
+        }
     }
 }
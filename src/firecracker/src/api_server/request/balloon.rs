@@ -53,6 +53,8 @@ pub(crate) fn parse_patch_balloon(
 
 #[cfg(test)]
 mod tests {
+    use vmm::vmm_config::balloon::BalloonAutoTargetConfig;
+
     use super::*;
     use crate::api_server::parsed_request::tests::vmm_action_from_request;
 
@@ -140,6 +142,28 @@ mod tests {
         }"#;
         let expected_config = BalloonUpdateStatsConfig {
             stats_polling_interval_s: 1,
+            stats_push: None,
+            auto_target: None,
+        };
+        assert_eq!(
+            vmm_action_from_request(
+                parse_patch_balloon(&Body::new(body), Some("statistics")).unwrap()
+            ),
+            VmmAction::UpdateBalloonStatistics(expected_config)
+        );
+
+        let body = r#"{
+            "stats_polling_interval_s": 1,
+            "auto_target": { "min_mib": 0, "max_mib": 512, "available_mib": 128 }
+        }"#;
+        let expected_config = BalloonUpdateStatsConfig {
+            stats_polling_interval_s: 1,
+            stats_push: None,
+            auto_target: Some(BalloonAutoTargetConfig {
+                min_mib: 0,
+                max_mib: 512,
+                available_mib: 128,
+            }),
         };
         assert_eq!(
             vmm_action_from_request(
@@ -32,8 +32,13 @@ mod tests {
         }"#;
         let same_body = BootSourceConfig {
             kernel_image_path: String::from("/foo/bar"),
-            initrd_path: Some(String::from("/bar/foo")),
-            boot_args: Some(String::from("foobar")),
+            initrd_path: Some(String::from("/bar/foo").into()),
+            boot_args: Some(String::from("foobar").into()),
+            kernel_image_fd: None,
+            initrd_fd: None,
+            firmware_path: None,
+            kernel_digest: None,
+            initrd_digest: None,
         };
         let parsed_req = parse_put_boot_source(&Body::new(body)).unwrap();
 
@@ -1,6 +1,7 @@
 // Copyright 2022 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
+use micro_http::StatusCode;
 use vmm::cpu_config::templates::CustomCpuTemplate;
 use vmm::logger::{IncMetric, METRICS};
 use vmm::rpc_interface::VmmAction;
@@ -8,16 +9,39 @@ use vmm::rpc_interface::VmmAction;
 use super::super::parsed_request::{ParsedRequest, RequestError};
 use super::Body;
 
-pub(crate) fn parse_put_cpu_config(body: &Body) -> Result<ParsedRequest, RequestError> {
+pub(crate) fn parse_put_cpu_config(
+    body: &Body,
+    query: Option<&str>,
+) -> Result<ParsedRequest, RequestError> {
     METRICS.put_api_requests.cpu_cfg_count.inc();
 
-    // Convert the API request into a a deserialized/binary format
-    Ok(ParsedRequest::new_sync(VmmAction::PutCpuConfiguration(
-        CustomCpuTemplate::try_from(body.raw()).map_err(|err| {
+    let dry_run = match query {
+        None | Some("dry_run=false") => false,
+        Some("dry_run=true") => true,
+        Some(query) => {
             METRICS.put_api_requests.cpu_cfg_fails.inc();
-            RequestError::SerdeJson(err)
-        })?,
-    )))
+            return Err(RequestError::Generic(
+                StatusCode::BadRequest,
+                format!("Invalid query string: {query}. Expected dry_run=true or dry_run=false."),
+            ));
+        }
+    };
+
+    // Convert the API request into a a deserialized/binary format
+    let cpu_template = CustomCpuTemplate::try_from(body.raw()).map_err(|err| {
+        METRICS.put_api_requests.cpu_cfg_fails.inc();
+        RequestError::SerdeJson(err)
+    })?;
+    if dry_run {
+        // The template is only validated against the host, without being applied.
+        Ok(ParsedRequest::new_sync(
+            VmmAction::ValidateCpuConfiguration(cpu_template),
+        ))
+    } else {
+        Ok(ParsedRequest::new_sync(VmmAction::PutCpuConfiguration(
+            cpu_template,
+        )))
+    }
 }
 
 #[cfg(test)]
@@ -44,13 +68,25 @@ mod tests {
         // was read in from a test file.
         assert_eq!(
             vmm_action_from_request(
-                parse_put_cpu_config(&Body::new(cpu_template_json.as_bytes())).unwrap()
+                parse_put_cpu_config(&Body::new(cpu_template_json.as_bytes()), None).unwrap()
+            ),
+            VmmAction::PutCpuConfiguration(cpu_template.clone())
+        );
+
+        // Test that a dry run only validates the CPU config.
+        assert_eq!(
+            vmm_action_from_request(
+                parse_put_cpu_config(
+                    &Body::new(cpu_template_json.as_bytes()),
+                    Some("dry_run=true")
+                )
+                .unwrap()
             ),
-            VmmAction::PutCpuConfiguration(cpu_template)
+            VmmAction::ValidateCpuConfiguration(cpu_template)
         );
 
         // Test empty request succeeds
-        let parse_cpu_config_result = parse_put_cpu_config(&Body::new(r#"{ }"#));
+        let parse_cpu_config_result = parse_put_cpu_config(&Body::new(r#"{ }"#), None);
         assert!(
             parse_cpu_config_result.is_ok(),
             "Failed to parse cpu-config: [{}]",
@@ -67,7 +103,7 @@ mod tests {
 
         // Test case for invalid payload
         let unparsable_cpu_config_result =
-            parse_put_cpu_config(&Body::new("<unparseable_payload>"));
+            parse_put_cpu_config(&Body::new("<unparseable_payload>"), None);
         unparsable_cpu_config_result.unwrap_err();
         assert_eq!(
             METRICS.put_api_requests.cpu_cfg_fails.count(),
@@ -75,7 +111,7 @@ mod tests {
         );
 
         // Test request with invalid fields
-        let invalid_put_result = parse_put_cpu_config(&Body::new(TEST_INVALID_TEMPLATE_JSON));
+        let invalid_put_result = parse_put_cpu_config(&Body::new(TEST_INVALID_TEMPLATE_JSON), None);
         expected_err_count += 1;
 
         assert_eq!(
@@ -87,5 +123,22 @@ mod tests {
             "{:?}",
             invalid_put_result
         );
+
+        // Test request with an invalid query string
+        let invalid_query_result = parse_put_cpu_config(&Body::new(r#"{ }"#), Some("dry_run=1"));
+        expected_err_count += 1;
+
+        assert_eq!(
+            METRICS.put_api_requests.cpu_cfg_fails.count(),
+            expected_err_count
+        );
+        assert!(
+            matches!(
+                invalid_query_result,
+                Err(RequestError::Generic(StatusCode::BadRequest, _))
+            ),
+            "{:?}",
+            invalid_query_result
+        );
     }
 }
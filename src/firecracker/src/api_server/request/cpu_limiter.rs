@@ -0,0 +1,70 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use vmm::rpc_interface::VmmAction;
+use vmm::vmm_config::cpu_limiter::CpuLimiterConfig;
+
+use super::super::parsed_request::{ParsedRequest, RequestError};
+use super::Body;
+
+pub(crate) fn parse_put_cpu_limiter(body: &Body) -> Result<ParsedRequest, RequestError> {
+    let cfg = serde_json::from_slice::<CpuLimiterConfig>(body.raw())?;
+    Ok(ParsedRequest::new_sync(VmmAction::SetCpuLimiter(cfg)))
+}
+
+pub(crate) fn parse_patch_cpu_limiter(body: &Body) -> Result<ParsedRequest, RequestError> {
+    let cfg = serde_json::from_slice::<CpuLimiterConfig>(body.raw())?;
+    Ok(ParsedRequest::new_sync(VmmAction::UpdateCpuLimiter(cfg)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_put_cpu_limiter_request() {
+        parse_put_cpu_limiter(&Body::new("invalid_payload")).unwrap_err();
+
+        // PUT with missing fields.
+        parse_put_cpu_limiter(&Body::new("{}")).unwrap_err();
+
+        // PUT with invalid fields.
+        let body = r#"{
+            "millicpus": 1500,
+            "quota_us": 100000
+        }"#;
+        parse_put_cpu_limiter(&Body::new(body)).unwrap_err();
+
+        // PUT with valid fields.
+        let body = r#"{
+            "millicpus": 1500,
+            "burst_ms": 10
+        }"#;
+        let expected_cfg = CpuLimiterConfig {
+            millicpus: 1500,
+            burst_ms: 10,
+        };
+        assert_eq!(
+            parse_put_cpu_limiter(&Body::new(body)).unwrap(),
+            ParsedRequest::new_sync(VmmAction::SetCpuLimiter(expected_cfg))
+        );
+    }
+
+    #[test]
+    fn test_parse_patch_cpu_limiter_request() {
+        parse_patch_cpu_limiter(&Body::new("invalid_payload")).unwrap_err();
+
+        // PATCH with valid fields, the burst defaulting to 0.
+        let body = r#"{
+            "millicpus": 500
+        }"#;
+        let expected_cfg = CpuLimiterConfig {
+            millicpus: 500,
+            burst_ms: 0,
+        };
+        assert_eq!(
+            parse_patch_cpu_limiter(&Body::new(body)).unwrap(),
+            ParsedRequest::new_sync(VmmAction::UpdateCpuLimiter(expected_cfg))
+        );
+    }
+}
@@ -69,6 +69,8 @@ pub(crate) fn parse_patch_drive(
 
 #[cfg(test)]
 mod tests {
+    use vmm::vmm_config::DeviceQueuesState;
+
     use super::*;
     use crate::api_server::parsed_request::tests::vmm_action_from_request;
 
@@ -137,6 +139,7 @@ mod tests {
             drive_id: "foo".to_string(),
             path_on_host: Some("dummy".to_string()),
             rate_limiter: None,
+            state: None,
         };
         assert_eq!(
             vmm_action_from_request(parse_patch_drive(&Body::new(body), Some("foo")).unwrap()),
@@ -194,6 +197,28 @@ mod tests {
         }"#;
         // Validate that parse_patch_drive fails for invalid rate limiter cfg.
         parse_patch_drive(&Body::new(body), Some("foo")).unwrap_err();
+
+        // PATCH pausing the drive.
+        let body = r#"{
+            "drive_id": "foo",
+            "state": "paused"
+        }"#;
+        let expected_config = BlockDeviceUpdateConfig {
+            drive_id: "foo".to_string(),
+            state: Some(DeviceQueuesState::Paused),
+            ..Default::default()
+        };
+        assert_eq!(
+            vmm_action_from_request(parse_patch_drive(&Body::new(body), Some("foo")).unwrap()),
+            VmmAction::UpdateBlockDevice(expected_config)
+        );
+
+        // PATCH with an invalid state.
+        let body = r#"{
+            "drive_id": "foo",
+            "state": "stopped"
+        }"#;
+        parse_patch_drive(&Body::new(body), Some("foo")).unwrap_err();
     }
 
     #[test]
@@ -243,5 +268,53 @@ mod tests {
             }
         }"#;
         parse_put_drive(&Body::new(body), Some("1000")).unwrap();
+
+        // PUT with the lowercase cache types and direct I/O.
+        for cache_type in ["unsafe", "writeback", "writethrough", "directsync"] {
+            let body = format!(
+                r#"{{
+                    "drive_id": "1000",
+                    "path_on_host": "dummy",
+                    "is_root_device": true,
+                    "is_read_only": true,
+                    "cache_type": "{cache_type}",
+                    "direct_io": true
+                }}"#
+            );
+            parse_put_drive(&Body::new(body), Some("1000")).unwrap();
+        }
+
+        // PUT with an NBD backend.
+        let body = r#"{
+            "drive_id": "1000",
+            "path_on_host": "nbd://10.0.0.1/rootfs",
+            "backend": "nbd",
+            "is_root_device": true,
+            "is_read_only": true
+        }"#;
+        parse_put_drive(&Body::new(body), Some("1000")).unwrap();
+
+        // PUT with latency injection.
+        let body = r#"{
+            "drive_id": "1000",
+            "path_on_host": "dummy",
+            "is_root_device": true,
+            "latency_injection": {
+                "delay_us": 1000,
+                "jitter_us": 500,
+                "error_rate_ppm": 100
+            }
+        }"#;
+        parse_put_drive(&Body::new(body), Some("1000")).unwrap();
+
+        let body = r#"{
+            "drive_id": "1000",
+            "path_on_host": "dummy",
+            "is_root_device": true,
+            "latency_injection": {
+                "delay_ms": 1
+            }
+        }"#;
+        parse_put_drive(&Body::new(body), Some("1000")).unwrap_err();
     }
 }
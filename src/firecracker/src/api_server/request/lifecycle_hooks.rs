@@ -0,0 +1,64 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use vmm::rpc_interface::VmmAction;
+use vmm::vmm_config::lifecycle_hooks::LifecycleHooksConfig;
+
+use super::super::parsed_request::{ParsedRequest, RequestError};
+use super::Body;
+
+pub(crate) fn parse_put_lifecycle_hooks(body: &Body) -> Result<ParsedRequest, RequestError> {
+    let cfg = serde_json::from_slice::<LifecycleHooksConfig>(body.raw())?;
+    Ok(ParsedRequest::new_sync(VmmAction::SetLifecycleHooks(cfg)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use vmm::vmm_config::lifecycle_hooks::{LifecycleEvent, LifecycleHookConfig};
+
+    use super::*;
+
+    #[test]
+    fn test_parse_put_lifecycle_hooks_request() {
+        parse_put_lifecycle_hooks(&Body::new("invalid_payload")).unwrap_err();
+
+        // PUT with missing fields.
+        parse_put_lifecycle_hooks(&Body::new("{}")).unwrap_err();
+
+        // PUT with an invalid event.
+        let body = r#"{
+            "hooks": [{ "event": "pre_pause", "socket_path": "/tmp/hooks.sock" }]
+        }"#;
+        parse_put_lifecycle_hooks(&Body::new(body)).unwrap_err();
+
+        // PUT with valid fields.
+        let body = r#"{
+            "hooks": [
+                { "event": "post_boot", "command": ["/usr/bin/notify", "booted"] },
+                { "event": "pre_shutdown", "socket_path": "/tmp/hooks.sock", "timeout_ms": 100 }
+            ]
+        }"#;
+        let expected_cfg = LifecycleHooksConfig {
+            hooks: vec![
+                LifecycleHookConfig {
+                    event: LifecycleEvent::PostBoot,
+                    command: Some(vec!["/usr/bin/notify".to_string(), "booted".to_string()]),
+                    socket_path: None,
+                    timeout_ms: 5000,
+                },
+                LifecycleHookConfig {
+                    event: LifecycleEvent::PreShutdown,
+                    command: None,
+                    socket_path: Some(PathBuf::from("/tmp/hooks.sock")),
+                    timeout_ms: 100,
+                },
+            ],
+        };
+        assert_eq!(
+            parse_put_lifecycle_hooks(&Body::new(body)).unwrap(),
+            ParsedRequest::new_sync(VmmAction::SetLifecycleHooks(expected_cfg))
+        );
+    }
+}
@@ -72,7 +72,7 @@ pub(crate) fn parse_patch_machine_config(body: &Body) -> Result<ParsedRequest, R
 #[cfg(test)]
 mod tests {
     use vmm::cpu_config::templates::StaticCpuTemplate;
-    use vmm::vmm_config::machine_config::HugePageConfig;
+    use vmm::vmm_config::machine_config::{HugePageConfig, MemoryBackend};
 
     use super::*;
     use crate::api_server::parsed_request::tests::{depr_action_from_req, vmm_action_from_request};
@@ -121,6 +121,13 @@ mod tests {
                 cpu_template: None,
                 track_dirty_pages: Some(false),
                 huge_pages: Some(expected),
+                pmu: Some(false),
+                nested_virt: Some(false),
+                memory_tiers: Some(vec![]),
+                disabled_legacy_devices: Some(vec![]),
+                reserved_memory: Some(vec![]),
+                memory_backend: Some(MemoryBackend::Anonymous),
+                prefault: Some(false),
             };
             assert_eq!(
                 vmm_action_from_request(parse_put_machine_config(&Body::new(body)).unwrap()),
@@ -140,6 +147,13 @@ mod tests {
             cpu_template: Some(StaticCpuTemplate::None),
             track_dirty_pages: Some(false),
             huge_pages: Some(HugePageConfig::None),
+            pmu: Some(false),
+            nested_virt: Some(false),
+            memory_tiers: Some(vec![]),
+            disabled_legacy_devices: Some(vec![]),
+            reserved_memory: Some(vec![]),
+            memory_backend: Some(MemoryBackend::Anonymous),
+            prefault: Some(false),
         };
         assert_eq!(
             vmm_action_from_request(parse_put_machine_config(&Body::new(body)).unwrap()),
@@ -159,6 +173,13 @@ mod tests {
             cpu_template: None,
             track_dirty_pages: Some(true),
             huge_pages: Some(HugePageConfig::None),
+            pmu: Some(false),
+            nested_virt: Some(false),
+            memory_tiers: Some(vec![]),
+            disabled_legacy_devices: Some(vec![]),
+            reserved_memory: Some(vec![]),
+            memory_backend: Some(MemoryBackend::Anonymous),
+            prefault: Some(false),
         };
         assert_eq!(
             vmm_action_from_request(parse_put_machine_config(&Body::new(body)).unwrap()),
@@ -182,6 +203,13 @@ mod tests {
                 cpu_template: Some(StaticCpuTemplate::T2),
                 track_dirty_pages: Some(true),
                 huge_pages: Some(HugePageConfig::None),
+                pmu: Some(false),
+                nested_virt: Some(false),
+                memory_tiers: Some(vec![]),
+                disabled_legacy_devices: Some(vec![]),
+                reserved_memory: Some(vec![]),
+                memory_backend: Some(MemoryBackend::Anonymous),
+                prefault: Some(false),
             };
             assert_eq!(
                 vmm_action_from_request(parse_put_machine_config(&Body::new(body)).unwrap()),
@@ -207,13 +235,46 @@ mod tests {
             cpu_template: None,
             track_dirty_pages: Some(true),
             huge_pages: Some(HugePageConfig::None),
+            pmu: Some(false),
+            nested_virt: Some(false),
+            memory_tiers: Some(vec![]),
+            disabled_legacy_devices: Some(vec![]),
+            reserved_memory: Some(vec![]),
+            memory_backend: Some(MemoryBackend::Anonymous),
+            prefault: Some(false),
         };
         assert_eq!(
             vmm_action_from_request(parse_put_machine_config(&Body::new(body)).unwrap()),
             VmmAction::UpdateVmConfiguration(expected_config)
         );
 
-        // 6. Test nonsense values for huge page size
+        // 6. Test that setting `pmu: true` is successful
+        let body = r#"{
+            "vcpu_count": 8,
+            "mem_size_mib": 1024,
+            "pmu": true
+        }"#;
+        let expected_config = MachineConfigUpdate {
+            vcpu_count: Some(8),
+            mem_size_mib: Some(1024),
+            smt: Some(false),
+            cpu_template: None,
+            track_dirty_pages: Some(false),
+            huge_pages: Some(HugePageConfig::None),
+            pmu: Some(true),
+            nested_virt: Some(false),
+            memory_tiers: Some(vec![]),
+            disabled_legacy_devices: Some(vec![]),
+            reserved_memory: Some(vec![]),
+            memory_backend: Some(MemoryBackend::Anonymous),
+            prefault: Some(false),
+        };
+        assert_eq!(
+            vmm_action_from_request(parse_put_machine_config(&Body::new(body)).unwrap()),
+            VmmAction::UpdateVmConfiguration(expected_config)
+        );
+
+        // 7. Test nonsense values for huge page size
         let body = r#"{
             "vcpu_count": 8,
             "mem_size_mib": 1024,
@@ -0,0 +1,71 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use vmm::rpc_interface::VmmAction;
+use vmm::vmm_config::memory_target::MemoryTargetConfig;
+
+use super::super::parsed_request::{ParsedRequest, RequestError};
+use super::{Body, StatusCode};
+
+pub(crate) fn parse_get_memory(path_token: Option<&str>) -> Result<ParsedRequest, RequestError> {
+    match path_token {
+        Some("target") => Ok(ParsedRequest::new_sync(VmmAction::GetMemoryTarget)),
+        Some(unknown_path) => Err(RequestError::Generic(
+            StatusCode::BadRequest,
+            format!("Unrecognized GET request path `{}`.", unknown_path),
+        )),
+        None => Err(RequestError::Generic(
+            StatusCode::BadRequest,
+            "Unrecognized GET request path `memory`.".to_string(),
+        )),
+    }
+}
+
+pub(crate) fn parse_put_memory(
+    body: &Body,
+    path_token: Option<&str>,
+) -> Result<ParsedRequest, RequestError> {
+    match path_token {
+        Some("target") => Ok(ParsedRequest::new_sync(VmmAction::UpdateMemoryTarget(
+            serde_json::from_slice::<MemoryTargetConfig>(body.raw())?,
+        ))),
+        Some(unknown_path) => Err(RequestError::Generic(
+            StatusCode::BadRequest,
+            format!("Unrecognized PUT request path `{}`.", unknown_path),
+        )),
+        None => Err(RequestError::Generic(
+            StatusCode::BadRequest,
+            "Unrecognized PUT request path `memory`.".to_string(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_get_memory_request() {
+        assert_eq!(
+            parse_get_memory(Some("target")).unwrap(),
+            ParsedRequest::new_sync(VmmAction::GetMemoryTarget)
+        );
+        parse_get_memory(Some("slots")).unwrap_err();
+        parse_get_memory(None).unwrap_err();
+    }
+
+    #[test]
+    fn test_parse_put_memory_request() {
+        let body = r#"{"target_mib": 512}"#;
+        assert_eq!(
+            parse_put_memory(&Body::new(body), Some("target")).unwrap(),
+            ParsedRequest::new_sync(VmmAction::UpdateMemoryTarget(MemoryTargetConfig {
+                target_mib: 512
+            }))
+        );
+        parse_put_memory(&Body::new(body), None).unwrap_err();
+        parse_put_memory(&Body::new(body), Some("size")).unwrap_err();
+        parse_put_memory(&Body::new(r#"{"amount_mib": 512}"#), Some("target")).unwrap_err();
+        parse_put_memory(&Body::new("invalid_payload"), Some("target")).unwrap_err();
+    }
+}
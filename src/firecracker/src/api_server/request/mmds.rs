@@ -5,14 +5,24 @@ use micro_http::StatusCode;
 use vmm::logger::{IncMetric, METRICS};
 use vmm::mmds::data_store::MmdsVersion;
 use vmm::rpc_interface::VmmAction;
-use vmm::vmm_config::mmds::MmdsConfig;
+use vmm::vmm_config::mmds::{MmdsConfig, MmdsSessionRevocation};
 
 use super::super::parsed_request::{ParsedRequest, RequestError};
 use super::Body;
 
-pub(crate) fn parse_get_mmds() -> Result<ParsedRequest, RequestError> {
+pub(crate) fn parse_get_mmds(
+    path_second_token: Option<&str>,
+) -> Result<ParsedRequest, RequestError> {
     METRICS.get_api_requests.mmds_count.inc();
-    Ok(ParsedRequest::new_sync(VmmAction::GetMMDS))
+    match path_second_token {
+        None => Ok(ParsedRequest::new_sync(VmmAction::GetMMDS)),
+        Some("guest-data") => Ok(ParsedRequest::new_sync(VmmAction::GetMmdsGuestData)),
+        Some("sessions") => Ok(ParsedRequest::new_sync(VmmAction::GetMmdsSessions)),
+        Some(unrecognized) => Err(RequestError::Generic(
+            StatusCode::BadRequest,
+            format!("Unrecognized GET request path `{}`.", unrecognized),
+        )),
+    }
 }
 
 fn parse_put_mmds_config(body: &Body) -> Result<ParsedRequest, RequestError> {
@@ -34,19 +44,31 @@ fn parse_put_mmds_config(body: &Body) -> Result<ParsedRequest, RequestError> {
     Ok(parsed_request)
 }
 
+fn parse_put_mmds_session_revocation(body: &Body) -> Result<ParsedRequest, RequestError> {
+    let revocation: MmdsSessionRevocation =
+        serde_json::from_slice(body.raw()).inspect_err(|_| {
+            METRICS.put_api_requests.mmds_fails.inc();
+        })?;
+    Ok(ParsedRequest::new_sync(VmmAction::RevokeMmdsSession(
+        revocation,
+    )))
+}
+
 pub(crate) fn parse_put_mmds(
     body: &Body,
     path_second_token: Option<&str>,
+    path_third_token: Option<&str>,
 ) -> Result<ParsedRequest, RequestError> {
     METRICS.put_api_requests.mmds_count.inc();
-    match path_second_token {
-        None => Ok(ParsedRequest::new_sync(VmmAction::PutMMDS(
+    match (path_second_token, path_third_token) {
+        (None, _) => Ok(ParsedRequest::new_sync(VmmAction::PutMMDS(
             serde_json::from_slice(body.raw()).inspect_err(|_| {
                 METRICS.put_api_requests.mmds_fails.inc();
             })?,
         ))),
-        Some("config") => parse_put_mmds_config(body),
-        Some(unrecognized) => {
+        (Some("config"), None) => parse_put_mmds_config(body),
+        (Some("sessions"), Some("revoke")) => parse_put_mmds_session_revocation(body),
+        (Some(unrecognized), _) => {
             METRICS.put_api_requests.mmds_fails.inc();
             Err(RequestError::Generic(
                 StatusCode::BadRequest,
@@ -68,12 +90,22 @@ pub(crate) fn parse_patch_mmds(body: &Body) -> Result<ParsedRequest, RequestErro
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::api_server::parsed_request::tests::depr_action_from_req;
+    use crate::api_server::parsed_request::tests::{depr_action_from_req, vmm_action_from_request};
 
     #[test]
     fn test_parse_get_mmds_request() {
-        parse_get_mmds().unwrap();
+        parse_get_mmds(None).unwrap();
         assert!(METRICS.get_api_requests.mmds_count.count() > 0);
+
+        assert_eq!(
+            vmm_action_from_request(parse_get_mmds(Some("guest-data")).unwrap()),
+            VmmAction::GetMmdsGuestData
+        );
+        assert_eq!(
+            vmm_action_from_request(parse_get_mmds(Some("sessions")).unwrap()),
+            VmmAction::GetMmdsSessions
+        );
+        parse_get_mmds(Some("invalid_path")).unwrap_err();
     }
 
     #[test]
@@ -81,10 +113,10 @@ mod tests {
         let body = r#"{
             "foo": "bar"
         }"#;
-        parse_put_mmds(&Body::new(body), None).unwrap();
+        parse_put_mmds(&Body::new(body), None, None).unwrap();
 
         let invalid_body = "invalid_body";
-        parse_put_mmds(&Body::new(invalid_body), None).unwrap_err();
+        parse_put_mmds(&Body::new(invalid_body), None, None).unwrap_err();
         assert!(METRICS.put_api_requests.mmds_fails.count() > 0);
 
         // Test `config` path.
@@ -94,37 +126,68 @@ mod tests {
             "network_interfaces": []
         }"#;
         let config_path = "config";
-        parse_put_mmds(&Body::new(body), Some(config_path)).unwrap();
+        parse_put_mmds(&Body::new(body), Some(config_path), None).unwrap();
 
         let body = r#"{
             "network_interfaces": []
         }"#;
-        parse_put_mmds(&Body::new(body), Some(config_path)).unwrap();
+        parse_put_mmds(&Body::new(body), Some(config_path), None).unwrap();
+
+        let body = r#"{
+            "version": "V2",
+            "network_interfaces": [],
+            "guest_data_limit": 1024
+        }"#;
+        parse_put_mmds(&Body::new(body), Some(config_path), None).unwrap();
 
         let body = r#"{
             "version": "foo",
             "ipv4_address": "169.254.170.2",
             "network_interfaces": []
         }"#;
-        parse_put_mmds(&Body::new(body), Some(config_path)).unwrap_err();
+        parse_put_mmds(&Body::new(body), Some(config_path), None).unwrap_err();
 
         let body = r#"{
             "version": "V2"
         }"#;
-        parse_put_mmds(&Body::new(body), Some(config_path)).unwrap_err();
+        parse_put_mmds(&Body::new(body), Some(config_path), None).unwrap_err();
 
         let body = r#"{
             "ipv4_address": "",
             "network_interfaces": []
         }"#;
-        parse_put_mmds(&Body::new(body), Some(config_path)).unwrap_err();
+        parse_put_mmds(&Body::new(body), Some(config_path), None).unwrap_err();
 
         let invalid_config_body = r#"{
             "invalid_config": "invalid_value"
         }"#;
-        parse_put_mmds(&Body::new(invalid_config_body), Some(config_path)).unwrap_err();
-        parse_put_mmds(&Body::new(body), Some("invalid_path")).unwrap_err();
-        parse_put_mmds(&Body::new(invalid_body), Some(config_path)).unwrap_err();
+        parse_put_mmds(&Body::new(invalid_config_body), Some(config_path), None).unwrap_err();
+        parse_put_mmds(&Body::new(body), Some("invalid_path"), None).unwrap_err();
+        parse_put_mmds(&Body::new(invalid_body), Some(config_path), None).unwrap_err();
+    }
+
+    #[test]
+    fn test_parse_put_mmds_session_revocation() {
+        let body = r#"{
+            "session_id": "0123456789abcdef01234567"
+        }"#;
+        assert_eq!(
+            vmm_action_from_request(
+                parse_put_mmds(&Body::new(body), Some("sessions"), Some("revoke")).unwrap()
+            ),
+            VmmAction::RevokeMmdsSession(MmdsSessionRevocation {
+                session_id: "0123456789abcdef01234567".to_string(),
+            })
+        );
+
+        let invalid_body = r#"{
+            "session_id": "0123456789abcdef01234567",
+            "foo": "bar"
+        }"#;
+        parse_put_mmds(&Body::new(invalid_body), Some("sessions"), Some("revoke")).unwrap_err();
+        parse_put_mmds(&Body::new(body), Some("sessions"), None).unwrap_err();
+        parse_put_mmds(&Body::new(body), Some("sessions"), Some("invalid_path")).unwrap_err();
+        parse_put_mmds(&Body::new(body), Some("config"), Some("revoke")).unwrap_err();
     }
 
     #[test]
@@ -136,7 +199,7 @@ mod tests {
             "network_interfaces": []
         }"#;
         depr_action_from_req(
-            parse_put_mmds(&Body::new(body), Some(config_path)).unwrap(),
+            parse_put_mmds(&Body::new(body), Some(config_path), None).unwrap(),
             Some("PUT /mmds/config: V1 is deprecated. Use V2 instead.".to_string()),
         );
 
@@ -146,7 +209,7 @@ mod tests {
             "network_interfaces": []
         }"#;
         depr_action_from_req(
-            parse_put_mmds(&Body::new(body), Some(config_path)).unwrap(),
+            parse_put_mmds(&Body::new(body), Some(config_path), None).unwrap(),
             Some("PUT /mmds/config: V1 is deprecated. Use V2 instead.".to_string()),
         );
 
@@ -155,7 +218,7 @@ mod tests {
             "ipv4_address": "169.254.170.2",
             "network_interfaces": []
         }"#;
-        let (_, mut parsing_info) = parse_put_mmds(&Body::new(body), Some(config_path))
+        let (_, mut parsing_info) = parse_put_mmds(&Body::new(body), Some(config_path), None)
             .unwrap()
             .into_parts();
         assert!(parsing_info.take_deprecation_message().is_none());
@@ -5,15 +5,25 @@ pub mod actions;
 pub mod balloon;
 pub mod boot_source;
 pub mod cpu_configuration;
+pub mod cpu_limiter;
 pub mod drive;
 pub mod entropy;
 pub mod instance_info;
+pub mod lifecycle_hooks;
 pub mod logger;
 pub mod machine_configuration;
+pub mod memory;
 pub mod metrics;
 pub mod mmds;
 pub mod net;
+pub mod pvpanic;
+pub mod rate_limiters;
+pub mod seccomp;
+pub mod serial;
+pub mod shared_memory;
+pub mod smbios;
 pub mod snapshot;
+pub mod tpm;
 pub mod version;
 pub mod vsock;
 pub use micro_http::{Body, Method, StatusCode};
@@ -3,14 +3,42 @@
 
 use vmm::logger::{IncMetric, METRICS};
 use vmm::rpc_interface::VmmAction;
-use vmm::vmm_config::net::{NetworkInterfaceConfig, NetworkInterfaceUpdateConfig};
+use vmm::vmm_config::net::{
+    NetworkCaptureConfig, NetworkInterfaceConfig, NetworkInterfaceUpdateConfig,
+};
 
 use super::super::parsed_request::{checked_id, ParsedRequest, RequestError};
 use super::{Body, StatusCode};
 
+pub(crate) fn parse_get_net(
+    id_from_path: Option<&str>,
+    path_second_token: Option<&str>,
+) -> Result<ParsedRequest, RequestError> {
+    let id = if let Some(id) = id_from_path {
+        checked_id(id)?
+    } else {
+        return Err(RequestError::EmptyID);
+    };
+
+    match path_second_token {
+        Some("flows") => Ok(ParsedRequest::new_sync(VmmAction::GetNetworkFlows(
+            id.to_string(),
+        ))),
+        Some(unknown_path) => Err(RequestError::Generic(
+            StatusCode::BadRequest,
+            format!("Unrecognized GET request path `{}`.", unknown_path),
+        )),
+        None => Err(RequestError::Generic(
+            StatusCode::BadRequest,
+            "Unrecognized GET request path `network-interfaces`.".to_string(),
+        )),
+    }
+}
+
 pub(crate) fn parse_put_net(
     body: &Body,
     id_from_path: Option<&str>,
+    path_second_token: Option<&str>,
 ) -> Result<ParsedRequest, RequestError> {
     METRICS.put_api_requests.network_count.inc();
     let id = if let Some(id) = id_from_path {
@@ -20,6 +48,27 @@ pub(crate) fn parse_put_net(
         return Err(RequestError::EmptyID);
     };
 
+    match path_second_token {
+        Some("capture") => {
+            let capture =
+                serde_json::from_slice::<NetworkCaptureConfig>(body.raw()).inspect_err(|_| {
+                    METRICS.put_api_requests.network_fails.inc();
+                })?;
+            return Ok(ParsedRequest::new_sync(VmmAction::UpdateNetworkCapture(
+                id.to_string(),
+                capture,
+            )));
+        }
+        Some(unknown_path) => {
+            METRICS.put_api_requests.network_fails.inc();
+            return Err(RequestError::Generic(
+                StatusCode::BadRequest,
+                format!("Unrecognized PUT request path `{}`.", unknown_path),
+            ));
+        }
+        None => (),
+    }
+
     let netif = serde_json::from_slice::<NetworkInterfaceConfig>(body.raw()).inspect_err(|_| {
         METRICS.put_api_requests.network_fails.inc();
     })?;
@@ -73,9 +122,24 @@ pub(crate) fn parse_patch_net(
 
 #[cfg(test)]
 mod tests {
+    use vmm::vmm_config::DeviceQueuesState;
+
     use super::*;
     use crate::api_server::parsed_request::tests::vmm_action_from_request;
 
+    #[test]
+    fn test_parse_get_net_request() {
+        parse_get_net(None, Some("flows")).unwrap_err();
+        parse_get_net(Some("foo"), None).unwrap_err();
+        parse_get_net(Some("foo"), Some("bar")).unwrap_err();
+        parse_get_net(Some("foo bar"), Some("flows")).unwrap_err();
+
+        assert_eq!(
+            vmm_action_from_request(parse_get_net(Some("foo"), Some("flows")).unwrap()),
+            VmmAction::GetNetworkFlows("foo".to_string())
+        );
+    }
+
     #[test]
     fn test_parse_put_net_request() {
         let body = r#"{
@@ -84,14 +148,14 @@ mod tests {
             "guest_mac": "12:34:56:78:9A:BC"
         }"#;
         // 1. Exercise infamous "The id from the path does not match id from the body!".
-        parse_put_net(&Body::new(body), Some("bar")).unwrap_err();
+        parse_put_net(&Body::new(body), Some("bar"), None).unwrap_err();
         // 2. The `id_from_path` cannot be None.
-        parse_put_net(&Body::new(body), None).unwrap_err();
+        parse_put_net(&Body::new(body), None, None).unwrap_err();
 
         // 3. Success case.
         let expected_config = serde_json::from_str::<NetworkInterfaceConfig>(body).unwrap();
         assert_eq!(
-            vmm_action_from_request(parse_put_net(&Body::new(body), Some("foo")).unwrap()),
+            vmm_action_from_request(parse_put_net(&Body::new(body), Some("foo"), None).unwrap()),
             VmmAction::InsertNetworkDevice(expected_config)
         );
 
@@ -111,7 +175,32 @@ mod tests {
                 }
             }
         }"#;
-        parse_put_net(&Body::new(body), Some("foo")).unwrap_err();
+        parse_put_net(&Body::new(body), Some("foo"), None).unwrap_err();
+    }
+
+    #[test]
+    fn test_parse_put_net_capture_request() {
+        let body = r#"{
+            "enabled": true,
+            "path": "/tmp/foo.pcap",
+            "snaplen": 128
+        }"#;
+        let expected_config = serde_json::from_str::<NetworkCaptureConfig>(body).unwrap();
+        assert_eq!(
+            vmm_action_from_request(
+                parse_put_net(&Body::new(body), Some("foo"), Some("capture")).unwrap()
+            ),
+            VmmAction::UpdateNetworkCapture("foo".to_string(), expected_config)
+        );
+
+        parse_put_net(&Body::new(body), Some("foo"), Some("bar")).unwrap_err();
+        parse_put_net(&Body::new(body), None, Some("capture")).unwrap_err();
+        parse_put_net(
+            &Body::new(r#"{"path": "/tmp/foo.pcap"}"#),
+            Some("foo"),
+            Some("capture"),
+        )
+        .unwrap_err();
     }
 
     #[test]
@@ -150,5 +239,21 @@ mod tests {
             }
         }"#;
         parse_patch_net(&Body::new(body), Some("foo")).unwrap_err();
+
+        // 5. Pausing the queues.
+        let body = r#"{
+            "iface_id": "foo",
+            "state": "paused"
+        }"#;
+        let expected_config = NetworkInterfaceUpdateConfig {
+            iface_id: "foo".to_string(),
+            rx_rate_limiter: None,
+            tx_rate_limiter: None,
+            state: Some(DeviceQueuesState::Paused),
+        };
+        assert_eq!(
+            vmm_action_from_request(parse_patch_net(&Body::new(body), Some("foo")).unwrap()),
+            VmmAction::UpdateNetworkInterface(expected_config)
+        );
     }
 }
@@ -0,0 +1,63 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use vmm::rpc_interface::VmmAction;
+use vmm::vmm_config::pvpanic::PvPanicConfig;
+
+use super::super::parsed_request::{ParsedRequest, RequestError};
+use super::Body;
+
+pub(crate) fn parse_put_pvpanic(body: &Body) -> Result<ParsedRequest, RequestError> {
+    let cfg = serde_json::from_slice::<PvPanicConfig>(body.raw())?;
+    Ok(ParsedRequest::new_sync(VmmAction::SetPvPanicDevice(cfg)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use vmm::vmm_config::pvpanic::PanicSnapshotConfig;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_put_pvpanic_request() {
+        parse_put_pvpanic(&Body::new("invalid_payload")).unwrap_err();
+
+        // PUT with invalid fields.
+        let body = r#"{
+            "some_field": "some_value"
+        }"#;
+        parse_put_pvpanic(&Body::new(body)).unwrap_err();
+
+        // PUT with missing snapshot fields.
+        let body = r#"{
+            "snapshot": { "snapshot_path": "/tmp/panic.snap" }
+        }"#;
+        parse_put_pvpanic(&Body::new(body)).unwrap_err();
+
+        // PUT without a snapshot on panic.
+        assert_eq!(
+            parse_put_pvpanic(&Body::new("{}")).unwrap(),
+            ParsedRequest::new_sync(VmmAction::SetPvPanicDevice(PvPanicConfig::default()))
+        );
+
+        // PUT with a snapshot on panic.
+        let body = r#"{
+            "snapshot": {
+                "snapshot_path": "/tmp/panic.snap",
+                "mem_file_path": "/tmp/panic.mem"
+            }
+        }"#;
+        let expected_cfg = PvPanicConfig {
+            snapshot: Some(PanicSnapshotConfig {
+                snapshot_path: PathBuf::from("/tmp/panic.snap"),
+                mem_file_path: PathBuf::from("/tmp/panic.mem"),
+            }),
+        };
+        assert_eq!(
+            parse_put_pvpanic(&Body::new(body)).unwrap(),
+            ParsedRequest::new_sync(VmmAction::SetPvPanicDevice(expected_cfg))
+        );
+    }
+}
@@ -0,0 +1,98 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use vmm::rpc_interface::VmmAction;
+use vmm::vmm_config::rate_limiters::SharedRateLimiterConfig;
+
+use super::super::parsed_request::{checked_id, ParsedRequest, RequestError};
+use super::{Body, StatusCode};
+
+pub(crate) fn parse_get_rate_limiters(
+    path_token: Option<&str>,
+) -> Result<ParsedRequest, RequestError> {
+    match path_token {
+        None => Ok(ParsedRequest::new_sync(VmmAction::GetSharedRateLimiters)),
+        Some(unknown_path) => Err(RequestError::Generic(
+            StatusCode::BadRequest,
+            format!("Unrecognized GET request path `{}`.", unknown_path),
+        )),
+    }
+}
+
+pub(crate) fn parse_put_rate_limiter(
+    body: &Body,
+    id_from_path: Option<&str>,
+) -> Result<ParsedRequest, RequestError> {
+    let id = match id_from_path {
+        Some(id) => checked_id(id)?,
+        None => return Err(RequestError::EmptyID),
+    };
+
+    let cfg = serde_json::from_slice::<SharedRateLimiterConfig>(body.raw())?;
+    if id != cfg.id {
+        return Err(RequestError::Generic(
+            StatusCode::BadRequest,
+            "The id from the path does not match the id from the body!".to_string(),
+        ));
+    }
+
+    Ok(ParsedRequest::new_sync(VmmAction::InsertSharedRateLimiter(
+        cfg,
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use vmm::vmm_config::TokenBucketConfig;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_get_rate_limiters_request() {
+        assert_eq!(
+            parse_get_rate_limiters(None).unwrap(),
+            ParsedRequest::new_sync(VmmAction::GetSharedRateLimiters)
+        );
+        parse_get_rate_limiters(Some("drives")).unwrap_err();
+    }
+
+    #[test]
+    fn test_parse_put_rate_limiter_request() {
+        parse_put_rate_limiter(&Body::new("invalid_payload"), None).unwrap_err();
+        parse_put_rate_limiter(&Body::new("invalid_payload"), Some("id")).unwrap_err();
+
+        let body = r#"{
+            "id": "drives",
+            "bandwidth": {
+                "size": 104857600,
+                "refill_time": 1000
+            }
+        }"#;
+        // PUT with a mismatching id.
+        parse_put_rate_limiter(&Body::new(body), Some("other")).unwrap_err();
+        // PUT without id.
+        parse_put_rate_limiter(&Body::new(body), None).unwrap_err();
+
+        // PUT with invalid fields.
+        let invalid_body = r#"{
+            "id": "drives",
+            "borrow": true
+        }"#;
+        parse_put_rate_limiter(&Body::new(invalid_body), Some("drives")).unwrap_err();
+
+        // PUT with valid fields.
+        let expected_cfg = SharedRateLimiterConfig {
+            id: "drives".to_string(),
+            bandwidth: Some(TokenBucketConfig {
+                size: 104857600,
+                one_time_burst: None,
+                refill_time: 1000,
+            }),
+            ops: None,
+        };
+        assert_eq!(
+            parse_put_rate_limiter(&Body::new(body), Some("drives")).unwrap(),
+            ParsedRequest::new_sync(VmmAction::InsertSharedRateLimiter(expected_cfg))
+        );
+    }
+}
@@ -0,0 +1,49 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use vmm::rpc_interface::VmmAction;
+use vmm::vmm_config::seccomp::SeccompFiltersConfig;
+
+use super::super::parsed_request::{ParsedRequest, RequestError};
+use super::Body;
+
+pub(crate) fn parse_put_seccomp(body: &Body) -> Result<ParsedRequest, RequestError> {
+    let cfg = serde_json::from_slice::<SeccompFiltersConfig>(body.raw())?;
+    Ok(ParsedRequest::new_sync(VmmAction::InstallSeccompFilters(
+        cfg,
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_put_seccomp_request() {
+        parse_put_seccomp(&Body::new("invalid_payload")).unwrap_err();
+
+        // PUT with missing fields.
+        parse_put_seccomp(&Body::new("{}")).unwrap_err();
+
+        // PUT with invalid fields.
+        let body = r#"{
+            "filter_path": "/tmp/filters.bpf",
+            "some_field": "some_value"
+        }"#;
+        parse_put_seccomp(&Body::new(body)).unwrap_err();
+
+        // PUT with valid fields.
+        let body = r#"{
+            "filter_path": "/tmp/filters.bpf"
+        }"#;
+        let expected_cfg = SeccompFiltersConfig {
+            filter_path: PathBuf::from("/tmp/filters.bpf"),
+        };
+        assert_eq!(
+            parse_put_seccomp(&Body::new(body)).unwrap(),
+            ParsedRequest::new_sync(VmmAction::InstallSeccompFilters(expected_cfg))
+        );
+    }
+}
@@ -0,0 +1,77 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use vmm::rpc_interface::VmmAction;
+use vmm::vmm_config::serial::SerialConfig;
+
+use super::super::parsed_request::{ParsedRequest, RequestError};
+use super::{Body, StatusCode};
+
+pub(crate) fn parse_get_serial(path_token: Option<&str>) -> Result<ParsedRequest, RequestError> {
+    match path_token {
+        Some("log") => Ok(ParsedRequest::new_sync(VmmAction::GetSerialLog)),
+        Some(unknown_path) => Err(RequestError::Generic(
+            StatusCode::BadRequest,
+            format!("Unrecognized GET request path `{}`.", unknown_path),
+        )),
+        None => Err(RequestError::Generic(
+            StatusCode::BadRequest,
+            "Unrecognized GET request path `serial`.".to_string(),
+        )),
+    }
+}
+
+pub(crate) fn parse_put_serial(body: &Body) -> Result<ParsedRequest, RequestError> {
+    let cfg = serde_json::from_slice::<SerialConfig>(body.raw())?;
+    Ok(ParsedRequest::new_sync(VmmAction::SetSerialConfiguration(
+        cfg,
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use vmm::vmm_config::serial::SerialMode;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_get_serial_request() {
+        assert_eq!(
+            parse_get_serial(Some("log")).unwrap(),
+            ParsedRequest::new_sync(VmmAction::GetSerialLog)
+        );
+        parse_get_serial(Some("output")).unwrap_err();
+        parse_get_serial(None).unwrap_err();
+    }
+
+    #[test]
+    fn test_parse_put_serial_request() {
+        parse_put_serial(&Body::new("invalid_payload")).unwrap_err();
+
+        // PUT with invalid fields.
+        let body = r#"{
+            "mode": "pty",
+            "socket_path": "/tmp/console"
+        }"#;
+        parse_put_serial(&Body::new(body)).unwrap_err();
+
+        // PUT with valid fields.
+        let body = r#"{
+            "mode": "unix_socket",
+            "path": "/tmp/console.sock",
+            "log_buffer_size": 65536,
+            "replay_log": true
+        }"#;
+        let expected_cfg = SerialConfig {
+            mode: SerialMode::UnixSocket,
+            path: Some("/tmp/console.sock".to_string()),
+            log_buffer_size: Some(65536),
+            replay_log: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            parse_put_serial(&Body::new(body)).unwrap(),
+            ParsedRequest::new_sync(VmmAction::SetSerialConfiguration(expected_cfg))
+        );
+    }
+}
@@ -0,0 +1,69 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use vmm::rpc_interface::VmmAction;
+use vmm::vmm_config::shared_memory::SharedMemoryConfig;
+
+use super::super::parsed_request::{checked_id, ParsedRequest, RequestError};
+use super::{Body, StatusCode};
+
+pub(crate) fn parse_put_shared_memory(
+    body: &Body,
+    id_from_path: Option<&str>,
+) -> Result<ParsedRequest, RequestError> {
+    let id = match id_from_path {
+        Some(id) => checked_id(id)?,
+        None => return Err(RequestError::EmptyID),
+    };
+
+    let cfg = serde_json::from_slice::<SharedMemoryConfig>(body.raw())?;
+    if id != cfg.segment_id {
+        return Err(RequestError::Generic(
+            StatusCode::BadRequest,
+            "The id from the path does not match the id from the body!".to_string(),
+        ));
+    }
+
+    Ok(ParsedRequest::new_sync(VmmAction::InsertSharedMemory(cfg)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_put_shared_memory_request() {
+        parse_put_shared_memory(&Body::new("invalid_payload"), None).unwrap_err();
+        parse_put_shared_memory(&Body::new("invalid_payload"), Some("id")).unwrap_err();
+
+        let body = r#"{
+            "segment_id": "runtime",
+            "path_on_host": "/srv/runtime.img",
+            "guest_addr": 4294967296
+        }"#;
+        // PUT with a mismatching id.
+        parse_put_shared_memory(&Body::new(body), Some("other")).unwrap_err();
+        // PUT without id.
+        parse_put_shared_memory(&Body::new(body), None).unwrap_err();
+
+        // PUT with invalid fields.
+        let invalid_body = r#"{
+            "segment_id": "runtime",
+            "path_on_host": "/srv/runtime.img",
+            "guest_addr": 4294967296,
+            "read_only": false
+        }"#;
+        parse_put_shared_memory(&Body::new(invalid_body), Some("runtime")).unwrap_err();
+
+        // PUT with valid fields.
+        let expected_cfg = SharedMemoryConfig {
+            segment_id: "runtime".to_string(),
+            path_on_host: "/srv/runtime.img".to_string(),
+            guest_addr: 0x1_0000_0000,
+        };
+        assert_eq!(
+            parse_put_shared_memory(&Body::new(body), Some("runtime")).unwrap(),
+            ParsedRequest::new_sync(VmmAction::InsertSharedMemory(expected_cfg))
+        );
+    }
+}
@@ -0,0 +1,50 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use vmm::rpc_interface::VmmAction;
+use vmm::vmm_config::smbios::SmbiosConfig;
+
+use super::super::parsed_request::{ParsedRequest, RequestError};
+use super::Body;
+
+pub(crate) fn parse_put_smbios(body: &Body) -> Result<ParsedRequest, RequestError> {
+    let cfg = serde_json::from_slice::<SmbiosConfig>(body.raw())?;
+    Ok(ParsedRequest::new_sync(VmmAction::SetSmbiosConfiguration(
+        cfg,
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_put_smbios_request() {
+        parse_put_smbios(&Body::new("invalid_payload")).unwrap_err();
+
+        // PUT with invalid fields.
+        let body = r#"{
+            "system_serial": "serial"
+        }"#;
+        parse_put_smbios(&Body::new(body)).unwrap_err();
+
+        // PUT with valid fields.
+        let body = r#"{
+            "system_serial_number": "serial",
+            "system_uuid": "00112233-4455-6677-8899-aabbccddeeff",
+            "chassis_asset_tag": "tag",
+            "oem_strings": ["key=value"]
+        }"#;
+        let expected_cfg = SmbiosConfig {
+            system_serial_number: Some("serial".to_string()),
+            system_uuid: Some("00112233-4455-6677-8899-aabbccddeeff".to_string()),
+            chassis_asset_tag: Some("tag".to_string()),
+            oem_strings: vec!["key=value".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            parse_put_smbios(&Body::new(body)).unwrap(),
+            ParsedRequest::new_sync(VmmAction::SetSmbiosConfiguration(expected_cfg))
+        );
+    }
+}
@@ -5,8 +5,8 @@ use serde::de::Error as DeserializeError;
 use vmm::logger::{IncMetric, METRICS};
 use vmm::rpc_interface::VmmAction;
 use vmm::vmm_config::snapshot::{
-    CreateSnapshotParams, LoadSnapshotConfig, LoadSnapshotParams, MemBackendConfig, MemBackendType,
-    Vm, VmState,
+    CheckSnapshotParams, CreateSnapshotParams, LoadSnapshotConfig, LoadSnapshotParams,
+    MemBackendConfig, MemBackendType, Vm, VmState,
 };
 
 use super::super::parsed_request::{ParsedRequest, RequestError};
@@ -22,12 +22,27 @@ pub const MISSING_FIELD: &str =
 pub const TOO_MANY_FIELDS: &str =
     "too many fields: either `mem_backend` or `mem_file_path` exclusively is required";
 
+pub(crate) fn parse_get_snapshot(path_token: Option<&str>) -> Result<ParsedRequest, RequestError> {
+    match path_token {
+        Some("status") => Ok(ParsedRequest::new_sync(VmmAction::GetSnapshotStatus)),
+        Some(unknown_path) => Err(RequestError::Generic(
+            StatusCode::BadRequest,
+            format!("Unrecognized GET request path `{}`.", unknown_path),
+        )),
+        None => Err(RequestError::Generic(
+            StatusCode::BadRequest,
+            "Unrecognized GET request path `snapshot`.".to_string(),
+        )),
+    }
+}
+
 pub(crate) fn parse_put_snapshot(
     body: &Body,
     request_type_from_path: Option<&str>,
 ) -> Result<ParsedRequest, RequestError> {
     match request_type_from_path {
         Some(request_type) => match request_type {
+            "compat" => parse_put_snapshot_compat(body),
             "create" => parse_put_snapshot_create(body),
             "load" => parse_put_snapshot_load(body),
             _ => Err(RequestError::InvalidPathMethod(
@@ -51,6 +66,13 @@ pub(crate) fn parse_patch_vm_state(body: &Body) -> Result<ParsedRequest, Request
     }
 }
 
+fn parse_put_snapshot_compat(body: &Body) -> Result<ParsedRequest, RequestError> {
+    let check_params = serde_json::from_slice::<CheckSnapshotParams>(body.raw())?;
+    Ok(ParsedRequest::new_sync(
+        VmmAction::CheckSnapshotCompatibility(check_params),
+    ))
+}
+
 fn parse_put_snapshot_create(body: &Body) -> Result<ParsedRequest, RequestError> {
     let snapshot_config = serde_json::from_slice::<CreateSnapshotParams>(body.raw())?;
     Ok(ParsedRequest::new_sync(VmmAction::CreateSnapshot(
@@ -105,6 +127,8 @@ fn parse_put_snapshot_load(body: &Body) -> Result<ParsedRequest, RequestError> {
         mem_backend,
         enable_diff_snapshots: snapshot_config.enable_diff_snapshots,
         resume_vm: snapshot_config.resume_vm,
+        encryption: snapshot_config.encryption,
+        prefault: snapshot_config.prefault,
     };
 
     // Construct the `ParsedRequest` object.
@@ -125,21 +149,42 @@ mod tests {
     use super::*;
     use crate::api_server::parsed_request::tests::{depr_action_from_req, vmm_action_from_request};
 
+    #[test]
+    fn test_parse_get_snapshot() {
+        assert_eq!(
+            vmm_action_from_request(parse_get_snapshot(Some("status")).unwrap()),
+            VmmAction::GetSnapshotStatus
+        );
+        parse_get_snapshot(Some("invalid")).unwrap_err();
+        parse_get_snapshot(None).unwrap_err();
+    }
+
     #[test]
     fn test_parse_put_snapshot() {
         use std::path::PathBuf;
 
-        use vmm::vmm_config::snapshot::SnapshotType;
+        use vmm::vmm_config::snapshot::{SnapshotEncryptionConfig, SnapshotType};
 
         let body = r#"{
             "snapshot_type": "Diff",
             "snapshot_path": "foo",
-            "mem_file_path": "bar"
+            "mem_file_path": "bar",
+            "labels": {
+                "creator": "test",
+                "image_id": "42"
+            }
         }"#;
         let expected_config = CreateSnapshotParams {
             snapshot_type: SnapshotType::Diff,
             snapshot_path: PathBuf::from("foo"),
             mem_file_path: PathBuf::from("bar"),
+            labels: [
+                ("creator".to_string(), "test".to_string()),
+                ("image_id".to_string(), "42".to_string()),
+            ]
+            .into(),
+            encryption: None,
+            background: false,
         };
         assert_eq!(
             vmm_action_from_request(parse_put_snapshot(&Body::new(body), Some("create")).unwrap()),
@@ -154,6 +199,47 @@ mod tests {
             snapshot_type: SnapshotType::Full,
             snapshot_path: PathBuf::from("foo"),
             mem_file_path: PathBuf::from("bar"),
+            labels: Default::default(),
+            encryption: None,
+            background: false,
+        };
+        assert_eq!(
+            vmm_action_from_request(parse_put_snapshot(&Body::new(body), Some("create")).unwrap()),
+            VmmAction::CreateSnapshot(expected_config)
+        );
+
+        let body = r#"{
+            "snapshot_path": "foo",
+            "mem_file_path": "bar",
+            "encryption": {
+                "key_fd": 3
+            }
+        }"#;
+        let expected_config = CreateSnapshotParams {
+            snapshot_type: SnapshotType::Full,
+            snapshot_path: PathBuf::from("foo"),
+            mem_file_path: PathBuf::from("bar"),
+            labels: Default::default(),
+            encryption: Some(SnapshotEncryptionConfig { key_fd: 3 }),
+            background: false,
+        };
+        assert_eq!(
+            vmm_action_from_request(parse_put_snapshot(&Body::new(body), Some("create")).unwrap()),
+            VmmAction::CreateSnapshot(expected_config)
+        );
+
+        let body = r#"{
+            "snapshot_path": "foo",
+            "mem_file_path": "bar",
+            "background": true
+        }"#;
+        let expected_config = CreateSnapshotParams {
+            snapshot_type: SnapshotType::Full,
+            snapshot_path: PathBuf::from("foo"),
+            mem_file_path: PathBuf::from("bar"),
+            labels: Default::default(),
+            encryption: None,
+            background: true,
         };
         assert_eq!(
             vmm_action_from_request(parse_put_snapshot(&Body::new(body), Some("create")).unwrap()),
@@ -181,6 +267,8 @@ mod tests {
             },
             enable_diff_snapshots: false,
             resume_vm: false,
+            encryption: None,
+            prefault: false,
         };
         let mut parsed_request = parse_put_snapshot(&Body::new(body), Some("load")).unwrap();
         assert!(parsed_request
@@ -208,6 +296,8 @@ mod tests {
             },
             enable_diff_snapshots: true,
             resume_vm: false,
+            encryption: None,
+            prefault: false,
         };
         let mut parsed_request = parse_put_snapshot(&Body::new(body), Some("load")).unwrap();
         assert!(parsed_request
@@ -235,6 +325,8 @@ mod tests {
             },
             enable_diff_snapshots: false,
             resume_vm: true,
+            encryption: None,
+            prefault: false,
         };
         let mut parsed_request = parse_put_snapshot(&Body::new(body), Some("load")).unwrap();
         assert!(parsed_request
@@ -246,6 +338,56 @@ mod tests {
             VmmAction::LoadSnapshot(expected_config)
         );
 
+        let body = r#"{
+            "snapshot_path": "foo",
+            "mem_backend": {
+                "backend_path": "bar",
+                "backend_type": "File"
+            },
+            "encryption": {
+                "key_fd": 3
+            }
+        }"#;
+        let expected_config = LoadSnapshotParams {
+            snapshot_path: PathBuf::from("foo"),
+            mem_backend: MemBackendConfig {
+                backend_path: PathBuf::from("bar"),
+                backend_type: MemBackendType::File,
+            },
+            enable_diff_snapshots: false,
+            resume_vm: false,
+            encryption: Some(SnapshotEncryptionConfig { key_fd: 3 }),
+            prefault: false,
+        };
+        assert_eq!(
+            vmm_action_from_request(parse_put_snapshot(&Body::new(body), Some("load")).unwrap()),
+            VmmAction::LoadSnapshot(expected_config)
+        );
+
+        let body = r#"{
+            "snapshot_path": "foo",
+            "mem_backend": {
+                "backend_path": "bar",
+                "backend_type": "File"
+            },
+            "prefault": true
+        }"#;
+        let expected_config = LoadSnapshotParams {
+            snapshot_path: PathBuf::from("foo"),
+            mem_backend: MemBackendConfig {
+                backend_path: PathBuf::from("bar"),
+                backend_type: MemBackendType::File,
+            },
+            enable_diff_snapshots: false,
+            resume_vm: false,
+            encryption: None,
+            prefault: true,
+        };
+        assert_eq!(
+            vmm_action_from_request(parse_put_snapshot(&Body::new(body), Some("load")).unwrap()),
+            VmmAction::LoadSnapshot(expected_config)
+        );
+
         let body = r#"{
             "snapshot_path": "foo",
             "mem_file_path": "bar",
@@ -259,6 +401,8 @@ mod tests {
             },
             enable_diff_snapshots: false,
             resume_vm: true,
+            encryption: None,
+            prefault: false,
         };
         let parsed_request = parse_put_snapshot(&Body::new(body), Some("load")).unwrap();
         assert_eq!(
@@ -343,6 +487,29 @@ mod tests {
         parse_put_snapshot(&Body::new(body), None).unwrap_err();
     }
 
+    #[test]
+    fn test_parse_put_snapshot_compat() {
+        use std::path::PathBuf;
+
+        let body = r#"{
+            "snapshot_path": "foo"
+        }"#;
+        let expected_params = CheckSnapshotParams {
+            snapshot_path: PathBuf::from("foo"),
+            encryption: None,
+        };
+        assert_eq!(
+            vmm_action_from_request(parse_put_snapshot(&Body::new(body), Some("compat")).unwrap()),
+            VmmAction::CheckSnapshotCompatibility(expected_params)
+        );
+
+        let body = r#"{
+            "snapshot_path": "foo",
+            "mem_file_path": "bar"
+        }"#;
+        parse_put_snapshot(&Body::new(body), Some("compat")).unwrap_err();
+    }
+
     #[test]
     fn test_parse_patch_vm_state() {
         let body = r#"{
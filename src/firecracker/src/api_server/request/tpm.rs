@@ -0,0 +1,47 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use vmm::rpc_interface::VmmAction;
+use vmm::vmm_config::tpm::TpmConfig;
+
+use super::super::parsed_request::{ParsedRequest, RequestError};
+use super::Body;
+
+pub(crate) fn parse_put_tpm(body: &Body) -> Result<ParsedRequest, RequestError> {
+    let cfg = serde_json::from_slice::<TpmConfig>(body.raw())?;
+    Ok(ParsedRequest::new_sync(VmmAction::SetTpmDevice(cfg)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_put_tpm_request() {
+        parse_put_tpm(&Body::new("invalid_payload")).unwrap_err();
+
+        // PUT with missing fields.
+        parse_put_tpm(&Body::new("{}")).unwrap_err();
+
+        // PUT with invalid fields.
+        let body = r#"{
+            "socket": "/tmp/swtpm.sock",
+            "some_field": "some_value"
+        }"#;
+        parse_put_tpm(&Body::new(body)).unwrap_err();
+
+        // PUT with valid fields.
+        let body = r#"{
+            "socket": "/tmp/swtpm.sock"
+        }"#;
+        let expected_cfg = TpmConfig {
+            socket: PathBuf::from("/tmp/swtpm.sock"),
+        };
+        assert_eq!(
+            parse_put_tpm(&Body::new(body)).unwrap(),
+            ParsedRequest::new_sync(VmmAction::SetTpmDevice(expected_cfg))
+        );
+    }
+}
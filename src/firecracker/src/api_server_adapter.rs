@@ -6,21 +6,34 @@ use std::path::PathBuf;
 use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
 use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::Duration;
 
 use event_manager::{EventOps, Events, MutEventSubscriber, SubscriberOps};
-use seccompiler::BpfThreadMap;
-use vmm::logger::{error, warn, ProcessTimeReporter};
+use libc::{c_int, c_void, siginfo_t, SIGUSR2};
+use seccompiler::{BpfProgram, BpfThreadMap};
+use timerfd::{ClockId, SetTimeFlags, TimerFd, TimerState};
+use vmm::chaos::ChaosMonkey;
+use vmm::diagnostics::DiagnosticDumper;
+use vmm::event_stream::EventStream;
+use vmm::logger::{error, info, warn, ProcessTimeReporter};
 use vmm::resources::VmResources;
 use vmm::rpc_interface::{
     ApiRequest, ApiResponse, BuildMicrovmFromRequestsError, PrebootApiController,
     RuntimeApiController, VmmAction,
 };
+use vmm::utils::signal::register_signal_handler;
 use vmm::vmm_config::instance_info::InstanceInfo;
 use vmm::{EventManager, FcExitCode, Vmm};
 use vmm_sys_util::epoll::EventSet;
 use vmm_sys_util::eventfd::EventFd;
 
-use super::api_server::{ApiServer, HttpServer, ServerError};
+use super::api_server::latency_budget::LatencyBudget;
+use super::api_server::{
+    request_shutdown, request_socket_rebind, ApiServer, HttpServer, ServerError,
+};
+
+/// Period at which the API socket is checked for existence while the microVM is running.
+const API_SOCKET_CHECK_PERIOD_MS: u64 = 1000;
 
 #[derive(Debug, thiserror::Error, displaydoc::Display)]
 pub enum ApiServerError {
@@ -34,6 +47,21 @@ pub enum ApiServerError {
     FailedToBindAndRunHttpServer(ServerError),
     /// Failed to build MicroVM from Json: {0}
     BuildFromJson(crate::BuildFromJsonError),
+    /// Failed to register the API socket rebind signal handler: {0}
+    RegisterSignalHandler(vmm_sys_util::errno::Error),
+    /// Failed to bind the guest API socket at {0}: {1}
+    FailedToBindGuestApi(String, ServerError),
+    /// Failed to start the gRPC server: {0}
+    #[cfg(feature = "grpc")]
+    Grpc(crate::grpc::GrpcError),
+}
+
+/// Channels between the guest API thread and the VMM.
+#[derive(Debug)]
+struct GuestApiChannels {
+    event_fd: EventFd,
+    from_api: Receiver<ApiRequest>,
+    to_api: Sender<ApiResponse>,
 }
 
 #[derive(Debug)]
@@ -41,6 +69,7 @@ struct ApiServerAdapter {
     api_event_fd: EventFd,
     from_api: Receiver<ApiRequest>,
     to_api: Sender<ApiResponse>,
+    guest_api: Option<GuestApiChannels>,
     controller: RuntimeApiController,
 }
 
@@ -51,6 +80,7 @@ impl ApiServerAdapter {
         api_event_fd: EventFd,
         from_api: Receiver<ApiRequest>,
         to_api: Sender<ApiResponse>,
+        guest_api: Option<GuestApiChannels>,
         vm_resources: VmResources,
         vmm: Arc<Mutex<Vmm>>,
         event_manager: &mut EventManager,
@@ -59,6 +89,7 @@ impl ApiServerAdapter {
             api_event_fd,
             from_api,
             to_api,
+            guest_api,
             controller: RuntimeApiController::new(vm_resources, vmm.clone()),
         }));
         event_manager.add_subscriber(api_adapter);
@@ -84,6 +115,32 @@ impl ApiServerAdapter {
             .map_err(|_| ())
             .expect("one-shot channel closed");
     }
+
+    /// Handles a request received through the guest API.
+    ///
+    /// The guest API never forwards pause requests, so these are not waited on.
+    fn handle_guest_request(&mut self) {
+        let Some(guest_api) = &self.guest_api else {
+            return;
+        };
+        let _ = guest_api.event_fd.read();
+        match guest_api.from_api.try_recv() {
+            Ok(api_request) => {
+                let response = self.controller.handle_request(api_request.dequeue());
+                guest_api
+                    .to_api
+                    .send(Box::new(response))
+                    .map_err(|_| ())
+                    .expect("one-shot channel closed");
+            }
+            Err(TryRecvError::Empty) => {
+                warn!("Got a spurious notification from guest api thread");
+            }
+            Err(TryRecvError::Disconnected) => {
+                error!("The guest API channel was disconnected.");
+            }
+        }
+    }
 }
 impl MutEventSubscriber for ApiServerAdapter {
     /// Handle a read event (EPOLLIN).
@@ -95,8 +152,9 @@ impl MutEventSubscriber for ApiServerAdapter {
             let _ = self.api_event_fd.read();
             match self.from_api.try_recv() {
                 Ok(api_request) => {
-                    let request_is_pause = *api_request == VmmAction::Pause;
-                    self.handle_request(*api_request);
+                    let api_request = api_request.dequeue();
+                    let request_is_pause = api_request == VmmAction::Pause;
+                    self.handle_request(api_request);
 
                     // If the latest req is a pause request, temporarily switch to a mode where we
                     // do blocking `recv`s on the `from_api` receiver in a loop, until we get
@@ -107,9 +165,13 @@ impl MutEventSubscriber for ApiServerAdapter {
                         // This loop only attempts to process API requests, so things like the
                         // metric flush timerfd handling are frozen as well.
                         loop {
-                            let req = self.from_api.recv().expect("Error receiving API request.");
-                            let req_is_resume = *req == VmmAction::Resume;
-                            self.handle_request(*req);
+                            let req = self
+                                .from_api
+                                .recv()
+                                .expect("Error receiving API request.")
+                                .dequeue();
+                            let req_is_resume = req == VmmAction::Resume;
+                            self.handle_request(req);
                             if req_is_resume {
                                 break;
                             }
@@ -123,6 +185,13 @@ impl MutEventSubscriber for ApiServerAdapter {
                     panic!("The channel's sending half was disconnected. Cannot receive data.");
                 }
             };
+        } else if self
+            .guest_api
+            .as_ref()
+            .is_some_and(|guest_api| source == guest_api.event_fd.as_raw_fd())
+            && event_set == EventSet::IN
+        {
+            self.handle_guest_request();
         } else {
             error!("Spurious EventManager event for handler: ApiServerAdapter");
         }
@@ -132,7 +201,138 @@ impl MutEventSubscriber for ApiServerAdapter {
         if let Err(err) = ops.add(Events::new(&self.api_event_fd, EventSet::IN)) {
             error!("Failed to register activate event: {}", err);
         }
+        if let Some(guest_api) = &self.guest_api {
+            if let Err(err) = ops.add(Events::new(&guest_api.event_fd, EventSet::IN)) {
+                error!("Failed to register guest API event: {}", err);
+            }
+        }
+    }
+}
+
+/// Requests a rebind when the API socket file disappears, e.g. because its
+/// parent directory was removed.
+#[derive(Debug)]
+struct ApiSocketWatcher {
+    bind_path: PathBuf,
+    timer_fd: TimerFd,
+}
+
+impl ApiSocketWatcher {
+    /// Creates a watcher checking `bind_path` every `interval_ms` millisecs.
+    /// Can panic on `TimerFd` creation failure.
+    fn new(bind_path: PathBuf, interval_ms: u64) -> Self {
+        let mut timer_fd = TimerFd::new_custom(ClockId::Monotonic, true, true)
+            .expect("Cannot create the API socket watcher timer fd.");
+        let timer_state = TimerState::Periodic {
+            current: Duration::from_millis(interval_ms),
+            interval: Duration::from_millis(interval_ms),
+        };
+        timer_fd.set_state(timer_state, SetTimeFlags::Default);
+        ApiSocketWatcher {
+            bind_path,
+            timer_fd,
+        }
+    }
+}
+
+impl MutEventSubscriber for ApiSocketWatcher {
+    /// Handle a read event (EPOLLIN).
+    fn process(&mut self, event: Events, _: &mut EventOps) {
+        if event.fd() == self.timer_fd.as_raw_fd() && event.event_set() == EventSet::IN {
+            self.timer_fd.read();
+            if !self.bind_path.exists() {
+                warn!(
+                    "API socket {} disappeared, re-creating it.",
+                    self.bind_path.display()
+                );
+                request_socket_rebind();
+            }
+        } else {
+            error!("Spurious EventManager event for handler: ApiSocketWatcher");
+        }
+    }
+
+    fn init(&mut self, ops: &mut EventOps) {
+        if let Err(err) = ops.add(Events::new(&self.timer_fd, EventSet::IN)) {
+            error!(
+                "Failed to register API socket watcher timerfd event: {}",
+                err
+            );
+        }
+    }
+}
+
+/// Re-creates the API socket on `SIGUSR2`.
+extern "C" fn sigusr2_handler(num: c_int, info: *mut siginfo_t, _unused: *mut c_void) {
+    // SAFETY: Safe because we're just reading some fields from a supposedly valid argument.
+    let si_signo = unsafe { (*info).si_signo };
+    if num != si_signo || num != SIGUSR2 {
+        return;
     }
+    request_socket_rebind();
+}
+
+/// Guest API thread and the kill switch stopping it.
+#[derive(Debug)]
+struct GuestApiThread {
+    thread: thread::JoinHandle<()>,
+    kill_switch: EventFd,
+}
+
+/// Serves the guest API on the host side socket of vsock port `port`, i.e. at
+/// `<uds_path>_<port>`, to which guest connections on host CID 2 and `port` are forwarded.
+fn start_guest_api(
+    uds_path: &str,
+    port: u32,
+    seccomp_filter: Arc<BpfProgram>,
+    api_payload_limit: usize,
+) -> Result<(GuestApiChannels, GuestApiThread), ApiServerError> {
+    let bind_path = PathBuf::from(format!("{}_{}", uds_path, port));
+    let bind_err = |err| ApiServerError::FailedToBindGuestApi(bind_path.display().to_string(), err);
+    let mut server = HttpServer::new(&bind_path).map_err(bind_err)?;
+    let kill_switch =
+        EventFd::new(libc::EFD_NONBLOCK).expect("Cannot create guest API kill switch.");
+    server
+        .add_kill_switch(
+            kill_switch
+                .try_clone()
+                .expect("Failed to clone guest API kill switch"),
+        )
+        .map_err(bind_err)?;
+
+    let event_fd = EventFd::new(libc::EFD_NONBLOCK).expect("Cannot create guest API Eventfd.");
+    let to_vmm_event_fd = event_fd
+        .try_clone()
+        .expect("Failed to clone guest API event FD");
+    let (to_vmm, from_api) = channel();
+    let (to_api, from_vmm) = channel();
+
+    let thread = thread::Builder::new()
+        .name("fc_guest_api".to_owned())
+        .spawn(move || {
+            ApiServer::new(to_vmm, from_vmm, to_vmm_event_fd)
+                .with_guest_scope()
+                .run(
+                    server,
+                    ProcessTimeReporter::new(None, None, None),
+                    &seccomp_filter,
+                    api_payload_limit,
+                );
+        })
+        .expect("Guest API thread spawn failed.");
+    info!("Serving the guest API at {}.", bind_path.display());
+
+    Ok((
+        GuestApiChannels {
+            event_fd,
+            from_api,
+            to_api,
+        },
+        GuestApiThread {
+            thread,
+            kill_switch,
+        },
+    ))
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -146,6 +346,12 @@ pub(crate) fn run_with_api(
     api_payload_limit: usize,
     mmds_size_limit: usize,
     metadata_json: Option<&str>,
+    diagnostic_dumper: Option<DiagnosticDumper>,
+    chaos_monkey: Option<Arc<Mutex<ChaosMonkey>>>,
+    event_stream: Option<Arc<Mutex<EventStream>>>,
+    coredump_snapshot_dir: Option<PathBuf>,
+    latency_budget: LatencyBudget,
+    guest_api_vsock_port: Option<u32>,
 ) -> Result<(), ApiServerError> {
     // FD to notify of API events. This is a blocking eventfd by design.
     // It is used in the config/pre-boot loop which is a simple blocking loop
@@ -164,6 +370,8 @@ pub(crate) fn run_with_api(
     let api_seccomp_filter = seccomp_filters
         .remove("api")
         .expect("Missing seccomp filter for API thread.");
+    // The guest API thread runs the same server, so it gets the same filter.
+    let guest_api_seccomp_filter = api_seccomp_filter.clone();
 
     let mut server = match HttpServer::new(&bind_path) {
         Ok(s) => s,
@@ -184,24 +392,66 @@ pub(crate) fn run_with_api(
         .add_kill_switch(api_kill_switch_clone)
         .expect("Cannot add HTTP server kill switch");
 
+    // The gRPC calls are served as REST requests by the API thread.
+    #[cfg(feature = "grpc")]
+    if let Some(grpc_socket_path) = crate::grpc::SOCKET_PATH.get() {
+        let local_requests = crate::grpc::start(grpc_socket_path, api_seccomp_filter.clone())
+            .map_err(ApiServerError::Grpc)?;
+        server
+            .add_local_requests(local_requests)
+            .map_err(ApiServerError::FailedToBindAndRunHttpServer)?;
+    }
+
+    // The handler only performs async-signal-safe operations.
+    register_signal_handler(SIGUSR2, sigusr2_handler)
+        .map_err(ApiServerError::RegisterSignalHandler)?;
+
+    let rebind_kill_switch = api_kill_switch
+        .try_clone()
+        .expect("Failed to clone API kill switch");
+    let api_bind_path = bind_path.clone();
+
     // Start the separate API thread.
     let api_thread = thread::Builder::new()
         .name("fc_api".to_owned())
         .spawn(move || {
-            ApiServer::new(to_vmm, from_vmm, to_vmm_event_fd).run(
-                server,
-                process_time_reporter,
-                &api_seccomp_filter,
-                api_payload_limit,
-            );
+            ApiServer::new(to_vmm, from_vmm, to_vmm_event_fd)
+                .with_socket_rebind(api_bind_path, rebind_kill_switch)
+                .with_latency_budget(latency_budget)
+                .run(
+                    server,
+                    process_time_reporter,
+                    &api_seccomp_filter,
+                    api_payload_limit,
+                );
         })
         .expect("API thread spawn failed.");
 
     let mut event_manager = EventManager::new().expect("Unable to create EventManager");
 
+    // Re-create the API socket if it disappears while the microVM is running.
+    let api_socket_watcher = Arc::new(Mutex::new(ApiSocketWatcher::new(
+        bind_path,
+        API_SOCKET_CHECK_PERIOD_MS,
+    )));
+    event_manager.add_subscriber(api_socket_watcher);
+
     // Create the firecracker metrics object responsible for periodically printing metrics.
     let firecracker_metrics = Arc::new(Mutex::new(super::metrics::PeriodicMetrics::new()));
     event_manager.add_subscriber(firecracker_metrics.clone());
+    // Sample how long the event loop is stalled, e.g. while handling API requests.
+    event_manager.add_subscriber(Arc::new(Mutex::new(super::metrics::EventLoopProbe::new(
+        super::metrics::EVENT_LOOP_PROBE_PERIOD_MS,
+    ))));
+
+    // Cycles only start once the microVM is built.
+    if let Some(monkey) = &chaos_monkey {
+        event_manager.add_subscriber(monkey.clone());
+    }
+    // Events emitted before the microVM runs are queued until the event loop starts.
+    if let Some(stream) = event_stream {
+        event_manager.add_subscriber(stream);
+    }
 
     // Configure, build and start the microVM.
     let build_result = match config_json {
@@ -229,26 +479,63 @@ pub(crate) fn run_with_api(
         .map_err(ApiServerError::BuildMicroVmError),
     };
 
+    let mut guest_api_thread = None;
     let result = build_result.and_then(|(vm_resources, vmm)| {
         firecracker_metrics
             .lock()
             .expect("Poisoned lock")
             .start(super::metrics::WRITE_METRICS_PERIOD_MS);
 
+        if let Some(dumper) = diagnostic_dumper {
+            dumper.set_vmm(vmm.clone());
+        }
+        if let Some(monkey) = chaos_monkey {
+            monkey.lock().expect("Poisoned lock").set_vmm(vmm.clone());
+        }
+        if let Some(dir) = coredump_snapshot_dir {
+            super::set_coredump_snapshot(&vmm, dir, &vm_resources);
+        }
+
+        let guest_api = match (guest_api_vsock_port, vm_resources.vsock.config()) {
+            (Some(port), Some(vsock_config)) => {
+                let (channels, thread) = start_guest_api(
+                    &vsock_config.uds_path,
+                    port,
+                    guest_api_seccomp_filter,
+                    api_payload_limit,
+                )?;
+                guest_api_thread = Some(thread);
+                Some(channels)
+            }
+            (Some(_), None) => {
+                warn!("The guest API is not served since the microVM has no vsock device.");
+                None
+            }
+            (None, _) => None,
+        };
+
         ApiServerAdapter::run_microvm(
             api_event_fd,
             from_api,
             to_api,
+            guest_api,
             vm_resources,
             vmm,
             &mut event_manager,
         )
     });
 
-    api_kill_switch.write(1).unwrap();
+    request_shutdown(&api_kill_switch).unwrap();
     // This call to thread::join() should block until the API thread has processed the
     // shutdown-internal and returns from its function.
     api_thread.join().expect("Api thread should join");
+    if let Some(guest_api_thread) = guest_api_thread {
+        request_shutdown(&guest_api_thread.kill_switch).unwrap();
+        guest_api_thread
+            .thread
+            .join()
+            .expect("Guest api thread should join");
+    }
 
     result
 }
@@ -0,0 +1,598 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! gRPC control API, mirroring the machine configuration, drive and snapshot requests of the
+//! REST API.
+//!
+//! Each call is translated to the matching REST request, which the API thread serves along with
+//! the requests of the API socket. Both APIs thus share the request validation, the metrics and
+//! the path to the VMM event loop. Calls are served one at a time, like REST requests.
+
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, OnceLock};
+use std::thread;
+
+use micro_http::{Request, Response, StatusCode};
+use seccompiler::BpfProgram;
+use serde_json::{Map, Value};
+use tokio::net::UnixListener;
+use tokio::sync::oneshot;
+use tokio_stream::wrappers::UnixListenerStream;
+use tonic::transport::Server;
+use tonic::{Code, Status};
+use vmm::logger::{error, info};
+use vmm_sys_util::eventfd::EventFd;
+
+use crate::api_server::http_server::{LocalRequest, LocalRequests};
+
+/// Code generated from `proto/firecracker.proto`.
+#[allow(clippy::all, clippy::pedantic, missing_debug_implementations)]
+pub mod proto {
+    tonic::include_proto!("firecracker.v1");
+}
+
+use proto::firecracker_server::{Firecracker, FirecrackerServer};
+
+/// Path of the socket on which the gRPC API is served, if any.
+pub static SOCKET_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+/// Errors starting the gRPC server.
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum GrpcError {
+    /// Failed to create the gRPC runtime: {0}
+    Runtime(std::io::Error),
+    /// Failed to bind the gRPC socket at {0}: {1}
+    Bind(String, std::io::Error),
+    /// Failed to create the gRPC event fd: {0}
+    EventFd(std::io::Error),
+    /// Failed to spawn the gRPC thread: {0}
+    Thread(std::io::Error),
+}
+
+/// Serves the gRPC API at `path` on a new thread, which runs with `seccomp_filter`.
+///
+/// Returns the queue of the translated requests, to be served by the API server.
+pub fn start(path: &Path, seccomp_filter: Arc<BpfProgram>) -> Result<LocalRequests, GrpcError> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_io()
+        .build()
+        .map_err(GrpcError::Runtime)?;
+    let listener = {
+        // Tokio sockets are registered with the runtime they are created in.
+        let _guard = runtime.enter();
+        UnixListener::bind(path).map_err(|err| GrpcError::Bind(path.display().to_string(), err))?
+    };
+    let event_fd = EventFd::new(libc::EFD_NONBLOCK).map_err(GrpcError::EventFd)?;
+    let (sender, receiver) = mpsc::channel();
+    let local_requests =
+        LocalRequests::new(receiver, event_fd.try_clone().map_err(GrpcError::EventFd)?);
+    let service = GrpcService { sender, event_fd };
+
+    thread::Builder::new()
+        .name("fc_grpc".to_owned())
+        .spawn(move || {
+            // The runtime and the listener were set up before, so the thread gets by with the
+            // filter of the API thread.
+            if let Err(err) = seccompiler::apply_filter(&seccomp_filter) {
+                panic!(
+                    "Failed to set the requested seccomp filters on the gRPC thread: {}",
+                    err
+                );
+            }
+            let server = Server::builder()
+                .add_service(FirecrackerServer::new(service))
+                .serve_with_incoming(UnixListenerStream::new(listener));
+            if let Err(err) = runtime.block_on(server) {
+                error!("The gRPC server stopped: {}", err);
+            }
+        })
+        .map_err(GrpcError::Thread)?;
+    info!("Serving the gRPC API at {}.", path.display());
+    Ok(local_requests)
+}
+
+/// Implementation of the gRPC service, which forwards the calls to the API thread.
+#[derive(Debug)]
+struct GrpcService {
+    sender: Sender<LocalRequest>,
+    /// Written after each request sent through `sender`.
+    event_fd: EventFd,
+}
+
+impl GrpcService {
+    /// Serves the REST request `method path` with `body`, and returns the body of its response.
+    ///
+    /// The response is awaited, so that a slow request doesn't block the other calls.
+    async fn call(
+        &self,
+        method: &str,
+        path: &str,
+        body: Option<Value>,
+    ) -> Result<Option<Value>, Status> {
+        let raw = match body {
+            Some(body) => {
+                let body = body.to_string();
+                format!(
+                    "{method} {path} HTTP/1.1\r\nContent-Type: \
+                     application/json\r\nContent-Length: {}\r\n\r\n{body}",
+                    body.len()
+                )
+            }
+            None => format!("{method} {path} HTTP/1.1\r\n\r\n"),
+        };
+        let request = Request::try_from(raw.as_bytes(), None)
+            .map_err(|err| Status::invalid_argument(err.to_string()))?;
+
+        let (response_sender, response_receiver) = oneshot::channel();
+        self.sender
+            .send(LocalRequest {
+                request,
+                response_sender,
+            })
+            .map_err(unavailable)?;
+        self.event_fd
+            .write(1)
+            .map_err(|err| Status::internal(err.to_string()))?;
+        let response = response_receiver.await.map_err(unavailable)?;
+        response_result(&response)
+    }
+}
+
+#[tonic::async_trait]
+impl Firecracker for GrpcService {
+    async fn get_machine_config(
+        &self,
+        _: tonic::Request<proto::Empty>,
+    ) -> Result<tonic::Response<proto::MachineConfig>, Status> {
+        let body = self.call("GET", "/machine-config", None).await?;
+        machine_config_from_body(&body.unwrap_or_default()).map(tonic::Response::new)
+    }
+
+    async fn put_machine_config(
+        &self,
+        request: tonic::Request<proto::MachineConfig>,
+    ) -> Result<tonic::Response<proto::Empty>, Status> {
+        let body = machine_config_body(request.get_ref());
+        self.call("PUT", "/machine-config", Some(body))
+            .await
+            .map(empty)
+    }
+
+    async fn patch_machine_config(
+        &self,
+        request: tonic::Request<proto::MachineConfigUpdate>,
+    ) -> Result<tonic::Response<proto::Empty>, Status> {
+        let body = machine_config_update_body(request.get_ref());
+        self.call("PATCH", "/machine-config", Some(body))
+            .await
+            .map(empty)
+    }
+
+    async fn put_drive(
+        &self,
+        request: tonic::Request<proto::Drive>,
+    ) -> Result<tonic::Response<proto::Empty>, Status> {
+        let drive = request.get_ref();
+        let path = format!("/drives/{}", checked_id(&drive.drive_id)?);
+        self.call("PUT", &path, Some(drive_body(drive)))
+            .await
+            .map(empty)
+    }
+
+    async fn patch_drive(
+        &self,
+        request: tonic::Request<proto::PartialDrive>,
+    ) -> Result<tonic::Response<proto::Empty>, Status> {
+        let drive = request.get_ref();
+        let path = format!("/drives/{}", checked_id(&drive.drive_id)?);
+        self.call("PATCH", &path, Some(partial_drive_body(drive)))
+            .await
+            .map(empty)
+    }
+
+    async fn patch_vm(
+        &self,
+        request: tonic::Request<proto::Vm>,
+    ) -> Result<tonic::Response<proto::Empty>, Status> {
+        let body = object([("state", Some(request.get_ref().state.as_str().into()))]);
+        self.call("PATCH", "/vm", Some(body)).await.map(empty)
+    }
+
+    async fn create_snapshot(
+        &self,
+        request: tonic::Request<proto::SnapshotCreateParams>,
+    ) -> Result<tonic::Response<proto::Empty>, Status> {
+        let body = snapshot_create_body(request.get_ref());
+        self.call("PUT", "/snapshot/create", Some(body))
+            .await
+            .map(empty)
+    }
+
+    async fn load_snapshot(
+        &self,
+        request: tonic::Request<proto::SnapshotLoadParams>,
+    ) -> Result<tonic::Response<proto::Empty>, Status> {
+        let body = snapshot_load_body(request.get_ref());
+        self.call("PUT", "/snapshot/load", Some(body))
+            .await
+            .map(empty)
+    }
+}
+
+fn unavailable<E>(_: E) -> Status {
+    Status::unavailable("The API server is not running.")
+}
+
+fn empty(_: Option<Value>) -> tonic::Response<proto::Empty> {
+    tonic::Response::new(proto::Empty {})
+}
+
+/// Returns the body of a successful REST response, or the status matching its error.
+fn response_result(response: &Response) -> Result<Option<Value>, Status> {
+    let body = response
+        .body()
+        .map(|body| serde_json::from_slice::<Value>(body.raw()).unwrap_or_default());
+    let code = match response.status() {
+        StatusCode::OK | StatusCode::NoContent => return Ok(body),
+        StatusCode::BadRequest | StatusCode::PayloadTooLarge => Code::InvalidArgument,
+        StatusCode::NotFound => Code::NotFound,
+        StatusCode::MethodNotAllowed | StatusCode::NotImplemented => Code::Unimplemented,
+        StatusCode::TooManyRequests | StatusCode::ServiceUnavailable => Code::Unavailable,
+        _ => Code::Internal,
+    };
+    let message = body
+        .as_ref()
+        .and_then(|body| body.get("fault_message"))
+        .and_then(Value::as_str)
+        .unwrap_or("The request failed.");
+    Err(Status::new(code, message))
+}
+
+/// Ids are part of the request path, so they are checked the way the REST API checks them.
+fn checked_id(id: &str) -> Result<&str, Status> {
+    if id.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        Ok(id)
+    } else {
+        Err(Status::invalid_argument(format!(
+            "API Resource IDs can only contain alphanumeric characters and underscores, got \
+             {id:?}."
+        )))
+    }
+}
+
+/// Builds a JSON object out of the fields which are set.
+fn object<const N: usize>(fields: [(&str, Option<Value>); N]) -> Value {
+    Value::Object(
+        fields
+            .into_iter()
+            .filter_map(|(key, value)| Some((key.to_owned(), value?)))
+            .collect(),
+    )
+}
+
+fn machine_config_body(config: &proto::MachineConfig) -> Value {
+    object([
+        ("vcpu_count", Some(config.vcpu_count.into())),
+        ("mem_size_mib", Some(config.mem_size_mib.into())),
+        ("smt", config.smt.map(Value::from)),
+        (
+            "track_dirty_pages",
+            config.track_dirty_pages.map(Value::from),
+        ),
+        ("huge_pages", config.huge_pages.clone().map(Value::from)),
+        ("cpu_template", config.cpu_template.clone().map(Value::from)),
+        ("pmu", config.pmu.map(Value::from)),
+        ("nested_virt", config.nested_virt.map(Value::from)),
+        (
+            "memory_backend",
+            config.memory_backend.clone().map(Value::from),
+        ),
+        ("prefault", config.prefault.map(Value::from)),
+    ])
+}
+
+fn machine_config_update_body(update: &proto::MachineConfigUpdate) -> Value {
+    object([
+        ("vcpu_count", update.vcpu_count.map(Value::from)),
+        ("mem_size_mib", update.mem_size_mib.map(Value::from)),
+        ("smt", update.smt.map(Value::from)),
+        (
+            "track_dirty_pages",
+            update.track_dirty_pages.map(Value::from),
+        ),
+        ("huge_pages", update.huge_pages.clone().map(Value::from)),
+        ("cpu_template", update.cpu_template.clone().map(Value::from)),
+        ("pmu", update.pmu.map(Value::from)),
+        ("nested_virt", update.nested_virt.map(Value::from)),
+        (
+            "memory_backend",
+            update.memory_backend.clone().map(Value::from),
+        ),
+        ("prefault", update.prefault.map(Value::from)),
+    ])
+}
+
+fn machine_config_from_body(body: &Value) -> Result<proto::MachineConfig, Status> {
+    let bool_field = |key: &str| body.get(key).and_then(Value::as_bool);
+    let string_field = |key: &str| body.get(key).and_then(Value::as_str).map(str::to_owned);
+    let vcpu_count = body
+        .get("vcpu_count")
+        .and_then(Value::as_u64)
+        .and_then(|count| u32::try_from(count).ok());
+    let mem_size_mib = body.get("mem_size_mib").and_then(Value::as_u64);
+    let (Some(vcpu_count), Some(mem_size_mib)) = (vcpu_count, mem_size_mib) else {
+        return Err(Status::internal("Invalid machine configuration."));
+    };
+    Ok(proto::MachineConfig {
+        vcpu_count,
+        mem_size_mib,
+        smt: bool_field("smt"),
+        track_dirty_pages: bool_field("track_dirty_pages"),
+        huge_pages: string_field("huge_pages"),
+        cpu_template: string_field("cpu_template"),
+        pmu: bool_field("pmu"),
+        nested_virt: bool_field("nested_virt"),
+        memory_backend: string_field("memory_backend"),
+        prefault: bool_field("prefault"),
+    })
+}
+
+fn token_bucket_body(bucket: &proto::TokenBucket) -> Value {
+    object([
+        ("size", Some(bucket.size.into())),
+        ("one_time_burst", bucket.one_time_burst.map(Value::from)),
+        ("refill_time", Some(bucket.refill_time.into())),
+    ])
+}
+
+fn rate_limiter_body(rate_limiter: &proto::RateLimiter) -> Value {
+    object([
+        (
+            "bandwidth",
+            rate_limiter.bandwidth.as_ref().map(token_bucket_body),
+        ),
+        ("ops", rate_limiter.ops.as_ref().map(token_bucket_body)),
+        ("borrow", rate_limiter.borrow.map(Value::from)),
+        ("smoothing", rate_limiter.smoothing.map(Value::from)),
+    ])
+}
+
+fn drive_body(drive: &proto::Drive) -> Value {
+    object([
+        ("drive_id", Some(drive.drive_id.as_str().into())),
+        ("is_root_device", Some(drive.is_root_device.into())),
+        ("partuuid", drive.partuuid.clone().map(Value::from)),
+        ("cache_type", drive.cache_type.clone().map(Value::from)),
+        ("is_read_only", drive.is_read_only.map(Value::from)),
+        ("path_on_host", drive.path_on_host.clone().map(Value::from)),
+        (
+            "rate_limiter",
+            drive.rate_limiter.as_ref().map(rate_limiter_body),
+        ),
+        ("io_engine", drive.io_engine.clone().map(Value::from)),
+        ("direct_io", drive.direct_io.map(Value::from)),
+        ("backend", drive.backend.clone().map(Value::from)),
+        (
+            "shared_rate_limiter",
+            drive.shared_rate_limiter.clone().map(Value::from),
+        ),
+        ("socket", drive.socket.clone().map(Value::from)),
+    ])
+}
+
+fn partial_drive_body(drive: &proto::PartialDrive) -> Value {
+    object([
+        ("drive_id", Some(drive.drive_id.as_str().into())),
+        ("path_on_host", drive.path_on_host.clone().map(Value::from)),
+        (
+            "rate_limiter",
+            drive.rate_limiter.as_ref().map(rate_limiter_body),
+        ),
+    ])
+}
+
+fn encryption_body(encryption: &proto::SnapshotEncryption) -> Value {
+    object([("key_fd", Some(encryption.key_fd.into()))])
+}
+
+fn snapshot_create_body(params: &proto::SnapshotCreateParams) -> Value {
+    let labels = (!params.labels.is_empty()).then(|| {
+        Value::Object(
+            params
+                .labels
+                .iter()
+                .map(|(key, value)| (key.clone(), Value::from(value.as_str())))
+                .collect::<Map<_, _>>(),
+        )
+    });
+    object([
+        ("snapshot_path", Some(params.snapshot_path.as_str().into())),
+        ("mem_file_path", Some(params.mem_file_path.as_str().into())),
+        (
+            "snapshot_type",
+            params.snapshot_type.clone().map(Value::from),
+        ),
+        ("labels", labels),
+        (
+            "encryption",
+            params.encryption.as_ref().map(encryption_body),
+        ),
+        ("background", params.background.map(Value::from)),
+    ])
+}
+
+fn snapshot_load_body(params: &proto::SnapshotLoadParams) -> Value {
+    let mem_backend = params.mem_backend.as_ref().map(|backend| {
+        object([
+            ("backend_type", Some(backend.backend_type.as_str().into())),
+            ("backend_path", Some(backend.backend_path.as_str().into())),
+        ])
+    });
+    object([
+        ("snapshot_path", Some(params.snapshot_path.as_str().into())),
+        (
+            "mem_file_path",
+            params.mem_file_path.clone().map(Value::from),
+        ),
+        ("mem_backend", mem_backend),
+        (
+            "enable_diff_snapshots",
+            params.enable_diff_snapshots.map(Value::from),
+        ),
+        ("resume_vm", params.resume_vm.map(Value::from)),
+        (
+            "encryption",
+            params.encryption.as_ref().map(encryption_body),
+        ),
+        ("prefault", params.prefault.map(Value::from)),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use micro_http::{Body, Version};
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_request_bodies() {
+        let config = proto::MachineConfig {
+            vcpu_count: 2,
+            mem_size_mib: 256,
+            track_dirty_pages: Some(true),
+            huge_pages: Some("2M".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(
+            machine_config_body(&config),
+            json!({"vcpu_count": 2, "mem_size_mib": 256, "track_dirty_pages": true, "huge_pages": "2M"})
+        );
+        let update = proto::MachineConfigUpdate {
+            vcpu_count: Some(4),
+            ..Default::default()
+        };
+        assert_eq!(
+            machine_config_update_body(&update),
+            json!({"vcpu_count": 4})
+        );
+
+        let drive = proto::Drive {
+            drive_id: "rootfs".to_owned(),
+            is_root_device: true,
+            is_read_only: Some(false),
+            path_on_host: Some("/rootfs.ext4".to_owned()),
+            rate_limiter: Some(proto::RateLimiter {
+                bandwidth: Some(proto::TokenBucket {
+                    size: 1000,
+                    one_time_burst: None,
+                    refill_time: 100,
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert_eq!(
+            drive_body(&drive),
+            json!({
+                "drive_id": "rootfs",
+                "is_root_device": true,
+                "is_read_only": false,
+                "path_on_host": "/rootfs.ext4",
+                "rate_limiter": {"bandwidth": {"size": 1000, "refill_time": 100}}
+            })
+        );
+
+        let params = proto::SnapshotCreateParams {
+            snapshot_path: "/vmstate".to_owned(),
+            mem_file_path: "/mem".to_owned(),
+            snapshot_type: Some("Diff".to_owned()),
+            labels: [("tenant".to_owned(), "a".to_owned())].into(),
+            ..Default::default()
+        };
+        assert_eq!(
+            snapshot_create_body(&params),
+            json!({
+                "snapshot_path": "/vmstate",
+                "mem_file_path": "/mem",
+                "snapshot_type": "Diff",
+                "labels": {"tenant": "a"}
+            })
+        );
+
+        let params = proto::SnapshotLoadParams {
+            snapshot_path: "/vmstate".to_owned(),
+            mem_backend: Some(proto::MemoryBackend {
+                backend_type: "Uffd".to_owned(),
+                backend_path: "/uffd.socket".to_owned(),
+            }),
+            resume_vm: Some(true),
+            ..Default::default()
+        };
+        assert_eq!(
+            snapshot_load_body(&params),
+            json!({
+                "snapshot_path": "/vmstate",
+                "mem_backend": {"backend_type": "Uffd", "backend_path": "/uffd.socket"},
+                "resume_vm": true
+            })
+        );
+
+        checked_id("rootfs_1").unwrap();
+        assert_eq!(
+            checked_id("../actions").unwrap_err().code(),
+            Code::InvalidArgument
+        );
+    }
+
+    #[test]
+    fn test_machine_config_from_body() {
+        let body = json!({
+            "vcpu_count": 2,
+            "mem_size_mib": 256,
+            "smt": false,
+            "track_dirty_pages": true,
+            "huge_pages": "None"
+        });
+        let config = machine_config_from_body(&body).unwrap();
+        assert_eq!(config.vcpu_count, 2);
+        assert_eq!(config.mem_size_mib, 256);
+        assert_eq!(config.smt, Some(false));
+        assert_eq!(config.track_dirty_pages, Some(true));
+        assert_eq!(config.huge_pages.as_deref(), Some("None"));
+        assert_eq!(config.cpu_template, None);
+
+        assert_eq!(
+            machine_config_from_body(&json!({"vcpu_count": 2}))
+                .unwrap_err()
+                .code(),
+            Code::Internal
+        );
+    }
+
+    #[test]
+    fn test_response_result() {
+        let response = Response::new(Version::Http11, StatusCode::NoContent);
+        assert_eq!(response_result(&response).unwrap(), None);
+
+        let mut response = Response::new(Version::Http11, StatusCode::OK);
+        response.set_body(Body::new(r#"{"vcpu_count": 2}"#));
+        assert_eq!(
+            response_result(&response).unwrap(),
+            Some(json!({"vcpu_count": 2}))
+        );
+
+        let mut response = Response::new(Version::Http11, StatusCode::BadRequest);
+        response.set_body(Body::new(r#"{"fault_message": "Invalid drive."}"#));
+        let status = response_result(&response).unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+        assert_eq!(status.message(), "Invalid drive.");
+
+        let response = Response::new(Version::Http11, StatusCode::ServiceUnavailable);
+        assert_eq!(
+            response_result(&response).unwrap_err().code(),
+            Code::Unavailable
+        );
+    }
+}
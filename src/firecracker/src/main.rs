@@ -4,33 +4,49 @@
 mod api_server;
 mod api_server_adapter;
 mod gen;
+#[cfg(feature = "grpc")]
+mod grpc;
 mod metrics;
 mod seccomp;
 
 use std::fs::{self, File};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::ExitCode;
 use std::str::FromStr;
+use std::sync::atomic::Ordering;
 use std::sync::{Arc, Mutex};
 use std::{io, panic};
 
+use api_server::latency_budget::{
+    LatencyBudget, RequestTimeouts, RequestTimeoutsError, DEFAULT_MAX_PENDING_REQUESTS,
+};
 use api_server_adapter::ApiServerError;
 use event_manager::SubscriberOps;
 use seccomp::FilterError;
 use seccompiler::BpfThreadMap;
 use utils::arg_parser::{ArgParser, Argument};
+use utils::time::{get_time_us, ClockType};
 use utils::validators::validate_instance_id;
+use vmm::boot_timings::BOOT_TIMINGS;
 use vmm::builder::StartMicrovmError;
+use vmm::chaos::{ChaosMonkey, DEFAULT_CHAOS_DOWNTIME_MS};
+use vmm::coredump::CoredumpSnapshot;
+use vmm::cpu_config::templates::config_to_template;
+use vmm::diagnostics::{DiagnosticDumper, DiagnosticsError, DEFAULT_DIAGNOSTIC_SIGNAL};
+use vmm::event_stream::{EventStream, EventStreamError};
+use vmm::landlock::{LandlockConfig, LANDLOCK};
 use vmm::logger::{
     debug, error, info, LoggerConfig, ProcessTimeReporter, StoreMetric, LOGGER, METRICS,
 };
 use vmm::persist::SNAPSHOT_VERSION;
 use vmm::resources::VmResources;
 use vmm::signal_handler::register_signal_handlers;
+use vmm::snapshot::storage::HTTP_SNAPSHOT_STORAGE;
 use vmm::snapshot::{Snapshot, SnapshotError};
-use vmm::vmm_config::instance_info::{InstanceInfo, VmState};
+use vmm::state_dir::{StateDirError, STATE_DIR};
+use vmm::vmm_config::instance_info::{InstanceInfo, ProcessInfo, VmState, PROCESS_INFO};
 use vmm::vmm_config::metrics::{init_metrics, MetricsConfig, MetricsConfigError};
-use vmm::{EventManager, FcExitCode, HTTP_MAX_PAYLOAD_SIZE};
+use vmm::{EventManager, FcExitCode, Vmm, HTTP_MAX_PAYLOAD_SIZE};
 use vmm_sys_util::terminal::Terminal;
 
 use crate::seccomp::SeccompConfig;
@@ -50,6 +66,8 @@ enum MainError {
     RegisterSignalHandlers(#[source] vmm_sys_util::errno::Error),
     /// Arguments parsing error: {0} \n\nFor more information try --help.
     ParseArguments(#[from] utils::arg_parser::UtilsArgParserError),
+    /// Invalid value for the API request timeouts: {0}
+    InvalidApiRequestTimeouts(RequestTimeoutsError),
     /// When printing Snapshot Data format: {0}
     PrintSnapshotDataFormat(#[from] SnapshotVersionError),
     /// Invalid value for logger level: {0}.Possible values: [Error, Warning, Info, Debug]
@@ -58,6 +76,14 @@ enum MainError {
     LoggerInitialization(vmm::logger::LoggerUpdateError),
     /// Could not initialize metrics: {0}
     MetricsInitialization(MetricsConfigError),
+    /// Could not initialize diagnostic reports: {0}
+    DiagnosticsInitialization(DiagnosticsError),
+    /// Could not initialize the event stream: {0}
+    EventStreamInitialization(EventStreamError),
+    /// Could not initialize the state directory: {0}
+    StateDirInitialization(StateDirError),
+    /// Could not recover the state directory: {0}
+    Recover(StateDirError),
     /// Seccomp error: {0}
     SeccompFilter(FilterError),
     /// Failed to resize fd table: {0}
@@ -66,6 +92,8 @@ enum MainError {
     RunWithApi(ApiServerError),
     /// RunWithoutApiError error: {0}
     RunWithoutApiError(RunWithoutApiError),
+    /// Failed to dump the CPU configuration: {0}
+    DumpCpuConfig(BuildFromJsonError),
 }
 
 #[derive(Debug, thiserror::Error, displaydoc::Display)]
@@ -83,6 +111,7 @@ impl From<MainError> for FcExitCode {
         match value {
             MainError::ParseArguments(_) => FcExitCode::ArgParsing,
             MainError::InvalidLogLevel(_) => FcExitCode::BadConfiguration,
+            MainError::InvalidApiRequestTimeouts(_) => FcExitCode::ArgParsing,
             MainError::RunWithApi(ApiServerError::MicroVMStoppedWithError(code)) => code,
             MainError::RunWithoutApiError(RunWithoutApiError::Shutdown(code)) => code,
             _ => FcExitCode::GenericError,
@@ -97,9 +126,11 @@ fn main() -> ExitCode {
         eprintln!("Error: {err:?}");
         let exit_code = FcExitCode::from(err) as u8;
         error!("Firecracker exiting with error. exit_code={exit_code}");
+        STATE_DIR.record_exit(exit_code);
         ExitCode::from(exit_code)
     } else {
         info!("Firecracker exiting successfully. exit_code=0");
+        STATE_DIR.record_exit(0);
         ExitCode::SUCCESS
     }
 }
@@ -168,6 +199,30 @@ fn main_exec() -> Result<(), MainError> {
                          seccomp filtering. Not recommended.",
                     ),
             )
+            .arg(Argument::new("landlock").takes_value(false).help(
+                "Sandbox the VMM with Landlock once the microVM is built, restricting the files \
+                 it can open to the ones it has open, e.g. the kernel, the drives and the \
+                 sockets. Requires Landlock support in the host kernel.",
+            ))
+            .arg(
+                Argument::new("landlock-allow")
+                    .allow_multiple(true)
+                    .requires("landlock")
+                    .help(
+                        "Path of a file, or of a directory along with everything beneath it, the \
+                         sandboxed VMM keeps full access to, e.g. the directory in which \
+                         snapshots are created. Can be repeated.",
+                    ),
+            )
+            .arg(
+                Argument::new("http-snapshot-storage")
+                    .takes_value(false)
+                    .help(
+                        "Allow snapshots to be created on http:// URLs. Otherwise, the seccomp \
+                         filters forbid the VMM thread from opening TCP connections, unless a \
+                         device needs them.",
+                    ),
+            )
             .arg(
                 Argument::new("start-time-us").takes_value(true).help(
                     "Process start time (wall clock, microseconds). This parameter is optional.",
@@ -179,6 +234,10 @@ fn main_exec() -> Result<(), MainError> {
             .arg(Argument::new("parent-cpu-time-us").takes_value(true).help(
                 "Parent process CPU time (wall clock, microseconds). This parameter is optional.",
             ))
+            .arg(Argument::new("jailer-chroot-dir").takes_value(true).help(
+                "Host path of the jail root directory, reported by the API. Set by the jailer. \
+                 This parameter is optional.",
+            ))
             .arg(
                 Argument::new("config-file")
                     .takes_value(true)
@@ -226,6 +285,60 @@ fn main_exec() -> Result<(), MainError> {
                     .takes_value(true)
                     .help("Path to a fifo or a file used for configuring the metrics on startup."),
             )
+            .arg(
+                Argument::new("diagnostic-dump-path")
+                    .takes_value(true)
+                    .help(
+                        "Path to a file to which a diagnostic report is written whenever the \
+                         diagnostic signal is received.",
+                    ),
+            )
+            .arg(
+                Argument::new("diagnostic-signal")
+                    .takes_value(true)
+                    .requires("diagnostic-dump-path")
+                    .help("Signal number triggering a diagnostic report. Defaults to SIGUSR1."),
+            )
+            .arg(Argument::new("events-socket").takes_value(true).help(
+                "Path of a Unix socket streaming the events of the microVM, as lines of JSON, to \
+                 the connected clients.",
+            ))
+            .arg(
+                Argument::new("chaos-interval-ms")
+                    .takes_value(true)
+                    .requires("no-seccomp")
+                    .help(
+                        "Enables the chaos mode, which pauses, snapshots and restores the running \
+                         microVM in place, on average every given number of milliseconds. Only \
+                         meant for testing, and requires --no-seccomp.",
+                    ),
+            )
+            .arg(
+                Argument::new("chaos-downtime-ms")
+                    .takes_value(true)
+                    .requires("chaos-interval-ms")
+                    .help(
+                        "Number of milliseconds during which the microVM stays paused in each \
+                         chaos mode cycle. Defaults to 1000.",
+                    ),
+            )
+            .arg(
+                Argument::new("coredump-snapshot-dir")
+                    .takes_value(true)
+                    .help(
+                        "Path to an existing directory to which a best-effort snapshot of the \
+                         microVM is written when a vCPU exits on an error or the guest resets, \
+                         before Firecracker exits.",
+                    ),
+            )
+            .arg(Argument::new("state-dir").takes_value(true).help(
+                "Path to a directory in which the configuration, the device backends and the \
+                 lifecycle state of the microVM are recorded in a crash-safe manner.",
+            ))
+            .arg(Argument::new("recover").takes_value(true).help(
+                "Print a report of what the Firecracker process which recorded the given state \
+                 directory is or was doing, and exit.",
+            ))
             .arg(Argument::new("boot-timer").takes_value(false).help(
                 "Whether or not to load boot timer device for logging elapsed time since \
                  InstanceStart command.",
@@ -245,6 +358,10 @@ fn main_exec() -> Result<(), MainError> {
                     .takes_value(true)
                     .help("Print the data format version of the provided snapshot state file."),
             )
+            .arg(Argument::new("dump-cpu-config").takes_value(false).help(
+                "Print the CPU configuration of the microVM described by `config-file`, or of a \
+                 default microVM, as a custom CPU template, and exit.",
+            ))
             .arg(
                 Argument::new("http-api-max-payload-size")
                     .takes_value(true)
@@ -255,7 +372,48 @@ fn main_exec() -> Result<(), MainError> {
                 Argument::new("mmds-size-limit")
                     .takes_value(true)
                     .help("Mmds data store limit, in bytes."),
+            )
+            .arg(
+                Argument::new("api-request-timeouts")
+                    .takes_value(true)
+                    .help(
+                        "Comma separated list of `[<endpoint>=]<milliseconds>` timeouts of the \
+                         API requests, e.g. `500,snapshot=60000`. Requests which are not handled \
+                         by the VMM in time are answered with 503.",
+                    ),
+            )
+            .arg(
+                Argument::new("api-max-pending-requests")
+                    .takes_value(true)
+                    .requires("api-request-timeouts")
+                    .help(
+                        "Maximum number of timed out API requests still handled by the VMM, above \
+                         which further requests are answered with 429. Defaults to 1.",
+                    ),
+            )
+            .arg(
+                Argument::new("guest-api-vsock-port")
+                    .takes_value(true)
+                    .help(
+                        "Vsock port on which the guest can reach a restricted API, allowing to \
+                         get the balloon statistics and to update the `guest` MMDS subtree. \
+                         Requires a vsock device.",
+                    ),
             );
+    #[cfg(feature = "gdb")]
+    {
+        arg_parser = arg_parser.arg(Argument::new("gdb-socket").takes_value(true).help(
+            "Path of the Unix socket on which to wait for a GDB connection before booting the \
+             guest, unless the machine configuration sets `gdb_socket_path`.",
+        ));
+    }
+    #[cfg(feature = "grpc")]
+    {
+        arg_parser = arg_parser.arg(Argument::new("grpc-socket").takes_value(true).help(
+            "Path of the Unix socket on which to serve the gRPC mirror of the machine \
+             configuration, drive and snapshot requests of the API. Requires the API socket.",
+        ));
+    }
 
     arg_parser.parse_from_cmdline()?;
     let arguments = arg_parser.arguments();
@@ -281,6 +439,22 @@ fn main_exec() -> Result<(), MainError> {
         return Ok(());
     }
 
+    if let Some(state_dir) = arguments.single_value("recover") {
+        let report = vmm::state_dir::recover(Path::new(state_dir)).map_err(MainError::Recover)?;
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+        return Ok(());
+    }
+
+    // The CPU configuration is dumped before the logger is set up, so that the template printed
+    // on stdout is not mixed with the logs.
+    if arguments.flag_present("dump-cpu-config") {
+        let config_json = arguments
+            .single_value("config-file")
+            .map(fs::read_to_string)
+            .map(|x| x.expect("Unable to open or read from the configuration file"));
+        return dump_cpu_config(config_json).map_err(MainError::DumpCpuConfig);
+    }
+
     // It's safe to unwrap here because the field's been provided with a default value.
     let instance_id = arguments.single_value("id").unwrap();
     validate_instance_id(instance_id.as_str()).expect("Invalid instance ID");
@@ -338,6 +512,31 @@ fn main_exec() -> Result<(), MainError> {
         vmm_version: FIRECRACKER_VERSION.to_string(),
         app_name: "Firecracker".to_string(),
     };
+    PROCESS_INFO
+        .set(ProcessInfo {
+            pid: std::process::id(),
+            vcpu_tids: Vec::new(),
+            chroot_dir: arguments
+                .single_value("jailer-chroot-dir")
+                .map(PathBuf::from),
+        })
+        .expect("Process info already set");
+    // The boot phases are timed from the start of the jailer, when there is one.
+    BOOT_TIMINGS.set_start(
+        arguments
+            .single_value("start-time-us")
+            .and_then(|s| s.parse::<u64>().ok())
+            .unwrap_or_else(|| get_time_us(ClockType::Monotonic)),
+    );
+
+    if let Some(state_dir) = arguments.single_value("state-dir") {
+        let api_socket = (!arguments.flag_present("no-api"))
+            .then(|| arguments.single_value("api-sock").map(PathBuf::from))
+            .flatten();
+        STATE_DIR
+            .init(PathBuf::from(state_dir), &instance_info, api_socket)
+            .map_err(MainError::StateDirInitialization)?;
+    }
 
     if let Some(metrics_path) = arguments.single_value("metrics-path") {
         let metrics_config = MetricsConfig {
@@ -346,6 +545,64 @@ fn main_exec() -> Result<(), MainError> {
         init_metrics(metrics_config).map_err(MainError::MetricsInitialization)?;
     }
 
+    let event_stream = arguments
+        .single_value("events-socket")
+        .map(|socket_path| {
+            EventStream::new(Path::new(socket_path), instance_info.id.clone())
+                .map(|stream| Arc::new(Mutex::new(stream)))
+        })
+        .transpose()
+        .map_err(MainError::EventStreamInitialization)?;
+
+    let chaos_monkey = arguments.single_value("chaos-interval-ms").map(|interval| {
+        let interval_ms = interval
+            .parse::<u64>()
+            .expect("'chaos-interval-ms' parameter expected to be of 'u64' type.");
+        let downtime_ms = arguments.single_value("chaos-downtime-ms").map_or(
+            DEFAULT_CHAOS_DOWNTIME_MS,
+            |downtime| {
+                downtime
+                    .parse::<u64>()
+                    .expect("'chaos-downtime-ms' parameter expected to be of 'u64' type.")
+            },
+        );
+        Arc::new(Mutex::new(ChaosMonkey::new(interval_ms, downtime_ms)))
+    });
+
+    #[cfg(feature = "gdb")]
+    if let Some(gdb_socket_path) = arguments.single_value("gdb-socket") {
+        vmm::gdb::DEFAULT_SOCKET_PATH
+            .set(gdb_socket_path.clone())
+            .unwrap();
+    }
+
+    #[cfg(feature = "grpc")]
+    if let Some(grpc_socket_path) = arguments.single_value("grpc-socket") {
+        grpc::SOCKET_PATH
+            .set(PathBuf::from(grpc_socket_path))
+            .unwrap();
+    }
+
+    let coredump_snapshot_dir = arguments
+        .single_value("coredump-snapshot-dir")
+        .map(PathBuf::from);
+
+    if arguments.flag_present("landlock") {
+        // The snapshots of the failures are created after the VMM is sandboxed.
+        let allowed_paths = arguments
+            .multiple_values("landlock-allow")
+            .unwrap_or_default()
+            .iter()
+            .map(PathBuf::from)
+            .chain(coredump_snapshot_dir.clone())
+            .collect();
+        LANDLOCK.set(LandlockConfig { allowed_paths }).unwrap();
+    }
+
+    if arguments.flag_present("http-snapshot-storage") {
+        HTTP_SNAPSHOT_STORAGE.store(true, Ordering::Relaxed);
+    }
+
     let mut seccomp_filters: BpfThreadMap = SeccompConfig::from_args(
         arguments.flag_present("no-seccomp"),
         arguments.single_value("seccomp-filter"),
@@ -353,6 +610,27 @@ fn main_exec() -> Result<(), MainError> {
     .and_then(seccomp::get_filters)
     .map_err(MainError::SeccompFilter)?;
 
+    let diagnostic_dumper = arguments
+        .single_value("diagnostic-dump-path")
+        .map(|dump_path| {
+            let signum = arguments.single_value("diagnostic-signal").map_or(
+                DEFAULT_DIAGNOSTIC_SIGNAL,
+                |s| {
+                    s.parse::<i32>()
+                        .expect("'diagnostic-signal' parameter expected to be of 'i32' type.")
+                },
+            );
+            // The diagnostics thread runs with the filter of the VMM thread, whose state it
+            // reports.
+            DiagnosticDumper::new(
+                PathBuf::from(dump_path),
+                signum,
+                seccomp_filters.get("vmm").cloned().unwrap_or_default(),
+            )
+        })
+        .transpose()
+        .map_err(MainError::DiagnosticsInitialization)?;
+
     let vmm_config_json = arguments
         .single_value("config-file")
         .map(fs::read_to_string)
@@ -387,6 +665,29 @@ fn main_exec() -> Result<(), MainError> {
         .unwrap_or_else(|| api_payload_limit);
 
     if api_enabled {
+        let timeouts = arguments
+            .single_value("api-request-timeouts")
+            .map(|timeouts| timeouts.parse::<RequestTimeouts>())
+            .transpose()
+            .map_err(MainError::InvalidApiRequestTimeouts)?
+            .unwrap_or_default();
+        let max_pending_requests = arguments.single_value("api-max-pending-requests").map_or(
+            DEFAULT_MAX_PENDING_REQUESTS,
+            |max| {
+                max.parse::<usize>()
+                    .expect("'api-max-pending-requests' parameter expected to be of 'usize' type.")
+            },
+        );
+        let latency_budget = LatencyBudget {
+            timeouts,
+            max_pending_requests,
+        };
+
+        let guest_api_vsock_port = arguments.single_value("guest-api-vsock-port").map(|port| {
+            port.parse::<u32>()
+                .expect("'guest-api-vsock-port' parameter expected to be of 'u32' type.")
+        });
+
         let bind_path = arguments
             .single_value("api-sock")
             .map(PathBuf::from)
@@ -420,6 +721,12 @@ fn main_exec() -> Result<(), MainError> {
             api_payload_limit,
             mmds_size_limit,
             metadata_json.as_deref(),
+            diagnostic_dumper,
+            chaos_monkey,
+            event_stream,
+            coredump_snapshot_dir,
+            latency_budget,
+            guest_api_vsock_port,
         )
         .map_err(MainError::RunWithApi)
     } else {
@@ -434,6 +741,10 @@ fn main_exec() -> Result<(), MainError> {
             boot_timer_enabled,
             mmds_size_limit,
             metadata_json.as_deref(),
+            diagnostic_dumper,
+            chaos_monkey,
+            event_stream,
+            coredump_snapshot_dir,
         )
         .map_err(MainError::RunWithoutApiError)
     }
@@ -548,6 +859,26 @@ pub enum BuildFromJsonError {
     StartMicroVM(StartMicrovmError),
 }
 
+// Print the CPU configuration of a vCPU of the microVM described by the command-line JSON, or of a
+// default microVM, as a custom CPU template.
+fn dump_cpu_config(config_json: Option<String>) -> Result<(), BuildFromJsonError> {
+    let vm_resources = match config_json {
+        Some(config_json) => VmResources::from_json(
+            &config_json,
+            &InstanceInfo::default(),
+            HTTP_MAX_PAYLOAD_SIZE,
+            None,
+        )
+        .map_err(BuildFromJsonError::ParseFromJson)?,
+        None => VmResources::default(),
+    };
+    let cpu_config =
+        vmm::builder::dump_cpu_config(&vm_resources).map_err(BuildFromJsonError::StartMicroVM)?;
+    let cpu_template = config_to_template(&cpu_config);
+    println!("{}", serde_json::to_string_pretty(&cpu_template).unwrap());
+    Ok(())
+}
+
 // Configure and start a microVM as described by the command-line JSON.
 fn build_microvm_from_json(
     seccomp_filters: &BpfThreadMap,
@@ -569,6 +900,8 @@ fn build_microvm_from_json(
         seccomp_filters,
     )
     .map_err(BuildFromJsonError::StartMicroVM)?;
+    let vm_state = vmm.lock().expect("Poisoned lock").instance_info().state;
+    STATE_DIR.record_vm(&vm_state, &vm_resources);
 
     info!("Successfully started microvm that was configured from one single json");
 
@@ -583,6 +916,18 @@ enum RunWithoutApiError {
     BuildMicroVMFromJson(BuildFromJsonError),
 }
 
+/// Snapshots the microVM to `dir` when it fails, unless it can not be snapshotted.
+fn set_coredump_snapshot(vmm: &Mutex<Vmm>, dir: PathBuf, vm_resources: &VmResources) {
+    match CoredumpSnapshot::new(dir, vm_resources) {
+        Ok(coredump_snapshot) => vmm
+            .lock()
+            .expect("Poisoned lock")
+            .set_coredump_snapshot(coredump_snapshot),
+        Err(err) => error!("The failures of the microVM are not snapshotted: {}", err),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn run_without_api(
     seccomp_filters: &BpfThreadMap,
     config_json: Option<String>,
@@ -590,15 +935,29 @@ fn run_without_api(
     bool_timer_enabled: bool,
     mmds_size_limit: usize,
     metadata_json: Option<&str>,
+    diagnostic_dumper: Option<DiagnosticDumper>,
+    chaos_monkey: Option<Arc<Mutex<ChaosMonkey>>>,
+    event_stream: Option<Arc<Mutex<EventStream>>>,
+    coredump_snapshot_dir: Option<PathBuf>,
 ) -> Result<(), RunWithoutApiError> {
     let mut event_manager = EventManager::new().expect("Unable to create EventManager");
 
     // Create the firecracker metrics object responsible for periodically printing metrics.
     let firecracker_metrics = Arc::new(Mutex::new(metrics::PeriodicMetrics::new()));
     event_manager.add_subscriber(firecracker_metrics.clone());
+    event_manager.add_subscriber(Arc::new(Mutex::new(metrics::EventLoopProbe::new(
+        metrics::EVENT_LOOP_PROBE_PERIOD_MS,
+    ))));
+
+    if let Some(monkey) = &chaos_monkey {
+        event_manager.add_subscriber(monkey.clone());
+    }
+    if let Some(stream) = event_stream {
+        event_manager.add_subscriber(stream);
+    }
 
-    // Build the microVm. We can ignore VmResources since it's not used without api.
-    let (_, vmm) = build_microvm_from_json(
+    // Build the microVm. VmResources is only used to snapshot the failures without api.
+    let (vm_resources, vmm) = build_microvm_from_json(
         seccomp_filters,
         &mut event_manager,
         // Safe to unwrap since '--no-api' requires this to be set.
@@ -610,6 +969,16 @@ fn run_without_api(
     )
     .map_err(RunWithoutApiError::BuildMicroVMFromJson)?;
 
+    if let Some(dumper) = diagnostic_dumper {
+        dumper.set_vmm(vmm.clone());
+    }
+    if let Some(monkey) = chaos_monkey {
+        monkey.lock().expect("Poisoned lock").set_vmm(vmm.clone());
+    }
+    if let Some(dir) = coredump_snapshot_dir {
+        set_coredump_snapshot(&vmm, dir, &vm_resources);
+    }
+
     // Start the metrics.
     firecracker_metrics
         .lock()
@@ -2,7 +2,7 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use std::os::unix::io::AsRawFd;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use event_manager::{EventOps, Events, MutEventSubscriber};
 use timerfd::{ClockId, SetTimeFlags, TimerFd, TimerState};
@@ -11,6 +11,8 @@ use vmm_sys_util::epoll::EventSet;
 
 /// Metrics reporting period.
 pub(crate) const WRITE_METRICS_PERIOD_MS: u64 = 60000;
+/// Period at which the dispatch latency of the event loop is sampled.
+pub(crate) const EVENT_LOOP_PROBE_PERIOD_MS: u64 = 1000;
 
 /// Object to drive periodic reporting of metrics.
 #[derive(Debug)]
@@ -91,11 +93,72 @@ impl MutEventSubscriber for PeriodicMetrics {
     }
 }
 
+/// Samples how long the event loop takes to dispatch a periodic timer event, i.e. for how long
+/// the VMM thread is kept from handling new events.
+#[derive(Debug)]
+pub(crate) struct EventLoopProbe {
+    timer_fd: TimerFd,
+    interval: Duration,
+    /// Time of the first expiration not handled yet.
+    next_expiration: Instant,
+}
+
+impl EventLoopProbe {
+    /// Creates a probe sampling the dispatch latency every `interval_ms` millisecs.
+    /// Can panic on `TimerFd` creation failure.
+    pub fn new(interval_ms: u64) -> Self {
+        let mut timer_fd = TimerFd::new_custom(ClockId::Monotonic, true, true)
+            .expect("Cannot create the event loop probe timer fd.");
+        let interval = Duration::from_millis(interval_ms);
+        timer_fd.set_state(
+            TimerState::Periodic {
+                current: interval,
+                interval,
+            },
+            SetTimeFlags::Default,
+        );
+        EventLoopProbe {
+            timer_fd,
+            interval,
+            next_expiration: Instant::now() + interval,
+        }
+    }
+
+    /// Returns the time elapsed since the first expiration not handled yet.
+    fn dispatch_latency(&mut self) -> Duration {
+        let latency = self.next_expiration.elapsed();
+        // Periodic expirations keep their schedule, regardless of when they are handled.
+        let expirations = u32::try_from(self.timer_fd.read()).unwrap_or(u32::MAX);
+        self.next_expiration += self.interval.saturating_mul(expirations);
+        latency
+    }
+}
+
+impl MutEventSubscriber for EventLoopProbe {
+    /// Handle a read event (EPOLLIN).
+    fn process(&mut self, event: Events, _: &mut EventOps) {
+        if event.fd() == self.timer_fd.as_raw_fd() && event.event_set() == EventSet::IN {
+            let latency_us = u64::try_from(self.dispatch_latency().as_micros()).unwrap_or(u64::MAX);
+            METRICS.vmm.event_loop_samples.inc();
+            METRICS.vmm.event_loop_dispatch_agg.record(latency_us);
+        } else {
+            error!("Spurious EventManager event for handler: EventLoopProbe");
+        }
+    }
+
+    fn init(&mut self, ops: &mut EventOps) {
+        if let Err(err) = ops.add(Events::new(&self.timer_fd, EventSet::IN)) {
+            error!("Failed to register event loop probe timerfd event: {}", err);
+        }
+    }
+}
+
 #[cfg(test)]
 pub mod tests {
     use std::sync::{Arc, Mutex};
 
     use event_manager::{EventManager, SubscriberOps};
+    use vmm::logger::StoreMetric;
 
     use super::*;
 
@@ -120,4 +183,20 @@ pub mod tests {
         // Verify there was another flush.
         assert_eq!(metrics.lock().expect("Unlock failed.").flush_counter, 2);
     }
+
+    #[test]
+    fn test_event_loop_probe() {
+        let mut event_manager = EventManager::new().expect("Unable to create EventManager");
+        let probe = Arc::new(Mutex::new(EventLoopProbe::new(10)));
+        event_manager.add_subscriber(probe.clone());
+
+        // Stall the event loop for several periods before dispatching the event.
+        std::thread::sleep(Duration::from_millis(35));
+        let samples = METRICS.vmm.event_loop_samples.count();
+        event_manager
+            .run_with_timeout(100)
+            .expect("Probe event timeout or error.");
+        assert_eq!(METRICS.vmm.event_loop_samples.count(), samples + 1);
+        assert!(METRICS.vmm.event_loop_dispatch_agg.max_us.fetch() >= 20_000);
+    }
 }
@@ -0,0 +1,245 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::collections::HashSet;
+
+use serde::Deserialize;
+
+use super::JailerError;
+
+// Bounds of the CPU bandwidth period enforced by the kernel, in microseconds.
+const MIN_CPU_PERIOD_US: u64 = 1_000;
+const MAX_CPU_PERIOD_US: u64 = 1_000_000;
+// Smallest CPU quota accepted by the kernel, in microseconds.
+const MIN_CPU_QUOTA_US: u64 = 1_000;
+// Default CPU bandwidth period of the kernel, in microseconds.
+const DEFAULT_CPU_PERIOD_US: u64 = 100_000;
+
+// CPU bandwidth limit, written into `cpu.max`.
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct CpuLimits {
+    // CPU time the microVM can use in each period, unlimited if not set.
+    quota_us: Option<u64>,
+    // Length of the period.
+    #[serde(default = "default_cpu_period_us")]
+    period_us: u64,
+}
+
+fn default_cpu_period_us() -> u64 {
+    DEFAULT_CPU_PERIOD_US
+}
+
+// Memory limits, written into `memory.high` and `memory.max`.
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct MemoryLimits {
+    // Usage above which the microVM is throttled and its memory reclaimed.
+    high_bytes: Option<u64>,
+    // Usage above which the microVM is OOM killed.
+    max_bytes: Option<u64>,
+}
+
+// IO throttling of a block device, written into `io.max`.
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct IoLimits {
+    // Block device, as `<major>:<minor>`.
+    device: String,
+    read_bps: Option<u64>,
+    write_bps: Option<u64>,
+    read_iops: Option<u64>,
+    write_iops: Option<u64>,
+}
+
+// Resource limits of the microVM cgroup, given with `--resource-limits` in a JSON object, which
+// are translated into cgroup v2 properties.
+#[derive(Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct CgroupLimits {
+    cpu: Option<CpuLimits>,
+    memory: Option<MemoryLimits>,
+    #[serde(default)]
+    io: Vec<IoLimits>,
+}
+
+impl CgroupLimits {
+    // Parses and validates the limits.
+    pub fn from_json(json: &str) -> Result<Self, JailerError> {
+        let limits: CgroupLimits =
+            serde_json::from_str(json).map_err(|err| JailerError::CgroupLimits(err.to_string()))?;
+        limits.validate()?;
+        Ok(limits)
+    }
+
+    fn validate(&self) -> Result<(), JailerError> {
+        if let Some(cpu) = &self.cpu {
+            if !(MIN_CPU_PERIOD_US..=MAX_CPU_PERIOD_US).contains(&cpu.period_us) {
+                return Err(JailerError::CgroupLimits(format!(
+                    "cpu period_us must be between {MIN_CPU_PERIOD_US} and {MAX_CPU_PERIOD_US}"
+                )));
+            }
+            if cpu.quota_us.is_some_and(|quota| quota < MIN_CPU_QUOTA_US) {
+                return Err(JailerError::CgroupLimits(format!(
+                    "cpu quota_us must be at least {MIN_CPU_QUOTA_US}"
+                )));
+            }
+        }
+
+        if let Some(memory) = &self.memory {
+            if memory.high_bytes == Some(0) || memory.max_bytes == Some(0) {
+                return Err(JailerError::CgroupLimits(
+                    "memory limits must be greater than 0".to_string(),
+                ));
+            }
+            if let (Some(high), Some(max)) = (memory.high_bytes, memory.max_bytes) {
+                if high > max {
+                    return Err(JailerError::CgroupLimits(
+                        "memory high_bytes must not be greater than max_bytes".to_string(),
+                    ));
+                }
+            }
+        }
+
+        let mut devices = HashSet::new();
+        for io in &self.io {
+            let valid_device = io.device.split_once(':').is_some_and(|(major, minor)| {
+                major.parse::<u32>().is_ok() && minor.parse::<u32>().is_ok()
+            });
+            if !valid_device {
+                return Err(JailerError::CgroupLimits(format!(
+                    "io device {:?} must follow the <major>:<minor> format",
+                    io.device
+                )));
+            }
+            if !devices.insert(io.device.as_str()) {
+                return Err(JailerError::CgroupLimits(format!(
+                    "io device {} is limited more than once",
+                    io.device
+                )));
+            }
+            let limits = [io.read_bps, io.write_bps, io.read_iops, io.write_iops];
+            if limits.iter().all(Option::is_none) {
+                return Err(JailerError::CgroupLimits(format!(
+                    "io device {} has no limit",
+                    io.device
+                )));
+            }
+            if limits.contains(&Some(0)) {
+                return Err(JailerError::CgroupLimits(format!(
+                    "io limits of device {} must be greater than 0",
+                    io.device
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    // Returns the cgroup files and the values to write into them.
+    pub fn properties(&self) -> Vec<(String, String)> {
+        let mut properties = Vec::new();
+        if let Some(cpu) = &self.cpu {
+            let quota = cpu
+                .quota_us
+                .map_or_else(|| "max".to_string(), |quota| quota.to_string());
+            properties.push((
+                "cpu.max".to_string(),
+                format!("{} {}", quota, cpu.period_us),
+            ));
+        }
+        if let Some(memory) = &self.memory {
+            if let Some(high) = memory.high_bytes {
+                properties.push(("memory.high".to_string(), high.to_string()));
+            }
+            if let Some(max) = memory.max_bytes {
+                properties.push(("memory.max".to_string(), max.to_string()));
+            }
+        }
+        // `io.max` is written once per device.
+        for io in &self.io {
+            let mut value = io.device.clone();
+            for (key, limit) in [
+                ("rbps", io.read_bps),
+                ("wbps", io.write_bps),
+                ("riops", io.read_iops),
+                ("wiops", io.write_iops),
+            ] {
+                if let Some(limit) = limit {
+                    value.push_str(&format!(" {key}={limit}"));
+                }
+            }
+            properties.push(("io.max".to_string(), value));
+        }
+        properties
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cgroup_limits_properties() {
+        let limits = CgroupLimits::from_json(
+            r#"{
+                "cpu": { "quota_us": 50000 },
+                "memory": { "high_bytes": 536870912, "max_bytes": 1073741824 },
+                "io": [
+                    { "device": "8:0", "read_bps": 1048576, "write_iops": 100 },
+                    { "device": "259:1", "write_bps": 2097152 }
+                ]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            limits.properties(),
+            vec![
+                ("cpu.max".to_string(), "50000 100000".to_string()),
+                ("memory.high".to_string(), "536870912".to_string()),
+                ("memory.max".to_string(), "1073741824".to_string()),
+                (
+                    "io.max".to_string(),
+                    "8:0 rbps=1048576 wiops=100".to_string()
+                ),
+                ("io.max".to_string(), "259:1 wbps=2097152".to_string()),
+            ]
+        );
+
+        let limits = CgroupLimits::from_json(r#"{ "cpu": { "period_us": 10000 } }"#).unwrap();
+        assert_eq!(
+            limits.properties(),
+            vec![("cpu.max".to_string(), "max 10000".to_string())]
+        );
+
+        assert!(CgroupLimits::from_json("{}")
+            .unwrap()
+            .properties()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_cgroup_limits_validation() {
+        for json in [
+            "invalid_json",
+            r#"{ "cpu.max": "50000 100000" }"#,
+            r#"{ "cpu": { "quota_us": 50000, "period_us": 100 } }"#,
+            r#"{ "cpu": { "quota_us": 50000, "period_us": 2000000 } }"#,
+            r#"{ "cpu": { "quota_us": 10 } }"#,
+            r#"{ "memory": { "max_bytes": 0 } }"#,
+            r#"{ "memory": { "high_bytes": 2048, "max_bytes": 1024 } }"#,
+            r#"{ "io": [{ "device": "sda", "read_bps": 1 }] }"#,
+            r#"{ "io": [{ "device": "8:x", "read_bps": 1 }] }"#,
+            r#"{ "io": [{ "device": "8:0" }] }"#,
+            r#"{ "io": [{ "device": "8:0", "read_bps": 0 }] }"#,
+            r#"{ "io": [{ "device": "8:0", "read_bps": 1 }, { "device": "8:0", "write_bps": 1 }] }"#,
+        ] {
+            assert!(
+                matches!(
+                    CgroupLimits::from_json(json),
+                    Err(JailerError::CgroupLimits(_))
+                ),
+                "{json}"
+            );
+        }
+    }
+}
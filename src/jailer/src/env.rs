@@ -3,9 +3,9 @@
 
 use std::ffi::{CString, OsString};
 use std::fs::{self, canonicalize, read_to_string, File, OpenOptions, Permissions};
-use std::io::Write;
+use std::io::{Read, Write};
 use std::os::unix::fs::PermissionsExt;
-use std::os::unix::io::AsRawFd;
+use std::os::unix::io::{AsRawFd, FromRawFd};
 use std::os::unix::process::CommandExt;
 use std::path::{Component, Path, PathBuf};
 use std::process::{exit, id, Command, Stdio};
@@ -17,8 +17,10 @@ use utils::{arg_parser, validators};
 use vmm_sys_util::syscall::SyscallReturnCode;
 
 use crate::cgroup::{CgroupConfiguration, CgroupConfigurationBuilder};
+use crate::cgroup_limits::CgroupLimits;
 use crate::chroot::chroot;
 use crate::resource_limits::{ResourceLimits, FSIZE_ARG, NO_FILE_ARG};
+use crate::tap::TapConfig;
 use crate::JailerError;
 
 pub const PROC_MOUNTS: &str = "/proc/mounts";
@@ -104,6 +106,34 @@ fn clone(child_stack: *mut libc::c_void, flags: libc::c_int) -> Result<libc::c_i
     .map_err(JailerError::Clone)
 }
 
+// Maps `id` in the user namespace of the process `pid` to `host_id`, through the `uid_map` or
+// `gid_map` file of the host `/proc` directory.
+fn write_id_map(
+    proc_dir: &File,
+    pid: libc::c_int,
+    map: &str,
+    id: u32,
+    host_id: u32,
+) -> Result<(), JailerError> {
+    let map_path = CString::new(format!("{}/{}", pid, map)).unwrap();
+    // SAFETY: Safe because `map_path` is null-terminated and `proc_dir` is a valid directory.
+    let fd = SyscallReturnCode(unsafe {
+        libc::openat(
+            proc_dir.as_raw_fd(),
+            map_path.as_ptr(),
+            libc::O_WRONLY | libc::O_CLOEXEC,
+        )
+    })
+    .into_result()
+    .map_err(|err| JailerError::UserNsMap(map.to_owned(), err))?;
+    // SAFETY: Safe because the file descriptor was just opened and is owned by nothing else.
+    let mut map_file = unsafe { File::from_raw_fd(fd) };
+    // The whole map has to be written at once.
+    map_file
+        .write_all(format!("{} {} 1\n", id, host_id).as_bytes())
+        .map_err(|err| JailerError::UserNsMap(map.to_owned(), err))
+}
+
 #[derive(Debug, thiserror::Error)]
 enum UserfaultfdParseError {
     #[error("Could not read /proc/misc: {0}")]
@@ -123,12 +153,14 @@ pub struct Env {
     netns: Option<String>,
     daemonize: bool,
     new_pid_ns: bool,
+    userns: Option<(u32, u32)>,
     start_time_us: u64,
     start_time_cpu_us: u64,
     jailer_cpu_time_us: u64,
     extra_args: Vec<String>,
     cgroup_conf: Option<CgroupConfiguration>,
     resource_limits: ResourceLimits,
+    taps: Vec<TapConfig>,
     uffd_dev_minor: Option<u32>,
 }
 
@@ -143,11 +175,13 @@ impl fmt::Debug for Env {
             .field("netns", &self.netns)
             .field("daemonize", &self.daemonize)
             .field("new_pid_ns", &self.new_pid_ns)
+            .field("userns", &self.userns)
             .field("start_time_us", &self.start_time_us)
             .field("jailer_cpu_time_us", &self.jailer_cpu_time_us)
             .field("extra_args", &self.extra_args)
             .field("cgroups", &self.cgroup_conf)
             .field("resource_limits", &self.resource_limits)
+            .field("taps", &self.taps)
             .finish()
     }
 }
@@ -206,6 +240,11 @@ impl Env {
 
         let new_pid_ns = arguments.flag_present("new-pid-ns");
 
+        let userns = arguments
+            .single_value("userns")
+            .map(|ids| Env::parse_userns(ids))
+            .transpose()?;
+
         // Optional arguments.
         let mut cgroup_conf = None;
         let parent_cgroup = match arguments.single_value("parent-cgroup") {
@@ -228,10 +267,18 @@ impl Env {
 
         let cgroups_args: &[String] = arguments.multiple_values("cgroup").unwrap_or_default();
 
+        let cgroup_limits = arguments
+            .single_value("resource-limits")
+            .map(|json| CgroupLimits::from_json(json))
+            .transpose()?;
+        if cgroup_limits.is_some() && cgroup_ver != 2 {
+            return Err(JailerError::CgroupLimitsVersion);
+        }
+
         // If the --parent-cgroup exists, and we have no other cgroups,
         // then the intent is to move the process to that cgroup.
         // Only applies to cgroupsv2 since it's a unified hierarchy
-        if cgroups_args.is_empty() && cgroup_ver == 2 {
+        if cgroups_args.is_empty() && cgroup_limits.is_none() && cgroup_ver == 2 {
             let builder = CgroupConfigurationBuilder::new(cgroup_ver, proc_mounts)?;
             let cg_parent = builder.get_v2_hierarchy_path()?.join(parent_cgroup);
             let cg_parent_procs = cg_parent.join("cgroup.procs");
@@ -242,7 +289,7 @@ impl Env {
         }
 
         // cgroup format: <cgroup_controller>.<cgroup_property>=<value>,...
-        if let Some(cgroups_args) = arguments.multiple_values("cgroup") {
+        if !cgroups_args.is_empty() || cgroup_limits.is_some() {
             let mut builder = CgroupConfigurationBuilder::new(cgroup_ver, proc_mounts)?;
             for cg in cgroups_args {
                 let aux: Vec<&str> = cg.split('=').collect();
@@ -263,6 +310,10 @@ impl Env {
                     parent_cgroup,
                 )?;
             }
+            // The limits are written after the --cgroup values, which can not override them.
+            for (file, value) in cgroup_limits.iter().flat_map(CgroupLimits::properties) {
+                builder.add_cgroup_property(file, value, id, parent_cgroup)?;
+            }
             cgroup_conf = Some(builder.build());
         }
 
@@ -271,6 +322,13 @@ impl Env {
             Env::parse_resource_limits(&mut resource_limits, args)?;
         }
 
+        let taps = arguments
+            .multiple_values("tap")
+            .unwrap_or_default()
+            .iter()
+            .map(|arg| TapConfig::parse(arg))
+            .collect::<Result<Vec<_>, _>>()?;
+
         let uffd_dev_minor = Self::get_userfaultfd_minor_dev_number().ok();
 
         Ok(Env {
@@ -282,12 +340,14 @@ impl Env {
             netns,
             daemonize,
             new_pid_ns,
+            userns,
             start_time_us,
             start_time_cpu_us,
             jailer_cpu_time_us: 0,
             extra_args: arguments.extra_args(),
             cgroup_conf,
             resource_limits,
+            taps,
             uffd_dev_minor,
         })
     }
@@ -304,6 +364,23 @@ impl Env {
         self.uid
     }
 
+    // Host uid owning the jail, which is the one Firecracker runs as on the host.
+    fn owner_uid(&self) -> u32 {
+        self.userns.map_or(self.uid, |(host_uid, _)| host_uid)
+    }
+
+    // Host gid owning the jail, which is the one Firecracker runs as on the host.
+    fn owner_gid(&self) -> u32 {
+        self.userns.map_or(self.gid, |(_, host_gid)| host_gid)
+    }
+
+    // Parses the host ids to which the uid and gid are mapped, as <host_uid>:<host_gid>.
+    fn parse_userns(ids: &str) -> Result<(u32, u32), JailerError> {
+        ids.split_once(':')
+            .and_then(|(uid, gid)| Some((uid.parse().ok()?, gid.parse().ok()?)))
+            .ok_or_else(|| JailerError::UserNsFormat(ids.to_string()))
+    }
+
     fn validate_exec_file(exec_file: &str) -> Result<(PathBuf, String), JailerError> {
         let exec_file_path = canonicalize(exec_file)
             .map_err(|err| JailerError::Canonicalize(PathBuf::from(exec_file), err))?;
@@ -365,6 +442,54 @@ impl Env {
         }
     }
 
+    fn exec_into_new_user_ns(
+        &mut self,
+        chroot_exec_file: PathBuf,
+        proc_dir: &File,
+    ) -> Result<(), JailerError> {
+        let mut fds = [0; 2];
+        // SAFETY: Safe because `fds` is a valid array of two file descriptors.
+        SyscallReturnCode(unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) })
+            .into_empty_result()
+            .map_err(JailerError::UserNsSync)?;
+        // SAFETY: Safe because the pipe ends were just created and are owned by nothing else.
+        let (mut ready_rx, mut ready_tx) =
+            unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+
+        // The user namespace owns the PID namespace, if one is requested as well.
+        let mut flags = libc::CLONE_NEWUSER;
+        if self.new_pid_ns {
+            flags |= libc::CLONE_NEWPID;
+        }
+        let pid = clone(std::ptr::null_mut(), flags)?;
+        match pid {
+            0 => {
+                drop(ready_tx);
+                // Wait for the parent to map the ids of the namespace, which fails if the parent
+                // exits without doing so.
+                ready_rx
+                    .read_exact(&mut [0u8])
+                    .map_err(JailerError::UserNsSync)?;
+                // The supplementary groups of the jailer are not mapped in the namespace, but
+                // would still grant access to the host files.
+                // SAFETY: Safe because an empty list of groups is valid.
+                SyscallReturnCode(unsafe { libc::setgroups(0, std::ptr::null()) })
+                    .into_empty_result()
+                    .map_err(JailerError::SetGroups)?;
+                Err(JailerError::Exec(self.exec_command(chroot_exec_file)))
+            }
+            child_pid => {
+                drop(ready_rx);
+                write_id_map(proc_dir, child_pid, "uid_map", self.uid, self.owner_uid())?;
+                write_id_map(proc_dir, child_pid, "gid_map", self.gid, self.owner_gid())?;
+                ready_tx.write_all(&[1]).map_err(JailerError::UserNsSync)?;
+                self.save_exec_file_pid(child_pid, chroot_exec_file)?;
+                // SAFETY: This is safe because 0 is valid input to exit.
+                unsafe { libc::exit(0) }
+            }
+        }
+    }
+
     fn save_exec_file_pid(
         &mut self,
         pid: i32,
@@ -427,12 +552,12 @@ impl Env {
         .map_err(|err| JailerError::MknodDev(err, dev_path_str.to_owned()))?;
 
         // SAFETY: This is safe because dev_path is CStr, and hence null-terminated.
-        SyscallReturnCode(unsafe { libc::chown(dev_path.as_ptr(), self.uid(), self.gid()) })
-            .into_empty_result()
-            // Safe to unwrap as we provided valid file names.
-            .map_err(|err| {
-                JailerError::ChangeFileOwner(PathBuf::from(dev_path.to_str().unwrap()), err)
-            })
+        SyscallReturnCode(unsafe {
+            libc::chown(dev_path.as_ptr(), self.owner_uid(), self.owner_gid())
+        })
+        .into_empty_result()
+        // Safe to unwrap as we provided valid file names.
+        .map_err(|err| JailerError::ChangeFileOwner(PathBuf::from(dev_path.to_str().unwrap()), err))
     }
 
     fn setup_jailed_folder(&self, folder: impl AsRef<Path>) -> Result<(), JailerError> {
@@ -448,9 +573,11 @@ impl Env {
         #[cfg(target_arch = "aarch64")]
         let folder_bytes_ptr = c_path.as_ptr();
         // SAFETY: This is safe because folder was checked for a null-terminator.
-        SyscallReturnCode(unsafe { libc::chown(folder_bytes_ptr, self.uid(), self.gid()) })
-            .into_empty_result()
-            .map_err(|err| JailerError::ChangeFileOwner(folder_path.to_owned(), err))
+        SyscallReturnCode(unsafe {
+            libc::chown(folder_bytes_ptr, self.owner_uid(), self.owner_gid())
+        })
+        .into_empty_result()
+        .map_err(|err| JailerError::ChangeFileOwner(folder_path.to_owned(), err))
     }
 
     fn copy_exec_to_chroot(&mut self) -> Result<OsString, JailerError> {
@@ -497,6 +624,8 @@ impl Env {
             .args(["--start-time-us", &self.start_time_us.to_string()])
             .args(["--start-time-cpu-us", &self.start_time_cpu_us.to_string()])
             .args(["--parent-cpu-time-us", &self.jailer_cpu_time_us.to_string()])
+            .arg("--jailer-chroot-dir")
+            .arg(&self.chroot_dir)
             .stdin(Stdio::inherit())
             .stdout(Stdio::inherit())
             .stderr(Stdio::inherit())
@@ -558,7 +687,7 @@ impl Env {
                 let dest_path_cstr = to_cstring(&jailer_cache_file)?;
                 // SAFETY: Safe because dest_path_cstr is null-terminated.
                 SyscallReturnCode(unsafe {
-                    libc::chown(dest_path_cstr.as_ptr(), self.uid(), self.gid())
+                    libc::chown(dest_path_cstr.as_ptr(), self.owner_uid(), self.owner_gid())
                 })
                 .into_empty_result()
                 .map_err(|err| JailerError::ChangeFileOwner(jailer_cache_file.to_owned(), err))?;
@@ -588,9 +717,11 @@ impl Env {
         // Change the permissions.
         let dest_path_cstr = to_cstring(&jailer_midr_el1_file)?;
         // SAFETY: Safe because `dest_path_cstr` is null-terminated.
-        SyscallReturnCode(unsafe { libc::chown(dest_path_cstr.as_ptr(), self.uid(), self.gid()) })
-            .into_empty_result()
-            .map_err(|err| JailerError::ChangeFileOwner(jailer_midr_el1_file.to_owned(), err))?;
+        SyscallReturnCode(unsafe {
+            libc::chown(dest_path_cstr.as_ptr(), self.owner_uid(), self.owner_gid())
+        })
+        .into_empty_result()
+        .map_err(|err| JailerError::ChangeFileOwner(jailer_midr_el1_file.to_owned(), err))?;
 
         Ok(())
     }
@@ -604,6 +735,12 @@ impl Env {
             Env::join_netns(path)?;
         }
 
+        // Create the TAP devices in the network namespace of the microVM, which needs the host
+        // `/dev/net/tun`.
+        for tap in &self.taps {
+            tap.create(self.owner_uid(), self.owner_gid())?;
+        }
+
         // Set limits on resources.
         self.resource_limits.install()?;
 
@@ -623,6 +760,16 @@ impl Env {
         #[cfg(target_arch = "aarch64")]
         self.copy_midr_el1_info()?;
 
+        // The ids of the user namespace are mapped through the host `/proc`, which can not be
+        // reached anymore after chrooting.
+        let proc_dir = match self.userns {
+            Some(_) => Some(
+                File::open("/proc")
+                    .map_err(|err| JailerError::FileOpen(PathBuf::from("/proc"), err))?,
+            ),
+            None => None,
+        };
+
         // Jail self.
         chroot(self.chroot_dir())?;
 
@@ -719,8 +866,11 @@ impl Env {
         // Reset process start time.
         self.start_time_cpu_us = 0;
 
-        // If specified, exec the provided binary into a new PID namespace.
-        if self.new_pid_ns {
+        // If specified, exec the provided binary into a new user namespace, and into a new PID
+        // namespace.
+        if let Some(proc_dir) = proc_dir {
+            self.exec_into_new_user_ns(chroot_exec_file, &proc_dir)
+        } else if self.new_pid_ns {
             self.exec_into_new_pid_ns(chroot_exec_file)
         } else {
             self.save_exec_file_pid(id().try_into().unwrap(), chroot_exec_file.clone())?;
@@ -997,6 +1147,125 @@ mod tests {
         // actually attempt to create the folder structure (the same goes for netns).
     }
 
+    #[test]
+    fn test_new_env_cgroup_limits() {
+        let mut mock_cgroups = MockCgroupFs::new().unwrap();
+        mock_cgroups.add_v1_mounts().unwrap();
+        mock_cgroups.add_v2_mounts().unwrap();
+        let pseudo_exec_file_path = get_pseudo_exec_file_path();
+        let arg_vals = ArgVals {
+            cgroups: Vec::new(),
+            ..ArgVals::new(pseudo_exec_file_path.as_str())
+        };
+        let limits = r#"{ "cpu": { "quota_us": 50000 }, "memory": { "max_bytes": 1073741824 } }"#;
+
+        // The limits require cgroup v2.
+        let mut args = build_arg_parser().arguments().clone();
+        let mut arg_vec = make_args(&arg_vals);
+        arg_vec.extend(["--resource-limits".to_string(), limits.to_string()]);
+        args.parse(&arg_vec).unwrap();
+        assert!(matches!(
+            Env::new(&args, 0, 0, mock_cgroups.proc_mounts_path.as_str()),
+            Err(JailerError::CgroupLimitsVersion)
+        ));
+
+        let mut args = build_arg_parser().arguments().clone();
+        arg_vec.extend(["--cgroup-version".to_string(), "2".to_string()]);
+        args.parse(&arg_vec).unwrap();
+        let env = Env::new(&args, 0, 0, mock_cgroups.proc_mounts_path.as_str()).unwrap();
+        assert!(matches!(env.cgroup_conf, Some(CgroupConfiguration::V2(_))));
+
+        let mut args = build_arg_parser().arguments().clone();
+        let mut arg_vec = make_args(&arg_vals);
+        arg_vec.extend([
+            "--cgroup-version".to_string(),
+            "2".to_string(),
+            "--resource-limits".to_string(),
+            r#"{ "cpu": { "quota_us": 10 } }"#.to_string(),
+        ]);
+        args.parse(&arg_vec).unwrap();
+        assert!(matches!(
+            Env::new(&args, 0, 0, mock_cgroups.proc_mounts_path.as_str()),
+            Err(JailerError::CgroupLimits(_))
+        ));
+    }
+
+    #[test]
+    fn test_new_env_taps() {
+        let mut mock_cgroups = MockCgroupFs::new().unwrap();
+        mock_cgroups.add_v1_mounts().unwrap();
+        let pseudo_exec_file_path = get_pseudo_exec_file_path();
+        let arg_vals = ArgVals::new(pseudo_exec_file_path.as_str());
+
+        let mut args = build_arg_parser().arguments().clone();
+        let mut arg_vec = make_args(&arg_vals);
+        arg_vec.extend([
+            "--tap".to_string(),
+            "name=tap0".to_string(),
+            "--tap".to_string(),
+            "name=tap1,mac=06:00:AC:10:00:02,queues=2".to_string(),
+        ]);
+        args.parse(&arg_vec).unwrap();
+        let env = Env::new(&args, 0, 0, mock_cgroups.proc_mounts_path.as_str()).unwrap();
+        assert_eq!(
+            env.taps,
+            vec![
+                TapConfig::parse("name=tap0").unwrap(),
+                TapConfig::parse("name=tap1,mac=06:00:AC:10:00:02,queues=2").unwrap(),
+            ]
+        );
+
+        let mut args = build_arg_parser().arguments().clone();
+        let mut arg_vec = make_args(&arg_vals);
+        arg_vec.extend(["--tap".to_string(), "tap0".to_string()]);
+        args.parse(&arg_vec).unwrap();
+        assert!(matches!(
+            Env::new(&args, 0, 0, mock_cgroups.proc_mounts_path.as_str()),
+            Err(JailerError::TapFormat(_))
+        ));
+    }
+
+    #[test]
+    fn test_new_env_userns() {
+        let mut mock_cgroups = MockCgroupFs::new().unwrap();
+        mock_cgroups.add_v1_mounts().unwrap();
+        let pseudo_exec_file_path = get_pseudo_exec_file_path();
+        let arg_vals = ArgVals::new(pseudo_exec_file_path.as_str());
+
+        // Without a user namespace, the jail is owned by the uid and gid of Firecracker.
+        let env = create_env(mock_cgroups.proc_mounts_path.as_str());
+        assert_eq!(env.userns, None);
+        assert_eq!((env.owner_uid(), env.owner_gid()), (env.uid(), env.gid()));
+
+        let mut args = build_arg_parser().arguments().clone();
+        let mut arg_vec = make_args(&arg_vals);
+        arg_vec.extend(["--userns".to_string(), "100001:100002".to_string()]);
+        args.parse(&arg_vec).unwrap();
+        let env = Env::new(&args, 0, 0, mock_cgroups.proc_mounts_path.as_str()).unwrap();
+        assert_eq!((env.uid(), env.gid()), (1001, 1002));
+        assert_eq!((env.owner_uid(), env.owner_gid()), (100001, 100002));
+
+        for ids in [
+            "100001",
+            "100001:",
+            ":100002",
+            "100001:100002:0",
+            "x:100002",
+        ] {
+            let mut args = build_arg_parser().arguments().clone();
+            let mut arg_vec = make_args(&arg_vals);
+            arg_vec.extend(["--userns".to_string(), ids.to_string()]);
+            args.parse(&arg_vec).unwrap();
+            assert!(
+                matches!(
+                    Env::new(&args, 0, 0, mock_cgroups.proc_mounts_path.as_str()),
+                    Err(JailerError::UserNsFormat(_))
+                ),
+                "{ids}"
+            );
+        }
+    }
+
     #[test]
     fn test_dup2() {
         // Open /dev/kvm since it should be available anyway.
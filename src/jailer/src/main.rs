@@ -15,9 +15,11 @@ use vmm_sys_util::syscall::SyscallReturnCode;
 use crate::env::Env;
 
 mod cgroup;
+mod cgroup_limits;
 mod chroot;
 mod env;
 mod resource_limits;
+mod tap;
 
 const JAILER_VERSION: &str = env!("CARGO_PKG_VERSION");
 
@@ -35,6 +37,10 @@ pub enum JailerError {
     CgroupInvalidFile(String),
     #[error("Invalid format for cgroups: {0}")]
     CgroupFormat(String),
+    #[error("Invalid cgroup resource limits: {0}")]
+    CgroupLimits(String),
+    #[error("Cgroup resource limits require cgroup version 2")]
+    CgroupLimitsVersion,
     #[error("Hierarchy not found: {0}")]
     CgroupHierarchyMissing(String),
     #[error("Controller {0} is unavailable")]
@@ -129,8 +135,14 @@ pub enum JailerError {
     SetNetNs(io::Error),
     #[error("Failed to set limit for resource: {0}")]
     Setrlimit(String),
+    #[error("Failed to drop the supplementary groups: {0}")]
+    SetGroups(io::Error),
     #[error("Failed to daemonize: setsid: {0}")]
     SetSid(io::Error),
+    #[error("Failed to create tap {0}: {1}")]
+    TapCreate(String, io::Error),
+    #[error("Invalid format for tap: {0}")]
+    TapFormat(String),
     #[error("Invalid uid: {0}")]
     Uid(String),
     #[error("Failed to unmount the old jail root: {0}")]
@@ -141,6 +153,12 @@ pub enum JailerError {
     UnshareNewNs(io::Error),
     #[error("Failed to unset the O_CLOEXEC flag on the socket fd: {0}")]
     UnsetCloexec(io::Error),
+    #[error("Invalid format for the user namespace ids: {0}")]
+    UserNsFormat(String),
+    #[error("Failed to write the {0} of the user namespace: {1}")]
+    UserNsMap(String, io::Error),
+    #[error("Failed to synchronize with the user namespace process: {0}")]
+    UserNsSync(io::Error),
     #[error("Slice contains invalid UTF-8 data : {0}")]
     UTF8Parsing(std::str::Utf8Error),
     #[error("{}", format!("Failed to write to {:?}: {}", .0, .1).replace('\"', ""))]
@@ -195,6 +213,11 @@ pub fn build_arg_parser() -> ArgParser<'static> {
                 .takes_value(false)
                 .help("Exec into a new PID namespace."),
         )
+        .arg(Argument::new("userns").takes_value(true).help(
+            "Exec into a new user namespace, in which the uid and gid are mapped to the given \
+             host uid and gid. It must follow this format: <host_uid>:<host_gid> (e.g \
+             100001:100001). The jail is then owned by the host uid and gid.",
+        ))
         .arg(Argument::new("cgroup").allow_multiple(true).help(
             "Cgroup and value to be set by the jailer. It must follow this format: \
              <cgroup_file>=<value> (e.g cpu.shares=10). This argument can be used multiple times \
@@ -208,6 +231,18 @@ pub fn build_arg_parser() -> ArgParser<'static> {
              value one greater than the maximum file descriptor number that can be opened by this \
              process.",
         ))
+        .arg(Argument::new("resource-limits").takes_value(true).help(
+            "Resource limits of the microVM cgroup, as a JSON object with the optional `cpu` \
+             (`quota_us`, `period_us`), `memory` (`high_bytes`, `max_bytes`) and `io` (list of \
+             `device` as <major>:<minor> along with `read_bps`, `write_bps`, `read_iops`, \
+             `write_iops`) limits. Requires cgroup version 2.",
+        ))
+        .arg(Argument::new("tap").allow_multiple(true).help(
+            "TAP device to be created by the jailer in the network namespace of the microVM, \
+             owned by the uid and gid of Firecracker. It must follow this format: \
+             name=<name>[,mac=<mac>][,queues=<queues>] (e.g name=tap0,mac=06:00:AC:10:00:02). \
+             This argument can be used multiple times to create multiple TAP devices.",
+        ))
         .arg(
             Argument::new("cgroup-version")
                 .takes_value(true)
@@ -0,0 +1,246 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::fs::OpenOptions;
+use std::io;
+use std::os::unix::io::{FromRawFd, OwnedFd};
+
+use vmm_sys_util::ioctl::{ioctl_with_mut_ref, ioctl_with_ref, ioctl_with_val};
+use vmm_sys_util::{ioctl_ioc_nr, ioctl_iow_nr};
+
+use crate::JailerError;
+
+const TUNTAP: ::std::os::raw::c_uint = 84;
+ioctl_iow_nr!(TUNSETIFF, TUNTAP, 202, ::std::os::raw::c_int);
+ioctl_iow_nr!(TUNSETPERSIST, TUNTAP, 203, ::std::os::raw::c_int);
+ioctl_iow_nr!(TUNSETOWNER, TUNTAP, 204, ::std::os::raw::c_int);
+ioctl_iow_nr!(TUNSETGROUP, TUNTAP, 206, ::std::os::raw::c_int);
+
+// As defined in the Linux UAPI:
+// https://elixir.bootlin.com/linux/v6.1/source/include/uapi/linux/sockios.h
+const SIOCGIFFLAGS: libc::c_ulong = 0x8913;
+const SIOCSIFFLAGS: libc::c_ulong = 0x8914;
+const SIOCSIFHWADDR: libc::c_ulong = 0x8924;
+
+// As defined in the Linux UAPI:
+// https://elixir.bootlin.com/linux/v6.1/source/include/uapi/linux/if_tun.h
+const IFF_TAP: libc::c_short = 0x0002;
+const IFF_NO_PI: libc::c_short = 0x1000;
+const IFF_VNET_HDR: libc::c_short = 0x4000;
+const IFF_MULTI_QUEUE: libc::c_short = 0x0100;
+
+const DEV_NET_TUN: &str = "/dev/net/tun";
+// Largest number of queue pairs of a Firecracker network device.
+const MAX_QUEUES: u16 = 16;
+
+// Layout of `struct ifreq`, of which only the flags and the hardware address members of the
+// request data union are used.
+#[repr(C)]
+#[derive(Debug, Default)]
+struct IfReq {
+    name: [u8; libc::IFNAMSIZ],
+    data: [u8; 24],
+}
+
+impl IfReq {
+    fn new(name: &str) -> Self {
+        let mut ifreq = IfReq::default();
+        // The name is validated to be shorter than `IFNAMSIZ`, so it stays NUL terminated.
+        ifreq.name[..name.len()].copy_from_slice(name.as_bytes());
+        ifreq
+    }
+
+    fn flags(&self) -> libc::c_short {
+        libc::c_short::from_ne_bytes([self.data[0], self.data[1]])
+    }
+
+    fn set_flags(&mut self, flags: libc::c_short) {
+        self.data[..2].copy_from_slice(&flags.to_ne_bytes());
+    }
+
+    fn set_hwaddr(&mut self, mac: &[u8; 6]) {
+        // The hardware address is a `struct sockaddr`, starting with its family.
+        self.data[..2].copy_from_slice(&libc::ARPHRD_ETHER.to_ne_bytes());
+        self.data[2..8].copy_from_slice(mac);
+    }
+}
+
+// TAP device created by the jailer inside the network namespace of the microVM, given with
+// `--tap name=<name>[,mac=<mac>][,queues=<queues>]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TapConfig {
+    name: String,
+    mac: Option<[u8; 6]>,
+    queues: u16,
+}
+
+// Mirrors the checks of the kernel on the names of network interfaces.
+fn valid_if_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() < libc::IFNAMSIZ
+        && name != "."
+        && name != ".."
+        && !name.contains(|c: char| c == '/' || c == ':' || c.is_whitespace())
+}
+
+fn parse_mac(mac: &str) -> Option<[u8; 6]> {
+    let mut bytes = [0u8; 6];
+    let mut parts = mac.split(':');
+    for byte in bytes.iter_mut() {
+        let part = parts.next().filter(|part| part.len() == 2)?;
+        *byte = u8::from_str_radix(part, 16).ok()?;
+    }
+    parts.next().is_none().then_some(bytes)
+}
+
+impl TapConfig {
+    pub fn parse(arg: &str) -> Result<Self, JailerError> {
+        let format_error = || JailerError::TapFormat(arg.to_string());
+        let mut name = None;
+        let mut mac = None;
+        let mut queues = 1;
+        for pair in arg.split(',') {
+            let (key, value) = pair.split_once('=').ok_or_else(format_error)?;
+            match key {
+                "name" if valid_if_name(value) => name = Some(value.to_string()),
+                "mac" => mac = Some(parse_mac(value).ok_or_else(format_error)?),
+                "queues" => {
+                    queues = value
+                        .parse()
+                        .ok()
+                        .filter(|queues| (1..=MAX_QUEUES).contains(queues))
+                        .ok_or_else(format_error)?
+                }
+                _ => return Err(format_error()),
+            }
+        }
+        Ok(TapConfig {
+            name: name.ok_or_else(format_error)?,
+            mac,
+            queues,
+        })
+    }
+
+    // Creates the TAP device in the current network namespace, persisting after the jailer
+    // exits and owned by `uid` and `gid` so that Firecracker can open it, and brings it up.
+    pub fn create(&self, uid: u32, gid: u32) -> Result<(), JailerError> {
+        self.setup(uid, gid)
+            .map_err(|err| JailerError::TapCreate(self.name.clone(), err))
+    }
+
+    fn setup(&self, uid: u32, gid: u32) -> io::Result<()> {
+        let tun = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(DEV_NET_TUN)?;
+        let mut flags = IFF_TAP | IFF_NO_PI | IFF_VNET_HDR;
+        // Firecracker opens the queues of multi-queue devices itself.
+        if self.queues > 1 {
+            flags |= IFF_MULTI_QUEUE;
+        }
+        let mut ifreq = IfReq::new(&self.name);
+        ifreq.set_flags(flags);
+        // SAFETY: Safe because `ifreq` is a valid `struct ifreq` and the return value is checked.
+        if unsafe { ioctl_with_mut_ref(&tun, TUNSETIFF(), &mut ifreq) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        for (request, value) in [
+            (TUNSETOWNER(), libc::c_ulong::from(uid)),
+            (TUNSETGROUP(), libc::c_ulong::from(gid)),
+            (TUNSETPERSIST(), 1),
+        ] {
+            // SAFETY: Safe because these requests take an integer and the return value is
+            // checked.
+            if unsafe { ioctl_with_val(&tun, request, value) } < 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+
+        // SAFETY: Safe because the arguments are valid and the return value is checked.
+        let sock = unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0) };
+        if sock < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: Safe because the socket was just created and is owned by nothing else.
+        let sock = unsafe { OwnedFd::from_raw_fd(sock) };
+
+        if let Some(mac) = &self.mac {
+            let mut ifreq = IfReq::new(&self.name);
+            ifreq.set_hwaddr(mac);
+            // SAFETY: Safe because `ifreq` is a valid `struct ifreq` and the return value is
+            // checked.
+            if unsafe { ioctl_with_ref(&sock, SIOCSIFHWADDR, &ifreq) } < 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+
+        let mut ifreq = IfReq::new(&self.name);
+        // SAFETY: Safe because `ifreq` is a valid `struct ifreq` and the return value is checked.
+        if unsafe { ioctl_with_mut_ref(&sock, SIOCGIFFLAGS, &mut ifreq) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // IFF_UP fits in the `short` flags of `struct ifreq`.
+        #[allow(clippy::cast_possible_truncation)]
+        let iff_up = libc::IFF_UP as libc::c_short;
+        ifreq.set_flags(ifreq.flags() | iff_up);
+        // SAFETY: Safe because `ifreq` is a valid `struct ifreq` and the return value is checked.
+        if unsafe { ioctl_with_ref(&sock, SIOCSIFFLAGS, &ifreq) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ifreq_layout() {
+        assert_eq!(std::mem::size_of::<IfReq>(), 40);
+        let mut ifreq = IfReq::new("tap0");
+        assert_eq!(&ifreq.name[..5], b"tap0\0");
+        ifreq.set_flags(IFF_TAP | IFF_NO_PI);
+        assert_eq!(ifreq.flags(), IFF_TAP | IFF_NO_PI);
+    }
+
+    #[test]
+    fn test_parse_tap_config() {
+        assert_eq!(
+            TapConfig::parse("name=tap0").unwrap(),
+            TapConfig {
+                name: "tap0".to_string(),
+                mac: None,
+                queues: 1,
+            }
+        );
+        assert_eq!(
+            TapConfig::parse("name=tap1,mac=06:00:AC:10:00:02,queues=4").unwrap(),
+            TapConfig {
+                name: "tap1".to_string(),
+                mac: Some([0x06, 0x00, 0xac, 0x10, 0x00, 0x02]),
+                queues: 4,
+            }
+        );
+
+        for arg in [
+            "",
+            "tap0",
+            "mac=06:00:AC:10:00:02",
+            "name=",
+            "name=tap/0",
+            "name=a_very_long_tap_name",
+            "name=tap0,mac=06:00:AC:10:00",
+            "name=tap0,mac=06:00:AC:10:00:02:03",
+            "name=tap0,mac=06:00:AC:10:00:0x",
+            "name=tap0,queues=0",
+            "name=tap0,queues=17",
+            "name=tap0,mtu=1500",
+        ] {
+            assert!(
+                matches!(TapConfig::parse(arg), Err(JailerError::TapFormat(_))),
+                "{arg}"
+            );
+        }
+    }
+}
@@ -259,6 +259,24 @@ pub struct SeccompFilter {
 }
 
 impl SeccompCondition {
+    /// Creates a new `SeccompCondition`.
+    pub fn new(
+        arg_number: u8,
+        arg_len: SeccompCmpArgLen,
+        operator: SeccompCmpOp,
+        value: u64,
+    ) -> Result<Self, FilterError> {
+        let instance = Self {
+            arg_number,
+            arg_len,
+            operator,
+            value,
+            comment: None,
+        };
+
+        instance.validate().map(|_| Ok(instance))?
+    }
+
     /// Validates the SeccompCondition data
     pub fn validate(&self) -> Result<(), FilterError> {
         // Checks that the given argument number is valid.
@@ -867,26 +885,6 @@ mod tests {
         (syscall_number, rules)
     }
 
-    impl SeccompCondition {
-        /// Creates a new `SeccompCondition`.
-        pub fn new(
-            arg_number: u8,
-            arg_len: SeccompCmpArgLen,
-            operator: SeccompCmpOp,
-            value: u64,
-        ) -> Result<Self, FilterError> {
-            let instance = Self {
-                arg_number,
-                arg_len,
-                operator,
-                value,
-                comment: None,
-            };
-
-            instance.validate().map(|_| Ok(instance))?
-        }
-    }
-
     // The type of the `req` parameter is different for the `musl` library. This will enable
     // successful build for other non-musl libraries.
     #[cfg(target_env = "musl")]
@@ -8,7 +8,7 @@ use clap_num::maybe_hex;
 use vmm::arch::aarch64::regs::Aarch64RegisterVec;
 use vmm::persist::MicrovmState;
 
-use crate::utils::{open_vmstate, save_vmstate, UtilsError};
+use crate::utils::{open_labels, open_vmstate, save_vmstate, UtilsError};
 
 #[derive(Debug, thiserror::Error, displaydoc::Display)]
 pub enum EditVmStateError {
@@ -52,8 +52,9 @@ fn edit(
     f: impl Fn(MicrovmState) -> Result<MicrovmState, EditVmStateError>,
 ) -> Result<(), EditVmStateError> {
     let (microvm_state, version) = open_vmstate(vmstate_path)?;
+    let labels = open_labels(vmstate_path)?;
     let microvm_state = f(microvm_state)?;
-    save_vmstate(microvm_state, output_path, version)?;
+    save_vmstate(microvm_state, output_path, version, labels)?;
     Ok(())
 }
 
@@ -35,6 +35,12 @@ pub enum InfoVmStateSubCommand {
         #[arg(short, long)]
         vmstate_path: PathBuf,
     },
+    /// Print labels stored in the snapshot header.
+    Labels {
+        /// Path to the vmstate file.
+        #[arg(short, long)]
+        vmstate_path: PathBuf,
+    },
 }
 
 pub fn info_vmstate_command(command: InfoVmStateSubCommand) -> Result<(), InfoVmStateError> {
@@ -44,6 +50,7 @@ pub fn info_vmstate_command(command: InfoVmStateSubCommand) -> Result<(), InfoVm
             info(&vmstate_path, info_vcpu_states)?
         }
         InfoVmStateSubCommand::VmState { vmstate_path } => info(&vmstate_path, info_vmstate)?,
+        InfoVmStateSubCommand::Labels { vmstate_path } => info_labels(&vmstate_path)?,
     }
     Ok(())
 }
@@ -57,6 +64,13 @@ fn info(
     Ok(())
 }
 
+fn info_labels(vmstate_path: &PathBuf) -> Result<(), InfoVmStateError> {
+    for (key, value) in open_labels(vmstate_path)? {
+        println!("{key}={value}");
+    }
+    Ok(())
+}
+
 fn info_version(_: &MicrovmState, version: Version) -> Result<(), InfoVmStateError> {
     println!("v{version}");
     Ok(())
@@ -7,12 +7,14 @@ mod edit_memory;
 #[cfg(target_arch = "aarch64")]
 mod edit_vmstate;
 mod info;
+mod migrate_vmstate;
 mod utils;
 
 use edit_memory::{edit_memory_command, EditMemoryError, EditMemorySubCommand};
 #[cfg(target_arch = "aarch64")]
 use edit_vmstate::{edit_vmstate_command, EditVmStateError, EditVmStateSubCommand};
 use info::{info_vmstate_command, InfoVmStateError, InfoVmStateSubCommand};
+use migrate_vmstate::{migrate_vmstate_command, MigrateVmStateError, MigrateVmStateSubCommand};
 
 #[derive(Debug, thiserror::Error, displaydoc::Display)]
 enum SnapEditorError {
@@ -23,6 +25,8 @@ enum SnapEditorError {
     EditVmState(#[from] EditVmStateError),
     /// Error during getting info from a vmstate file: {0}
     InfoVmState(#[from] InfoVmStateError),
+    /// Error during migrating a vmstate file: {0}
+    MigrateVmState(#[from] MigrateVmStateError),
 }
 
 #[derive(Debug, Parser)]
@@ -41,6 +45,8 @@ enum Command {
     EditVmstate(EditVmStateSubCommand),
     #[command(subcommand)]
     InfoVmstate(InfoVmStateSubCommand),
+    #[command(subcommand)]
+    MigrateVmstate(MigrateVmStateSubCommand),
 }
 
 fn main_exec() -> Result<(), SnapEditorError> {
@@ -51,6 +57,7 @@ fn main_exec() -> Result<(), SnapEditorError> {
         #[cfg(target_arch = "aarch64")]
         Command::EditVmstate(command) => edit_vmstate_command(command)?,
         Command::InfoVmstate(command) => info_vmstate_command(command)?,
+        Command::MigrateVmstate(command) => migrate_vmstate_command(command)?,
     }
 
     Ok(())
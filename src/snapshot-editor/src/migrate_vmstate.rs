@@ -0,0 +1,105 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+use clap::Subcommand;
+use semver::Version;
+use vmm::persist::{SNAPSHOT_MIGRATIONS, SNAPSHOT_VERSION};
+use vmm::snapshot::migration::{migrate, migration_targets, MigrationError};
+use vmm::snapshot::Snapshot;
+use vmm::utils::u64_to_usize;
+
+use crate::utils::UtilsError;
+
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum MigrateVmStateError {
+    /// {0}
+    Utils(#[from] UtilsError),
+    /// Can not migrate snapshot: {0}
+    Migration(#[from] MigrationError),
+}
+
+#[derive(Debug, Subcommand)]
+pub enum MigrateVmStateSubCommand {
+    /// Rewrite the vmstate file in another version of the snapshot format.
+    To {
+        /// Path to the vmstate file.
+        #[arg(short, long)]
+        vmstate_path: PathBuf,
+        /// Path to the new vmstate file.
+        #[arg(short, long)]
+        output_path: PathBuf,
+        /// Version of the snapshot format to rewrite the vmstate file in. Defaults to the
+        /// version of the snapshot format of this snapshot-editor.
+        #[arg(short = 't', long)]
+        target_version: Option<Version>,
+    },
+    /// Print the versions of the snapshot format the vmstate file can be rewritten in.
+    Targets {
+        /// Path to the vmstate file.
+        #[arg(short, long)]
+        vmstate_path: PathBuf,
+    },
+}
+
+pub fn migrate_vmstate_command(
+    command: MigrateVmStateSubCommand,
+) -> Result<(), MigrateVmStateError> {
+    match command {
+        MigrateVmStateSubCommand::To {
+            vmstate_path,
+            output_path,
+            target_version,
+        } => migrate_to(
+            &vmstate_path,
+            &output_path,
+            &target_version.unwrap_or(SNAPSHOT_VERSION),
+        )?,
+        MigrateVmStateSubCommand::Targets { vmstate_path } => migrate_targets(&vmstate_path)?,
+    }
+    Ok(())
+}
+
+fn migrate_to(
+    vmstate_path: &PathBuf,
+    output_path: &PathBuf,
+    target_version: &Version,
+) -> Result<(), MigrateVmStateError> {
+    let mut snapshot_reader = File::open(vmstate_path).map_err(UtilsError::VmStateFileOpen)?;
+    let metadata = std::fs::metadata(vmstate_path).map_err(UtilsError::VmStateFileMeta)?;
+    let snapshot_len = u64_to_usize(metadata.len());
+    // The migration is done before creating the output file, so that it is not left truncated
+    // if the snapshot cannot be migrated.
+    let mut migrated = Vec::new();
+    let version = migrate(
+        SNAPSHOT_MIGRATIONS,
+        &mut snapshot_reader,
+        snapshot_len,
+        &mut migrated,
+        target_version,
+    )?;
+    let mut output_file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(output_path)
+        .map_err(UtilsError::OutputFileOpen)?;
+    output_file
+        .write_all(&migrated)
+        .map_err(UtilsError::OutputFileWrite)?;
+    println!("v{version} -> v{target_version}");
+    Ok(())
+}
+
+fn migrate_targets(vmstate_path: &PathBuf) -> Result<(), MigrateVmStateError> {
+    let mut snapshot_reader = File::open(vmstate_path).map_err(UtilsError::VmStateFileOpen)?;
+    let version =
+        Snapshot::get_format_version(&mut snapshot_reader).map_err(UtilsError::VmStateLoad)?;
+    for target in migration_targets(SNAPSHOT_MIGRATIONS, &version) {
+        println!("v{target}");
+    }
+    Ok(())
+}
@@ -6,7 +6,7 @@ use std::path::PathBuf;
 
 use semver::Version;
 use vmm::persist::MicrovmState;
-use vmm::snapshot::Snapshot;
+use vmm::snapshot::{Snapshot, SnapshotLabels};
 use vmm::utils::u64_to_usize;
 
 // Some errors are only used in aarch64 code
@@ -21,6 +21,8 @@ pub enum UtilsError {
     VmStateLoad(vmm::snapshot::SnapshotError),
     /// Can not open output file: {0}
     OutputFileOpen(std::io::Error),
+    /// Can not write output file: {0}
+    OutputFileWrite(std::io::Error),
     /// Can not save snapshot: {0}
     VmStateSave(vmm::snapshot::SnapshotError),
 }
@@ -33,12 +35,18 @@ pub fn open_vmstate(snapshot_path: &PathBuf) -> Result<(MicrovmState, Version),
     Snapshot::load(&mut snapshot_reader, snapshot_len).map_err(UtilsError::VmStateLoad)
 }
 
+pub fn open_labels(snapshot_path: &PathBuf) -> Result<SnapshotLabels, UtilsError> {
+    let mut snapshot_reader = File::open(snapshot_path).map_err(UtilsError::VmStateFileOpen)?;
+    Snapshot::get_labels(&mut snapshot_reader).map_err(UtilsError::VmStateLoad)
+}
+
 // This method is used only in aarch64 code so far
 #[allow(unused)]
 pub fn save_vmstate(
     microvm_state: MicrovmState,
     output_path: &PathBuf,
     version: Version,
+    labels: SnapshotLabels,
 ) -> Result<(), UtilsError> {
     let mut output_file = OpenOptions::new()
         .create(true)
@@ -46,7 +54,7 @@ pub fn save_vmstate(
         .truncate(true)
         .open(output_path)
         .map_err(UtilsError::OutputFileOpen)?;
-    let mut snapshot = Snapshot::new(version);
+    let mut snapshot = Snapshot::new(version).with_labels(labels);
     snapshot
         .save(&mut output_file, &microvm_state)
         .map_err(UtilsError::VmStateSave)?;
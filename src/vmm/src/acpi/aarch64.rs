@@ -0,0 +1,228 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::mem::size_of;
+
+use acpi_tables::fadt::{ARM_BOOT_ARCH_FLAGS_PSCI_COMPLIANT, ARM_BOOT_ARCH_FLAGS_PSCI_USE_HVC};
+use acpi_tables::gtdt::{GtdtTimer, GTDT_F_ALWAYS_ON};
+use acpi_tables::madt::{GicIts, Gicc, Gicd, Gicr};
+use acpi_tables::pptt::{
+    ProcessorHierarchyNode, PPTT_F_ACPI_PROCESSOR_ID_VALID, PPTT_F_LEAF, PPTT_F_PHYSICAL_PACKAGE,
+    PPTT_NODES_OFFSET,
+};
+use acpi_tables::spcr::{SPCR_INTERFACE_TYPE_16550, SPCR_INTERRUPT_TYPE_ARM_GIC};
+use acpi_tables::srat::GiccAffinity;
+use acpi_tables::{Fadt, GenericAddressStructure, Gtdt, Pptt, Spcr};
+use zerocopy::IntoBytes;
+
+use super::{AcpiError, AcpiTableWriter, OEM_ID, OEM_REVISION};
+use crate::arch::aarch64::gic::GICDevice;
+use crate::arch::aarch64::layout::{PMU_PPI, PPI_BASE, SPI_BASE};
+use crate::arch::DeviceType;
+use crate::device_manager::mmio::MMIODeviceManager;
+use crate::Vcpu;
+
+// PPIs of the generic timers, as described in the FDT: secure physical, non-secure physical,
+// virtual and hypervisor timers.
+const SECURE_EL1_TIMER_PPI: u32 = 13;
+const NON_SECURE_EL1_TIMER_PPI: u32 = 14;
+const VIRTUAL_EL1_TIMER_PPI: u32 = 11;
+const EL2_TIMER_PPI: u32 = 10;
+
+/// Returns the GSIV, i.e. the GIC interrupt id, of a device interrupt line.
+pub(crate) const fn gsiv(gsi: u32) -> u32 {
+    SPI_BASE + gsi
+}
+
+#[inline(always)]
+pub(crate) fn setup_interrupt_controllers(
+    gic_device: &GICDevice,
+    vcpus: &[Vcpu],
+    pmu: bool,
+) -> Vec<u8> {
+    let [dist_addr, _, cpu_or_redists_addr, cpu_or_redists_size] = gic_device.device_properties()
+    else {
+        unreachable!("GIC devices have 4 properties");
+    };
+    let maint_irq = PPI_BASE + gic_device.fdt_maint_irq();
+    let pmu_gsiv = if pmu { PPI_BASE + PMU_PPI } else { 0 };
+    // The CPU interface of a GICv2 is described in the GICC structures, while the redistributors
+    // of a GICv3 have their own structure.
+    let (gicc_base, gic_version) = match gic_device {
+        GICDevice::V2(_) => (*cpu_or_redists_addr, 2),
+        GICDevice::V3(_) => (0, 3),
+    };
+
+    let mut ic = Vec::with_capacity(
+        vcpus.len() * size_of::<Gicc>()
+            + size_of::<Gicd>()
+            + size_of::<Gicr>()
+            + size_of::<GicIts>(),
+    );
+    for (cpu_id, vcpu) in (0u32..).zip(vcpus) {
+        ic.extend_from_slice(
+            Gicc::new(
+                cpu_id,
+                vcpu.kvm_vcpu.get_mpidr(),
+                gicc_base,
+                maint_irq,
+                pmu_gsiv,
+            )
+            .as_bytes(),
+        );
+    }
+    ic.extend_from_slice(Gicd::new(*dist_addr, gic_version).as_bytes());
+    if let GICDevice::V3(_) = gic_device {
+        ic.extend_from_slice(
+            Gicr::new(
+                *cpu_or_redists_addr,
+                (*cpu_or_redists_size).try_into().unwrap(),
+            )
+            .as_bytes(),
+        );
+    }
+    if let Some((_, [its_addr, _])) = gic_device.fdt_its() {
+        ic.extend_from_slice(GicIts::new(0, *its_addr).as_bytes());
+    }
+    ic
+}
+
+/// Returns the SRAT structures placing all vCPUs in the first NUMA node.
+#[inline(always)]
+pub(crate) fn setup_processor_affinities(nr_vcpus: u32) -> Vec<u8> {
+    let mut affinities = Vec::with_capacity(nr_vcpus as usize * size_of::<GiccAffinity>());
+    for cpu_id in 0..nr_vcpus {
+        affinities.extend_from_slice(GiccAffinity::new(cpu_id, 0).as_bytes());
+    }
+    affinities
+}
+
+#[inline(always)]
+pub(crate) fn setup_arch_fadt(fadt: &mut Fadt) {
+    // Let the guest kernel know that PSCI is available through the HVC conduit, as also described
+    // in the FDT.
+    fadt.setup_arm_flags(
+        1 << ARM_BOOT_ARCH_FLAGS_PSCI_COMPLIANT | 1 << ARM_BOOT_ARCH_FLAGS_PSCI_USE_HVC,
+    );
+}
+
+/// The MADT of GIC-based systems has no local interrupt controller address.
+pub(crate) const fn apic_addr() -> u32 {
+    0
+}
+
+impl AcpiTableWriter<'_> {
+    /// Build the GTDT table for the guest
+    ///
+    /// This includes the interrupts of the generic timers
+    pub(crate) fn build_gtdt(&mut self) -> Result<u64, AcpiError> {
+        let timer = |ppi| GtdtTimer {
+            gsiv: PPI_BASE + ppi,
+            flags: 1 << GTDT_F_ALWAYS_ON,
+        };
+        let mut gtdt = Gtdt::new(
+            OEM_ID,
+            *b"FCVMGTDT",
+            OEM_REVISION,
+            timer(SECURE_EL1_TIMER_PPI),
+            timer(NON_SECURE_EL1_TIMER_PPI),
+            timer(VIRTUAL_EL1_TIMER_PPI),
+            timer(EL2_TIMER_PPI),
+        );
+        self.write_acpi_table(&mut gtdt)
+    }
+
+    /// Build the SPCR table for the guest, if it has a serial console
+    ///
+    /// This points the guest to the 16550 UART to use as its console
+    pub(crate) fn build_spcr(
+        &mut self,
+        mmio_device_manager: &MMIODeviceManager,
+    ) -> Result<Option<u64>, AcpiError> {
+        let Some(serial) = mmio_device_manager
+            .id_to_dev_info
+            .get(&(DeviceType::Serial, DeviceType::Serial.to_string()))
+        else {
+            return Ok(None);
+        };
+
+        // The UART registers are 1 byte wide, in the system memory address space.
+        let base_address = GenericAddressStructure::new(0, 8, 0, 1, serial.addr);
+        let mut spcr = Spcr::new(
+            OEM_ID,
+            *b"FCVMSPCR",
+            OEM_REVISION,
+            SPCR_INTERFACE_TYPE_16550,
+            base_address,
+            SPCR_INTERRUPT_TYPE_ARM_GIC,
+            gsiv(serial.irqs[0]),
+        );
+        self.write_acpi_table(&mut spcr).map(Some)
+    }
+
+    /// Build the PPTT table for the guest
+    ///
+    /// All vCPUs are described as cores of a single physical package
+    pub(crate) fn build_pptt(&mut self, nr_vcpus: u32) -> Result<u64, AcpiError> {
+        let mut nodes =
+            Vec::with_capacity((nr_vcpus as usize + 1) * size_of::<ProcessorHierarchyNode>());
+        nodes.extend_from_slice(
+            ProcessorHierarchyNode::new(1 << PPTT_F_PHYSICAL_PACKAGE, 0, 0).as_bytes(),
+        );
+        let package_offset = u32::try_from(PPTT_NODES_OFFSET).unwrap();
+        for cpu_id in 0..nr_vcpus {
+            nodes.extend_from_slice(
+                ProcessorHierarchyNode::new(
+                    1 << PPTT_F_ACPI_PROCESSOR_ID_VALID | 1 << PPTT_F_LEAF,
+                    package_offset,
+                    cpu_id,
+                )
+                .as_bytes(),
+            );
+        }
+
+        let mut pptt = Pptt::new(OEM_ID, *b"FCVMPPTT", OEM_REVISION, nodes);
+        self.write_acpi_table(&mut pptt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use vm_memory::{Bytes, GuestAddress};
+
+    use crate::acpi::AcpiTableWriter;
+    use crate::builder::tests::default_vmm;
+
+    #[test]
+    fn test_build_arch_tables() {
+        let mut vmm = default_vmm();
+        let mut writer = AcpiTableWriter {
+            mem: &vmm.guest_memory,
+            resource_allocator: &mut vmm.resource_allocator,
+        };
+
+        let read_header = |addr: u64| {
+            let mut header = [0u8; 8];
+            vmm.guest_memory
+                .read_slice(&mut header, GuestAddress(addr))
+                .unwrap();
+            (
+                header[..4].to_vec(),
+                u32::from_le_bytes(header[4..].try_into().unwrap()),
+            )
+        };
+
+        let gtdt_addr = writer.build_gtdt().unwrap();
+        assert_eq!(read_header(gtdt_addr), (b"GTDT".to_vec(), 104));
+
+        // One package node and one node per vCPU.
+        let pptt_addr = writer.build_pptt(2).unwrap();
+        assert_eq!(read_header(pptt_addr), (b"PPTT".to_vec(), 36 + 3 * 20));
+
+        // There is no serial console, so no SPCR either.
+        assert!(writer
+            .build_spcr(&vmm.mmio_device_manager)
+            .unwrap()
+            .is_none());
+    }
+}
@@ -2,19 +2,41 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use acpi_tables::fadt::{FADT_F_HW_REDUCED_ACPI, FADT_F_PWR_BUTTON, FADT_F_SLP_BUTTON};
-use acpi_tables::{aml, Aml, Dsdt, Fadt, Madt, Rsdp, Sdt, Xsdt};
+use acpi_tables::srat::MemoryAffinity;
+use acpi_tables::tpm2::TPM2_START_METHOD_CRB;
+use acpi_tables::{aml, Aml, Dsdt, Fadt, Madt, Rsdp, Sdt, Srat, Tpm2, Xsdt};
 use log::{debug, error};
 use vm_allocator::AllocPolicy;
+use zerocopy::IntoBytes;
 
+#[cfg(target_arch = "aarch64")]
+pub(crate) use crate::acpi::aarch64::gsiv;
+#[cfg(target_arch = "aarch64")]
+use crate::acpi::aarch64::{
+    apic_addr, setup_arch_fadt, setup_interrupt_controllers, setup_processor_affinities,
+};
+#[cfg(target_arch = "x86_64")]
+pub(crate) use crate::acpi::x86_64::gsiv;
+#[cfg(target_arch = "x86_64")]
 use crate::acpi::x86_64::{
-    apic_addr, rsdp_addr, setup_arch_dsdt, setup_arch_fadt, setup_interrupt_controllers,
+    apic_addr, rsdp_addr, setup_arch_fadt, setup_interrupt_controllers, setup_processor_affinities,
 };
+#[cfg(target_arch = "aarch64")]
+use crate::arch::aarch64::gic::GICDevice;
+use crate::arch::DeviceType;
 use crate::device_manager::acpi::ACPIDeviceManager;
+#[cfg(target_arch = "x86_64")]
+use crate::device_manager::legacy::PortIODeviceManager;
 use crate::device_manager::mmio::MMIODeviceManager;
 use crate::device_manager::resources::ResourceAllocator;
-use crate::vstate::memory::{GuestAddress, GuestMemoryMmap};
+use crate::devices::tpm::TPM_CRB_CONTROL_AREA_OFFSET;
+use crate::utils::usize_to_u64;
+use crate::vstate::memory::{Address, GuestAddress, GuestMemoryMmap};
 use crate::Vcpu;
 
+#[cfg(target_arch = "aarch64")]
+mod aarch64;
+#[cfg(target_arch = "x86_64")]
 mod x86_64;
 
 // Our (Original Equipment Manufacturer" (OEM) name. OEM is how ACPI names the manufacturer of the
@@ -81,6 +103,7 @@ impl AcpiTableWriter<'_> {
         &mut self,
         mmio_device_manager: &MMIODeviceManager,
         acpi_device_manager: &ACPIDeviceManager,
+        #[cfg(target_arch = "x86_64")] pio_device_manager: &PortIODeviceManager,
     ) -> Result<u64, AcpiError> {
         let mut dsdt_data = Vec::new();
 
@@ -90,8 +113,10 @@ impl AcpiTableWriter<'_> {
         // Add GED and VMGenID AML data.
         acpi_device_manager.append_aml_bytes(&mut dsdt_data)?;
 
-        // Architecture specific DSDT data
-        setup_arch_dsdt(&mut dsdt_data)?;
+        // Legacy devices DSDT data. On aarch64, the legacy devices are MMIO devices, so their AML
+        // is part of the MMIO devices'.
+        #[cfg(target_arch = "x86_64")]
+        pio_device_manager.append_aml_bytes(&mut dsdt_data)?;
 
         let mut dsdt = Dsdt::new(OEM_ID, *b"FCVMDSDT", OEM_REVISION, dsdt_data);
         self.write_acpi_table(&mut dsdt)
@@ -114,36 +139,82 @@ impl AcpiTableWriter<'_> {
     /// Build the MADT table for the guest
     ///
     /// This includes information about the interrupt controllers supported in the platform
-    fn build_madt(&mut self, nr_vcpus: u8) -> Result<u64, AcpiError> {
+    fn build_madt(&mut self, interrupt_controllers: Vec<u8>) -> Result<u64, AcpiError> {
         let mut madt = Madt::new(
             OEM_ID,
             *b"FCVMMADT",
             OEM_REVISION,
             apic_addr(),
-            setup_interrupt_controllers(nr_vcpus),
+            interrupt_controllers,
         );
         self.write_acpi_table(&mut madt)
     }
 
-    /// Build the XSDT table for the guest
+    /// Build the TPM2 table for the guest, if it has a TPM device
     ///
-    /// Currently, we pass to the guest just FADT and MADT tables.
-    fn build_xsdt(&mut self, fadt_addr: u64, madt_addr: u64) -> Result<u64, AcpiError> {
-        let mut xsdt = Xsdt::new(
+    /// This points the guest to the control area of the TPM CRB interface
+    fn build_tpm2(
+        &mut self,
+        mmio_device_manager: &MMIODeviceManager,
+    ) -> Result<Option<u64>, AcpiError> {
+        let Some(tpm) = mmio_device_manager
+            .id_to_dev_info
+            .get(&(DeviceType::Tpm, DeviceType::Tpm.to_string()))
+        else {
+            return Ok(None);
+        };
+
+        let mut tpm2 = Tpm2::new(
             OEM_ID,
-            *b"FCMVXSDT",
+            *b"FCVMTPM2",
             OEM_REVISION,
-            vec![fadt_addr, madt_addr],
+            tpm.addr + TPM_CRB_CONTROL_AREA_OFFSET,
+            TPM2_START_METHOD_CRB,
         );
+        self.write_acpi_table(&mut tpm2).map(Some)
+    }
+
+    /// Build the SRAT table for the guest, if its memory is split in several NUMA nodes
+    ///
+    /// All vCPUs belong to the first node, along with the regions of `memory_nodes[0]`, while the
+    /// regions of every other entry of `memory_nodes` make up a node without processors.
+    fn build_srat(
+        &mut self,
+        nr_vcpus: u32,
+        memory_nodes: &[Vec<(GuestAddress, usize)>],
+    ) -> Result<Option<u64>, AcpiError> {
+        if memory_nodes.len() < 2 {
+            return Ok(None);
+        }
+
+        let mut affinities = setup_processor_affinities(nr_vcpus);
+        for (node, regions) in (0u32..).zip(memory_nodes) {
+            for (start, size) in regions {
+                affinities.extend_from_slice(
+                    MemoryAffinity::new(node, start.raw_value(), usize_to_u64(*size), 0).as_bytes(),
+                );
+            }
+        }
+
+        let mut srat = Srat::new(OEM_ID, *b"FCVMSRAT", OEM_REVISION, affinities);
+        self.write_acpi_table(&mut srat).map(Some)
+    }
+
+    /// Build the XSDT table for the guest
+    ///
+    /// This points to the FADT and MADT tables, as well as to the architecture specific ones.
+    fn build_xsdt(&mut self, tables: Vec<u64>) -> Result<u64, AcpiError> {
+        let mut xsdt = Xsdt::new(OEM_ID, *b"FCMVXSDT", OEM_REVISION, tables);
         self.write_acpi_table(&mut xsdt)
     }
 
     /// Build the RSDP pointer for the guest.
     ///
     /// This will build the RSDP pointer which points to the XSDT table and write it in guest
-    /// memory. The address in which we write RSDP is pre-determined for every architecture.
-    /// We will not allocate arbitrary memory for it
-    fn build_rsdp(&mut self, xsdt_addr: u64) -> Result<(), AcpiError> {
+    /// memory. On x86_64, the address in which we write RSDP is pre-determined and we will not
+    /// allocate arbitrary memory for it.
+    #[cfg(target_arch = "x86_64")]
+    fn build_rsdp(&mut self, xsdt_addr: u64) -> Result<GuestAddress, AcpiError> {
         let mut rsdp = Rsdp::new(OEM_ID, xsdt_addr);
         rsdp.write_to_guest(self.mem, rsdp_addr())
             .inspect_err(|err| error!("acpi: Could not write RSDP in guest memory: {err}"))?;
@@ -153,41 +224,79 @@ impl AcpiTableWriter<'_> {
             rsdp.len(),
             rsdp_addr().0
         );
-        Ok(())
+        Ok(rsdp_addr())
+    }
+
+    /// Build the RSDP pointer for the guest.
+    ///
+    /// This will build the RSDP pointer which points to the XSDT table and write it in guest
+    /// memory. On aarch64, there is no pre-determined address for it: it is allocated like the
+    /// other tables and its address is passed to the guest through the FDT.
+    #[cfg(target_arch = "aarch64")]
+    fn build_rsdp(&mut self, xsdt_addr: u64) -> Result<GuestAddress, AcpiError> {
+        let mut rsdp = Rsdp::new(OEM_ID, xsdt_addr);
+        self.write_acpi_table(&mut rsdp).map(GuestAddress)
     }
 }
 
 /// Create ACPI tables for the guest
 ///
 /// This will create the ACPI tables needed to describe to the guest OS the available hardware,
-/// such as interrupt controllers, vCPUs and VirtIO devices.
+/// such as interrupt controllers, vCPUs and VirtIO devices. It returns the address of the RSDP.
+/// `memory_nodes` holds the guest memory regions of each NUMA node, and is only described to the
+/// guest if there is more than one node.
 pub(crate) fn create_acpi_tables(
     mem: &GuestMemoryMmap,
     resource_allocator: &mut ResourceAllocator,
     mmio_device_manager: &MMIODeviceManager,
     acpi_device_manager: &ACPIDeviceManager,
+    #[cfg(target_arch = "x86_64")] pio_device_manager: &PortIODeviceManager,
     vcpus: &[Vcpu],
-) -> Result<(), AcpiError> {
+    memory_nodes: &[Vec<(GuestAddress, usize)>],
+    #[cfg(target_arch = "aarch64")] gic_device: &GICDevice,
+    #[cfg(target_arch = "aarch64")] pmu: bool,
+) -> Result<GuestAddress, AcpiError> {
     let mut writer = AcpiTableWriter {
         mem,
         resource_allocator,
     };
 
-    let dsdt_addr = writer.build_dsdt(mmio_device_manager, acpi_device_manager)?;
+    let dsdt_addr = writer.build_dsdt(
+        mmio_device_manager,
+        acpi_device_manager,
+        #[cfg(target_arch = "x86_64")]
+        pio_device_manager,
+    )?;
     let fadt_addr = writer.build_fadt(dsdt_addr)?;
-    let madt_addr = writer.build_madt(vcpus.len().try_into().unwrap())?;
-    let xsdt_addr = writer.build_xsdt(fadt_addr, madt_addr)?;
+    #[cfg(target_arch = "x86_64")]
+    let interrupt_controllers = setup_interrupt_controllers(vcpus.len().try_into().unwrap());
+    #[cfg(target_arch = "aarch64")]
+    let interrupt_controllers = setup_interrupt_controllers(gic_device, vcpus, pmu);
+    let madt_addr = writer.build_madt(interrupt_controllers)?;
+    let mut tables = vec![fadt_addr, madt_addr];
+    tables.extend(writer.build_tpm2(mmio_device_manager)?);
+    tables.extend(writer.build_srat(vcpus.len().try_into().unwrap(), memory_nodes)?);
+    #[cfg(target_arch = "aarch64")]
+    {
+        tables.push(writer.build_gtdt()?);
+        tables.push(writer.build_pptt(vcpus.len().try_into().unwrap())?);
+        tables.extend(writer.build_spcr(mmio_device_manager)?);
+    }
+    let xsdt_addr = writer.build_xsdt(tables)?;
     writer.build_rsdp(xsdt_addr)
 }
 
 #[cfg(test)]
+#[cfg(target_arch = "x86_64")]
 mod tests {
     use acpi_tables::Sdt;
-    use vm_memory::Bytes;
+    use vm_memory::{Bytes, GuestAddress};
 
     use crate::acpi::{AcpiError, AcpiTableWriter};
     use crate::arch::x86_64::layout::{SYSTEM_MEM_SIZE, SYSTEM_MEM_START};
+    use crate::arch::DeviceType;
     use crate::builder::tests::default_vmm;
+    use crate::device_manager::mmio::{MMIODeviceInfo, MMIO_LEN};
     use crate::test_utils::arch_mem;
 
     struct MockSdt(Vec<u8>);
@@ -290,4 +399,44 @@ mod tests {
             err
         );
     }
+
+    #[test]
+    fn test_build_tpm2() {
+        let mut vmm = default_vmm();
+        let mut writer = AcpiTableWriter {
+            mem: &vmm.guest_memory,
+            resource_allocator: &mut vmm.resource_allocator,
+        };
+
+        // There is no TPM device, so no TPM2 table either.
+        assert!(writer
+            .build_tpm2(&vmm.mmio_device_manager)
+            .unwrap()
+            .is_none());
+
+        vmm.mmio_device_manager.id_to_dev_info.insert(
+            (DeviceType::Tpm, DeviceType::Tpm.to_string()),
+            MMIODeviceInfo {
+                addr: 0xd000_0000,
+                len: MMIO_LEN,
+                irqs: vec![],
+            },
+        );
+        let addr = writer
+            .build_tpm2(&vmm.mmio_device_manager)
+            .unwrap()
+            .unwrap();
+        let mut table = [0u8; 64];
+        vmm.guest_memory
+            .read_slice(&mut table, GuestAddress(addr))
+            .unwrap();
+        assert_eq!(&table[..4], b"TPM2");
+        assert_eq!(u32::from_le_bytes(table[4..8].try_into().unwrap()), 64);
+        // Control area address and start method.
+        assert_eq!(
+            u64::from_le_bytes(table[40..48].try_into().unwrap()),
+            0xd000_0040
+        );
+        assert_eq!(u32::from_le_bytes(table[48..52].try_into().unwrap()), 7);
+    }
 }
@@ -8,12 +8,17 @@ use acpi_tables::fadt::{
     IAPC_BOOT_ARG_FLAGS_VGA_NOT_PRESENT,
 };
 use acpi_tables::madt::{IoAPIC, LocalAPIC};
-use acpi_tables::{aml, Fadt};
+use acpi_tables::srat::LocalApicAffinity;
+use acpi_tables::Fadt;
 use vm_memory::GuestAddress;
 use zerocopy::IntoBytes;
 
 use crate::arch::x86_64::layout;
-use crate::device_manager::legacy::PortIODeviceManager;
+
+/// Returns the GSIV of a device interrupt line, which is the line itself with an IOAPIC.
+pub(crate) const fn gsiv(gsi: u32) -> u32 {
+    gsi
+}
 
 #[inline(always)]
 pub(crate) fn setup_interrupt_controllers(nr_vcpus: u8) -> Vec<u8> {
@@ -27,6 +32,17 @@ pub(crate) fn setup_interrupt_controllers(nr_vcpus: u8) -> Vec<u8> {
     ic
 }
 
+/// Returns the SRAT structures placing all vCPUs in the first NUMA node.
+#[inline(always)]
+pub(crate) fn setup_processor_affinities(nr_vcpus: u32) -> Vec<u8> {
+    let mut affinities = Vec::with_capacity(nr_vcpus as usize * size_of::<LocalApicAffinity>());
+    for apic_id in 0..nr_vcpus {
+        affinities
+            .extend_from_slice(LocalApicAffinity::new(apic_id.try_into().unwrap(), 0).as_bytes());
+    }
+    affinities
+}
+
 #[inline(always)]
 pub(crate) fn setup_arch_fadt(fadt: &mut Fadt) {
     // Let the guest kernel know that there is not VGA hardware present
@@ -40,11 +56,6 @@ pub(crate) fn setup_arch_fadt(fadt: &mut Fadt) {
     );
 }
 
-#[inline(always)]
-pub(crate) fn setup_arch_dsdt(dsdt_data: &mut Vec<u8>) -> Result<(), aml::AmlError> {
-    PortIODeviceManager::append_aml_bytes(dsdt_data)
-}
-
 pub(crate) const fn apic_addr() -> u32 {
     layout::APIC_ADDR
 }
@@ -15,13 +15,18 @@ use vm_memory::GuestMemoryError;
 use super::super::{DeviceType, InitrdConfig};
 use super::cache_info::{read_cache_config, CacheEntry};
 use super::gic::GICDevice;
+use super::layout::PMU_PPI;
 use crate::devices::acpi::vmgenid::{VmGenId, VMGENID_MEM_SIZE};
-use crate::vstate::memory::{Address, GuestMemory, GuestMemoryMmap};
+use crate::utils::usize_to_u64;
+use crate::vmm_config::machine_config::ReservedMemoryRegion;
+use crate::vstate::memory::{Address, GuestAddress, GuestMemory, GuestMemoryMmap};
 
 // This is a value for uniquely identifying the FDT node declaring the interrupt controller.
 const GIC_PHANDLE: u32 = 1;
 // This is a value for uniquely identifying the FDT node containing the clock definition.
 const CLOCK_PHANDLE: u32 = 2;
+// This is a value for uniquely identifying the FDT node declaring the MSI controller (GICv3 ITS).
+const MSI_PHANDLE: u32 = 3;
 // You may be wondering why this big value?
 // This phandle is used to uniquely identify the FDT nodes containing cache information. Each cpu
 // can have a variable number of caches, some of these caches may be shared with other cpus.
@@ -64,6 +69,7 @@ pub enum FdtError {
 }
 
 /// Creates the flattened device tree for this aarch64 microVM.
+#[allow(clippy::too_many_arguments)]
 pub fn create_fdt<T: DeviceInfoForFDT + Clone + Debug>(
     guest_mem: &GuestMemoryMmap,
     vcpu_mpidr: Vec<u64>,
@@ -72,6 +78,10 @@ pub fn create_fdt<T: DeviceInfoForFDT + Clone + Debug>(
     gic_device: &GICDevice,
     vmgenid: &Option<VmGenId>,
     initrd: &Option<InitrdConfig>,
+    pmu: bool,
+    acpi_rsdp: Option<GuestAddress>,
+    reserved_memory: &[ReservedMemoryRegion],
+    memory_nodes: &[Vec<(GuestAddress, usize)>],
 ) -> Result<Vec<u8>, FdtError> {
     // Allocate stuff necessary for storing the blob.
     let mut fdt_writer = FdtWriter::new()?;
@@ -90,11 +100,15 @@ pub fn create_fdt<T: DeviceInfoForFDT + Clone + Debug>(
     // This is not mandatory but we use it to point the root node to the node
     // containing description of the interrupt controller for this VM.
     fdt_writer.property_u32("interrupt-parent", GIC_PHANDLE)?;
-    create_cpu_nodes(&mut fdt_writer, &vcpu_mpidr)?;
-    create_memory_node(&mut fdt_writer, guest_mem)?;
-    create_chosen_node(&mut fdt_writer, cmdline, initrd)?;
+    create_cpu_nodes(&mut fdt_writer, &vcpu_mpidr, memory_nodes.len() > 1)?;
+    create_memory_node(&mut fdt_writer, guest_mem, memory_nodes)?;
+    create_reserved_memory_node(&mut fdt_writer, reserved_memory)?;
+    create_chosen_node(&mut fdt_writer, cmdline, initrd, acpi_rsdp)?;
     create_gic_node(&mut fdt_writer, gic_device)?;
     create_timer_node(&mut fdt_writer)?;
+    if pmu {
+        create_pmu_node(&mut fdt_writer)?;
+    }
     create_clock_node(&mut fdt_writer)?;
     create_psci_node(&mut fdt_writer)?;
     create_devices_node(&mut fdt_writer, device_info)?;
@@ -109,7 +123,7 @@ pub fn create_fdt<T: DeviceInfoForFDT + Clone + Debug>(
 }
 
 // Following are the auxiliary function for creating the different nodes that we append to our FDT.
-fn create_cpu_nodes(fdt: &mut FdtWriter, vcpu_mpidr: &[u64]) -> Result<(), FdtError> {
+fn create_cpu_nodes(fdt: &mut FdtWriter, vcpu_mpidr: &[u64], numa: bool) -> Result<(), FdtError> {
     // Since the L1 caches are not shareable among CPUs and they are direct attributes of the
     // cpu in the device tree, we process the L1 and non-L1 caches separately.
     // We use sysfs for extracting the cache information.
@@ -135,6 +149,10 @@ fn create_cpu_nodes(fdt: &mut FdtWriter, vcpu_mpidr: &[u64]) -> Result<(), FdtEr
         // Set the field to first 24 bits of the MPIDR - Multiprocessor Affinity Register.
         // See http://infocenter.arm.com/help/index.jsp?topic=/com.arm.doc.ddi0488c/BABHBJCI.html.
         fdt.property_u64("reg", mpidr & 0x7FFFFF)?;
+        // All vCPUs belong to the first NUMA node, along with the DRAM.
+        if numa {
+            fdt.property_u32("numa-node-id", 0)?;
+        }
 
         for cache in l1_caches.iter() {
             // Please check out
@@ -216,7 +234,11 @@ fn create_cpu_nodes(fdt: &mut FdtWriter, vcpu_mpidr: &[u64]) -> Result<(), FdtEr
     Ok(())
 }
 
-fn create_memory_node(fdt: &mut FdtWriter, guest_mem: &GuestMemoryMmap) -> Result<(), FdtError> {
+fn create_memory_node(
+    fdt: &mut FdtWriter,
+    guest_mem: &GuestMemoryMmap,
+    memory_nodes: &[Vec<(GuestAddress, usize)>],
+) -> Result<(), FdtError> {
     // See https://github.com/torvalds/linux/blob/master/Documentation/devicetree/booting-without-of.txt#L960
     // for an explanation of this.
 
@@ -228,10 +250,14 @@ fn create_memory_node(fdt: &mut FdtWriter, guest_mem: &GuestMemoryMmap) -> Resul
     // The reason we do this is that Linux does not allow remapping system memory. However, without
     // remap, kernel drivers cannot get virtual addresses to read data from device memory. Leaving
     // this memory region out allows Linux kernel modules to remap and thus read this region.
-    let mem_size = guest_mem.last_addr().raw_value()
-        - super::layout::DRAM_MEM_START
-        - super::layout::SYSTEM_MEM_SIZE
-        + 1;
+    //
+    // When the memory is split in several NUMA nodes, the DRAM stops where the first memory tier
+    // starts, and each tier is described by a separate memory node.
+    let last_addr = match memory_nodes.get(1).and_then(|regions| regions.first()) {
+        Some((tier_start, _)) => tier_start.raw_value() - 1,
+        None => guest_mem.last_addr().raw_value(),
+    };
+    let mem_size = last_addr - super::layout::DRAM_MEM_START - super::layout::SYSTEM_MEM_SIZE + 1;
     let mem_reg_prop = &[
         super::layout::DRAM_MEM_START + super::layout::SYSTEM_MEM_SIZE,
         mem_size,
@@ -239,8 +265,48 @@ fn create_memory_node(fdt: &mut FdtWriter, guest_mem: &GuestMemoryMmap) -> Resul
     let mem = fdt.begin_node("memory@ram")?;
     fdt.property_string("device_type", "memory")?;
     fdt.property_array_u64("reg", mem_reg_prop)?;
+    if memory_nodes.len() > 1 {
+        fdt.property_u32("numa-node-id", 0)?;
+    }
     fdt.end_node(mem)?;
 
+    // See https://github.com/torvalds/linux/blob/master/Documentation/devicetree/bindings/numa.txt
+    for (node_id, regions) in (0u32..).zip(memory_nodes).skip(1) {
+        for (start, size) in regions {
+            let mem = fdt.begin_node(&format!("memory@{:x}", start.raw_value()))?;
+            fdt.property_string("device_type", "memory")?;
+            fdt.property_array_u64("reg", &[start.raw_value(), usize_to_u64(*size)])?;
+            fdt.property_u32("numa-node-id", node_id)?;
+            fdt.end_node(mem)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn create_reserved_memory_node(
+    fdt: &mut FdtWriter,
+    reserved_memory: &[ReservedMemoryRegion],
+) -> Result<(), FdtError> {
+    if reserved_memory.is_empty() {
+        return Ok(());
+    }
+
+    // See https://github.com/torvalds/linux/blob/master/Documentation/devicetree/bindings/reserved-memory/reserved-memory.yaml
+    // The regions are part of the memory node, and `no-map` keeps the kernel from mapping them
+    // so that drivers can remap them as device memory, looking them up by name.
+    let reserved_mem = fdt.begin_node("reserved-memory")?;
+    fdt.property_u32("#address-cells", ADDRESS_CELLS)?;
+    fdt.property_u32("#size-cells", SIZE_CELLS)?;
+    fdt.property_null("ranges")?;
+    for region in reserved_memory {
+        let node = fdt.begin_node(&format!("{}@{:x}", region.name, region.guest_addr))?;
+        fdt.property_array_u64("reg", &[region.guest_addr, region.size])?;
+        fdt.property_null("no-map")?;
+        fdt.end_node(node)?;
+    }
+    fdt.end_node(reserved_mem)?;
+
     Ok(())
 }
 
@@ -248,6 +314,7 @@ fn create_chosen_node(
     fdt: &mut FdtWriter,
     cmdline: CString,
     initrd: &Option<InitrdConfig>,
+    acpi_rsdp: Option<GuestAddress>,
 ) -> Result<(), FdtError> {
     let chosen = fdt.begin_node("chosen")?;
     // Workaround to be able to reuse an existing property_*() method; in property_string() method,
@@ -265,6 +332,12 @@ fn create_chosen_node(
         )?;
     }
 
+    // There is no standard binding for locating the ACPI tables without UEFI, so the RSDP is
+    // advertised through a vendor property, for firmware to install the tables.
+    if let Some(rsdp) = acpi_rsdp {
+        fdt.property_u64("firecracker,acpi-rsdp", rsdp.raw_value())?;
+    }
+
     fdt.end_node(chosen)?;
 
     Ok(())
@@ -305,6 +378,17 @@ fn create_gic_node(fdt: &mut FdtWriter, gic_device: &GICDevice) -> Result<(), Fd
     ];
 
     fdt.property_array_u32("interrupts", &gic_intr)?;
+
+    // The ITS translates MSIs into LPIs, it is only available on GICv3.
+    if let Some((its_compatibility, its_properties)) = gic_device.fdt_its() {
+        let msic = fdt.begin_node("msic")?;
+        fdt.property_string("compatible", its_compatibility)?;
+        fdt.property_null("msi-controller")?;
+        fdt.property_u32("phandle", MSI_PHANDLE)?;
+        fdt.property_array_u64("reg", its_properties)?;
+        fdt.end_node(msic)?;
+    }
+
     fdt.end_node(interrupt)?;
 
     Ok(())
@@ -347,6 +431,19 @@ fn create_timer_node(fdt: &mut FdtWriter) -> Result<(), FdtError> {
     Ok(())
 }
 
+fn create_pmu_node(fdt: &mut FdtWriter) -> Result<(), FdtError> {
+    // See
+    // https://github.com/torvalds/linux/blob/master/Documentation/devicetree/bindings/arm/pmu.yaml
+    let pmu = fdt.begin_node("pmu")?;
+    fdt.property_string("compatible", "arm,armv8-pmuv3")?;
+    fdt.property_array_u32(
+        "interrupts",
+        &[GIC_FDT_IRQ_TYPE_PPI, PMU_PPI, IRQ_TYPE_LEVEL_HI],
+    )?;
+    fdt.end_node(pmu)?;
+    Ok(())
+}
+
 fn create_psci_node(fdt: &mut FdtWriter) -> Result<(), FdtError> {
     let compatible = "arm,psci-0.2";
 
@@ -418,6 +515,34 @@ fn create_rtc_node<T: DeviceInfoForFDT + Clone + Debug>(
     Ok(())
 }
 
+fn create_tpm_node<T: DeviceInfoForFDT + Clone + Debug>(
+    fdt: &mut FdtWriter,
+    dev_info: &T,
+) -> Result<(), FdtError> {
+    // There is no upstream device tree binding for the CRB interface, so Linux guests discover
+    // the TPM through ACPI. The node uses the same compatible string as other VMMs.
+    let tpm = fdt.begin_node(&format!("tpm@{:x}", dev_info.addr()))?;
+    fdt.property_string("compatible", "tcg,tpm_crb")?;
+    fdt.property_array_u64("reg", &[dev_info.addr(), dev_info.length()])?;
+    fdt.end_node(tpm)?;
+
+    Ok(())
+}
+
+fn create_pvpanic_node<T: DeviceInfoForFDT + Clone + Debug>(
+    fdt: &mut FdtWriter,
+    dev_info: &T,
+) -> Result<(), FdtError> {
+    // Driver requirements:
+    // https://elixir.bootlin.com/linux/latest/source/drivers/misc/pvpanic/pvpanic-mmio.c
+    let pvpanic = fdt.begin_node(&format!("pvpanic@{:x}", dev_info.addr()))?;
+    fdt.property_string("compatible", "qemu,pvpanic-mmio")?;
+    fdt.property_array_u64("reg", &[dev_info.addr(), dev_info.length()])?;
+    fdt.end_node(pvpanic)?;
+
+    Ok(())
+}
+
 fn create_devices_node<T: DeviceInfoForFDT + Clone + Debug, S: std::hash::BuildHasher>(
     fdt: &mut FdtWriter,
     dev_info: &HashMap<(DeviceType, String), T, S>,
@@ -430,6 +555,8 @@ fn create_devices_node<T: DeviceInfoForFDT + Clone + Debug, S: std::hash::BuildH
             DeviceType::BootTimer => (), // since it's not a real device
             DeviceType::Rtc => create_rtc_node(fdt, info)?,
             DeviceType::Serial => create_serial_node(fdt, info)?,
+            DeviceType::Tpm => create_tpm_node(fdt, info)?,
+            DeviceType::PvPanic => create_pvpanic_node(fdt, info)?,
             DeviceType::Virtio(_) => {
                 ordered_virtio_device.push(info);
             }
@@ -456,7 +583,6 @@ mod tests {
     use crate::arch::aarch64::layout;
     use crate::device_manager::resources::ResourceAllocator;
     use crate::test_utils::arch_mem;
-    use crate::vstate::memory::GuestAddress;
 
     const LEN: u64 = 4096;
 
@@ -506,6 +632,20 @@ mod tests {
                     irq: 3,
                 },
             ),
+            (
+                (DeviceType::Tpm, DeviceType::Tpm.to_string()),
+                MMIODeviceInfo {
+                    addr: 3 * LEN,
+                    irq: 0,
+                },
+            ),
+            (
+                (DeviceType::PvPanic, DeviceType::PvPanic.to_string()),
+                MMIODeviceInfo {
+                    addr: 4 * LEN,
+                    irq: 0,
+                },
+            ),
         ]
         .iter()
         .cloned()
@@ -521,6 +661,10 @@ mod tests {
             &gic,
             &None,
             &None,
+            false,
+            None,
+            &[],
+            &[],
         )
         .unwrap();
     }
@@ -541,10 +685,134 @@ mod tests {
             &gic,
             &Some(vmgenid),
             &None,
+            false,
+            None,
+            &[],
+            &[],
         )
         .unwrap();
     }
 
+    #[test]
+    fn test_create_fdt_with_pmu() {
+        let mem = arch_mem(layout::FDT_MAX_SIZE + 0x1000);
+        let kvm = Kvm::new().unwrap();
+        let vm = kvm.create_vm().unwrap();
+        let gic = create_gic(&vm, 1, None).unwrap();
+        let dtb = create_fdt(
+            &mem,
+            vec![0],
+            CString::new("console=tty0").unwrap(),
+            &HashMap::<(DeviceType, std::string::String), MMIODeviceInfo>::new(),
+            &gic,
+            &None,
+            &None,
+            true,
+            None,
+            &[],
+            &[],
+        )
+        .unwrap();
+
+        let compatible = b"arm,armv8-pmuv3";
+        assert!(dtb
+            .windows(compatible.len())
+            .any(|window| window == compatible));
+    }
+
+    #[test]
+    fn test_create_fdt_with_acpi_rsdp() {
+        let mem = arch_mem(layout::FDT_MAX_SIZE + 0x1000);
+        let kvm = Kvm::new().unwrap();
+        let vm = kvm.create_vm().unwrap();
+        let gic = create_gic(&vm, 1, None).unwrap();
+        let dtb = create_fdt(
+            &mem,
+            vec![0],
+            CString::new("console=tty0").unwrap(),
+            &HashMap::<(DeviceType, std::string::String), MMIODeviceInfo>::new(),
+            &gic,
+            &None,
+            &None,
+            false,
+            Some(GuestAddress(0x8000_1000)),
+            &[],
+            &[],
+        )
+        .unwrap();
+
+        let property = b"firecracker,acpi-rsdp";
+        assert!(dtb.windows(property.len()).any(|window| window == property));
+        let rsdp = 0x8000_1000u64.to_be_bytes();
+        assert!(dtb.windows(rsdp.len()).any(|window| window == rsdp));
+    }
+
+    #[test]
+    fn test_create_fdt_with_reserved_memory() {
+        let mem = arch_mem(layout::FDT_MAX_SIZE + 0x1000);
+        let kvm = Kvm::new().unwrap();
+        let vm = kvm.create_vm().unwrap();
+        let gic = create_gic(&vm, 1, None).unwrap();
+        let reserved_memory = [ReservedMemoryRegion {
+            name: "shmem".to_string(),
+            guest_addr: 0x8040_0000,
+            size: 0x1000,
+        }];
+        let dtb = create_fdt(
+            &mem,
+            vec![0],
+            CString::new("console=tty0").unwrap(),
+            &HashMap::<(DeviceType, std::string::String), MMIODeviceInfo>::new(),
+            &gic,
+            &None,
+            &None,
+            false,
+            None,
+            &reserved_memory,
+            &[],
+        )
+        .unwrap();
+
+        let node = b"shmem@80400000";
+        assert!(dtb.windows(node.len()).any(|window| window == node));
+        let property = b"no-map";
+        assert!(dtb.windows(property.len()).any(|window| window == property));
+    }
+
+    #[test]
+    fn test_create_fdt_with_memory_tiers() {
+        let mem_size = layout::FDT_MAX_SIZE + 0x1000;
+        let mem = arch_mem(mem_size);
+        let kvm = Kvm::new().unwrap();
+        let vm = kvm.create_vm().unwrap();
+        let gic = create_gic(&vm, 1, None).unwrap();
+        let tier_start = layout::DRAM_MEM_START + usize_to_u64(mem_size);
+        let memory_nodes = [
+            vec![(GuestAddress(layout::DRAM_MEM_START), mem_size)],
+            vec![(GuestAddress(tier_start), 0x10_0000)],
+        ];
+        let dtb = create_fdt(
+            &mem,
+            vec![0],
+            CString::new("console=tty0").unwrap(),
+            &HashMap::<(DeviceType, std::string::String), MMIODeviceInfo>::new(),
+            &gic,
+            &None,
+            &None,
+            false,
+            None,
+            &[],
+            &memory_nodes,
+        )
+        .unwrap();
+
+        let node = format!("memory@{tier_start:x}");
+        let node = node.as_bytes();
+        assert!(dtb.windows(node.len()).any(|window| window == node));
+        let property = b"numa-node-id";
+        assert!(dtb.windows(property.len()).any(|window| window == property));
+    }
+
     #[test]
     fn test_create_fdt() {
         let mem = arch_mem(layout::FDT_MAX_SIZE + 0x1000);
@@ -566,6 +834,10 @@ mod tests {
             &gic,
             &None,
             &None,
+            false,
+            None,
+            &[],
+            &[],
         )
         .unwrap();
 
@@ -628,6 +900,10 @@ mod tests {
             &gic,
             &None,
             &Some(initrd),
+            false,
+            None,
+            &[],
+            &[],
         )
         .unwrap();
 
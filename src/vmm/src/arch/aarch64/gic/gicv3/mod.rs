@@ -8,13 +8,21 @@ use kvm_ioctls::{DeviceFd, VmFd};
 use crate::arch::aarch64::gic::{GicError, GicState};
 
 #[derive(Debug)]
-pub struct GICv3(super::GIC);
+pub struct GICv3 {
+    gic: super::GIC,
+
+    /// The file descriptor for the KVM ITS device
+    its_fd: DeviceFd,
+
+    /// ITS device properties, to be used for setting up the fdt entry
+    its_properties: [u64; 2],
+}
 
 impl std::ops::Deref for GICv3 {
     type Target = super::GIC;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.gic
     }
 }
 
@@ -24,6 +32,7 @@ impl GICv3 {
     const SZ_64K: u64 = 0x0001_0000;
     const KVM_VGIC_V3_DIST_SIZE: u64 = GICv3::SZ_64K;
     const KVM_VGIC_V3_REDIST_SIZE: u64 = (2 * GICv3::SZ_64K);
+    const KVM_VGIC_V3_ITS_SIZE: u64 = (2 * GICv3::SZ_64K);
 
     // Device trees specific constants
     const ARCH_GIC_V3_MAINT_IRQ: u32 = 9;
@@ -48,6 +57,16 @@ impl GICv3 {
         vcpu_count * GICv3::KVM_VGIC_V3_REDIST_SIZE
     }
 
+    /// Get the address of the ITS, placed right below the redistributors.
+    fn get_its_addr(vcpu_count: u64) -> u64 {
+        GICv3::get_redists_addr(vcpu_count) - GICv3::KVM_VGIC_V3_ITS_SIZE
+    }
+
+    /// Get the size of the ITS.
+    fn get_its_size() -> u64 {
+        GICv3::KVM_VGIC_V3_ITS_SIZE
+    }
+
     pub const VERSION: u32 = kvm_bindings::kvm_device_type_KVM_DEV_TYPE_ARM_VGIC_V3;
 
     pub fn fdt_compatibility(&self) -> &str {
@@ -58,26 +77,44 @@ impl GICv3 {
         GICv3::ARCH_GIC_V3_MAINT_IRQ
     }
 
+    pub fn fdt_its_compatibility(&self) -> &str {
+        "arm,gic-v3-its"
+    }
+
+    /// Returns the file descriptor of the ITS device
+    pub fn its_device_fd(&self) -> &DeviceFd {
+        &self.its_fd
+    }
+
+    /// Returns an array with ITS device properties
+    pub fn its_device_properties(&self) -> &[u64] {
+        &self.its_properties
+    }
+
     /// Create the GIC device object
-    pub fn create_device(fd: DeviceFd, vcpu_count: u64) -> Self {
-        GICv3(super::GIC {
-            fd,
-            properties: [
-                GICv3::get_dist_addr(),
-                GICv3::get_dist_size(),
-                GICv3::get_redists_addr(vcpu_count),
-                GICv3::get_redists_size(vcpu_count),
-            ],
-            vcpu_count,
-        })
+    pub fn create_device(fd: DeviceFd, its_fd: DeviceFd, vcpu_count: u64) -> Self {
+        GICv3 {
+            gic: super::GIC {
+                fd,
+                properties: [
+                    GICv3::get_dist_addr(),
+                    GICv3::get_dist_size(),
+                    GICv3::get_redists_addr(vcpu_count),
+                    GICv3::get_redists_size(vcpu_count),
+                ],
+                vcpu_count,
+            },
+            its_fd,
+            its_properties: [GICv3::get_its_addr(vcpu_count), GICv3::get_its_size()],
+        }
     }
 
     pub fn save_device(&self, mpidrs: &[u64]) -> Result<GicState, GicError> {
-        regs::save_state(&self.fd, mpidrs)
+        regs::save_state(&self.fd, &self.its_fd, mpidrs)
     }
 
     pub fn restore_device(&self, mpidrs: &[u64], state: &GicState) -> Result<(), GicError> {
-        regs::restore_state(&self.fd, mpidrs, state)
+        regs::restore_state(&self.fd, &self.its_fd, mpidrs, state)
     }
 
     pub fn init_device_attributes(gic_device: &Self) -> Result<(), GicError> {
@@ -101,6 +138,16 @@ impl GICv3 {
             0,
         )?;
 
+        // Setting up the ITS attribute.
+        // The ITS translates MSIs into LPIs and is placed right below the redistributors.
+        Self::set_device_attribute(
+            gic_device.its_device_fd(),
+            kvm_bindings::KVM_DEV_ARM_VGIC_GRP_ADDR,
+            u64::from(kvm_bindings::KVM_VGIC_ITS_ADDR_TYPE),
+            &GICv3::get_its_addr(gic_device.vcpu_count()) as *const u64 as u64,
+            0,
+        )?;
+
         Ok(())
     }
 
@@ -116,11 +163,24 @@ impl GICv3 {
             .map_err(GicError::CreateGIC)
     }
 
+    /// Initialize an ITS device
+    pub fn init_its_device(vm: &VmFd) -> Result<DeviceFd, GicError> {
+        let mut its_device = kvm_bindings::kvm_create_device {
+            type_: kvm_bindings::kvm_device_type_KVM_DEV_TYPE_ARM_VGIC_ITS,
+            fd: 0,
+            flags: 0,
+        };
+
+        vm.create_device(&mut its_device)
+            .map_err(GicError::CreateITS)
+    }
+
     /// Method to initialize the GIC device
     pub fn create(vm: &VmFd, vcpu_count: u64) -> Result<Self, GicError> {
         let vgic_fd = Self::init_device(vm)?;
+        let its_fd = Self::init_its_device(vm)?;
 
-        let device = Self::create_device(vgic_fd, vcpu_count);
+        let device = Self::create_device(vgic_fd, its_fd, vcpu_count);
 
         Self::init_device_attributes(&device)?;
 
@@ -156,6 +216,15 @@ impl GICv3 {
             0,
         )?;
 
+        // Finalize the ITS, this has to happen after the GIC is initialized.
+        Self::set_device_attribute(
+            gic_device.its_device_fd(),
+            kvm_bindings::KVM_DEV_ARM_VGIC_GRP_CTRL,
+            u64::from(kvm_bindings::KVM_DEV_ARM_VGIC_CTRL_INIT),
+            0,
+            0,
+        )?;
+
         Ok(())
     }
 
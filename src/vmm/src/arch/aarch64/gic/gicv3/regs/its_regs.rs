@@ -0,0 +1,128 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use kvm_bindings::*;
+use kvm_ioctls::DeviceFd;
+
+use crate::arch::aarch64::gic::regs::{GicRegState, ItsState, SimpleReg, VgicRegEngine};
+use crate::arch::aarch64::gic::GicError;
+
+// ITS registers that we want to save/restore. KVM always accesses them through a 64-bit value,
+// regardless of the register width.
+const GITS_CTLR: SimpleReg = SimpleReg::new(0x0000, 8);
+const GITS_IIDR: SimpleReg = SimpleReg::new(0x0004, 8);
+const GITS_CBASER: SimpleReg = SimpleReg::new(0x0080, 8);
+const GITS_CWRITER: SimpleReg = SimpleReg::new(0x0088, 8);
+const GITS_CREADR: SimpleReg = SimpleReg::new(0x0090, 8);
+const GITS_BASER: SimpleReg = SimpleReg::new(0x0100, 64);
+
+// List with the ITS registers restored before the ITS tables, in the order required by KVM.
+// See Documentation/virt/kvm/devices/arm-vgic-its.rst in the linux kernel.
+// GITS_CTLR is restored last, after the tables.
+static VGIC_ITS_REGS: &[SimpleReg] = &[
+    GITS_IIDR,
+    GITS_CBASER,
+    GITS_CREADR,
+    GITS_CWRITER,
+    GITS_BASER,
+];
+
+struct ItsRegEngine {}
+
+impl VgicRegEngine for ItsRegEngine {
+    type Reg = SimpleReg;
+    type RegChunk = u64;
+
+    fn group() -> u32 {
+        KVM_DEV_ARM_VGIC_GRP_ITS_REGS
+    }
+}
+
+fn its_regs() -> Box<dyn Iterator<Item = &'static SimpleReg>> {
+    Box::new(VGIC_ITS_REGS.iter())
+}
+
+/// Issue an ITS control command (save or restore of the ITS tables).
+fn its_tables_ctrl(fd: &DeviceFd, attr: u32) -> Result<(), GicError> {
+    let ctrl_attr = kvm_device_attr {
+        group: KVM_DEV_ARM_VGIC_GRP_CTRL,
+        attr: u64::from(attr),
+        addr: 0,
+        flags: 0,
+    };
+    fd.set_device_attr(&ctrl_attr)
+        .map_err(|err| GicError::DeviceAttribute(err, true, KVM_DEV_ARM_VGIC_GRP_CTRL))
+}
+
+/// Save the state of the ITS.
+///
+/// The device, collection and interrupt translation tables are flushed into guest RAM, so this
+/// has to be called before the guest memory is saved.
+pub(crate) fn get_its_state(fd: &DeviceFd) -> Result<ItsState, GicError> {
+    its_tables_ctrl(fd, KVM_DEV_ARM_ITS_SAVE_TABLES)?;
+
+    Ok(ItsState {
+        regs: ItsRegEngine::get_regs_data(fd, its_regs(), 0)?,
+        ctlr: ItsRegEngine::get_reg_data(fd, &GITS_CTLR, 0)?,
+    })
+}
+
+/// Restore the state of the ITS.
+///
+/// The guest memory and the redistributors have to be restored beforehand.
+pub(crate) fn set_its_state(fd: &DeviceFd, state: &ItsState) -> Result<(), GicError> {
+    ItsRegEngine::set_regs_data(fd, its_regs(), &state.regs, 0)?;
+    its_tables_ctrl(fd, KVM_DEV_ARM_ITS_RESTORE_TABLES)?;
+    ItsRegEngine::set_reg_data(fd, &GITS_CTLR, &state.ctlr, 0)
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::undocumented_unsafe_blocks)]
+    use std::os::unix::io::AsRawFd;
+
+    use kvm_ioctls::Kvm;
+
+    use super::*;
+    use crate::arch::aarch64::gic::{create_gic, GICDevice, GICVersion};
+
+    #[test]
+    fn test_access_its_regs() {
+        let kvm = Kvm::new().unwrap();
+        let vm = kvm.create_vm().unwrap();
+        let _ = vm.create_vcpu(0).unwrap();
+        let gic = create_gic(&vm, 1, Some(GICVersion::GICV3)).expect("Cannot create gic");
+        let GICDevice::V3(gic) = gic else {
+            panic!("Unexpected gic version!");
+        };
+        let its_fd = gic.its_device_fd();
+
+        let state = get_its_state(its_fd).unwrap();
+        // GITS_IIDR, GITS_CBASER, GITS_CREADR, GITS_CWRITER and the 8 GITS_BASER<n>.
+        assert_eq!(state.regs.len(), 5);
+        assert_eq!(
+            state.regs.iter().map(|reg| reg.chunks.len()).sum::<usize>(),
+            12
+        );
+        assert_eq!(state.ctlr.chunks.len(), 1);
+
+        set_its_state(its_fd, &state).unwrap();
+
+        unsafe { libc::close(its_fd.as_raw_fd()) };
+
+        let res = set_its_state(its_fd, &state);
+        assert_eq!(
+            format!("{:?}", res.unwrap_err()),
+            "DeviceAttribute(Error(9), true, 8)"
+        );
+
+        let res = get_its_state(its_fd);
+        assert_eq!(
+            format!("{:?}", res.unwrap_err()),
+            "DeviceAttribute(Error(9), true, 4)"
+        );
+
+        // dropping gic would double close the its fd, so leak it
+        std::mem::forget(gic);
+    }
+}
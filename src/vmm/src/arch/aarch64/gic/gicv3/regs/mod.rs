@@ -3,6 +3,7 @@
 
 mod dist_regs;
 mod icc_regs;
+mod its_regs;
 mod redist_regs;
 
 use kvm_ioctls::DeviceFd;
@@ -10,8 +11,8 @@ use kvm_ioctls::DeviceFd;
 use crate::arch::aarch64::gic::regs::{GicState, GicVcpuState};
 use crate::arch::aarch64::gic::GicError;
 
-/// Save the state of the GIC device.
-pub fn save_state(fd: &DeviceFd, mpidrs: &[u64]) -> Result<GicState, GicError> {
+/// Save the state of the GIC device and of its ITS.
+pub fn save_state(fd: &DeviceFd, its_fd: &DeviceFd, mpidrs: &[u64]) -> Result<GicState, GicError> {
     // Flush redistributors pending tables to guest RAM.
     super::save_pending_tables(fd)?;
 
@@ -26,11 +27,17 @@ pub fn save_state(fd: &DeviceFd, mpidrs: &[u64]) -> Result<GicState, GicError> {
     Ok(GicState {
         dist: dist_regs::get_dist_regs(fd)?,
         gic_vcpu_states: vcpu_states,
+        its: Some(its_regs::get_its_state(its_fd)?),
     })
 }
 
-/// Restore the state of the GIC device.
-pub fn restore_state(fd: &DeviceFd, mpidrs: &[u64], state: &GicState) -> Result<(), GicError> {
+/// Restore the state of the GIC device and of its ITS.
+pub fn restore_state(
+    fd: &DeviceFd,
+    its_fd: &DeviceFd,
+    mpidrs: &[u64],
+    state: &GicState,
+) -> Result<(), GicError> {
     dist_regs::set_dist_regs(fd, &state.dist)?;
 
     if mpidrs.len() != state.gic_vcpu_states.len() {
@@ -41,6 +48,13 @@ pub fn restore_state(fd: &DeviceFd, mpidrs: &[u64], state: &GicState) -> Result<
         icc_regs::set_icc_regs(fd, *mpidr, &vcpu_state.icc)?;
     }
 
+    // The ITS has to be restored after the redistributors. Snapshots migrated from a format
+    // without the ITS state describe a guest which doesn't know about the ITS, so it is left in
+    // its reset state.
+    if let Some(its_state) = &state.its {
+        its_regs::set_its_state(its_fd, its_state)?;
+    }
+
     Ok(())
 }
 
@@ -51,17 +65,25 @@ mod tests {
     use kvm_ioctls::Kvm;
 
     use super::*;
-    use crate::arch::aarch64::gic::{create_gic, GICVersion};
+    use crate::arch::aarch64::gic::{create_gic, GICDevice, GICVersion};
+
+    fn create_gicv3(vm: &kvm_ioctls::VmFd) -> crate::arch::aarch64::gic::gicv3::GICv3 {
+        match create_gic(vm, 1, Some(GICVersion::GICV3)).expect("Cannot create gic") {
+            GICDevice::V3(gic) => gic,
+            GICDevice::V2(_) => panic!("Unexpected gic version!"),
+        }
+    }
 
     #[test]
     fn test_vm_save_restore_state() {
         let kvm = Kvm::new().unwrap();
         let vm = kvm.create_vm().unwrap();
-        let gic = create_gic(&vm, 1, Some(GICVersion::GICV3)).expect("Cannot create gic");
+        let gic = create_gicv3(&vm);
         let gic_fd = gic.device_fd();
+        let its_fd = gic.its_device_fd();
 
         let mpidr = vec![1];
-        let res = save_state(gic_fd, &mpidr);
+        let res = save_state(gic_fd, its_fd, &mpidr);
         // We will receive an error if trying to call before creating vcpu.
         assert_eq!(
             format!("{:?}", res.unwrap_err()),
@@ -71,10 +93,11 @@ mod tests {
         let kvm = Kvm::new().unwrap();
         let vm = kvm.create_vm().unwrap();
         let _vcpu = vm.create_vcpu(0).unwrap();
-        let gic = create_gic(&vm, 1, Some(GICVersion::GICV3)).expect("Cannot create gic");
+        let gic = create_gicv3(&vm);
         let gic_fd = gic.device_fd();
+        let its_fd = gic.its_device_fd();
 
-        let vm_state = save_state(gic_fd, &mpidr).unwrap();
+        let vm_state = save_state(gic_fd, its_fd, &mpidr).unwrap();
         let val: u32 = 0;
         let gicd_statusr_off = 0x0010u64;
         let mut gic_dist_attr = kvm_bindings::kvm_device_attr {
@@ -94,7 +117,15 @@ mod tests {
 
         assert_eq!(gicd_statusr.chunks[0], val);
         assert_eq!(vm_state.dist.len(), 12);
-        restore_state(gic_fd, &mpidr, &vm_state).unwrap();
-        restore_state(gic_fd, &[1, 2], &vm_state).unwrap_err();
+        assert!(vm_state.its.is_some());
+        restore_state(gic_fd, its_fd, &mpidr, &vm_state).unwrap();
+        restore_state(gic_fd, its_fd, &[1, 2], &vm_state).unwrap_err();
+
+        // The ITS is left in its reset state if the snapshot doesn't describe it.
+        let vm_state = GicState {
+            its: None,
+            ..vm_state
+        };
+        restore_state(gic_fd, its_fd, &mpidr, &vm_state).unwrap();
     }
 }
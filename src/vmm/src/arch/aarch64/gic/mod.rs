@@ -8,7 +8,7 @@ mod regs;
 use gicv2::GICv2;
 use gicv3::GICv3;
 use kvm_ioctls::{DeviceFd, VmFd};
-pub use regs::GicState;
+pub use regs::{GicRegState, GicState, GicVcpuState, ItsState};
 
 use super::layout;
 
@@ -46,6 +46,8 @@ impl GIC {
 pub enum GicError {
     /// Error while calling KVM ioctl for setting up the global interrupt controller: {0}
     CreateGIC(kvm_ioctls::Error),
+    /// Error while calling KVM ioctl for setting up the interrupt translation service: {0}
+    CreateITS(kvm_ioctls::Error),
     /// Error while setting or getting device attributes for the GIC: {0}, {1}, {2}
     DeviceAttribute(kvm_ioctls::Error, bool, u32),
     /// The number of vCPUs in the GicState doesn't match the number of vCPUs on the system.
@@ -59,7 +61,7 @@ pub enum GicError {
 pub enum GICVersion {
     /// Legacy version.
     GICV2,
-    /// GICV3 without ITS.
+    /// GICV3 with ITS.
     GICV3,
 }
 
@@ -68,7 +70,7 @@ pub enum GICVersion {
 pub enum GICDevice {
     /// Legacy version.
     V2(GICv2),
-    /// GICV3 without ITS.
+    /// GICV3 with ITS.
     V3(GICv3),
 }
 impl GICDevice {
@@ -112,6 +114,14 @@ impl GICDevice {
         }
     }
 
+    /// Returns the fdt compatibility property and the properties of the ITS, if any
+    pub fn fdt_its(&self) -> Option<(&str, &[u64])> {
+        match self {
+            Self::V2(_) => None,
+            Self::V3(x) => Some((x.fdt_its_compatibility(), x.its_device_properties())),
+        }
+    }
+
     /// Returns the GIC version of the device
     pub fn version(&self) -> u32 {
         match self {
@@ -177,4 +187,15 @@ mod tests {
         let vm = kvm.create_vm().unwrap();
         create_gic(&vm, 1, None).unwrap();
     }
+
+    #[test]
+    fn test_create_gicv3_its() {
+        let kvm = Kvm::new().unwrap();
+        let vm = kvm.create_vm().unwrap();
+        let gic = create_gic(&vm, 1, Some(GICVersion::GICV3)).unwrap();
+        let (compatibility, properties) = gic.fdt_its().unwrap();
+        assert_eq!(compatibility, "arm,gic-v3-its");
+        // The ITS is placed right below the redistributors.
+        assert_eq!(properties[0] + properties[1], gic.device_properties()[2]);
+    }
 }
@@ -11,7 +11,7 @@ use serde::{Deserialize, Serialize};
 
 use crate::arch::aarch64::gic::GicError;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct GicRegState<T> {
     pub(crate) chunks: Vec<T>,
 }
@@ -30,6 +30,17 @@ pub struct GicState {
     pub dist: Vec<GicRegState<u32>>,
     /// The state of the vcpu interfaces.
     pub gic_vcpu_states: Vec<GicVcpuState>,
+    /// The state of the ITS, only present for GICv3.
+    pub its: Option<ItsState>,
+}
+
+/// Structure used for serializing the state of the GICv3 ITS.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ItsState {
+    /// The ITS registers restored before the ITS tables.
+    pub regs: Vec<GicRegState<u64>>,
+    /// The GITS_CTLR register, restored after the ITS tables.
+    pub ctlr: GicRegState<u64>,
 }
 
 /// Structure used for serializing the state of the GIC registers for a specific vCPU.
@@ -62,6 +62,10 @@ pub const SYSTEM_MEM_START: u64 = DRAM_MEM_START;
 /// image, which needs to be 2MB aligned.
 pub const SYSTEM_MEM_SIZE: u64 = 0x20_0000;
 
+/// Maximum size of a UEFI firmware image. Like kvmtool, which edk2's `ArmVirtKvmTool` platform
+/// targets, the image is loaded in the DRAM, where a kernel would be, and executed from there.
+pub const FIRMWARE_MAX_SIZE: usize = 0x400_0000; // 64 MB.
+
 /// Kernel command line maximum size.
 /// As per `arch/arm64/include/uapi/asm/setup.h`.
 pub const CMDLINE_MAX_SIZE: usize = 2048;
@@ -77,6 +81,15 @@ pub const FDT_MAX_SIZE: usize = 0x20_0000;
 /// The highest usable SPI on aarch64.
 pub const IRQ_MAX: u32 = 128;
 
+/// PPI used by the virtual PMU overflow interrupt, as used by QEMU and kvmtool.
+/// It is relative to the first PPI (INTID 16), so it maps to INTID 23.
+pub const PMU_PPI: u32 = 7;
+/// Offset of the first PPI in the GIC interrupt id space.
+pub const PPI_BASE: u32 = 16;
+/// Offset of the first SPI in the GIC interrupt id space. KVM numbers the interrupt lines of
+/// devices (GSIs) relative to it.
+pub const SPI_BASE: u32 = 32;
+
 /// First usable interrupt on aarch64.
 pub const IRQ_BASE: u32 = 32;
 
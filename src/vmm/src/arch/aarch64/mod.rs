@@ -16,13 +16,15 @@ use std::cmp::min;
 use std::collections::HashMap;
 use std::ffi::CString;
 use std::fmt::Debug;
+use std::io::{Seek, SeekFrom};
 
-use vm_memory::GuestMemoryError;
+use vm_memory::{GuestMemoryError, ReadVolatile, VolatileMemoryError};
 
 pub use self::fdt::DeviceInfoForFDT;
 use self::gic::GICDevice;
 use crate::arch::DeviceType;
 use crate::devices::acpi::vmgenid::VmGenId;
+use crate::vmm_config::machine_config::ReservedMemoryRegion;
 use crate::vstate::memory::{Address, Bytes, GuestAddress, GuestMemory, GuestMemoryMmap};
 
 /// Errors thrown while configuring aarch64 system.
@@ -34,6 +36,12 @@ pub enum ConfigurationError {
     InitrdAddress,
     /// Failed to write to guest memory.
     MemoryError(GuestMemoryError),
+    /// Cannot read the firmware image: {0}
+    FirmwareRead(std::io::Error),
+    /// The firmware image must not be empty nor larger than 64 MiB, got {0} bytes.
+    FirmwareSize(u64),
+    /// Cannot load the firmware image into guest memory: {0}
+    FirmwareLoad(VolatileMemoryError),
 }
 
 /// The start of the memory area reserved for MMIO devices.
@@ -59,6 +67,10 @@ pub fn arch_memory_regions(size: usize) -> Vec<(GuestAddress, usize)> {
 /// * `device_info` - A hashmap containing the attached devices for building FDT device nodes.
 /// * `gic_device` - The GIC device.
 /// * `initrd` - Information about an optional initrd.
+/// * `pmu` - Whether the PMU node should be added to the FDT.
+/// * `acpi_rsdp` - The address of the ACPI RSDP, if any.
+/// * `reserved_memory` - Guest memory ranges to describe as reserved in the FDT.
+#[allow(clippy::too_many_arguments)]
 pub fn configure_system<T: DeviceInfoForFDT + Clone + Debug>(
     guest_mem: &GuestMemoryMmap,
     cmdline_cstring: CString,
@@ -67,6 +79,10 @@ pub fn configure_system<T: DeviceInfoForFDT + Clone + Debug>(
     gic_device: &GICDevice,
     vmgenid: &Option<VmGenId>,
     initrd: &Option<super::InitrdConfig>,
+    pmu: bool,
+    acpi_rsdp: Option<GuestAddress>,
+    reserved_memory: &[ReservedMemoryRegion],
+    memory_nodes: &[Vec<(GuestAddress, usize)>],
 ) -> Result<(), ConfigurationError> {
     let fdt = fdt::create_fdt(
         guest_mem,
@@ -76,6 +92,10 @@ pub fn configure_system<T: DeviceInfoForFDT + Clone + Debug>(
         gic_device,
         vmgenid,
         initrd,
+        pmu,
+        acpi_rsdp,
+        reserved_memory,
+        memory_nodes,
     )?;
     let fdt_address = GuestAddress(get_fdt_addr(guest_mem));
     guest_mem
@@ -89,6 +109,35 @@ pub fn get_kernel_start() -> u64 {
     layout::SYSTEM_MEM_START + layout::SYSTEM_MEM_SIZE
 }
 
+/// Loads a UEFI firmware image where the kernel would be loaded and returns its entry point.
+pub fn load_firmware<F>(
+    guest_mem: &GuestMemoryMmap,
+    image: &mut F,
+) -> Result<GuestAddress, ConfigurationError>
+where
+    F: ReadVolatile + Seek,
+{
+    let size = image
+        .seek(SeekFrom::End(0))
+        .map_err(ConfigurationError::FirmwareRead)?;
+    if size == 0 || size > layout::FIRMWARE_MAX_SIZE as u64 {
+        return Err(ConfigurationError::FirmwareSize(size));
+    }
+    image
+        .seek(SeekFrom::Start(0))
+        .map_err(ConfigurationError::FirmwareRead)?;
+
+    let entry_addr = GuestAddress(get_kernel_start());
+    let mut slice = guest_mem
+        .get_slice(entry_addr, crate::utils::u64_to_usize(size))
+        .map_err(ConfigurationError::MemoryError)?;
+    image
+        .read_exact_volatile(&mut slice)
+        .map_err(ConfigurationError::FirmwareLoad)?;
+
+    Ok(entry_addr)
+}
+
 /// Returns the memory address where the initrd could be loaded.
 pub fn initrd_load_addr(
     guest_mem: &GuestMemoryMmap,
@@ -143,6 +192,40 @@ mod tests {
         assert_eq!(super::layout::DRAM_MEM_MAX_SIZE, regions[0].1);
     }
 
+    #[test]
+    fn test_load_firmware() {
+        use std::io::Write;
+
+        use vmm_sys_util::tempfile::TempFile;
+
+        let mem = arch_mem(layout::FIRMWARE_MAX_SIZE + (256 << 20));
+
+        let mut image = TempFile::new().unwrap().into_file();
+        assert!(matches!(
+            load_firmware(&mem, &mut image),
+            Err(ConfigurationError::FirmwareSize(0))
+        ));
+
+        image.write_all(&[0xaa; 0x1000]).unwrap();
+        assert_eq!(
+            load_firmware(&mem, &mut image).unwrap(),
+            GuestAddress(get_kernel_start())
+        );
+        let mut buf = [0u8; 0x1000];
+        mem.read_slice(&mut buf, GuestAddress(get_kernel_start()))
+            .unwrap();
+        assert_eq!(buf, [0xaa; 0x1000]);
+        // The image is executed from the DRAM, past the system data, and must not overlap the FDT.
+        assert!(get_kernel_start() >= layout::SYSTEM_MEM_START + layout::SYSTEM_MEM_SIZE);
+        assert!(get_kernel_start() + layout::FIRMWARE_MAX_SIZE as u64 <= get_fdt_addr(&mem));
+
+        image.set_len(layout::FIRMWARE_MAX_SIZE as u64 + 1).unwrap();
+        assert!(matches!(
+            load_firmware(&mem, &mut image),
+            Err(ConfigurationError::FirmwareSize(_))
+        ));
+    }
+
     #[test]
     fn test_get_fdt_addr() {
         let mem = arch_mem(layout::FDT_MAX_SIZE - 0x1000);
@@ -109,12 +109,114 @@ arm64_sys_reg!(TCR_EL1, 3, 0, 2, 0, 2);
 // https://developer.arm.com/documentation/100798/0400/register-descriptions/aarch64-system-registers/id-aa64mmfr0-el1--aarch64-memory-model-feature-register-0--el1
 arm64_sys_reg!(ID_AA64MMFR0_EL1, 3, 0, 0, 7, 0);
 
+// Identification registers outside of the feature ID register space, which KVM allows userspace
+// to write so that the guest sees the same CPU across hosts.
+// https://elixir.bootlin.com/linux/v6.10/source/arch/arm64/kvm/sys_regs.c
+// Revision ID Register
+arm64_sys_reg!(REVIDR_EL1, 3, 0, 0, 0, 6);
+// Auxiliary ID Register
+arm64_sys_reg!(AIDR_EL1, 3, 1, 0, 0, 7);
+// Cache Type Register
+arm64_sys_reg!(CTR_EL0, 3, 3, 0, 0, 1);
+
+/// Bits of MIDR_EL1 that can be modified: the upper 32 bits are RES0.
+const MIDR_EL1_WRITABLE_BITS: u64 = 0xffff_ffff;
+/// Bits of CTR_EL0 that can be modified: IminLine, L1Ip, DminLine, ERG, CWG, IDC, DIC and
+/// TminLine. The other bits are reserved.
+const CTR_EL0_WRITABLE_BITS: u64 = 0x3f_3fff_c00f;
+
+/// Identification registers outside of the feature ID register space that custom CPU templates
+/// can modify, with their names and the bits that can be modified.
+pub const IDENTITY_REGS: [(&str, u64, u64); 4] = [
+    ("MIDR_EL1", MIDR_EL1, MIDR_EL1_WRITABLE_BITS),
+    ("REVIDR_EL1", REVIDR_EL1, u64::MAX),
+    ("AIDR_EL1", AIDR_EL1, u64::MAX),
+    ("CTR_EL0", CTR_EL0, CTR_EL0_WRITABLE_BITS),
+];
+
+/// Registers that custom CPU templates can name instead of giving their ID.
+pub const NAMED_REGS: [(&str, u64); 9] = [
+    ("ID_AA64PFR0_EL1", ID_AA64PFR0_EL1),
+    ("ID_AA64ISAR0_EL1", ID_AA64ISAR0_EL1),
+    ("ID_AA64ISAR1_EL1", ID_AA64ISAR1_EL1),
+    ("ID_AA64MMFR0_EL1", ID_AA64MMFR0_EL1),
+    ("ID_AA64MMFR2_EL1", ID_AA64MMFR2_EL1),
+    ("MIDR_EL1", MIDR_EL1),
+    ("REVIDR_EL1", REVIDR_EL1),
+    ("AIDR_EL1", AIDR_EL1),
+    ("CTR_EL0", CTR_EL0),
+];
+
+/// Checks if the register is in the feature ID register space (Op0 == 3, Op1 == 0, CRn == 0,
+/// CRm in 1..=7), whose registers KVM allows userspace to write before the vCPU first runs.
+pub fn is_feature_id_reg(reg_id: u64) -> bool {
+    let field = |mask: u32, shift: u32| (reg_id & u64::from(mask)) >> shift;
+    reg_id & u64::from(KVM_REG_ARM_COPROC_MASK) == u64::from(KVM_REG_ARM64_SYSREG)
+        && field(
+            KVM_REG_ARM64_SYSREG_OP0_MASK,
+            KVM_REG_ARM64_SYSREG_OP0_SHIFT,
+        ) == 3
+        && field(
+            KVM_REG_ARM64_SYSREG_OP1_MASK,
+            KVM_REG_ARM64_SYSREG_OP1_SHIFT,
+        ) == 0
+        && field(
+            KVM_REG_ARM64_SYSREG_CRN_MASK,
+            KVM_REG_ARM64_SYSREG_CRN_SHIFT,
+        ) == 0
+        && (1..=7).contains(&field(
+            KVM_REG_ARM64_SYSREG_CRM_MASK,
+            KVM_REG_ARM64_SYSREG_CRM_SHIFT,
+        ))
+}
+
 /// Vector lengths pseudo-register
 /// TODO: this can be removed after https://github.com/rust-vmm/kvm-bindings/pull/89
 /// is merged and new version is used in Firecracker.
 pub const KVM_REG_ARM64_SVE_VLS: u64 =
     KVM_REG_ARM64 | KVM_REG_ARM64_SVE as u64 | KVM_REG_SIZE_U512 | 0xffff;
 
+/// Size of an SVE vector quadword (the vector length granule) in bits.
+pub const SVE_VQ_BITS: u16 = 128;
+/// Maximum SVE vector length allowed by the architecture in bits.
+pub const SVE_MAX_VECTOR_LENGTH: u16 = 2048;
+/// Number of 64 bit words in the `KVM_REG_ARM64_SVE_VLS` pseudo-register.
+pub const SVE_VLS_WORDS: usize = 8;
+
+/// Checks if the register belongs to the SVE register group
+/// (Z, P and FFR registers together with the VLS pseudo-register).
+/// Once SVE is finalized, KVM lists the Z, P and FFR registers in `KVM_GET_REG_LIST`,
+/// so they are saved and restored along with the other registers.
+pub fn is_sve_reg(reg_id: u64) -> bool {
+    reg_id & u64::from(KVM_REG_ARM_COPROC_MASK) == u64::from(KVM_REG_ARM64_SVE)
+}
+
+/// Restricts the set of vector lengths supported by the host (`host_vls`, as
+/// read from `KVM_REG_ARM64_SVE_VLS`) to the ones not longer than `max_len` bits.
+///
+/// Bit `vq - 1` of the set corresponds to a vector length of `vq * 128` bits.
+/// Returns `None` if `max_len` is not a valid vector length or if it is not
+/// supported by the host.
+pub fn sve_vls_limit(
+    host_vls: &[u64; SVE_VLS_WORDS],
+    max_len: u16,
+) -> Option<[u64; SVE_VLS_WORDS]> {
+    if max_len == 0 || max_len > SVE_MAX_VECTOR_LENGTH || max_len % SVE_VQ_BITS != 0 {
+        return None;
+    }
+    let max_vq = usize::from(max_len / SVE_VQ_BITS);
+    let word = (max_vq - 1) / 64;
+    let bit = (max_vq - 1) % 64;
+    if host_vls[word] & (1 << bit) == 0 {
+        return None;
+    }
+
+    let mut vls = [0u64; SVE_VLS_WORDS];
+    vls[..word].copy_from_slice(&host_vls[..word]);
+    vls[word] = host_vls[word] & (u64::MAX >> (63 - bit));
+    Some(vls)
+}
+
 /// Program Counter
 /// The offset value (0x100 = 32 * 8) is calcuated as follows:
 /// - `kvm_regs` includes `regs` field of type `user_pt_regs` at the beginning (i.e., at offset 0).
@@ -508,6 +610,49 @@ mod tests {
         assert_eq!(reg_size(ID_AA64PFR0_EL1), 8);
     }
 
+    #[test]
+    fn test_is_feature_id_reg() {
+        assert!(is_feature_id_reg(ID_AA64PFR0_EL1));
+        assert!(is_feature_id_reg(ID_AA64MMFR2_EL1));
+        assert!(!is_feature_id_reg(MIDR_EL1));
+        assert!(!is_feature_id_reg(CTR_EL0));
+        assert!(!is_feature_id_reg(TCR_EL1));
+        assert!(!is_feature_id_reg(PC));
+    }
+
+    #[test]
+    fn test_sve_reg_ids() {
+        // Slice 0 of the Z0 register.
+        let z0 = KVM_REG_ARM64 | KVM_REG_ARM64_SVE as u64 | KVM_REG_SIZE_U2048;
+        assert!(is_sve_reg(KVM_REG_ARM64_SVE_VLS));
+        assert!(is_sve_reg(z0));
+        assert!(!is_sve_reg(ID_AA64PFR0_EL1));
+        assert!(!is_sve_reg(PC));
+
+        assert_eq!(reg_size(z0), 256);
+        assert_eq!(reg_size(KVM_REG_ARM64_SVE_VLS), 64);
+    }
+
+    #[test]
+    fn test_sve_vls_limit() {
+        // Host supports 128, 256 and 512 bit vectors.
+        let host_vls = [0b1011, 0, 0, 0, 0, 0, 0, 0];
+
+        assert_eq!(sve_vls_limit(&host_vls, 128).unwrap()[0], 0b1);
+        assert_eq!(sve_vls_limit(&host_vls, 256).unwrap()[0], 0b11);
+        assert_eq!(sve_vls_limit(&host_vls, 512).unwrap(), host_vls);
+        // 384 bit vectors are not supported by the host.
+        assert_eq!(sve_vls_limit(&host_vls, 384), None);
+        assert_eq!(sve_vls_limit(&host_vls, 1024), None);
+        // Invalid vector lengths.
+        assert_eq!(sve_vls_limit(&host_vls, 0), None);
+        assert_eq!(sve_vls_limit(&host_vls, 200), None);
+        assert_eq!(sve_vls_limit(&host_vls, 2176), None);
+
+        let host_vls = [0xffff, 0, 0, 0, 0, 0, 0, 0];
+        assert_eq!(sve_vls_limit(&host_vls, 2048).unwrap(), host_vls);
+    }
+
     #[test]
     fn test_aarch64_register_vec_serde() {
         let mut v = Aarch64RegisterVec::default();
@@ -41,6 +41,10 @@ pub enum DeviceType {
     Rtc,
     /// Device Type: BootTimer.
     BootTimer,
+    /// Device Type: TPM.
+    Tpm,
+    /// Device Type: pvpanic.
+    PvPanic,
 }
 
 /// Type for passing information about the initrd in the guest memory.
@@ -39,6 +39,11 @@ pub const IOAPIC_ADDR: u32 = 0xfec0_0000;
 /// Location of RSDP pointer in x86 machines
 pub const RSDP_ADDR: u64 = 0x000e_0000;
 
+/// Location of the SMBIOS entry point, at the start of the area scanned by guests to find it.
+pub const SMBIOS_START: u64 = 0x000f_0000;
+/// Maximum size of the SMBIOS entry point and tables, up to the end of the legacy BIOS area.
+pub const SMBIOS_MAX_SIZE: u64 = HIMEM_START - SMBIOS_START;
+
 /// Start of memory region we will use for system data (MPTable, ACPI, etc). We are putting its
 /// start address where EBDA normally starts, i.e. in the last 1 KiB of the first 640KiB of memory
 pub const SYSTEM_MEM_START: u64 = 0x9fc00;
@@ -17,6 +17,8 @@ mod mptable;
 pub mod msr;
 /// Logic for configuring x86_64 registers.
 pub mod regs;
+/// Logic for configuring the SMBIOS tables.
+pub mod smbios;
 
 #[allow(missing_docs)]
 pub mod gen;
@@ -28,6 +30,7 @@ use linux_loader::loader::bootparam::boot_params;
 use crate::arch::InitrdConfig;
 use crate::device_manager::resources::ResourceAllocator;
 use crate::utils::u64_to_usize;
+use crate::vmm_config::machine_config::ReservedMemoryRegion;
 use crate::vstate::memory::{
     Address, GuestAddress, GuestMemory, GuestMemoryMmap, GuestMemoryRegion,
 };
@@ -110,6 +113,7 @@ pub fn initrd_load_addr(
 /// * `cmdline_size` - Size of the kernel command line in bytes including the null terminator.
 /// * `initrd` - Information about where the ramdisk image was loaded in the `guest_mem`.
 /// * `num_cpus` - Number of virtual CPUs the guest will have.
+/// * `reserved_memory` - Guest memory ranges to mark as reserved in the e820 map.
 pub fn configure_system(
     guest_mem: &GuestMemoryMmap,
     resource_allocator: &mut ResourceAllocator,
@@ -117,6 +121,7 @@ pub fn configure_system(
     cmdline_size: usize,
     initrd: &Option<InitrdConfig>,
     num_cpus: u8,
+    reserved_memory: &[ReservedMemoryRegion],
 ) -> Result<(), ConfigurationError> {
     const KERNEL_BOOT_FLAG_MAGIC: u16 = 0xaa55;
     const KERNEL_HDR_MAGIC: u32 = 0x5372_6448;
@@ -158,38 +163,50 @@ pub fn configure_system(
         E820_RESERVED,
     )?;
 
+    let mut reserved_memory = reserved_memory.to_vec();
+    reserved_memory.sort_by_key(|region| region.guest_addr);
+
     let last_addr = guest_mem.last_addr();
     if last_addr < end_32bit_gap_start {
-        add_e820_entry(
+        add_e820_ram_entries(
             &mut params,
             himem_start.raw_value(),
             // it's safe to use unchecked_offset_from because
             // mem_end > himem_start
             last_addr.unchecked_offset_from(himem_start) + 1,
-            E820_RAM,
+            &reserved_memory,
         )?;
     } else {
-        add_e820_entry(
+        add_e820_ram_entries(
             &mut params,
             himem_start.raw_value(),
             // it's safe to use unchecked_offset_from because
             // end_32bit_gap_start > himem_start
             end_32bit_gap_start.unchecked_offset_from(himem_start),
-            E820_RAM,
+            &reserved_memory,
         )?;
 
         if last_addr > first_addr_past_32bits {
-            add_e820_entry(
+            add_e820_ram_entries(
                 &mut params,
                 first_addr_past_32bits.raw_value(),
                 // it's safe to use unchecked_offset_from because
                 // mem_end > first_addr_past_32bits
                 last_addr.unchecked_offset_from(first_addr_past_32bits) + 1,
-                E820_RAM,
+                &reserved_memory,
             )?;
         }
     }
 
+    // Reserved regions mapped past guest RAM, like shared memory segments, are not carved out
+    // of any RAM entry above.
+    for region in reserved_memory
+        .iter()
+        .filter(|region| !guest_mem.address_in_range(GuestAddress(region.guest_addr)))
+    {
+        add_e820_entry(&mut params, region.guest_addr, region.size, E820_RESERVED)?;
+    }
+
     LinuxBootConfigurator::write_bootparams(
         &BootParams::new(&params, GuestAddress(layout::ZERO_PAGE_START)),
         guest_mem,
@@ -217,6 +234,35 @@ fn add_e820_entry(
     Ok(())
 }
 
+/// Add e820 entries describing the RAM range [addr, addr + size), carving out the reserved memory
+/// regions, sorted by address, that fall inside it.
+fn add_e820_ram_entries(
+    params: &mut boot_params,
+    addr: u64,
+    size: u64,
+    reserved_memory: &[ReservedMemoryRegion],
+) -> Result<(), ConfigurationError> {
+    let end = addr + size;
+    let mut ram_start = addr;
+
+    for region in reserved_memory
+        .iter()
+        .filter(|region| addr <= region.guest_addr && region.end() <= end)
+    {
+        if region.guest_addr > ram_start {
+            add_e820_entry(params, ram_start, region.guest_addr - ram_start, E820_RAM)?;
+        }
+        add_e820_entry(params, region.guest_addr, region.size, E820_RESERVED)?;
+        ram_start = region.end();
+    }
+
+    if end > ram_start {
+        add_e820_entry(params, ram_start, end - ram_start, E820_RAM)?;
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use linux_loader::loader::bootparam::boot_e820_entry;
@@ -245,8 +291,15 @@ mod tests {
         let no_vcpus = 4;
         let gm = single_region_mem(0x10000);
         let mut resource_allocator = ResourceAllocator::new().unwrap();
-        let config_err =
-            configure_system(&gm, &mut resource_allocator, GuestAddress(0), 0, &None, 1);
+        let config_err = configure_system(
+            &gm,
+            &mut resource_allocator,
+            GuestAddress(0),
+            0,
+            &None,
+            1,
+            &[],
+        );
         assert_eq!(
             config_err.unwrap_err(),
             super::ConfigurationError::MpTableSetup(mptable::MptableError::NotEnoughMemory)
@@ -263,6 +316,7 @@ mod tests {
             0,
             &None,
             no_vcpus,
+            &[],
         )
         .unwrap();
 
@@ -277,6 +331,7 @@ mod tests {
             0,
             &None,
             no_vcpus,
+            &[],
         )
         .unwrap();
 
@@ -291,6 +346,7 @@ mod tests {
             0,
             &None,
             no_vcpus,
+            &[],
         )
         .unwrap();
     }
@@ -334,4 +390,42 @@ mod tests {
         )
         .is_err());
     }
+
+    #[test]
+    fn test_add_e820_ram_entries() {
+        let reserved_memory = [
+            ReservedMemoryRegion {
+                name: "first".to_string(),
+                guest_addr: 0x10_0000,
+                size: 0x1000,
+            },
+            ReservedMemoryRegion {
+                name: "second".to_string(),
+                guest_addr: 0x20_0000,
+                size: 0x2000,
+            },
+            ReservedMemoryRegion {
+                name: "outside".to_string(),
+                guest_addr: 0x1_0000_0000,
+                size: 0x1000,
+            },
+        ];
+
+        let mut params: boot_params = Default::default();
+        add_e820_ram_entries(&mut params, 0x10_0000, 0x30_0000, &reserved_memory).unwrap();
+
+        let entries: Vec<_> = params.e820_table[..params.e820_entries as usize]
+            .iter()
+            .map(|entry| (entry.addr, entry.size, entry.type_))
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                (0x10_0000, 0x1000, E820_RESERVED),
+                (0x10_1000, 0xf_f000, E820_RAM),
+                (0x20_0000, 0x2000, E820_RESERVED),
+                (0x20_2000, 0x1f_e000, E820_RAM),
+            ]
+        );
+    }
 }
@@ -0,0 +1,387 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Generation of the SMBIOS tables, which let the guest discover the identity of the microVM
+//! (e.g. through `dmidecode`).
+//!
+//! The tables are described by a SMBIOS 3.0 (64-bit) entry point, which the guest finds by
+//! scanning the legacy BIOS area for its anchor string.
+
+use log::debug;
+
+use super::layout::{SMBIOS_MAX_SIZE, SMBIOS_START};
+use crate::utils::u64_to_usize;
+use crate::vmm_config::smbios::{SmbiosConfig, SmbiosConfigError};
+use crate::vstate::memory::{Bytes, GuestAddress, GuestMemoryMmap};
+
+/// Errors thrown while writing the SMBIOS tables.
+#[derive(Debug, PartialEq, Eq, thiserror::Error, displaydoc::Display)]
+pub enum SmbiosError {
+    /// Invalid SMBIOS configuration: {0}
+    Config(#[from] SmbiosConfigError),
+    /// The SMBIOS tables are too large to fit in guest memory: {0} bytes.
+    TooLarge(usize),
+    /// Failure to write the SMBIOS tables to guest memory.
+    Write,
+}
+
+const SM3_ANCHOR: &[u8; 5] = b"_SM3_";
+const SM3_ENTRY_POINT_LENGTH: u8 = 0x18;
+const SM3_MAJOR_VERSION: u8 = 3;
+const SM3_MINOR_VERSION: u8 = 0;
+const SM3_ENTRY_POINT_REVISION: u8 = 1;
+// Offset of the structure table from the entry point, which is 16 bytes aligned.
+const SM3_TABLE_OFFSET: u64 = 0x20;
+
+const TYPE_BIOS_INFORMATION: u8 = 0;
+const TYPE_SYSTEM_INFORMATION: u8 = 1;
+const TYPE_SYSTEM_ENCLOSURE: u8 = 3;
+const TYPE_OEM_STRINGS: u8 = 11;
+const TYPE_END_OF_TABLE: u8 = 127;
+
+// BIOS characteristics: "BIOS Characteristics are not supported".
+const BIOS_CHARACTERISTICS_NOT_SUPPORTED: u64 = 1 << 3;
+// BIOS characteristics extension byte 2: "SMBIOS table describes a virtual machine".
+const BIOS_CHARACTERISTICS_EXT2_VIRTUAL_MACHINE: u8 = 1 << 4;
+const WAKE_UP_TYPE_POWER_SWITCH: u8 = 0x06;
+const CHASSIS_TYPE_OTHER: u8 = 0x01;
+const CHASSIS_STATE_SAFE: u8 = 0x03;
+const CHASSIS_SECURITY_STATUS_NONE: u8 = 0x03;
+
+const DEFAULT_MANUFACTURER: &str = "Firecracker";
+const DEFAULT_PRODUCT_NAME: &str = "Firecracker";
+const BIOS_VERSION: &str = "0";
+
+// A SMBIOS structure: its formatted area followed by its string set.
+struct Structure {
+    data: Vec<u8>,
+    strings: Vec<u8>,
+    num_strings: u8,
+}
+
+impl Structure {
+    fn new(type_: u8, length: u8, handle: u16) -> Self {
+        let mut data = Vec::with_capacity(length as usize);
+        data.push(type_);
+        data.push(length);
+        data.extend_from_slice(&handle.to_le_bytes());
+        Structure {
+            data,
+            strings: Vec::new(),
+            num_strings: 0,
+        }
+    }
+
+    fn u8(&mut self, value: u8) -> &mut Self {
+        self.data.push(value);
+        self
+    }
+
+    fn u16(&mut self, value: u16) -> &mut Self {
+        self.data.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    fn u32(&mut self, value: u32) -> &mut Self {
+        self.data.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    fn u64(&mut self, value: u64) -> &mut Self {
+        self.data.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    fn bytes(&mut self, value: &[u8]) -> &mut Self {
+        self.data.extend_from_slice(value);
+        self
+    }
+
+    // Adds a string to the string set and its index to the formatted area. Empty strings are
+    // reported with the index 0, which means that the string is not specified.
+    fn string(&mut self, value: Option<&str>) -> &mut Self {
+        match value {
+            Some(value) if !value.is_empty() => {
+                self.num_strings += 1;
+                self.strings.extend_from_slice(value.as_bytes());
+                self.strings.push(0);
+                self.data.push(self.num_strings);
+            }
+            _ => self.data.push(0),
+        }
+        self
+    }
+
+    fn write_to(&self, table: &mut Vec<u8>) {
+        debug_assert_eq!(self.data.len(), self.data[1] as usize);
+        table.extend_from_slice(&self.data);
+        table.extend_from_slice(&self.strings);
+        // The string set is terminated by an additional NUL, or by two NULs when it is empty.
+        if self.strings.is_empty() {
+            table.push(0);
+        }
+        table.push(0);
+    }
+}
+
+fn checksum(bytes: &[u8]) -> u8 {
+    let sum = bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+    (!sum).wrapping_add(1)
+}
+
+// Builds the SMBIOS structure table described by `config`.
+fn build_table(config: &SmbiosConfig) -> Result<Vec<u8>, SmbiosConfigError> {
+    let uuid = config.uuid()?;
+    let mut table = Vec::new();
+    let mut handle = 0u16;
+    let mut next_handle = || {
+        handle += 1;
+        handle - 1
+    };
+
+    Structure::new(TYPE_BIOS_INFORMATION, 0x14, next_handle())
+        // Vendor
+        .string(Some(DEFAULT_MANUFACTURER))
+        // BIOS version
+        .string(Some(BIOS_VERSION))
+        // BIOS starting address segment
+        .u16(0xe800)
+        // BIOS release date
+        .string(None)
+        // BIOS ROM size
+        .u8(0)
+        .u64(BIOS_CHARACTERISTICS_NOT_SUPPORTED)
+        .u8(0)
+        .u8(BIOS_CHARACTERISTICS_EXT2_VIRTUAL_MACHINE)
+        .write_to(&mut table);
+
+    // The UUID is encoded with its first three fields in little endian.
+    let mut uuid_bytes = uuid;
+    uuid_bytes[0..4].reverse();
+    uuid_bytes[4..6].reverse();
+    uuid_bytes[6..8].reverse();
+    Structure::new(TYPE_SYSTEM_INFORMATION, 0x1b, next_handle())
+        .string(Some(
+            config
+                .system_manufacturer
+                .as_deref()
+                .unwrap_or(DEFAULT_MANUFACTURER),
+        ))
+        .string(Some(
+            config
+                .system_product_name
+                .as_deref()
+                .unwrap_or(DEFAULT_PRODUCT_NAME),
+        ))
+        // Version
+        .string(None)
+        .string(config.system_serial_number.as_deref())
+        .bytes(&uuid_bytes)
+        .u8(WAKE_UP_TYPE_POWER_SWITCH)
+        // SKU number
+        .string(None)
+        // Family
+        .string(None)
+        .write_to(&mut table);
+
+    Structure::new(TYPE_SYSTEM_ENCLOSURE, 0x15, next_handle())
+        .string(Some(
+            config
+                .system_manufacturer
+                .as_deref()
+                .unwrap_or(DEFAULT_MANUFACTURER),
+        ))
+        .u8(CHASSIS_TYPE_OTHER)
+        // Version
+        .string(None)
+        .string(config.chassis_serial_number.as_deref())
+        .string(config.chassis_asset_tag.as_deref())
+        // Boot-up, power supply and thermal states
+        .u8(CHASSIS_STATE_SAFE)
+        .u8(CHASSIS_STATE_SAFE)
+        .u8(CHASSIS_STATE_SAFE)
+        .u8(CHASSIS_SECURITY_STATUS_NONE)
+        // OEM-defined
+        .u32(0)
+        // Height, number of power cords, contained element count and record length
+        .u8(0)
+        .u8(0)
+        .u8(0)
+        .u8(0)
+        .write_to(&mut table);
+
+    if !config.oem_strings.is_empty() {
+        let count = u8::try_from(config.oem_strings.len())
+            .map_err(|_| SmbiosConfigError::TooManyOemStrings)?;
+        let mut oem_strings = Structure::new(TYPE_OEM_STRINGS, 0x05, next_handle());
+        oem_strings.u8(count);
+        for string in &config.oem_strings {
+            // Empty strings can not be represented in a string set, a single space is used
+            // instead so that the count stays accurate.
+            let string = if string.is_empty() { " " } else { string };
+            oem_strings.num_strings += 1;
+            oem_strings.strings.extend_from_slice(string.as_bytes());
+            oem_strings.strings.push(0);
+        }
+        oem_strings.write_to(&mut table);
+    }
+
+    Structure::new(TYPE_END_OF_TABLE, 0x04, next_handle()).write_to(&mut table);
+
+    Ok(table)
+}
+
+/// Writes the SMBIOS entry point and structure table described by `config` in guest memory.
+pub fn setup_smbios(mem: &GuestMemoryMmap, config: &SmbiosConfig) -> Result<(), SmbiosError> {
+    let table = build_table(config)?;
+    let table_addr = SMBIOS_START + SM3_TABLE_OFFSET;
+    if u64_to_usize(SM3_TABLE_OFFSET) + table.len() > u64_to_usize(SMBIOS_MAX_SIZE) {
+        return Err(SmbiosError::TooLarge(table.len()));
+    }
+
+    let mut entry_point = Vec::with_capacity(SM3_ENTRY_POINT_LENGTH as usize);
+    entry_point.extend_from_slice(SM3_ANCHOR);
+    // Checksum, computed below.
+    entry_point.push(0);
+    entry_point.push(SM3_ENTRY_POINT_LENGTH);
+    entry_point.push(SM3_MAJOR_VERSION);
+    entry_point.push(SM3_MINOR_VERSION);
+    // Docrev
+    entry_point.push(0);
+    entry_point.push(SM3_ENTRY_POINT_REVISION);
+    // Reserved
+    entry_point.push(0);
+    // The size was checked against SMBIOS_MAX_SIZE, so it fits in 32 bits.
+    entry_point.extend_from_slice(&u32::try_from(table.len()).unwrap().to_le_bytes());
+    entry_point.extend_from_slice(&table_addr.to_le_bytes());
+    entry_point[5] = checksum(&entry_point);
+
+    mem.write_slice(&entry_point, GuestAddress(SMBIOS_START))
+        .map_err(|_| SmbiosError::Write)?;
+    mem.write_slice(&table, GuestAddress(table_addr))
+        .map_err(|_| SmbiosError::Write)?;
+    debug!(
+        "smbios: Wrote {} bytes of SMBIOS tables at address {:#010x}",
+        table.len(),
+        table_addr
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::single_region_mem;
+
+    // Returns the strings of the structure starting at `offset` in `table`, and the offset of
+    // the next structure.
+    fn read_strings(table: &[u8], offset: usize) -> (Vec<String>, usize) {
+        let mut pos = offset + table[offset + 1] as usize;
+        let mut strings = Vec::new();
+        if table[pos] == 0 {
+            return (strings, pos + 2);
+        }
+        while table[pos] != 0 {
+            let end = pos + table[pos..].iter().position(|byte| *byte == 0).unwrap();
+            strings.push(String::from_utf8(table[pos..end].to_vec()).unwrap());
+            pos = end + 1;
+        }
+        (strings, pos + 1)
+    }
+
+    #[test]
+    fn test_build_table() {
+        let config = SmbiosConfig {
+            system_serial_number: Some("system-serial".to_string()),
+            system_uuid: Some("00112233-4455-6677-8899-aabbccddeeff".to_string()),
+            chassis_asset_tag: Some("asset-tag".to_string()),
+            oem_strings: vec!["oem-1".to_string(), String::new()],
+            ..Default::default()
+        };
+        let table = build_table(&config).unwrap();
+
+        // BIOS information.
+        assert_eq!(table[0], TYPE_BIOS_INFORMATION);
+        let (strings, offset) = read_strings(&table, 0);
+        assert_eq!(strings, ["Firecracker", "0"]);
+
+        // System information.
+        assert_eq!(table[offset], TYPE_SYSTEM_INFORMATION);
+        assert_eq!(&table[offset + 2..offset + 4], &[1, 0]);
+        // Manufacturer, product name, version and serial number.
+        assert_eq!(&table[offset + 4..offset + 8], &[1, 2, 0, 3]);
+        assert_eq!(
+            &table[offset + 8..offset + 24],
+            &[
+                0x33, 0x22, 0x11, 0x00, 0x55, 0x44, 0x77, 0x66, 0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd,
+                0xee, 0xff
+            ]
+        );
+        let (strings, offset) = read_strings(&table, offset);
+        assert_eq!(strings, ["Firecracker", "Firecracker", "system-serial"]);
+
+        // System enclosure.
+        assert_eq!(table[offset], TYPE_SYSTEM_ENCLOSURE);
+        // Manufacturer, type, version, serial number and asset tag.
+        assert_eq!(&table[offset + 4..offset + 9], &[1, 1, 0, 0, 2]);
+        let (strings, offset) = read_strings(&table, offset);
+        assert_eq!(strings, ["Firecracker", "asset-tag"]);
+
+        // OEM strings.
+        assert_eq!(table[offset], TYPE_OEM_STRINGS);
+        assert_eq!(table[offset + 4], 2);
+        let (strings, offset) = read_strings(&table, offset);
+        assert_eq!(strings, ["oem-1", " "]);
+
+        // End of table.
+        assert_eq!(table[offset], TYPE_END_OF_TABLE);
+        assert_eq!(&table[offset + 2..offset + 4], &[4, 0]);
+        let (strings, offset) = read_strings(&table, offset);
+        assert!(strings.is_empty());
+        assert_eq!(offset, table.len());
+    }
+
+    #[test]
+    fn test_setup_smbios() {
+        let mem = single_region_mem(0x10_0000);
+        let config = SmbiosConfig::default();
+        setup_smbios(&mem, &config).unwrap();
+
+        let mut entry_point = [0u8; SM3_ENTRY_POINT_LENGTH as usize];
+        mem.read_slice(&mut entry_point, GuestAddress(SMBIOS_START))
+            .unwrap();
+        assert_eq!(&entry_point[0..5], SM3_ANCHOR);
+        assert_eq!(
+            entry_point
+                .iter()
+                .fold(0u8, |sum, byte| sum.wrapping_add(*byte)),
+            0
+        );
+        let table_len = u32::from_le_bytes(entry_point[12..16].try_into().unwrap());
+        let table_addr = u64::from_le_bytes(entry_point[16..24].try_into().unwrap());
+        assert_eq!(table_addr, SMBIOS_START + SM3_TABLE_OFFSET);
+
+        let mut table = vec![0u8; table_len as usize];
+        mem.read_slice(&mut table, GuestAddress(table_addr))
+            .unwrap();
+        assert_eq!(table, build_table(&config).unwrap());
+
+        // The tables must fit in the legacy BIOS area.
+        let config = SmbiosConfig {
+            oem_strings: vec!["x".repeat(1024); 64],
+            ..Default::default()
+        };
+        assert!(matches!(
+            setup_smbios(&mem, &config),
+            Err(SmbiosError::TooLarge(_))
+        ));
+
+        // Without enough guest memory, the tables can not be written.
+        let mem = single_region_mem(0x1000);
+        assert_eq!(
+            setup_smbios(&mem, &SmbiosConfig::default()),
+            Err(SmbiosError::Write)
+        );
+    }
+}
@@ -0,0 +1,453 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Background snapshots of the guest memory.
+//!
+//! A background snapshot write-protects the guest memory with userfaultfd, so the microVM can be
+//! resumed as soon as its state is saved instead of once its whole memory is written. The memory
+//! file is then written by the `fc_snapshot` thread, which copies each page the guest is about to
+//! change before letting the write through: the memory file thus holds the guest memory as it
+//! was when the snapshot was created.
+
+use std::fs::File;
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use seccompiler::BpfProgram;
+use serde::Serialize;
+use userfaultfd::{Event, FeatureFlags, RegisterMode, Uffd, UffdBuilder};
+use vmm_sys_util::errno;
+
+use crate::logger::{error, info, warn};
+use crate::utils::{get_page_size, u64_to_usize};
+use crate::vmm_config::machine_config::HugePageConfig;
+use crate::vstate::memory::{
+    GuestMemory, GuestMemoryMmap, GuestMemoryRegion, GuestRegionMmap, MemoryRegionAddress,
+};
+
+/// Amount of guest memory written at once, between two checks for pages the guest is about to
+/// change.
+const CHUNK_SIZE: usize = 1 << 20;
+
+/// Errors associated with background snapshots.
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum BackgroundSnapshotError {
+    /// Another background snapshot is in progress.
+    InProgress,
+    /// Background snapshots can only be full snapshots.
+    DiffSnapshot,
+    /// Background snapshots cannot be encrypted.
+    Encrypted,
+    /// Background snapshots can only be written to local memory files.
+    RemoteStorage,
+    /// Background snapshots are not supported for microVMs restored with a Uffd memory backend.
+    Uffd,
+    /// Background snapshots require guest memory which is not backed by a file, e.g. shared with
+    /// vhost-user backends or mapped from a memory file.
+    FileBackedMemory,
+    /// The snapshot writer thread is not running.
+    NoWriter,
+    /// Cannot fetch the page size: {0}
+    PageSize(errno::Error),
+    /// Cannot create the userfaultfd: {0}
+    CreateUffd(userfaultfd::Error),
+    /// Cannot write-protect the guest memory: {0}
+    WriteProtect(userfaultfd::Error),
+    /// Cannot read the guest memory: {0}
+    ReadMemory(vm_memory::GuestMemoryError),
+    /// Cannot read the pages the guest is about to change: {0}
+    ReadEvent(userfaultfd::Error),
+    /// Cannot write the memory file: {0}
+    WriteFile(io::Error),
+    /// The snapshot writer thread exited.
+    WriterExited,
+}
+
+/// State of the last background snapshot.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackgroundSnapshotState {
+    /// No background snapshot was created.
+    #[default]
+    None,
+    /// The memory file is being written.
+    InProgress,
+    /// The memory file is complete.
+    Done,
+    /// The memory file could not be written.
+    Failed,
+}
+
+/// Progress of the last background snapshot.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct BackgroundSnapshotStatus {
+    /// State of the snapshot.
+    pub state: BackgroundSnapshotState,
+    /// Path of the memory file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mem_file_path: Option<PathBuf>,
+    /// Size of the guest memory, in bytes.
+    pub total_bytes: u64,
+    /// Amount of guest memory written to the memory file, in bytes.
+    pub written_bytes: u64,
+    /// Number of pages copied before the guest changed them.
+    pub copied_pages: u64,
+    /// Error which made the snapshot fail.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Handle of the thread writing the memory files of background snapshots.
+///
+/// The thread is spawned before the seccomp filters of the VMM thread are installed, as these
+/// do not allow creating threads, and waits for snapshots to write.
+#[derive(Debug)]
+pub struct SnapshotWriter {
+    jobs: Sender<Job>,
+    status: Arc<Mutex<BackgroundSnapshotStatus>>,
+}
+
+impl SnapshotWriter {
+    /// Spawns the snapshot writer thread, which installs `seccomp_filter` before waiting for
+    /// snapshots to write.
+    pub fn spawn(seccomp_filter: Arc<BpfProgram>) -> io::Result<Self> {
+        let (jobs, receiver) = channel::<Job>();
+        let status = Arc::new(Mutex::new(BackgroundSnapshotStatus::default()));
+        let thread_status = status.clone();
+
+        thread::Builder::new()
+            .name("fc_snapshot".to_string())
+            .spawn(move || {
+                // Execution panics if filters cannot be loaded, use --no-seccomp if skipping
+                // filters altogether is the desired behaviour.
+                if let Err(err) = seccompiler::apply_filter(&seccomp_filter) {
+                    panic!(
+                        "Failed to set the requested seccomp filters on the snapshot writer \
+                         thread: Error: {err}"
+                    );
+                }
+                while let Ok(mut job) = receiver.recv() {
+                    let result = job.run(&thread_status);
+                    job.release();
+                    let mut status = thread_status.lock().expect("Poisoned lock");
+                    match result {
+                        Ok(()) => {
+                            info!(
+                                "Background snapshot written to {:?}, after copying {} pages \
+                                 changed by the guest.",
+                                job.mem_file_path, status.copied_pages
+                            );
+                            status.state = BackgroundSnapshotState::Done;
+                        }
+                        Err(err) => {
+                            error!("Background snapshot failed: {err}");
+                            status.state = BackgroundSnapshotState::Failed;
+                            status.error = Some(err.to_string());
+                        }
+                    }
+                }
+            })?;
+
+        Ok(SnapshotWriter { jobs, status })
+    }
+
+    /// Returns the progress of the last background snapshot.
+    pub fn status(&self) -> BackgroundSnapshotStatus {
+        self.status.lock().expect("Poisoned lock").clone()
+    }
+
+    /// Write-protects `guest_memory` and hands it over to the snapshot writer thread, which
+    /// writes it to `file` at `mem_file_path`. The file must be empty and of the size of the
+    /// guest memory, so that the pages holding only zeros can be skipped.
+    ///
+    /// The microVM must be paused, and can be resumed as soon as this returns.
+    pub fn start(
+        &self,
+        guest_memory: &GuestMemoryMmap,
+        huge_pages: HugePageConfig,
+        file: File,
+        mem_file_path: &Path,
+    ) -> Result<(), BackgroundSnapshotError> {
+        use self::BackgroundSnapshotError::*;
+
+        if self.status().state == BackgroundSnapshotState::InProgress {
+            return Err(InProgress);
+        }
+        check_guest_memory(guest_memory)?;
+        let page_size = match huge_pages {
+            HugePageConfig::None => get_page_size().map_err(PageSize)?,
+            _ => huge_pages.page_size_kib(),
+        };
+
+        // The write faults of KVM, which happen in kernel mode, must be reported as well.
+        let uffd = UffdBuilder::new()
+            .close_on_exec(true)
+            .non_blocking(true)
+            .user_mode_only(false)
+            .require_features(FeatureFlags::PAGEFAULT_FLAG_WP)
+            .create()
+            .map_err(CreateUffd)?;
+
+        for region in guest_memory.iter() {
+            let (start, len) = (region.as_ptr(), u64_to_usize(region.len()));
+            uffd.register_with_mode(start.cast(), len, RegisterMode::WRITE_PROTECT)
+                .map_err(WriteProtect)?;
+            // Pages which were never accessed are not mapped, and cannot be write-protected
+            // until they are mapped to the zero page by reading them.
+            for offset in (0..len).step_by(page_size) {
+                // SAFETY: `offset` is within the region, which stays mapped as long as
+                // `guest_memory` is borrowed.
+                let _: u8 = unsafe { start.add(offset).read_volatile() };
+            }
+            uffd.write_protect(start.cast(), len)
+                .map_err(WriteProtect)?;
+        }
+
+        // The status is updated before handing over the job, which updates it in turn.
+        *self.status.lock().expect("Poisoned lock") = BackgroundSnapshotStatus {
+            state: BackgroundSnapshotState::InProgress,
+            mem_file_path: Some(mem_file_path.to_path_buf()),
+            total_bytes: guest_memory.iter().map(|region| region.len()).sum(),
+            ..Default::default()
+        };
+        let job = Job {
+            uffd,
+            guest_memory: guest_memory.clone(),
+            file,
+            mem_file_path: mem_file_path.to_path_buf(),
+            page_size,
+            written: Vec::new(),
+            buf: Vec::new(),
+        };
+        // Dropping the job on failure closes the userfaultfd, which lifts the write protection.
+        self.jobs.send(job).map_err(|_| {
+            let mut status = self.status.lock().expect("Poisoned lock");
+            status.state = BackgroundSnapshotState::Failed;
+            status.error = Some(WriterExited.to_string());
+            WriterExited
+        })
+    }
+}
+
+/// Checks that `guest_memory` can be write-protected for a background snapshot. Writes to shared
+/// memory through other mappings, e.g. by vhost-user backends, are not reported by the
+/// userfaultfd, so guest memory must not be backed by a file.
+pub fn check_guest_memory(guest_memory: &GuestMemoryMmap) -> Result<(), BackgroundSnapshotError> {
+    if guest_memory
+        .iter()
+        .any(|region| region.file_offset().is_some())
+    {
+        return Err(BackgroundSnapshotError::FileBackedMemory);
+    }
+    Ok(())
+}
+
+/// Background snapshot handed over to the snapshot writer thread.
+struct Job {
+    uffd: Uffd,
+    guest_memory: GuestMemoryMmap,
+    file: File,
+    mem_file_path: PathBuf,
+    page_size: usize,
+    /// Whether each page of each region was written to the memory file.
+    written: Vec<Vec<bool>>,
+    buf: Vec<u8>,
+}
+
+impl Job {
+    /// Writes the guest memory to the memory file, chunk by chunk, copying the pages the guest
+    /// is about to change in between.
+    fn run(
+        &mut self,
+        status: &Mutex<BackgroundSnapshotStatus>,
+    ) -> Result<(), BackgroundSnapshotError> {
+        use self::BackgroundSnapshotError::*;
+
+        let chunk_size = CHUNK_SIZE.max(self.page_size);
+        self.buf = vec![0u8; chunk_size];
+        self.written = self
+            .guest_memory
+            .iter()
+            .map(|region| vec![false; u64_to_usize(region.len()) / self.page_size])
+            .collect();
+
+        let guest_memory = self.guest_memory.clone();
+        let mut region_file_offset = 0u64;
+        for (region_index, region) in guest_memory.iter().enumerate() {
+            let region_len = u64_to_usize(region.len());
+            let mut chunk_offset = 0;
+
+            while chunk_offset < region_len {
+                self.copy_faulting_pages(status)?;
+
+                // The pages not written yet are still write-protected, so the guest did not
+                // change them since the snapshot was created.
+                let chunk_len = (region_len - chunk_offset).min(chunk_size);
+                let mut buf = std::mem::take(&mut self.buf);
+                region
+                    .read_slice(
+                        &mut buf[..chunk_len],
+                        MemoryRegionAddress(chunk_offset as u64),
+                    )
+                    .map_err(ReadMemory)?;
+                for (index, page) in buf[..chunk_len].chunks(self.page_size).enumerate() {
+                    let page_index = chunk_offset / self.page_size + index;
+                    let written = &mut self.written[region_index][page_index];
+                    if !*written {
+                        let file_offset =
+                            region_file_offset + (chunk_offset + index * self.page_size) as u64;
+                        write_page(&mut self.file, file_offset, page)?;
+                        *written = true;
+                    }
+                }
+                self.buf = buf;
+
+                // SAFETY: the chunk is within the region, which stays mapped as long as
+                // `guest_memory` lives.
+                let chunk_start = unsafe { region.as_ptr().add(chunk_offset) };
+                self.uffd
+                    .remove_write_protection(chunk_start.cast(), chunk_len, true)
+                    .map_err(WriteProtect)?;
+
+                chunk_offset += chunk_len;
+                status.lock().expect("Poisoned lock").written_bytes += chunk_len as u64;
+            }
+            region_file_offset += region.len();
+        }
+
+        self.file.flush().map_err(WriteFile)?;
+        self.file.sync_all().map_err(WriteFile)
+    }
+
+    /// Writes the pages the guest is about to change, and lets their writes through.
+    fn copy_faulting_pages(
+        &mut self,
+        status: &Mutex<BackgroundSnapshotStatus>,
+    ) -> Result<(), BackgroundSnapshotError> {
+        use self::BackgroundSnapshotError::*;
+
+        let guest_memory = self.guest_memory.clone();
+        while let Some(event) = self.uffd.read_event().map_err(ReadEvent)? {
+            let Event::Pagefault { addr, .. } = event else {
+                continue;
+            };
+            let addr = addr as usize;
+            let mut region_file_offset = 0u64;
+            for (region_index, region) in guest_memory.iter().enumerate() {
+                let start = region.as_ptr() as usize;
+                if !(start..start + u64_to_usize(region.len())).contains(&addr) {
+                    region_file_offset += region.len();
+                    continue;
+                }
+                let page_offset = (addr - start) / self.page_size * self.page_size;
+                let page_index = page_offset / self.page_size;
+                if !self.written[region_index][page_index] {
+                    copy_page(region, page_offset, &mut self.buf[..self.page_size])?;
+                    write_page(
+                        &mut self.file,
+                        region_file_offset + page_offset as u64,
+                        &self.buf[..self.page_size],
+                    )?;
+                    self.written[region_index][page_index] = true;
+                    status.lock().expect("Poisoned lock").copied_pages += 1;
+                }
+                // SAFETY: the page is within the region, which stays mapped as long as
+                // `guest_memory` lives.
+                let page_start = unsafe { region.as_ptr().add(page_offset) };
+                self.uffd
+                    .remove_write_protection(page_start.cast(), self.page_size, true)
+                    .map_err(WriteProtect)?;
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Unregisters the guest memory from the userfaultfd, which lifts the write protection left
+    /// if the snapshot failed and wakes up the threads waiting for it.
+    fn release(&self) {
+        for region in self.guest_memory.iter() {
+            if let Err(err) = self
+                .uffd
+                .unregister(region.as_ptr().cast(), u64_to_usize(region.len()))
+            {
+                warn!("Cannot unregister the guest memory from the userfaultfd: {err}");
+            }
+        }
+    }
+}
+
+fn copy_page(
+    region: &GuestRegionMmap,
+    page_offset: usize,
+    buf: &mut [u8],
+) -> Result<(), BackgroundSnapshotError> {
+    region
+        .read_slice(buf, MemoryRegionAddress(page_offset as u64))
+        .map_err(BackgroundSnapshotError::ReadMemory)
+}
+
+/// Writes `page` at `offset` of the memory file, unless it only holds zeros: the memory file
+/// is empty when the snapshot starts, so these already read as zeros.
+fn write_page(file: &mut File, offset: u64, page: &[u8]) -> Result<(), BackgroundSnapshotError> {
+    if page.iter().all(|&byte| byte == 0) {
+        return Ok(());
+    }
+    file.seek(SeekFrom::Start(offset))
+        .and_then(|_| file.write_all(page))
+        .map_err(BackgroundSnapshotError::WriteFile)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use vmm_sys_util::tempfile::TempFile;
+
+    use super::*;
+    use crate::vstate::memory::{GuestAddress, GuestMemoryExtension};
+
+    #[test]
+    fn test_write_page() {
+        let mut file = TempFile::new().unwrap().into_file();
+        file.set_len(8192).unwrap();
+
+        write_page(&mut file, 0, &[0u8; 4096]).unwrap();
+        write_page(&mut file, 4096, &[1u8; 4096]).unwrap();
+
+        let mut contents = Vec::new();
+        file.seek(SeekFrom::Start(0)).unwrap();
+        file.read_to_end(&mut contents).unwrap();
+        assert!(contents[..4096].iter().all(|&byte| byte == 0));
+        assert!(contents[4096..].iter().all(|&byte| byte == 1));
+    }
+
+    #[test]
+    fn test_start_checks() {
+        let writer = SnapshotWriter::spawn(Arc::new(BpfProgram::new())).unwrap();
+        assert_eq!(writer.status(), BackgroundSnapshotStatus::default());
+
+        // Guest memory backed by a file cannot be write-protected.
+        let page_size = get_page_size().unwrap();
+        let guest_memory = GuestMemoryMmap::memfd_backed(
+            &[(GuestAddress(0), page_size)],
+            false,
+            HugePageConfig::None,
+        )
+        .unwrap();
+        let file = TempFile::new().unwrap();
+        let err = writer
+            .start(
+                &guest_memory,
+                HugePageConfig::None,
+                file.as_file().try_clone().unwrap(),
+                file.as_path(),
+            )
+            .unwrap_err();
+        assert!(matches!(err, BackgroundSnapshotError::FileBackedMemory));
+        assert_eq!(writer.status().state, BackgroundSnapshotState::None);
+    }
+}
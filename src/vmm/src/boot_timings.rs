@@ -0,0 +1,169 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Timestamps of the phases of the boot of the microVM, to track the boot latency.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde::{Deserialize, Serialize};
+use utils::time::{get_time_us, ClockType};
+
+use crate::logger::{SharedStoreMetric, StoreMetric, METRICS};
+
+/// The phases of the boot of the microVM, in the order in which they are reached.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BootPhase {
+    /// The microVM is configured, and its start was requested.
+    ConfigDone,
+    /// The kernel, or the firmware, is loaded in the guest memory.
+    KernelLoaded,
+    /// The devices of the microVM are created.
+    DevicesCreated,
+    /// A vCPU is about to run for the first time.
+    FirstVcpuRun,
+    /// The guest wrote the boot complete magic value to the boot timer device.
+    GuestBootComplete,
+}
+
+impl BootPhase {
+    const ALL: [BootPhase; 5] = [
+        BootPhase::ConfigDone,
+        BootPhase::KernelLoaded,
+        BootPhase::DevicesCreated,
+        BootPhase::FirstVcpuRun,
+        BootPhase::GuestBootComplete,
+    ];
+
+    fn metric(self) -> &'static SharedStoreMetric {
+        let metrics = &METRICS.boot_timings;
+        match self {
+            BootPhase::ConfigDone => &metrics.config_done_us,
+            BootPhase::KernelLoaded => &metrics.kernel_loaded_us,
+            BootPhase::DevicesCreated => &metrics.devices_created_us,
+            BootPhase::FirstVcpuRun => &metrics.first_vcpu_run_us,
+            BootPhase::GuestBootComplete => &metrics.guest_boot_complete_us,
+        }
+    }
+}
+
+/// Timestamps of the boot phases of the microVM, shared by the threads reaching them.
+pub static BOOT_TIMINGS: BootTimings = BootTimings::new();
+
+// Timestamp of the phases which are not reached yet.
+const NOT_REACHED: u64 = u64::MAX;
+
+/// Timestamps of the boot phases, in microseconds since the start of the process. Only the first
+/// time a phase is reached is recorded.
+#[derive(Debug)]
+pub struct BootTimings {
+    // Monotonic time of the start of the process, in microseconds.
+    start_us: AtomicU64,
+    phases_us: [AtomicU64; BootPhase::ALL.len()],
+}
+
+impl BootTimings {
+    const fn new() -> Self {
+        BootTimings {
+            start_us: AtomicU64::new(0),
+            phases_us: [const { AtomicU64::new(NOT_REACHED) }; BootPhase::ALL.len()],
+        }
+    }
+
+    /// Sets the monotonic time in microseconds of the start of the process, from which the
+    /// phases are timed.
+    pub fn set_start(&self, start_us: u64) {
+        self.start_us.store(start_us, Ordering::Relaxed);
+    }
+
+    /// Records that `phase` is reached now, unless it was reached before.
+    pub fn record(&self, phase: BootPhase) {
+        let elapsed_us =
+            get_time_us(ClockType::Monotonic).saturating_sub(self.start_us.load(Ordering::Relaxed));
+        if self.phases_us[phase as usize]
+            .compare_exchange(
+                NOT_REACHED,
+                elapsed_us,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            )
+            .is_ok()
+        {
+            phase.metric().store(elapsed_us);
+        }
+    }
+
+    /// Returns the time in microseconds since the start of the process at which `phase` was
+    /// reached, if it was.
+    pub fn get(&self, phase: BootPhase) -> Option<u64> {
+        Some(self.phases_us[phase as usize].load(Ordering::Relaxed))
+            .filter(|elapsed_us| *elapsed_us != NOT_REACHED)
+    }
+
+    /// Returns the timestamps of the phases reached so far.
+    pub fn info(&self) -> BootTimingsInfo {
+        BootTimingsInfo {
+            config_done_us: self.get(BootPhase::ConfigDone),
+            kernel_loaded_us: self.get(BootPhase::KernelLoaded),
+            devices_created_us: self.get(BootPhase::DevicesCreated),
+            first_vcpu_run_us: self.get(BootPhase::FirstVcpuRun),
+            guest_boot_complete_us: self.get(BootPhase::GuestBootComplete),
+        }
+    }
+}
+
+/// Serializable timestamps of the boot phases of the microVM, in microseconds since the start of
+/// the process. The phases which are not reached yet are left out.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct BootTimingsInfo {
+    /// The microVM is configured, and its start was requested.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config_done_us: Option<u64>,
+    /// The kernel, or the firmware, is loaded in the guest memory.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kernel_loaded_us: Option<u64>,
+    /// The devices of the microVM are created.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub devices_created_us: Option<u64>,
+    /// A vCPU is about to run for the first time.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub first_vcpu_run_us: Option<u64>,
+    /// The guest wrote the boot complete magic value to the boot timer device.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub guest_boot_complete_us: Option<u64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_boot_timings() {
+        // The global timings are shared with the other tests, which may boot microVMs.
+        let timings = BootTimings::new();
+        assert_eq!(timings.info(), BootTimingsInfo::default());
+
+        timings.set_start(get_time_us(ClockType::Monotonic));
+        timings.record(BootPhase::KernelLoaded);
+        let kernel_loaded_us = timings.get(BootPhase::KernelLoaded).unwrap();
+        assert!(kernel_loaded_us < 1_000_000);
+        assert_eq!(timings.get(BootPhase::ConfigDone), None);
+
+        // Only the first time a phase is reached is recorded.
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        timings.record(BootPhase::KernelLoaded);
+        assert_eq!(timings.get(BootPhase::KernelLoaded), Some(kernel_loaded_us));
+
+        timings.record(BootPhase::GuestBootComplete);
+        let info = timings.info();
+        assert_eq!(info.kernel_loaded_us, Some(kernel_loaded_us));
+        assert!(info.guest_boot_complete_us.unwrap() >= kernel_loaded_us + 2000);
+        assert_eq!(
+            serde_json::to_value(&info)
+                .unwrap()
+                .as_object()
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+}
@@ -6,12 +6,15 @@
 #[cfg(target_arch = "x86_64")]
 use std::convert::TryFrom;
 use std::fmt::Debug;
+use std::fs::OpenOptions;
 use std::io::{self, Seek, SeekFrom};
 #[cfg(feature = "gdb")]
 use std::sync::mpsc;
 use std::sync::{Arc, Mutex};
+use std::thread;
 
 use event_manager::{MutEventSubscriber, SubscriberOps};
+use kvm_ioctls::VmFd;
 use libc::EFD_NONBLOCK;
 use linux_loader::cmdline::Cmdline as LoaderKernelCmdline;
 #[cfg(target_arch = "x86_64")]
@@ -19,7 +22,8 @@ use linux_loader::loader::elf::Elf as Loader;
 #[cfg(target_arch = "aarch64")]
 use linux_loader::loader::pe::PE as Loader;
 use linux_loader::loader::KernelLoader;
-use seccompiler::BpfThreadMap;
+use seccompiler::{BpfProgram, BpfThreadMap};
+use timerfd::{ClockId, TimerFd};
 use userfaultfd::Uffd;
 use utils::time::TimestampUs;
 use vm_memory::ReadVolatile;
@@ -28,28 +32,30 @@ use vm_superio::Rtc;
 use vm_superio::Serial;
 use vmm_sys_util::eventfd::EventFd;
 
-#[cfg(target_arch = "x86_64")]
-use crate::acpi;
 use crate::arch::InitrdConfig;
+use crate::boot_timings::{BootPhase, BOOT_TIMINGS};
 #[cfg(target_arch = "aarch64")]
 use crate::construct_kvm_mpidrs;
 use crate::cpu_config::templates::{
-    CpuConfiguration, CustomCpuTemplate, GetCpuTemplate, GetCpuTemplateError, GuestConfigError,
-    KvmCapability,
+    CpuConfiguration, CpuTemplateReport, CustomCpuTemplate, GetCpuTemplate, GetCpuTemplateError,
+    GuestConfigError,
 };
 use crate::device_manager::acpi::ACPIDeviceManager;
 #[cfg(target_arch = "x86_64")]
 use crate::device_manager::legacy::PortIODeviceManager;
-use crate::device_manager::mmio::MMIODeviceManager;
+use crate::device_manager::mmio::{DeferredVirtioEvents, MMIODeviceManager};
 use crate::device_manager::persist::{
     ACPIDeviceManagerConstructorArgs, ACPIDeviceManagerRestoreError, MMIODevManagerConstructorArgs,
 };
 use crate::device_manager::resources::ResourceAllocator;
+use crate::devices::acpi::power_button::{PowerButton, PowerButtonError};
 use crate::devices::acpi::vmgenid::{VmGenId, VmGenIdError};
-use crate::devices::legacy::serial::SerialOut;
+use crate::devices::legacy::serial::{SerialBackend, SerialOut};
 #[cfg(target_arch = "aarch64")]
 use crate::devices::legacy::RTCDevice;
 use crate::devices::legacy::{EventFdTrigger, SerialEventsWrapper, SerialWrapper};
+use crate::devices::pseudo::PvPanic;
+use crate::devices::tpm::Swtpm;
 use crate::devices::virtio::balloon::Balloon;
 use crate::devices::virtio::block::device::Block;
 use crate::devices::virtio::device::VirtioDevice;
@@ -58,32 +64,54 @@ use crate::devices::virtio::net::Net;
 use crate::devices::virtio::rng::Entropy;
 use crate::devices::virtio::vsock::{Vsock, VsockUnixBackend};
 use crate::devices::BusDevice;
+use crate::event_stream::{self, VmEvent};
 #[cfg(feature = "gdb")]
 use crate::gdb;
-use crate::logger::{debug, error};
-use crate::persist::{MicrovmState, MicrovmStateError};
+use crate::landlock::{LandlockError, LANDLOCK};
+use crate::logger::{debug, error, IncMetric, METRICS};
+use crate::persist::{MicrovmState, MicrovmStateError, VmInfo};
+use crate::rate_limiter::persist::restore_shared_rate_limiters;
 use crate::resources::VmResources;
+use crate::seccomp_filters::{get_deferred_devices_filter, get_tcp_denying_filter};
 use crate::snapshot::Persist;
-use crate::utils::u64_to_usize;
-use crate::vmm_config::boot_source::BootConfig;
+use crate::utils::{u64_to_usize, usize_to_u64};
+use crate::vmm_config::boot_source::{BootConfig, ImageDigestError};
 use crate::vmm_config::instance_info::InstanceInfo;
-use crate::vmm_config::machine_config::{VmConfig, VmConfigError};
-use crate::vstate::memory::{GuestAddress, GuestMemory, GuestMemoryMmap};
+use crate::vmm_config::lifecycle_hooks::LifecycleEvent;
+use crate::vmm_config::machine_config::{
+    LegacyDevice, MemoryBackend, ReservedMemoryRegion, VmConfig, VmConfigError,
+};
+use crate::vmm_config::pvpanic::PvPanicConfig;
+use crate::vmm_config::rate_limiters::shared_rate_limiter_configs;
+use crate::vmm_config::serial::SerialMode;
+#[cfg(target_arch = "x86_64")]
+use crate::vmm_config::serial::SerialPortConfig;
+use crate::vmm_config::shared_memory::SharedMemoryConfigError;
+use crate::vmm_config::snapshot::{CreateSnapshotParams, SnapshotType};
+use crate::vmm_config::tpm::TpmConfig;
+use crate::vstate::memory::{
+    Bytes, GuestAddress, GuestMemory, GuestMemoryExtension, GuestMemoryMmap,
+};
+use crate::vstate::vcpu::limiter::CpuLimiter;
 use crate::vstate::vcpu::{Vcpu, VcpuConfig, VcpuError};
 use crate::vstate::vm::Vm;
-use crate::{device_manager, EventManager, Vmm, VmmError};
+use crate::{acpi, device_manager, EventManager, PanicSnapshot, Vmm, VmmError};
 
 /// Errors associated with starting the instance.
 #[derive(Debug, thiserror::Error, displaydoc::Display)]
 pub enum StartMicrovmError {
     /// Unable to attach block device to Vmm: {0}
     AttachBlockDevice(io::Error),
+    /// Unable to attach the power button device: {0}
+    AttachPowerButtonDevice(kvm_ioctls::Error),
     /// Unable to attach the VMGenID device: {0}
     AttachVmgenidDevice(kvm_ioctls::Error),
     /// System configuration error: {0}
     ConfigureSystem(crate::arch::ConfigurationError),
     /// Failed to create guest config: {0}
     CreateGuestConfig(#[from] GuestConfigError),
+    /// Failed to dump CPU config: {0}
+    DumpCpuConfig(crate::vstate::vcpu::KvmVcpuError),
     /// Cannot create network device: {0}
     CreateNetDevice(crate::devices::virtio::net::NetError),
     /// Cannot create RateLimiter: {0}
@@ -91,22 +119,39 @@ pub enum StartMicrovmError {
     /// Error creating legacy device: {0}
     #[cfg(target_arch = "x86_64")]
     CreateLegacyDevice(device_manager::legacy::LegacyDeviceError),
+    /// Error creating power button device: {0}
+    CreatePowerButton(PowerButtonError),
     /// Error creating VMGenID device: {0}
     CreateVMGenID(VmGenIdError),
     /// Invalid Memory Configuration: {0}
     GuestMemory(crate::vstate::memory::MemoryError),
+    /// Cannot open the file backing memory tier {0:?}: {1}
+    OpenMemoryTier(String, io::Error),
+    /// The file backing memory tier {0:?} is smaller than the tier.
+    MemoryTierFileSize(String),
     /// Cannot load initrd due to an invalid memory configuration.
     InitrdLoad,
+    /// Cannot sandbox the VMM with Landlock: {0}
+    Landlock(LandlockError),
     /// Cannot load initrd due to an invalid image: {0}
     InitrdRead(io::Error),
+    /// Cannot verify the initrd: {0}
+    InitrdDigest(ImageDigestError),
     /// Internal error while starting microVM: {0}
     Internal(VmmError),
     /// Failed to get CPU template: {0}
     GetCpuTemplate(#[from] GetCpuTemplateError),
     /// Invalid kernel command line: {0}
     KernelCmdline(String),
+    /// The kernel command line parameter {0:?} uses the serial console, which is disabled.
+    DisabledSerialConsole(String),
+    /// Cannot verify the kernel image: {0}
+    KernelDigest(ImageDigestError),
     /// Cannot load kernel due to invalid memory configuration or invalid kernel image: {0}
     KernelLoader(linux_loader::loader::Error),
+    /// Cannot load firmware: {0}
+    #[cfg(target_arch = "aarch64")]
+    FirmwareLoad(crate::arch::ConfigurationError),
     /// Cannot load command line string: {0}
     LoadCommandline(linux_loader::loader::Error),
     /// Cannot start microvm without kernel configuration.
@@ -125,12 +170,25 @@ pub enum StartMicrovmError {
     RestoreMicrovmState(MicrovmStateError),
     /// Cannot set vm resources: {0}
     SetVmResources(VmConfigError),
+    /// Shared memory segment {0:?} must be mapped past guest memory and outside of the MMIO gap.
+    SharedMemoryPlacement(String),
+    /// Cannot map shared memory segment: {0}
+    SharedMemoryMmap(vm_memory::mmap::MmapRegionError),
+    /// MTE can't be enabled with memory tiers or shared memory segments, backed by host files.
+    #[cfg(target_arch = "aarch64")]
+    MteFileBackedMemory,
+    /// Cannot write the SMBIOS tables: {0}
+    #[cfg(target_arch = "x86_64")]
+    Smbios(crate::arch::x86_64::smbios::SmbiosError),
     /// Cannot create the entropy device: {0}
     CreateEntropyDevice(crate::devices::virtio::rng::EntropyError),
+    /// Cannot create the TPM device: {0}
+    CreateTpmDevice(crate::devices::tpm::SwtpmError),
+    /// Snapshots on guest panics are not allowed with nested virtualization or memory tiers.
+    PanicSnapshotNotSupported,
     /// Failed to allocate guest resource: {0}
     AllocateResources(#[from] vm_allocator::Error),
     /// Error configuring ACPI: {0}
-    #[cfg(target_arch = "x86_64")]
     Acpi(#[from] crate::acpi::AcpiError),
     /// Error starting GDB debug session
     #[cfg(feature = "gdb")]
@@ -152,19 +210,16 @@ impl std::convert::From<linux_loader::cmdline::Error> for StartMicrovmError {
 fn create_vmm_and_vcpus(
     instance_info: &InstanceInfo,
     event_manager: &mut EventManager,
+    mut vm: Vm,
     guest_memory: GuestMemoryMmap,
     uffd: Option<Uffd>,
-    track_dirty_pages: bool,
-    vcpu_count: u8,
-    kvm_capabilities: Vec<KvmCapability>,
+    vm_resources: &VmResources,
 ) -> Result<(Vmm, Vec<Vcpu>), StartMicrovmError> {
+    let track_dirty_pages = vm_resources.vm_config.track_dirty_pages;
+    let vcpu_count = vm_resources.vm_config.vcpu_count;
     use self::StartMicrovmError::*;
 
-    // Set up Kvm Vm and register memory regions.
-    // Build custom CPU config if a custom template is provided.
-    let mut vm = Vm::new(kvm_capabilities)
-        .map_err(VmmError::Vm)
-        .map_err(StartMicrovmError::Internal)?;
+    // Register memory regions.
     vm.memory_init(&guest_memory, track_dirty_pages)
         .map_err(VmmError::Vm)
         .map_err(StartMicrovmError::Internal)?;
@@ -172,6 +227,9 @@ fn create_vmm_and_vcpus(
     let vcpus_exit_evt = EventFd::new(libc::EFD_NONBLOCK)
         .map_err(VmmError::EventFd)
         .map_err(Internal)?;
+    let shutdown_timer = TimerFd::new_custom(ClockId::Monotonic, true, true)
+        .map_err(VmmError::TimerFd)
+        .map_err(Internal)?;
 
     let resource_allocator = ResourceAllocator::new()?;
 
@@ -188,23 +246,42 @@ fn create_vmm_and_vcpus(
         setup_interrupt_controller(&mut vm)?;
         let vcpus = create_vcpus(&vm, vcpu_count, &vcpus_exit_evt).map_err(Internal)?;
 
-        // Make stdout non blocking.
-        set_stdout_nonblocking();
+        let vm_config = &vm_resources.vm_config;
 
         // Serial device setup.
-        let serial_device =
-            setup_serial_device(event_manager, std::io::stdin(), io::stdout()).map_err(Internal)?;
+        let serial_device = if vm_config.has_legacy_device(LegacyDevice::Serial) {
+            Some(setup_serial_device(event_manager, vm_resources).map_err(Internal)?)
+        } else {
+            None
+        };
 
         // x86_64 uses the i8042 reset event as the Vmm exit event.
-        let reset_evt = vcpus_exit_evt
-            .try_clone()
-            .map_err(VmmError::EventFd)
-            .map_err(Internal)?;
+        let reset_evt = if vm_config.has_legacy_device(LegacyDevice::I8042) {
+            Some(
+                vcpus_exit_evt
+                    .try_clone()
+                    .map_err(VmmError::EventFd)
+                    .map_err(Internal)?,
+            )
+        } else {
+            None
+        };
 
         // create pio dev manager with legacy devices
         let pio_device_manager = {
             // TODO Remove these unwraps.
             let mut pio_dev_mgr = PortIODeviceManager::new(serial_device, reset_evt).unwrap();
+            if vm_config.has_legacy_device(LegacyDevice::Serial) {
+                for port_config in &vm_resources.serial.extra_ports {
+                    let interrupt_evt = pio_dev_mgr
+                        .serial_interrupt_evt(port_config.port)
+                        .map_err(VmmError::LegacyIOBus)
+                        .map_err(Internal)?;
+                    let serial = setup_serial_port(event_manager, port_config, interrupt_evt)
+                        .map_err(Internal)?;
+                    pio_dev_mgr.set_extra_serial(port_config.port, serial);
+                }
+            }
             pio_dev_mgr.register_devices(vm.fd()).unwrap();
             pio_dev_mgr
         };
@@ -232,6 +309,15 @@ fn create_vmm_and_vcpus(
         uffd,
         vcpus_handles: Vec::new(),
         vcpus_exit_evt,
+        shutdown_timer,
+        snapshot_writer: None,
+        hook_runner: None,
+        cpu_limiter: vm_resources
+            .cpu_limiter
+            .clone()
+            .map(|config| Arc::new(CpuLimiter::new(config))),
+        panic_snapshot: None,
+        coredump_snapshot: None,
         resource_allocator,
         mmio_device_manager,
         #[cfg(target_arch = "x86_64")]
@@ -257,6 +343,7 @@ pub fn build_microvm_for_boot(
 
     // Timestamp for measuring microVM boot duration.
     let request_ts = TimestampUs::default();
+    BOOT_TIMINGS.record(BootPhase::ConfigDone);
 
     let boot_config = vm_resources
         .boot_source
@@ -264,26 +351,52 @@ pub fn build_microvm_for_boot(
         .as_ref()
         .ok_or(MissingKernelConfig)?;
 
-    let guest_memory = vm_resources
-        .allocate_guest_memory()
-        .map_err(StartMicrovmError::GuestMemory)?;
+    let cpu_template = vm_resources.vm_config.cpu_template.get_cpu_template()?;
+    #[cfg(target_arch = "x86_64")]
+    let kvm_capabilities = cpu_template.kvm_capabilities.clone();
+    #[cfg(target_arch = "aarch64")]
+    let kvm_capabilities = cpu_template.all_kvm_capabilities();
+    // KVM refuses to map memory which can't store allocation tags into a VM with MTE enabled,
+    // which is the case of the host files backing memory tiers and shared memory segments. The CPU
+    // template may have been set after them, so they are checked again here.
+    #[cfg(target_arch = "aarch64")]
+    if cpu_template.mte
+        && (!vm_resources.vm_config.memory_tiers.is_empty()
+            || vm_resources.shared_memory.iter().next().is_some())
+    {
+        return Err(MteFileBackedMemory);
+    }
+
+    // Set up Kvm Vm before allocating guest memory, which can be backed by a guest_memfd of the
+    // VM.
+    let mut vm = Vm::new(kvm_capabilities)
+        .map_err(VmmError::Vm)
+        .map_err(Internal)?;
+    let guest_memory = allocate_guest_memory(&mut vm, vm_resources)?;
+    if vm_resources.vm_config.prefault {
+        guest_memory.prefault().map_err(GuestMemory)?;
+    }
 
-    let entry_addr = load_kernel(boot_config, &guest_memory)?;
+    let entry_addr = match &boot_config.firmware_file {
+        // When booting from firmware, the kernel is loaded by the firmware from the guest disk.
+        #[cfg(target_arch = "aarch64")]
+        Some(firmware_file) => load_firmware(firmware_file, &guest_memory)?,
+        _ => load_kernel(boot_config, &guest_memory)?,
+    };
     let initrd = load_initrd_from_config(boot_config, &guest_memory)?;
+    BOOT_TIMINGS.record(BootPhase::KernelLoaded);
     // Clone the command-line so that a failed boot doesn't pollute the original.
     #[allow(unused_mut)]
     let mut boot_cmdline = boot_config.cmdline.clone();
-
-    let cpu_template = vm_resources.vm_config.cpu_template.get_cpu_template()?;
+    check_disabled_serial_console(&boot_cmdline, &vm_resources.vm_config)?;
 
     let (mut vmm, mut vcpus) = create_vmm_and_vcpus(
         instance_info,
         event_manager,
+        vm,
         guest_memory,
         None,
-        vm_resources.vm_config.track_dirty_pages,
-        vm_resources.vm_config.vcpu_count,
-        cpu_template.kvm_capabilities.clone(),
+        vm_resources,
     )?;
 
     #[cfg(feature = "gdb")]
@@ -298,6 +411,8 @@ pub fn build_microvm_for_boot(
         .map(|vcpu| vcpu.copy_kvm_vcpu_fd(vmm.vm()))
         .collect::<Result<Vec<_>, _>>()?;
 
+    attach_shared_memory(&mut vmm, vm_resources)?;
+
     // The boot timer device needs to be the first device attached in order
     // to maintain the same MMIO address referenced in the documentation
     // and tests.
@@ -330,25 +445,49 @@ pub fn build_microvm_for_boot(
         attach_entropy_device(&mut vmm, &mut boot_cmdline, entropy, event_manager)?;
     }
 
+    if let Some(tpm) = &vm_resources.tpm {
+        attach_tpm_device(&mut vmm, tpm)?;
+    }
+
+    if let Some(pvpanic) = &vm_resources.pvpanic {
+        attach_pvpanic_device(&mut vmm, pvpanic, vm_resources)?;
+    }
+
     #[cfg(target_arch = "aarch64")]
-    attach_legacy_devices_aarch64(event_manager, &mut vmm, &mut boot_cmdline).map_err(Internal)?;
+    attach_legacy_devices_aarch64(event_manager, &mut vmm, &mut boot_cmdline, vm_resources)
+        .map_err(Internal)?;
 
     attach_vmgenid_device(&mut vmm)?;
+    attach_power_button_device(&mut vmm)?;
+    BOOT_TIMINGS.record(BootPhase::DevicesCreated);
 
     configure_system_for_boot(
         &mut vmm,
         vcpus.as_mut(),
-        &vm_resources.vm_config,
+        vm_resources,
         &cpu_template,
         entry_addr,
         &initrd,
         boot_cmdline,
     )?;
 
+    // The deferred devices are registered without the lock of the VMM, while the vCPUs run.
+    let deferred_devices = vmm.mmio_device_manager.take_deferred_virtio();
+    let vm_fd = vmm.vm.shared_fd();
     let vmm = Arc::new(Mutex::new(vmm));
 
+    // Sandbox the VMM before it spawns its other threads, so that they inherit the sandbox.
+    if let Some(landlock) = LANDLOCK.get() {
+        landlock.restrict_self().map_err(Landlock)?;
+    }
+
     #[cfg(feature = "gdb")]
-    if let Some(gdb_socket_path) = &vm_resources.vm_config.gdb_socket_path {
+    if let Some(gdb_socket_path) = vm_resources
+        .vm_config
+        .gdb_socket_path
+        .as_ref()
+        .or(gdb::DEFAULT_SOCKET_PATH.get())
+    {
         gdb::gdb_thread(vmm.clone(), vcpu_fds, gdb_rx, entry_addr, gdb_socket_path)
             .map_err(GdbServer)?;
     } else {
@@ -368,14 +507,50 @@ pub fn build_microvm_for_boot(
         .map_err(VmmError::VcpuStart)
         .map_err(Internal)?;
 
+    // The snapshot writer thread cannot be spawned once the seccomp filters are installed.
+    vmm.lock()
+        .unwrap()
+        .start_snapshot_writer(
+            seccomp_filters
+                .get("vmm")
+                .ok_or_else(|| MissingSeccompFilters("vmm".to_string()))?
+                .clone(),
+        )
+        .map_err(VmmError::SnapshotWriterSpawn)
+        .map_err(Internal)?;
+    vmm.lock()
+        .unwrap()
+        .start_hook_runner(
+            &vm_resources.lifecycle_hooks,
+            seccomp_filters
+                .get("vmm")
+                .ok_or_else(|| MissingSeccompFilters("vmm".to_string()))?
+                .clone(),
+        )
+        .map_err(VmmError::HookRunnerSpawn)
+        .map_err(Internal)?;
+
+    // The thread outlives the start of the microVM, its handle is not kept.
+    spawn_deferred_devices(
+        deferred_devices,
+        vm_fd,
+        !seccomp_filters
+            .get("vmm")
+            .ok_or_else(|| MissingSeccompFilters("vmm".to_string()))?
+            .is_empty(),
+    )
+    .map_err(VmmError::DeferredDevicesSpawn)
+    .map_err(Internal)?;
+
     // Load seccomp filters for the VMM thread.
     // Execution panics if filters cannot be loaded, use --no-seccomp if skipping filters
     // altogether is the desired behaviour.
     // Keep this as the last step before resuming vcpus.
-    seccompiler::apply_filter(
+    apply_vmm_seccomp_filter(
         seccomp_filters
             .get("vmm")
             .ok_or_else(|| MissingSeccompFilters("vmm".to_string()))?,
+        vm_resources,
     )
     .map_err(VmmError::SeccompFilters)
     .map_err(Internal)?;
@@ -385,6 +560,20 @@ pub fn build_microvm_for_boot(
     Ok(vmm)
 }
 
+/// Installs `vmm_filter` on the VMM thread and, unless a feature of the microVM connects to TCP
+/// servers, the filter denying the creation of TCP sockets on top of it.
+fn apply_vmm_seccomp_filter(
+    vmm_filter: &BpfProgram,
+    vm_resources: &VmResources,
+) -> Result<(), seccompiler::InstallationError> {
+    seccompiler::apply_filter(vmm_filter)?;
+    // Running without seccomp filters leaves the VMM thread unrestricted.
+    if !vmm_filter.is_empty() && !vm_resources.connects_over_tcp() {
+        seccompiler::apply_filter(&get_tcp_denying_filter())?;
+    }
+    Ok(())
+}
+
 /// Builds and boots a microVM based on the current Firecracker VmResources configuration.
 ///
 /// This is the default build recipe, one could build other microVM flavors by using the
@@ -401,6 +590,9 @@ pub fn build_and_boot_microvm(
     debug!("event_start: build microvm for boot");
     let vmm = build_microvm_for_boot(instance_info, vm_resources, event_manager, seccomp_filters)?;
     debug!("event_end: build microvm for boot");
+    vmm.lock()
+        .unwrap()
+        .run_lifecycle_hooks(LifecycleEvent::PreBoot);
     // The vcpus start off in the `Paused` state, let them run.
     debug!("event_start: boot microvm");
     vmm.lock()
@@ -408,6 +600,10 @@ pub fn build_and_boot_microvm(
         .resume_vm()
         .map_err(StartMicrovmError::Internal)?;
     debug!("event_end: boot microvm");
+    vmm.lock()
+        .unwrap()
+        .run_lifecycle_hooks(LifecycleEvent::PostBoot);
+    event_stream::emit(VmEvent::BootComplete);
     Ok(vmm)
 }
 
@@ -444,10 +640,16 @@ pub enum BuildMicrovmFromSnapshotError {
     MissingVmmSeccompFilters,
     /// Failed to apply VMM secccomp filter: {0}
     SeccompFiltersInternal(#[from] seccompiler::InstallationError),
+    /// Failed to spawn the snapshot writer thread: {0}
+    SnapshotWriterSpawn(std::io::Error),
+    /// Failed to spawn the lifecycle hooks thread: {0}
+    HookRunnerSpawn(std::io::Error),
     /// Failed to restore ACPI device manager: {0}
     ACPIDeviManager(#[from] ACPIDeviceManagerRestoreError),
     /// VMGenID update failed: {0}
     VMGenIDUpdate(std::io::Error),
+    /// Failed to restore shared memory segment: {0}
+    SharedMemoryConfig(#[from] SharedMemoryConfigError),
 }
 
 /// Builds and starts a microVM based on the provided MicrovmState.
@@ -466,14 +668,16 @@ pub fn build_microvm_from_snapshot(
 ) -> Result<Arc<Mutex<Vmm>>, BuildMicrovmFromSnapshotError> {
     // Build Vmm.
     debug!("event_start: build microvm from snapshot");
+    let vm = Vm::new(microvm_state.vm_state.kvm_cap_modifiers.clone())
+        .map_err(VmmError::Vm)
+        .map_err(StartMicrovmError::Internal)?;
     let (mut vmm, mut vcpus) = create_vmm_and_vcpus(
         instance_info,
         event_manager,
+        vm,
         guest_memory.clone(),
         uffd,
-        vm_resources.vm_config.track_dirty_pages,
-        vm_resources.vm_config.vcpu_count,
-        microvm_state.vm_state.kvm_cap_modifiers.clone(),
+        vm_resources,
     )?;
 
     #[cfg(target_arch = "x86_64")]
@@ -512,6 +716,18 @@ pub fn build_microvm_from_snapshot(
     // Restore the boot source config paths.
     vm_resources.boot_source.config = microvm_state.vm_info.boot_source;
 
+    // Map the shared memory segments again from their backing files, as they are not part of the
+    // guest memory snapshot.
+    for config in microvm_state.vm_info.shared_memory {
+        vm_resources.set_shared_memory(config)?;
+    }
+    attach_shared_memory(&mut vmm, vm_resources)?;
+
+    // Restore the shared rate limiters before the devices referencing them.
+    restore_shared_rate_limiters(&microvm_state.shared_rate_limiters)
+        .map_err(MicrovmStateError::RestoreSharedRateLimiters)?;
+    vm_resources.shared_rate_limiters = shared_rate_limiter_configs();
+
     // Restore devices states.
     let mmio_ctor_args = MMIODevManagerConstructorArgs {
         mem: &guest_memory,
@@ -545,6 +761,13 @@ pub fn build_microvm_from_snapshot(
             .map_err(BuildMicrovmFromSnapshotError::VMGenIDUpdate)?;
     }
 
+    // Sandbox the VMM before it spawns its other threads, so that they inherit the sandbox.
+    if let Some(landlock) = LANDLOCK.get() {
+        landlock
+            .restrict_self()
+            .map_err(StartMicrovmError::Landlock)?;
+    }
+
     // Move vcpus to their own threads and start their state machine in the 'Paused' state.
     vmm.start_vcpus(
         vcpus,
@@ -553,19 +776,40 @@ pub fn build_microvm_from_snapshot(
             .ok_or(BuildMicrovmFromSnapshotError::MissingVcpuSeccompFilters)?
             .clone(),
     )?;
+    // The snapshot writer thread cannot be spawned once the seccomp filters are installed.
+    vmm.start_snapshot_writer(
+        seccomp_filters
+            .get("vmm")
+            .ok_or(BuildMicrovmFromSnapshotError::MissingVmmSeccompFilters)?
+            .clone(),
+    )
+    .map_err(BuildMicrovmFromSnapshotError::SnapshotWriterSpawn)?;
+    vmm.start_hook_runner(
+        &vm_resources.lifecycle_hooks,
+        seccomp_filters
+            .get("vmm")
+            .ok_or(BuildMicrovmFromSnapshotError::MissingVmmSeccompFilters)?
+            .clone(),
+    )
+    .map_err(BuildMicrovmFromSnapshotError::HookRunnerSpawn)?;
 
     let vmm = Arc::new(Mutex::new(vmm));
     event_manager.add_subscriber(vmm.clone());
 
     // Load seccomp filters for the VMM thread.
     // Keep this as the last step of the building process.
-    seccompiler::apply_filter(
+    apply_vmm_seccomp_filter(
         seccomp_filters
             .get("vmm")
             .ok_or(BuildMicrovmFromSnapshotError::MissingVmmSeccompFilters)?,
+        vm_resources,
     )?;
     debug!("event_end: build microvm from snapshot");
 
+    vmm.lock()
+        .unwrap()
+        .run_lifecycle_hooks(LifecycleEvent::PostRestore);
+
     Ok(vmm)
 }
 
@@ -575,8 +819,15 @@ fn load_kernel(
 ) -> Result<GuestAddress, StartMicrovmError> {
     let mut kernel_file = boot_config
         .kernel_file
+        .as_ref()
+        .ok_or(StartMicrovmError::MissingKernelConfig)?
         .try_clone()
         .map_err(|err| StartMicrovmError::Internal(VmmError::KernelFile(err)))?;
+    if let Some(digest) = &boot_config.kernel_digest {
+        digest
+            .verify(&mut kernel_file)
+            .map_err(StartMicrovmError::KernelDigest)?;
+    }
 
     #[cfg(target_arch = "x86_64")]
     let entry_addr = Loader::load::<std::fs::File, GuestMemoryMmap>(
@@ -599,62 +850,110 @@ fn load_kernel(
     Ok(entry_addr.kernel_load)
 }
 
+#[cfg(target_arch = "aarch64")]
+fn load_firmware(
+    firmware_file: &std::fs::File,
+    guest_memory: &GuestMemoryMmap,
+) -> Result<GuestAddress, StartMicrovmError> {
+    let mut firmware_file = firmware_file.try_clone().map_err(|err| {
+        StartMicrovmError::FirmwareLoad(crate::arch::ConfigurationError::FirmwareRead(err))
+    })?;
+
+    crate::arch::aarch64::load_firmware(guest_memory, &mut firmware_file)
+        .map_err(StartMicrovmError::FirmwareLoad)
+}
+
 fn load_initrd_from_config(
     boot_cfg: &BootConfig,
     vm_memory: &GuestMemoryMmap,
 ) -> Result<Option<InitrdConfig>, StartMicrovmError> {
-    use self::StartMicrovmError::InitrdRead;
+    use self::StartMicrovmError::{InitrdDigest, InitrdRead};
 
-    Ok(match &boot_cfg.initrd_file {
-        Some(f) => Some(load_initrd(
-            vm_memory,
-            &mut f.try_clone().map_err(InitrdRead)?,
-        )?),
-        None => None,
-    })
+    if boot_cfg.initrd_files.is_empty() {
+        return Ok(None);
+    }
+
+    let mut initrd_files = boot_cfg
+        .initrd_files
+        .iter()
+        .map(|f| f.try_clone())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(InitrdRead)?;
+    if let Some(digest) = &boot_cfg.initrd_digest {
+        digest
+            .verify_concatenated(&mut initrd_files)
+            .map_err(InitrdDigest)?;
+    }
+    Ok(Some(load_initrd(vm_memory, &mut initrd_files)?))
 }
 
-/// Loads the initrd from a file into the given memory slice.
+/// Alignment of each image in an initrd made of several images, as expected by the kernel for
+/// the headers of concatenated cpio archives.
+const INITRD_IMAGE_ALIGNMENT: usize = 4;
+
+/// Loads the initrd from one or more files into the given memory slice.
+///
+/// Images are loaded one after the other, each one starting 4 bytes aligned and the gaps
+/// zero-filled, so that the kernel unpacks them as concatenated initramfs archives.
 ///
 /// * `vm_memory` - The guest memory the initrd is written to.
-/// * `image` - The initrd image.
+/// * `images` - The initrd images.
 ///
 /// Returns the result of initrd loading
 fn load_initrd<F>(
     vm_memory: &GuestMemoryMmap,
-    image: &mut F,
+    images: &mut [F],
 ) -> Result<InitrdConfig, StartMicrovmError>
 where
     F: ReadVolatile + Seek + Debug,
 {
     use self::StartMicrovmError::{InitrdLoad, InitrdRead};
 
-    let size: usize;
-    // Get the image size
-    match image.seek(SeekFrom::End(0)) {
-        Err(err) => return Err(InitrdRead(err)),
-        Ok(0) => {
-            return Err(InitrdRead(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "Initrd image seek returned a size of zero",
-            )))
-        }
-        Ok(s) => size = u64_to_usize(s),
-    };
-    // Go back to the image start
-    image.seek(SeekFrom::Start(0)).map_err(InitrdRead)?;
+    // Offset of each image in the initrd, and the total size of the initrd.
+    let mut offsets = Vec::with_capacity(images.len());
+    let mut size: usize = 0;
+    for image in images.iter_mut() {
+        // Get the image size
+        let image_size = match image.seek(SeekFrom::End(0)) {
+            Err(err) => return Err(InitrdRead(err)),
+            Ok(0) => {
+                return Err(InitrdRead(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Initrd image seek returned a size of zero",
+                )))
+            }
+            Ok(s) => u64_to_usize(s),
+        };
+        // Go back to the image start
+        image.seek(SeekFrom::Start(0)).map_err(InitrdRead)?;
+
+        let offset = size.next_multiple_of(INITRD_IMAGE_ALIGNMENT);
+        offsets.push((offset, image_size));
+        size = offset + image_size;
+    }
 
     // Get the target address
     let address = crate::arch::initrd_load_addr(vm_memory, size).map_err(|_| InitrdLoad)?;
 
-    // Load the image into memory
-    let mut slice = vm_memory
-        .get_slice(GuestAddress(address), size)
-        .map_err(|_| InitrdLoad)?;
-
-    image
-        .read_exact_volatile(&mut slice)
-        .map_err(|_| InitrdLoad)?;
+    // Load the images into memory
+    let mut end = 0;
+    for (image, (offset, image_size)) in images.iter_mut().zip(offsets) {
+        vm_memory
+            .write_slice(
+                &[0u8; INITRD_IMAGE_ALIGNMENT][..offset - end],
+                GuestAddress(address + end as u64),
+            )
+            .map_err(|_| InitrdLoad)?;
+
+        let mut slice = vm_memory
+            .get_slice(GuestAddress(address + offset as u64), image_size)
+            .map_err(|_| InitrdLoad)?;
+
+        image
+            .read_exact_volatile(&mut slice)
+            .map_err(|_| InitrdLoad)?;
+        end = offset + image_size;
+    }
 
     Ok(InitrdConfig {
         address: GuestAddress(address),
@@ -678,13 +977,81 @@ pub fn setup_interrupt_controller(vm: &mut Vm, vcpu_count: u8) -> Result<(), Sta
         .map_err(StartMicrovmError::Internal)
 }
 
-/// Sets up the serial device.
+/// Checks that the kernel command line does not send the guest console to the serial console
+/// when it is disabled.
+fn check_disabled_serial_console(
+    cmdline: &LoaderKernelCmdline,
+    vm_config: &VmConfig,
+) -> Result<(), StartMicrovmError> {
+    if vm_config.has_legacy_device(LegacyDevice::Serial) {
+        return Ok(());
+    }
+    let cmdline = cmdline
+        .as_cstring()?
+        .into_string()
+        .map_err(|err| StartMicrovmError::KernelCmdline(err.to_string()))?;
+    match cmdline
+        .split_ascii_whitespace()
+        .find(|param| uses_serial_console(param))
+    {
+        Some(param) => Err(StartMicrovmError::DisabledSerialConsole(param.to_string())),
+        None => Ok(()),
+    }
+}
+
+/// Checks whether a kernel command line parameter sends console output to a serial port.
+fn uses_serial_console(param: &str) -> bool {
+    match param.split_once('=') {
+        Some(("console", value)) => value.starts_with("ttyS") || value.starts_with("uart"),
+        Some(("earlycon", _)) => true,
+        Some(("earlyprintk", value)) => value.starts_with("serial") || value.starts_with("ttyS"),
+        Some(_) => false,
+        None => param == "earlycon",
+    }
+}
+
+/// Sets up the serial device, attached to the host side configured in `vm_resources`, and
+/// capturing its output if enabled.
 pub fn setup_serial_device(
     event_manager: &mut EventManager,
-    input: std::io::Stdin,
-    out: std::io::Stdout,
+    vm_resources: &VmResources,
 ) -> Result<Arc<Mutex<BusDevice>>, VmmError> {
+    if vm_resources.serial.mode == SerialMode::Stdio {
+        // Make stdout non blocking.
+        set_stdout_nonblocking();
+    }
+    let backend = SerialBackend::open(&vm_resources.serial, vm_resources.serial_log.as_ref())
+        .map_err(VmmError::SerialBackend)?;
+    let output = match &vm_resources.serial_log {
+        Some(log) => SerialOut::Logged(Box::new(backend.output), log.clone()),
+        None => backend.output,
+    };
     let interrupt_evt = EventFdTrigger::new(EventFd::new(EFD_NONBLOCK).map_err(VmmError::EventFd)?);
+    add_serial_device(
+        event_manager,
+        SerialBackend { output, ..backend },
+        interrupt_evt,
+    )
+}
+
+/// Sets up one of the additional serial ports, attached to the host side configured in
+/// `port_config`, which signals its interrupts through `interrupt_evt`.
+#[cfg(target_arch = "x86_64")]
+fn setup_serial_port(
+    event_manager: &mut EventManager,
+    port_config: &SerialPortConfig,
+    interrupt_evt: EventFdTrigger,
+) -> Result<Arc<Mutex<BusDevice>>, VmmError> {
+    let backend =
+        SerialBackend::open(&port_config.host_config(), None).map_err(VmmError::SerialBackend)?;
+    add_serial_device(event_manager, backend, interrupt_evt)
+}
+
+fn add_serial_device(
+    event_manager: &mut EventManager,
+    backend: SerialBackend,
+    interrupt_evt: EventFdTrigger,
+) -> Result<Arc<Mutex<BusDevice>>, VmmError> {
     let kick_stdin_read_evt =
         EventFdTrigger::new(EventFd::new(EFD_NONBLOCK).map_err(VmmError::EventFd)?);
     let serial = Arc::new(Mutex::new(BusDevice::Serial(SerialWrapper {
@@ -693,9 +1060,10 @@ pub fn setup_serial_device(
             SerialEventsWrapper {
                 buffer_ready_event_fd: Some(kick_stdin_read_evt),
             },
-            SerialOut::Stdout(out),
+            backend.output,
         ),
-        input: Some(input),
+        input: backend.input,
+        listener: backend.listener,
     })));
     event_manager.add_subscriber(serial.clone());
     Ok(serial)
@@ -706,7 +1074,10 @@ fn attach_legacy_devices_aarch64(
     event_manager: &mut EventManager,
     vmm: &mut Vmm,
     cmdline: &mut LoaderKernelCmdline,
+    vm_resources: &VmResources,
 ) -> Result<(), VmmError> {
+    let vm_config = &vm_resources.vm_config;
+
     // Serial device setup.
     let cmdline_contains_console = cmdline
         .as_cstring()
@@ -715,10 +1086,8 @@ fn attach_legacy_devices_aarch64(
         .map_err(|_| VmmError::Cmdline)?
         .contains("console=");
 
-    if cmdline_contains_console {
-        // Make stdout non-blocking.
-        set_stdout_nonblocking();
-        let serial = setup_serial_device(event_manager, std::io::stdin(), std::io::stdout())?;
+    if cmdline_contains_console && vm_config.has_legacy_device(LegacyDevice::Serial) {
+        let serial = setup_serial_device(event_manager, vm_resources)?;
         vmm.mmio_device_manager
             .register_mmio_serial(vmm.vm.fd(), &mut vmm.resource_allocator, serial, None)
             .map_err(VmmError::RegisterMMIODevice)?;
@@ -727,12 +1096,16 @@ fn attach_legacy_devices_aarch64(
             .map_err(VmmError::RegisterMMIODevice)?;
     }
 
-    let rtc = RTCDevice(Rtc::with_events(
-        &crate::devices::legacy::rtc_pl031::METRICS,
-    ));
-    vmm.mmio_device_manager
-        .register_mmio_rtc(&mut vmm.resource_allocator, rtc, None)
-        .map_err(VmmError::RegisterMMIODevice)
+    if vm_config.has_legacy_device(LegacyDevice::Rtc) {
+        let rtc = RTCDevice(Rtc::with_events(
+            &crate::devices::legacy::rtc_pl031::METRICS,
+        ));
+        vmm.mmio_device_manager
+            .register_mmio_rtc(&mut vmm.resource_allocator, rtc, None)
+            .map_err(VmmError::RegisterMMIODevice)?;
+    }
+
+    Ok(())
 }
 
 fn create_vcpus(vm: &Vm, vcpu_count: u8, exit_evt: &EventFd) -> Result<Vec<Vcpu>, VmmError> {
@@ -750,7 +1123,7 @@ fn create_vcpus(vm: &Vm, vcpu_count: u8, exit_evt: &EventFd) -> Result<Vec<Vcpu>
 pub fn configure_system_for_boot(
     vmm: &mut Vmm,
     vcpus: &mut [Vcpu],
-    vm_config: &VmConfig,
+    vm_resources: &VmResources,
     cpu_template: &CustomCpuTemplate,
     entry_addr: GuestAddress,
     initrd: &Option<InitrdConfig>,
@@ -758,45 +1131,27 @@ pub fn configure_system_for_boot(
 ) -> Result<(), StartMicrovmError> {
     use self::StartMicrovmError::*;
 
-    // Construct the base CpuConfiguration to apply CPU template onto.
-    #[cfg(target_arch = "x86_64")]
-    let cpu_config = {
-        use crate::cpu_config::x86_64::cpuid;
-        let cpuid = cpuid::Cpuid::try_from(vmm.vm.supported_cpuid().clone())
-            .map_err(GuestConfigError::CpuidFromKvmCpuid)?;
-        let msrs = vcpus[0]
-            .kvm_vcpu
-            .get_msrs(cpu_template.msr_index_iter())
-            .map_err(GuestConfigError::VcpuIoctl)?;
-        CpuConfiguration { cpuid, msrs }
-    };
-
-    #[cfg(target_arch = "aarch64")]
-    let cpu_config = {
-        use crate::arch::aarch64::regs::Aarch64RegisterVec;
-        use crate::arch::aarch64::vcpu::get_registers;
-
-        for vcpu in vcpus.iter_mut() {
-            vcpu.kvm_vcpu
-                .init(&cpu_template.vcpu_features)
-                .map_err(VmmError::VcpuInit)
-                .map_err(Internal)?;
-        }
-
-        let mut regs = Aarch64RegisterVec::default();
-        get_registers(&vcpus[0].kvm_vcpu.fd, &cpu_template.reg_list(), &mut regs)
-            .map_err(GuestConfigError)?;
-        CpuConfiguration { regs }
-    };
+    let vm_config = &vm_resources.vm_config;
+    // Shared memory segments are reported to the guest as reserved memory, named by their id.
+    let reserved_memory: Vec<ReservedMemoryRegion> = vm_config
+        .reserved_memory
+        .iter()
+        .cloned()
+        .chain(
+            vm_resources
+                .shared_memory
+                .iter()
+                .map(|segment| ReservedMemoryRegion {
+                    name: segment.id().to_string(),
+                    guest_addr: segment.guest_addr(),
+                    size: segment.size(),
+                }),
+        )
+        .collect();
+    // The memory tiers, if any, are described to the guest as NUMA nodes without vCPUs.
+    let memory_nodes = vm_config.guest_memory_layout();
 
-    // Apply CPU template to the base CpuConfiguration.
-    let cpu_config = CpuConfiguration::apply_template(cpu_config, cpu_template)?;
-
-    let vcpu_config = VcpuConfig {
-        vcpu_count: vm_config.vcpu_count,
-        smt: vm_config.smt,
-        cpu_config,
-    };
+    let vcpu_config = create_vcpu_config(&vmm.vm, vcpus, vm_config, cpu_template)?;
 
     // Configure vCPUs with normalizing and setting the generated CPU configuration.
     for vcpu in vcpus.iter_mut() {
@@ -827,18 +1182,25 @@ pub fn configure_system_for_boot(
             cmdline_size,
             initrd,
             vcpu_config.vcpu_count,
+            &reserved_memory,
         )
         .map_err(ConfigureSystem)?;
 
         // Create ACPI tables and write them in guest memory
-        // For the time being we only support ACPI in x86_64
         acpi::create_acpi_tables(
             &vmm.guest_memory,
             &mut vmm.resource_allocator,
             &vmm.mmio_device_manager,
             &vmm.acpi_device_manager,
+            &vmm.pio_device_manager,
             vcpus,
+            &memory_nodes,
         )?;
+
+        if let Some(smbios_config) = &vm_resources.smbios {
+            crate::arch::x86_64::smbios::setup_smbios(&vmm.guest_memory, smbios_config)
+                .map_err(Smbios)?;
+        }
     }
     #[cfg(target_arch = "aarch64")]
     {
@@ -847,6 +1209,20 @@ pub fn configure_system_for_boot(
             .map(|cpu| cpu.kvm_vcpu.get_mpidr())
             .collect();
         let cmdline = boot_cmdline.as_cstring()?;
+
+        // Create ACPI tables and write them in guest memory. Their location is passed to the
+        // guest through the FDT.
+        let rsdp_addr = acpi::create_acpi_tables(
+            &vmm.guest_memory,
+            &mut vmm.resource_allocator,
+            &vmm.mmio_device_manager,
+            &vmm.acpi_device_manager,
+            vcpus,
+            &memory_nodes,
+            vmm.vm.get_irqchip(),
+            vm_config.pmu,
+        )?;
+
         crate::arch::aarch64::configure_system(
             &vmm.guest_memory,
             cmdline,
@@ -855,13 +1231,231 @@ pub fn configure_system_for_boot(
             vmm.vm.get_irqchip(),
             &vmm.acpi_device_manager.vmgenid,
             initrd,
+            vm_config.pmu,
+            Some(rsdp_addr),
+            &reserved_memory,
+            &memory_nodes,
         )
         .map_err(ConfigureSystem)?;
     }
     Ok(())
 }
 
-/// Attaches a VirtioDevice device to the device manager and event manager.
+/// Creates the configuration of the vCPUs, by applying the CPU template and the machine
+/// configuration onto the CPU configuration supported by KVM.
+fn create_vcpu_config(
+    vm: &Vm,
+    vcpus: &mut [Vcpu],
+    vm_config: &VmConfig,
+    cpu_template: &CustomCpuTemplate,
+) -> Result<VcpuConfig, StartMicrovmError> {
+    // Construct the base CpuConfiguration to apply CPU template onto.
+    #[cfg(target_arch = "x86_64")]
+    let cpu_config = {
+        use crate::cpu_config::x86_64::cpuid;
+        let cpuid = cpuid::Cpuid::try_from(vm.supported_cpuid().clone())
+            .map_err(GuestConfigError::CpuidFromKvmCpuid)?;
+        let msrs = vcpus[0]
+            .kvm_vcpu
+            .get_msrs(cpu_template.msr_index_iter())
+            .map_err(GuestConfigError::VcpuIoctl)?;
+        CpuConfiguration { cpuid, msrs }
+    };
+
+    #[cfg(target_arch = "aarch64")]
+    let cpu_config = {
+        use kvm_bindings::KVM_ARM_VCPU_PMU_V3;
+
+        use crate::arch::aarch64::regs::Aarch64RegisterVec;
+        use crate::arch::aarch64::vcpu::get_registers;
+        use crate::cpu_config::aarch64::custom_cpu_template::VcpuFeatures;
+        use crate::cpu_config::templates::RegisterValueFilter;
+
+        let mut vcpu_features = cpu_template.all_vcpu_features();
+        if vm_config.pmu {
+            vcpu_features.push(VcpuFeatures {
+                index: 0,
+                bitmap: RegisterValueFilter {
+                    filter: 1 << KVM_ARM_VCPU_PMU_V3,
+                    value: 1 << KVM_ARM_VCPU_PMU_V3,
+                },
+            });
+        }
+
+        for vcpu in vcpus.iter_mut() {
+            vcpu.kvm_vcpu
+                .init(&vcpu_features, cpu_template.sve_vector_length)
+                .map_err(VmmError::VcpuInit)
+                .map_err(StartMicrovmError::Internal)?;
+        }
+
+        let mut regs = Aarch64RegisterVec::default();
+        get_registers(&vcpus[0].kvm_vcpu.fd, &cpu_template.reg_list(), &mut regs)
+            .map_err(GuestConfigError)?;
+        CpuConfiguration { regs }
+    };
+
+    // Apply CPU template to the base CpuConfiguration.
+    let cpu_config = CpuConfiguration::apply_template(cpu_config, cpu_template)?;
+
+    // Nested virtualization is enabled on top of the template, which may disable it.
+    #[cfg(target_arch = "x86_64")]
+    let cpu_config = if vm_config.nested_virt {
+        let supported =
+            crate::cpu_config::x86_64::cpuid::Cpuid::try_from(vm.supported_cpuid().clone())
+                .map_err(GuestConfigError::CpuidFromKvmCpuid)?;
+        cpu_config.enable_nested_virt(&supported)?
+    } else {
+        cpu_config
+    };
+
+    Ok(VcpuConfig {
+        vcpu_count: vm_config.vcpu_count,
+        smt: vm_config.smt,
+        cpu_config,
+    })
+}
+
+/// Dumps the CPU configuration of the microVM described by `vm_resources`.
+///
+/// A scratch vCPU is created and configured as the first vCPU of the microVM, after the CPU
+/// template is applied and the CPUID is normalized, but no guest is loaded nor run.
+pub fn dump_cpu_config(vm_resources: &VmResources) -> Result<CpuConfiguration, StartMicrovmError> {
+    use self::StartMicrovmError::*;
+
+    let cpu_template = vm_resources.vm_config.cpu_template.get_cpu_template()?;
+    #[cfg(target_arch = "x86_64")]
+    let kvm_capabilities = cpu_template.kvm_capabilities.clone();
+    #[cfg(target_arch = "aarch64")]
+    let kvm_capabilities = cpu_template.all_kvm_capabilities();
+
+    let mut vm = Vm::new(kvm_capabilities)
+        .map_err(VmmError::Vm)
+        .map_err(Internal)?;
+    let guest_memory = allocate_guest_memory(&mut vm, vm_resources)?;
+    let exit_evt = EventFd::new(libc::EFD_NONBLOCK)
+        .map_err(VmmError::EventFd)
+        .map_err(Internal)?;
+
+    #[cfg(target_arch = "x86_64")]
+    let mut vcpus = {
+        setup_interrupt_controller(&mut vm)?;
+        create_vcpus(&vm, 1, &exit_evt).map_err(Internal)?
+    };
+    #[cfg(target_arch = "aarch64")]
+    let mut vcpus = {
+        let vcpus = create_vcpus(&vm, 1, &exit_evt).map_err(Internal)?;
+        setup_interrupt_controller(&mut vm, 1)?;
+        vcpus
+    };
+
+    let vcpu_config = create_vcpu_config(&vm, &mut vcpus, &vm_resources.vm_config, &cpu_template)?;
+    vcpus[0]
+        .kvm_vcpu
+        .configure(&guest_memory, GuestAddress(0), &vcpu_config)
+        .map_err(VmmError::VcpuConfigure)
+        .map_err(Internal)?;
+    vcpus[0].kvm_vcpu.dump_cpu_config().map_err(DumpCpuConfig)
+}
+
+/// Validates `cpu_template` against the CPU configuration of the microVM described by
+/// `vm_resources` without CPU template, without starting the microVM.
+pub fn validate_cpu_template(
+    vm_resources: &VmResources,
+    cpu_template: &CustomCpuTemplate,
+) -> Result<CpuTemplateReport, StartMicrovmError> {
+    // Only the vCPU configuration matters, so the guest memory is kept to the default.
+    let vm_config = &vm_resources.vm_config;
+    let scratch_resources = VmResources {
+        vm_config: VmConfig {
+            vcpu_count: vm_config.vcpu_count,
+            smt: vm_config.smt,
+            pmu: vm_config.pmu,
+            nested_virt: vm_config.nested_virt,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let host_config = dump_cpu_config(&scratch_resources)?;
+    Ok(CpuTemplateReport::new(cpu_template, &host_config))
+}
+
+/// Allocates guest memory, backed as configured in `vm_resources`, followed by the memory tiers.
+fn allocate_guest_memory(
+    vm: &mut Vm,
+    vm_resources: &VmResources,
+) -> Result<GuestMemoryMmap, StartMicrovmError> {
+    use self::StartMicrovmError::*;
+
+    let guest_memory = match vm_resources.vm_config.memory_backend {
+        MemoryBackend::Anonymous => vm_resources.allocate_guest_memory().map_err(GuestMemory)?,
+        MemoryBackend::GuestMemfd => {
+            let regions = vm_resources.guest_memory_regions();
+            let size = regions.iter().map(|(_, size)| usize_to_u64(*size)).sum();
+            let guest_memfd = vm
+                .create_guest_memfd(size)
+                .map_err(VmmError::Vm)
+                .map_err(Internal)?;
+            GuestMemoryMmap::guest_memfd_backed(guest_memfd, &regions).map_err(GuestMemory)?
+        }
+    };
+
+    // Each memory tier is mapped from its own host file or device.
+    let vm_config = &vm_resources.vm_config;
+    let layout = vm_config.guest_memory_layout();
+    vm_config.memory_tiers.iter().zip(&layout[1..]).try_fold(
+        guest_memory,
+        |guest_memory, (tier, regions)| {
+            let file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(&tier.path_on_host)
+                .map_err(|err| OpenMemoryTier(tier.tier_id.clone(), err))?;
+            // DAX and other devices report no size, only regular files can be checked.
+            let metadata = file
+                .metadata()
+                .map_err(|err| OpenMemoryTier(tier.tier_id.clone(), err))?;
+            if metadata.is_file() && metadata.len() < usize_to_u64(tier.size_mib << 20) {
+                return Err(MemoryTierFileSize(tier.tier_id.clone()));
+            }
+            guest_memory
+                .with_file_regions(&file, regions, vm_config.track_dirty_pages)
+                .map_err(GuestMemory)
+        },
+    )
+}
+
+/// Maps the read-only memory segments shared with other microVMs in the guest physical address
+/// space, past the guest memory regions.
+fn attach_shared_memory(
+    vmm: &mut Vmm,
+    vm_resources: &VmResources,
+) -> Result<(), StartMicrovmError> {
+    use self::StartMicrovmError::*;
+
+    let mmio_gap =
+        crate::arch::MMIO_MEM_START..crate::arch::MMIO_MEM_START + crate::arch::MMIO_MEM_SIZE;
+    let last_addr = vmm.guest_memory.last_addr().raw_value();
+
+    for segment in vm_resources.shared_memory.iter() {
+        let start = segment.guest_addr();
+        let end = start + segment.size();
+        if start <= last_addr || (start < mmio_gap.end && mmio_gap.start < end) {
+            return Err(SharedMemoryPlacement(segment.id().to_string()));
+        }
+
+        let region = segment.map().map_err(SharedMemoryMmap)?;
+        vmm.vm
+            .add_readonly_memory_region(GuestAddress(start), region)
+            .map_err(VmmError::Vm)
+            .map_err(Internal)?;
+    }
+    Ok(())
+}
+
+/// Attaches a VirtioDevice device to the device manager and event manager. The registration of the
+/// KVM events of the device is left to a background thread when `deferred` is set, for the devices
+/// which the guest does not need to boot.
 fn attach_virtio_device<T: 'static + VirtioDevice + MutEventSubscriber + Debug>(
     event_manager: &mut EventManager,
     vmm: &mut Vmm,
@@ -869,6 +1463,7 @@ fn attach_virtio_device<T: 'static + VirtioDevice + MutEventSubscriber + Debug>(
     device: Arc<Mutex<T>>,
     cmdline: &mut LoaderKernelCmdline,
     is_vhost_user: bool,
+    deferred: bool,
 ) -> Result<(), StartMicrovmError> {
     use self::StartMicrovmError::*;
 
@@ -883,11 +1478,46 @@ fn attach_virtio_device<T: 'static + VirtioDevice + MutEventSubscriber + Debug>(
             id,
             device,
             cmdline,
+            deferred,
         )
         .map_err(RegisterMmioDevice)
         .map(|_| ())
 }
 
+/// Registers the KVM events of the deferred devices on a background thread, which runs alongside
+/// the vCPUs. When `filtered` is set, the thread installs a seccomp filter only allowing the
+/// registration.
+fn spawn_deferred_devices(
+    deferred_devices: Vec<DeferredVirtioEvents>,
+    vm_fd: Arc<VmFd>,
+    filtered: bool,
+) -> Result<Option<thread::JoinHandle<()>>, io::Error> {
+    if deferred_devices.is_empty() {
+        return Ok(None);
+    }
+    thread::Builder::new()
+        .name("fc_deferred_dev".to_string())
+        .spawn(move || {
+            // Execution panics if filters cannot be loaded, use --no-seccomp if skipping filters
+            // altogether is the desired behaviour.
+            if filtered {
+                if let Err(err) = seccompiler::apply_filter(&get_deferred_devices_filter()) {
+                    panic!(
+                        "Failed to set the requested seccomp filters on the deferred devices \
+                         thread: Error: {err}"
+                    );
+                }
+            }
+            for device in deferred_devices {
+                if let Err(err) = device.register(&vm_fd) {
+                    error!("Failed to set up a deferred device: {err}");
+                    METRICS.vmm.deferred_device_fails.inc();
+                }
+            }
+        })
+        .map(Some)
+}
+
 pub(crate) fn attach_boot_timer_device(
     vmm: &mut Vmm,
     request_ts: TimestampUs,
@@ -903,6 +1533,56 @@ pub(crate) fn attach_boot_timer_device(
     Ok(())
 }
 
+fn attach_tpm_device(vmm: &mut Vmm, tpm: &TpmConfig) -> Result<(), StartMicrovmError> {
+    let swtpm = Swtpm::connect(&tpm.socket, true).map_err(StartMicrovmError::CreateTpmDevice)?;
+
+    vmm.mmio_device_manager
+        .register_mmio_tpm(&mut vmm.resource_allocator, swtpm, None)
+        .map_err(StartMicrovmError::RegisterMmioDevice)?;
+
+    Ok(())
+}
+
+fn attach_pvpanic_device(
+    vmm: &mut Vmm,
+    pvpanic: &PvPanicConfig,
+    vm_resources: &VmResources,
+) -> Result<(), StartMicrovmError> {
+    let panic_evt = match &pvpanic.snapshot {
+        Some(snapshot) => {
+            // The same restrictions as for the snapshots requested through the API apply.
+            if vm_resources.vm_config.nested_virt || !vm_resources.vm_config.memory_tiers.is_empty()
+            {
+                return Err(StartMicrovmError::PanicSnapshotNotSupported);
+            }
+            let panic_evt = EventFd::new(libc::EFD_NONBLOCK)
+                .map_err(|err| StartMicrovmError::Internal(VmmError::EventFd(err)))?;
+            vmm.panic_snapshot = Some(PanicSnapshot {
+                panic_evt: panic_evt
+                    .try_clone()
+                    .map_err(|err| StartMicrovmError::Internal(VmmError::EventFd(err)))?,
+                vm_info: VmInfo::from(vm_resources),
+                params: CreateSnapshotParams {
+                    snapshot_type: SnapshotType::Full,
+                    snapshot_path: snapshot.snapshot_path.clone(),
+                    mem_file_path: snapshot.mem_file_path.clone(),
+                    labels: Default::default(),
+                    encryption: None,
+                    background: false,
+                },
+            });
+            Some(panic_evt)
+        }
+        None => None,
+    };
+
+    vmm.mmio_device_manager
+        .register_mmio_pvpanic(&mut vmm.resource_allocator, PvPanic::new(panic_evt), None)
+        .map_err(StartMicrovmError::RegisterMmioDevice)?;
+
+    Ok(())
+}
+
 fn attach_vmgenid_device(vmm: &mut Vmm) -> Result<(), StartMicrovmError> {
     let vmgenid = VmGenId::new(&vmm.guest_memory, &mut vmm.resource_allocator)
         .map_err(StartMicrovmError::CreateVMGenID)?;
@@ -914,6 +1594,17 @@ fn attach_vmgenid_device(vmm: &mut Vmm) -> Result<(), StartMicrovmError> {
     Ok(())
 }
 
+fn attach_power_button_device(vmm: &mut Vmm) -> Result<(), StartMicrovmError> {
+    let power_button = PowerButton::new(&mut vmm.resource_allocator)
+        .map_err(StartMicrovmError::CreatePowerButton)?;
+
+    vmm.acpi_device_manager
+        .attach_power_button(power_button, vmm.vm.fd())
+        .map_err(StartMicrovmError::AttachPowerButtonDevice)?;
+
+    Ok(())
+}
+
 fn attach_entropy_device(
     vmm: &mut Vmm,
     cmdline: &mut LoaderKernelCmdline,
@@ -933,6 +1624,7 @@ fn attach_entropy_device(
         entropy_device.clone(),
         cmdline,
         false,
+        true,
     )
 }
 
@@ -967,6 +1659,7 @@ fn attach_block_devices<'a, I: Iterator<Item = &'a Arc<Mutex<Block>>> + Debug>(
             block.clone(),
             cmdline,
             is_vhost_user,
+            false,
         )?;
     }
     Ok(())
@@ -981,7 +1674,15 @@ fn attach_net_devices<'a, I: Iterator<Item = &'a Arc<Mutex<Net>>> + Debug>(
     for net_device in net_devices {
         let id = net_device.lock().expect("Poisoned lock").id().clone();
         // The device mutex mustn't be locked here otherwise it will deadlock.
-        attach_virtio_device(event_manager, vmm, id, net_device.clone(), cmdline, false)?;
+        attach_virtio_device(
+            event_manager,
+            vmm,
+            id,
+            net_device.clone(),
+            cmdline,
+            false,
+            false,
+        )?;
     }
     Ok(())
 }
@@ -994,7 +1695,15 @@ fn attach_unixsock_vsock_device(
 ) -> Result<(), StartMicrovmError> {
     let id = String::from(unix_vsock.lock().expect("Poisoned lock").id());
     // The device mutex mustn't be locked here otherwise it will deadlock.
-    attach_virtio_device(event_manager, vmm, id, unix_vsock.clone(), cmdline, false)
+    attach_virtio_device(
+        event_manager,
+        vmm,
+        id,
+        unix_vsock.clone(),
+        cmdline,
+        false,
+        true,
+    )
 }
 
 fn attach_balloon_device(
@@ -1005,7 +1714,15 @@ fn attach_balloon_device(
 ) -> Result<(), StartMicrovmError> {
     let id = String::from(balloon.lock().expect("Poisoned lock").id());
     // The device mutex mustn't be locked here otherwise it will deadlock.
-    attach_virtio_device(event_manager, vmm, id, balloon.clone(), cmdline, false)
+    attach_virtio_device(
+        event_manager,
+        vmm,
+        id,
+        balloon.clone(),
+        cmdline,
+        false,
+        true,
+    )
 }
 
 // Adds `O_NONBLOCK` to the stdout flags.
@@ -1114,7 +1831,7 @@ pub(crate) mod tests {
         let acpi_device_manager = ACPIDeviceManager::new();
         #[cfg(target_arch = "x86_64")]
         let pio_device_manager = PortIODeviceManager::new(
-            Arc::new(Mutex::new(BusDevice::Serial(SerialWrapper {
+            Some(Arc::new(Mutex::new(BusDevice::Serial(SerialWrapper {
                 serial: Serial::with_events(
                     EventFdTrigger::new(EventFd::new(EFD_NONBLOCK).unwrap()),
                     SerialEventsWrapper {
@@ -1123,8 +1840,9 @@ pub(crate) mod tests {
                     SerialOut::Sink(std::io::sink()),
                 ),
                 input: None,
-            }))),
-            EventFd::new(libc::EFD_NONBLOCK).unwrap(),
+                listener: None,
+            })))),
+            Some(EventFd::new(libc::EFD_NONBLOCK).unwrap()),
         )
         .unwrap();
 
@@ -1147,6 +1865,12 @@ pub(crate) mod tests {
             uffd: None,
             vcpus_handles: Vec::new(),
             vcpus_exit_evt,
+            shutdown_timer: TimerFd::new_custom(ClockId::Monotonic, true, true).unwrap(),
+            snapshot_writer: None,
+            hook_runner: None,
+            cpu_limiter: None,
+            panic_snapshot: None,
+            coredump_snapshot: None,
             resource_allocator: ResourceAllocator::new().unwrap(),
             mmio_device_manager,
             #[cfg(target_arch = "x86_64")]
@@ -1184,6 +1908,11 @@ pub(crate) mod tests {
                 ),
                 rate_limiter: None,
                 file_engine_type: None,
+                remote: None,
+                direct_io: None,
+                backend: None,
+                latency_injection: None,
+                shared_rate_limiter: None,
 
                 socket: None,
             };
@@ -1275,6 +2004,12 @@ pub(crate) mod tests {
         assert!(vmm.acpi_device_manager.vmgenid.is_some());
     }
 
+    #[cfg(target_arch = "x86_64")]
+    pub(crate) fn insert_power_button_device(vmm: &mut Vmm) {
+        attach_power_button_device(vmm).unwrap();
+        assert!(vmm.acpi_device_manager.power_button.is_some());
+    }
+
     pub(crate) fn insert_balloon_device(
         vmm: &mut Vmm,
         cmdline: &mut Cmdline,
@@ -1299,6 +2034,35 @@ pub(crate) mod tests {
         fake_bin
     }
 
+    #[test]
+    fn test_check_disabled_serial_console() {
+        let mut vm_config = VmConfig::default();
+        let cmdline = |params: &str| Cmdline::try_from(params, 4096).unwrap();
+
+        check_disabled_serial_console(&cmdline("console=ttyS0"), &vm_config).unwrap();
+
+        vm_config.disabled_legacy_devices = vec![LegacyDevice::Serial];
+        check_disabled_serial_console(&default_kernel_cmdline(), &vm_config).unwrap();
+        check_disabled_serial_console(&cmdline("console=hvc0 earlyprintk=vga"), &vm_config)
+            .unwrap();
+        for param in [
+            "console=ttyS0",
+            "console=uart,mmio,0x40002000",
+            "earlycon",
+            "earlycon=uart,mmio,0x40002000",
+            "earlyprintk=serial",
+            "earlyprintk=ttyS0,115200",
+        ] {
+            let res =
+                check_disabled_serial_console(&cmdline(&format!("panic=1 {param}")), &vm_config);
+            assert!(
+                matches!(res, Err(StartMicrovmError::DisabledSerialConsole(ref p)) if p == param),
+                "{:?}",
+                res
+            );
+        }
+    }
+
     #[test]
     // Test that loading the initrd is successful on different archs.
     fn test_load_initrd() {
@@ -1317,12 +2081,33 @@ pub(crate) mod tests {
         #[cfg(target_arch = "aarch64")]
         let gm = single_region_mem(mem_size + crate::arch::aarch64::layout::FDT_MAX_SIZE);
 
-        let res = load_initrd(&gm, &mut tempfile);
+        let res = load_initrd(&gm, std::slice::from_mut(&mut tempfile));
         let initrd = res.unwrap();
         assert!(gm.address_in_range(initrd.address));
         assert_eq!(initrd.size, image.len());
     }
 
+    #[test]
+    fn test_load_initrd_concatenated() {
+        let gm = single_region_mem(16 << 20);
+        let mut images = [&[1u8, 2, 3][..], &[4, 5, 6, 7, 8][..], &[9][..]].map(|content| {
+            let mut file = TempFile::new().unwrap().into_file();
+            file.write_all(content).unwrap();
+            file
+        });
+
+        let initrd = load_initrd(&gm, &mut images).unwrap();
+        assert_eq!(initrd.size, 13);
+        // Load the images again over dirty memory: every image starts 4 bytes aligned, and the
+        // gaps are zeroed.
+        gm.write_slice(&[0xff; 13], initrd.address).unwrap();
+        let reloaded = load_initrd(&gm, &mut images).unwrap();
+        assert_eq!(reloaded.address, initrd.address);
+        let mut content = [0u8; 13];
+        gm.read_slice(&mut content, initrd.address).unwrap();
+        assert_eq!(content, [1, 2, 3, 0, 4, 5, 6, 7, 8, 0, 0, 0, 9]);
+    }
+
     #[test]
     fn test_load_initrd_no_memory() {
         let gm = single_region_mem(79);
@@ -1330,7 +2115,7 @@ pub(crate) mod tests {
         let tempfile = TempFile::new().unwrap();
         let mut tempfile = tempfile.into_file();
         tempfile.write_all(&image).unwrap();
-        let res = load_initrd(&gm, &mut tempfile);
+        let res = load_initrd(&gm, std::slice::from_mut(&mut tempfile));
         assert!(
             matches!(res, Err(StartMicrovmError::InitrdLoad)),
             "{:?}",
@@ -1346,7 +2131,7 @@ pub(crate) mod tests {
         tempfile.write_all(&image).unwrap();
         let gm = single_region_mem_at(crate::arch::PAGE_SIZE as u64 + 1, image.len() * 2);
 
-        let res = load_initrd(&gm, &mut tempfile);
+        let res = load_initrd(&gm, std::slice::from_mut(&mut tempfile));
         assert!(
             matches!(res, Err(StartMicrovmError::InitrdLoad)),
             "{:?}",
@@ -1354,6 +2139,35 @@ pub(crate) mod tests {
         );
     }
 
+    #[test]
+    fn test_load_initrd_digest() {
+        use crate::vmm_config::boot_source::BootSourceConfig;
+
+        let tempfile = TempFile::new().unwrap();
+        tempfile.as_file().write_all(&[1, 2, 3, 4]).unwrap();
+        let path = tempfile.as_path().to_str().unwrap().to_string();
+        let boot_cfg = BootConfig::new(&BootSourceConfig {
+            kernel_image_path: path.clone(),
+            initrd_path: Some(path.into()),
+            initrd_digest: Some(format!("sha256:{}", "0".repeat(64))),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let gm = single_region_mem(16 << 20);
+        let res = load_initrd_from_config(&boot_cfg, &gm);
+        assert!(
+            matches!(
+                res,
+                Err(StartMicrovmError::InitrdDigest(
+                    ImageDigestError::Mismatch { .. }
+                ))
+            ),
+            "{:?}",
+            res
+        );
+    }
+
     #[test]
     fn test_create_vcpus() {
         let vcpu_count = 2;
@@ -1371,6 +2185,23 @@ pub(crate) mod tests {
         assert_eq!(vcpu_vec.len(), vcpu_count as usize);
     }
 
+    #[test]
+    fn test_dump_cpu_config() {
+        let vm_resources = VmResources::default();
+        let cpu_config = dump_cpu_config(&vm_resources).unwrap();
+        #[cfg(target_arch = "x86_64")]
+        {
+            use crate::cpu_config::x86_64::cpuid::{CpuidKey, CpuidTrait};
+
+            assert!(!cpu_config.msrs.is_empty());
+            // The CPUID is normalized, e.g. the APIC ID of the first vCPU is 0.
+            let leaf_1 = cpu_config.cpuid.get(&CpuidKey::leaf(0x1)).unwrap();
+            assert_eq!(leaf_1.result.ebx >> 24, 0);
+        }
+        #[cfg(target_arch = "aarch64")]
+        assert!(!cpu_config.regs.is_empty());
+    }
+
     #[test]
     fn test_attach_net_devices() {
         let mut event_manager = EventManager::new().expect("Unable to create EventManager");
@@ -1382,6 +2213,16 @@ pub(crate) mod tests {
             guest_mac: None,
             rx_rate_limiter: None,
             tx_rate_limiter: None,
+            flow_accounting: None,
+            pause_responder: None,
+            rx_coalescing: None,
+            mtu: None,
+            mmds_only: None,
+            num_queues: None,
+            offloads: None,
+            enforce_mac: None,
+            enforce_arp: None,
+            shared_rate_limiter: None,
         };
 
         let mut cmdline = default_kernel_cmdline();
@@ -1597,6 +2438,8 @@ pub(crate) mod tests {
             amount_mib: 0,
             deflate_on_oom: false,
             stats_polling_interval_s: 0,
+            stats_push: None,
+            auto_target: None,
         };
 
         let mut cmdline = default_kernel_cmdline();
@@ -1644,4 +2487,33 @@ pub(crate) mod tests {
             "virtio_mmio.device=4K@0xd0000000:5"
         ));
     }
+
+    #[test]
+    fn test_deferred_devices() {
+        let mut event_manager = EventManager::new().expect("Unable to create EventManager");
+        let mut vmm = default_vmm();
+        let mut cmdline = default_kernel_cmdline();
+
+        insert_entropy_device(
+            &mut vmm,
+            &mut cmdline,
+            &mut event_manager,
+            EntropyDeviceConfig::default(),
+        );
+        let deferred_devices = vmm.mmio_device_manager.take_deferred_virtio();
+        assert_eq!(deferred_devices.len(), 1);
+
+        spawn_deferred_devices(deferred_devices, vmm.vm.shared_fd(), true)
+            .unwrap()
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(METRICS.vmm.deferred_device_fails.count(), 0);
+
+        // No thread is spawned without deferred devices.
+        assert!(vmm.mmio_device_manager.take_deferred_virtio().is_empty());
+        assert!(spawn_deferred_devices(vec![], vmm.vm.shared_fd(), true)
+            .unwrap()
+            .is_none());
+    }
 }
@@ -0,0 +1,213 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Chaos mode, which pauses, snapshots and restores the running microVM in place at random
+//! intervals, to test how the guest software copes with being restored from a snapshot, e.g. with
+//! the time jumps and the connection resets caused by the downtime.
+
+use std::os::unix::io::AsRawFd;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use event_manager::{EventOps, Events, MutEventSubscriber};
+use timerfd::{ClockId, SetTimeFlags, TimerFd, TimerState};
+use vmm_sys_util::epoll::EventSet;
+use vmm_sys_util::rand::xor_pseudo_rng_u64;
+
+use crate::devices::acpi::vmgenid::VmGenIdError;
+use crate::logger::{error, info, IncMetric, METRICS};
+use crate::persist::{MicrovmState, MicrovmStateError, VmInfo, SNAPSHOT_VERSION};
+use crate::snapshot::{Snapshot, SnapshotError};
+use crate::vmm_config::instance_info::VmState;
+use crate::{Vmm, VmmError};
+
+/// Time during which the microVM stays paused in each cycle, unless configured otherwise.
+pub const DEFAULT_CHAOS_DOWNTIME_MS: u64 = 1000;
+
+/// Errors associated with the chaos mode cycles.
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum ChaosError {
+    /// Failed to pause the microVM: {0}
+    Pause(VmmError),
+    /// Failed to save the microVM state: {0}
+    SaveState(MicrovmStateError),
+    /// Failed to serialize the microVM state: {0}
+    Serialize(SnapshotError),
+    /// Failed to deserialize the microVM state: {0}
+    Deserialize(SnapshotError),
+    /// Failed to restore the microVM state: {0}
+    RestoreState(MicrovmStateError),
+    /// Failed to notify the guest of the restore: {0}
+    VmGenId(VmGenIdError),
+    /// Failed to resume the microVM: {0}
+    Resume(VmmError),
+}
+
+/// Periodically snapshots and restores the microVM in place, keeping its resources.
+///
+/// Each cycle pauses the microVM and saves its state through the snapshot format, then restores
+/// it after the configured downtime, notifies the guest through the VMGenID device, if any, and
+/// resumes the microVM. The guest memory is left as is, and the devices keep their state.
+#[derive(Debug)]
+pub struct ChaosMonkey {
+    interval: Duration,
+    downtime: Duration,
+    timer_fd: TimerFd,
+    vmm: Option<Arc<Mutex<Vmm>>>,
+    /// State saved at the start of the cycle in progress, if any.
+    saved_state: Option<MicrovmState>,
+}
+
+impl ChaosMonkey {
+    /// Creates a chaos monkey starting cycles every `interval_ms` millisecs on average, during
+    /// which the microVM is paused for `downtime_ms` millisecs.
+    /// Can panic on `TimerFd` creation failure.
+    pub fn new(interval_ms: u64, downtime_ms: u64) -> Self {
+        let timer_fd = TimerFd::new_custom(ClockId::Monotonic, true, true)
+            .expect("Cannot create the chaos mode timer fd.");
+        ChaosMonkey {
+            interval: Duration::from_millis(interval_ms),
+            downtime: Duration::from_millis(downtime_ms),
+            timer_fd,
+            vmm: None,
+            saved_state: None,
+        }
+    }
+
+    /// Sets the microVM to snapshot and restore, and schedules the first cycle.
+    pub fn set_vmm(&mut self, vmm: Arc<Mutex<Vmm>>) {
+        self.vmm = Some(vmm);
+        self.arm(self.next_interval());
+    }
+
+    /// Returns a random delay before the next cycle, between half and one and a half intervals.
+    fn next_interval(&self) -> Duration {
+        let interval_ms = u64::try_from(self.interval.as_millis()).unwrap_or(u64::MAX);
+        let jitter_ms = xor_pseudo_rng_u64() % interval_ms.max(1);
+        Duration::from_millis(interval_ms / 2 + jitter_ms)
+    }
+
+    fn arm(&mut self, delay: Duration) {
+        // A zero duration would disarm the timer.
+        let delay = delay.max(Duration::from_millis(1));
+        self.timer_fd
+            .set_state(TimerState::Oneshot(delay), SetTimeFlags::Default);
+    }
+
+    /// Pauses the microVM and saves its state, round-tripped through the snapshot format.
+    fn start_cycle(vmm: &mut Vmm) -> Result<MicrovmState, ChaosError> {
+        vmm.pause_vm().map_err(ChaosError::Pause)?;
+        let state = vmm
+            .save_state(&VmInfo::default())
+            .map_err(ChaosError::SaveState);
+        let state = state.and_then(|state| {
+            let mut buffer = Vec::new();
+            Snapshot::new(SNAPSHOT_VERSION)
+                .save(&mut buffer, &state)
+                .map_err(ChaosError::Serialize)?;
+            let len = buffer.len();
+            Snapshot::new(SNAPSHOT_VERSION)
+                .load_with_version_check(&mut buffer.as_slice(), len)
+                .map_err(ChaosError::Deserialize)
+        });
+        // Leave the microVM running if it can not be snapshotted.
+        if state.is_err() {
+            vmm.resume_vm().map_err(ChaosError::Resume)?;
+        }
+        state
+    }
+
+    /// Restores the saved state into the microVM, notifies the guest and resumes the microVM.
+    fn end_cycle(vmm: &mut Vmm, state: MicrovmState) -> Result<(), ChaosError> {
+        let restored = vmm
+            .restore_state_in_place(state)
+            .map_err(ChaosError::RestoreState)
+            .and_then(|()| {
+                vmm.acpi_device_manager
+                    .regenerate_vmgenid(&vmm.guest_memory)
+                    .map_err(ChaosError::VmGenId)
+            });
+        vmm.resume_vm().map_err(ChaosError::Resume)?;
+        restored
+    }
+
+    fn run_cycle(&mut self) {
+        let Some(vmm) = self.vmm.clone() else {
+            return;
+        };
+        let mut vmm = vmm.lock().expect("Poisoned lock");
+
+        let result = match self.saved_state.take() {
+            // The microVM may have been paused or resumed through the API in the meantime, in
+            // which case the cycle is skipped.
+            None if vmm.instance_info.state != VmState::Running => Ok(()),
+            None => Self::start_cycle(&mut vmm).map(|state| {
+                info!("Chaos mode: microVM paused and snapshotted");
+                self.saved_state = Some(state);
+            }),
+            Some(_) if vmm.instance_info.state != VmState::Paused => Ok(()),
+            Some(state) => Self::end_cycle(&mut vmm, state).map(|()| {
+                info!("Chaos mode: microVM restored and resumed");
+                METRICS.vmm.chaos_cycles.inc();
+            }),
+        };
+        if let Err(err) = result {
+            error!("Chaos mode cycle failed: {}", err);
+            METRICS.vmm.chaos_cycle_fails.inc();
+        }
+
+        match self.saved_state {
+            Some(_) => self.arm(self.downtime),
+            None => self.arm(self.next_interval()),
+        }
+    }
+}
+
+impl MutEventSubscriber for ChaosMonkey {
+    /// Handle a read event (EPOLLIN).
+    fn process(&mut self, event: Events, _: &mut EventOps) {
+        if event.fd() == self.timer_fd.as_raw_fd() && event.event_set() == EventSet::IN {
+            self.timer_fd.read();
+            self.run_cycle();
+        } else {
+            error!("Spurious EventManager event for handler: ChaosMonkey");
+        }
+    }
+
+    fn init(&mut self, ops: &mut EventOps) {
+        if let Err(err) = ops.add(Events::new(&self.timer_fd, EventSet::IN)) {
+            error!("Failed to register chaos mode timerfd event: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::tests::default_vmm;
+
+    #[test]
+    fn test_next_interval() {
+        let monkey = ChaosMonkey::new(100, 10);
+        for _ in 0..100 {
+            let interval = monkey.next_interval();
+            assert!(interval >= Duration::from_millis(50));
+            assert!(interval < Duration::from_millis(150));
+        }
+    }
+
+    #[test]
+    fn test_skip_cycle() {
+        let mut monkey = ChaosMonkey::new(100, 10);
+        // Nothing happens until the microVM is set.
+        monkey.run_cycle();
+        assert!(monkey.saved_state.is_none());
+
+        // A microVM which is not running is left alone.
+        monkey.set_vmm(Arc::new(Mutex::new(default_vmm())));
+        let fails = METRICS.vmm.chaos_cycle_fails.count();
+        monkey.run_cycle();
+        assert!(monkey.saved_state.is_none());
+        assert_eq!(METRICS.vmm.chaos_cycle_fails.count(), fails);
+    }
+}
@@ -0,0 +1,155 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Snapshots written when the microVM fails, i.e. when a vCPU exits on an error or the guest
+//! resets, for the post-mortem analysis of the failure.
+
+use std::path::PathBuf;
+
+use utils::time::{get_time_us, ClockType};
+
+use crate::logger::{error, info, IncMetric, METRICS};
+use crate::persist::{create_snapshot, CreateSnapshotError, VmInfo};
+use crate::resources::VmResources;
+use crate::vmm_config::instance_info::VmState;
+use crate::vmm_config::snapshot::{CreateSnapshotParams, SnapshotType};
+use crate::{VcpuEvent, VcpuResponse, Vmm, VmmError, RECV_TIMEOUT_SEC};
+
+/// Errors associated with the snapshots of the failures.
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum CoredumpSnapshotError {
+    /// The snapshot directory {0:?} does not exist.
+    MissingDirectory(PathBuf),
+    /// Snapshots are not allowed on uVMs with nested virtualization enabled.
+    NestedVirt,
+    /// Snapshots are not allowed on uVMs with memory tiers.
+    MemoryTiers,
+    /// Failed to pause the vCPUs: {0}
+    Pause(VmmError),
+    /// Failed to create the snapshot: {0}
+    CreateSnapshot(#[from] CreateSnapshotError),
+}
+
+/// Writes a best-effort full snapshot of the microVM to a directory when it fails, before
+/// Firecracker exits.
+///
+/// The vCPUs which are still running are paused, and the state of the exited ones is saved as it
+/// was when they exited. The files are named after the instance id and the time of the failure.
+#[derive(Debug)]
+pub struct CoredumpSnapshot {
+    dir: PathBuf,
+    vm_info: VmInfo,
+}
+
+impl CoredumpSnapshot {
+    /// Prepares the snapshots, to `dir`, of the failures of the microVM built from
+    /// `vm_resources`.
+    pub fn new(dir: PathBuf, vm_resources: &VmResources) -> Result<Self, CoredumpSnapshotError> {
+        if !dir.is_dir() {
+            return Err(CoredumpSnapshotError::MissingDirectory(dir));
+        }
+        // The same restrictions as for the snapshots requested through the API apply.
+        if vm_resources.vm_config.nested_virt {
+            return Err(CoredumpSnapshotError::NestedVirt);
+        }
+        if !vm_resources.vm_config.memory_tiers.is_empty() {
+            return Err(CoredumpSnapshotError::MemoryTiers);
+        }
+        Ok(CoredumpSnapshot {
+            dir,
+            vm_info: VmInfo::from(vm_resources),
+        })
+    }
+
+    /// Returns the parameters of the snapshot of a failure of `instance_id` at `timestamp_s`.
+    fn params(&self, instance_id: &str, timestamp_s: u64) -> CreateSnapshotParams {
+        let name = format!("{instance_id}-{timestamp_s}");
+        CreateSnapshotParams {
+            snapshot_type: SnapshotType::Full,
+            snapshot_path: self.dir.join(format!("{name}.vmstate")),
+            mem_file_path: self.dir.join(format!("{name}.mem")),
+            labels: Default::default(),
+            encryption: None,
+            background: false,
+        }
+    }
+
+    /// Snapshots the microVM after the failure of one of its vCPUs.
+    pub(crate) fn write(&self, vmm: &mut Vmm) {
+        let params = self.params(
+            &vmm.instance_info.id,
+            get_time_us(ClockType::Real) / 1_000_000,
+        );
+        let result = Self::pause_vcpus(vmm).and_then(|()| {
+            create_snapshot(vmm, &self.vm_info, &params).map_err(CoredumpSnapshotError::from)
+        });
+        match result {
+            Ok(()) => {
+                info!(
+                    "Snapshotted the failed microVM to {}.",
+                    params.snapshot_path.display()
+                );
+                METRICS.vmm.coredump_snapshots.inc();
+            }
+            Err(err) => {
+                error!("Failed to snapshot the failed microVM: {}", err);
+                METRICS.vmm.coredump_snapshot_fails.inc();
+            }
+        }
+    }
+
+    // Pauses the vCPUs which are still running, while the exited ones keep answering `Exited`.
+    fn pause_vcpus(vmm: &mut Vmm) -> Result<(), CoredumpSnapshotError> {
+        vmm.vcpus_handles
+            .iter()
+            .try_for_each(|handle| handle.send_event(VcpuEvent::Pause))
+            .map_err(|_| CoredumpSnapshotError::Pause(VmmError::VcpuMessage))?;
+
+        if vmm
+            .vcpus_handles
+            .iter()
+            .map(|handle| handle.response_receiver().recv_timeout(RECV_TIMEOUT_SEC))
+            .any(|response| {
+                !matches!(
+                    response,
+                    Ok(VcpuResponse::Paused) | Ok(VcpuResponse::Exited(..))
+                )
+            })
+        {
+            return Err(CoredumpSnapshotError::Pause(VmmError::VcpuMessage));
+        }
+
+        vmm.mmio_device_manager.set_net_devices_paused(true);
+        vmm.instance_info.state = VmState::Paused;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use vmm_sys_util::tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_coredump_snapshot() {
+        let vm_resources = VmResources::default();
+        assert!(matches!(
+            CoredumpSnapshot::new(PathBuf::from("/nonexistent"), &vm_resources),
+            Err(CoredumpSnapshotError::MissingDirectory(_))
+        ));
+
+        let dir = TempDir::new().unwrap();
+        let coredump = CoredumpSnapshot::new(dir.as_path().to_path_buf(), &vm_resources).unwrap();
+        let params = coredump.params("vm0", 1700000000);
+        assert_eq!(params.snapshot_type, SnapshotType::Full);
+        assert_eq!(
+            params.snapshot_path,
+            dir.as_path().join("vm0-1700000000.vmstate")
+        );
+        assert_eq!(
+            params.mem_file_path,
+            dir.as_path().join("vm0-1700000000.mem")
+        );
+    }
+}
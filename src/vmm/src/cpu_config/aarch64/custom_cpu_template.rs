@@ -5,10 +5,16 @@
 /// config templates.
 use std::borrow::Cow;
 
-use serde::de::Error;
-use serde::{Deserialize, Serialize};
+use kvm_bindings::{
+    KVM_ARM_VCPU_PTRAUTH_ADDRESS, KVM_ARM_VCPU_PTRAUTH_GENERIC, KVM_CAP_ARM_MTE,
+    KVM_CAP_ARM_PTRAUTH_ADDRESS, KVM_CAP_ARM_PTRAUTH_GENERIC,
+};
+use serde::de::{Error, IntoDeserializer};
+use serde::{Deserialize, Deserializer, Serialize};
 
-use crate::arch::aarch64::regs::{reg_size, RegSize};
+use crate::arch::aarch64::regs::{
+    reg_size, RegSize, IDENTITY_REGS, NAMED_REGS, SVE_MAX_VECTOR_LENGTH, SVE_VQ_BITS,
+};
 use crate::cpu_config::aarch64::static_cpu_templates::v1n1;
 use crate::cpu_config::templates::{
     CpuTemplateType, GetCpuTemplate, GetCpuTemplateError, KvmCapability, RegisterValueFilter,
@@ -46,6 +52,16 @@ pub struct CustomCpuTemplate {
     /// Modifiers for registers on Aarch64 CPUs.
     #[serde(default)]
     pub reg_modifiers: Vec<RegisterModifier>,
+    /// Maximum SVE vector length in bits exposed to the guest.
+    /// Requires SVE to be enabled through `vcpu_features`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sve_vector_length: Option<u16>,
+    /// Enables pointer authentication (address and generic) for the guest.
+    #[serde(default)]
+    pub ptrauth: bool,
+    /// Enables the Memory Tagging Extension for the guest.
+    #[serde(default)]
+    pub mte: bool,
 }
 
 impl CustomCpuTemplate {
@@ -57,8 +73,101 @@ impl CustomCpuTemplate {
             .collect()
     }
 
+    /// Get the registers modified by the template, identified as in the template annotations,
+    /// along with the modified bits.
+    pub fn annotated_registers(&self) -> Vec<(String, u128)> {
+        self.modified_registers()
+            .into_iter()
+            .map(|(register, bitmap)| (register, bitmap.filter))
+            .collect()
+    }
+
+    /// Get the registers modified by the template, identified as in the template annotations,
+    /// along with their modifiers, in the order they are applied.
+    pub fn modified_registers(&self) -> Vec<(String, RegisterValueFilter<u128>)> {
+        self.reg_modifiers
+            .iter()
+            .map(|modifier| (format!("reg:{:#x}", modifier.addr), modifier.bitmap))
+            .collect()
+    }
+
+    /// Get the list of KVM capabilities to check, including the ones
+    /// required by the `ptrauth` and `mte` flags.
+    /// `KVM_CAP_ARM_MTE` is also enabled on the VM when it is present in the list.
+    pub fn all_kvm_capabilities(&self) -> Vec<KvmCapability> {
+        let mut caps = self.kvm_capabilities.clone();
+        if self.ptrauth {
+            caps.push(KvmCapability::Add(KVM_CAP_ARM_PTRAUTH_ADDRESS));
+            caps.push(KvmCapability::Add(KVM_CAP_ARM_PTRAUTH_GENERIC));
+        }
+        if self.mte {
+            caps.push(KvmCapability::Add(KVM_CAP_ARM_MTE));
+        }
+        caps
+    }
+
+    /// Get the list of vcpu feature modifiers, including the ones
+    /// required by the `ptrauth` flag.
+    pub fn all_vcpu_features(&self) -> Vec<VcpuFeatures> {
+        let mut features = self.vcpu_features.clone();
+        if self.ptrauth {
+            let ptrauth = (1 << KVM_ARM_VCPU_PTRAUTH_ADDRESS) | (1 << KVM_ARM_VCPU_PTRAUTH_GENERIC);
+            features.push(VcpuFeatures {
+                index: 0,
+                bitmap: RegisterValueFilter {
+                    filter: ptrauth,
+                    value: ptrauth,
+                },
+            });
+        }
+        features
+    }
+
+    /// Apply the modifiers of `overrides` on top of the ones of the template. The bits modified
+    /// by both templates take the value from `overrides`.
+    pub fn apply_overrides(&mut self, overrides: CustomCpuTemplate) {
+        for capability in overrides.kvm_capabilities {
+            if !self.kvm_capabilities.contains(&capability) {
+                self.kvm_capabilities.push(capability);
+            }
+        }
+        for feature in overrides.vcpu_features {
+            match self
+                .vcpu_features
+                .iter_mut()
+                .find(|base| base.index == feature.index)
+            {
+                Some(base) => base.bitmap = base.bitmap.overridden_by(&feature.bitmap),
+                None => self.vcpu_features.push(feature),
+            }
+        }
+        for modifier in overrides.reg_modifiers {
+            match self
+                .reg_modifiers
+                .iter_mut()
+                .find(|base| base.addr == modifier.addr)
+            {
+                Some(base) => base.bitmap = base.bitmap.overridden_by(&modifier.bitmap),
+                None => self.reg_modifiers.push(modifier),
+            }
+        }
+        if overrides.sve_vector_length.is_some() {
+            self.sve_vector_length = overrides.sve_vector_length;
+        }
+        self.ptrauth |= overrides.ptrauth;
+        self.mte |= overrides.mte;
+    }
+
     /// Validate the correctness of the template.
     pub fn validate(&self) -> Result<(), serde_json::Error> {
+        if let Some(len) = self.sve_vector_length {
+            if len == 0 || len > SVE_MAX_VECTOR_LENGTH || len % SVE_VQ_BITS != 0 {
+                return Err(serde_json::Error::custom(format!(
+                    "Invalid SVE vector length: {len} - should be a multiple of {SVE_VQ_BITS} \
+                     bits, not greater than {SVE_MAX_VECTOR_LENGTH} bits"
+                )));
+            }
+        }
         for modifier in self.reg_modifiers.iter() {
             let reg_size = reg_size(modifier.addr);
             match RegSize::from(reg_size) {
@@ -82,6 +191,16 @@ impl CustomCpuTemplate {
                     )))
                 }
             }
+            if let Some((name, _, writable_bits)) =
+                IDENTITY_REGS.iter().find(|(_, id, _)| *id == modifier.addr)
+            {
+                if modifier.bitmap.filter & !u128::from(*writable_bits) != 0 {
+                    return Err(serde_json::Error::custom(format!(
+                        "Invalid bitmap for register {name}: only the bits {writable_bits:#x} can \
+                         be modified"
+                    )));
+                }
+            }
         }
         Ok(())
     }
@@ -102,7 +221,7 @@ pub struct VcpuFeatures {
 pub struct RegisterModifier {
     /// Pointer of the location to be bit mapped.
     #[serde(
-        deserialize_with = "deserialize_from_str_u64",
+        deserialize_with = "deserialize_reg_addr",
         serialize_with = "serialize_to_hex_str"
     )]
     pub addr: u64,
@@ -111,6 +230,21 @@ pub struct RegisterModifier {
     pub bitmap: RegisterValueFilter<u128>,
 }
 
+/// Deserializes a register ID, given either as a number or as the name of one of the registers
+/// of [`NAMED_REGS`].
+fn deserialize_reg_addr<'de, D>(deserializer: D) -> Result<u64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let addr = String::deserialize(deserializer)?;
+    match NAMED_REGS.iter().find(|(name, _)| *name == addr) {
+        Some((_, id)) => Ok(*id),
+        None => deserialize_from_str_u64(IntoDeserializer::<D::Error>::into_deserializer(
+            addr.as_str(),
+        )),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use serde_json::Value;
@@ -170,6 +304,7 @@ mod tests {
             r#"{
                     "kvm_capabilities": ["1", "!2"],
                     "vcpu_features":[{"index":0,"bitmap":"0b1100000"}],
+                    "sve_vector_length": 512,
                     "reg_modifiers":  [
                         {
                             "addr": "0x0030000000000000",
@@ -315,6 +450,37 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_ptrauth_mte_flags() {
+        let template = CustomCpuTemplate::default();
+        assert!(template.all_kvm_capabilities().is_empty());
+        assert!(template.all_vcpu_features().is_empty());
+
+        let template = serde_json::from_str::<CustomCpuTemplate>(
+            r#"{
+                "kvm_capabilities": ["1"],
+                "ptrauth": true,
+                "mte": true
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            template.all_kvm_capabilities(),
+            vec![
+                KvmCapability::Add(1),
+                KvmCapability::Add(KVM_CAP_ARM_PTRAUTH_ADDRESS),
+                KvmCapability::Add(KVM_CAP_ARM_PTRAUTH_GENERIC),
+                KvmCapability::Add(KVM_CAP_ARM_MTE),
+            ]
+        );
+        let features = template.all_vcpu_features();
+        assert_eq!(features.len(), 1);
+        assert_eq!(
+            features[0].bitmap.apply(0),
+            (1 << KVM_ARM_VCPU_PTRAUTH_ADDRESS) | (1 << KVM_ARM_VCPU_PTRAUTH_GENERIC)
+        );
+    }
+
     #[test]
     fn test_cpu_template_validate() {
         // 32, 64 and 128 bit regs with correct filters and values
@@ -384,5 +550,168 @@ mod tests {
             ..Default::default()
         };
         template.validate().unwrap_err();
+
+        // Valid SVE vector lengths
+        for len in [128, 512, 2048] {
+            let template = CustomCpuTemplate {
+                sve_vector_length: Some(len),
+                ..Default::default()
+            };
+            template.validate().unwrap();
+        }
+
+        // Invalid SVE vector lengths
+        for len in [0, 100, 2176] {
+            let template = CustomCpuTemplate {
+                sve_vector_length: Some(len),
+                ..Default::default()
+            };
+            template.validate().unwrap_err();
+        }
+    }
+
+    #[test]
+    fn test_identity_regs() {
+        use crate::arch::aarch64::regs::{CTR_EL0, ID_AA64PFR0_EL1, MIDR_EL1};
+
+        // Registers can be named instead of given by ID.
+        let template = CustomCpuTemplate::try_from(
+            r#"{
+                "reg_modifiers": [
+                    {"addr": "MIDR_EL1", "bitmap": "0b1xxxx"},
+                    {"addr": "ID_AA64PFR0_EL1", "bitmap": "0b0x"},
+                    {"addr": "0x603000000013d801", "bitmap": "0b1x"}
+                ]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            template.reg_list(),
+            vec![MIDR_EL1, ID_AA64PFR0_EL1, CTR_EL0]
+        );
+        CustomCpuTemplate::try_from(r#"{"reg_modifiers": [{"addr": "MIDR", "bitmap": "0b1"}]}"#)
+            .unwrap_err();
+
+        // Only the bits of the identity registers that can be written are modified.
+        let mut template = CustomCpuTemplate {
+            reg_modifiers: vec![RegisterModifier {
+                addr: MIDR_EL1,
+                bitmap: RegisterValueFilter {
+                    filter: 0xffff_ffff,
+                    value: 0x410f_d401,
+                },
+            }],
+            ..Default::default()
+        };
+        template.validate().unwrap();
+        template.reg_modifiers[0].bitmap.filter = 1 << 32;
+        template.validate().unwrap_err();
+        template.reg_modifiers[0] = RegisterModifier {
+            addr: CTR_EL0,
+            bitmap: RegisterValueFilter {
+                filter: 1 << 29,
+                value: 1 << 29,
+            },
+        };
+        template.validate().unwrap();
+        template.reg_modifiers[0].bitmap.filter = 1 << 31;
+        template.validate().unwrap_err();
+    }
+
+    #[test]
+    fn test_annotations() {
+        let template_json = |annotations: &str| {
+            format!(
+                r#"{{
+                    "reg_modifiers": [
+                        {{
+                            "addr": "0x603000000013c020",
+                            "bitmap": "0bxxxxxxxxxxxx0000xxxxxxxxxxxx0000xxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx"
+                        }}
+                    ],
+                    "annotations": {annotations}
+                }}"#
+            )
+        };
+
+        let template = CustomCpuTemplate::try_from(
+            template_json(
+                r#"{
+                    "provenance": "Test template",
+                    "features": [
+                        {"register": "reg:0x603000000013c020", "bit": 32, "name": "sve"},
+                        {"register": "reg:0x603000000013c020", "bit": 48, "name": "dit"}
+                    ]
+                }"#,
+            )
+            .as_str(),
+        )
+        .unwrap();
+        // The annotations are stripped.
+        assert_eq!(template.reg_modifiers.len(), 1);
+        assert!(!serde_json::to_string(&template)
+            .unwrap()
+            .contains("annotations"));
+
+        // Bit not modified by the template.
+        CustomCpuTemplate::try_from(
+            template_json(
+                r#"{"features": [{"register": "reg:0x603000000013c020", "bit": 0, "name": "a"}]}"#,
+            )
+            .as_str(),
+        )
+        .unwrap_err();
+        // Register not modified by the template.
+        CustomCpuTemplate::try_from(
+            template_json(
+                r#"{"features": [{"register": "reg:0x603000000013c030", "bit": 32, "name": "a"}]}"#,
+            )
+            .as_str(),
+        )
+        .unwrap_err();
+        // Unknown annotation.
+        CustomCpuTemplate::try_from(template_json(r#"{"author": "someone"}"#).as_str())
+            .unwrap_err();
+    }
+
+    #[test]
+    fn test_extends_static_template() {
+        let template = CustomCpuTemplate::try_from(
+            r#"{
+                "extends": "V1N1",
+                "reg_modifiers": [
+                    {
+                        "addr": "0x603000000013c020",
+                        "bitmap": "0bxxxxxxxxxxxx0001xxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx"
+                    }
+                ],
+                "ptrauth": true
+            }"#,
+        )
+        .unwrap();
+
+        let base = v1n1::v1n1();
+        assert_eq!(template.reg_modifiers.len(), base.reg_modifiers.len());
+        // The overridden bits take the value of the extending template, the other bits of the
+        // static template are kept.
+        let base_bitmap = base.reg_modifiers[0].bitmap;
+        assert_eq!(template.reg_modifiers[0].addr, 0x603000000013c020);
+        assert_eq!(template.reg_modifiers[0].bitmap.filter, base_bitmap.filter);
+        assert_eq!(
+            template.reg_modifiers[0].bitmap.value,
+            base_bitmap.value | 1 << 48
+        );
+        assert_eq!(template.reg_modifiers[1..], base.reg_modifiers[1..]);
+        assert!(template.ptrauth);
+
+        // Extending a template with no modifiers gives the static template.
+        assert_eq!(
+            CustomCpuTemplate::try_from(r#"{"extends": "V1N1"}"#).unwrap(),
+            base
+        );
+        // There is nothing to extend.
+        CustomCpuTemplate::try_from(r#"{"extends": "None"}"#).unwrap_err();
+        // Unknown static template.
+        CustomCpuTemplate::try_from(r#"{"extends": "V2N2"}"#).unwrap_err();
     }
 }
@@ -1,21 +1,32 @@
 // Copyright 2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
-use vmm::arch::aarch64::regs::{RegSize, PC, SYS_CNTPCT_EL0, SYS_CNTV_CVAL_EL0};
-use vmm::cpu_config::aarch64::custom_cpu_template::RegisterModifier;
-use vmm::cpu_config::templates::{CpuConfiguration, CustomCpuTemplate, RegisterValueFilter};
-use vmm::logger::warn;
+use crate::arch::aarch64::regs::{RegSize, PC, SYS_CNTPCT_EL0, SYS_CNTV_CVAL_EL0};
+use crate::cpu_config::aarch64::custom_cpu_template::RegisterModifier;
+use crate::cpu_config::templates::{CpuConfiguration, CustomCpuTemplate, RegisterValueFilter};
+use crate::logger::warn;
 
-use crate::utils::aarch64::reg_modifier;
+fn reg_modifier(addr: u64, value: u128) -> RegisterModifier {
+    RegisterModifier {
+        addr,
+        bitmap: RegisterValueFilter {
+            filter: u128::MAX,
+            value,
+        },
+    }
+}
 
+/// Converts a CPU configuration to a custom CPU template setting all of its bits.
+///
+/// The registers which depend on the elapsed time or on the kernel image are excluded.
 pub fn config_to_template(cpu_config: &CpuConfiguration) -> CustomCpuTemplate {
     let mut reg_modifiers: Vec<RegisterModifier> = cpu_config
         .regs
         .iter()
         .filter_map(|reg| match reg.size() {
-            RegSize::U32 => Some(reg_modifier!(reg.id, u128::from(reg.value::<u32, 4>()))),
-            RegSize::U64 => Some(reg_modifier!(reg.id, u128::from(reg.value::<u64, 8>()))),
-            RegSize::U128 => Some(reg_modifier!(reg.id, reg.value::<u128, 16>())),
+            RegSize::U32 => Some(reg_modifier(reg.id, u128::from(reg.value::<u32, 4>()))),
+            RegSize::U64 => Some(reg_modifier(reg.id, u128::from(reg.value::<u64, 8>()))),
+            RegSize::U128 => Some(reg_modifier(reg.id, reg.value::<u128, 16>())),
             _ => {
                 warn!(
                     "Only 32, 64 and 128 bit wide registers are supported in cpu templates. \
@@ -50,9 +61,8 @@ const REG_EXCLUSION_LIST: [u64; 3] = [
 
 #[cfg(test)]
 mod tests {
-    use vmm::arch::aarch64::regs::{reg_size, Aarch64RegisterRef, Aarch64RegisterVec};
-
     use super::*;
+    use crate::arch::aarch64::regs::{reg_size, Aarch64RegisterRef, Aarch64RegisterVec};
 
     // These are used as IDs to satisfy requirenments
     // of `Aarch64RegisterRef::new`
@@ -93,9 +103,9 @@ mod tests {
 
     fn build_expected_reg_modifiers() -> Vec<RegisterModifier> {
         vec![
-            reg_modifier!(KVM_REG_SIZE_U32, 0x0000_ffff),
-            reg_modifier!(KVM_REG_SIZE_U64, 0x0000_ffff_0000_ffff),
-            reg_modifier!(KVM_REG_SIZE_U128, 0xffff_ffff_ffff_ffff_ffff_ffff_ffff_ffff),
+            reg_modifier(KVM_REG_SIZE_U32, 0x0000_ffff),
+            reg_modifier(KVM_REG_SIZE_U64, 0x0000_ffff_0000_ffff),
+            reg_modifier(KVM_REG_SIZE_U128, 0xffff_ffff_ffff_ffff_ffff_ffff_ffff_ffff),
         ]
     }
 
@@ -3,13 +3,15 @@
 
 /// Module for custom CPU templates
 pub mod custom_cpu_template;
+/// Module for dumping CPU configurations as custom CPU templates
+pub mod dump;
 /// Module for static CPU templates
 pub mod static_cpu_templates;
 /// Module with test utils for custom CPU templates
 pub mod test_utils;
 
 use super::templates::CustomCpuTemplate;
-use crate::arch::aarch64::regs::{Aarch64RegisterVec, RegSize};
+use crate::arch::aarch64::regs::{is_feature_id_reg, Aarch64RegisterVec, RegSize};
 use crate::arch::aarch64::vcpu::VcpuError as ArchError;
 
 /// Errors thrown while configuring templates.
@@ -53,6 +55,42 @@ impl CpuConfiguration {
         Ok(self)
     }
 
+    /// Get the registers modified by `template` that are missing from this configuration,
+    /// identified as in the template annotations.
+    pub fn unsupported_registers(&self, template: &CustomCpuTemplate) -> Vec<String> {
+        template
+            .reg_list()
+            .into_iter()
+            .filter(|id| self.regs.iter().all(|reg| reg.id != *id))
+            .map(|id| format!("reg:{id:#x}"))
+            .collect()
+    }
+
+    /// Get the features of `guest` that are missing from this configuration, as the feature ID
+    /// register identified as in the template annotations and the mask of the 4-bit feature
+    /// fields with a higher value in `guest`.
+    pub fn missing_features(&self, guest: &CpuConfiguration) -> Vec<(String, u64)> {
+        guest
+            .regs
+            .iter()
+            .filter(|reg| is_feature_id_reg(reg.id))
+            .filter_map(|reg| {
+                let guest_value = reg.value::<u64, 8>();
+                let host_value = self
+                    .regs
+                    .iter()
+                    .find(|host_reg| host_reg.id == reg.id)
+                    .map_or(0, |host_reg| host_reg.value::<u64, 8>());
+                let missing = (0..64)
+                    .step_by(4)
+                    .map(|shift| 0xfu64 << shift)
+                    .filter(|field| guest_value & field > host_value & field)
+                    .fold(0, |mask, field| mask | field);
+                (missing != 0).then(|| (format!("reg:{:#x}", reg.id), missing))
+            })
+            .collect()
+    }
+
     /// Returns ids of registers that are changed
     /// by this template
     pub fn register_ids(&self) -> Vec<u64> {
@@ -3,6 +3,8 @@
 
 use serde::{Deserialize, Serialize};
 
+use crate::cpu_config::templates::CustomCpuTemplate;
+
 /// Module with V1N1 CPU template for aarch64
 pub mod v1n1;
 
@@ -21,6 +23,14 @@ impl StaticCpuTemplate {
     pub fn is_none(&self) -> bool {
         self == &StaticCpuTemplate::None
     }
+
+    /// Get the modifiers of the template, without checking that it can be applied on the host.
+    pub fn template(&self) -> Option<CustomCpuTemplate> {
+        match self {
+            StaticCpuTemplate::V1N1 => Some(v1n1::v1n1()),
+            StaticCpuTemplate::None => None,
+        }
+    }
 }
 
 impl std::fmt::Display for StaticCpuTemplate {
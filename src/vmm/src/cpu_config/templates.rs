@@ -4,6 +4,7 @@
 #[cfg(target_arch = "x86_64")]
 mod common_types {
     pub use crate::cpu_config::x86_64::custom_cpu_template::CustomCpuTemplate;
+    pub use crate::cpu_config::x86_64::dump::config_to_template;
     pub use crate::cpu_config::x86_64::static_cpu_templates::StaticCpuTemplate;
     pub use crate::cpu_config::x86_64::{
         test_utils, CpuConfiguration, CpuConfigurationError as GuestConfigError,
@@ -13,6 +14,7 @@ mod common_types {
 #[cfg(target_arch = "aarch64")]
 mod common_types {
     pub use crate::cpu_config::aarch64::custom_cpu_template::CustomCpuTemplate;
+    pub use crate::cpu_config::aarch64::dump::config_to_template;
     pub use crate::cpu_config::aarch64::static_cpu_templates::StaticCpuTemplate;
     pub use crate::cpu_config::aarch64::{
         test_utils, CpuConfiguration, CpuConfigurationError as GuestConfigError,
@@ -83,12 +85,178 @@ impl From<&CpuTemplateType> for StaticCpuTemplate {
     }
 }
 
+/// Key of the annotations section in the JSON representation of a custom CPU template.
+pub const ANNOTATIONS_KEY: &str = "annotations";
+
+/// Key of the static CPU template extended by a custom CPU template, in its JSON representation.
+pub const EXTENDS_KEY: &str = "extends";
+
+/// Resolves a custom CPU template extending the static CPU template `base`, by applying the
+/// modifiers of `overrides` on top of the ones of `base`.
+///
+/// The host is not checked against the requirements of `base`, as for any custom CPU template.
+pub fn extend_static_template(
+    base: StaticCpuTemplate,
+    overrides: CustomCpuTemplate,
+) -> Result<CustomCpuTemplate, serde_json::Error> {
+    let mut template = base.template().ok_or_else(|| {
+        serde_json::Error::custom(format!("Invalid static CPU template to extend: {base}"))
+    })?;
+    template.apply_overrides(overrides);
+    Ok(template)
+}
+
+/// Human-readable annotations of a custom CPU template, making templates shared between users
+/// auditable before they are applied.
+///
+/// The annotations are validated against the modifiers of the template when it is loaded, then
+/// dropped: they have no effect on the guest CPU configuration.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TemplateAnnotations {
+    /// Where the template comes from, e.g. who created it, from which host and with which tool.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<String>,
+    /// Names of the features controlled by the modified bits.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub features: Vec<FeatureAnnotation>,
+}
+
+/// Name of the feature controlled by a bit modified by a custom CPU template.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FeatureAnnotation {
+    /// Register holding the bit: `cpuid:<leaf>:<subleaf>:<register>` or `msr:<index>` on x86_64,
+    /// `reg:<id>` on aarch64, with the numbers in lowercase hexadecimal, e.g. `cpuid:0x7:0x0:ebx`.
+    pub register: String,
+    /// Index of the bit in the register.
+    pub bit: u8,
+    /// Name of the feature, e.g. `avx512f`.
+    pub name: String,
+}
+
+impl TemplateAnnotations {
+    /// Validate that the annotated bits are modified by the template.
+    pub fn validate(&self, template: &CustomCpuTemplate) -> Result<(), serde_json::Error> {
+        let registers = template.annotated_registers();
+        for feature in self.features.iter() {
+            let filter = registers
+                .iter()
+                .find(|(register, _)| register == &feature.register)
+                .map(|(_, filter)| *filter)
+                .ok_or_else(|| {
+                    serde_json::Error::custom(format!(
+                        "Invalid annotation of feature {}: register {} is not modified by the CPU \
+                         template",
+                        feature.name, feature.register
+                    ))
+                })?;
+            if 1u128
+                .checked_shl(u32::from(feature.bit))
+                .map_or(true, |bit| filter & bit == 0)
+            {
+                return Err(serde_json::Error::custom(format!(
+                    "Invalid annotation of feature {}: bit {} of register {} is not modified by \
+                     the CPU template",
+                    feature.name, feature.bit, feature.register
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Kind of problem found when validating a custom CPU template against the host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CpuTemplateIssueKind {
+    /// The register is not supported by KVM on the host.
+    UnsupportedRegister,
+    /// The register is modified several times, with different values for the same bits.
+    ConflictingModifiers,
+}
+
+/// Problem found when validating a custom CPU template against the host.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CpuTemplateIssue {
+    /// Kind of the problem.
+    pub kind: CpuTemplateIssueKind,
+    /// Register concerned, identified as in the template annotations.
+    pub register: String,
+    /// Human-readable description of the problem.
+    pub description: String,
+}
+
+/// Result of the validation of a custom CPU template against the host, without applying it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct CpuTemplateReport {
+    /// Whether the template can be applied to the vCPUs of the microVM.
+    pub valid: bool,
+    /// Problems found in the template.
+    pub issues: Vec<CpuTemplateIssue>,
+}
+
+impl CpuTemplateReport {
+    /// Validates `template` against `host`, the configuration of a vCPU created without CPU
+    /// template.
+    pub fn new(template: &CustomCpuTemplate, host: &CpuConfiguration) -> Self {
+        let mut issues: Vec<CpuTemplateIssue> = host
+            .unsupported_registers(template)
+            .into_iter()
+            .map(|register| CpuTemplateIssue {
+                kind: CpuTemplateIssueKind::UnsupportedRegister,
+                description: format!("Register {register} is not supported by KVM on this host"),
+                register,
+            })
+            .collect();
+
+        let modifiers = template.modified_registers();
+        for (i, (register, bitmap)) in modifiers.iter().enumerate() {
+            let conflicting = modifiers[..i].iter().any(|(other_register, other)| {
+                let overlap = bitmap.filter & other.filter;
+                other_register == register && (bitmap.value ^ other.value) & overlap != 0
+            });
+            if conflicting {
+                issues.push(CpuTemplateIssue {
+                    kind: CpuTemplateIssueKind::ConflictingModifiers,
+                    register: register.clone(),
+                    description: format!(
+                        "Register {register} is modified several times with different values for \
+                         the same bits"
+                    ),
+                });
+            }
+        }
+
+        CpuTemplateReport {
+            valid: issues.is_empty(),
+            issues,
+        }
+    }
+}
+
 impl TryFrom<&[u8]> for CustomCpuTemplate {
     type Error = serde_json::Error;
 
+    /// Parses a custom CPU template, resolving the static CPU template it extends, if any, then
+    /// validating and stripping its annotations, if any.
     fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
-        let template: CustomCpuTemplate = serde_json::from_slice(value)?;
+        let mut json: serde_json::Value = serde_json::from_slice(value)?;
+        let annotations = json
+            .as_object_mut()
+            .and_then(|template| template.remove(ANNOTATIONS_KEY));
+        let extends = json
+            .as_object_mut()
+            .and_then(|template| template.remove(EXTENDS_KEY));
+        let mut template: CustomCpuTemplate = serde_json::from_value(json)?;
+        if let Some(extends) = extends {
+            let base = serde_json::from_value::<StaticCpuTemplate>(extends)?;
+            template = extend_static_template(base, template)?;
+        }
         template.validate()?;
+        if let Some(annotations) = annotations {
+            serde_json::from_value::<TemplateAnnotations>(annotations)?.validate(&template)?;
+        }
         Ok(template)
     }
 }
@@ -175,6 +343,15 @@ where
     pub fn apply(&self, value: V) -> V {
         (value & !self.filter) | self.value
     }
+
+    /// Combines the filter with `other`, the bits set by both taking the value from `other`.
+    #[inline]
+    pub fn overridden_by(&self, other: &Self) -> Self {
+        RegisterValueFilter {
+            filter: self.filter | other.filter,
+            value: (self.value & !other.filter) | other.value,
+        }
+    }
 }
 
 impl<V> Serialize for RegisterValueFilter<V>
@@ -363,4 +540,27 @@ mod tests {
         let deserialized: Result<RegisterValueFilter<u8>, _> = serde_json::from_str(serialized);
         deserialized.unwrap_err();
     }
+
+    #[test]
+    fn test_register_value_filter_overridden_by() {
+        let base = RegisterValueFilter::<u8> {
+            filter: 0b1111_0000,
+            value: 0b1010_0000,
+        };
+        let overrides = RegisterValueFilter::<u8> {
+            filter: 0b0011_1100,
+            value: 0b0001_0100,
+        };
+        assert_eq!(
+            base.overridden_by(&overrides),
+            RegisterValueFilter::<u8> {
+                filter: 0b1111_1100,
+                value: 0b1001_0100,
+            }
+        );
+        assert_eq!(
+            base.overridden_by(&overrides).apply(0b0000_0011),
+            0b1001_0111
+        );
+    }
 }
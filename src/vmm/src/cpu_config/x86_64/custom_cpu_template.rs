@@ -149,6 +149,85 @@ impl CustomCpuTemplate {
         self.msr_modifiers.iter().map(|modifier| modifier.addr)
     }
 
+    /// Get the registers modified by the template, identified as in the template annotations,
+    /// along with the modified bits.
+    pub fn annotated_registers(&self) -> Vec<(String, u128)> {
+        self.modified_registers()
+            .into_iter()
+            .map(|(register, bitmap)| (register, bitmap.filter))
+            .collect()
+    }
+
+    /// Get the registers modified by the template, identified as in the template annotations,
+    /// along with their modifiers, in the order they are applied.
+    pub fn modified_registers(&self) -> Vec<(String, RegisterValueFilter<u128>)> {
+        let cpuid = self.cpuid_modifiers.iter().flat_map(|leaf| {
+            leaf.modifiers.iter().map(move |modifier| {
+                (
+                    format!(
+                        "cpuid:{:#x}:{:#x}:{}",
+                        leaf.leaf,
+                        leaf.subleaf,
+                        format!("{:?}", modifier.register).to_lowercase()
+                    ),
+                    RegisterValueFilter {
+                        filter: u128::from(modifier.bitmap.filter),
+                        value: u128::from(modifier.bitmap.value),
+                    },
+                )
+            })
+        });
+        let msr = self.msr_modifiers.iter().map(|modifier| {
+            (
+                format!("msr:{:#x}", modifier.addr),
+                RegisterValueFilter {
+                    filter: u128::from(modifier.bitmap.filter),
+                    value: u128::from(modifier.bitmap.value),
+                },
+            )
+        });
+        cpuid.chain(msr).collect()
+    }
+
+    /// Apply the modifiers of `overrides` on top of the ones of the template. The bits modified
+    /// by both templates take the value from `overrides`.
+    pub fn apply_overrides(&mut self, overrides: CustomCpuTemplate) {
+        for capability in overrides.kvm_capabilities {
+            if !self.kvm_capabilities.contains(&capability) {
+                self.kvm_capabilities.push(capability);
+            }
+        }
+        for leaf_modifier in overrides.cpuid_modifiers {
+            let Some(base) = self.cpuid_modifiers.iter_mut().find(|base| {
+                base.leaf == leaf_modifier.leaf && base.subleaf == leaf_modifier.subleaf
+            }) else {
+                self.cpuid_modifiers.push(leaf_modifier);
+                continue;
+            };
+            base.flags = KvmCpuidFlags(base.flags.0 | leaf_modifier.flags.0);
+            for modifier in leaf_modifier.modifiers {
+                match base
+                    .modifiers
+                    .iter_mut()
+                    .find(|base| base.register == modifier.register)
+                {
+                    Some(base) => base.bitmap = base.bitmap.overridden_by(&modifier.bitmap),
+                    None => base.modifiers.push(modifier),
+                }
+            }
+        }
+        for modifier in overrides.msr_modifiers {
+            match self
+                .msr_modifiers
+                .iter_mut()
+                .find(|base| base.addr == modifier.addr)
+            {
+                Some(base) => base.bitmap = base.bitmap.overridden_by(&modifier.bitmap),
+                None => self.msr_modifiers.push(modifier),
+            }
+        }
+    }
+
     /// Validate the correctness of the template.
     pub fn validate(&self) -> Result<(), serde_json::Error> {
         Ok(())
@@ -531,4 +610,165 @@ mod tests {
             "MSR bitmap width in a x86_64 template was not tested."
         );
     }
+
+    #[test]
+    fn test_annotations() {
+        let template_json = |annotations: &str| {
+            format!(
+                r#"{{
+                    "cpuid_modifiers": [
+                        {{
+                            "leaf": "0x7",
+                            "subleaf": "0x0",
+                            "flags": 1,
+                            "modifiers": [
+                                {{
+                                    "register": "ebx",
+                                    "bitmap": "0bxxxxxxxxxxxxxxx0xxxxxxxxxxxxxxxx"
+                                }}
+                            ]
+                        }}
+                    ],
+                    "msr_modifiers": [
+                        {{
+                            "addr": "0x10a",
+                            "bitmap": "0bxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx1x"
+                        }}
+                    ],
+                    "annotations": {annotations}
+                }}"#
+            )
+        };
+
+        let template = CustomCpuTemplate::try_from(
+            template_json(
+                r#"{
+                    "provenance": "Test template",
+                    "features": [
+                        {"register": "cpuid:0x7:0x0:ebx", "bit": 16, "name": "avx512f"},
+                        {"register": "msr:0x10a", "bit": 1, "name": "ibrs_all"}
+                    ]
+                }"#,
+            )
+            .as_str(),
+        )
+        .unwrap();
+        // The annotations are stripped.
+        assert_eq!(template.cpuid_modifiers.len(), 1);
+        assert_eq!(template.msr_modifiers.len(), 1);
+        assert!(!serde_json::to_string(&template)
+            .unwrap()
+            .contains("annotations"));
+
+        // Bit not modified by the template.
+        CustomCpuTemplate::try_from(
+            template_json(r#"{"features": [{"register": "msr:0x10a", "bit": 2, "name": "a"}]}"#)
+                .as_str(),
+        )
+        .unwrap_err();
+        // Bit out of the register.
+        CustomCpuTemplate::try_from(
+            template_json(r#"{"features": [{"register": "msr:0x10a", "bit": 200, "name": "a"}]}"#)
+                .as_str(),
+        )
+        .unwrap_err();
+        // Register not modified by the template.
+        CustomCpuTemplate::try_from(
+            template_json(
+                r#"{"features": [{"register": "cpuid:0x7:0x0:ecx", "bit": 16, "name": "a"}]}"#,
+            )
+            .as_str(),
+        )
+        .unwrap_err();
+        // Unknown annotation.
+        CustomCpuTemplate::try_from(template_json(r#"{"author": "someone"}"#).as_str())
+            .unwrap_err();
+    }
+
+    #[test]
+    fn test_extends_static_template() {
+        let template = CustomCpuTemplate::try_from(
+            r#"{
+                "extends": "T2CL",
+                "cpuid_modifiers": [
+                    {
+                        "leaf": "0x7",
+                        "subleaf": "0x0",
+                        "flags": 1,
+                        "modifiers": [
+                            {
+                                "register": "ebx",
+                                "bitmap": "0bxxxxxxxxxxxxxxx1xxxxxxxxxxxxxxxx"
+                            }
+                        ]
+                    }
+                ],
+                "msr_modifiers": [
+                    {
+                        "addr": "0x10a",
+                        "bitmap": "0bxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx0x"
+                    },
+                    {
+                        "addr": "0x1a0",
+                        "bitmap": "0bxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx1"
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let base = t2cl::t2cl();
+        assert_eq!(template.cpuid_modifiers.len(), base.cpuid_modifiers.len());
+        assert_eq!(template.msr_modifiers.len(), base.msr_modifiers.len() + 1);
+        // The overridden bits take the value of the extending template, the other bits of the
+        // static template are kept.
+        let leaf_0x7 = |template: &CustomCpuTemplate| {
+            template
+                .cpuid_modifiers
+                .iter()
+                .find(|leaf| leaf.leaf == 0x7 && leaf.subleaf == 0x0)
+                .unwrap()
+                .modifiers
+                .iter()
+                .find(|modifier| modifier.register == CpuidRegister::Ebx)
+                .unwrap()
+                .bitmap
+        };
+        let bitmap = leaf_0x7(&template);
+        let base_bitmap = leaf_0x7(&base);
+        assert_eq!(bitmap.filter, base_bitmap.filter | 1 << 16);
+        assert_eq!(bitmap.value, base_bitmap.value | 1 << 16);
+        let arch_capabilities = |template: &CustomCpuTemplate| {
+            template
+                .msr_modifiers
+                .iter()
+                .find(|modifier| modifier.addr == 0x10a)
+                .unwrap()
+                .bitmap
+        };
+        let bitmap = arch_capabilities(&template);
+        let base_bitmap = arch_capabilities(&base);
+        assert_eq!(bitmap.filter, base_bitmap.filter | 1 << 1);
+        assert_eq!(bitmap.value, base_bitmap.value & !(1 << 1));
+        assert_eq!(
+            template.msr_modifiers.last().unwrap(),
+            &RegisterModifier {
+                addr: 0x1a0,
+                bitmap: RegisterValueFilter {
+                    filter: 1,
+                    value: 1,
+                },
+            }
+        );
+
+        // Extending a template with no modifiers gives the static template.
+        assert_eq!(
+            CustomCpuTemplate::try_from(r#"{"extends": "T2S"}"#).unwrap(),
+            t2s::t2s()
+        );
+        // There is nothing to extend.
+        CustomCpuTemplate::try_from(r#"{"extends": "None"}"#).unwrap_err();
+        // Unknown static template.
+        CustomCpuTemplate::try_from(r#"{"extends": "T3"}"#).unwrap_err();
+    }
 }
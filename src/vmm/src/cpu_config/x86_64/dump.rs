@@ -3,19 +3,40 @@
 
 use std::collections::BTreeMap;
 
-use vmm::arch::x86_64::gen::msr_index::*;
-use vmm::arch::x86_64::msr::MsrRange;
-use vmm::cpu_config::templates::{CpuConfiguration, CustomCpuTemplate, RegisterValueFilter};
-use vmm::cpu_config::x86_64::cpuid::common::get_vendor_id_from_host;
-use vmm::cpu_config::x86_64::cpuid::{Cpuid, VENDOR_ID_AMD};
-use vmm::cpu_config::x86_64::custom_cpu_template::{
+use crate::arch::x86_64::gen::msr_index::*;
+use crate::arch::x86_64::msr::MsrRange;
+use crate::cpu_config::templates::{CpuConfiguration, CustomCpuTemplate, RegisterValueFilter};
+use crate::cpu_config::x86_64::cpuid::common::get_vendor_id_from_host;
+use crate::cpu_config::x86_64::cpuid::{Cpuid, VENDOR_ID_AMD};
+use crate::cpu_config::x86_64::custom_cpu_template::{
     CpuidLeafModifier, CpuidRegister, CpuidRegisterModifier, RegisterModifier,
 };
-use vmm::MSR_RANGE;
+use crate::MSR_RANGE;
 
-use crate::utils::x86_64::{cpuid_leaf_modifier, cpuid_reg_modifier, msr_modifier};
+fn cpuid_reg_modifier(register: CpuidRegister, value: u32) -> CpuidRegisterModifier {
+    CpuidRegisterModifier {
+        register,
+        bitmap: RegisterValueFilter {
+            filter: u32::MAX,
+            value,
+        },
+    }
+}
+
+fn msr_modifier(addr: u32, value: u64) -> RegisterModifier {
+    RegisterModifier {
+        addr,
+        bitmap: RegisterValueFilter {
+            filter: u64::MAX,
+            value,
+        },
+    }
+}
 
-/// Convert `&CpuConfiguration` to `CustomCputemplate`.
+/// Converts a CPU configuration to a custom CPU template setting all of its bits.
+///
+/// The MSRs which depend on the elapsed time or on features not supported by Firecracker are
+/// excluded.
 pub fn config_to_template(cpu_config: &CpuConfiguration) -> CustomCpuTemplate {
     CustomCpuTemplate {
         cpuid_modifiers: cpuid_to_modifiers(&cpu_config.cpuid),
@@ -28,18 +49,16 @@ fn cpuid_to_modifiers(cpuid: &Cpuid) -> Vec<CpuidLeafModifier> {
     cpuid
         .inner()
         .iter()
-        .map(|(key, entry)| {
-            cpuid_leaf_modifier!(
-                key.leaf,
-                key.subleaf,
-                entry.flags,
-                vec![
-                    cpuid_reg_modifier!(CpuidRegister::Eax, entry.result.eax),
-                    cpuid_reg_modifier!(CpuidRegister::Ebx, entry.result.ebx),
-                    cpuid_reg_modifier!(CpuidRegister::Ecx, entry.result.ecx),
-                    cpuid_reg_modifier!(CpuidRegister::Edx, entry.result.edx),
-                ]
-            )
+        .map(|(key, entry)| CpuidLeafModifier {
+            leaf: key.leaf,
+            subleaf: key.subleaf,
+            flags: entry.flags,
+            modifiers: vec![
+                cpuid_reg_modifier(CpuidRegister::Eax, entry.result.eax),
+                cpuid_reg_modifier(CpuidRegister::Ebx, entry.result.ebx),
+                cpuid_reg_modifier(CpuidRegister::Ecx, entry.result.ecx),
+                cpuid_reg_modifier(CpuidRegister::Edx, entry.result.edx),
+            ],
         })
         .collect()
 }
@@ -47,7 +66,7 @@ fn cpuid_to_modifiers(cpuid: &Cpuid) -> Vec<CpuidLeafModifier> {
 fn msrs_to_modifier(msrs: &BTreeMap<u32, u64>) -> Vec<RegisterModifier> {
     let mut msrs: Vec<RegisterModifier> = msrs
         .iter()
-        .map(|(index, value)| msr_modifier!(*index, *value))
+        .map(|(index, value)| msr_modifier(*index, *value))
         .collect();
 
     msrs.retain(|modifier| !should_exclude_msr(modifier.addr));
@@ -121,12 +140,11 @@ fn should_exclude_msr_amd(index: u32) -> bool {
 mod tests {
     use std::collections::BTreeMap;
 
-    use vmm::cpu_config::x86_64::cpuid::{
+    use super::*;
+    use crate::cpu_config::x86_64::cpuid::{
         CpuidEntry, CpuidKey, CpuidRegisters, IntelCpuid, KvmCpuidFlags,
     };
 
-    use super::*;
-
     fn build_sample_cpuid() -> Cpuid {
         Cpuid::Intel(IntelCpuid(BTreeMap::from([
             (
@@ -164,28 +182,28 @@ mod tests {
 
     fn build_expected_cpuid_modifiers() -> Vec<CpuidLeafModifier> {
         vec![
-            cpuid_leaf_modifier!(
-                0x0,
-                0x0,
-                KvmCpuidFlags::EMPTY,
-                vec![
-                    cpuid_reg_modifier!(CpuidRegister::Eax, 0xffff_ffff),
-                    cpuid_reg_modifier!(CpuidRegister::Ebx, 0x0000_ffff),
-                    cpuid_reg_modifier!(CpuidRegister::Ecx, 0xffff_0000),
-                    cpuid_reg_modifier!(CpuidRegister::Edx, 0x0000_0000),
-                ]
-            ),
-            cpuid_leaf_modifier!(
-                0x1,
-                0x1,
-                KvmCpuidFlags::SIGNIFICANT_INDEX,
-                vec![
-                    cpuid_reg_modifier!(CpuidRegister::Eax, 0xaaaa_aaaa),
-                    cpuid_reg_modifier!(CpuidRegister::Ebx, 0xaaaa_5555),
-                    cpuid_reg_modifier!(CpuidRegister::Ecx, 0x5555_aaaa),
-                    cpuid_reg_modifier!(CpuidRegister::Edx, 0x5555_5555),
-                ]
-            ),
+            CpuidLeafModifier {
+                leaf: 0x0,
+                subleaf: 0x0,
+                flags: KvmCpuidFlags::EMPTY,
+                modifiers: vec![
+                    cpuid_reg_modifier(CpuidRegister::Eax, 0xffff_ffff),
+                    cpuid_reg_modifier(CpuidRegister::Ebx, 0x0000_ffff),
+                    cpuid_reg_modifier(CpuidRegister::Ecx, 0xffff_0000),
+                    cpuid_reg_modifier(CpuidRegister::Edx, 0x0000_0000),
+                ],
+            },
+            CpuidLeafModifier {
+                leaf: 0x1,
+                subleaf: 0x1,
+                flags: KvmCpuidFlags::SIGNIFICANT_INDEX,
+                modifiers: vec![
+                    cpuid_reg_modifier(CpuidRegister::Eax, 0xaaaa_aaaa),
+                    cpuid_reg_modifier(CpuidRegister::Ebx, 0xaaaa_5555),
+                    cpuid_reg_modifier(CpuidRegister::Ecx, 0x5555_aaaa),
+                    cpuid_reg_modifier(CpuidRegister::Edx, 0x5555_5555),
+                ],
+            },
         ]
     }
 
@@ -211,15 +229,15 @@ mod tests {
 
     fn build_expected_msr_modifiers() -> Vec<RegisterModifier> {
         let mut v = vec![
-            msr_modifier!(0x1, 0xffff_ffff_ffff_ffff),
-            msr_modifier!(0x2, 0x0000_0000_0000_0000),
-            msr_modifier!(0x3, 0x0000_0000_ffff_ffff),
-            msr_modifier!(0x5, 0xffff_ffff_0000_0000),
+            msr_modifier(0x1, 0xffff_ffff_ffff_ffff),
+            msr_modifier(0x2, 0x0000_0000_0000_0000),
+            msr_modifier(0x3, 0x0000_0000_ffff_ffff),
+            msr_modifier(0x5, 0xffff_ffff_0000_0000),
         ];
         if &get_vendor_id_from_host().unwrap() != VENDOR_ID_AMD {
             MSR_EXCLUSION_LIST_AMD.iter().for_each(|range| {
                 (range.base..(range.base + range.nmsrs)).for_each(|id| {
-                    v.push(msr_modifier!(id, 0));
+                    v.push(msr_modifier(id, 0));
                 })
             });
         }
@@ -5,6 +5,8 @@
 pub mod cpuid;
 /// Module for custom CPU templates
 pub mod custom_cpu_template;
+/// Module for dumping CPU configurations as custom CPU templates
+pub mod dump;
 /// Module for static CPU templates
 pub mod static_cpu_templates;
 /// Module with test utils for custom CPU templates
@@ -14,7 +16,33 @@ use std::collections::BTreeMap;
 
 use self::custom_cpu_template::CpuidRegister;
 use super::templates::CustomCpuTemplate;
-use crate::cpu_config::x86_64::cpuid::{Cpuid, CpuidKey};
+use crate::cpu_config::x86_64::cpuid::{Cpuid, CpuidEntry, CpuidKey, CpuidTrait};
+
+/// CPUID registers holding feature flags, with the flags that KVM updates from the guest state
+/// while it runs, so that they differ between a running and a newly created vCPU.
+const CPUID_FEATURE_REGISTERS: [(u32, u32, CpuidRegister, u32); 10] = [
+    // OSXSAVE reflects CR4.OSXSAVE.
+    (0x1, 0x0, CpuidRegister::Ecx, 1 << 27),
+    (0x1, 0x0, CpuidRegister::Edx, 0),
+    (0x7, 0x0, CpuidRegister::Ebx, 0),
+    // OSPKE reflects CR4.PKE.
+    (0x7, 0x0, CpuidRegister::Ecx, 1 << 4),
+    (0x7, 0x0, CpuidRegister::Edx, 0),
+    (0x7, 0x1, CpuidRegister::Eax, 0),
+    (0xd, 0x1, CpuidRegister::Eax, 0),
+    (0x8000_0001, 0x0, CpuidRegister::Ecx, 0),
+    (0x8000_0001, 0x0, CpuidRegister::Edx, 0),
+    (0x8000_0008, 0x0, CpuidRegister::Ebx, 0),
+];
+
+fn cpuid_register_value(entry: &CpuidEntry, register: &CpuidRegister) -> u32 {
+    match register {
+        CpuidRegister::Eax => entry.result.eax,
+        CpuidRegister::Ebx => entry.result.ebx,
+        CpuidRegister::Ecx => entry.result.ecx,
+        CpuidRegister::Edx => entry.result.edx,
+    }
+}
 
 /// Errors thrown while configuring templates.
 #[derive(Debug, PartialEq, Eq, thiserror::Error, displaydoc::Display)]
@@ -27,6 +55,9 @@ pub enum CpuConfigurationError {
     CpuidFromKvmCpuid(crate::cpu_config::x86_64::cpuid::CpuidTryFromKvmCpuid),
     /// KVM vcpu ioctl failed: {0}
     VcpuIoctl(crate::vstate::vcpu::KvmVcpuError),
+    /// Nested virtualization is not supported by KVM, check the `nested` parameter of the
+    /// kvm_intel or kvm_amd module.
+    NestedVirtNotSupported,
 }
 
 /// CPU configuration for x86_64 CPUs
@@ -97,6 +128,102 @@ impl CpuConfiguration {
 
         Ok(Self { cpuid, msrs })
     }
+
+    /// Get the registers modified by `template` that are missing from this configuration,
+    /// identified as in the template annotations.
+    pub fn unsupported_registers(&self, template: &CustomCpuTemplate) -> Vec<String> {
+        let cpuid = template
+            .cpuid_modifiers
+            .iter()
+            .filter(|leaf| {
+                self.cpuid
+                    .get(&CpuidKey {
+                        leaf: leaf.leaf,
+                        subleaf: leaf.subleaf,
+                    })
+                    .is_none()
+            })
+            .map(|leaf| format!("cpuid:{:#x}:{:#x}", leaf.leaf, leaf.subleaf));
+        let msr = template
+            .msr_index_iter()
+            .filter(|addr| !self.msrs.contains_key(addr))
+            .map(|addr| format!("msr:{addr:#x}"));
+        cpuid.chain(msr).collect()
+    }
+
+    /// Get the CPUID feature flags of `guest` that are missing from this configuration, as the
+    /// CPUID register identified as in the template annotations and the missing bits.
+    pub fn missing_features(&self, guest: &CpuConfiguration) -> Vec<(String, u64)> {
+        CPUID_FEATURE_REGISTERS
+            .iter()
+            .filter_map(|(leaf, subleaf, register, dynamic_bits)| {
+                let key = CpuidKey {
+                    leaf: *leaf,
+                    subleaf: *subleaf,
+                };
+                let guest_value = cpuid_register_value(guest.cpuid.get(&key)?, register);
+                let host_value = self
+                    .cpuid
+                    .get(&key)
+                    .map_or(0, |entry| cpuid_register_value(entry, register));
+                let missing = guest_value & !host_value & !dynamic_bits;
+                (missing != 0).then(|| {
+                    let register = format!("{register:?}").to_lowercase();
+                    (
+                        format!("cpuid:{leaf:#x}:{subleaf:#x}:{register}"),
+                        u64::from(missing),
+                    )
+                })
+            })
+            .collect()
+    }
+
+    /// Exposes the hardware virtualization extensions to the guest, so that it can run KVM
+    /// itself: VMX on Intel, SVM on AMD. `supported` is the CPUID supported by KVM, which only
+    /// reports them if nested virtualization is enabled on the host.
+    pub fn enable_nested_virt(mut self, supported: &Cpuid) -> Result<Self, CpuConfigurationError> {
+        // VMX: CPUID.01H:ECX[5] (Intel SDM).
+        const VMX_LEAF: u32 = 0x1;
+        const VMX_BITINDEX: u32 = 5;
+        // SVM: CPUID.80000001H:ECX[2] (AMD APM).
+        const SVM_LEAF: u32 = 0x8000_0001;
+        const SVM_BITINDEX: u32 = 2;
+        // SVM revision and features, e.g. nested paging.
+        const SVM_FEATURES_LEAF: u32 = 0x8000_000a;
+        // Largest extended function.
+        const MAX_EXTENDED_LEAF: u32 = 0x8000_0000;
+
+        let (leaf, bit) = match supported {
+            Cpuid::Intel(_) => (VMX_LEAF, VMX_BITINDEX),
+            Cpuid::Amd(_) => (SVM_LEAF, SVM_BITINDEX),
+        };
+        let key = CpuidKey::leaf(leaf);
+        if !supported
+            .get(&key)
+            .is_some_and(|entry| entry.result.ecx & (1 << bit) != 0)
+        {
+            return Err(CpuConfigurationError::NestedVirtNotSupported);
+        }
+        self.cpuid
+            .get_mut(&key)
+            .ok_or(CpuConfigurationError::CpuidFeatureNotSupported(leaf, 0))?
+            .result
+            .ecx |= 1 << bit;
+
+        if let Cpuid::Amd(_) = supported {
+            let key = CpuidKey::leaf(SVM_FEATURES_LEAF);
+            let svm_features = supported
+                .get(&key)
+                .ok_or(CpuConfigurationError::NestedVirtNotSupported)?
+                .clone();
+            self.cpuid.inner_mut().insert(key, svm_features);
+            if let Some(entry) = self.cpuid.get_mut(&CpuidKey::leaf(MAX_EXTENDED_LEAF)) {
+                entry.result.eax = entry.result.eax.max(SVM_FEATURES_LEAF);
+            }
+        }
+
+        Ok(self)
+    }
 }
 
 #[cfg(test)]
@@ -107,8 +234,12 @@ mod tests {
 
     use super::custom_cpu_template::{CpuidLeafModifier, CpuidRegisterModifier, RegisterModifier};
     use super::*;
-    use crate::cpu_config::templates::RegisterValueFilter;
-    use crate::cpu_config::x86_64::cpuid::{CpuidEntry, IntelCpuid, KvmCpuidFlags};
+    use crate::cpu_config::templates::{
+        CpuTemplateIssue, CpuTemplateIssueKind, CpuTemplateReport, RegisterValueFilter,
+    };
+    use crate::cpu_config::x86_64::cpuid::{
+        AmdCpuid, CpuidEntry, CpuidRegisters, IntelCpuid, KvmCpuidFlags,
+    };
 
     fn build_test_template() -> CustomCpuTemplate {
         CustomCpuTemplate {
@@ -264,4 +395,146 @@ mod tests {
             CpuConfigurationError::MsrNotSupported(guest_template.msr_modifiers[0].addr)
         )
     }
+
+    #[test]
+    fn test_cpu_template_report() {
+        let report = CpuTemplateReport::new(&build_test_template(), &supported_cpu_config());
+        assert!(report.valid);
+        assert!(report.issues.is_empty());
+
+        let report = CpuTemplateReport::new(&build_test_template(), &unsupported_cpu_config());
+        assert!(!report.valid);
+        assert_eq!(
+            report.issues,
+            vec![CpuTemplateIssue {
+                kind: CpuTemplateIssueKind::UnsupportedRegister,
+                register: "msr:0x9999".to_string(),
+                description: "Register msr:0x9999 is not supported by KVM on this host".to_string(),
+            }]
+        );
+
+        // The same MSR is modified twice, with different values for bit 1.
+        let mut template = build_test_template();
+        template.msr_modifiers[0].bitmap = RegisterValueFilter {
+            filter: 0b0011,
+            value: 0b0010,
+        };
+        template.msr_modifiers.push(RegisterModifier {
+            addr: 0x9999,
+            bitmap: RegisterValueFilter {
+                filter: 0b0110,
+                value: 0b0100,
+            },
+        });
+        let report = CpuTemplateReport::new(&template, &supported_cpu_config());
+        assert!(!report.valid);
+        assert_eq!(report.issues.len(), 1);
+        assert_eq!(
+            report.issues[0].kind,
+            CpuTemplateIssueKind::ConflictingModifiers
+        );
+        assert_eq!(report.issues[0].register, "msr:0x9999");
+
+        // Modifying the same MSR twice with the same values for the same bits is fine.
+        template.msr_modifiers[2].bitmap.value = 0b0110;
+        assert!(CpuTemplateReport::new(&template, &supported_cpu_config()).valid);
+    }
+
+    #[test]
+    fn test_missing_features() {
+        let config = |leaf_1_ecx: u32, leaf_7_ebx: u32| CpuConfiguration {
+            cpuid: Cpuid::Intel(IntelCpuid(BTreeMap::from([
+                (CpuidKey::leaf(0x1), cpuid_entry(0, leaf_1_ecx)),
+                (
+                    CpuidKey::subleaf(0x7, 0x0),
+                    CpuidEntry {
+                        result: CpuidRegisters {
+                            ebx: leaf_7_ebx,
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                ),
+            ]))),
+            msrs: Default::default(),
+        };
+
+        let host = config(0b1100, 0b1);
+        assert!(host.missing_features(&config(0b0100, 0b1)).is_empty());
+        // OSXSAVE depends on the guest state.
+        assert!(host.missing_features(&config(1 << 27, 0b0)).is_empty());
+        assert_eq!(
+            host.missing_features(&config(0b0011, 0b11)),
+            vec![
+                ("cpuid:0x1:0x0:ecx".to_string(), 0b0011),
+                ("cpuid:0x7:0x0:ebx".to_string(), 0b10),
+            ]
+        );
+        // Leaves missing from the host have no features.
+        assert_eq!(
+            empty_cpu_config().missing_features(&config(0b1, 0b0)),
+            vec![("cpuid:0x1:0x0:ecx".to_string(), 0b1)]
+        );
+    }
+
+    fn cpuid_entry(eax: u32, ecx: u32) -> CpuidEntry {
+        CpuidEntry {
+            result: CpuidRegisters {
+                eax,
+                ecx,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_enable_nested_virt() {
+        // Intel: VMX is only exposed if KVM supports it.
+        let guest = CpuConfiguration {
+            cpuid: Cpuid::Intel(IntelCpuid(BTreeMap::from([(
+                CpuidKey::leaf(0x1),
+                cpuid_entry(0, 0),
+            )]))),
+            msrs: Default::default(),
+        };
+        let supported = Cpuid::Intel(IntelCpuid(BTreeMap::from([(
+            CpuidKey::leaf(0x1),
+            cpuid_entry(0, 1 << 5),
+        )])));
+        let config = guest.clone().enable_nested_virt(&supported).unwrap();
+        assert_eq!(
+            config.cpuid.get(&CpuidKey::leaf(0x1)).unwrap().result.ecx,
+            1 << 5
+        );
+        assert_eq!(
+            guest.enable_nested_virt(&build_supported_cpuid()),
+            Err(CpuConfigurationError::NestedVirtNotSupported)
+        );
+
+        // AMD: SVM and its features leaf are exposed.
+        let guest = CpuConfiguration {
+            cpuid: Cpuid::Amd(AmdCpuid(BTreeMap::from([
+                (CpuidKey::leaf(0x8000_0000), cpuid_entry(0x8000_0008, 0)),
+                (CpuidKey::leaf(0x8000_0001), cpuid_entry(0, 0)),
+            ]))),
+            msrs: Default::default(),
+        };
+        let supported = Cpuid::Amd(AmdCpuid(BTreeMap::from([
+            (CpuidKey::leaf(0x8000_0001), cpuid_entry(0, 1 << 2)),
+            (CpuidKey::leaf(0x8000_000a), cpuid_entry(1, 0)),
+        ])));
+        let config = guest.enable_nested_virt(&supported).unwrap();
+        let get = |leaf| {
+            config
+                .cpuid
+                .get(&CpuidKey::leaf(leaf))
+                .unwrap()
+                .result
+                .clone()
+        };
+        assert_eq!(get(0x8000_0000).eax, 0x8000_000a);
+        assert_eq!(get(0x8000_0001).ecx, 1 << 2);
+        assert_eq!(get(0x8000_000a).eax, 1);
+    }
 }
@@ -4,6 +4,8 @@
 use derive_more::Display;
 use serde::{Deserialize, Serialize};
 
+use crate::cpu_config::templates::CustomCpuTemplate;
+
 /// Module with C3 CPU template for x86_64
 pub mod c3;
 /// Module with T2 CPU template for x86_64
@@ -45,6 +47,18 @@ impl StaticCpuTemplate {
     pub fn is_none(&self) -> bool {
         self == &StaticCpuTemplate::None
     }
+
+    /// Get the modifiers of the template, without checking that it can be applied on the host.
+    pub fn template(&self) -> Option<CustomCpuTemplate> {
+        match self {
+            StaticCpuTemplate::C3 => Some(c3::c3()),
+            StaticCpuTemplate::T2 => Some(t2::t2()),
+            StaticCpuTemplate::T2S => Some(t2s::t2s()),
+            StaticCpuTemplate::T2CL => Some(t2cl::t2cl()),
+            StaticCpuTemplate::T2A => Some(t2a::t2a()),
+            StaticCpuTemplate::None => None,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -4,18 +4,25 @@
 use acpi_tables::{aml, Aml};
 use kvm_ioctls::VmFd;
 
-use crate::devices::acpi::vmgenid::VmGenId;
+use crate::devices::acpi::power_button::PowerButton;
+use crate::devices::acpi::vmgenid::{VmGenId, VmGenIdError};
+use crate::vstate::memory::GuestMemoryMmap;
 
 #[derive(Debug)]
 pub struct ACPIDeviceManager {
     /// VMGenID device
     pub vmgenid: Option<VmGenId>,
+    /// Power button device
+    pub power_button: Option<PowerButton>,
 }
 
 impl ACPIDeviceManager {
     /// Create a new ACPIDeviceManager object
     pub fn new() -> Self {
-        Self { vmgenid: None }
+        Self {
+            vmgenid: None,
+            power_button: None,
+        }
     }
 
     /// Attach a new VMGenID device to the microVM
@@ -31,6 +38,29 @@ impl ACPIDeviceManager {
         Ok(())
     }
 
+    /// Attach a new power button device to the microVM
+    ///
+    /// This will register the device's interrupt with KVM
+    pub fn attach_power_button(
+        &mut self,
+        power_button: PowerButton,
+        vm_fd: &VmFd,
+    ) -> Result<(), kvm_ioctls::Error> {
+        vm_fd.register_irqfd(&power_button.interrupt_evt, power_button.gsi)?;
+        self.power_button = Some(power_button);
+        Ok(())
+    }
+
+    /// Press the power button, if it exists.
+    ///
+    /// Returns whether the microVM has a power button.
+    pub fn press_power_button(&self) -> Result<bool, std::io::Error> {
+        match &self.power_button {
+            Some(power_button) => power_button.press().map(|()| true),
+            None => Ok(false),
+        }
+    }
+
     /// If it exists, notify guest VMGenID device that we have resumed from a snapshot.
     pub fn notify_vmgenid(&mut self) -> Result<(), std::io::Error> {
         if let Some(vmgenid) = &mut self.vmgenid {
@@ -38,52 +68,91 @@ impl ACPIDeviceManager {
         }
         Ok(())
     }
+
+    /// If it exists, change the generation ID of the VMGenID device and notify the guest, as if
+    /// it was restored from a snapshot.
+    pub fn regenerate_vmgenid(&mut self, mem: &GuestMemoryMmap) -> Result<(), VmGenIdError> {
+        if let Some(vmgenid) = &mut self.vmgenid {
+            vmgenid.regenerate(mem)?;
+            vmgenid.notify_guest()?;
+        }
+        Ok(())
+    }
 }
 
 impl Aml for ACPIDeviceManager {
     fn append_aml_bytes(&self, v: &mut Vec<u8>) -> Result<(), aml::AmlError> {
-        // If we have a VMGenID device, create the AML for the device and GED interrupt handler
-        match self.vmgenid.as_ref() {
-            Some(vmgenid) => {
-                // AML for GED
-                aml::Device::new(
-                    "_SB_.GED_".try_into()?,
-                    vec![
-                        &aml::Name::new("_HID".try_into()?, &"ACPI0013")?,
-                        &aml::Name::new(
-                            "_CRS".try_into()?,
-                            &aml::ResourceTemplate::new(vec![&aml::Interrupt::new(
-                                true,
-                                true,
-                                false,
-                                false,
-                                vmgenid.gsi,
-                            )]),
-                        )?,
-                        &aml::Method::new(
-                            "_EVT".try_into()?,
-                            1,
-                            true,
-                            vec![&aml::If::new(
-                                // We know that the maximum IRQ number fits in a u8. We have up to
-                                // 32 IRQs in x86 and up to 128 in
-                                // ARM (look into
-                                // `vmm::crate::arch::layout::IRQ_MAX`)
-                                #[allow(clippy::cast_possible_truncation)]
-                                &aml::Equal::new(&aml::Arg(0), &(vmgenid.gsi as u8)),
-                                vec![&aml::Notify::new(
-                                    &aml::Path::new("\\_SB_.VGEN")?,
-                                    &0x80usize,
-                                )],
-                            )],
-                        ),
-                    ],
-                )
-                .append_aml_bytes(v)?;
-                // AML for VMGenID itself.
-                vmgenid.append_aml_bytes(v)
-            }
-            None => Ok(()),
+        // GSIs raised by the devices, with the device which the GED notifies for each.
+        let mut events = Vec::new();
+        if let Some(vmgenid) = &self.vmgenid {
+            events.push((crate::acpi::gsiv(vmgenid.gsi), "\\_SB_.VGEN"));
+        }
+        if let Some(power_button) = &self.power_button {
+            events.push((crate::acpi::gsiv(power_button.gsi), "\\_SB_.PWRB"));
         }
+        if events.is_empty() {
+            return Ok(());
+        }
+
+        // AML for GED
+        let interrupts = events
+            .iter()
+            .map(|(gsiv, _)| aml::Interrupt::new(true, true, false, false, *gsiv))
+            .collect::<Vec<_>>();
+        let paths = events
+            .iter()
+            .map(|(_, path)| aml::Path::new(path))
+            .collect::<Result<Vec<_>, _>>()?;
+        let notifies = paths
+            .iter()
+            .map(|path| aml::Notify::new(path, &0x80usize))
+            .collect::<Vec<_>>();
+        let irqs = events
+            .iter()
+            // We know that the maximum IRQ number fits in a u8. We have up to 32 IRQs in x86 and
+            // up to 128 in ARM (look into `vmm::crate::arch::layout::IRQ_MAX`), offset by the 32
+            // private interrupts of the GIC.
+            .map(
+                #[allow(clippy::cast_possible_truncation)]
+                |(gsiv, _)| *gsiv as u8,
+            )
+            .collect::<Vec<_>>();
+        let conditions = irqs
+            .iter()
+            .map(|irq| aml::Equal::new(&aml::Arg(0), irq))
+            .collect::<Vec<_>>();
+        let ifs = conditions
+            .iter()
+            .zip(&notifies)
+            .map(|(condition, notify)| aml::If::new(condition, vec![notify]))
+            .collect::<Vec<_>>();
+        aml::Device::new(
+            "_SB_.GED_".try_into()?,
+            vec![
+                &aml::Name::new("_HID".try_into()?, &"ACPI0013")?,
+                &aml::Name::new(
+                    "_CRS".try_into()?,
+                    &aml::ResourceTemplate::new(
+                        interrupts.iter().map(|irq| irq as &dyn Aml).collect(),
+                    ),
+                )?,
+                &aml::Method::new(
+                    "_EVT".try_into()?,
+                    1,
+                    true,
+                    ifs.iter().map(|cond| cond as &dyn Aml).collect(),
+                ),
+            ],
+        )
+        .append_aml_bytes(v)?;
+
+        if let Some(vmgenid) = &self.vmgenid {
+            // AML for VMGenID itself.
+            vmgenid.append_aml_bytes(v)?;
+        }
+        if let Some(power_button) = &self.power_button {
+            power_button.append_aml_bytes(v)?;
+        }
+        Ok(())
     }
 }
@@ -6,19 +6,22 @@
 // found in the THIRD-PARTY file.
 #![cfg(target_arch = "x86_64")]
 
+use std::collections::BTreeMap;
 use std::fmt::Debug;
 use std::sync::{Arc, Mutex};
 
 use acpi_tables::aml::AmlError;
 use acpi_tables::{aml, Aml};
-use kvm_ioctls::VmFd;
+use kvm_ioctls::{IoEventAddress, VmFd};
 use libc::EFD_NONBLOCK;
 use vm_superio::Serial;
 use vmm_sys_util::eventfd::EventFd;
 
 use crate::devices::bus::BusDevice;
 use crate::devices::legacy::serial::SerialOut;
-use crate::devices::legacy::{EventFdTrigger, SerialDevice, SerialEventsWrapper};
+use crate::devices::legacy::{
+    EventFdTrigger, SerialDevice, SerialEventsWrapper, I8042_CMD_RESET_CPU,
+};
 
 /// Errors corresponding to the `PortIODeviceManager`.
 #[derive(Debug, derive_more::From, thiserror::Error, displaydoc::Display)]
@@ -30,15 +33,19 @@ pub enum LegacyDeviceError {
 }
 
 /// The `PortIODeviceManager` is a wrapper that is used for registering legacy devices
-/// on an I/O Bus. It currently manages the uart and i8042 devices.
+/// on an I/O Bus. It currently manages the uart and i8042 devices, each of which can be left out
+/// of the microVM.
 /// The `LegacyDeviceManger` should be initialized only by using the constructor.
 #[derive(Debug)]
 pub struct PortIODeviceManager {
     pub io_bus: crate::devices::Bus,
-    // BusDevice::Serial
-    pub stdio_serial: Arc<Mutex<BusDevice>>,
-    // BusDevice::I8042Device
-    pub i8042: Arc<Mutex<BusDevice>>,
+    // BusDevice::Serial, if the serial console is enabled.
+    pub stdio_serial: Option<Arc<Mutex<BusDevice>>>,
+    // BusDevice::Serial attached to a host output, by COM port number from 2 to 4. The other
+    // ports are not connected to anything.
+    pub extra_serials: BTreeMap<u8, Arc<Mutex<BusDevice>>>,
+    // BusDevice::I8042Device, if the i8042 is enabled.
+    pub i8042: Option<Arc<Mutex<BusDevice>>>,
 
     // Communication event on ports 1 & 3.
     pub com_evt_1_3: EventFdTrigger,
@@ -46,6 +53,8 @@ pub struct PortIODeviceManager {
     pub com_evt_2_4: EventFdTrigger,
     // Keyboard event.
     pub kbd_evt: EventFd,
+    // i8042 reset command, signaled by KVM without exiting to the VMM.
+    pub i8042_reset_evt: EventFd,
 }
 
 impl PortIODeviceManager {
@@ -70,86 +79,113 @@ impl PortIODeviceManager {
     const I8042_KDB_DATA_REGISTER_ADDRESS: u64 = 0x060;
     /// i8042 keyboard data register size.
     const I8042_KDB_DATA_REGISTER_SIZE: u64 = 0x5;
+    /// i8042 command register address, to which the guest writes the reset command.
+    const I8042_COMMAND_REGISTER_ADDRESS: u64 = 0x064;
 
-    /// Create a new DeviceManager handling legacy devices (uart, i8042).
+    /// Create a new DeviceManager handling legacy devices (uart, i8042). The uart devices are
+    /// left out if no serial device is given, and the i8042 if no reset event is given.
     pub fn new(
-        serial: Arc<Mutex<BusDevice>>,
-        i8042_reset_evfd: EventFd,
+        serial: Option<Arc<Mutex<BusDevice>>>,
+        i8042_reset_evfd: Option<EventFd>,
     ) -> Result<Self, LegacyDeviceError> {
-        debug_assert!(matches!(*serial.lock().unwrap(), BusDevice::Serial(_)));
         let io_bus = crate::devices::Bus::new();
-        let com_evt_1_3 = serial
-            .lock()
-            .expect("Poisoned lock")
-            .serial_mut()
-            .unwrap()
-            .serial
-            .interrupt_evt()
-            .try_clone()?;
+        let com_evt_1_3 = match &serial {
+            Some(serial) => {
+                debug_assert!(matches!(*serial.lock().unwrap(), BusDevice::Serial(_)));
+                serial
+                    .lock()
+                    .expect("Poisoned lock")
+                    .serial_mut()
+                    .unwrap()
+                    .serial
+                    .interrupt_evt()
+                    .try_clone()?
+            }
+            None => EventFdTrigger::new(EventFd::new(EFD_NONBLOCK)?),
+        };
         let com_evt_2_4 = EventFdTrigger::new(EventFd::new(EFD_NONBLOCK)?);
         let kbd_evt = EventFd::new(libc::EFD_NONBLOCK)?;
+        let i8042_reset_evt = EventFd::new(libc::EFD_NONBLOCK)?;
 
-        let i8042 = Arc::new(Mutex::new(BusDevice::I8042Device(
-            crate::devices::legacy::I8042Device::new(i8042_reset_evfd, kbd_evt.try_clone()?),
-        )));
+        let i8042 = match i8042_reset_evfd {
+            Some(reset_evfd) => Some(Arc::new(Mutex::new(BusDevice::I8042Device(
+                crate::devices::legacy::I8042Device::new(reset_evfd, kbd_evt.try_clone()?),
+            )))),
+            None => None,
+        };
 
         Ok(PortIODeviceManager {
             io_bus,
             stdio_serial: serial,
+            extra_serials: BTreeMap::new(),
             i8042,
             com_evt_1_3,
             com_evt_2_4,
             kbd_evt,
+            i8042_reset_evt,
         })
     }
 
+    /// Returns the event through which the serial port `port`, from 1 for COM1 to 4 for COM4,
+    /// signals its interrupts, shared by COM1 and COM3, and by COM2 and COM4.
+    pub fn serial_interrupt_evt(&self, port: u8) -> Result<EventFdTrigger, LegacyDeviceError> {
+        let evt = if port % 2 == 1 {
+            &self.com_evt_1_3
+        } else {
+            &self.com_evt_2_4
+        };
+        Ok(evt.try_clone()?)
+    }
+
+    /// Connects the serial port `port`, from 2 for COM2 to 4 for COM4, to `serial`, whose
+    /// interrupt event must be the one returned by [`Self::serial_interrupt_evt`]. It must be
+    /// called before the devices are registered.
+    pub fn set_extra_serial(&mut self, port: u8, serial: Arc<Mutex<BusDevice>>) {
+        debug_assert!((2..=4).contains(&port));
+        self.extra_serials.insert(port, serial);
+    }
+
     /// Register supported legacy devices.
     pub fn register_devices(&mut self, vm_fd: &VmFd) -> Result<(), LegacyDeviceError> {
-        let serial_2_4 = Arc::new(Mutex::new(BusDevice::Serial(SerialDevice {
-            serial: Serial::with_events(
-                self.com_evt_2_4.try_clone()?.try_clone()?,
-                SerialEventsWrapper {
-                    buffer_ready_event_fd: None,
-                },
-                SerialOut::Sink(std::io::sink()),
-            ),
-            input: None,
-        })));
-        let serial_1_3 = Arc::new(Mutex::new(BusDevice::Serial(SerialDevice {
-            serial: Serial::with_events(
-                self.com_evt_1_3.try_clone()?.try_clone()?,
-                SerialEventsWrapper {
-                    buffer_ready_event_fd: None,
-                },
-                SerialOut::Sink(std::io::sink()),
-            ),
-            input: None,
-        })));
+        if let Some(stdio_serial) = self.stdio_serial.clone() {
+            self.register_serial_devices(vm_fd, stdio_serial)?;
+        }
+        if let Some(i8042) = self.i8042.clone() {
+            self.register_i8042(vm_fd, i8042)?;
+        }
+        Ok(())
+    }
+
+    /// Registers the 4 serial ports, the first of which is connected to the serial console. The
+    /// other ports are connected to their host output, if any, or to a sink.
+    fn register_serial_devices(
+        &mut self,
+        vm_fd: &VmFd,
+        stdio_serial: Arc<Mutex<BusDevice>>,
+    ) -> Result<(), LegacyDeviceError> {
         self.io_bus.insert(
-            self.stdio_serial.clone(),
+            stdio_serial,
             Self::SERIAL_PORT_ADDRESSES[0],
             Self::SERIAL_PORT_SIZE,
         )?;
-        self.io_bus.insert(
-            serial_2_4.clone(),
-            Self::SERIAL_PORT_ADDRESSES[1],
-            Self::SERIAL_PORT_SIZE,
-        )?;
-        self.io_bus.insert(
-            serial_1_3,
-            Self::SERIAL_PORT_ADDRESSES[2],
-            Self::SERIAL_PORT_SIZE,
-        )?;
-        self.io_bus.insert(
-            serial_2_4,
-            Self::SERIAL_PORT_ADDRESSES[3],
-            Self::SERIAL_PORT_SIZE,
-        )?;
-        self.io_bus.insert(
-            self.i8042.clone(),
-            Self::I8042_KDB_DATA_REGISTER_ADDRESS,
-            Self::I8042_KDB_DATA_REGISTER_SIZE,
-        )?;
+        for (port, address) in (2u8..).zip(&Self::SERIAL_PORT_ADDRESSES[1..]) {
+            let serial = match self.extra_serials.get(&port) {
+                Some(serial) => serial.clone(),
+                None => Arc::new(Mutex::new(BusDevice::Serial(SerialDevice {
+                    serial: Serial::with_events(
+                        self.serial_interrupt_evt(port)?,
+                        SerialEventsWrapper {
+                            buffer_ready_event_fd: None,
+                        },
+                        SerialOut::Sink(std::io::sink()),
+                    ),
+                    input: None,
+                    listener: None,
+                }))),
+            };
+            self.io_bus
+                .insert(serial, *address, Self::SERIAL_PORT_SIZE)?;
+        }
 
         vm_fd
             .register_irqfd(&self.com_evt_1_3, Self::COM_EVT_1_3_GSI)
@@ -161,16 +197,54 @@ impl PortIODeviceManager {
             .map_err(|e| {
                 LegacyDeviceError::EventFd(std::io::Error::from_raw_os_error(e.errno()))
             })?;
+
+        Ok(())
+    }
+
+    /// Registers the i8042, through which the guest resets the microVM.
+    fn register_i8042(
+        &mut self,
+        vm_fd: &VmFd,
+        i8042: Arc<Mutex<BusDevice>>,
+    ) -> Result<(), LegacyDeviceError> {
+        self.io_bus.insert(
+            i8042,
+            Self::I8042_KDB_DATA_REGISTER_ADDRESS,
+            Self::I8042_KDB_DATA_REGISTER_SIZE,
+        )?;
+
         vm_fd
             .register_irqfd(&self.kbd_evt, Self::KBD_EVT_GSI)
             .map_err(|e| {
                 LegacyDeviceError::EventFd(std::io::Error::from_raw_os_error(e.errno()))
             })?;
+        // The vCPU resetting the microVM doesn't need to wait for the reset to be handled, so the
+        // reset command is signaled through an ioeventfd instead of a port IO exit.
+        vm_fd
+            .register_ioevent(
+                &self.i8042_reset_evt,
+                &IoEventAddress::Pio(Self::I8042_COMMAND_REGISTER_ADDRESS),
+                I8042_CMD_RESET_CPU,
+            )
+            .map_err(|e| {
+                LegacyDeviceError::EventFd(std::io::Error::from_raw_os_error(e.errno()))
+            })?;
+
+        Ok(())
+    }
 
+    /// Appends the AML describing the registered legacy devices to the DSDT data.
+    pub(crate) fn append_aml_bytes(&self, bytes: &mut Vec<u8>) -> Result<(), AmlError> {
+        if self.stdio_serial.is_some() {
+            Self::append_serial_aml_bytes(bytes)?;
+        }
+        if self.i8042.is_some() {
+            Self::append_i8042_aml_bytes(bytes)?;
+        }
         Ok(())
     }
 
-    pub(crate) fn append_aml_bytes(bytes: &mut Vec<u8>) -> Result<(), AmlError> {
+    fn append_serial_aml_bytes(bytes: &mut Vec<u8>) -> Result<(), AmlError> {
         // Set up COM devices
         let gsi = [
             Self::COM_EVT_1_3_GSI,
@@ -206,6 +280,10 @@ impl PortIODeviceManager {
             )
             .append_aml_bytes(bytes)?;
         }
+        Ok(())
+    }
+
+    fn append_i8042_aml_bytes(bytes: &mut Vec<u8>) -> Result<(), AmlError> {
         // Setup i8042
         aml::Device::new(
             "_SB_.PS2_".try_into()?,
@@ -254,7 +332,7 @@ mod tests {
         vm.memory_init(&guest_mem, false).unwrap();
         crate::builder::setup_interrupt_controller(&mut vm).unwrap();
         let mut ldm = PortIODeviceManager::new(
-            Arc::new(Mutex::new(BusDevice::Serial(SerialDevice {
+            Some(Arc::new(Mutex::new(BusDevice::Serial(SerialDevice {
                 serial: Serial::with_events(
                     EventFdTrigger::new(EventFd::new(EFD_NONBLOCK).unwrap()),
                     SerialEventsWrapper {
@@ -263,10 +341,70 @@ mod tests {
                     SerialOut::Sink(std::io::sink()),
                 ),
                 input: None,
-            }))),
-            EventFd::new(libc::EFD_NONBLOCK).unwrap(),
+                listener: None,
+            })))),
+            Some(EventFd::new(libc::EFD_NONBLOCK).unwrap()),
         )
         .unwrap();
         ldm.register_devices(vm.fd()).unwrap();
+        assert!(ldm.io_bus.get_device(0x3f8).is_some());
+        assert!(ldm.io_bus.get_device(0x060).is_some());
+    }
+
+    #[test]
+    fn test_register_extra_serial() {
+        let guest_mem = single_region_mem(0x1000);
+        let mut vm = Vm::new(vec![]).unwrap();
+        vm.memory_init(&guest_mem, false).unwrap();
+        crate::builder::setup_interrupt_controller(&mut vm).unwrap();
+        let sink_serial = |interrupt_evt| {
+            Arc::new(Mutex::new(BusDevice::Serial(SerialDevice {
+                serial: Serial::with_events(
+                    interrupt_evt,
+                    SerialEventsWrapper {
+                        buffer_ready_event_fd: None,
+                    },
+                    SerialOut::Sink(std::io::sink()),
+                ),
+                input: None,
+                listener: None,
+            })))
+        };
+        let mut ldm = PortIODeviceManager::new(
+            Some(sink_serial(EventFdTrigger::new(
+                EventFd::new(EFD_NONBLOCK).unwrap(),
+            ))),
+            None,
+        )
+        .unwrap();
+        let com3 = sink_serial(ldm.serial_interrupt_evt(3).unwrap());
+        ldm.set_extra_serial(3, com3.clone());
+        ldm.register_devices(vm.fd()).unwrap();
+
+        // The port is referenced by the test, the manager and the bus.
+        assert_eq!(Arc::strong_count(&com3), 3);
+        for address in PortIODeviceManager::SERIAL_PORT_ADDRESSES {
+            assert!(ldm.io_bus.get_device(address).is_some());
+        }
+
+        // COM3 shares its interrupt with COM1.
+        ldm.serial_interrupt_evt(3).unwrap().write(1).unwrap();
+        assert_eq!(ldm.com_evt_1_3.read().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_register_no_legacy_devices() {
+        let guest_mem = single_region_mem(0x1000);
+        let mut vm = Vm::new(vec![]).unwrap();
+        vm.memory_init(&guest_mem, false).unwrap();
+        crate::builder::setup_interrupt_controller(&mut vm).unwrap();
+        let mut ldm = PortIODeviceManager::new(None, None).unwrap();
+        ldm.register_devices(vm.fd()).unwrap();
+        assert!(ldm.io_bus.get_device(0x3f8).is_none());
+        assert!(ldm.io_bus.get_device(0x060).is_none());
+
+        let mut dsdt_data = Vec::new();
+        ldm.append_aml_bytes(&mut dsdt_data).unwrap();
+        assert!(dsdt_data.is_empty());
     }
 }
@@ -9,15 +9,13 @@ use std::collections::HashMap;
 use std::fmt::Debug;
 use std::sync::{Arc, Mutex};
 
-#[cfg(target_arch = "x86_64")]
 use acpi_tables::{aml, Aml};
 use kvm_ioctls::{IoEventAddress, VmFd};
 use linux_loader::cmdline as kernel_cmdline;
-#[cfg(target_arch = "x86_64")]
-use log::debug;
-use log::info;
+use log::{debug, info};
 use serde::{Deserialize, Serialize};
 use vm_allocator::AllocPolicy;
+use vmm_sys_util::eventfd::EventFd;
 
 use super::resources::ResourceAllocator;
 #[cfg(target_arch = "aarch64")]
@@ -26,7 +24,8 @@ use crate::arch::DeviceType;
 use crate::arch::DeviceType::Virtio;
 #[cfg(target_arch = "aarch64")]
 use crate::devices::legacy::RTCDevice;
-use crate::devices::pseudo::BootTimer;
+use crate::devices::pseudo::{BootTimer, PvPanic};
+use crate::devices::tpm::{Swtpm, TpmCrb, TPM_CRB_MMIO_SIZE};
 use crate::devices::virtio::balloon::Balloon;
 use crate::devices::virtio::block::device::Block;
 use crate::devices::virtio::device::VirtioDevice;
@@ -60,7 +59,8 @@ pub enum MmioError {
     RegisterIoEvent(kvm_ioctls::Error),
     /// Failed to register irqfd: {0}
     RegisterIrqFd(kvm_ioctls::Error),
-    #[cfg(target_arch = "x86_64")]
+    /// Failed to clone the events of a deferred device: {0}
+    CloneEventFd(std::io::Error),
     /// Failed to create AML code for device
     AmlError(#[from] aml::AmlError),
 }
@@ -72,6 +72,9 @@ pub enum MmioError {
 /// Currently hardcoded to 4K.
 pub const MMIO_LEN: u64 = 0x1000;
 
+// The TPM CRB registers and buffer fit in a regular MMIO slot.
+const _: () = assert!(TPM_CRB_MMIO_SIZE == MMIO_LEN);
+
 /// Stores the address range and irq allocated to this device.
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct MMIODeviceInfo {
@@ -83,7 +86,6 @@ pub struct MMIODeviceInfo {
     pub irqs: Vec<u32>,
 }
 
-#[cfg(target_arch = "x86_64")]
 fn add_virtio_aml(
     dsdt_data: &mut Vec<u8>,
     addr: u64,
@@ -109,7 +111,40 @@ fn add_virtio_aml(
                         addr.try_into().unwrap(),
                         len.try_into().unwrap(),
                     ),
-                    &aml::Interrupt::new(true, true, false, false, irq),
+                    &aml::Interrupt::new(true, true, false, false, crate::acpi::gsiv(irq)),
+                ]),
+            )?,
+        ],
+    )
+    .append_aml_bytes(dsdt_data)
+}
+
+#[cfg(target_arch = "aarch64")]
+fn add_serial_aml(
+    dsdt_data: &mut Vec<u8>,
+    addr: u64,
+    len: u64,
+    irq: u32,
+) -> Result<(), aml::AmlError> {
+    debug!(
+        "acpi: Building AML for serial device _SB_.COM1. memory range: {:#010x}:{} irq: {}",
+        addr, len, irq
+    );
+    aml::Device::new(
+        "_SB_.COM1".try_into()?,
+        vec![
+            &aml::Name::new("_HID".try_into()?, &aml::EisaName::new("PNP0501")?)?,
+            &aml::Name::new("_UID".try_into()?, &aml::ZERO)?,
+            &aml::Name::new("_CCA".try_into()?, &aml::ONE)?,
+            &aml::Name::new(
+                "_CRS".try_into()?,
+                &aml::ResourceTemplate::new(vec![
+                    &aml::Memory32Fixed::new(
+                        true,
+                        addr.try_into().unwrap(),
+                        len.try_into().unwrap(),
+                    ),
+                    &aml::Interrupt::new(true, true, false, false, crate::acpi::gsiv(irq)),
                 ]),
             )?,
         ],
@@ -117,6 +152,52 @@ fn add_virtio_aml(
     .append_aml_bytes(dsdt_data)
 }
 
+fn add_tpm_aml(dsdt_data: &mut Vec<u8>, addr: u64, len: u64) -> Result<(), aml::AmlError> {
+    debug!(
+        "acpi: Building AML for TPM device _SB_.TPM0. memory range: {:#010x}:{}",
+        addr, len
+    );
+    aml::Device::new(
+        "_SB_.TPM0".try_into()?,
+        vec![
+            &aml::Name::new("_HID".try_into()?, &"MSFT0101")?,
+            &aml::Name::new("_UID".try_into()?, &aml::ZERO)?,
+            &aml::Name::new(
+                "_CRS".try_into()?,
+                &aml::ResourceTemplate::new(vec![&aml::Memory32Fixed::new(
+                    true,
+                    addr.try_into().unwrap(),
+                    len.try_into().unwrap(),
+                )]),
+            )?,
+        ],
+    )
+    .append_aml_bytes(dsdt_data)
+}
+
+fn add_pvpanic_aml(dsdt_data: &mut Vec<u8>, addr: u64, len: u64) -> Result<(), aml::AmlError> {
+    debug!(
+        "acpi: Building AML for pvpanic device _SB_.PVPN. memory range: {:#010x}:{}",
+        addr, len
+    );
+    aml::Device::new(
+        "_SB_.PVPN".try_into()?,
+        vec![
+            &aml::Name::new("_HID".try_into()?, &"QEMU0001")?,
+            &aml::Name::new("_UID".try_into()?, &aml::ZERO)?,
+            &aml::Name::new(
+                "_CRS".try_into()?,
+                &aml::ResourceTemplate::new(vec![&aml::Memory32Fixed::new(
+                    true,
+                    addr.try_into().unwrap(),
+                    len.try_into().unwrap(),
+                )]),
+            )?,
+        ],
+    )
+    .append_aml_bytes(dsdt_data)
+}
+
 /// Manages the complexities of registering a MMIO device.
 #[derive(Debug)]
 pub struct MMIODeviceManager {
@@ -129,8 +210,58 @@ pub struct MMIODeviceManager {
     // The alternative would be that we iterate the bus to get the data after all
     // of the devices are build. However, iterating the bus won't give us the
     // devices in the order they were added.
-    #[cfg(target_arch = "x86_64")]
     pub(crate) dsdt_data: Vec<u8>,
+    // The KVM events of the virtio devices which are registered only once the vCPUs are started,
+    // so that they don't delay the boot.
+    deferred_virtio: Vec<DeferredVirtioEvents>,
+}
+
+/// The KVM events of a virtio device whose registration is deferred: the ioeventfds notifying its
+/// queues, and the irqfd of its interrupt. The events are copies of the ones of the device, so
+/// that they can be registered without access to the device manager.
+#[derive(Debug)]
+pub struct DeferredVirtioEvents {
+    queue_evts: Vec<EventFd>,
+    notify_addr: u64,
+    irq_evt: EventFd,
+    gsi: u32,
+}
+
+impl DeferredVirtioEvents {
+    fn new(mmio_device: &MmioTransport, device_info: &MMIODeviceInfo) -> Result<Self, MmioError> {
+        let locked_device = mmio_device.locked_device();
+        let queue_evts = locked_device
+            .queue_events()
+            .iter()
+            .map(EventFd::try_clone)
+            .collect::<Result<_, _>>()
+            .map_err(MmioError::CloneEventFd)?;
+        let irq_evt = locked_device
+            .interrupt_trigger()
+            .irq_evt
+            .try_clone()
+            .map_err(MmioError::CloneEventFd)?;
+        Ok(DeferredVirtioEvents {
+            queue_evts,
+            notify_addr: device_info.addr + u64::from(crate::devices::virtio::NOTIFY_REG_OFFSET),
+            irq_evt,
+            gsi: device_info.irqs[0],
+        })
+    }
+
+    /// Register the events with KVM.
+    pub fn register(&self, vm: &VmFd) -> Result<(), MmioError> {
+        for (i, queue_evt) in self.queue_evts.iter().enumerate() {
+            vm.register_ioevent(
+                queue_evt,
+                &IoEventAddress::Mmio(self.notify_addr),
+                u32::try_from(i).unwrap(),
+            )
+            .map_err(MmioError::RegisterIoEvent)?;
+        }
+        vm.register_irqfd(&self.irq_evt, self.gsi)
+            .map_err(MmioError::RegisterIrqFd)
+    }
 }
 
 impl MMIODeviceManager {
@@ -139,8 +270,8 @@ impl MMIODeviceManager {
         MMIODeviceManager {
             bus: crate::devices::Bus::new(),
             id_to_dev_info: HashMap::new(),
-            #[cfg(target_arch = "x86_64")]
             dsdt_data: vec![],
+            deferred_virtio: Vec::new(),
         }
     }
 
@@ -190,29 +321,72 @@ impl MMIODeviceManager {
         if device_info.irqs.len() != 1 {
             return Err(MmioError::InvalidIrqConfig);
         }
-        let identifier;
-        {
-            let locked_device = mmio_device.locked_device();
-            identifier = (DeviceType::Virtio(locked_device.device_type()), device_id);
-            for (i, queue_evt) in locked_device.queue_events().iter().enumerate() {
-                let io_addr = IoEventAddress::Mmio(
-                    device_info.addr + u64::from(crate::devices::virtio::NOTIFY_REG_OFFSET),
-                );
-                vm.register_ioevent(queue_evt, &io_addr, u32::try_from(i).unwrap())
-                    .map_err(MmioError::RegisterIoEvent)?;
-            }
-            vm.register_irqfd(
-                &locked_device.interrupt_trigger().irq_evt,
-                device_info.irqs[0],
-            )
-            .map_err(MmioError::RegisterIrqFd)?;
+        Self::register_virtio_events(vm, &mmio_device, device_info)?;
+        let identifier = (
+            DeviceType::Virtio(mmio_device.locked_device().device_type()),
+            device_id,
+        );
+
+        self.register_mmio_device(
+            identifier,
+            device_info.clone(),
+            Arc::new(Mutex::new(BusDevice::MmioTransport(mmio_device))),
+        )
+    }
+
+    /// Register a virtio-over-MMIO device at a specific slot, leaving the registration of its KVM
+    /// events to the caller of `take_deferred_virtio`. Until then, the guest notifies the queues
+    /// of the device through MMIO exits, and its interrupts are held by the interrupt event.
+    fn register_mmio_virtio_deferred(
+        &mut self,
+        device_id: String,
+        mmio_device: MmioTransport,
+        device_info: &MMIODeviceInfo,
+    ) -> Result<(), MmioError> {
+        if device_info.irqs.len() != 1 {
+            return Err(MmioError::InvalidIrqConfig);
         }
+        let events = DeferredVirtioEvents::new(&mmio_device, device_info)?;
+        let identifier = (
+            DeviceType::Virtio(mmio_device.locked_device().device_type()),
+            device_id,
+        );
 
         self.register_mmio_device(
             identifier,
             device_info.clone(),
             Arc::new(Mutex::new(BusDevice::MmioTransport(mmio_device))),
+        )?;
+        self.deferred_virtio.push(events);
+        Ok(())
+    }
+
+    /// Register the KVM events of a virtio-over-MMIO device: the ioeventfds notifying its queues,
+    /// and the irqfd of its interrupt.
+    fn register_virtio_events(
+        vm: &VmFd,
+        mmio_device: &MmioTransport,
+        device_info: &MMIODeviceInfo,
+    ) -> Result<(), MmioError> {
+        let locked_device = mmio_device.locked_device();
+        for (i, queue_evt) in locked_device.queue_events().iter().enumerate() {
+            let io_addr = IoEventAddress::Mmio(
+                device_info.addr + u64::from(crate::devices::virtio::NOTIFY_REG_OFFSET),
+            );
+            vm.register_ioevent(queue_evt, &io_addr, u32::try_from(i).unwrap())
+                .map_err(MmioError::RegisterIoEvent)?;
+        }
+        vm.register_irqfd(
+            &locked_device.interrupt_trigger().irq_evt,
+            device_info.irqs[0],
         )
+        .map_err(MmioError::RegisterIrqFd)
+    }
+
+    /// Take the KVM events of the virtio devices whose registration was deferred, leaving their
+    /// registration to the caller.
+    pub fn take_deferred_virtio(&mut self) -> Vec<DeferredVirtioEvents> {
+        std::mem::take(&mut self.deferred_virtio)
     }
 
     /// Append a registered virtio-over-MMIO device to the kernel cmdline.
@@ -237,7 +411,8 @@ impl MMIODeviceManager {
     }
 
     /// Allocate slot and register an already created virtio-over-MMIO device. Also Adds the device
-    /// to the boot cmdline.
+    /// to the boot cmdline. The registration of the KVM events of the device is left to the caller
+    /// of `take_deferred_virtio` when `defer_events` is set.
     pub fn register_mmio_virtio_for_boot(
         &mut self,
         vm: &VmFd,
@@ -245,21 +420,24 @@ impl MMIODeviceManager {
         device_id: String,
         mmio_device: MmioTransport,
         _cmdline: &mut kernel_cmdline::Cmdline,
+        defer_events: bool,
     ) -> Result<MMIODeviceInfo, MmioError> {
         let device_info = self.allocate_mmio_resources(resource_allocator, 1)?;
-        self.register_mmio_virtio(vm, device_id, mmio_device, &device_info)?;
-        #[cfg(target_arch = "x86_64")]
-        {
-            Self::add_virtio_device_to_cmdline(_cmdline, &device_info)?;
-            add_virtio_aml(
-                &mut self.dsdt_data,
-                device_info.addr,
-                device_info.len,
-                // We are sure that `irqs` has at least one element; allocate_mmio_resources makes
-                // sure of it.
-                device_info.irqs[0],
-            )?;
+        if defer_events {
+            self.register_mmio_virtio_deferred(device_id, mmio_device, &device_info)?;
+        } else {
+            self.register_mmio_virtio(vm, device_id, mmio_device, &device_info)?;
         }
+        #[cfg(target_arch = "x86_64")]
+        Self::add_virtio_device_to_cmdline(_cmdline, &device_info)?;
+        add_virtio_aml(
+            &mut self.dsdt_data,
+            device_info.addr,
+            device_info.len,
+            // We are sure that `irqs` has at least one element; allocate_mmio_resources makes
+            // sure of it.
+            device_info.irqs[0],
+        )?;
         Ok(device_info)
     }
 
@@ -278,7 +456,14 @@ impl MMIODeviceManager {
         let device_info = if let Some(device_info) = device_info_opt {
             device_info
         } else {
-            self.allocate_mmio_resources(resource_allocator, 1)?
+            let device_info = self.allocate_mmio_resources(resource_allocator, 1)?;
+            add_serial_aml(
+                &mut self.dsdt_data,
+                device_info.addr,
+                device_info.len,
+                device_info.irqs[0],
+            )?;
+            device_info
         };
 
         vm.register_irqfd(
@@ -357,6 +542,59 @@ impl MMIODeviceManager {
         )
     }
 
+    /// Create and register a TPM CRB device backed by `swtpm`, at the specified MMIO
+    /// configuration if given as parameter, otherwise allocate a new MMIO resources for it.
+    pub fn register_mmio_tpm(
+        &mut self,
+        resource_allocator: &mut ResourceAllocator,
+        swtpm: Swtpm,
+        device_info_opt: Option<MMIODeviceInfo>,
+    ) -> Result<(), MmioError> {
+        // Create a new MMIODeviceInfo object on boot path or unwrap the
+        // existing object on restore path.
+        let device_info = if let Some(device_info) = device_info_opt {
+            device_info
+        } else {
+            let device_info = self.allocate_mmio_resources(resource_allocator, 0)?;
+            add_tpm_aml(&mut self.dsdt_data, device_info.addr, device_info.len)?;
+            device_info
+        };
+
+        let tpm = TpmCrb::new(swtpm, device_info.addr);
+        let identifier = (DeviceType::Tpm, DeviceType::Tpm.to_string());
+        self.register_mmio_device(
+            identifier,
+            device_info,
+            Arc::new(Mutex::new(BusDevice::Tpm(tpm))),
+        )
+    }
+
+    /// Register a pvpanic device at the specified MMIO configuration if given as parameter,
+    /// otherwise allocate a new MMIO resources for it.
+    pub fn register_mmio_pvpanic(
+        &mut self,
+        resource_allocator: &mut ResourceAllocator,
+        device: PvPanic,
+        device_info_opt: Option<MMIODeviceInfo>,
+    ) -> Result<(), MmioError> {
+        // Create a new MMIODeviceInfo object on boot path or unwrap the
+        // existing object on restore path.
+        let device_info = if let Some(device_info) = device_info_opt {
+            device_info
+        } else {
+            let device_info = self.allocate_mmio_resources(resource_allocator, 0)?;
+            add_pvpanic_aml(&mut self.dsdt_data, device_info.addr, device_info.len)?;
+            device_info
+        };
+
+        let identifier = (DeviceType::PvPanic, DeviceType::PvPanic.to_string());
+        self.register_mmio_device(
+            identifier,
+            device_info,
+            Arc::new(Mutex::new(BusDevice::PvPanic(device))),
+        )
+    }
+
     /// Gets the information of the devices registered up to some point in time.
     pub fn get_device_info(&self) -> &HashMap<(DeviceType, String), MMIODeviceInfo> {
         &self.id_to_dev_info
@@ -517,6 +755,19 @@ impl MMIODeviceManager {
                 Ok(())
             });
     }
+
+    /// Notifies the net devices that the microVM was paused or resumed.
+    pub fn set_net_devices_paused(&self, paused: bool) {
+        let _: Result<(), MmioError> =
+            self.for_each_virtio_device(|virtio_type, _id, _info, dev| {
+                if virtio_type == TYPE_NET {
+                    let mut virtio = dev.lock().expect("Poisoned lock");
+                    let net = virtio.as_mut_any().downcast_mut::<Net>().unwrap();
+                    net.set_vm_paused(paused);
+                }
+                Ok(())
+            });
+    }
 }
 
 #[cfg(target_arch = "aarch64")]
@@ -566,6 +817,7 @@ mod tests {
                 dev_id.to_string(),
                 mmio_device,
                 cmdline,
+                false,
             )?;
             Ok(device_info.addr)
         }
@@ -4,6 +4,7 @@
 //! Provides functionality for saving/restoring the MMIO device manager and its devices.
 
 use std::fmt::{self, Debug};
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 
 use event_manager::{MutEventSubscriber, SubscriberOps};
@@ -15,9 +16,11 @@ use vm_allocator::AllocPolicy;
 use super::acpi::ACPIDeviceManager;
 use super::mmio::*;
 use super::resources::ResourceAllocator;
-#[cfg(target_arch = "aarch64")]
 use crate::arch::DeviceType;
+use crate::devices::acpi::power_button::{PowerButton, PowerButtonError, PowerButtonState};
 use crate::devices::acpi::vmgenid::{VMGenIDState, VMGenIdConstructorArgs, VmGenId, VmGenIdError};
+use crate::devices::pseudo::PvPanic;
+use crate::devices::tpm::{Swtpm, SwtpmError};
 use crate::devices::virtio::balloon::persist::{BalloonConstructorArgs, BalloonState};
 use crate::devices::virtio::balloon::{Balloon, BalloonError};
 use crate::devices::virtio::block::device::Block;
@@ -45,6 +48,8 @@ use crate::mmds::data_store::MmdsVersion;
 use crate::resources::{ResourcesError, VmResources};
 use crate::snapshot::Persist;
 use crate::vmm_config::mmds::MmdsConfigError;
+use crate::vmm_config::pvpanic::PvPanicConfig;
+use crate::vmm_config::tpm::TpmConfig;
 use crate::vstate::memory::GuestMemoryMmap;
 use crate::EventManager;
 
@@ -72,6 +77,8 @@ pub enum DevicePersistError {
     MmdsConfig(#[from] MmdsConfigError),
     /// Entropy: {0}
     Entropy(#[from] EntropyError),
+    /// TPM: {0}
+    Tpm(#[from] SwtpmError),
     /// Resource misconfiguration: {0}. Is the snapshot file corrupted?
     ResourcesError(#[from] ResourcesError),
 }
@@ -151,6 +158,22 @@ pub struct ConnectedLegacyState {
     pub device_info: MMIODeviceInfo,
 }
 
+/// Holds the state of a TPM device connected to the MMIO space.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConnectedTpmState {
+    /// Path of the swtpm control socket.
+    pub socket: PathBuf,
+    /// VmmResources.
+    pub device_info: MMIODeviceInfo,
+}
+
+/// Holds the state of a pvpanic device connected to the MMIO space.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConnectedPvPanicState {
+    /// VmmResources.
+    pub device_info: MMIODeviceInfo,
+}
+
 /// Holds the MMDS data store version.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum MmdsVersionState {
@@ -194,6 +217,10 @@ pub struct DeviceStates {
     pub mmds_version: Option<MmdsVersionState>,
     /// Entropy device state.
     pub entropy_device: Option<ConnectedEntropyState>,
+    /// TPM device state.
+    pub tpm_device: Option<ConnectedTpmState>,
+    /// pvpanic device state.
+    pub pvpanic_device: Option<ConnectedPvPanicState>,
 }
 
 /// A type used to extract the concrete `Arc<Mutex<T>>` for each of the device
@@ -231,6 +258,7 @@ impl fmt::Debug for MMIODevManagerConstructorArgs<'_> {
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct ACPIDeviceManagerState {
     vmgenid: Option<VMGenIDState>,
+    power_button: Option<PowerButtonState>,
 }
 
 pub struct ACPIDeviceManagerConstructorArgs<'a> {
@@ -245,6 +273,8 @@ pub enum ACPIDeviceManagerRestoreError {
     Interrupt(#[from] kvm_ioctls::Error),
     /// Could not create VMGenID device: {0}
     VMGenID(#[from] VmGenIdError),
+    /// Could not create power button device: {0}
+    PowerButton(#[from] PowerButtonError),
 }
 
 impl<'a> Persist<'a> for ACPIDeviceManager {
@@ -255,6 +285,7 @@ impl<'a> Persist<'a> for ACPIDeviceManager {
     fn save(&self) -> Self::State {
         ACPIDeviceManagerState {
             vmgenid: self.vmgenid.as_ref().map(|dev| dev.save()),
+            power_button: self.power_button.as_ref().map(|dev| dev.save()),
         }
     }
 
@@ -273,6 +304,10 @@ impl<'a> Persist<'a> for ACPIDeviceManager {
             )?;
             dev_manager.attach_vmgenid(vmgenid, constructor_args.vm)?;
         }
+        if let Some(power_button_state) = &state.power_button {
+            let power_button = PowerButton::restore((), power_button_state)?;
+            dev_manager.attach_power_button(power_button, constructor_args.vm)?;
+        }
         Ok(dev_manager)
     }
 }
@@ -290,6 +325,25 @@ impl<'a> Persist<'a> for MMIODeviceManager {
                 return Ok(());
             }
 
+            if *devtype == DeviceType::Tpm {
+                // The TPM state is held by swtpm, which keeps running.
+                let locked_bus_dev = bus_dev.lock().expect("Poisoned lock");
+                let tpm = locked_bus_dev.tpm_ref().expect("Unexpected device type");
+                states.tpm_device = Some(ConnectedTpmState {
+                    socket: tpm.swtpm().socket().to_path_buf(),
+                    device_info: device_info.clone(),
+                });
+                return Ok(());
+            }
+
+            if *devtype == DeviceType::PvPanic {
+                // The pvpanic device is stateless.
+                states.pvpanic_device = Some(ConnectedPvPanicState {
+                    device_info: device_info.clone(),
+                });
+                return Ok(());
+            }
+
             #[cfg(target_arch = "aarch64")]
             {
                 if *devtype == DeviceType::Serial || *devtype == DeviceType::Rtc {
@@ -422,8 +476,7 @@ impl<'a> Persist<'a> for MMIODeviceManager {
                 if state.type_ == DeviceType::Serial {
                     let serial = crate::builder::setup_serial_device(
                         constructor_args.event_manager,
-                        std::io::stdin(),
-                        std::io::stdout(),
+                        constructor_args.vm_resources,
                     )?;
 
                     constructor_args
@@ -467,6 +520,51 @@ impl<'a> Persist<'a> for MMIODeviceManager {
             }
         }
 
+        if let Some(tpm_state) = &state.tpm_device {
+            let swtpm = Swtpm::connect(&tpm_state.socket, false)?;
+            constructor_args
+                .resource_allocator
+                .allocate_mmio_memory(
+                    MMIO_LEN,
+                    MMIO_LEN,
+                    AllocPolicy::ExactMatch(tpm_state.device_info.addr),
+                )
+                .map_err(|e| {
+                    DevicePersistError::DeviceManager(super::mmio::MmioError::Allocator(e))
+                })?;
+            dev_manager.register_mmio_tpm(
+                constructor_args.resource_allocator,
+                swtpm,
+                Some(tpm_state.device_info.clone()),
+            )?;
+            constructor_args.vm_resources.set_tpm_config(TpmConfig {
+                socket: tpm_state.socket.clone(),
+            });
+        }
+
+        if let Some(pvpanic_state) = &state.pvpanic_device {
+            constructor_args
+                .resource_allocator
+                .allocate_mmio_memory(
+                    MMIO_LEN,
+                    MMIO_LEN,
+                    AllocPolicy::ExactMatch(pvpanic_state.device_info.addr),
+                )
+                .map_err(|e| {
+                    DevicePersistError::DeviceManager(super::mmio::MmioError::Allocator(e))
+                })?;
+            // The guest panics are only reported on the restored microVM, as the snapshot
+            // configured for them is not part of the snapshot.
+            dev_manager.register_mmio_pvpanic(
+                constructor_args.resource_allocator,
+                PvPanic::new(None),
+                Some(pvpanic_state.device_info.clone()),
+            )?;
+            constructor_args
+                .vm_resources
+                .set_pvpanic_config(PvPanicConfig::default());
+        }
+
         let mut restore_helper = |device: Arc<Mutex<dyn VirtioDevice>>,
                                   is_vhost_user: bool,
                                   as_subscriber: Arc<Mutex<dyn MutEventSubscriber>>,
@@ -600,6 +698,7 @@ impl<'a> Persist<'a> for MMIODeviceManager {
         if let Some(vsock_state) = &state.vsock_device {
             let ctor_args = VsockUdsConstructorArgs {
                 cid: vsock_state.device_state.frontend.cid,
+                avail_features: vsock_state.device_state.frontend.avail_features(),
             };
             let backend = VsockUnixBackend::restore(ctor_args, &vsock_state.device_state.backend)?;
             let device = Arc::new(Mutex::new(Vsock::restore(
@@ -749,6 +848,8 @@ mod tests {
                 amount_mib: 123,
                 deflate_on_oom: false,
                 stats_polling_interval_s: 1,
+                stats_push: None,
+                auto_target: None,
             };
             insert_balloon_device(&mut vmm, &mut cmdline, &mut event_manager, balloon_cfg);
             // Add a block device.
@@ -769,6 +870,16 @@ mod tests {
                 guest_mac: None,
                 rx_rate_limiter: None,
                 tx_rate_limiter: None,
+                flow_accounting: None,
+                pause_responder: None,
+                rx_coalescing: None,
+                mtu: None,
+                mmds_only: None,
+                num_queues: None,
+                offloads: None,
+                enforce_mac: None,
+                enforce_arp: None,
+                shared_rate_limiter: None,
             };
             insert_net_device_with_mmds(
                 &mut vmm,
@@ -783,6 +894,10 @@ mod tests {
                 vsock_id: Some(vsock_dev_id.to_string()),
                 guest_cid: 3,
                 uds_path: tmp_sock_file.as_path().to_str().unwrap().to_string(),
+                port_rate_limiters: Vec::new(),
+                tcp_forwards: Vec::new(),
+                seqpacket: false,
+                dgram: false,
             };
             insert_vsock_device(&mut vmm, &mut cmdline, &mut event_manager, vsock_config);
             // Add an entropy device.
@@ -834,16 +949,28 @@ mod tests {
   "boot-source": {{
     "kernel_image_path": "",
     "initrd_path": null,
-    "boot_args": null
+    "kernel_image_fd": null,
+    "initrd_fd": null,
+    "boot_args": null,
+    "firmware_path": null,
+    "kernel_digest": null,
+    "initrd_digest": null
   }},
   "cpu-config": null,
+  "cpu-limiter": null,
+  "lifecycle-hooks": {{
+    "hooks": []
+  }},
   "logger": null,
   "machine-config": {{
     "vcpu_count": 1,
     "mem_size_mib": 128,
     "smt": false,
     "track_dirty_pages": false,
-    "huge_pages": "None"
+    "huge_pages": "None",
+    "pmu": false,
+    "nested_virt": false,
+    "memory_backend": "anonymous"
   }},
   "metrics": null,
   "mmds-config": {{
@@ -862,9 +989,19 @@ mod tests {
       "tx_rate_limiter": null
     }}
   ],
+  "pvpanic": null,
+  "rate-limiters": [],
+  "serial": {{
+    "mode": "stdio"
+  }},
+  "shared-memory": [],
+  "smbios": null,
+  "tpm": null,
   "vsock": {{
     "guest_cid": 3,
-    "uds_path": "{}"
+    "uds_path": "{}",
+    "seqpacket": false,
+    "dgram": false
   }},
   "entropy": {{
     "rate_limiter": null
@@ -0,0 +1,112 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use acpi_tables::{aml, Aml};
+use log::{debug, error};
+use serde::{Deserialize, Serialize};
+use vm_superio::Trigger;
+use vmm_sys_util::eventfd::EventFd;
+
+use super::super::legacy::EventFdTrigger;
+use crate::device_manager::resources::ResourceAllocator;
+use crate::snapshot::Persist;
+
+/// ACPI power button device
+///
+/// Pressing the button raises a GED interrupt, which the GED turns into a notification of the
+/// `\_SB_.PWRB` device. The guest ACPI button driver then reports a power key press, upon which
+/// the guest usually shuts down gracefully.
+#[derive(Debug)]
+pub struct PowerButton {
+    /// Interrupt line notifying the guest that the button was pressed
+    pub interrupt_evt: EventFdTrigger,
+    /// GSI number for the device
+    pub gsi: u32,
+}
+
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum PowerButtonError {
+    /// Error with the power button interrupt: {0}
+    Interrupt(#[from] std::io::Error),
+    /// Failed to allocate requested resource: {0}
+    Allocator(#[from] vm_allocator::Error),
+}
+
+impl PowerButton {
+    /// Create a power button notifying the guest through `gsi`.
+    pub fn from_gsi(gsi: u32) -> Result<Self, PowerButtonError> {
+        debug!("power button: building device. IRQ: {}", gsi);
+        let interrupt_evt = EventFdTrigger::new(EventFd::new(libc::EFD_NONBLOCK)?);
+        Ok(Self { interrupt_evt, gsi })
+    }
+
+    /// Create a new power button, allocating a GSI for its notifications.
+    pub fn new(resource_allocator: &mut ResourceAllocator) -> Result<Self, PowerButtonError> {
+        let gsi = resource_allocator.allocate_gsi(1)?;
+        Self::from_gsi(gsi[0])
+    }
+
+    /// Notify the guest that the button was pressed.
+    pub fn press(&self) -> Result<(), std::io::Error> {
+        self.interrupt_evt
+            .trigger()
+            .inspect_err(|err| error!("power button: could not notify guest: {err}"))?;
+        debug!("power button: notifying guest about a button press");
+        Ok(())
+    }
+}
+
+/// Logic to save/restore the state of a power button device
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct PowerButtonState {
+    /// GSI used for the power button device
+    pub gsi: u32,
+}
+
+impl Persist<'_> for PowerButton {
+    type State = PowerButtonState;
+    type ConstructorArgs = ();
+    type Error = PowerButtonError;
+
+    fn save(&self) -> Self::State {
+        PowerButtonState { gsi: self.gsi }
+    }
+
+    fn restore(_: Self::ConstructorArgs, state: &Self::State) -> Result<Self, Self::Error> {
+        Self::from_gsi(state.gsi)
+    }
+}
+
+impl Aml for PowerButton {
+    fn append_aml_bytes(&self, v: &mut Vec<u8>) -> Result<(), aml::AmlError> {
+        aml::Device::new(
+            "_SB_.PWRB".try_into()?,
+            vec![
+                &aml::Name::new("_HID".try_into()?, &aml::EisaName::new("PNP0C0C")?)?,
+                &aml::Name::new("_UID".try_into()?, &aml::ZERO)?,
+            ],
+        )
+        .append_aml_bytes(v)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_power_button() {
+        let mut resource_allocator = ResourceAllocator::new().unwrap();
+        let button = PowerButton::new(&mut resource_allocator).unwrap();
+        button.press().unwrap();
+        assert_eq!(button.interrupt_evt.read().unwrap(), 1);
+
+        let restored = PowerButton::restore((), &button.save()).unwrap();
+        assert_eq!(restored.gsi, button.gsi);
+
+        let mut aml = Vec::new();
+        button.append_aml_bytes(&mut aml).unwrap();
+        assert!(!aml.is_empty());
+    }
+}
@@ -107,6 +107,20 @@ impl VmGenId {
         Ok(u128::from_le_bytes(gen_id_bytes))
     }
 
+    /// Write a new generation ID to guest memory, as when re-creating the device after snapshot
+    /// resumption.
+    pub fn regenerate(&mut self, mem: &GuestMemoryMmap) -> Result<(), VmGenIdError> {
+        let gen_id = Self::make_genid()?;
+        debug!(
+            "vmgenid: writing new generation ID to guest: {:#034x}",
+            gen_id
+        );
+        mem.write_slice(&gen_id.to_le_bytes(), self.guest_address)
+            .inspect_err(|err| error!("vmgenid: could not write generation ID to guest: {err}"))?;
+        self.gen_id = gen_id;
+        Ok(())
+    }
+
     /// Send an ACPI notification to guest device.
     ///
     /// This will only have effect if we have updated the generation ID in guest memory, i.e. when
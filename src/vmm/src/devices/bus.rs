@@ -52,11 +52,14 @@ pub struct Bus {
 
 use event_manager::{EventOps, Events, MutEventSubscriber};
 
+use super::legacy::serial::SerialIn;
 #[cfg(target_arch = "aarch64")]
 use super::legacy::RTCDevice;
 use super::legacy::{I8042Device, SerialDevice};
-use super::pseudo::BootTimer;
+use super::pseudo::{BootTimer, PvPanic};
+use super::tpm::TpmCrb;
 use super::virtio::mmio::MmioTransport;
+use crate::logger::{IncMetric, SharedIncMetric, METRICS};
 
 #[derive(Debug)]
 pub enum BusDevice {
@@ -64,8 +67,10 @@ pub enum BusDevice {
     #[cfg(target_arch = "aarch64")]
     RTCDevice(RTCDevice),
     BootTimer(BootTimer),
+    PvPanic(PvPanic),
+    Tpm(TpmCrb),
     MmioTransport(MmioTransport),
-    Serial(SerialDevice<std::io::Stdin>),
+    Serial(SerialDevice<SerialIn>),
     #[cfg(test)]
     Dummy(DummyDevice),
     #[cfg(test)]
@@ -121,13 +126,19 @@ impl BusDevice {
             _ => None,
         }
     }
+    pub fn tpm_ref(&self) -> Option<&TpmCrb> {
+        match self {
+            Self::Tpm(x) => Some(x),
+            _ => None,
+        }
+    }
     pub fn mmio_transport_ref(&self) -> Option<&MmioTransport> {
         match self {
             Self::MmioTransport(x) => Some(x),
             _ => None,
         }
     }
-    pub fn serial_ref(&self) -> Option<&SerialDevice<std::io::Stdin>> {
+    pub fn serial_ref(&self) -> Option<&SerialDevice<SerialIn>> {
         match self {
             Self::Serial(x) => Some(x),
             _ => None,
@@ -153,25 +164,53 @@ impl BusDevice {
             _ => None,
         }
     }
+    pub fn tpm_mut(&mut self) -> Option<&mut TpmCrb> {
+        match self {
+            Self::Tpm(x) => Some(x),
+            _ => None,
+        }
+    }
     pub fn mmio_transport_mut(&mut self) -> Option<&mut MmioTransport> {
         match self {
             Self::MmioTransport(x) => Some(x),
             _ => None,
         }
     }
-    pub fn serial_mut(&mut self) -> Option<&mut SerialDevice<std::io::Stdin>> {
+    pub fn serial_mut(&mut self) -> Option<&mut SerialDevice<SerialIn>> {
         match self {
             Self::Serial(x) => Some(x),
             _ => None,
         }
     }
 
+    // Counter of the KVM exits handled by this kind of device.
+    fn exit_metric(&self) -> Option<&'static SharedIncMetric> {
+        let exits = &METRICS.device_exits;
+        match self {
+            Self::I8042Device(_) => Some(&exits.i8042),
+            #[cfg(target_arch = "aarch64")]
+            Self::RTCDevice(_) => Some(&exits.rtc),
+            Self::BootTimer(_) => Some(&exits.boot_timer),
+            Self::PvPanic(_) => Some(&exits.pvpanic),
+            Self::Tpm(_) => Some(&exits.tpm),
+            Self::MmioTransport(_) => Some(&exits.virtio_mmio),
+            Self::Serial(_) => Some(&exits.serial),
+            #[cfg(test)]
+            Self::Dummy(_) | Self::Constant(_) => None,
+        }
+    }
+
     pub fn read(&mut self, offset: u64, data: &mut [u8]) {
+        if let Some(metric) = self.exit_metric() {
+            metric.inc();
+        }
         match self {
             Self::I8042Device(x) => x.bus_read(offset, data),
             #[cfg(target_arch = "aarch64")]
             Self::RTCDevice(x) => x.bus_read(offset, data),
             Self::BootTimer(x) => x.bus_read(offset, data),
+            Self::PvPanic(x) => x.bus_read(offset, data),
+            Self::Tpm(x) => x.bus_read(offset, data),
             Self::MmioTransport(x) => x.bus_read(offset, data),
             Self::Serial(x) => x.bus_read(offset, data),
             #[cfg(test)]
@@ -182,11 +221,16 @@ impl BusDevice {
     }
 
     pub fn write(&mut self, offset: u64, data: &[u8]) {
+        if let Some(metric) = self.exit_metric() {
+            metric.inc();
+        }
         match self {
             Self::I8042Device(x) => x.bus_write(offset, data),
             #[cfg(target_arch = "aarch64")]
             Self::RTCDevice(x) => x.bus_write(offset, data),
             Self::BootTimer(x) => x.bus_write(offset, data),
+            Self::PvPanic(x) => x.bus_write(offset, data),
+            Self::Tpm(x) => x.bus_write(offset, data),
             Self::MmioTransport(x) => x.bus_write(offset, data),
             Self::Serial(x) => x.bus_write(offset, data),
             #[cfg(test)]
@@ -370,6 +414,21 @@ mod tests {
         assert!(bus.write(0x15, &values));
     }
 
+    #[test]
+    fn bus_exit_metrics() {
+        let mut bus = Bus::new();
+        let boot_timer = Arc::new(Mutex::new(BusDevice::BootTimer(BootTimer::new(
+            utils::time::TimestampUs::default(),
+        ))));
+        bus.insert(boot_timer, 0x10, 0x10).unwrap();
+
+        let exits = METRICS.device_exits.boot_timer.count();
+        assert!(bus.read(0x10, &mut [0]));
+        assert!(bus.write(0x11, &[0]));
+        assert!(!bus.write(0x20, &[0]));
+        assert_eq!(METRICS.device_exits.boot_timer.count(), exits + 2);
+    }
+
     #[test]
     fn busrange_cmp_and_clone() {
         assert_eq!(BusRange(0x10, 2), BusRange(0x10, 3));
@@ -0,0 +1,231 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Defines the typed error events of devices.
+//!
+//! Every device error is classified by its origin, so that a guest driver misbehaving can be told
+//! apart from a host backend failing. Errors are reported both as a log event and as metrics.
+//!
+//! # Events format
+//! Each error is logged as a warning with a fixed set of fields:
+//! ```text
+//! Device error: device=block_rootfs class=backend errno=5
+//! ```
+//! `errno` is `none` when the error does not come from a failed system call.
+//!
+//! # Metrics format
+//! The metrics are flushed in JSON when requested by vmm::logger::metrics::METRICS.write().
+//!
+//! ## JSON example with metrics:
+//! ```json
+//! {
+//!  "device_errors_block_rootfs": {
+//!     "guest_errors": "SharedIncMetric",
+//!     "backend_errors": "SharedIncMetric",
+//!     "vmm_errors": "SharedIncMetric"
+//!  }
+//!  "device_errors_net_eth0": {
+//!     "guest_errors": "SharedIncMetric",
+//!     "backend_errors": "SharedIncMetric",
+//!     "vmm_errors": "SharedIncMetric"
+//!  }
+//!  "device_errors": {
+//!     "guest_errors": "SharedIncMetric",
+//!     "backend_errors": "SharedIncMetric",
+//!     "vmm_errors": "SharedIncMetric"
+//!  }
+//! }
+//! ```
+//! Each `device_errors_{id}` field is a serializable `DeviceErrorMetrics` structure counting the
+//! errors of one device per class, where `{id}` is the device type followed by the device id from
+//! the API, and `device_errors` is the aggregate of all the per device metrics.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+use serde::ser::SerializeMap;
+use serde::{Serialize, Serializer};
+
+use crate::event_stream::{self, VmEvent};
+use crate::logger::{warn, IncMetric, SharedIncMetric};
+
+/// Origin of a device error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceErrorClass {
+    /// The guest driver misbehaved, e.g. it wrote a malformed descriptor chain or request.
+    Guest,
+    /// The host backend of the device failed, e.g. an I/O error on a disk or a tap device.
+    Backend,
+    /// The device emulation itself failed, e.g. an event or interrupt could not be handled.
+    Vmm,
+}
+
+impl fmt::Display for DeviceErrorClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DeviceErrorClass::Guest => "guest",
+            DeviceErrorClass::Backend => "backend",
+            DeviceErrorClass::Vmm => "vmm",
+        })
+    }
+}
+
+/// Map of device id and error metrics.
+/// This should be protected by a lock before accessing.
+#[derive(Debug)]
+pub struct DeviceErrorMetricsPerDevice {
+    /// Used to access per device error metrics.
+    pub metrics: BTreeMap<String, Arc<DeviceErrorMetrics>>,
+}
+
+impl DeviceErrorMetricsPerDevice {
+    /// Allocate `DeviceErrorMetrics` for the device having id `device_id`. Also, allocate only if
+    /// it doesn't exist to avoid overwriting previously allocated data.
+    pub fn alloc(device_id: String) -> Arc<DeviceErrorMetrics> {
+        Arc::clone(
+            METRICS
+                .write()
+                .unwrap()
+                .metrics
+                .entry(device_id)
+                .or_insert_with(|| Arc::new(DeviceErrorMetrics::default())),
+        )
+    }
+}
+
+/// Pool of device error metrics per device behind a lock to keep things thread safe. Since the
+/// lock is initialized here it is safe to unwrap it without any check.
+static METRICS: RwLock<DeviceErrorMetricsPerDevice> = RwLock::new(DeviceErrorMetricsPerDevice {
+    metrics: BTreeMap::new(),
+});
+
+/// This function facilitates aggregation and serialization of per device error metrics.
+pub fn flush_metrics<S: Serializer>(serializer: S) -> Result<S::Ok, S::Error> {
+    let error_metrics = METRICS.read().unwrap();
+    let metrics_len = error_metrics.metrics.len();
+    // +1 to accommodate aggregate device error metrics
+    let mut seq = serializer.serialize_map(Some(1 + metrics_len))?;
+
+    let mut aggregated = DeviceErrorMetrics::default();
+
+    for (name, metrics) in error_metrics.metrics.iter() {
+        let devn = format!("device_errors_{}", name);
+        // serialization will flush the metrics so aggregate before it.
+        aggregated.aggregate(metrics);
+        seq.serialize_entry(&devn, metrics.as_ref())?;
+    }
+    seq.serialize_entry("device_errors", &aggregated)?;
+    seq.end()
+}
+
+/// Error counters of a device, per error class.
+#[derive(Debug, Default, Serialize)]
+pub struct DeviceErrorMetrics {
+    /// Number of errors caused by the guest driver.
+    pub guest_errors: SharedIncMetric,
+    /// Number of errors of the host backend.
+    pub backend_errors: SharedIncMetric,
+    /// Number of errors of the device emulation.
+    pub vmm_errors: SharedIncMetric,
+}
+
+impl DeviceErrorMetrics {
+    /// Returns the counter of the errors of class `class`.
+    pub fn class(&self, class: DeviceErrorClass) -> &SharedIncMetric {
+        match class {
+            DeviceErrorClass::Guest => &self.guest_errors,
+            DeviceErrorClass::Backend => &self.backend_errors,
+            DeviceErrorClass::Vmm => &self.vmm_errors,
+        }
+    }
+
+    /// Device error metrics are SharedIncMetric where the diff of current vs old is serialized
+    /// i.e. serialize_u64(current-old). So to have the aggregate serialized in same way we need
+    /// to fetch the diff of current vs old metrics and add it to the aggregate.
+    pub fn aggregate(&mut self, other: &Self) {
+        self.guest_errors.add(other.guest_errors.fetch_diff());
+        self.backend_errors.add(other.backend_errors.fetch_diff());
+        self.vmm_errors.add(other.vmm_errors.fetch_diff());
+    }
+}
+
+/// Reports the errors of a device, both as log events and in the device error metrics.
+#[derive(Debug, Clone)]
+pub struct DeviceErrorReporter {
+    device_id: String,
+    metrics: Arc<DeviceErrorMetrics>,
+}
+
+impl DeviceErrorReporter {
+    /// Creates a reporter for the device with the given id, made of the device type and of the
+    /// device id from the API, e.g. `block_rootfs`.
+    pub fn new(device_id: String) -> Self {
+        DeviceErrorReporter {
+            metrics: DeviceErrorMetricsPerDevice::alloc(device_id.clone()),
+            device_id,
+        }
+    }
+
+    /// Returns the error metrics of the device.
+    pub fn metrics(&self) -> &DeviceErrorMetrics {
+        &self.metrics
+    }
+
+    /// Reports an error of class `class`, caused by a failed system call if `errno` is set.
+    pub fn report(&self, class: DeviceErrorClass, errno: Option<i32>) {
+        match errno {
+            Some(errno) => warn!(
+                "Device error: device={} class={} errno={}",
+                self.device_id, class, errno
+            ),
+            None => warn!(
+                "Device error: device={} class={} errno=none",
+                self.device_id, class
+            ),
+        }
+        self.metrics.class(class).inc();
+        event_stream::emit(VmEvent::DeviceError {
+            device: self.device_id.clone(),
+            class: class.to_string(),
+            errno,
+        });
+    }
+
+    /// Reports an error of class `class` caused by the I/O error `err`.
+    pub fn report_io(&self, class: DeviceErrorClass, err: &std::io::Error) {
+        self.report(class, err.raw_os_error());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_device_error_reporter() {
+        let reporter = DeviceErrorReporter::new("block_errors_test".to_string());
+        reporter.report(DeviceErrorClass::Guest, None);
+        reporter.report_io(
+            DeviceErrorClass::Backend,
+            &std::io::Error::from_raw_os_error(libc::EIO),
+        );
+        reporter.report(DeviceErrorClass::Backend, None);
+        assert_eq!(reporter.metrics().guest_errors.count(), 1);
+        assert_eq!(reporter.metrics().backend_errors.count(), 2);
+        assert_eq!(reporter.metrics().vmm_errors.count(), 0);
+
+        // Reporters of the same device share their metrics.
+        let other = DeviceErrorReporter::new("block_errors_test".to_string());
+        other.report(DeviceErrorClass::Vmm, None);
+        assert_eq!(reporter.metrics().vmm_errors.count(), 1);
+
+        let mut aggregated = DeviceErrorMetrics::default();
+        aggregated.aggregate(reporter.metrics());
+        assert_eq!(aggregated.backend_errors.count(), 2);
+        assert_eq!(
+            serde_json::to_string(&aggregated).unwrap(),
+            r#"{"guest_errors":1,"backend_errors":2,"vmm_errors":1}"#
+        );
+    }
+}
@@ -70,7 +70,9 @@ const CMD_READ_CTR: u8 = 0x20; // Read control register
 const CMD_WRITE_CTR: u8 = 0x60; // Write control register
 const CMD_READ_OUTP: u8 = 0xD0; // Read output port
 const CMD_WRITE_OUTP: u8 = 0xD1; // Write output port
-const CMD_RESET_CPU: u8 = 0xFE; // Reset CPU
+/// Reset CPU. This is the only command whose handling doesn't depend on the device state, so
+/// it is signaled by KVM through an ioeventfd, without exiting to the VMM.
+pub const CMD_RESET_CPU: u8 = 0xFE;
 
 /// i8042 status register bits
 const SB_OUT_DATA_AVAIL: u8 = 0x0001; // Data available at port 0x60
@@ -210,6 +212,17 @@ impl I8042Device {
 }
 
 impl I8042Device {
+    /// Asserts the CPU reset line, when the guest issues `CMD_RESET_CPU`.
+    pub fn reset(&mut self) {
+        // We handle that by triggering our exit event fd. Meaning Firecracker will be exiting as
+        // soon as the VMM thread wakes up to handle this event.
+        if let Err(err) = self.reset_evt.write(1) {
+            error!("Failed to trigger i8042 reset event: {:?}", err);
+            METRICS.error_count.inc();
+        }
+        METRICS.reset_count.inc();
+    }
+
     pub fn bus_read(&mut self, offset: u64, data: &mut [u8]) {
         // All our ports are byte-wide. We don't know how to handle any wider data.
         if data.len() != 1 {
@@ -255,16 +268,7 @@ impl I8042Device {
         let mut write_ok = true;
 
         match offset {
-            OFS_STATUS if data[0] == CMD_RESET_CPU => {
-                // The guest wants to assert the CPU reset line. We handle that by triggering
-                // our exit event fd. Meaning Firecracker will be exiting as soon as the VMM
-                // thread wakes up to handle this event.
-                if let Err(err) = self.reset_evt.write(1) {
-                    error!("Failed to trigger i8042 reset event: {:?}", err);
-                    METRICS.error_count.inc();
-                }
-                METRICS.reset_count.inc();
-            }
+            OFS_STATUS if data[0] == CMD_RESET_CPU => self.reset(),
             OFS_STATUS if data[0] == CMD_READ_CTR => {
                 // The guest wants to read the control register.
                 // Let's make sure only the control register will be available for reading from
@@ -370,6 +374,10 @@ mod tests {
         let mut data = [CMD_RESET_CPU];
         i8042.bus_write(OFS_STATUS, &data);
         assert_eq!(reset_evt.read().unwrap(), 2);
+        let resets = METRICS.reset_count.count();
+        i8042.reset();
+        assert_eq!(reset_evt.read().unwrap(), 1);
+        assert_eq!(METRICS.reset_count.count(), resets + 1);
 
         // Check if reading with offset 1 doesn't have side effects.
         i8042.bus_read(1, &mut data);
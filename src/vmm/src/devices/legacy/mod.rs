@@ -10,6 +10,7 @@ mod i8042;
 #[cfg(target_arch = "aarch64")]
 pub mod rtc_pl031;
 pub mod serial;
+pub mod websocket;
 
 use std::io;
 use std::ops::Deref;
@@ -19,7 +20,9 @@ use serde::Serializer;
 use vm_superio::Trigger;
 use vmm_sys_util::eventfd::EventFd;
 
-pub use self::i8042::{I8042Device, I8042Error as I8042DeviceError};
+pub use self::i8042::{
+    I8042Device, I8042Error as I8042DeviceError, CMD_RESET_CPU as I8042_CMD_RESET_CPU,
+};
 #[cfg(target_arch = "aarch64")]
 pub use self::rtc_pl031::RTCDevice;
 pub use self::serial::{
@@ -6,20 +6,34 @@
 // found in the THIRD-PARTY file.
 
 //! Implements a wrapper over an UART serial device.
+use std::collections::VecDeque;
+use std::ffi::CStr;
 use std::fmt::Debug;
+use std::fs::{File, OpenOptions};
 use std::io;
 use std::io::{Read, Write};
-use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use event_manager::{EventOps, Events, MutEventSubscriber};
-use log::{error, warn};
+use log::{error, info, warn};
 use serde::Serialize;
 use vm_superio::serial::{Error as SerialError, SerialEvents};
 use vm_superio::{Serial, Trigger};
 use vmm_sys_util::epoll::EventSet;
 
+use super::websocket::{self, WebSocketAuth, WebSocketClient};
 use crate::devices::legacy::EventFdTrigger;
 use crate::logger::{IncMetric, SharedIncMetric};
+use crate::vmm_config::serial::{SerialConfig, SerialMode};
+
+/// Time after which the replay of the captured output to a console client that does not read it
+/// is abandoned.
+const REPLAY_LOG_TIMEOUT: Duration = Duration::from_millis(100);
 
 /// Received Data Available interrupt - for letting the driver know that
 /// there is some pending data to be processed.
@@ -123,26 +137,339 @@ impl SerialEvents for SerialEventsWrapper {
     }
 }
 
+/// Client attached to the serial console Unix socket.
+#[derive(Debug)]
+pub enum ConsoleClient {
+    /// Client exchanging the raw input and output.
+    Raw(UnixStream),
+    /// Client attached with the WebSocket protocol.
+    WebSocket(WebSocketClient),
+}
+
+impl ConsoleClient {
+    /// Returns the connection of the client.
+    pub fn stream(&self) -> &UnixStream {
+        match self {
+            Self::Raw(stream) => stream,
+            Self::WebSocket(client) => client.stream(),
+        }
+    }
+}
+
+impl Read for ConsoleClient {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Raw(stream) => stream.read(buf),
+            Self::WebSocket(client) => client.read(buf),
+        }
+    }
+}
+
+impl Write for ConsoleClient {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Raw(stream) => stream.write(buf),
+            Self::WebSocket(client) => client.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl AsRawFd for ConsoleClient {
+    fn as_raw_fd(&self) -> RawFd {
+        self.stream().as_raw_fd()
+    }
+}
+
+/// Client of the serial console Unix socket, if any, shared between the input and the output of
+/// the serial device.
+pub type SerialSocketClient = Arc<Mutex<Option<ConsoleClient>>>;
+
+/// Most recent output of the serial console, as returned by the API.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SerialLogContent {
+    /// Captured output, with invalid UTF-8 sequences replaced.
+    pub log: String,
+    /// Number of bytes of output dropped from the buffer to make room for newer output.
+    pub dropped_bytes: u64,
+}
+
+#[derive(Debug)]
+struct SerialLogBuffer {
+    data: VecDeque<u8>,
+    capacity: usize,
+    dropped_bytes: u64,
+}
+
+/// Ring buffer capturing the most recent output of the serial console.
+#[derive(Debug, Clone)]
+pub struct SerialLog(Arc<Mutex<SerialLogBuffer>>);
+
+impl SerialLog {
+    /// Creates a buffer keeping the last `capacity` bytes of output.
+    pub fn new(capacity: usize) -> Self {
+        SerialLog(Arc::new(Mutex::new(SerialLogBuffer {
+            data: VecDeque::with_capacity(capacity),
+            capacity,
+            dropped_bytes: 0,
+        })))
+    }
+
+    /// Appends output to the buffer, dropping the oldest output once it is full.
+    pub fn append(&self, bytes: &[u8]) {
+        let mut buffer = self.0.lock().expect("Poisoned lock");
+        // Output longer than the buffer only has its end kept.
+        let skipped = bytes.len().saturating_sub(buffer.capacity);
+        let bytes = &bytes[skipped..];
+        let overflow = (buffer.data.len() + bytes.len()).saturating_sub(buffer.capacity);
+        buffer.data.drain(..overflow);
+        buffer.data.extend(bytes);
+        buffer.dropped_bytes += (skipped + overflow) as u64;
+    }
+
+    /// Returns the content of the buffer.
+    pub fn content(&self) -> SerialLogContent {
+        let buffer = self.0.lock().expect("Poisoned lock");
+        let (front, back) = buffer.data.as_slices();
+        SerialLogContent {
+            log: String::from_utf8_lossy(&[front, back].concat()).into_owned(),
+            dropped_bytes: buffer.dropped_bytes,
+        }
+    }
+
+    /// Returns the raw bytes held by the buffer.
+    pub fn bytes(&self) -> Vec<u8> {
+        let buffer = self.0.lock().expect("Poisoned lock");
+        let (front, back) = buffer.data.as_slices();
+        [front, back].concat()
+    }
+}
+
 #[derive(Debug)]
 pub enum SerialOut {
     Sink(std::io::Sink),
     Stdout(std::io::Stdout),
+    /// Primary side of a pseudo terminal.
+    Pty(File),
+    /// Client of the serial console Unix socket.
+    Socket(SerialSocketClient),
+    /// File to which the output is appended.
+    File(File),
+    /// Output also captured in a ring buffer.
+    Logged(Box<SerialOut>, SerialLog),
 }
 impl std::io::Write for SerialOut {
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
         match self {
+            // The output is captured even if it cannot be written, as the serial device drops
+            // it rather than retrying.
+            Self::Logged(out, log) => {
+                let res = out.write(buf);
+                log.append(res.as_ref().map_or(buf, |written| &buf[..*written]));
+                res
+            }
             Self::Sink(sink) => sink.write(buf),
             Self::Stdout(stdout) => stdout.write(buf),
+            // The output is dropped, rather than failing the guest writes, while the console
+            // client does not keep up or is detached. The input side detects the detached
+            // clients.
+            Self::Pty(pty) => Ok(pty.write(buf).unwrap_or(buf.len())),
+            // The output is dropped, rather than failing the guest writes, e.g. while the disk
+            // is full.
+            Self::File(file) => Ok(file.write(buf).unwrap_or(buf.len())),
+            Self::Socket(client) => Ok(client
+                .lock()
+                .expect("Poisoned lock")
+                .as_mut()
+                .and_then(|client| client.write(buf).ok())
+                .unwrap_or(buf.len())),
         }
     }
     fn flush(&mut self) -> std::io::Result<()> {
         match self {
             Self::Sink(sink) => sink.flush(),
             Self::Stdout(stdout) => stdout.flush(),
+            Self::Pty(_) | Self::Socket(_) | Self::File(_) => Ok(()),
+            Self::Logged(out, _) => out.flush(),
+        }
+    }
+}
+
+/// Input of the serial device.
+#[derive(Debug)]
+pub enum SerialIn {
+    Stdin(std::io::Stdin),
+    /// Primary side of a pseudo terminal, and its secondary side, which is kept open so that the
+    /// pseudo terminal does not hang up while no client has it open.
+    Pty(File, File),
+    /// Client of the serial console Unix socket.
+    Socket(SerialSocketClient),
+}
+impl Read for SerialIn {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Stdin(stdin) => stdin.read(buf),
+            Self::Pty(primary, _) => primary.read(buf),
+            Self::Socket(client) => match client.lock().expect("Poisoned lock").as_mut() {
+                Some(client) => client.read(buf),
+                None => Err(io::Error::from_raw_os_error(libc::EWOULDBLOCK)),
+            },
+        }
+    }
+}
+impl AsRawFd for SerialIn {
+    fn as_raw_fd(&self) -> RawFd {
+        match self {
+            Self::Stdin(stdin) => stdin.as_raw_fd(),
+            Self::Pty(primary, _) => primary.as_raw_fd(),
+            Self::Socket(client) => client
+                .lock()
+                .expect("Poisoned lock")
+                .as_ref()
+                .map_or(-1, |client| client.as_raw_fd()),
         }
     }
 }
 
+/// Unix socket on which clients attach to the serial console, one at a time.
+#[derive(Debug)]
+pub struct SerialListener {
+    listener: UnixListener,
+    client: SerialSocketClient,
+    /// Captured output written to each client attaching, if enabled.
+    replay_log: Option<SerialLog>,
+    /// Users allowed to attach, if the clients use the WebSocket protocol.
+    websocket: Option<WebSocketAuth>,
+}
+
+/// Host side of the serial console.
+#[derive(Debug)]
+pub struct SerialBackend {
+    /// Input of the serial device, if any.
+    pub input: Option<SerialIn>,
+    /// Output of the serial device.
+    pub output: SerialOut,
+    /// Socket on which console clients attach, if any.
+    pub listener: Option<SerialListener>,
+}
+
+impl SerialBackend {
+    /// Opens the host side of the serial console described by `config`. `log` is the buffer
+    /// capturing the output, if any.
+    pub fn open(config: &SerialConfig, log: Option<&SerialLog>) -> io::Result<Self> {
+        let path = config.path.as_deref().map(Path::new);
+        match (config.mode, path) {
+            (SerialMode::Pty, Some(path)) => {
+                let (primary, secondary) = open_pty(path)?;
+                Ok(SerialBackend {
+                    output: SerialOut::Pty(primary.try_clone()?),
+                    input: Some(SerialIn::Pty(primary, secondary)),
+                    listener: None,
+                })
+            }
+            (SerialMode::UnixSocket | SerialMode::WebSocket, Some(path)) => {
+                let listener = UnixListener::bind(path)?;
+                listener.set_nonblocking(true)?;
+                let client = SerialSocketClient::default();
+                Ok(SerialBackend {
+                    input: Some(SerialIn::Socket(client.clone())),
+                    output: SerialOut::Socket(client.clone()),
+                    listener: Some(SerialListener {
+                        listener,
+                        client,
+                        replay_log: log.filter(|_| config.replay_log).cloned(),
+                        websocket: (config.mode == SerialMode::WebSocket)
+                            .then(|| WebSocketAuth::new(config.websocket.as_ref())),
+                    }),
+                })
+            }
+            (SerialMode::File, Some(path)) => Ok(SerialBackend {
+                input: None,
+                output: SerialOut::File(OpenOptions::new().create(true).append(true).open(path)?),
+                listener: None,
+            }),
+            _ => Ok(SerialBackend {
+                input: Some(SerialIn::Stdin(std::io::stdin())),
+                output: SerialOut::Stdout(std::io::stdout()),
+                listener: None,
+            }),
+        }
+    }
+}
+
+/// Writes the captured output to a client attaching to the console socket, giving up if it does
+/// not read it.
+fn replay_log(log: &SerialLog, client: &mut ConsoleClient) -> io::Result<()> {
+    client
+        .stream()
+        .set_write_timeout(Some(REPLAY_LOG_TIMEOUT))?;
+    match client {
+        ConsoleClient::Raw(stream) => stream.write_all(&log.bytes()),
+        // The output is replayed in a single frame.
+        ConsoleClient::WebSocket(client) => client.send_all(&log.bytes()),
+    }
+}
+
+fn last_os_error_if(failed: bool) -> io::Result<()> {
+    if failed {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// Opens a pseudo terminal in raw mode and links its secondary side at `path`.
+///
+/// Returns the non-blocking primary side and the secondary side.
+fn open_pty(path: &Path) -> io::Result<(File, File)> {
+    // SAFETY: posix_openpt has no invariants, its result is checked below.
+    let fd = unsafe {
+        libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY | libc::O_NONBLOCK | libc::O_CLOEXEC)
+    };
+    last_os_error_if(fd < 0)?;
+    // SAFETY: `fd` is a valid file descriptor that nothing else owns.
+    let primary = unsafe { File::from_raw_fd(fd) };
+    // SAFETY: `fd` is the primary side of a pseudo terminal.
+    last_os_error_if(unsafe { libc::grantpt(fd) } < 0)?;
+    // SAFETY: `fd` is the primary side of a pseudo terminal.
+    last_os_error_if(unsafe { libc::unlockpt(fd) } < 0)?;
+
+    let mut name = [0 as libc::c_char; 64];
+    // SAFETY: `name` is valid for writes of its length.
+    let ret = unsafe { libc::ptsname_r(fd, name.as_mut_ptr(), name.len()) };
+    if ret != 0 {
+        return Err(io::Error::from_raw_os_error(ret));
+    }
+    // SAFETY: ptsname_r succeeded, so `name` holds a NUL terminated string.
+    let name = unsafe { CStr::from_ptr(name.as_ptr()) }
+        .to_str()
+        .map_err(|_| io::Error::from(io::ErrorKind::InvalidData))?;
+
+    let secondary = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(libc::O_NOCTTY)
+        .open(name)?;
+    // The guest output must reach the client unchanged, and must not be echoed back as input.
+    let mut termios = std::mem::MaybeUninit::<libc::termios>::uninit();
+    // SAFETY: `termios` is valid for writes of a termios structure.
+    last_os_error_if(unsafe { libc::tcgetattr(secondary.as_raw_fd(), termios.as_mut_ptr()) } < 0)?;
+    // SAFETY: tcgetattr succeeded, so `termios` is initialized.
+    let mut termios = unsafe { termios.assume_init() };
+    // SAFETY: `termios` is a valid termios structure.
+    unsafe { libc::cfmakeraw(&mut termios) };
+    // SAFETY: `termios` is a valid termios structure.
+    last_os_error_if(
+        unsafe { libc::tcsetattr(secondary.as_raw_fd(), libc::TCSANOW, &termios) } < 0,
+    )?;
+
+    std::os::unix::fs::symlink(name, path)?;
+    Ok((primary, secondary))
+}
+
 /// Wrapper over the imported serial device.
 #[derive(Debug)]
 pub struct SerialWrapper<T: Trigger, EV: SerialEvents, I: Read + AsRawFd + Send> {
@@ -150,6 +477,8 @@ pub struct SerialWrapper<T: Trigger, EV: SerialEvents, I: Read + AsRawFd + Send>
     pub serial: Serial<T, EV, SerialOut>,
     /// Input to the serial device (needs to be readable).
     pub input: Option<I>,
+    /// Socket on which clients attach to the serial console, replacing the input and the output.
+    pub listener: Option<SerialListener>,
 }
 
 impl<I: Read + AsRawFd + Send + Debug> SerialWrapper<EventFdTrigger, SerialEventsWrapper, I> {
@@ -217,6 +546,64 @@ impl<I: Read + AsRawFd + Send + Debug> SerialWrapper<EventFdTrigger, SerialEvent
             .as_ref()
             .map_or(Ok(0), |buf_ready| buf_ready.read())
     }
+
+    /// Attaches a client connecting to the console socket, replacing the current one.
+    fn accept_client(&self, ops: &mut EventOps) {
+        let Some(listener) = self.listener.as_ref() else {
+            return;
+        };
+        let stream = match listener.listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(err) => {
+                warn!("Failed to accept a serial console client: {}", err);
+                return;
+            }
+        };
+        let mut client = match listener.websocket.as_ref() {
+            Some(auth) => match websocket::accept(&stream, auth) {
+                Ok(access) => ConsoleClient::WebSocket(WebSocketClient::new(stream, access)),
+                Err(err) => {
+                    warn!("Rejected a serial console client: {}", err);
+                    return;
+                }
+            },
+            None => ConsoleClient::Raw(stream),
+        };
+        if let Some(log) = listener.replay_log.as_ref() {
+            if let Err(err) = replay_log(log, &mut client) {
+                warn!("Failed to replay the serial console output: {}", err);
+            }
+        }
+        if let Err(err) = client.stream().set_nonblocking(true) {
+            warn!("Failed to accept a serial console client: {}", err);
+            return;
+        }
+        self.detach_client(ops);
+
+        let stream_fd = client.as_raw_fd();
+        *listener.client.lock().expect("Poisoned lock") = Some(client);
+        if let Err(err) = ops.add(Events::new(&stream_fd, EventSet::IN)) {
+            warn!("Failed to register the serial console client: {}", err);
+        }
+        let buffer_ready_fd = self.buffer_ready_evt_fd();
+        match ops.add(Events::new(&buffer_ready_fd, EventSet::IN)) {
+            Ok(()) | Err(event_manager::Error::FdAlreadyRegistered) => (),
+            Err(err) => warn!("Failed to register serial buffer ready event: {}", err),
+        }
+        info!("Attached a client to the serial console.");
+    }
+
+    /// Detaches the client of the console socket, if any, so that another one can attach.
+    fn detach_client(&self, ops: &mut EventOps) {
+        let Some(listener) = self.listener.as_ref() else {
+            return;
+        };
+        if let Some(client) = listener.client.lock().expect("Poisoned lock").take() {
+            // The client may already be unregistered, on errors.
+            let _ = ops.remove(Events::new(&client, EventSet::IN));
+            info!("Detached the client of the serial console.");
+        }
+    }
 }
 
 /// Type for representing a serial device.
@@ -235,10 +622,20 @@ impl<I: Read + AsRawFd + Send + Debug> MutEventSubscriber
             }
         }
 
+        if let Some(listener) = &self.listener {
+            if listener.listener.as_raw_fd() == event.fd() {
+                self.accept_client(ops);
+                return;
+            }
+        }
+
         let input_fd = self.serial_input_fd();
         let buffer_ready_fd = self.buffer_ready_evt_fd();
         if input_fd < 0 || buffer_ready_fd < 0 {
-            error!("Serial does not have a configured input source.");
+            // Console sockets have no input source while no client is attached.
+            if self.listener.is_none() {
+                error!("Serial does not have a configured input source.");
+            }
             return;
         }
 
@@ -268,6 +665,7 @@ impl<I: Read + AsRawFd + Send + Debug> MutEventSubscriber
                     unregister_source(ops, &input_fd);
                     unregister_source(ops, &buffer_ready_fd);
                     warn!("Detached the serial input due to peer close/error.");
+                    self.detach_client(ops);
                 }
             }
             Err(err) => {
@@ -288,6 +686,7 @@ impl<I: Read + AsRawFd + Send + Debug> MutEventSubscriber
                         unregister_source(ops, &input_fd);
                         unregister_source(ops, &buffer_ready_fd);
                         warn!("Detached the serial input due to peer close/error.");
+                        self.detach_client(ops);
                     }
                 }
             }
@@ -297,6 +696,14 @@ impl<I: Read + AsRawFd + Send + Debug> MutEventSubscriber
     /// Initial registration of pollable objects.
     /// If serial input is present, register the serial input FD as readable.
     fn init(&mut self, ops: &mut EventOps) {
+        // The input source is registered once a client attaches to the console socket.
+        if let Some(listener) = &self.listener {
+            if let Err(err) = ops.add(Events::new(&listener.listener, EventSet::IN)) {
+                warn!("Failed to register the serial console socket: {}", err);
+            }
+            return;
+        }
+
         if self.input.is_some() && self.serial.events().buffer_ready_event_fd.is_some() {
             let serial_fd = self.serial_input_fd();
             let buf_ready_evt = self.buffer_ready_evt_fd();
@@ -385,6 +792,7 @@ mod tests {
                 SerialOut::Sink(std::io::sink()),
             ),
             input: None::<std::io::Stdin>,
+            listener: None,
         };
         serial.serial.raw_input(b"abc").unwrap();
 
@@ -404,6 +812,54 @@ mod tests {
         assert_eq!(invalid_reads_after_2, invalid_reads_after);
     }
 
+    #[test]
+    fn test_serial_log() {
+        let log = SerialLog::new(8);
+        let mut out = SerialOut::Logged(Box::new(SerialOut::Sink(std::io::sink())), log.clone());
+        out.write_all(b"boot").unwrap();
+        assert_eq!(
+            log.content(),
+            SerialLogContent {
+                log: "boot".to_string(),
+                dropped_bytes: 0
+            }
+        );
+
+        out.write_all(b" failed").unwrap();
+        assert_eq!(log.content().log, "t failed");
+        assert_eq!(log.content().dropped_bytes, 3);
+
+        log.append(b"kernel panic");
+        assert_eq!(log.content().log, "el panic");
+        assert_eq!(log.content().dropped_bytes, 15);
+    }
+
+    #[test]
+    fn test_serial_log_replay() {
+        let log = SerialLog::new(16);
+        log.append(b"early boot");
+        let (sender, mut receiver) = UnixStream::pair().unwrap();
+        replay_log(&log, &mut ConsoleClient::Raw(sender)).unwrap();
+        let mut replayed = Vec::new();
+        receiver.read_to_end(&mut replayed).unwrap();
+        assert_eq!(replayed, b"early boot");
+    }
+
+    #[test]
+    fn test_serial_file_backend() {
+        let tmp_file = vmm_sys_util::tempfile::TempFile::new().unwrap();
+        let config = SerialConfig {
+            mode: SerialMode::File,
+            path: Some(tmp_file.as_path().to_str().unwrap().to_string()),
+            ..Default::default()
+        };
+        let mut backend = SerialBackend::open(&config, None).unwrap();
+        assert!(backend.input.is_none());
+        assert!(backend.listener.is_none());
+        backend.output.write_all(b"login: ").unwrap();
+        assert_eq!(std::fs::read(tmp_file.as_path()).unwrap(), b"login: ");
+    }
+
     #[test]
     fn test_is_fifo() {
         // invalid file descriptors arent fifos
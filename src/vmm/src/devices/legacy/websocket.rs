@@ -0,0 +1,626 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! WebSocket transport of the serial console.
+//!
+//! Clients attach to the console socket with a WebSocket upgrade request on `/vm/console/ws`,
+//! and are authorized with the credentials of their peer process. The guest output is sent in
+//! binary frames, and the payload of the data frames received from read-write clients is the
+//! guest input.
+
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::time::Duration;
+
+use aws_lc_rs::digest;
+use base64::Engine;
+
+use crate::vmm_config::serial::WebSocketConsoleConfig;
+
+/// Path of the upgrade requests of the console clients.
+pub const CONSOLE_WS_PATH: &str = "/vm/console/ws";
+/// Time given to a client to send its upgrade request.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(1);
+/// Maximum length of an upgrade request.
+const MAX_HANDSHAKE_LEN: usize = 8192;
+/// Appended to the key of the client to compute the accept key, as defined in RFC 6455.
+const WS_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+/// Maximum payload of the frames received from clients.
+const MAX_FRAME_PAYLOAD_LEN: u64 = 1 << 16;
+
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_BINARY: u8 = 0x2;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xa;
+
+/// Errors associated with attaching a WebSocket client to the console.
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum WebSocketError {
+    /// Failed to exchange the upgrade request: {0}
+    Io(#[from] io::Error),
+    /// Invalid upgrade request: {0}
+    BadRequest(&'static str),
+    /// No console at {0}.
+    NotFound(String),
+    /// User {0} is not allowed {1} access to the console.
+    Forbidden(u32, &'static str),
+}
+
+/// Access of a client to the console.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConsoleAccess {
+    /// The client receives the output of the guest.
+    ReadOnly,
+    /// The client also writes the input of the guest.
+    ReadWrite,
+}
+
+impl ConsoleAccess {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ConsoleAccess::ReadOnly => "read_only",
+            ConsoleAccess::ReadWrite => "read_write",
+        }
+    }
+}
+
+/// Users allowed to attach to the console.
+#[derive(Debug, Clone)]
+pub struct WebSocketAuth {
+    read_write_uids: Vec<u32>,
+    read_only_uids: Vec<u32>,
+}
+
+impl WebSocketAuth {
+    /// Allows the users of `config`, and the user running Firecracker, which is allowed read-write
+    /// access.
+    pub fn new(config: Option<&WebSocketConsoleConfig>) -> Self {
+        let config = config.cloned().unwrap_or_default();
+        let mut read_write_uids = config.read_write_uids;
+        // SAFETY: geteuid has no invariants, and cannot fail.
+        read_write_uids.push(unsafe { libc::geteuid() });
+        WebSocketAuth {
+            read_write_uids,
+            read_only_uids: config.read_only_uids,
+        }
+    }
+
+    /// Returns the highest access allowed to `uid`, if any.
+    fn access(&self, uid: u32) -> Option<ConsoleAccess> {
+        if self.read_write_uids.contains(&uid) {
+            Some(ConsoleAccess::ReadWrite)
+        } else if self.read_only_uids.contains(&uid) {
+            Some(ConsoleAccess::ReadOnly)
+        } else {
+            None
+        }
+    }
+}
+
+/// Reads the upgrade request of a client connecting to the console socket, authorizes it, and
+/// completes the handshake. The client is sent an HTTP error response if it is rejected.
+pub fn accept(stream: &UnixStream, auth: &WebSocketAuth) -> Result<ConsoleAccess, WebSocketError> {
+    let result = handshake(stream, auth);
+    let mut stream = stream;
+    let response = match &result {
+        Ok((_, accept_key)) => format!(
+            "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: \
+             Upgrade\r\nSec-WebSocket-Accept: {accept_key}\r\n\r\n"
+        ),
+        Err(WebSocketError::Io(_)) => String::new(),
+        Err(err) => {
+            let status = match err {
+                WebSocketError::NotFound(_) => "404 Not Found",
+                WebSocketError::Forbidden(..) => "403 Forbidden",
+                _ => "400 Bad Request",
+            };
+            let body = format!("{err}\n");
+            format!(
+                "HTTP/1.1 {status}\r\nContent-Type: text/plain\r\nContent-Length: \
+                 {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            )
+        }
+    };
+    if !response.is_empty() {
+        stream.write_all(response.as_bytes())?;
+    }
+    result.map(|(access, _)| access)
+}
+
+/// Validates the upgrade request, and returns the access granted and the accept key.
+fn handshake(
+    mut stream: &UnixStream,
+    auth: &WebSocketAuth,
+) -> Result<(ConsoleAccess, String), WebSocketError> {
+    stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+    stream.set_write_timeout(Some(HANDSHAKE_TIMEOUT))?;
+
+    // The client does not send anything else before the response, so the request is read
+    // entirely, without reading past its end.
+    let mut request = Vec::new();
+    let mut byte = [0u8; 1];
+    while !request.ends_with(b"\r\n\r\n") {
+        if request.len() == MAX_HANDSHAKE_LEN {
+            return Err(WebSocketError::BadRequest("request too long"));
+        }
+        if stream.read(&mut byte)? == 0 {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        request.push(byte[0]);
+    }
+    let request = std::str::from_utf8(&request)
+        .map_err(|_| WebSocketError::BadRequest("request is not valid UTF-8"))?;
+
+    let mut lines = request.split("\r\n");
+    let mut request_line = lines.next().unwrap_or_default().split(' ');
+    let (Some("GET"), Some(target), Some("HTTP/1.1")) = (
+        request_line.next(),
+        request_line.next(),
+        request_line.next(),
+    ) else {
+        return Err(WebSocketError::BadRequest(
+            "expected a GET HTTP/1.1 request",
+        ));
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    if path != CONSOLE_WS_PATH {
+        return Err(WebSocketError::NotFound(path.to_string()));
+    }
+
+    let headers: Vec<(&str, &str)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim(), value.trim()))
+        .collect();
+    let header = |name: &str| {
+        headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| *value)
+    };
+    let has_token = |name: &str, token: &str| {
+        header(name).is_some_and(|value| {
+            value
+                .split(',')
+                .any(|item| item.trim().eq_ignore_ascii_case(token))
+        })
+    };
+    if !has_token("upgrade", "websocket") || !has_token("connection", "upgrade") {
+        return Err(WebSocketError::BadRequest(
+            "not a WebSocket upgrade request",
+        ));
+    }
+    if header("sec-websocket-version") != Some("13") {
+        return Err(WebSocketError::BadRequest("unsupported WebSocket version"));
+    }
+    let key = header("sec-websocket-key")
+        .ok_or(WebSocketError::BadRequest("missing Sec-WebSocket-Key"))?;
+
+    let requested = match query
+        .split('&')
+        .find_map(|param| param.strip_prefix("mode="))
+    {
+        None => None,
+        Some("read_only") => Some(ConsoleAccess::ReadOnly),
+        Some("read_write") => Some(ConsoleAccess::ReadWrite),
+        Some(_) => return Err(WebSocketError::BadRequest("invalid mode")),
+    };
+    let uid = peer_uid(stream)?;
+    let access = match (auth.access(uid), requested) {
+        (None, requested) => {
+            let requested = requested.unwrap_or(ConsoleAccess::ReadOnly);
+            return Err(WebSocketError::Forbidden(uid, requested.as_str()));
+        }
+        (Some(ConsoleAccess::ReadOnly), Some(ConsoleAccess::ReadWrite)) => {
+            return Err(WebSocketError::Forbidden(
+                uid,
+                ConsoleAccess::ReadWrite.as_str(),
+            ));
+        }
+        (Some(allowed), requested) => requested.unwrap_or(allowed),
+    };
+
+    Ok((access, accept_key(key)))
+}
+
+/// Computes the accept key answering the key of the client.
+fn accept_key(key: &str) -> String {
+    let digest = digest::digest(
+        &digest::SHA1_FOR_LEGACY_USE_ONLY,
+        format!("{key}{WS_GUID}").as_bytes(),
+    );
+    base64::engine::general_purpose::STANDARD.encode(digest.as_ref())
+}
+
+/// Returns the user ID of the process which connected `stream`.
+fn peer_uid(stream: &UnixStream) -> io::Result<u32> {
+    let mut cred = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut len = libc::socklen_t::try_from(std::mem::size_of::<libc::ucred>()).unwrap();
+    // SAFETY: `cred` is valid for writes of `len` bytes, and `len` is valid for writes.
+    let ret = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            std::ptr::addr_of_mut!(cred).cast(),
+            &mut len,
+        )
+    };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(cred.uid)
+}
+
+/// Encodes an unmasked frame, as sent by servers.
+fn encode_frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(payload.len() + 10);
+    frame.push(0x80 | opcode);
+    match (u8::try_from(payload.len()), u16::try_from(payload.len())) {
+        (Ok(len), _) if len < 126 => frame.push(len),
+        (_, Ok(len)) => {
+            frame.push(126);
+            frame.extend_from_slice(&len.to_be_bytes());
+        }
+        _ => {
+            frame.push(127);
+            frame.extend_from_slice(&(payload.len() as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// Decodes the first frame of `buf`, which clients must mask, and removes it from `buf`.
+///
+/// Returns `None` if `buf` does not hold the whole frame yet.
+fn decode_frame(buf: &mut Vec<u8>) -> io::Result<Option<(u8, Vec<u8>)>> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    let [first, second, ..] = buf[..] else {
+        return Ok(None);
+    };
+    if first & 0x70 != 0 {
+        return Err(invalid("reserved bits set"));
+    }
+    if second & 0x80 == 0 {
+        return Err(invalid("unmasked client frame"));
+    }
+    let opcode = first & 0x0f;
+    let (len, mut offset) = match second & 0x7f {
+        126 => match buf.get(2..4) {
+            Some(len) => (u64::from(u16::from_be_bytes([len[0], len[1]])), 4),
+            None => return Ok(None),
+        },
+        127 => match buf.get(2..10) {
+            Some(len) => (u64::from_be_bytes(len.try_into().unwrap()), 10),
+            None => return Ok(None),
+        },
+        len => (u64::from(len), 2),
+    };
+    if len > MAX_FRAME_PAYLOAD_LEN {
+        return Err(invalid("frame too large"));
+    }
+    // Bounded by `MAX_FRAME_PAYLOAD_LEN`.
+    let len = usize::try_from(len).unwrap();
+    let Some(mask) = buf.get(offset..offset + 4) else {
+        return Ok(None);
+    };
+    let mask: [u8; 4] = mask.try_into().unwrap();
+    offset += 4;
+    let Some(payload) = buf.get(offset..offset + len) else {
+        return Ok(None);
+    };
+    let payload = payload
+        .iter()
+        .zip(mask.iter().cycle())
+        .map(|(byte, mask)| byte ^ mask)
+        .collect();
+    buf.drain(..offset + len);
+    Ok(Some((opcode, payload)))
+}
+
+/// Client attached to the console with the WebSocket protocol.
+#[derive(Debug)]
+pub struct WebSocketClient {
+    stream: UnixStream,
+    access: ConsoleAccess,
+    /// Bytes received which do not form a whole frame yet.
+    received: Vec<u8>,
+    /// Guest input received, which the serial device did not read yet.
+    input: VecDeque<u8>,
+    /// End of a frame partially written, which is written before any other frame.
+    pending_output: Vec<u8>,
+    /// Whether the client closed the connection.
+    closed: bool,
+}
+
+impl WebSocketClient {
+    /// Wraps a client whose handshake completed.
+    pub fn new(stream: UnixStream, access: ConsoleAccess) -> Self {
+        WebSocketClient {
+            stream,
+            access,
+            received: Vec::new(),
+            input: VecDeque::new(),
+            pending_output: Vec::new(),
+            closed: false,
+        }
+    }
+
+    /// Returns the underlying stream.
+    pub fn stream(&self) -> &UnixStream {
+        &self.stream
+    }
+
+    /// Sends `payload` in a binary frame, waiting for it to be written.
+    pub fn send_all(&mut self, payload: &[u8]) -> io::Result<()> {
+        self.stream.write_all(&encode_frame(OPCODE_BINARY, payload))
+    }
+
+    /// Sends a frame, without blocking. Frames are dropped while the end of a previous frame
+    /// cannot be written, so that the frames are never interleaved.
+    fn send(&mut self, opcode: u8, payload: &[u8]) {
+        if !self.pending_output.is_empty() {
+            match self.stream.write(&self.pending_output) {
+                Ok(written) => {
+                    self.pending_output.drain(..written);
+                }
+                Err(_) => return,
+            }
+            if !self.pending_output.is_empty() {
+                return;
+            }
+        }
+        let frame = encode_frame(opcode, payload);
+        if let Ok(written) = self.stream.write(&frame) {
+            // Nothing is written while the client does not keep up, and the frame is dropped.
+            if written > 0 {
+                self.pending_output = frame[written..].to_vec();
+            }
+        }
+    }
+
+    /// Reads the available bytes from the client, and handles the frames received.
+    fn receive(&mut self) -> io::Result<()> {
+        let mut chunk = [0u8; 4096];
+        let len = self.stream.read(&mut chunk)?;
+        if len == 0 {
+            self.closed = true;
+            return Ok(());
+        }
+        self.received.extend_from_slice(&chunk[..len]);
+        while let Some((opcode, payload)) = decode_frame(&mut self.received)? {
+            match opcode {
+                OPCODE_CONTINUATION | OPCODE_TEXT | OPCODE_BINARY => {
+                    // The input of read-only clients is dropped.
+                    if self.access == ConsoleAccess::ReadWrite {
+                        self.input.extend(payload);
+                    }
+                }
+                OPCODE_CLOSE => {
+                    // Echoes the status code, if any.
+                    self.send(OPCODE_CLOSE, payload.get(..2).unwrap_or_default());
+                    self.closed = true;
+                    return Ok(());
+                }
+                OPCODE_PING => self.send(OPCODE_PONG, &payload),
+                OPCODE_PONG => (),
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("unknown opcode {opcode}"),
+                    ))
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Read for WebSocketClient {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.input.is_empty() && !self.closed {
+            self.receive()?;
+        }
+        if self.input.is_empty() {
+            if self.closed {
+                return Ok(0);
+            }
+            // Only control frames, or part of a frame, were received.
+            return Err(io::Error::from_raw_os_error(libc::EWOULDBLOCK));
+        }
+        let len = buf.len().min(self.input.len());
+        for (byte, input) in buf.iter_mut().zip(self.input.drain(..len)) {
+            *byte = input;
+        }
+        Ok(len)
+    }
+}
+
+impl Write for WebSocketClient {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.send(OPCODE_BINARY, buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl AsRawFd for WebSocketClient {
+    fn as_raw_fd(&self) -> RawFd {
+        self.stream.as_raw_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn masked_frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+        let mask = [1, 2, 3, 4];
+        let mut frame = encode_frame(opcode, payload);
+        let offset = frame.len() - payload.len();
+        frame[1] |= 0x80;
+        let masked: Vec<u8> = payload
+            .iter()
+            .zip(mask.iter().cycle())
+            .map(|(byte, mask)| byte ^ mask)
+            .collect();
+        frame.truncate(offset);
+        frame.extend_from_slice(&mask);
+        frame.extend_from_slice(&masked);
+        frame
+    }
+
+    fn upgrade_request(target: &str) -> String {
+        format!(
+            "GET {target} HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: \
+             keep-alive, Upgrade\r\nSec-WebSocket-Key: \
+             dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n"
+        )
+    }
+
+    fn upgrade(auth: &WebSocketAuth, request: &str) -> (Result<ConsoleAccess, String>, String) {
+        let (server, mut client) = UnixStream::pair().unwrap();
+        client.write_all(request.as_bytes()).unwrap();
+        let result = accept(&server, auth).map_err(|err| err.to_string());
+        drop(server);
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        (result, response)
+    }
+
+    #[test]
+    fn test_accept_key() {
+        // Example of RFC 6455.
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBsXzm3e1HkxKxWaG4w+mo="
+        );
+    }
+
+    #[test]
+    fn test_handshake() {
+        let auth = WebSocketAuth::new(None);
+        let (result, response) = upgrade(&auth, &upgrade_request(CONSOLE_WS_PATH));
+        assert_eq!(result.unwrap(), ConsoleAccess::ReadWrite);
+        assert!(response.starts_with("HTTP/1.1 101 Switching Protocols\r\n"));
+        assert!(response.contains("Sec-WebSocket-Accept: s3pPLMBsXzm3e1HkxKxWaG4w+mo=\r\n"));
+
+        let (result, _) = upgrade(
+            &auth,
+            &upgrade_request(&format!("{CONSOLE_WS_PATH}?mode=read_only")),
+        );
+        assert_eq!(result.unwrap(), ConsoleAccess::ReadOnly);
+
+        let (result, response) = upgrade(&auth, &upgrade_request("/vm/console"));
+        result.unwrap_err();
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+
+        let (result, response) = upgrade(
+            &auth,
+            &upgrade_request(CONSOLE_WS_PATH).replace("websocket", "h2c"),
+        );
+        result.unwrap_err();
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+
+        // The user running the tests is only allowed read-only access.
+        // SAFETY: geteuid has no invariants.
+        let uid = unsafe { libc::geteuid() };
+        let auth = WebSocketAuth {
+            read_write_uids: vec![],
+            read_only_uids: vec![uid],
+        };
+        let (result, _) = upgrade(&auth, &upgrade_request(CONSOLE_WS_PATH));
+        assert_eq!(result.unwrap(), ConsoleAccess::ReadOnly);
+        let (result, response) = upgrade(
+            &auth,
+            &upgrade_request(&format!("{CONSOLE_WS_PATH}?mode=read_write")),
+        );
+        assert_eq!(
+            result.unwrap_err(),
+            format!("User {uid} is not allowed read_write access to the console.")
+        );
+        assert!(response.starts_with("HTTP/1.1 403 Forbidden\r\n"));
+    }
+
+    #[test]
+    fn test_frames() {
+        for len in [0, 125, 126, 65535, 65536] {
+            let payload = vec![0x5a; len];
+            let mut buf = masked_frame(OPCODE_BINARY, &payload);
+            let frame_len = buf.len();
+            buf.extend_from_slice(&masked_frame(OPCODE_PING, b"ping"));
+            assert_eq!(
+                decode_frame(&mut buf[..frame_len - 1].to_vec()).unwrap(),
+                None
+            );
+            assert_eq!(
+                decode_frame(&mut buf).unwrap(),
+                Some((OPCODE_BINARY, payload))
+            );
+            assert_eq!(
+                decode_frame(&mut buf).unwrap(),
+                Some((OPCODE_PING, b"ping".to_vec()))
+            );
+            assert!(buf.is_empty());
+        }
+        // Frames sent by clients must be masked.
+        decode_frame(&mut encode_frame(OPCODE_BINARY, b"ls")).unwrap_err();
+        decode_frame(&mut masked_frame(OPCODE_BINARY, &[0; 65537])).unwrap_err();
+    }
+
+    #[test]
+    fn test_client() {
+        let (server, mut peer) = UnixStream::pair().unwrap();
+        server.set_nonblocking(true).unwrap();
+        let mut client = WebSocketClient::new(server, ConsoleAccess::ReadWrite);
+
+        client.write_all(b"login: ").unwrap();
+        let mut frame = [0u8; 9];
+        peer.read_exact(&mut frame).unwrap();
+        assert_eq!(frame[..], encode_frame(OPCODE_BINARY, b"login: ")[..]);
+
+        let mut buf = [0u8; 16];
+        assert_eq!(
+            client.read(&mut buf).unwrap_err().raw_os_error(),
+            Some(libc::EWOULDBLOCK)
+        );
+        peer.write_all(&masked_frame(OPCODE_TEXT, b"root\n"))
+            .unwrap();
+        peer.write_all(&masked_frame(OPCODE_PING, b"hi")).unwrap();
+        assert_eq!(client.read(&mut buf[..2]).unwrap(), 2);
+        assert_eq!(client.read(&mut buf[2..]).unwrap(), 3);
+        assert_eq!(&buf[..5], b"root\n");
+        let mut pong = [0u8; 4];
+        peer.read_exact(&mut pong).unwrap();
+        assert_eq!(pong[..], encode_frame(OPCODE_PONG, b"hi")[..]);
+
+        peer.write_all(&masked_frame(OPCODE_CLOSE, &[0x03, 0xe8]))
+            .unwrap();
+        assert_eq!(client.read(&mut buf).unwrap(), 0);
+        let mut close = [0u8; 4];
+        peer.read_exact(&mut close).unwrap();
+        assert_eq!(close[..], encode_frame(OPCODE_CLOSE, &[0x03, 0xe8])[..]);
+
+        // The input of read-only clients is dropped.
+        let (server, mut peer) = UnixStream::pair().unwrap();
+        server.set_nonblocking(true).unwrap();
+        let mut client = WebSocketClient::new(server, ConsoleAccess::ReadOnly);
+        peer.write_all(&masked_frame(OPCODE_BINARY, b"reboot\n"))
+            .unwrap();
+        client.read(&mut buf).unwrap_err();
+        drop(peer);
+        assert_eq!(client.read(&mut buf).unwrap(), 0);
+    }
+}
@@ -11,8 +11,10 @@ use std::io;
 
 pub mod acpi;
 pub mod bus;
+pub mod error_events;
 pub mod legacy;
 pub mod pseudo;
+pub mod tpm;
 pub mod virtio;
 
 pub use bus::{Bus, BusDevice, BusError};
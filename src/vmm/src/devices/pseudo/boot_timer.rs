@@ -3,6 +3,7 @@
 
 use utils::time::TimestampUs;
 
+use crate::boot_timings::{BootPhase, BOOT_TIMINGS};
 use crate::logger::info;
 
 const MAGIC_VALUE_SIGNAL_GUEST_BOOT_COMPLETE: u8 = 123;
@@ -22,6 +23,7 @@ impl BootTimer {
 
         if data[0] == MAGIC_VALUE_SIGNAL_GUEST_BOOT_COMPLETE {
             let now_tm_us = TimestampUs::default();
+            BOOT_TIMINGS.record(BootPhase::GuestBootComplete);
 
             let boot_time_us = now_tm_us.time_us - self.start_ts.time_us;
             let boot_time_cpu_us = now_tm_us.cputime_us - self.start_ts.cputime_us;
@@ -3,5 +3,7 @@
 
 //! Implements Firecracker specific devices (e.g. signal when boot is completed).
 mod boot_timer;
+mod pvpanic;
 
 pub use self::boot_timer::BootTimer;
+pub use self::pvpanic::{PvPanic, PVPANIC_CRASH_LOADED, PVPANIC_PANICKED};
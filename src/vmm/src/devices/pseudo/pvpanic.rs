@@ -0,0 +1,93 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use vmm_sys_util::eventfd::EventFd;
+
+use crate::event_stream::{self, VmEvent};
+use crate::logger::{error, IncMetric, METRICS};
+
+/// The guest kernel panicked.
+pub const PVPANIC_PANICKED: u8 = 1 << 0;
+/// The guest kernel panicked, and a crash kernel is loaded to handle the panic.
+pub const PVPANIC_CRASH_LOADED: u8 = 1 << 1;
+
+/// pvpanic device, with which the guest reports its kernel panics.
+///
+/// The guest reads the events supported by the device from its single register, and writes the
+/// events it reports to it. This is the register of the `pvpanic-mmio` device of QEMU, which the
+/// Linux `pvpanic-mmio` driver (`CONFIG_PVPANIC_MMIO`) discovers through ACPI or the device tree.
+#[derive(Debug)]
+pub struct PvPanic {
+    // Notifies the VMM thread of the panics, to snapshot the microVM.
+    panic_evt: Option<EventFd>,
+}
+
+impl PvPanic {
+    /// Creates a pvpanic device, signaling `panic_evt` when the guest panics.
+    pub fn new(panic_evt: Option<EventFd>) -> Self {
+        PvPanic { panic_evt }
+    }
+
+    pub fn bus_read(&mut self, offset: u64, data: &mut [u8]) {
+        data.fill(0);
+        if offset == 0 {
+            if let Some(events) = data.first_mut() {
+                *events = PVPANIC_PANICKED | PVPANIC_CRASH_LOADED;
+            }
+        }
+    }
+
+    pub fn bus_write(&mut self, offset: u64, data: &[u8]) {
+        // Only handle the events written to the register.
+        let Some(events) = data.first().filter(|_| offset == 0) else {
+            return;
+        };
+        if events & (PVPANIC_PANICKED | PVPANIC_CRASH_LOADED) == 0 {
+            return;
+        }
+
+        let crash_loaded = events & PVPANIC_CRASH_LOADED != 0;
+        error!("The guest kernel panicked (crash kernel loaded: {crash_loaded}).");
+        METRICS.vmm.guest_panics.inc();
+        event_stream::emit(VmEvent::GuestPanic { crash_loaded });
+        if let Some(panic_evt) = &self.panic_evt {
+            if let Err(err) = panic_evt.write(1) {
+                error!("Failed to signal the guest panic: {err}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pvpanic() {
+        let panic_evt = EventFd::new(libc::EFD_NONBLOCK).unwrap();
+        let mut pvpanic = PvPanic::new(Some(panic_evt.try_clone().unwrap()));
+
+        let mut data = [0xff; 2];
+        pvpanic.bus_read(0, &mut data);
+        assert_eq!(data, [PVPANIC_PANICKED | PVPANIC_CRASH_LOADED, 0]);
+        pvpanic.bus_read(1, &mut data);
+        assert_eq!(data, [0, 0]);
+
+        // Unknown events and other offsets are ignored.
+        pvpanic.bus_write(0, &[1 << 2]);
+        pvpanic.bus_write(1, &[PVPANIC_PANICKED]);
+        pvpanic.bus_write(0, &[]);
+        panic_evt.read().unwrap_err();
+
+        let panics = METRICS.vmm.guest_panics.count();
+        pvpanic.bus_write(0, &[PVPANIC_PANICKED]);
+        assert_eq!(panic_evt.read().unwrap(), 1);
+        pvpanic.bus_write(0, &[PVPANIC_CRASH_LOADED]);
+        assert_eq!(panic_evt.read().unwrap(), 1);
+        assert!(METRICS.vmm.guest_panics.count() >= panics + 2);
+
+        // Without a snapshot on panic, the panics are only reported.
+        let mut pvpanic = PvPanic::new(None);
+        pvpanic.bus_write(0, &[PVPANIC_PANICKED]);
+    }
+}
@@ -0,0 +1,275 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use log::{error, warn};
+
+use super::swtpm::{Swtpm, TPM_HEADER_SIZE};
+use crate::utils::usize_to_u64;
+
+/// Size of the MMIO region of the CRB interface: the registers of locality 0 followed by the
+/// command and response buffer.
+pub const TPM_CRB_MMIO_SIZE: u64 = 0x1000;
+/// Offset of the control area, referenced by the TPM2 ACPI table.
+pub const TPM_CRB_CONTROL_AREA_OFFSET: u64 = CRB_CTRL_REQ;
+
+// Registers of locality 0, as defined in the TCG PC Client Platform TPM Profile specification.
+const CRB_LOC_STATE: u64 = 0x00;
+const CRB_LOC_CTRL: u64 = 0x08;
+const CRB_LOC_STS: u64 = 0x0c;
+const CRB_INTF_ID: u64 = 0x30;
+const CRB_INTF_ID2: u64 = 0x34;
+const CRB_CTRL_REQ: u64 = 0x40;
+const CRB_CTRL_STS: u64 = 0x44;
+const CRB_CTRL_CANCEL: u64 = 0x48;
+const CRB_CTRL_START: u64 = 0x4c;
+const CRB_INT_ENABLE: u64 = 0x50;
+const CRB_CTRL_CMD_SIZE: u64 = 0x58;
+const CRB_CTRL_CMD_LADDR: u64 = 0x5c;
+const CRB_CTRL_CMD_HADDR: u64 = 0x60;
+const CRB_CTRL_RSP_SIZE: u64 = 0x64;
+const CRB_CTRL_RSP_ADDR: u64 = 0x68;
+const CRB_CTRL_RSP_HADDR: u64 = 0x6c;
+const CRB_DATA_BUFFER: u64 = 0x80;
+
+const CRB_BUFFER_SIZE: usize = 0x1000 - 0x80;
+
+const CRB_LOC_STATE_LOC_ASSIGNED: u32 = 1 << 1;
+const CRB_LOC_STATE_REG_VALID_STS: u32 = 1 << 7;
+const CRB_LOC_CTRL_REQUEST_ACCESS: u32 = 1;
+const CRB_LOC_CTRL_RELINQUISH: u32 = 1 << 1;
+const CRB_LOC_CTRL_SEIZE: u32 = 1 << 2;
+const CRB_LOC_STS_GRANTED: u32 = 1;
+const CRB_CTRL_REQ_CMD_READY: u32 = 1;
+const CRB_CTRL_REQ_GO_IDLE: u32 = 1 << 1;
+const CRB_CTRL_STS_TPM_IDLE: u32 = 1 << 1;
+const CRB_CTRL_START_INVOKE: u32 = 1;
+
+// CRB interface, version 1, supporting 64 bytes transfers and locality 0 only.
+const CRB_INTF_ID_VALUE: u32 = 1 | 1 << 4 | 3 << 11 | 1 << 14 | 1 << 17;
+// Vendor and device IDs, the same as the ones of the QEMU CRB device.
+const CRB_INTF_ID2_VALUE: u32 = 0x1014 | 1 << 16;
+
+// `TPM_ST_NO_SESSIONS` tag and `TPM_RC_FAILURE` response code of the TPM 2.0 specification.
+const TPM_ST_NO_SESSIONS: u16 = 0x8001;
+const TPM_RC_FAILURE: u32 = 0x101;
+
+/// TPM 2.0 device with a Command Response Buffer (CRB) interface, backed by swtpm.
+///
+/// Only locality 0 is implemented. The commands are executed synchronously, when the guest
+/// writes the start register, so the vCPU is blocked while swtpm processes them.
+#[derive(Debug)]
+pub struct TpmCrb {
+    swtpm: Swtpm,
+    base: u64,
+    loc_assigned: bool,
+    idle: bool,
+    cancel: u32,
+    int_enable: u32,
+    buffer: Vec<u8>,
+}
+
+impl TpmCrb {
+    /// Creates a CRB device at the `base` guest physical address, backed by `swtpm`.
+    pub fn new(swtpm: Swtpm, base: u64) -> Self {
+        TpmCrb {
+            swtpm,
+            base,
+            loc_assigned: false,
+            idle: true,
+            cancel: 0,
+            int_enable: 0,
+            buffer: vec![0; CRB_BUFFER_SIZE],
+        }
+    }
+
+    /// swtpm backing the device.
+    pub fn swtpm(&self) -> &Swtpm {
+        &self.swtpm
+    }
+
+    fn register(&self, offset: u64) -> u32 {
+        let buffer_addr = self.base + CRB_DATA_BUFFER;
+        match offset {
+            CRB_LOC_STATE if self.loc_assigned => {
+                CRB_LOC_STATE_REG_VALID_STS | CRB_LOC_STATE_LOC_ASSIGNED
+            }
+            CRB_LOC_STATE => CRB_LOC_STATE_REG_VALID_STS,
+            CRB_LOC_STS if self.loc_assigned => CRB_LOC_STS_GRANTED,
+            CRB_INTF_ID => CRB_INTF_ID_VALUE,
+            CRB_INTF_ID2 => CRB_INTF_ID2_VALUE,
+            CRB_CTRL_STS if self.idle => CRB_CTRL_STS_TPM_IDLE,
+            CRB_CTRL_CANCEL => self.cancel,
+            CRB_INT_ENABLE => self.int_enable,
+            CRB_CTRL_CMD_SIZE | CRB_CTRL_RSP_SIZE => u32::try_from(CRB_BUFFER_SIZE).unwrap(),
+            CRB_CTRL_CMD_LADDR | CRB_CTRL_RSP_ADDR => {
+                u32::try_from(buffer_addr & 0xffff_ffff).unwrap()
+            }
+            CRB_CTRL_CMD_HADDR | CRB_CTRL_RSP_HADDR => u32::try_from(buffer_addr >> 32).unwrap(),
+            _ => 0,
+        }
+    }
+
+    fn write_register(&mut self, offset: u64, value: u32) {
+        match offset {
+            CRB_LOC_CTRL => {
+                if value & (CRB_LOC_CTRL_REQUEST_ACCESS | CRB_LOC_CTRL_SEIZE) != 0 {
+                    self.loc_assigned = true;
+                }
+                if value & CRB_LOC_CTRL_RELINQUISH != 0 {
+                    self.loc_assigned = false;
+                }
+            }
+            CRB_CTRL_REQ => {
+                if value & CRB_CTRL_REQ_CMD_READY != 0 {
+                    self.idle = false;
+                }
+                if value & CRB_CTRL_REQ_GO_IDLE != 0 {
+                    self.idle = true;
+                }
+            }
+            CRB_CTRL_CANCEL => self.cancel = value,
+            CRB_CTRL_START if value & CRB_CTRL_START_INVOKE != 0 => {
+                if self.idle {
+                    warn!("tpm: command started while the TPM is idle");
+                } else {
+                    self.execute();
+                }
+            }
+            CRB_CTRL_START => (),
+            CRB_INT_ENABLE => self.int_enable = value,
+            _ => warn!("tpm: write to read-only register at offset {offset:#x}"),
+        }
+    }
+
+    fn execute(&mut self) {
+        let size = u32::from_be_bytes(self.buffer[2..6].try_into().unwrap());
+        let size = usize::try_from(size).unwrap();
+        if !(TPM_HEADER_SIZE..=CRB_BUFFER_SIZE).contains(&size) {
+            warn!("tpm: invalid command size: {size}");
+            self.write_failure();
+            return;
+        }
+        let command = self.buffer[..size].to_vec();
+        if let Err(err) = self.swtpm.execute(&command, &mut self.buffer) {
+            error!("tpm: cannot execute command: {err}");
+            self.write_failure();
+        }
+    }
+
+    // Answers the command with a failure response, so that the guest does not wait for it.
+    fn write_failure(&mut self) {
+        self.buffer[..2].copy_from_slice(&TPM_ST_NO_SESSIONS.to_be_bytes());
+        self.buffer[2..6].copy_from_slice(&u32::try_from(TPM_HEADER_SIZE).unwrap().to_be_bytes());
+        self.buffer[6..10].copy_from_slice(&TPM_RC_FAILURE.to_be_bytes());
+    }
+
+    pub fn bus_read(&mut self, offset: u64, data: &mut [u8]) {
+        let len = usize_to_u64(data.len());
+        if offset >= CRB_DATA_BUFFER && offset + len <= TPM_CRB_MMIO_SIZE {
+            let start = usize::try_from(offset - CRB_DATA_BUFFER).unwrap();
+            data.copy_from_slice(&self.buffer[start..start + data.len()]);
+        } else if offset + len <= CRB_DATA_BUFFER {
+            // Registers may be read with any access size, e.g. the 64-bit response buffer
+            // address is read as a whole.
+            for (i, byte) in data.iter_mut().enumerate() {
+                let byte_offset = offset + usize_to_u64(i);
+                let register = self.register(byte_offset & !3);
+                *byte = register.to_le_bytes()[usize::try_from(byte_offset & 3).unwrap()];
+            }
+        } else {
+            warn!("tpm: invalid read at offset {offset:#x} of {len} bytes");
+        }
+    }
+
+    pub fn bus_write(&mut self, offset: u64, data: &[u8]) {
+        let len = usize_to_u64(data.len());
+        if offset >= CRB_DATA_BUFFER && offset + len <= TPM_CRB_MMIO_SIZE {
+            let start = usize::try_from(offset - CRB_DATA_BUFFER).unwrap();
+            self.buffer[start..start + data.len()].copy_from_slice(data);
+        } else if data.len() == 4 && offset % 4 == 0 && offset < CRB_DATA_BUFFER {
+            self.write_register(offset, u32::from_le_bytes(data.try_into().unwrap()));
+        } else {
+            warn!("tpm: invalid write at offset {offset:#x} of {len} bytes");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use vmm_sys_util::tempdir::TempDir;
+
+    use super::*;
+    use crate::devices::tpm::swtpm::tests::fake_swtpm;
+
+    const BASE: u64 = 0x1_fed4_0000;
+
+    fn read_u32(crb: &mut TpmCrb, offset: u64) -> u32 {
+        let mut data = [0u8; 4];
+        crb.bus_read(offset, &mut data);
+        u32::from_le_bytes(data)
+    }
+
+    fn write_u32(crb: &mut TpmCrb, offset: u64, value: u32) {
+        crb.bus_write(offset, &value.to_le_bytes());
+    }
+
+    #[test]
+    fn test_tpm_crb() {
+        let dir = TempDir::new().unwrap();
+        let (socket, _) = fake_swtpm(&dir, 1 | 1 << 12);
+        let mut crb = TpmCrb::new(Swtpm::connect(&socket, true).unwrap(), BASE);
+
+        assert_eq!(read_u32(&mut crb, CRB_INTF_ID), CRB_INTF_ID_VALUE);
+        assert_eq!(read_u32(&mut crb, CRB_CTRL_CMD_LADDR), 0xfed4_0080);
+        assert_eq!(read_u32(&mut crb, CRB_CTRL_CMD_HADDR), 1);
+        let mut rsp_addr = [0u8; 8];
+        crb.bus_read(CRB_CTRL_RSP_ADDR, &mut rsp_addr);
+        assert_eq!(u64::from_le_bytes(rsp_addr), BASE + CRB_DATA_BUFFER);
+        assert_eq!(read_u32(&mut crb, CRB_CTRL_RSP_SIZE), 0xf80);
+
+        // Request the locality.
+        assert_eq!(
+            read_u32(&mut crb, CRB_LOC_STATE),
+            CRB_LOC_STATE_REG_VALID_STS
+        );
+        write_u32(&mut crb, CRB_LOC_CTRL, CRB_LOC_CTRL_REQUEST_ACCESS);
+        assert_eq!(
+            read_u32(&mut crb, CRB_LOC_STATE),
+            CRB_LOC_STATE_REG_VALID_STS | CRB_LOC_STATE_LOC_ASSIGNED
+        );
+        assert_eq!(read_u32(&mut crb, CRB_LOC_STS), CRB_LOC_STS_GRANTED);
+
+        // Commands are ignored while the TPM is idle.
+        let command = [0x80, 0x01, 0, 0, 0, 12, 0, 0, 0x01, 0x44, 0, 0];
+        crb.bus_write(CRB_DATA_BUFFER, &command);
+        assert_eq!(read_u32(&mut crb, CRB_CTRL_STS), CRB_CTRL_STS_TPM_IDLE);
+        write_u32(&mut crb, CRB_CTRL_START, CRB_CTRL_START_INVOKE);
+        let mut response = [0u8; 14];
+        crb.bus_read(CRB_DATA_BUFFER, &mut response);
+        assert_eq!(response[..12], command);
+
+        write_u32(&mut crb, CRB_CTRL_REQ, CRB_CTRL_REQ_CMD_READY);
+        assert_eq!(read_u32(&mut crb, CRB_CTRL_STS), 0);
+        write_u32(&mut crb, CRB_CTRL_START, CRB_CTRL_START_INVOKE);
+        assert_eq!(read_u32(&mut crb, CRB_CTRL_START), 0);
+        crb.bus_read(CRB_DATA_BUFFER, &mut response);
+        assert_eq!(
+            response,
+            [0x80, 0x01, 0, 0, 0, 14, 0, 0, 0, 0, 0, 0, 0x01, 0x44]
+        );
+
+        // Commands with an invalid size fail.
+        crb.bus_write(CRB_DATA_BUFFER + 2, &[0, 0, 0x10, 0]);
+        write_u32(&mut crb, CRB_CTRL_START, CRB_CTRL_START_INVOKE);
+        crb.bus_read(CRB_DATA_BUFFER, &mut response[..10]);
+        assert_eq!(response[..10], [0x80, 0x01, 0, 0, 0, 10, 0, 0, 0x01, 0x01]);
+
+        write_u32(&mut crb, CRB_CTRL_REQ, CRB_CTRL_REQ_GO_IDLE);
+        write_u32(&mut crb, CRB_LOC_CTRL, CRB_LOC_CTRL_RELINQUISH);
+        assert_eq!(
+            read_u32(&mut crb, CRB_LOC_STATE),
+            CRB_LOC_STATE_REG_VALID_STS
+        );
+        assert_eq!(read_u32(&mut crb, CRB_CTRL_STS), CRB_CTRL_STS_TPM_IDLE);
+    }
+}
@@ -0,0 +1,9 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Implements a TPM 2.0 device backed by the swtpm TPM emulator.
+mod crb;
+mod swtpm;
+
+pub use self::crb::{TpmCrb, TPM_CRB_CONTROL_AREA_OFFSET, TPM_CRB_MMIO_SIZE};
+pub use self::swtpm::{Swtpm, SwtpmError};
@@ -0,0 +1,234 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::io::{self, Read, Write};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use log::debug;
+use vmm_sys_util::sock_ctrl_msg::ScmSocket;
+
+// Commands of the swtpm control channel, as defined in swtpm's `tpm_ioctl.h`.
+const CMD_GET_CAPABILITY: u32 = 1;
+const CMD_INIT: u32 = 2;
+const CMD_SET_DATAFD: u32 = 16;
+
+// Capabilities reported by `CMD_GET_CAPABILITY`.
+const PTM_CAP_INIT: u64 = 1;
+const PTM_CAP_SET_DATAFD: u64 = 1 << 12;
+
+/// Size of the header of TPM 2.0 commands and responses: tag (2 bytes), size (4 bytes) and
+/// command or response code (4 bytes).
+pub const TPM_HEADER_SIZE: usize = 10;
+
+// swtpm answers immediately, except while generating keys, which can take a few seconds.
+const IO_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Errors triggered when communicating with swtpm.
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum SwtpmError {
+    /// Cannot connect to the swtpm control socket {0:?}: {1}
+    Connect(PathBuf, io::Error),
+    /// Cannot communicate with swtpm: {0}
+    Io(#[from] io::Error),
+    /// Cannot pass the data channel to swtpm: {0}
+    SendFd(vmm_sys_util::errno::Error),
+    /// swtpm does not support the required control commands, capabilities: {0:#x}
+    Unsupported(u64),
+    /// swtpm failed control command {0} with result {1:#x}
+    Command(u32, u32),
+    /// Invalid TPM response size: {0}
+    InvalidResponseSize(usize),
+}
+
+/// Client of an swtpm TPM emulator, started with a Unix control socket, e.g. with
+/// `swtpm socket --tpm2 --ctrl type=unixio,path=<socket>`.
+///
+/// The TPM commands are exchanged over a socket pair whose other end is passed to swtpm through
+/// the control channel.
+#[derive(Debug)]
+pub struct Swtpm {
+    socket: PathBuf,
+    ctrl: UnixStream,
+    data: UnixStream,
+}
+
+impl Swtpm {
+    /// Connects to swtpm through its control socket and sets up the data channel.
+    ///
+    /// `init` initializes the TPM, as on a cold boot. It is not set when restoring a snapshot,
+    /// so that the TPM state held by swtpm is kept.
+    pub fn connect(socket: &Path, init: bool) -> Result<Self, SwtpmError> {
+        debug!("tpm: connecting to swtpm at {}", socket.display());
+        let ctrl = UnixStream::connect(socket)
+            .map_err(|err| SwtpmError::Connect(socket.to_path_buf(), err))?;
+        ctrl.set_read_timeout(Some(IO_TIMEOUT))?;
+        let (data, remote) = UnixStream::pair()?;
+        data.set_read_timeout(Some(IO_TIMEOUT))?;
+
+        let mut swtpm = Swtpm {
+            socket: socket.to_path_buf(),
+            ctrl,
+            data,
+        };
+
+        swtpm.ctrl.write_all(&CMD_GET_CAPABILITY.to_be_bytes())?;
+        let mut caps = [0u8; 8];
+        swtpm.ctrl.read_exact(&mut caps)?;
+        let caps = u64::from_be_bytes(caps);
+        if caps & (PTM_CAP_INIT | PTM_CAP_SET_DATAFD) != PTM_CAP_INIT | PTM_CAP_SET_DATAFD {
+            return Err(SwtpmError::Unsupported(caps));
+        }
+
+        swtpm
+            .ctrl
+            .send_with_fd(&CMD_SET_DATAFD.to_be_bytes()[..], remote.as_raw_fd())
+            .map_err(SwtpmError::SendFd)?;
+        swtpm.read_result(CMD_SET_DATAFD)?;
+
+        if init {
+            let mut command = CMD_INIT.to_be_bytes().to_vec();
+            // No init flags: the volatile state of a previous run is used, if any.
+            command.extend_from_slice(&0u32.to_be_bytes());
+            swtpm.ctrl.write_all(&command)?;
+            swtpm.read_result(CMD_INIT)?;
+        }
+
+        Ok(swtpm)
+    }
+
+    /// Path of the swtpm control socket.
+    pub fn socket(&self) -> &Path {
+        &self.socket
+    }
+
+    /// Executes a TPM command, and writes the response in `response`. Returns the size of the
+    /// response.
+    pub fn execute(&mut self, command: &[u8], response: &mut [u8]) -> Result<usize, SwtpmError> {
+        self.data.write_all(command)?;
+        self.data.read_exact(&mut response[..TPM_HEADER_SIZE])?;
+        let size = u32::from_be_bytes(response[2..6].try_into().unwrap());
+        let size = usize::try_from(size).unwrap();
+        if !(TPM_HEADER_SIZE..=response.len()).contains(&size) {
+            return Err(SwtpmError::InvalidResponseSize(size));
+        }
+        self.data.read_exact(&mut response[TPM_HEADER_SIZE..size])?;
+        Ok(size)
+    }
+
+    fn read_result(&mut self, command: u32) -> Result<(), SwtpmError> {
+        let mut result = [0u8; 4];
+        self.ctrl.read_exact(&mut result)?;
+        match u32::from_be_bytes(result) {
+            0 => Ok(()),
+            result => Err(SwtpmError::Command(command, result)),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::os::fd::OwnedFd;
+    use std::os::unix::net::UnixListener;
+    use std::thread::JoinHandle;
+
+    use vmm_sys_util::tempdir::TempDir;
+
+    use super::*;
+
+    // Answers every TPM command with a response holding the command code.
+    fn serve_data(mut data: UnixStream) {
+        let mut header = [0u8; TPM_HEADER_SIZE];
+        while data.read_exact(&mut header).is_ok() {
+            let size = u32::from_be_bytes(header[2..6].try_into().unwrap());
+            let mut body = vec![0u8; usize::try_from(size).unwrap() - TPM_HEADER_SIZE];
+            data.read_exact(&mut body).unwrap();
+            let mut response = vec![0x80, 0x01, 0, 0, 0, 14, 0, 0, 0, 0];
+            response.extend_from_slice(&header[6..]);
+            data.write_all(&response).unwrap();
+        }
+    }
+
+    /// Starts a fake swtpm reporting the `caps` capabilities, and returns the path of its
+    /// control socket. The thread returns the control commands it received.
+    pub(crate) fn fake_swtpm(dir: &TempDir, caps: u64) -> (PathBuf, JoinHandle<Vec<u32>>) {
+        let socket = dir.as_path().join("swtpm.sock");
+        let listener = UnixListener::bind(&socket).unwrap();
+        let handle = std::thread::spawn(move || {
+            let (mut ctrl, _) = listener.accept().unwrap();
+            let mut commands = Vec::new();
+            loop {
+                let mut command = [0u8; 4];
+                let (len, fd) = ctrl.recv_with_fd(&mut command[..]).unwrap();
+                if len == 0 {
+                    return commands;
+                }
+                let command = u32::from_be_bytes(command);
+                commands.push(command);
+                match command {
+                    CMD_GET_CAPABILITY => ctrl.write_all(&caps.to_be_bytes()).unwrap(),
+                    CMD_INIT => {
+                        ctrl.read_exact(&mut [0u8; 4]).unwrap();
+                        ctrl.write_all(&0u32.to_be_bytes()).unwrap();
+                    }
+                    CMD_SET_DATAFD => {
+                        let data = UnixStream::from(OwnedFd::from(fd.unwrap()));
+                        std::thread::spawn(move || serve_data(data));
+                        ctrl.write_all(&0u32.to_be_bytes()).unwrap();
+                    }
+                    _ => ctrl.write_all(&1u32.to_be_bytes()).unwrap(),
+                }
+            }
+        });
+        (socket, handle)
+    }
+
+    #[test]
+    fn test_connect() {
+        let dir = TempDir::new().unwrap();
+        Swtpm::connect(&dir.as_path().join("swtpm.sock"), true).unwrap_err();
+
+        let (socket, handle) = fake_swtpm(&dir, PTM_CAP_INIT);
+        assert!(matches!(
+            Swtpm::connect(&socket, true),
+            Err(SwtpmError::Unsupported(PTM_CAP_INIT))
+        ));
+        assert_eq!(handle.join().unwrap(), vec![CMD_GET_CAPABILITY]);
+
+        std::fs::remove_file(&socket).unwrap();
+        let (socket, handle) = fake_swtpm(&dir, PTM_CAP_INIT | PTM_CAP_SET_DATAFD);
+        drop(Swtpm::connect(&socket, false).unwrap());
+        assert_eq!(
+            handle.join().unwrap(),
+            vec![CMD_GET_CAPABILITY, CMD_SET_DATAFD]
+        );
+    }
+
+    #[test]
+    fn test_execute() {
+        let dir = TempDir::new().unwrap();
+        let (socket, handle) = fake_swtpm(&dir, PTM_CAP_INIT | PTM_CAP_SET_DATAFD);
+        let mut swtpm = Swtpm::connect(&socket, true).unwrap();
+        assert_eq!(swtpm.socket(), socket);
+
+        let command = [0x80, 0x01, 0, 0, 0, 12, 0, 0, 0x01, 0x44, 0, 0];
+        let mut response = [0u8; 32];
+        assert_eq!(swtpm.execute(&command, &mut response).unwrap(), 14);
+        assert_eq!(&response[10..14], &[0, 0, 0x01, 0x44]);
+
+        // The response does not fit in the buffer.
+        let mut response = [0u8; 12];
+        assert!(matches!(
+            swtpm.execute(&command, &mut response),
+            Err(SwtpmError::InvalidResponseSize(14))
+        ));
+
+        drop(swtpm);
+        assert_eq!(
+            handle.join().unwrap(),
+            vec![CMD_GET_CAPABILITY, CMD_SET_DATAFD, CMD_INIT]
+        );
+    }
+}
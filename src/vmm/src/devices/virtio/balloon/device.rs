@@ -2,11 +2,15 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use std::fmt;
+use std::io::{self, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
-use log::error;
-use serde::Serialize;
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
 use timerfd::{ClockId, SetTimeFlags, TimerFd, TimerState};
+use utils::time::{get_time_us, ClockType};
 use vmm_sys_util::eventfd::EventFd;
 
 use super::super::device::{DeviceState, VirtioDevice};
@@ -15,18 +19,19 @@ use super::super::{ActivateError, TYPE_BALLOON};
 use super::metrics::METRICS;
 use super::util::{compact_page_frame_numbers, remove_range};
 use super::{
-    BALLOON_DEV_ID, BALLOON_NUM_QUEUES, BALLOON_QUEUE_SIZES, DEFLATE_INDEX, INFLATE_INDEX,
-    MAX_PAGES_IN_DESC, MAX_PAGE_COMPACT_BUFFER, MIB_TO_4K_PAGES, STATS_INDEX,
-    VIRTIO_BALLOON_F_DEFLATE_ON_OOM, VIRTIO_BALLOON_F_STATS_VQ, VIRTIO_BALLOON_PFN_SHIFT,
-    VIRTIO_BALLOON_S_AVAIL, VIRTIO_BALLOON_S_CACHES, VIRTIO_BALLOON_S_HTLB_PGALLOC,
-    VIRTIO_BALLOON_S_HTLB_PGFAIL, VIRTIO_BALLOON_S_MAJFLT, VIRTIO_BALLOON_S_MEMFREE,
-    VIRTIO_BALLOON_S_MEMTOT, VIRTIO_BALLOON_S_MINFLT, VIRTIO_BALLOON_S_SWAP_IN,
-    VIRTIO_BALLOON_S_SWAP_OUT,
+    AUTO_TARGET_MIN_STEP_MIB, BALLOON_DEV_ID, BALLOON_NUM_QUEUES, BALLOON_QUEUE_SIZES,
+    DEFLATE_INDEX, INFLATE_INDEX, MAX_PAGES_IN_DESC, MAX_PAGE_COMPACT_BUFFER, MIB_TO_4K_PAGES,
+    STATS_INDEX, VIRTIO_BALLOON_F_DEFLATE_ON_OOM, VIRTIO_BALLOON_F_STATS_VQ,
+    VIRTIO_BALLOON_PFN_SHIFT, VIRTIO_BALLOON_S_AVAIL, VIRTIO_BALLOON_S_CACHES,
+    VIRTIO_BALLOON_S_HTLB_PGALLOC, VIRTIO_BALLOON_S_HTLB_PGFAIL, VIRTIO_BALLOON_S_MAJFLT,
+    VIRTIO_BALLOON_S_MEMFREE, VIRTIO_BALLOON_S_MEMTOT, VIRTIO_BALLOON_S_MINFLT,
+    VIRTIO_BALLOON_S_SWAP_IN, VIRTIO_BALLOON_S_SWAP_OUT,
 };
 use crate::devices::virtio::balloon::BalloonError;
 use crate::devices::virtio::device::{IrqTrigger, IrqType};
 use crate::devices::virtio::gen::virtio_blk::VIRTIO_F_VERSION_1;
-use crate::logger::IncMetric;
+use crate::event_stream::{self, VmEvent};
+use crate::logger::{IncMetric, StoreMetric};
 use crate::utils::u64_to_usize;
 use crate::vstate::memory::{Address, ByteValued, Bytes, GuestAddress, GuestMemoryMmap};
 
@@ -74,10 +79,49 @@ pub struct BalloonConfig {
     pub deflate_on_oom: bool,
     /// Interval of time in seconds at which the balloon statistics are updated.
     pub stats_polling_interval_s: u16,
+    /// Destinations to which the balloon statistics are pushed.
+    pub stats_push: Option<BalloonStatsPushConfig>,
+    /// Bounds within which the target size is adjusted automatically.
+    pub auto_target: Option<BalloonAutoTargetConfig>,
+}
+
+/// Bounds within which Firecracker adjusts the target size of the balloon each time the driver
+/// reports the statistics, so that the guest keeps `available_mib` of memory available.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct BalloonAutoTargetConfig {
+    /// Minimum target size of the balloon, in MiB.
+    pub min_mib: u32,
+    /// Maximum target size of the balloon, in MiB.
+    pub max_mib: u32,
+    /// Memory the guest should keep available, in MiB.
+    pub available_mib: u32,
+}
+
+/// Destinations to which the balloon statistics are pushed each time the driver reports them,
+/// i.e. every statistics polling interval.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct BalloonStatsPushConfig {
+    /// Whether the statistics are recorded in the balloon metrics, which are then flushed.
+    #[serde(default)]
+    pub metrics: bool,
+    /// Path of a Unix socket to which the statistics are written, one JSON object per line.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub socket_path: Option<PathBuf>,
+}
+
+/// Balloon statistics written to the socket of [`BalloonStatsPushConfig`].
+#[derive(Debug, Serialize)]
+struct PushedBalloonStats<'a> {
+    /// Wall clock time at which the statistics were reported, in microseconds.
+    timestamp_us: u64,
+    #[serde(flatten)]
+    stats: &'a BalloonStats,
 }
 
 /// BalloonStats holds statistics returned from the stats_queue.
-#[derive(Clone, Default, Debug, PartialEq, Eq, Serialize)]
+#[derive(Clone, Default, Debug, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct BalloonStats {
     /// The target size of the balloon, in 4K pages.
@@ -171,6 +215,10 @@ pub struct Balloon {
     // it is acknowledged after the stats queue is processed.
     pub(crate) stats_desc_index: Option<u16>,
     pub(crate) latest_stats: BalloonStats,
+    pub(crate) stats_push: Option<BalloonStatsPushConfig>,
+    // Connection to the socket the statistics are pushed to, opened on the first push.
+    pub(crate) stats_socket: Option<UnixStream>,
+    pub(crate) auto_target: Option<BalloonAutoTargetConfig>,
     // A buffer used as pfn accumulator during descriptor processing.
     pub(crate) pfn_buffer: [u32; MAX_PAGE_COMPACT_BUFFER],
 }
@@ -193,6 +241,9 @@ impl fmt::Debug for Balloon {
             .field("stats_polling_interval_s", &self.stats_polling_interval_s)
             .field("stats_desc_index", &self.stats_desc_index)
             .field("latest_stats", &self.latest_stats)
+            .field("stats_push", &self.stats_push)
+            .field("stats_socket", &self.stats_socket)
+            .field("auto_target", &self.auto_target)
             .field("pfn_buffer", &self.pfn_buffer)
             .finish()
     }
@@ -250,6 +301,9 @@ impl Balloon {
             stats_timer,
             stats_desc_index: None,
             latest_stats: BalloonStats::default(),
+            stats_push: None,
+            stats_socket: None,
+            auto_target: None,
             pfn_buffer: [0u32; MAX_PAGE_COMPACT_BUFFER],
         })
     }
@@ -374,12 +428,25 @@ impl Balloon {
 
         let queue = &mut self.queues[DEFLATE_INDEX];
         let mut needs_interrupt = false;
+        let mut deflated_pages: usize = 0;
 
         while let Some(head) = queue.pop() {
+            deflated_pages += head.len as usize / SIZE_OF_U32;
             queue.add_used(head.index, 0).map_err(BalloonError::Queue)?;
             needs_interrupt = true;
         }
 
+        // The guest updates the actual size once the host acknowledged the deflation, so the
+        // guest deflating while the balloon is not above its target can only be due to OOM.
+        if deflated_pages > 0
+            && self.deflate_on_oom()
+            && self.config_space.num_pages >= self.config_space.actual_pages
+        {
+            event_stream::emit(VmEvent::BalloonOomDeflate {
+                pages: u32::try_from(deflated_pages).unwrap_or(u32::MAX),
+            });
+        }
+
         if needs_interrupt {
             self.signal_used_queue()
         } else {
@@ -420,11 +487,119 @@ impl Balloon {
             }
 
             self.stats_desc_index = Some(head.index);
+            self.push_stats();
+            self.adjust_target();
         }
 
         Ok(())
     }
 
+    /// Pushes the latest statistics to the destinations of [`BalloonStatsPushConfig`].
+    fn push_stats(&mut self) {
+        let Some(push) = self.stats_push.clone() else {
+            return;
+        };
+        let Some(stats) = self.latest_stats().cloned() else {
+            return;
+        };
+
+        if push.metrics {
+            METRICS.target_mib.store(u64::from(stats.target_mib));
+            METRICS.actual_mib.store(u64::from(stats.actual_mib));
+            let gauges = [
+                (&METRICS.free_memory, stats.free_memory),
+                (&METRICS.available_memory, stats.available_memory),
+                (&METRICS.total_memory, stats.total_memory),
+                (&METRICS.disk_caches, stats.disk_caches),
+                (&METRICS.major_faults, stats.major_faults),
+                (&METRICS.minor_faults, stats.minor_faults),
+                (&METRICS.swap_in, stats.swap_in),
+                (&METRICS.swap_out, stats.swap_out),
+            ];
+            for (gauge, value) in gauges {
+                if let Some(value) = value {
+                    gauge.store(value);
+                }
+            }
+            if let Err(err) = crate::logger::METRICS.write() {
+                METRICS.stats_push_fails.inc();
+                warn!("balloon: failed to flush the metrics with the statistics: {err}");
+            }
+        }
+
+        if let Some(path) = push.socket_path.as_deref() {
+            if let Err(err) = self.push_stats_to_socket(path, &stats) {
+                METRICS.stats_push_fails.inc();
+                warn!("balloon: failed to push the statistics to {path:?}: {err}");
+            }
+        }
+    }
+
+    /// Adjusts the target size of the balloon to the memory the guest reports as available,
+    /// within the bounds of [`BalloonAutoTargetConfig`].
+    fn adjust_target(&mut self) {
+        let Some(auto_target) = self.auto_target.clone() else {
+            return;
+        };
+        let Some(available) = self
+            .latest_stats
+            .available_memory
+            .or(self.latest_stats.free_memory)
+        else {
+            return;
+        };
+
+        // The guest has the memory it reports as available on top of the memory the balloon
+        // currently holds, so the balloon can hold all of it but `available_mib`.
+        let target_mib = (u64::from(pages_to_mib(self.config_space.actual_pages))
+            + (available >> 20))
+            .saturating_sub(u64::from(auto_target.available_mib))
+            .clamp(
+                u64::from(auto_target.min_mib),
+                u64::from(auto_target.max_mib),
+            );
+        // Cannot fail, as the target is bounded by `max_mib`.
+        let target_mib = u32::try_from(target_mib).unwrap();
+
+        let current_mib = self.size_mb();
+        let at_bound = target_mib == auto_target.min_mib || target_mib == auto_target.max_mib;
+        if target_mib == current_mib
+            || (target_mib.abs_diff(current_mib) < AUTO_TARGET_MIN_STEP_MIB && !at_bound)
+        {
+            return;
+        }
+        match self.update_size(target_mib) {
+            Ok(()) => METRICS.auto_target_updates.inc(),
+            Err(err) => {
+                METRICS.event_fails.inc();
+                error!("balloon: failed to adjust the target size to {target_mib} MiB: {err}");
+            }
+        }
+    }
+
+    fn push_stats_to_socket(&mut self, path: &Path, stats: &BalloonStats) -> io::Result<()> {
+        let mut line = serde_json::to_vec(&PushedBalloonStats {
+            timestamp_us: get_time_us(ClockType::Real),
+            stats,
+        })?;
+        line.push(b'\n');
+
+        let mut socket = match self.stats_socket.take() {
+            Some(socket) => socket,
+            None => {
+                let socket = UnixStream::connect(path)?;
+                // A slow reader must not stall the device.
+                socket.set_nonblocking(true)?;
+                socket
+            }
+        };
+        // On failure, the connection is closed and reopened on the next push, so that a
+        // partially written line does not corrupt the following ones.
+        socket.write_all(&line)?;
+        self.stats_socket = Some(socket);
+        Ok(())
+    }
+
     pub(crate) fn signal_used_queue(&self) -> Result<(), BalloonError> {
         self.irq_trigger.trigger_irq(IrqType::Vring).map_err(|err| {
             METRICS.event_fails.inc();
@@ -505,6 +680,11 @@ impl Balloon {
         pages_to_mib(self.config_space.num_pages)
     }
 
+    /// Obtain the size of the pages the guest currently gave to the device in MIB.
+    pub fn actual_mb(&self) -> u32 {
+        pages_to_mib(self.config_space.actual_pages)
+    }
+
     pub fn deflate_on_oom(&self) -> bool {
         self.avail_features & (1u64 << VIRTIO_BALLOON_F_DEFLATE_ON_OOM) != 0
     }
@@ -532,9 +712,37 @@ impl Balloon {
             amount_mib: self.size_mb(),
             deflate_on_oom: self.deflate_on_oom(),
             stats_polling_interval_s: self.stats_polling_interval_s(),
+            stats_push: self.stats_push.clone(),
+            auto_target: self.auto_target.clone(),
         }
     }
 
+    /// Sets the bounds within which the target size is adjusted automatically, or stops
+    /// adjusting it if `None`.
+    pub fn set_auto_target(
+        &mut self,
+        auto_target: Option<BalloonAutoTargetConfig>,
+    ) -> Result<(), BalloonError> {
+        if let Some(auto_target) = &auto_target {
+            if !self.stats_enabled() {
+                return Err(BalloonError::StatisticsDisabled);
+            }
+            if auto_target.min_mib > auto_target.max_mib {
+                return Err(BalloonError::InvalidAutoTarget);
+            }
+            mib_to_pages(auto_target.max_mib)?;
+        }
+        self.auto_target = auto_target;
+        Ok(())
+    }
+
+    /// Sets the destinations to which the statistics are pushed, closing the connection to the
+    /// previous socket.
+    pub fn set_stats_push(&mut self, stats_push: Option<BalloonStatsPushConfig>) {
+        self.stats_push = stats_push;
+        self.stats_socket = None;
+    }
+
     pub(crate) fn stats_enabled(&self) -> bool {
         self.stats_polling_interval_s > 0
     }
@@ -757,6 +965,8 @@ pub(crate) mod tests {
             amount_mib: 16,
             deflate_on_oom: true,
             stats_polling_interval_s: 0,
+            stats_push: None,
+            auto_target: None,
         };
         assert_eq!(balloon.config(), cfg);
 
@@ -1144,4 +1354,95 @@ pub(crate) mod tests {
         assert_eq!(balloon.num_pages(), 0x1122_3344);
         assert_eq!(balloon.actual_pages(), 0x1234_5678);
     }
+
+    #[test]
+    fn test_push_stats_to_socket() {
+        use std::io::{BufRead, BufReader};
+        use std::os::unix::net::UnixListener;
+
+        use vmm_sys_util::tempdir::TempDir;
+
+        let dir = TempDir::new().unwrap();
+        let path = dir.as_path().join("stats.sock");
+        let listener = UnixListener::bind(&path).unwrap();
+
+        let mut balloon = Balloon::new(0, true, 1, false).unwrap();
+        balloon.latest_stats.free_memory = Some(0x1000);
+        balloon.set_stats_push(Some(BalloonStatsPushConfig {
+            metrics: false,
+            socket_path: Some(path),
+        }));
+        balloon.push_stats();
+        balloon.push_stats();
+
+        let (stream, _) = listener.accept().unwrap();
+        let mut lines = BufReader::new(stream).lines();
+        for _ in 0..2 {
+            let line: serde_json::Value =
+                serde_json::from_str(&lines.next().unwrap().unwrap()).unwrap();
+            assert!(line["timestamp_us"].as_u64().unwrap() > 0);
+            assert_eq!(line["free_memory"], 0x1000);
+            assert!(line.get("swap_in").is_none());
+        }
+        // The connection is kept between the pushes.
+        assert!(balloon.stats_socket.is_some());
+    }
+
+    #[test]
+    fn test_adjust_target() {
+        let auto_target = BalloonAutoTargetConfig {
+            min_mib: 16,
+            max_mib: 512,
+            available_mib: 256,
+        };
+        let mut balloon = Balloon::new(0, true, 0, false).unwrap();
+        assert!(matches!(
+            balloon.set_auto_target(Some(auto_target.clone())),
+            Err(BalloonError::StatisticsDisabled)
+        ));
+
+        let mut balloon = Balloon::new(0, true, 1, false).unwrap();
+        balloon.device_state = DeviceState::Activated(single_region_mem(0x1));
+        let invalid_auto_target = BalloonAutoTargetConfig {
+            min_mib: 1024,
+            ..auto_target.clone()
+        };
+        assert!(matches!(
+            balloon.set_auto_target(Some(invalid_auto_target)),
+            Err(BalloonError::InvalidAutoTarget)
+        ));
+        balloon.set_auto_target(Some(auto_target)).unwrap();
+
+        // The target is not adjusted until the guest reports its available memory.
+        balloon.adjust_target();
+        assert_eq!(balloon.size_mb(), 0);
+
+        balloon.latest_stats.available_memory = Some(400 << 20);
+        balloon.adjust_target();
+        assert_eq!(balloon.size_mb(), 144);
+
+        // The memory already held by the balloon is accounted for.
+        balloon.update_actual_pages(100 * MIB_TO_4K_PAGES);
+        balloon.latest_stats.available_memory = Some(300 << 20);
+        balloon.adjust_target();
+        assert_eq!(balloon.size_mb(), 144);
+
+        // Small changes are ignored.
+        balloon.latest_stats.available_memory = Some(310 << 20);
+        balloon.adjust_target();
+        assert_eq!(balloon.size_mb(), 144);
+
+        // The target is kept within bounds.
+        balloon.latest_stats.available_memory = Some(100 << 20);
+        balloon.adjust_target();
+        assert_eq!(balloon.size_mb(), 16);
+        balloon.latest_stats.available_memory = Some(2000 << 20);
+        balloon.adjust_target();
+        assert_eq!(balloon.size_mb(), 512);
+
+        balloon.set_auto_target(None).unwrap();
+        balloon.latest_stats.available_memory = Some(100 << 20);
+        balloon.adjust_target();
+        assert_eq!(balloon.size_mb(), 512);
+    }
 }
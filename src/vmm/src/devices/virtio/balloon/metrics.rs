@@ -29,14 +29,17 @@
 //!   FirecrackerDeviceMetrics.
 //! * Rely on `serde` to provide the actual serialization for writing the metrics.
 //!
-//! The system implements 1 type of metrics:
+//! The system implements 2 types of metrics:
 //! * Shared Incremental Metrics (SharedIncMetrics) - dedicated for the metrics which need a counter
 //!   (i.e the number of times an API request failed). These metrics are reset upon flush.
+//! * Shared Store Metrics (SharedStoreMetrics) - dedicated for the metrics which need to record the
+//!   last value (i.e the free memory reported by the guest). These are only updated when the
+//!   balloon statistics are pushed to the metrics.
 
 use serde::ser::SerializeMap;
 use serde::{Serialize, Serializer};
 
-use crate::logger::SharedIncMetric;
+use crate::logger::{SharedIncMetric, SharedStoreMetric};
 
 /// Stores aggregated balloon metrics
 pub(super) static METRICS: BalloonDeviceMetrics = BalloonDeviceMetrics::new();
@@ -63,6 +66,30 @@ pub(super) struct BalloonDeviceMetrics {
     pub deflate_count: SharedIncMetric,
     /// Number of times when handling events on a balloon device failed.
     pub event_fails: SharedIncMetric,
+    /// Number of times the balloon statistics could not be pushed.
+    pub stats_push_fails: SharedIncMetric,
+    /// Number of times the target size of the balloon was adjusted automatically.
+    pub auto_target_updates: SharedIncMetric,
+    /// Target size of the balloon, in MiB, when the statistics were last pushed.
+    pub target_mib: SharedStoreMetric,
+    /// Size of the balloon, in MiB, when the statistics were last pushed.
+    pub actual_mib: SharedStoreMetric,
+    /// Last free memory reported by the guest, in bytes.
+    pub free_memory: SharedStoreMetric,
+    /// Last available memory reported by the guest, in bytes.
+    pub available_memory: SharedStoreMetric,
+    /// Last total memory reported by the guest, in bytes.
+    pub total_memory: SharedStoreMetric,
+    /// Last amount of disk caches reported by the guest, in bytes.
+    pub disk_caches: SharedStoreMetric,
+    /// Last number of major faults reported by the guest.
+    pub major_faults: SharedStoreMetric,
+    /// Last number of minor faults reported by the guest.
+    pub minor_faults: SharedStoreMetric,
+    /// Last amount of memory swapped in reported by the guest.
+    pub swap_in: SharedStoreMetric,
+    /// Last amount of memory swapped out reported by the guest.
+    pub swap_out: SharedStoreMetric,
 }
 impl BalloonDeviceMetrics {
     /// Const default construction.
@@ -74,6 +101,18 @@ impl BalloonDeviceMetrics {
             stats_update_fails: SharedIncMetric::new(),
             deflate_count: SharedIncMetric::new(),
             event_fails: SharedIncMetric::new(),
+            stats_push_fails: SharedIncMetric::new(),
+            auto_target_updates: SharedIncMetric::new(),
+            target_mib: SharedStoreMetric::new(),
+            actual_mib: SharedStoreMetric::new(),
+            free_memory: SharedStoreMetric::new(),
+            available_memory: SharedStoreMetric::new(),
+            total_memory: SharedStoreMetric::new(),
+            disk_caches: SharedStoreMetric::new(),
+            major_faults: SharedStoreMetric::new(),
+            minor_faults: SharedStoreMetric::new(),
+            swap_in: SharedStoreMetric::new(),
+            swap_out: SharedStoreMetric::new(),
         }
     }
 }
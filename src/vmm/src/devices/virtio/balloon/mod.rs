@@ -13,7 +13,9 @@ mod util;
 use log::error;
 use vm_memory::GuestMemoryError;
 
-pub use self::device::{Balloon, BalloonConfig, BalloonStats};
+pub use self::device::{
+    Balloon, BalloonAutoTargetConfig, BalloonConfig, BalloonStats, BalloonStatsPushConfig,
+};
 use super::queue::QueueError;
 use crate::devices::virtio::balloon::metrics::METRICS;
 use crate::devices::virtio::queue::FIRECRACKER_MAX_QUEUE_SIZE;
@@ -48,6 +50,9 @@ pub const INFLATE_INDEX: usize = 0;
 pub const DEFLATE_INDEX: usize = 1;
 /// The index of the deflate queue from Balloon device queues/queues_evts vector.
 pub const STATS_INDEX: usize = 2;
+/// Smallest change of the target size of the balloon, in MiB, made when adjusting it
+/// automatically, unless the target reaches one of its bounds.
+pub const AUTO_TARGET_MIN_STEP_MIB: u32 = 16;
 
 // The feature bitmap for virtio balloon.
 const VIRTIO_BALLOON_F_STATS_VQ: u32 = 1; // Enable statistics.
@@ -86,6 +91,8 @@ pub enum BalloonError {
     MalformedPayload,
     /// Error restoring the balloon device queues.
     QueueRestoreError,
+    /// The minimum target size of the balloon is greater than its maximum.
+    InvalidAutoTarget,
     /// Received stats querry when stats are disabled.
     StatisticsDisabled,
     /// Statistics cannot be enabled/disabled after activation.
@@ -69,9 +69,29 @@ impl Block {
         }
     }
 
+    pub fn pause(&mut self) -> Result<(), BlockError> {
+        match self {
+            Self::Virtio(b) => {
+                b.pause();
+                Ok(())
+            }
+            Self::VhostUser(_) => Err(BlockError::InvalidBlockBackend),
+        }
+    }
+
+    pub fn resume(&mut self) -> Result<(), BlockError> {
+        match self {
+            Self::Virtio(b) => {
+                b.resume();
+                Ok(())
+            }
+            Self::VhostUser(_) => Err(BlockError::InvalidBlockBackend),
+        }
+    }
+
     pub fn update_config(&mut self) -> Result<(), BlockError> {
         match self {
-            Self::Virtio(_) => Err(BlockError::InvalidBlockBackend),
+            Self::Virtio(b) => b.update_disk_size().map_err(BlockError::VirtioBackend),
             Self::VhostUser(b) => b.config_update().map_err(BlockError::VhostUserBackend),
         }
     }
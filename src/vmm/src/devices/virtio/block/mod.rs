@@ -15,11 +15,34 @@ pub mod virtio;
 pub enum CacheType {
     /// Flushing mechanic not will be advertised to the guest driver
     #[default]
+    #[serde(alias = "unsafe")]
     Unsafe,
     /// Flushing mechanic will be advertised to the guest driver and
     /// flush requests coming from the guest will be performed using
     /// `fsync`.
+    #[serde(alias = "writeback")]
     Writeback,
+    /// The backing file is opened with `O_DSYNC`, so that writes are
+    /// completed only once they reached the disk. Flushing mechanic
+    /// will not be advertised to the guest driver.
+    #[serde(alias = "writethrough")]
+    Writethrough,
+    /// Like `Writethrough`, with the backing file also opened with
+    /// `O_DIRECT`, bypassing the host page cache.
+    #[serde(alias = "directsync")]
+    Directsync,
+}
+
+impl CacheType {
+    /// Whether flush requests are advertised to the guest driver.
+    pub fn advertises_flush(&self) -> bool {
+        *self == CacheType::Writeback
+    }
+
+    /// Whether the writes reach the disk before being completed.
+    pub fn is_write_through(&self) -> bool {
+        matches!(self, CacheType::Writethrough | CacheType::Directsync)
+    }
 }
 
 /// Errors the block device can trigger.
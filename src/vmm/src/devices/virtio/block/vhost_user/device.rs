@@ -71,6 +71,12 @@ impl TryFrom<&BlockDeviceConfig> for VhostUserBlockConfig {
             && value.path_on_host.is_none()
             && value.rate_limiter.is_none()
             && value.file_engine_type.is_none()
+            && value.remote.is_none()
+            && value.direct_io.is_none()
+            && value.backend.is_none()
+            && value.latency_injection.is_none()
+            && value.shared_rate_limiter.is_none()
+            && !value.cache_type.is_write_through()
         {
             Ok(Self {
                 drive_id: value.drive_id.clone(),
@@ -98,6 +104,11 @@ impl From<VhostUserBlockConfig> for BlockDeviceConfig {
             path_on_host: None,
             rate_limiter: None,
             file_engine_type: None,
+            remote: None,
+            direct_io: None,
+            backend: None,
+            latency_injection: None,
+            shared_rate_limiter: None,
 
             socket: Some(value.socket),
         }
@@ -392,11 +403,30 @@ mod tests {
             path_on_host: None,
             rate_limiter: None,
             file_engine_type: None,
+            remote: None,
+            direct_io: None,
+            backend: None,
+            latency_injection: None,
+            shared_rate_limiter: None,
 
             socket: Some("sock".to_string()),
         };
         VhostUserBlockConfig::try_from(&block_config).unwrap();
 
+        // The host page cache of vhost-user backends is not controlled by Firecracker.
+        for (cache_type, direct_io) in [
+            (CacheType::Writethrough, None),
+            (CacheType::Directsync, None),
+            (CacheType::Unsafe, Some(true)),
+        ] {
+            let block_config = BlockDeviceConfig {
+                cache_type,
+                direct_io,
+                ..block_config.clone()
+            };
+            VhostUserBlockConfig::try_from(&block_config).unwrap_err();
+        }
+
         let block_config = BlockDeviceConfig {
             drive_id: "".to_string(),
             partuuid: None,
@@ -407,6 +437,11 @@ mod tests {
             path_on_host: Some("path".to_string()),
             rate_limiter: None,
             file_engine_type: Some(FileEngineType::Sync),
+            remote: None,
+            direct_io: None,
+            backend: None,
+            latency_injection: None,
+            shared_rate_limiter: None,
 
             socket: None,
         };
@@ -422,6 +457,11 @@ mod tests {
             path_on_host: Some("path".to_string()),
             rate_limiter: None,
             file_engine_type: Some(FileEngineType::Sync),
+            remote: None,
+            direct_io: None,
+            backend: None,
+            latency_injection: None,
+            shared_rate_limiter: None,
 
             socket: Some("sock".to_string()),
         };
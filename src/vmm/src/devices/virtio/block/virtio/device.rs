@@ -10,19 +10,23 @@ use std::convert::From;
 use std::fs::{File, OpenOptions};
 use std::io::{Seek, SeekFrom, Write};
 use std::os::linux::fs::MetadataExt;
+use std::os::unix::fs::OpenOptionsExt;
 use std::path::PathBuf;
 use std::sync::Arc;
 
-use block_io::FileEngine;
+use block_io::{FileEngine, NbdEngine};
 use serde::{Deserialize, Serialize};
 use vmm_sys_util::eventfd::EventFd;
 
 use super::io::async_io;
+use super::io::remote::RemoteImage;
+use super::latency_injection::LatencyInjector;
 use super::request::*;
 use super::{
     io as block_io, VirtioBlockError, BLOCK_CONFIG_SPACE_SIZE, BLOCK_QUEUE_SIZES, SECTOR_SHIFT,
     SECTOR_SIZE,
 };
+use crate::devices::error_events::{DeviceErrorClass, DeviceErrorReporter};
 use crate::devices::virtio::block::virtio::metrics::{BlockDeviceMetrics, BlockMetricsPerDevice};
 use crate::devices::virtio::block::CacheType;
 use crate::devices::virtio::device::{DeviceState, IrqTrigger, IrqType, VirtioDevice};
@@ -32,10 +36,11 @@ use crate::devices::virtio::gen::virtio_blk::{
 use crate::devices::virtio::gen::virtio_ring::VIRTIO_RING_F_EVENT_IDX;
 use crate::devices::virtio::queue::Queue;
 use crate::devices::virtio::{ActivateError, TYPE_BLOCK};
+use crate::event_stream::{self, VmEvent};
 use crate::logger::{error, warn, IncMetric};
-use crate::rate_limiter::{BucketUpdate, RateLimiter};
+use crate::rate_limiter::{shared, BucketUpdate, RateLimiter};
 use crate::utils::u64_to_usize;
-use crate::vmm_config::drive::BlockDeviceConfig;
+use crate::vmm_config::drive::{BlockDeviceConfig, LatencyInjectionConfig, RemoteDriveConfig};
 use crate::vmm_config::RateLimiterConfig;
 use crate::vstate::memory::GuestMemoryMmap;
 
@@ -49,6 +54,17 @@ pub enum FileEngineType {
     Sync,
 }
 
+/// The backend of the block device, from which its image is read.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DriveBackend {
+    /// The image is a file on the host, at `path_on_host`.
+    #[default]
+    File,
+    /// The image is an export of an NBD server, located by the NBD URI in `path_on_host`.
+    Nbd,
+}
+
 /// Helper object for setting up all `Block` fields derived from its backing file.
 #[derive(Debug)]
 pub struct DiskProperties {
@@ -56,20 +72,43 @@ pub struct DiskProperties {
     pub file_engine: FileEngine<PendingRequest>,
     pub nsectors: u64,
     pub image_id: [u8; VIRTIO_BLK_ID_BYTES as usize],
+    /// Remote image whose chunks are fetched in the backing file on first access.
+    pub remote: Option<RemoteImage>,
+    /// Flags, such as `O_DIRECT`, the backing file is opened with.
+    pub open_flags: i32,
 }
 
 impl DiskProperties {
+    /// Flags the backing file is opened with, given the cache type of the drive and whether
+    /// the host page cache is bypassed. Both the sync and async engines honor them, as they
+    /// are set on the file itself.
+    pub fn open_flags(cache_type: CacheType, direct_io: bool) -> i32 {
+        let mut flags = 0;
+        if direct_io || cache_type == CacheType::Directsync {
+            flags |= libc::O_DIRECT;
+        }
+        if cache_type.is_write_through() {
+            flags |= libc::O_DSYNC;
+        }
+        flags
+    }
+
     // Helper function that opens the file with the proper access permissions
-    fn open_file(disk_image_path: &str, is_disk_read_only: bool) -> Result<File, VirtioBlockError> {
+    fn open_file(
+        disk_image_path: &str,
+        is_disk_read_only: bool,
+        open_flags: i32,
+    ) -> Result<File, VirtioBlockError> {
         OpenOptions::new()
             .read(true)
             .write(!is_disk_read_only)
+            .custom_flags(open_flags)
             .open(PathBuf::from(&disk_image_path))
             .map_err(|x| VirtioBlockError::BackingFile(x, disk_image_path.to_string()))
     }
 
     // Helper function that gets the size of the file
-    fn file_size(disk_image_path: &str, disk_image: &mut File) -> Result<u64, VirtioBlockError> {
+    fn file_size(disk_image_path: &str, mut disk_image: &File) -> Result<u64, VirtioBlockError> {
         let disk_size = disk_image
             .seek(SeekFrom::End(0))
             .map_err(|x| VirtioBlockError::BackingFile(x, disk_image_path.to_string()))?;
@@ -87,14 +126,47 @@ impl DiskProperties {
         Ok(disk_size)
     }
 
-    /// Create a new file for the block device using a FileEngine
+    /// Create a new file for the block device using a FileEngine. If the disk image is remote,
+    /// the file is its cache, and is created if needed. With the NBD backend, the disk image
+    /// path is the NBD URI of the export.
     pub fn new(
         disk_image_path: String,
         is_disk_read_only: bool,
+        open_flags: i32,
         file_engine_type: FileEngineType,
+        backend: DriveBackend,
+        remote: Option<RemoteDriveConfig>,
     ) -> Result<Self, VirtioBlockError> {
-        let mut disk_image = Self::open_file(&disk_image_path, is_disk_read_only)?;
-        let disk_size = Self::file_size(&disk_image_path, &mut disk_image)?;
+        if backend == DriveBackend::Nbd {
+            if remote.is_some() {
+                return Err(VirtioBlockError::NbdUnsupported("remote images"));
+            }
+            if open_flags != 0 {
+                return Err(VirtioBlockError::NbdUnsupported(
+                    "direct I/O and write-through cache types",
+                ));
+            }
+            if file_engine_type == FileEngineType::Async {
+                return Err(VirtioBlockError::NbdUnsupported("the Async IO engine"));
+            }
+            let engine = NbdEngine::connect(&disk_image_path, is_disk_read_only)
+                .map_err(VirtioBlockError::Nbd)?;
+            return Ok(Self {
+                file_path: disk_image_path,
+                nsectors: engine.size() >> SECTOR_SHIFT,
+                image_id: Self::build_nbd_image_id(&engine),
+                file_engine: FileEngine::Nbd(engine),
+                remote: None,
+                open_flags,
+            });
+        }
+
+        let remote = remote
+            .map(|config| RemoteImage::open(&disk_image_path, config))
+            .transpose()
+            .map_err(VirtioBlockError::RemoteImage)?;
+        let disk_image = Self::open_file(&disk_image_path, is_disk_read_only, open_flags)?;
+        let disk_size = Self::file_size(&disk_image_path, &disk_image)?;
         let image_id = Self::build_disk_image_id(&disk_image);
 
         Ok(Self {
@@ -103,6 +175,8 @@ impl DiskProperties {
                 .map_err(VirtioBlockError::FileEngine)?,
             nsectors: disk_size >> SECTOR_SHIFT,
             image_id,
+            remote,
+            open_flags,
         })
     }
 
@@ -112,8 +186,18 @@ impl DiskProperties {
         disk_image_path: String,
         is_disk_read_only: bool,
     ) -> Result<(), VirtioBlockError> {
-        let mut disk_image = Self::open_file(&disk_image_path, is_disk_read_only)?;
-        let disk_size = Self::file_size(&disk_image_path, &mut disk_image)?;
+        if let FileEngine::Nbd(_) = self.file_engine {
+            let engine = NbdEngine::connect(&disk_image_path, is_disk_read_only)
+                .map_err(VirtioBlockError::Nbd)?;
+            self.image_id = Self::build_nbd_image_id(&engine);
+            self.nsectors = engine.size() >> SECTOR_SHIFT;
+            self.file_engine = FileEngine::Nbd(engine);
+            self.file_path = disk_image_path;
+            return Ok(());
+        }
+
+        let disk_image = Self::open_file(&disk_image_path, is_disk_read_only, self.open_flags)?;
+        let disk_size = Self::file_size(&disk_image_path, &disk_image)?;
 
         self.image_id = Self::build_disk_image_id(&disk_image);
         self.file_engine
@@ -121,10 +205,35 @@ impl DiskProperties {
             .map_err(VirtioBlockError::FileEngine)?;
         self.nsectors = disk_size >> SECTOR_SHIFT;
         self.file_path = disk_image_path;
+        // The new backing file is a regular, fully present, disk image.
+        self.remote = None;
+
+        Ok(())
+    }
 
+    /// Re-read the size of the file backing the block device, e.g. after it was grown on the
+    /// host. NBD exports are reconnected, as their size is only sent on connection.
+    pub fn refresh_size(&mut self, is_disk_read_only: bool) -> Result<(), VirtioBlockError> {
+        // The size of a remote image is the one of the remote image, not of its cache.
+        if self.remote.is_some() {
+            return Err(VirtioBlockError::RemoteImageResize);
+        }
+        let Some(disk_image) = self.file_engine.file() else {
+            return self.update(self.file_path.clone(), is_disk_read_only);
+        };
+        let disk_size = Self::file_size(&self.file_path, disk_image)?;
+        self.nsectors = disk_size >> SECTOR_SHIFT;
         Ok(())
     }
 
+    /// Returns the backend of the block device.
+    pub fn backend(&self) -> DriveBackend {
+        match self.file_engine {
+            FileEngine::Nbd(_) => DriveBackend::Nbd,
+            FileEngine::Sync(_) | FileEngine::Async(_) => DriveBackend::File,
+        }
+    }
+
     fn build_device_id(disk_file: &File) -> Result<String, VirtioBlockError> {
         let blk_metadata = disk_file
             .metadata()
@@ -156,6 +265,15 @@ impl DiskProperties {
         default_id
     }
 
+    // NBD exports are identified by their name, as they have no file metadata.
+    fn build_nbd_image_id(engine: &NbdEngine) -> [u8; VIRTIO_BLK_ID_BYTES as usize] {
+        let mut id = [0; VIRTIO_BLK_ID_BYTES as usize];
+        let name = engine.export_name().as_bytes();
+        let bytes_to_copy = cmp::min(name.len(), VIRTIO_BLK_ID_BYTES as usize);
+        id[..bytes_to_copy].copy_from_slice(&name[..bytes_to_copy]);
+        id
+    }
+
     /// Provides vec containing the virtio block configuration space
     /// buffer. The config space is populated with the disk size based
     /// on the backing file size.
@@ -182,8 +300,8 @@ pub struct VirtioBlockConfig {
     /// Setting this flag to true will mount the block device in the
     /// guest under /dev/vda unless the partuuid is present.
     pub is_root_device: bool,
-    /// If set to true, the drive will ignore flush requests coming from
-    /// the guest driver.
+    /// Caching strategy of the drive. With `Unsafe`, the drive ignores flush
+    /// requests coming from the guest driver.
     #[serde(default)]
     pub cache_type: CacheType,
 
@@ -198,6 +316,21 @@ pub struct VirtioBlockConfig {
     #[serde(default)]
     #[serde(rename = "io_engine")]
     pub file_engine_type: FileEngineType,
+    /// Remote image lazily fetched in the backing file, used as a cache.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote: Option<RemoteDriveConfig>,
+    /// If set to true, the drive is opened with `O_DIRECT`, bypassing the host page cache.
+    #[serde(default)]
+    pub direct_io: bool,
+    /// The backend from which the image of the drive is read.
+    #[serde(default)]
+    pub backend: DriveBackend,
+    /// Latency and errors injected in the requests of the drive.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency_injection: Option<LatencyInjectionConfig>,
+    /// Id of the shared rate limiter the drive consumes the budget of.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shared_rate_limiter: Option<String>,
 }
 
 impl TryFrom<&BlockDeviceConfig> for VirtioBlockConfig {
@@ -215,6 +348,11 @@ impl TryFrom<&BlockDeviceConfig> for VirtioBlockConfig {
                 path_on_host: value.path_on_host.as_ref().unwrap().clone(),
                 rate_limiter: value.rate_limiter,
                 file_engine_type: value.file_engine_type.unwrap_or_default(),
+                remote: value.remote.clone(),
+                direct_io: value.direct_io.unwrap_or(false),
+                backend: value.backend.unwrap_or_default(),
+                latency_injection: value.latency_injection,
+                shared_rate_limiter: value.shared_rate_limiter.clone(),
             })
         } else {
             Err(VirtioBlockError::Config)
@@ -234,6 +372,12 @@ impl From<VirtioBlockConfig> for BlockDeviceConfig {
             path_on_host: Some(value.path_on_host),
             rate_limiter: value.rate_limiter,
             file_engine_type: Some(value.file_engine_type),
+            remote: value.remote,
+            // Only shown in the configuration when not the default.
+            direct_io: value.direct_io.then_some(true),
+            backend: (value.backend != DriveBackend::File).then_some(value.backend),
+            latency_injection: value.latency_injection,
+            shared_rate_limiter: value.shared_rate_limiter,
 
             socket: None,
         }
@@ -261,19 +405,24 @@ pub struct VirtioBlock {
     pub cache_type: CacheType,
     pub root_device: bool,
     pub read_only: bool,
+    pub direct_io: bool,
 
     // Host file and properties.
     pub disk: DiskProperties,
     pub rate_limiter: RateLimiter,
     pub is_io_engine_throttled: bool,
+    pub is_paused: bool,
+    /// Injects latency and errors in the requests, for fault testing.
+    pub latency_injector: Option<LatencyInjector>,
     pub metrics: Arc<BlockDeviceMetrics>,
+    pub error_reporter: DeviceErrorReporter,
 }
 
 macro_rules! unwrap_async_file_engine_or_return {
     ($file_engine: expr) => {
         match $file_engine {
             FileEngine::Async(engine) => engine,
-            FileEngine::Sync(_) => {
+            FileEngine::Sync(_) | FileEngine::Nbd(_) => {
                 error!("The block device doesn't use an async IO engine");
                 return;
             }
@@ -289,19 +438,32 @@ impl VirtioBlock {
         let disk_properties = DiskProperties::new(
             config.path_on_host,
             config.is_read_only,
+            DiskProperties::open_flags(config.cache_type, config.direct_io),
             config.file_engine_type,
+            config.backend,
+            config.remote,
         )?;
 
-        let rate_limiter = config
+        let mut rate_limiter: RateLimiter = config
             .rate_limiter
             .map(RateLimiterConfig::try_into)
             .transpose()
             .map_err(VirtioBlockError::RateLimiter)?
             .unwrap_or_default();
+        if let Some(id) = config.shared_rate_limiter {
+            let shared = shared::get(&id).ok_or(VirtioBlockError::SharedRateLimiterNotFound(id))?;
+            rate_limiter.set_shared(Some(shared));
+        }
+
+        let latency_injector = config
+            .latency_injection
+            .map(LatencyInjector::new)
+            .transpose()
+            .map_err(VirtioBlockError::LatencyInjection)?;
 
         let mut avail_features = (1u64 << VIRTIO_F_VERSION_1) | (1u64 << VIRTIO_RING_F_EVENT_IDX);
 
-        if config.cache_type == CacheType::Writeback {
+        if config.cache_type.advertises_flush() {
             avail_features |= 1u64 << VIRTIO_BLK_F_FLUSH;
         }
 
@@ -329,10 +491,14 @@ impl VirtioBlock {
             cache_type: config.cache_type,
             root_device: config.is_root_device,
             read_only: config.is_read_only,
+            direct_io: config.direct_io,
 
             disk: disk_properties,
             rate_limiter,
             is_io_engine_throttled: false,
+            is_paused: false,
+            latency_injector,
+            error_reporter: DeviceErrorReporter::new(format!("block_{}", config.drive_id)),
             metrics: BlockMetricsPerDevice::alloc(config.drive_id),
         })
     }
@@ -349,6 +515,18 @@ impl VirtioBlock {
             cache_type: self.cache_type,
             rate_limiter: rl.into_option(),
             file_engine_type: self.file_engine_type(),
+            remote: self
+                .disk
+                .remote
+                .as_ref()
+                .map(|remote| remote.config().clone()),
+            direct_io: self.direct_io,
+            backend: self.disk.backend(),
+            latency_injection: self
+                .latency_injector
+                .as_ref()
+                .map(|injector| *injector.config()),
+            shared_rate_limiter: self.rate_limiter.shared_id(),
         }
     }
 
@@ -361,11 +539,12 @@ impl VirtioBlock {
         if let Err(err) = self.queue_evts[0].read() {
             error!("Failed to get queue event: {:?}", err);
             self.metrics.event_fails.inc();
+            self.error_reporter.report_io(DeviceErrorClass::Vmm, &err);
         } else if self.rate_limiter.is_blocked() {
             self.metrics.rate_limiter_throttled_events.inc();
         } else if self.is_io_engine_throttled {
             self.metrics.io_engine_throttled_events.inc();
-        } else {
+        } else if !self.is_paused {
             self.process_virtio_queues();
         }
     }
@@ -379,7 +558,7 @@ impl VirtioBlock {
         self.metrics.rate_limiter_event_count.inc();
         // Upon rate limiter event, call the rate limiter handler
         // and restart processing the queue.
-        if self.rate_limiter.event_handler().is_ok() {
+        if self.rate_limiter.event_handler().is_ok() && !self.is_paused {
             self.process_queue(0);
         }
     }
@@ -402,6 +581,49 @@ impl VirtioBlock {
         }
     }
 
+    // Holds a completed request back when latency is injected, returning it otherwise.
+    fn delay_request(
+        latency_injector: &mut Option<LatencyInjector>,
+        block_metrics: &BlockDeviceMetrics,
+        finished: FinishedRequest,
+    ) -> Option<FinishedRequest> {
+        let Some(injector) = latency_injector else {
+            return Some(finished);
+        };
+        let finished = injector.delay(finished);
+        if finished.is_none() {
+            block_metrics.injected_delays.inc();
+        }
+        finished
+    }
+
+    // Returns the completed requests held back by the latency injection to the guest, either
+    // the ones whose delay elapsed, or all of them.
+    fn release_delayed_requests(&mut self, all: bool) {
+        let Some(injector) = self.latency_injector.as_mut() else {
+            return;
+        };
+        let released = if all {
+            injector.drain()
+        } else {
+            injector.pop_due()
+        };
+        let queue = &mut self.queues[0];
+        for finished in released {
+            Self::add_used_descriptor(
+                queue,
+                finished.desc_idx,
+                finished.num_bytes_to_mem,
+                &self.irq_trigger,
+                &self.metrics,
+            );
+        }
+    }
+
+    pub(crate) fn process_latency_injection_event(&mut self) {
+        self.release_delayed_requests(false);
+    }
+
     /// Device specific function for peaking inside a queue and processing descriptors.
     pub fn process_queue(&mut self, queue_index: usize) {
         // This is safe since we checked in the event handler that the device is activated.
@@ -419,15 +641,40 @@ impl VirtioBlock {
                         // avail ring, for later processing.
                         queue.undo_pop();
                         self.metrics.rate_limiter_throttled_events.inc();
+                        event_stream::emit(VmEvent::RateLimited {
+                            device: format!("block_{}", self.id),
+                            operation: "io",
+                        });
                         break;
                     }
 
                     used_any = true;
-                    request.process(&mut self.disk, head.index, mem, &self.metrics)
+                    if self
+                        .latency_injector
+                        .as_ref()
+                        .is_some_and(LatencyInjector::should_fail)
+                    {
+                        self.metrics.injected_errors.inc();
+                        ProcessingResult::Executed(request.fail(
+                            head.index,
+                            mem,
+                            &self.metrics,
+                            &self.error_reporter,
+                        ))
+                    } else {
+                        request.process(
+                            &mut self.disk,
+                            head.index,
+                            mem,
+                            &self.metrics,
+                            &self.error_reporter,
+                        )
+                    }
                 }
                 Err(err) => {
                     error!("Failed to parse available descriptor chain: {:?}", err);
                     self.metrics.execute_fails.inc();
+                    self.error_reporter.report(DeviceErrorClass::Guest, None);
                     ProcessingResult::Executed(FinishedRequest {
                         num_bytes_to_mem: 0,
                         desc_idx: head.index,
@@ -443,13 +690,17 @@ impl VirtioBlock {
                     break;
                 }
                 ProcessingResult::Executed(finished) => {
-                    Self::add_used_descriptor(
-                        queue,
-                        head.index,
-                        finished.num_bytes_to_mem,
-                        &self.irq_trigger,
-                        &self.metrics,
-                    );
+                    if let Some(finished) =
+                        Self::delay_request(&mut self.latency_injector, &self.metrics, finished)
+                    {
+                        Self::add_used_descriptor(
+                            queue,
+                            finished.desc_idx,
+                            finished.num_bytes_to_mem,
+                            &self.irq_trigger,
+                            &self.metrics,
+                        );
+                    }
                 }
             }
         }
@@ -492,15 +743,19 @@ impl VirtioBlock {
                             ))),
                         ),
                     };
-                    let finished = pending.finish(mem, res, &self.metrics);
-
-                    Self::add_used_descriptor(
-                        queue,
-                        finished.desc_idx,
-                        finished.num_bytes_to_mem,
-                        &self.irq_trigger,
-                        &self.metrics,
-                    );
+                    let finished = pending.finish(mem, res, &self.metrics, &self.error_reporter);
+
+                    if let Some(finished) =
+                        Self::delay_request(&mut self.latency_injector, &self.metrics, finished)
+                    {
+                        Self::add_used_descriptor(
+                            queue,
+                            finished.desc_idx,
+                            finished.num_bytes_to_mem,
+                            &self.irq_trigger,
+                            &self.metrics,
+                        );
+                    }
                 }
             }
         }
@@ -514,7 +769,7 @@ impl VirtioBlock {
         } else {
             self.process_async_completion_queue();
 
-            if self.is_io_engine_throttled {
+            if self.is_io_engine_throttled && !self.is_paused {
                 self.is_io_engine_throttled = false;
                 self.process_queue(0);
             }
@@ -533,6 +788,21 @@ impl VirtioBlock {
         Ok(())
     }
 
+    /// Re-read the size of the backing file, and notify the driver if it changed.
+    pub fn update_disk_size(&mut self) -> Result<(), VirtioBlockError> {
+        let nsectors = self.disk.nsectors;
+        self.disk.refresh_size(self.read_only)?;
+        if self.disk.nsectors != nsectors {
+            self.config_space = self.disk.virtio_block_config_space();
+
+            // Kick the driver to pick up the new capacity.
+            self.irq_trigger.trigger_irq(IrqType::Config).unwrap();
+        }
+
+        self.metrics.update_count.inc();
+        Ok(())
+    }
+
     /// Updates the parameters for the rate limiter
     pub fn update_rate_limiter(&mut self, bytes: BucketUpdate, ops: BucketUpdate) {
         self.rate_limiter.update_buckets(bytes, ops);
@@ -541,7 +811,8 @@ impl VirtioBlock {
     /// Retrieve the file engine type.
     pub fn file_engine_type(&self) -> FileEngineType {
         match self.disk.file_engine {
-            FileEngine::Sync(_) => FileEngineType::Sync,
+            // NBD requests are performed synchronously.
+            FileEngine::Sync(_) | FileEngine::Nbd(_) => FileEngineType::Sync,
             FileEngine::Async(_) => FileEngineType::Async,
         }
     }
@@ -552,15 +823,45 @@ impl VirtioBlock {
         }
     }
 
+    // Completes the requests in flight and flushes the data to the backing file.
+    fn complete_in_flight_requests(&mut self) {
+        self.drain_and_flush(false);
+        if let FileEngine::Async(ref _engine) = self.disk.file_engine {
+            self.process_async_completion_queue();
+        }
+        // The guest gets the completed requests right away, rather than after the snapshot.
+        self.release_delayed_requests(true);
+    }
+
     /// Prepare device for being snapshotted.
     pub fn prepare_save(&mut self) {
         if !self.is_activated() {
             return;
         }
 
-        self.drain_and_flush(false);
-        if let FileEngine::Async(ref _engine) = self.disk.file_engine {
-            self.process_async_completion_queue();
+        self.complete_in_flight_requests();
+    }
+
+    /// Pauses the processing of the queue, e.g. while the backing file is swapped. The requests
+    /// in flight are completed, and the ones submitted afterwards are left in the queue until
+    /// the device is resumed.
+    pub fn pause(&mut self) {
+        self.is_paused = true;
+        if self.is_activated() {
+            self.complete_in_flight_requests();
+            // No request is left in the IO engine.
+            self.is_io_engine_throttled = false;
+        }
+    }
+
+    /// Resumes the processing of the queue, handling the requests submitted while paused.
+    pub fn resume(&mut self) {
+        if !self.is_paused {
+            return;
+        }
+        self.is_paused = false;
+        if self.is_activated() {
+            self.process_queue(0);
         }
     }
 }
@@ -603,6 +904,7 @@ impl VirtioDevice for VirtioBlock {
         if offset >= config_len {
             error!("Failed to read config space");
             self.metrics.cfg_fails.inc();
+            self.error_reporter.report(DeviceErrorClass::Guest, None);
             return;
         }
         if let Some(end) = offset.checked_add(data.len() as u64) {
@@ -623,6 +925,7 @@ impl VirtioDevice for VirtioBlock {
         else {
             error!("Failed to write config space");
             self.metrics.cfg_fails.inc();
+            self.error_reporter.report(DeviceErrorClass::Guest, None);
             return;
         };
 
@@ -644,6 +947,7 @@ impl VirtioDevice for VirtioBlock {
 
         if self.activate_evt.write(1).is_err() {
             self.metrics.activate_fails.inc();
+            self.error_reporter.report(DeviceErrorClass::Vmm, None);
             return Err(ActivateError::EventFd);
         }
         self.device_state = DeviceState::Activated(mem);
@@ -658,7 +962,7 @@ impl VirtioDevice for VirtioBlock {
 impl Drop for VirtioBlock {
     fn drop(&mut self) {
         match self.cache_type {
-            CacheType::Unsafe => {
+            CacheType::Unsafe | CacheType::Writethrough | CacheType::Directsync => {
                 if let Err(err) = self.disk.file_engine.drain(true) {
                     error!("Failed to drain ops on drop: {:?}", err);
                 }
@@ -675,6 +979,7 @@ mod tests {
     use std::fs::metadata;
     use std::io::Read;
     use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::io::AsRawFd;
     use std::thread;
     use std::time::Duration;
 
@@ -690,7 +995,7 @@ mod tests {
     use crate::devices::virtio::block::virtio::IO_URING_NUM_ENTRIES;
     use crate::devices::virtio::queue::{VIRTQ_DESC_F_NEXT, VIRTQ_DESC_F_WRITE};
     use crate::devices::virtio::test_utils::{default_mem, VirtQueue};
-    use crate::rate_limiter::TokenType;
+    use crate::rate_limiter::{TokenBucket, TokenType};
     use crate::vstate::memory::{Address, Bytes, GuestAddress};
 
     #[test]
@@ -705,6 +1010,11 @@ mod tests {
             path_on_host: Some("path".to_string()),
             rate_limiter: None,
             file_engine_type: Default::default(),
+            remote: None,
+            direct_io: None,
+            backend: None,
+            latency_injection: None,
+            shared_rate_limiter: None,
 
             socket: None,
         };
@@ -720,6 +1030,11 @@ mod tests {
             path_on_host: None,
             rate_limiter: None,
             file_engine_type: Default::default(),
+            remote: None,
+            direct_io: None,
+            backend: None,
+            latency_injection: None,
+            shared_rate_limiter: None,
 
             socket: Some("sock".to_string()),
         };
@@ -735,6 +1050,11 @@ mod tests {
             path_on_host: Some("path".to_string()),
             rate_limiter: None,
             file_engine_type: Default::default(),
+            remote: None,
+            direct_io: None,
+            backend: None,
+            latency_injection: None,
+            shared_rate_limiter: None,
 
             socket: Some("sock".to_string()),
         };
@@ -749,9 +1069,15 @@ mod tests {
         f.as_file().set_len(size).unwrap();
 
         for engine in [FileEngineType::Sync, FileEngineType::Async] {
-            let disk_properties =
-                DiskProperties::new(String::from(f.as_path().to_str().unwrap()), true, engine)
-                    .unwrap();
+            let disk_properties = DiskProperties::new(
+                String::from(f.as_path().to_str().unwrap()),
+                true,
+                0,
+                engine,
+                DriveBackend::File,
+                None,
+            )
+            .unwrap();
 
             assert_eq!(size, u64::from(SECTOR_SIZE) * num_sectors);
             assert_eq!(disk_properties.nsectors, num_sectors);
@@ -763,7 +1089,14 @@ mod tests {
             // Testing `backing_file.virtio_block_disk_image_id()` implies
             // duplicating that logic in tests, so skipping it.
 
-            let res = DiskProperties::new("invalid-disk-path".to_string(), true, engine);
+            let res = DiskProperties::new(
+                "invalid-disk-path".to_string(),
+                true,
+                0,
+                engine,
+                DriveBackend::File,
+                None,
+            );
             assert!(
                 matches!(res, Err(VirtioBlockError::BackingFile(_, _))),
                 "{:?}",
@@ -772,6 +1105,40 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_nbd_backend_config() {
+        let uri = "nbd+unix:///rootfs?socket=/nonexistent.sock".to_string();
+        for (open_flags, engine, remote) in [
+            (libc::O_DIRECT, FileEngineType::Sync, None),
+            (0, FileEngineType::Async, None),
+            (
+                0,
+                FileEngineType::Sync,
+                Some(RemoteDriveConfig {
+                    manifest_path: "manifest.json".to_string(),
+                    chunk_store: "chunks".to_string(),
+                }),
+            ),
+        ] {
+            let res = DiskProperties::new(
+                uri.clone(),
+                true,
+                open_flags,
+                engine,
+                DriveBackend::Nbd,
+                remote,
+            );
+            assert!(
+                matches!(res, Err(VirtioBlockError::NbdUnsupported(_))),
+                "{:?}",
+                res
+            );
+        }
+
+        let res = DiskProperties::new(uri, true, 0, FileEngineType::Sync, DriveBackend::Nbd, None);
+        assert!(matches!(res, Err(VirtioBlockError::Nbd(_))), "{:?}", res);
+    }
+
     #[test]
     fn test_virtio_features() {
         for engine in [FileEngineType::Sync, FileEngineType::Async] {
@@ -1109,9 +1476,16 @@ mod tests {
                     .disk
                     .file_engine
                     .file()
+                    .unwrap()
                     .seek(SeekFrom::Start(0))
                     .unwrap();
-                block.disk.file_engine.file().read_exact(&mut buf).unwrap();
+                block
+                    .disk
+                    .file_engine
+                    .file()
+                    .unwrap()
+                    .read_exact(&mut buf)
+                    .unwrap();
                 assert_eq!(buf, empty_data.as_slice());
             }
 
@@ -1248,9 +1622,16 @@ mod tests {
                     .disk
                     .file_engine
                     .file()
+                    .unwrap()
                     .seek(SeekFrom::End(0))
                     .unwrap();
-                block.disk.file_engine.file().set_len(size / 2).unwrap();
+                block
+                    .disk
+                    .file_engine
+                    .file()
+                    .unwrap()
+                    .set_len(size / 2)
+                    .unwrap();
                 mem.write_obj(10, GuestAddress(request_type_addr.0 + 8))
                     .unwrap();
 
@@ -1282,9 +1663,16 @@ mod tests {
                     .disk
                     .file_engine
                     .file()
+                    .unwrap()
                     .seek(SeekFrom::End(0))
                     .unwrap();
-                block.disk.file_engine.file().set_len(size / 2).unwrap();
+                block
+                    .disk
+                    .file_engine
+                    .file()
+                    .unwrap()
+                    .set_len(size / 2)
+                    .unwrap();
                 // Update sector number: stored at `request_type_addr.0 + 8`
                 mem.write_obj(5, GuestAddress(request_type_addr.0 + 8))
                     .unwrap();
@@ -1331,12 +1719,14 @@ mod tests {
                     .disk
                     .file_engine
                     .file()
+                    .unwrap()
                     .seek(SeekFrom::Start(512))
                     .unwrap();
                 block
                     .disk
                     .file_engine
                     .file()
+                    .unwrap()
                     .write_all(&rand_data[512..])
                     .unwrap();
 
@@ -1442,6 +1832,58 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_cache_types() {
+        assert_eq!(DiskProperties::open_flags(CacheType::Unsafe, false), 0);
+        assert_eq!(DiskProperties::open_flags(CacheType::Writeback, false), 0);
+        assert_eq!(
+            DiskProperties::open_flags(CacheType::Writethrough, false),
+            libc::O_DSYNC
+        );
+        assert_eq!(
+            DiskProperties::open_flags(CacheType::Directsync, false),
+            libc::O_DIRECT | libc::O_DSYNC
+        );
+        assert_eq!(
+            DiskProperties::open_flags(CacheType::Writeback, true),
+            libc::O_DIRECT
+        );
+
+        // O_DIRECT is not supported by every file system a temporary file may be on, so only
+        // write-through caching is exercised on an actual file.
+        let f = TempFile::new().unwrap();
+        f.as_file().set_len(0x1000).unwrap();
+        for engine in [FileEngineType::Sync, FileEngineType::Async] {
+            let block = VirtioBlock::new(VirtioBlockConfig {
+                drive_id: "test".to_string(),
+                partuuid: None,
+                is_root_device: false,
+                cache_type: CacheType::Writethrough,
+                is_read_only: false,
+                path_on_host: f.as_path().to_str().unwrap().to_string(),
+                rate_limiter: None,
+                file_engine_type: engine,
+                remote: None,
+                direct_io: false,
+                backend: DriveBackend::File,
+                latency_injection: None,
+                shared_rate_limiter: None,
+            })
+            .unwrap();
+
+            // Writes reach the disk before completing, so flushes are not advertised.
+            assert_eq!(block.avail_features & (1u64 << VIRTIO_BLK_F_FLUSH), 0);
+            // SAFETY: the file descriptor is valid.
+            let flags = unsafe {
+                libc::fcntl(
+                    block.disk.file_engine.file().unwrap().as_raw_fd(),
+                    libc::F_GETFL,
+                )
+            };
+            assert_eq!(flags & libc::O_DSYNC, libc::O_DSYNC);
+        }
+    }
+
     #[test]
     fn test_get_device_id() {
         for engine in [FileEngineType::Sync, FileEngineType::Async] {
@@ -1455,7 +1897,7 @@ mod tests {
             let request_type_addr = GuestAddress(vq.dtable[0].addr.get());
             let data_addr = GuestAddress(vq.dtable[1].addr.get());
             let status_addr = GuestAddress(vq.dtable[2].addr.get());
-            let blk_metadata = block.disk.file_engine.file().metadata();
+            let blk_metadata = block.disk.file_engine.file().unwrap().metadata();
 
             // Test that the driver receives the correct device id.
             {
@@ -1646,6 +2088,121 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_pause_resume() {
+        for engine in [FileEngineType::Sync, FileEngineType::Async] {
+            let mut block = default_block(engine);
+
+            let mem = default_mem();
+            let vq = VirtQueue::new(GuestAddress(0), &mem, 16);
+            block.activate(mem.clone()).unwrap();
+
+            // The requests in flight are completed when the device is paused.
+            add_flush_requests_batch(&mut block, &vq, 5);
+            simulate_queue_event(&mut block, None);
+            block.pause();
+            check_flush_requests_batch(5, &vq);
+
+            // The requests submitted while paused are left in the queue.
+            add_flush_requests_batch(&mut block, &vq, 5);
+            simulate_queue_event(&mut block, None);
+            assert_eq!(vq.used.idx.get(), 0);
+
+            // They are processed once the device is resumed.
+            block.resume();
+            assert!(!block.is_paused);
+            block.prepare_save();
+            check_flush_requests_batch(5, &vq);
+        }
+    }
+
+    #[test]
+    fn test_latency_injection() {
+        for engine in [FileEngineType::Sync, FileEngineType::Async] {
+            let mut block = default_block(engine);
+            block.latency_injector = Some(
+                LatencyInjector::new(LatencyInjectionConfig {
+                    delay_us: 60_000_000,
+                    ..Default::default()
+                })
+                .unwrap(),
+            );
+
+            let mem = default_mem();
+            let vq = VirtQueue::new(GuestAddress(0), &mem, 16);
+            block.activate(mem.clone()).unwrap();
+
+            // The completed requests are held back until their delay elapsed.
+            add_flush_requests_batch(&mut block, &vq, 5);
+            simulate_queue_event(&mut block, None);
+            simulate_async_completion_event(&mut block, false);
+            block.process_latency_injection_event();
+            assert_eq!(vq.used.idx.get(), 0);
+            assert_eq!(block.latency_injector.as_ref().unwrap().delayed_count(), 5);
+
+            // They are returned to the guest before a snapshot is taken.
+            block.prepare_save();
+            check_flush_requests_batch(5, &vq);
+            assert_eq!(block.latency_injector.as_ref().unwrap().delayed_count(), 0);
+
+            // The failed requests are not executed.
+            block.latency_injector = Some(
+                LatencyInjector::new(LatencyInjectionConfig {
+                    error_rate_ppm: 1_000_000,
+                    ..Default::default()
+                })
+                .unwrap(),
+            );
+            add_flush_requests_batch(&mut block, &vq, 1);
+            simulate_queue_event(&mut block, Some(true));
+            assert_eq!(vq.used.idx.get(), 1);
+            let status_addr = vq.dtable[1].addr.get();
+            assert_eq!(
+                u32::from(mem.read_obj::<u8>(GuestAddress(status_addr)).unwrap()),
+                VIRTIO_BLK_S_IOERR
+            );
+            assert_eq!(
+                block.config().latency_injection.unwrap().error_rate_ppm,
+                1_000_000
+            );
+        }
+    }
+
+    #[test]
+    fn test_shared_rate_limiter() {
+        let f = TempFile::new().unwrap();
+        f.as_file().set_len(0x1000).unwrap();
+        let config = |shared_rate_limiter: &str| VirtioBlockConfig {
+            drive_id: "test".to_string(),
+            path_on_host: f.as_path().to_str().unwrap().to_string(),
+            is_root_device: false,
+            partuuid: None,
+            is_read_only: false,
+            cache_type: CacheType::Unsafe,
+            rate_limiter: None,
+            file_engine_type: FileEngineType::Sync,
+            remote: None,
+            direct_io: false,
+            backend: DriveBackend::File,
+            latency_injection: None,
+            shared_rate_limiter: Some(shared_rate_limiter.to_string()),
+        };
+        assert!(matches!(
+            VirtioBlock::new(config("test_block_missing")),
+            Err(VirtioBlockError::SharedRateLimiterNotFound(id)) if id == "test_block_missing"
+        ));
+
+        shared::insert("test_block", None, TokenBucket::new(1, 0, 100_000));
+        let mut block = VirtioBlock::new(config("test_block")).unwrap();
+        assert_eq!(
+            block.config().shared_rate_limiter.as_deref(),
+            Some("test_block")
+        );
+        // The device consumes the budget of the shared rate limiter.
+        assert!(block.rate_limiter.consume(1, TokenType::Ops));
+        assert!(!block.rate_limiter.consume(1, TokenType::Ops));
+    }
+
     #[test]
     fn test_bandwidth_rate_limiter() {
         for engine in [FileEngineType::Sync, FileEngineType::Async] {
@@ -1798,6 +2355,30 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_update_disk_size() {
+        for engine in [FileEngineType::Sync, FileEngineType::Async] {
+            let mut block = default_block(engine);
+            assert_eq!(block.disk.nsectors, 0x1000 >> SECTOR_SHIFT);
+
+            // The driver is not notified if the size did not change.
+            block.update_disk_size().unwrap();
+            assert!(!block.irq_trigger.has_pending_irq(IrqType::Config));
+
+            block
+                .disk
+                .file_engine
+                .file()
+                .unwrap()
+                .set_len(0x4000)
+                .unwrap();
+            block.update_disk_size().unwrap();
+            assert_eq!(block.disk.nsectors, 0x4000 >> SECTOR_SHIFT);
+            assert_eq!(block.config_space, block.disk.virtio_block_config_space());
+            assert!(block.irq_trigger.has_pending_irq(IrqType::Config));
+        }
+    }
+
     #[test]
     fn test_update_disk_image() {
         for engine in [FileEngineType::Sync, FileEngineType::Async] {
@@ -1817,7 +2398,14 @@ mod tests {
                 .unwrap();
 
             assert_eq!(
-                block.disk.file_engine.file().metadata().unwrap().st_ino(),
+                block
+                    .disk
+                    .file_engine
+                    .file()
+                    .unwrap()
+                    .metadata()
+                    .unwrap()
+                    .st_ino(),
                 mdata.st_ino()
             );
             assert_eq!(block.disk.image_id, id.as_slice());
@@ -13,6 +13,7 @@ impl VirtioBlock {
     const PROCESS_QUEUE: u32 = 1;
     const PROCESS_RATE_LIMITER: u32 = 2;
     const PROCESS_ASYNC_COMPLETION: u32 = 3;
+    const PROCESS_LATENCY_INJECTION: u32 = 4;
 
     fn register_runtime_events(&self, ops: &mut EventOps) {
         if let Err(err) = ops.add(Events::with_data(
@@ -38,6 +39,15 @@ impl VirtioBlock {
                 error!("Failed to register IO engine completion event: {}", err);
             }
         }
+        if let Some(ref injector) = self.latency_injector {
+            if let Err(err) = ops.add(Events::with_data(
+                injector.timer(),
+                Self::PROCESS_LATENCY_INJECTION,
+                EventSet::IN,
+            )) {
+                error!("Failed to register latency injection event: {}", err);
+            }
+        }
     }
 
     fn register_activate_event(&self, ops: &mut EventOps) {
@@ -88,6 +98,7 @@ impl MutEventSubscriber for VirtioBlock {
                 Self::PROCESS_QUEUE => self.process_queue_event(),
                 Self::PROCESS_RATE_LIMITER => self.process_rate_limiter_event(),
                 Self::PROCESS_ASYNC_COMPLETION => self.process_async_completion_event(),
+                Self::PROCESS_LATENCY_INJECTION => self.process_latency_injection_event(),
                 _ => warn!("Block: Spurious event received: {:?}", source),
             }
         } else {
@@ -113,7 +113,6 @@ impl<T: Debug> AsyncFileEngine<T> {
         Ok(())
     }
 
-    #[cfg(test)]
     pub fn file(&self) -> &File {
         &self.file
     }
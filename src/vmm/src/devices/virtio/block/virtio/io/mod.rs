@@ -2,12 +2,15 @@
 // SPDX-License-Identifier: Apache-2.0
 
 pub mod async_io;
+pub mod nbd;
+pub mod remote;
 pub mod sync_io;
 
 use std::fmt::Debug;
 use std::fs::File;
 
 pub use self::async_io::{AsyncFileEngine, AsyncIoError};
+pub use self::nbd::{NbdEngine, NbdError};
 pub use self::sync_io::{SyncFileEngine, SyncIoError};
 use crate::devices::virtio::block::virtio::device::FileEngineType;
 use crate::vstate::memory::{GuestAddress, GuestMemoryMmap};
@@ -30,6 +33,8 @@ pub enum BlockIoError {
     Sync(SyncIoError),
     /// Async error: {0}
     Async(AsyncIoError),
+    /// NBD error: {0}
+    Nbd(NbdError),
 }
 
 impl BlockIoError {
@@ -53,6 +58,7 @@ pub enum FileEngine<T> {
     #[allow(unused)]
     Async(AsyncFileEngine<T>),
     Sync(SyncFileEngine),
+    Nbd(NbdEngine),
 }
 
 impl<T: Debug> FileEngine<T> {
@@ -72,16 +78,19 @@ impl<T: Debug> FileEngine<T> {
         match self {
             FileEngine::Async(engine) => engine.update_file(file).map_err(BlockIoError::Async)?,
             FileEngine::Sync(engine) => engine.update_file(file),
+            // NBD exports are switched by reconnecting the engine.
+            FileEngine::Nbd(_) => return Err(BlockIoError::Nbd(NbdError::NoBackingFile)),
         };
 
         Ok(())
     }
 
-    #[cfg(test)]
-    pub fn file(&self) -> &File {
+    /// Returns the backing file, unless the engine is backed by an NBD export.
+    pub fn file(&self) -> Option<&File> {
         match self {
-            FileEngine::Async(engine) => engine.file(),
-            FileEngine::Sync(engine) => engine.file(),
+            FileEngine::Async(engine) => Some(engine.file()),
+            FileEngine::Sync(engine) => Some(engine.file()),
+            FileEngine::Nbd(_) => None,
         }
     }
 
@@ -110,6 +119,13 @@ impl<T: Debug> FileEngine<T> {
                     error: BlockIoError::Sync(err),
                 }),
             },
+            FileEngine::Nbd(engine) => match engine.read(offset, mem, addr, count) {
+                Ok(count) => Ok(FileEngineOk::Executed(UserDataOk { user_data, count })),
+                Err(err) => Err(UserDataError {
+                    user_data,
+                    error: BlockIoError::Nbd(err),
+                }),
+            },
         }
     }
 
@@ -138,6 +154,13 @@ impl<T: Debug> FileEngine<T> {
                     error: BlockIoError::Sync(err),
                 }),
             },
+            FileEngine::Nbd(engine) => match engine.write(offset, mem, addr, count) {
+                Ok(count) => Ok(FileEngineOk::Executed(UserDataOk { user_data, count })),
+                Err(err) => Err(UserDataError {
+                    user_data,
+                    error: BlockIoError::Nbd(err),
+                }),
+            },
         }
     }
 
@@ -163,13 +186,23 @@ impl<T: Debug> FileEngine<T> {
                     error: BlockIoError::Sync(err),
                 }),
             },
+            FileEngine::Nbd(engine) => match engine.flush() {
+                Ok(_) => Ok(FileEngineOk::Executed(UserDataOk {
+                    user_data,
+                    count: 0,
+                })),
+                Err(err) => Err(UserDataError {
+                    user_data,
+                    error: BlockIoError::Nbd(err),
+                }),
+            },
         }
     }
 
     pub fn drain(&mut self, discard: bool) -> Result<(), BlockIoError> {
         match self {
             FileEngine::Async(engine) => engine.drain(discard).map_err(BlockIoError::Async),
-            FileEngine::Sync(_engine) => Ok(()),
+            FileEngine::Sync(_) | FileEngine::Nbd(_) => Ok(()),
         }
     }
 
@@ -179,6 +212,7 @@ impl<T: Debug> FileEngine<T> {
                 engine.drain_and_flush(discard).map_err(BlockIoError::Async)
             }
             FileEngine::Sync(engine) => engine.flush().map_err(BlockIoError::Sync),
+            FileEngine::Nbd(engine) => engine.flush().map_err(BlockIoError::Nbd),
         }
     }
 }
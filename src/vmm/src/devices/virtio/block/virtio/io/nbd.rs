@@ -0,0 +1,570 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Client of the Network Block Device (NBD) protocol, backing block devices whose image is
+//! served by an NBD server, e.g. `qemu-nbd` or `nbdkit`, rather than stored in a local file.
+//!
+//! The export is located by an NBD URI, either `nbd://<ip>[:<port>][/<export>]` for TCP
+//! servers, or `nbd+unix:///[<export>]?socket=<path>` for Unix socket servers. The client uses
+//! the fixed newstyle handshake with `NBD_OPT_EXPORT_NAME`, and simple replies. Requests are sent
+//! one at a time, the device waiting for the reply of the server before processing the next one.
+//! After a transmission error, the connection is dropped and the requests fail until the device
+//! is reconnected.
+
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::os::unix::net::UnixStream;
+use std::time::Duration;
+
+use vm_memory::GuestMemoryError;
+
+use crate::vstate::memory::{Bytes, GuestAddress, GuestMemoryMmap};
+
+/// Port of the NBD servers whose URI does not specify one.
+pub const NBD_DEFAULT_PORT: u16 = 10809;
+/// Time after which a request the server did not answer fails, and the connection is dropped.
+const NBD_IO_TIMEOUT: Duration = Duration::from_secs(30);
+
+const NBD_MAGIC: u64 = 0x4e42_444d_4147_4943;
+const NBD_IHAVEOPT: u64 = 0x4948_4156_454f_5054;
+const NBD_FLAG_FIXED_NEWSTYLE: u16 = 1 << 0;
+const NBD_FLAG_NO_ZEROES: u16 = 1 << 1;
+const NBD_FLAG_C_FIXED_NEWSTYLE: u32 = 1 << 0;
+const NBD_FLAG_C_NO_ZEROES: u32 = 1 << 1;
+const NBD_OPT_EXPORT_NAME: u32 = 1;
+// Padding the server sends after the transmission flags, unless told not to.
+const NBD_EXPORT_ZEROES_LEN: usize = 124;
+
+const NBD_FLAG_READ_ONLY: u16 = 1 << 1;
+const NBD_FLAG_SEND_FLUSH: u16 = 1 << 2;
+
+const NBD_REQUEST_MAGIC: u32 = 0x2560_9513;
+const NBD_SIMPLE_REPLY_MAGIC: u32 = 0x6744_6698;
+const NBD_CMD_READ: u16 = 0;
+const NBD_CMD_WRITE: u16 = 1;
+const NBD_CMD_DISC: u16 = 2;
+const NBD_CMD_FLUSH: u16 = 3;
+
+/// Errors associated with NBD backed block devices.
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum NbdError {
+    /// Invalid NBD URI: {0}
+    InvalidUri(String),
+    /// Cannot connect to the NBD server: {0}
+    Connect(io::Error),
+    /// NBD handshake failed: {0}
+    Handshake(io::Error),
+    /// The NBD server does not support the fixed newstyle handshake.
+    UnsupportedServer,
+    /// The export is read-only on the NBD server.
+    ReadOnlyExport,
+    /// The connection to the NBD server was dropped after an error.
+    Disconnected,
+    /// NBD exports have no backing file.
+    NoBackingFile,
+    /// Error talking to the NBD server: {0}
+    Transmission(io::Error),
+    /// Invalid reply from the NBD server.
+    InvalidReply,
+    /// The NBD server failed the request with error {0}.
+    Server(u32),
+    /// Guest memory error: {0}
+    GuestMemory(GuestMemoryError),
+}
+
+/// Location of an export on an NBD server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NbdUri {
+    /// Address of the server.
+    pub server: NbdServer,
+    /// Name of the export, the default export of the server when empty.
+    pub export_name: String,
+}
+
+/// Address of an NBD server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NbdServer {
+    /// Server listening on this TCP address.
+    Tcp(SocketAddr),
+    /// Server listening on the Unix socket at this path.
+    Unix(String),
+}
+
+impl NbdUri {
+    /// Parses an NBD URI, such as `nbd://10.0.0.1/rootfs` or
+    /// `nbd+unix:///rootfs?socket=/run/nbd.sock`.
+    pub fn parse(uri: &str) -> Result<Self, NbdError> {
+        let invalid = || NbdError::InvalidUri(uri.to_string());
+        if let Some(rest) = uri.strip_prefix("nbd+unix://") {
+            let (path, query) = rest.split_once('?').ok_or_else(invalid)?;
+            let socket = query.strip_prefix("socket=").ok_or_else(invalid)?;
+            // The URI of Unix socket servers has no authority, so the path starts with '/'.
+            let export_name = path.strip_prefix('/').unwrap_or(path);
+            if socket.is_empty() || !(path.is_empty() || path.starts_with('/')) {
+                return Err(invalid());
+            }
+            Ok(NbdUri {
+                server: NbdServer::Unix(socket.to_string()),
+                export_name: export_name.to_string(),
+            })
+        } else if let Some(rest) = uri.strip_prefix("nbd://") {
+            let (authority, export_name) = rest.split_once('/').unwrap_or((rest, ""));
+            if authority.is_empty() || export_name.contains('?') {
+                return Err(invalid());
+            }
+            // Host names are not resolved, as the VMM thread cannot send DNS queries.
+            let address = authority
+                .parse::<SocketAddr>()
+                .or_else(|_| format!("{authority}:{NBD_DEFAULT_PORT}").parse())
+                .map_err(|_| invalid())?;
+            Ok(NbdUri {
+                server: NbdServer::Tcp(address),
+                export_name: export_name.to_string(),
+            })
+        } else {
+            Err(invalid())
+        }
+    }
+}
+
+#[derive(Debug)]
+enum NbdStream {
+    Tcp(TcpStream),
+    Unix(UnixStream),
+}
+
+impl NbdStream {
+    fn connect(server: &NbdServer) -> io::Result<Self> {
+        let stream = match server {
+            NbdServer::Tcp(address) => {
+                let stream = TcpStream::connect(address)?;
+                stream.set_nodelay(true)?;
+                stream.set_read_timeout(Some(NBD_IO_TIMEOUT))?;
+                stream.set_write_timeout(Some(NBD_IO_TIMEOUT))?;
+                NbdStream::Tcp(stream)
+            }
+            NbdServer::Unix(path) => {
+                let stream = UnixStream::connect(path)?;
+                stream.set_read_timeout(Some(NBD_IO_TIMEOUT))?;
+                stream.set_write_timeout(Some(NBD_IO_TIMEOUT))?;
+                NbdStream::Unix(stream)
+            }
+        };
+        Ok(stream)
+    }
+
+    fn read_u16(&mut self) -> io::Result<u16> {
+        let mut buf = [0; 2];
+        self.read_exact(&mut buf)?;
+        Ok(u16::from_be_bytes(buf))
+    }
+
+    fn read_u32(&mut self) -> io::Result<u32> {
+        let mut buf = [0; 4];
+        self.read_exact(&mut buf)?;
+        Ok(u32::from_be_bytes(buf))
+    }
+
+    fn read_u64(&mut self) -> io::Result<u64> {
+        let mut buf = [0; 8];
+        self.read_exact(&mut buf)?;
+        Ok(u64::from_be_bytes(buf))
+    }
+}
+
+impl Read for NbdStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            NbdStream::Tcp(stream) => stream.read(buf),
+            NbdStream::Unix(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for NbdStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            NbdStream::Tcp(stream) => stream.write(buf),
+            NbdStream::Unix(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            NbdStream::Tcp(stream) => stream.flush(),
+            NbdStream::Unix(stream) => stream.flush(),
+        }
+    }
+}
+
+/// Engine performing the I/O of a block device on an NBD export.
+#[derive(Debug)]
+pub struct NbdEngine {
+    uri: NbdUri,
+    stream: Option<NbdStream>,
+    size: u64,
+    transmission_flags: u16,
+    next_handle: u64,
+    // Bounce buffer between the guest memory and the connection.
+    buf: Vec<u8>,
+}
+
+impl NbdEngine {
+    /// Connects to the export at `uri`, which must be writable unless `read_only` is set.
+    pub fn connect(uri: &str, read_only: bool) -> Result<Self, NbdError> {
+        let uri = NbdUri::parse(uri)?;
+        let mut stream = NbdStream::connect(&uri.server).map_err(NbdError::Connect)?;
+        let (size, transmission_flags) = Self::handshake(&mut stream, &uri.export_name)?;
+        if !read_only && transmission_flags & NBD_FLAG_READ_ONLY != 0 {
+            return Err(NbdError::ReadOnlyExport);
+        }
+
+        Ok(NbdEngine {
+            uri,
+            stream: Some(stream),
+            size,
+            transmission_flags,
+            next_handle: 0,
+            buf: Vec::new(),
+        })
+    }
+
+    // Negotiates the export with the server, returning its size and transmission flags.
+    fn handshake(stream: &mut NbdStream, export_name: &str) -> Result<(u64, u16), NbdError> {
+        let handshake = |stream: &mut NbdStream| -> io::Result<Option<(u64, u16)>> {
+            if stream.read_u64()? != NBD_MAGIC || stream.read_u64()? != NBD_IHAVEOPT {
+                return Ok(None);
+            }
+            let handshake_flags = stream.read_u16()?;
+            if handshake_flags & NBD_FLAG_FIXED_NEWSTYLE == 0 {
+                return Ok(None);
+            }
+            let no_zeroes = handshake_flags & NBD_FLAG_NO_ZEROES != 0;
+            let mut client_flags = NBD_FLAG_C_FIXED_NEWSTYLE;
+            if no_zeroes {
+                client_flags |= NBD_FLAG_C_NO_ZEROES;
+            }
+
+            let mut request = Vec::with_capacity(20 + export_name.len());
+            request.extend_from_slice(&client_flags.to_be_bytes());
+            request.extend_from_slice(&NBD_IHAVEOPT.to_be_bytes());
+            request.extend_from_slice(&NBD_OPT_EXPORT_NAME.to_be_bytes());
+            let name_len = u32::try_from(export_name.len())
+                .map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+            request.extend_from_slice(&name_len.to_be_bytes());
+            request.extend_from_slice(export_name.as_bytes());
+            stream.write_all(&request)?;
+
+            // The server closes the connection if the export does not exist.
+            let size = stream.read_u64()?;
+            let transmission_flags = stream.read_u16()?;
+            if !no_zeroes {
+                stream.read_exact(&mut [0; NBD_EXPORT_ZEROES_LEN])?;
+            }
+            Ok(Some((size, transmission_flags)))
+        };
+
+        handshake(stream)
+            .map_err(NbdError::Handshake)?
+            .ok_or(NbdError::UnsupportedServer)
+    }
+
+    /// Size of the export, in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Name of the export.
+    pub fn export_name(&self) -> &str {
+        &self.uri.export_name
+    }
+
+    /// Whether the server writes the data to its storage on flush requests.
+    pub fn supports_flush(&self) -> bool {
+        self.transmission_flags & NBD_FLAG_SEND_FLUSH != 0
+    }
+
+    // Sends a request and waits for its reply, reading `reply_len` bytes of payload into `buf`.
+    // The connection is dropped after a transmission error, as it may be left in the middle of
+    // a request or reply.
+    fn request(
+        &mut self,
+        command: u16,
+        offset: u64,
+        len: u32,
+        reply_len: usize,
+    ) -> Result<(), NbdError> {
+        let stream = self.stream.as_mut().ok_or(NbdError::Disconnected)?;
+        let handle = self.next_handle;
+        self.next_handle = self.next_handle.wrapping_add(1);
+
+        let mut header = [0u8; 28];
+        header[0..4].copy_from_slice(&NBD_REQUEST_MAGIC.to_be_bytes());
+        // Bytes 4..6 hold the command flags, none of which are used.
+        header[6..8].copy_from_slice(&command.to_be_bytes());
+        header[8..16].copy_from_slice(&handle.to_be_bytes());
+        header[16..24].copy_from_slice(&offset.to_be_bytes());
+        header[24..28].copy_from_slice(&len.to_be_bytes());
+
+        let buf = &mut self.buf;
+        let mut transmit = |stream: &mut NbdStream| -> io::Result<Result<(), NbdError>> {
+            stream.write_all(&header)?;
+            if command == NBD_CMD_WRITE {
+                stream.write_all(buf)?;
+            }
+            if stream.read_u32()? != NBD_SIMPLE_REPLY_MAGIC {
+                return Ok(Err(NbdError::InvalidReply));
+            }
+            let error = stream.read_u32()?;
+            if stream.read_u64()? != handle {
+                return Ok(Err(NbdError::InvalidReply));
+            }
+            if error != 0 {
+                // No payload follows the replies of failed requests.
+                return Ok(Err(NbdError::Server(error)));
+            }
+            buf.resize(reply_len, 0);
+            stream.read_exact(buf)?;
+            Ok(Ok(()))
+        };
+
+        match transmit(stream) {
+            Ok(Err(NbdError::Server(error))) => Err(NbdError::Server(error)),
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(err)) => {
+                self.stream = None;
+                Err(err)
+            }
+            Err(err) => {
+                self.stream = None;
+                Err(NbdError::Transmission(err))
+            }
+        }
+    }
+
+    pub fn read(
+        &mut self,
+        offset: u64,
+        mem: &GuestMemoryMmap,
+        addr: GuestAddress,
+        count: u32,
+    ) -> Result<u32, NbdError> {
+        self.request(NBD_CMD_READ, offset, count, count as usize)?;
+        mem.write_slice(&self.buf, addr)
+            .map_err(NbdError::GuestMemory)?;
+        Ok(count)
+    }
+
+    pub fn write(
+        &mut self,
+        offset: u64,
+        mem: &GuestMemoryMmap,
+        addr: GuestAddress,
+        count: u32,
+    ) -> Result<u32, NbdError> {
+        self.buf.resize(count as usize, 0);
+        mem.read_slice(&mut self.buf, addr)
+            .map_err(NbdError::GuestMemory)?;
+        self.request(NBD_CMD_WRITE, offset, count, 0)?;
+        Ok(count)
+    }
+
+    pub fn flush(&mut self) -> Result<(), NbdError> {
+        if !self.supports_flush() {
+            return Ok(());
+        }
+        self.request(NBD_CMD_FLUSH, 0, 0, 0)
+    }
+}
+
+impl Drop for NbdEngine {
+    fn drop(&mut self) {
+        // The server does not reply to disconnection requests.
+        if let Some(stream) = self.stream.as_mut() {
+            let mut header = [0u8; 28];
+            header[0..4].copy_from_slice(&NBD_REQUEST_MAGIC.to_be_bytes());
+            header[6..8].copy_from_slice(&NBD_CMD_DISC.to_be_bytes());
+            let _ = stream.write_all(&header);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::net::UnixListener;
+    use std::thread;
+
+    use vmm_sys_util::tempdir::TempDir;
+
+    use super::*;
+    use crate::utils::u64_to_usize;
+    use crate::vmm_config::machine_config::HugePageConfig;
+    use crate::vstate::memory::GuestMemoryExtension;
+
+    #[test]
+    fn test_parse_uri() {
+        assert_eq!(
+            NbdUri::parse("nbd://10.0.0.1/rootfs").unwrap(),
+            NbdUri {
+                server: NbdServer::Tcp(SocketAddr::from(([10, 0, 0, 1], NBD_DEFAULT_PORT))),
+                export_name: "rootfs".to_string(),
+            }
+        );
+        assert_eq!(
+            NbdUri::parse("nbd://[::1]:10000").unwrap(),
+            NbdUri {
+                server: NbdServer::Tcp("[::1]:10000".parse().unwrap()),
+                export_name: String::new(),
+            }
+        );
+        assert_eq!(
+            NbdUri::parse("nbd+unix:///rootfs?socket=/run/nbd.sock").unwrap(),
+            NbdUri {
+                server: NbdServer::Unix("/run/nbd.sock".to_string()),
+                export_name: "rootfs".to_string(),
+            }
+        );
+        assert_eq!(
+            NbdUri::parse("nbd+unix://?socket=/run/nbd.sock").unwrap(),
+            NbdUri {
+                server: NbdServer::Unix("/run/nbd.sock".to_string()),
+                export_name: String::new(),
+            }
+        );
+
+        for uri in [
+            "/path/to/rootfs",
+            "nbd://",
+            "nbd://10.0.0.1:port/rootfs",
+            "nbd://nbd.example.com/rootfs",
+            "nbd+unix:///rootfs",
+            "nbd+unix://host/rootfs?socket=/run/nbd.sock",
+            "nbd+unix:///rootfs?socket=",
+        ] {
+            assert!(
+                matches!(NbdUri::parse(uri), Err(NbdError::InvalidUri(_))),
+                "{uri}"
+            );
+        }
+    }
+
+    // Serves an in-memory export of `size` bytes to a single client.
+    fn serve_export(listener: UnixListener, size: usize, transmission_flags: u16) {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut export = vec![0u8; size];
+        let mut handshake = Vec::new();
+        handshake.extend_from_slice(&NBD_MAGIC.to_be_bytes());
+        handshake.extend_from_slice(&NBD_IHAVEOPT.to_be_bytes());
+        handshake.extend_from_slice(&NBD_FLAG_FIXED_NEWSTYLE.to_be_bytes());
+        stream.write_all(&handshake).unwrap();
+
+        let mut option = [0u8; 16];
+        stream.read_exact(&mut option).unwrap();
+        assert_eq!(u32::from_be_bytes(option[0..4].try_into().unwrap()), 1);
+        let name_len = u32::from_be_bytes(option[12..16].try_into().unwrap());
+        let mut name = vec![0u8; name_len as usize];
+        stream.read_exact(&mut name).unwrap();
+        assert_eq!(name, b"test");
+        stream.write_all(&(size as u64).to_be_bytes()).unwrap();
+        stream.write_all(&transmission_flags.to_be_bytes()).unwrap();
+        stream.write_all(&[0; NBD_EXPORT_ZEROES_LEN]).unwrap();
+
+        loop {
+            let mut header = [0u8; 28];
+            // Clients may go away without sending a disconnection request.
+            if stream.read_exact(&mut header).is_err() {
+                return;
+            }
+            let command = u16::from_be_bytes(header[6..8].try_into().unwrap());
+            let handle = &header[8..16];
+            let offset = u64_to_usize(u64::from_be_bytes(header[16..24].try_into().unwrap()));
+            let len = u32::from_be_bytes(header[24..28].try_into().unwrap()) as usize;
+            let mut reply = NBD_SIMPLE_REPLY_MAGIC.to_be_bytes().to_vec();
+            match command {
+                NBD_CMD_READ | NBD_CMD_WRITE if offset + len > size => {
+                    reply.extend_from_slice(&libc::EINVAL.unsigned_abs().to_be_bytes());
+                    reply.extend_from_slice(handle);
+                    if command == NBD_CMD_WRITE {
+                        stream.read_exact(&mut vec![0; len]).unwrap();
+                    }
+                }
+                NBD_CMD_READ => {
+                    reply.extend_from_slice(&0u32.to_be_bytes());
+                    reply.extend_from_slice(handle);
+                    reply.extend_from_slice(&export[offset..offset + len]);
+                }
+                NBD_CMD_WRITE => {
+                    stream
+                        .read_exact(&mut export[offset..offset + len])
+                        .unwrap();
+                    reply.extend_from_slice(&0u32.to_be_bytes());
+                    reply.extend_from_slice(handle);
+                }
+                NBD_CMD_FLUSH => {
+                    reply.extend_from_slice(&0u32.to_be_bytes());
+                    reply.extend_from_slice(handle);
+                }
+                NBD_CMD_DISC => return,
+                _ => panic!("unexpected NBD command {command}"),
+            }
+            stream.write_all(&reply).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_nbd_engine() {
+        let dir = TempDir::new().unwrap();
+        let socket_path = dir.as_path().join("nbd.sock");
+        let uri = format!("nbd+unix:///test?socket={}", socket_path.display());
+        let mem = GuestMemoryMmap::from_raw_regions(
+            &[(GuestAddress(0), 0x2000)],
+            false,
+            HugePageConfig::None,
+        )
+        .unwrap();
+
+        // Read-only exports cannot back writable drives.
+        let listener = UnixListener::bind(&socket_path).unwrap();
+        let server = thread::spawn(move || serve_export(listener, 0x1000, NBD_FLAG_READ_ONLY));
+        assert!(matches!(
+            NbdEngine::connect(&uri, false),
+            Err(NbdError::ReadOnlyExport)
+        ));
+        server.join().unwrap();
+
+        std::fs::remove_file(&socket_path).unwrap();
+        let listener = UnixListener::bind(&socket_path).unwrap();
+        let server = thread::spawn(move || serve_export(listener, 0x1000, NBD_FLAG_SEND_FLUSH));
+        let mut engine = NbdEngine::connect(&uri, false).unwrap();
+        assert_eq!(engine.size(), 0x1000);
+        assert_eq!(engine.export_name(), "test");
+        assert!(engine.supports_flush());
+
+        let data = vec![0xab; 0x200];
+        mem.write_slice(&data, GuestAddress(0)).unwrap();
+        assert_eq!(
+            engine.write(0x400, &mem, GuestAddress(0), 0x200).unwrap(),
+            0x200
+        );
+        engine.flush().unwrap();
+        assert_eq!(
+            engine
+                .read(0x400, &mem, GuestAddress(0x1000), 0x200)
+                .unwrap(),
+            0x200
+        );
+        let mut read = vec![0; 0x200];
+        mem.read_slice(&mut read, GuestAddress(0x1000)).unwrap();
+        assert_eq!(read, data);
+
+        // Errors of the server fail the request, without dropping the connection.
+        assert!(matches!(
+            engine.read(0x1000, &mem, GuestAddress(0), 0x200),
+            Err(NbdError::Server(error)) if error == libc::EINVAL.unsigned_abs()
+        ));
+        engine.read(0, &mem, GuestAddress(0), 0x200).unwrap();
+
+        drop(engine);
+        server.join().unwrap();
+    }
+}
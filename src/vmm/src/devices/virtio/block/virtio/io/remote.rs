@@ -0,0 +1,455 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Lazily fetches the image of a block device from a remote chunk store.
+//!
+//! The image is described by a manifest listing the digests of its fixed size chunks. Each chunk
+//! is fetched from a content-addressed store the first time the guest reads or writes it,
+//! verified against its digest, and written to a local cache file, which is the file the block
+//! device then reads and writes. A state file next to the cache records which chunks are
+//! present, so that the cache is reused across Firecracker restarts and snapshot restores.
+
+use std::fmt::Debug;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use aws_lc_rs::digest;
+use serde::Deserialize;
+
+use crate::devices::virtio::block::virtio::SECTOR_SIZE;
+use crate::snapshot::storage::{open_storage, SnapshotStorageError};
+use crate::utils::u64_to_usize;
+use crate::vmm_config::boot_source::{ImageDigest, ImageDigestError};
+use crate::vmm_config::drive::RemoteDriveConfig;
+
+/// Suffix appended to the path of the cache file to name its state file.
+pub const CHUNK_STATE_SUFFIX: &str = ".chunks";
+/// Maximum size of the chunks of a remote image.
+pub const MAX_CHUNK_SIZE: u64 = 64 << 20;
+// The state file starts with the digest of the manifest it was created for, followed by one byte
+// per chunk, set once the chunk is present in the cache.
+const STATE_HEADER_LEN: u64 = digest::SHA256_OUTPUT_LEN as u64;
+
+/// Errors associated with remote block device images.
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum RemoteImageError {
+    /// Cannot read the chunk manifest: {0}
+    ReadManifest(io::Error),
+    /// Cannot parse the chunk manifest: {0}
+    ParseManifest(serde_json::Error),
+    /// Invalid chunk manifest: {0}
+    InvalidManifest(String),
+    /// Invalid chunk digest in the manifest: {0}
+    InvalidDigest(ImageDigestError),
+    /// Invalid chunk store: {0}
+    Store(SnapshotStorageError),
+    /// Cannot access the chunk cache: {0}
+    Cache(io::Error),
+    /// Cannot fetch chunk {0}: {1}
+    Fetch(u64, io::Error),
+    /// Chunk {0} does not match the manifest: {1}
+    Verify(u64, ImageDigestError),
+}
+
+/// Description of a remote image, as a list of chunk digests.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ChunkManifest {
+    /// Size of the image, in bytes.
+    size: u64,
+    /// Size of the chunks, in bytes. The last chunk is shorter if the size of the image is not a
+    /// multiple of the chunk size.
+    chunk_size: u64,
+    /// Digests of the chunks, formatted as `<algorithm>:<hex value>`. Chunks without digest are
+    /// filled with zeros, and never fetched.
+    chunks: Vec<Option<String>>,
+}
+
+/// Store from which the chunks of remote images are fetched, by digest.
+pub trait ChunkStore: Debug + Send {
+    /// Fetches the chunk with digest `digest`, filling `buf` with it.
+    fn fetch(&self, digest: &ImageDigest, buf: &mut [u8]) -> io::Result<()>;
+}
+
+/// Chunk store in a local directory or on an HTTP server, storing each chunk at
+/// `<location>/<algorithm>/<hex value>`.
+#[derive(Debug)]
+pub struct StorageChunkStore {
+    location: String,
+}
+
+impl StorageChunkStore {
+    /// Creates the chunk store at `location`, which is either a local path or an `http://` URL.
+    pub fn new(location: &str) -> Result<Self, RemoteImageError> {
+        let store = StorageChunkStore {
+            location: location.trim_end_matches('/').to_string(),
+        };
+        open_storage(&store.chunk_path("sha256:0")).map_err(RemoteImageError::Store)?;
+        Ok(store)
+    }
+
+    fn chunk_path(&self, digest: &str) -> PathBuf {
+        PathBuf::from(format!(
+            "{}/{}",
+            self.location,
+            digest.replacen(':', "/", 1)
+        ))
+    }
+}
+
+impl ChunkStore for StorageChunkStore {
+    fn fetch(&self, digest: &ImageDigest, buf: &mut [u8]) -> io::Result<()> {
+        open_storage(&self.chunk_path(&digest.to_string()))
+            .map_err(io::Error::other)?
+            .read_range(0, buf.len() as u64)?
+            .read_exact(buf)
+    }
+}
+
+/// Block device image whose chunks are fetched from a chunk store on first access.
+#[derive(Debug)]
+pub struct RemoteImage {
+    config: RemoteDriveConfig,
+    size: u64,
+    chunk_size: u64,
+    digests: Vec<Option<ImageDigest>>,
+    present: Vec<bool>,
+    cache: File,
+    state: File,
+    store: Box<dyn ChunkStore>,
+}
+
+impl RemoteImage {
+    /// Opens the image described by `config`, cached at `cache_path`. The cache file is created
+    /// if it does not exist, and discarded if it was created for another manifest.
+    pub fn open(cache_path: &str, config: RemoteDriveConfig) -> Result<Self, RemoteImageError> {
+        let store = StorageChunkStore::new(&config.chunk_store)?;
+        Self::with_store(cache_path, config, Box::new(store))
+    }
+
+    /// Opens the image described by `config`, fetching its chunks from `store`.
+    pub fn with_store(
+        cache_path: &str,
+        config: RemoteDriveConfig,
+        store: Box<dyn ChunkStore>,
+    ) -> Result<Self, RemoteImageError> {
+        let raw_manifest =
+            std::fs::read(&config.manifest_path).map_err(RemoteImageError::ReadManifest)?;
+        let manifest: ChunkManifest =
+            serde_json::from_slice(&raw_manifest).map_err(RemoteImageError::ParseManifest)?;
+        if manifest.chunk_size == 0
+            || manifest.chunk_size % u64::from(SECTOR_SIZE) != 0
+            || manifest.chunk_size > MAX_CHUNK_SIZE
+        {
+            return Err(RemoteImageError::InvalidManifest(format!(
+                "the chunk size must be a multiple of {} bytes, up to {} bytes",
+                SECTOR_SIZE, MAX_CHUNK_SIZE
+            )));
+        }
+        let num_chunks = manifest.size.div_ceil(manifest.chunk_size);
+        if manifest.chunks.len() as u64 != num_chunks {
+            return Err(RemoteImageError::InvalidManifest(format!(
+                "{} chunks listed instead of {}",
+                manifest.chunks.len(),
+                num_chunks
+            )));
+        }
+        let digests = manifest
+            .chunks
+            .iter()
+            .map(|digest| digest.as_deref().map(ImageDigest::parse).transpose())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(RemoteImageError::InvalidDigest)?;
+
+        let open = |path: &Path| {
+            OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(path)
+                .map_err(RemoteImageError::Cache)
+        };
+        let mut image = RemoteImage {
+            config,
+            size: manifest.size,
+            chunk_size: manifest.chunk_size,
+            present: digests.iter().map(Option::is_none).collect(),
+            digests,
+            cache: open(Path::new(cache_path))?,
+            state: open(Path::new(&format!("{cache_path}{CHUNK_STATE_SUFFIX}")))?,
+            store,
+        };
+        let manifest_digest = digest::digest(&digest::SHA256, &raw_manifest);
+        if !image
+            .load_state(manifest_digest.as_ref())
+            .map_err(RemoteImageError::Cache)?
+        {
+            image
+                .reset_cache(manifest_digest.as_ref())
+                .map_err(RemoteImageError::Cache)?;
+        }
+        Ok(image)
+    }
+
+    /// Loads the chunks present in the cache from the state file. Returns `false` if the cache
+    /// does not match the manifest.
+    fn load_state(&mut self, manifest_digest: &[u8]) -> io::Result<bool> {
+        let state_len = STATE_HEADER_LEN + self.present.len() as u64;
+        if self.cache.metadata()?.len() != self.size || self.state.metadata()?.len() != state_len {
+            return Ok(false);
+        }
+        let mut raw_state = vec![0u8; u64_to_usize(state_len)];
+        self.state.seek(SeekFrom::Start(0))?;
+        self.state.read_exact(&mut raw_state)?;
+        let (header, chunks) = raw_state.split_at(u64_to_usize(STATE_HEADER_LEN));
+        if header != manifest_digest {
+            return Ok(false);
+        }
+        for (present, chunk) in self.present.iter_mut().zip(chunks) {
+            *present |= *chunk != 0;
+        }
+        Ok(true)
+    }
+
+    /// Empties the cache, in which only the chunks without digest are present, as zeros.
+    fn reset_cache(&mut self, manifest_digest: &[u8]) -> io::Result<()> {
+        self.cache.set_len(0)?;
+        self.cache.set_len(self.size)?;
+        let mut raw_state = manifest_digest.to_vec();
+        raw_state.extend(self.present.iter().map(|present| u8::from(*present)));
+        self.state.set_len(0)?;
+        self.state.seek(SeekFrom::Start(0))?;
+        self.state.write_all(&raw_state)?;
+        self.state.sync_all()
+    }
+
+    /// Returns the configuration of the image.
+    pub fn config(&self) -> &RemoteDriveConfig {
+        &self.config
+    }
+
+    /// Returns the number of chunks which are not present in the cache yet.
+    pub fn missing_chunks(&self) -> usize {
+        self.present.iter().filter(|present| !**present).count()
+    }
+
+    /// Fetches the chunks overlapping `len` bytes at `offset` which are not present in the cache
+    /// yet. Returns the number of bytes fetched.
+    pub fn fetch_range(&mut self, offset: u64, len: u64) -> Result<u64, RemoteImageError> {
+        if len == 0 {
+            return Ok(0);
+        }
+        let first = offset / self.chunk_size;
+        let last = (offset + len - 1) / self.chunk_size;
+        let mut fetched = 0;
+        for index in first..=last {
+            fetched += self.fetch_chunk(index)?;
+        }
+        Ok(fetched)
+    }
+
+    fn fetch_chunk(&mut self, index: u64) -> Result<u64, RemoteImageError> {
+        let slot = u64_to_usize(index);
+        let Some(digest) = self.digests[slot].as_ref().filter(|_| !self.present[slot]) else {
+            return Ok(0);
+        };
+
+        let offset = index * self.chunk_size;
+        let len = self.chunk_size.min(self.size - offset);
+        let mut chunk = vec![0u8; u64_to_usize(len)];
+        self.store
+            .fetch(digest, &mut chunk)
+            .map_err(|err| RemoteImageError::Fetch(index, err))?;
+        digest
+            .verify_bytes(&chunk)
+            .map_err(|err| RemoteImageError::Verify(index, err))?;
+
+        // The chunk is only recorded as present once it is durably in the cache, so that a crash
+        // in between fetches it again rather than exposing a hole.
+        self.cache
+            .seek(SeekFrom::Start(offset))
+            .and_then(|_| self.cache.write_all(&chunk))
+            .and_then(|()| self.cache.sync_all())
+            .and_then(|()| self.state.seek(SeekFrom::Start(STATE_HEADER_LEN + index)))
+            .and_then(|_| self.state.write_all(&[1]))
+            .map_err(RemoteImageError::Cache)?;
+        self.present[slot] = true;
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use vmm_sys_util::tempdir::TempDir;
+
+    use super::*;
+
+    const CHUNK_SIZE: u64 = 1024;
+
+    // Chunk store serving corrupted chunks.
+    #[derive(Debug)]
+    struct CorruptChunkStore;
+
+    impl ChunkStore for CorruptChunkStore {
+        fn fetch(&self, _digest: &ImageDigest, buf: &mut [u8]) -> io::Result<()> {
+            buf.fill(3);
+            Ok(())
+        }
+    }
+
+    // Chunk store serving the chunks of an in-memory image, and counting the fetches.
+    #[derive(Debug)]
+    struct MemoryChunkStore {
+        chunks: Vec<Vec<u8>>,
+        fetches: Arc<Mutex<usize>>,
+    }
+
+    impl ChunkStore for MemoryChunkStore {
+        fn fetch(&self, digest: &ImageDigest, buf: &mut [u8]) -> io::Result<()> {
+            *self.fetches.lock().unwrap() += 1;
+            let chunk = self
+                .chunks
+                .iter()
+                .find(|chunk| digest.verify_bytes(chunk).is_ok())
+                .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+            buf.copy_from_slice(chunk);
+            Ok(())
+        }
+    }
+
+    fn sha256(data: &[u8]) -> String {
+        digest::digest(&digest::SHA256, data)
+            .as_ref()
+            .iter()
+            .fold("sha256:".to_string(), |acc, byte| {
+                format!("{acc}{byte:02x}")
+            })
+    }
+
+    // Writes the manifest of an image made of `chunks`, and returns the image configuration.
+    fn remote_config(dir: &TempDir, chunks: &[Vec<u8>], holes: &[usize]) -> RemoteDriveConfig {
+        let manifest = serde_json::json!({
+            "size": chunks.iter().map(|chunk| chunk.len() as u64).sum::<u64>(),
+            "chunk_size": CHUNK_SIZE,
+            "chunks": chunks
+                .iter()
+                .enumerate()
+                .map(|(i, chunk)| (!holes.contains(&i)).then(|| sha256(chunk)))
+                .collect::<Vec<_>>(),
+        });
+        let manifest_path = dir.as_path().join("manifest.json");
+        std::fs::write(&manifest_path, manifest.to_string()).unwrap();
+        RemoteDriveConfig {
+            manifest_path: manifest_path.to_str().unwrap().to_string(),
+            chunk_store: "http://10.0.0.1/chunks".to_string(),
+        }
+    }
+
+    fn open_image(
+        cache_path: &str,
+        config: &RemoteDriveConfig,
+        chunks: &[Vec<u8>],
+    ) -> (RemoteImage, Arc<Mutex<usize>>) {
+        let fetches = Arc::new(Mutex::new(0));
+        let store = MemoryChunkStore {
+            chunks: chunks.to_vec(),
+            fetches: fetches.clone(),
+        };
+        let image = RemoteImage::with_store(cache_path, config.clone(), Box::new(store)).unwrap();
+        (image, fetches)
+    }
+
+    #[test]
+    fn test_remote_image_fetch() {
+        let dir = TempDir::new().unwrap();
+        let chunks = vec![
+            vec![1u8; 1024],
+            vec![2u8; 1024],
+            vec![0u8; 1024],
+            vec![4u8; 512],
+        ];
+        let config = remote_config(&dir, &chunks, &[2]);
+        let cache_path = dir.as_path().join("cache").to_str().unwrap().to_string();
+
+        let (mut image, fetches) = open_image(&cache_path, &config, &chunks);
+        assert_eq!(std::fs::metadata(&cache_path).unwrap().len(), 3584);
+        assert_eq!(image.missing_chunks(), 3);
+
+        // Only the chunks overlapping the range are fetched, and only once.
+        assert_eq!(image.fetch_range(1000, 100).unwrap(), 2048);
+        assert_eq!(image.fetch_range(0, 2048).unwrap(), 0);
+        assert_eq!(*fetches.lock().unwrap(), 2);
+        // Holes are never fetched.
+        assert_eq!(image.fetch_range(2048, 1024).unwrap(), 0);
+        assert_eq!(image.missing_chunks(), 1);
+
+        let cache = std::fs::read(&cache_path).unwrap();
+        assert_eq!(
+            &cache[..2048],
+            [chunks[0].clone(), chunks[1].clone()].concat()
+        );
+        assert!(cache[2048..].iter().all(|byte| *byte == 0));
+
+        // The cache is reused when opening the image again.
+        drop(image);
+        let (mut image, fetches) = open_image(&cache_path, &config, &chunks);
+        assert_eq!(image.missing_chunks(), 1);
+        assert_eq!(image.fetch_range(0, 3584).unwrap(), 512);
+        assert_eq!(*fetches.lock().unwrap(), 1);
+        assert_eq!(std::fs::read(&cache_path).unwrap()[3072..], chunks[3][..]);
+
+        // The cache is discarded if it was created for another manifest.
+        let config = remote_config(&dir, &chunks, &[]);
+        let (image, _) = open_image(&cache_path, &config, &chunks);
+        assert_eq!(image.missing_chunks(), 4);
+    }
+
+    #[test]
+    fn test_remote_image_errors() {
+        let dir = TempDir::new().unwrap();
+        let chunks = vec![vec![1u8; 1024], vec![2u8; 1024]];
+        let cache_path = dir.as_path().join("cache").to_str().unwrap().to_string();
+
+        // Chunks which do not match their digest are rejected.
+        let config = remote_config(&dir, &chunks, &[]);
+        let (mut image, _) = open_image(&cache_path, &config, &chunks);
+        image.store = Box::new(CorruptChunkStore);
+        assert!(matches!(
+            image.fetch_range(1024, 512),
+            Err(RemoteImageError::Verify(1, _))
+        ));
+        assert_eq!(image.missing_chunks(), 2);
+
+        let manifest_path = dir.as_path().join("manifest.json");
+        for manifest in [
+            r#"{"size": 2048, "chunk_size": 1000, "chunks": [null, null, null]}"#,
+            r#"{"size": 2048, "chunk_size": 1024, "chunks": [null]}"#,
+        ] {
+            std::fs::write(&manifest_path, manifest).unwrap();
+            assert!(matches!(
+                RemoteImage::open(&cache_path, config.clone()),
+                Err(RemoteImageError::InvalidManifest(_))
+            ));
+        }
+        std::fs::write(
+            &manifest_path,
+            r#"{"size": 1024, "chunk_size": 1024, "chunks": ["0"]}"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            RemoteImage::open(&cache_path, config.clone()),
+            Err(RemoteImageError::InvalidDigest(_))
+        ));
+
+        let mut invalid_store = config;
+        invalid_store.chunk_store = "s3://bucket/chunks".to_string();
+        assert!(matches!(
+            RemoteImage::open(&cache_path, invalid_store),
+            Err(RemoteImageError::Store(_))
+        ));
+    }
+}
@@ -0,0 +1,262 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Injection of latency and I/O errors in the requests of a block device, to test how the guest
+//! storage stack copes with a slow or failing disk.
+
+use std::time::Duration;
+
+use timerfd::{ClockId, SetTimeFlags, TimerFd, TimerState};
+use utils::time::{get_time_us, ClockType};
+use vmm_sys_util::rand::{xor_pseudo_rng_u32, xor_pseudo_rng_u64};
+
+use super::request::FinishedRequest;
+use crate::vmm_config::drive::LatencyInjectionConfig;
+
+/// Largest delay, in microseconds, which can be added to a request.
+pub const MAX_INJECTED_DELAY_US: u64 = 60_000_000;
+/// Error rate failing all the requests, in parts per million.
+const MAX_ERROR_RATE_PPM: u32 = 1_000_000;
+
+/// Errors of the latency injection.
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum LatencyInjectionError {
+    /// The error rate must be at most 1000000 parts per million, got {0}.
+    InvalidErrorRate(u32),
+    /// The injected delay, including the jitter, must be at most 60 seconds.
+    InvalidDelay,
+    /// Cannot create the latency injection timer: {0}
+    Timer(std::io::Error),
+}
+
+/// Fails some of the requests of a block device, and holds the completed ones back for the
+/// configured delay before they are returned to the guest.
+#[derive(Debug)]
+pub struct LatencyInjector {
+    config: LatencyInjectionConfig,
+    /// Armed when requests are held back, to release them once their delay elapsed.
+    timer_fd: TimerFd,
+    /// The completed requests held back, with the monotonic time, in microseconds, at which
+    /// they are released.
+    delayed: Vec<(u64, FinishedRequest)>,
+    /// Release time the timer is armed for, if any.
+    armed_deadline_us: Option<u64>,
+}
+
+impl LatencyInjector {
+    /// Creates an injector for the given configuration.
+    ///
+    /// The timer is created here rather than on the first delayed request, as the seccomp
+    /// filters do not allow creating it once the microVM runs.
+    pub fn new(config: LatencyInjectionConfig) -> Result<Self, LatencyInjectionError> {
+        if config.error_rate_ppm > MAX_ERROR_RATE_PPM {
+            return Err(LatencyInjectionError::InvalidErrorRate(
+                config.error_rate_ppm,
+            ));
+        }
+        if config
+            .delay_us
+            .checked_add(config.jitter_us)
+            .map_or(true, |max_delay_us| max_delay_us > MAX_INJECTED_DELAY_US)
+        {
+            return Err(LatencyInjectionError::InvalidDelay);
+        }
+
+        let timer_fd = TimerFd::new_custom(ClockId::Monotonic, true, true)
+            .map_err(LatencyInjectionError::Timer)?;
+        Ok(LatencyInjector {
+            config,
+            timer_fd,
+            delayed: Vec::new(),
+            armed_deadline_us: None,
+        })
+    }
+
+    /// Returns the configuration of the injector.
+    pub fn config(&self) -> &LatencyInjectionConfig {
+        &self.config
+    }
+
+    /// Returns the timer signaling that delayed requests are due.
+    pub fn timer(&self) -> &TimerFd {
+        &self.timer_fd
+    }
+
+    /// Returns the number of requests held back.
+    pub fn delayed_count(&self) -> usize {
+        self.delayed.len()
+    }
+
+    /// Randomly decides whether the next request fails, according to the error rate.
+    pub fn should_fail(&self) -> bool {
+        self.config.error_rate_ppm > 0
+            && xor_pseudo_rng_u32() % MAX_ERROR_RATE_PPM < self.config.error_rate_ppm
+    }
+
+    /// Holds back a completed request for the configured delay. The request is handed back
+    /// when no delay is configured.
+    pub fn delay(&mut self, finished: FinishedRequest) -> Option<FinishedRequest> {
+        if self.config.delay_us == 0 && self.config.jitter_us == 0 {
+            return Some(finished);
+        }
+
+        let mut delay_us = self.config.delay_us;
+        if self.config.jitter_us > 0 {
+            delay_us += xor_pseudo_rng_u64() % (self.config.jitter_us + 1);
+        }
+        let now_us = get_time_us(ClockType::Monotonic);
+        let deadline_us = now_us + delay_us;
+        self.delayed.push((deadline_us, finished));
+
+        if self
+            .armed_deadline_us
+            .map_or(true, |armed_deadline_us| deadline_us < armed_deadline_us)
+        {
+            self.arm(deadline_us, now_us);
+        }
+        None
+    }
+
+    /// Returns the requests whose delay elapsed, in the order of their release times, and
+    /// re-arms the timer for the remaining ones.
+    pub fn pop_due(&mut self) -> Vec<FinishedRequest> {
+        // The timer is non blocking, so this does not wait for it to expire.
+        self.timer_fd.read();
+        self.armed_deadline_us = None;
+
+        let now_us = get_time_us(ClockType::Monotonic);
+        let (mut due, delayed): (Vec<_>, Vec<_>) = std::mem::take(&mut self.delayed)
+            .into_iter()
+            .partition(|(deadline_us, _)| *deadline_us <= now_us);
+        self.delayed = delayed;
+        if let Some(next_deadline_us) = self
+            .delayed
+            .iter()
+            .map(|(deadline_us, _)| *deadline_us)
+            .min()
+        {
+            self.arm(next_deadline_us, now_us);
+        }
+
+        due.sort_by_key(|(deadline_us, _)| *deadline_us);
+        due.into_iter().map(|(_, finished)| finished).collect()
+    }
+
+    /// Returns all the requests held back, regardless of their delay, and disarms the timer.
+    pub fn drain(&mut self) -> Vec<FinishedRequest> {
+        self.timer_fd
+            .set_state(TimerState::Disarmed, SetTimeFlags::Default);
+        self.armed_deadline_us = None;
+
+        let mut delayed = std::mem::take(&mut self.delayed);
+        delayed.sort_by_key(|(deadline_us, _)| *deadline_us);
+        delayed.into_iter().map(|(_, finished)| finished).collect()
+    }
+
+    fn arm(&mut self, deadline_us: u64, now_us: u64) {
+        // A zero duration would disarm the timer.
+        let delay_us = deadline_us.saturating_sub(now_us).max(1);
+        self.timer_fd.set_state(
+            TimerState::Oneshot(Duration::from_micros(delay_us)),
+            SetTimeFlags::Default,
+        );
+        self.armed_deadline_us = Some(deadline_us);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn finished(desc_idx: u16) -> FinishedRequest {
+        FinishedRequest {
+            num_bytes_to_mem: 1,
+            desc_idx,
+        }
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        let config = LatencyInjectionConfig {
+            error_rate_ppm: MAX_ERROR_RATE_PPM + 1,
+            ..Default::default()
+        };
+        assert!(matches!(
+            LatencyInjector::new(config),
+            Err(LatencyInjectionError::InvalidErrorRate(1_000_001))
+        ));
+
+        let config = LatencyInjectionConfig {
+            delay_us: MAX_INJECTED_DELAY_US,
+            jitter_us: 1,
+            ..Default::default()
+        };
+        assert!(matches!(
+            LatencyInjector::new(config),
+            Err(LatencyInjectionError::InvalidDelay)
+        ));
+
+        let config = LatencyInjectionConfig {
+            delay_us: 1,
+            jitter_us: u64::MAX,
+            ..Default::default()
+        };
+        assert!(matches!(
+            LatencyInjector::new(config),
+            Err(LatencyInjectionError::InvalidDelay)
+        ));
+    }
+
+    #[test]
+    fn test_error_rate() {
+        let injector = LatencyInjector::new(LatencyInjectionConfig::default()).unwrap();
+        assert!((0..1000).all(|_| !injector.should_fail()));
+
+        let injector = LatencyInjector::new(LatencyInjectionConfig {
+            error_rate_ppm: MAX_ERROR_RATE_PPM,
+            ..Default::default()
+        })
+        .unwrap();
+        assert!((0..1000).all(|_| injector.should_fail()));
+    }
+
+    #[test]
+    fn test_no_delay() {
+        let mut injector = LatencyInjector::new(LatencyInjectionConfig::default()).unwrap();
+        assert_eq!(injector.delay(finished(1)).unwrap().desc_idx, 1);
+        assert_eq!(injector.delayed_count(), 0);
+    }
+
+    #[test]
+    fn test_delay() {
+        let mut injector = LatencyInjector::new(LatencyInjectionConfig {
+            delay_us: 10_000,
+            jitter_us: 1_000,
+            ..Default::default()
+        })
+        .unwrap();
+
+        let start_us = get_time_us(ClockType::Monotonic);
+        assert!(injector.delay(finished(1)).is_none());
+        assert!(injector.delay(finished(2)).is_none());
+        assert_eq!(injector.delayed_count(), 2);
+        assert!(injector.pop_due().is_empty());
+
+        // Wait for the timer, armed for the first request to be released.
+        let mut due = Vec::new();
+        while due.len() < 2 {
+            assert!(get_time_us(ClockType::Monotonic) - start_us < 1_000_000);
+            std::thread::sleep(Duration::from_millis(1));
+            due.extend(injector.pop_due());
+        }
+        assert!(get_time_us(ClockType::Monotonic) - start_us >= 10_000);
+        assert_eq!(injector.delayed_count(), 0);
+
+        // Draining releases the requests right away.
+        assert!(injector.delay(finished(3)).is_none());
+        let drained = injector.drain();
+        assert_eq!(drained.len(), 1);
+        assert_eq!(drained[0].desc_idx, 3);
+        assert_eq!(injector.delayed_count(), 0);
+    }
+}
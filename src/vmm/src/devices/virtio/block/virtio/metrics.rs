@@ -184,6 +184,14 @@ pub struct BlockDeviceMetrics {
     pub io_engine_throttled_events: SharedIncMetric,
     /// Number of remaining requests in the queue.
     pub remaining_reqs_count: SharedIncMetric,
+    /// Number of bytes of the remote image fetched from the chunk store.
+    pub remote_fetch_bytes: SharedIncMetric,
+    /// Number of failures fetching chunks of the remote image.
+    pub remote_fetch_fails: SharedIncMetric,
+    /// Number of requests failed by the latency injection.
+    pub injected_errors: SharedIncMetric,
+    /// Number of requests whose completion was delayed by the latency injection.
+    pub injected_delays: SharedIncMetric,
 }
 
 impl BlockDeviceMetrics {
@@ -230,6 +238,12 @@ impl BlockDeviceMetrics {
             .add(other.io_engine_throttled_events.fetch_diff());
         self.remaining_reqs_count
             .add(other.remaining_reqs_count.fetch_diff());
+        self.remote_fetch_bytes
+            .add(other.remote_fetch_bytes.fetch_diff());
+        self.remote_fetch_fails
+            .add(other.remote_fetch_fails.fetch_diff());
+        self.injected_errors.add(other.injected_errors.fetch_diff());
+        self.injected_delays.add(other.injected_delays.fetch_diff());
     }
 }
 
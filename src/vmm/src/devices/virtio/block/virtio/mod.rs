@@ -6,6 +6,7 @@
 pub mod device;
 mod event_handler;
 mod io;
+mod latency_injection;
 pub mod metrics;
 pub mod persist;
 pub mod request;
@@ -57,12 +58,24 @@ pub enum VirtioBlockError {
     FileEngine(io::BlockIoError),
     /// Error manipulating the backing file: {0} {1}
     BackingFile(std::io::Error, String),
+    /// Error opening the remote image: {0}
+    RemoteImage(io::remote::RemoteImageError),
+    /// The size of a remote image cannot be refreshed.
+    RemoteImageResize,
+    /// Error connecting to the NBD export: {0}
+    Nbd(io::NbdError),
+    /// The NBD backend does not support {0}.
+    NbdUnsupported(&'static str),
+    /// Invalid latency injection configuration: {0}
+    LatencyInjection(latency_injection::LatencyInjectionError),
     /// Error opening eventfd: {0}
     EventFd(std::io::Error),
     /// Error creating an irqfd: {0}
     IrqTrigger(std::io::Error),
     /// Error coming from the rate limiter: {0}
     RateLimiter(std::io::Error),
+    /// Shared rate limiter {0} not found.
+    SharedRateLimiterNotFound(String),
     /// Persistence error: {0}
     Persist(crate::devices::virtio::persist::PersistError),
 }
@@ -10,9 +10,11 @@ use serde::{Deserialize, Serialize};
 use vmm_sys_util::eventfd::EventFd;
 
 use super::device::DiskProperties;
+use super::latency_injection::LatencyInjector;
 use super::*;
+use crate::devices::error_events::DeviceErrorReporter;
 use crate::devices::virtio::block::persist::BlockConstructorArgs;
-use crate::devices::virtio::block::virtio::device::FileEngineType;
+use crate::devices::virtio::block::virtio::device::{DriveBackend, FileEngineType};
 use crate::devices::virtio::block::virtio::metrics::BlockMetricsPerDevice;
 use crate::devices::virtio::device::{DeviceState, IrqTrigger};
 use crate::devices::virtio::gen::virtio_blk::VIRTIO_BLK_F_RO;
@@ -21,6 +23,7 @@ use crate::devices::virtio::TYPE_BLOCK;
 use crate::rate_limiter::persist::RateLimiterState;
 use crate::rate_limiter::RateLimiter;
 use crate::snapshot::Persist;
+use crate::vmm_config::drive::{LatencyInjectionConfig, RemoteDriveConfig};
 
 /// Holds info about block's file engine type. Gets saved in snapshot.
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
@@ -63,6 +66,14 @@ pub struct VirtioBlockState {
     virtio_state: VirtioDeviceState,
     rate_limiter_state: RateLimiterState,
     file_engine_type: FileEngineTypeState,
+    /// Remote image cached in the disk file, if any. The chunks present in the cache are
+    /// recorded next to it, rather than in the snapshot.
+    remote: Option<RemoteDriveConfig>,
+    direct_io: bool,
+    /// NBD backed drives are reconnected to their export on restore.
+    backend: DriveBackend,
+    /// The requests held back by the latency injection are completed before saving.
+    latency_injection: Option<LatencyInjectionConfig>,
 }
 
 impl Persist<'_> for VirtioBlock {
@@ -81,6 +92,17 @@ impl Persist<'_> for VirtioBlock {
             virtio_state: VirtioDeviceState::from_device(self),
             rate_limiter_state: self.rate_limiter.save(),
             file_engine_type: FileEngineTypeState::from(self.file_engine_type()),
+            remote: self
+                .disk
+                .remote
+                .as_ref()
+                .map(|remote| remote.config().clone()),
+            direct_io: self.direct_io,
+            backend: self.disk.backend(),
+            latency_injection: self
+                .latency_injector
+                .as_ref()
+                .map(|injector| *injector.config()),
         }
     }
 
@@ -95,9 +117,18 @@ impl Persist<'_> for VirtioBlock {
         let disk_properties = DiskProperties::new(
             state.disk_path.clone(),
             is_read_only,
+            DiskProperties::open_flags(state.cache_type, state.direct_io),
             state.file_engine_type.into(),
+            state.backend,
+            state.remote.clone(),
         )?;
 
+        let latency_injector = state
+            .latency_injection
+            .map(LatencyInjector::new)
+            .transpose()
+            .map_err(VirtioBlockError::LatencyInjection)?;
+
         let queue_evts = [EventFd::new(libc::EFD_NONBLOCK).map_err(VirtioBlockError::EventFd)?];
 
         let queues = state
@@ -138,11 +169,15 @@ impl Persist<'_> for VirtioBlock {
             cache_type: state.cache_type,
             root_device: state.root_device,
             read_only: is_read_only,
+            direct_io: state.direct_io,
 
             disk: disk_properties,
             rate_limiter,
             is_io_engine_throttled: false,
+            is_paused: false,
+            latency_injector,
             metrics: BlockMetricsPerDevice::alloc(state.id.clone()),
+            error_reporter: DeviceErrorReporter::new(format!("block_{}", state.id)),
         })
     }
 }
@@ -174,6 +209,11 @@ mod tests {
             cache_type: CacheType::Writeback,
             rate_limiter: None,
             file_engine_type: FileEngineType::default(),
+            remote: None,
+            direct_io: false,
+            backend: DriveBackend::File,
+            latency_injection: None,
+            shared_rate_limiter: None,
         };
 
         let block = VirtioBlock::new(config).unwrap();
@@ -213,9 +253,14 @@ mod tests {
             is_root_device: false,
             partuuid: None,
             is_read_only: false,
-            cache_type: CacheType::Unsafe,
+            cache_type: CacheType::Writethrough,
             rate_limiter: None,
             file_engine_type: FileEngineType::default(),
+            remote: None,
+            direct_io: false,
+            backend: DriveBackend::File,
+            latency_injection: None,
+            shared_rate_limiter: None,
         };
 
         let block = VirtioBlock::new(config).unwrap();
@@ -246,5 +291,7 @@ mod tests {
 
         // Test that block specific fields are the same.
         assert_eq!(restored_block.disk.file_path, block.disk.file_path);
+        assert_eq!(restored_block.cache_type, block.cache_type);
+        assert_eq!(restored_block.disk.open_flags, block.disk.open_flags);
     }
 }
@@ -10,7 +10,12 @@ use std::convert::From;
 use vm_memory::GuestMemoryError;
 
 use super::{io as block_io, VirtioBlockError, SECTOR_SHIFT, SECTOR_SIZE};
+use crate::devices::error_events::{DeviceErrorClass, DeviceErrorReporter};
 use crate::devices::virtio::block::virtio::device::DiskProperties;
+use crate::devices::virtio::block::virtio::io::async_io::AsyncIoError;
+use crate::devices::virtio::block::virtio::io::nbd::NbdError;
+use crate::devices::virtio::block::virtio::io::remote::RemoteImageError;
+use crate::devices::virtio::block::virtio::io::sync_io::SyncIoError;
 use crate::devices::virtio::block::virtio::metrics::BlockDeviceMetrics;
 pub use crate::devices::virtio::gen::virtio_blk::{
     VIRTIO_BLK_ID_BYTES, VIRTIO_BLK_S_IOERR, VIRTIO_BLK_S_OK, VIRTIO_BLK_S_UNSUPP,
@@ -24,8 +29,50 @@ use crate::vstate::memory::{ByteValued, Bytes, GuestAddress, GuestMemoryMmap};
 #[derive(Debug, derive_more::From)]
 pub enum IoErr {
     GetId(GuestMemoryError),
-    PartialTransfer { completed: u32, expected: u32 },
+    PartialTransfer {
+        completed: u32,
+        expected: u32,
+    },
     FileEngine(block_io::BlockIoError),
+    Remote(RemoteImageError),
+    /// The request was failed by the latency injection of the drive.
+    Injected,
+}
+
+impl IoErr {
+    /// Returns the class of the error, and the errno of the failed system call if any.
+    fn error_class(&self) -> (DeviceErrorClass, Option<i32>) {
+        use block_io::BlockIoError::{Async, Nbd, Sync};
+
+        match self {
+            IoErr::GetId(_)
+            | IoErr::FileEngine(Async(AsyncIoError::GuestMemory(_)))
+            | IoErr::FileEngine(Nbd(NbdError::GuestMemory(_)))
+            | IoErr::FileEngine(Sync(SyncIoError::Transfer(
+                GuestMemoryError::InvalidGuestAddress(_),
+            ))) => (DeviceErrorClass::Guest, None),
+            IoErr::FileEngine(Sync(
+                SyncIoError::Flush(err) | SyncIoError::Seek(err) | SyncIoError::SyncAll(err),
+            ))
+            | IoErr::FileEngine(Sync(SyncIoError::Transfer(GuestMemoryError::IOError(err))))
+            | IoErr::FileEngine(Async(
+                AsyncIoError::IO(err)
+                | AsyncIoError::Submit(err)
+                | AsyncIoError::SyncAll(err)
+                | AsyncIoError::EventFd(err),
+            ))
+            | IoErr::Remote(RemoteImageError::Fetch(_, err) | RemoteImageError::Cache(err))
+            | IoErr::FileEngine(Nbd(NbdError::Transmission(err))) => {
+                (DeviceErrorClass::Backend, err.raw_os_error())
+            }
+            // NBD servers reply with errno values.
+            IoErr::FileEngine(Nbd(NbdError::Server(error))) => {
+                (DeviceErrorClass::Backend, i32::try_from(*error).ok())
+            }
+            IoErr::Injected => (DeviceErrorClass::Backend, Some(libc::EIO)),
+            _ => (DeviceErrorClass::Backend, None),
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -103,6 +150,7 @@ impl PendingRequest {
         status: &Status,
         mem: &GuestMemoryMmap,
         block_metrics: &BlockDeviceMetrics,
+        error_reporter: &DeviceErrorReporter,
     ) -> FinishedRequest {
         let (num_bytes_to_mem, status_code) = match status {
             Status::Ok { num_bytes_to_mem } => {
@@ -117,11 +165,14 @@ impl PendingRequest {
                     "Failed to execute {:?} virtio block request: {:?}",
                     self.r#type, err
                 );
+                let (class, errno) = err.error_class();
+                error_reporter.report(class, errno);
                 (*num_bytes_to_mem, u8::try_from(VIRTIO_BLK_S_IOERR).unwrap())
             }
             Status::Unsupported { op } => {
                 block_metrics.invalid_reqs_count.inc();
                 error!("Received unsupported virtio block request: {}", op);
+                error_reporter.report(DeviceErrorClass::Guest, None);
                 (0, u8::try_from(VIRTIO_BLK_S_UNSUPP).unwrap())
             }
         };
@@ -149,6 +200,7 @@ impl PendingRequest {
         mem: &GuestMemoryMmap,
         res: Result<u32, IoErr>,
         block_metrics: &BlockDeviceMetrics,
+        error_reporter: &DeviceErrorReporter,
     ) -> FinishedRequest {
         let status = match (res, self.r#type) {
             (Ok(transferred_data_len), RequestType::In) => {
@@ -183,7 +235,7 @@ impl PendingRequest {
             },
         };
 
-        self.write_status_and_finish(&status, mem, block_metrics)
+        self.write_status_and_finish(&status, mem, block_metrics, error_reporter)
     }
 }
 
@@ -363,14 +415,48 @@ impl Request {
         }
     }
 
+    /// Fails the request with an I/O error, without executing it.
+    pub(crate) fn fail(
+        self,
+        desc_idx: u16,
+        mem: &GuestMemoryMmap,
+        block_metrics: &BlockDeviceMetrics,
+        error_reporter: &DeviceErrorReporter,
+    ) -> FinishedRequest {
+        self.to_pending_request(desc_idx).finish(
+            mem,
+            Err(IoErr::Injected),
+            block_metrics,
+            error_reporter,
+        )
+    }
+
     pub(crate) fn process(
         self,
         disk: &mut DiskProperties,
         desc_idx: u16,
         mem: &GuestMemoryMmap,
         block_metrics: &BlockDeviceMetrics,
+        error_reporter: &DeviceErrorReporter,
     ) -> ProcessingResult {
         let pending = self.to_pending_request(desc_idx);
+        // The chunks of a remote image are fetched before being read or partially overwritten.
+        if let (RequestType::In | RequestType::Out, Some(remote)) =
+            (self.r#type, disk.remote.as_mut())
+        {
+            match remote.fetch_range(self.offset(), u64::from(self.data_len)) {
+                Ok(fetched) => block_metrics.remote_fetch_bytes.add(fetched),
+                Err(err) => {
+                    block_metrics.remote_fetch_fails.inc();
+                    return ProcessingResult::Executed(pending.finish(
+                        mem,
+                        Err(IoErr::Remote(err)),
+                        block_metrics,
+                        error_reporter,
+                    ));
+                }
+            }
+        }
         let res = match self.r#type {
             RequestType::In => {
                 let _metric = block_metrics.read_agg.record_latency_metrics();
@@ -388,18 +474,29 @@ impl Request {
                     .write_slice(&disk.image_id, self.data_addr)
                     .map(|_| VIRTIO_BLK_ID_BYTES)
                     .map_err(IoErr::GetId);
-                return ProcessingResult::Executed(pending.finish(mem, res, block_metrics));
+                return ProcessingResult::Executed(pending.finish(
+                    mem,
+                    res,
+                    block_metrics,
+                    error_reporter,
+                ));
             }
             RequestType::Unsupported(_) => {
-                return ProcessingResult::Executed(pending.finish(mem, Ok(0), block_metrics));
+                return ProcessingResult::Executed(pending.finish(
+                    mem,
+                    Ok(0),
+                    block_metrics,
+                    error_reporter,
+                ));
             }
         };
 
         match res {
             Ok(block_io::FileEngineOk::Submitted) => ProcessingResult::Submitted,
-            Ok(block_io::FileEngineOk::Executed(res)) => {
-                ProcessingResult::Executed(res.user_data.finish(mem, Ok(res.count), block_metrics))
-            }
+            Ok(block_io::FileEngineOk::Executed(res)) => ProcessingResult::Executed(
+                res.user_data
+                    .finish(mem, Ok(res.count), block_metrics, error_reporter),
+            ),
             Err(err) => {
                 if err.error.is_throttling_err() {
                     ProcessingResult::Throttled
@@ -408,6 +505,7 @@ impl Request {
                         mem,
                         Err(IoErr::FileEngine(err.error)),
                         block_metrics,
+                        error_reporter,
                     ))
                 }
             }
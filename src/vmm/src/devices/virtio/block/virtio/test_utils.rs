@@ -12,7 +12,7 @@ use vmm_sys_util::tempfile::TempFile;
 
 use super::device::VirtioBlockConfig;
 use super::RequestHeader;
-use crate::devices::virtio::block::virtio::device::FileEngineType;
+use crate::devices::virtio::block::virtio::device::{DriveBackend, FileEngineType};
 #[cfg(test)]
 use crate::devices::virtio::block::virtio::io::FileEngine;
 use crate::devices::virtio::block::virtio::{CacheType, VirtioBlock};
@@ -54,8 +54,15 @@ pub fn default_block_with_path(path: String, file_engine_type: FileEngineType) -
                 one_time_burst: Some(0),
                 refill_time: 10,
             }),
+            borrow: None,
+            smoothing: None,
         }),
         file_engine_type,
+        remote: None,
+        direct_io: false,
+        backend: DriveBackend::File,
+        latency_injection: None,
+        shared_rate_limiter: None,
     };
 
     // The default block device is read-write and non-root.
@@ -16,7 +16,7 @@ use super::queue::{Queue, QueueError};
 use super::ActivateError;
 use crate::devices::virtio::AsAny;
 use crate::logger::{error, warn};
-use crate::vstate::memory::GuestMemoryMmap;
+use crate::vstate::memory::{GuestAddress, GuestMemoryMmap};
 
 /// Enum that indicates if a VirtioDevice is inactive or has been activated
 /// and memory attached to it.
@@ -84,6 +84,16 @@ impl IrqTrigger {
     }
 }
 
+/// Shared memory region exposed by a virtio device to the driver, e.g. the DAX window through
+/// which a virtio-fs device maps file extents directly in the guest address space.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VirtioShmRegion {
+    /// Guest physical address of the region.
+    pub addr: GuestAddress,
+    /// Length of the region in bytes.
+    pub len: u64,
+}
+
 /// Trait for virtio devices to be driven by a virtio transport.
 ///
 /// The lifecycle of a virtio device is to be moved to a virtio transport, which will then query the
@@ -181,6 +191,11 @@ pub trait VirtioDevice: AsAny + Send {
         None
     }
 
+    /// Returns the shared memory region with the given id, if the device exposes one.
+    fn shm_region(&self, _id: u32) -> Option<VirtioShmRegion> {
+        None
+    }
+
     /// Mark pages used by queues as dirty.
     fn mark_queue_memory_dirty(&self, mem: &GuestMemoryMmap) -> Result<(), QueueError> {
         for queue in self.queues() {
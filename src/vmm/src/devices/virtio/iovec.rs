@@ -419,6 +419,59 @@ impl<const L: u16> IoVecBufferMut<L> {
         }
     }
 
+    /// Reads up to `len` bytes from the `IoVecBufferMut` starting at the given offset.
+    ///
+    /// This will try to write to the given [`WriteVolatile`].
+    pub fn read_volatile_at<W: WriteVolatile>(
+        &self,
+        dst: &mut W,
+        mut offset: usize,
+        mut len: usize,
+    ) -> Result<usize, VolatileMemoryError> {
+        let mut total_bytes_read = 0;
+
+        for iov in self.vecs.as_slice() {
+            if len == 0 {
+                break;
+            }
+
+            if offset >= iov.iov_len {
+                offset -= iov.iov_len;
+                continue;
+            }
+
+            let mut slice =
+                // SAFETY: the constructor IoVecBufferMut::from_descriptor_chain ensures that
+                // all iovecs contained point towards valid ranges of guest memory
+                unsafe { VolatileSlice::new(iov.iov_base.cast(), iov.iov_len).offset(offset)? };
+            offset = 0;
+
+            if slice.len() > len {
+                slice = slice.subslice(0, len)?;
+            }
+
+            let bytes_read = loop {
+                match dst.write_volatile(&slice) {
+                    Err(VolatileMemoryError::IOError(err))
+                        if err.kind() == ErrorKind::Interrupted =>
+                    {
+                        continue
+                    }
+                    Ok(bytes_read) => break bytes_read,
+                    Err(volatile_memory_error) => return Err(volatile_memory_error),
+                }
+            };
+            total_bytes_read += bytes_read;
+
+            if bytes_read < slice.len() {
+                break;
+            }
+            len -= bytes_read;
+        }
+
+        Ok(total_bytes_read)
+    }
+
     /// Writes up to `len` bytes into the `IoVecBuffer` starting at the given offset.
     ///
     /// This will try to write to the given [`WriteVolatile`].
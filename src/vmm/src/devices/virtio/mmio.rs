@@ -30,6 +30,10 @@ const MMIO_MAGIC_VALUE: u32 = 0x7472_6976;
 // current version specified by the mmio standard (legacy devices used 1 here)
 const MMIO_VERSION: u32 = 2;
 
+// value of the shared memory region length and base registers when the selected region does not
+// exist
+const SHM_REGION_NONE: u64 = u64::MAX;
+
 /// Implements the
 /// [MMIO](http://docs.oasis-open.org/virtio/virtio/v1.0/cs04/virtio-v1.0-cs04.html#x1-1090002)
 /// transport for virtio devices.
@@ -38,7 +42,8 @@ const MMIO_VERSION: u32 = 2;
 ///
 /// 1. Mmio reads and writes must be sent to this device at what is referred to here as MMIO base.
 /// 1. `Mmio::queue_evts` must be installed at `virtio::NOTIFY_REG_OFFSET` offset from the MMIO
-///    base. Each event in the array must be signaled if the index is written at that offset.
+///    base. Each event in the array must be signaled if the index is written at that offset. Until
+///    they are installed, the writes at that offset reaching the transport signal the events.
 /// 1. `Mmio::interrupt_evt` must signal an interrupt that the guest driver is listening to when it
 ///    is written to.
 ///
@@ -52,6 +57,8 @@ pub struct MmioTransport {
     // The register where features page is selected.
     pub(crate) acked_features_select: u32,
     pub(crate) queue_select: u32,
+    // The register where the shared memory region is selected.
+    pub(crate) shm_select: u32,
     pub(crate) device_status: u32,
     pub(crate) config_generation: u32,
     mem: GuestMemoryMmap,
@@ -73,6 +80,7 @@ impl MmioTransport {
             features_select: 0,
             acked_features_select: 0,
             queue_select: 0,
+            shm_select: 0,
             device_status: device_status::INIT,
             config_generation: 0,
             mem,
@@ -280,6 +288,19 @@ impl MmioTransport {
                         }
                     }
                     0x70 => self.device_status,
+                    0xb0..=0xbc => {
+                        let region = self.locked_device().shm_region(self.shm_select);
+                        let v = match offset {
+                            0xb0 | 0xb4 => region.map_or(SHM_REGION_NONE, |r| r.len),
+                            _ => region.map_or(SHM_REGION_NONE, |r| r.addr.0),
+                        };
+                        // Low and high halves of the 64-bit registers.
+                        if offset & 0x4 == 0 {
+                            (v & 0xffff_ffff) as u32
+                        } else {
+                            (v >> 32) as u32
+                        }
+                    }
                     0xfc => self.config_generation,
                     _ => {
                         warn!("unknown virtio mmio register read: {:#x}", offset);
@@ -329,6 +350,17 @@ impl MmioTransport {
                     0x30 => self.queue_select = v,
                     0x38 => self.update_queue_field(|q| q.size = (v & 0xffff) as u16),
                     0x44 => self.update_queue_field(|q| q.ready = v == 1),
+                    0x50 => {
+                        let locked_device = self.locked_device();
+                        match locked_device.queue_events().get(v as usize) {
+                            Some(queue_evt) => {
+                                if let Err(err) = queue_evt.write(1) {
+                                    error!("Failed to notify virtio queue {}: {}", v, err);
+                                }
+                            }
+                            None => warn!("invalid virtio queue notification: {:#x}", v),
+                        }
+                    }
                     0x64 => {
                         if self.check_device_status(device_status::DRIVER_OK, 0) {
                             self.interrupt_status.fetch_and(!v, Ordering::SeqCst);
@@ -341,6 +373,7 @@ impl MmioTransport {
                     0x94 => self.update_queue_field(|q| hi(&mut q.avail_ring_address, v)),
                     0xa0 => self.update_queue_field(|q| lo(&mut q.used_ring_address, v)),
                     0xa4 => self.update_queue_field(|q| hi(&mut q.used_ring_address, v)),
+                    0xac => self.shm_select = v,
                     _ => {
                         warn!("unknown virtio mmio register write: {:#x}", offset);
                     }
@@ -368,7 +401,7 @@ pub(crate) mod tests {
     use vmm_sys_util::eventfd::EventFd;
 
     use super::*;
-    use crate::devices::virtio::device::IrqTrigger;
+    use crate::devices::virtio::device::{IrqTrigger, VirtioShmRegion};
     use crate::devices::virtio::device_status::DEVICE_NEEDS_RESET;
     use crate::devices::virtio::ActivateError;
     use crate::test_utils::single_region_mem;
@@ -386,6 +419,7 @@ pub(crate) mod tests {
         device_activated: bool,
         config_bytes: [u8; 0xeff],
         activate_should_error: bool,
+        shm_regions: Vec<VirtioShmRegion>,
     }
 
     impl DummyDevice {
@@ -402,6 +436,7 @@ pub(crate) mod tests {
                 device_activated: false,
                 config_bytes: [0; 0xeff],
                 activate_should_error: false,
+                shm_regions: Vec::new(),
             }
         }
 
@@ -465,6 +500,10 @@ pub(crate) mod tests {
         fn is_activated(&self) -> bool {
             self.device_activated
         }
+
+        fn shm_region(&self, id: u32) -> Option<VirtioShmRegion> {
+            self.shm_regions.get(id as usize).copied()
+        }
     }
 
     fn set_device_status(d: &mut MmioTransport, status: u32) {
@@ -756,6 +795,58 @@ pub(crate) mod tests {
         }
     }
 
+    #[test]
+    fn test_bus_device_shm_regions() {
+        let m = single_region_mem(0x1000);
+        let mut dummy = DummyDevice::new();
+        dummy.shm_regions.push(VirtioShmRegion {
+            addr: GuestAddress(0x1_2345_6000),
+            len: 0x4000_0000,
+        });
+        let mut d = MmioTransport::new(m, Arc::new(Mutex::new(dummy)), false);
+        let mut buf = [0; 4];
+
+        let mut read_reg = |d: &mut MmioTransport, offset| {
+            d.bus_read(offset, &mut buf[..]);
+            read_le_u32(&buf[..])
+        };
+
+        // Region 0 is selected by default.
+        assert_eq!(read_reg(&mut d, 0xb0), 0x4000_0000);
+        assert_eq!(read_reg(&mut d, 0xb4), 0);
+        assert_eq!(read_reg(&mut d, 0xb8), 0x2345_6000);
+        assert_eq!(read_reg(&mut d, 0xbc), 0x1);
+
+        // Selecting a region that does not exist reads back all ones.
+        let mut select = [0; 4];
+        write_le_u32(&mut select[..], 1);
+        d.bus_write(0xac, &select[..]);
+        assert_eq!(d.shm_select, 1);
+        for offset in [0xb0, 0xb4, 0xb8, 0xbc] {
+            assert_eq!(read_reg(&mut d, offset), u32::MAX);
+        }
+    }
+
+    #[test]
+    fn test_bus_device_queue_notify() {
+        let m = single_region_mem(0x1000);
+        let dummy_dev = Arc::new(Mutex::new(DummyDevice::new()));
+        let mut d = MmioTransport::new(m, dummy_dev.clone(), false);
+        let mut buf = vec![0; 4];
+
+        // Writing the index of a queue at the notify register signals its event.
+        write_le_u32(&mut buf[..], 1);
+        d.bus_write(0x50, &buf[..]);
+        dummy_dev.lock().unwrap().queue_evts[0].read().unwrap_err();
+        assert_eq!(dummy_dev.lock().unwrap().queue_evts[1].read().unwrap(), 1);
+
+        // Unknown queues are ignored.
+        write_le_u32(&mut buf[..], 2);
+        d.bus_write(0x50, &buf[..]);
+        dummy_dev.lock().unwrap().queue_evts[0].read().unwrap_err();
+        dummy_dev.lock().unwrap().queue_evts[1].read().unwrap_err();
+    }
+
     #[test]
     fn test_bus_device_activate() {
         let m = single_region_mem(0x1000);
@@ -72,6 +72,8 @@ pub enum ActivateError {
     VhostUser(vhost_user::VhostUserError),
     /// Setting tap interface offload flags failed: {0}
     TapSetOffload(TapError),
+    /// Attaching or detaching the tap interface queues failed: {0}
+    TapSetQueue(TapError),
     /// Error setting pointers in the queue: (0)
     QueueMemoryError(QueueError),
 }
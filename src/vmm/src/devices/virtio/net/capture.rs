@@ -0,0 +1,291 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Capture of the frames exchanged by the guest, written in the pcap format to a host file or
+//! fifo.
+//!
+//! The frames are captured as seen by the guest, including those exchanged with the MMDS, which
+//! never reach the tap device. They are buffered in a bounded ring, flushed without blocking after
+//! each batch of frames, so that a slow reader of the capture never stalls the device: the frames
+//! which do not fit in the ring are dropped from the capture.
+
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use libc::{EAGAIN, O_NONBLOCK};
+
+use super::device::vnet_hdr_len;
+use super::metrics::NetDeviceMetrics;
+use super::{MAX_BUFFER_SIZE, NET_QUEUE_MAX_SIZE};
+use crate::devices::virtio::iovec::{IoVecBuffer, IoVecBufferMut};
+use crate::logger::IncMetric;
+
+/// Default maximum number of bytes captured of each frame.
+pub const DEFAULT_SNAPLEN: u32 = 65535;
+/// Default size in bytes of the ring buffering the capture.
+pub const DEFAULT_RING_SIZE: usize = 1 << 20;
+/// Maximum size in bytes of the ring buffering the capture.
+pub const MAX_RING_SIZE: usize = 64 << 20;
+
+const PCAP_MAGIC: u32 = 0xa1b2_c3d4;
+const PCAP_VERSION_MAJOR: u16 = 2;
+const PCAP_VERSION_MINOR: u16 = 4;
+const LINKTYPE_ETHERNET: u32 = 1;
+const PCAP_HEADER_LEN: usize = 24;
+const PCAP_RECORD_HEADER_LEN: usize = 16;
+
+/// Errors triggered when starting a packet capture.
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum CaptureError {
+    /// Invalid snapshot length of {0} bytes: it must be between 1 and 65562 bytes.
+    InvalidSnaplen(u32),
+    /// Invalid ring size of {0} bytes: it must hold a captured frame, and be at most 64 MiB.
+    InvalidRingSize(usize),
+    /// Cannot open the capture file: {0}
+    Open(io::Error),
+}
+
+/// Captures the frames exchanged by the guest to a pcap file or fifo.
+#[derive(Debug)]
+pub struct PacketCapture {
+    file: File,
+    snaplen: usize,
+    ring_size: usize,
+    ring: VecDeque<u8>,
+    frame: Vec<u8>,
+    metrics: Arc<NetDeviceMetrics>,
+}
+
+impl PacketCapture {
+    /// Opens the file or fifo at `path` to capture at most `snaplen` bytes of each frame,
+    /// buffering at most `ring_size` bytes of the capture until they are written.
+    pub fn open(
+        path: &Path,
+        snaplen: u32,
+        ring_size: usize,
+        metrics: Arc<NetDeviceMetrics>,
+    ) -> Result<Self, CaptureError> {
+        if snaplen == 0 || snaplen as usize > MAX_BUFFER_SIZE {
+            return Err(CaptureError::InvalidSnaplen(snaplen));
+        }
+        let snaplen = snaplen as usize;
+        if ring_size < PCAP_HEADER_LEN + PCAP_RECORD_HEADER_LEN + snaplen
+            || ring_size > MAX_RING_SIZE
+        {
+            return Err(CaptureError::InvalidRingSize(ring_size));
+        }
+        // The file is opened for reading as well, so that opening a fifo does not fail when it
+        // has no reader yet.
+        let file = OpenOptions::new()
+            .custom_flags(O_NONBLOCK)
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+            .map_err(CaptureError::Open)?;
+
+        let mut ring = VecDeque::with_capacity(ring_size);
+        ring.extend(PCAP_MAGIC.to_le_bytes());
+        ring.extend(PCAP_VERSION_MAJOR.to_le_bytes());
+        ring.extend(PCAP_VERSION_MINOR.to_le_bytes());
+        // The timestamps are in UTC, with no accuracy information.
+        ring.extend(0i32.to_le_bytes());
+        ring.extend(0u32.to_le_bytes());
+        ring.extend(u32::try_from(snaplen).unwrap().to_le_bytes());
+        ring.extend(LINKTYPE_ETHERNET.to_le_bytes());
+
+        Ok(PacketCapture {
+            file,
+            snaplen,
+            ring_size,
+            ring,
+            frame: vec![0u8; snaplen],
+            metrics,
+        })
+    }
+
+    /// Captures a frame sent by the guest, including its VirtIO header.
+    pub fn capture_tx(&mut self, frame: &IoVecBuffer) {
+        let len = (frame.len() as usize).saturating_sub(vnet_hdr_len());
+        let incl_len = frame
+            .read_volatile_at(
+                &mut &mut self.frame[..],
+                vnet_hdr_len(),
+                len.min(self.snaplen),
+            )
+            .unwrap_or(0);
+        self.record(incl_len, len);
+    }
+
+    /// Captures a frame of `len` bytes received by the guest, including its VirtIO header, which
+    /// was written at the beginning of `frame`.
+    pub fn capture_rx(&mut self, frame: &IoVecBufferMut<NET_QUEUE_MAX_SIZE>, len: usize) {
+        let len = len.saturating_sub(vnet_hdr_len());
+        let incl_len = frame
+            .read_volatile_at(
+                &mut &mut self.frame[..],
+                vnet_hdr_len(),
+                len.min(self.snaplen),
+            )
+            .unwrap_or(0);
+        self.record(incl_len, len);
+    }
+
+    // Adds the record of a frame of `len` bytes, whose first `incl_len` bytes are in
+    // `self.frame`, to the ring.
+    fn record(&mut self, incl_len: usize, len: usize) {
+        if self.ring.len() + PCAP_RECORD_HEADER_LEN + incl_len > self.ring_size {
+            self.metrics.capture_dropped_frames.inc();
+            return;
+        }
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        // The seconds of the timestamps wrap around in 2106, as defined by the format.
+        #[allow(clippy::cast_possible_truncation)]
+        self.ring.extend((timestamp.as_secs() as u32).to_le_bytes());
+        self.ring.extend(timestamp.subsec_micros().to_le_bytes());
+        // Both lengths are bounded by `MAX_BUFFER_SIZE`.
+        self.ring
+            .extend(u32::try_from(incl_len).unwrap().to_le_bytes());
+        self.ring.extend(u32::try_from(len).unwrap().to_le_bytes());
+        self.ring.extend(&self.frame[..incl_len]);
+        self.metrics.capture_frames.inc();
+    }
+
+    /// Writes as much of the capture as possible without blocking. Fails if the capture can no
+    /// longer be written, e.g. when the reader of a fifo went away.
+    pub fn flush(&mut self) -> io::Result<()> {
+        while !self.ring.is_empty() {
+            let (front, _) = self.ring.as_slices();
+            match self.file.write(front) {
+                Ok(0) => return Err(io::Error::from(io::ErrorKind::WriteZero)),
+                Ok(written) => {
+                    self.ring.drain(..written);
+                }
+                Err(err) if err.raw_os_error() == Some(EAGAIN) => break,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the number of bytes of the capture waiting to be written.
+    pub fn pending_bytes(&self) -> usize {
+        self.ring.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use vmm_sys_util::tempfile::TempFile;
+
+    use super::*;
+    use crate::devices::virtio::net::metrics::NetMetricsPerDevice;
+
+    // Returns a frame sent by the guest, prefixed with its VirtIO header.
+    fn tx_frame(payload: &[u8]) -> Vec<u8> {
+        let mut buf = vec![0u8; vnet_hdr_len()];
+        buf.extend_from_slice(payload);
+        buf
+    }
+
+    fn read_capture(file: &TempFile) -> Vec<u8> {
+        let mut data = Vec::new();
+        File::open(file.as_path())
+            .unwrap()
+            .read_to_end(&mut data)
+            .unwrap();
+        data
+    }
+
+    #[test]
+    fn test_open_capture() {
+        let file = TempFile::new().unwrap();
+        let metrics = NetMetricsPerDevice::alloc("capture_open".to_string());
+        assert!(matches!(
+            PacketCapture::open(file.as_path(), 0, DEFAULT_RING_SIZE, metrics.clone()),
+            Err(CaptureError::InvalidSnaplen(0))
+        ));
+        assert!(matches!(
+            PacketCapture::open(file.as_path(), 128, 64, metrics.clone()),
+            Err(CaptureError::InvalidRingSize(64))
+        ));
+        assert!(matches!(
+            PacketCapture::open(file.as_path(), 128, MAX_RING_SIZE + 1, metrics.clone()),
+            Err(CaptureError::InvalidRingSize(_))
+        ));
+        assert!(matches!(
+            PacketCapture::open(Path::new("/no/such/dir/capture.pcap"), 128, 4096, metrics),
+            Err(CaptureError::Open(_))
+        ));
+    }
+
+    #[test]
+    fn test_capture_frames() {
+        let file = TempFile::new().unwrap();
+        let metrics = NetMetricsPerDevice::alloc("capture_frames".to_string());
+        let mut capture = PacketCapture::open(file.as_path(), 4, 4096, metrics.clone()).unwrap();
+
+        capture.capture_tx(&IoVecBuffer::from(&tx_frame(&[1, 2, 3, 4, 5, 6])[..]));
+        capture.capture_tx(&IoVecBuffer::from(&tx_frame(&[7, 8])[..]));
+        capture.flush().unwrap();
+        assert_eq!(capture.pending_bytes(), 0);
+        assert_eq!(metrics.capture_frames.count(), 2);
+
+        let data = read_capture(&file);
+        assert_eq!(
+            data.len(),
+            PCAP_HEADER_LEN + 2 * PCAP_RECORD_HEADER_LEN + 4 + 2
+        );
+        assert_eq!(data[..4], PCAP_MAGIC.to_le_bytes());
+        // Snapshot length and link type.
+        assert_eq!(data[16..20], 4u32.to_le_bytes());
+        assert_eq!(data[20..24], LINKTYPE_ETHERNET.to_le_bytes());
+
+        // The first frame is truncated to the snapshot length.
+        let record = &data[PCAP_HEADER_LEN..];
+        assert_eq!(record[8..12], 4u32.to_le_bytes());
+        assert_eq!(record[12..16], 6u32.to_le_bytes());
+        assert_eq!(record[16..20], [1, 2, 3, 4]);
+        let record = &record[PCAP_RECORD_HEADER_LEN + 4..];
+        assert_eq!(record[8..12], 2u32.to_le_bytes());
+        assert_eq!(record[12..16], 2u32.to_le_bytes());
+        assert_eq!(record[16..], [7, 8]);
+    }
+
+    #[test]
+    fn test_capture_ring_full() {
+        let file = TempFile::new().unwrap();
+        let metrics = NetMetricsPerDevice::alloc("capture_ring_full".to_string());
+        let ring_size = PCAP_HEADER_LEN + PCAP_RECORD_HEADER_LEN + 8;
+        let mut capture =
+            PacketCapture::open(file.as_path(), 8, ring_size, metrics.clone()).unwrap();
+
+        // Only the first frame fits in the ring until it is flushed.
+        let buf = tx_frame(&[0xaa; 8]);
+        let frame = IoVecBuffer::from(&buf[..]);
+        capture.capture_tx(&frame);
+        capture.capture_tx(&frame);
+        assert_eq!(metrics.capture_frames.count(), 1);
+        assert_eq!(metrics.capture_dropped_frames.count(), 1);
+
+        capture.flush().unwrap();
+        capture.capture_tx(&frame);
+        assert_eq!(metrics.capture_frames.count(), 2);
+        capture.flush().unwrap();
+        assert_eq!(
+            read_capture(&file).len(),
+            PCAP_HEADER_LEN + 2 * (PCAP_RECORD_HEADER_LEN + 8)
+        );
+    }
+}
@@ -0,0 +1,429 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Coalesces the TCP segments received for the guest, like the generic receive offload of Linux.
+//!
+//! Consecutive in-order segments of the same TCP over IPv4 flow read from the tap are merged into
+//! a single large segment, which the guest receives as a GSO frame with a partial checksum. The
+//! guest then handles one frame instead of many, which reduces its per-packet processing for high
+//! bandwidth flows. Only segments carrying data, without options in their IP header and with no
+//! other TCP flag than ACK and PSH, are merged, and only if their checksums are known to be valid.
+
+use std::io;
+use std::mem::{self, offset_of};
+
+use super::device::vnet_hdr_len;
+use super::MAX_BUFFER_SIZE;
+use crate::devices::virtio::gen::virtio_net::virtio_net_hdr_v1;
+use crate::dumbo::pdu::ethernet::{EthernetFrame, ETHERTYPE_IPV4, PAYLOAD_OFFSET};
+use crate::dumbo::pdu::ipv4::{IPv4Packet, PROTOCOL_TCP};
+use crate::dumbo::pdu::tcp::{Flags as TcpFlags, TcpSegment};
+
+/// Maximum number of segments merged into a single frame.
+pub const MAX_COALESCED_SEGMENTS: usize = 64;
+
+// VirtIO header flags and GSO types, from the VirtIO specification.
+const VIRTIO_NET_HDR_F_NEEDS_CSUM: u8 = 1;
+const VIRTIO_NET_HDR_F_DATA_VALID: u8 = 2;
+const VIRTIO_NET_HDR_GSO_NONE: u8 = 0;
+const VIRTIO_NET_HDR_GSO_TCPV4: u8 = 1;
+
+const IPV4_HEADER_LEN: usize = 20;
+const IPV4_FLAG_MORE_FRAGMENTS: u8 = 1;
+const TCP_HEADER_MIN_LEN: usize = 20;
+const TCP_FLAGS_OFFSET: usize = 13;
+const TCP_CHECKSUM_OFFSET: usize = 16;
+
+// Offsets of the IP and TCP headers in a frame, including its VirtIO header.
+const IP_OFFSET: usize = vnet_hdr_len() + PAYLOAD_OFFSET;
+const TCP_OFFSET: usize = IP_OFFSET + IPV4_HEADER_LEN;
+
+/// Segment which can be merged with the following segments of its flow.
+#[derive(Debug)]
+struct Segment {
+    /// Length of the headers, including the VirtIO header.
+    headers_len: usize,
+    seq: u32,
+    payload_len: usize,
+    push: bool,
+}
+
+/// Frame being built by merging segments.
+#[derive(Debug)]
+struct MergedFrame {
+    len: usize,
+    headers_len: usize,
+    /// Payload length of the first segment, which all the merged segments but the last match.
+    mss: usize,
+    next_seq: u32,
+    segments: usize,
+    /// Whether the last merged segment ends the frame, because it is shorter than the first one or
+    /// has the PSH flag.
+    last: bool,
+}
+
+/// Merges consecutive TCP segments of the same flow received for the guest.
+#[derive(Debug)]
+pub struct RxCoalescer {
+    max_segments: usize,
+    /// Frame returned to the device, including its VirtIO header.
+    frame: Vec<u8>,
+    /// Frame read from the tap which could not be merged, returned next.
+    pending: Vec<u8>,
+    pending_len: usize,
+}
+
+impl RxCoalescer {
+    /// Creates a coalescer merging at most `max_segments` segments into a frame.
+    pub fn new(max_segments: usize) -> Self {
+        RxCoalescer {
+            max_segments,
+            frame: vec![0u8; MAX_BUFFER_SIZE],
+            pending: vec![0u8; MAX_BUFFER_SIZE],
+            pending_len: 0,
+        }
+    }
+
+    /// Returns the maximum number of segments merged into a frame.
+    pub fn max_segments(&self) -> usize {
+        self.max_segments
+    }
+
+    /// Returns the next frame for the guest, including its VirtIO header, and the number of
+    /// segments merged into it. The frames are read with `read`, which fails once no frame is
+    /// left, and the first frame which can't be merged is kept for the next call.
+    pub fn read_frame<F>(&mut self, mut read: F) -> io::Result<(&[u8], usize)>
+    where
+        F: FnMut(&mut [u8]) -> io::Result<usize>,
+    {
+        let len = if self.pending_len != 0 {
+            mem::swap(&mut self.frame, &mut self.pending);
+            mem::take(&mut self.pending_len)
+        } else {
+            read(&mut self.frame)?
+        };
+        let Some(first) = parse_segment(&self.frame[..len]) else {
+            return Ok((&self.frame[..len], 1));
+        };
+
+        let mut merged = MergedFrame {
+            len: first.headers_len + first.payload_len,
+            headers_len: first.headers_len,
+            mss: first.payload_len,
+            next_seq: first.seq.wrapping_add(payload_len_u32(first.payload_len)),
+            segments: 1,
+            last: first.push,
+        };
+        while !merged.last && merged.segments < self.max_segments {
+            // A failed read, including when the tap is drained, fails again on the next call.
+            let Ok(next_len) = read(&mut self.pending) else {
+                break;
+            };
+            self.pending_len = next_len;
+            if !self.merge_pending(&mut merged) {
+                break;
+            }
+            self.pending_len = 0;
+        }
+
+        if merged.segments > 1 {
+            self.finalize(&merged);
+        } else {
+            // The frame is returned as read, including any Ethernet padding.
+            merged.len = len;
+        }
+        Ok((&self.frame[..merged.len], merged.segments))
+    }
+
+    // Appends the payload of the pending frame to the merged frame, if it is the next segment of
+    // its flow. Returns whether it was merged.
+    fn merge_pending(&mut self, merged: &mut MergedFrame) -> bool {
+        let next = &self.pending[..self.pending_len];
+        let Some(segment) = parse_segment(next) else {
+            return false;
+        };
+        if segment.headers_len != merged.headers_len
+            || segment.seq != merged.next_seq
+            || segment.payload_len > merged.mss
+            || merged.len + segment.payload_len - IP_OFFSET > usize::from(u16::MAX)
+            || !same_flow(&self.frame, next, merged.headers_len)
+        {
+            return false;
+        }
+
+        let payload = &next[segment.headers_len..segment.headers_len + segment.payload_len];
+        self.frame[merged.len..merged.len + payload.len()].copy_from_slice(payload);
+        merged.len += payload.len();
+        merged.next_seq = segment.seq.wrapping_add(payload_len_u32(payload.len()));
+        merged.segments += 1;
+        merged.last = segment.push || segment.payload_len < merged.mss;
+        if segment.push {
+            self.frame[TCP_OFFSET + TCP_FLAGS_OFFSET] |= TcpFlags::PSH.bits();
+        }
+        true
+    }
+
+    // Turns the merged frame into a GSO frame with a partial checksum.
+    fn finalize(&mut self, merged: &MergedFrame) {
+        let frame = &mut self.frame[..merged.len];
+        // The length was checked when merging the segments.
+        let ip_len = u16::try_from(merged.len - IP_OFFSET).unwrap();
+        let mut packet = IPv4Packet::from_bytes_unchecked(&mut frame[IP_OFFSET..]);
+        packet.set_total_len(ip_len).set_header_checksum(0);
+        let checksum = packet.compute_checksum_unchecked(IPV4_HEADER_LEN);
+        packet.set_header_checksum(checksum);
+
+        // With a partial checksum, the TCP checksum field holds the checksum of the pseudo header,
+        // which the guest completes with the checksum of the segment.
+        let tcp_len = ip_len - u16::try_from(IPV4_HEADER_LEN).unwrap();
+        let mut sum = u32::from(PROTOCOL_TCP) + u32::from(tcp_len);
+        for word in frame[IP_OFFSET + 12..TCP_OFFSET].chunks_exact(2) {
+            sum += u32::from(u16::from_be_bytes([word[0], word[1]]));
+        }
+        while sum >> 16 != 0 {
+            sum = (sum & 0xffff) + (sum >> 16);
+        }
+        let pseudo_header_checksum = u16::try_from(sum).unwrap();
+        frame[TCP_OFFSET + TCP_CHECKSUM_OFFSET..TCP_OFFSET + TCP_CHECKSUM_OFFSET + 2]
+            .copy_from_slice(&pseudo_header_checksum.to_be_bytes());
+
+        // All the header lengths are bounded by the size of the frame headers.
+        let to_le = |value: usize| u16::try_from(value).unwrap().to_le_bytes();
+        let csum_start = offset_of!(virtio_net_hdr_v1, __bindgen_anon_1);
+        frame[offset_of!(virtio_net_hdr_v1, flags)] = VIRTIO_NET_HDR_F_NEEDS_CSUM;
+        frame[offset_of!(virtio_net_hdr_v1, gso_type)] = VIRTIO_NET_HDR_GSO_TCPV4;
+        for (offset, value) in [
+            (
+                offset_of!(virtio_net_hdr_v1, hdr_len),
+                merged.headers_len - vnet_hdr_len(),
+            ),
+            (offset_of!(virtio_net_hdr_v1, gso_size), merged.mss),
+            (csum_start, TCP_OFFSET - vnet_hdr_len()),
+            (csum_start + 2, TCP_CHECKSUM_OFFSET),
+        ] {
+            frame[offset..offset + 2].copy_from_slice(&to_le(value));
+        }
+    }
+}
+
+// The payload of a segment is bounded by the size of an IPv4 packet.
+fn payload_len_u32(len: usize) -> u32 {
+    u32::try_from(len).unwrap()
+}
+
+// Returns the segment carried by a frame, including its VirtIO header, if it can be merged.
+fn parse_segment(frame: &[u8]) -> Option<Segment> {
+    if frame.len() < TCP_OFFSET + TCP_HEADER_MIN_LEN {
+        return None;
+    }
+    let vnet_flags = frame[offset_of!(virtio_net_hdr_v1, flags)];
+    if frame[offset_of!(virtio_net_hdr_v1, gso_type)] != VIRTIO_NET_HDR_GSO_NONE {
+        return None;
+    }
+    // Segments with a partial checksum come from the host itself, so only the other segments
+    // have to be checked, unless the host already did.
+    let checksum_valid =
+        vnet_flags & (VIRTIO_NET_HDR_F_NEEDS_CSUM | VIRTIO_NET_HDR_F_DATA_VALID) != 0;
+
+    let eth_frame = EthernetFrame::from_bytes(&frame[vnet_hdr_len()..]).ok()?;
+    if eth_frame.ethertype() != ETHERTYPE_IPV4 {
+        return None;
+    }
+    let eth_payload = &frame[IP_OFFSET..];
+    let total_len = usize::from(IPv4Packet::from_bytes_unchecked(eth_payload).total_len());
+    let packet = IPv4Packet::from_bytes(eth_payload.get(..total_len)?, true).ok()?;
+    let (ip_flags, fragment_offset) = packet.flags_and_fragment_offset();
+    if usize::from(packet.header_len()) != IPV4_HEADER_LEN
+        || packet.protocol() != PROTOCOL_TCP
+        || ip_flags & IPV4_FLAG_MORE_FRAGMENTS != 0
+        || fragment_offset != 0
+    {
+        return None;
+    }
+
+    let addresses =
+        (!checksum_valid).then(|| (packet.source_address(), packet.destination_address()));
+    let segment = TcpSegment::from_bytes(packet.payload(), addresses).ok()?;
+    let flags = segment.flags_after_ns();
+    if (flags != TcpFlags::ACK && flags != TcpFlags::ACK | TcpFlags::PSH)
+        || segment.payload_len() == 0
+    {
+        return None;
+    }
+    Some(Segment {
+        headers_len: TCP_OFFSET + usize::from(segment.header_len()),
+        seq: segment.sequence_number(),
+        payload_len: usize::from(segment.payload_len()),
+        push: flags.contains(TcpFlags::PSH),
+    })
+}
+
+// Returns whether the segment in `next` belongs to the same flow as the one in `first`, with the
+// same headers, except for the fields which differ between consecutive segments: the IP length,
+// identification and checksum, and the TCP sequence number, PSH flag and checksum.
+fn same_flow(first: &[u8], next: &[u8], headers_len: usize) -> bool {
+    let same = |start: usize, end: usize| first[start..end] == next[start..end];
+    // Ethernet header, IP version, header length, DSCP and ECN.
+    same(vnet_hdr_len(), IP_OFFSET + 2)
+        // IP fragmentation, TTL and protocol.
+        && same(IP_OFFSET + 6, IP_OFFSET + 10)
+        // IP addresses and TCP ports.
+        && same(IP_OFFSET + 12, TCP_OFFSET + 4)
+        // TCP acknowledgement number and header length.
+        && same(TCP_OFFSET + 8, TCP_OFFSET + TCP_FLAGS_OFFSET)
+        && first[TCP_OFFSET + TCP_FLAGS_OFFSET]
+            == next[TCP_OFFSET + TCP_FLAGS_OFFSET] & !TcpFlags::PSH.bits()
+        // TCP window.
+        && same(TCP_OFFSET + 14, TCP_OFFSET + 16)
+        // TCP urgent pointer and options.
+        && same(TCP_OFFSET + 18, headers_len)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::net::Ipv4Addr;
+
+    use super::*;
+    use crate::utils::net::mac::MacAddr;
+
+    const SRC_ADDR: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+    const DST_ADDR: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 2);
+    const MSS: usize = 1000;
+
+    // Builds a frame carrying a TCP segment, with a valid checksum and the given VirtIO flags.
+    fn segment_frame(src_port: u16, seq: u32, payload: &[u8], flags: TcpFlags) -> Vec<u8> {
+        let mut frame = vec![0u8; TCP_OFFSET + TCP_HEADER_MIN_LEN + payload.len()];
+        let mut eth_frame = EthernetFrame::write_incomplete(
+            &mut frame[vnet_hdr_len()..],
+            MacAddr::from_bytes_unchecked(&[2, 0, 0, 0, 0, 2]),
+            MacAddr::from_bytes_unchecked(&[2, 0, 0, 0, 0, 1]),
+            ETHERTYPE_IPV4,
+        )
+        .unwrap();
+        let mut packet = IPv4Packet::write_header(
+            eth_frame.inner_mut().payload_mut(),
+            PROTOCOL_TCP,
+            SRC_ADDR,
+            DST_ADDR,
+        )
+        .unwrap();
+        let segment_len = TcpSegment::write_segment::<[u8]>(
+            packet.inner_mut().payload_mut(),
+            src_port,
+            80,
+            seq,
+            1,
+            flags,
+            1000,
+            None,
+            u16::MAX,
+            Some((payload, payload.len())),
+            Some((SRC_ADDR, DST_ADDR)),
+        )
+        .unwrap()
+        .len();
+        packet.with_payload_len_unchecked(segment_len, true);
+        frame
+    }
+
+    // Merges the given frames, returning the frames handed to the guest.
+    fn coalesce(coalescer: &mut RxCoalescer, frames: Vec<Vec<u8>>) -> Vec<(Vec<u8>, usize)> {
+        let mut frames = VecDeque::from(frames);
+        let mut read = |buf: &mut [u8]| -> io::Result<usize> {
+            let frame = frames
+                .pop_front()
+                .ok_or_else(|| io::Error::from(io::ErrorKind::WouldBlock))?;
+            buf[..frame.len()].copy_from_slice(&frame);
+            Ok(frame.len())
+        };
+        let mut output = Vec::new();
+        while let Ok((frame, segments)) = coalescer.read_frame(&mut read) {
+            output.push((frame.to_vec(), segments));
+        }
+        output
+    }
+
+    #[test]
+    fn test_coalesce_segments() {
+        let mut coalescer = RxCoalescer::new(MAX_COALESCED_SEGMENTS);
+        let payload: Vec<u8> = (0..MSS * 3)
+            .map(|i| u8::try_from(i % 251).unwrap())
+            .collect();
+        let frames = vec![
+            segment_frame(1234, 0, &payload[..MSS], TcpFlags::ACK),
+            segment_frame(1234, 1000, &payload[MSS..2 * MSS], TcpFlags::ACK),
+            segment_frame(1234, 2000, &payload[2 * MSS..2500], TcpFlags::ACK),
+            // Not merged after a short segment.
+            segment_frame(1234, 2500, &payload[2500..], TcpFlags::ACK | TcpFlags::PSH),
+            // Another flow.
+            segment_frame(4321, 0, &payload[..MSS], TcpFlags::ACK),
+            segment_frame(4321, 1000, &payload[..100], TcpFlags::ACK | TcpFlags::PSH),
+            // Out of order.
+            segment_frame(4321, 3000, &payload[..MSS], TcpFlags::ACK),
+            // Not a data segment.
+            segment_frame(4321, 4000, &[], TcpFlags::ACK),
+        ];
+        let output = coalesce(&mut coalescer, frames.clone());
+        let segments: Vec<usize> = output.iter().map(|(_, segments)| *segments).collect();
+        assert_eq!(segments, [3, 1, 2, 1, 1]);
+
+        let (frame, _) = &output[0];
+        assert_eq!(frame.len(), TCP_OFFSET + TCP_HEADER_MIN_LEN + 2500);
+        assert_eq!(frame[0], VIRTIO_NET_HDR_F_NEEDS_CSUM);
+        assert_eq!(frame[1], VIRTIO_NET_HDR_GSO_TCPV4);
+        assert_eq!(
+            u16::from_le_bytes([frame[2], frame[3]]),
+            u16::try_from(TCP_OFFSET + TCP_HEADER_MIN_LEN - vnet_hdr_len()).unwrap()
+        );
+        assert_eq!(
+            u16::from_le_bytes([frame[4], frame[5]]),
+            u16::try_from(MSS).unwrap()
+        );
+        let packet = IPv4Packet::from_bytes(&frame[IP_OFFSET..], true).unwrap();
+        let segment = TcpSegment::from_bytes(packet.payload(), None).unwrap();
+        assert_eq!(segment.sequence_number(), 0);
+        assert_eq!(segment.payload(), &payload[..2500]);
+
+        // The frames which are not merged are passed as is.
+        assert_eq!(output[1].0, frames[3]);
+        let (frame, _) = &output[2];
+        assert_eq!(
+            TcpSegment::from_bytes_unchecked(&frame[TCP_OFFSET..]).flags_after_ns(),
+            TcpFlags::ACK | TcpFlags::PSH
+        );
+        assert_eq!(output[3].0, frames[6]);
+        assert_eq!(output[4].0, frames[7]);
+    }
+
+    #[test]
+    fn test_coalesce_limits() {
+        let payload = vec![7u8; MSS];
+        let frames: Vec<_> = (0..5)
+            .map(|i| segment_frame(1234, i * 1000, &payload, TcpFlags::ACK))
+            .collect();
+        let mut coalescer = RxCoalescer::new(2);
+        assert_eq!(coalescer.max_segments(), 2);
+        let segments: Vec<usize> = coalesce(&mut coalescer, frames.clone())
+            .iter()
+            .map(|(_, segments)| *segments)
+            .collect();
+        assert_eq!(segments, [2, 2, 1]);
+
+        // Segments with an invalid checksum are not merged, unless the host validated them.
+        let mut corrupted = frames;
+        corrupted[1][TCP_OFFSET + TCP_HEADER_MIN_LEN] ^= 1;
+        let mut coalescer = RxCoalescer::new(MAX_COALESCED_SEGMENTS);
+        let segments: Vec<usize> = coalesce(&mut coalescer, corrupted.clone())
+            .iter()
+            .map(|(_, segments)| *segments)
+            .collect();
+        assert_eq!(segments, [1, 1, 3]);
+        corrupted[1][0] = VIRTIO_NET_HDR_F_DATA_VALID;
+        let segments: Vec<usize> = coalesce(&mut coalescer, corrupted)
+            .iter()
+            .map(|(_, segments)| *segments)
+            .collect();
+        assert_eq!(segments, [5]);
+    }
+}
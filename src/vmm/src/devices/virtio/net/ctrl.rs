@@ -0,0 +1,152 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Implements the commands sent by the driver on the control queue of the network device. Only
+//! the command setting the number of queue pairs used by the driver is supported.
+
+use vm_memory::GuestMemoryError;
+
+use crate::devices::virtio::queue::DescriptorChain;
+use crate::vstate::memory::{Bytes, GuestAddress, GuestMemoryMmap};
+
+/// Status acknowledging a command which was applied.
+pub const VIRTIO_NET_OK: u8 = 0;
+/// Status acknowledging a command which failed, or which is not supported.
+pub const VIRTIO_NET_ERR: u8 = 1;
+/// Class of the commands controlling the queue pairs used by the driver.
+const VIRTIO_NET_CTRL_MQ: u8 = 4;
+/// Command setting the number of queue pairs used by the driver.
+const VIRTIO_NET_CTRL_MQ_VQ_PAIRS_SET: u8 = 0;
+
+/// Length of the header of the commands, made of their class and their command number.
+const CTRL_HDR_LEN: usize = 2;
+/// Length of the longest command supported, a header followed by a number of queue pairs.
+const CTRL_CMD_MAX_LEN: usize = CTRL_HDR_LEN + 2;
+
+/// Errors triggered when reading a command from the control queue.
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum CtrlQueueError {
+    /// The command is truncated
+    Truncated,
+    /// The command has no writable descriptor to acknowledge it
+    MissingStatus,
+    /// Error reading the command from the guest memory: {0}
+    GuestMemory(#[from] GuestMemoryError),
+}
+
+/// Commands sent by the driver on the control queue.
+#[derive(Debug, PartialEq, Eq)]
+pub enum CtrlCommand {
+    /// Sets the number of queue pairs used by the driver.
+    SetQueuePairs(u16),
+    /// Any other command, which is acknowledged with an error.
+    Unsupported {
+        /// Class of the command.
+        class: u8,
+        /// Number of the command within its class.
+        command: u8,
+    },
+}
+
+/// Reads the command held by the descriptor chain starting at `head`, and returns it with the
+/// address of the status byte acknowledging it.
+pub fn read_command(
+    mem: &GuestMemoryMmap,
+    head: DescriptorChain,
+) -> Result<(CtrlCommand, GuestAddress), CtrlQueueError> {
+    let mut cmd = [0u8; CTRL_CMD_MAX_LEN];
+    let mut cmd_len = 0;
+    let mut status_addr = None;
+
+    let mut desc = Some(head);
+    while let Some(d) = desc {
+        if d.is_write_only() {
+            if status_addr.is_none() && d.len > 0 {
+                status_addr = Some(d.addr);
+            }
+        } else if cmd_len < CTRL_CMD_MAX_LEN {
+            // The data of the commands not supported is not needed.
+            let len = (CTRL_CMD_MAX_LEN - cmd_len).min(d.len as usize);
+            mem.read_slice(&mut cmd[cmd_len..cmd_len + len], d.addr)?;
+            cmd_len += len;
+        }
+        desc = d.next_descriptor();
+    }
+
+    let status_addr = status_addr.ok_or(CtrlQueueError::MissingStatus)?;
+    if cmd_len < CTRL_HDR_LEN {
+        return Err(CtrlQueueError::Truncated);
+    }
+    let command = match (cmd[0], cmd[1]) {
+        (VIRTIO_NET_CTRL_MQ, VIRTIO_NET_CTRL_MQ_VQ_PAIRS_SET) => {
+            if cmd_len < CTRL_CMD_MAX_LEN {
+                return Err(CtrlQueueError::Truncated);
+            }
+            CtrlCommand::SetQueuePairs(u16::from_le_bytes([cmd[2], cmd[3]]))
+        }
+        (class, command) => CtrlCommand::Unsupported { class, command },
+    };
+    Ok((command, status_addr))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::devices::virtio::queue::{VIRTQ_DESC_F_NEXT, VIRTQ_DESC_F_WRITE};
+    use crate::devices::virtio::test_utils::{default_mem, VirtQueue};
+
+    // Adds a chain made of a descriptor holding `cmd` and, if `with_status`, a writable one for
+    // the status, and reads the command it holds.
+    fn read_test_command(
+        cmd: &[u8],
+        with_status: bool,
+    ) -> Result<(CtrlCommand, GuestAddress), CtrlQueueError> {
+        let mem = default_mem();
+        let vq = VirtQueue::new(GuestAddress(0), &mem, 16);
+        let cmd_addr = vq.end().0;
+        let status_addr = cmd_addr + 0x100;
+        mem.write_slice(cmd, GuestAddress(cmd_addr)).unwrap();
+
+        let flags = if with_status { VIRTQ_DESC_F_NEXT } else { 0 };
+        vq.dtable[0].set(cmd_addr, u32::try_from(cmd.len()).unwrap(), flags, 1);
+        vq.dtable[1].set(status_addr, 1, VIRTQ_DESC_F_WRITE, 0);
+        vq.avail.ring[0].set(0);
+        vq.avail.idx.set(1);
+
+        let mut queue = vq.create_queue();
+        read_command(&mem, queue.pop().unwrap())
+    }
+
+    #[test]
+    fn test_read_set_queue_pairs() {
+        let (command, status_addr) = read_test_command(&[4, 0, 3, 0], true).unwrap();
+        assert_eq!(command, CtrlCommand::SetQueuePairs(3));
+        assert_ne!(status_addr, GuestAddress(0));
+
+        assert!(matches!(
+            read_test_command(&[4, 0, 3], true),
+            Err(CtrlQueueError::Truncated)
+        ));
+        assert!(matches!(
+            read_test_command(&[4, 0, 3, 0], false),
+            Err(CtrlQueueError::MissingStatus)
+        ));
+    }
+
+    #[test]
+    fn test_read_unsupported_command() {
+        // Setting the receive mode is not supported.
+        let (command, _) = read_test_command(&[0, 1, 1], true).unwrap();
+        assert_eq!(
+            command,
+            CtrlCommand::Unsupported {
+                class: 0,
+                command: 1
+            }
+        );
+        assert!(matches!(
+            read_test_command(&[0], true),
+            Err(CtrlQueueError::Truncated)
+        ));
+    }
+}
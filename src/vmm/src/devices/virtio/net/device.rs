@@ -8,44 +8,65 @@
 use std::collections::VecDeque;
 use std::mem::{self};
 use std::net::Ipv4Addr;
+use std::path::Path;
 use std::sync::{Arc, Mutex};
 
 use libc::{iovec, EAGAIN};
 use log::error;
+use serde::{Deserialize, Serialize};
 use vmm_sys_util::eventfd::EventFd;
 
-use super::NET_QUEUE_MAX_SIZE;
+use super::{ctrl, NET_QUEUE_MAX_SIZE};
+use crate::devices::error_events::{DeviceErrorClass, DeviceErrorReporter};
 use crate::devices::virtio::device::{DeviceState, IrqTrigger, IrqType, VirtioDevice};
 use crate::devices::virtio::gen::virtio_blk::VIRTIO_F_VERSION_1;
 use crate::devices::virtio::gen::virtio_net::{
-    virtio_net_hdr_v1, VIRTIO_NET_F_CSUM, VIRTIO_NET_F_GUEST_CSUM, VIRTIO_NET_F_GUEST_TSO4,
-    VIRTIO_NET_F_GUEST_TSO6, VIRTIO_NET_F_GUEST_UFO, VIRTIO_NET_F_HOST_TSO4,
-    VIRTIO_NET_F_HOST_TSO6, VIRTIO_NET_F_HOST_UFO, VIRTIO_NET_F_MAC, VIRTIO_NET_F_MRG_RXBUF,
+    virtio_net_hdr_v1, VIRTIO_NET_F_CSUM, VIRTIO_NET_F_CTRL_VQ, VIRTIO_NET_F_GUEST_CSUM,
+    VIRTIO_NET_F_GUEST_TSO4, VIRTIO_NET_F_GUEST_TSO6, VIRTIO_NET_F_GUEST_UFO,
+    VIRTIO_NET_F_HOST_TSO4, VIRTIO_NET_F_HOST_TSO6, VIRTIO_NET_F_HOST_UFO, VIRTIO_NET_F_MAC,
+    VIRTIO_NET_F_MQ, VIRTIO_NET_F_MRG_RXBUF, VIRTIO_NET_F_MTU,
 };
 use crate::devices::virtio::gen::virtio_ring::VIRTIO_RING_F_EVENT_IDX;
 use crate::devices::virtio::iovec::{
     IoVecBuffer, IoVecBufferMut, IoVecError, ParsedDescriptorChain,
 };
+use crate::devices::virtio::net::capture::{CaptureError, PacketCapture};
+use crate::devices::virtio::net::coalesce::RxCoalescer;
+use crate::devices::virtio::net::ctrl::{CtrlCommand, VIRTIO_NET_ERR, VIRTIO_NET_OK};
+use crate::devices::virtio::net::flows::FlowTable;
 use crate::devices::virtio::net::metrics::{NetDeviceMetrics, NetMetricsPerDevice};
-use crate::devices::virtio::net::tap::Tap;
+use crate::devices::virtio::net::pause_responder::{PauseResponder, REPLY_FRAME_LEN};
+use crate::devices::virtio::net::tap::{Tap, TapError};
 use crate::devices::virtio::net::{
-    gen, NetError, NetQueue, MAX_BUFFER_SIZE, NET_QUEUE_SIZES, RX_INDEX, TX_INDEX,
+    gen, rx_index, tx_index, NetError, MAX_BUFFER_SIZE, MAX_QUEUE_PAIRS, NET_QUEUE_SIZES,
 };
 use crate::devices::virtio::queue::{DescriptorChain, Queue};
 use crate::devices::virtio::{ActivateError, TYPE_NET};
 use crate::devices::{report_net_event_fail, DeviceError};
-use crate::dumbo::pdu::arp::ETH_IPV4_FRAME_LEN;
-use crate::dumbo::pdu::ethernet::{EthernetFrame, PAYLOAD_OFFSET};
+use crate::dumbo::pdu::arp::{EthIPv4ArpFrame, ETH_IPV4_FRAME_LEN};
+use crate::dumbo::pdu::ethernet::{EthernetFrame, ETHERTYPE_ARP, PAYLOAD_OFFSET};
+use crate::event_stream::{self, VmEvent};
 use crate::logger::{IncMetric, METRICS};
 use crate::mmds::data_store::Mmds;
 use crate::mmds::ns::MmdsNetworkStack;
-use crate::rate_limiter::{BucketUpdate, RateLimiter, TokenType};
-use crate::utils::net::mac::MacAddr;
-use crate::utils::u64_to_usize;
-use crate::vstate::memory::{ByteValued, GuestMemoryMmap};
+use crate::rate_limiter::{RateLimiter, TokenType};
+use crate::utils::net::mac::{MacAddr, MAC_ADDR_LEN};
+use crate::utils::{u64_to_usize, usize_to_u64};
+use crate::vmm_config::RateLimiterUpdate;
+use crate::vstate::memory::{ByteValued, Bytes, GuestMemoryMmap};
 
 const FRAME_HEADER_MAX_LEN: usize = PAYLOAD_OFFSET + ETH_IPV4_FRAME_LEN;
 
+/// The checksum and segmentation offload features, in both directions, which can be disabled.
+pub const NET_OFFLOAD_FEATURES: u64 = 1 << VIRTIO_NET_F_GUEST_CSUM
+    | 1 << VIRTIO_NET_F_CSUM
+    | 1 << VIRTIO_NET_F_GUEST_TSO4
+    | 1 << VIRTIO_NET_F_GUEST_TSO6
+    | 1 << VIRTIO_NET_F_GUEST_UFO
+    | 1 << VIRTIO_NET_F_HOST_TSO4
+    | 1 << VIRTIO_NET_F_HOST_TSO6
+    | 1 << VIRTIO_NET_F_HOST_UFO;
+
 pub(crate) const fn vnet_hdr_len() -> usize {
     mem::size_of::<virtio_net_hdr_v1>()
 }
@@ -57,6 +78,36 @@ const fn frame_hdr_len() -> usize {
     vnet_hdr_len() + FRAME_HEADER_MAX_LEN
 }
 
+/// Filtering of the frames sent by the guest with a source address other than its MAC address.
+/// The frames are only filtered on devices with a guest MAC address.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MacFilter {
+    /// Drops the frames whose source MAC address is not the guest one.
+    pub enforce_mac: bool,
+    /// Drops the ARP frames whose sender hardware address is not the guest one.
+    pub enforce_arp: bool,
+}
+
+impl MacFilter {
+    // Returns whether the frame starting with `headers` has to be dropped, as the source MAC
+    // address it carries is not `guest_mac`. Spoofed frames which are let through are only
+    // accounted.
+    fn drops(&self, headers: &[u8], guest_mac: MacAddr, net_metrics: &NetDeviceMetrics) -> bool {
+        let Ok(eth_frame) = EthernetFrame::from_bytes(headers) else {
+            return false;
+        };
+        let spoofed_src = eth_frame.src_mac() != guest_mac;
+        if spoofed_src {
+            net_metrics.tx_spoofed_mac_count.inc();
+        }
+        let spoofed_arp = self.enforce_arp
+            && eth_frame.ethertype() == ETHERTYPE_ARP
+            && eth_frame.payload().len() >= ETH_IPV4_FRAME_LEN
+            && EthIPv4ArpFrame::from_bytes_unchecked(eth_frame.payload()).sha() != guest_mac;
+        (self.enforce_mac && spoofed_src) || spoofed_arp
+    }
+}
+
 // Frames being sent/received through the network device model have a VNET header. This
 // function returns a slice which holds the L2 frame bytes without this header.
 fn frame_bytes_from_buf(buf: &[u8]) -> Result<&[u8], NetError> {
@@ -85,6 +136,11 @@ fn init_vnet_hdr(buf: &mut [u8]) {
 #[repr(C)]
 pub struct ConfigSpace {
     pub guest_mac: MacAddr,
+    // Only used with `VIRTIO_NET_F_STATUS`, which is not offered.
+    pub status: u16,
+    // Only used with `VIRTIO_NET_F_MQ`, offered with more than one queue pair.
+    pub max_virtqueue_pairs: u16,
+    pub mtu: u16,
 }
 
 // SAFETY: `ConfigSpace` contains only PODs in `repr(C)` or `repr(transparent)`, without padding.
@@ -227,13 +283,17 @@ impl RxBuffers {
 /// VirtIO network device.
 ///
 /// It emulates a network device able to exchange L2 frames between the guest
-/// and a host-side tap device.
+/// and a host-side tap device. Devices without a tap only exchange frames with the MMDS.
+///
+/// Devices with several RX/TX queue pairs open a queue of a multi-queue tap for each of them, so
+/// that the frames of each pair are processed by their own epoll events.
 #[derive(Debug)]
 pub struct Net {
     pub(crate) id: String,
 
-    /// The backend for this device: a tap.
-    pub tap: Tap,
+    /// The backend for this device: a tap queue per queue pair, none if the device only serves
+    /// the MMDS.
+    pub taps: Vec<Tap>,
 
     pub(crate) avail_features: u64,
     pub(crate) acked_features: u64,
@@ -252,6 +312,8 @@ pub struct Net {
 
     pub(crate) config_space: ConfigSpace,
     pub(crate) guest_mac: Option<MacAddr>,
+    /// The filtering of the frames sent by the guest with a spoofed source address.
+    pub(crate) mac_filter: MacFilter,
 
     pub(crate) device_state: DeviceState,
     pub(crate) activate_evt: EventFd,
@@ -260,9 +322,24 @@ pub struct Net {
     /// Only if MMDS transport has been associated with it.
     pub mmds_ns: Option<MmdsNetworkStack>,
     pub(crate) metrics: Arc<NetDeviceMetrics>,
+    pub(crate) error_reporter: DeviceErrorReporter,
+    /// The per-flow accounting of the traffic, if enabled.
+    pub(crate) flow_table: Option<FlowTable>,
+    /// The responder answering the TCP peers of the guest while it is paused, if enabled.
+    pub(crate) pause_responder: Option<PauseResponder>,
+    /// The coalescer merging the received TCP segments, if enabled.
+    pub(crate) rx_coalescer: Option<RxCoalescer>,
+    /// The capture of the frames exchanged by the guest, if started.
+    pub(crate) capture: Option<PacketCapture>,
+
+    /// Number of queue pairs used by the driver, whose tap queues are attached.
+    pub(crate) active_queue_pairs: u16,
+    /// Whether the processing of the RX and TX queues is paused for maintenance.
+    pub(crate) paused: bool,
 
     tx_buffer: IoVecBuffer,
-    pub(crate) rx_buffer: RxBuffers,
+    /// The RX buffers of each queue pair.
+    pub(crate) rx_buffers: Vec<RxBuffers>,
 }
 
 impl Net {
@@ -274,14 +351,29 @@ impl Net {
         rx_rate_limiter: RateLimiter,
         tx_rate_limiter: RateLimiter,
     ) -> Result<Self, NetError> {
-        let mut avail_features = 1 << VIRTIO_NET_F_GUEST_CSUM
-            | 1 << VIRTIO_NET_F_CSUM
-            | 1 << VIRTIO_NET_F_GUEST_TSO4
-            | 1 << VIRTIO_NET_F_GUEST_TSO6
-            | 1 << VIRTIO_NET_F_GUEST_UFO
-            | 1 << VIRTIO_NET_F_HOST_TSO4
-            | 1 << VIRTIO_NET_F_HOST_TSO6
-            | 1 << VIRTIO_NET_F_HOST_UFO
+        Self::new_with_backend(id, vec![tap], guest_mac, rx_rate_limiter, tx_rate_limiter)
+    }
+
+    /// Create a new virtio network device which is not connected to any tap, and only exchanges
+    /// frames with the MMDS.
+    pub fn new_mmds_only(
+        id: String,
+        guest_mac: Option<MacAddr>,
+        rx_rate_limiter: RateLimiter,
+        tx_rate_limiter: RateLimiter,
+    ) -> Result<Self, NetError> {
+        Self::new_with_backend(id, Vec::new(), guest_mac, rx_rate_limiter, tx_rate_limiter)
+    }
+
+    // Creates a device with a queue pair per tap queue, or a single one without any tap.
+    fn new_with_backend(
+        id: String,
+        taps: Vec<Tap>,
+        guest_mac: Option<MacAddr>,
+        rx_rate_limiter: RateLimiter,
+        tx_rate_limiter: RateLimiter,
+    ) -> Result<Self, NetError> {
+        let mut avail_features = NET_OFFLOAD_FEATURES
             | 1 << VIRTIO_F_VERSION_1
             | 1 << VIRTIO_NET_F_MRG_RXBUF
             | 1 << VIRTIO_RING_F_EVENT_IDX;
@@ -294,16 +386,29 @@ impl Net {
             avail_features |= 1 << VIRTIO_NET_F_MAC;
         }
 
+        let num_queue_pairs = taps.len().max(1);
+        let mut queue_sizes = NET_QUEUE_SIZES.repeat(num_queue_pairs);
+        if num_queue_pairs > 1 {
+            // The driver sets the number of queue pairs it uses through the control queue, which
+            // follows the queue pairs.
+            avail_features |= 1 << VIRTIO_NET_F_CTRL_VQ | 1 << VIRTIO_NET_F_MQ;
+            config_space.max_virtqueue_pairs = u16::try_from(num_queue_pairs).unwrap();
+            queue_sizes.push(NET_QUEUE_MAX_SIZE);
+        }
+
         let mut queue_evts = Vec::new();
         let mut queues = Vec::new();
-        for size in NET_QUEUE_SIZES {
+        for size in queue_sizes {
             queue_evts.push(EventFd::new(libc::EFD_NONBLOCK).map_err(NetError::EventFd)?);
             queues.push(Queue::new(size));
         }
+        let rx_buffers = (0..num_queue_pairs)
+            .map(|_| RxBuffers::new())
+            .collect::<Result<_, _>>()?;
 
         Ok(Net {
             id: id.clone(),
-            tap,
+            taps,
             avail_features,
             acked_features: 0u64,
             queues,
@@ -315,12 +420,21 @@ impl Net {
             irq_trigger: IrqTrigger::new().map_err(NetError::EventFd)?,
             config_space,
             guest_mac,
+            mac_filter: MacFilter::default(),
             device_state: DeviceState::Inactive,
             activate_evt: EventFd::new(libc::EFD_NONBLOCK).map_err(NetError::EventFd)?,
             mmds_ns: None,
+            error_reporter: DeviceErrorReporter::new(format!("net_{}", id)),
             metrics: NetMetricsPerDevice::alloc(id),
+            flow_table: None,
+            pause_responder: None,
+            rx_coalescer: None,
+            capture: None,
+            // All the queues of a tap are attached when opened.
+            active_queue_pairs: u16::try_from(num_queue_pairs).unwrap(),
+            paused: false,
             tx_buffer: Default::default(),
-            rx_buffer: RxBuffers::new()?,
+            rx_buffers,
         })
     }
 
@@ -332,13 +446,41 @@ impl Net {
         rx_rate_limiter: RateLimiter,
         tx_rate_limiter: RateLimiter,
     ) -> Result<Self, NetError> {
-        let tap = Tap::open_named(tap_if_name).map_err(NetError::TapOpen)?;
+        Self::new_with_queues(
+            id,
+            tap_if_name,
+            1,
+            guest_mac,
+            rx_rate_limiter,
+            tx_rate_limiter,
+        )
+    }
+
+    /// Create a new virtio network device given the interface name, with `num_queue_pairs` RX/TX
+    /// queue pairs. Several queue pairs require a multi-queue tap.
+    pub fn new_with_queues(
+        id: String,
+        tap_if_name: &str,
+        num_queue_pairs: u16,
+        guest_mac: Option<MacAddr>,
+        rx_rate_limiter: RateLimiter,
+        tx_rate_limiter: RateLimiter,
+    ) -> Result<Self, NetError> {
+        let taps = match num_queue_pairs {
+            1 => vec![Tap::open_named(tap_if_name).map_err(NetError::TapOpen)?],
+            2..=MAX_QUEUE_PAIRS => {
+                Tap::open_named_queues(tap_if_name, num_queue_pairs).map_err(NetError::TapOpen)?
+            }
+            _ => return Err(NetError::InvalidQueuePairs(num_queue_pairs)),
+        };
 
         let vnet_hdr_size = i32::try_from(vnet_hdr_len()).unwrap();
-        tap.set_vnet_hdr_size(vnet_hdr_size)
-            .map_err(NetError::TapSetVnetHdrSize)?;
+        for tap in &taps {
+            tap.set_vnet_hdr_size(vnet_hdr_size)
+                .map_err(NetError::TapSetVnetHdrSize)?;
+        }
 
-        Self::new_with_tap(id, tap, guest_mac, rx_rate_limiter, tx_rate_limiter)
+        Self::new_with_backend(id, taps, guest_mac, rx_rate_limiter, tx_rate_limiter)
     }
 
     /// Provides the ID of this net device.
@@ -351,9 +493,49 @@ impl Net {
         self.guest_mac.as_ref()
     }
 
-    /// Provides the host IFACE name of this net device.
+    /// Provides the host IFACE name of this net device, empty if it only serves the MMDS.
     pub fn iface_name(&self) -> String {
-        self.tap.if_name_as_str().to_string()
+        self.taps
+            .first()
+            .map(|tap| tap.if_name_as_str().to_string())
+            .unwrap_or_default()
+    }
+
+    /// Whether this net device is not connected to any tap, and only serves the MMDS.
+    pub fn is_mmds_only(&self) -> bool {
+        self.taps.is_empty()
+    }
+
+    /// Provides the number of RX/TX queue pairs of this net device.
+    pub fn num_queue_pairs(&self) -> u16 {
+        u16::try_from(self.rx_buffers.len()).unwrap()
+    }
+
+    /// Provides the number of queue pairs used by the driver.
+    pub fn active_queue_pairs(&self) -> u16 {
+        self.active_queue_pairs
+    }
+
+    /// Attaches the tap queues of the first `pairs` queue pairs and detaches the others, so that
+    /// the kernel only queues the received frames on the queue pairs used by the driver.
+    pub(crate) fn set_active_queue_pairs(&mut self, pairs: u16) -> Result<(), TapError> {
+        // Taps of devices with a single queue pair are not multi-queue.
+        if self.taps.len() > 1 {
+            let active = usize::from(self.active_queue_pairs);
+            for (pair, tap) in self.taps.iter().enumerate() {
+                let enabled = pair < usize::from(pairs);
+                if enabled != (pair < active) {
+                    tap.set_queue_enabled(enabled)?;
+                }
+            }
+        }
+        self.active_queue_pairs = pairs;
+        Ok(())
+    }
+
+    // Returns the index of the control queue, which only devices with several queue pairs have.
+    pub(crate) fn ctrl_queue_index(&self) -> Option<usize> {
+        (self.rx_buffers.len() > 1).then_some(rx_index(self.rx_buffers.len()))
     }
 
     /// Provides the MmdsNetworkStack of this net device.
@@ -371,11 +553,184 @@ impl Net {
         }
     }
 
+    /// Restricts the MMDS content retrieved through this device to the paths under
+    /// `allowed_paths`, or lifts the restriction if `None`.
+    pub fn set_mmds_allowed_paths(&mut self, allowed_paths: Option<Vec<String>>) {
+        if let Some(mmds_ns) = self.mmds_ns.as_mut() {
+            mmds_ns.set_allowed_paths(allowed_paths);
+        }
+    }
+
     /// Disables the `MmdsNetworkStack` to prevent device to forward MMDS requests.
     pub fn disable_mmds_network_stack(&mut self) {
         self.mmds_ns = None
     }
 
+    /// Enables the per-flow accounting of the traffic, tracking at most `max_flows` flows.
+    pub fn enable_flow_accounting(&mut self, max_flows: usize) {
+        self.flow_table = Some(FlowTable::new(max_flows));
+    }
+
+    /// Provides the per-flow accounting of the traffic, if enabled.
+    pub fn flow_table(&self) -> Option<&FlowTable> {
+        self.flow_table.as_ref()
+    }
+
+    /// Starts capturing the frames exchanged by the guest to the pcap file or fifo at `path`,
+    /// replacing any previous capture.
+    pub fn start_capture(
+        &mut self,
+        path: &Path,
+        snaplen: u32,
+        ring_size: usize,
+    ) -> Result<(), CaptureError> {
+        let capture = PacketCapture::open(path, snaplen, ring_size, self.metrics.clone())?;
+        self.stop_capture();
+        self.capture = Some(capture);
+        Ok(())
+    }
+
+    /// Stops capturing the frames exchanged by the guest, writing what the capture still buffers
+    /// if possible.
+    pub fn stop_capture(&mut self) {
+        if let Some(mut capture) = self.capture.take() {
+            if let Err(err) = capture.flush() {
+                error!("net: Could not write the end of the packet capture: {err}");
+            }
+        }
+    }
+
+    /// Returns whether the frames exchanged by the guest are captured.
+    pub fn is_capturing(&self) -> bool {
+        self.capture.is_some()
+    }
+
+    // Writes the frames captured since the last call, stopping the capture if its file can no
+    // longer be written.
+    fn flush_capture(&mut self) {
+        if let Some(capture) = self.capture.as_mut() {
+            if let Err(err) = capture.flush() {
+                error!("net: Stopping the packet capture: {err}");
+                self.metrics.capture_fails.inc();
+                self.error_reporter
+                    .report_io(DeviceErrorClass::Backend, &err);
+                self.capture = None;
+            }
+        }
+    }
+
+    /// Enables the TCP pause responder, tracking at most `max_connections` connections.
+    pub fn enable_pause_responder(&mut self, max_connections: usize) {
+        self.pause_responder = Some(PauseResponder::new(max_connections));
+    }
+
+    /// Returns the TCP pause responder, if enabled.
+    pub fn pause_responder(&self) -> Option<&PauseResponder> {
+        self.pause_responder.as_ref()
+    }
+
+    /// Sets the filtering of the frames sent by the guest with a spoofed source address.
+    pub fn set_mac_filter(&mut self, mac_filter: MacFilter) {
+        self.mac_filter = mac_filter;
+    }
+
+    /// Returns the filtering of the frames sent by the guest with a spoofed source address.
+    pub fn mac_filter(&self) -> MacFilter {
+        self.mac_filter
+    }
+
+    /// Notifies the device that the microVM was paused or resumed. While the microVM is paused,
+    /// the pause responder, if enabled, answers the TCP peers of the guest on its behalf.
+    pub fn set_vm_paused(&mut self, paused: bool) {
+        let Some(responder) = self.pause_responder.as_mut() else {
+            return;
+        };
+        responder.set_paused(paused);
+        // The tap events are edge triggered, so the frames already queued have to be handled now.
+        if paused && self.is_activated() {
+            self.respond_while_paused();
+        }
+    }
+
+    /// Pauses the processing of the RX and TX queues, e.g. while the tap is maintained. The frames
+    /// sent by the guest are left in the TX queues, and the ones received on the tap are left on
+    /// it until the device is resumed.
+    pub fn pause(&mut self) {
+        self.paused = true;
+        self.flush_capture();
+    }
+
+    /// Resumes the processing of the RX and TX queues, handling the frames queued while paused.
+    pub fn resume(&mut self) {
+        if !self.paused {
+            return;
+        }
+        self.paused = false;
+        if self.is_activated() {
+            for pair in 0..usize::from(self.active_queue_pairs) {
+                self.resume_rx(pair)
+                    .unwrap_or_else(|err| report_net_event_fail(&self.metrics, err));
+                self.process_tx(pair)
+                    .unwrap_or_else(|err| report_net_event_fail(&self.metrics, err));
+            }
+        }
+    }
+
+    /// Returns whether the processing of the RX and TX queues is paused.
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Sets the MTU of the tap and advertises it to the guest, so that it uses the same MTU.
+    pub fn set_mtu(&mut self, mtu: u16) -> Result<(), NetError> {
+        // The MTU is set on the interface, shared by all the tap queues.
+        if let Some(tap) = self.taps.first() {
+            tap.set_mtu(mtu).map_err(NetError::TapSetMtu)?;
+        }
+        self.config_space.mtu = mtu;
+        self.avail_features |= 1 << VIRTIO_NET_F_MTU;
+        Ok(())
+    }
+
+    /// Returns the MTU advertised to the guest, if any.
+    pub fn mtu(&self) -> Option<u16> {
+        (self.avail_features & (1 << VIRTIO_NET_F_MTU) != 0).then_some(self.config_space.mtu)
+    }
+
+    /// Sets the offload features offered to the guest, out of [`NET_OFFLOAD_FEATURES`]. The
+    /// others are not offered, so that e.g. without segmentation offloads the guest sees the
+    /// actual frames exchanged with the tap.
+    pub fn set_offload_features(&mut self, features: u64) {
+        self.avail_features =
+            (self.avail_features & !NET_OFFLOAD_FEATURES) | (features & NET_OFFLOAD_FEATURES);
+    }
+
+    /// Returns the offload features offered to the guest.
+    pub fn offload_features(&self) -> u64 {
+        self.avail_features & NET_OFFLOAD_FEATURES
+    }
+
+    /// Enables the coalescing of the received TCP segments, merging at most `max_segments`
+    /// segments into a frame.
+    pub fn enable_rx_coalescing(&mut self, max_segments: usize) {
+        self.rx_coalescer = Some(RxCoalescer::new(max_segments));
+    }
+
+    /// Returns the coalescer of the received TCP segments, if enabled.
+    pub fn rx_coalescer(&self) -> Option<&RxCoalescer> {
+        self.rx_coalescer.as_ref()
+    }
+
+    /// Returns whether the received TCP segments are coalesced. Coalesced segments are passed to
+    /// the guest as GSO frames with a partial checksum, spread over mergeable RX buffers, so the
+    /// guest has to support all three.
+    fn rx_coalescing_active(&self) -> bool {
+        self.rx_coalescer.is_some()
+            && self.has_feature(VIRTIO_NET_F_MRG_RXBUF as u64)
+            && self.has_feature(VIRTIO_NET_F_GUEST_TSO4 as u64)
+            && self.has_feature(VIRTIO_NET_F_GUEST_CSUM as u64)
+    }
+
     /// Provides a reference to the configured RX rate limiter.
     pub fn rx_rate_limiter(&self) -> &RateLimiter {
         &self.rx_rate_limiter
@@ -390,13 +745,8 @@ impl Net {
     /// for the notification to be enabled.
     /// https://docs.oasis-open.org/virtio/virtio/v1.1/csprd01/virtio-v1.1-csprd01.html#x1-320005
     /// 2.6.7.1 Driver Requirements: Used Buffer Notification Suppression
-    fn try_signal_queue(&mut self, queue_type: NetQueue) -> Result<(), DeviceError> {
-        let queue = match queue_type {
-            NetQueue::Rx => &mut self.queues[RX_INDEX],
-            NetQueue::Tx => &mut self.queues[TX_INDEX],
-        };
-
-        if queue.prepare_kick() {
+    fn try_signal_queue(&mut self, queue_index: usize) -> Result<(), DeviceError> {
+        if self.queues[queue_index].prepare_kick() {
             self.irq_trigger
                 .trigger_irq(IrqType::Vring)
                 .map_err(|err| {
@@ -408,13 +758,20 @@ impl Net {
         Ok(())
     }
 
-    // Helper function to consume one op with `size` bytes from a rate limiter
-    fn rate_limiter_consume_op(rate_limiter: &mut RateLimiter, size: u64) -> bool {
-        if !rate_limiter.consume(1, TokenType::Ops) {
+    // Helper function to consume one op with `size` bytes from a rate limiter, borrowing from
+    // the `peer` rate limiter of the other direction if allowed.
+    fn rate_limiter_consume_op(
+        rate_limiter: &mut RateLimiter,
+        peer: &mut RateLimiter,
+        size: u64,
+    ) -> bool {
+        if !rate_limiter.consume_or_borrow(1, TokenType::Ops, peer) {
             return false;
         }
 
-        if !rate_limiter.consume(size, TokenType::Bytes) {
+        // The op may have been borrowed from the peer, but it is given back to `rate_limiter`.
+        // This only lets the borrower slightly exceed its own budget until the next refill.
+        if !rate_limiter.consume_or_borrow(size, TokenType::Bytes, peer) {
             rate_limiter.manual_replenish(1, TokenType::Ops);
             return false;
         }
@@ -428,17 +785,25 @@ impl Net {
         rate_limiter.manual_replenish(size, TokenType::Bytes);
     }
 
-    // Attempts to copy a single frame into the guest if there is enough
-    // rate limiting budget.
+    // Attempts to copy a single frame into the guest, in the RX queue of `pair`, if there is
+    // enough rate limiting budget.
     // Returns true on successful frame delivery.
-    pub fn rate_limited_rx_single_frame(&mut self, frame_size: u32) -> bool {
-        let rx_queue = &mut self.queues[RX_INDEX];
-        if !Self::rate_limiter_consume_op(&mut self.rx_rate_limiter, frame_size as u64) {
+    pub fn rate_limited_rx_single_frame(&mut self, pair: usize, frame_size: u32) -> bool {
+        let rx_queue = &mut self.queues[rx_index(pair)];
+        if !Self::rate_limiter_consume_op(
+            &mut self.rx_rate_limiter,
+            &mut self.tx_rate_limiter,
+            frame_size as u64,
+        ) {
             self.metrics.rx_rate_limiter_throttled.inc();
+            event_stream::emit(VmEvent::RateLimited {
+                device: format!("net_{}", self.id),
+                operation: "rx",
+            });
             return false;
         }
 
-        self.rx_buffer.finish_frame(rx_queue);
+        self.rx_buffers[pair].finish_frame(rx_queue);
         true
     }
 
@@ -451,6 +816,12 @@ impl Net {
                 || self.has_feature(VIRTIO_NET_F_GUEST_UFO as u64)
             {
                 MAX_BUFFER_SIZE.try_into().unwrap()
+            } else if self.has_feature(VIRTIO_NET_F_MTU as u64) {
+                // Room for the VNET header, the ethernet header and the payload, as with the
+                // default 1526 bytes for a 1500 bytes MTU.
+                let mtu_buffer_size =
+                    vnet_hdr_len() + PAYLOAD_OFFSET + usize::from(self.config_space.mtu);
+                u32::try_from(mtu_buffer_size).unwrap().max(1526)
             } else {
                 1526
             }
@@ -459,16 +830,18 @@ impl Net {
         }
     }
 
-    /// Parse available RX `DescriptorChains` from the queue
-    pub fn parse_rx_descriptors(&mut self) {
+    /// Parse available RX `DescriptorChains` from the RX queue of `pair`
+    pub fn parse_rx_descriptors(&mut self, pair: usize) {
         // This is safe since we checked in the event handler that the device is activated.
         let mem = self.device_state.mem().unwrap();
-        let queue = &mut self.queues[RX_INDEX];
+        let queue = &mut self.queues[rx_index(pair)];
+        let rx_buffer = &mut self.rx_buffers[pair];
         while let Some(head) = queue.pop_or_enable_notification() {
             let index = head.index;
             // SAFETY: we are only using this `DescriptorChain` here.
-            if let Err(err) = unsafe { self.rx_buffer.add_buffer(mem, head) } {
+            if let Err(err) = unsafe { rx_buffer.add_buffer(mem, head) } {
                 self.metrics.rx_fails.inc();
+                self.error_reporter.report(DeviceErrorClass::Guest, None);
 
                 // If guest uses dirty tricks to make us add more descriptors than
                 // we can hold, just stop processing.
@@ -485,9 +858,9 @@ impl Net {
                 // SAFETY:
                 // index is verified on `DescriptorChain` creation.
                 queue
-                    .write_used_element(self.rx_buffer.used_descriptors, index, 0)
+                    .write_used_element(rx_buffer.used_descriptors, index, 0)
                     .unwrap();
-                self.rx_buffer.used_descriptors += 1;
+                rx_buffer.used_descriptors += 1;
             }
         }
     }
@@ -495,14 +868,19 @@ impl Net {
     // Tries to detour the frame to MMDS and if MMDS doesn't accept it, sends it on the host TAP.
     //
     // Returns whether MMDS consumed the frame.
+    #[allow(clippy::too_many_arguments)]
     fn write_to_mmds_or_tap(
         mmds_ns: Option<&mut MmdsNetworkStack>,
         rate_limiter: &mut RateLimiter,
         headers: &mut [u8],
         frame_iovec: &IoVecBuffer,
-        tap: &mut Tap,
+        tap: Option<&mut Tap>,
         guest_mac: Option<MacAddr>,
+        mac_filter: MacFilter,
         net_metrics: &NetDeviceMetrics,
+        error_reporter: &DeviceErrorReporter,
+        flow_table: Option<&mut FlowTable>,
+        pause_responder: Option<&mut PauseResponder>,
     ) -> Result<bool, NetError> {
         // Read the frame headers from the IoVecBuffer
         let max_header_len = headers.len();
@@ -511,12 +889,14 @@ impl Net {
             .map_err(|err| {
                 error!("Received malformed TX buffer: {:?}", err);
                 net_metrics.tx_malformed_frames.inc();
+                error_reporter.report(DeviceErrorClass::Guest, None);
                 NetError::VnetHeaderMissing
             })?;
 
         let headers = frame_bytes_from_buf(&headers[..header_len]).inspect_err(|_| {
             error!("VNET headers missing in TX frame");
             net_metrics.tx_malformed_frames.inc();
+            error_reporter.report(DeviceErrorClass::Guest, None);
         })?;
 
         if let Some(ns) = mmds_ns {
@@ -538,15 +918,18 @@ impl Net {
             }
         }
 
-        // This frame goes to the TAP.
+        // This frame goes to the TAP, if any.
+        let Some(tap) = tap else {
+            net_metrics.tx_dropped_frames.inc();
+            return Ok(false);
+        };
 
         // Check for guest MAC spoofing.
         if let Some(guest_mac) = guest_mac {
-            let _ = EthernetFrame::from_bytes(headers).map(|eth_frame| {
-                if guest_mac != eth_frame.src_mac() {
-                    net_metrics.tx_spoofed_mac_count.inc();
-                }
-            });
+            if mac_filter.drops(headers, guest_mac, net_metrics) {
+                net_metrics.tx_spoofed_dropped_frames.inc();
+                return Ok(false);
+            }
         }
 
         let _metric = net_metrics.tap_write_agg.record_latency_metrics();
@@ -556,33 +939,41 @@ impl Net {
                 net_metrics.tx_bytes_count.add(len);
                 net_metrics.tx_packets_count.inc();
                 net_metrics.tx_count.inc();
+                if let Some(flow_table) = flow_table {
+                    flow_table.account_tx(frame_iovec);
+                }
+                if let Some(pause_responder) = pause_responder {
+                    pause_responder.track_tx(frame_iovec);
+                }
             }
             Err(err) => {
                 error!("Failed to write to tap: {:?}", err);
                 net_metrics.tap_write_fails.inc();
+                error_reporter.report_io(DeviceErrorClass::Backend, &err);
             }
         };
         Ok(false)
     }
 
-    // We currently prioritize packets from the MMDS over regular network packets.
-    fn read_from_mmds_or_tap(&mut self) -> Result<Option<u32>, NetError> {
+    // We currently prioritize packets from the MMDS over regular network packets. The frames of
+    // the MMDS are only passed to the guest through the first queue pair.
+    fn read_from_mmds_or_tap(&mut self, pair: usize) -> Result<Option<u32>, NetError> {
         // We only want to read from TAP (or mmds) if we have at least 64K of available capacity as
         // this is the max size of 1 packet.
         // SAFETY:
         // * MAX_BUFFER_SIZE is constant and fits into u32
         #[allow(clippy::cast_possible_truncation)]
-        if self.rx_buffer.capacity() < MAX_BUFFER_SIZE as u32 {
-            self.parse_rx_descriptors();
+        if self.rx_buffers[pair].capacity() < MAX_BUFFER_SIZE as u32 {
+            self.parse_rx_descriptors(pair);
 
             // If after parsing the RX queue we still don't have enough capacity, stop processing RX
             // frames.
-            if self.rx_buffer.capacity() < MAX_BUFFER_SIZE as u32 {
+            if self.rx_buffers[pair].capacity() < MAX_BUFFER_SIZE as u32 {
                 return Ok(None);
             }
         }
 
-        if let Some(ns) = self.mmds_ns.as_mut() {
+        if let Some(ns) = self.mmds_ns.as_mut().filter(|_| pair == 0) {
             if let Some(len) =
                 ns.write_next_frame(frame_bytes_from_buf_mut(&mut self.rx_frame_buf)?)
             {
@@ -590,28 +981,47 @@ impl Net {
                 METRICS.mmds.tx_frames.inc();
                 METRICS.mmds.tx_bytes.add(len as u64);
                 init_vnet_hdr(&mut self.rx_frame_buf);
-                self.rx_buffer
+                self.rx_buffers[pair]
                     .iovec
                     .write_all_volatile_at(&self.rx_frame_buf[..vnet_hdr_len() + len], 0)?;
                 // SAFETY:
                 // * len will never be bigger that u32::MAX because mmds is bound
                 // by the size of `self.rx_frame_buf` which is MAX_BUFFER_SIZE size.
                 let len: u32 = (vnet_hdr_len() + len).try_into().unwrap();
+                if let Some(capture) = self.capture.as_mut() {
+                    capture.capture_rx(&self.rx_buffers[pair].iovec, len as usize);
+                }
 
                 // SAFETY:
                 // * We checked that `rx_buffer` includes at least one `DescriptorChain`
                 // * `rx_frame_buf` has size of `MAX_BUFFER_SIZE` and all `DescriptorChain` objects
                 //   are at least that big.
                 unsafe {
-                    self.rx_buffer.mark_used(len, &mut self.queues[RX_INDEX]);
+                    self.rx_buffers[pair].mark_used(len, &mut self.queues[rx_index(pair)]);
                 }
                 return Ok(Some(len));
             }
         }
 
+        // There is nothing else to read on devices only serving the MMDS.
+        if self.taps.is_empty() {
+            return Err(NetError::IO(std::io::Error::from_raw_os_error(EAGAIN)));
+        }
+
+        if self.rx_coalescing_active() {
+            return self.read_coalesced_tap(pair).map(Some);
+        }
+
         // SAFETY:
-        // * We ensured that `self.rx_buffer` has at least one DescriptorChain parsed in it.
-        let len = unsafe { self.read_tap().map_err(NetError::IO) }?;
+        // * We ensured that `self.rx_buffers[pair]` has at least one DescriptorChain parsed in it.
+        let len = unsafe { self.read_tap(pair).map_err(NetError::IO) }?;
+        if let Some(flow_table) = self.flow_table.as_mut() {
+            // The frame has to be accounted before its descriptors are dropped from `rx_buffer`.
+            flow_table.account_rx(&self.rx_buffers[pair].iovec, len);
+        }
+        if let Some(capture) = self.capture.as_mut() {
+            capture.capture_rx(&self.rx_buffers[pair].iovec, len);
+        }
         // SAFETY:
         // * len will never be bigger that u32::MAX
         let len: u32 = len.try_into().unwrap();
@@ -621,15 +1031,51 @@ impl Net {
         // * `read_tap` passes the first `DescriptorChain` to `readv` so we can't have read more
         //   bytes than its capacity.
         unsafe {
-            self.rx_buffer.mark_used(len, &mut self.queues[RX_INDEX]);
+            self.rx_buffers[pair].mark_used(len, &mut self.queues[rx_index(pair)]);
         }
         Ok(Some(len))
     }
 
-    /// Read as many frames as possible.
-    fn process_rx(&mut self) -> Result<(), DeviceError> {
+    // Reads a frame from the tap queue of `pair` through the RX coalescer, merging the following
+    // TCP segments of its flow into it, and copies it to the guest. The caller ensures that
+    // `self.rx_buffers[pair]` has at least `MAX_BUFFER_SIZE` bytes of capacity.
+    fn read_coalesced_tap(&mut self, pair: usize) -> Result<u32, NetError> {
+        // The coalescer is only used once enabled.
+        let coalescer = self.rx_coalescer.as_mut().unwrap();
+        // The caller ensures that the device has a tap.
+        let tap = &mut self.taps[pair];
+        let (frame, segments) = coalescer
+            .read_frame(|buf| tap.read_buf(buf))
+            .map_err(NetError::IO)?;
+        let rx_buffer = &mut self.rx_buffers[pair];
+        rx_buffer.iovec.write_all_volatile_at(frame, 0)?;
+        self.metrics
+            .rx_coalesced_segments
+            .add(usize_to_u64(segments - 1));
+        // The frames are bounded by `MAX_BUFFER_SIZE`.
+        let len: u32 = frame.len().try_into().unwrap();
+        if let Some(flow_table) = self.flow_table.as_mut() {
+            // The frame has to be accounted before its descriptors are dropped from `rx_buffer`.
+            flow_table.account_rx(&rx_buffer.iovec, frame.len());
+        }
+        if let Some(capture) = self.capture.as_mut() {
+            capture.capture_rx(&rx_buffer.iovec, frame.len());
+        }
+
+        // SAFETY:
+        // * `rx_buffer` has at least one `DescriptorChain`
+        // * Mergeable RX buffers were negotiated, and the frame fits in the capacity of
+        //   `rx_buffer`, which is at least `MAX_BUFFER_SIZE` bytes.
+        unsafe {
+            rx_buffer.mark_used(len, &mut self.queues[rx_index(pair)]);
+        }
+        Ok(len)
+    }
+
+    /// Read as many frames as possible in the RX queue of `pair`.
+    fn process_rx(&mut self, pair: usize) -> Result<(), DeviceError> {
         loop {
-            match self.read_from_mmds_or_tap() {
+            match self.read_from_mmds_or_tap(pair) {
                 Ok(None) => {
                     self.metrics.no_rx_avail_buffer.inc();
                     break;
@@ -638,7 +1084,7 @@ impl Net {
                     self.metrics.rx_count.inc();
                     self.metrics.rx_bytes_count.add(bytes as u64);
                     self.metrics.rx_packets_count.inc();
-                    if !self.rate_limited_rx_single_frame(bytes) {
+                    if !self.rate_limited_rx_single_frame(pair, bytes) {
                         break;
                     }
                 }
@@ -650,6 +1096,8 @@ impl Net {
                         _ => {
                             error!("Failed to read tap: {:?}", err);
                             self.metrics.tap_read_fails.inc();
+                            self.error_reporter
+                                .report_io(DeviceErrorClass::Backend, &err);
                             return Err(DeviceError::FailedReadTap);
                         }
                     };
@@ -661,23 +1109,76 @@ impl Net {
             }
         }
 
-        self.try_signal_queue(NetQueue::Rx)
+        self.flush_capture();
+        self.try_signal_queue(rx_index(pair))
     }
 
-    fn resume_rx(&mut self) -> Result<(), DeviceError> {
+    // Drains the frames received on all the tap queues while the microVM is paused, answering
+    // those of the TCP connections tracked by the pause responder.
+    fn respond_while_paused(&mut self) {
+        let Some(responder) = self.pause_responder.as_mut() else {
+            return;
+        };
+        let mut reply = [0u8; REPLY_FRAME_LEN];
+        for tap in self.taps.iter_mut() {
+            loop {
+                let len = match tap.read_buf(&mut self.rx_frame_buf) {
+                    Ok(len) => len,
+                    Err(err) => {
+                        // The tap device is non-blocking, so EAGAIN means that it was drained.
+                        if err.raw_os_error() != Some(EAGAIN) {
+                            error!("Failed to read tap: {:?}", err);
+                            self.metrics.tap_read_fails.inc();
+                            self.error_reporter
+                                .report_io(DeviceErrorClass::Backend, &err);
+                        }
+                        break;
+                    }
+                };
+                self.metrics.paused_rx_dropped_frames.inc();
+                let Some(frame) = self.rx_frame_buf.get(vnet_hdr_len()..len) else {
+                    continue;
+                };
+                if let Some(reply_len) = responder.respond(frame, &mut reply) {
+                    match tap.write_buf(&reply[..reply_len]) {
+                        Ok(_) => self.metrics.pause_responder_acks.inc(),
+                        Err(err) => {
+                            error!("Failed to write to tap: {:?}", err);
+                            self.metrics.tap_write_fails.inc();
+                            self.error_reporter
+                                .report_io(DeviceErrorClass::Backend, &err);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn resume_rx(&mut self, pair: usize) -> Result<(), DeviceError> {
+        // The frames received while the microVM is paused are handled by the pause responder.
+        if self
+            .pause_responder
+            .as_ref()
+            .is_some_and(PauseResponder::is_paused)
+        {
+            self.respond_while_paused();
+            return Ok(());
+        }
+
         // First try to handle any deferred frame
-        if self.rx_buffer.used_bytes != 0 {
+        let used_bytes = self.rx_buffers[pair].used_bytes;
+        if used_bytes != 0 {
             // If can't finish sending this frame, re-set it as deferred and return; we can't
             // process any more frames from the TAP.
-            if !self.rate_limited_rx_single_frame(self.rx_buffer.used_bytes) {
+            if !self.rate_limited_rx_single_frame(pair, used_bytes) {
                 return Ok(());
             }
         }
 
-        self.process_rx()
+        self.process_rx(pair)
     }
 
-    fn process_tx(&mut self) -> Result<(), DeviceError> {
+    fn process_tx(&mut self, pair: usize) -> Result<(), DeviceError> {
         // This is safe since we checked in the event handler that the device is activated.
         let mem = self.device_state.mem().unwrap();
 
@@ -687,7 +1188,7 @@ impl Net {
         // with the MMDS network stack.
         let mut process_rx_for_mmds = false;
         let mut used_any = false;
-        let tx_queue = &mut self.queues[TX_INDEX];
+        let tx_queue = &mut self.queues[tx_index(pair)];
 
         while let Some(head) = tx_queue.pop_or_enable_notification() {
             self.metrics
@@ -700,6 +1201,7 @@ impl Net {
             // are live at the same time, meaning this has exclusive ownership over the memory
             if unsafe { self.tx_buffer.load_descriptor_chain(mem, head).is_err() } {
                 self.metrics.tx_fails.inc();
+                self.error_reporter.report(DeviceErrorClass::Guest, None);
                 tx_queue
                     .add_used(head_index, 0)
                     .map_err(DeviceError::QueueError)?;
@@ -710,6 +1212,7 @@ impl Net {
             if self.tx_buffer.len() as usize > MAX_BUFFER_SIZE {
                 error!("net: received too big frame from driver");
                 self.metrics.tx_malformed_frames.inc();
+                self.error_reporter.report(DeviceErrorClass::Guest, None);
                 tx_queue
                     .add_used(head_index, 0)
                     .map_err(DeviceError::QueueError)?;
@@ -718,24 +1221,36 @@ impl Net {
 
             if !Self::rate_limiter_consume_op(
                 &mut self.tx_rate_limiter,
+                &mut self.rx_rate_limiter,
                 u64::from(self.tx_buffer.len()),
             ) {
                 tx_queue.undo_pop();
                 self.metrics.tx_rate_limiter_throttled.inc();
+                event_stream::emit(VmEvent::RateLimited {
+                    device: format!("net_{}", self.id),
+                    operation: "tx",
+                });
                 break;
             }
 
+            if let Some(capture) = self.capture.as_mut() {
+                capture.capture_tx(&self.tx_buffer);
+            }
             let frame_consumed_by_mmds = Self::write_to_mmds_or_tap(
                 self.mmds_ns.as_mut(),
                 &mut self.tx_rate_limiter,
                 &mut self.tx_frame_headers,
                 &self.tx_buffer,
-                &mut self.tap,
+                self.taps.get_mut(pair),
                 self.guest_mac,
+                self.mac_filter,
                 &self.metrics,
+                &self.error_reporter,
+                self.flow_table.as_mut(),
+                self.pause_responder.as_mut(),
             )
             .unwrap_or(false);
-            if frame_consumed_by_mmds && self.rx_buffer.used_bytes == 0 {
+            if frame_consumed_by_mmds && self.rx_buffers[0].used_bytes == 0 {
                 // MMDS consumed this frame/request, let's also try to process the response.
                 process_rx_for_mmds = true;
             }
@@ -752,16 +1267,81 @@ impl Net {
 
         // Cleanup tx_buffer to ensure no two buffers point at the same memory
         self.tx_buffer.clear();
-        self.try_signal_queue(NetQueue::Tx)?;
+        self.flush_capture();
+        self.try_signal_queue(tx_index(pair))?;
 
-        // An incoming frame for the MMDS may trigger the transmission of a new message.
+        // An incoming frame for the MMDS may trigger the transmission of a new message, passed to
+        // the guest through the first queue pair.
         if process_rx_for_mmds {
-            self.process_rx()
+            self.process_rx(0)
         } else {
             Ok(())
         }
     }
 
+    // Applies the commands sent by the driver on the control queue, and acknowledges them.
+    fn process_ctrl_queue(&mut self, queue_index: usize) -> Result<(), DeviceError> {
+        loop {
+            // This is safe since we checked in the event handler that the device is activated.
+            let mem = self.device_state.mem().unwrap();
+            let Some(head) = self.queues[queue_index].pop_or_enable_notification() else {
+                break;
+            };
+            let head_index = head.index;
+            let used_len = match ctrl::read_command(mem, head) {
+                Ok((command, status_addr)) => {
+                    let status = self.apply_ctrl_command(command);
+                    let mem = self.device_state.mem().unwrap();
+                    if let Err(err) = mem.write_obj(status, status_addr) {
+                        error!("net: Could not acknowledge a control command: {err}");
+                        self.metrics.ctrl_fails.inc();
+                        0
+                    } else {
+                        1
+                    }
+                }
+                Err(err) => {
+                    error!("net: Could not read a control command: {err}");
+                    self.metrics.ctrl_fails.inc();
+                    self.error_reporter.report(DeviceErrorClass::Guest, None);
+                    0
+                }
+            };
+            self.queues[queue_index]
+                .add_used(head_index, used_len)
+                .map_err(DeviceError::QueueError)?;
+        }
+
+        self.try_signal_queue(queue_index)
+    }
+
+    // Applies a command of the control queue, and returns the status acknowledging it.
+    fn apply_ctrl_command(&mut self, command: CtrlCommand) -> u8 {
+        match command {
+            CtrlCommand::SetQueuePairs(pairs) if (1..=self.num_queue_pairs()).contains(&pairs) => {
+                match self.set_active_queue_pairs(pairs) {
+                    Ok(()) => VIRTIO_NET_OK,
+                    Err(err) => {
+                        error!("net: Could not set the number of queue pairs: {err}");
+                        self.metrics.ctrl_fails.inc();
+                        self.error_reporter.report(DeviceErrorClass::Backend, None);
+                        VIRTIO_NET_ERR
+                    }
+                }
+            }
+            CtrlCommand::SetQueuePairs(pairs) => {
+                error!("net: Invalid number of queue pairs requested: {pairs}");
+                self.metrics.ctrl_fails.inc();
+                VIRTIO_NET_ERR
+            }
+            CtrlCommand::Unsupported { class, command } => {
+                error!("net: Unsupported control command {command} of class {class}");
+                self.metrics.ctrl_fails.inc();
+                VIRTIO_NET_ERR
+            }
+        }
+    }
+
     /// Builds the offload features we will setup on the TAP device based on the features that the
     /// guest supports.
     pub fn build_tap_offload_features(guest_supported_features: u64) -> u32 {
@@ -803,87 +1383,109 @@ impl Net {
     }
 
     /// Updates the parameters for the rate limiters
-    pub fn patch_rate_limiters(
-        &mut self,
-        rx_bytes: BucketUpdate,
-        rx_ops: BucketUpdate,
-        tx_bytes: BucketUpdate,
-        tx_ops: BucketUpdate,
-    ) {
-        self.rx_rate_limiter.update_buckets(rx_bytes, rx_ops);
-        self.tx_rate_limiter.update_buckets(tx_bytes, tx_ops);
+    pub fn patch_rate_limiters(&mut self, rx: RateLimiterUpdate, tx: RateLimiterUpdate) {
+        self.rx_rate_limiter.update_buckets(rx.bandwidth, rx.ops);
+        if let Some(borrow) = rx.borrow {
+            self.rx_rate_limiter.set_borrow(borrow);
+        }
+        if let Some(smoothing) = rx.smoothing {
+            self.rx_rate_limiter.set_smoothing(smoothing);
+        }
+        self.tx_rate_limiter.update_buckets(tx.bandwidth, tx.ops);
+        if let Some(borrow) = tx.borrow {
+            self.tx_rate_limiter.set_borrow(borrow);
+        }
+        if let Some(smoothing) = tx.smoothing {
+            self.tx_rate_limiter.set_smoothing(smoothing);
+        }
     }
 
-    /// Reads a frame from the TAP device inside the first descriptor held by `self.rx_buffer`.
+    /// Reads a frame from the TAP queue of `pair` inside the first descriptor held by
+    /// `self.rx_buffers[pair]`. Fails with `EAGAIN` if the device has no tap.
     ///
     /// # Safety
     ///
-    /// `self.rx_buffer` needs to have at least one descriptor chain parsed
-    pub unsafe fn read_tap(&mut self) -> std::io::Result<usize> {
+    /// `self.rx_buffers[pair]` needs to have at least one descriptor chain parsed
+    pub unsafe fn read_tap(&mut self, pair: usize) -> std::io::Result<usize> {
         let slice = if self.has_feature(VIRTIO_NET_F_MRG_RXBUF as u64) {
-            self.rx_buffer.all_chains_slice_mut()
+            self.rx_buffers[pair].all_chains_slice_mut()
         } else {
-            self.rx_buffer.single_chain_slice_mut()
+            self.rx_buffers[pair].single_chain_slice_mut()
         };
-        self.tap.read_iovec(slice)
+        match self.taps.get_mut(pair) {
+            Some(tap) => tap.read_iovec(slice),
+            None => Err(std::io::Error::from_raw_os_error(EAGAIN)),
+        }
     }
 
     fn write_tap(tap: &mut Tap, buf: &IoVecBuffer) -> std::io::Result<usize> {
         tap.write_iovec(buf)
     }
 
-    /// Process a single RX queue event.
+    /// Process a single RX queue event of the queue pair `pair`.
     ///
     /// This is called by the event manager responding to the guest adding a new
     /// buffer in the RX queue.
-    pub fn process_rx_queue_event(&mut self) {
+    pub fn process_rx_queue_event(&mut self, pair: usize) {
         self.metrics.rx_queue_event_count.inc();
 
-        if let Err(err) = self.queue_evts[RX_INDEX].read() {
+        if let Err(err) = self.queue_evts[rx_index(pair)].read() {
             // rate limiters present but with _very high_ allowed rate
             error!("Failed to get rx queue event: {:?}", err);
             self.metrics.event_fails.inc();
             return;
         } else {
-            self.parse_rx_descriptors();
+            self.parse_rx_descriptors(pair);
+        }
+
+        // The frames are received once the device is resumed.
+        if self.paused {
+            return;
         }
 
         if self.rx_rate_limiter.is_blocked() {
             self.metrics.rx_rate_limiter_throttled.inc();
         } else {
             // If the limiter is not blocked, resume the receiving of bytes.
-            self.resume_rx()
+            self.resume_rx(pair)
                 .unwrap_or_else(|err| report_net_event_fail(&self.metrics, err));
         }
     }
 
-    pub fn process_tap_rx_event(&mut self) {
+    pub fn process_tap_rx_event(&mut self, pair: usize) {
         // This is safe since we checked in the event handler that the device is activated.
         self.metrics.rx_tap_event_count.inc();
 
+        // The frames are left on the tap until the device is resumed.
+        if self.paused {
+            return;
+        }
+
         // While limiter is blocked, don't process any more incoming.
         if self.rx_rate_limiter.is_blocked() {
             self.metrics.rx_rate_limiter_throttled.inc();
             return;
         }
 
-        self.resume_rx()
+        self.resume_rx(pair)
             .unwrap_or_else(|err| report_net_event_fail(&self.metrics, err));
     }
 
-    /// Process a single TX queue event.
+    /// Process a single TX queue event of the queue pair `pair`.
     ///
     /// This is called by the event manager responding to the guest adding a new
     /// buffer in the TX queue.
-    pub fn process_tx_queue_event(&mut self) {
+    pub fn process_tx_queue_event(&mut self, pair: usize) {
         self.metrics.tx_queue_event_count.inc();
-        if let Err(err) = self.queue_evts[TX_INDEX].read() {
+        if let Err(err) = self.queue_evts[tx_index(pair)].read() {
             error!("Failed to get tx queue event: {:?}", err);
             self.metrics.event_fails.inc();
+        } else if self.paused {
+            // The frames are sent once the device is resumed.
         } else if !self.tx_rate_limiter.is_blocked()
         // If the limiter is not blocked, continue transmitting bytes.
         {
-            self.process_tx()
+            self.process_tx(pair)
                 .unwrap_or_else(|err| report_net_event_fail(&self.metrics, err));
         } else {
             self.metrics.tx_rate_limiter_throttled.inc();
@@ -896,10 +1498,14 @@ impl Net {
         // and restart processing the queue.
 
         match self.rx_rate_limiter.event_handler() {
+            // The frames are received once the device is resumed.
+            Ok(_) if self.paused => (),
             Ok(_) => {
-                // There might be enough budget now to receive the frame.
-                self.resume_rx()
-                    .unwrap_or_else(|err| report_net_event_fail(&self.metrics, err));
+                // There might be enough budget now to receive the frames of the queue pairs in use.
+                for pair in 0..usize::from(self.active_queue_pairs) {
+                    self.resume_rx(pair)
+                        .unwrap_or_else(|err| report_net_event_fail(&self.metrics, err));
+                }
             }
             Err(err) => {
                 error!("Failed to get rx rate-limiter event: {:?}", err);
@@ -913,10 +1519,14 @@ impl Net {
         // Upon rate limiter event, call the rate limiter handler
         // and restart processing the queue.
         match self.tx_rate_limiter.event_handler() {
+            // The frames are sent once the device is resumed.
+            Ok(_) if self.paused => (),
             Ok(_) => {
-                // There might be enough budget now to send the frame.
-                self.process_tx()
-                    .unwrap_or_else(|err| report_net_event_fail(&self.metrics, err));
+                // There might be enough budget now to send the frames of the queue pairs in use.
+                for pair in 0..usize::from(self.active_queue_pairs) {
+                    self.process_tx(pair)
+                        .unwrap_or_else(|err| report_net_event_fail(&self.metrics, err));
+                }
             }
             Err(err) => {
                 error!("Failed to get tx rate-limiter event: {:?}", err);
@@ -925,10 +1535,33 @@ impl Net {
         }
     }
 
+    /// Process a single control queue event.
+    ///
+    /// This is called by the event manager responding to the guest sending a command on the
+    /// control queue.
+    pub fn process_ctrl_queue_event(&mut self) {
+        self.metrics.ctrl_queue_event_count.inc();
+        let Some(queue_index) = self.ctrl_queue_index() else {
+            return;
+        };
+        if let Err(err) = self.queue_evts[queue_index].read() {
+            error!("Failed to get ctrl queue event: {:?}", err);
+            self.metrics.event_fails.inc();
+        } else {
+            self.process_ctrl_queue(queue_index)
+                .unwrap_or_else(|err| report_net_event_fail(&self.metrics, err));
+        }
+    }
+
     /// Process device virtio queue(s).
     pub fn process_virtio_queues(&mut self) {
-        let _ = self.resume_rx();
-        let _ = self.process_tx();
+        if let Some(queue_index) = self.ctrl_queue_index() {
+            let _ = self.process_ctrl_queue(queue_index);
+        }
+        for pair in 0..usize::from(self.active_queue_pairs) {
+            let _ = self.resume_rx(pair);
+            let _ = self.process_tx(pair);
+        }
     }
 }
 
@@ -971,11 +1604,13 @@ impl VirtioDevice for Net {
         } else {
             error!("Failed to read config space");
             self.metrics.cfg_fails.inc();
+            self.error_reporter.report(DeviceErrorClass::Guest, None);
         }
     }
 
     fn write_config(&mut self, offset: u64, data: &[u8]) {
-        let config_space_bytes = self.config_space.as_mut_slice();
+        // Only the MAC address can be written by the driver.
+        let config_space_bytes = &mut self.config_space.as_mut_slice()[..usize::from(MAC_ADDR_LEN)];
         let start = usize::try_from(offset).ok();
         let end = start.and_then(|s| s.checked_add(data.len()));
         let Some(dst) = start
@@ -984,6 +1619,7 @@ impl VirtioDevice for Net {
         else {
             error!("Failed to write config space");
             self.metrics.cfg_fails.inc();
+            self.error_reporter.report(DeviceErrorClass::Guest, None);
             return;
         };
 
@@ -1006,14 +1642,22 @@ impl VirtioDevice for Net {
         }
 
         let supported_flags: u32 = Net::build_tap_offload_features(self.acked_features);
-        self.tap
-            .set_offload(supported_flags)
-            .map_err(super::super::ActivateError::TapSetOffload)?;
+        for tap in &self.taps {
+            tap.set_offload(supported_flags)
+                .map_err(super::super::ActivateError::TapSetOffload)?;
+        }
+        // The driver only uses the first queue pair until it sets the number of queue pairs.
+        self.set_active_queue_pairs(1)
+            .map_err(ActivateError::TapSetQueue)?;
 
-        self.rx_buffer.min_buffer_size = self.minimum_rx_buffer_size();
+        let min_buffer_size = self.minimum_rx_buffer_size();
+        for rx_buffer in &mut self.rx_buffers {
+            rx_buffer.min_buffer_size = min_buffer_size;
+        }
 
         if self.activate_evt.write(1).is_err() {
             self.metrics.activate_fails.inc();
+            self.error_reporter.report(DeviceErrorClass::Vmm, None);
             return Err(ActivateError::EventFd);
         }
         self.device_state = DeviceState::Activated(mem);
@@ -1029,13 +1673,14 @@ impl VirtioDevice for Net {
 #[macro_use]
 #[allow(clippy::cast_possible_truncation)]
 pub mod tests {
-    use std::net::Ipv4Addr;
+    use std::net::{IpAddr, Ipv4Addr};
     use std::os::fd::AsRawFd;
     use std::str::FromStr;
     use std::time::Duration;
     use std::{mem, thread};
 
     use vm_memory::GuestAddress;
+    use vmm_sys_util::tempfile::TempFile;
 
     use super::*;
     use crate::check_metric_after_block;
@@ -1044,16 +1689,18 @@ pub mod tests {
     use crate::devices::virtio::net::device::{
         frame_bytes_from_buf, frame_bytes_from_buf_mut, frame_hdr_len, init_vnet_hdr, vnet_hdr_len,
     };
+    use crate::devices::virtio::net::flows::{FlowCounters, FlowKey};
     use crate::devices::virtio::net::test_utils::test::TestHelper;
     use crate::devices::virtio::net::test_utils::{
         default_net, if_index, inject_tap_tx_frame, set_mac, NetEvent, NetQueue,
         TapTrafficSimulator,
     };
-    use crate::devices::virtio::net::NET_QUEUE_SIZES;
+    use crate::devices::virtio::net::{NET_QUEUE_SIZES, RX_INDEX, TX_INDEX};
     use crate::devices::virtio::queue::VIRTQ_DESC_F_WRITE;
     use crate::devices::virtio::test_utils::VirtQueue;
     use crate::dumbo::pdu::arp::{EthIPv4ArpFrame, ETH_IPV4_FRAME_LEN};
-    use crate::dumbo::pdu::ethernet::ETHERTYPE_ARP;
+    use crate::dumbo::pdu::ethernet::{ETHERTYPE_ARP, ETHERTYPE_IPV4};
+    use crate::dumbo::pdu::ipv4::PROTOCOL_TCP;
     use crate::dumbo::EthernetFrame;
     use crate::logger::IncMetric;
     use crate::rate_limiter::{BucketUpdate, RateLimiter, TokenBucket, TokenType};
@@ -1063,7 +1710,7 @@ pub mod tests {
 
     impl Net {
         pub fn finish_frame(&mut self) {
-            self.rx_buffer.finish_frame(&mut self.queues[RX_INDEX]);
+            self.rx_buffers[0].finish_frame(&mut self.queues[RX_INDEX]);
         }
     }
 
@@ -1144,6 +1791,94 @@ pub mod tests {
         assert_eq!(net.acked_features, features);
     }
 
+    #[test]
+    fn test_queue_pairs() {
+        let new_net = |num_queue_pairs| {
+            Net::new_with_queues(
+                "mq-net".to_string(),
+                "net-device%d",
+                num_queue_pairs,
+                None,
+                RateLimiter::default(),
+                RateLimiter::default(),
+            )
+        };
+        for invalid in [0, MAX_QUEUE_PAIRS + 1] {
+            assert!(matches!(
+                new_net(invalid),
+                Err(NetError::InvalidQueuePairs(pairs)) if pairs == invalid
+            ));
+        }
+
+        // A single queue pair has no control queue.
+        let net = default_net();
+        assert_eq!(net.num_queue_pairs(), 1);
+        assert_eq!(net.queues().len(), 2);
+        assert_eq!(net.ctrl_queue_index(), None);
+        assert_eq!(net.avail_features() & (1 << VIRTIO_NET_F_MQ), 0);
+
+        let mut net = new_net(3).unwrap();
+        assert_eq!(net.num_queue_pairs(), 3);
+        assert_eq!(net.taps.len(), 3);
+        assert_eq!(net.queues().len(), 7);
+        assert_eq!(net.queue_events().len(), 7);
+        assert_eq!(net.ctrl_queue_index(), Some(6));
+        assert_eq!(net.config_space.max_virtqueue_pairs, 3);
+        let mq_features = 1 << VIRTIO_NET_F_MQ | 1 << VIRTIO_NET_F_CTRL_VQ;
+        assert_eq!(net.avail_features() & mq_features, mq_features);
+        // All the tap queues are attached until the driver sets the queue pairs it uses.
+        assert_eq!(net.active_queue_pairs(), 3);
+
+        net.set_active_queue_pairs(1).unwrap();
+        assert_eq!(
+            net.apply_ctrl_command(CtrlCommand::SetQueuePairs(2)),
+            VIRTIO_NET_OK
+        );
+        assert_eq!(net.active_queue_pairs(), 2);
+        // Detaching a queue twice fails, so only the queues changing state are updated.
+        assert_eq!(
+            net.apply_ctrl_command(CtrlCommand::SetQueuePairs(1)),
+            VIRTIO_NET_OK
+        );
+        assert_eq!(net.active_queue_pairs(), 1);
+
+        for command in [
+            CtrlCommand::SetQueuePairs(0),
+            CtrlCommand::SetQueuePairs(4),
+            CtrlCommand::Unsupported {
+                class: 0,
+                command: 0,
+            },
+        ] {
+            check_metric_after_block!(
+                net.metrics.ctrl_fails,
+                1,
+                assert_eq!(net.apply_ctrl_command(command), VIRTIO_NET_ERR)
+            );
+        }
+        assert_eq!(net.active_queue_pairs(), 1);
+    }
+
+    #[test]
+    fn test_offload_features() {
+        let mut net = default_net();
+        assert_eq!(net.offload_features(), NET_OFFLOAD_FEATURES);
+
+        let features = 1 << VIRTIO_NET_F_GUEST_CSUM | 1 << VIRTIO_NET_F_CSUM;
+        net.set_offload_features(features | 1 << VIRTIO_NET_F_MQ);
+        assert_eq!(net.offload_features(), features);
+        // The other features are left unchanged.
+        assert_eq!(
+            net.avail_features() & !NET_OFFLOAD_FEATURES,
+            default_net().avail_features() & !NET_OFFLOAD_FEATURES
+        );
+
+        // Without any offload, the tap does not pass GSO frames to the guest.
+        net.set_offload_features(0);
+        net.set_acked_features(net.avail_features());
+        assert_eq!(Net::build_tap_offload_features(net.acked_features()), 0);
+    }
+
     #[test]
     // Test that `Net::build_tap_offload_features` creates the TAP offload features that we expect
     // it to do, based on the available guest features
@@ -1229,6 +1964,31 @@ pub mod tests {
         assert_eq!(new_config, new_config_read);
     }
 
+    #[test]
+    fn test_mtu() {
+        let mut net = default_net();
+        assert_eq!(net.mtu(), None);
+        assert_eq!(net.avail_features() & (1 << VIRTIO_NET_F_MTU), 0);
+
+        net.set_mtu(9000).unwrap();
+        assert_eq!(net.mtu(), Some(9000));
+        assert_ne!(net.avail_features() & (1 << VIRTIO_NET_F_MTU), 0);
+        let mut mtu = [0u8; 2];
+        net.read_config(10, &mut mtu);
+        assert_eq!(u16::from_le_bytes(mtu), 9000);
+
+        // The MTU is read-only.
+        net.write_config(10, &1500u16.to_le_bytes());
+        net.read_config(10, &mut mtu);
+        assert_eq!(u16::from_le_bytes(mtu), 9000);
+
+        // Without mergeable RX buffers, the RX buffers have to fit a whole frame.
+        net.acked_features = 1 << VIRTIO_NET_F_MTU;
+        assert_eq!(net.minimum_rx_buffer_size(), 9026);
+        net.acked_features = 0;
+        assert_eq!(net.minimum_rx_buffer_size(), 1526);
+    }
+
     #[test]
     fn test_rx_missing_queue_signal() {
         let mem = single_region_mem(2 * MAX_BUFFER_SIZE);
@@ -1397,7 +2157,7 @@ pub mod tests {
         th.rxq.check_used_elem(1, 3, 0);
         th.rxq.check_used_elem(2, 4, 0);
         // Check that the frame wasn't deferred.
-        assert!(th.net().rx_buffer.used_descriptors == 0);
+        assert!(th.net().rx_buffers[0].used_descriptors == 0);
         // Check that the frame has been written successfully to the valid Rx descriptor chain.
         th.rxq
             .check_used_elem(3, 5, frame.len().try_into().unwrap());
@@ -1445,7 +2205,7 @@ pub mod tests {
         );
 
         // Check that the frame wasn't deferred.
-        assert!(th.net().rx_buffer.used_descriptors == 0);
+        assert!(th.net().rx_buffers[0].used_descriptors == 0);
         // Check that the used queue has advanced.
         assert_eq!(th.rxq.used.idx.get(), 1);
         assert!(&th.net().irq_trigger.has_pending_irq(IrqType::Vring));
@@ -1508,7 +2268,7 @@ pub mod tests {
         );
 
         // Check that the frames weren't deferred.
-        assert!(th.net().rx_buffer.used_bytes == 0);
+        assert!(th.net().rx_buffers[0].used_bytes == 0);
         // Check that the used queue has advanced.
         assert_eq!(th.rxq.used.idx.get(), 2);
         assert!(&th.net().irq_trigger.has_pending_irq(IrqType::Vring));
@@ -1566,7 +2326,7 @@ pub mod tests {
         );
 
         // Check that the frame wasn't deferred.
-        assert!(th.net().rx_buffer.used_bytes == 0);
+        assert!(th.net().rx_buffers[0].used_bytes == 0);
         // Check that the used queue has advanced.
         assert_eq!(th.rxq.used.idx.get(), 2);
         assert!(&th.net().irq_trigger.has_pending_irq(IrqType::Vring));
@@ -1604,7 +2364,7 @@ pub mod tests {
         let mem = single_region_mem(2 * MAX_BUFFER_SIZE);
         let mut th = TestHelper::get_default(&mem);
         th.activate_net();
-        let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&th.net().tap));
+        let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&th.net().taps[0]));
 
         th.add_desc_chain(NetQueue::Tx, 0, &[(0, 4096, 0)]);
         th.net().queue_evts[TX_INDEX].read().unwrap();
@@ -1625,7 +2385,7 @@ pub mod tests {
         let mem = single_region_mem(2 * MAX_BUFFER_SIZE);
         let mut th = TestHelper::get_default(&mem);
         th.activate_net();
-        let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&th.net().tap));
+        let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&th.net().taps[0]));
 
         let desc_list = [(0, 100, 0), (1, 100, VIRTQ_DESC_F_WRITE), (2, 500, 0)];
         th.add_desc_chain(NetQueue::Tx, 0, &desc_list);
@@ -1645,7 +2405,7 @@ pub mod tests {
         let mem = single_region_mem(2 * MAX_BUFFER_SIZE);
         let mut th = TestHelper::get_default(&mem);
         th.activate_net();
-        let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&th.net().tap));
+        let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&th.net().taps[0]));
 
         // Send an invalid frame (too small, VNET header missing).
         th.add_desc_chain(NetQueue::Tx, 0, &[(0, 1, 0)]);
@@ -1668,7 +2428,7 @@ pub mod tests {
         let mem = single_region_mem(2 * MAX_BUFFER_SIZE);
         let mut th = TestHelper::get_default(&mem);
         th.activate_net();
-        let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&th.net().tap));
+        let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&th.net().taps[0]));
 
         // Send an invalid frame (too big, maximum buffer is MAX_BUFFER_SIZE).
         th.add_desc_chain(
@@ -1695,7 +2455,7 @@ pub mod tests {
         let mem = single_region_mem(2 * MAX_BUFFER_SIZE);
         let mut th = TestHelper::get_default(&mem);
         th.activate_net();
-        let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&th.net().tap));
+        let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&th.net().taps[0]));
 
         // Send an invalid frame (too small, VNET header missing).
         th.add_desc_chain(NetQueue::Tx, 0, &[(0, 0, 0)]);
@@ -1718,7 +2478,7 @@ pub mod tests {
         let mem = single_region_mem(2 * MAX_BUFFER_SIZE);
         let mut th = TestHelper::get_default(&mem);
         th.activate_net();
-        let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&th.net().tap));
+        let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&th.net().taps[0]));
 
         // Add invalid descriptor chain - writeable descriptor.
         th.add_desc_chain(
@@ -1761,7 +2521,7 @@ pub mod tests {
         let mem = single_region_mem(2 * MAX_BUFFER_SIZE);
         let mut th = TestHelper::get_default(&mem);
         th.activate_net();
-        let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&th.net().tap));
+        let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&th.net().taps[0]));
 
         // Add gaps between the descriptor ids in order to ensure that we follow
         // the `next` field.
@@ -1792,7 +2552,7 @@ pub mod tests {
         th.activate_net();
         // force the next write to the tap to return an error by simply closing the fd
         // SAFETY: its a valid fd
-        unsafe { libc::close(th.net.lock().unwrap().tap.as_raw_fd()) };
+        unsafe { libc::close(th.net.lock().unwrap().taps[0].as_raw_fd()) };
 
         let desc_list = [(0, 1000, 0)];
         th.add_desc_chain(NetQueue::Tx, 0, &desc_list);
@@ -1818,7 +2578,7 @@ pub mod tests {
         let mem = single_region_mem(2 * MAX_BUFFER_SIZE);
         let mut th = TestHelper::get_default(&mem);
         th.activate_net();
-        let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&th.net().tap));
+        let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&th.net().taps[0]));
 
         // Write the first frame to the Tx queue
         let desc_list = [(0, 50, 0), (1, 100, 0), (2, 150, 0)];
@@ -1850,6 +2610,30 @@ pub mod tests {
         assert_eq!(&buf[..600], &frame_2[..600]);
     }
 
+    #[test]
+    fn test_pause_resume() {
+        let mem = single_region_mem(2 * MAX_BUFFER_SIZE);
+        let mut th = TestHelper::get_default(&mem);
+        th.activate_net();
+        let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&th.net().taps[0]));
+
+        // The frames sent by the guest while the device is paused are left in the TX queue.
+        th.net().pause();
+        let desc_list = [(0, 50, 0), (1, 100, 0), (2, 150, 0)];
+        th.add_desc_chain(NetQueue::Tx, 0, &desc_list);
+        let frame = th.write_tx_frame(&desc_list, 300);
+        th.event_manager.run_with_timeout(100).unwrap();
+        assert_eq!(th.txq.used.idx.get(), 0);
+
+        // They are sent once the device is resumed.
+        th.net().resume();
+        assert!(!th.net().is_paused());
+        assert_eq!(th.txq.used.idx.get(), 1);
+        let mut buf = vec![0; 300];
+        assert!(tap_traffic_simulator.pop_rx_packet(&mut buf[vnet_hdr_len()..]));
+        assert_eq!(&buf[..300], &frame[..300]);
+    }
+
     fn create_arp_request(
         src_mac: MacAddr,
         src_ip: Ipv4Addr,
@@ -1894,8 +2678,8 @@ pub mod tests {
         // MMDS frame. One iovec will be just fine.
         let mut fake_buffer = vec![0u8; MAX_BUFFER_SIZE];
         let iov_buffer = IoVecBufferMut::from(fake_buffer.as_mut_slice());
-        net.rx_buffer.iovec = iov_buffer;
-        net.rx_buffer
+        net.rx_buffers[0].iovec = iov_buffer;
+        net.rx_buffers[0]
             .parsed_descriptors
             .push_back(ParsedDescriptorChain {
                 head_index: 1,
@@ -1924,9 +2708,13 @@ pub mod tests {
                 &mut net.tx_rate_limiter,
                 &mut headers,
                 &buffer,
-                &mut net.tap,
+                net.taps.first_mut(),
                 Some(src_mac),
+                MacFilter::default(),
                 &net.metrics,
+                &net.error_reporter,
+                net.flow_table.as_mut(),
+                net.pause_responder.as_mut(),
             )
             .unwrap())
         );
@@ -1935,7 +2723,95 @@ pub mod tests {
         check_metric_after_block!(
             &METRICS.mmds.tx_frames,
             1,
-            net.read_from_mmds_or_tap().unwrap()
+            net.read_from_mmds_or_tap(0).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_mmds_only() {
+        let mut net = Net::new_mmds_only(
+            "mmds-only".to_string(),
+            None,
+            RateLimiter::default(),
+            RateLimiter::default(),
+        )
+        .unwrap();
+        net.configure_mmds_network_stack(
+            MmdsNetworkStack::default_ipv4_addr(),
+            Arc::new(Mutex::new(Mmds::default())),
+        );
+        assert!(net.is_mmds_only());
+        assert_eq!(net.iface_name(), "");
+
+        let mem = single_region_mem(2 * MAX_BUFFER_SIZE);
+        let rxq = VirtQueue::new(GuestAddress(0), &mem, 16);
+        net.queues[RX_INDEX] = rxq.create_queue();
+        let mut fake_buffer = vec![0u8; MAX_BUFFER_SIZE];
+        net.rx_buffers[0].iovec = IoVecBufferMut::from(fake_buffer.as_mut_slice());
+        net.rx_buffers[0]
+            .parsed_descriptors
+            .push_back(ParsedDescriptorChain {
+                head_index: 1,
+                length: 1024,
+                nr_iovecs: 1,
+            });
+
+        // There is nothing to read without a tap.
+        match net.read_from_mmds_or_tap(0) {
+            Err(NetError::IO(err)) => assert_eq!(err.raw_os_error(), Some(EAGAIN)),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+
+        let src_mac = MacAddr::from_str("11:11:11:11:11:11").unwrap();
+        let src_ip = Ipv4Addr::new(10, 1, 2, 3);
+        let dst_mac = MacAddr::from_str("22:22:22:22:22:22").unwrap();
+        let mut headers = vec![0; frame_hdr_len()];
+
+        // The frames which are not for the MMDS are dropped.
+        let (frame_buf, frame_len) =
+            create_arp_request(src_mac, src_ip, dst_mac, Ipv4Addr::new(10, 1, 1, 1));
+        let buffer = IoVecBuffer::from(&frame_buf[..frame_len]);
+        check_metric_after_block!(
+            net.metrics.tx_dropped_frames,
+            1,
+            assert!(!Net::write_to_mmds_or_tap(
+                net.mmds_ns.as_mut(),
+                &mut net.tx_rate_limiter,
+                &mut headers,
+                &buffer,
+                net.taps.first_mut(),
+                Some(src_mac),
+                MacFilter::default(),
+                &net.metrics,
+                &net.error_reporter,
+                net.flow_table.as_mut(),
+                net.pause_responder.as_mut(),
+            )
+            .unwrap())
+        );
+
+        // The MMDS frames are still answered.
+        let (frame_buf, frame_len) =
+            create_arp_request(src_mac, src_ip, dst_mac, Ipv4Addr::new(169, 254, 169, 254));
+        let buffer = IoVecBuffer::from(&frame_buf[..frame_len]);
+        assert!(Net::write_to_mmds_or_tap(
+            net.mmds_ns.as_mut(),
+            &mut net.tx_rate_limiter,
+            &mut headers,
+            &buffer,
+            net.taps.first_mut(),
+            Some(src_mac),
+            MacFilter::default(),
+            &net.metrics,
+            &net.error_reporter,
+            net.flow_table.as_mut(),
+            net.pause_responder.as_mut(),
+        )
+        .unwrap());
+        check_metric_after_block!(
+            &METRICS.mmds.tx_frames,
+            1,
+            net.read_from_mmds_or_tap(0).unwrap()
         );
     }
 
@@ -1962,9 +2838,13 @@ pub mod tests {
                 &mut net.tx_rate_limiter,
                 &mut headers,
                 &buffer,
-                &mut net.tap,
+                net.taps.first_mut(),
                 Some(guest_mac),
+                MacFilter::default(),
                 &net.metrics,
+                &net.error_reporter,
+                net.flow_table.as_mut(),
+                net.pause_responder.as_mut(),
             )
         );
 
@@ -1977,13 +2857,274 @@ pub mod tests {
                 &mut net.tx_rate_limiter,
                 &mut headers,
                 &buffer,
-                &mut net.tap,
+                net.taps.first_mut(),
                 Some(not_guest_mac),
+                MacFilter::default(),
+                &net.metrics,
+                &net.error_reporter,
+                net.flow_table.as_mut(),
+                net.pause_responder.as_mut(),
+            )
+        );
+    }
+
+    #[test]
+    fn test_mac_spoofing_enforcement() {
+        let mut net = default_net();
+        let guest_mac = MacAddr::from_str("11:11:11:11:11:11").unwrap();
+        let not_guest_mac = MacAddr::from_str("33:33:33:33:33:33").unwrap();
+        let guest_ip = Ipv4Addr::new(10, 1, 2, 3);
+        let dst_mac = MacAddr::from_str("22:22:22:22:22:22").unwrap();
+        let dst_ip = Ipv4Addr::new(10, 1, 1, 1);
+        let mut headers = vec![0; frame_hdr_len()];
+        let mut write_frame = |net: &mut Net, frame: &[u8], mac_filter| {
+            Net::write_to_mmds_or_tap(
+                net.mmds_ns.as_mut(),
+                &mut net.tx_rate_limiter,
+                &mut headers,
+                &IoVecBuffer::from(frame),
+                net.taps.first_mut(),
+                Some(guest_mac),
+                mac_filter,
                 &net.metrics,
+                &net.error_reporter,
+                net.flow_table.as_mut(),
+                net.pause_responder.as_mut(),
             )
+            .unwrap();
+        };
+        let enforce_mac = MacFilter {
+            enforce_mac: true,
+            enforce_arp: false,
+        };
+        let enforce_arp = MacFilter {
+            enforce_mac: false,
+            enforce_arp: true,
+        };
+
+        // Frames sent from the guest MAC address go through.
+        let (frame_buf, frame_len) = create_arp_request(guest_mac, guest_ip, dst_mac, dst_ip);
+        check_metric_after_block!(
+            net.metrics.tx_packets_count,
+            1,
+            write_frame(&mut net, &frame_buf[..frame_len], enforce_mac)
+        );
+
+        // Frames sent from another MAC address are only dropped when enforcing the MAC address.
+        let (frame_buf, frame_len) = create_arp_request(not_guest_mac, guest_ip, dst_mac, dst_ip);
+        check_metric_after_block!(
+            net.metrics.tx_spoofed_dropped_frames,
+            1,
+            write_frame(&mut net, &frame_buf[..frame_len], enforce_mac)
+        );
+        check_metric_after_block!(
+            net.metrics.tx_packets_count,
+            1,
+            write_frame(&mut net, &frame_buf[..frame_len], MacFilter::default())
+        );
+
+        // ARP frames from the guest MAC address announcing another one are only dropped when
+        // enforcing the ARP sender address.
+        let (mut frame_buf, frame_len) = create_arp_request(guest_mac, guest_ip, dst_mac, dst_ip);
+        let sha_offset = vnet_hdr_len() + PAYLOAD_OFFSET + 8;
+        frame_buf[sha_offset..sha_offset + MAC_ADDR_LEN as usize]
+            .copy_from_slice(not_guest_mac.get_bytes());
+        check_metric_after_block!(
+            net.metrics.tx_packets_count,
+            1,
+            write_frame(&mut net, &frame_buf[..frame_len], enforce_mac)
+        );
+        check_metric_after_block!(
+            net.metrics.tx_spoofed_dropped_frames,
+            1,
+            write_frame(&mut net, &frame_buf[..frame_len], enforce_arp)
         );
     }
 
+    #[test]
+    fn test_flow_accounting() {
+        let mem = single_region_mem(3 * MAX_BUFFER_SIZE);
+        let mut th = TestHelper::get_default(&mem);
+        th.net().enable_flow_accounting(16);
+        th.activate_net();
+
+        // A TCP segment sent by the guest.
+        let desc_list = [(0, 1000, 0)];
+        th.add_desc_chain(NetQueue::Tx, 0, &desc_list);
+        th.write_tx_frame(&desc_list, 1000);
+        let guest_ip = Ipv4Addr::new(10, 0, 0, 2);
+        let peer_ip = Ipv4Addr::new(1, 1, 1, 1);
+        let mut headers = ETHERTYPE_IPV4.to_be_bytes().to_vec();
+        let mut ipv4_header = [0u8; 20];
+        ipv4_header[0] = 0x45;
+        ipv4_header[9] = PROTOCOL_TCP;
+        ipv4_header[12..16].copy_from_slice(&guest_ip.octets());
+        ipv4_header[16..20].copy_from_slice(&peer_ip.octets());
+        headers.extend_from_slice(&ipv4_header);
+        headers.extend_from_slice(&40000u16.to_be_bytes());
+        headers.extend_from_slice(&443u16.to_be_bytes());
+        th.mem
+            .write_slice(
+                &headers,
+                GuestAddress::new(th.txq.dtable[0].addr.get() + vnet_hdr_len() as u64 + 12),
+            )
+            .unwrap();
+        check_metric_after_block!(
+            th.net().metrics.tx_packets_count,
+            1,
+            th.event_manager.run_with_timeout(100).unwrap()
+        );
+
+        // A non-IP frame received by the guest.
+        th.add_desc_chain(
+            NetQueue::Rx,
+            MAX_BUFFER_SIZE as u64,
+            &[
+                (1, 500, VIRTQ_DESC_F_WRITE),
+                (2, MAX_BUFFER_SIZE as u32 - 500, VIRTQ_DESC_F_WRITE),
+            ],
+        );
+        inject_tap_tx_frame(&th.net(), 800);
+        check_metric_after_block!(
+            th.net().metrics.rx_packets_count,
+            1,
+            th.event_manager.run_with_timeout(100).unwrap()
+        );
+
+        let flows = th.net().flow_table().unwrap().snapshot();
+        assert_eq!(flows.flows.len(), 1);
+        assert_eq!(
+            flows.flows[0].key,
+            FlowKey {
+                protocol: PROTOCOL_TCP,
+                local_addr: IpAddr::V4(guest_ip),
+                local_port: 40000,
+                remote_addr: IpAddr::V4(peer_ip),
+                remote_port: 443,
+            }
+        );
+        assert_eq!(
+            flows.flows[0].counters,
+            FlowCounters {
+                tx_bytes: (1000 - vnet_hdr_len()) as u64,
+                tx_packets: 1,
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            flows.other,
+            FlowCounters {
+                rx_bytes: (800 - vnet_hdr_len()) as u64,
+                rx_packets: 1,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_packet_capture() {
+        let mem = single_region_mem(3 * MAX_BUFFER_SIZE);
+        let mut th = TestHelper::get_default(&mem);
+        let file = TempFile::new().unwrap();
+        th.net().start_capture(file.as_path(), 100, 4096).unwrap();
+        assert!(th.net().is_capturing());
+        th.activate_net();
+
+        // A frame sent by the guest is captured, truncated to the snapshot length.
+        let desc_list = [(0, 1000, 0)];
+        th.add_desc_chain(NetQueue::Tx, 0, &desc_list);
+        th.write_tx_frame(&desc_list, 1000);
+        check_metric_after_block!(
+            th.net().metrics.capture_frames,
+            1,
+            th.event_manager.run_with_timeout(100).unwrap()
+        );
+
+        // So is a frame received by the guest.
+        th.add_desc_chain(
+            NetQueue::Rx,
+            MAX_BUFFER_SIZE as u64,
+            &[(1, MAX_BUFFER_SIZE as u32, VIRTQ_DESC_F_WRITE)],
+        );
+        inject_tap_tx_frame(&th.net(), 800);
+        check_metric_after_block!(
+            th.net().metrics.capture_frames,
+            1,
+            th.event_manager.run_with_timeout(100).unwrap()
+        );
+
+        th.net().stop_capture();
+        assert!(!th.net().is_capturing());
+        // The pcap header, followed by two records of 100 bytes.
+        let len = std::fs::metadata(file.as_path()).unwrap().len();
+        assert_eq!(len, 24 + 2 * (16 + 100));
+    }
+
+    #[test]
+    fn test_pause_responder() {
+        let mem = single_region_mem(2 * MAX_BUFFER_SIZE);
+        let mut th = TestHelper::get_default(&mem);
+        th.net().enable_pause_responder(16);
+        th.activate_net();
+        th.add_desc_chain(NetQueue::Rx, 0, &[(0, 4096, VIRTQ_DESC_F_WRITE)]);
+
+        // The frames received while paused are not delivered to the guest.
+        th.net().set_vm_paused(true);
+        assert!(th.net().pause_responder().unwrap().is_paused());
+        inject_tap_tx_frame(&th.net(), 1000);
+        check_metric_after_block!(
+            th.net().metrics.paused_rx_dropped_frames,
+            1,
+            th.event_manager.run_with_timeout(100).unwrap()
+        );
+        assert_eq!(th.net().metrics.rx_packets_count.count(), 0);
+        // Only the TCP segments of tracked connections are answered.
+        assert_eq!(th.net().metrics.pause_responder_acks.count(), 0);
+
+        // Once resumed, the frames are delivered to the guest again.
+        th.net().set_vm_paused(false);
+        inject_tap_tx_frame(&th.net(), 1000);
+        check_metric_after_block!(
+            th.net().metrics.rx_packets_count,
+            1,
+            th.event_manager.run_with_timeout(100).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_rx_coalescing() {
+        let mem = single_region_mem(2 * MAX_BUFFER_SIZE);
+        let mut th = TestHelper::get_default(&mem);
+        th.net().enable_rx_coalescing(16);
+        th.net().acked_features = 1 << VIRTIO_NET_F_MRG_RXBUF
+            | 1 << VIRTIO_NET_F_GUEST_TSO4
+            | 1 << VIRTIO_NET_F_GUEST_CSUM;
+        assert!(th.net().rx_coalescing_active());
+        th.activate_net();
+        th.add_desc_chain(
+            NetQueue::Rx,
+            0,
+            &[(0, MAX_BUFFER_SIZE as u32, VIRTQ_DESC_F_WRITE)],
+        );
+
+        // Frames which are not TCP segments go through the coalescer unchanged.
+        let mut frame = inject_tap_tx_frame(&th.net(), 1000);
+        check_metric_after_block!(
+            th.net().metrics.rx_packets_count,
+            1,
+            th.event_manager.run_with_timeout(100).unwrap()
+        );
+        assert_eq!(th.net().metrics.rx_coalesced_segments.count(), 0);
+        header_set_num_buffers(frame.as_mut_slice(), 1);
+        th.rxq
+            .check_used_elem(0, 0, frame.len().try_into().unwrap());
+        th.rxq.dtable[0].check_data(&frame);
+
+        // The segments are only coalesced if the guest accepts the resulting frames.
+        th.net().acked_features = 1 << VIRTIO_NET_F_MRG_RXBUF;
+        assert!(!th.net().rx_coalescing_active());
+    }
+
     #[test]
     fn test_process_error_cases() {
         let mem = single_region_mem(2 * MAX_BUFFER_SIZE);
@@ -2017,11 +3158,11 @@ pub mod tests {
         th.activate_net();
         // force the next write to the tap to return an error by simply closing the fd
         // SAFETY: its a valid fd
-        unsafe { libc::close(th.net.lock().unwrap().tap.as_raw_fd()) };
+        unsafe { libc::close(th.net.lock().unwrap().taps[0].as_raw_fd()) };
 
         // The RX queue is empty and there is a deferred frame.
-        th.net().rx_buffer.used_descriptors = 1;
-        th.net().rx_buffer.used_bytes = 100;
+        th.net().rx_buffers[0].used_descriptors = 1;
+        th.net().rx_buffers[0].used_bytes = 100;
         check_metric_after_block!(
             th.net().metrics.no_rx_avail_buffer,
             1,
@@ -2031,8 +3172,8 @@ pub mod tests {
         // We need to set this here to false, otherwise the device will try to
         // handle a deferred frame, it will fail and will never try to read from
         // the tap.
-        th.net().rx_buffer.used_descriptors = 0;
-        th.net().rx_buffer.used_bytes = 0;
+        th.net().rx_buffers[0].used_descriptors = 0;
+        th.net().rx_buffers[0].used_bytes = 0;
 
         th.add_desc_chain(
             NetQueue::Rx,
@@ -2161,7 +3302,7 @@ pub mod tests {
             let mut rl = RateLimiter::new(1000, 0, 1000, 0, 0, 0).unwrap();
 
             // set up RX
-            assert!(th.net().rx_buffer.used_descriptors == 0);
+            assert!(th.net().rx_buffers[0].used_descriptors == 0);
             th.add_desc_chain(
                 NetQueue::Rx,
                 0,
@@ -2185,7 +3326,7 @@ pub mod tests {
                 // assert that limiter is blocked
                 assert!(th.net().rx_rate_limiter.is_blocked());
                 assert_eq!(th.net().metrics.rx_rate_limiter_throttled.count(), 1);
-                assert!(th.net().rx_buffer.used_descriptors != 0);
+                assert!(th.net().rx_buffers[0].used_descriptors != 0);
                 // assert that no operation actually completed (limiter blocked it)
                 assert!(&th.net().irq_trigger.has_pending_irq(IrqType::Vring));
                 // make sure the data is still queued for processing
@@ -2284,7 +3425,7 @@ pub mod tests {
             let mut rl = RateLimiter::new(0, 0, 0, 1, 0, 1000).unwrap();
 
             // set up RX
-            assert!(th.net().rx_buffer.used_descriptors == 0);
+            assert!(th.net().rx_buffers[0].used_descriptors == 0);
             th.add_desc_chain(
                 NetQueue::Rx,
                 0,
@@ -2310,7 +3451,7 @@ pub mod tests {
                 // assert that limiter is blocked
                 assert!(th.net().rx_rate_limiter.is_blocked());
                 assert!(th.net().metrics.rx_rate_limiter_throttled.count() >= 1);
-                assert!(th.net().rx_buffer.used_descriptors != 0);
+                assert!(th.net().rx_buffers[0].used_descriptors != 0);
                 // assert that no operation actually completed (limiter blocked it)
                 assert!(&th.net().irq_trigger.has_pending_irq(IrqType::Vring));
                 // make sure the data is still queued for processing
@@ -2343,6 +3484,39 @@ pub mod tests {
         }
     }
 
+    #[test]
+    fn test_rate_limiter_borrowing() {
+        let mem = single_region_mem(2 * MAX_BUFFER_SIZE);
+        let mut th = TestHelper::get_default(&mem);
+        th.activate_net();
+
+        // The TX limiter has used up its budget, but may borrow from the idle RX limiter.
+        let mut tx_rl = RateLimiter::new(0x1000, 0, 100_000, 0, 0, 0).unwrap();
+        assert!(tx_rl.consume(0x1000, TokenType::Bytes));
+        tx_rl.set_borrow(true);
+        th.net().tx_rate_limiter = tx_rl;
+        th.net().rx_rate_limiter = RateLimiter::new(0x1000, 0, 100_000, 0, 0, 0).unwrap();
+
+        // The frame is sent using the RX budget.
+        th.add_desc_chain(NetQueue::Tx, 0, &[(0, 4096, 0)]);
+        check_metric_after_block!(
+            th.net().metrics.tx_count,
+            1,
+            th.simulate_event(NetEvent::TxQueue)
+        );
+        assert_eq!(th.txq.used.idx.get(), 1);
+        assert!(!th.net().tx_rate_limiter.is_blocked());
+        assert_eq!(th.net().rx_rate_limiter.bandwidth().unwrap().budget(), 0);
+        assert!(!th.net().rx_rate_limiter.is_blocked());
+
+        // Both budgets are now exhausted, so the next frame is throttled.
+        th.add_desc_chain(NetQueue::Tx, 0, &[(1, 1024, 0)]);
+        th.simulate_event(NetEvent::TxQueue);
+        assert!(th.net().tx_rate_limiter.is_blocked());
+        assert_eq!(th.net().metrics.tx_rate_limiter_throttled.count(), 1);
+        assert_eq!(th.txq.used.idx.get(), 1);
+    }
+
     #[test]
     fn test_patch_rate_limiters() {
         let mem = single_region_mem(2 * MAX_BUFFER_SIZE);
@@ -2358,10 +3532,18 @@ pub mod tests {
         let tx_ops = TokenBucket::new(1009, 1010, 1011).unwrap();
 
         th.net().patch_rate_limiters(
-            BucketUpdate::Update(rx_bytes.clone()),
-            BucketUpdate::Update(rx_ops.clone()),
-            BucketUpdate::Update(tx_bytes.clone()),
-            BucketUpdate::Update(tx_ops.clone()),
+            RateLimiterUpdate {
+                bandwidth: BucketUpdate::Update(rx_bytes.clone()),
+                ops: BucketUpdate::Update(rx_ops.clone()),
+                borrow: Some(true),
+                smoothing: None,
+            },
+            RateLimiterUpdate {
+                bandwidth: BucketUpdate::Update(tx_bytes.clone()),
+                ops: BucketUpdate::Update(tx_ops.clone()),
+                borrow: None,
+                smoothing: None,
+            },
         );
         let compare_buckets = |a: &TokenBucket, b: &TokenBucket| {
             assert_eq!(a.capacity(), b.capacity());
@@ -2372,13 +3554,25 @@ pub mod tests {
         compare_buckets(th.net().rx_rate_limiter.ops().unwrap(), &rx_ops);
         compare_buckets(th.net().tx_rate_limiter.bandwidth().unwrap(), &tx_bytes);
         compare_buckets(th.net().tx_rate_limiter.ops().unwrap(), &tx_ops);
+        assert!(th.net().rx_rate_limiter.borrow());
+        assert!(!th.net().tx_rate_limiter.borrow());
 
         th.net().patch_rate_limiters(
-            BucketUpdate::Disabled,
-            BucketUpdate::Disabled,
-            BucketUpdate::Disabled,
-            BucketUpdate::Disabled,
+            RateLimiterUpdate {
+                bandwidth: BucketUpdate::Disabled,
+                ops: BucketUpdate::Disabled,
+                borrow: None,
+                smoothing: None,
+            },
+            RateLimiterUpdate {
+                bandwidth: BucketUpdate::Disabled,
+                ops: BucketUpdate::Disabled,
+                borrow: Some(false),
+                smoothing: None,
+            },
         );
+        assert!(th.net().rx_rate_limiter.borrow());
+        assert!(!th.net().tx_rate_limiter.borrow());
         assert!(th.net().rx_rate_limiter.bandwidth().is_none());
         assert!(th.net().rx_rate_limiter.ops().is_none());
         assert!(th.net().tx_rate_limiter.bandwidth().is_none());
@@ -6,7 +6,7 @@ use vmm_sys_util::epoll::EventSet;
 
 use crate::devices::virtio::device::VirtioDevice;
 use crate::devices::virtio::net::device::Net;
-use crate::devices::virtio::net::{RX_INDEX, TX_INDEX};
+use crate::devices::virtio::net::{rx_index, tx_index};
 use crate::logger::{error, warn, IncMetric};
 
 impl Net {
@@ -16,21 +16,41 @@ impl Net {
     const PROCESS_TAP_RX: u32 = 3;
     const PROCESS_RX_RATE_LIMITER: u32 = 4;
     const PROCESS_TX_RATE_LIMITER: u32 = 5;
+    const PROCESS_CTRL_QUEUE: u32 = 6;
+
+    // The events of the queue pairs carry the index of their pair above the event type.
+    const PAIR_SHIFT: u32 = 8;
+    const SOURCE_MASK: u32 = (1 << Self::PAIR_SHIFT) - 1;
+
+    fn pair_event_data(source: u32, pair: usize) -> u32 {
+        source | (u32::try_from(pair).unwrap() << Self::PAIR_SHIFT)
+    }
 
     fn register_runtime_events(&self, ops: &mut EventOps) {
-        if let Err(err) = ops.add(Events::with_data(
-            &self.queue_evts[RX_INDEX],
-            Self::PROCESS_VIRTQ_RX,
-            EventSet::IN,
-        )) {
-            error!("Failed to register rx queue event: {}", err);
+        for pair in 0..self.rx_buffers.len() {
+            if let Err(err) = ops.add(Events::with_data(
+                &self.queue_evts[rx_index(pair)],
+                Self::pair_event_data(Self::PROCESS_VIRTQ_RX, pair),
+                EventSet::IN,
+            )) {
+                error!("Failed to register rx queue event: {}", err);
+            }
+            if let Err(err) = ops.add(Events::with_data(
+                &self.queue_evts[tx_index(pair)],
+                Self::pair_event_data(Self::PROCESS_VIRTQ_TX, pair),
+                EventSet::IN,
+            )) {
+                error!("Failed to register tx queue event: {}", err);
+            }
         }
-        if let Err(err) = ops.add(Events::with_data(
-            &self.queue_evts[TX_INDEX],
-            Self::PROCESS_VIRTQ_TX,
-            EventSet::IN,
-        )) {
-            error!("Failed to register tx queue event: {}", err);
+        if let Some(queue_index) = self.ctrl_queue_index() {
+            if let Err(err) = ops.add(Events::with_data(
+                &self.queue_evts[queue_index],
+                Self::PROCESS_CTRL_QUEUE,
+                EventSet::IN,
+            )) {
+                error!("Failed to register ctrl queue event: {}", err);
+            }
         }
         if let Err(err) = ops.add(Events::with_data(
             &self.rx_rate_limiter,
@@ -46,12 +66,14 @@ impl Net {
         )) {
             error!("Failed to register tx queue event: {}", err);
         }
-        if let Err(err) = ops.add(Events::with_data(
-            &self.tap,
-            Self::PROCESS_TAP_RX,
-            EventSet::IN | EventSet::EDGE_TRIGGERED,
-        )) {
-            error!("Failed to register tap event: {}", err);
+        for (pair, tap) in self.taps.iter().enumerate() {
+            if let Err(err) = ops.add(Events::with_data(
+                tap,
+                Self::pair_event_data(Self::PROCESS_TAP_RX, pair),
+                EventSet::IN | EventSet::EDGE_TRIGGERED,
+            )) {
+                error!("Failed to register tap event: {}", err);
+            }
         }
     }
 
@@ -97,13 +119,19 @@ impl MutEventSubscriber for Net {
         }
 
         if self.is_activated() {
-            match source {
+            let pair = usize::try_from(source >> Self::PAIR_SHIFT).unwrap();
+            match source & Self::SOURCE_MASK {
+                _ if pair >= self.rx_buffers.len() => {
+                    warn!("Net: Spurious event received: {:?}", source);
+                    self.metrics.event_fails.inc();
+                }
                 Self::PROCESS_ACTIVATE => self.process_activate_event(ops),
-                Self::PROCESS_VIRTQ_RX => self.process_rx_queue_event(),
-                Self::PROCESS_VIRTQ_TX => self.process_tx_queue_event(),
-                Self::PROCESS_TAP_RX => self.process_tap_rx_event(),
+                Self::PROCESS_VIRTQ_RX => self.process_rx_queue_event(pair),
+                Self::PROCESS_VIRTQ_TX => self.process_tx_queue_event(pair),
+                Self::PROCESS_TAP_RX => self.process_tap_rx_event(pair),
                 Self::PROCESS_RX_RATE_LIMITER => self.process_rx_rate_limiter_event(),
                 Self::PROCESS_TX_RATE_LIMITER => self.process_tx_rate_limiter_event(),
+                Self::PROCESS_CTRL_QUEUE => self.process_ctrl_queue_event(),
                 _ => {
                     warn!("Net: Spurious event received: {:?}", source);
                     self.metrics.event_fails.inc();
@@ -0,0 +1,529 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Per-flow accounting of the traffic exchanged between the guest and the tap device.
+//!
+//! Flows are identified by their IP 5-tuple, from the guest's point of view: the local end is the
+//! guest and the remote end is its peer, so both directions of a connection share a flow.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv6Addr};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use super::device::vnet_hdr_len;
+use super::NET_QUEUE_MAX_SIZE;
+use crate::devices::virtio::iovec::{IoVecBuffer, IoVecBufferMut};
+use crate::dumbo::pdu::ethernet::{EthernetFrame, ETHERTYPE_IPV4, PAYLOAD_OFFSET};
+use crate::dumbo::pdu::ipv4::{IPv4Packet, IPV4_VERSION, PROTOCOL_TCP, PROTOCOL_UDP};
+
+/// Maximum number of flows a flow table can track.
+pub const MAX_FLOW_TABLE_SIZE: usize = 65536;
+
+const ETHERTYPE_IPV6: u16 = 0x86dd;
+const IPV4_HEADER_MIN_LEN: usize = 20;
+const IPV6_HEADER_LEN: usize = 40;
+const IPV6_NEXT_HEADER_OFFSET: usize = 6;
+const IPV6_SOURCE_ADDRESS_OFFSET: usize = 8;
+const IPV6_DESTINATION_ADDRESS_OFFSET: usize = 24;
+// Both TCP and UDP start with the source and destination ports.
+const PORTS_LEN: usize = 4;
+
+/// Length of the frame prefix needed to identify the flow of any supported frame: an Ethernet
+/// header, followed by an IPv6 header and the transport ports.
+const FLOW_HEADER_MAX_LEN: usize = PAYLOAD_OFFSET + IPV6_HEADER_LEN + PORTS_LEN;
+
+/// Flows which have not seen any traffic for this long are evicted from a full table to make room
+/// for new ones.
+const FLOW_IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+/// Minimum interval between two scans of a full table for idle flows.
+const FLOW_EXPIRY_PERIOD: Duration = Duration::from_secs(1);
+
+/// Identifies a flow between the guest and a remote peer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub struct FlowKey {
+    /// IP protocol number, e.g. 6 for TCP and 17 for UDP.
+    pub protocol: u8,
+    /// Address of the guest end of the flow.
+    pub local_addr: IpAddr,
+    /// Port of the guest end of the flow, 0 for protocols without ports.
+    pub local_port: u16,
+    /// Address of the remote end of the flow.
+    pub remote_addr: IpAddr,
+    /// Port of the remote end of the flow, 0 for protocols without ports.
+    pub remote_port: u16,
+}
+
+/// Traffic counters of a flow. Bytes count the Ethernet frames, without the VirtIO header.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct FlowCounters {
+    /// Bytes received by the guest.
+    pub rx_bytes: u64,
+    /// Frames received by the guest.
+    pub rx_packets: u64,
+    /// Bytes sent by the guest.
+    pub tx_bytes: u64,
+    /// Frames sent by the guest.
+    pub tx_packets: u64,
+}
+
+impl FlowCounters {
+    fn add(&mut self, direction: Direction, len: u64) {
+        match direction {
+            Direction::Rx => {
+                self.rx_bytes = self.rx_bytes.wrapping_add(len);
+                self.rx_packets = self.rx_packets.wrapping_add(1);
+            }
+            Direction::Tx => {
+                self.tx_bytes = self.tx_bytes.wrapping_add(len);
+                self.tx_packets = self.tx_packets.wrapping_add(1);
+            }
+        }
+    }
+
+    fn merge(&mut self, other: &FlowCounters) {
+        self.rx_bytes = self.rx_bytes.wrapping_add(other.rx_bytes);
+        self.rx_packets = self.rx_packets.wrapping_add(other.rx_packets);
+        self.tx_bytes = self.tx_bytes.wrapping_add(other.tx_bytes);
+        self.tx_packets = self.tx_packets.wrapping_add(other.tx_packets);
+    }
+}
+
+/// A tracked flow and its counters.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Flow {
+    /// The flow identifier.
+    #[serde(flatten)]
+    pub key: FlowKey,
+    /// The flow counters.
+    #[serde(flatten)]
+    pub counters: FlowCounters,
+}
+
+/// The per-flow accounting of a network device.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct NetFlows {
+    /// The tracked flows, ordered by their identifier.
+    pub flows: Vec<Flow>,
+    /// Traffic which is not accounted to a tracked flow: non-IP frames, frames of new flows
+    /// received while the table was full, and flows evicted from the table.
+    pub other: FlowCounters,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    Rx,
+    Tx,
+}
+
+#[derive(Debug)]
+struct FlowEntry {
+    counters: FlowCounters,
+    last_seen: Instant,
+}
+
+/// Bounded table accounting the traffic of a network device per flow.
+#[derive(Debug)]
+pub struct FlowTable {
+    max_flows: usize,
+    flows: HashMap<FlowKey, FlowEntry>,
+    other: FlowCounters,
+    last_expiry: Option<Instant>,
+}
+
+impl FlowTable {
+    /// Creates an empty table tracking at most `max_flows` flows.
+    pub fn new(max_flows: usize) -> Self {
+        FlowTable {
+            max_flows,
+            flows: HashMap::new(),
+            other: FlowCounters::default(),
+            last_expiry: None,
+        }
+    }
+
+    /// Returns the maximum number of flows tracked by this table.
+    pub fn max_flows(&self) -> usize {
+        self.max_flows
+    }
+
+    /// Accounts a frame sent by the guest, including its VirtIO header.
+    pub fn account_tx(&mut self, frame: &IoVecBuffer) {
+        let mut headers = [0u8; FLOW_HEADER_MAX_LEN];
+        let len = frame
+            .read_volatile_at(&mut &mut headers[..], vnet_hdr_len(), FLOW_HEADER_MAX_LEN)
+            .unwrap_or(0);
+        let frame_len = (frame.len() as usize).saturating_sub(vnet_hdr_len());
+        self.account(Direction::Tx, &headers[..len], frame_len, Instant::now());
+    }
+
+    /// Accounts a frame of `len` bytes received by the guest, including its VirtIO header, which
+    /// was written at the beginning of `frame`.
+    pub fn account_rx(&mut self, frame: &IoVecBufferMut<NET_QUEUE_MAX_SIZE>, len: usize) {
+        let frame_len = len.saturating_sub(vnet_hdr_len());
+        let mut headers = [0u8; FLOW_HEADER_MAX_LEN];
+        let headers_len = frame
+            .read_volatile_at(
+                &mut &mut headers[..],
+                vnet_hdr_len(),
+                FLOW_HEADER_MAX_LEN.min(frame_len),
+            )
+            .unwrap_or(0);
+        self.account(
+            Direction::Rx,
+            &headers[..headers_len],
+            frame_len,
+            Instant::now(),
+        );
+    }
+
+    fn account(&mut self, direction: Direction, headers: &[u8], len: usize, now: Instant) {
+        let len = len as u64;
+        let Some(key) = parse_flow_key(direction, headers) else {
+            self.other.add(direction, len);
+            return;
+        };
+
+        if !self.flows.contains_key(&key) && self.flows.len() >= self.max_flows {
+            self.expire_idle_flows(now);
+        }
+        if let Some(entry) = self.flows.get_mut(&key) {
+            entry.counters.add(direction, len);
+            entry.last_seen = now;
+        } else if self.flows.len() < self.max_flows {
+            let mut counters = FlowCounters::default();
+            counters.add(direction, len);
+            self.flows.insert(
+                key,
+                FlowEntry {
+                    counters,
+                    last_seen: now,
+                },
+            );
+        } else {
+            self.other.add(direction, len);
+        }
+    }
+
+    // Evicts the flows idle for longer than `FLOW_IDLE_TIMEOUT`, accounting their traffic as
+    // `other`. The table is scanned at most once per `FLOW_EXPIRY_PERIOD`, so that a guest
+    // opening many flows can not make each frame cost a full scan.
+    fn expire_idle_flows(&mut self, now: Instant) {
+        if self
+            .last_expiry
+            .is_some_and(|last| now.saturating_duration_since(last) < FLOW_EXPIRY_PERIOD)
+        {
+            return;
+        }
+        self.last_expiry = Some(now);
+
+        let other = &mut self.other;
+        self.flows.retain(|_, entry| {
+            let idle = now.saturating_duration_since(entry.last_seen) >= FLOW_IDLE_TIMEOUT;
+            if idle {
+                other.merge(&entry.counters);
+            }
+            !idle
+        });
+    }
+
+    /// Returns the current counters of all tracked flows.
+    pub fn snapshot(&self) -> NetFlows {
+        let mut flows: Vec<Flow> = self
+            .flows
+            .iter()
+            .map(|(key, entry)| Flow {
+                key: *key,
+                counters: entry.counters,
+            })
+            .collect();
+        flows.sort_unstable_by_key(|flow| flow.key);
+        NetFlows {
+            flows,
+            other: self.other,
+        }
+    }
+}
+
+// Returns the flow of an Ethernet frame, or `None` if it does not carry an IP packet.
+fn parse_flow_key(direction: Direction, headers: &[u8]) -> Option<FlowKey> {
+    let frame = EthernetFrame::from_bytes(headers).ok()?;
+    let (protocol, src_addr, dst_addr, transport) = match frame.ethertype() {
+        ETHERTYPE_IPV4 => {
+            let payload = frame.payload();
+            if payload.len() < IPV4_HEADER_MIN_LEN {
+                return None;
+            }
+            let packet = IPv4Packet::from_bytes_unchecked(payload);
+            let (version, header_len) = packet.version_and_header_len();
+            if version != IPV4_VERSION || usize::from(header_len) < IPV4_HEADER_MIN_LEN {
+                return None;
+            }
+            // Only the first fragment of a packet carries the transport header.
+            let (_, fragment_offset) = packet.flags_and_fragment_offset();
+            let transport = if fragment_offset == 0 {
+                payload.get(usize::from(header_len)..)
+            } else {
+                None
+            };
+            (
+                packet.protocol(),
+                IpAddr::V4(packet.source_address()),
+                IpAddr::V4(packet.destination_address()),
+                transport,
+            )
+        }
+        ETHERTYPE_IPV6 => {
+            let payload = frame.payload();
+            if payload.len() < IPV6_HEADER_LEN {
+                return None;
+            }
+            let address = |offset: usize| {
+                let mut octets = [0u8; 16];
+                octets.copy_from_slice(&payload[offset..offset + 16]);
+                IpAddr::V6(Ipv6Addr::from(octets))
+            };
+            // Extension headers are not walked, so packets using them are accounted under the
+            // first extension header type, without ports.
+            (
+                payload[IPV6_NEXT_HEADER_OFFSET],
+                address(IPV6_SOURCE_ADDRESS_OFFSET),
+                address(IPV6_DESTINATION_ADDRESS_OFFSET),
+                payload.get(IPV6_HEADER_LEN..),
+            )
+        }
+        _ => return None,
+    };
+
+    let (src_port, dst_port) = match transport {
+        Some(ports)
+            if ports.len() >= PORTS_LEN && [PROTOCOL_TCP, PROTOCOL_UDP].contains(&protocol) =>
+        {
+            (
+                u16::from_be_bytes([ports[0], ports[1]]),
+                u16::from_be_bytes([ports[2], ports[3]]),
+            )
+        }
+        _ => (0, 0),
+    };
+
+    Some(match direction {
+        Direction::Tx => FlowKey {
+            protocol,
+            local_addr: src_addr,
+            local_port: src_port,
+            remote_addr: dst_addr,
+            remote_port: dst_port,
+        },
+        Direction::Rx => FlowKey {
+            protocol,
+            local_addr: dst_addr,
+            local_port: dst_port,
+            remote_addr: src_addr,
+            remote_port: src_port,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+    use crate::dumbo::pdu::ethernet::ETHERTYPE_ARP;
+
+    const GUEST_V4: Ipv4Addr = Ipv4Addr::new(192, 168, 0, 2);
+    const PEER_V4: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+
+    fn ethernet(ethertype: u16, payload: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0u8; PAYLOAD_OFFSET];
+        frame[12..14].copy_from_slice(&ethertype.to_be_bytes());
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    fn ipv4_frame(protocol: u8, src: (Ipv4Addr, u16), dst: (Ipv4Addr, u16)) -> Vec<u8> {
+        let mut packet = vec![0u8; IPV4_HEADER_MIN_LEN];
+        packet[0] = 0x45;
+        packet[9] = protocol;
+        packet[12..16].copy_from_slice(&src.0.octets());
+        packet[16..20].copy_from_slice(&dst.0.octets());
+        packet.extend_from_slice(&src.1.to_be_bytes());
+        packet.extend_from_slice(&dst.1.to_be_bytes());
+        ethernet(ETHERTYPE_IPV4, &packet)
+    }
+
+    fn ipv6_frame(protocol: u8, src: (Ipv6Addr, u16), dst: (Ipv6Addr, u16)) -> Vec<u8> {
+        let mut packet = vec![0u8; IPV6_HEADER_LEN];
+        packet[0] = 0x60;
+        packet[IPV6_NEXT_HEADER_OFFSET] = protocol;
+        packet[8..24].copy_from_slice(&src.0.octets());
+        packet[24..40].copy_from_slice(&dst.0.octets());
+        packet.extend_from_slice(&src.1.to_be_bytes());
+        packet.extend_from_slice(&dst.1.to_be_bytes());
+        ethernet(ETHERTYPE_IPV6, &packet)
+    }
+
+    fn tcp_key(local_port: u16, remote_port: u16) -> FlowKey {
+        FlowKey {
+            protocol: PROTOCOL_TCP,
+            local_addr: IpAddr::V4(GUEST_V4),
+            local_port,
+            remote_addr: IpAddr::V4(PEER_V4),
+            remote_port,
+        }
+    }
+
+    #[test]
+    fn test_parse_flow_key() {
+        // Both directions of a connection map to the same flow.
+        let tx = ipv4_frame(PROTOCOL_TCP, (GUEST_V4, 40000), (PEER_V4, 443));
+        let rx = ipv4_frame(PROTOCOL_TCP, (PEER_V4, 443), (GUEST_V4, 40000));
+        assert_eq!(
+            parse_flow_key(Direction::Tx, &tx).unwrap(),
+            tcp_key(40000, 443)
+        );
+        assert_eq!(
+            parse_flow_key(Direction::Rx, &rx).unwrap(),
+            tcp_key(40000, 443)
+        );
+
+        // Protocols without ports.
+        let icmp = ipv4_frame(1, (GUEST_V4, 1234), (PEER_V4, 5678));
+        let key = parse_flow_key(Direction::Tx, &icmp).unwrap();
+        assert_eq!((key.protocol, key.local_port, key.remote_port), (1, 0, 0));
+
+        // Non-first fragments do not carry ports.
+        let mut fragment = ipv4_frame(PROTOCOL_UDP, (GUEST_V4, 53), (PEER_V4, 53));
+        fragment[PAYLOAD_OFFSET + 7] = 0x10;
+        let key = parse_flow_key(Direction::Tx, &fragment).unwrap();
+        assert_eq!((key.local_port, key.remote_port), (0, 0));
+
+        // Truncated transport headers.
+        let truncated = ipv4_frame(PROTOCOL_UDP, (GUEST_V4, 53), (PEER_V4, 53));
+        let key = parse_flow_key(Direction::Tx, &truncated[..truncated.len() - 1]).unwrap();
+        assert_eq!((key.local_port, key.remote_port), (0, 0));
+
+        let guest_v6 = Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 2);
+        let peer_v6 = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1);
+        let rx = ipv6_frame(PROTOCOL_UDP, (peer_v6, 53), (guest_v6, 5353));
+        assert_eq!(
+            parse_flow_key(Direction::Rx, &rx).unwrap(),
+            FlowKey {
+                protocol: PROTOCOL_UDP,
+                local_addr: IpAddr::V6(guest_v6),
+                local_port: 5353,
+                remote_addr: IpAddr::V6(peer_v6),
+                remote_port: 53,
+            }
+        );
+
+        // Frames not carrying IP packets.
+        assert!(parse_flow_key(Direction::Tx, &ethernet(ETHERTYPE_ARP, &[0u8; 28])).is_none());
+        assert!(parse_flow_key(Direction::Tx, &tx[..PAYLOAD_OFFSET + 10]).is_none());
+        assert!(parse_flow_key(Direction::Tx, &tx[..PAYLOAD_OFFSET - 1]).is_none());
+        let mut bad_version = tx.clone();
+        bad_version[PAYLOAD_OFFSET] = 0x65;
+        assert!(parse_flow_key(Direction::Tx, &bad_version).is_none());
+    }
+
+    #[test]
+    fn test_flow_table() {
+        let now = Instant::now();
+        let mut table = FlowTable::new(2);
+        assert_eq!(table.max_flows(), 2);
+
+        let tx_1 = ipv4_frame(PROTOCOL_TCP, (GUEST_V4, 1), (PEER_V4, 80));
+        let rx_1 = ipv4_frame(PROTOCOL_TCP, (PEER_V4, 80), (GUEST_V4, 1));
+        let tx_2 = ipv4_frame(PROTOCOL_TCP, (GUEST_V4, 2), (PEER_V4, 80));
+        let tx_3 = ipv4_frame(PROTOCOL_TCP, (GUEST_V4, 3), (PEER_V4, 80));
+
+        table.account(Direction::Tx, &tx_1, 100, now);
+        table.account(Direction::Rx, &rx_1, 1000, now);
+        table.account(Direction::Tx, &tx_2, 200, now);
+        table.account(Direction::Rx, &ethernet(ETHERTYPE_ARP, &[0u8; 28]), 42, now);
+        // The table is full and no flow is idle.
+        table.account(Direction::Tx, &tx_3, 300, now);
+
+        assert_eq!(
+            table.snapshot(),
+            NetFlows {
+                flows: vec![
+                    Flow {
+                        key: tcp_key(1, 80),
+                        counters: FlowCounters {
+                            rx_bytes: 1000,
+                            rx_packets: 1,
+                            tx_bytes: 100,
+                            tx_packets: 1,
+                        },
+                    },
+                    Flow {
+                        key: tcp_key(2, 80),
+                        counters: FlowCounters {
+                            tx_bytes: 200,
+                            tx_packets: 1,
+                            ..Default::default()
+                        },
+                    },
+                ],
+                other: FlowCounters {
+                    rx_bytes: 42,
+                    rx_packets: 1,
+                    tx_bytes: 300,
+                    tx_packets: 1,
+                },
+            }
+        );
+
+        // Once idle, the first flow is evicted to make room for a new one.
+        let later = now + FLOW_IDLE_TIMEOUT;
+        table.account(Direction::Tx, &tx_2, 200, later - FLOW_EXPIRY_PERIOD);
+        table.account(Direction::Tx, &tx_3, 300, later);
+        let snapshot = table.snapshot();
+        let keys: Vec<FlowKey> = snapshot.flows.iter().map(|flow| flow.key).collect();
+        assert_eq!(keys, vec![tcp_key(2, 80), tcp_key(3, 80)]);
+        assert_eq!(
+            snapshot.other,
+            FlowCounters {
+                rx_bytes: 1042,
+                rx_packets: 2,
+                tx_bytes: 400,
+                tx_packets: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn test_flow_table_expiry_period() {
+        let now = Instant::now();
+        let mut table = FlowTable::new(1);
+        let tx_1 = ipv4_frame(PROTOCOL_UDP, (GUEST_V4, 1), (PEER_V4, 53));
+        let tx_2 = ipv4_frame(PROTOCOL_UDP, (GUEST_V4, 2), (PEER_V4, 53));
+
+        table.account(Direction::Tx, &tx_1, 10, now);
+        let half_period = FLOW_EXPIRY_PERIOD / 2;
+        // Scanning the table for idle flows does not evict anything yet.
+        table.account(
+            Direction::Tx,
+            &tx_2,
+            10,
+            now + FLOW_IDLE_TIMEOUT - half_period,
+        );
+        // The first flow became idle, but the table was scanned too recently.
+        table.account(Direction::Tx, &tx_2, 10, now + FLOW_IDLE_TIMEOUT);
+        assert_eq!(table.snapshot().flows[0].key.local_port, 1);
+
+        table.account(
+            Direction::Tx,
+            &tx_2,
+            10,
+            now + FLOW_IDLE_TIMEOUT + half_period,
+        );
+        let snapshot = table.snapshot();
+        assert_eq!(snapshot.flows.len(), 1);
+        assert_eq!(snapshot.flows[0].key.local_port, 2);
+        assert_eq!(snapshot.other.tx_packets, 3);
+    }
+}
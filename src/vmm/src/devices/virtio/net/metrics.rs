@@ -201,8 +201,29 @@ pub struct NetDeviceMetrics {
     pub tx_rate_limiter_throttled: SharedIncMetric,
     /// Number of packets with a spoofed mac, sent by the guest.
     pub tx_spoofed_mac_count: SharedIncMetric,
+    /// Number of frames sent by the guest with a spoofed source address, and dropped.
+    pub tx_spoofed_dropped_frames: SharedIncMetric,
     /// Number of remaining requests in the TX queue.
     pub tx_remaining_reqs_count: SharedIncMetric,
+    /// Number of frames received and dropped while the microVM was paused.
+    pub paused_rx_dropped_frames: SharedIncMetric,
+    /// Number of acknowledgements sent on behalf of the guest while the microVM was paused.
+    pub pause_responder_acks: SharedIncMetric,
+    /// Number of received TCP segments merged into a preceding segment of their flow.
+    pub rx_coalesced_segments: SharedIncMetric,
+    /// Number of frames sent by the guest on an MMDS-only interface and dropped, as they are not
+    /// for the MMDS.
+    pub tx_dropped_frames: SharedIncMetric,
+    /// Number of events associated with the control queue.
+    pub ctrl_queue_event_count: SharedIncMetric,
+    /// Number of control commands which could not be read or applied.
+    pub ctrl_fails: SharedIncMetric,
+    /// Number of frames written to the packet capture.
+    pub capture_frames: SharedIncMetric,
+    /// Number of frames dropped from the packet capture, as its ring was full.
+    pub capture_dropped_frames: SharedIncMetric,
+    /// Number of packet captures stopped as their file could no longer be written.
+    pub capture_fails: SharedIncMetric,
 }
 
 impl NetDeviceMetrics {
@@ -266,8 +287,25 @@ impl NetDeviceMetrics {
             .add(other.tx_rate_limiter_throttled.fetch_diff());
         self.tx_spoofed_mac_count
             .add(other.tx_spoofed_mac_count.fetch_diff());
+        self.tx_spoofed_dropped_frames
+            .add(other.tx_spoofed_dropped_frames.fetch_diff());
         self.tx_remaining_reqs_count
             .add(other.tx_remaining_reqs_count.fetch_diff());
+        self.paused_rx_dropped_frames
+            .add(other.paused_rx_dropped_frames.fetch_diff());
+        self.pause_responder_acks
+            .add(other.pause_responder_acks.fetch_diff());
+        self.rx_coalesced_segments
+            .add(other.rx_coalesced_segments.fetch_diff());
+        self.tx_dropped_frames
+            .add(other.tx_dropped_frames.fetch_diff());
+        self.ctrl_queue_event_count
+            .add(other.ctrl_queue_event_count.fetch_diff());
+        self.ctrl_fails.add(other.ctrl_fails.fetch_diff());
+        self.capture_frames.add(other.capture_frames.fetch_diff());
+        self.capture_dropped_frames
+            .add(other.capture_dropped_frames.fetch_diff());
+        self.capture_fails.add(other.capture_fails.fetch_diff());
     }
 }
 
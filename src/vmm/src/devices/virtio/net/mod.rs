@@ -9,6 +9,8 @@ use std::io;
 pub const NET_QUEUE_MAX_SIZE: u16 = 256;
 /// Maximum size of the frame buffers handled by this device.
 pub const MAX_BUFFER_SIZE: usize = 65562;
+/// Minimum MTU of the network device, the minimum MTU of ethernet devices.
+pub const MIN_MTU: u16 = 68;
 /// The number of queues of the network device.
 pub const NET_NUM_QUEUES: usize = 2;
 pub const NET_QUEUE_SIZES: [u16; NET_NUM_QUEUES] = [NET_QUEUE_MAX_SIZE; NET_NUM_QUEUES];
@@ -16,10 +18,28 @@ pub const NET_QUEUE_SIZES: [u16; NET_NUM_QUEUES] = [NET_QUEUE_MAX_SIZE; NET_NUM_
 pub const RX_INDEX: usize = 0;
 /// The index of the tx queue from Net device queues/queues_evts vector.
 pub const TX_INDEX: usize = 1;
+/// Maximum number of RX/TX queue pairs of the network device.
+pub const MAX_QUEUE_PAIRS: u16 = 16;
 
+/// Returns the index of the rx queue of the queue pair `pair`. The queues of the pairs are
+/// interleaved, as defined by the virtio specification.
+pub const fn rx_index(pair: usize) -> usize {
+    2 * pair
+}
+
+/// Returns the index of the tx queue of the queue pair `pair`.
+pub const fn tx_index(pair: usize) -> usize {
+    2 * pair + 1
+}
+
+pub mod capture;
+pub mod coalesce;
+pub mod ctrl;
 pub mod device;
 mod event_handler;
+pub mod flows;
 pub mod metrics;
+pub mod pause_responder;
 pub mod persist;
 mod tap;
 pub mod test_utils;
@@ -32,15 +52,6 @@ use vm_memory::VolatileMemoryError;
 pub use self::device::Net;
 use super::iovec::IoVecError;
 
-/// Enum representing the Net device queue types
-#[derive(Debug)]
-pub enum NetQueue {
-    /// The RX queue
-    Rx,
-    /// The TX queue
-    Tx,
-}
-
 /// Errors the network device can trigger.
 #[derive(Debug, thiserror::Error, displaydoc::Display)]
 pub enum NetError {
@@ -48,6 +59,10 @@ pub enum NetError {
     TapOpen(TapError),
     /// Setting vnet header size failed: {0}
     TapSetVnetHdrSize(TapError),
+    /// Setting the tap MTU failed: {0}
+    TapSetMtu(TapError),
+    /// Invalid number of queue pairs {0}: it must be between 1 and 16
+    InvalidQueuePairs(u16),
     /// EventFd error: {0}
     EventFd(io::Error),
     /// IO error: {0}
@@ -0,0 +1,473 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Keeps the TCP connections of the guest alive while the microVM is paused.
+//!
+//! While the guest runs, the responder follows the TCP connections it has established over IPv4,
+//! recording the next sequence number the guest will send and the last sequence number it
+//! acknowledged. While the microVM is paused, the segments received from the peers of these
+//! connections are answered on behalf of the guest with an acknowledgement advertising a zero
+//! receive window, without acknowledging any new data. The peers then wait for the window to
+//! reopen, probing it periodically, instead of retransmitting and eventually timing out. The
+//! frames received while paused are dropped, and the peers retransmit their data once the guest
+//! reopens its window after the microVM is resumed.
+
+use std::collections::HashMap;
+use std::net::Ipv4Addr;
+use std::time::{Duration, Instant};
+
+use super::device::vnet_hdr_len;
+use crate::devices::virtio::iovec::IoVecBuffer;
+use crate::dumbo::pdu::ethernet::{EthernetFrame, ETHERTYPE_IPV4, PAYLOAD_OFFSET};
+use crate::dumbo::pdu::ipv4::{IPv4Packet, IPV4_VERSION, PROTOCOL_TCP};
+use crate::dumbo::pdu::tcp::{Flags as TcpFlags, TcpSegment};
+
+/// Maximum number of TCP connections a pause responder can track.
+pub const MAX_TRACKED_CONNECTIONS: usize = 65536;
+
+const IPV4_HEADER_MIN_LEN: usize = 20;
+const IPV4_HEADER_MAX_LEN: usize = 60;
+const TCP_HEADER_MIN_LEN: usize = 20;
+/// TTL of the acknowledgements sent on behalf of the guest.
+const REPLY_TTL: u8 = 64;
+
+/// Length of the frame prefix needed to follow a TCP segment: an Ethernet header, followed by an
+/// IPv4 header with options and a TCP header without options.
+const SEGMENT_HEADER_MAX_LEN: usize = PAYLOAD_OFFSET + IPV4_HEADER_MAX_LEN + TCP_HEADER_MIN_LEN;
+/// Length of the acknowledgements sent on behalf of the guest, including their VirtIO header.
+pub const REPLY_FRAME_LEN: usize =
+    vnet_hdr_len() + PAYLOAD_OFFSET + IPV4_HEADER_MIN_LEN + TCP_HEADER_MIN_LEN;
+
+/// Connections which have not seen any traffic for this long are evicted from a full table to
+/// make room for new ones.
+const CONNECTION_IDLE_TIMEOUT: Duration = Duration::from_secs(7200);
+/// Minimum interval between two scans of a full table for idle connections.
+const CONNECTION_EXPIRY_PERIOD: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct ConnectionKey {
+    guest_addr: Ipv4Addr,
+    guest_port: u16,
+    peer_addr: Ipv4Addr,
+    peer_port: u16,
+}
+
+#[derive(Debug)]
+struct Connection {
+    /// Next sequence number the guest will send.
+    snd_nxt: u32,
+    /// Next sequence number the guest expects from its peer, i.e. the last it acknowledged.
+    rcv_nxt: u32,
+    last_seen: Instant,
+}
+
+/// Fields of a TCP segment relevant to the responder.
+#[derive(Debug)]
+struct SegmentInfo {
+    key: ConnectionKey,
+    seq: u32,
+    ack: Option<u32>,
+    flags: TcpFlags,
+    payload_len: u32,
+}
+
+// Returns whether sequence number `a` comes after `b`, modulo 2^32.
+fn seq_after(a: u32, b: u32) -> bool {
+    let distance = a.wrapping_sub(b);
+    distance != 0 && distance < 1 << 31
+}
+
+/// Answers the TCP peers of the guest on its behalf while the microVM is paused.
+#[derive(Debug)]
+pub struct PauseResponder {
+    max_connections: usize,
+    connections: HashMap<ConnectionKey, Connection>,
+    last_expiry: Option<Instant>,
+    paused: bool,
+}
+
+impl PauseResponder {
+    /// Creates a responder tracking at most `max_connections` connections.
+    pub fn new(max_connections: usize) -> Self {
+        PauseResponder {
+            max_connections,
+            connections: HashMap::new(),
+            last_expiry: None,
+            paused: false,
+        }
+    }
+
+    /// Returns the maximum number of connections tracked by this responder.
+    pub fn max_connections(&self) -> usize {
+        self.max_connections
+    }
+
+    /// Returns the number of connections currently tracked.
+    pub fn tracked_connections(&self) -> usize {
+        self.connections.len()
+    }
+
+    /// Returns whether the microVM is paused, i.e. whether the responder answers the peers.
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Sets whether the microVM is paused.
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    /// Follows a frame sent by the guest, including its VirtIO header.
+    pub fn track_tx(&mut self, frame: &IoVecBuffer) {
+        let mut headers = [0u8; SEGMENT_HEADER_MAX_LEN];
+        let len = frame
+            .read_volatile_at(
+                &mut &mut headers[..],
+                vnet_hdr_len(),
+                SEGMENT_HEADER_MAX_LEN,
+            )
+            .unwrap_or(0);
+        self.track(&headers[..len], Instant::now());
+    }
+
+    fn track(&mut self, headers: &[u8], now: Instant) {
+        let Some(segment) = parse_segment(headers) else {
+            return;
+        };
+        // Connections are only followed once established, and dropped once reset.
+        let ack = match segment.ack {
+            Some(ack) if !segment.flags.contains(TcpFlags::RST) => ack,
+            _ => {
+                self.connections.remove(&segment.key);
+                return;
+            }
+        };
+        let mut snd_nxt = segment.seq.wrapping_add(segment.payload_len);
+        if segment.flags.intersects(TcpFlags::SYN | TcpFlags::FIN) {
+            snd_nxt = snd_nxt.wrapping_add(1);
+        }
+
+        if !self.connections.contains_key(&segment.key)
+            && self.connections.len() >= self.max_connections
+        {
+            self.expire_idle_connections(now);
+        }
+        if let Some(connection) = self.connections.get_mut(&segment.key) {
+            // Retransmitted segments must not move the connection back.
+            if seq_after(snd_nxt, connection.snd_nxt) {
+                connection.snd_nxt = snd_nxt;
+            }
+            if seq_after(ack, connection.rcv_nxt) {
+                connection.rcv_nxt = ack;
+            }
+            connection.last_seen = now;
+        } else if self.connections.len() < self.max_connections {
+            self.connections.insert(
+                segment.key,
+                Connection {
+                    snd_nxt,
+                    rcv_nxt: ack,
+                    last_seen: now,
+                },
+            );
+        }
+    }
+
+    // Evicts the connections idle for longer than `CONNECTION_IDLE_TIMEOUT`, at most once per
+    // `CONNECTION_EXPIRY_PERIOD`.
+    fn expire_idle_connections(&mut self, now: Instant) {
+        if self
+            .last_expiry
+            .is_some_and(|last| now.saturating_duration_since(last) < CONNECTION_EXPIRY_PERIOD)
+        {
+            return;
+        }
+        self.last_expiry = Some(now);
+        self.connections.retain(|_, connection| {
+            now.saturating_duration_since(connection.last_seen) < CONNECTION_IDLE_TIMEOUT
+        });
+    }
+
+    /// Handles a frame received for the paused guest, without its VirtIO header. If the frame
+    /// belongs to a tracked connection, writes the acknowledgement to send back in `reply`,
+    /// including its VirtIO header, and returns its length.
+    pub fn respond(&mut self, frame: &[u8], reply: &mut [u8; REPLY_FRAME_LEN]) -> Option<usize> {
+        let segment = parse_segment(frame)?;
+        // The segment comes from the peer, so its source is the remote end of the connection.
+        let key = ConnectionKey {
+            guest_addr: segment.key.peer_addr,
+            guest_port: segment.key.peer_port,
+            peer_addr: segment.key.guest_addr,
+            peer_port: segment.key.guest_port,
+        };
+        if segment.flags.contains(TcpFlags::RST) {
+            self.connections.remove(&key);
+            return None;
+        }
+        let connection = self.connections.get(&key)?;
+        let eth_frame = EthernetFrame::from_bytes(frame).ok()?;
+
+        reply.fill(0);
+        let mut eth_unsized = EthernetFrame::write_incomplete(
+            &mut reply[vnet_hdr_len()..],
+            eth_frame.src_mac(),
+            eth_frame.dst_mac(),
+            ETHERTYPE_IPV4,
+        )
+        .ok()?;
+        let packet_len = {
+            let mut packet = IPv4Packet::write_header(
+                eth_unsized.inner_mut().payload_mut(),
+                PROTOCOL_TCP,
+                key.guest_addr,
+                key.peer_addr,
+            )
+            .ok()?;
+            packet.inner_mut().set_ttl(REPLY_TTL);
+            let segment_len = TcpSegment::write_segment::<[u8]>(
+                packet.inner_mut().payload_mut(),
+                key.guest_port,
+                key.peer_port,
+                connection.snd_nxt,
+                connection.rcv_nxt,
+                TcpFlags::ACK,
+                0,
+                None,
+                0,
+                None,
+                Some((key.guest_addr, key.peer_addr)),
+            )
+            .ok()?
+            .len();
+            packet.with_payload_len_unchecked(segment_len, true).len()
+        };
+        Some(vnet_hdr_len() + eth_unsized.with_payload_len_unchecked(packet_len).len())
+    }
+}
+
+// Returns the relevant fields of the TCP segment carried by an Ethernet frame, if any. The
+// checksums are not verified.
+fn parse_segment(frame: &[u8]) -> Option<SegmentInfo> {
+    let frame = EthernetFrame::from_bytes(frame).ok()?;
+    if frame.ethertype() != ETHERTYPE_IPV4 {
+        return None;
+    }
+    let payload = frame.payload();
+    if payload.len() < IPV4_HEADER_MIN_LEN {
+        return None;
+    }
+    let packet = IPv4Packet::from_bytes_unchecked(payload);
+    let (version, header_len) = packet.version_and_header_len();
+    let header_len = usize::from(header_len);
+    let (_, fragment_offset) = packet.flags_and_fragment_offset();
+    if version != IPV4_VERSION
+        || header_len < IPV4_HEADER_MIN_LEN
+        || packet.protocol() != PROTOCOL_TCP
+        || fragment_offset != 0
+    {
+        return None;
+    }
+    let tcp_bytes = payload.get(header_len..)?;
+    if tcp_bytes.len() < TCP_HEADER_MIN_LEN {
+        return None;
+    }
+    let segment = TcpSegment::from_bytes_unchecked(tcp_bytes);
+    let headers_len = header_len + usize::from(segment.header_len());
+    let payload_len = usize::from(packet.total_len()).checked_sub(headers_len)?;
+    let flags = segment.flags_after_ns();
+
+    Some(SegmentInfo {
+        key: ConnectionKey {
+            guest_addr: packet.source_address(),
+            guest_port: segment.source_port(),
+            peer_addr: packet.destination_address(),
+            peer_port: segment.destination_port(),
+        },
+        seq: segment.sequence_number(),
+        ack: flags
+            .contains(TcpFlags::ACK)
+            .then_some(segment.ack_number()),
+        flags,
+        payload_len: u32::try_from(payload_len).ok()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::net::mac::MacAddr;
+
+    const GUEST_ADDR: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 2);
+    const PEER_ADDR: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+    const GUEST_PORT: u16 = 40000;
+    const PEER_PORT: u16 = 443;
+
+    // Writes an Ethernet frame carrying a TCP segment with `payload_len` bytes of payload.
+    fn write_frame(
+        buf: &mut [u8],
+        src: (Ipv4Addr, u16),
+        dst: (Ipv4Addr, u16),
+        seq: u32,
+        ack: u32,
+        flags: TcpFlags,
+        payload_len: usize,
+    ) -> usize {
+        let src_mac = MacAddr::from_bytes_unchecked(&[0x06, 0, 0, 0, 0, 1]);
+        let dst_mac = MacAddr::from_bytes_unchecked(&[0x06, 0, 0, 0, 0, 2]);
+        let payload = vec![0xaa; payload_len];
+        let mut eth_unsized =
+            EthernetFrame::write_incomplete(buf, dst_mac, src_mac, ETHERTYPE_IPV4).unwrap();
+        let packet_len = {
+            let mut packet = IPv4Packet::write_header(
+                eth_unsized.inner_mut().payload_mut(),
+                PROTOCOL_TCP,
+                src.0,
+                dst.0,
+            )
+            .unwrap();
+            let segment_len = TcpSegment::write_segment(
+                packet.inner_mut().payload_mut(),
+                src.1,
+                dst.1,
+                seq,
+                ack,
+                flags,
+                1000,
+                None,
+                1460,
+                (payload_len > 0).then_some((payload.as_slice(), payload_len)),
+                Some((src.0, dst.0)),
+            )
+            .unwrap()
+            .len();
+            packet.with_payload_len_unchecked(segment_len, true).len()
+        };
+        eth_unsized.with_payload_len_unchecked(packet_len).len()
+    }
+
+    fn guest_segment(
+        responder: &mut PauseResponder,
+        seq: u32,
+        ack: u32,
+        flags: TcpFlags,
+        len: usize,
+    ) {
+        let mut buf = [0u8; 2048];
+        let frame_len = write_frame(
+            &mut buf,
+            (GUEST_ADDR, GUEST_PORT),
+            (PEER_ADDR, PEER_PORT),
+            seq,
+            ack,
+            flags,
+            len,
+        );
+        responder.track(&buf[..frame_len], Instant::now());
+    }
+
+    fn peer_segment(
+        responder: &mut PauseResponder,
+        seq: u32,
+        ack: u32,
+        flags: TcpFlags,
+    ) -> Option<(u32, u32, u16)> {
+        let mut buf = [0u8; 2048];
+        let frame_len = write_frame(
+            &mut buf,
+            (PEER_ADDR, PEER_PORT),
+            (GUEST_ADDR, GUEST_PORT),
+            seq,
+            ack,
+            flags,
+            100,
+        );
+        let mut reply = [0u8; REPLY_FRAME_LEN];
+        let reply_len = responder.respond(&buf[..frame_len], &mut reply)?;
+        assert_eq!(reply_len, REPLY_FRAME_LEN);
+
+        let eth_frame = EthernetFrame::from_bytes(&reply[vnet_hdr_len()..]).unwrap();
+        let packet = IPv4Packet::from_bytes(eth_frame.payload(), true).unwrap();
+        assert_eq!(packet.source_address(), GUEST_ADDR);
+        assert_eq!(packet.destination_address(), PEER_ADDR);
+        assert_eq!(packet.ttl(), REPLY_TTL);
+        let segment =
+            TcpSegment::from_bytes(packet.payload(), Some((GUEST_ADDR, PEER_ADDR))).unwrap();
+        assert_eq!(segment.source_port(), GUEST_PORT);
+        assert_eq!(segment.destination_port(), PEER_PORT);
+        assert_eq!(segment.flags_after_ns(), TcpFlags::ACK);
+        assert_eq!(segment.payload_len(), 0);
+        Some((
+            segment.sequence_number(),
+            segment.ack_number(),
+            segment.window_size(),
+        ))
+    }
+
+    #[test]
+    fn test_pause_responder() {
+        let mut responder = PauseResponder::new(2);
+        responder.set_paused(true);
+        assert!(responder.is_paused());
+
+        // Connections are not tracked before being established.
+        guest_segment(&mut responder, 1000, 0, TcpFlags::SYN, 0);
+        assert_eq!(responder.tracked_connections(), 0);
+        assert!(peer_segment(&mut responder, 5000, 1001, TcpFlags::ACK).is_none());
+
+        guest_segment(&mut responder, 1001, 5000, TcpFlags::ACK, 200);
+        assert_eq!(responder.tracked_connections(), 1);
+        // The peer is acknowledged up to the data the guest acknowledged, with a zero window.
+        assert_eq!(
+            peer_segment(&mut responder, 5000, 1001, TcpFlags::ACK),
+            Some((1201, 5000, 0))
+        );
+
+        // Retransmissions do not move the connection back.
+        guest_segment(&mut responder, 1001, 4000, TcpFlags::ACK, 100);
+        guest_segment(&mut responder, 1201, 5100, TcpFlags::ACK | TcpFlags::FIN, 0);
+        assert_eq!(
+            peer_segment(&mut responder, 5100, 1202, TcpFlags::ACK),
+            Some((1202, 5100, 0))
+        );
+
+        // A reset from either end stops tracking the connection.
+        assert!(peer_segment(&mut responder, 5100, 1202, TcpFlags::RST).is_none());
+        assert_eq!(responder.tracked_connections(), 0);
+        guest_segment(&mut responder, 1001, 5000, TcpFlags::ACK, 0);
+        guest_segment(&mut responder, 1001, 5000, TcpFlags::RST | TcpFlags::ACK, 0);
+        assert_eq!(responder.tracked_connections(), 0);
+    }
+
+    #[test]
+    fn test_pause_responder_full() {
+        let mut responder = PauseResponder::new(1);
+        let mut buf = [0u8; 2048];
+        let now = Instant::now();
+        let mut len = 0;
+        for port in [1, 2] {
+            len = write_frame(
+                &mut buf,
+                (GUEST_ADDR, port),
+                (PEER_ADDR, PEER_PORT),
+                0,
+                0,
+                TcpFlags::ACK,
+                0,
+            );
+            responder.track(&buf[..len], now);
+        }
+        assert_eq!(responder.tracked_connections(), 1);
+        assert!(responder.connections.keys().all(|key| key.guest_port == 1));
+
+        // Idle connections are evicted to make room for new ones.
+        let later = now + CONNECTION_IDLE_TIMEOUT;
+        responder.track(&buf[..len], later);
+        assert_eq!(responder.tracked_connections(), 1);
+        assert!(responder.connections.keys().all(|key| key.guest_port == 2));
+
+        // Frames which are not TCP segments are ignored.
+        responder.track(&[0u8; 64], later);
+        let mut reply = [0u8; REPLY_FRAME_LEN];
+        assert!(responder.respond(&[0u8; 64], &mut reply).is_none());
+    }
+}
@@ -9,8 +9,8 @@ use std::sync::{Arc, Mutex};
 
 use serde::{Deserialize, Serialize};
 
-use super::device::{Net, RxBuffers};
-use super::{TapError, NET_NUM_QUEUES, NET_QUEUE_MAX_SIZE, RX_INDEX};
+use super::device::{MacFilter, Net, RxBuffers};
+use super::{rx_index, TapError, NET_NUM_QUEUES, NET_QUEUE_MAX_SIZE};
 use crate::devices::virtio::device::DeviceState;
 use crate::devices::virtio::persist::{PersistError as VirtioStateError, VirtioDeviceState};
 use crate::devices::virtio::TYPE_NET;
@@ -28,6 +28,8 @@ use crate::vstate::memory::GuestMemoryMmap;
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct NetConfigSpaceState {
     guest_mac: Option<MacAddr>,
+    /// MTU advertised to the guest, if any.
+    mtu: Option<u16>,
 }
 
 /// Information about the parsed RX buffers
@@ -63,7 +65,20 @@ pub struct NetState {
     pub mmds_ns: Option<MmdsNetworkStackState>,
     config_space: NetConfigSpaceState,
     virtio_state: VirtioDeviceState,
-    rx_buffers_state: RxBufferState,
+    /// The RX buffers of each queue pair.
+    rx_buffers_state: Vec<RxBufferState>,
+    /// Number of queue pairs used by the driver.
+    active_queue_pairs: u16,
+    /// Maximum number of flows accounted, if per-flow accounting is enabled. The counters
+    /// themselves are not saved.
+    max_flows: Option<usize>,
+    /// Maximum number of TCP connections tracked by the pause responder, if enabled. The
+    /// connections themselves are not saved.
+    max_paused_connections: Option<usize>,
+    /// Maximum number of TCP segments merged into a received frame, if RX coalescing is enabled.
+    max_coalesced_segments: Option<usize>,
+    /// Filtering of the frames sent by the guest with a spoofed source address.
+    mac_filter: MacFilter,
 }
 
 /// Auxiliary structure for creating a device when resuming from a snapshot.
@@ -88,6 +103,8 @@ pub enum NetPersistError {
     NoMmdsDataStore,
     /// Setting tap interface offload flags failed: {0}
     TapSetOffload(TapError),
+    /// Attaching or detaching the tap interface queues failed: {0}
+    TapSetQueue(TapError),
 }
 
 impl Persist<'_> for Net {
@@ -104,9 +121,25 @@ impl Persist<'_> for Net {
             mmds_ns: self.mmds_ns.as_ref().map(|mmds| mmds.save()),
             config_space: NetConfigSpaceState {
                 guest_mac: self.guest_mac,
+                mtu: self.mtu(),
             },
             virtio_state: VirtioDeviceState::from_device(self),
-            rx_buffers_state: RxBufferState::from_rx_buffers(&self.rx_buffer),
+            rx_buffers_state: self
+                .rx_buffers
+                .iter()
+                .map(RxBufferState::from_rx_buffers)
+                .collect(),
+            active_queue_pairs: self.active_queue_pairs,
+            max_flows: self.flow_table.as_ref().map(|table| table.max_flows()),
+            max_paused_connections: self
+                .pause_responder
+                .as_ref()
+                .map(|responder| responder.max_connections()),
+            max_coalesced_segments: self
+                .rx_coalescer
+                .as_ref()
+                .map(|coalescer| coalescer.max_segments()),
+            mac_filter: self.mac_filter,
         }
     }
 
@@ -117,13 +150,24 @@ impl Persist<'_> for Net {
         // RateLimiter::restore() can fail at creating a timerfd.
         let rx_rate_limiter = RateLimiter::restore((), &state.rx_rate_limiter_state)?;
         let tx_rate_limiter = RateLimiter::restore((), &state.tx_rate_limiter_state)?;
-        let mut net = Net::new(
-            state.id.clone(),
-            &state.tap_if_name,
-            state.config_space.guest_mac,
-            rx_rate_limiter,
-            tx_rate_limiter,
-        )?;
+        // Devices only serving the MMDS have no tap.
+        let mut net = if state.tap_if_name.is_empty() {
+            Net::new_mmds_only(
+                state.id.clone(),
+                state.config_space.guest_mac,
+                rx_rate_limiter,
+                tx_rate_limiter,
+            )?
+        } else {
+            Net::new_with_queues(
+                state.id.clone(),
+                &state.tap_if_name,
+                u16::try_from(state.rx_buffers_state.len()).unwrap(),
+                state.config_space.guest_mac,
+                rx_rate_limiter,
+                tx_rate_limiter,
+            )?
+        };
 
         // We trust the MMIODeviceManager::restore to pass us an MMDS data store reference if
         // there is at least one net device having the MMDS NS present and/or the mmds version was
@@ -142,10 +186,29 @@ impl Persist<'_> for Net {
             );
         }
 
+        if let Some(mtu) = state.config_space.mtu {
+            net.set_mtu(mtu)?;
+        }
+        if let Some(max_flows) = state.max_flows {
+            net.enable_flow_accounting(max_flows);
+        }
+        if let Some(max_connections) = state.max_paused_connections {
+            net.enable_pause_responder(max_connections);
+        }
+        if let Some(max_segments) = state.max_coalesced_segments {
+            net.enable_rx_coalescing(max_segments);
+        }
+        net.set_mac_filter(state.mac_filter);
+
+        // Devices with several queue pairs also have a control queue.
+        let num_queues = match state.rx_buffers_state.len() {
+            1 => NET_NUM_QUEUES,
+            pairs => NET_NUM_QUEUES * pairs + 1,
+        };
         net.queues = state.virtio_state.build_queues_checked(
             &constructor_args.mem,
             TYPE_NET,
-            NET_NUM_QUEUES,
+            num_queues,
             NET_QUEUE_MAX_SIZE,
         )?;
         net.irq_trigger.irq_status = Arc::new(AtomicU32::new(state.virtio_state.interrupt_status));
@@ -154,18 +217,24 @@ impl Persist<'_> for Net {
 
         if state.virtio_state.activated {
             let supported_flags: u32 = Net::build_tap_offload_features(net.acked_features);
-            net.tap
-                .set_offload(supported_flags)
-                .map_err(NetPersistError::TapSetOffload)?;
+            for tap in &net.taps {
+                tap.set_offload(supported_flags)
+                    .map_err(NetPersistError::TapSetOffload)?;
+            }
+            net.set_active_queue_pairs(state.active_queue_pairs)
+                .map_err(NetPersistError::TapSetQueue)?;
 
             net.device_state = DeviceState::Activated(constructor_args.mem);
 
-            // Recreate `Net::rx_buffer`. We do it by re-parsing the RX queue. We're temporarily
-            // rolling back `next_avail` in the RX queue and call `parse_rx_descriptors`.
-            net.queues[RX_INDEX].next_avail -= state.rx_buffers_state.parsed_descriptor_chains_nr;
-            net.parse_rx_descriptors();
-            net.rx_buffer.used_descriptors = state.rx_buffers_state.used_descriptors;
-            net.rx_buffer.used_bytes = state.rx_buffers_state.used_bytes;
+            // Recreate `Net::rx_buffers`. We do it by re-parsing the RX queues. We're temporarily
+            // rolling back `next_avail` in the RX queues and call `parse_rx_descriptors`.
+            for (pair, rx_buffers_state) in state.rx_buffers_state.iter().enumerate() {
+                net.queues[rx_index(pair)].next_avail -=
+                    rx_buffers_state.parsed_descriptor_chains_nr;
+                net.parse_rx_descriptors(pair);
+                net.rx_buffers[pair].used_descriptors = rx_buffers_state.used_descriptors;
+                net.rx_buffers[pair].used_bytes = rx_buffers_state.used_bytes;
+            }
         }
 
         Ok(net)
@@ -178,7 +247,9 @@ mod tests {
 
     use super::*;
     use crate::devices::virtio::device::VirtioDevice;
-    use crate::devices::virtio::net::test_utils::{default_net, default_net_no_mmds};
+    use crate::devices::virtio::net::test_utils::{
+        default_guest_mac, default_net, default_net_no_mmds,
+    };
     use crate::devices::virtio::test_utils::default_mem;
     use crate::snapshot::Snapshot;
 
@@ -191,6 +262,12 @@ mod tests {
         let has_mmds_ns;
         let allow_mmds_requests;
         let virtio_state;
+        let max_flows;
+        let max_paused_connections;
+        let max_coalesced_segments;
+        let mtu;
+        let num_queue_pairs;
+        let mac_filter;
 
         // Create and save the net device.
         {
@@ -202,6 +279,14 @@ mod tests {
             has_mmds_ns = net.mmds_ns.is_some();
             allow_mmds_requests = has_mmds_ns && mmds_ds.is_some();
             virtio_state = VirtioDeviceState::from_device(&net);
+            max_flows = net.flow_table().map(|table| table.max_flows());
+            max_paused_connections = net
+                .pause_responder()
+                .map(|responder| responder.max_connections());
+            max_coalesced_segments = net.rx_coalescer().map(|coalescer| coalescer.max_segments());
+            mtu = net.mtu();
+            num_queue_pairs = net.num_queue_pairs();
+            mac_filter = net.mac_filter();
         }
 
         // Drop the initial net device so that we don't get an error when trying to recreate the
@@ -230,9 +315,29 @@ mod tests {
                     // Test that net specific fields are the same.
                     assert_eq!(&restored_net.id, &id);
                     assert_eq!(&restored_net.iface_name(), &tap_if_name);
+                    assert_eq!(restored_net.is_mmds_only(), tap_if_name.is_empty());
                     assert_eq!(restored_net.mmds_ns.is_some(), allow_mmds_requests);
                     assert_eq!(restored_net.rx_rate_limiter, RateLimiter::default());
                     assert_eq!(restored_net.tx_rate_limiter, RateLimiter::default());
+                    assert_eq!(
+                        restored_net.flow_table().map(|table| table.max_flows()),
+                        max_flows
+                    );
+                    assert_eq!(
+                        restored_net
+                            .pause_responder()
+                            .map(|responder| responder.max_connections()),
+                        max_paused_connections
+                    );
+                    assert_eq!(
+                        restored_net
+                            .rx_coalescer()
+                            .map(|coalescer| coalescer.max_segments()),
+                        max_coalesced_segments
+                    );
+                    assert_eq!(restored_net.mtu(), mtu);
+                    assert_eq!(restored_net.num_queue_pairs(), num_queue_pairs);
+                    assert_eq!(restored_net.mac_filter(), mac_filter);
                 }
                 Err(NetPersistError::NoMmdsDataStore) => {
                     assert!(has_mmds_ns && !allow_mmds_requests)
@@ -253,6 +358,39 @@ mod tests {
         // The restore should be conservative and not configure the mmds ns.
         validate_save_and_restore(default_net_no_mmds(), mmds);
 
+        let mut net = default_net_no_mmds();
+        net.enable_flow_accounting(64);
+        net.enable_pause_responder(64);
+        net.enable_rx_coalescing(16);
+        net.set_mtu(9000).unwrap();
+        net.set_mac_filter(MacFilter {
+            enforce_mac: true,
+            enforce_arp: true,
+        });
+        validate_save_and_restore(net, None);
+
+        // Devices with several queue pairs are restored with a queue of the tap for each.
+        let net = Net::new_with_queues(
+            "mq-net".to_string(),
+            "net-device%d",
+            4,
+            Some(default_guest_mac()),
+            RateLimiter::default(),
+            RateLimiter::default(),
+        )
+        .unwrap();
+        validate_save_and_restore(net, None);
+
+        // Devices only serving the MMDS are restored without a tap.
+        let net = Net::new_mmds_only(
+            "mmds-only".to_string(),
+            Some(default_guest_mac()),
+            RateLimiter::default(),
+            RateLimiter::default(),
+        )
+        .unwrap();
+        validate_save_and_restore(net, None);
+
         // Check what happens if the MMIODeviceManager does not give us the reference to the MMDS
         // data store. This will return an error.
         validate_save_and_restore(default_net(), None);
@@ -7,7 +7,7 @@
 
 use std::fmt::{self, Debug};
 use std::fs::File;
-use std::io::Error as IoError;
+use std::io::{Error as IoError, Read, Write};
 use std::os::raw::*;
 use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
 
@@ -35,12 +35,24 @@ pub enum TapError {
     SetOffloadFlags(IoError),
     /// Error while setting size of the vnet header: {0}
     SetSizeOfVnetHdr(IoError),
+    /// Error while setting the MTU: {0}
+    SetMtu(IoError),
+    /// MTU {0} exceeds the MTU {2} of {1}, the device the tap is attached to
+    HostPathMtu(u16, String, u16),
+    /// Error while attaching or detaching a queue of the tap: {0}
+    SetQueue(IoError),
 }
 
 const TUNTAP: ::std::os::raw::c_uint = 84;
 ioctl_iow_nr!(TUNSETIFF, TUNTAP, 202, ::std::os::raw::c_int);
 ioctl_iow_nr!(TUNSETOFFLOAD, TUNTAP, 208, ::std::os::raw::c_uint);
 ioctl_iow_nr!(TUNSETVNETHDRSZ, TUNTAP, 216, ::std::os::raw::c_int);
+ioctl_iow_nr!(TUNSETQUEUE, TUNTAP, 217, ::std::os::raw::c_int);
+
+// As defined in the Linux UAPI:
+// https://elixir.bootlin.com/linux/v6.1/source/include/uapi/linux/if_tun.h#L68
+const IFF_ATTACH_QUEUE: u32 = 0x0200;
+const IFF_DETACH_QUEUE: u32 = 0x0400;
 
 /// Handle for a network tap interface.
 ///
@@ -98,6 +110,11 @@ impl IfReqBuilder {
         self
     }
 
+    pub(crate) fn mtu(mut self, mtu: u16) -> Self {
+        self.0.ifr_ifru.ifru_mtu = c_int::from(mtu);
+        self
+    }
+
     pub(crate) fn execute<F: AsRawFd + Debug>(
         mut self,
         socket: &F,
@@ -118,6 +135,26 @@ impl Tap {
     ///
     /// * `if_name` - the name of the interface.
     pub fn open_named(if_name: &str) -> Result<Tap, TapError> {
+        Self::open_named_with_flags(if_name, gen::IFF_TAP | gen::IFF_NO_PI | gen::IFF_VNET_HDR)
+    }
+
+    /// Opens `num_queues` queues of a multi-queue TUN/TAP device given the interface name, each
+    /// with its own file descriptor. The kernel spreads the received frames over the queues by
+    /// flow.
+    pub fn open_named_queues(if_name: &str, num_queues: u16) -> Result<Vec<Tap>, TapError> {
+        let flags = gen::IFF_TAP | gen::IFF_NO_PI | gen::IFF_VNET_HDR | gen::IFF_MULTI_QUEUE;
+        let first = Self::open_named_with_flags(if_name, flags)?;
+        // The name may have been assigned by the kernel, e.g. from "tap%d", so the other queues
+        // are opened with the actual name.
+        let if_name = first.if_name_as_str().to_string();
+        let mut taps = vec![first];
+        for _ in 1..num_queues {
+            taps.push(Self::open_named_with_flags(&if_name, flags)?);
+        }
+        Ok(taps)
+    }
+
+    fn open_named_with_flags(if_name: &str, flags: u32) -> Result<Tap, TapError> {
         // SAFETY: Open calls are safe because we give a constant null-terminated
         // string and verify the result.
         let fd = unsafe {
@@ -136,7 +173,7 @@ impl Tap {
         let terminated_if_name = build_terminated_if_name(if_name)?;
         let ifreq = IfReqBuilder::new()
             .if_name(&terminated_if_name)
-            .flags(i16::try_from(gen::IFF_TAP | gen::IFF_NO_PI | gen::IFF_VNET_HDR).unwrap())
+            .flags(i16::try_from(flags).unwrap())
             .execute(&tuntap, TUNSETIFF())
             .map_err(|io_error| TapError::IfreqExecuteError(io_error, if_name.to_owned()))?;
 
@@ -167,6 +204,21 @@ impl Tap {
         Ok(())
     }
 
+    /// Attaches the queue of a multi-queue tap to the interface, or detaches it so that the
+    /// kernel no longer queues frames on it.
+    pub fn set_queue_enabled(&self, enabled: bool) -> Result<(), TapError> {
+        let flag = if enabled {
+            IFF_ATTACH_QUEUE
+        } else {
+            IFF_DETACH_QUEUE
+        };
+        IfReqBuilder::new()
+            .flags(i16::try_from(flag).unwrap())
+            .execute(&self.tap_file, TUNSETQUEUE())
+            .map_err(TapError::SetQueue)?;
+        Ok(())
+    }
+
     /// Set the size of the vnet hdr.
     pub fn set_vnet_hdr_size(&self, size: c_int) -> Result<(), TapError> {
         // SAFETY: ioctl is safe. Called with a valid tap fd, and we check the return.
@@ -177,6 +229,44 @@ impl Tap {
         Ok(())
     }
 
+    /// Set the MTU of the tap interface.
+    ///
+    /// If the tap is attached to another device, e.g. a bridge, the MTU can not exceed the MTU of
+    /// that device, otherwise the larger frames would be dropped on the host path.
+    pub fn set_mtu(&self, mtu: u16) -> Result<(), TapError> {
+        let if_name = self.if_name_as_str();
+        // The master device is only visible when sysfs is available, e.g. not in a jail without
+        // /sys, in which case the check is skipped.
+        let master_mtu = std::fs::read_to_string(format!("/sys/class/net/{if_name}/master/mtu"));
+        if let Ok(master_mtu) = master_mtu {
+            let master_mtu = master_mtu.trim().parse::<u16>().unwrap_or(u16::MAX);
+            if mtu > master_mtu {
+                let master = std::fs::read_link(format!("/sys/class/net/{if_name}/master"))
+                    .ok()
+                    .and_then(|path| Some(path.file_name()?.to_string_lossy().into_owned()))
+                    .unwrap_or_default();
+                return Err(TapError::HostPathMtu(mtu, master, master_mtu));
+            }
+        }
+
+        // The MTU is set through a socket, as the tap fd does not handle `SIOCSIFMTU`.
+        // SAFETY: Socket calls are safe, and we check the result.
+        let fd = unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0) };
+        if fd < 0 {
+            return Err(TapError::SetMtu(IoError::last_os_error()));
+        }
+        // SAFETY: We just checked that the fd is valid.
+        let socket = unsafe { File::from_raw_fd(fd) };
+
+        IfReqBuilder::new()
+            .if_name(&self.if_name)
+            .mtu(mtu)
+            .execute(&socket, c_ulong::from(gen::sockios::SIOCSIFMTU))
+            .map_err(TapError::SetMtu)?;
+
+        Ok(())
+    }
+
     /// Write an `IoVecBuffer` to tap
     pub(crate) fn write_iovec(&mut self, buffer: &IoVecBuffer) -> Result<usize, IoError> {
         let iovcnt = i32::try_from(buffer.iovec_count()).unwrap();
@@ -191,6 +281,16 @@ impl Tap {
         Ok(usize::try_from(ret).unwrap())
     }
 
+    /// Write a single buffer to tap
+    pub(crate) fn write_buf(&mut self, buf: &[u8]) -> Result<usize, IoError> {
+        self.tap_file.write(buf)
+    }
+
+    /// Read from tap to a single buffer
+    pub(crate) fn read_buf(&mut self, buf: &mut [u8]) -> Result<usize, IoError> {
+        self.tap_file.read(buf)
+    }
+
     /// Read from tap to an `IoVecBufferMut`
     pub(crate) fn read_iovec(&mut self, buffer: &mut [libc::iovec]) -> Result<usize, IoError> {
         let iov = buffer.as_mut_ptr();
@@ -271,6 +371,22 @@ pub mod tests {
         Tap::open_named("exclusivetap").unwrap_err();
     }
 
+    #[test]
+    fn test_tap_queues() {
+        let taps = Tap::open_named_queues("mqtap%d", 3).unwrap();
+        assert_eq!(taps.len(), 3);
+        assert!(taps
+            .iter()
+            .all(|tap| tap.if_name_as_str() == taps[0].if_name_as_str()));
+        assert_ne!(taps[0].as_raw_fd(), taps[1].as_raw_fd());
+
+        taps[2].set_queue_enabled(false).unwrap();
+        taps[2].set_queue_enabled(true).unwrap();
+        // The queues of a single-queue tap cannot be detached.
+        let tap = Tap::open_named("").unwrap();
+        tap.set_queue_enabled(false).unwrap_err();
+    }
+
     #[test]
     fn test_set_options() {
         // This line will fail to provide an initialized FD if the test is not run as root.
@@ -279,6 +395,19 @@ pub mod tests {
         tap.set_offload(0).unwrap();
     }
 
+    #[test]
+    fn test_set_mtu() {
+        let tap = Tap::open_named("").unwrap();
+        tap.set_mtu(9000).unwrap();
+
+        let sock = crate::devices::virtio::net::test_utils::create_socket();
+        let ifreq = IfReqBuilder::new()
+            .if_name(&tap.if_name)
+            .execute(&sock, c_ulong::from(gen::sockios::SIOCGIFMTU))
+            .unwrap();
+        assert_eq!(unsafe { ifreq.ifr_ifru.ifru_mtu }, 9000);
+    }
+
     #[test]
     fn test_raw_fd() {
         let tap = Tap::open_named("").unwrap();
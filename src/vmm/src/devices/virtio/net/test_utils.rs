@@ -51,7 +51,7 @@ pub fn default_net() -> Net {
         MmdsNetworkStack::default_ipv4_addr(),
         Arc::new(Mutex::new(Mmds::default())),
     );
-    enable(&net.tap);
+    enable(&net.taps[0]);
 
     net
 }
@@ -70,7 +70,7 @@ pub fn default_net_no_mmds() -> Net {
         RateLimiter::default(),
     )
     .unwrap();
-    enable(&net.tap);
+    enable(&net.taps[0]);
 
     net
 }
@@ -249,7 +249,7 @@ pub(crate) fn inject_tap_tx_frame(net: &Net, len: usize) -> Vec<u8> {
     use std::os::unix::ffi::OsStrExt;
 
     assert!(len >= vnet_hdr_len());
-    let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&net.tap));
+    let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&net.taps[0]));
     let mut frame = vmm_sys_util::rand::rand_alphanumerics(len - vnet_hdr_len())
         .as_bytes()
         .to_vec();
@@ -380,10 +380,10 @@ pub mod test {
 
         pub fn simulate_event(&mut self, event: NetEvent) {
             match event {
-                NetEvent::RxQueue => self.net().process_rx_queue_event(),
+                NetEvent::RxQueue => self.net().process_rx_queue_event(0),
                 NetEvent::RxRateLimiter => self.net().process_rx_rate_limiter_event(),
-                NetEvent::Tap => self.net().process_tap_rx_event(),
-                NetEvent::TxQueue => self.net().process_tx_queue_event(),
+                NetEvent::Tap => self.net().process_tap_rx_event(0),
+                NetEvent::TxQueue => self.net().process_tx_queue_event(0),
                 NetEvent::TxRateLimiter => self.net().process_tx_rate_limiter_event(),
             };
         }
@@ -434,7 +434,7 @@ pub mod test {
         /// Generate a tap frame of `frame_len` and check that it is not read and
         /// the descriptor chain has been discarded
         pub fn check_rx_discarded_buffer(&mut self, frame_len: usize) -> Vec<u8> {
-            let old_used_descriptors = self.net().rx_buffer.used_descriptors;
+            let old_used_descriptors = self.net().rx_buffers[0].used_descriptors;
 
             // Inject frame to tap and run epoll.
             let frame = inject_tap_tx_frame(&self.net(), frame_len);
@@ -445,7 +445,7 @@ pub mod test {
             );
             // Check that the descriptor chain has been discarded.
             assert_eq!(
-                self.net().rx_buffer.used_descriptors,
+                self.net().rx_buffers[0].used_descriptors,
                 old_used_descriptors + 1
             );
 
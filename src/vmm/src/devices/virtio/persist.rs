@@ -205,6 +205,8 @@ pub struct MmioTransportState {
     // The register where features page is selected.
     acked_features_select: u32,
     queue_select: u32,
+    // The register where the shared memory region is selected.
+    shm_select: u32,
     device_status: u32,
     config_generation: u32,
 }
@@ -230,6 +232,7 @@ impl Persist<'_> for MmioTransport {
             features_select: self.features_select,
             acked_features_select: self.acked_features_select,
             queue_select: self.queue_select,
+            shm_select: self.shm_select,
             device_status: self.device_status,
             config_generation: self.config_generation,
         }
@@ -247,6 +250,7 @@ impl Persist<'_> for MmioTransport {
         transport.features_select = state.features_select;
         transport.acked_features_select = state.acked_features_select;
         transport.queue_select = state.queue_select;
+        transport.shm_select = state.shm_select;
         transport.device_status = state.device_status;
         transport.config_generation = state.config_generation;
         Ok(transport)
@@ -383,6 +387,7 @@ mod tests {
             self.acked_features_select == other.acked_features_select &&
                 self.features_select == other.features_select &&
                 self.queue_select == other.queue_select &&
+                self.shm_select == other.shm_select &&
                 self.device_status == other.device_status &&
                 self.config_generation == other.config_generation &&
                 self.interrupt_status.load(Ordering::SeqCst) == other.interrupt_status.load(Ordering::SeqCst) &&
@@ -1,11 +1,14 @@
 // Copyright 2022 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
-use std::io;
+use std::fs::File;
+use std::io::{self, Read};
+use std::os::fd::RawFd;
 use std::sync::atomic::AtomicU32;
 use std::sync::Arc;
 
 use aws_lc_rs::rand;
+use serde::{Deserialize, Serialize};
 use vm_memory::GuestMemoryError;
 use vmm_sys_util::eventfd::EventFd;
 
@@ -20,9 +23,12 @@ use crate::devices::virtio::{ActivateError, TYPE_RNG};
 use crate::devices::DeviceError;
 use crate::logger::{debug, error, IncMetric};
 use crate::rate_limiter::{RateLimiter, TokenType};
+use crate::utils::file_from_fd;
 use crate::vstate::memory::GuestMemoryMmap;
 
 pub const ENTROPY_DEV_ID: &str = "rng";
+/// Path of the host entropy source used by the `urandom` source type.
+pub const URANDOM_PATH: &str = "/dev/urandom";
 
 #[derive(Debug, thiserror::Error, displaydoc::Display)]
 pub enum EntropyError {
@@ -34,6 +40,72 @@ pub enum EntropyError {
     Random(#[from] aws_lc_rs::error::Unspecified),
     /// Underlying IovDeque error: {0}
     IovDeque(#[from] IovDequeError),
+    /// Could not open the entropy source: {0}
+    OpenSource(io::Error),
+    /// Could not read from the entropy source: {0}
+    ReadSource(io::Error),
+}
+
+/// Host source from which the entropy device gets the random bytes it provides to the guest.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntropySourceType {
+    /// The DRBG of the cryptographic library, seeded by the host kernel.
+    #[default]
+    Drbg,
+    /// The `/dev/urandom` device of the host.
+    Urandom,
+    /// The `getrandom` system call.
+    Getrandom,
+    /// A file descriptor inherited by the Firecracker process, e.g. a pipe fed by a hardware RNG.
+    Fd(RawFd),
+}
+
+/// Opened entropy source.
+#[derive(Debug)]
+enum EntropySource {
+    Drbg,
+    Getrandom,
+    File(File),
+}
+
+impl EntropySource {
+    fn open(source_type: EntropySourceType) -> Result<Self, EntropyError> {
+        match source_type {
+            EntropySourceType::Drbg => Ok(EntropySource::Drbg),
+            EntropySourceType::Getrandom => Ok(EntropySource::Getrandom),
+            EntropySourceType::Urandom => File::open(URANDOM_PATH)
+                .map(EntropySource::File)
+                .map_err(EntropyError::OpenSource),
+            EntropySourceType::Fd(fd) => file_from_fd(fd)
+                .map(EntropySource::File)
+                .map_err(EntropyError::OpenSource),
+        }
+    }
+
+    /// Fills the start of `buf` with random bytes, and returns their number. Sources other than
+    /// the DRBG may return fewer bytes than requested.
+    fn fill(&mut self, buf: &mut [u8]) -> Result<usize, EntropyError> {
+        match self {
+            EntropySource::Drbg => {
+                rand::fill(buf)?;
+                Ok(buf.len())
+            }
+            EntropySource::Getrandom => {
+                // SAFETY: `buf` is valid for writes of `buf.len()` bytes.
+                let ret = unsafe { libc::getrandom(buf.as_mut_ptr().cast(), buf.len(), 0) };
+                usize::try_from(ret)
+                    .map_err(|_| EntropyError::ReadSource(io::Error::last_os_error()))
+            }
+            EntropySource::File(file) => match file.read(buf) {
+                Ok(len) => Ok(len),
+                // Sources in non-blocking mode which have no bytes ready complete the request
+                // with an empty buffer.
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => Ok(0),
+                Err(err) => Err(EntropyError::ReadSource(err)),
+            },
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -51,20 +123,27 @@ pub struct Entropy {
 
     // Device specific fields
     rate_limiter: RateLimiter,
+    source_type: EntropySourceType,
+    source: EntropySource,
 
     buffer: IoVecBufferMut,
 }
 
 impl Entropy {
-    pub fn new(rate_limiter: RateLimiter) -> Result<Self, EntropyError> {
+    pub fn new(
+        rate_limiter: RateLimiter,
+        source_type: EntropySourceType,
+    ) -> Result<Self, EntropyError> {
         let queues = vec![Queue::new(FIRECRACKER_MAX_QUEUE_SIZE); RNG_NUM_QUEUES];
-        Self::new_with_queues(queues, rate_limiter)
+        Self::new_with_queues(queues, rate_limiter, source_type)
     }
 
     pub fn new_with_queues(
         queues: Vec<Queue>,
         rate_limiter: RateLimiter,
+        source_type: EntropySourceType,
     ) -> Result<Self, EntropyError> {
+        let source = EntropySource::open(source_type)?;
         let activate_event = EventFd::new(libc::EFD_NONBLOCK)?;
         let queue_events = (0..RNG_NUM_QUEUES)
             .map(|_| EventFd::new(libc::EFD_NONBLOCK))
@@ -80,6 +159,8 @@ impl Entropy {
             queue_events,
             irq_trigger,
             rate_limiter,
+            source_type,
+            source,
             buffer: IoVecBufferMut::new()?,
         })
     }
@@ -119,13 +200,16 @@ impl Entropy {
         }
 
         let mut rand_bytes = vec![0; self.buffer.len() as usize];
-        rand::fill(&mut rand_bytes).inspect_err(|_| {
+        let len = self.source.fill(&mut rand_bytes).inspect_err(|_| {
             METRICS.host_rng_fails.inc();
         })?;
 
-        // It is ok to unwrap here. We are writing `iovec.len()` bytes at offset 0.
-        self.buffer.write_all_volatile_at(&rand_bytes, 0).unwrap();
-        Ok(self.buffer.len())
+        // It is ok to unwrap here. We are writing at most `iovec.len()` bytes at offset 0.
+        self.buffer
+            .write_all_volatile_at(&rand_bytes[..len], 0)
+            .unwrap();
+        // `len` is at most `self.buffer.len()`, which is a u32.
+        Ok(u32::try_from(len).unwrap())
     }
 
     fn process_entropy_queue(&mut self) {
@@ -156,11 +240,16 @@ impl Entropy {
                         break;
                     }
 
-                    self.handle_one().unwrap_or_else(|err| {
+                    let requested = self.buffer.len();
+                    let bytes = self.handle_one().unwrap_or_else(|err| {
                         error!("entropy: {err}");
                         METRICS.entropy_event_fails.inc();
                         0
-                    })
+                    });
+                    // Only charge the rate limiter for the bytes actually provided.
+                    self.rate_limiter
+                        .manual_replenish(u64::from(requested - bytes), TokenType::Bytes);
+                    bytes
                 }
                 Err(err) => {
                     error!("entropy: Could not parse descriptor chain: {err}");
@@ -228,6 +317,11 @@ impl Entropy {
         &self.rate_limiter
     }
 
+    /// Returns the type of the source of the random bytes.
+    pub fn source_type(&self) -> EntropySourceType {
+        self.source_type
+    }
+
     pub(crate) fn set_avail_features(&mut self, features: u64) {
         self.avail_features = features;
     }
@@ -307,6 +401,9 @@ impl VirtioDevice for Entropy {
 
 #[cfg(test)]
 mod tests {
+    use std::io::Write;
+    use std::os::fd::AsRawFd;
+    use std::os::unix::net::UnixStream;
     use std::time::Duration;
 
     use super::*;
@@ -328,7 +425,7 @@ mod tests {
     }
 
     fn default_entropy() -> Entropy {
-        Entropy::new(RateLimiter::default()).unwrap()
+        Entropy::new(RateLimiter::default(), EntropySourceType::default()).unwrap()
     }
 
     #[test]
@@ -340,6 +437,50 @@ mod tests {
         assert!(!entropy_dev.is_activated());
     }
 
+    #[test]
+    fn test_entropy_sources() {
+        for source_type in [
+            EntropySourceType::Drbg,
+            EntropySourceType::Urandom,
+            EntropySourceType::Getrandom,
+        ] {
+            let mut source = EntropySource::open(source_type).unwrap();
+            let mut buf = [0u8; 64];
+            assert_eq!(source.fill(&mut buf).unwrap(), 64);
+            assert_ne!(buf, [0u8; 64]);
+        }
+
+        let err = Entropy::new(RateLimiter::default(), EntropySourceType::Fd(-1)).unwrap_err();
+        assert!(matches!(err, EntropyError::OpenSource(_)), "{err}");
+    }
+
+    #[test]
+    fn test_fd_source() {
+        let (mut writer, reader) = UnixStream::pair().unwrap();
+        writer.write_all(&[0xAA; 16]).unwrap();
+        let device = Entropy::new(
+            RateLimiter::default(),
+            EntropySourceType::Fd(reader.as_raw_fd()),
+        )
+        .unwrap();
+        assert_eq!(
+            device.source_type(),
+            EntropySourceType::Fd(reader.as_raw_fd())
+        );
+
+        let mem = create_virtio_mem();
+        let mut th = VirtioTestHelper::<Entropy>::new(&mem, device);
+        th.activate_device(&mem);
+
+        // The guest only gets the bytes available from the source.
+        th.add_desc_chain(RNG_QUEUE, 0, &[(0, 64, VIRTQ_DESC_F_WRITE)]);
+        check_metric_after_block!(
+            METRICS.entropy_bytes,
+            16,
+            th.device().process_entropy_queue()
+        );
+    }
+
     #[test]
     fn test_id() {
         let entropy_dev = default_entropy();
@@ -526,7 +667,11 @@ mod tests {
     fn test_bandwidth_rate_limiter() {
         let mem = create_virtio_mem();
         // Rate Limiter with 4000 bytes / sec allowance and no initial burst allowance
-        let device = Entropy::new(RateLimiter::new(4000, 0, 1000, 0, 0, 0).unwrap()).unwrap();
+        let device = Entropy::new(
+            RateLimiter::new(4000, 0, 1000, 0, 0, 0).unwrap(),
+            EntropySourceType::default(),
+        )
+        .unwrap();
         let mut th = VirtioTestHelper::<Entropy>::new(&mem, device);
 
         th.activate_device(&mem);
@@ -574,7 +719,11 @@ mod tests {
         let mem = create_virtio_mem();
         // Rate Limiter with unlimited bandwidth and allowance for 1 operation every 100 msec,
         // (10 ops/sec), without initial burst.
-        let device = Entropy::new(RateLimiter::new(0, 0, 0, 1, 0, 100).unwrap()).unwrap();
+        let device = Entropy::new(
+            RateLimiter::new(0, 0, 0, 1, 0, 100).unwrap(),
+            EntropySourceType::default(),
+        )
+        .unwrap();
         let mut th = VirtioTestHelper::<Entropy>::new(&mem, device);
 
         th.activate_device(&mem);
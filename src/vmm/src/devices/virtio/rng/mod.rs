@@ -6,7 +6,7 @@ mod event_handler;
 pub mod metrics;
 pub mod persist;
 
-pub use self::device::{Entropy, EntropyError};
+pub use self::device::{Entropy, EntropyError, EntropySourceType};
 
 pub(crate) const RNG_NUM_QUEUES: usize = 1;
 
@@ -7,7 +7,7 @@ use serde::{Deserialize, Serialize};
 
 use crate::devices::virtio::persist::{PersistError as VirtioStateError, VirtioDeviceState};
 use crate::devices::virtio::queue::FIRECRACKER_MAX_QUEUE_SIZE;
-use crate::devices::virtio::rng::{Entropy, EntropyError, RNG_NUM_QUEUES};
+use crate::devices::virtio::rng::{Entropy, EntropyError, EntropySourceType, RNG_NUM_QUEUES};
 use crate::devices::virtio::TYPE_RNG;
 use crate::rate_limiter::persist::RateLimiterState;
 use crate::rate_limiter::RateLimiter;
@@ -18,6 +18,7 @@ use crate::vstate::memory::GuestMemoryMmap;
 pub struct EntropyState {
     virtio_state: VirtioDeviceState,
     rate_limiter_state: RateLimiterState,
+    source: EntropySourceType,
 }
 
 #[derive(Debug)]
@@ -48,6 +49,7 @@ impl Persist<'_> for Entropy {
         EntropyState {
             virtio_state: VirtioDeviceState::from_device(self),
             rate_limiter_state: self.rate_limiter().save(),
+            source: self.source_type(),
         }
     }
 
@@ -63,7 +65,7 @@ impl Persist<'_> for Entropy {
         )?;
 
         let rate_limiter = RateLimiter::restore((), &state.rate_limiter_state)?;
-        let mut entropy = Entropy::new_with_queues(queues, rate_limiter)?;
+        let mut entropy = Entropy::new_with_queues(queues, rate_limiter, state.source)?;
         entropy.set_avail_features(state.virtio_state.avail_features);
         entropy.set_acked_features(state.virtio_state.acked_features);
         entropy.set_irq_status(state.virtio_state.interrupt_status);
@@ -88,7 +90,7 @@ mod tests {
     #[test]
     fn test_persistence() {
         let mut mem = vec![0u8; 4096];
-        let entropy = Entropy::new(RateLimiter::default()).unwrap();
+        let entropy = Entropy::new(RateLimiter::default(), EntropySourceType::Urandom).unwrap();
 
         Snapshot::serialize(&mut mem.as_mut_slice(), &entropy.save()).unwrap();
 
@@ -101,6 +103,7 @@ mod tests {
 
         assert_eq!(restored.device_type(), TYPE_RNG);
         assert_eq!(restored.id(), ENTROPY_DEV_ID);
+        assert_eq!(restored.source_type(), EntropySourceType::Urandom);
         assert_eq!(restored.is_activated(), entropy.is_activated());
         assert_eq!(restored.avail_features(), entropy.avail_features());
         assert_eq!(restored.acked_features(), entropy.acked_features());
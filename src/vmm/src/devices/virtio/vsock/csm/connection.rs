@@ -23,6 +23,16 @@ use std::fmt::Debug;
 ///         consume it.  If that data can't be forwarded straight to the host stream, we'll
 ///         have to store it in a buffer (and flush it at a later time). Vsock flow control
 ///         ensures that our TX buffer doesn't overflow.
+///
+/// Seqpacket connections work the same, except that their messages keep their boundaries: the
+/// messages of the guest are reassembled from their packets, then written whole to the host
+/// socket, and the messages of the host are read whole, then sent in as many packets as
+/// needed, the last of which is flagged with VSOCK_FLAGS_SEQ_EOM.
+///
+/// The data of a connection can be throttled, in either direction, by the rate limiters of its
+/// port: while RX is throttled, no data is read from the host stream, and while TX is
+/// throttled, the data of the guest is kept in the TX buffer, which eventually runs the guest
+/// out of credit.
 // The code in this file is best read with a fresh memory of the vsock protocol inner-workings.
 // To help with that, here is a
 //
@@ -84,11 +94,12 @@ use std::time::{Duration, Instant};
 
 use log::{debug, error, info, warn};
 use vm_memory::io::{ReadVolatile, WriteVolatile};
-use vm_memory::GuestMemoryError;
+use vm_memory::{GuestMemoryError, VolatileSlice};
 use vmm_sys_util::epoll::EventSet;
 
 use super::super::defs::uapi;
 use super::super::{VsockChannel, VsockEpollListener, VsockError};
+use super::msgbuf::MsgBuf;
 use super::txbuf::TxBuf;
 use super::{defs, ConnState, PendingRx, PendingRxSet, VsockCsmError};
 use crate::devices::virtio::vsock::metrics::METRICS;
@@ -117,10 +128,21 @@ pub struct VsockConnection<S: VsockConnectionBackend> {
     local_port: u32,
     /// The peer (guest) port.
     peer_port: u32,
+    /// The socket type of the connection, `VSOCK_TYPE_STREAM` or `VSOCK_TYPE_SEQPACKET`.
+    type_: u16,
+    /// The port to which the connection was requested: the local port of guest-initiated
+    /// connections, and the peer port of host-initiated ones.
+    service_port: u32,
     /// The (connected) host-side stream.
     stream: S,
     /// The TX buffer for this connection.
     tx_buf: TxBuf,
+    /// The TX buffer of the messages of a seqpacket connection.
+    tx_msg_buf: MsgBuf,
+    /// The message of a seqpacket connection read from the host socket, which is yet to be
+    /// sent to the peer, and how much of it was already sent.
+    rx_msg: Vec<u8>,
+    rx_msg_sent: usize,
     /// Total number of bytes that have been successfully written to `self.stream`, either
     /// directly, or flushed from `self.tx_buf`.
     fwd_cnt: Wrapping<u32>,
@@ -139,6 +161,10 @@ pub struct VsockConnection<S: VsockConnectionBackend> {
     /// Instant when this connection should be scheduled for immediate termination, due to some
     /// timeout condition having been fulfilled.
     expiry: Option<Instant>,
+    /// Whether reading data from the host stream is throttled.
+    rx_throttled: bool,
+    /// Whether writing data to the host stream is throttled.
+    tx_throttled: bool,
 }
 
 impl<S> VsockChannel for VsockConnection<S>
@@ -190,7 +216,7 @@ where
             return Ok(());
         }
 
-        if self.pending_rx.remove(PendingRx::Rw) {
+        if !self.rx_throttled && self.pending_rx.remove(PendingRx::Rw) {
             // We're due to produce a data packet, by reading the data from the host-side
             // Unix socket.
 
@@ -218,8 +244,15 @@ where
             // the peer available buffer space.
             let max_len = std::cmp::min(pkt.buf_size(), self.peer_avail_credit());
 
-            // Read data from the stream straight to the RX buffer, for maximum throughput.
-            match pkt.read_at_offset_from(&mut self.stream, 0, max_len) {
+            // Read data from the stream straight to the RX buffer, for maximum throughput. The
+            // messages of seqpacket connections are read whole, then sent in as many packets
+            // as needed.
+            let read_res = if self.type_ == uapi::VSOCK_TYPE_SEQPACKET {
+                self.read_msg_to(pkt, max_len)
+            } else {
+                pkt.read_at_offset_from(&mut self.stream, 0, max_len)
+            };
+            match read_res {
                 Ok(read_cnt) => {
                     if read_cnt == 0 {
                         // A 0-length read means the host stream was closed down. In that case,
@@ -239,6 +272,9 @@ where
                         // Safe to unwrap because read_cnt is no more than max_len, which is bounded
                         // by self.peer_avail_credit(), a u32 internally.
                         pkt.hdr.set_op(uapi::VSOCK_OP_RW).set_len(read_cnt);
+                        if self.type_ == uapi::VSOCK_TYPE_SEQPACKET && self.rx_msg.is_empty() {
+                            pkt.hdr.set_flag(uapi::VSOCK_FLAGS_SEQ_EOM);
+                        }
                         METRICS.rx_bytes_count.add(read_cnt as u64);
                     }
                     self.rx_cnt += Wrapping(pkt.hdr.len());
@@ -313,8 +349,12 @@ where
                     return Ok(());
                 }
 
-                // Unwrapping here is safe, since we just checked `pkt.buf()` above.
-                if let Err(err) = self.send_bytes(pkt) {
+                let send_res = if self.type_ == uapi::VSOCK_TYPE_SEQPACKET {
+                    self.send_msg_bytes(pkt)
+                } else {
+                    self.send_bytes(pkt)
+                };
+                if let Err(err) = send_res {
                     // If we can't write to the host stream, that's an unrecoverable error, so
                     // we'll terminate this connection.
                     warn!(
@@ -350,7 +390,7 @@ where
                 let send_off = pkt.hdr.flags() & uapi::VSOCK_FLAGS_SHUTDOWN_SEND != 0;
                 self.state = ConnState::PeerClosed(recv_off, send_off);
                 if recv_off && send_off {
-                    if self.tx_buf.is_empty() {
+                    if self.tx_bufs_empty() {
                         self.pending_rx.insert(PendingRx::Rst);
                     } else {
                         self.expiry = Some(
@@ -367,7 +407,7 @@ where
             {
                 *recv_off = *recv_off || (pkt.hdr.flags() & uapi::VSOCK_FLAGS_SHUTDOWN_RCV != 0);
                 *send_off = *send_off || (pkt.hdr.flags() & uapi::VSOCK_FLAGS_SHUTDOWN_SEND != 0);
-                if *recv_off && *send_off && self.tx_buf.is_empty() {
+                if *recv_off && *send_off && self.tx_bufs_empty() {
                     self.pending_rx.insert(PendingRx::Rst);
                 }
             }
@@ -400,7 +440,12 @@ where
     }
 
     /// Check if the connection has any pending packet addressed to the peer.
+    ///
+    /// Data packets aren't pending while RX is throttled.
     fn has_pending_rx(&self) -> bool {
+        if self.rx_throttled {
+            return !self.pending_rx.is_empty_except(PendingRx::Rw);
+        }
         !self.pending_rx.is_empty()
     }
 }
@@ -428,9 +473,11 @@ where
     /// - data is available to be read from the host stream, so that it can store an RW pending RX
     ///   indication; and
     /// - data can be written to the host stream, and the TX buffer needs to be flushed.
+    ///
+    /// Throttled directions are not polled.
     fn get_polled_evset(&self) -> EventSet {
         let mut evset = EventSet::empty();
-        if !self.tx_buf.is_empty() {
+        if !self.tx_throttled && !self.tx_bufs_empty() {
             // There's data waiting in the TX buffer, so we are interested in being notified
             // when writing to the host stream wouldn't block.
             evset.insert(EventSet::OUT);
@@ -439,7 +486,7 @@ where
         // stream, unless we're in a state which doesn't allow moving data from host to guest.
         match self.state {
             ConnState::Killed | ConnState::LocalClosed | ConnState::PeerClosed(true, _) => (),
-            _ if self.need_credit_update_from_peer() => (),
+            _ if self.need_credit_update_from_peer() || self.rx_throttled => (),
             _ => evset.insert(EventSet::IN),
         }
         evset
@@ -456,37 +503,37 @@ where
         if evset.contains(EventSet::OUT) {
             // Data can be written to the host stream. Time to flush out the TX buffer.
             //
-            if self.tx_buf.is_empty() {
+            if self.tx_bufs_empty() {
                 METRICS.conn_event_fails.inc();
                 info!("vsock: connection received unexpected EPOLLOUT event");
                 return;
             }
-            let flushed = self
-                .tx_buf
-                .flush_to(&mut self.stream)
-                .unwrap_or_else(|err| {
-                    METRICS.tx_flush_fails.inc();
-                    warn!(
-                        "vsock: error flushing TX buf for (lp={}, pp={}): {:?}",
-                        self.local_port, self.peer_port, err
-                    );
-                    match err {
-                        VsockCsmError::TxBufFlush(inner)
-                            if inner.kind() == ErrorKind::WouldBlock =>
-                        {
-                            // This should never happen (EWOULDBLOCK after EPOLLOUT), but
-                            // it does, so let's absorb it.
-                        }
-                        _ => self.kill(),
-                    };
-                    0
-                });
+            let flush_res = if self.type_ == uapi::VSOCK_TYPE_SEQPACKET {
+                self.tx_msg_buf.flush_to(&mut self.stream)
+            } else {
+                self.tx_buf.flush_to(&mut self.stream)
+            };
+            let flushed = flush_res.unwrap_or_else(|err| {
+                METRICS.tx_flush_fails.inc();
+                warn!(
+                    "vsock: error flushing TX buf for (lp={}, pp={}): {:?}",
+                    self.local_port, self.peer_port, err
+                );
+                match err {
+                    VsockCsmError::TxBufFlush(inner) if inner.kind() == ErrorKind::WouldBlock => {
+                        // This should never happen (EWOULDBLOCK after EPOLLOUT), but
+                        // it does, so let's absorb it.
+                    }
+                    _ => self.kill(),
+                };
+                0
+            });
             self.fwd_cnt += wrap_usize_to_u32(flushed);
             METRICS.tx_bytes_count.add(flushed as u64);
 
             // If this connection was shutting down, but is waiting to drain the TX buffer
             // before forceful termination, the wait might be over.
-            if self.state == ConnState::PeerClosed(true, true) && self.tx_buf.is_empty() {
+            if self.state == ConnState::PeerClosed(true, true) && self.tx_bufs_empty() {
                 self.pending_rx.insert(PendingRx::Rst);
             } else if self.peer_needs_credit_update() {
                 // If we've freed up some more buffer space, we may need to let the peer know it
@@ -501,7 +548,7 @@ impl<S> VsockConnection<S>
 where
     S: VsockConnectionBackend + Debug,
 {
-    /// Create a new guest-initiated connection object.
+    /// Create a new guest-initiated connection object, of the socket type `type_`.
     pub fn new_peer_init(
         stream: S,
         local_cid: u64,
@@ -509,15 +556,21 @@ where
         local_port: u32,
         peer_port: u32,
         peer_buf_alloc: u32,
+        type_: u16,
     ) -> Self {
         Self {
             local_cid,
             peer_cid,
             local_port,
             peer_port,
+            type_,
+            service_port: local_port,
             stream,
             state: ConnState::PeerInit,
             tx_buf: TxBuf::new(),
+            tx_msg_buf: MsgBuf::new(),
+            rx_msg: Vec::new(),
+            rx_msg_sent: 0,
             fwd_cnt: Wrapping(0),
             peer_buf_alloc,
             peer_fwd_cnt: Wrapping(0),
@@ -525,6 +578,8 @@ where
             last_fwd_cnt_to_peer: Wrapping(0),
             pending_rx: PendingRxSet::from(PendingRx::Response),
             expiry: None,
+            rx_throttled: false,
+            tx_throttled: false,
         }
     }
 
@@ -541,9 +596,14 @@ where
             peer_cid,
             local_port,
             peer_port,
+            type_: uapi::VSOCK_TYPE_STREAM,
+            service_port: peer_port,
             stream,
             state: ConnState::LocalInit,
             tx_buf: TxBuf::new(),
+            tx_msg_buf: MsgBuf::new(),
+            rx_msg: Vec::new(),
+            rx_msg_sent: 0,
             fwd_cnt: Wrapping(0),
             peer_buf_alloc: 0,
             peer_fwd_cnt: Wrapping(0),
@@ -551,6 +611,8 @@ where
             last_fwd_cnt_to_peer: Wrapping(0),
             pending_rx: PendingRxSet::from(PendingRx::Request),
             expiry: None,
+            rx_throttled: false,
+            tx_throttled: false,
         }
     }
 
@@ -589,6 +651,25 @@ where
         self.state
     }
 
+    /// Return the port to which the connection was requested, i.e. the host port of
+    /// guest-initiated connections, and the guest port of host-initiated ones.
+    pub fn service_port(&self) -> u32 {
+        self.service_port
+    }
+
+    /// Return the total number of bytes sent to the peer, and the total number of bytes written
+    /// to the host stream.
+    pub fn data_counts(&self) -> (Wrapping<u32>, Wrapping<u32>) {
+        (self.rx_cnt, self.fwd_cnt)
+    }
+
+    /// Throttle, or stop throttling, the reads from the host stream (`rx`) and the writes to it
+    /// (`tx`).
+    pub fn set_throttled(&mut self, rx: bool, tx: bool) {
+        self.rx_throttled = rx;
+        self.tx_throttled = tx;
+    }
+
     /// Send some raw, untracked, data straight to the underlying connected stream.
     /// Returns: number of bytes written, or the error describing the write failure.
     ///
@@ -609,8 +690,9 @@ where
         // If there is data in the TX buffer, that means we're already registered for EPOLLOUT
         // events on the underlying stream. Therefore, there's no point in attempting a write
         // at this point. `self.notify()` will get called when EPOLLOUT arrives, and it will
-        // attempt to drain the TX buffer then.
-        if !self.tx_buf.is_empty() {
+        // attempt to drain the TX buffer then. While TX is throttled, the data is also buffered,
+        // to be flushed once the throttling stops.
+        if self.tx_throttled || !self.tx_buf.is_empty() {
             return pkt
                 .write_from_offset_to(&mut self.tx_buf, 0, len)
                 .map(|_| ());
@@ -646,6 +728,83 @@ where
         Ok(())
     }
 
+    /// Send the data of a packet of a seqpacket connection to the host socket.
+    ///
+    /// The data is added to the message being reassembled in our TX buffer, and the complete
+    /// messages are then written whole to the host socket, if they are not already waiting to be.
+    fn send_msg_bytes(&mut self, pkt: &VsockPacketTx) -> Result<(), VsockError> {
+        pkt.write_from_offset_to(&mut self.tx_msg_buf, 0, pkt.hdr.len())?;
+        if pkt.hdr.flags() & uapi::VSOCK_FLAGS_SEQ_EOM == 0 {
+            return Ok(());
+        }
+
+        // If there are already messages in the TX buffer, we're registered for EPOLLOUT events,
+        // and `self.notify()` will flush this one after them, as it will once TX is no longer
+        // throttled.
+        let waiting = !self.tx_msg_buf.is_empty();
+        self.tx_msg_buf.end_msg();
+        if waiting || self.tx_throttled {
+            return Ok(());
+        }
+
+        let written = match self.tx_msg_buf.flush_to(&mut self.stream) {
+            Ok(cnt) => cnt,
+            // Absorb any would-block errors, since we can always try again later.
+            Err(VsockCsmError::TxBufFlush(err)) if err.kind() == ErrorKind::WouldBlock => 0,
+            Err(err) => {
+                METRICS.tx_write_fails.inc();
+                return Err(VsockError::GuestMemoryMmap(GuestMemoryError::IOError(
+                    std::io::Error::other(err),
+                )));
+            }
+        };
+        self.fwd_cnt += wrap_usize_to_u32(written);
+        METRICS.tx_bytes_count.add(written as u64);
+        Ok(())
+    }
+
+    /// Read the data of a packet of a seqpacket connection from the host socket.
+    ///
+    /// A message is read whole from the host socket, and sent in the packets of the next calls,
+    /// the last of which is flagged with `VSOCK_FLAGS_SEQ_EOM`. Returns 0 if the host socket was
+    /// closed.
+    fn read_msg_to(&mut self, pkt: &mut VsockPacketRx, max_len: u32) -> Result<u32, VsockError> {
+        if self.rx_msg.is_empty() {
+            self.rx_msg.resize(defs::CONN_RX_MSG_SIZE, 0);
+            let read = self
+                .stream
+                .read_volatile(&mut VolatileSlice::from(self.rx_msg.as_mut_slice()));
+            match read {
+                Ok(len) => self.rx_msg.truncate(len),
+                Err(err) => {
+                    self.rx_msg.clear();
+                    return Err(VsockError::GuestMemoryMmap(GuestMemoryError::from(err)));
+                }
+            }
+            self.rx_msg_sent = 0;
+        }
+
+        let remaining = &self.rx_msg[self.rx_msg_sent..];
+        let len = u32::try_from(remaining.len())
+            .unwrap_or(u32::MAX)
+            .min(max_len);
+        let read_cnt = pkt.read_at_offset_from(&mut &remaining[..len as usize], 0, len)?;
+        self.rx_msg_sent += read_cnt as usize;
+        if self.rx_msg_sent == self.rx_msg.len() {
+            self.rx_msg.clear();
+        } else {
+            // The rest of the message is sent in the next packets, whether or not the host
+            // socket is readable.
+            self.pending_rx.insert(PendingRx::Rw);
+        }
+        Ok(read_cnt)
+    }
+
+    /// Check if the TX buffers hold no data waiting for the host stream to be writable.
+    fn tx_bufs_empty(&self) -> bool {
+        self.tx_buf.is_empty() && self.tx_msg_buf.is_empty()
+    }
+
     /// Check if the credit information the peer has last received from us is outdated.
     fn peer_needs_credit_update(&self) -> bool {
         let peer_seen_free_buf =
@@ -671,7 +830,7 @@ where
             .set_dst_cid(self.peer_cid)
             .set_src_port(self.local_port)
             .set_dst_port(self.peer_port)
-            .set_type(uapi::VSOCK_TYPE_STREAM)
+            .set_type(self.type_)
             .set_buf_alloc(defs::CONN_TX_BUF_SIZE)
             .set_fwd_cnt(self.fwd_cnt.0);
     }
@@ -882,6 +1041,7 @@ mod tests {
                     LOCAL_PORT,
                     PEER_PORT,
                     PEER_BUF_ALLOC,
+                    uapi::VSOCK_TYPE_STREAM,
                 ),
                 ConnState::LocalInit => VsockConnection::<TestStream>::new_local_init(
                     stream, LOCAL_CID, PEER_CID, LOCAL_PORT, PEER_PORT,
@@ -894,6 +1054,7 @@ mod tests {
                         LOCAL_PORT,
                         PEER_PORT,
                         PEER_BUF_ALLOC,
+                        uapi::VSOCK_TYPE_STREAM,
                     );
                     assert!(conn.has_pending_rx());
                     conn.recv_pkt(&mut rx_pkt).unwrap();
@@ -1237,6 +1398,41 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_throttling() {
+        let mut ctx = CsmTestContext::new_established();
+        assert_eq!(ctx.conn.service_port(), LOCAL_PORT);
+        let data = &[1, 2, 3, 4];
+        ctx.set_stream(TestStream::new_with_read_buf(data));
+        ctx.conn.set_throttled(true, true);
+
+        // While RX is throttled, the host stream is neither polled nor read.
+        assert!(!ctx.conn.get_polled_evset().contains(EventSet::IN));
+        ctx.conn.notify(EventSet::IN);
+        assert!(!ctx.conn.has_pending_rx());
+        assert!(matches!(
+            ctx.conn.recv_pkt(&mut ctx.rx_pkt),
+            Err(VsockError::NoData)
+        ));
+
+        // While TX is throttled, the data of the guest is buffered.
+        ctx.init_data_tx_pkt(data);
+        ctx.send();
+        assert_eq!(ctx.conn.tx_buf.len(), data.len());
+        assert!(!ctx.conn.get_polled_evset().contains(EventSet::OUT));
+        assert_eq!(ctx.conn.data_counts(), (Wrapping(0), Wrapping(0)));
+
+        // Once the throttling stops, the pending data moves in both directions.
+        ctx.conn.set_throttled(false, false);
+        assert!(ctx.conn.has_pending_rx());
+        ctx.recv();
+        assert_eq!(ctx.rx_pkt.hdr.op(), uapi::VSOCK_OP_RW);
+        assert!(ctx.conn.get_polled_evset().contains(EventSet::OUT));
+        ctx.notify_epollout();
+        assert_eq!(ctx.conn.stream.write_buf, data);
+        assert_eq!(ctx.conn.data_counts(), (Wrapping(4), Wrapping(4)));
+    }
+
     #[test]
     fn test_stream_write_error() {
         // Test case: sending a data packet to a broken / closed backing stream should kill it.
@@ -1306,4 +1502,63 @@ mod tests {
         ctx.recv();
         assert_eq!(ctx.rx_pkt.hdr.op(), uapi::VSOCK_OP_RST);
     }
+
+    #[test]
+    fn test_seqpacket_tx() {
+        let mut ctx = CsmTestContext::new_established();
+        ctx.conn.type_ = uapi::VSOCK_TYPE_SEQPACKET;
+
+        // The fragments of a message are only written to the host socket once it is complete.
+        ctx.init_data_tx_pkt(&[1, 2]);
+        ctx.send();
+        assert!(ctx.conn.stream.write_buf.is_empty());
+        assert_eq!(ctx.conn.fwd_cnt().0, 0);
+        ctx.init_data_tx_pkt(&[3]);
+        ctx.tx_pkt.hdr.set_flags(uapi::VSOCK_FLAGS_SEQ_EOM);
+        ctx.send();
+        assert_eq!(ctx.conn.stream.write_buf, vec![1, 2, 3]);
+        assert_eq!(ctx.conn.fwd_cnt().0, 3);
+
+        // A message which can't be written is buffered until the host socket is writable.
+        let mut stream = TestStream::new();
+        stream.write_state = StreamState::WouldBlock;
+        ctx.set_stream(stream);
+        ctx.init_data_tx_pkt(&[4, 5]);
+        ctx.tx_pkt.hdr.set_flags(uapi::VSOCK_FLAGS_SEQ_EOM);
+        ctx.send();
+        assert!(ctx.conn.get_polled_evset().contains(EventSet::OUT));
+        ctx.conn.stream.write_state = StreamState::Ready;
+        ctx.notify_epollout();
+        assert_eq!(ctx.conn.stream.write_buf, vec![4, 5]);
+        assert_eq!(ctx.conn.fwd_cnt().0, 5);
+        assert!(!ctx.conn.get_polled_evset().contains(EventSet::OUT));
+    }
+
+    #[test]
+    fn test_seqpacket_rx() {
+        let mut ctx = CsmTestContext::new_established();
+        ctx.conn.type_ = uapi::VSOCK_TYPE_SEQPACKET;
+
+        // A message longer than the RX buffer is sent in several packets, the last of which
+        // ends the message.
+        let buf_size = ctx.rx_pkt.buf_size();
+        let data = vec![7u8; buf_size as usize + 1];
+        ctx.set_stream(TestStream::new_with_read_buf(&data));
+        ctx.notify_epollin();
+        ctx.recv();
+        assert_eq!(ctx.rx_pkt.hdr.op(), uapi::VSOCK_OP_RW);
+        assert_eq!(ctx.rx_pkt.hdr.type_(), uapi::VSOCK_TYPE_SEQPACKET);
+        assert_eq!(ctx.rx_pkt.hdr.len(), buf_size);
+        assert_eq!(ctx.rx_pkt.hdr.flags() & uapi::VSOCK_FLAGS_SEQ_EOM, 0);
+        // The rest of the message is pending, although the host socket has nothing more to read.
+        assert!(ctx.conn.has_pending_rx());
+        assert!(ctx.conn.stream.read_buf.is_empty());
+
+        ctx.rx_pkt.hdr.set_flags(0);
+        ctx.recv();
+        assert_eq!(ctx.rx_pkt.hdr.op(), uapi::VSOCK_OP_RW);
+        assert_eq!(ctx.rx_pkt.hdr.len(), 1);
+        assert_ne!(ctx.rx_pkt.hdr.flags() & uapi::VSOCK_FLAGS_SEQ_EOM, 0);
+        assert!(!ctx.conn.has_pending_rx());
+    }
 }
@@ -4,6 +4,7 @@
 /// This module implements our vsock connection state machine. The heavy lifting is done by
 /// `connection::VsockConnection`, while this file only defines some constants and helper structs.
 mod connection;
+mod msgbuf;
 mod txbuf;
 
 pub use connection::{VsockConnection, VsockConnectionBackend};
@@ -12,6 +13,10 @@ pub mod defs {
     /// Vsock connection TX buffer capacity.
     pub const CONN_TX_BUF_SIZE: u32 = 64 * 1024;
 
+    /// Largest message read from the host socket of a seqpacket connection. Longer messages are
+    /// truncated.
+    pub const CONN_RX_MSG_SIZE: usize = 64 * 1024;
+
     /// When the guest thinks we have less than this amount of free buffer space,
     /// we will send them a credit update packet.
     pub const CONN_CREDIT_UPDATE_THRESHOLD: u32 = 4 * 1024;
@@ -106,6 +111,11 @@ impl PendingRxSet {
     fn is_empty(&self) -> bool {
         self.data == 0
     }
+
+    /// Check if the set holds no item other than `it`.
+    fn is_empty_except(&self, it: PendingRx) -> bool {
+        self.data & !it.into_mask() == 0
+    }
 }
 
 /// Create a set containing only one item.
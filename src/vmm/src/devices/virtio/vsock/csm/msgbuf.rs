@@ -0,0 +1,195 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use std::collections::VecDeque;
+use std::fmt::Debug;
+use std::io::Write;
+
+use vm_memory::{VolatileMemoryError, VolatileSlice, WriteVolatile};
+
+use super::{defs, VsockCsmError};
+use crate::vstate::memory::{BitmapSlice, Bytes};
+
+/// The TX (guest -> host) buffer of seqpacket connections. The messages sent by the guest are
+/// reassembled from the packets carrying them, and are only written whole to the host socket,
+/// so that they keep their boundaries.
+#[derive(Debug, Default)]
+pub struct MsgBuf {
+    /// The complete messages, yet to be written to the host socket.
+    msgs: VecDeque<Vec<u8>>,
+    /// The message being reassembled.
+    partial: Vec<u8>,
+    /// Number of bytes held, in complete messages or not.
+    len: usize,
+}
+
+impl MsgBuf {
+    /// Total buffer size, in bytes.
+    const SIZE: usize = defs::CONN_TX_BUF_SIZE as usize;
+
+    /// Message buffer constructor.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the used length of this buffer - number of bytes that have been pushed in, but not
+    /// yet flushed out.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Push a byte slice at the end of the message being reassembled.
+    ///
+    /// Either the entire source slice will be pushed to the buffer, or none of it, if there
+    /// isn't enough room, in which case `Err(Error::TxBufFull)` is returned.
+    pub fn push(&mut self, src: &VolatileSlice<impl BitmapSlice>) -> Result<(), VsockCsmError> {
+        if self.len + src.len() > Self::SIZE {
+            return Err(VsockCsmError::TxBufFull);
+        }
+
+        let start = self.partial.len();
+        self.partial.resize(start + src.len(), 0);
+        let _ = src.read(&mut self.partial[start..], 0);
+        self.len += src.len();
+        Ok(())
+    }
+
+    /// Complete the message being reassembled, which will be written to the host socket after
+    /// the ones before it.
+    pub fn end_msg(&mut self) {
+        self.msgs.push_back(std::mem::take(&mut self.partial));
+    }
+
+    /// Flush the complete messages to a writable socket, in order, each with a single write.
+    ///
+    /// Return the number of bytes that have been transferred out of the buffer and into the
+    /// socket.
+    pub fn flush_to<W: Write + Debug>(&mut self, sink: &mut W) -> Result<usize, VsockCsmError> {
+        let mut flushed = 0;
+        while let Some(msg) = self.msgs.front() {
+            match sink.write(msg) {
+                Ok(_) => {
+                    flushed += msg.len();
+                    self.len -= msg.len();
+                    self.msgs.pop_front();
+                }
+                // Like for stream data, if some messages were already flushed, we consider the
+                // flush a success, and try again later for the others.
+                Err(_) if flushed > 0 => break,
+                Err(err) => return Err(VsockCsmError::TxBufFlush(err)),
+            }
+        }
+        Ok(flushed)
+    }
+
+    /// Check if the buffer holds any complete message that hasn't yet been flushed out.
+    pub fn is_empty(&self) -> bool {
+        self.msgs.is_empty()
+    }
+}
+
+impl WriteVolatile for MsgBuf {
+    fn write_volatile<B: BitmapSlice>(
+        &mut self,
+        buf: &VolatileSlice<B>,
+    ) -> Result<usize, VolatileMemoryError> {
+        self.push(buf).map(|()| buf.len()).map_err(|err| {
+            VolatileMemoryError::IOError(std::io::Error::new(std::io::ErrorKind::Other, err))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Error as IoError, ErrorKind};
+
+    use super::*;
+
+    #[derive(Debug, Default)]
+    struct TestSink {
+        msgs: Vec<Vec<u8>>,
+        err: Option<ErrorKind>,
+    }
+
+    impl Write for TestSink {
+        fn write(&mut self, src: &[u8]) -> Result<usize, IoError> {
+            if let Some(kind) = self.err {
+                return Err(IoError::from(kind));
+            }
+            self.msgs.push(src.to_vec());
+            Ok(src.len())
+        }
+        fn flush(&mut self) -> Result<(), IoError> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_msg_reassembly() {
+        let mut msg_buf = MsgBuf::new();
+        let mut sink = TestSink::default();
+
+        msg_buf
+            .push(&VolatileSlice::from([1, 2].as_mut_slice()))
+            .unwrap();
+        // An incomplete message is not flushed.
+        assert!(msg_buf.is_empty());
+        assert_eq!(msg_buf.flush_to(&mut sink).unwrap(), 0);
+
+        msg_buf
+            .push(&VolatileSlice::from([3].as_mut_slice()))
+            .unwrap();
+        msg_buf.end_msg();
+        msg_buf
+            .push(&VolatileSlice::from([4].as_mut_slice()))
+            .unwrap();
+        msg_buf.end_msg();
+        assert!(!msg_buf.is_empty());
+        assert_eq!(msg_buf.len(), 4);
+
+        assert_eq!(msg_buf.flush_to(&mut sink).unwrap(), 4);
+        assert_eq!(sink.msgs, vec![vec![1, 2, 3], vec![4]]);
+        assert!(msg_buf.is_empty());
+        assert_eq!(msg_buf.len(), 0);
+    }
+
+    #[test]
+    fn test_msg_buf_full() {
+        let mut msg_buf = MsgBuf::new();
+        let mut data = vec![0u8; MsgBuf::SIZE];
+        msg_buf
+            .push(&VolatileSlice::from(&mut data[..MsgBuf::SIZE - 1]))
+            .unwrap();
+        assert!(matches!(
+            msg_buf.push(&VolatileSlice::from(&mut data[..2])),
+            Err(VsockCsmError::TxBufFull)
+        ));
+        msg_buf.push(&VolatileSlice::from(&mut data[..1])).unwrap();
+        assert_eq!(msg_buf.len(), MsgBuf::SIZE);
+    }
+
+    #[test]
+    fn test_msg_flush_error() {
+        let mut msg_buf = MsgBuf::new();
+        let mut sink = TestSink {
+            err: Some(ErrorKind::WouldBlock),
+            ..Default::default()
+        };
+        msg_buf
+            .push(&VolatileSlice::from([1].as_mut_slice()))
+            .unwrap();
+        msg_buf.end_msg();
+
+        match msg_buf.flush_to(&mut sink) {
+            Err(VsockCsmError::TxBufFlush(err)) => assert_eq!(err.kind(), ErrorKind::WouldBlock),
+            other => panic!("Unexpected flush result: {:?}", other),
+        }
+        // The message is kept, to be flushed later.
+        assert!(!msg_buf.is_empty());
+
+        sink.err = None;
+        assert_eq!(msg_buf.flush_to(&mut sink).unwrap(), 1);
+        assert_eq!(sink.msgs, vec![vec![1]]);
+    }
+}
@@ -48,6 +48,8 @@ pub(crate) const VIRTIO_VSOCK_EVENT_TRANSPORT_RESET: u32 = 0;
 /// - VIRTIO_F_VERSION_1: the device conforms to at least version 1.0 of the VirtIO spec.
 /// - VIRTIO_F_IN_ORDER: the device returns used buffers in the same order that the driver makes
 ///   them available.
+///
+/// The features of the other socket types are added when the backend handles them.
 pub(crate) const AVAIL_FEATURES: u64 =
     1 << uapi::VIRTIO_F_VERSION_1 as u64 | 1 << uapi::VIRTIO_F_IN_ORDER as u64;
 
@@ -98,8 +100,8 @@ where
             cid,
             queues,
             queue_events,
+            avail_features: AVAIL_FEATURES | backend.socket_features(),
             backend,
-            avail_features: AVAIL_FEATURES,
             acked_features: 0,
             irq_trigger: IrqTrigger::new().map_err(VsockError::EventFd)?,
             activate_evt: EventFd::new(libc::EFD_NONBLOCK).map_err(VsockError::EventFd)?,
@@ -17,12 +17,21 @@
 //!     "muxer_event_fails": "SharedIncMetric",
 //!     ...
 //!  }
+//!  "vsock_port_52": {
+//!     "conns_open": "SharedStoreMetric",
+//!     "conns_added": "SharedIncMetric",
+//!     "rx_bytes_count": "SharedIncMetric",
+//!     ...
+//!  }
 //! }
 //! ```
 //! Each `vsock` field in the example above is a serializable `VsockDeviceMetrics` structure
 //! collecting metrics such as `activate_fails`, `cfg_fails`, etc. for the Vsock device.
 //! Since vsock doesn't support multiple devices, there is no per device metrics and
 //! `vsock` represents the aggregate metrics for all vsock connections.
+//! `vsock_port_52` represents the metrics of the connections to the vsock port 52, a
+//! `VsockPortMetrics` structure. Only the ports configured with rate limiters have their own
+//! metrics, so that the guest can't grow them without bounds.
 //!
 //! # Design
 //! The main design goals of this system are:
@@ -32,22 +41,35 @@
 //!   FirecrackerDeviceMetrics.
 //! * Rely on `serde` to provide the actual serialization for writing the metrics.
 //!
-//! The system implements 1 type of metrics:
+//! The system implements 2 types of metrics:
 //! * Shared Incremental Metrics (SharedIncMetrics) - dedicated for the metrics which need a counter
 //!   (i.e the number of times an API request failed). These metrics are reset upon flush.
+//! * Shared Store Metrics (SharedStoreMetrics) - dedicated for the metrics which need a gauge (i.e
+//!   the number of open connections). These metrics are not reset upon flush.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
 
 use serde::ser::SerializeMap;
 use serde::{Serialize, Serializer};
 
-use crate::logger::SharedIncMetric;
+use crate::logger::{SharedIncMetric, SharedStoreMetric};
 
 /// Stores aggregate metrics of all Vsock connections/actions
 pub(super) static METRICS: VsockDeviceMetrics = VsockDeviceMetrics::new();
 
+/// Metrics of the rate limited vsock ports, keyed by port. Since the lock is initialized here it
+/// is safe to unwrap it without any check.
+static PORT_METRICS: RwLock<BTreeMap<u32, Arc<VsockPortMetrics>>> = RwLock::new(BTreeMap::new());
+
 /// Called by METRICS.flush(), this function facilitates serialization of vsock device metrics.
 pub fn flush_metrics<S: Serializer>(serializer: S) -> Result<S::Ok, S::Error> {
-    let mut seq = serializer.serialize_map(Some(1))?;
+    let port_metrics = PORT_METRICS.read().unwrap();
+    let mut seq = serializer.serialize_map(Some(1 + port_metrics.len()))?;
     seq.serialize_entry("vsock", &METRICS)?;
+    for (port, metrics) in port_metrics.iter() {
+        seq.serialize_entry(&format!("vsock_port_{}", port), metrics)?;
+    }
     seq.end()
 }
 
@@ -86,6 +108,10 @@ pub(super) struct VsockDeviceMetrics {
     pub conns_killed: SharedIncMetric,
     /// Number of removed connections.
     pub conns_removed: SharedIncMetric,
+    /// Number of RST packets sent to the guest.
+    pub rx_rst_count: SharedIncMetric,
+    /// Number of RST packets received from the guest.
+    pub tx_rst_count: SharedIncMetric,
     /// How many times the killq has been resynced.
     pub killq_resync: SharedIncMetric,
     /// How many flush fails have been seen.
@@ -94,6 +120,8 @@ pub(super) struct VsockDeviceMetrics {
     pub tx_write_fails: SharedIncMetric,
     /// Number of times read() has failed.
     pub rx_read_fails: SharedIncMetric,
+    /// Number of datagrams dropped, in either direction.
+    pub dgram_drops: SharedIncMetric,
 }
 
 impl VsockDeviceMetrics {
@@ -117,14 +145,52 @@ impl VsockDeviceMetrics {
             conns_added: SharedIncMetric::new(),
             conns_killed: SharedIncMetric::new(),
             conns_removed: SharedIncMetric::new(),
+            rx_rst_count: SharedIncMetric::new(),
+            tx_rst_count: SharedIncMetric::new(),
             killq_resync: SharedIncMetric::new(),
             tx_flush_fails: SharedIncMetric::new(),
             tx_write_fails: SharedIncMetric::new(),
             rx_read_fails: SharedIncMetric::new(),
+            dgram_drops: SharedIncMetric::new(),
         }
     }
 }
 
+/// Metrics of the connections to a rate limited vsock port.
+#[derive(Debug, Default, Serialize)]
+pub(super) struct VsockPortMetrics {
+    /// Number of open connections.
+    pub conns_open: SharedStoreMetric,
+    /// Number of added connections.
+    pub conns_added: SharedIncMetric,
+    /// Number of bytes received.
+    pub rx_bytes_count: SharedIncMetric,
+    /// Number of transmitted bytes.
+    pub tx_bytes_count: SharedIncMetric,
+    /// Number of RST packets sent to the guest.
+    pub rx_rst_count: SharedIncMetric,
+    /// Number of RST packets received from the guest.
+    pub tx_rst_count: SharedIncMetric,
+    /// Number of times the connections were throttled by the RX rate limiter.
+    pub rx_rate_limiter_throttled: SharedIncMetric,
+    /// Number of times the connections were throttled by the TX rate limiter.
+    pub tx_rate_limiter_throttled: SharedIncMetric,
+}
+
+impl VsockPortMetrics {
+    /// Allocate the metrics of the vsock port `port`, only if they don't exist yet, to avoid
+    /// overwriting previously allocated data.
+    pub fn alloc(port: u32) -> Arc<VsockPortMetrics> {
+        Arc::clone(
+            PORT_METRICS
+                .write()
+                .unwrap()
+                .entry(port)
+                .or_insert_with(|| Arc::new(VsockPortMetrics::default())),
+        )
+    }
+}
+
 #[cfg(test)]
 pub mod tests {
     use super::*;
@@ -142,4 +208,17 @@ pub mod tests {
         vsock_metrics.conns_added.inc();
         assert_eq!(vsock_metrics.conns_added.count(), 1);
     }
+
+    #[test]
+    fn test_vsock_port_metrics() {
+        let metrics = VsockPortMetrics::alloc(1234);
+        metrics.rx_bytes_count.add(10);
+        // The metrics of a port are allocated once.
+        assert_eq!(VsockPortMetrics::alloc(1234).rx_bytes_count.count(), 10);
+
+        let mut buf = Vec::new();
+        flush_metrics(&mut serde_json::Serializer::new(&mut buf)).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        assert_eq!(json["vsock_port_1234"]["rx_bytes_count"], 10);
+    }
 }
@@ -25,11 +25,13 @@ use std::os::unix::io::AsRawFd;
 use vm_memory::GuestMemoryError;
 use vmm_sys_util::epoll::EventSet;
 
-pub use self::defs::uapi::VIRTIO_ID_VSOCK as TYPE_VSOCK;
+pub use self::defs::uapi::{
+    VIRTIO_ID_VSOCK as TYPE_VSOCK, VIRTIO_VSOCK_F_DGRAM, VIRTIO_VSOCK_F_SEQPACKET,
+};
 pub use self::defs::VSOCK_DEV_ID;
 pub use self::device::Vsock;
 use self::packet::{VsockPacketRx, VsockPacketTx};
-pub use self::unix::{VsockUnixBackend, VsockUnixBackendError};
+pub use self::unix::{PortLimiter, VsockUnixBackend, VsockUnixBackendError};
 use super::iov_deque::IovDequeError;
 use crate::devices::virtio::iovec::IoVecError;
 use crate::devices::virtio::persist::PersistError as VirtioStateError;
@@ -66,6 +68,16 @@ mod defs {
         /// The device conforms to the virtio spec version 1.0.
         pub const VIRTIO_F_VERSION_1: u32 = 32;
 
+        /// Vsock feature flags.
+        /// Defined in `/include/uapi/linux/virtio_vsock.h`.
+        ///
+        /// The device supports seqpacket sockets.
+        pub const VIRTIO_VSOCK_F_SEQPACKET: u32 = 1;
+        /// The device supports datagram sockets.
+        /// Not defined by Linux nor by the virtio spec: this is the feature bit of the datagram
+        /// support proposed for virtio-vsock, which guests need to be patched with.
+        pub const VIRTIO_VSOCK_F_DGRAM: u32 = 3;
+
         /// Virtio vsock device ID.
         /// Defined in `include/uapi/linux/virtio_ids.h`.
         pub const VIRTIO_ID_VSOCK: u32 = 19;
@@ -95,12 +107,19 @@ mod defs {
         pub const VSOCK_FLAGS_SHUTDOWN_RCV: u32 = 1;
         /// Valid with a VSOCK_OP_SHUTDOWN packet: the packet sender will send no more data.
         pub const VSOCK_FLAGS_SHUTDOWN_SEND: u32 = 2;
+        /// Valid with a seqpacket VSOCK_OP_RW packet: the packet ends a message.
+        pub const VSOCK_FLAGS_SEQ_EOM: u32 = 1;
 
-        /// Vsock packet type.
+        /// Vsock packet types.
         /// Defined in `/include/uapi/linux/virtio_vsock.h`.
         ///
-        /// Stream / connection-oriented packet (the only currently valid type).
+        /// Stream / connection-oriented packet.
         pub const VSOCK_TYPE_STREAM: u16 = 1;
+        /// Seqpacket packet: connection-oriented, keeping the boundaries of the messages.
+        pub const VSOCK_TYPE_SEQPACKET: u16 = 2;
+        /// Datagram packet: connectionless, without flow control.
+        /// Not defined by Linux, like `VIRTIO_VSOCK_F_DGRAM`.
+        pub const VSOCK_TYPE_DGRAM: u16 = 3;
 
         pub const VSOCK_HOST_CID: u64 = 2;
     }
@@ -194,4 +213,9 @@ pub trait VsockChannel {
 /// The vsock backend, which is basically an epoll-event-driven vsock channel.
 /// Currently, the only implementation we have is `crate::devices::virtio::unix::muxer::VsockMuxer`,
 /// which translates guest-side vsock connections to host-side Unix domain socket connections.
-pub trait VsockBackend: VsockChannel + VsockEpollListener + Send {}
+pub trait VsockBackend: VsockChannel + VsockEpollListener + Send {
+    /// The vsock feature bits of the socket types the backend handles, besides stream sockets.
+    fn socket_features(&self) -> u64 {
+        0
+    }
+}
@@ -4,6 +4,7 @@
 //! Defines state and support structures for persisting Vsock devices and backends.
 
 use std::fmt::Debug;
+use std::net::SocketAddr;
 use std::sync::atomic::AtomicU32;
 use std::sync::Arc;
 
@@ -14,6 +15,8 @@ use crate::devices::virtio::device::DeviceState;
 use crate::devices::virtio::persist::VirtioDeviceState;
 use crate::devices::virtio::queue::FIRECRACKER_MAX_QUEUE_SIZE;
 use crate::devices::virtio::vsock::TYPE_VSOCK;
+use crate::rate_limiter::persist::RateLimiterState;
+use crate::rate_limiter::RateLimiter;
 use crate::snapshot::Persist;
 use crate::vstate::memory::GuestMemoryMmap;
 
@@ -34,6 +37,13 @@ pub struct VsockFrontendState {
     virtio_state: VirtioDeviceState,
 }
 
+impl VsockFrontendState {
+    /// The virtio features offered by the device.
+    pub fn avail_features(&self) -> u64 {
+        self.virtio_state.avail_features
+    }
+}
+
 /// An enum for the serializable backend state types.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum VsockBackendState {
@@ -46,6 +56,25 @@ pub enum VsockBackendState {
 pub struct VsockUdsState {
     /// The path for the UDS socket.
     pub(crate) path: String,
+    /// The rate limiters of the ports.
+    pub(crate) port_limiters: Vec<VsockPortLimiterState>,
+    /// The TCP addresses to which the ports are forwarded.
+    pub(crate) tcp_forwards: Vec<VsockTcpForwardState>,
+}
+
+/// The serializable state of the rate limiters of a port.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VsockPortLimiterState {
+    port: u32,
+    rx_rate_limiter_state: RateLimiterState,
+    tx_rate_limiter_state: RateLimiterState,
+}
+
+/// The serializable state of the forwarding of a port to a TCP address.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VsockTcpForwardState {
+    port: u32,
+    addr: SocketAddr,
 }
 
 /// A helper structure that holds the constructor arguments for VsockUnixBackend
@@ -62,6 +91,9 @@ pub struct VsockConstructorArgs<B> {
 pub struct VsockUdsConstructorArgs {
     /// cid available in VsockFrontendState.
     pub cid: u64,
+    /// The virtio features offered by the device, available in VsockFrontendState. They tell the
+    /// socket types the backend handles.
+    pub avail_features: u64,
 }
 
 impl Persist<'_> for VsockUnixBackend {
@@ -72,6 +104,23 @@ impl Persist<'_> for VsockUnixBackend {
     fn save(&self) -> Self::State {
         VsockBackendState::Uds(VsockUdsState {
             path: self.host_sock_path.clone(),
+            port_limiters: self
+                .port_limiters()
+                .iter()
+                .map(|(port, limiter)| VsockPortLimiterState {
+                    port: *port,
+                    rx_rate_limiter_state: limiter.rx.save(),
+                    tx_rate_limiter_state: limiter.tx.save(),
+                })
+                .collect(),
+            tcp_forwards: self
+                .tcp_forwards()
+                .iter()
+                .map(|(port, addr)| VsockTcpForwardState {
+                    port: *port,
+                    addr: *addr,
+                })
+                .collect(),
         })
     }
 
@@ -80,10 +129,29 @@ impl Persist<'_> for VsockUnixBackend {
         state: &Self::State,
     ) -> Result<Self, Self::Error> {
         match state {
-            VsockBackendState::Uds(uds_state) => Ok(VsockUnixBackend::new(
-                constructor_args.cid,
-                uds_state.path.clone(),
-            )?),
+            VsockBackendState::Uds(uds_state) => {
+                let mut backend =
+                    VsockUnixBackend::new(constructor_args.cid, uds_state.path.clone())?;
+                if constructor_args.avail_features & (1 << defs::uapi::VIRTIO_VSOCK_F_SEQPACKET)
+                    != 0
+                {
+                    backend.enable_seqpacket();
+                }
+                if constructor_args.avail_features & (1 << defs::uapi::VIRTIO_VSOCK_F_DGRAM) != 0 {
+                    backend.enable_dgram()?;
+                }
+                for port_state in &uds_state.port_limiters {
+                    let rx = RateLimiter::restore((), &port_state.rx_rate_limiter_state)
+                        .map_err(VsockUnixBackendError::CreateRateLimiter)?;
+                    let tx = RateLimiter::restore((), &port_state.tx_rate_limiter_state)
+                        .map_err(VsockUnixBackendError::CreateRateLimiter)?;
+                    backend.add_port_limiter(port_state.port, rx, tx)?;
+                }
+                for forward_state in &uds_state.tcp_forwards {
+                    backend.add_tcp_forward(forward_state.port, forward_state.addr)?;
+                }
+                Ok(backend)
+            }
         }
     }
 }
@@ -150,6 +218,8 @@ pub(crate) mod tests {
         fn save(&self) -> Self::State {
             VsockBackendState::Uds(VsockUdsState {
                 path: "test".to_owned(),
+                port_limiters: Vec::new(),
+                tcp_forwards: Vec::new(),
             })
         }
 
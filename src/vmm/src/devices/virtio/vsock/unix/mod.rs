@@ -11,7 +11,7 @@ mod muxer;
 mod muxer_killq;
 mod muxer_rxq;
 
-pub use muxer::VsockMuxer as VsockUnixBackend;
+pub use muxer::{PortLimiter, VsockMuxer as VsockUnixBackend};
 
 use crate::devices::virtio::vsock::csm::VsockConnectionBackend;
 
@@ -24,6 +24,13 @@ mod defs {
 
     /// Size of the muxer connection kill queue.
     pub const MUXER_KILLQ_SIZE: u32 = 128;
+
+    /// Size of the buffer in which the datagrams of the host are received: the longest valid
+    /// datagram, with its ports, plus one byte to detect the longer ones.
+    pub const DGRAM_BUF_SIZE: usize = 8 + 64 * 1024 + 1;
+
+    /// How long to wait for the connections forwarded to host TCP addresses to be established.
+    pub const TCP_CONNECT_TIMEOUT_MS: u64 = 100;
 }
 
 /// Vsock backend related errors.
@@ -45,6 +52,12 @@ pub enum VsockUnixBackendError {
     UnixRead(std::io::Error),
     /// Muxer connection limit reached.
     TooManyConnections,
+    /// The vsock port {0} has more than one rate limiter configuration.
+    DuplicatePortLimiter(u32),
+    /// Error creating a rate limiter of a vsock port: {0}
+    CreateRateLimiter(std::io::Error),
+    /// The vsock port {0} is forwarded to more than one TCP address.
+    DuplicateTcpForward(u32),
 }
 
 type MuxerConnection = super::csm::VsockConnection<std::os::unix::net::UnixStream>;
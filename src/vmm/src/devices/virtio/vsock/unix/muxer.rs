@@ -23,18 +23,37 @@
 ///       the host is ready to issue a vsock connection request, informing us of the
 ///       destination port to which it wants to connect);
 ///    3. Some event was triggered for a connected Unix socket, that belongs to a
-///       `VsockConnection`.
+///       `VsockConnection`;
+///    4. A datagram is available for reading from the host-side datagram socket.
 ///
 ///  The muxer gets notified about all of these events, because, as a `VsockEpollListener`
 ///  implementor, it gets to register a nested epoll FD into the main VMM epolling loop. All
 ///  other pollable FDs are then registered under this nested epoll FD.
 ///  To route all these events to their handlers, the muxer uses another `HashMap` object,
 ///  mapping `RawFd`s to `EpollListener`s.
-use std::collections::{HashMap, HashSet};
+///
+///  Seqpacket connections are handled like stream ones, only with host-side seqpacket Unix
+///  sockets. Datagrams don't belong to connections: the muxer forwards them between the guest
+///  and a single host-side datagram Unix socket, prefixing them with their source and
+///  destination ports on the host side.
+///
+///  The connections to a port can be rate limited, in each direction, by the limiters of the
+///  port. The data the connections move is charged to the limiters as it goes, and once a
+///  limiter is blocked, the connections of its port are throttled in that direction until the
+///  limiter timer fires.
+///
+///  The guest-initiated stream connections to some ports can be forwarded to host TCP
+///  addresses instead, in which case the muxer connects to the TCP address rather than to the
+///  Unix socket of the port.
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::Debug;
-use std::io::Read;
-use std::os::unix::io::{AsRawFd, RawFd};
-use std::os::unix::net::{UnixListener, UnixStream};
+use std::io::{ErrorKind, Read};
+use std::net::{SocketAddr, TcpStream};
+use std::num::Wrapping;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::net::{UnixDatagram, UnixListener, UnixStream};
+use std::sync::Arc;
+use std::time::Duration;
 
 use log::{debug, error, info, warn};
 use vmm_sys_util::epoll::{ControlOperation, Epoll, EpollEvent, EventSet};
@@ -45,9 +64,10 @@ use super::super::{VsockBackend, VsockChannel, VsockEpollListener, VsockError};
 use super::muxer_killq::MuxerKillQ;
 use super::muxer_rxq::MuxerRxQ;
 use super::{defs, MuxerConnection, VsockUnixBackendError};
-use crate::devices::virtio::vsock::metrics::METRICS;
+use crate::devices::virtio::vsock::metrics::{VsockPortMetrics, METRICS};
 use crate::devices::virtio::vsock::packet::{VsockPacketRx, VsockPacketTx};
-use crate::logger::IncMetric;
+use crate::logger::{IncMetric, StoreMetric};
+use crate::rate_limiter::{RateLimiter, TokenType};
 
 /// A unique identifier of a `MuxerConnection` object. Connections are stored in a hash map,
 /// keyed by a `ConnMapKey` object.
@@ -62,8 +82,12 @@ pub struct ConnMapKey {
 pub enum MuxerRx {
     /// The packet must be fetched from the connection identified by `ConnMapKey`.
     ConnRx(ConnMapKey),
-    /// The muxer must produce an RST packet.
-    RstPkt { local_port: u32, peer_port: u32 },
+    /// The muxer must produce an RST packet, of the socket type `type_`.
+    RstPkt {
+        local_port: u32,
+        peer_port: u32,
+        type_: u16,
+    },
 }
 
 /// An epoll listener, registered under the muxer's nested epoll FD.
@@ -78,6 +102,63 @@ enum EpollListener {
     /// A listener interested in reading host `connect <port>` commands from a freshly
     /// connected host socket.
     LocalStream(UnixStream),
+    /// A listener interested in datagrams sent by the host.
+    DgramSock,
+    /// A listener interested in the timers of the rate limiters of a port.
+    PortLimiter(u32),
+}
+
+/// The rate limiters of the connections to a port, and their metrics.
+#[derive(Debug)]
+pub struct PortLimiter {
+    /// The limiter of the data sent to the guest.
+    pub rx: RateLimiter,
+    /// The limiter of the data sent by the guest.
+    pub tx: RateLimiter,
+    /// The metrics of the connections to the port.
+    metrics: Arc<VsockPortMetrics>,
+}
+
+impl PortLimiter {
+    /// Throttle a connection of the port in the directions in which the limiters are blocked.
+    fn throttle(&self, conn: &mut MuxerConnection) {
+        conn.set_throttled(self.rx.is_blocked(), self.tx.is_blocked());
+    }
+
+    /// Charge the limiters with the data a connection moved since its `data_counts()` were
+    /// `(rx_cnt, fwd_cnt)`. Every direction in which data moved counts as one operation.
+    fn charge(
+        &mut self,
+        conn: &MuxerConnection,
+        (rx_cnt, fwd_cnt): (Wrapping<u32>, Wrapping<u32>),
+    ) {
+        let (new_rx_cnt, new_fwd_cnt) = conn.data_counts();
+        let rx_bytes = u64::from((new_rx_cnt - rx_cnt).0);
+        if rx_bytes > 0 {
+            self.metrics.rx_bytes_count.add(rx_bytes);
+            if !Self::consume(&mut self.rx, rx_bytes) {
+                self.metrics.rx_rate_limiter_throttled.inc();
+            }
+        }
+        let tx_bytes = u64::from((new_fwd_cnt - fwd_cnt).0);
+        if tx_bytes > 0 {
+            self.metrics.tx_bytes_count.add(tx_bytes);
+            if !Self::consume(&mut self.tx, tx_bytes) {
+                self.metrics.tx_rate_limiter_throttled.inc();
+            }
+        }
+    }
+
+    /// Consume an operation of `bytes` bytes, and return whether the limiter is still unblocked.
+    ///
+    /// The data was already moved, so a limiter running out of budget is only blocked, for
+    /// the connections of the port to wait for its replenishment.
+    fn consume(limiter: &mut RateLimiter, bytes: u64) -> bool {
+        if limiter.consume(1, TokenType::Ops) {
+            limiter.consume(bytes, TokenType::Bytes);
+        }
+        !limiter.is_blocked()
+    }
 }
 
 /// The vsock connection multiplexer.
@@ -108,6 +189,20 @@ pub struct VsockMuxer {
     local_port_set: HashSet<u32>,
     /// The last used host-side port.
     local_port_last: u32,
+    /// Whether the guest can connect seqpacket sockets.
+    seqpacket: bool,
+    /// The Unix socket, through which datagrams are exchanged with the host, if the guest can
+    /// use datagram sockets.
+    dgram_sock: Option<UnixDatagram>,
+    /// The buffer in which the datagrams of the host are received.
+    dgram_buf: Vec<u8>,
+    /// Whether datagrams may be waiting to be read from `dgram_sock`.
+    dgram_rx: bool,
+    /// The rate limiters of the connections, keyed by the port to which they are requested.
+    port_limiters: BTreeMap<u32, PortLimiter>,
+    /// The host TCP addresses to which the guest-initiated connections are forwarded, keyed by
+    /// destination port.
+    tcp_forwards: BTreeMap<u32, SocketAddr>,
 }
 
 impl VsockChannel for VsockMuxer {
@@ -131,6 +226,7 @@ impl VsockChannel for VsockMuxer {
                 MuxerRx::RstPkt {
                     local_port,
                     peer_port,
+                    type_,
                 } => {
                     pkt.hdr
                         .set_op(uapi::VSOCK_OP_RST)
@@ -139,11 +235,12 @@ impl VsockChannel for VsockMuxer {
                         .set_src_port(local_port)
                         .set_dst_port(peer_port)
                         .set_len(0)
-                        .set_type(uapi::VSOCK_TYPE_STREAM)
+                        .set_type(type_)
                         .set_flags(0)
                         .set_buf_alloc(0)
                         .set_fwd_cnt(0);
                     self.rxq.pop().unwrap();
+                    METRICS.rx_rst_count.inc();
                     return Ok(());
                 }
 
@@ -168,10 +265,15 @@ impl VsockChannel for VsockMuxer {
                 // terminate and remove this connection from the active connection pool.
                 //
                 if pkt.hdr.op() == uapi::VSOCK_OP_RST {
-                    self.remove_connection(ConnMapKey {
+                    let key = ConnMapKey {
                         local_port: pkt.hdr.src_port(),
                         peer_port: pkt.hdr.dst_port(),
-                    });
+                    };
+                    METRICS.rx_rst_count.inc();
+                    if let Some(limiter) = self.conn_port_limiter(key) {
+                        limiter.metrics.rx_rst_count.inc();
+                    }
+                    self.remove_connection(key);
                 }
 
                 debug!("vsock muxer: RX pkt: {:?}", pkt.hdr);
@@ -179,6 +281,11 @@ impl VsockChannel for VsockMuxer {
             }
         }
 
+        // The connections have nothing to say, so we can deliver the datagrams of the host.
+        if self.dgram_rx {
+            return self.recv_dgram(pkt);
+        }
+
         Err(VsockError::NoData)
     }
 
@@ -202,10 +309,16 @@ impl VsockChannel for VsockMuxer {
             pkt.hdr
         );
 
-        // If this packet has an unsupported type (!=stream), we must send back an RST.
+        // If this packet has an unsupported type, we must send back an RST.
         //
-        if pkt.hdr.type_() != uapi::VSOCK_TYPE_STREAM {
-            self.enq_rst(pkt.hdr.dst_port(), pkt.hdr.src_port());
+        let supported_type = match pkt.hdr.type_() {
+            uapi::VSOCK_TYPE_STREAM => true,
+            uapi::VSOCK_TYPE_SEQPACKET => self.seqpacket,
+            uapi::VSOCK_TYPE_DGRAM => self.dgram_sock.is_some(),
+            _ => false,
+        };
+        if !supported_type {
+            self.enq_rst(pkt.hdr.dst_port(), pkt.hdr.src_port(), pkt.hdr.type_());
             return Ok(());
         }
 
@@ -219,6 +332,12 @@ impl VsockChannel for VsockMuxer {
             return Ok(());
         }
 
+        // Datagrams don't belong to any connection.
+        if pkt.hdr.type_() == uapi::VSOCK_TYPE_DGRAM {
+            self.send_dgram(pkt);
+            return Ok(());
+        }
+
         if !self.conn_map.contains_key(&conn_key) {
             // This packet can't be routed to any active connection (based on its src and dst
             // ports).  The only orphan / unroutable packets we know how to handle are
@@ -228,7 +347,7 @@ impl VsockChannel for VsockMuxer {
                 self.handle_peer_request_pkt(pkt);
             } else {
                 // Send back an RST, to let the drive know we weren't expecting this packet.
-                self.enq_rst(pkt.hdr.dst_port(), pkt.hdr.src_port());
+                self.enq_rst(pkt.hdr.dst_port(), pkt.hdr.src_port(), pkt.hdr.type_());
             }
             return Ok(());
         }
@@ -237,6 +356,10 @@ impl VsockChannel for VsockMuxer {
         // However, if this is an RST, we have to forcefully terminate the connection, so
         // there's no point in forwarding it the packet.
         if pkt.hdr.op() == uapi::VSOCK_OP_RST {
+            METRICS.tx_rst_count.inc();
+            if let Some(limiter) = self.conn_port_limiter(conn_key) {
+                limiter.metrics.tx_rst_count.inc();
+            }
             self.remove_connection(conn_key);
             return Ok(());
         }
@@ -253,7 +376,7 @@ impl VsockChannel for VsockMuxer {
     /// Check if the muxer has any pending RX data, with which to fill a guest-provided RX
     /// buffer.
     fn has_pending_rx(&self) -> bool {
-        !self.rxq.is_empty() || !self.rxq.is_synced()
+        !self.rxq.is_empty() || !self.rxq.is_synced() || self.dgram_rx
     }
 }
 
@@ -299,7 +422,18 @@ impl VsockEpollListener for VsockMuxer {
     }
 }
 
-impl VsockBackend for VsockMuxer {}
+impl VsockBackend for VsockMuxer {
+    fn socket_features(&self) -> u64 {
+        let mut features = 0;
+        if self.seqpacket {
+            features |= 1 << uapi::VIRTIO_VSOCK_F_SEQPACKET;
+        }
+        if self.dgram_sock.is_some() {
+            features |= 1 << uapi::VIRTIO_VSOCK_F_DGRAM;
+        }
+        features
+    }
+}
 
 impl VsockMuxer {
     /// Muxer constructor.
@@ -321,6 +455,12 @@ impl VsockMuxer {
             killq: MuxerKillQ::new(),
             local_port_last: (1u32 << 30) - 1,
             local_port_set: HashSet::with_capacity(defs::MAX_CONNECTIONS),
+            seqpacket: false,
+            dgram_sock: None,
+            dgram_buf: Vec::new(),
+            dgram_rx: false,
+            port_limiters: BTreeMap::new(),
+            tcp_forwards: BTreeMap::new(),
         };
 
         // Listen on the host initiated socket, for incoming connections.
@@ -328,11 +468,97 @@ impl VsockMuxer {
         Ok(muxer)
     }
 
+    /// Let the guest connect seqpacket sockets, to host-side seqpacket Unix sockets.
+    pub fn enable_seqpacket(&mut self) {
+        self.seqpacket = true;
+    }
+
+    /// Return whether the guest can connect seqpacket sockets.
+    pub fn seqpacket_enabled(&self) -> bool {
+        self.seqpacket
+    }
+
+    /// Let the guest use datagram sockets, exchanging datagrams with the host through a datagram
+    /// Unix socket bound at `<host_sock_path>_dgram`.
+    pub fn enable_dgram(&mut self) -> Result<(), VsockUnixBackendError> {
+        if self.dgram_sock.is_some() {
+            return Ok(());
+        }
+        let dgram_sock = UnixDatagram::bind(dgram_sock_path(&self.host_sock_path))
+            .and_then(|sock| sock.set_nonblocking(true).map(|_| sock))
+            .map_err(VsockUnixBackendError::UnixBind)?;
+        // Listen on the host datagram socket, for incoming datagrams.
+        self.add_listener(dgram_sock.as_raw_fd(), EpollListener::DgramSock)?;
+        self.dgram_sock = Some(dgram_sock);
+        self.dgram_buf = vec![0; defs::DGRAM_BUF_SIZE];
+        Ok(())
+    }
+
+    /// Return whether the guest can use datagram sockets.
+    pub fn dgram_enabled(&self) -> bool {
+        self.dgram_sock.is_some()
+    }
+
     /// Return the file system path of the host-side Unix socket.
     pub fn host_sock_path(&self) -> &str {
         &self.host_sock_path
     }
 
+    /// Rate limit the connections to `port`, i.e. the guest-initiated connections to the host
+    /// port `port`, and the host-initiated connections to the guest port `port`, with the `rx`
+    /// and `tx` limiters. The connections to the port get their own metrics.
+    pub fn add_port_limiter(
+        &mut self,
+        port: u32,
+        rx: RateLimiter,
+        tx: RateLimiter,
+    ) -> Result<(), VsockUnixBackendError> {
+        if self.port_limiters.contains_key(&port) {
+            return Err(VsockUnixBackendError::DuplicatePortLimiter(port));
+        }
+        self.add_listener(rx.as_raw_fd(), EpollListener::PortLimiter(port))?;
+        self.add_listener(tx.as_raw_fd(), EpollListener::PortLimiter(port))?;
+        self.port_limiters.insert(
+            port,
+            PortLimiter {
+                rx,
+                tx,
+                metrics: VsockPortMetrics::alloc(port),
+            },
+        );
+        Ok(())
+    }
+
+    /// Return the rate limiters of the ports, keyed by port.
+    pub fn port_limiters(&self) -> &BTreeMap<u32, PortLimiter> {
+        &self.port_limiters
+    }
+
+    /// Forward the guest-initiated stream connections to the host port `port` to the TCP
+    /// address `addr`, instead of the Unix socket of the port.
+    pub fn add_tcp_forward(
+        &mut self,
+        port: u32,
+        addr: SocketAddr,
+    ) -> Result<(), VsockUnixBackendError> {
+        if self.tcp_forwards.insert(port, addr).is_some() {
+            return Err(VsockUnixBackendError::DuplicateTcpForward(port));
+        }
+        Ok(())
+    }
+
+    /// Return the TCP addresses to which the guest-initiated connections are forwarded, keyed
+    /// by port.
+    pub fn tcp_forwards(&self) -> &BTreeMap<u32, SocketAddr> {
+        &self.tcp_forwards
+    }
+
+    /// Return the rate limiters of the port of a connection, if the port is rate limited.
+    fn conn_port_limiter(&mut self, key: ConnMapKey) -> Option<&mut PortLimiter> {
+        let port = self.conn_map.get(&key)?.service_port();
+        self.port_limiters.get_mut(&port)
+    }
+
     /// Handle/dispatch an epoll event to its listener.
     fn handle_event(&mut self, fd: RawFd, event_set: EventSet) {
         debug!(
@@ -411,6 +637,42 @@ impl VsockMuxer {
                 }
             }
 
+            // Datagrams are ready to be read from the host datagram socket. We'll stop listening
+            // until `recv_dgram()` has read all of them, since the guest may not provide RX
+            // buffers as fast as the host sends datagrams.
+            Some(EpollListener::DgramSock) => {
+                self.remove_listener(fd);
+                self.dgram_rx = true;
+            }
+
+            // A rate limiter of a port was replenished, so the connections to the port may no
+            // longer be throttled.
+            Some(EpollListener::PortLimiter(port)) => {
+                let port = *port;
+                if let Some(limiter) = self.port_limiters.get_mut(&port) {
+                    let rate_limiter = if limiter.rx.as_raw_fd() == fd {
+                        &mut limiter.rx
+                    } else {
+                        &mut limiter.tx
+                    };
+                    if let Err(err) = rate_limiter.event_handler() {
+                        warn!("vsock: error handling port {} limiter event: {}", port, err);
+                        METRICS.muxer_event_fails.inc();
+                    }
+                }
+                let keys: Vec<ConnMapKey> = self
+                    .conn_map
+                    .iter()
+                    .filter(|(_, conn)| conn.service_port() == port)
+                    .map(|(key, _)| *key)
+                    .collect();
+                for key in keys {
+                    // Applying an empty mutation is enough to update the throttling of the
+                    // connection, along with its RX indication and epoll listener.
+                    self.apply_conn_mutation(key, |_| ());
+                }
+            }
+
             _ => {
                 info!(
                     "vsock: unexpected event: fd={:?}, evset={:?}",
@@ -505,6 +767,11 @@ impl VsockMuxer {
                 // the next time we need to yield an RX packet.
                 self.rxq.push(MuxerRx::ConnRx(key));
             }
+            if let Some(limiter) = self.port_limiters.get(&conn.service_port()) {
+                limiter.metrics.conns_added.inc();
+                let conns_open = limiter.metrics.conns_open.fetch();
+                limiter.metrics.conns_open.store(conns_open + 1);
+            }
             self.conn_map.insert(key, conn);
             METRICS.conns_added.inc();
         })
@@ -514,6 +781,13 @@ impl VsockMuxer {
     fn remove_connection(&mut self, key: ConnMapKey) {
         if let Some(conn) = self.conn_map.remove(&key) {
             self.remove_listener(conn.as_raw_fd());
+            if let Some(limiter) = self.port_limiters.get(&conn.service_port()) {
+                let conns_open = limiter.metrics.conns_open.fetch();
+                limiter
+                    .metrics
+                    .conns_open
+                    .store(conns_open.saturating_sub(1));
+            }
             METRICS.conns_removed.inc();
         }
         self.free_local_port(key.local_port);
@@ -550,6 +824,8 @@ impl VsockMuxer {
             EpollListener::Connection { evset, .. } => evset,
             EpollListener::LocalStream(_) => EventSet::IN,
             EpollListener::HostSock => EventSet::IN,
+            EpollListener::DgramSock => EventSet::IN,
+            EpollListener::PortLimiter(_) => EventSet::IN,
         };
 
         self.epoll
@@ -606,14 +882,22 @@ impl VsockMuxer {
 
     /// Handle a new connection request comming from our peer (the guest vsock driver).
     ///
-    /// This will attempt to connect to a host-side Unix socket, expected to be listening at
-    /// the file system path corresponing to the destination port. If successful, a new
-    /// connection object will be created and added to the connection pool. On failure, a new
-    /// RST packet will be scheduled for delivery to the guest.
+    /// This will attempt to connect to a host-side Unix socket, of the same type as the
+    /// connection, expected to be listening at the file system path corresponing to the
+    /// destination port, or to the host TCP address to which stream connections to the port are
+    /// forwarded. If successful, a new connection object will be created and added to the
+    /// connection pool. On failure, a new RST packet will be scheduled for delivery to the guest.
     fn handle_peer_request_pkt(&mut self, pkt: &VsockPacketTx) {
         let port_path = format!("{}_{}", self.host_sock_path, pkt.hdr.dst_port());
 
-        UnixStream::connect(port_path)
+        let stream = if pkt.hdr.type_() == uapi::VSOCK_TYPE_SEQPACKET {
+            connect_seqpacket(&port_path)
+        } else if let Some(addr) = self.tcp_forwards.get(&pkt.hdr.dst_port()) {
+            connect_tcp(addr)
+        } else {
+            UnixStream::connect(port_path)
+        };
+        stream
             .and_then(|stream| stream.set_nonblocking(true).map(|_| stream))
             .map_err(VsockUnixBackendError::UnixConnect)
             .and_then(|stream| {
@@ -629,10 +913,120 @@ impl VsockMuxer {
                         pkt.hdr.dst_port(),
                         pkt.hdr.src_port(),
                         pkt.hdr.buf_alloc(),
+                        pkt.hdr.type_(),
                     ),
                 )
             })
-            .unwrap_or_else(|_| self.enq_rst(pkt.hdr.dst_port(), pkt.hdr.src_port()));
+            .unwrap_or_else(|_| {
+                self.enq_rst(pkt.hdr.dst_port(), pkt.hdr.src_port(), pkt.hdr.type_())
+            });
+    }
+
+    /// Forward a datagram of our peer (the guest vsock driver) to the host.
+    ///
+    /// The datagram is sent, prefixed with its ports, to the host-side datagram Unix socket
+    /// expected to be bound at the file system path corresponding to the destination port.
+    /// Datagrams which can't be sent are dropped.
+    fn send_dgram(&mut self, pkt: &VsockPacketTx) {
+        let Some(dgram_sock) = &self.dgram_sock else {
+            return;
+        };
+        if pkt.hdr.op() != uapi::VSOCK_OP_RW {
+            debug!("vsock: dropping invalid datagram pkt: {:?}", pkt.hdr);
+            return;
+        }
+
+        let len = pkt.hdr.len() as usize;
+        let mut dgram = vec![0u8; DGRAM_HDR_SIZE + len];
+        dgram[..4].copy_from_slice(&pkt.hdr.src_port().to_le_bytes());
+        dgram[4..DGRAM_HDR_SIZE].copy_from_slice(&pkt.hdr.dst_port().to_le_bytes());
+        if let Err(err) =
+            pkt.write_from_offset_to(&mut &mut dgram[DGRAM_HDR_SIZE..], 0, pkt.hdr.len())
+        {
+            warn!("vsock: error reading datagram from guest: {:?}", err);
+            METRICS.dgram_drops.inc();
+            return;
+        }
+
+        // Without a host socket bound to the destination port, or with its buffer full, the
+        // datagram is lost, as it would be between two hosts.
+        let port_path = format!("{}_{}", self.host_sock_path, pkt.hdr.dst_port());
+        if let Err(err) = dgram_sock.send_to(&dgram, port_path) {
+            debug!(
+                "vsock: dropping datagram for port {}: {}",
+                pkt.hdr.dst_port(),
+                err
+            );
+            METRICS.dgram_drops.inc();
+            return;
+        }
+        METRICS.tx_packets_count.inc();
+        METRICS.tx_bytes_count.add(len as u64);
+    }
+
+    /// Fill in a vsock packet with a datagram of the host.
+    ///
+    /// The datagrams start with their source and destination ports. Datagrams which are invalid,
+    /// or too long for the RX buffer, are dropped. Once all the datagrams were read, we're
+    /// listening for new ones again.
+    fn recv_dgram(&mut self, pkt: &mut VsockPacketRx) -> Result<(), VsockError> {
+        let Some(dgram_sock) = &self.dgram_sock else {
+            self.dgram_rx = false;
+            return Err(VsockError::NoData);
+        };
+        let dgram_fd = dgram_sock.as_raw_fd();
+        loop {
+            let len = match dgram_sock.recv(&mut self.dgram_buf) {
+                Ok(len) => len,
+                Err(err) => {
+                    if err.kind() != ErrorKind::WouldBlock {
+                        warn!("vsock: error reading from datagram socket: {}", err);
+                        METRICS.rx_read_fails.inc();
+                    }
+                    self.dgram_rx = false;
+                    self.add_listener(dgram_fd, EpollListener::DgramSock)
+                        .unwrap_or_else(|err| {
+                            error!("vsock: error listening on datagram socket: {:?}", err);
+                            METRICS.muxer_event_fails.inc();
+                        });
+                    return Err(VsockError::NoData);
+                }
+            };
+
+            // The datagram buffer is one byte longer than the longest valid datagram, so that
+            // truncated datagrams are detected.
+            let data_len = len.saturating_sub(DGRAM_HDR_SIZE);
+            if len < DGRAM_HDR_SIZE
+                || len == self.dgram_buf.len()
+                || data_len > pkt.buf_size() as usize
+            {
+                debug!("vsock: dropping invalid host datagram of {} bytes", len);
+                METRICS.dgram_drops.inc();
+                continue;
+            }
+
+            let port = |bytes: &[u8]| u32::from_le_bytes(bytes.try_into().unwrap());
+            let src_port = port(&self.dgram_buf[..4]);
+            let dst_port = port(&self.dgram_buf[4..DGRAM_HDR_SIZE]);
+            // Safe to unwrap, since the length is no more than the RX buffer size, a u32.
+            let data_len = u32::try_from(data_len).unwrap();
+            pkt.read_at_offset_from(&mut &self.dgram_buf[DGRAM_HDR_SIZE..len], 0, data_len)?;
+            pkt.hdr
+                .set_op(uapi::VSOCK_OP_RW)
+                .set_src_cid(uapi::VSOCK_HOST_CID)
+                .set_dst_cid(self.cid)
+                .set_src_port(src_port)
+                .set_dst_port(dst_port)
+                .set_len(data_len)
+                .set_type(uapi::VSOCK_TYPE_DGRAM)
+                .set_flags(0)
+                .set_buf_alloc(0)
+                .set_fwd_cnt(0);
+            METRICS.rx_packets_count.inc();
+            METRICS.rx_bytes_count.add(u64::from(data_len));
+            debug!("vsock muxer: RX dgram pkt: {:?}", pkt.hdr);
+            return Ok(());
+        }
     }
 
     /// Perform an action that might mutate a connection's state.
@@ -641,7 +1035,9 @@ impl VsockMuxer {
     /// connection object mutates. E.g.
     /// - update the connection's epoll listener;
     /// - schedule the connection to be queried for RX data;
-    /// - kill the connection if an unrecoverable error occurs.
+    /// - kill the connection if an unrecoverable error occurs;
+    /// - charge the data moved by the connection to the rate limiters of its port, and throttle it
+    ///   accordingly.
     fn apply_conn_mutation<F>(&mut self, key: ConnMapKey, mut_fn: F)
     where
         F: FnOnce(&mut MuxerConnection),
@@ -651,8 +1047,19 @@ impl VsockMuxer {
             let was_expiring = conn.will_expire();
             let prev_state = conn.state();
 
+            let mut limiter = self.port_limiters.get_mut(&conn.service_port());
+            let data_counts = conn.data_counts();
+            if let Some(limiter) = limiter.as_mut() {
+                limiter.throttle(conn);
+            }
+
             mut_fn(conn);
 
+            if let Some(limiter) = limiter {
+                limiter.charge(conn, data_counts);
+                limiter.throttle(conn);
+            }
+
             // If this is a host-initiated connection that has just become established, we'll have
             // to send an ack message to the host end.
             if prev_state == ConnState::LocalInit && conn.state() == ConnState::Established {
@@ -772,10 +1179,11 @@ impl VsockMuxer {
     /// Enqueue errors aren't propagated up the call chain, since there is nothing we can do to
     /// handle them. We do, however, log a warning, since not being able to enqueue an RST
     /// packet means we have to drop it, which is not normal operation.
-    fn enq_rst(&mut self, local_port: u32, peer_port: u32) {
+    fn enq_rst(&mut self, local_port: u32, peer_port: u32, type_: u16) {
         let pushed = self.rxq.push(MuxerRx::RstPkt {
             local_port,
             peer_port,
+            type_,
         });
         if !pushed {
             warn!(
@@ -786,11 +1194,82 @@ impl VsockMuxer {
     }
 }
 
+impl Drop for VsockMuxer {
+    fn drop(&mut self) {
+        // The host datagram socket is created by the muxer, so it goes away with it.
+        if self.dgram_sock.is_some() {
+            if let Err(err) = std::fs::remove_file(dgram_sock_path(&self.host_sock_path)) {
+                warn!("vsock: error removing the datagram socket: {}", err);
+            }
+        }
+    }
+}
+
+/// Size of the ports prefixing the datagrams exchanged with the host.
+const DGRAM_HDR_SIZE: usize = 8;
+
+/// Return the file system path of the host-side datagram Unix socket.
+fn dgram_sock_path(host_sock_path: &str) -> String {
+    format!("{}_dgram", host_sock_path)
+}
+
+/// Build the address of the Unix socket at `path`.
+fn unix_sockaddr(path: &str) -> std::io::Result<libc::sockaddr_un> {
+    let mut addr = libc::sockaddr_un {
+        sun_family: libc::sa_family_t::try_from(libc::AF_UNIX).unwrap(),
+        sun_path: [0; 108],
+    };
+    // Keep the path NUL-terminated.
+    if path.len() >= addr.sun_path.len() {
+        return Err(std::io::Error::from_raw_os_error(libc::ENAMETOOLONG));
+    }
+    for (dst, src) in addr.sun_path.iter_mut().zip(path.as_bytes()) {
+        *dst = libc::c_char::from_ne_bytes([*src]);
+    }
+    Ok(addr)
+}
+
+/// Connect a TCP socket to `addr`. Like seqpacket sockets, the socket is wrapped in a
+/// `UnixStream` to be read and written.
+fn connect_tcp(addr: &SocketAddr) -> std::io::Result<UnixStream> {
+    // The muxer waits for the connection to be established, so the forwarded services are
+    // expected to be quick to reach, e.g. on the host itself.
+    let stream =
+        TcpStream::connect_timeout(addr, Duration::from_millis(defs::TCP_CONNECT_TIMEOUT_MS))?;
+    stream.set_nodelay(true)?;
+    Ok(UnixStream::from(OwnedFd::from(stream)))
+}
+
+/// Connect a seqpacket Unix socket to the listening one at `path`. The socket is wrapped in a
+/// `UnixStream` to be read and written, each call receiving or sending a whole message.
+fn connect_seqpacket(path: &str) -> std::io::Result<UnixStream> {
+    let addr = unix_sockaddr(path)?;
+    // SAFETY: Safe because the arguments are valid and the return value is checked.
+    let fd = unsafe { libc::socket(libc::AF_UNIX, libc::SOCK_SEQPACKET | libc::SOCK_CLOEXEC, 0) };
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    // SAFETY: Safe because the socket was just created and is owned by nothing else.
+    let stream = unsafe { UnixStream::from_raw_fd(fd) };
+    // SAFETY: Safe because `addr` is a valid `sockaddr_un` and the return value is checked.
+    let ret = unsafe {
+        libc::connect(
+            fd,
+            (&addr as *const libc::sockaddr_un).cast(),
+            libc::socklen_t::try_from(std::mem::size_of::<libc::sockaddr_un>()).unwrap(),
+        )
+    };
+    if ret < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(stream)
+}
+
 #[cfg(test)]
 mod tests {
     use std::io::{Read, Write};
     use std::ops::Drop;
-    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::os::unix::net::{UnixDatagram, UnixListener, UnixStream};
     use std::path::{Path, PathBuf};
 
     use vmm_sys_util::tempfile::TempFile;
@@ -991,6 +1470,34 @@ mod tests {
             stream.set_nonblocking(true).unwrap();
             stream
         }
+        fn new_seqpacket(path: String) -> Self {
+            let addr = unix_sockaddr(&path).unwrap();
+            // SAFETY: Safe because the arguments are valid and the return value is checked.
+            let fd = unsafe {
+                libc::socket(libc::AF_UNIX, libc::SOCK_SEQPACKET | libc::SOCK_CLOEXEC, 0)
+            };
+            assert!(fd >= 0);
+            // SAFETY: Safe because the socket was just created and is owned by nothing else.
+            // `UnixListener` accepts connections on seqpacket sockets as on stream ones.
+            let sock = unsafe { UnixListener::from_raw_fd(fd) };
+            // SAFETY: Safe because `addr` is a valid `sockaddr_un` and the return value is
+            // checked.
+            let ret = unsafe {
+                libc::bind(
+                    fd,
+                    (&addr as *const libc::sockaddr_un).cast(),
+                    libc::socklen_t::try_from(std::mem::size_of::<libc::sockaddr_un>()).unwrap(),
+                )
+            };
+            assert_eq!(ret, 0);
+            // SAFETY: Safe because the socket is valid and the return value is checked.
+            assert_eq!(unsafe { libc::listen(fd, 1) }, 0);
+            sock.set_nonblocking(true).unwrap();
+            Self {
+                path: PathBuf::from(path),
+                sock,
+            }
+        }
     }
     impl Drop for LocalListener {
         fn drop(&mut self) {
@@ -1027,15 +1534,15 @@ mod tests {
     fn test_bad_peer_pkt() {
         const LOCAL_PORT: u32 = 1026;
         const PEER_PORT: u32 = 1025;
-        const SOCK_DGRAM: u16 = 2;
+        const BAD_TYPE: u16 = 4;
 
         let mut ctx = MuxerTestContext::new("bad_peer_pkt");
         let tx_pkt = ctx.init_tx_pkt(LOCAL_PORT, PEER_PORT, uapi::VSOCK_OP_REQUEST);
-        tx_pkt.hdr.set_type(SOCK_DGRAM);
+        tx_pkt.hdr.set_type(BAD_TYPE);
         ctx.send();
 
-        // The guest sent a SOCK_DGRAM packet. Per the vsock spec, we need to reply with an RST
-        // packet, since vsock only supports stream sockets.
+        // The guest sent a packet of an unknown type. Per the vsock spec, we need to reply with
+        // an RST packet, since we only support stream, seqpacket and datagram sockets.
         assert!(ctx.muxer.has_pending_rx());
         ctx.recv();
         assert_eq!(ctx.rx_pkt.hdr.op(), uapi::VSOCK_OP_RST);
@@ -1138,6 +1645,206 @@ mod tests {
         assert!(!ctx.muxer.has_pending_rx());
     }
 
+    #[test]
+    fn test_peer_seqpacket_connection() {
+        const LOCAL_PORT: u32 = 1026;
+        const PEER_PORT: u32 = 1025;
+
+        let mut ctx = MuxerTestContext::new("peer_seqpacket_connection");
+
+        // Seqpacket connections are refused unless enabled, even with a host socket listening.
+        {
+            let _listener = LocalListener::new_seqpacket(format!(
+                "{}_{}",
+                ctx.muxer.host_sock_path, LOCAL_PORT
+            ));
+            ctx.init_tx_pkt(LOCAL_PORT, PEER_PORT, uapi::VSOCK_OP_REQUEST)
+                .hdr
+                .set_type(uapi::VSOCK_TYPE_SEQPACKET);
+            ctx.send();
+            ctx.recv();
+            assert_eq!(ctx.rx_pkt.hdr.op(), uapi::VSOCK_OP_RST);
+            assert_eq!(ctx.muxer.socket_features(), 0);
+        }
+        ctx.muxer.enable_seqpacket();
+        assert_eq!(
+            ctx.muxer.socket_features(),
+            1 << uapi::VIRTIO_VSOCK_F_SEQPACKET
+        );
+
+        // Test peer connection refused, with an RST of the type of the request.
+        ctx.init_tx_pkt(LOCAL_PORT, PEER_PORT, uapi::VSOCK_OP_REQUEST)
+            .hdr
+            .set_type(uapi::VSOCK_TYPE_SEQPACKET);
+        ctx.send();
+        ctx.recv();
+        assert_eq!(ctx.rx_pkt.hdr.op(), uapi::VSOCK_OP_RST);
+        assert_eq!(ctx.rx_pkt.hdr.type_(), uapi::VSOCK_TYPE_SEQPACKET);
+
+        // Test peer connection accepted, by a seqpacket socket.
+        let mut listener =
+            LocalListener::new_seqpacket(format!("{}_{}", ctx.muxer.host_sock_path, LOCAL_PORT));
+        ctx.init_tx_pkt(LOCAL_PORT, PEER_PORT, uapi::VSOCK_OP_REQUEST)
+            .hdr
+            .set_type(uapi::VSOCK_TYPE_SEQPACKET);
+        ctx.send();
+        let mut stream = listener.accept();
+        ctx.recv();
+        assert_eq!(ctx.rx_pkt.hdr.op(), uapi::VSOCK_OP_RESPONSE);
+        assert_eq!(ctx.rx_pkt.hdr.type_(), uapi::VSOCK_TYPE_SEQPACKET);
+
+        // Test guest -> host messages, written whole once all their packets were sent.
+        ctx.init_data_tx_pkt(LOCAL_PORT, PEER_PORT, &[1, 2])
+            .hdr
+            .set_type(uapi::VSOCK_TYPE_SEQPACKET);
+        ctx.send();
+        ctx.init_data_tx_pkt(LOCAL_PORT, PEER_PORT, &[3])
+            .hdr
+            .set_type(uapi::VSOCK_TYPE_SEQPACKET)
+            .set_flags(uapi::VSOCK_FLAGS_SEQ_EOM);
+        ctx.send();
+        let mut buf = [0u8; 8];
+        assert_eq!(stream.read(&mut buf).unwrap(), 3);
+        assert_eq!(&buf[..3], &[1, 2, 3]);
+
+        // Test host -> guest messages.
+        let data = [5u8, 6];
+        stream.write_all(&data).unwrap();
+        ctx.notify_muxer();
+        assert!(ctx.muxer.has_pending_rx());
+        ctx.recv();
+        assert_eq!(ctx.rx_pkt.hdr.op(), uapi::VSOCK_OP_RW);
+        assert_eq!(ctx.rx_pkt.hdr.type_(), uapi::VSOCK_TYPE_SEQPACKET);
+        assert_eq!(ctx.rx_pkt.hdr.len(), 2);
+        assert_ne!(ctx.rx_pkt.hdr.flags() & uapi::VSOCK_FLAGS_SEQ_EOM, 0);
+        let buf = test_utils::read_packet_data(&ctx.tx_pkt, 2);
+        assert_eq!(&buf, &data);
+    }
+
+    #[test]
+    fn test_peer_tcp_connection() {
+        const LOCAL_PORT: u32 = 1026;
+        const PEER_PORT: u32 = 1025;
+
+        let mut ctx = MuxerTestContext::new("peer_tcp_connection");
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        ctx.muxer.add_tcp_forward(LOCAL_PORT, addr).unwrap();
+        assert!(matches!(
+            ctx.muxer.add_tcp_forward(LOCAL_PORT, addr),
+            Err(VsockUnixBackendError::DuplicateTcpForward(LOCAL_PORT))
+        ));
+        assert_eq!(ctx.muxer.tcp_forwards().get(&LOCAL_PORT), Some(&addr));
+
+        // Test peer connection accepted, by the TCP listener rather than a Unix socket.
+        ctx.init_tx_pkt(LOCAL_PORT, PEER_PORT, uapi::VSOCK_OP_REQUEST);
+        ctx.send();
+        let (mut stream, _) = listener.accept().unwrap();
+        ctx.recv();
+        assert_eq!(ctx.rx_pkt.hdr.op(), uapi::VSOCK_OP_RESPONSE);
+        assert_eq!(ctx.rx_pkt.hdr.src_port(), LOCAL_PORT);
+        assert_eq!(ctx.rx_pkt.hdr.dst_port(), PEER_PORT);
+
+        // Test guest -> host data flow.
+        let data = [1, 2, 3, 4];
+        ctx.init_data_tx_pkt(LOCAL_PORT, PEER_PORT, &data);
+        ctx.send();
+        let mut buf = vec![0; data.len()];
+        stream.read_exact(buf.as_mut_slice()).unwrap();
+        assert_eq!(buf.as_slice(), data);
+
+        // Test host -> guest data flow.
+        let data = [5u8, 6, 7, 8];
+        stream.write_all(&data).unwrap();
+        ctx.notify_muxer();
+        assert!(ctx.muxer.has_pending_rx());
+        ctx.recv();
+        assert_eq!(ctx.rx_pkt.hdr.op(), uapi::VSOCK_OP_RW);
+        let buf = test_utils::read_packet_data(&ctx.tx_pkt, 4);
+        assert_eq!(&buf, &data);
+
+        // Test peer connection refused, once the TCP listener is gone.
+        drop(listener);
+        ctx.init_tx_pkt(LOCAL_PORT, PEER_PORT + 1, uapi::VSOCK_OP_REQUEST);
+        ctx.send();
+        ctx.recv();
+        assert_eq!(ctx.rx_pkt.hdr.op(), uapi::VSOCK_OP_RST);
+        assert_eq!(ctx.rx_pkt.hdr.dst_port(), PEER_PORT + 1);
+    }
+
+    #[test]
+    fn test_dgram() {
+        const LOCAL_PORT: u32 = 1026;
+        const PEER_PORT: u32 = 1025;
+
+        let mut ctx = MuxerTestContext::new("dgram");
+        let dgram_path = dgram_sock_path(&ctx.muxer.host_sock_path);
+
+        // Without datagram support, no socket is bound and the datagrams of the guest are
+        // rejected.
+        assert!(!Path::new(&dgram_path).exists());
+        assert_eq!(ctx.muxer.socket_features(), 0);
+        ctx.init_data_tx_pkt(LOCAL_PORT, PEER_PORT, &[1, 2, 3])
+            .hdr
+            .set_type(uapi::VSOCK_TYPE_DGRAM);
+        ctx.send();
+        ctx.recv();
+        assert_eq!(ctx.rx_pkt.hdr.op(), uapi::VSOCK_OP_RST);
+
+        ctx.muxer.enable_dgram().unwrap();
+        assert_eq!(ctx.muxer.socket_features(), 1 << uapi::VIRTIO_VSOCK_F_DGRAM);
+        let local_path = format!("{}_{}", ctx.muxer.host_sock_path, LOCAL_PORT);
+        let local_sock = UnixDatagram::bind(&local_path).unwrap();
+
+        // Test guest -> host datagrams, prefixed with their ports.
+        ctx.init_data_tx_pkt(LOCAL_PORT, PEER_PORT, &[1, 2, 3])
+            .hdr
+            .set_type(uapi::VSOCK_TYPE_DGRAM);
+        ctx.send();
+        assert!(!ctx.muxer.has_pending_rx());
+        let mut buf = [0u8; 16];
+        let (len, addr) = local_sock.recv_from(&mut buf).unwrap();
+        let mut expected = PEER_PORT.to_le_bytes().to_vec();
+        expected.extend(LOCAL_PORT.to_le_bytes());
+        expected.extend([1, 2, 3]);
+        assert_eq!(&buf[..len], expected.as_slice());
+        assert_eq!(addr.as_pathname(), Some(Path::new(&dgram_path)));
+
+        // Test host -> guest datagrams. Invalid ones are dropped.
+        let mut dgram = LOCAL_PORT.to_le_bytes().to_vec();
+        dgram.extend(PEER_PORT.to_le_bytes());
+        dgram.extend([5, 6]);
+        local_sock.send_to(&dgram, &dgram_path).unwrap();
+        local_sock.send_to(&[1, 2, 3], &dgram_path).unwrap();
+        ctx.notify_muxer();
+        assert!(ctx.muxer.has_pending_rx());
+        ctx.recv();
+        assert_eq!(ctx.rx_pkt.hdr.op(), uapi::VSOCK_OP_RW);
+        assert_eq!(ctx.rx_pkt.hdr.type_(), uapi::VSOCK_TYPE_DGRAM);
+        assert_eq!(ctx.rx_pkt.hdr.src_cid(), uapi::VSOCK_HOST_CID);
+        assert_eq!(ctx.rx_pkt.hdr.dst_cid(), PEER_CID);
+        assert_eq!(ctx.rx_pkt.hdr.src_port(), LOCAL_PORT);
+        assert_eq!(ctx.rx_pkt.hdr.dst_port(), PEER_PORT);
+        assert_eq!(ctx.rx_pkt.hdr.len(), 2);
+        let buf = test_utils::read_packet_data(&ctx.tx_pkt, 2);
+        assert_eq!(&buf, &[5, 6]);
+
+        // Once the socket is drained, the muxer listens for new datagrams again.
+        assert!(matches!(
+            ctx.muxer.recv_pkt(&mut ctx.rx_pkt),
+            Err(VsockError::NoData)
+        ));
+        assert!(!ctx.muxer.has_pending_rx());
+        assert!(matches!(
+            ctx.muxer
+                .listener_map
+                .get(&ctx.muxer.dgram_sock.as_ref().unwrap().as_raw_fd()),
+            Some(EpollListener::DgramSock)
+        ));
+
+        std::fs::remove_file(local_path).unwrap();
+    }
+
     #[test]
     fn test_local_connection() {
         // Test guest -> host data flow.
@@ -1534,4 +2241,76 @@ mod tests {
         // Check that the connection was removed.
         assert_eq!(METRICS.conns_removed.count(), conns_removed + 1);
     }
+
+    #[test]
+    fn test_port_limiter() {
+        const LOCAL_PORT: u32 = 1030;
+        const PEER_PORT: u32 = 1025;
+
+        let mut ctx = MuxerTestContext::new("port_limiter");
+        // The guest may send 4 bytes every 100ms to the port.
+        let tx_limiter = RateLimiter::new(4, 0, 100, 0, 0, 0).unwrap();
+        ctx.muxer
+            .add_port_limiter(LOCAL_PORT, RateLimiter::default(), tx_limiter)
+            .unwrap();
+        assert!(matches!(
+            ctx.muxer
+                .add_port_limiter(LOCAL_PORT, RateLimiter::default(), RateLimiter::default()),
+            Err(VsockUnixBackendError::DuplicatePortLimiter(LOCAL_PORT))
+        ));
+        let metrics = VsockPortMetrics::alloc(LOCAL_PORT);
+
+        let mut listener = ctx.create_local_listener(LOCAL_PORT);
+        ctx.init_tx_pkt(LOCAL_PORT, PEER_PORT, uapi::VSOCK_OP_REQUEST);
+        ctx.send();
+        let mut stream = listener.accept();
+        ctx.recv();
+        assert_eq!(ctx.rx_pkt.hdr.op(), uapi::VSOCK_OP_RESPONSE);
+        assert_eq!(metrics.conns_open.fetch(), 1);
+        let key = ConnMapKey {
+            local_port: LOCAL_PORT,
+            peer_port: PEER_PORT,
+        };
+
+        // The data sent while the limiter has budget goes through, and blocks the limiter once
+        // the budget is exceeded.
+        let data = [1, 2, 3, 4];
+        for _ in 0..2 {
+            ctx.init_data_tx_pkt(LOCAL_PORT, PEER_PORT, &data);
+            ctx.send();
+        }
+        let mut buf = vec![0; 2 * data.len()];
+        stream.read_exact(buf.as_mut_slice()).unwrap();
+        assert!(ctx.muxer.port_limiters()[&LOCAL_PORT].tx.is_blocked());
+
+        // While the limiter is blocked, the connection is throttled.
+        ctx.init_data_tx_pkt(LOCAL_PORT, PEER_PORT, &data);
+        ctx.send();
+        assert_eq!(
+            stream.read(&mut buf).unwrap_err().kind(),
+            ErrorKind::WouldBlock
+        );
+        assert!(!ctx.muxer.conn_map[&key]
+            .get_polled_evset()
+            .contains(EventSet::OUT));
+
+        // Once the limiter timer fires, the buffered data is flushed.
+        std::thread::sleep(std::time::Duration::from_millis(150));
+        ctx.notify_muxer();
+        assert!(!ctx.muxer.port_limiters()[&LOCAL_PORT].tx.is_blocked());
+        assert!(ctx.muxer.conn_map[&key]
+            .get_polled_evset()
+            .contains(EventSet::OUT));
+        ctx.notify_muxer();
+        stream.read_exact(&mut buf[..data.len()]).unwrap();
+        assert_eq!(&buf[..data.len()], &data);
+        assert_eq!(metrics.tx_bytes_count.count(), 3 * data.len() as u64);
+        assert!(metrics.tx_rate_limiter_throttled.count() > 0);
+
+        // The reset of the connection is accounted to the port.
+        ctx.init_tx_pkt(LOCAL_PORT, PEER_PORT, uapi::VSOCK_OP_RST);
+        ctx.send();
+        assert_eq!(metrics.tx_rst_count.count(), 1);
+        assert_eq!(metrics.conns_open.fetch(), 0);
+    }
 }
@@ -0,0 +1,661 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Diagnostic reports describing what the VMM is doing, written to a file upon receiving a
+//! configurable signal.
+//!
+//! The reports are written by the `fc_diagnostics` thread, so that they are available even if the
+//! event loop of the VMM is wedged. The stack of each thread is unwound by a signal handler, which
+//! the thread writing the report triggers on every thread in turn.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicI32, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+
+use libc::{c_int, c_void, siginfo_t};
+use seccompiler::BpfProgram;
+use utils::time::{get_time_us, ClockType};
+use vmm_sys_util::eventfd::EventFd;
+
+use crate::arch::DeviceType;
+use crate::device_manager::mmio::MMIODeviceInfo;
+use crate::devices::BusDevice;
+use crate::logger::{error, info, METRICS};
+use crate::utils::signal::{register_signal_handler, sigrtmin};
+use crate::Vmm;
+
+/// Signal triggering a diagnostic report, unless configured otherwise.
+pub const DEFAULT_DIAGNOSTIC_SIGNAL: c_int = libc::SIGUSR1;
+
+/// Offset from `SIGRTMIN` of the signal asking a thread to unwind its stack.
+pub const STACK_RTSIG_OFFSET: c_int = 1;
+
+/// Signals which Firecracker already handles or which can not be caught.
+const RESERVED_SIGNALS: [c_int; 11] = [
+    libc::SIGBUS,
+    libc::SIGSEGV,
+    libc::SIGSYS,
+    libc::SIGXFSZ,
+    libc::SIGXCPU,
+    libc::SIGPIPE,
+    libc::SIGHUP,
+    libc::SIGILL,
+    libc::SIGUSR2,
+    libc::SIGKILL,
+    libc::SIGSTOP,
+];
+
+/// Maximum number of frames reported in the stack of a thread.
+const MAX_BACKTRACE_FRAMES: usize = 128;
+
+/// Time given to a thread to unwind its stack, after which it is reported as not collected.
+const STACK_TIMEOUT_MS: c_int = 100;
+
+/// Directory listing the threads of the process.
+const TASKS_PATH: &str = "/proc/self/task";
+
+// `si_code` of the signals queued with a value by a process (`SI_QUEUE`).
+const SI_QUEUE: c_int = -1;
+
+/// Event fd written by the signal handler, or -1 if no dumper was created.
+static DIAGNOSTIC_EVENT_FD: AtomicI32 = AtomicI32::new(-1);
+
+/// Event fd written by the stack signal handler once it recorded the stack of its thread.
+static STACK_EVENT_FD: AtomicI32 = AtomicI32::new(-1);
+/// Number of the stack request the stack signal handler answers, or 0 if none is pending.
+static STACK_REQUEST: AtomicUsize = AtomicUsize::new(0);
+/// Stack recorded by the stack signal handler.
+static STACK_FRAMES: [AtomicUsize; MAX_BACKTRACE_FRAMES] =
+    [const { AtomicUsize::new(0) }; MAX_BACKTRACE_FRAMES];
+static STACK_LEN: AtomicUsize = AtomicUsize::new(0);
+
+/// Errors associated with diagnostic reports.
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum DiagnosticsError {
+    /// Signal {0} can not be used to trigger diagnostic reports.
+    InvalidSignal(c_int),
+    /// Failed to create the diagnostic event fd: {0}
+    EventFd(io::Error),
+    /// Failed to register the diagnostic signal handler: {0}
+    RegisterSignalHandler(vmm_sys_util::errno::Error),
+    /// Failed to spawn the diagnostics thread: {0}
+    Spawn(io::Error),
+}
+
+/// Writes 1 to the event fd stored in `event_fd`, if any.
+fn signal_event_fd(event_fd: &AtomicI32) {
+    let fd = event_fd.load(Ordering::SeqCst);
+    if fd < 0 {
+        return;
+    }
+    let val: u64 = 1;
+    // SAFETY: `write` is async-signal-safe and `val` outlives the call. The fd belongs to an
+    // event fd of the dumper, so a failed write only means the report is incomplete.
+    unsafe {
+        libc::write(
+            fd,
+            std::ptr::addr_of!(val).cast(),
+            std::mem::size_of::<u64>(),
+        )
+    };
+}
+
+/// Signal handler which defers the report to the diagnostics thread, since building it is not
+/// async-signal-safe.
+extern "C" fn diagnostic_signal_handler(_num: c_int, _info: *mut siginfo_t, _unused: *mut c_void) {
+    signal_event_fd(&DIAGNOSTIC_EVENT_FD);
+}
+
+/// Layout of the `siginfo_t` of the signals queued with a value, on 64-bit architectures. The
+/// libc crate does not allow setting the value.
+#[repr(C)]
+// Most fields are only read by the kernel.
+#[allow(dead_code)]
+struct QueuedSigInfo {
+    si_signo: c_int,
+    si_errno: c_int,
+    si_code: c_int,
+    _pad: c_int,
+    si_pid: libc::pid_t,
+    si_uid: libc::uid_t,
+    si_value: usize,
+    _rest: [u8; 96],
+}
+
+const _: () = assert!(std::mem::size_of::<QueuedSigInfo>() == std::mem::size_of::<siginfo_t>());
+
+/// Signal handler recording the stack of the thread it interrupts, for the stack request whose
+/// number is the value of the signal.
+extern "C" fn stack_signal_handler(_num: c_int, info: *mut siginfo_t, _unused: *mut c_void) {
+    // SAFETY: The kernel passes a valid `siginfo_t`, which has the layout of `QueuedSigInfo` for
+    // the signals queued by `queue_stack_signal`. The value of other signals does not match a
+    // pending request.
+    let request = unsafe { (*info.cast::<QueuedSigInfo>()).si_value };
+    // Handlers running after the dumper stopped waiting for them do not answer later requests.
+    if request == 0
+        || STACK_REQUEST
+            .compare_exchange(request, 0, Ordering::SeqCst, Ordering::SeqCst)
+            .is_err()
+    {
+        return;
+    }
+    // The frames are recorded without allocating, as allocating is not async-signal-safe.
+    let mut frames = Frames::new();
+    unwind_stack(&mut frames);
+    for (slot, ip) in STACK_FRAMES.iter().zip(frames.as_slice()) {
+        slot.store(*ip, Ordering::Relaxed);
+    }
+    STACK_LEN.store(frames.len, Ordering::SeqCst);
+    signal_event_fd(&STACK_EVENT_FD);
+}
+
+/// Registers the stack signal handler. The syscalls it interrupts are restarted, as the threads
+/// running them do not expect to be signalled.
+fn register_stack_signal_handler() -> Result<(), vmm_sys_util::errno::Error> {
+    // SAFETY: An all-zero `sigaction` is valid, with an empty signal mask.
+    let mut action: libc::sigaction = unsafe { std::mem::zeroed() };
+    action.sa_sigaction = stack_signal_handler as usize;
+    action.sa_flags = libc::SA_SIGINFO | libc::SA_RESTART;
+    // SAFETY: `action` is valid and outlives the call, and the handler only does
+    // async-signal-safe operations, besides unwinding the stack.
+    if unsafe {
+        libc::sigaction(
+            sigrtmin() + STACK_RTSIG_OFFSET,
+            &action,
+            std::ptr::null_mut(),
+        )
+    } < 0
+    {
+        return Err(vmm_sys_util::errno::Error::last());
+    }
+    Ok(())
+}
+
+/// Queues the stack signal for thread `tid` of process `pid`, with `request` as its value.
+fn queue_stack_signal(pid: libc::pid_t, tid: libc::pid_t, request: usize) -> io::Result<()> {
+    let info = QueuedSigInfo {
+        si_signo: sigrtmin() + STACK_RTSIG_OFFSET,
+        si_errno: 0,
+        si_code: SI_QUEUE,
+        _pad: 0,
+        si_pid: pid,
+        si_uid: 0,
+        si_value: request,
+        _rest: [0; 96],
+    };
+    // SAFETY: `info` is a valid `siginfo_t`, which outlives the call.
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_rt_tgsigqueueinfo,
+            pid,
+            tid,
+            info.si_signo,
+            std::ptr::addr_of!(info),
+        )
+    };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Waits up to `timeout_ms` milliseconds for `event_fd` to be readable, and returns whether it is.
+fn wait_readable(event_fd: &EventFd, timeout_ms: c_int) -> io::Result<bool> {
+    let mut pollfd = libc::pollfd {
+        fd: event_fd.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    loop {
+        // SAFETY: `pollfd` is valid and outlives the call.
+        match unsafe { libc::poll(&mut pollfd, 1, timeout_ms) } {
+            -1 => {
+                let err = io::Error::last_os_error();
+                if err.kind() != io::ErrorKind::Interrupted {
+                    return Err(err);
+                }
+            }
+            ready => return Ok(ready > 0),
+        }
+    }
+}
+
+/// Handle to the thread writing diagnostic reports to a file whenever the configured signal is
+/// received.
+#[derive(Debug)]
+pub struct DiagnosticDumper {
+    vmm: Arc<OnceLock<Arc<Mutex<Vmm>>>>,
+}
+
+impl DiagnosticDumper {
+    /// Spawns the thread writing reports to `dump_path` whenever signal `signum` is received. The
+    /// thread installs `seccomp_filter`.
+    pub fn new(
+        dump_path: PathBuf,
+        signum: c_int,
+        seccomp_filter: Arc<BpfProgram>,
+    ) -> Result<Self, DiagnosticsError> {
+        // Real-time signals are used to kick vcpus and to unwind the stacks of the threads.
+        if signum < 1 || signum >= sigrtmin() || RESERVED_SIGNALS.contains(&signum) {
+            return Err(DiagnosticsError::InvalidSignal(signum));
+        }
+        // The diagnostics thread blocks on the event fd until a report is requested.
+        let event_fd = EventFd::new(0).map_err(DiagnosticsError::EventFd)?;
+        let stack_event_fd = EventFd::new(libc::EFD_NONBLOCK).map_err(DiagnosticsError::EventFd)?;
+        DIAGNOSTIC_EVENT_FD.store(event_fd.as_raw_fd(), Ordering::SeqCst);
+        STACK_EVENT_FD.store(stack_event_fd.as_raw_fd(), Ordering::SeqCst);
+        register_signal_handler(signum, diagnostic_signal_handler)
+            .map_err(DiagnosticsError::RegisterSignalHandler)?;
+        register_stack_signal_handler().map_err(DiagnosticsError::RegisterSignalHandler)?;
+
+        let vmm = Arc::new(OnceLock::new());
+        let mut reporter = Reporter {
+            dump_path,
+            tasks_path: PathBuf::from(TASKS_PATH),
+            stack_event_fd,
+            vmm: vmm.clone(),
+            pid: libc::pid_t::try_from(std::process::id()).unwrap(),
+            last_request: 0,
+        };
+        thread::Builder::new()
+            .name("fc_diagnostics".to_string())
+            .spawn(move || {
+                // Execution panics if filters cannot be loaded, use --no-seccomp if skipping
+                // filters altogether is the desired behaviour.
+                if let Err(err) = seccompiler::apply_filter(&seccomp_filter) {
+                    panic!(
+                        "Failed to set the requested seccomp filters on the diagnostics thread: \
+                         Error: {err}"
+                    );
+                }
+                loop {
+                    match event_fd.read() {
+                        Ok(_) => match reporter.dump() {
+                            Ok(()) => info!(
+                                "Diagnostic report written to {}",
+                                reporter.dump_path.display()
+                            ),
+                            Err(err) => error!("Failed to write diagnostic report: {}", err),
+                        },
+                        // The diagnostic signal may be handled by this thread.
+                        Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
+                        Err(err) => {
+                            error!("Failed to wait for diagnostic requests: {}", err);
+                            return;
+                        }
+                    }
+                }
+            })
+            .map_err(DiagnosticsError::Spawn)?;
+
+        Ok(DiagnosticDumper { vmm })
+    }
+
+    /// Sets the microVM whose state is included in the reports.
+    pub fn set_vmm(&self, vmm: Arc<Mutex<Vmm>>) {
+        // There is only one microVM per process.
+        let _ = self.vmm.set(vmm);
+    }
+}
+
+/// Writes the reports, on the diagnostics thread.
+#[derive(Debug)]
+struct Reporter {
+    dump_path: PathBuf,
+    tasks_path: PathBuf,
+    stack_event_fd: EventFd,
+    vmm: Arc<OnceLock<Arc<Mutex<Vmm>>>>,
+    pid: libc::pid_t,
+    last_request: usize,
+}
+
+impl Reporter {
+    /// Writes a diagnostic report to the configured path, replacing any previous one.
+    fn dump(&mut self) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(&self.dump_path)?);
+        self.write_report(&mut out)?;
+        out.flush()
+    }
+
+    fn write_report<W: Write>(&mut self, out: &mut W) -> io::Result<()> {
+        writeln!(out, "Firecracker diagnostic report")?;
+        writeln!(out, "timestamp_us: {}", get_time_us(ClockType::Real))?;
+
+        writeln!(out, "\n[vmm]")?;
+        match self.vmm.get() {
+            // The VMM thread holds the lock while handling events, and may be stuck doing so.
+            Some(vmm) => match vmm.try_lock() {
+                Ok(vmm) => write_vmm_state(out, &vmm)?,
+                Err(_) => writeln!(out, "unavailable")?,
+            },
+            None => writeln!(out, "not built")?,
+        }
+
+        self.write_threads(out)?;
+
+        writeln!(out, "\n[metrics]")?;
+        // Metrics are flushed to the metrics file as well, so that no increments are lost.
+        match METRICS.write_snapshot() {
+            Ok((_, metrics)) => writeln!(out, "{metrics}"),
+            Err(err) => writeln!(out, "unavailable: {err}"),
+        }
+    }
+
+    fn write_threads<W: Write>(&mut self, out: &mut W) -> io::Result<()> {
+        writeln!(out, "\n[threads]")?;
+        // `/proc` is usually not mounted inside the jail.
+        let tasks = match std::fs::read_dir(&self.tasks_path) {
+            Ok(tasks) => tasks,
+            Err(err) => return writeln!(out, "unavailable: {err}"),
+        };
+        for task in tasks.flatten() {
+            let path = task.path();
+            let tid = task.file_name().to_string_lossy().into_owned();
+            let name = std::fs::read_to_string(path.join("comm")).unwrap_or_default();
+            let stat = std::fs::read_to_string(path.join("stat")).unwrap_or_default();
+            // The state follows the thread name, which is parenthesized and may contain spaces.
+            let state = stat
+                .rsplit_once(") ")
+                .and_then(|(_, rest)| rest.split(' ').next())
+                .unwrap_or("?");
+            writeln!(out, "{} {}: state {}", tid, name.trim(), state)?;
+
+            let Ok(tid) = tid.parse() else {
+                continue;
+            };
+            match self.thread_stack(tid) {
+                Ok(frames) => write_stack(out, &frames)?,
+                Err(err) => writeln!(out, "  stack not collected: {err}")?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Unwinds the stack of thread `tid`, by signalling it and waiting for its signal handler to
+    /// record the stack. Threads which do not run the handler in time, e.g. because they are
+    /// blocked in the kernel, are given up on.
+    fn thread_stack(&mut self, tid: libc::pid_t) -> io::Result<Vec<usize>> {
+        // 0 means that no request is pending.
+        self.last_request = self.last_request.wrapping_add(1).max(1);
+        let request = self.last_request;
+        STACK_REQUEST.store(request, Ordering::SeqCst);
+        if let Err(err) = queue_stack_signal(self.pid, tid, request) {
+            STACK_REQUEST.store(0, Ordering::SeqCst);
+            return Err(err);
+        }
+        // The handler claims the request before unwinding the stack, so a request which can not
+        // be withdrawn is about to be answered.
+        if !wait_readable(&self.stack_event_fd, STACK_TIMEOUT_MS)?
+            && STACK_REQUEST
+                .compare_exchange(request, 0, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+        {
+            return Err(io::Error::from(io::ErrorKind::TimedOut));
+        }
+        wait_readable(&self.stack_event_fd, -1)?;
+        self.stack_event_fd.read()?;
+        let len = STACK_LEN.load(Ordering::SeqCst);
+        Ok(STACK_FRAMES[..len]
+            .iter()
+            .map(|ip| ip.load(Ordering::Relaxed))
+            .collect())
+    }
+}
+
+fn write_vmm_state<W: Write>(out: &mut W, vmm: &Vmm) -> io::Result<()> {
+    writeln!(out, "id: {}", vmm.instance_info.id)?;
+    writeln!(out, "state: {}", vmm.instance_info.state)?;
+    writeln!(out, "vmm_version: {}", vmm.instance_info.vmm_version)?;
+    writeln!(out, "vcpus: {}", vmm.vcpus_handles.len())?;
+
+    writeln!(out, "\n[devices]")?;
+    vmm.mmio_device_manager
+        .for_each_device(|device_type, device_id, info, bus_device| {
+            write_device(out, device_type, device_id, info, bus_device)
+        })
+}
+
+fn write_device<W: Write>(
+    out: &mut W,
+    device_type: &DeviceType,
+    device_id: &str,
+    info: &MMIODeviceInfo,
+    bus_device: &Mutex<BusDevice>,
+) -> io::Result<()> {
+    write!(
+        out,
+        "{:?} {}: addr {:#x}, irqs {:?}",
+        device_type, device_id, info.addr, info.irqs
+    )?;
+    // Devices are locked by vcpus while handling MMIO exits, which may be stuck.
+    let Ok(bus_device) = bus_device.try_lock() else {
+        return writeln!(out, ", busy");
+    };
+    let Some(transport) = bus_device.mmio_transport_ref() else {
+        return writeln!(out);
+    };
+    let device = transport.device();
+    let Ok(device) = device.try_lock() else {
+        return writeln!(out, ", busy");
+    };
+    writeln!(
+        out,
+        ", activated: {}, acked features: {:#x}, interrupt status: {:#x}",
+        device.is_activated(),
+        device.acked_features(),
+        device.interrupt_status().load(Ordering::SeqCst)
+    )?;
+    for (index, queue) in device.queues().iter().enumerate() {
+        write!(
+            out,
+            "  queue {}: size {}/{}, ready: {}, next_avail: {}, next_used: {}",
+            index, queue.size, queue.max_size, queue.ready, queue.next_avail, queue.next_used
+        )?;
+        // The avail ring is only accessible once the device is activated.
+        if !queue.avail_ring_ptr.is_null() {
+            write!(out, ", pending: {}", queue.len())?;
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+/// Writes the return addresses of a stack, the first ones being the frames of the stack signal
+/// handler.
+///
+/// Symbolizing them would require reading the binary, which the seccomp filters do not allow, so
+/// the addresses are written relative to the load address of the binary. They can be resolved
+/// offline with `addr2line -f -C -e firecracker <addresses>`.
+fn write_stack<W: Write>(out: &mut W, frames: &[usize]) -> io::Result<()> {
+    let base = executable_load_address();
+    for ip in frames {
+        writeln!(out, "  {:#x}", ip.wrapping_sub(base))?;
+    }
+    Ok(())
+}
+
+// `_URC_NO_REASON` and `_URC_NORMAL_STOP` from the unwinder ABI.
+const URC_NO_REASON: c_int = 0;
+const URC_NORMAL_STOP: c_int = 4;
+
+extern "C" {
+    fn _Unwind_Backtrace(
+        trace: extern "C" fn(*mut c_void, *mut c_void) -> c_int,
+        arg: *mut c_void,
+    ) -> c_int;
+    fn _Unwind_GetIP(ctx: *mut c_void) -> usize;
+}
+
+/// Return addresses of a stack, stored without allocating.
+struct Frames {
+    ips: [usize; MAX_BACKTRACE_FRAMES],
+    len: usize,
+}
+
+impl Frames {
+    fn new() -> Self {
+        Frames {
+            ips: [0; MAX_BACKTRACE_FRAMES],
+            len: 0,
+        }
+    }
+
+    fn as_slice(&self) -> &[usize] {
+        &self.ips[..self.len]
+    }
+}
+
+extern "C" fn unwind_trace(ctx: *mut c_void, arg: *mut c_void) -> c_int {
+    // SAFETY: `arg` is the `Frames` passed to `_Unwind_Backtrace` by `unwind_stack`.
+    let frames = unsafe { &mut *arg.cast::<Frames>() };
+    // SAFETY: `ctx` is the unwind context of the current frame, provided by the unwinder.
+    let ip = unsafe { _Unwind_GetIP(ctx) };
+    if ip == 0 || frames.len >= MAX_BACKTRACE_FRAMES {
+        return URC_NORMAL_STOP;
+    }
+    frames.ips[frames.len] = ip;
+    frames.len += 1;
+    URC_NO_REASON
+}
+
+/// Records the return addresses of the stack of the calling thread in `frames`.
+fn unwind_stack(frames: &mut Frames) {
+    // SAFETY: `unwind_trace` only accesses `frames`, which outlives the call.
+    unsafe { _Unwind_Backtrace(unwind_trace, std::ptr::from_mut(frames).cast::<c_void>()) };
+}
+
+extern "C" fn first_object_address(
+    info: *mut libc::dl_phdr_info,
+    _size: usize,
+    data: *mut c_void,
+) -> c_int {
+    // SAFETY: `info` is provided by `dl_iterate_phdr` and `data` is the `usize` passed to it by
+    // `executable_load_address`.
+    unsafe { *data.cast::<usize>() = usize::try_from((*info).dlpi_addr).unwrap_or(0) };
+    // The executable is always the first object, so stop iterating.
+    1
+}
+
+fn executable_load_address() -> usize {
+    let mut address: usize = 0;
+    // SAFETY: `first_object_address` only writes to `address`, which outlives the call.
+    unsafe {
+        libc::dl_iterate_phdr(
+            Some(first_object_address),
+            std::ptr::addr_of_mut!(address).cast::<c_void>(),
+        )
+    };
+    address
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::channel;
+
+    use vmm_sys_util::tempdir::TempDir;
+    use vmm_sys_util::tempfile::TempFile;
+
+    use super::*;
+    use crate::builder::tests::default_vmm;
+
+    fn gettid() -> libc::pid_t {
+        // SAFETY: `gettid` has no invariants.
+        libc::pid_t::try_from(unsafe { libc::syscall(libc::SYS_gettid) }).unwrap()
+    }
+
+    #[test]
+    fn test_invalid_signal() {
+        for signum in [0, libc::SIGSEGV, libc::SIGUSR2, libc::SIGKILL, sigrtmin()] {
+            assert!(matches!(
+                DiagnosticDumper::new(PathBuf::from("/tmp/report"), signum, Arc::new(vec![])),
+                Err(DiagnosticsError::InvalidSignal(num)) if num == signum
+            ));
+        }
+    }
+
+    #[test]
+    fn test_dump() {
+        let file = TempFile::new().unwrap();
+        // Only the threads of this test are listed, the other tests don't expect to be signalled.
+        let tasks = TempDir::new().unwrap();
+        let tid = gettid();
+        std::os::unix::fs::symlink(
+            format!("{TASKS_PATH}/{tid}"),
+            tasks.as_path().join(tid.to_string()),
+        )
+        .unwrap();
+
+        let stack_event_fd = EventFd::new(libc::EFD_NONBLOCK).unwrap();
+        STACK_EVENT_FD.store(stack_event_fd.as_raw_fd(), Ordering::SeqCst);
+        register_stack_signal_handler().unwrap();
+        let vmm = Arc::new(OnceLock::new());
+        let mut reporter = Reporter {
+            dump_path: file.as_path().to_path_buf(),
+            tasks_path: tasks.as_path().to_path_buf(),
+            stack_event_fd,
+            vmm: vmm.clone(),
+            pid: libc::pid_t::try_from(std::process::id()).unwrap(),
+            last_request: 0,
+        };
+
+        reporter.dump().unwrap();
+        let report = std::fs::read_to_string(file.as_path()).unwrap();
+        assert!(report.contains("[vmm]\nnot built\n"));
+        assert!(report.contains("[metrics]\n{"));
+        // The stack of the thread is unwound, with at least the frames of this test.
+        let mut threads = report.split("[threads]\n").nth(1).unwrap().lines();
+        assert!(threads.next().unwrap().starts_with(&format!("{tid} ")));
+        let frames = threads.take_while(|line| line.starts_with("  0x")).count();
+        assert!(frames > 0);
+
+        DiagnosticDumper { vmm }.set_vmm(Arc::new(Mutex::new(default_vmm())));
+        reporter.dump().unwrap();
+        let report = std::fs::read_to_string(file.as_path()).unwrap();
+        assert!(report.contains("state: Not started\n"));
+        assert!(report.contains("[devices]"));
+
+        // The stacks of other threads are unwound by signalling them.
+        let (tid_sender, tid_receiver) = channel();
+        let (stop_sender, stop_receiver) = channel::<()>();
+        let thread = thread::spawn(move || {
+            tid_sender.send(gettid()).unwrap();
+            let _ = stop_receiver.recv();
+        });
+        let other_tid = tid_receiver.recv().unwrap();
+        assert!(!reporter.thread_stack(other_tid).unwrap().is_empty());
+
+        // Threads which don't run the signal handler are given up on.
+        let (tid_sender, tid_receiver) = channel();
+        let (unblock_sender, unblock_receiver) = channel::<()>();
+        let blocked = thread::spawn(move || {
+            // SAFETY: The set is initialized by `sigemptyset` before being used.
+            unsafe {
+                let mut set = std::mem::zeroed();
+                libc::sigemptyset(&mut set);
+                libc::sigaddset(&mut set, sigrtmin() + STACK_RTSIG_OFFSET);
+                libc::pthread_sigmask(libc::SIG_BLOCK, &set, std::ptr::null_mut());
+                tid_sender.send(gettid()).unwrap();
+                let _ = unblock_receiver.recv();
+                libc::pthread_sigmask(libc::SIG_UNBLOCK, &set, std::ptr::null_mut());
+            }
+        });
+        let blocked_tid = tid_receiver.recv().unwrap();
+        assert_eq!(
+            reporter.thread_stack(blocked_tid).unwrap_err().kind(),
+            io::ErrorKind::TimedOut
+        );
+        // Once the handler runs late, it does not answer the next request.
+        unblock_sender.send(()).unwrap();
+        blocked.join().unwrap();
+        assert_eq!(STACK_REQUEST.load(Ordering::SeqCst), 0);
+        assert!(!reporter.thread_stack(other_tid).unwrap().is_empty());
+        assert!(!wait_readable(&reporter.stack_event_fd, 0).unwrap());
+
+        stop_sender.send(()).unwrap();
+        thread.join().unwrap();
+    }
+}
@@ -37,8 +37,22 @@ const MAX_HEADER_LEN: u8 = 60;
 const OPTION_KIND_EOL: u8 = 0x00;
 const OPTION_KIND_NOP: u8 = 0x01;
 const OPTION_KIND_MSS: u8 = 0x02;
+const OPTION_KIND_WINDOW_SCALE: u8 = 0x03;
+const OPTION_KIND_SACK_PERMITTED: u8 = 0x04;
+const OPTION_KIND_SACK: u8 = 0x05;
 
 const OPTION_LEN_MSS: u8 = 0x04;
+const OPTION_LEN_WINDOW_SCALE: u8 = 0x03;
+const OPTION_LEN_SACK_PERMITTED: u8 = 0x02;
+// The length of the SACK option without its blocks.
+const OPTION_LEN_SACK_BASE: u8 = 0x02;
+const OPTION_LEN_SACK_BLOCK: u8 = 0x08;
+
+/// The largest shift count allowed for the window scale option (RFC 7323).
+pub const MAX_WINDOW_SCALE: u8 = 14;
+
+/// The largest number of blocks carried by a SACK option (RFC 2018).
+pub const MAX_SACK_BLOCKS: usize = 4;
 
 // An arbitrarily chosen value, used for sanity checks.
 const MSS_MIN: u16 = 100;
@@ -85,10 +99,67 @@ pub enum TcpError {
     MssOption,
     /// The remaining segment length cannot accommodate the MSS option.
     MssRemaining,
+    /// A TCP option has an invalid length.
+    OptionLen,
+    /// The TCP options do not fit in the header.
+    OptionsTooLong,
     /// The specified slice is shorter than the header length.
     SliceTooShort,
 }
 
+/// The TCP header options supported when parsing and writing segments.
+///
+/// Segments are written with every option aligned on a 4 byte boundary, using `NOP` options as
+/// padding.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TcpOptions {
+    /// The value of the `MSS` option.
+    pub mss: Option<NonZeroU16>,
+    /// The shift count of the `window scale` option, capped to [`MAX_WINDOW_SCALE`].
+    pub window_scale: Option<u8>,
+    /// Whether the `SACK permitted` option is present.
+    pub sack_permitted: bool,
+    // The (left edge, right edge) pairs of the `SACK` option. Only the first sack_blocks_len
+    // entries are valid.
+    sack_blocks: [(u32, u32); MAX_SACK_BLOCKS],
+    sack_blocks_len: usize,
+}
+
+impl TcpOptions {
+    /// Returns the blocks of the `SACK` option, as (left edge, right edge) pairs.
+    #[inline]
+    pub fn sack_blocks(&self) -> &[(u32, u32)] {
+        &self.sack_blocks[..self.sack_blocks_len]
+    }
+
+    /// Sets the blocks of the `SACK` option, keeping at most [`MAX_SACK_BLOCKS`] of them. No
+    /// `SACK` option is written if `blocks` is empty.
+    pub fn set_sack_blocks(&mut self, blocks: &[(u32, u32)]) -> &mut Self {
+        self.sack_blocks_len = min(blocks.len(), MAX_SACK_BLOCKS);
+        self.sack_blocks[..self.sack_blocks_len].copy_from_slice(&blocks[..self.sack_blocks_len]);
+        self
+    }
+
+    // Returns the number of bytes taken up by the options when written to a segment.
+    fn written_len(&self) -> usize {
+        let mut len = 0;
+        if self.mss.is_some() {
+            len += usize::from(OPTION_LEN_MSS);
+        }
+        // The window scale and SACK permitted options are padded to 4 bytes.
+        if self.window_scale.is_some() {
+            len += 4;
+        }
+        if self.sack_permitted {
+            len += 4;
+        }
+        if self.sack_blocks_len > 0 {
+            len += 4 + self.sack_blocks_len * usize::from(OPTION_LEN_SACK_BLOCK);
+        }
+        len
+    }
+}
+
 // TODO: The implementation of TcpSegment is IPv4 specific in regard to checksum computation. Maybe
 // make it more generic at some point.
 
@@ -217,7 +288,7 @@ impl<T: NetworkBytes + Debug> TcpSegment<'_, T> {
         crate::dumbo::pdu::compute_checksum(&self.bytes, src_addr, dst_addr, ChecksumProto::Tcp)
     }
 
-    /// Parses TCP header options (only `MSS` is supported for now).
+    /// Parses the `MSS` TCP header option.
     ///
     /// If no error is encountered, returns the `MSS` value, or `None` if the option is not
     /// present.
@@ -229,41 +300,89 @@ impl<T: NetworkBytes + Debug> TcpSegment<'_, T> {
         &self,
         header_len: usize,
     ) -> Result<Option<NonZeroU16>, TcpError> {
+        self.parse_options_unchecked(header_len)
+            .map(|options| options.mss)
+    }
+
+    /// Parses the TCP header options (`MSS`, `window scale`, `SACK permitted` and `SACK` are
+    /// supported, the others are skipped).
+    ///
+    /// # Panics
+    ///
+    /// This method may panic if the value of `header_len` is invalid.
+    pub fn parse_options_unchecked(&self, header_len: usize) -> Result<TcpOptions, TcpError> {
         let b = self.options_unchecked(header_len);
+        let mut options = TcpOptions::default();
         let mut i = 0;
 
         // All TCP options (except EOL and NOP) are encoded using x bytes (x >= 2), where the first
         // byte represents the option kind, the second is the option length (including these first
-        // two bytes), and finally the next x - 2 bytes represent option data. The length of
-        // the MSS option is 4, so the option data encodes an u16 in network order.
-
-        // The MSS option is 4 bytes wide, so we need at least 4 more bytes to look for it.
-        while i + 3 < b.len() {
-            match b[i] {
+        // two bytes), and finally the next x - 2 bytes represent option data.
+        while i < b.len() {
+            let kind = b[i];
+            match kind {
                 OPTION_KIND_EOL => break,
                 OPTION_KIND_NOP => {
                     i += 1;
                     continue;
                 }
+                _ => (),
+            }
+
+            let len = match b.get(i + 1) {
+                Some(&len) if len >= 2 && i + usize::from(len) <= b.len() => len,
+                _ => return Err(TcpError::OptionLen),
+            };
+
+            match kind {
                 OPTION_KIND_MSS => {
-                    // Read from option data (we skip checking if the len is valid).
                     // TODO: To be super strict, we should make sure there aren't additional MSS
                     // options present (which would be super wrong). Should we be super strict?
+                    if len != OPTION_LEN_MSS {
+                        return Err(TcpError::OptionLen);
+                    }
                     let mss = b.ntohs_unchecked(i + 2);
                     if mss < MSS_MIN {
                         return Err(TcpError::MssOption);
                     }
-                    // The unwarp() is safe because mms >= MSS_MIN at this point.
-                    return Ok(Some(NonZeroU16::new(mss).unwrap()));
+                    // The unwrap() is safe because mss >= MSS_MIN at this point.
+                    options.mss = Some(NonZeroU16::new(mss).unwrap());
                 }
-                _ => {
-                    // Some other option; just skip opt_len bytes in total.
-                    i += b[i + 1] as usize;
-                    continue;
+                OPTION_KIND_WINDOW_SCALE => {
+                    if len != OPTION_LEN_WINDOW_SCALE {
+                        return Err(TcpError::OptionLen);
+                    }
+                    // Larger shift counts have to be treated as the largest one (RFC 7323).
+                    options.window_scale = Some(min(b[i + 2], MAX_WINDOW_SCALE));
                 }
+                OPTION_KIND_SACK_PERMITTED => {
+                    if len != OPTION_LEN_SACK_PERMITTED {
+                        return Err(TcpError::OptionLen);
+                    }
+                    options.sack_permitted = true;
+                }
+                OPTION_KIND_SACK => {
+                    let blocks_len = len - OPTION_LEN_SACK_BASE;
+                    if blocks_len == 0 || blocks_len % OPTION_LEN_SACK_BLOCK != 0 {
+                        return Err(TcpError::OptionLen);
+                    }
+                    let count = min(
+                        usize::from(blocks_len / OPTION_LEN_SACK_BLOCK),
+                        MAX_SACK_BLOCKS,
+                    );
+                    for block in 0..count {
+                        let offset = i + 2 + block * usize::from(OPTION_LEN_SACK_BLOCK);
+                        options.sack_blocks[block] =
+                            (b.ntohl_unchecked(offset), b.ntohl_unchecked(offset + 4));
+                    }
+                    options.sack_blocks_len = count;
+                }
+                // Some other option; just skip it.
+                _ => (),
             }
+            i += usize::from(len);
         }
-        Ok(None)
+        Ok(options)
     }
 
     /// Interprets `bytes` as a TCP segment without any validity checks.
@@ -453,8 +572,7 @@ impl<T: NetworkBytesMut + Debug> TcpSegment<'_, T> {
     /// and `checksum` fields.
     ///
     /// This method writes the rest of the segment, including data (when available). Only the `MSS`
-    /// option is supported for now. The `NS` flag, `URG` flag, and `urgent pointer` field are set
-    /// to 0.
+    /// option is written. The `NS` flag, `URG` flag, and `urgent pointer` field are set to 0.
     ///
     /// # Arguments
     ///
@@ -484,22 +602,59 @@ impl<T: NetworkBytesMut + Debug> TcpSegment<'_, T> {
         mss_remaining: u16,
         payload: Option<(&R, usize)>,
     ) -> Result<Incomplete<Self>, TcpError> {
-        let mut mss_left = mss_remaining;
-
-        // We're going to need at least this many bytes.
-        let mut segment_len = u16::from(OPTIONS_OFFSET);
+        let options = TcpOptions {
+            mss: mss_option.and_then(NonZeroU16::new),
+            ..Default::default()
+        };
+        Self::write_incomplete_segment_with_options(
+            buf,
+            seq_number,
+            ack_number,
+            flags_after_ns,
+            window_size,
+            &options,
+            mss_remaining,
+            payload.map(|(payload_buf, max_payload_bytes)| (payload_buf, 0, max_payload_bytes)),
+        )
+    }
 
+    /// Writes an incomplete TCP segment like [`write_incomplete_segment`], with the specified
+    /// TCP options.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - The TCP options written to the header.
+    /// * `payload` - May contain a buffer which holds payload data, the offset of the first byte we
+    ///   should read from that buffer, and the maximum amount of bytes we should read. When `None`,
+    ///   the TCP segment will carry no payload.
+    ///
+    /// The other arguments are the same as the ones of [`write_incomplete_segment`].
+    ///
+    /// [`write_incomplete_segment`]: #method.write_incomplete_segment
+    #[allow(clippy::too_many_arguments)]
+    #[inline]
+    pub fn write_incomplete_segment_with_options<R: ByteBuffer + ?Sized + Debug>(
+        buf: T,
+        seq_number: u32,
+        ack_number: u32,
+        flags_after_ns: Flags,
+        window_size: u16,
+        options: &TcpOptions,
+        mss_remaining: u16,
+        payload: Option<(&R, usize, usize)>,
+    ) -> Result<Incomplete<Self>, TcpError> {
         // The TCP options will require this much more bytes.
-        let options_len = if mss_option.is_some() {
-            mss_left = mss_left
-                .checked_sub(OPTION_LEN_MSS.into())
-                .ok_or(TcpError::MssRemaining)?;
-            OPTION_LEN_MSS
-        } else {
-            0
-        };
+        let options_len = u8::try_from(options.written_len())
+            .ok()
+            .filter(|len| *len <= MAX_HEADER_LEN - OPTIONS_OFFSET)
+            .ok_or(TcpError::OptionsTooLong)?;
+
+        let mss_left = mss_remaining
+            .checked_sub(options_len.into())
+            .ok_or(TcpError::MssRemaining)?;
 
-        segment_len += u16::from(options_len);
+        // We're going to need at least this many bytes.
+        let mut segment_len = u16::from(OPTIONS_OFFSET) + u16::from(options_len);
 
         if buf.len() < usize::from(segment_len) {
             return Err(TcpError::SliceTooShort);
@@ -516,41 +671,38 @@ impl<T: NetworkBytesMut + Debug> TcpSegment<'_, T> {
             .set_window_size(window_size)
             .set_urgent_pointer(0);
 
-        // Let's write the MSS option if we have to.
-        if let Some(value) = mss_option {
-            segment.bytes[usize::from(OPTIONS_OFFSET)] = OPTION_KIND_MSS;
-            segment.bytes[usize::from(OPTIONS_OFFSET) + 1] = OPTION_LEN_MSS;
-            segment
-                .bytes
-                .htons_unchecked(usize::from(OPTIONS_OFFSET) + 2, value);
-        }
+        segment.write_options_unchecked(options);
 
-        let payload_bytes_count = if let Some((payload_buf, max_payload_bytes)) = payload {
-            let left_to_read = min(payload_buf.len(), max_payload_bytes);
+        let payload_bytes_count =
+            if let Some((payload_buf, payload_offset, max_payload_bytes)) = payload {
+                let left_to_read = min(
+                    payload_buf.len().saturating_sub(payload_offset),
+                    max_payload_bytes,
+                );
 
-            // The subtraction makes sense because we previously checked that
-            // buf.len() >= segment_len.
-            let mut room_for_payload = min(segment.len() - segment_len, mss_left);
-            // The unwrap is safe because room_for_payload is a u16.
-            room_for_payload =
-                u16::try_from(min(usize::from(room_for_payload), left_to_read)).unwrap();
+                // The subtraction makes sense because we previously checked that
+                // buf.len() >= segment_len.
+                let mut room_for_payload = min(segment.len() - segment_len, mss_left);
+                // The unwrap is safe because room_for_payload is a u16.
+                room_for_payload =
+                    u16::try_from(min(usize::from(room_for_payload), left_to_read)).unwrap();
 
-            if room_for_payload == 0 {
-                return Err(TcpError::EmptyPayload);
-            }
+                if room_for_payload == 0 {
+                    return Err(TcpError::EmptyPayload);
+                }
 
-            // Copy `room_for_payload` bytes into `payload_buf` using `offset=0`.
-            // Guaranteed not to panic since we checked above that:
-            // `offset + room_for_payload <= payload_buf.len()`.
-            payload_buf.read_to_slice(
-                0,
-                &mut segment.bytes
-                    [usize::from(segment_len)..usize::from(segment_len + room_for_payload)],
-            );
-            room_for_payload
-        } else {
-            0
-        };
+                // Copy `room_for_payload` bytes from `payload_buf` starting at `payload_offset`.
+                // Guaranteed not to panic since we checked above that:
+                // `payload_offset + room_for_payload <= payload_buf.len()`.
+                payload_buf.read_to_slice(
+                    payload_offset,
+                    &mut segment.bytes
+                        [usize::from(segment_len)..usize::from(segment_len + room_for_payload)],
+                );
+                room_for_payload
+            } else {
+                0
+            };
         segment_len += payload_bytes_count;
 
         // This is ok because segment_len <= buf.len().
@@ -559,6 +711,51 @@ impl<T: NetworkBytesMut + Debug> TcpSegment<'_, T> {
         // Shrink the resulting segment to a slice of exact size, so using self.len() makes sense.
         Ok(Incomplete::new(segment))
     }
+
+    // Writes the options right after the fixed part of the header. The caller must make sure
+    // there's enough room for them.
+    fn write_options_unchecked(&mut self, options: &TcpOptions) {
+        let mut i = usize::from(OPTIONS_OFFSET);
+
+        if let Some(value) = options.mss {
+            self.bytes[i] = OPTION_KIND_MSS;
+            self.bytes[i + 1] = OPTION_LEN_MSS;
+            self.bytes.htons_unchecked(i + 2, value.get());
+            i += usize::from(OPTION_LEN_MSS);
+        }
+
+        if let Some(shift) = options.window_scale {
+            self.bytes[i] = OPTION_KIND_NOP;
+            self.bytes[i + 1] = OPTION_KIND_WINDOW_SCALE;
+            self.bytes[i + 2] = OPTION_LEN_WINDOW_SCALE;
+            self.bytes[i + 3] = shift;
+            i += 4;
+        }
+
+        if options.sack_permitted {
+            self.bytes[i] = OPTION_KIND_NOP;
+            self.bytes[i + 1] = OPTION_KIND_NOP;
+            self.bytes[i + 2] = OPTION_KIND_SACK_PERMITTED;
+            self.bytes[i + 3] = OPTION_LEN_SACK_PERMITTED;
+            i += 4;
+        }
+
+        let blocks = options.sack_blocks();
+        if !blocks.is_empty() {
+            self.bytes[i] = OPTION_KIND_NOP;
+            self.bytes[i + 1] = OPTION_KIND_NOP;
+            self.bytes[i + 2] = OPTION_KIND_SACK;
+            // The unwrap is safe because there are at most MAX_SACK_BLOCKS blocks.
+            self.bytes[i + 3] =
+                OPTION_LEN_SACK_BASE + u8::try_from(blocks.len()).unwrap() * OPTION_LEN_SACK_BLOCK;
+            i += 4;
+            for (left, right) in blocks {
+                self.bytes.htonl_unchecked(i, *left);
+                self.bytes.htonl_unchecked(i + 4, *right);
+                i += usize::from(OPTION_LEN_SACK_BLOCK);
+            }
+        }
+    }
 }
 
 impl<'a, T: NetworkBytesMut + Debug> Incomplete<TcpSegment<'a, T>> {
@@ -813,4 +1010,86 @@ mod tests {
             TcpError::MssRemaining
         );
     }
+
+    #[test]
+    fn test_options() {
+        let mut a = [0u8; 100];
+        let payload = [5u8; 10];
+        let mut options = TcpOptions {
+            mss: NonZeroU16::new(1460),
+            window_scale: Some(7),
+            sack_permitted: true,
+            ..Default::default()
+        };
+        options.set_sack_blocks(&[(100, 200), (300, 400)]);
+
+        let header_len = OPTIONS_OFFSET + 4 + 4 + 4 + 4 + 2 * OPTION_LEN_SACK_BLOCK;
+        let segment = TcpSegment::write_incomplete_segment_with_options(
+            a.as_mut(),
+            1,
+            2,
+            Flags::ACK,
+            3,
+            &options,
+            1460,
+            Some((payload.as_ref(), 4, 100)),
+        )
+        .unwrap()
+        .finalize(10, 20, None);
+
+        assert_eq!(segment.header_len(), header_len);
+        // Only the bytes after the offset are read from the payload buffer.
+        assert_eq!(segment.payload(), [5u8; 6]);
+        assert_eq!(
+            segment.parse_options_unchecked(header_len.into()),
+            Ok(options)
+        );
+        assert_eq!(
+            segment.parse_mss_option_unchecked(header_len.into()),
+            Ok(NonZeroU16::new(1460))
+        );
+
+        // Shift counts above the maximum are capped.
+        let mut b = [0u8; 24];
+        let mut segment = TcpSegment::from_bytes_unchecked(b.as_mut());
+        segment.set_header_len_rsvd_ns(24, false);
+        segment.bytes[20..24].copy_from_slice(&[OPTION_KIND_WINDOW_SCALE, 3, 20, OPTION_KIND_EOL]);
+        assert_eq!(
+            segment.parse_options_unchecked(24).unwrap().window_scale,
+            Some(MAX_WINDOW_SCALE)
+        );
+
+        // Options with invalid lengths are rejected.
+        for option in [
+            [OPTION_KIND_WINDOW_SCALE, 4, 0, 0],
+            [OPTION_KIND_SACK_PERMITTED, 3, 0, 0],
+            [OPTION_KIND_SACK, 4, 0, 0],
+            [OPTION_KIND_NOP, 0x42, 0, 0],
+            [OPTION_KIND_NOP, 0x42, 5, 0],
+        ] {
+            segment.bytes[20..24].copy_from_slice(&option);
+            assert_eq!(
+                segment.parse_options_unchecked(24).unwrap_err(),
+                TcpError::OptionLen
+            );
+        }
+
+        // There's no room for four SACK blocks next to the other options.
+        options.set_sack_blocks(&[(1, 2); MAX_SACK_BLOCKS + 1]);
+        assert_eq!(options.sack_blocks().len(), MAX_SACK_BLOCKS);
+        assert_eq!(
+            TcpSegment::write_incomplete_segment_with_options::<[u8]>(
+                a.as_mut(),
+                1,
+                2,
+                Flags::ACK,
+                3,
+                &options,
+                1460,
+                None,
+            )
+            .unwrap_err(),
+            TcpError::OptionsTooLong
+        );
+    }
 }
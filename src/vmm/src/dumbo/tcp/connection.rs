@@ -13,7 +13,9 @@ use bitflags::bitflags;
 use vmm_sys_util::rand::xor_pseudo_rng_u32;
 
 use crate::dumbo::pdu::bytes::NetworkBytes;
-use crate::dumbo::pdu::tcp::{Flags as TcpFlags, TcpError as TcpSegmentError, TcpSegment};
+use crate::dumbo::pdu::tcp::{
+    Flags as TcpFlags, TcpError as TcpSegmentError, TcpOptions, TcpSegment, MAX_WINDOW_SCALE,
+};
 use crate::dumbo::pdu::Incomplete;
 use crate::dumbo::tcp::{
     seq_after, seq_at_or_after, NextSegmentStatus, RstConfig, MAX_WINDOW_SIZE, MSS_DEFAULT,
@@ -69,6 +71,9 @@ bitflags! {
         /// The connection received a `FIN` whose sequence number does not match the next
         /// expected sequence number.
         const INVALID_FIN =             1 << 10;
+        /// The connection received a `SACK` block which does not fall within the sequence
+        /// numbers sent but not yet acknowledged.
+        const INVALID_SACK =            1 << 11;
     }
 }
 
@@ -129,9 +134,10 @@ pub enum WriteNextError {
 /// improvements/changes may happen in the future (this also goes for other aspects of the
 /// current implementation).
 ///
-/// A `Connection` object can only be created via passive open, and will not recognize/use any TCP
-/// options except `MSS` during the handshake. The associated state machine is similar to how
-/// TCP normally functions, but there are some differences:
+/// A `Connection` object can only be created via passive open, and will only recognize/use the
+/// `MSS`, `window scale` (RFC 7323) and `SACK permitted` (RFC 2018) TCP options during the
+/// handshake. The associated state machine is similar to how TCP normally functions, but there are
+/// some differences:
 ///
 /// * Since only passive opens are supported, a `Connection` can only be instantiated in response to
 ///   an incoming `SYN` segment. If the segment is valid, it will start directly in a state called
@@ -153,7 +159,11 @@ pub enum WriteNextError {
 /// The current implementation does not do any kind of congestion control, expects segments to
 /// arrive in order, triggers a retransmission after the first duplicate `ACK`, and relies on the
 /// user to supply an opaque `u64` timestamp value when invoking send or receive functionality. The
-/// timestamps must be non-decreasing, and are mainly used for retransmission timeouts.
+/// timestamps must be non-decreasing, and are mainly used for retransmission timeouts. When the
+/// other endpoint permits `SACK`, the blocks it reports are used to retransmit only the missing
+/// sequence number ranges, each of them once per duplicate `ACK`, instead of the first
+/// unacknowledged segment over and over. Received data is never reported in `SACK` blocks, since
+/// out of order segments are dropped.
 ///
 /// See [mmds-design](https://github.com/firecracker-microvm/firecracker/blob/main/docs/mmds/mmds-design.md#dumbo)
 /// for why we are able to make these simplifications. Specifically, we want to stress that no
@@ -196,15 +206,36 @@ pub struct Connection {
     send_rst: Option<RstConfig>,
     // The MSS used when sending data segments.
     mss: u16,
+    // The shift count applied to the window sizes advertised by the other endpoint. This is 0
+    // unless both endpoints sent the window scale option during the handshake.
+    remote_window_shift: u8,
+    // The shift count applied to the window sizes we advertise, under the same conditions.
+    local_window_shift: u8,
+    // Set when the SYN carried the window scale option, so that we reply with our own.
+    window_scale_received: bool,
+    // The other endpoint permitted us to rely on the SACK blocks it sends.
+    sack_permitted: bool,
+    // The disjoint ranges of sequence numbers reported by the other endpoint via SACK blocks,
+    // after highest_ack_received and sorted in ascending order. At most MAX_SACKED_RANGES are
+    // kept.
+    sacked: Vec<(Wrapping<u32>, Wrapping<u32>)>,
+    // The first sequence number which has not been retransmitted since the holes between the
+    // sacked ranges were last computed. Only meaningful when sacked is not empty.
+    retransmit_next: Wrapping<u32>,
     // If true, send an ACK segment at the first opportunity. ACKs can piggyback data segments, so
     // we'll only send an empty ACK segment if we can't transmit any data.
     pending_ack: bool,
     // We've got a duplicate ACK, so we'll retransmit the highest ACKed sequence number at the
-    // first opportunity. Unlike regular TCP, we retransmit after the first duplicate ACK.
+    // first opportunity. Unlike regular TCP, we retransmit after the first duplicate ACK. When
+    // there are SACKed ranges, we retransmit the holes between them instead.
     dup_ack: bool,
     status_flags: ConnStatusFlags,
 }
 
+// The largest number of sequence number ranges reported by SACK blocks that a connection
+// remembers. Ranges which don't fit are forgotten, which only means they may get retransmitted.
+const MAX_SACKED_RANGES: usize = 16;
+
 fn parse_mss_option<T: NetworkBytes + Debug>(
     segment: &TcpSegment<T>,
 ) -> Result<u16, PassiveOpenError> {
@@ -215,6 +246,15 @@ fn parse_mss_option<T: NetworkBytes + Debug>(
     }
 }
 
+// Returns the smallest shift count which allows advertising a receive window of the specified size.
+fn window_shift_for(rwnd_size: u32) -> u8 {
+    let mut shift = 0;
+    while shift < MAX_WINDOW_SCALE && rwnd_size >> shift > u32::from(u16::MAX) {
+        shift += 1;
+    }
+    shift
+}
+
 fn is_valid_syn<T: NetworkBytes + Debug>(segment: &TcpSegment<T>) -> bool {
     segment.flags_after_ns() == TcpFlags::SYN && segment.payload_len() == 0
 }
@@ -241,11 +281,18 @@ impl Connection {
             return Err(PassiveOpenError::InvalidSyn);
         }
 
-        // TODO: If we ever implement window scaling, change the part that computes
-        // remote_rwnd_edge below.
-
-        // We only care about the MSS option for now.
         let mss = parse_mss_option(segment)?;
+        // The MSS option parsed fine, so the other ones did too.
+        let options = segment
+            .parse_options_unchecked(segment.header_len().into())
+            .unwrap_or_default();
+
+        // Window scaling is only in effect if both endpoints send the option, which we do
+        // whenever the SYN carries it.
+        let (remote_window_shift, local_window_shift) = match options.window_scale {
+            Some(shift) => (shift, window_shift_for(local_rwnd_size)),
+            None => (0, 0),
+        };
 
         // This is going to get sent on the SYNACK.
         let ack_to_send = Wrapping(segment.sequence_number()) + Wrapping(1);
@@ -253,6 +300,7 @@ impl Connection {
         // Let's pick the initial sequence number.
         let isn = Wrapping(xor_pseudo_rng_u32());
         let first_not_sent = isn + Wrapping(1);
+        // The window size of SYN segments is never scaled.
         let remote_rwnd_edge = first_not_sent + Wrapping(u32::from(segment.window_size()));
 
         Ok(Connection {
@@ -272,6 +320,12 @@ impl Connection {
             send_fin: None,
             send_rst: None,
             mss,
+            remote_window_shift,
+            local_window_shift,
+            window_scale_received: options.window_scale.is_some(),
+            sack_permitted: options.sack_permitted,
+            sacked: Vec::new(),
+            retransmit_next: isn,
             pending_ack: false,
             dup_ack: false,
             status_flags: ConnStatusFlags::SYN_RECEIVED,
@@ -346,17 +400,20 @@ impl Connection {
             && matches!(self.send_fin, Some(fin_seq) if fin_seq == self.highest_ack_received)
     }
 
-    // Returns the window size which should be written to an outgoing segment. This is going to be
-    // even more useful when we'll support window scaling.
-    fn local_rwnd(&self) -> u16 {
-        let rwnd = (self.local_rwnd_edge - self.ack_to_send).0;
+    // Returns the window size which should be written to an outgoing segment. The window size of
+    // SYNACK segments is never scaled.
+    fn local_rwnd(&self, flags_after_ns: TcpFlags) -> u16 {
+        let mut rwnd = (self.local_rwnd_edge - self.ack_to_send).0;
+        if !flags_after_ns.intersects(TcpFlags::SYN) {
+            rwnd >>= self.local_window_shift;
+        }
 
         u16::try_from(rwnd).unwrap_or(u16::MAX)
     }
 
-    // Will actually become meaningful when/if we implement window scaling.
+    // Returns the size of the receive window advertised by the other endpoint on a non-SYN segment.
     fn remote_window_size(&self, window_size: u16) -> u32 {
-        u32::from(window_size)
+        u32::from(window_size) << self.remote_window_shift
     }
 
     // Computes the remote rwnd edge given the ACK number and window size from an incoming segment.
@@ -473,6 +530,85 @@ impl Connection {
         self.dup_ack
     }
 
+    /// Returns `true` if the other endpoint permitted the use of `SACK` during the handshake.
+    #[inline]
+    pub fn sack_permitted(&self) -> bool {
+        self.sack_permitted
+    }
+
+    // Records the sequence number ranges reported by the SACK blocks of an incoming segment, and
+    // returns false if some of them are invalid.
+    fn record_sack_blocks(&mut self, blocks: &[(u32, u32)]) -> bool {
+        let mut valid = true;
+        for &(left, right) in blocks {
+            let (left, right) = (Wrapping(left), Wrapping(right));
+            // A block must cover sequence numbers which were sent, but not acknowledged yet.
+            if !seq_after(right, left)
+                || !seq_at_or_after(left, self.highest_ack_received)
+                || !seq_at_or_after(self.first_not_sent, right)
+            {
+                valid = false;
+                continue;
+            }
+
+            // Merge the block with the ranges it overlaps or touches.
+            let mut merged = (left, right);
+            self.sacked.retain(|&(l, r)| {
+                if seq_at_or_after(r, merged.0) && seq_at_or_after(merged.1, l) {
+                    if seq_after(merged.0, l) {
+                        merged.0 = l;
+                    }
+                    if seq_after(r, merged.1) {
+                        merged.1 = r;
+                    }
+                    false
+                } else {
+                    true
+                }
+            });
+            let pos = self
+                .sacked
+                .iter()
+                .position(|&(l, _)| seq_after(l, merged.0))
+                .unwrap_or(self.sacked.len());
+            self.sacked.insert(pos, merged);
+            // Forget about the highest ranges when there are too many of them.
+            self.sacked.truncate(MAX_SACKED_RANGES);
+        }
+        valid
+    }
+
+    // Drops the sacked ranges which are covered by the cumulative ACK.
+    fn prune_sacked(&mut self) {
+        let ack = self.highest_ack_received;
+        self.sacked.retain(|&(_, r)| seq_after(r, ack));
+        if let Some(first) = self.sacked.first_mut() {
+            if seq_after(ack, first.0) {
+                first.0 = ack;
+            }
+        }
+    }
+
+    // Returns the next range of sequence numbers which has not been SACKed nor retransmitted yet,
+    // below the highest SACKed sequence number. Data sent after that point might still be in
+    // flight, so it's not considered lost.
+    fn next_sack_hole(&self) -> Option<(Wrapping<u32>, Wrapping<u32>)> {
+        let mut start = if seq_after(self.retransmit_next, self.highest_ack_received) {
+            self.retransmit_next
+        } else {
+            self.highest_ack_received
+        };
+        for &(left, right) in self.sacked.iter() {
+            if seq_after(left, start) {
+                return Some((start, left));
+            }
+            if seq_after(right, start) {
+                start = right;
+            }
+        }
+        None
+    }
+
     /// Describes whether a control segment can be sent immediately, a retransmission is pending,
     /// or there's nothing to transmit until more segments are received.
     ///
@@ -606,6 +742,10 @@ impl Connection {
                     // We're making progress. We should also reset rto_start in this case.
                     self.highest_ack_received = ack;
                     self.rto_start = now;
+                    if seq_after(ack, self.retransmit_next) {
+                        self.retransmit_next = ack;
+                    }
+                    self.prune_sacked();
                     if !self.is_established() && self.synack_sent() {
                         // The connection becomes ESTABLISHED.
                         self.set_flags(ConnStatusFlags::ESTABLISHED);
@@ -616,6 +756,20 @@ impl Connection {
                     }
                 }
 
+                // Look for SACK blocks, which tell us about the data the other endpoint received
+                // after a missing segment.
+                if self.is_established() && self.sack_permitted {
+                    if let Ok(options) = s.parse_options_unchecked(s.header_len().into()) {
+                        if !self.record_sack_blocks(options.sack_blocks()) {
+                            recv_status_flags |= RecvStatusFlags::INVALID_SACK;
+                        }
+                    }
+                    // Retransmit the missing data as soon as we know about it.
+                    if self.next_sack_hole().is_some() {
+                        self.dup_ack = true;
+                    }
+                }
+
                 // Look for remote remote rwnd updates.
                 if self.is_established() {
                     let edge = self.compute_remote_rwnd_edge(ack, s.window_size());
@@ -728,6 +882,7 @@ impl Connection {
     // destination L3 addresses (which are required for checksum computation). We need this stupid
     // ?Sized trait bound, because otherwise Sized would be implied, and we can have unsized types
     // which implement ByteBuffer (such as [u8]), since payload expects a reference to some R.
+    #[allow(clippy::too_many_arguments)]
     fn write_segment<'a, R: ByteBuffer + ?Sized + Debug>(
         &mut self,
         buf: &'a mut [u8],
@@ -735,22 +890,30 @@ impl Connection {
         seq: Wrapping<u32>,
         ack: Wrapping<u32>,
         flags_after_ns: TcpFlags,
-        payload: Option<(&R, usize)>,
+        payload: Option<(&R, usize, usize)>,
     ) -> Result<Incomplete<TcpSegment<'a, &'a mut [u8]>>, WriteNextError> {
-        // Write the MSS option on SYNACK segments.
-        let mss_option = if flags_after_ns == TcpFlags::SYN | TcpFlags::ACK {
-            Some(self.mss)
+        // Write the MSS option on SYNACK segments, along with the window scale and SACK permitted
+        // options when the SYN carried them.
+        let options = if flags_after_ns == TcpFlags::SYN | TcpFlags::ACK {
+            TcpOptions {
+                mss: NonZeroU16::new(self.mss),
+                window_scale: self
+                    .window_scale_received
+                    .then_some(self.local_window_shift),
+                sack_permitted: self.sack_permitted,
+                ..Default::default()
+            }
         } else {
-            None
+            TcpOptions::default()
         };
 
-        let segment = TcpSegment::write_incomplete_segment(
+        let segment = TcpSegment::write_incomplete_segment_with_options(
             buf,
             seq.0,
             ack.0,
             flags_after_ns,
-            self.local_rwnd(),
-            mss_option,
+            self.local_rwnd(flags_after_ns),
+            &options,
             self.mss
                 .checked_sub(mss_reserved)
                 .ok_or(WriteNextError::MssRemaining)?,
@@ -880,6 +1043,8 @@ impl Connection {
             let payload_end = payload_seq + Wrapping(len);
 
             let mut rto_triggered = false;
+            // When retransmitting a hole between SACKed ranges, this is where the hole ends.
+            let mut hole_end = None;
 
             // Decide what sequence number to send next. Check out if a timeout expired first.
             let seq_to_send =
@@ -902,7 +1067,23 @@ impl Connection {
 
                     // We have to remember this is a retransmission for later.
                     rto_triggered = true;
+                    // The other endpoint is allowed to discard the data it SACKed (RFC 2018), so
+                    // we don't rely on the SACKed ranges anymore.
+                    self.sacked.clear();
                     self.highest_ack_received
+                } else if self.dup_ack && !self.sacked.is_empty() {
+                    // Retransmit the next hole between the SACKed ranges, if any. Otherwise, the
+                    // holes have all been retransmitted already, so we send new data.
+                    match self.next_sack_hole() {
+                        Some((start, end)) => {
+                            hole_end = Some(end);
+                            start
+                        }
+                        None => {
+                            self.dup_ack = false;
+                            self.first_not_sent
+                        }
+                    }
                 } else if self.dup_ack {
                     // We retransmit an older segment if a DUPACK is recorded. We'll clear
                     // self.dup_ack after we make sure the segment has been successfully written.
@@ -921,12 +1102,19 @@ impl Connection {
 
             // We can only send data if it's within both the send buffer and the remote rwnd, and
             // before the sequence number of the local FIN (if the connection is closing).
-            let actual_end = if seq_at_or_after(self.remote_rwnd_edge, payload_end) {
+            let mut actual_end = if seq_at_or_after(self.remote_rwnd_edge, payload_end) {
                 payload_end
             } else {
                 self.remote_rwnd_edge
             };
 
+            // A retransmission of a hole stops where the next SACKed range begins.
+            if let Some(end) = hole_end {
+                if seq_after(actual_end, end) {
+                    actual_end = end;
+                }
+            }
+
             // Make sure we're not trying to send data past the FIN sequence we previously
             // announced.
             if let Some(fin_seq) = self.send_fin {
@@ -939,6 +1127,9 @@ impl Connection {
             // delimit a valid sequence number interval.
             if seq_after(actual_end, seq_to_send) {
                 let max_payload_len = (actual_end - seq_to_send).0 as usize;
+                // The payload buffer begins at payload_seq, which may come before seq_to_send
+                // when retransmitting.
+                let payload_offset = (seq_to_send - payload_seq).0 as usize;
 
                 // We always set the ACK flag for data segments.
                 let tcp_flags = TcpFlags::ACK;
@@ -950,16 +1141,22 @@ impl Connection {
                     seq_to_send,
                     ack_to_send,
                     tcp_flags,
-                    Some((read_buf, max_payload_len)),
+                    Some((read_buf, payload_offset, max_payload_len)),
                 )?;
 
-                // If self.dup_ack was Some(_), we've just written the retransmission segment,
-                // either directly or via the RTO timer expiring.
-                self.dup_ack = false;
-
                 let payload_len = segment.inner().payload_len();
                 let mut first_seq_after = seq_to_send + Wrapping(u32::from(payload_len));
 
+                // If self.dup_ack was Some(_), we've just written the retransmission segment,
+                // either directly or via the RTO timer expiring. When retransmitting holes between
+                // SACKed ranges, we keep going until none is left.
+                if hole_end.is_some() {
+                    self.retransmit_next = first_seq_after;
+                    self.dup_ack = self.next_sack_hole().is_some();
+                } else {
+                    self.dup_ack = false;
+                }
+
                 if let Some(fin_seq) = self.send_fin {
                     if first_seq_after == fin_seq {
                         // This segment contains the last bytes of data we're going to send, so
@@ -1092,6 +1289,27 @@ pub(crate) mod tests {
             .unwrap()
         }
 
+        // Like write_segment_helper(), but the segment carries the specified options and no
+        // payload.
+        fn write_segment_with_options<'a>(
+            &self,
+            buf: &'a mut [u8],
+            options: &TcpOptions,
+        ) -> TcpSegment<'a, &'a mut [u8]> {
+            TcpSegment::write_incomplete_segment_with_options::<[u8]>(
+                buf,
+                self.remote_isn,
+                0,
+                TcpFlags::empty(),
+                self.remote_window_size,
+                options,
+                self.mss.checked_sub(self.mss_reserved).unwrap(),
+                None,
+            )
+            .unwrap()
+            .finalize(self.src_port, self.dst_port, None)
+        }
+
         pub fn write_syn<'a>(&self, buf: &'a mut [u8]) -> TcpSegment<'a, &'a mut [u8]> {
             self.write_segment_helper(buf, true, None)
         }
@@ -1785,4 +2003,185 @@ pub(crate) mod tests {
         // and we don't wait for our FIN to be ACKed.
         assert!(c.is_done());
     }
+
+    #[test]
+    fn test_window_shift_for() {
+        assert_eq!(window_shift_for(0), 0);
+        assert_eq!(window_shift_for(u32::from(u16::MAX)), 0);
+        assert_eq!(window_shift_for(u32::from(u16::MAX) + 1), 1);
+        assert_eq!(window_shift_for(1 << 20), 5);
+        assert_eq!(window_shift_for(MAX_WINDOW_SIZE), MAX_WINDOW_SCALE);
+        assert_eq!(window_shift_for(u32::MAX), MAX_WINDOW_SCALE);
+    }
+
+    #[test]
+    #[allow(clippy::cognitive_complexity)]
+    fn test_window_scaling_and_sack() {
+        let mut buf1 = [0u8; 100];
+        let mut buf2 = [0u8; 100];
+        // The bytes of the outgoing data segments differ, so we can check what got retransmitted.
+        let send_buf: Vec<u8> = (0..20_000u32).map(|i| (i % 251) as u8).collect();
+
+        let mut t = ConnectionTester::new();
+        t.local_rwnd_size = 1 << 20;
+        t.remote_window_size = 1000;
+
+        let mut syn_options = TcpOptions {
+            mss: NonZeroU16::new(t.mss),
+            window_scale: Some(7),
+            sack_permitted: true,
+            ..Default::default()
+        };
+        let mut syn = t.write_segment_with_options(buf1.as_mut(), &syn_options);
+        syn.set_flags_after_ns(TcpFlags::SYN);
+        let mut c = t.passive_open(&syn).unwrap();
+
+        assert_eq!(c.remote_window_shift, 7);
+        assert_eq!(c.local_window_shift, 5);
+        assert!(c.sack_permitted());
+        // The window size of the SYN is not scaled.
+        assert_eq!(c.remote_rwnd_edge, c.first_not_sent + Wrapping(1000));
+
+        // The SYNACK carries our own window scale and SACK permitted options, and an unscaled
+        // window size.
+        let conn_isn = {
+            let s = t.write_next_segment(&mut c, None).unwrap().unwrap();
+            check_control_segment(&s, 12, TcpFlags::SYN | TcpFlags::ACK);
+            syn_options.window_scale = Some(5);
+            assert_eq!(
+                s.parse_options_unchecked(s.header_len().into()),
+                Ok(syn_options)
+            );
+            assert_eq!(s.window_size(), u16::MAX);
+            s.sequence_number()
+        };
+        let first_seq = Wrapping(conn_isn) + Wrapping(1);
+        let mss = u32::from(t.mss);
+        let mss_len = usize::from(t.mss);
+
+        // The window size advertised by the ACK is scaled.
+        let mut ctrl = t.write_ctrl(buf2.as_mut());
+        ctrl.set_flags_after_ns(TcpFlags::ACK)
+            .set_sequence_number(t.remote_isn.wrapping_add(1))
+            .set_ack_number(first_seq.0);
+        assert_eq!(
+            t.receive_segment(&mut c, &ctrl).unwrap(),
+            (None, RecvStatusFlags::empty())
+        );
+        check_established(&c);
+        assert_eq!(c.remote_rwnd_edge, first_seq + Wrapping(1000 << 7));
+
+        let payload_src = Some((send_buf.as_slice(), first_seq));
+
+        // Our own window size is scaled too.
+        c.enqueue_ack();
+        {
+            let s = t.write_next_segment(&mut c, None).unwrap().unwrap();
+            assert_eq!(s.window_size(), 1 << 15);
+        }
+
+        // Let's send 8 data segments.
+        for i in 0..8 {
+            let s = t.write_next_segment(&mut c, payload_src).unwrap().unwrap();
+            assert_eq!(s.sequence_number(), (first_seq + Wrapping(i * mss)).0);
+            assert_eq!(usize::from(s.payload_len()), mss_len);
+        }
+
+        // The first and fourth segments got lost, and the other endpoint reports the others via
+        // SACK blocks on a duplicate ACK.
+        let seq_of = |i: u32| first_seq + Wrapping(i * mss);
+        let mut ack_options = TcpOptions::default();
+        ack_options.set_sack_blocks(&[(seq_of(4).0, seq_of(8).0), (seq_of(1).0, seq_of(3).0)]);
+        let mut sack = t.write_segment_with_options(buf1.as_mut(), &ack_options);
+        sack.set_flags_after_ns(TcpFlags::ACK)
+            .set_sequence_number(t.remote_isn.wrapping_add(1))
+            .set_ack_number(first_seq.0);
+        assert_eq!(
+            t.receive_segment(&mut c, &sack).unwrap(),
+            (None, RecvStatusFlags::DUP_ACK)
+        );
+        assert_eq!(
+            c.sacked,
+            vec![(seq_of(1), seq_of(3)), (seq_of(4), seq_of(8))]
+        );
+        assert!(c.dup_ack_pending());
+
+        // Both holes get retransmitted, each with the right payload, and nothing else.
+        for hole in [0, 3] {
+            let s = t.write_next_segment(&mut c, payload_src).unwrap().unwrap();
+            assert_eq!(s.sequence_number(), seq_of(hole).0);
+            let offset = (hole * mss) as usize;
+            assert_eq!(s.payload(), &send_buf[offset..offset + mss_len]);
+        }
+        assert!(!c.dup_ack_pending());
+
+        // Since the holes were already retransmitted, another duplicate ACK leads to sending
+        // new data.
+        assert_eq!(
+            t.receive_segment(&mut c, &sack).unwrap(),
+            (None, RecvStatusFlags::DUP_ACK)
+        );
+        {
+            let s = t.write_next_segment(&mut c, payload_src).unwrap().unwrap();
+            assert_eq!(s.sequence_number(), seq_of(8).0);
+        }
+
+        // An ACK for the first retransmission prunes the SACKed ranges it covers.
+        ack_options.set_sack_blocks(&[(seq_of(4).0, seq_of(8).0)]);
+        let mut sack = t.write_segment_with_options(buf1.as_mut(), &ack_options);
+        sack.set_flags_after_ns(TcpFlags::ACK)
+            .set_sequence_number(t.remote_isn.wrapping_add(1))
+            .set_ack_number(seq_of(3).0);
+        assert_eq!(
+            t.receive_segment(&mut c, &sack).unwrap(),
+            (None, RecvStatusFlags::empty())
+        );
+        assert_eq!(c.sacked, vec![(seq_of(4), seq_of(8))]);
+        assert!(!c.dup_ack_pending());
+
+        // SACK blocks must fall between the highest ACK and the first sequence number not sent.
+        ack_options.set_sack_blocks(&[(seq_of(8).0, seq_of(10).0), (seq_of(2).0, seq_of(3).0)]);
+        let mut sack = t.write_segment_with_options(buf1.as_mut(), &ack_options);
+        sack.set_flags_after_ns(TcpFlags::ACK)
+            .set_sequence_number(t.remote_isn.wrapping_add(1))
+            .set_ack_number(seq_of(3).0);
+        assert_eq!(
+            t.receive_segment(&mut c, &sack).unwrap(),
+            (
+                None,
+                RecvStatusFlags::DUP_ACK | RecvStatusFlags::INVALID_SACK
+            )
+        );
+        assert_eq!(c.sacked, vec![(seq_of(4), seq_of(8))]);
+
+        // A retransmission timeout makes us forget about the SACKed ranges.
+        c.dup_ack = false;
+        t.now += t.rto_period;
+        {
+            let s = t.write_next_segment(&mut c, payload_src).unwrap().unwrap();
+            assert_eq!(s.sequence_number(), seq_of(3).0);
+        }
+        assert!(c.sacked.is_empty());
+    }
+
+    #[test]
+    fn test_no_window_scaling() {
+        let mut buf = [0u8; 100];
+        let mut t = ConnectionTester::new();
+        t.local_rwnd_size = 1 << 20;
+
+        // Without the window scale option on the SYN, windows are not scaled in either direction,
+        // and the SYNACK only carries the MSS option.
+        let syn = t.write_syn(buf.as_mut());
+        let mut c = t.passive_open(&syn).unwrap();
+        assert_eq!(c.remote_window_shift, 0);
+        assert_eq!(c.local_window_shift, 0);
+        assert!(!c.sack_permitted());
+        t.check_synack_is_next(&mut c);
+
+        c.set_flags(ConnStatusFlags::ESTABLISHED);
+        c.enqueue_ack();
+        let s = t.write_next_segment(&mut c, None).unwrap().unwrap();
+        assert_eq!(s.window_size(), u16::MAX);
+    }
 }
@@ -50,10 +50,9 @@ pub struct Endpoint {
     receive_buf_left: usize,
     // This is filled with the HTTP response bytes after we parse a request and generate the reply.
     response_buf: Vec<u8>,
-    // Initial response sequence, used to track if the entire `response_buf` was sent.
+    // Represents the sequence number associated with the first byte from response_buf, used to
+    // track if the entire `response_buf` was sent.
     initial_response_seq: Wrapping<u32>,
-    // Represents the sequence number associated with the first byte from response_buf.
-    response_seq: Wrapping<u32>,
     // The TCP connection that does all the receiving/sending work.
     connection: Connection,
     // Timestamp (in cycles) associated with the most recent reception of a segment.
@@ -118,7 +117,6 @@ impl Endpoint {
             // TODO: Using first_not_sent() makes sense here because a connection is currently
             // created via passive open only, so this points to the sequence number right after
             // the SYNACK. It might stop working like that if/when the implementation changes.
-            response_seq: connection.first_not_sent(),
             initial_response_seq: connection.first_not_sent(),
             connection,
             last_segment_received_timestamp: timestamp_cycles(),
@@ -192,10 +190,9 @@ impl Endpoint {
             // stored in self.response_buf).
 
             // It seems we just received the last ACK we were waiting for, so the entire
-            // response has been successfully received. Set the new response_seq and clear
-            // the response_buf.
-            self.response_seq = self.connection.highest_ack_received();
-            self.initial_response_seq = self.response_seq;
+            // response has been successfully received. Set the new initial_response_seq and
+            // clear the response_buf.
+            self.initial_response_seq = self.connection.highest_ack_received();
             self.response_buf.clear();
         }
 
@@ -220,6 +217,14 @@ impl Endpoint {
                             continue;
                         };
 
+                        // Requests which carry a body (such as guest data writes) end after
+                        // the number of bytes given by the Content-Length header, so we keep
+                        // waiting until the whole body is available.
+                        let end = match end.checked_add(content_length(&b[..end])) {
+                            Some(end) if end <= self.receive_buf_left => end,
+                            _ => break,
+                        };
+
                         // We found a potential request, let's parse it.
                         let response = parse_request_bytes(&b[..end], callback);
 
@@ -265,12 +270,10 @@ impl Endpoint {
         buf: &'a mut [u8],
         mss_reserved: u16,
     ) -> Option<Incomplete<TcpSegment<'a, &'a mut [u8]>>> {
+        // The whole response is handed over to the connection, which picks the bytes to send
+        // (including retransmissions) based on their sequence numbers.
         let tcp_payload_src = if !self.response_buf.is_empty() {
-            let offset = self.response_seq - self.initial_response_seq;
-            Some((
-                self.response_buf.split_at(offset.0 as usize).1,
-                self.response_seq,
-            ))
+            Some((self.response_buf.as_slice(), self.initial_response_seq))
         } else {
             None
         };
@@ -281,9 +284,7 @@ impl Endpoint {
             tcp_payload_src,
             timestamp_cycles(),
         ) {
-            Ok(write_result) => write_result.inspect(|segment| {
-                self.response_seq += Wrapping(u32::from(segment.inner().payload_len()));
-            }),
+            Ok(write_result) => write_result,
             Err(_) => {
                 METRICS.mmds.tx_errors.inc();
                 None
@@ -328,12 +329,36 @@ fn build_response(status_code: StatusCode, body: Body) -> Response {
     response
 }
 
+// Returns the value of the Content-Length header found in the request line and headers `head`, or
+// 0 if the header is missing or invalid, in which case the parser reports the error.
+fn content_length(head: &[u8]) -> usize {
+    head.split(|c| *c == b'\n')
+        .filter_map(|line| {
+            let colon = line.iter().position(|c| *c == b':')?;
+            if !line[..colon].eq_ignore_ascii_case(b"content-length") {
+                return None;
+            }
+            std::str::from_utf8(&line[colon + 1..])
+                .ok()?
+                .trim()
+                .parse()
+                .ok()
+        })
+        .next()
+        .unwrap_or(0)
+}
+
 /// Parses the request bytes and builds a `micro_http::Response` by the given callback function.
 fn parse_request_bytes<F: FnOnce(Request) -> Response>(
     byte_stream: &[u8],
     callback: F,
 ) -> Response {
-    let request = Request::try_from(byte_stream, None);
+    // The HTTP parser doesn't know about the POST method, which guests may use to write guest
+    // data, so we handle POST requests as PUT requests.
+    let request = match byte_stream.strip_prefix(b"POST ") {
+        Some(rest) => Request::try_from(&[b"PUT ", rest].concat(), None),
+        None => Request::try_from(byte_stream, None),
+    };
     match request {
         Ok(request) => callback(request),
         Err(err) => match err {
@@ -599,7 +624,7 @@ mod tests {
         assert_eq!(actual_response, expected_response);
 
         // Test invalid HTTP methods.
-        let invalid_methods = ["POST", "HEAD", "DELETE", "CONNECT", "OPTIONS", "TRACE"];
+        let invalid_methods = ["HEAD", "DELETE", "CONNECT", "OPTIONS", "TRACE"];
         for method in invalid_methods.iter() {
             let request_bytes = format!("{} http://169.254.169.255/ HTTP/1.0\r\n\r\n", method);
             let mut expected_response = Response::new(Version::Http11, StatusCode::NotImplemented);
@@ -609,7 +634,7 @@ mod tests {
         }
 
         // Test valid methods.
-        let valid_methods = ["PUT", "PATCH", "GET"];
+        let valid_methods = ["PUT", "PATCH", "GET", "POST"];
         for method in valid_methods.iter() {
             let request_bytes = format!("{} http://169.254.169.255/ HTTP/1.0\r\n\r\n", method);
             let expected_response = Response::new(Version::Http11, StatusCode::OK);
@@ -656,4 +681,34 @@ mod tests {
         let actual_response = parse_request_bytes(request_bytes, mock_callback);
         assert_eq!(actual_response, expected_response);
     }
+
+    #[test]
+    fn test_content_length() {
+        assert_eq!(
+            content_length(b"GET http://169.254.169.254/ HTTP/1.1\r\n\r\n"),
+            0
+        );
+        assert_eq!(
+            content_length(b"PUT /latest/guest HTTP/1.1\r\nContent-Length: 14\r\n\r\n"),
+            14
+        );
+        assert_eq!(
+            content_length(b"POST /latest/guest HTTP/1.1\ncontent-length:3\n\n"),
+            3
+        );
+        // Invalid values are left for the parser to report.
+        assert_eq!(
+            content_length(b"PUT /latest/guest HTTP/1.1\r\nContent-Length: alpha\r\n\r\n"),
+            0
+        );
+
+        // POST requests are handled like PUT requests, including their body.
+        let request_bytes = b"POST /latest/guest HTTP/1.1\r\nContent-Length: 4\r\n\r\ntrue";
+        let response = parse_request_bytes(request_bytes, |request| {
+            assert_eq!(request.method(), micro_http::Method::Put);
+            assert_eq!(request.body.unwrap().raw(), b"true");
+            mock_callback(request)
+        });
+        assert_eq!(response.status(), StatusCode::OK);
+    }
 }
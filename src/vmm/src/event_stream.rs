@@ -0,0 +1,281 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Stream of the events of the microVM, written as lines of JSON to the clients of a Unix socket.
+//!
+//! Events are emitted from any thread with [`emit`], which never blocks: the events are queued
+//! and written to the clients by the VMM thread. Events emitted while no stream is configured
+//! are discarded. Clients which do not keep up with the stream are disconnected.
+//!
+//! # Events format
+//! ```json
+//! {"event": "device_error", "device": "block_rootfs", "class": "backend", "errno": 5,
+//!  "instance_id": "vm0", "timestamp_us": 1735689600000000}
+//! ```
+
+use std::io::{self, ErrorKind, Write};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::sync::Mutex;
+
+use event_manager::{EventOps, Events, MutEventSubscriber};
+use serde::Serialize;
+use utils::time::{get_time_us, ClockType};
+use vmm_sys_util::epoll::EventSet;
+use vmm_sys_util::eventfd::EventFd;
+
+use crate::logger::{error, warn, IncMetric, METRICS};
+
+/// Maximum number of clients connected to the event stream.
+pub const MAX_EVENT_STREAM_CLIENTS: usize = 16;
+/// Maximum number of events queued before they are written to the clients.
+const MAX_QUEUED_EVENTS: usize = 1024;
+
+/// Queue of the configured event stream, if any.
+static EVENT_SINK: Mutex<Option<EventSink>> = Mutex::new(None);
+
+/// Errors associated with the event stream.
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum EventStreamError {
+    /// Failed to bind the event stream socket: {0}
+    Bind(io::Error),
+    /// Failed to create the event stream event fd: {0}
+    EventFd(io::Error),
+}
+
+/// Event of the microVM.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum VmEvent {
+    /// The vCPUs of the microVM were started for the first time.
+    BootComplete,
+    /// A vCPU exited, e.g. because the guest halted or rebooted.
+    VcpuExit {
+        /// Index of the vCPU.
+        vcpu: u8,
+        /// Exit code with which the microVM stops.
+        exit_code: u8,
+    },
+    /// A device failed, see [`crate::devices::error_events`].
+    DeviceError {
+        /// Device type followed by the device id, e.g. `block_rootfs`.
+        device: String,
+        /// Origin of the error.
+        class: String,
+        /// Error number of the failed system call, if any.
+        errno: Option<i32>,
+    },
+    /// The guest deflated the balloon without being asked to, because it ran out of memory.
+    BalloonOomDeflate {
+        /// Number of 4 KiB pages given back to the guest.
+        pages: u32,
+    },
+    /// The rate limiter of a device ran out of budget, throttling the device.
+    RateLimited {
+        /// Device type followed by the device id, e.g. `net_eth0`.
+        device: String,
+        /// Throttled operation, e.g. `rx` or `tx`.
+        operation: &'static str,
+    },
+    /// The guest kernel panicked, as reported through the pvpanic device.
+    GuestPanic {
+        /// Whether a crash kernel is loaded to handle the panic.
+        crash_loaded: bool,
+    },
+}
+
+#[derive(Debug, Serialize)]
+struct EventRecord<'a> {
+    #[serde(flatten)]
+    event: &'a VmEvent,
+    instance_id: &'a str,
+    /// Wall clock time at which the event occurred, in microseconds.
+    timestamp_us: u64,
+}
+
+#[derive(Debug)]
+struct EventSink {
+    sender: SyncSender<(u64, VmEvent)>,
+    event_fd: EventFd,
+}
+
+/// Queues `event` to be written to the clients of the event stream, if one is configured.
+pub fn emit(event: VmEvent) {
+    let sink = EVENT_SINK.lock().expect("Poisoned lock");
+    let Some(sink) = sink.as_ref() else {
+        return;
+    };
+    let timestamp_us = get_time_us(ClockType::Real);
+    if sink.sender.try_send((timestamp_us, event)).is_err() {
+        METRICS.vmm.events_dropped.inc();
+        return;
+    }
+    if let Err(err) = sink.event_fd.write(1) {
+        error!("Failed to signal the event stream: {}", err);
+    }
+}
+
+/// Writes the emitted events to the clients connected to a Unix socket.
+#[derive(Debug)]
+pub struct EventStream {
+    instance_id: String,
+    listener: UnixListener,
+    clients: Vec<UnixStream>,
+    event_fd: EventFd,
+    receiver: Receiver<(u64, VmEvent)>,
+}
+
+impl EventStream {
+    /// Creates a stream accepting clients on the Unix socket `socket_path`, and starts queuing
+    /// the emitted events.
+    pub fn new(socket_path: &Path, instance_id: String) -> Result<Self, EventStreamError> {
+        let listener = UnixListener::bind(socket_path).map_err(EventStreamError::Bind)?;
+        listener
+            .set_nonblocking(true)
+            .map_err(EventStreamError::Bind)?;
+        let event_fd = EventFd::new(libc::EFD_NONBLOCK).map_err(EventStreamError::EventFd)?;
+        let (sender, receiver) = sync_channel(MAX_QUEUED_EVENTS);
+        *EVENT_SINK.lock().expect("Poisoned lock") = Some(EventSink {
+            sender,
+            event_fd: event_fd.try_clone().map_err(EventStreamError::EventFd)?,
+        });
+
+        Ok(EventStream {
+            instance_id,
+            listener,
+            clients: Vec::new(),
+            event_fd,
+            receiver,
+        })
+    }
+
+    /// Accepts the pending clients.
+    fn accept_clients(&mut self) {
+        loop {
+            match self.listener.accept() {
+                Ok((client, _)) => {
+                    if self.clients.len() >= MAX_EVENT_STREAM_CLIENTS {
+                        warn!("Event stream client rejected, too many clients are connected.");
+                        continue;
+                    }
+                    if let Err(err) = client.set_nonblocking(true) {
+                        error!("Failed to set up the event stream client: {}", err);
+                        continue;
+                    }
+                    self.clients.push(client);
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) => {
+                    error!("Failed to accept an event stream client: {}", err);
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Writes the queued events to the clients, disconnecting the clients which fail to take
+    /// them.
+    fn write_events(&mut self) {
+        while let Ok((timestamp_us, event)) = self.receiver.try_recv() {
+            let record = EventRecord {
+                event: &event,
+                instance_id: &self.instance_id,
+                timestamp_us,
+            };
+            let mut line = match serde_json::to_vec(&record) {
+                Ok(line) => line,
+                Err(err) => {
+                    error!("Failed to serialize event {:?}: {}", event, err);
+                    continue;
+                }
+            };
+            line.push(b'\n');
+            self.clients.retain_mut(|client| {
+                // A partially written line cannot be completed later without blocking the VMM
+                // thread, so clients are disconnected as soon as they stop keeping up.
+                let written = client.write_all(&line);
+                if written.is_err() {
+                    METRICS.vmm.event_clients_dropped.inc();
+                }
+                written.is_ok()
+            });
+        }
+    }
+}
+
+impl MutEventSubscriber for EventStream {
+    /// Handle a read event (EPOLLIN).
+    fn process(&mut self, event: Events, _: &mut EventOps) {
+        let source = event.fd();
+        if source == self.event_fd.as_raw_fd() {
+            let _ = self.event_fd.read();
+        } else if source != self.listener.as_raw_fd() {
+            error!("Spurious EventManager event for handler: EventStream");
+            return;
+        }
+        // Clients waiting to be accepted get the events queued in the meantime, e.g. the ones
+        // emitted before the event loop started.
+        self.accept_clients();
+        self.write_events();
+    }
+
+    fn init(&mut self, ops: &mut EventOps) {
+        if let Err(err) = ops.add(Events::new(&self.listener, EventSet::IN)) {
+            error!("Failed to register event stream listener: {}", err);
+        }
+        if let Err(err) = ops.add(Events::new(&self.event_fd, EventSet::IN)) {
+            error!("Failed to register event stream event fd: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader};
+
+    use vmm_sys_util::tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_event_stream() {
+        let dir = TempDir::new().unwrap();
+        let socket_path = dir.as_path().join("events.sock");
+        let mut stream = EventStream::new(&socket_path, "vm0".to_string()).unwrap();
+
+        // Events emitted before the client is accepted are still written to it.
+        let client = UnixStream::connect(&socket_path).unwrap();
+        emit(VmEvent::DeviceError {
+            device: "block_event_stream_test".to_string(),
+            class: "backend".to_string(),
+            errno: Some(libc::EIO),
+        });
+        stream.accept_clients();
+        stream.write_events();
+        assert_eq!(stream.clients.len(), 1);
+
+        // Tests running concurrently may emit other events.
+        let mut reader = BufReader::new(client);
+        let value = loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+            if value["device"] == "block_event_stream_test" {
+                break value;
+            }
+        };
+        assert_eq!(value["event"], "device_error");
+        assert_eq!(value["class"], "backend");
+        assert_eq!(value["errno"], libc::EIO);
+        assert_eq!(value["instance_id"], "vm0");
+        assert!(value["timestamp_us"].as_u64().unwrap() > 0);
+
+        // Clients which went away are disconnected.
+        drop(reader);
+        emit(VmEvent::BootComplete);
+        stream.write_events();
+        assert!(stream.clients.is_empty());
+    }
+}
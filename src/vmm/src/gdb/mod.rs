@@ -11,7 +11,7 @@ pub mod target;
 use std::os::unix::net::UnixListener;
 use std::path::Path;
 use std::sync::mpsc::Receiver;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, OnceLock};
 
 use arch::vcpu_set_debug;
 use event_loop::event_loop;
@@ -22,6 +22,10 @@ use vm_memory::GuestAddress;
 use crate::logger::trace;
 use crate::Vmm;
 
+/// Path of the GDB socket given on the command line, used when the machine configuration does
+/// not set one.
+pub static DEFAULT_SOCKET_PATH: OnceLock<String> = OnceLock::new();
+
 /// Kickstarts the GDB debugging process, it takes in the VMM object, a slice of
 /// the paused Vcpu's, the GDB event queue which is used as a mechanism for the Vcpu's to notify
 /// our GDB thread that they've been paused, then finally the entry address of the kernel.
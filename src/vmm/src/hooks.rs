@@ -0,0 +1,582 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Hooks run at the lifecycle events of the microVM.
+//!
+//! The hooks are run by the `fc_hooks` thread, while the VMM thread waits for them to complete
+//! or time out. A hook either runs a command, or writes a notification to a Unix socket and waits
+//! for the listener to acknowledge it. Failing hooks are logged, but do not stop the action which
+//! triggered them.
+//!
+//! The commands are not run by the thread itself, as they would inherit its seccomp filter, but by
+//! a launcher process forked from Firecracker before the filter is installed.
+
+use std::ffi::{CString, OsString};
+use std::fs::OpenOptions;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::fd::{AsRawFd, RawFd};
+use std::os::unix::ffi::OsStringExt;
+use std::os::unix::net::UnixStream;
+use std::os::unix::process::ExitStatusExt;
+use std::path::Path;
+use std::process::ExitStatus;
+use std::sync::mpsc::{channel, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use seccompiler::BpfProgram;
+use serde::Serialize;
+use utils::time::{get_time_us, ClockType};
+
+use crate::logger::{error, IncMetric, METRICS};
+use crate::vmm_config::lifecycle_hooks::{
+    LifecycleEvent, LifecycleHookConfig, LifecycleHooksConfig,
+};
+
+/// Interval, in milliseconds, at which the launcher checks a running hook command for completion.
+const COMMAND_POLL_INTERVAL_MS: i32 = 10;
+/// Request asking the launcher to kill the running command. The other requests are the indices of
+/// the hooks whose command to run.
+const KILL_REQUEST: u32 = u32::MAX;
+/// Reply of the launcher once the command exited, along with its wait status.
+const REPLY_EXITED: i32 = 0;
+/// Reply of the launcher when the command could not be run, along with the error number.
+const REPLY_SPAWN_FAILED: i32 = 1;
+/// Reply of the launcher when the command could not be waited for, along with the error number.
+const REPLY_WAIT_FAILED: i32 = 2;
+
+/// Errors associated with running a lifecycle hook.
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum HookError {
+    /// Failed to run the command: {0}
+    Spawn(io::Error),
+    /// Failed to wait for the command: {0}
+    Wait(io::Error),
+    /// The command failed: {0}
+    Failed(ExitStatus),
+    /// The hook did not complete in time.
+    Timeout,
+    /// Failed to connect to the socket: {0}
+    Connect(io::Error),
+    /// Failed to write the notification: {0}
+    Write(io::Error),
+    /// Failed to read the acknowledgement: {0}
+    Read(io::Error),
+}
+
+/// Notification written to the socket of a hook, as a line of JSON.
+#[derive(Debug, Serialize)]
+struct HookNotification<'a> {
+    event: LifecycleEvent,
+    instance_id: &'a str,
+    /// Wall clock time at which the event occurred, in microseconds.
+    timestamp_us: u64,
+}
+
+/// Handle to the thread running the lifecycle hooks.
+#[derive(Debug)]
+pub struct HookRunner {
+    config: LifecycleHooksConfig,
+    events: Sender<(LifecycleEvent, Sender<()>)>,
+}
+
+impl HookRunner {
+    /// Spawns the thread running the hooks of `config`, which installs `seccomp_filter`. If a hook
+    /// runs a command, the launcher process running the commands is forked first.
+    pub fn spawn(
+        instance_id: String,
+        config: LifecycleHooksConfig,
+        seccomp_filter: Arc<BpfProgram>,
+    ) -> io::Result<Self> {
+        let (events, receiver) = channel::<(LifecycleEvent, Sender<()>)>();
+        let hooks = config.hooks.clone();
+        let mut launcher = if config.has_commands() {
+            Some(CommandLauncher::spawn(&hooks, &instance_id)?)
+        } else {
+            None
+        };
+
+        thread::Builder::new()
+            .name("fc_hooks".to_string())
+            .spawn(move || {
+                // Execution panics if filters cannot be loaded, use --no-seccomp if skipping
+                // filters altogether is the desired behaviour.
+                if let Err(err) = seccompiler::apply_filter(&seccomp_filter) {
+                    panic!(
+                        "Failed to set the requested seccomp filters on the lifecycle hooks \
+                         thread: Error: {err}"
+                    );
+                }
+                while let Ok((event, done)) = receiver.recv() {
+                    for (index, hook) in hooks.iter().enumerate() {
+                        if hook.event != event {
+                            continue;
+                        }
+                        if let Err(err) = run_hook(hook, index, launcher.as_mut(), &instance_id) {
+                            METRICS.vmm.hook_fails.inc();
+                            error!("Lifecycle hook for {} failed: {err}", event.as_str());
+                        }
+                    }
+                    // The VMM thread may have stopped waiting.
+                    let _ = done.send(());
+                }
+            })?;
+
+        Ok(HookRunner { config, events })
+    }
+
+    /// Runs the hooks of `event`, in order, and waits for them to complete.
+    pub fn run(&self, event: LifecycleEvent) {
+        if !self.config.hooks.iter().any(|hook| hook.event == event) {
+            return;
+        }
+        let (done, receiver) = channel();
+        if self.events.send((event, done)).is_err() || receiver.recv().is_err() {
+            METRICS.vmm.hook_fails.inc();
+            error!(
+                "Lifecycle hooks for {} not run: the hooks thread exited.",
+                event.as_str()
+            );
+        }
+    }
+}
+
+fn run_hook(
+    hook: &LifecycleHookConfig,
+    index: usize,
+    launcher: Option<&mut CommandLauncher>,
+    instance_id: &str,
+) -> Result<(), HookError> {
+    let timeout = Duration::from_millis(hook.timeout_ms);
+    match (launcher, &hook.command, &hook.socket_path) {
+        (Some(launcher), Some(_), _) => launcher.run(index, timeout),
+        (_, None, Some(path)) => notify_socket(path, hook.event, instance_id, timeout),
+        // The launcher is forked whenever a hook runs a command, and hooks without a command nor
+        // a socket are rejected by `LifecycleHooksConfig::validate`.
+        _ => Ok(()),
+    }
+}
+
+/// Handle to the process running the hook commands.
+///
+/// The launcher is forked before the `fc_hooks` thread installs its seccomp filter, and only runs
+/// the commands of the hooks it was forked with, identified by their index, so that the
+/// Firecracker process can't make it run anything else.
+#[derive(Debug)]
+struct CommandLauncher {
+    socket: UnixStream,
+}
+
+impl CommandLauncher {
+    /// Forks the launcher of the commands of `hooks`.
+    fn spawn(hooks: &[LifecycleHookConfig], instance_id: &str) -> io::Result<Self> {
+        // Everything the launcher needs is allocated before the fork.
+        let commands = hooks
+            .iter()
+            .map(|hook| {
+                hook.command
+                    .as_ref()
+                    .map(|command| PreparedCommand::new(command, hook.event, instance_id))
+                    .transpose()
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        // The standard input and output of Firecracker may be attached to the serial console.
+        let dev_null = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/null")?;
+        let (socket, launcher_socket) = UnixStream::pair()?;
+
+        // SAFETY: The other threads of the process may hold locks, e.g. the one of the allocator,
+        // so the child only makes async-signal-safe calls, and never returns.
+        match unsafe { libc::fork() } {
+            -1 => Err(io::Error::last_os_error()),
+            0 => launcher_main(
+                socket.as_raw_fd(),
+                launcher_socket.as_raw_fd(),
+                dev_null.as_raw_fd(),
+                &commands,
+            ),
+            _ => Ok(CommandLauncher { socket }),
+        }
+    }
+
+    /// Runs the command of the hook at `index`, and waits for it to complete, killing it if it
+    /// doesn't in time.
+    fn run(&mut self, index: usize, timeout: Duration) -> Result<(), HookError> {
+        let request = u32::try_from(index)
+            .map_err(|_| HookError::Spawn(io::Error::from(io::ErrorKind::InvalidInput)))?;
+        self.socket
+            .write_all(&request.to_ne_bytes())
+            .map_err(HookError::Spawn)?;
+        self.socket
+            .set_read_timeout(Some(timeout))
+            .map_err(HookError::Wait)?;
+
+        let mut reply = [0u8; 8];
+        if let Err(err) = self.socket.read_exact(&mut reply) {
+            if !matches!(
+                err.kind(),
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+            ) {
+                return Err(HookError::Wait(err));
+            }
+            // The launcher ignores the request if the command exited in the meantime, in which
+            // case its reply is already on the way.
+            self.socket
+                .write_all(&KILL_REQUEST.to_ne_bytes())
+                .map_err(HookError::Wait)?;
+            self.socket
+                .set_read_timeout(None)
+                .map_err(HookError::Wait)?;
+            self.socket
+                .read_exact(&mut reply)
+                .map_err(HookError::Wait)?;
+            return Err(HookError::Timeout);
+        }
+
+        // The unwraps are safe because the slices are 4 bytes long.
+        let kind = i32::from_ne_bytes(reply[..4].try_into().unwrap());
+        let value = i32::from_ne_bytes(reply[4..].try_into().unwrap());
+        match kind {
+            REPLY_EXITED => {
+                let status = ExitStatus::from_raw(value);
+                if !status.success() {
+                    return Err(HookError::Failed(status));
+                }
+                Ok(())
+            }
+            REPLY_SPAWN_FAILED => Err(HookError::Spawn(io::Error::from_raw_os_error(value))),
+            _ => Err(HookError::Wait(io::Error::from_raw_os_error(value))),
+        }
+    }
+}
+
+/// A hook command along with its environment, ready to be executed without allocating.
+#[derive(Debug)]
+struct PreparedCommand {
+    /// The strings `argv` and `envp` point to.
+    _strings: Vec<CString>,
+    /// Null-terminated arguments of the command, starting with the program.
+    argv: Vec<*const libc::c_char>,
+    /// Null-terminated environment of the command.
+    envp: Vec<*const libc::c_char>,
+}
+
+impl PreparedCommand {
+    fn new(command: &[String], event: LifecycleEvent, instance_id: &str) -> io::Result<Self> {
+        const EVENT_VAR: &str = "FC_LIFECYCLE_EVENT";
+        const INSTANCE_ID_VAR: &str = "FC_INSTANCE_ID";
+
+        let args = command.iter().map(|arg| arg.clone().into_bytes());
+        // The command inherits the environment of Firecracker.
+        let vars = std::env::vars_os()
+            .filter(|(name, _)| name != EVENT_VAR && name != INSTANCE_ID_VAR)
+            .map(|(name, value)| {
+                let mut var = name;
+                var.push("=");
+                var.push(value);
+                var
+            })
+            .chain([
+                OsString::from(format!("{EVENT_VAR}={}", event.as_str())),
+                OsString::from(format!("{INSTANCE_ID_VAR}={instance_id}")),
+            ])
+            .map(OsStringExt::into_vec);
+
+        let strings = args
+            .chain(vars)
+            .map(CString::new)
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut pointers = strings.iter().map(|string| string.as_ptr());
+        let argv = pointers
+            .by_ref()
+            .take(command.len())
+            .chain([std::ptr::null()])
+            .collect();
+        let envp = pointers.chain([std::ptr::null()]).collect();
+        Ok(PreparedCommand {
+            _strings: strings,
+            argv,
+            envp,
+        })
+    }
+}
+
+/// Main loop of the launcher process, which runs a command for each request received on `socket`,
+/// and exits once the Firecracker process closes its end of the socket. Only async-signal-safe
+/// calls are made.
+fn launcher_main(
+    parent_socket: RawFd,
+    socket: RawFd,
+    dev_null: RawFd,
+    commands: &[Option<PreparedCommand>],
+) -> ! {
+    // SAFETY: The end of the socket used by Firecracker is a valid file descriptor.
+    unsafe { libc::close(parent_socket) };
+    while let Some(request) = read_request(socket) {
+        // Kill requests sent as the command exited are ignored, as well as invalid requests.
+        let Some(Some(command)) = usize::try_from(request)
+            .ok()
+            .and_then(|index| commands.get(index))
+        else {
+            continue;
+        };
+        let (kind, value) = launch_command(command, socket, dev_null);
+        let mut reply = [0u8; 8];
+        reply[..4].copy_from_slice(&kind.to_ne_bytes());
+        reply[4..].copy_from_slice(&value.to_ne_bytes());
+        // SAFETY: The buffer is valid for its length. A short write can't happen for such a small
+        // message on a stream socket, and an error means that Firecracker is gone.
+        unsafe { libc::write(socket, reply.as_ptr().cast(), reply.len()) };
+    }
+    // SAFETY: Exiting without running the handlers registered by Firecracker.
+    unsafe { libc::_exit(0) }
+}
+
+/// Reads a request from `socket`, or returns None once it is closed.
+fn read_request(socket: RawFd) -> Option<u32> {
+    let mut request = [0u8; 4];
+    let mut len = 0;
+    while len < request.len() {
+        // SAFETY: The buffer is valid for the remaining length.
+        let ret = unsafe {
+            libc::read(
+                socket,
+                request[len..].as_mut_ptr().cast(),
+                request.len() - len,
+            )
+        };
+        match usize::try_from(ret) {
+            Ok(0) => return None,
+            Ok(read) => len += read,
+            Err(_) if io::Error::last_os_error().raw_os_error() == Some(libc::EINTR) => (),
+            Err(_) => return None,
+        }
+    }
+    Some(u32::from_ne_bytes(request))
+}
+
+/// Runs `command`, and waits for it to exit, killing it when asked to on `socket`. Returns the
+/// reply to send to Firecracker.
+fn launch_command(command: &PreparedCommand, socket: RawFd, dev_null: RawFd) -> (i32, i32) {
+    let errno = || {
+        io::Error::last_os_error()
+            .raw_os_error()
+            .unwrap_or(libc::EIO)
+    };
+
+    // The command reports through this pipe why it couldn't be executed, the pipe is closed
+    // without any data otherwise.
+    let mut pipe = [0; 2];
+    // SAFETY: The array has room for the two file descriptors.
+    if unsafe { libc::pipe2(pipe.as_mut_ptr(), libc::O_CLOEXEC) } < 0 {
+        return (REPLY_SPAWN_FAILED, errno());
+    }
+    // SAFETY: The launcher only has one thread, the child execs the command or exits.
+    let pid = unsafe { libc::fork() };
+    if pid == 0 {
+        exec_command(command, dev_null, pipe[1]);
+    }
+    let fork_errno = errno();
+    let mut exec_errno = [0u8; 4];
+    // SAFETY: The pipe file descriptors are valid, and the buffer is valid for its length.
+    let exec_failed = unsafe {
+        libc::close(pipe[1]);
+        let failed =
+            pid > 0 && libc::read(pipe[0], exec_errno.as_mut_ptr().cast(), exec_errno.len()) == 4;
+        libc::close(pipe[0]);
+        failed
+    };
+    if pid < 0 {
+        return (REPLY_SPAWN_FAILED, fork_errno);
+    }
+
+    let mut status = 0;
+    loop {
+        // SAFETY: `status` is valid for writes.
+        match unsafe { libc::waitpid(pid, &mut status, libc::WNOHANG) } {
+            0 => (),
+            -1 if errno() == libc::EINTR => continue,
+            -1 => return (REPLY_WAIT_FAILED, errno()),
+            _ if exec_failed => return (REPLY_SPAWN_FAILED, i32::from_ne_bytes(exec_errno)),
+            _ => return (REPLY_EXITED, status),
+        }
+        let mut pollfd = libc::pollfd {
+            fd: socket,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // SAFETY: `pollfd` is valid for the single file descriptor it holds.
+        if unsafe { libc::poll(&mut pollfd, 1, COMMAND_POLL_INTERVAL_MS) } <= 0 {
+            continue;
+        }
+        let request = read_request(socket);
+        if request == Some(KILL_REQUEST) || request.is_none() {
+            // SAFETY: The command hasn't been waited for, so its pid can't have been reused.
+            unsafe { libc::kill(pid, libc::SIGKILL) };
+        }
+        if request.is_none() {
+            // Firecracker is gone.
+            // SAFETY: `status` is valid for writes, and the handlers registered by Firecracker
+            // are not run on exit.
+            unsafe {
+                libc::waitpid(pid, &mut status, 0);
+                libc::_exit(0)
+            }
+        }
+    }
+}
+
+/// Executes `command` in the child forked by the launcher, or reports why it couldn't on
+/// `error_pipe` and exits.
+fn exec_command(command: &PreparedCommand, dev_null: RawFd, error_pipe: RawFd) -> ! {
+    // SAFETY: The file descriptors are valid, `argv` and `envp` are null-terminated arrays of
+    // pointers to C strings, and only async-signal-safe functions are called.
+    unsafe {
+        let mut mask = std::mem::zeroed();
+        libc::sigemptyset(&mut mask);
+        // The command doesn't inherit the signal mask and the ignored SIGPIPE of Firecracker.
+        if libc::dup2(dev_null, libc::STDIN_FILENO) >= 0
+            && libc::dup2(dev_null, libc::STDOUT_FILENO) >= 0
+            && libc::dup2(dev_null, libc::STDERR_FILENO) >= 0
+            && libc::sigprocmask(libc::SIG_SETMASK, &mask, std::ptr::null_mut()) == 0
+            && libc::signal(libc::SIGPIPE, libc::SIG_DFL) != libc::SIG_ERR
+        {
+            libc::execvpe(
+                command.argv[0],
+                command.argv.as_ptr(),
+                command.envp.as_ptr(),
+            );
+        }
+        let errno = io::Error::last_os_error()
+            .raw_os_error()
+            .unwrap_or(libc::EIO)
+            .to_ne_bytes();
+        libc::write(error_pipe, errno.as_ptr().cast(), errno.len());
+        libc::_exit(127)
+    }
+}
+
+fn notify_socket(
+    path: &Path,
+    event: LifecycleEvent,
+    instance_id: &str,
+    timeout: Duration,
+) -> Result<(), HookError> {
+    let mut line = serde_json::to_vec(&HookNotification {
+        event,
+        instance_id,
+        timestamp_us: get_time_us(ClockType::Real),
+    })
+    .map_err(|err| HookError::Write(err.into()))?;
+    line.push(b'\n');
+
+    let mut stream = UnixStream::connect(path).map_err(HookError::Connect)?;
+    stream
+        .set_write_timeout(Some(timeout))
+        .map_err(HookError::Write)?;
+    stream
+        .set_read_timeout(Some(timeout))
+        .map_err(HookError::Read)?;
+    stream
+        .write_all(&line)
+        .map_err(timeout_error(HookError::Write))?;
+
+    // The listener acknowledges the notification by replying with a line, or by closing the
+    // connection.
+    BufReader::new(stream)
+        .read_line(&mut String::new())
+        .map_err(timeout_error(HookError::Read))?;
+    Ok(())
+}
+
+/// Maps the errors of socket operations timing out to [`HookError::Timeout`].
+fn timeout_error(other: fn(io::Error) -> HookError) -> impl Fn(io::Error) -> HookError {
+    move |err| match err.kind() {
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => HookError::Timeout,
+        _ => other(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::net::UnixListener;
+
+    use vmm_sys_util::tempdir::TempDir;
+
+    use super::*;
+
+    fn hook(command: Option<Vec<&str>>, socket_path: Option<&Path>) -> LifecycleHookConfig {
+        LifecycleHookConfig {
+            event: LifecycleEvent::PreSnapshot,
+            command: command.map(|command| command.into_iter().map(String::from).collect()),
+            socket_path: socket_path.map(Path::to_path_buf),
+            timeout_ms: 200,
+        }
+    }
+
+    #[test]
+    fn test_run_command() {
+        let hooks: Vec<_> = [
+            vec!["true"],
+            vec!["false"],
+            vec!["sleep", "5"],
+            vec!["/nonexistent"],
+            // The event and the instance ID are passed in the environment of the command.
+            vec![
+                "sh",
+                "-c",
+                "test $FC_LIFECYCLE_EVENT = pre_snapshot -a $FC_INSTANCE_ID = vm0",
+            ],
+        ]
+        .into_iter()
+        .map(|command| hook(Some(command), None))
+        .collect();
+        let mut launcher = CommandLauncher::spawn(&hooks, "vm0").unwrap();
+        let mut run = |index| run_hook(&hooks[index], index, Some(&mut launcher), "vm0");
+
+        run(0).unwrap();
+        assert!(matches!(run(1), Err(HookError::Failed(_))));
+        assert!(matches!(run(2), Err(HookError::Timeout)));
+        // The launcher keeps running the commands once one was killed.
+        run(0).unwrap();
+        assert!(matches!(run(3), Err(HookError::Spawn(_))));
+        run(4).unwrap();
+    }
+
+    #[test]
+    fn test_notify_socket() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.as_path().join("hooks.sock");
+        assert!(matches!(
+            run_hook(&hook(None, Some(&path)), 0, None, "vm0"),
+            Err(HookError::Connect(_))
+        ));
+
+        let listener = UnixListener::bind(&path).unwrap();
+        let acknowledge = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut line = String::new();
+            BufReader::new(stream.try_clone().unwrap())
+                .read_line(&mut line)
+                .unwrap();
+            stream.write_all(b"ok\n").unwrap();
+            // The second notification is not acknowledged, and the connection is kept open.
+            let (unacknowledged, _) = listener.accept().unwrap();
+            (line, unacknowledged)
+        });
+        run_hook(&hook(None, Some(&path)), 0, None, "vm0").unwrap();
+        assert!(matches!(
+            run_hook(&hook(None, Some(&path)), 0, None, "vm0"),
+            Err(HookError::Timeout)
+        ));
+
+        let (line, _unacknowledged) = acknowledge.join().unwrap();
+        let notification: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(notification["event"], "pre_snapshot");
+        assert_eq!(notification["instance_id"], "vm0");
+        assert!(notification["timestamp_us"].as_u64().unwrap() > 0);
+    }
+}
@@ -0,0 +1,298 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Landlock sandboxing of the VMM, which restricts the files it can open once the microVM is
+//! built to the ones it already has open.
+//!
+//! This is defense-in-depth on top of the seccomp filters, for hosts on which the jailer can not
+//! be used. The restriction applies to the thread building the microVM and to the threads it
+//! spawns afterwards, i.e. the VMM and vCPU threads, but not to the API threads.
+
+use std::fs::{self, File};
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+
+use crate::logger::info;
+use crate::state_dir::STATE_DIR;
+
+/// Landlock configuration of the process. The VMM is not sandboxed unless it is set.
+pub static LANDLOCK: OnceLock<LandlockConfig> = OnceLock::new();
+
+// Set once the VMM is sandboxed. A sandboxed thread can not list its open files anymore, so
+// rebuilding the microVM, e.g. when restoring it in place, keeps the initial sandbox.
+static RESTRICTED: AtomicBool = AtomicBool::new(false);
+
+const LANDLOCK_CREATE_RULESET_VERSION: u32 = 1 << 0;
+const LANDLOCK_RULE_PATH_BENEATH: u32 = 1;
+
+const ACCESS_FS_EXECUTE: u64 = 1 << 0;
+const ACCESS_FS_WRITE_FILE: u64 = 1 << 1;
+const ACCESS_FS_READ_FILE: u64 = 1 << 2;
+// All the access rights of the first Landlock ABI, up to `LANDLOCK_ACCESS_FS_MAKE_SYM`.
+const ACCESS_FS_ABI_1: u64 = (1 << 13) - 1;
+// Added by the second Landlock ABI.
+const ACCESS_FS_REFER: u64 = 1 << 13;
+// Added by the third Landlock ABI.
+const ACCESS_FS_TRUNCATE: u64 = 1 << 14;
+
+#[repr(C)]
+struct LandlockRulesetAttr {
+    handled_access_fs: u64,
+}
+
+#[repr(C, packed)]
+struct LandlockPathBeneathAttr {
+    allowed_access: u64,
+    parent_fd: RawFd,
+}
+
+/// Errors associated with the Landlock sandboxing of the VMM.
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum LandlockError {
+    /// Landlock is not supported by the host kernel: {0}
+    Unsupported(io::Error),
+    /// Failed to create the Landlock ruleset: {0}
+    CreateRuleset(io::Error),
+    /// Failed to list the open files: {0}
+    OpenFiles(io::Error),
+    /// Failed to allow access to {0:?}: {1}
+    AllowPath(PathBuf, io::Error),
+    /// Failed to set no_new_privs: {0}
+    NoNewPrivs(io::Error),
+    /// Failed to restrict the VMM: {0}
+    RestrictSelf(io::Error),
+}
+
+/// Configuration of the Landlock sandbox of the VMM.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct LandlockConfig {
+    /// Files, or directories along with everything beneath them, the VMM keeps full access to on
+    /// top of the files it has open, e.g. the directory in which snapshots are created.
+    pub allowed_paths: Vec<PathBuf>,
+}
+
+impl LandlockConfig {
+    /// Restricts the calling thread, and the threads it spawns afterwards, to the files it has
+    /// open, with the same access mode, to the state directory and to the allowed paths.
+    ///
+    /// Does nothing if the VMM is already sandboxed.
+    pub fn restrict_self(&self) -> Result<(), LandlockError> {
+        if RESTRICTED.load(Ordering::Acquire) {
+            return Ok(());
+        }
+
+        let abi = abi_version()?;
+        let mut handled_access = ACCESS_FS_ABI_1;
+        let mut file_access = ACCESS_FS_EXECUTE | ACCESS_FS_WRITE_FILE | ACCESS_FS_READ_FILE;
+        if abi >= 2 {
+            handled_access |= ACCESS_FS_REFER;
+        }
+        if abi >= 3 {
+            handled_access |= ACCESS_FS_TRUNCATE;
+            file_access |= ACCESS_FS_TRUNCATE;
+        }
+
+        let open_fds = open_fds()?;
+        let ruleset = create_ruleset(handled_access)?;
+        let mut rules = 0;
+        for fd in open_fds {
+            if allow_open_file(&ruleset, fd, file_access) {
+                rules += 1;
+            }
+        }
+        let allowed_paths = STATE_DIR
+            .path()
+            .into_iter()
+            .chain(self.allowed_paths.clone());
+        for path in allowed_paths {
+            allow_path(&ruleset, &path, handled_access, file_access)
+                .map_err(|err| LandlockError::AllowPath(path, err))?;
+            rules += 1;
+        }
+
+        // SAFETY: Safe because the arguments are valid for `PR_SET_NO_NEW_PRIVS`.
+        if unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } != 0 {
+            return Err(LandlockError::NoNewPrivs(io::Error::last_os_error()));
+        }
+        // SAFETY: Safe because `ruleset` is a valid Landlock ruleset and no flags are set.
+        if unsafe { libc::syscall(libc::SYS_landlock_restrict_self, ruleset.as_raw_fd(), 0) } != 0 {
+            return Err(LandlockError::RestrictSelf(io::Error::last_os_error()));
+        }
+        RESTRICTED.store(true, Ordering::Release);
+        info!("Sandboxed the VMM with {rules} Landlock rules, using Landlock ABI {abi}.");
+        Ok(())
+    }
+}
+
+fn abi_version() -> Result<i64, LandlockError> {
+    // SAFETY: Safe because querying the ABI version takes no ruleset attributes.
+    let abi = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_create_ruleset,
+            std::ptr::null::<LandlockRulesetAttr>(),
+            0,
+            LANDLOCK_CREATE_RULESET_VERSION,
+        )
+    };
+    if abi < 0 {
+        return Err(LandlockError::Unsupported(io::Error::last_os_error()));
+    }
+    Ok(abi)
+}
+
+fn create_ruleset(handled_access: u64) -> Result<OwnedFd, LandlockError> {
+    let attr = LandlockRulesetAttr {
+        handled_access_fs: handled_access,
+    };
+    // SAFETY: Safe because `attr` is a valid ruleset attribute of the given size.
+    let fd = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_create_ruleset,
+            &attr as *const LandlockRulesetAttr,
+            std::mem::size_of::<LandlockRulesetAttr>(),
+            0,
+        )
+    };
+    if fd < 0 {
+        return Err(LandlockError::CreateRuleset(io::Error::last_os_error()));
+    }
+    // File descriptors fit in a `RawFd`.
+    #[allow(clippy::cast_possible_truncation)]
+    let fd = fd as RawFd;
+    // SAFETY: Safe because the file descriptor is a new valid ruleset, owned by nothing else.
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+fn add_rule(ruleset: &OwnedFd, fd: RawFd, allowed_access: u64) -> io::Result<()> {
+    let attr = LandlockPathBeneathAttr {
+        allowed_access,
+        parent_fd: fd,
+    };
+    // SAFETY: Safe because `attr` is a valid path beneath attribute and no flags are set.
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_add_rule,
+            ruleset.as_raw_fd(),
+            LANDLOCK_RULE_PATH_BENEATH,
+            &attr as *const LandlockPathBeneathAttr,
+            0,
+        )
+    };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn open_fds() -> Result<Vec<RawFd>, LandlockError> {
+    let entries = fs::read_dir("/proc/self/fd").map_err(LandlockError::OpenFiles)?;
+    let mut fds = Vec::new();
+    for entry in entries {
+        let entry = entry.map_err(LandlockError::OpenFiles)?;
+        if let Some(fd) = entry.file_name().to_str().and_then(|fd| fd.parse().ok()) {
+            fds.push(fd);
+        }
+    }
+    Ok(fds)
+}
+
+// Allows opening the file behind `fd` again with the access mode it is open with. Returns whether
+// it is allowed, which it is not for the files which have no path, e.g. sockets, and for the
+// directories.
+fn allow_open_file(ruleset: &OwnedFd, fd: RawFd, file_access: u64) -> bool {
+    // SAFETY: Safe because `stat` is plain data.
+    let mut stat = unsafe { std::mem::zeroed::<libc::stat>() };
+    // SAFETY: Safe because `stat` is a valid buffer.
+    if unsafe { libc::fstat(fd, &mut stat) } != 0 {
+        return false;
+    }
+    if !matches!(
+        stat.st_mode & libc::S_IFMT,
+        libc::S_IFREG | libc::S_IFCHR | libc::S_IFBLK
+    ) {
+        return false;
+    }
+    // SAFETY: Safe because `F_GETFL` takes no argument.
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+    if flags < 0 || flags & libc::O_PATH != 0 {
+        return false;
+    }
+    let access = match flags & libc::O_ACCMODE {
+        libc::O_RDONLY => ACCESS_FS_READ_FILE,
+        libc::O_WRONLY => ACCESS_FS_WRITE_FILE | ACCESS_FS_TRUNCATE,
+        _ => ACCESS_FS_READ_FILE | ACCESS_FS_WRITE_FILE | ACCESS_FS_TRUNCATE,
+    };
+    // Files on pseudo filesystems, e.g. anonymous inodes, can not be allowed.
+    add_rule(ruleset, fd, access & file_access).is_ok()
+}
+
+// Allows full access to `path`, or to everything beneath it if it is a directory.
+fn allow_path(
+    ruleset: &OwnedFd,
+    path: &Path,
+    handled_access: u64,
+    file_access: u64,
+) -> io::Result<()> {
+    let file = File::options()
+        .read(true)
+        .custom_flags(libc::O_PATH | libc::O_CLOEXEC)
+        .open(path)?;
+    let access = if file.metadata()?.is_dir() {
+        handled_access
+    } else {
+        file_access
+    };
+    add_rule(ruleset, file.as_raw_fd(), access)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use vmm_sys_util::tempdir::TempDir;
+    use vmm_sys_util::tempfile::TempFile;
+
+    use super::*;
+
+    #[test]
+    fn test_restrict_self() {
+        // Kept open, unlike the other file.
+        let open_file = TempFile::new().unwrap();
+        let other_dir = TempDir::new().unwrap();
+        let other_file = other_dir.as_path().join("other");
+        fs::write(&other_file, b"").unwrap();
+        let allowed_dir = TempDir::new().unwrap();
+        let allowed_file = allowed_dir.as_path().join("allowed");
+        let config = LandlockConfig {
+            allowed_paths: vec![allowed_dir.as_path().to_path_buf()],
+        };
+        let open_path = open_file.as_path().to_path_buf();
+
+        // The sandbox only applies to the thread restricting itself, and the temporary files are
+        // removed by the test thread.
+        thread::spawn(move || {
+            match config.restrict_self() {
+                Err(LandlockError::Unsupported(_)) => return,
+                res => res.unwrap(),
+            }
+            File::options()
+                .read(true)
+                .write(true)
+                .open(open_path)
+                .unwrap();
+            assert_eq!(
+                File::open(other_file).unwrap_err().kind(),
+                io::ErrorKind::PermissionDenied
+            );
+            File::create(allowed_file).unwrap();
+            // Restricting the VMM again does nothing.
+            config.restrict_self().unwrap();
+        })
+        .join()
+        .unwrap();
+    }
+}
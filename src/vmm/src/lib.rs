@@ -70,24 +70,40 @@ pub mod io_uring;
 pub mod rate_limiter;
 
 /// Module for handling ACPI tables.
-/// Currently, we only use ACPI on x86 microVMs.
-#[cfg(target_arch = "x86_64")]
 pub mod acpi;
+/// Background snapshots of the guest memory.
+pub mod background_snapshot;
+/// Timestamps of the boot phases of the microVM.
+pub mod boot_timings;
 /// Handles setup and initialization a `Vmm` object.
 pub mod builder;
+/// Chaos mode, snapshotting and restoring the microVM in place at random intervals.
+pub mod chaos;
+/// Snapshots of the failures of the microVM.
+pub mod coredump;
 /// Types for guest configuration.
 pub mod cpu_config;
 pub(crate) mod device_manager;
 /// Emulates virtual and hardware devices.
 #[allow(missing_docs)]
 pub mod devices;
+/// Diagnostic reports triggered by a signal.
+pub mod diagnostics;
 /// minimalist HTTP/TCP/IPv4 stack named DUMBO
 pub mod dumbo;
+/// Stream of the events of the microVM.
+pub mod event_stream;
 /// Support for GDB debugging the guest
 #[cfg(feature = "gdb")]
 pub mod gdb;
+/// Hooks run at the lifecycle events of the microVM.
+pub mod hooks;
+/// Landlock sandboxing of the VMM.
+pub mod landlock;
 /// Logger
 pub mod logger;
+/// Migrations of the microVM state between versions of the snapshot format.
+pub mod migrations;
 /// microVM Metadata Service MMDS
 pub mod mmds;
 /// Save/restore utilities.
@@ -102,6 +118,8 @@ pub mod seccomp_filters;
 pub mod signal_handler;
 /// Serialization and deserialization facilities
 pub mod snapshot;
+/// Crash-safe record of the state of the microVM.
+pub mod state_dir;
 /// Utility functions for integration and benchmark testing
 pub mod test_utils;
 /// Utility functions and struct
@@ -122,33 +140,49 @@ use device_manager::acpi::ACPIDeviceManager;
 use device_manager::resources::ResourceAllocator;
 use devices::acpi::vmgenid::VmGenIdError;
 use event_manager::{EventManager as BaseEventManager, EventOps, Events, MutEventSubscriber};
-use seccompiler::BpfProgram;
+use seccompiler::{BpfProgram, BpfThreadMap};
+use timerfd::{SetTimeFlags, TimerFd, TimerState};
 use userfaultfd::Uffd;
 use vmm_sys_util::epoll::EventSet;
 use vmm_sys_util::eventfd::EventFd;
 use vmm_sys_util::terminal::Terminal;
+use vstate::vcpu::limiter::CpuLimiter;
 use vstate::vcpu::{self, KvmVcpuConfigureError, StartThreadedError, VcpuSendEventError};
 
 use crate::arch::DeviceType;
+use crate::background_snapshot::{BackgroundSnapshotStatus, SnapshotWriter};
+use crate::coredump::CoredumpSnapshot;
 use crate::cpu_config::templates::CpuConfiguration;
 #[cfg(target_arch = "x86_64")]
 use crate::device_manager::legacy::PortIODeviceManager;
 use crate::device_manager::mmio::MMIODeviceManager;
 use crate::devices::legacy::{IER_RDA_BIT, IER_RDA_OFFSET};
 use crate::devices::virtio::balloon::{
-    Balloon, BalloonConfig, BalloonError, BalloonStats, BALLOON_DEV_ID,
+    Balloon, BalloonAutoTargetConfig, BalloonConfig, BalloonError, BalloonStats,
+    BalloonStatsPushConfig, BALLOON_DEV_ID,
 };
 use crate::devices::virtio::block::device::Block;
+use crate::devices::virtio::net::flows::{FlowTable, NetFlows};
 use crate::devices::virtio::net::Net;
 use crate::devices::virtio::{TYPE_BALLOON, TYPE_BLOCK, TYPE_NET};
-use crate::logger::{error, info, warn, MetricsError, METRICS};
+use crate::hooks::HookRunner;
+use crate::logger::{error, info, warn, IncMetric, MetricsError, METRICS};
 use crate::persist::{MicrovmState, MicrovmStateError, VmInfo};
+use crate::rate_limiter::persist::save_shared_rate_limiters;
 use crate::rate_limiter::BucketUpdate;
 use crate::snapshot::Persist;
 use crate::utils::u64_to_usize;
-use crate::vmm_config::instance_info::{InstanceInfo, VmState};
+use crate::vmm_config::cpu_limiter::{CpuLimiterConfig, CpuLimiterConfigError};
+use crate::vmm_config::instance_info::{InstanceInfo, ProcessInfo, VmState, PROCESS_INFO};
+use crate::vmm_config::lifecycle_hooks::{LifecycleEvent, LifecycleHooksConfig};
+use crate::vmm_config::memory_target::{balloon_target_mib, MemoryTargetError, MemoryTargetStatus};
+use crate::vmm_config::net::NetworkCaptureConfig;
+use crate::vmm_config::seccomp::SeccompFiltersError;
+use crate::vmm_config::shutdown::{ShutdownConfig, ShutdownError};
+use crate::vmm_config::snapshot::CreateSnapshotParams;
+use crate::vmm_config::{DeviceQueuesState, RateLimiterUpdate};
 use crate::vstate::memory::{
-    GuestMemory, GuestMemoryExtension, GuestMemoryMmap, GuestMemoryRegion,
+    GuestMemory, GuestMemoryExtension, GuestMemoryMmap, GuestMemoryRegion, MemorySlotsUsage,
 };
 use crate::vstate::vcpu::VcpuState;
 pub use crate::vstate::vcpu::{Vcpu, VcpuConfig, VcpuEvent, VcpuHandle, VcpuResponse};
@@ -213,8 +247,13 @@ pub enum VmmError {
     DirtyBitmap(kvm_ioctls::Error),
     /// Event fd error: {0}
     EventFd(io::Error),
+    /// Failed to open the host side of the serial console: {0}
+    SerialBackend(io::Error),
     /// I8042 error: {0}
     I8042Error(devices::legacy::I8042DeviceError),
+    #[cfg(target_arch = "x86_64")]
+    /// The i8042 device is disabled.
+    I8042Disabled,
     /// Cannot access kernel file: {0}
     KernelFile(io::Error),
     #[cfg(target_arch = "x86_64")]
@@ -253,6 +292,12 @@ pub enum VmmError {
     VcpuMessage,
     /// Cannot spawn Vcpu thread: {0}
     VcpuSpawn(io::Error),
+    /// Cannot spawn the snapshot writer thread: {0}
+    SnapshotWriterSpawn(io::Error),
+    /// Cannot spawn the lifecycle hooks thread: {0}
+    HookRunnerSpawn(io::Error),
+    /// Cannot spawn the deferred devices thread: {0}
+    DeferredDevicesSpawn(io::Error),
     /// Vm error: {0}
     Vm(vstate::vm::VmError),
     /// Error thrown by observer object on Vmm initialization: {0}
@@ -298,6 +343,18 @@ pub enum DumpCpuConfigError {
     NotAllowed(String),
 }
 
+/// Full snapshot created by the VMM the first time the guest reports a kernel panic through the
+/// pvpanic device.
+#[derive(Debug)]
+pub struct PanicSnapshot {
+    /// Signaled by the pvpanic device when the guest panics.
+    pub panic_evt: EventFd,
+    /// Information about the microVM stored in the snapshot.
+    pub vm_info: VmInfo,
+    /// Parameters of the full snapshot.
+    pub params: CreateSnapshotParams,
+}
+
 /// Contains the state and associated methods required for the Firecracker VMM.
 #[derive(Debug)]
 pub struct Vmm {
@@ -316,6 +373,18 @@ pub struct Vmm {
     vcpus_handles: Vec<VcpuHandle>,
     // Used by Vcpus and devices to initiate teardown; Vmm should never write here.
     vcpus_exit_evt: EventFd,
+    // Stops the microVM when the guest does not shut down within the requested timeout.
+    shutdown_timer: TimerFd,
+    // Writes the memory files of background snapshots.
+    snapshot_writer: Option<SnapshotWriter>,
+    // Runs the hooks configured for the lifecycle events of the microVM.
+    hook_runner: Option<HookRunner>,
+    // Throttles the vCPUs to the CPU allocation of the microVM.
+    cpu_limiter: Option<Arc<CpuLimiter>>,
+    // Snapshots the microVM when the guest panics, until the first panic.
+    panic_snapshot: Option<PanicSnapshot>,
+    // Snapshots the microVM when it fails.
+    coredump_snapshot: Option<CoredumpSnapshot>,
 
     // Allocator for guest resources
     resource_allocator: ResourceAllocator,
@@ -384,6 +453,9 @@ impl Vmm {
 
         for mut vcpu in vcpus.drain(..) {
             vcpu.set_mmio_bus(self.mmio_device_manager.bus.clone());
+            if let Some(cpu_limiter) = &self.cpu_limiter {
+                vcpu.set_cpu_limiter(cpu_limiter.clone());
+            }
             #[cfg(target_arch = "x86_64")]
             vcpu.kvm_vcpu
                 .set_pio_bus(self.pio_device_manager.io_bus.clone());
@@ -400,6 +472,7 @@ impl Vmm {
 
     /// Sends a resume command to the vCPUs.
     pub fn resume_vm(&mut self) -> Result<(), VmmError> {
+        self.mmio_device_manager.set_net_devices_paused(false);
         self.mmio_device_manager.kick_devices();
 
         // Send the events.
@@ -440,6 +513,7 @@ impl Vmm {
             return Err(VmmError::VcpuMessage);
         }
 
+        self.mmio_device_manager.set_net_devices_paused(true);
         self.instance_info.state = VmState::Paused;
         Ok(())
     }
@@ -449,6 +523,54 @@ impl Vmm {
         &self.guest_memory
     }
 
+    /// Spawns the thread writing the memory files of background snapshots. This must be done
+    /// before installing the seccomp filters of the VMM thread, which do not allow creating
+    /// threads.
+    pub fn start_snapshot_writer(&mut self, seccomp_filter: Arc<BpfProgram>) -> io::Result<()> {
+        self.snapshot_writer = Some(SnapshotWriter::spawn(seccomp_filter)?);
+        Ok(())
+    }
+
+    /// Spawns the thread running the lifecycle hooks of `config`, if any. This must be done
+    /// before installing the seccomp filters of the VMM thread, which do not allow creating
+    /// threads.
+    pub fn start_hook_runner(
+        &mut self,
+        config: &LifecycleHooksConfig,
+        seccomp_filter: Arc<BpfProgram>,
+    ) -> io::Result<()> {
+        if config.has_commands() {
+            // The launcher of the hook commands is forked from Firecracker, and has no use for
+            // guest memory.
+            self.guest_memory
+                .exclude_from_fork()
+                .map_err(io::Error::other)?;
+        }
+        if !config.hooks.is_empty() {
+            self.hook_runner = Some(HookRunner::spawn(
+                self.instance_info.id.clone(),
+                config.clone(),
+                seccomp_filter,
+            )?);
+        }
+        Ok(())
+    }
+
+    /// Runs the lifecycle hooks of `event`, and waits for them to complete.
+    pub fn run_lifecycle_hooks(&self, event: LifecycleEvent) {
+        if let Some(hook_runner) = &self.hook_runner {
+            hook_runner.run(event);
+        }
+    }
+
+    /// Returns the progress of the last background snapshot.
+    pub fn background_snapshot_status(&self) -> BackgroundSnapshotStatus {
+        self.snapshot_writer
+            .as_ref()
+            .map(SnapshotWriter::status)
+            .unwrap_or_default()
+    }
+
     /// Sets RDA bit in serial console
     pub fn emulate_serial_init(&self) -> Result<(), EmulateSerialInitError> {
         // When restoring from a previously saved state, there is no serial
@@ -479,17 +601,20 @@ impl Vmm {
 
         #[cfg(target_arch = "x86_64")]
         {
-            let mut guard = self
-                .pio_device_manager
+            let pio_device_manager = &self.pio_device_manager;
+            let serials = pio_device_manager
                 .stdio_serial
-                .lock()
-                .expect("Poisoned lock");
-            let serial = guard.serial_mut().unwrap();
-
-            serial
-                .serial
-                .write(IER_RDA_OFFSET, IER_RDA_BIT)
-                .map_err(|_| EmulateSerialInitError(std::io::Error::last_os_error()))?;
+                .iter()
+                .chain(pio_device_manager.extra_serials.values());
+            for serial in serials {
+                let mut guard = serial.lock().expect("Poisoned lock");
+                let serial = guard.serial_mut().unwrap();
+
+                serial
+                    .serial
+                    .write(IER_RDA_OFFSET, IER_RDA_BIT)
+                    .map_err(|_| EmulateSerialInitError(std::io::Error::last_os_error()))?;
+            }
             Ok(())
         }
     }
@@ -499,6 +624,8 @@ impl Vmm {
     pub fn send_ctrl_alt_del(&mut self) -> Result<(), VmmError> {
         self.pio_device_manager
             .i8042
+            .as_ref()
+            .ok_or(VmmError::I8042Disabled)?
             .lock()
             .expect("i8042 lock was poisoned")
             .i8042_device_mut()
@@ -507,6 +634,26 @@ impl Vmm {
             .map_err(VmmError::I8042Error)
     }
 
+    /// Presses the power button of the microVM, asking the guest to shut down gracefully.
+    ///
+    /// When the configuration has a timeout, the microVM is stopped if the guest did not shut
+    /// down by then.
+    pub fn send_shutdown(&mut self, config: &ShutdownConfig) -> Result<(), ShutdownError> {
+        let timeout = config.timeout()?;
+        let pressed = self
+            .acpi_device_manager
+            .press_power_button()
+            .map_err(ShutdownError::PowerButton)?;
+        if !pressed {
+            return Err(ShutdownError::NoPowerButton);
+        }
+        if let Some(timeout) = timeout {
+            self.shutdown_timer
+                .set_state(TimerState::Oneshot(timeout), SetTimeFlags::Default);
+        }
+        Ok(())
+    }
+
     /// Saves the state of a paused Microvm.
     pub fn save_state(&mut self, vm_info: &VmInfo) -> Result<MicrovmState, MicrovmStateError> {
         use self::MicrovmStateError::SaveVmState;
@@ -535,6 +682,7 @@ impl Vmm {
             vcpu_states,
             device_states,
             acpi_dev_state,
+            shared_rate_limiters: save_shared_rate_limiters(),
         })
     }
 
@@ -548,8 +696,15 @@ impl Vmm {
         let vcpu_responses = self
             .vcpus_handles
             .iter()
+            // Exited vCPUs, whose state is saved in the snapshots of the failures, may have sent
+            // `Exited` responses before their state.
+            .map(|handle| loop {
+                match handle.response_receiver().recv_timeout(RECV_TIMEOUT_SEC) {
+                    Ok(VcpuResponse::Exited(..)) => (),
+                    response => break response,
+                }
+            })
             // `Iterator::collect` can transform a `Vec<Result>` into a `Result<Vec>`.
-            .map(|handle| handle.response_receiver().recv_timeout(RECV_TIMEOUT_SEC))
             .collect::<Result<Vec<VcpuResponse>, RecvTimeoutError>>()
             .map_err(|_| MicrovmStateError::UnexpectedVcpuResponse)?;
 
@@ -566,6 +721,57 @@ impl Vmm {
         Ok(vcpu_states)
     }
 
+    /// Restores a state saved by [`Vmm::save_state`] into the paused microVM it was saved from.
+    ///
+    /// Only the KVM state of the vCPUs and of the VM is restored: the devices, which are not
+    /// re-created, keep their current state.
+    pub fn restore_state_in_place(
+        &mut self,
+        microvm_state: MicrovmState,
+    ) -> Result<(), MicrovmStateError> {
+        #[cfg(target_arch = "aarch64")]
+        let mpidrs = construct_kvm_mpidrs(&microvm_state.vcpu_states);
+        // The vCPUs are restored first, as the GIC state depends on them on aarch64.
+        self.restore_vcpu_states(microvm_state.vcpu_states)?;
+        #[cfg(target_arch = "x86_64")]
+        self.vm
+            .restore_state(&microvm_state.vm_state)
+            .map_err(MicrovmStateError::RestoreKvmVmState)?;
+        #[cfg(target_arch = "aarch64")]
+        self.vm
+            .restore_state(&mpidrs, &microvm_state.vm_state)
+            .map_err(MicrovmStateError::RestoreKvmVmState)?;
+        Ok(())
+    }
+
+    fn restore_vcpu_states(
+        &mut self,
+        vcpu_states: Vec<VcpuState>,
+    ) -> Result<(), MicrovmStateError> {
+        if vcpu_states.len() != self.vcpus_handles.len() {
+            return Err(MicrovmStateError::InvalidInput);
+        }
+        for (handle, state) in self.vcpus_handles.iter().zip(vcpu_states) {
+            handle
+                .send_event(VcpuEvent::RestoreState(Box::new(state)))
+                .map_err(MicrovmStateError::SignalVcpu)?;
+        }
+
+        for handle in self.vcpus_handles.iter() {
+            match handle.response_receiver().recv_timeout(RECV_TIMEOUT_SEC) {
+                Ok(VcpuResponse::RestoredState) => (),
+                Ok(VcpuResponse::Error(err)) => {
+                    return Err(MicrovmStateError::RestoreVcpuState(err))
+                }
+                Ok(VcpuResponse::NotAllowed(reason)) => {
+                    return Err(MicrovmStateError::NotAllowed(reason))
+                }
+                _ => return Err(MicrovmStateError::UnexpectedVcpuResponse),
+            }
+        }
+        Ok(())
+    }
+
     /// Dumps CPU configuration.
     pub fn dump_cpu_config(&mut self) -> Result<Vec<CpuConfiguration>, DumpCpuConfigError> {
         for handle in self.vcpus_handles.iter() {
@@ -594,6 +800,53 @@ impl Vmm {
         Ok(cpu_configs)
     }
 
+    /// Installs the `vmm` and `vcpu` filters of `filters` on the vCPU threads and on the calling
+    /// thread, which must be the VMM thread, on top of their current filters.
+    ///
+    /// The filters are expected to be validated by [`SeccompFiltersConfig::load`]. Seccomp filters
+    /// cannot be removed, so the installation is not atomic: the vCPU filters are installed
+    /// first, and the VMM filter last, only once all the vCPUs installed theirs. A vCPU failing
+    /// to install its filter leaves the other vCPUs with the new filter.
+    ///
+    /// [`SeccompFiltersConfig::load`]: crate::vmm_config::seccomp::SeccompFiltersConfig::load
+    pub fn install_seccomp_filters(
+        &self,
+        filters: &BpfThreadMap,
+    ) -> Result<(), SeccompFiltersError> {
+        if let Some(filter) = filters.get("vcpu") {
+            self.install_vcpus_seccomp_filter(filter)?;
+        }
+        if let Some(filter) = filters.get("vmm") {
+            seccompiler::apply_filter(filter).map_err(SeccompFiltersError::Install)?;
+        }
+        Ok(())
+    }
+
+    fn install_vcpus_seccomp_filter(
+        &self,
+        filter: &Arc<BpfProgram>,
+    ) -> Result<(), SeccompFiltersError> {
+        for handle in self.vcpus_handles.iter() {
+            handle
+                .send_event(VcpuEvent::InstallSeccompFilter(filter.clone()))
+                .map_err(SeccompFiltersError::SendEvent)?;
+        }
+        // Wait for all the vCPUs, so that no response is left behind in their channels.
+        let vcpu_responses: Vec<_> = self
+            .vcpus_handles
+            .iter()
+            .map(|handle| handle.response_receiver().recv_timeout(RECV_TIMEOUT_SEC))
+            .collect();
+        for response in vcpu_responses {
+            match response {
+                Ok(VcpuResponse::InstalledSeccompFilter) => (),
+                Ok(VcpuResponse::Error(err)) => return Err(SeccompFiltersError::Vcpu(err)),
+                _ => return Err(SeccompFiltersError::UnexpectedResponse),
+            }
+        }
+        Ok(())
+    }
+
     /// Retrieves the KVM dirty bitmap for each of the guest's memory regions.
     pub fn reset_dirty_bitmap(&self) {
         self.guest_memory
@@ -669,8 +922,26 @@ impl Vmm {
             .map_err(VmmError::DeviceManager)
     }
 
-    /// Updates the rate limiter parameters for block device with `drive_id` id.
-    pub fn update_vhost_user_block_config(&mut self, drive_id: &str) -> Result<(), VmmError> {
+    /// Pauses or resumes the processing of the queue of the block device with `drive_id` id.
+    pub fn update_block_device_state(
+        &mut self,
+        drive_id: &str,
+        state: DeviceQueuesState,
+    ) -> Result<(), VmmError> {
+        self.mmio_device_manager
+            .with_virtio_device_with_id(TYPE_BLOCK, drive_id, |block: &mut Block| {
+                match state {
+                    DeviceQueuesState::Paused => block.pause(),
+                    DeviceQueuesState::Resumed => block.resume(),
+                }
+                .map_err(|err| err.to_string())
+            })
+            .map_err(VmmError::DeviceManager)
+    }
+
+    /// Refreshes the config of the block device with `drive_id` id from its backend: the size of
+    /// the backing file for virtio-block, and the config of the backend for vhost-user-block.
+    pub fn update_block_device_config(&mut self, drive_id: &str) -> Result<(), VmmError> {
         self.mmio_device_manager
             .with_virtio_device_with_id(TYPE_BLOCK, drive_id, |block: &mut Block| {
                 block.update_config().map_err(|err| err.to_string())
@@ -682,19 +953,84 @@ impl Vmm {
     pub fn update_net_rate_limiters(
         &mut self,
         net_id: &str,
-        rx_bytes: BucketUpdate,
-        rx_ops: BucketUpdate,
-        tx_bytes: BucketUpdate,
-        tx_ops: BucketUpdate,
+        rx: RateLimiterUpdate,
+        tx: RateLimiterUpdate,
     ) -> Result<(), VmmError> {
         self.mmio_device_manager
             .with_virtio_device_with_id(TYPE_NET, net_id, |net: &mut Net| {
-                net.patch_rate_limiters(rx_bytes, rx_ops, tx_bytes, tx_ops);
+                net.patch_rate_limiters(rx, tx);
                 Ok(())
             })
             .map_err(VmmError::DeviceManager)
     }
 
+    /// Pauses or resumes the processing of the queues of the net device with `net_id` id.
+    pub fn update_net_state(
+        &mut self,
+        net_id: &str,
+        state: DeviceQueuesState,
+    ) -> Result<(), VmmError> {
+        self.mmio_device_manager
+            .with_virtio_device_with_id(TYPE_NET, net_id, |net: &mut Net| {
+                match state {
+                    DeviceQueuesState::Paused => net.pause(),
+                    DeviceQueuesState::Resumed => net.resume(),
+                }
+                Ok(())
+            })
+            .map_err(VmmError::DeviceManager)
+    }
+
+    /// Starts capturing the frames of the net device with `net_id` id as described by `config`,
+    /// or stops the capture if it is disabled.
+    pub fn update_net_capture(
+        &mut self,
+        net_id: &str,
+        config: &NetworkCaptureConfig,
+    ) -> Result<(), VmmError> {
+        self.mmio_device_manager
+            .with_virtio_device_with_id(TYPE_NET, net_id, |net: &mut Net| {
+                match config.path.as_deref().filter(|_| config.enabled) {
+                    Some(path) => net
+                        .start_capture(path, config.snaplen, config.ring_size)
+                        .map_err(|err| err.to_string()),
+                    None => {
+                        net.stop_capture();
+                        Ok(())
+                    }
+                }
+            })
+            .map_err(VmmError::DeviceManager)
+    }
+
+    /// Returns the per-flow accounting of the net device with `net_id` id, if enabled.
+    pub fn net_flows(&self, net_id: &str) -> Result<Option<NetFlows>, VmmError> {
+        let mut flows = None;
+        self.mmio_device_manager
+            .with_virtio_device_with_id(TYPE_NET, net_id, |net: &mut Net| {
+                flows = net.flow_table().map(FlowTable::snapshot);
+                Ok(())
+            })
+            .map_err(VmmError::DeviceManager)?;
+        Ok(flows)
+    }
+
+    /// Returns the current usage of the KVM memory slots of the microVM.
+    pub fn memory_slots(&self) -> MemorySlotsUsage {
+        self.vm.memory_slots()
+    }
+
+    /// Returns the host process details of the VMM, along with the IDs of its vCPU threads.
+    pub fn process_info(&self) -> ProcessInfo {
+        let mut info = PROCESS_INFO.get().cloned().unwrap_or_default();
+        info.vcpu_tids = self
+            .vcpus_handles
+            .iter()
+            .map(|handle| handle.tid())
+            .collect();
+        info
+    }
+
     /// Returns a reference to the balloon device if present.
     pub fn balloon_config(&self) -> Result<BalloonConfig, BalloonError> {
         if let Some(busdev) = self.get_bus_device(DeviceType::Virtio(TYPE_BALLOON), BALLOON_DEV_ID)
@@ -780,11 +1116,23 @@ impl Vmm {
         }
     }
 
-    /// Updates configuration for the balloon device as described in `balloon_stats_update`.
+    /// Updates the statistics polling interval of the balloon device, along with the destinations
+    /// the statistics are pushed to if `stats_push` is given, and the bounds within which the
+    /// target size is adjusted if `auto_target` is given.
     pub fn update_balloon_stats_config(
         &mut self,
         stats_polling_interval_s: u16,
+        stats_push: Option<BalloonStatsPushConfig>,
+        auto_target: Option<BalloonAutoTargetConfig>,
     ) -> Result<(), BalloonError> {
+        // The balloon cannot have a target size greater than the size of
+        // the guest memory.
+        if auto_target.as_ref().is_some_and(|auto_target| {
+            u64::from(auto_target.max_mib) > mem_size_mib(self.guest_memory())
+        }) {
+            return Err(BalloonError::TooManyPagesRequested);
+        }
+
         if let Some(busdev) = self.get_bus_device(DeviceType::Virtio(TYPE_BALLOON), BALLOON_DEV_ID)
         {
             {
@@ -795,13 +1143,21 @@ impl Vmm {
                     .expect("Unexpected device type")
                     .device();
 
-                virtio_device
-                    .lock()
-                    .expect("Poisoned lock")
+                let mut locked_device = virtio_device.lock().expect("Poisoned lock");
+                let balloon = locked_device
                     .as_mut_any()
                     .downcast_mut::<Balloon>()
-                    .unwrap()
-                    .update_stats_polling_interval(stats_polling_interval_s)?;
+                    .unwrap();
+                if stats_push.is_some() && !balloon.stats_enabled() {
+                    return Err(BalloonError::StatisticsDisabled);
+                }
+                if auto_target.is_some() {
+                    balloon.set_auto_target(auto_target)?;
+                }
+                balloon.update_stats_polling_interval(stats_polling_interval_s)?;
+                if stats_push.is_some() {
+                    balloon.set_stats_push(stats_push);
+                }
             }
             Ok(())
         } else {
@@ -809,6 +1165,99 @@ impl Vmm {
         }
     }
 
+    /// Resizes the memory available to the guest to `target_mib`, by setting the target size of
+    /// the balloon device accordingly.
+    pub fn set_memory_target(&mut self, target_mib: u32) -> Result<(), MemoryTargetError> {
+        let size_mib = u32::try_from(mem_size_mib(self.guest_memory())).unwrap_or(u32::MAX);
+        let amount_mib = balloon_target_mib(size_mib, target_mib)?;
+        if self.balloon_config()?.auto_target.is_some() {
+            return Err(MemoryTargetError::AutoTarget);
+        }
+        self.update_balloon_config(amount_mib)?;
+        info!("Guest memory target set to {target_mib} MiB, through the balloon.");
+        Ok(())
+    }
+
+    /// Updates the CPU allocation of the vCPUs, which must have been throttled since the microVM
+    /// started.
+    pub fn update_cpu_limiter(
+        &self,
+        config: CpuLimiterConfig,
+    ) -> Result<(), CpuLimiterConfigError> {
+        config.validate()?;
+        self.cpu_limiter
+            .as_ref()
+            .ok_or(CpuLimiterConfigError::NotConfigured)?
+            .update(config);
+        Ok(())
+    }
+
+    /// Writes a snapshot of the microVM when a vCPU exits on an error or the guest resets.
+    pub fn set_coredump_snapshot(&mut self, coredump_snapshot: CoredumpSnapshot) {
+        self.coredump_snapshot = Some(coredump_snapshot);
+    }
+
+    // Creates the full snapshot configured for the guest panics, pausing the microVM meanwhile if
+    // it is running.
+    fn snapshot_on_panic(&mut self, panic_snapshot: &PanicSnapshot) {
+        let running = self.instance_info.state == VmState::Running;
+        if running {
+            if let Err(err) = self.pause_vm() {
+                error!(
+                    "Failed to pause the microVM to snapshot the guest panic: {}",
+                    err
+                );
+                METRICS.vmm.panic_snapshot_fails.inc();
+                return;
+            }
+        }
+
+        match persist::create_snapshot(self, &panic_snapshot.vm_info, &panic_snapshot.params) {
+            Ok(()) => info!(
+                "Snapshotted the guest panic to {}.",
+                panic_snapshot.params.snapshot_path.display()
+            ),
+            Err(err) => {
+                error!("Failed to snapshot the guest panic: {}", err);
+                METRICS.vmm.panic_snapshot_fails.inc();
+            }
+        }
+
+        if running {
+            if let Err(err) = self.resume_vm() {
+                error!(
+                    "Failed to resume the microVM after snapshotting the guest panic: {}",
+                    err
+                );
+            }
+        }
+    }
+
+    /// Returns the progress of the memory available to the guest towards its target size.
+    pub fn memory_target(&self) -> Result<MemoryTargetStatus, MemoryTargetError> {
+        let size_mib = u32::try_from(mem_size_mib(self.guest_memory())).unwrap_or(u32::MAX);
+        let busdev = self
+            .get_bus_device(DeviceType::Virtio(TYPE_BALLOON), BALLOON_DEV_ID)
+            .ok_or(MemoryTargetError::NoMechanism)?;
+        let virtio_device = busdev
+            .lock()
+            .expect("Poisoned lock")
+            .mmio_transport_ref()
+            .expect("Unexpected device type")
+            .device();
+
+        let mut locked_device = virtio_device.lock().expect("Poisoned lock");
+        let balloon = locked_device
+            .as_mut_any()
+            .downcast_mut::<Balloon>()
+            .unwrap();
+        Ok(MemoryTargetStatus::from_balloon(
+            size_mib,
+            balloon.size_mb(),
+            balloon.actual_mb(),
+        ))
+    }
+
     /// Signals Vmm to stop and exit.
     pub fn stop(&mut self, exit_code: FcExitCode) {
         // To avoid cycles, all teardown paths take the following route:
@@ -830,6 +1279,12 @@ impl Vmm {
         // responsibility to break main event loop and propagate the exit code value.
         info!("Vmm is stopping.");
 
+        // The hooks thread exits once its handle is dropped, and the hooks are run only once
+        // when the Vmm is stopped again on drop.
+        if let Some(hook_runner) = self.hook_runner.take() {
+            hook_runner.run(LifecycleEvent::PreShutdown);
+        }
+
         // We send a "Finish" event.  If a VCPU has already exited, this is the only
         // message it will accept... but running and paused will take it as well.
         // It breaks out of the state machine loop so that the thread can be joined.
@@ -925,21 +1380,61 @@ impl Drop for Vmm {
 
 impl MutEventSubscriber for Vmm {
     /// Handle a read event (EPOLLIN).
-    fn process(&mut self, event: Events, _: &mut EventOps) {
+    fn process(&mut self, event: Events, ops: &mut EventOps) {
         let source = event.fd();
         let event_set = event.event_set();
 
+        #[cfg(target_arch = "x86_64")]
+        if source == self.pio_device_manager.i8042_reset_evt.as_raw_fd()
+            && event_set == EventSet::IN
+        {
+            let _ = self.pio_device_manager.i8042_reset_evt.read();
+            // Handled by the device as any other reset command, which signals the exit event. The
+            // reset command is only signaled when the i8042 is enabled.
+            if let Some(i8042) = &self.pio_device_manager.i8042 {
+                i8042
+                    .lock()
+                    .expect("Poisoned lock")
+                    .i8042_device_mut()
+                    .unwrap()
+                    .reset();
+            }
+            return;
+        }
+
+        if source == self.shutdown_timer.as_raw_fd() && event_set == EventSet::IN {
+            self.shutdown_timer.read();
+            warn!("The guest did not shut down within the timeout, stopping the microVM.");
+            METRICS.vmm.forced_shutdowns.inc();
+            self.stop(FcExitCode::Ok);
+            return;
+        }
+
+        if let Some(panic_snapshot) = self
+            .panic_snapshot
+            .take_if(|panic_snapshot| source == panic_snapshot.panic_evt.as_raw_fd())
+        {
+            let _ = panic_snapshot.panic_evt.read();
+            if let Err(err) = ops.remove(Events::new(&panic_snapshot.panic_evt, EventSet::IN)) {
+                error!("Failed to unregister the guest panic event: {}", err);
+            }
+            self.snapshot_on_panic(&panic_snapshot);
+            return;
+        }
+
         if source == self.vcpus_exit_evt.as_raw_fd() && event_set == EventSet::IN {
             // Exit event handling should never do anything more than call 'self.stop()'.
             let _ = self.vcpus_exit_evt.read();
 
+            let mut failed = false;
             let exit_code = 'exit_code: {
                 // Query each vcpu for their exit_code.
                 for handle in &self.vcpus_handles {
                     // Drain all vcpu responses that are pending from this vcpu until we find an
                     // exit status.
                     for response in handle.response_receiver().try_iter() {
-                        if let VcpuResponse::Exited(status) = response {
+                        if let VcpuResponse::Exited(status, guest_reset) = response {
+                            failed |= guest_reset || status != FcExitCode::Ok;
                             // It could be that some vcpus exited successfully while others
                             // errored out. Thus make sure that error exits from one vcpu always
                             // takes precedence over "ok" exits
@@ -953,6 +1448,11 @@ impl MutEventSubscriber for Vmm {
                 // No CPUs exited with error status code, report "Ok"
                 FcExitCode::Ok
             };
+            if failed {
+                if let Some(coredump_snapshot) = self.coredump_snapshot.take() {
+                    coredump_snapshot.write(self);
+                }
+            }
             self.stop(exit_code);
         } else {
             error!("Spurious EventManager event for handler: Vmm");
@@ -963,5 +1463,20 @@ impl MutEventSubscriber for Vmm {
         if let Err(err) = ops.add(Events::new(&self.vcpus_exit_evt, EventSet::IN)) {
             error!("Failed to register vmm exit event: {}", err);
         }
+        if let Err(err) = ops.add(Events::new(&self.shutdown_timer, EventSet::IN)) {
+            error!("Failed to register vmm shutdown timer: {}", err);
+        }
+        if let Some(panic_snapshot) = &self.panic_snapshot {
+            if let Err(err) = ops.add(Events::new(&panic_snapshot.panic_evt, EventSet::IN)) {
+                error!("Failed to register the guest panic event: {}", err);
+            }
+        }
+        #[cfg(target_arch = "x86_64")]
+        if let Err(err) = ops.add(Events::new(
+            &self.pio_device_manager.i8042_reset_evt,
+            EventSet::IN,
+        )) {
+            error!("Failed to register i8042 reset event: {}", err);
+        }
     }
 }
@@ -71,13 +71,14 @@ use serde::{Serialize, Serializer};
 use utils::time::{get_time_ns, get_time_us, ClockType};
 
 use super::FcLineWriter;
-use crate::devices::legacy;
 use crate::devices::virtio::balloon::metrics as balloon_metrics;
 use crate::devices::virtio::block::virtio::metrics as block_metrics;
 use crate::devices::virtio::net::metrics as net_metrics;
 use crate::devices::virtio::rng::metrics as entropy_metrics;
 use crate::devices::virtio::vhost_user_metrics;
 use crate::devices::virtio::vsock::metrics as vsock_metrics;
+use crate::devices::{error_events as device_error_metrics, legacy};
+use crate::rate_limiter::shared as shared_rate_limiter_metrics;
 
 /// Static instance used for handling metrics.
 pub static METRICS: Metrics<FirecrackerMetrics, FcLineWriter> =
@@ -140,35 +141,46 @@ impl<T: Serialize + Debug, M: Write + Send + Debug> Metrics<T, M> {
     /// The alternative is to hold a Mutex over the entire function call, but this increases the
     /// known deadlock potential.
     pub fn write(&self) -> Result<bool, MetricsError> {
-        if let Some(lock) = self.metrics_buf.get() {
-            match serde_json::to_string(&self.app_metrics) {
-                Ok(msg) => {
-                    if let Ok(mut guard) = lock.lock() {
-                        // No need to explicitly call flush because the underlying LineWriter
-                        // flushes automatically whenever a newline is
-                        // detected (and we always end with a newline the
-                        // current write).
-                        guard
-                            .write_all(format!("{msg}\n",).as_bytes())
-                            .map_err(MetricsError::Write)
-                            .map(|_| true)
-                    } else {
-                        // We have not incremented `missed_metrics_count` as there is no way to push
-                        // metrics if destination lock got poisoned.
-                        panic!(
-                            "Failed to write to the provided metrics destination due to poisoned \
-                             lock"
-                        );
-                    }
-                }
-                Err(err) => Err(MetricsError::Serde(err.to_string())),
-            }
+        if self.metrics_buf.get().is_some() {
+            self.write_snapshot().map(|(written, _)| written)
         } else {
             // If the metrics are not initialized, no error is thrown but we do let the user know
             // that metrics were not written.
             Ok(false)
         }
     }
+
+    /// Serializes the metrics, writes them to the destination if the metrics system is
+    /// initialized and returns the serialized metrics alongside whether they were written.
+    ///
+    /// Serializing resets the `SharedIncMetric`s, so this must be used whenever a copy of the
+    /// metrics is needed, otherwise the increments since the last flush would be lost.
+    pub fn write_snapshot(&self) -> Result<(bool, String), MetricsError> {
+        let msg = serde_json::to_string(&self.app_metrics)
+            .map_err(|err| MetricsError::Serde(err.to_string()))?;
+        let written = match self.metrics_buf.get() {
+            Some(lock) => {
+                if let Ok(mut guard) = lock.lock() {
+                    // No need to explicitly call flush because the underlying LineWriter
+                    // flushes automatically whenever a newline is
+                    // detected (and we always end with a newline the
+                    // current write).
+                    guard
+                        .write_all(format!("{msg}\n",).as_bytes())
+                        .map_err(MetricsError::Write)?;
+                    true
+                } else {
+                    // We have not incremented `missed_metrics_count` as there is no way to push
+                    // metrics if destination lock got poisoned.
+                    panic!(
+                        "Failed to write to the provided metrics destination due to poisoned lock"
+                    );
+                }
+            }
+            None => false,
+        };
+        Ok((written, msg))
+    }
 }
 
 impl<T: Serialize + Debug, M: Write + Send + Debug> Deref for Metrics<T, M> {
@@ -358,6 +370,17 @@ pub struct ApiServerMetrics {
     pub sync_response_fails: SharedIncMetric,
     /// Number of timeouts during communication with the VMM.
     pub sync_vmm_send_timeout_count: SharedIncMetric,
+    /// Number of times the API socket was re-created.
+    pub socket_rebinds: SharedIncMetric,
+    /// Number of failures to re-create the API socket.
+    pub socket_rebind_fails: SharedIncMetric,
+    /// Number of API requests rejected because the VMM is busy with timed out requests.
+    pub vmm_busy_rejections: SharedIncMetric,
+    /// Number of API requests answered with the response to a previous request with the same
+    /// idempotency key.
+    pub idempotent_replays: SharedIncMetric,
+    /// Number of guest API requests rejected because they are not allowed on the guest API.
+    pub guest_rejections: SharedIncMetric,
 }
 impl ApiServerMetrics {
     /// Const default construction.
@@ -367,6 +390,11 @@ impl ApiServerMetrics {
             process_startup_time_cpu_us: SharedStoreMetric::new(),
             sync_response_fails: SharedIncMetric::new(),
             sync_vmm_send_timeout_count: SharedIncMetric::new(),
+            socket_rebinds: SharedIncMetric::new(),
+            socket_rebind_fails: SharedIncMetric::new(),
+            vmm_busy_rejections: SharedIncMetric::new(),
+            idempotent_replays: SharedIncMetric::new(),
+            guest_rejections: SharedIncMetric::new(),
         }
     }
 }
@@ -570,6 +598,18 @@ pub struct MmdsMetrics {
     pub connections_created: SharedIncMetric,
     /// The number of connections cleaned up by the MMDS TCP handler.
     pub connections_destroyed: SharedIncMetric,
+    /// The number of key/value pairs successfully written by the guest.
+    pub guest_data_writes: SharedIncMetric,
+    /// The number of rejected guest writes.
+    pub guest_data_write_fails: SharedIncMetric,
+    /// The number of GET requests answered with `304 Not Modified`.
+    pub not_modified: SharedIncMetric,
+    /// The number of GET requests denied by the path ACL of the network interface.
+    pub acl_denied: SharedIncMetric,
+    /// The number of requests sent to the MMDS backend.
+    pub backend_requests: SharedIncMetric,
+    /// The number of requests the MMDS backend failed to answer.
+    pub backend_fails: SharedIncMetric,
 }
 impl MmdsMetrics {
     /// Const default construction.
@@ -586,6 +626,12 @@ impl MmdsMetrics {
             tx_frames: SharedIncMetric::new(),
             connections_created: SharedIncMetric::new(),
             connections_destroyed: SharedIncMetric::new(),
+            guest_data_writes: SharedIncMetric::new(),
+            guest_data_write_fails: SharedIncMetric::new(),
+            not_modified: SharedIncMetric::new(),
+            acl_denied: SharedIncMetric::new(),
+            backend_requests: SharedIncMetric::new(),
+            backend_fails: SharedIncMetric::new(),
         }
     }
 }
@@ -639,6 +685,34 @@ impl PerformanceMetrics {
     }
 }
 
+/// Timestamps of the boot phases of the microVM, in microseconds since the start of the process.
+/// A phase which is not reached yet is reported as 0.
+#[derive(Debug, Default, Serialize)]
+pub struct BootTimingsMetrics {
+    /// The microVM is configured, and its start was requested.
+    pub config_done_us: SharedStoreMetric,
+    /// The kernel, or the firmware, is loaded in the guest memory.
+    pub kernel_loaded_us: SharedStoreMetric,
+    /// The devices of the microVM are created.
+    pub devices_created_us: SharedStoreMetric,
+    /// A vCPU is about to run for the first time.
+    pub first_vcpu_run_us: SharedStoreMetric,
+    /// The guest wrote the boot complete magic value to the boot timer device.
+    pub guest_boot_complete_us: SharedStoreMetric,
+}
+impl BootTimingsMetrics {
+    /// Const default construction.
+    pub const fn new() -> Self {
+        Self {
+            config_done_us: SharedStoreMetric::new(),
+            kernel_loaded_us: SharedStoreMetric::new(),
+            devices_created_us: SharedStoreMetric::new(),
+            first_vcpu_run_us: SharedStoreMetric::new(),
+            guest_boot_complete_us: SharedStoreMetric::new(),
+        }
+    }
+}
+
 /// Metrics for the seccomp filtering.
 #[derive(Debug, Default, Serialize)]
 pub struct SeccompMetrics {
@@ -713,15 +787,7 @@ impl Drop for LatencyMetricsRecorder<'_> {
     ///  self.start_time is recorded in new() and metrics are updated in drop
     fn drop(&mut self) {
         let delta_us = get_time_us(ClockType::Monotonic) - self.start_time;
-        self.metric.sum_us.add(delta_us);
-        let min_us = self.metric.min_us.fetch();
-        let max_us = self.metric.max_us.fetch();
-        if (0 == min_us) || (min_us > delta_us) {
-            self.metric.min_us.store(delta_us);
-        }
-        if (0 == max_us) || (max_us < delta_us) {
-            self.metric.max_us.store(delta_us);
-        }
+        self.metric.record(delta_us);
     }
 }
 
@@ -754,6 +820,20 @@ impl LatencyAggregateMetrics {
     pub fn record_latency_metrics(&self) -> LatencyMetricsRecorder {
         LatencyMetricsRecorder::new(self)
     }
+
+    /// Updates the min/max/sum metrics with a latency of `delta_us` microseconds, for latencies
+    /// which are not measured around a single scope.
+    pub fn record(&self, delta_us: u64) {
+        self.sum_us.add(delta_us);
+        let min_us = self.min_us.fetch();
+        let max_us = self.max_us.fetch();
+        if (0 == min_us) || (min_us > delta_us) {
+            self.min_us.store(delta_us);
+        }
+        if (0 == max_us) || (max_us < delta_us) {
+            self.max_us.store(delta_us);
+        }
+    }
 }
 
 /// Structure provides Metrics specific to VCPUs' mode of functioning.
@@ -781,6 +861,10 @@ pub struct VcpuMetrics {
     pub failures: SharedIncMetric,
     /// Number of times that the `KVM_KVMCLOCK_CTRL` ioctl failed.
     pub kvmclock_ctrl_fails: SharedIncMetric,
+    /// Number of times a vCPU slept to stay within the allocation of the CPU limiter.
+    pub throttled: SharedIncMetric,
+    /// Time in microseconds the vCPUs slept to stay within the allocation of the CPU limiter.
+    pub throttled_us: SharedIncMetric,
     /// Provides Min/max/sum for KVM exits handling input IO.
     pub exit_io_in_agg: LatencyAggregateMetrics,
     /// Provides Min/max/sum for KVM exits handling output IO.
@@ -800,6 +884,8 @@ impl VcpuMetrics {
             exit_mmio_write: SharedIncMetric::new(),
             failures: SharedIncMetric::new(),
             kvmclock_ctrl_fails: SharedIncMetric::new(),
+            throttled: SharedIncMetric::new(),
+            throttled_us: SharedIncMetric::new(),
             exit_io_in_agg: LatencyAggregateMetrics::new(),
             exit_io_out_agg: LatencyAggregateMetrics::new(),
             exit_mmio_read_agg: LatencyAggregateMetrics::new(),
@@ -808,6 +894,40 @@ impl VcpuMetrics {
     }
 }
 
+/// Number of IO and MMIO KVM exits handled by each kind of device. The accesses which KVM handles
+/// itself, e.g. the VirtIO queue notifications signaled through ioeventfds, are not counted.
+#[derive(Debug, Default, Serialize)]
+pub struct DeviceExitMetrics {
+    /// Number of KVM exits handled by the i8042 device.
+    pub i8042: SharedIncMetric,
+    /// Number of KVM exits handled by the RTC device.
+    pub rtc: SharedIncMetric,
+    /// Number of KVM exits handled by the boot timer device.
+    pub boot_timer: SharedIncMetric,
+    /// Number of KVM exits handled by the pvpanic device.
+    pub pvpanic: SharedIncMetric,
+    /// Number of KVM exits handled by the TPM device.
+    pub tpm: SharedIncMetric,
+    /// Number of KVM exits handled by the serial devices.
+    pub serial: SharedIncMetric,
+    /// Number of KVM exits handled by the MMIO transports of the VirtIO devices.
+    pub virtio_mmio: SharedIncMetric,
+}
+impl DeviceExitMetrics {
+    /// Const default construction.
+    pub const fn new() -> Self {
+        Self {
+            i8042: SharedIncMetric::new(),
+            rtc: SharedIncMetric::new(),
+            boot_timer: SharedIncMetric::new(),
+            pvpanic: SharedIncMetric::new(),
+            tpm: SharedIncMetric::new(),
+            serial: SharedIncMetric::new(),
+            virtio_mmio: SharedIncMetric::new(),
+        }
+    }
+}
+
 /// Metrics specific to the machine manager as a whole.
 #[derive(Debug, Default, Serialize)]
 pub struct VmmMetrics {
@@ -815,6 +935,40 @@ pub struct VmmMetrics {
     pub device_events: SharedIncMetric,
     /// Metric for signaling a panic has occurred.
     pub panic_count: SharedStoreMetric,
+    /// Number of API actions handled by the VMM thread.
+    pub api_actions: SharedIncMetric,
+    /// Provides Min/max/sum for the time spent by the VMM thread handling API actions.
+    pub api_action_handling_agg: LatencyAggregateMetrics,
+    /// Provides Min/max/sum for the time API actions wait queued before the VMM thread handles
+    /// them.
+    pub api_action_queue_wait_agg: LatencyAggregateMetrics,
+    /// Number of samples of the event loop dispatch latency.
+    pub event_loop_samples: SharedIncMetric,
+    /// Provides Min/max/sum for the time between a periodic event firing and the event loop
+    /// dispatching it, i.e. for how long the event loop is stalled.
+    pub event_loop_dispatch_agg: LatencyAggregateMetrics,
+    /// Number of chaos mode cycles which restored and resumed the microVM.
+    pub chaos_cycles: SharedIncMetric,
+    /// Number of chaos mode cycles which failed.
+    pub chaos_cycle_fails: SharedIncMetric,
+    /// Number of lifecycle hooks which failed or timed out.
+    pub hook_fails: SharedIncMetric,
+    /// Number of events not streamed because too many events were queued.
+    pub events_dropped: SharedIncMetric,
+    /// Number of event stream clients disconnected because they did not keep up.
+    pub event_clients_dropped: SharedIncMetric,
+    /// Number of microVMs stopped because the guest did not shut down within the timeout.
+    pub forced_shutdowns: SharedIncMetric,
+    /// Number of guest kernel panics reported through the pvpanic device.
+    pub guest_panics: SharedIncMetric,
+    /// Number of snapshots on guest panic which failed.
+    pub panic_snapshot_fails: SharedIncMetric,
+    /// Number of snapshots written when the microVM failed.
+    pub coredump_snapshots: SharedIncMetric,
+    /// Number of snapshots of the failures of the microVM which failed.
+    pub coredump_snapshot_fails: SharedIncMetric,
+    /// Number of failures to set up the devices deferred after the vCPUs start.
+    pub deferred_device_fails: SharedIncMetric,
 }
 impl VmmMetrics {
     /// Const default construction.
@@ -822,6 +976,22 @@ impl VmmMetrics {
         Self {
             device_events: SharedIncMetric::new(),
             panic_count: SharedStoreMetric::new(),
+            api_actions: SharedIncMetric::new(),
+            api_action_handling_agg: LatencyAggregateMetrics::new(),
+            api_action_queue_wait_agg: LatencyAggregateMetrics::new(),
+            event_loop_samples: SharedIncMetric::new(),
+            event_loop_dispatch_agg: LatencyAggregateMetrics::new(),
+            chaos_cycles: SharedIncMetric::new(),
+            chaos_cycle_fails: SharedIncMetric::new(),
+            hook_fails: SharedIncMetric::new(),
+            events_dropped: SharedIncMetric::new(),
+            event_clients_dropped: SharedIncMetric::new(),
+            forced_shutdowns: SharedIncMetric::new(),
+            guest_panics: SharedIncMetric::new(),
+            panic_snapshot_fails: SharedIncMetric::new(),
+            coredump_snapshots: SharedIncMetric::new(),
+            coredump_snapshot_fails: SharedIncMetric::new(),
+            deferred_device_fails: SharedIncMetric::new(),
         }
     }
 }
@@ -869,6 +1039,11 @@ create_serialize_proxy!(BalloonMetricsSerializeProxy, balloon_metrics);
 create_serialize_proxy!(EntropyMetricsSerializeProxy, entropy_metrics);
 create_serialize_proxy!(VsockMetricsSerializeProxy, vsock_metrics);
 create_serialize_proxy!(LegacyDevMetricsSerializeProxy, legacy);
+create_serialize_proxy!(DeviceErrorMetricsSerializeProxy, device_error_metrics);
+create_serialize_proxy!(
+    SharedRateLimiterMetricsSerializeProxy,
+    shared_rate_limiter_metrics
+);
 
 /// Structure storing all metrics while enforcing serialization support on them.
 #[derive(Debug, Default, Serialize)]
@@ -879,11 +1054,18 @@ pub struct FirecrackerMetrics {
     #[serde(flatten)]
     /// A balloon device's related metrics.
     pub balloon_ser: BalloonMetricsSerializeProxy,
+    /// Timestamps of the boot phases of the microVM.
+    pub boot_timings: BootTimingsMetrics,
     #[serde(flatten)]
     /// A block device's related metrics.
     pub block_ser: BlockMetricsSerializeProxy,
     /// Metrics related to deprecated API calls.
     pub deprecated_api: DeprecatedApiMetrics,
+    #[serde(flatten)]
+    /// Device errors related metrics, per error class.
+    pub device_errors_ser: DeviceErrorMetricsSerializeProxy,
+    /// Metrics related to the KVM exits handled by the devices.
+    pub device_exits: DeviceExitMetrics,
     /// Metrics related to API GET requests.
     pub get_api_requests: GetRequestsMetrics,
     #[serde(flatten)]
@@ -904,6 +1086,9 @@ pub struct FirecrackerMetrics {
     pub put_api_requests: PutRequestsMetrics,
     /// Metrics related to seccomp filtering.
     pub seccomp: SeccompMetrics,
+    #[serde(flatten)]
+    /// Fill levels of the rate limiters shared by several devices.
+    pub shared_rate_limiters_ser: SharedRateLimiterMetricsSerializeProxy,
     /// Metrics related to a vcpu's functioning.
     pub vcpu: VcpuMetrics,
     /// Metrics related to the virtual machine manager.
@@ -927,8 +1112,11 @@ impl FirecrackerMetrics {
             utc_timestamp_ms: SerializeToUtcTimestampMs::new(),
             api_server: ApiServerMetrics::new(),
             balloon_ser: BalloonMetricsSerializeProxy {},
+            boot_timings: BootTimingsMetrics::new(),
             block_ser: BlockMetricsSerializeProxy {},
             deprecated_api: DeprecatedApiMetrics::new(),
+            device_errors_ser: DeviceErrorMetricsSerializeProxy {},
+            device_exits: DeviceExitMetrics::new(),
             get_api_requests: GetRequestsMetrics::new(),
             legacy_dev_ser: LegacyDevMetricsSerializeProxy {},
             latencies_us: PerformanceMetrics::new(),
@@ -938,6 +1126,7 @@ impl FirecrackerMetrics {
             patch_api_requests: PatchRequestsMetrics::new(),
             put_api_requests: PutRequestsMetrics::new(),
             seccomp: SeccompMetrics::new(),
+            shared_rate_limiters_ser: SharedRateLimiterMetricsSerializeProxy {},
             vcpu: VcpuMetrics::new(),
             vmm: VmmMetrics::new(),
             signals: SignalMetrics::new(),
@@ -984,6 +1173,32 @@ mod tests {
         m.init(LineWriter::new(f.into_file())).unwrap_err();
     }
 
+    #[test]
+    fn test_write_snapshot() {
+        let m = &Metrics::<_, FcLineWriter>::new(FirecrackerMetrics::new());
+
+        m.api_server.sync_response_fails.inc();
+        let (written, msg) = m.write_snapshot().unwrap();
+        assert!(!written);
+        assert!(msg.contains("\"sync_response_fails\":1"));
+
+        // The snapshot is written to the destination as well, with the same values.
+        let f = TempFile::new().expect("Failed to create temporary metrics file");
+        let file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(f.as_path())
+            .unwrap();
+        m.init(LineWriter::new(file)).unwrap();
+        m.api_server.sync_response_fails.add(2);
+        let (written, msg) = m.write_snapshot().unwrap();
+        assert!(written);
+        assert!(msg.contains("\"sync_response_fails\":2"));
+        assert_eq!(
+            std::fs::read_to_string(f.as_path()).unwrap(),
+            format!("{msg}\n")
+        );
+    }
+
     #[test]
     fn test_shared_inc_metric() {
         let metric = Arc::new(SharedIncMetric::default());
@@ -0,0 +1,813 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Migrations of the microVM state between the versions of the snapshot format.
+//!
+//! The states which changed between two versions are described here as they are laid out in
+//! each of them, so that the migrations keep working when the state types change again. The types
+//! which did not change are reused as they are, and nested structs are flattened where it doesn't
+//! change their serialized layout.
+
+use std::convert::identity;
+use std::os::unix::io::RawFd;
+
+use semver::Version;
+use serde::{Deserialize, Serialize};
+
+#[cfg(target_arch = "aarch64")]
+use crate::arch::aarch64::gic::{GicRegState, GicVcpuState, ItsState};
+#[cfg(target_arch = "aarch64")]
+use crate::cpu_config::templates::KvmCapability;
+use crate::cpu_config::templates::StaticCpuTemplate;
+use crate::device_manager::mmio::MMIODeviceInfo;
+#[cfg(target_arch = "aarch64")]
+use crate::device_manager::persist::ConnectedLegacyState;
+use crate::device_manager::persist::{ConnectedPvPanicState, ConnectedTpmState, MmdsVersionState};
+use crate::devices::acpi::power_button::PowerButtonState;
+use crate::devices::acpi::vmgenid::VMGenIDState;
+use crate::devices::virtio::balloon::persist::BalloonState;
+use crate::devices::virtio::block::vhost_user::persist::VhostUserBlockState;
+use crate::devices::virtio::block::virtio::device::DriveBackend;
+use crate::devices::virtio::block::virtio::persist::FileEngineTypeState;
+use crate::devices::virtio::block::CacheType;
+use crate::devices::virtio::net::device::MacFilter;
+use crate::devices::virtio::net::persist::RxBufferState;
+use crate::devices::virtio::persist::VirtioDeviceState;
+use crate::devices::virtio::rng::device::EntropySourceType;
+use crate::devices::virtio::vsock::persist::{
+    VsockFrontendState, VsockPortLimiterState, VsockTcpForwardState,
+};
+use crate::devices::virtio::vsock::{VIRTIO_VSOCK_F_DGRAM, VIRTIO_VSOCK_F_SEQPACKET};
+use crate::rate_limiter::persist::{SharedRateLimiterState, TokenBucketState};
+use crate::snapshot::migration::{convert, Migration, MigrationError};
+use crate::utils::net::mac::{MacAddr, MAC_ADDR_LEN};
+use crate::vmm_config::boot_args::BootArgs;
+use crate::vmm_config::drive::{LatencyInjectionConfig, RemoteDriveConfig};
+use crate::vmm_config::machine_config::{HugePageConfig, LegacyDevice, ReservedMemoryRegion};
+use crate::vmm_config::shared_memory::SharedMemoryConfig;
+use crate::vstate::memory::GuestMemoryState;
+use crate::vstate::vcpu::VcpuState;
+#[cfg(target_arch = "x86_64")]
+use crate::vstate::vm::VmState;
+
+/// Version of the snapshot format before the labels were added to the header.
+const V5: Version = Version::new(5, 0, 0);
+
+/// Changes of the microVM state between versions 5.0.0 and 6.0.0 of the snapshot format.
+pub const V5_TO_V6: Migration = Migration {
+    from: V5,
+    to: Version::new(6, 0, 0),
+    upgrade: upgrade_v6,
+    downgrade: downgrade_v6,
+};
+
+// Fails the downgrade to version 5.0.0 unless `representable` holds, as `what` cannot be
+// represented there.
+fn ensure_v5(representable: bool, what: &str) -> Result<(), MigrationError> {
+    if representable {
+        Ok(())
+    } else {
+        Err(MigrationError::Unrepresentable(what.to_string(), V5))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BootSourceConfigV5 {
+    kernel_image_path: String,
+    initrd_path: Option<String>,
+    boot_args: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BootSourceConfigV6 {
+    kernel_image_path: String,
+    initrd_path: Option<Vec<String>>,
+    kernel_image_fd: Option<RawFd>,
+    initrd_fd: Option<RawFd>,
+    boot_args: Option<BootArgs>,
+    firmware_path: Option<String>,
+    kernel_digest: Option<String>,
+    initrd_digest: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct VmInfoV5 {
+    mem_size_mib: u64,
+    smt: bool,
+    cpu_template: StaticCpuTemplate,
+    boot_source: BootSourceConfigV5,
+    huge_pages: HugePageConfig,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct VmInfoV6 {
+    mem_size_mib: u64,
+    smt: bool,
+    cpu_template: StaticCpuTemplate,
+    boot_source: BootSourceConfigV6,
+    huge_pages: HugePageConfig,
+    reserved_memory: Vec<ReservedMemoryRegion>,
+    shared_memory: Vec<SharedMemoryConfig>,
+    disabled_legacy_devices: Vec<LegacyDevice>,
+    kernel_version: String,
+}
+
+#[cfg(target_arch = "x86_64")]
+type VmStateV5 = VmState;
+
+#[cfg(target_arch = "x86_64")]
+type VmStateV6 = VmState;
+
+#[cfg(target_arch = "aarch64")]
+#[derive(Debug, Serialize, Deserialize)]
+struct VmStateV5 {
+    dist: Vec<GicRegState<u32>>,
+    gic_vcpu_states: Vec<GicVcpuState>,
+    kvm_cap_modifiers: Vec<KvmCapability>,
+}
+
+#[cfg(target_arch = "aarch64")]
+#[derive(Debug, Serialize, Deserialize)]
+struct VmStateV6 {
+    dist: Vec<GicRegState<u32>>,
+    gic_vcpu_states: Vec<GicVcpuState>,
+    its: Option<ItsState>,
+    kvm_cap_modifiers: Vec<KvmCapability>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RateLimiterStateV5 {
+    ops: Option<TokenBucketState>,
+    bandwidth: Option<TokenBucketState>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RateLimiterStateV6 {
+    ops: Option<TokenBucketState>,
+    bandwidth: Option<TokenBucketState>,
+    borrow: bool,
+    shared: Option<String>,
+    smoothing: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct MmioTransportStateV5 {
+    features_select: u32,
+    acked_features_select: u32,
+    queue_select: u32,
+    device_status: u32,
+    config_generation: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct MmioTransportStateV6 {
+    features_select: u32,
+    acked_features_select: u32,
+    queue_select: u32,
+    shm_select: u32,
+    device_status: u32,
+    config_generation: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ConnectedStateV5<T> {
+    device_id: String,
+    device_state: T,
+    transport_state: MmioTransportStateV5,
+    device_info: MMIODeviceInfo,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ConnectedStateV6<T> {
+    device_id: String,
+    device_state: T,
+    transport_state: MmioTransportStateV6,
+    device_info: MMIODeviceInfo,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct VirtioBlockStateV5 {
+    id: String,
+    partuuid: Option<String>,
+    cache_type: CacheType,
+    root_device: bool,
+    disk_path: String,
+    virtio_state: VirtioDeviceState,
+    rate_limiter_state: RateLimiterStateV5,
+    file_engine_type: FileEngineTypeState,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct VirtioBlockStateV6 {
+    id: String,
+    partuuid: Option<String>,
+    cache_type: CacheType,
+    root_device: bool,
+    disk_path: String,
+    virtio_state: VirtioDeviceState,
+    rate_limiter_state: RateLimiterStateV6,
+    file_engine_type: FileEngineTypeState,
+    remote: Option<RemoteDriveConfig>,
+    direct_io: bool,
+    backend: DriveBackend,
+    latency_injection: Option<LatencyInjectionConfig>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum BlockStateV5 {
+    Virtio(VirtioBlockStateV5),
+    VhostUser(VhostUserBlockState),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum BlockStateV6 {
+    Virtio(VirtioBlockStateV6),
+    VhostUser(VhostUserBlockState),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct MmdsNetworkStackStateV5 {
+    mac_addr: [u8; MAC_ADDR_LEN as usize],
+    ipv4_addr: u32,
+    tcp_port: u16,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct MmdsNetworkStackStateV6 {
+    mac_addr: [u8; MAC_ADDR_LEN as usize],
+    ipv4_addr: u32,
+    tcp_port: u16,
+    allowed_paths: Option<Vec<String>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct NetStateV5 {
+    id: String,
+    tap_if_name: String,
+    rx_rate_limiter_state: RateLimiterStateV5,
+    tx_rate_limiter_state: RateLimiterStateV5,
+    mmds_ns: Option<MmdsNetworkStackStateV5>,
+    guest_mac: Option<MacAddr>,
+    virtio_state: VirtioDeviceState,
+    rx_buffers_state: RxBufferState,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct NetStateV6 {
+    id: String,
+    tap_if_name: String,
+    rx_rate_limiter_state: RateLimiterStateV6,
+    tx_rate_limiter_state: RateLimiterStateV6,
+    mmds_ns: Option<MmdsNetworkStackStateV6>,
+    guest_mac: Option<MacAddr>,
+    mtu: Option<u16>,
+    virtio_state: VirtioDeviceState,
+    rx_buffers_state: Vec<RxBufferState>,
+    active_queue_pairs: u16,
+    max_flows: Option<usize>,
+    max_paused_connections: Option<usize>,
+    max_coalesced_segments: Option<usize>,
+    mac_filter: MacFilter,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum VsockBackendStateV5 {
+    Uds { path: String },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum VsockBackendStateV6 {
+    Uds {
+        path: String,
+        port_limiters: Vec<VsockPortLimiterState>,
+        tcp_forwards: Vec<VsockTcpForwardState>,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct VsockStateV5 {
+    backend: VsockBackendStateV5,
+    frontend: VsockFrontendState,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct VsockStateV6 {
+    backend: VsockBackendStateV6,
+    frontend: VsockFrontendState,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct EntropyStateV5 {
+    virtio_state: VirtioDeviceState,
+    rate_limiter_state: RateLimiterStateV5,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct EntropyStateV6 {
+    virtio_state: VirtioDeviceState,
+    rate_limiter_state: RateLimiterStateV6,
+    source: EntropySourceType,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct DeviceStatesV5 {
+    #[cfg(target_arch = "aarch64")]
+    legacy_devices: Vec<ConnectedLegacyState>,
+    block_devices: Vec<ConnectedStateV5<BlockStateV5>>,
+    net_devices: Vec<ConnectedStateV5<NetStateV5>>,
+    vsock_device: Option<ConnectedStateV5<VsockStateV5>>,
+    balloon_device: Option<ConnectedStateV5<BalloonState>>,
+    mmds_version: Option<MmdsVersionState>,
+    entropy_device: Option<ConnectedStateV5<EntropyStateV5>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct DeviceStatesV6 {
+    #[cfg(target_arch = "aarch64")]
+    legacy_devices: Vec<ConnectedLegacyState>,
+    block_devices: Vec<ConnectedStateV6<BlockStateV6>>,
+    net_devices: Vec<ConnectedStateV6<NetStateV6>>,
+    vsock_device: Option<ConnectedStateV6<VsockStateV6>>,
+    balloon_device: Option<ConnectedStateV6<BalloonState>>,
+    mmds_version: Option<MmdsVersionState>,
+    entropy_device: Option<ConnectedStateV6<EntropyStateV6>>,
+    tpm_device: Option<ConnectedTpmState>,
+    pvpanic_device: Option<ConnectedPvPanicState>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct MicrovmStateV5 {
+    vm_info: VmInfoV5,
+    memory_state: GuestMemoryState,
+    vm_state: VmStateV5,
+    vcpu_states: Vec<VcpuState>,
+    device_states: DeviceStatesV5,
+    vmgenid: Option<VMGenIDState>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct MicrovmStateV6 {
+    vm_info: VmInfoV6,
+    memory_state: GuestMemoryState,
+    vm_state: VmStateV6,
+    vcpu_states: Vec<VcpuState>,
+    device_states: DeviceStatesV6,
+    vmgenid: Option<VMGenIDState>,
+    power_button: Option<PowerButtonState>,
+    shared_rate_limiters: Vec<SharedRateLimiterState>,
+}
+
+fn upgrade_rate_limiter(old: RateLimiterStateV5) -> RateLimiterStateV6 {
+    RateLimiterStateV6 {
+        ops: old.ops,
+        bandwidth: old.bandwidth,
+        borrow: false,
+        shared: None,
+        smoothing: false,
+    }
+}
+
+fn downgrade_rate_limiter(new: RateLimiterStateV6) -> Result<RateLimiterStateV5, MigrationError> {
+    ensure_v5(!new.borrow, "Borrowing between rate limiters")?;
+    ensure_v5(new.shared.is_none(), "Shared rate limiters")?;
+    ensure_v5(!new.smoothing, "Smoothed rate limiters")?;
+    Ok(RateLimiterStateV5 {
+        ops: new.ops,
+        bandwidth: new.bandwidth,
+    })
+}
+
+fn upgrade_connected<T, U>(
+    old: ConnectedStateV5<T>,
+    f: impl FnOnce(T) -> U,
+) -> ConnectedStateV6<U> {
+    let transport = old.transport_state;
+    ConnectedStateV6 {
+        device_id: old.device_id,
+        device_state: f(old.device_state),
+        transport_state: MmioTransportStateV6 {
+            features_select: transport.features_select,
+            acked_features_select: transport.acked_features_select,
+            queue_select: transport.queue_select,
+            shm_select: 0,
+            device_status: transport.device_status,
+            config_generation: transport.config_generation,
+        },
+        device_info: old.device_info,
+    }
+}
+
+fn downgrade_connected<T, U>(
+    new: ConnectedStateV6<U>,
+    f: impl FnOnce(U) -> Result<T, MigrationError>,
+) -> Result<ConnectedStateV5<T>, MigrationError> {
+    let transport = new.transport_state;
+    ensure_v5(
+        transport.shm_select == 0,
+        "Shared memory regions of virtio devices",
+    )?;
+    Ok(ConnectedStateV5 {
+        device_id: new.device_id,
+        device_state: f(new.device_state)?,
+        transport_state: MmioTransportStateV5 {
+            features_select: transport.features_select,
+            acked_features_select: transport.acked_features_select,
+            queue_select: transport.queue_select,
+            device_status: transport.device_status,
+            config_generation: transport.config_generation,
+        },
+        device_info: new.device_info,
+    })
+}
+
+fn upgrade_block(old: BlockStateV5) -> BlockStateV6 {
+    match old {
+        BlockStateV5::Virtio(old) => BlockStateV6::Virtio(VirtioBlockStateV6 {
+            id: old.id,
+            partuuid: old.partuuid,
+            cache_type: old.cache_type,
+            root_device: old.root_device,
+            disk_path: old.disk_path,
+            virtio_state: old.virtio_state,
+            rate_limiter_state: upgrade_rate_limiter(old.rate_limiter_state),
+            file_engine_type: old.file_engine_type,
+            remote: None,
+            direct_io: false,
+            backend: DriveBackend::default(),
+            latency_injection: None,
+        }),
+        BlockStateV5::VhostUser(state) => BlockStateV6::VhostUser(state),
+    }
+}
+
+fn downgrade_block(new: BlockStateV6) -> Result<BlockStateV5, MigrationError> {
+    match new {
+        BlockStateV6::Virtio(new) => {
+            ensure_v5(
+                matches!(new.cache_type, CacheType::Unsafe | CacheType::Writeback),
+                "The writethrough and directsync cache types",
+            )?;
+            ensure_v5(new.remote.is_none(), "Remote drive images")?;
+            ensure_v5(!new.direct_io, "Direct I/O on drives")?;
+            ensure_v5(new.backend == DriveBackend::default(), "NBD drives")?;
+            ensure_v5(new.latency_injection.is_none(), "Latency injection")?;
+            Ok(BlockStateV5::Virtio(VirtioBlockStateV5 {
+                id: new.id,
+                partuuid: new.partuuid,
+                cache_type: new.cache_type,
+                root_device: new.root_device,
+                disk_path: new.disk_path,
+                virtio_state: new.virtio_state,
+                rate_limiter_state: downgrade_rate_limiter(new.rate_limiter_state)?,
+                file_engine_type: new.file_engine_type,
+            }))
+        }
+        BlockStateV6::VhostUser(state) => Ok(BlockStateV5::VhostUser(state)),
+    }
+}
+
+fn upgrade_net(old: NetStateV5) -> NetStateV6 {
+    NetStateV6 {
+        id: old.id,
+        tap_if_name: old.tap_if_name,
+        rx_rate_limiter_state: upgrade_rate_limiter(old.rx_rate_limiter_state),
+        tx_rate_limiter_state: upgrade_rate_limiter(old.tx_rate_limiter_state),
+        mmds_ns: old.mmds_ns.map(|ns| MmdsNetworkStackStateV6 {
+            mac_addr: ns.mac_addr,
+            ipv4_addr: ns.ipv4_addr,
+            tcp_port: ns.tcp_port,
+            allowed_paths: None,
+        }),
+        guest_mac: old.guest_mac,
+        mtu: None,
+        virtio_state: old.virtio_state,
+        rx_buffers_state: vec![old.rx_buffers_state],
+        active_queue_pairs: 1,
+        max_flows: None,
+        max_paused_connections: None,
+        max_coalesced_segments: None,
+        mac_filter: MacFilter::default(),
+    }
+}
+
+fn downgrade_net(new: NetStateV6) -> Result<NetStateV5, MigrationError> {
+    ensure_v5(
+        !new.tap_if_name.is_empty(),
+        "Network interfaces without a tap device",
+    )?;
+    ensure_v5(new.mtu.is_none(), "The MTU of network interfaces")?;
+    ensure_v5(new.max_flows.is_none(), "Per-flow accounting")?;
+    ensure_v5(new.max_paused_connections.is_none(), "The pause responder")?;
+    ensure_v5(new.max_coalesced_segments.is_none(), "RX coalescing")?;
+    ensure_v5(
+        new.mac_filter == MacFilter::default(),
+        "Filtering of spoofed frames",
+    )?;
+    let mmds_ns = match new.mmds_ns {
+        Some(ns) => {
+            ensure_v5(
+                ns.allowed_paths.is_none(),
+                "MMDS paths allowed per interface",
+            )?;
+            Some(MmdsNetworkStackStateV5 {
+                mac_addr: ns.mac_addr,
+                ipv4_addr: ns.ipv4_addr,
+                tcp_port: ns.tcp_port,
+            })
+        }
+        None => None,
+    };
+    let [rx_buffers_state]: [RxBufferState; 1] = new.rx_buffers_state.try_into().map_err(|_| {
+        MigrationError::Unrepresentable(
+            "Network interfaces with several queue pairs".to_string(),
+            V5,
+        )
+    })?;
+    Ok(NetStateV5 {
+        id: new.id,
+        tap_if_name: new.tap_if_name,
+        rx_rate_limiter_state: downgrade_rate_limiter(new.rx_rate_limiter_state)?,
+        tx_rate_limiter_state: downgrade_rate_limiter(new.tx_rate_limiter_state)?,
+        mmds_ns,
+        guest_mac: new.guest_mac,
+        virtio_state: new.virtio_state,
+        rx_buffers_state,
+    })
+}
+
+fn upgrade_vsock(old: VsockStateV5) -> VsockStateV6 {
+    let VsockBackendStateV5::Uds { path } = old.backend;
+    VsockStateV6 {
+        backend: VsockBackendStateV6::Uds {
+            path,
+            port_limiters: Vec::new(),
+            tcp_forwards: Vec::new(),
+        },
+        frontend: old.frontend,
+    }
+}
+
+fn downgrade_vsock(new: VsockStateV6) -> Result<VsockStateV5, MigrationError> {
+    let VsockBackendStateV6::Uds {
+        path,
+        port_limiters,
+        tcp_forwards,
+    } = new.backend;
+    ensure_v5(port_limiters.is_empty(), "Rate limiters of vsock ports")?;
+    ensure_v5(tcp_forwards.is_empty(), "Vsock ports forwarded to TCP")?;
+    let socket_features = 1 << VIRTIO_VSOCK_F_SEQPACKET | 1 << VIRTIO_VSOCK_F_DGRAM;
+    ensure_v5(
+        new.frontend.avail_features() & socket_features == 0,
+        "Seqpacket and datagram vsock sockets",
+    )?;
+    Ok(VsockStateV5 {
+        backend: VsockBackendStateV5::Uds { path },
+        frontend: new.frontend,
+    })
+}
+
+fn upgrade_entropy(old: EntropyStateV5) -> EntropyStateV6 {
+    EntropyStateV6 {
+        virtio_state: old.virtio_state,
+        rate_limiter_state: upgrade_rate_limiter(old.rate_limiter_state),
+        source: EntropySourceType::default(),
+    }
+}
+
+fn downgrade_entropy(new: EntropyStateV6) -> Result<EntropyStateV5, MigrationError> {
+    ensure_v5(
+        new.source == EntropySourceType::default(),
+        "Entropy sources other than the default one",
+    )?;
+    Ok(EntropyStateV5 {
+        virtio_state: new.virtio_state,
+        rate_limiter_state: downgrade_rate_limiter(new.rate_limiter_state)?,
+    })
+}
+
+#[cfg(target_arch = "x86_64")]
+fn upgrade_vm_state(old: VmStateV5) -> VmStateV6 {
+    old
+}
+
+#[cfg(target_arch = "aarch64")]
+fn upgrade_vm_state(old: VmStateV5) -> VmStateV6 {
+    // Guests restored from version 5.0.0 snapshots don't know about the ITS, which is left in its
+    // reset state.
+    VmStateV6 {
+        dist: old.dist,
+        gic_vcpu_states: old.gic_vcpu_states,
+        its: None,
+        kvm_cap_modifiers: old.kvm_cap_modifiers,
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+fn downgrade_vm_state(new: VmStateV6) -> VmStateV5 {
+    new
+}
+
+#[cfg(target_arch = "aarch64")]
+fn downgrade_vm_state(new: VmStateV6) -> VmStateV5 {
+    // The ITS only translates MSIs, which none of the devices use, so its state can be dropped.
+    VmStateV5 {
+        dist: new.dist,
+        gic_vcpu_states: new.gic_vcpu_states,
+        kvm_cap_modifiers: new.kvm_cap_modifiers,
+    }
+}
+
+fn upgrade_vm_info(old: VmInfoV5) -> VmInfoV6 {
+    let boot_source = old.boot_source;
+    VmInfoV6 {
+        mem_size_mib: old.mem_size_mib,
+        smt: old.smt,
+        cpu_template: old.cpu_template,
+        boot_source: BootSourceConfigV6 {
+            kernel_image_path: boot_source.kernel_image_path,
+            initrd_path: boot_source.initrd_path.map(|path| vec![path]),
+            kernel_image_fd: None,
+            initrd_fd: None,
+            boot_args: boot_source.boot_args.map(BootArgs::Raw),
+            firmware_path: None,
+            kernel_digest: None,
+            initrd_digest: None,
+        },
+        huge_pages: old.huge_pages,
+        reserved_memory: Vec::new(),
+        shared_memory: Vec::new(),
+        disabled_legacy_devices: Vec::new(),
+        kernel_version: String::new(),
+    }
+}
+
+fn downgrade_vm_info(new: VmInfoV6) -> Result<VmInfoV5, MigrationError> {
+    ensure_v5(new.reserved_memory.is_empty(), "Reserved memory regions")?;
+    ensure_v5(new.shared_memory.is_empty(), "Shared memory segments")?;
+    ensure_v5(
+        new.disabled_legacy_devices.is_empty(),
+        "Disabled legacy devices",
+    )?;
+    // The boot source is only reported by the restored microVM, which doesn't load the kernel
+    // again, so the fields unknown to version 5.0.0 are dropped along with the kernel version.
+    let boot_source = new.boot_source;
+    let initrd_path = match boot_source.initrd_path {
+        Some(mut paths) if paths.len() == 1 => paths.pop(),
+        Some(_) => {
+            return Err(MigrationError::Unrepresentable(
+                "Concatenated initrd images".to_string(),
+                V5,
+            ))
+        }
+        None => None,
+    };
+    let boot_args = match boot_source.boot_args {
+        Some(boot_args) => Some(boot_args.to_cmdline().map_err(|err| {
+            MigrationError::Unrepresentable(format!("The boot arguments ({err})"), V5)
+        })?),
+        None => None,
+    };
+    Ok(VmInfoV5 {
+        mem_size_mib: new.mem_size_mib,
+        smt: new.smt,
+        cpu_template: new.cpu_template,
+        boot_source: BootSourceConfigV5 {
+            kernel_image_path: boot_source.kernel_image_path,
+            initrd_path,
+            boot_args,
+        },
+        huge_pages: new.huge_pages,
+    })
+}
+
+fn upgrade_v6_state(old: MicrovmStateV5) -> MicrovmStateV6 {
+    let devices = old.device_states;
+    MicrovmStateV6 {
+        vm_info: upgrade_vm_info(old.vm_info),
+        memory_state: old.memory_state,
+        vm_state: upgrade_vm_state(old.vm_state),
+        vcpu_states: old.vcpu_states,
+        device_states: DeviceStatesV6 {
+            #[cfg(target_arch = "aarch64")]
+            legacy_devices: devices.legacy_devices,
+            block_devices: devices
+                .block_devices
+                .into_iter()
+                .map(|state| upgrade_connected(state, upgrade_block))
+                .collect(),
+            net_devices: devices
+                .net_devices
+                .into_iter()
+                .map(|state| upgrade_connected(state, upgrade_net))
+                .collect(),
+            vsock_device: devices
+                .vsock_device
+                .map(|state| upgrade_connected(state, upgrade_vsock)),
+            balloon_device: devices
+                .balloon_device
+                .map(|state| upgrade_connected(state, identity)),
+            mmds_version: devices.mmds_version,
+            entropy_device: devices
+                .entropy_device
+                .map(|state| upgrade_connected(state, upgrade_entropy)),
+            tpm_device: None,
+            pvpanic_device: None,
+        },
+        vmgenid: old.vmgenid,
+        // Microvms restored from version 5.0.0 snapshots don't have a power button.
+        power_button: None,
+        shared_rate_limiters: Vec::new(),
+    }
+}
+
+fn downgrade_v6_state(new: MicrovmStateV6) -> Result<MicrovmStateV5, MigrationError> {
+    let devices = new.device_states;
+    ensure_v5(devices.tpm_device.is_none(), "The TPM device")?;
+    ensure_v5(devices.pvpanic_device.is_none(), "The pvpanic device")?;
+    ensure_v5(new.shared_rate_limiters.is_empty(), "Shared rate limiters")?;
+    // The power button of the microVM, if any, is dropped, which only leaves the guest without
+    // its events.
+    Ok(MicrovmStateV5 {
+        vm_info: downgrade_vm_info(new.vm_info)?,
+        memory_state: new.memory_state,
+        vm_state: downgrade_vm_state(new.vm_state),
+        vcpu_states: new.vcpu_states,
+        device_states: DeviceStatesV5 {
+            #[cfg(target_arch = "aarch64")]
+            legacy_devices: devices.legacy_devices,
+            block_devices: devices
+                .block_devices
+                .into_iter()
+                .map(|state| downgrade_connected(state, downgrade_block))
+                .collect::<Result<_, _>>()?,
+            net_devices: devices
+                .net_devices
+                .into_iter()
+                .map(|state| downgrade_connected(state, downgrade_net))
+                .collect::<Result<_, _>>()?,
+            vsock_device: devices
+                .vsock_device
+                .map(|state| downgrade_connected(state, downgrade_vsock))
+                .transpose()?,
+            balloon_device: devices
+                .balloon_device
+                .map(|state| downgrade_connected(state, Ok))
+                .transpose()?,
+            mmds_version: devices.mmds_version,
+            entropy_device: devices
+                .entropy_device
+                .map(|state| downgrade_connected(state, downgrade_entropy))
+                .transpose()?,
+        },
+        vmgenid: new.vmgenid,
+    })
+}
+
+/// Converts a microVM state serialized in version 5.0.0 of the snapshot format to version 6.0.0.
+pub fn upgrade_v6(state: &[u8]) -> Result<Vec<u8>, MigrationError> {
+    convert(state, |old| Ok(upgrade_v6_state(old)))
+}
+
+/// Converts a microVM state serialized in version 6.0.0 of the snapshot format to version 5.0.0.
+pub fn downgrade_v6(state: &[u8]) -> Result<Vec<u8>, MigrationError> {
+    convert(state, downgrade_v6_state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rate_limiter::RateLimiter;
+    use crate::snapshot::{Persist, Snapshot};
+
+    #[test]
+    fn test_migrate_rate_limiter() {
+        let refill_time = 100_000;
+        let mut rate_limiter = RateLimiter::new(100, 0, refill_time, 10, 0, refill_time).unwrap();
+        let mut state = Vec::new();
+        Snapshot::serialize(&mut state, &rate_limiter.save()).unwrap();
+
+        // The state of version 5.0.0 has none of the fields added since then.
+        let v5_state = convert(&state, downgrade_rate_limiter).unwrap();
+        let mut reader = v5_state.as_slice();
+        let _: RateLimiterStateV5 = Snapshot::deserialize(&mut reader).unwrap();
+        assert!(reader.is_empty());
+
+        let v6_state = convert(&v5_state, |old| Ok(upgrade_rate_limiter(old))).unwrap();
+        assert_eq!(v6_state, state);
+        let restored_rate_limiter = RateLimiter::restore(
+            (),
+            &Snapshot::deserialize(&mut v6_state.as_slice()).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(restored_rate_limiter, rate_limiter);
+
+        // Borrowing limiters cannot be represented in version 5.0.0.
+        rate_limiter.set_borrow(true);
+        let mut state = Vec::new();
+        Snapshot::serialize(&mut state, &rate_limiter.save()).unwrap();
+        assert_eq!(
+            convert(&state, downgrade_rate_limiter).unwrap_err(),
+            MigrationError::Unrepresentable("Borrowing between rate limiters".to_string(), V5)
+        );
+    }
+}
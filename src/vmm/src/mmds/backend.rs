@@ -0,0 +1,193 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Retrieval of the MMDS content from a host process listening on a Unix socket, so that the
+//! content can be generated when the guest requests it.
+//!
+//! Each request is sent on a new connection, as a line of JSON holding the JSON pointer of the
+//! requested content. The backend answers with a line of JSON holding the content in its `value`
+//! field, which is left out or null when there is no content at this path. The backend is given
+//! a short time to answer, as the guest requests are handled by the VMM thread.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::logger::{IncMetric, METRICS};
+use crate::vmm_config::mmds::MmdsBackendConfig;
+
+/// Maximum length of the answers of the backend, in bytes.
+const MAX_RESPONSE_LEN: u64 = 1 << 20;
+
+/// Errors triggered when retrieving content from the MMDS backend.
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum MmdsBackendError {
+    /// Failed to connect to the MMDS backend: {0}
+    Connect(io::Error),
+    /// Failed to write the request to the MMDS backend: {0}
+    Write(io::Error),
+    /// Failed to read the answer of the MMDS backend: {0}
+    Read(io::Error),
+    /// The MMDS backend did not answer in time.
+    Timeout,
+    /// Invalid answer from the MMDS backend: {0}
+    InvalidResponse(serde_json::Error),
+}
+
+/// Request written to the backend, as a line of JSON.
+#[derive(Debug, Serialize)]
+struct BackendRequest<'a> {
+    path: &'a str,
+}
+
+/// Answer read from the backend, as a line of JSON.
+#[derive(Debug, Deserialize)]
+struct BackendResponse {
+    #[serde(default)]
+    value: Option<Value>,
+}
+
+/// Client of the MMDS backend.
+#[derive(Debug)]
+pub struct MmdsBackend {
+    socket_path: PathBuf,
+    timeout: Duration,
+}
+
+impl MmdsBackend {
+    /// Creates a client of the backend configured by `config`.
+    pub fn new(config: &MmdsBackendConfig) -> Self {
+        MmdsBackend {
+            socket_path: config.socket_path.clone(),
+            timeout: Duration::from_millis(config.timeout_ms),
+        }
+    }
+
+    /// Returns the configuration of the backend.
+    pub fn config(&self) -> MmdsBackendConfig {
+        MmdsBackendConfig {
+            socket_path: self.socket_path.clone(),
+            // The timeout was built from milliseconds fitting a `u64`.
+            timeout_ms: u64::try_from(self.timeout.as_millis()).unwrap(),
+        }
+    }
+
+    /// Retrieves the content located at the JSON pointer `path`, if any.
+    pub fn get(&self, path: &str) -> Result<Option<Value>, MmdsBackendError> {
+        METRICS.mmds.backend_requests.inc();
+        self.request(path).inspect_err(|_| {
+            METRICS.mmds.backend_fails.inc();
+        })
+    }
+
+    fn request(&self, path: &str) -> Result<Option<Value>, MmdsBackendError> {
+        let mut line = serde_json::to_vec(&BackendRequest { path })
+            .map_err(|err| MmdsBackendError::Write(err.into()))?;
+        line.push(b'\n');
+
+        let mut stream =
+            UnixStream::connect(&self.socket_path).map_err(MmdsBackendError::Connect)?;
+        stream
+            .set_write_timeout(Some(self.timeout))
+            .map_err(MmdsBackendError::Write)?;
+        stream
+            .set_read_timeout(Some(self.timeout))
+            .map_err(MmdsBackendError::Read)?;
+        stream
+            .write_all(&line)
+            .map_err(timeout_error(MmdsBackendError::Write))?;
+
+        let mut response = Vec::new();
+        BufReader::new(io::Read::take(stream, MAX_RESPONSE_LEN))
+            .read_until(b'\n', &mut response)
+            .map_err(timeout_error(MmdsBackendError::Read))?;
+        let response: BackendResponse =
+            serde_json::from_slice(&response).map_err(MmdsBackendError::InvalidResponse)?;
+        Ok(response.value.filter(|value| !value.is_null()))
+    }
+}
+
+/// Maps the errors of socket operations timing out to [`MmdsBackendError::Timeout`].
+fn timeout_error(
+    other: fn(io::Error) -> MmdsBackendError,
+) -> impl Fn(io::Error) -> MmdsBackendError {
+    move |err| match err.kind() {
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => MmdsBackendError::Timeout,
+        _ => other(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::net::UnixListener;
+    use std::path::Path;
+    use std::thread;
+
+    use vmm_sys_util::tempdir::TempDir;
+
+    use super::*;
+
+    fn backend(socket_path: &Path) -> MmdsBackend {
+        MmdsBackend::new(&MmdsBackendConfig {
+            socket_path: socket_path.to_path_buf(),
+            timeout_ms: 200,
+        })
+    }
+
+    #[test]
+    fn test_backend_get() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.as_path().join("mmds.sock");
+        let backend = backend(&path);
+        assert!(matches!(
+            backend.get("/latest"),
+            Err(MmdsBackendError::Connect(_))
+        ));
+
+        let listener = UnixListener::bind(&path).unwrap();
+        let server = thread::spawn(move || {
+            let mut requests = Vec::new();
+            for response in [
+                &b"{\"value\": {\"token\": \"abc\"}}\n"[..],
+                b"{}\n",
+                b"{\"value\": null}\n",
+                b"invalid\n",
+            ] {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut line = String::new();
+                BufReader::new(stream.try_clone().unwrap())
+                    .read_line(&mut line)
+                    .unwrap();
+                requests.push(line);
+                stream.write_all(response).unwrap();
+            }
+            // The last request is not answered.
+            let (unanswered, _) = listener.accept().unwrap();
+            (requests, unanswered)
+        });
+
+        assert_eq!(
+            backend.get("/latest/meta-data").unwrap(),
+            Some(serde_json::json!({"token": "abc"}))
+        );
+        assert_eq!(backend.get("/missing").unwrap(), None);
+        assert_eq!(backend.get("/null").unwrap(), None);
+        assert!(matches!(
+            backend.get("/invalid"),
+            Err(MmdsBackendError::InvalidResponse(_))
+        ));
+        assert!(matches!(
+            backend.get("/slow"),
+            Err(MmdsBackendError::Timeout)
+        ));
+
+        let (requests, _unanswered) = server.join().unwrap();
+        let request: Value = serde_json::from_str(&requests[0]).unwrap();
+        assert_eq!(request, serde_json::json!({"path": "/latest/meta-data"}));
+        assert_eq!(backend.config().timeout_ms, 200);
+    }
+}
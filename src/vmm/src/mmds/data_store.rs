@@ -1,13 +1,19 @@
 // Copyright 2018 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
 use std::fmt;
 use std::fmt::{Display, Formatter};
+use std::hash::{Hash, Hasher};
 
 use serde::{Deserialize, Serialize};
-use serde_json::{to_vec, Value};
+use serde_json::{to_vec, Map, Value};
 
+use crate::mmds::backend::{MmdsBackend, MmdsBackendError};
+pub use crate::mmds::token::MmdsSession;
 use crate::mmds::token::{MmdsTokenError as TokenError, TokenAuthority};
+use crate::vmm_config::mmds::MmdsBackendConfig;
 
 /// The Mmds is the Microvm Metadata Service represented as an untyped json.
 #[derive(Debug)]
@@ -17,8 +23,23 @@ pub struct Mmds {
     token_authority: Option<TokenAuthority>,
     is_initialized: bool,
     data_store_limit: usize,
+    // Key/value pairs written by the guest. Only accepted when a limit is configured.
+    guest_data: Map<String, Value>,
+    guest_data_limit: Option<usize>,
+    // Responses already rendered from the data store, dropped whenever it is updated.
+    rendered: HashMap<(String, OutputFormat), RenderedValue>,
+    // Serves the content retrieved by the guest in place of the data store, if configured.
+    backend: Option<MmdsBackend>,
 }
 
+/// Path prefix under which the guest is allowed to write key/value pairs.
+pub const PATH_TO_GUEST_DATA: &str = "/latest/guest-data";
+/// Maximum length of a key written by the guest.
+pub const MAX_GUEST_DATA_KEY_LEN: usize = 64;
+
+/// Maximum number of rendered responses cached by the MMDS.
+pub const MAX_RENDERED_VALUES: usize = 64;
+
 /// MMDS version.
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
 pub enum MmdsVersion {
@@ -39,7 +60,7 @@ impl Display for MmdsVersion {
 }
 
 /// MMDS possible outputs.
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum OutputFormat {
     /// MMDS output format as Json
     Json,
@@ -47,13 +68,43 @@ pub enum OutputFormat {
     Imds,
 }
 
+/// A subtree of the MMDS data store rendered in an output format, along with its entity tag.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RenderedValue {
+    /// The rendered subtree.
+    pub body: String,
+    /// Quoted entity tag derived from the rendered subtree, as sent in the `ETag` header.
+    pub etag: String,
+}
+
+impl RenderedValue {
+    pub(crate) fn new(body: String) -> Self {
+        // The default hasher uses fixed keys, so that the tag of a body does not change across
+        // updates of the data store leaving it untouched.
+        let mut hasher = DefaultHasher::new();
+        body.hash(&mut hasher);
+        let etag = format!("\"{:016x}\"", hasher.finish());
+        RenderedValue { body, etag }
+    }
+}
+
 #[derive(Debug, thiserror::Error, displaydoc::Display)]
 /// MMDS data store errors
 pub enum MmdsDatastoreError {
+    /// MMDS backend error: {0}
+    Backend(#[from] MmdsBackendError),
     /// The MMDS patch request doesn't fit.
     DataStoreLimitExceeded,
+    /// The guest data write doesn't fit in the configured quota.
+    GuestDataLimitExceeded,
+    /// Guest writes are not enabled for the MMDS data store.
+    GuestDataNotEnabled,
+    /// Invalid guest data key: {0}
+    InvalidGuestDataKey(String),
     /// The MMDS resource does not exist.
     NotFound,
+    /// No outstanding MMDS session matches `{0}`.
+    SessionNotFound(String),
     /// The MMDS data store is not initialized.
     NotInitialized,
     /// Token Authority error: {0}
@@ -77,6 +128,10 @@ impl Mmds {
             token_authority: None,
             is_initialized: false,
             data_store_limit,
+            guest_data: Map::new(),
+            guest_data_limit: None,
+            rendered: HashMap::new(),
+            backend: None,
         }
     }
 
@@ -140,11 +195,81 @@ impl Mmds {
             .and_then(|ta| ta.generate_token_secret(ttl_seconds))
     }
 
+    /// Returns the outstanding sessions opened by the guest. There are none when MMDS version 1
+    /// is configured.
+    pub fn sessions(&self) -> Vec<MmdsSession> {
+        self.token_authority
+            .as_ref()
+            .map(TokenAuthority::sessions)
+            .unwrap_or_default()
+    }
+
+    /// Revokes the session identified by `session`, either its identifier or its token.
+    pub fn revoke_session(&mut self, session: &str) -> Result<(), MmdsDatastoreError> {
+        match self.token_authority.as_mut() {
+            Some(ta) if ta.revoke_session(session) => Ok(()),
+            _ => Err(MmdsDatastoreError::SessionNotFound(session.to_string())),
+        }
+    }
+
+    /// Serves the content retrieved by the guest from the backend configured by `config`, or
+    /// from the data store if `None`.
+    pub fn set_backend(&mut self, config: Option<&MmdsBackendConfig>) {
+        self.backend = config.map(MmdsBackend::new);
+    }
+
+    /// Returns the configuration of the backend serving the content retrieved by the guest, if
+    /// any.
+    pub fn backend_config(&self) -> Option<MmdsBackendConfig> {
+        self.backend.as_ref().map(MmdsBackend::config)
+    }
+
     /// set MMDS data store limit to `data_store_limit`
     pub fn set_data_store_limit(&mut self, data_store_limit: usize) {
         self.data_store_limit = data_store_limit;
     }
 
+    /// Set the size limit of the guest writable data. `None` disables guest writes.
+    pub fn set_guest_data_limit(&mut self, guest_data_limit: Option<usize>) {
+        self.guest_data_limit = guest_data_limit;
+    }
+
+    /// Returns the size limit of the guest writable data, if guest writes are enabled.
+    pub fn guest_data_limit(&self) -> Option<usize> {
+        self.guest_data_limit
+    }
+
+    /// Store the `value` written by the guest under `key`.
+    pub fn put_guest_data(&mut self, key: &str, value: String) -> Result<(), MmdsDatastoreError> {
+        let limit = self
+            .guest_data_limit
+            .ok_or(MmdsDatastoreError::GuestDataNotEnabled)?;
+
+        if key.is_empty()
+            || key.len() > MAX_GUEST_DATA_KEY_LEN
+            || !key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+        {
+            return Err(MmdsDatastoreError::InvalidGuestDataKey(key.to_string()));
+        }
+
+        let mut guest_data_clone = self.guest_data.clone();
+        guest_data_clone.insert(key.to_string(), Value::String(value));
+        // It is safe to unwrap because all map keys are strings and
+        // we are using default serializer which does not return error.
+        if to_vec(&guest_data_clone).unwrap().len() > limit {
+            return Err(MmdsDatastoreError::GuestDataLimitExceeded);
+        }
+        self.guest_data = guest_data_clone;
+        Ok(())
+    }
+
+    /// Return the key/value pairs written by the guest.
+    pub fn guest_data_value(&self) -> Value {
+        Value::Object(self.guest_data.clone())
+    }
+
     /// put `data` in MMDS data store
     pub fn put_data(&mut self, data: Value) -> Result<(), MmdsDatastoreError> {
         // It is safe to unwrap because any map keys are all strings and
@@ -154,6 +279,7 @@ impl Mmds {
         } else {
             self.data_store = data;
             self.is_initialized = true;
+            self.rendered.clear();
 
             Ok(())
         }
@@ -171,6 +297,7 @@ impl Mmds {
             return Err(MmdsDatastoreError::DataStoreLimitExceeded);
         }
         self.data_store = data_store_clone;
+        self.rendered.clear();
         Ok(())
     }
 
@@ -251,7 +378,8 @@ impl Mmds {
     }
 
     /// Returns the subtree located at path. When the path corresponds to a leaf, it returns the
-    /// value. Returns Error::NotFound when the path is invalid.
+    /// value. Returns Error::NotFound when the path is invalid. The subtree is retrieved from the
+    /// backend instead of the data store if one is configured.
     pub fn get_value(
         &self,
         path: String,
@@ -259,10 +387,15 @@ impl Mmds {
     ) -> Result<String, MmdsDatastoreError> {
         // The pointer function splits the input by "/". With a trailing "/", pointer does not
         // know how to get the object.
-        let value = if path.ends_with('/') {
-            self.data_store.pointer(&path.as_str()[..(path.len() - 1)])
-        } else {
-            self.data_store.pointer(path.as_str())
+        let path = path.strip_suffix('/').unwrap_or(&path);
+
+        let backend_value;
+        let value = match self.backend.as_ref() {
+            Some(backend) => {
+                backend_value = backend.get(path)?;
+                backend_value.as_ref()
+            }
+            None => self.data_store.pointer(path),
         };
 
         if let Some(json) = value {
@@ -274,6 +407,36 @@ impl Mmds {
             Err(MmdsDatastoreError::NotFound)
         }
     }
+
+    /// Returns the subtree located at path like `get_value`, along with its entity tag. The
+    /// rendered subtree is cached until the next update of the data store, unless it was retrieved
+    /// from the backend.
+    pub fn get_rendered_value(
+        &mut self,
+        path: String,
+        format: OutputFormat,
+    ) -> Result<RenderedValue, MmdsDatastoreError> {
+        if self.backend.is_some() {
+            return Ok(RenderedValue::new(self.get_value(path, format)?));
+        }
+        let key = (path, format);
+        if let Some(rendered) = self.rendered.get(&key) {
+            return Ok(rendered.clone());
+        }
+
+        let rendered = RenderedValue::new(self.get_value(key.0.clone(), format)?);
+        // Bound the memory held by the cache, whatever the paths requested by the guest.
+        if self.rendered.len() >= MAX_RENDERED_VALUES {
+            self.rendered.clear();
+        }
+        self.rendered.insert(key, rendered.clone());
+        Ok(rendered)
+    }
+
+    /// Returns the number of rendered responses currently cached.
+    pub fn rendered_values_len(&self) -> usize {
+        self.rendered.len()
+    }
 }
 
 #[cfg(test)]
@@ -565,6 +728,152 @@ mod tests {
         assert_eq!(mmds.get_data_str().len(), 72);
     }
 
+    #[test]
+    fn test_get_value_from_backend() {
+        use std::io::{BufRead, BufReader, Write};
+        use std::os::unix::net::UnixListener;
+
+        use vmm_sys_util::tempdir::TempDir;
+
+        let dir = TempDir::new().unwrap();
+        let socket_path = dir.as_path().join("mmds.sock");
+        let listener = UnixListener::bind(&socket_path).unwrap();
+        let server = std::thread::spawn(move || {
+            for _ in 0..3 {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut line = String::new();
+                BufReader::new(stream.try_clone().unwrap())
+                    .read_line(&mut line)
+                    .unwrap();
+                let request: Value = serde_json::from_str(&line).unwrap();
+                let response = match request["path"].as_str().unwrap() {
+                    "/latest/token" => r#"{"value": "abc"}"#,
+                    _ => "{}",
+                };
+                stream.write_all(response.as_bytes()).unwrap();
+                stream.write_all(b"\n").unwrap();
+            }
+        });
+
+        let mut mmds = Mmds::default();
+        mmds.put_data(serde_json::json!({"latest": {"token": "stale"}}))
+            .unwrap();
+        let config = MmdsBackendConfig {
+            socket_path,
+            timeout_ms: 1000,
+        };
+        mmds.set_backend(Some(&config));
+        assert_eq!(mmds.backend_config(), Some(config));
+
+        // The content retrieved from the backend is not cached.
+        for _ in 0..2 {
+            assert_eq!(
+                mmds.get_rendered_value("/latest/token/".to_string(), OutputFormat::Imds)
+                    .unwrap()
+                    .body,
+                "abc"
+            );
+        }
+        assert_eq!(mmds.rendered_values_len(), 0);
+        assert!(matches!(
+            mmds.get_value("/latest/missing".to_string(), OutputFormat::Json),
+            Err(MmdsDatastoreError::NotFound)
+        ));
+        server.join().unwrap();
+
+        // The backend stopped listening.
+        assert!(matches!(
+            mmds.get_value("/latest/token".to_string(), OutputFormat::Json),
+            Err(MmdsDatastoreError::Backend(_))
+        ));
+
+        mmds.set_backend(None);
+        assert_eq!(
+            mmds.get_value("/latest/token".to_string(), OutputFormat::Json)
+                .unwrap(),
+            "\"stale\""
+        );
+    }
+
+    #[test]
+    fn test_sessions() {
+        let mut mmds = Mmds::default();
+        assert!(mmds.sessions().is_empty());
+        assert_eq!(
+            mmds.revoke_session("foo").unwrap_err().to_string(),
+            "No outstanding MMDS session matches `foo`."
+        );
+
+        mmds.set_version(MmdsVersion::V2).unwrap();
+        let token = mmds.generate_token(60).unwrap();
+        let sessions = mmds.sessions();
+        assert_eq!(sessions.len(), 1);
+        mmds.revoke_session(&sessions[0].session_id).unwrap();
+        assert!(!mmds.is_valid_token(&token).unwrap());
+        assert!(mmds.sessions().is_empty());
+        mmds.revoke_session(&token).unwrap_err();
+    }
+
+    #[test]
+    fn test_get_rendered_value() {
+        let mut mmds = Mmds::default();
+        let data = r#"{"name": {"first": "John", "second": "Doe"}, "age": 43}"#;
+        mmds.put_data(serde_json::from_str(data).unwrap()).unwrap();
+
+        let rendered = mmds
+            .get_rendered_value("/name".to_string(), OutputFormat::Imds)
+            .unwrap();
+        assert_eq!(rendered.body, "first\nsecond");
+        assert!(rendered.etag.starts_with('"') && rendered.etag.ends_with('"'));
+        assert_eq!(
+            mmds.get_rendered_value("/name".to_string(), OutputFormat::Imds)
+                .unwrap(),
+            rendered
+        );
+        let json = mmds
+            .get_rendered_value("/name".to_string(), OutputFormat::Json)
+            .unwrap();
+        assert_eq!(json.body, r#"{"first":"John","second":"Doe"}"#);
+        assert_ne!(json.etag, rendered.etag);
+        assert_eq!(mmds.rendered_values_len(), 2);
+
+        // Errors are not cached.
+        assert!(matches!(
+            mmds.get_rendered_value("/missing".to_string(), OutputFormat::Json),
+            Err(MmdsDatastoreError::NotFound)
+        ));
+        assert_eq!(mmds.rendered_values_len(), 2);
+
+        // Patching the data store drops the rendered values, and the tag of a subtree only
+        // changes along with its content.
+        mmds.patch_data(serde_json::from_str(r#"{"age": 44}"#).unwrap())
+            .unwrap();
+        assert_eq!(mmds.rendered_values_len(), 0);
+        assert_eq!(
+            mmds.get_rendered_value("/name".to_string(), OutputFormat::Json)
+                .unwrap(),
+            json
+        );
+        mmds.patch_data(serde_json::from_str(r#"{"name": {"first": "Jane"}}"#).unwrap())
+            .unwrap();
+        let patched = mmds
+            .get_rendered_value("/name".to_string(), OutputFormat::Json)
+            .unwrap();
+        assert_eq!(patched.body, r#"{"first":"Jane","second":"Doe"}"#);
+        assert_ne!(patched.etag, json.etag);
+
+        // The cache is bounded.
+        let keys: Map<String, Value> = (0..=MAX_RENDERED_VALUES)
+            .map(|i| (format!("key{}", i), Value::String(i.to_string())))
+            .collect();
+        mmds.put_data(Value::Object(keys)).unwrap();
+        for i in 0..=MAX_RENDERED_VALUES {
+            mmds.get_rendered_value(format!("/key{}", i), OutputFormat::Imds)
+                .unwrap();
+            assert!(mmds.rendered_values_len() <= MAX_RENDERED_VALUES);
+        }
+    }
+
     #[test]
     fn test_put_size_limit() {
         let mut mmds = Mmds::default();
@@ -581,6 +890,58 @@ mod tests {
         assert_eq!(mmds.get_data_str().len(), 2);
     }
 
+    #[test]
+    fn test_guest_data() {
+        let mut mmds = Mmds::default();
+
+        // Guest writes are disabled by default.
+        assert_eq!(mmds.guest_data_limit(), None);
+        assert_eq!(
+            mmds.put_guest_data("ready", "true".to_string())
+                .unwrap_err()
+                .to_string(),
+            MmdsDatastoreError::GuestDataNotEnabled.to_string()
+        );
+
+        mmds.set_guest_data_limit(Some(64));
+        mmds.put_guest_data("ready", "true".to_string()).unwrap();
+        mmds.put_guest_data("port", "8080".to_string()).unwrap();
+        assert_eq!(
+            mmds.guest_data_value(),
+            serde_json::json!({"ready": "true", "port": "8080"})
+        );
+
+        // Overwrite an existing key.
+        mmds.put_guest_data("ready", "false".to_string()).unwrap();
+        assert_eq!(mmds.guest_data_value()["ready"], "false");
+
+        // Invalid keys.
+        let long_key = "k".repeat(MAX_GUEST_DATA_KEY_LEN + 1);
+        for key in ["", "a/b", "with space", long_key.as_str()] {
+            assert_eq!(
+                mmds.put_guest_data(key, "v".to_string())
+                    .unwrap_err()
+                    .to_string(),
+                MmdsDatastoreError::InvalidGuestDataKey(key.to_string()).to_string()
+            );
+        }
+
+        // Quota exceeded, the data store is left untouched.
+        let filling = (0..64).map(|_| "X").collect::<String>();
+        assert_eq!(
+            mmds.put_guest_data("big", filling).unwrap_err().to_string(),
+            MmdsDatastoreError::GuestDataLimitExceeded.to_string()
+        );
+        assert!(mmds.guest_data_value().get("big").is_none());
+        assert_eq!(
+            mmds.guest_data_value(),
+            serde_json::json!({"ready": "false", "port": "8080"})
+        );
+
+        // The guest data is separate from the host data store.
+        assert_eq!(mmds.data_store_value(), Value::Null);
+    }
+
     #[test]
     fn test_is_valid() {
         let mut mmds = Mmds::default();
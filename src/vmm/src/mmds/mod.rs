@@ -1,6 +1,8 @@
 // Copyright 2018 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
+/// MMDS backend serving the content from a Unix socket
+pub mod backend;
 /// MMDS data store
 pub mod data_store;
 /// MMDS network stack
@@ -19,7 +21,11 @@ use micro_http::{
 use serde_json::{Map, Value};
 use token_headers::TokenHeaders;
 
-use crate::mmds::data_store::{Mmds, MmdsDatastoreError as MmdsError, MmdsVersion, OutputFormat};
+use crate::logger::{IncMetric, METRICS};
+use crate::mmds::data_store::{
+    Mmds, MmdsDatastoreError as MmdsError, MmdsVersion, OutputFormat, RenderedValue,
+    PATH_TO_GUEST_DATA,
+};
 use crate::mmds::token::PATH_TO_TOKEN;
 use crate::mmds::token_headers::REJECTED_HEADER;
 
@@ -39,6 +45,8 @@ pub enum VmmMmdsError {
     NoTtlProvided,
     /// Resource not found: {0}.
     ResourceNotFound(String),
+    /// Guest data value is not valid UTF-8.
+    InvalidGuestDataValue,
 }
 
 impl From<MediaType> for OutputFormat {
@@ -100,6 +108,16 @@ fn sanitize_uri(mut uri: String) -> String {
 
 /// Build a response for `request` and return response based on MMDS version
 pub fn convert_to_response(mmds: Arc<Mutex<Mmds>>, request: Request) -> Response {
+    convert_to_restricted_response(mmds, request, None)
+}
+
+/// Build a response for `request` like `convert_to_response`, only serving the content located
+/// under the `allowed_paths` prefixes if any.
+pub fn convert_to_restricted_response(
+    mmds: Arc<Mutex<Mmds>>,
+    request: Request,
+    allowed_paths: Option<&[String]>,
+) -> Response {
     let uri = request.uri().get_abs_path();
     if uri.is_empty() {
         return build_response(
@@ -112,15 +130,42 @@ pub fn convert_to_response(mmds: Arc<Mutex<Mmds>>, request: Request) -> Response
     let mut mmds_guard = mmds.lock().expect("Poisoned lock");
 
     match mmds_guard.version() {
-        MmdsVersion::V1 => respond_to_request_mmdsv1(&mmds_guard, request),
-        MmdsVersion::V2 => respond_to_request_mmdsv2(&mut mmds_guard, request),
+        MmdsVersion::V1 => respond_to_request_mmdsv1(&mut mmds_guard, request, allowed_paths),
+        MmdsVersion::V2 => respond_to_request_mmdsv2(&mut mmds_guard, request, allowed_paths),
     }
 }
 
-fn respond_to_request_mmdsv1(mmds: &Mmds, request: Request) -> Response {
-    // Allow only GET requests.
+// Returns the key targeted by a guest data write, if `json_path` is located
+// under the guest writable subtree.
+fn guest_data_key(json_path: &str) -> Option<&str> {
+    json_path
+        .strip_prefix(PATH_TO_GUEST_DATA)
+        .and_then(|key| key.strip_prefix('/'))
+        .map(|key| key.trim_end_matches('/'))
+}
+
+// Returns whether `json_path` is located under one of the `allowed_paths` prefixes, matched on
+// whole path segments.
+fn is_path_allowed(json_path: &str, allowed_paths: &[String]) -> bool {
+    let json_path = json_path.trim_end_matches('/');
+    allowed_paths.iter().any(|prefix| {
+        json_path
+            .strip_prefix(sanitize_uri(prefix.clone()).trim_end_matches('/'))
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    })
+}
+
+fn respond_to_request_mmdsv1(
+    mmds: &mut Mmds,
+    request: Request,
+    allowed_paths: Option<&[String]>,
+) -> Response {
+    let guest_writable = mmds.guest_data_limit().is_some();
+
+    // Allow only GET requests, and PUT requests on the guest writable subtree if enabled.
     match request.method() {
-        Method::Get => respond_to_get_request_unchecked(mmds, request),
+        Method::Get => respond_to_get_request_unchecked(mmds, request, allowed_paths),
+        Method::Put if guest_writable => respond_to_guest_data_put(mmds, request),
         _ => {
             let mut response = build_response(
                 request.http_version(),
@@ -128,12 +173,19 @@ fn respond_to_request_mmdsv1(mmds: &Mmds, request: Request) -> Response {
                 Body::new(VmmMmdsError::MethodNotAllowed.to_string()),
             );
             response.allow_method(Method::Get);
+            if guest_writable {
+                response.allow_method(Method::Put);
+            }
             response
         }
     }
 }
 
-fn respond_to_request_mmdsv2(mmds: &mut Mmds, request: Request) -> Response {
+fn respond_to_request_mmdsv2(
+    mmds: &mut Mmds,
+    request: Request,
+    allowed_paths: Option<&[String]>,
+) -> Response {
     // Fetch custom headers from request.
     let token_headers = match TokenHeaders::try_from(request.headers.custom_entries()) {
         Ok(token_headers) => token_headers,
@@ -148,7 +200,7 @@ fn respond_to_request_mmdsv2(mmds: &mut Mmds, request: Request) -> Response {
 
     // Allow only GET and PUT requests.
     match request.method() {
-        Method::Get => respond_to_get_request_checked(mmds, request, token_headers),
+        Method::Get => respond_to_get_request_checked(mmds, request, token_headers, allowed_paths),
         Method::Put => respond_to_put_request(mmds, request, token_headers),
         _ => {
             let mut response = build_response(
@@ -164,48 +216,163 @@ fn respond_to_request_mmdsv2(mmds: &mut Mmds, request: Request) -> Response {
 }
 
 fn respond_to_get_request_checked(
-    mmds: &Mmds,
+    mmds: &mut Mmds,
     request: Request,
     token_headers: TokenHeaders,
+    allowed_paths: Option<&[String]>,
 ) -> Response {
+    match check_token(mmds, &request, &token_headers) {
+        Ok(()) => respond_to_get_request_unchecked(mmds, request, allowed_paths),
+        Err(response) => response,
+    }
+}
+
+// Validates the session token provided through the custom headers, returning
+// the `Unauthorized` response to be sent back on failure.
+fn check_token(
+    mmds: &Mmds,
+    request: &Request,
+    token_headers: &TokenHeaders,
+) -> Result<(), Response> {
     // Get MMDS token from custom headers.
     let token = match token_headers.x_metadata_token() {
         Some(token) => token,
         None => {
             let error_msg = VmmMmdsError::NoTokenProvided.to_string();
-            return build_response(
+            return Err(build_response(
                 request.http_version(),
                 StatusCode::Unauthorized,
                 Body::new(error_msg),
-            );
+            ));
         }
     };
 
     // Validate MMDS token.
     match mmds.is_valid_token(token) {
-        Ok(true) => respond_to_get_request_unchecked(mmds, request),
-        Ok(false) => build_response(
+        Ok(true) => Ok(()),
+        Ok(false) => Err(build_response(
             request.http_version(),
             StatusCode::Unauthorized,
             Body::new(VmmMmdsError::InvalidToken.to_string()),
-        ),
+        )),
         Err(_) => unreachable!(),
     }
 }
 
-fn respond_to_get_request_unchecked(mmds: &Mmds, request: Request) -> Response {
+fn respond_to_guest_data_put(mmds: &mut Mmds, request: Request) -> Response {
+    let uri = request.uri().get_abs_path();
+    let json_path = sanitize_uri(uri.to_string());
+
+    let key = match guest_data_key(&json_path) {
+        Some(key) => key,
+        None => {
+            let error_msg = VmmMmdsError::ResourceNotFound(String::from(uri)).to_string();
+            return build_response(
+                request.http_version(),
+                StatusCode::NotFound,
+                Body::new(error_msg),
+            );
+        }
+    };
+
+    let value = match request
+        .body
+        .as_ref()
+        .map(|body| std::str::from_utf8(body.raw()))
+    {
+        Some(Ok(value)) => value.to_string(),
+        None => String::new(),
+        Some(Err(_)) => {
+            METRICS.mmds.guest_data_write_fails.inc();
+            return build_response(
+                request.http_version(),
+                StatusCode::BadRequest,
+                Body::new(VmmMmdsError::InvalidGuestDataValue.to_string()),
+            );
+        }
+    };
+
+    match mmds.put_guest_data(key, value) {
+        Ok(()) => {
+            METRICS.mmds.guest_data_writes.inc();
+            Response::new(request.http_version(), StatusCode::NoContent)
+        }
+        Err(err) => {
+            METRICS.mmds.guest_data_write_fails.inc();
+            let status_code = match err {
+                MmdsError::GuestDataLimitExceeded => StatusCode::PayloadTooLarge,
+                _ => StatusCode::BadRequest,
+            };
+            build_response(
+                request.http_version(),
+                status_code,
+                Body::new(err.to_string()),
+            )
+        }
+    }
+}
+
+/// Request header holding the entity tags of the responses already known to the guest.
+const IF_NONE_MATCH: &str = "If-None-Match";
+/// Response header holding the entity tag of the response.
+const ETAG: &str = "ETag";
+
+// Returns whether the `If-None-Match` header of the request, if any, matches the entity tag of
+// the rendered value. Weak comparison is used, as mandated for this header by RFC 9110.
+fn etag_matches(request: &Request, rendered: &RenderedValue) -> bool {
+    let if_none_match = match request
+        .headers
+        .custom_entries()
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(IF_NONE_MATCH))
+    {
+        Some((_, value)) => value,
+        None => return false,
+    };
+
+    if_none_match
+        .split(',')
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == rendered.etag.as_str())
+}
+
+fn respond_to_get_request_unchecked(
+    mmds: &mut Mmds,
+    request: Request,
+    allowed_paths: Option<&[String]>,
+) -> Response {
     let uri = request.uri().get_abs_path();
 
     // The data store expects a strict json path, so we need to
     // sanitize the URI.
     let json_path = sanitize_uri(uri.to_string());
 
-    match mmds.get_value(json_path, request.headers.accept().into()) {
-        Ok(response_body) => build_response(
+    // The content which cannot be retrieved is reported as missing, not to disclose it.
+    if allowed_paths.is_some_and(|allowed_paths| !is_path_allowed(&json_path, allowed_paths)) {
+        METRICS.mmds.acl_denied.inc();
+        let error_msg = VmmMmdsError::ResourceNotFound(String::from(uri)).to_string();
+        return build_response(
             request.http_version(),
-            StatusCode::OK,
-            Body::new(response_body),
-        ),
+            StatusCode::NotFound,
+            Body::new(error_msg),
+        );
+    }
+
+    match mmds.get_rendered_value(json_path, request.headers.accept().into()) {
+        Ok(rendered) => {
+            let mut response = if etag_matches(&request, &rendered) {
+                METRICS.mmds.not_modified.inc();
+                Response::new(request.http_version(), StatusCode::NotModified)
+            } else {
+                build_response(
+                    request.http_version(),
+                    StatusCode::OK,
+                    Body::new(rendered.body),
+                )
+            };
+            response.set_custom_header(ETAG, &rendered.etag);
+            response
+        }
         Err(err) => match err {
             MmdsError::NotFound => {
                 let error_msg = VmmMmdsError::ResourceNotFound(String::from(uri)).to_string();
@@ -225,6 +392,11 @@ fn respond_to_get_request_unchecked(mmds: &Mmds, request: Request) -> Response {
                 StatusCode::PayloadTooLarge,
                 Body::new(err.to_string()),
             ),
+            MmdsError::Backend(_) => build_response(
+                request.http_version(),
+                StatusCode::ServiceUnavailable,
+                Body::new(err.to_string()),
+            ),
             _ => unreachable!(),
         },
     }
@@ -256,6 +428,14 @@ fn respond_to_put_request(
     // Sanitize the URI into a strict json path.
     let json_path = sanitize_uri(uri.to_string());
 
+    // Writes to the guest data subtree are authenticated like any GET request.
+    if mmds.guest_data_limit().is_some() && guest_data_key(&json_path).is_some() {
+        return match check_token(mmds, &request, &token_headers) {
+            Ok(()) => respond_to_guest_data_put(mmds, request),
+            Err(response) => response,
+        };
+    }
+
     // Only accept PUT requests towards TOKEN_PATH.
     if json_path != PATH_TO_TOKEN {
         let error_msg = VmmMmdsError::ResourceNotFound(String::from(uri)).to_string();
@@ -343,6 +523,14 @@ mod tests {
         }"#
     }
 
+    // Builds the response to a successful GET request, tagged with the entity tag of its body.
+    fn ok_response(http_version: Version, body: &str) -> Response {
+        let rendered = RenderedValue::new(body.to_string());
+        let mut response = build_response(http_version, StatusCode::OK, Body::new(body));
+        response.set_custom_header(ETAG, &rendered.etag);
+        response
+    }
+
     #[test]
     fn test_sanitize_uri() {
         let sanitized = "/a/b/c/d";
@@ -422,8 +610,7 @@ mod tests {
                                     Accept: application/json\r\n
                                     X-metadata-token-ttl-seconds: application/json\r\n\r\n";
         let request = Request::try_from(request_bytes, None).unwrap();
-        let mut expected_response = Response::new(Version::Http10, StatusCode::OK);
-        expected_response.set_body(Body::new("\"John\""));
+        let expected_response = ok_response(Version::Http10, "\"John\"");
         let actual_response = convert_to_response(mmds.clone(), request);
         assert_eq!(actual_response, expected_response);
 
@@ -431,10 +618,9 @@ mod tests {
         let request_bytes = b"GET http://169.254.169.254/ HTTP/1.0\r\n\
                                     Accept: application/json\r\n\r\n";
         let request = Request::try_from(request_bytes, None).unwrap();
-        let mut expected_response = Response::new(Version::Http10, StatusCode::OK);
         let mut body = get_json_data().to_string();
         body.retain(|c| !c.is_whitespace());
-        expected_response.set_body(Body::new(body));
+        let expected_response = ok_response(Version::Http10, &body);
         let actual_response = convert_to_response(mmds, request);
         assert_eq!(actual_response, expected_response);
     }
@@ -545,10 +731,9 @@ mod tests {
             valid_token
         );
         let request = Request::try_from(request_bytes.as_bytes(), None).unwrap();
-        let mut expected_response = Response::new(Version::Http10, StatusCode::OK);
         let mut body = get_json_data().to_string();
         body.retain(|c| !c.is_whitespace());
-        expected_response.set_body(Body::new(body));
+        let expected_response = ok_response(Version::Http10, &body);
         let actual_response = convert_to_response(mmds.clone(), request);
         assert_eq!(actual_response, expected_response);
 
@@ -623,6 +808,185 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_guest_data_put() {
+        let mmds = populate_mmds();
+
+        // Guest writes are rejected while disabled.
+        let request_bytes = b"PUT http://169.254.169.254/latest/guest-data/ready HTTP/1.0\r\n\
+                                    Content-Length: 4\r\n\r\ntrue";
+        let request = Request::try_from(request_bytes, None).unwrap();
+        let actual_response = convert_to_response(mmds.clone(), request);
+        assert_eq!(actual_response.status(), StatusCode::MethodNotAllowed);
+
+        mmds.lock()
+            .expect("Poisoned lock")
+            .set_guest_data_limit(Some(32));
+
+        // V1 accepts writes without a token.
+        let request = Request::try_from(request_bytes, None).unwrap();
+        let expected_response = Response::new(Version::Http10, StatusCode::NoContent);
+        let actual_response = convert_to_response(mmds.clone(), request);
+        assert_eq!(actual_response, expected_response);
+        assert_eq!(
+            mmds.lock().expect("Poisoned lock").guest_data_value(),
+            serde_json::json!({"ready": "true"})
+        );
+
+        // PUT outside of the guest writable subtree.
+        let request_bytes = b"PUT http://169.254.169.254/name/first HTTP/1.0\r\n\
+                                    Content-Length: 4\r\n\r\nJane";
+        let request = Request::try_from(request_bytes, None).unwrap();
+        let actual_response = convert_to_response(mmds.clone(), request);
+        assert_eq!(actual_response.status(), StatusCode::NotFound);
+
+        // Quota exceeded.
+        let request_bytes = b"PUT http://169.254.169.254/latest/guest-data/port HTTP/1.0\r\n\
+                                    Content-Length: 32\r\n\r\n\
+                                    XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX";
+        let request = Request::try_from(request_bytes, None).unwrap();
+        let actual_response = convert_to_response(mmds.clone(), request);
+        assert_eq!(actual_response.status(), StatusCode::PayloadTooLarge);
+
+        // V2 requires a valid token.
+        mmds.lock()
+            .expect("Poisoned lock")
+            .set_version(MmdsVersion::V2)
+            .unwrap();
+        let request_bytes = b"PUT http://169.254.169.254/latest/guest-data/port HTTP/1.0\r\n\
+                                    Content-Length: 4\r\n\r\n8080";
+        let request = Request::try_from(request_bytes, None).unwrap();
+        let mut expected_response = Response::new(Version::Http10, StatusCode::Unauthorized);
+        expected_response.set_body(Body::new(VmmMmdsError::NoTokenProvided.to_string()));
+        let actual_response = convert_to_response(mmds.clone(), request);
+        assert_eq!(actual_response, expected_response);
+
+        let token = mmds
+            .lock()
+            .expect("Poisoned lock")
+            .generate_token(60)
+            .unwrap();
+        let request_bytes = format!(
+            "PUT http://169.254.169.254/latest/guest-data/port HTTP/1.0\r\nX-metadata-token: \
+             {}\r\nContent-Length: 4\r\n\r\n8080",
+            token
+        );
+        let request = Request::try_from(request_bytes.as_bytes(), None).unwrap();
+        let expected_response = Response::new(Version::Http10, StatusCode::NoContent);
+        let actual_response = convert_to_response(mmds.clone(), request);
+        assert_eq!(actual_response, expected_response);
+        assert_eq!(
+            mmds.lock().expect("Poisoned lock").guest_data_value(),
+            serde_json::json!({"ready": "true", "port": "8080"})
+        );
+    }
+
+    #[test]
+    fn test_conditional_get() {
+        let mmds = populate_mmds();
+        let request_bytes = b"GET http://169.254.169.254/name/first HTTP/1.1\r\n\
+                                    Accept: application/json\r\n\r\n";
+        let request = Request::try_from(request_bytes, None).unwrap();
+        let response = convert_to_response(mmds.clone(), request);
+        assert_eq!(response, ok_response(Version::Http11, "\"John\""));
+        let etag = RenderedValue::new("\"John\"".to_string()).etag;
+
+        // Test matching tags, compared case insensitively on the header name and weakly.
+        let not_modified_count = METRICS.mmds.not_modified.count();
+        for if_none_match in [
+            format!("If-None-Match: {}", etag),
+            format!("if-none-match: \"other\", W/{}", etag),
+            "If-None-Match: *".to_string(),
+        ] {
+            let request_bytes = format!(
+                "GET http://169.254.169.254/name/first HTTP/1.1\r\nAccept: \
+                 application/json\r\n{}\r\n\r\n",
+                if_none_match
+            );
+            let request = Request::try_from(request_bytes.as_bytes(), None).unwrap();
+            let mut expected_response = Response::new(Version::Http11, StatusCode::NotModified);
+            expected_response.set_custom_header(ETAG, &etag);
+            assert_eq!(
+                convert_to_response(mmds.clone(), request),
+                expected_response
+            );
+        }
+        assert_eq!(METRICS.mmds.not_modified.count(), not_modified_count + 3);
+
+        // Test a stale tag, after the data store is patched.
+        mmds.lock()
+            .expect("Poisoned lock")
+            .patch_data(serde_json::json!({"name": {"first": "Jane"}}))
+            .unwrap();
+        let request_bytes = format!(
+            "GET http://169.254.169.254/name/first HTTP/1.1\r\nAccept: \
+             application/json\r\nIf-None-Match: {}\r\n\r\n",
+            etag
+        );
+        let request = Request::try_from(request_bytes.as_bytes(), None).unwrap();
+        assert_eq!(
+            convert_to_response(mmds, request),
+            ok_response(Version::Http11, "\"Jane\"")
+        );
+    }
+
+    #[test]
+    fn test_is_path_allowed() {
+        let allowed_paths = ["/phones/".to_string(), "//name/first".to_string()];
+        assert!(is_path_allowed("/phones", &allowed_paths));
+        assert!(is_path_allowed("/phones/home/", &allowed_paths));
+        assert!(is_path_allowed("/name/first", &allowed_paths));
+        assert!(!is_path_allowed("/", &allowed_paths));
+        assert!(!is_path_allowed("/name", &allowed_paths));
+        assert!(!is_path_allowed("/name/firstname", &allowed_paths));
+        assert!(is_path_allowed("/age", &["/".to_string()]));
+        assert!(!is_path_allowed("/age", &[]));
+    }
+
+    #[test]
+    fn test_restricted_response() {
+        let mmds = populate_mmds();
+        let allowed_paths = ["/name".to_string()];
+
+        let request_bytes = b"GET http://169.254.169.254/name/first HTTP/1.0\r\n\
+                                    Accept: application/json\r\n\r\n";
+        let request = Request::try_from(request_bytes, None).unwrap();
+        assert_eq!(
+            convert_to_restricted_response(mmds.clone(), request, Some(&allowed_paths)),
+            ok_response(Version::Http10, "\"John\"")
+        );
+
+        // The content outside of the allowed paths is reported as missing.
+        let acl_denied_count = METRICS.mmds.acl_denied.count();
+        for path in ["/", "/phones/mobile"] {
+            let request_bytes = format!(
+                "GET http://169.254.169.254{} HTTP/1.0\r\nAccept: application/json\r\n\r\n",
+                path
+            );
+            let request = Request::try_from(request_bytes.as_bytes(), None).unwrap();
+            let mut expected_response = Response::new(Version::Http10, StatusCode::NotFound);
+            expected_response.set_body(Body::new(
+                VmmMmdsError::ResourceNotFound(path.to_string()).to_string(),
+            ));
+            assert_eq!(
+                convert_to_restricted_response(mmds.clone(), request, Some(&allowed_paths)),
+                expected_response
+            );
+        }
+        assert_eq!(METRICS.mmds.acl_denied.count(), acl_denied_count + 2);
+
+        // Tokens can still be generated when MMDS version 2 is configured.
+        mmds.lock()
+            .expect("Poisoned lock")
+            .set_version(MmdsVersion::V2)
+            .unwrap();
+        let request_bytes = b"PUT http://169.254.169.254/latest/api/token HTTP/1.0\r\n\
+                                    X-metadata-token-ttl-seconds: 60\r\n\r\n";
+        let request = Request::try_from(request_bytes, None).unwrap();
+        let response = convert_to_restricted_response(mmds, request, Some(&allowed_paths));
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
     #[test]
     fn test_json_patch() {
         let mut data = serde_json::json!({
@@ -74,6 +74,8 @@ pub struct MmdsNetworkStack {
     pub(crate) tcp_handler: TcpIPv4Handler,
     // Data store reference shared across all MmdsNetworkStack instances.
     pub mmds: Arc<Mutex<Mmds>>,
+    // Path prefixes of the content which can be retrieved through this stack, if restricted.
+    pub(crate) allowed_paths: Option<Vec<String>>,
 }
 
 impl MmdsNetworkStack {
@@ -95,6 +97,7 @@ impl MmdsNetworkStack {
                 NonZeroUsize::new(DEFAULT_MAX_PENDING_RESETS).unwrap(),
             ),
             mmds,
+            allowed_paths: None,
         }
     }
 
@@ -115,6 +118,18 @@ impl MmdsNetworkStack {
         self.ipv4_addr
     }
 
+    /// Restricts the content retrieved through this stack to the paths under `allowed_paths`, or
+    /// lifts the restriction if `None`.
+    pub fn set_allowed_paths(&mut self, allowed_paths: Option<Vec<String>>) {
+        self.allowed_paths = allowed_paths;
+    }
+
+    /// Returns the path prefixes of the content which can be retrieved through this stack, if
+    /// restricted.
+    pub fn allowed_paths(&self) -> Option<&[String]> {
+        self.allowed_paths.as_deref()
+    }
+
     pub fn default_ipv4_addr() -> Ipv4Addr {
         Ipv4Addr::from(DEFAULT_IPV4_ADDR)
     }
@@ -179,8 +194,9 @@ impl MmdsNetworkStack {
                 // each MmdsNetworkStack routes packets for only one network device.
                 self.remote_mac_addr = eth.src_mac();
                 let mmds_instance = self.mmds.clone();
+                let allowed_paths = self.allowed_paths.as_deref();
                 match &mut self.tcp_handler.receive_packet(&ip, move |request| {
-                    super::convert_to_response(mmds_instance, request)
+                    super::convert_to_restricted_response(mmds_instance, request, allowed_paths)
                 }) {
                     Ok(event) => {
                         METRICS.mmds.rx_count.inc();
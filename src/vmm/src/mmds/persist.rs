@@ -19,6 +19,7 @@ pub struct MmdsNetworkStackState {
     mac_addr: [u8; MAC_ADDR_LEN as usize],
     ipv4_addr: u32,
     tcp_port: u16,
+    allowed_paths: Option<Vec<String>>,
 }
 
 impl Persist<'_> for MmdsNetworkStack {
@@ -34,6 +35,7 @@ impl Persist<'_> for MmdsNetworkStack {
             mac_addr,
             ipv4_addr: self.ipv4_addr.into(),
             tcp_port: self.tcp_handler.local_port(),
+            allowed_paths: self.allowed_paths.clone(),
         }
     }
 
@@ -41,12 +43,14 @@ impl Persist<'_> for MmdsNetworkStack {
         mmds: Self::ConstructorArgs,
         state: &Self::State,
     ) -> std::result::Result<Self, Self::Error> {
-        Ok(MmdsNetworkStack::new(
+        let mut ns = MmdsNetworkStack::new(
             MacAddr::from_bytes_unchecked(&state.mac_addr),
             Ipv4Addr::from(state.ipv4_addr),
             state.tcp_port,
             mmds,
-        ))
+        );
+        ns.set_allowed_paths(state.allowed_paths.clone());
+        Ok(ns)
     }
 }
 
@@ -58,7 +62,9 @@ mod tests {
 
     #[test]
     fn test_persistence() {
-        let ns = MmdsNetworkStack::new_with_defaults(None, Arc::new(Mutex::new(Mmds::default())));
+        let mut ns =
+            MmdsNetworkStack::new_with_defaults(None, Arc::new(Mutex::new(Mmds::default())));
+        ns.set_allowed_paths(Some(vec!["/latest/meta-data".to_string()]));
 
         let mut mem = vec![0; 4096];
 
@@ -76,5 +82,6 @@ mod tests {
             restored_ns.tcp_handler.local_port(),
             ns.tcp_handler.local_port()
         );
+        assert_eq!(restored_ns.allowed_paths(), ns.allowed_paths());
     }
 }
@@ -1,6 +1,7 @@
 // Copyright 2021 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
+use std::collections::HashMap;
 use std::convert::TryInto;
 use std::fs::File;
 use std::io::Read;
@@ -33,6 +34,9 @@ pub const MAX_TOKEN_TTL_SECONDS: u32 = 21600;
 
 /// Path to token.
 pub const PATH_TO_TOKEN: &str = "/latest/api/token";
+/// Maximum number of sessions tracked at once. When it is reached, the session closest to expiry
+/// is revoked to make room for a new one.
+pub const MAX_SESSIONS: usize = 4096;
 /// Randomness pool file path.
 const RANDOMNESS_POOL: &str = "/dev/urandom";
 
@@ -70,6 +74,17 @@ pub struct TokenAuthority {
     entropy_pool: File,
     // Additional Authentication Data used for encryption and decryption.
     aad: String,
+    // Expiry, in milliseconds, of the outstanding sessions, keyed by the nonce of their token.
+    sessions: HashMap<[u8; IV_LEN], u64>,
+}
+
+/// Session opened by the guest, as reported to the host.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct MmdsSession {
+    /// Identifier of the session, derived from its token without disclosing it.
+    pub session_id: String,
+    /// Number of seconds before the session expires.
+    pub expires_in_secs: u64,
 }
 // TODO When https://github.com/RustCrypto/AEADs/pull/532 is merged replace these manual
 // implementation with `#[derive(Debug)]`.
@@ -79,6 +94,7 @@ impl fmt::Debug for TokenAuthority {
             .field("num_encrypted_tokens", &self.num_encrypted_tokens)
             .field("entropy_pool", &self.entropy_pool)
             .field("aad", &self.aad)
+            .field("sessions", &self.sessions.len())
             .finish()
     }
 }
@@ -93,6 +109,7 @@ impl TokenAuthority {
             num_encrypted_tokens: 0,
             entropy_pool: file,
             aad: "".to_string(),
+            sessions: HashMap::new(),
         })
     }
 
@@ -133,6 +150,7 @@ impl TokenAuthority {
         let expiry = TokenAuthority::compute_expiry(ttl_seconds);
         // Encrypt expiry using the nonce.
         let (payload, tag) = self.encrypt_expiry(expiry, iv.as_ref())?;
+        self.track_session(iv, expiry);
 
         Ok(Token::new(iv, payload, tag))
     }
@@ -183,8 +201,65 @@ impl TokenAuthority {
             Err(_) => return false,
         };
 
-        // Compare expiry (in ms) with current time in milliseconds.
-        expiry > get_time_ms(ClockType::Monotonic)
+        // Compare expiry (in ms) with current time in milliseconds, and make sure the session
+        // was not revoked.
+        expiry > get_time_ms(ClockType::Monotonic) && self.sessions.contains_key(&token.iv)
+    }
+
+    /// Records the session of a new token, forgetting the expired ones.
+    fn track_session(&mut self, iv: [u8; IV_LEN], expiry: u64) {
+        let now = get_time_ms(ClockType::Monotonic);
+        self.sessions
+            .retain(|_, session_expiry| *session_expiry > now);
+        if self.sessions.len() >= MAX_SESSIONS {
+            // The map is not empty, as the limit is not zero.
+            let (&oldest, _) = self
+                .sessions
+                .iter()
+                .min_by_key(|(_, session_expiry)| **session_expiry)
+                .unwrap();
+            self.sessions.remove(&oldest);
+            crate::logger::warn!(
+                "The limit of {} MMDS sessions has been reached. The session closest to expiry \
+                 has been revoked.",
+                MAX_SESSIONS
+            );
+        }
+        self.sessions.insert(iv, expiry);
+    }
+
+    /// Returns the outstanding sessions, sorted by expiry.
+    pub fn sessions(&self) -> Vec<MmdsSession> {
+        let now = get_time_ms(ClockType::Monotonic);
+        let mut sessions: Vec<_> = self
+            .sessions
+            .iter()
+            .filter(|(_, expiry)| **expiry > now)
+            .collect();
+        sessions.sort_by_key(|(_, expiry)| **expiry);
+        sessions
+            .into_iter()
+            .map(|(iv, expiry)| MmdsSession {
+                session_id: session_id(iv),
+                expires_in_secs: (expiry - now).div_ceil(MILLISECONDS_PER_SECOND),
+            })
+            .collect()
+    }
+
+    /// Revokes the session identified by `session`, either its identifier or its token. Returns
+    /// whether an outstanding session was revoked.
+    pub fn revoke_session(&mut self, session: &str) -> bool {
+        let iv = match parse_session_id(session) {
+            Some(iv) => iv,
+            None if session.len() <= TOKEN_LENGTH_LIMIT => match Token::base64_decode(session) {
+                Ok(token) => token.iv,
+                Err(_) => return false,
+            },
+            None => return false,
+        };
+        self.sessions
+            .remove(&iv)
+            .is_some_and(|expiry| expiry > get_time_ms(ClockType::Monotonic))
     }
 
     /// Decrypt ciphertext composed of payload and tag to obtain the expiry value.
@@ -239,6 +314,7 @@ impl TokenAuthority {
             self.cipher = TokenAuthority::create_cipher(&mut self.entropy_pool)?;
             // Reset encrypted tokens count.
             self.num_encrypted_tokens = 0;
+            self.sessions.clear();
             crate::logger::warn!(
                 "The limit of tokens generated under current MMDS token authority
                 has been reached. MMDS's token authority entity has been reseeded
@@ -268,6 +344,24 @@ impl TokenAuthority {
     }
 }
 
+/// Returns the identifier of the session whose token was encrypted with the nonce `iv`.
+fn session_id(iv: &[u8; IV_LEN]) -> String {
+    iv.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Parses a session identifier into the nonce of the token of the session.
+fn parse_session_id(session_id: &str) -> Option<[u8; IV_LEN]> {
+    if session_id.len() != 2 * IV_LEN || !session_id.is_ascii() {
+        return None;
+    }
+    let mut iv = [0u8; IV_LEN];
+    for (byte, hex) in iv.iter_mut().zip(session_id.as_bytes().chunks(2)) {
+        // The chunks are ASCII, hence valid UTF-8.
+        *byte = u8::from_str_radix(std::str::from_utf8(hex).unwrap(), 16).ok()?;
+    }
+    Some(iv)
+}
+
 /// Structure for token information.
 #[derive(Debug, Deserialize, PartialEq, Serialize)]
 struct Token {
@@ -519,6 +613,59 @@ mod tests {
         assert!(!token_authority.is_valid(&token1));
     }
 
+    #[test]
+    fn test_sessions() {
+        let mut token_authority = TokenAuthority::new().unwrap();
+        assert!(token_authority.sessions().is_empty());
+
+        let token0 = token_authority.generate_token_secret(60).unwrap();
+        let token1 = token_authority.generate_token_secret(30).unwrap();
+        let sessions = token_authority.sessions();
+        assert_eq!(sessions.len(), 2);
+        // The sessions are sorted by expiry.
+        assert_eq!(
+            sessions[0].session_id,
+            session_id(&Token::base64_decode(&token1).unwrap().iv)
+        );
+        assert!((29..=30).contains(&sessions[0].expires_in_secs));
+        assert!((59..=60).contains(&sessions[1].expires_in_secs));
+        assert_eq!(
+            parse_session_id(&sessions[1].session_id),
+            Some(Token::base64_decode(&token0).unwrap().iv)
+        );
+
+        // Revoke a session through its identifier, then through its token.
+        assert!(token_authority.revoke_session(&sessions[1].session_id));
+        assert!(!token_authority.is_valid(&token0));
+        assert!(token_authority.is_valid(&token1));
+        assert!(!token_authority.revoke_session(&sessions[1].session_id));
+        assert!(token_authority.revoke_session(&token1));
+        assert!(!token_authority.is_valid(&token1));
+        assert!(token_authority.sessions().is_empty());
+
+        // Unknown sessions cannot be revoked.
+        assert!(!token_authority.revoke_session("foo"));
+        assert!(!token_authority.revoke_session(&"0".repeat(2 * IV_LEN)));
+        assert!(!token_authority.revoke_session(&"g".repeat(2 * IV_LEN)));
+    }
+
+    #[test]
+    fn test_sessions_limit() {
+        let mut token_authority = TokenAuthority::new().unwrap();
+        let oldest = token_authority.generate_token_secret(30).unwrap();
+        for _ in 1..MAX_SESSIONS {
+            token_authority.generate_token_secret(60).unwrap();
+        }
+        assert_eq!(token_authority.sessions().len(), MAX_SESSIONS);
+        assert!(token_authority.is_valid(&oldest));
+
+        // The session closest to expiry makes room for the new one.
+        let newest = token_authority.generate_token_secret(60).unwrap();
+        assert_eq!(token_authority.sessions().len(), MAX_SESSIONS);
+        assert!(!token_authority.is_valid(&oldest));
+        assert!(token_authority.is_valid(&newest));
+    }
+
     #[test]
     fn test_error_display() {
         assert_eq!(
@@ -5,7 +5,8 @@
 
 use std::fmt::Debug;
 use std::fs::{File, OpenOptions};
-use std::io::{self, Write};
+use std::io::{self, Read, Write};
+use std::os::unix::fs::MetadataExt;
 use std::os::unix::io::AsRawFd;
 use std::os::unix::net::UnixStream;
 use std::path::Path;
@@ -15,33 +16,54 @@ use seccompiler::BpfThreadMap;
 use semver::Version;
 use serde::{Deserialize, Serialize};
 use userfaultfd::{FeatureFlags, Uffd, UffdBuilder};
+use vm_memory::{GuestMemoryError, ReadVolatile};
 use vmm_sys_util::sock_ctrl_msg::ScmSocket;
 
 #[cfg(target_arch = "aarch64")]
 use crate::arch::aarch64::vcpu::{get_manufacturer_id_from_host, get_manufacturer_id_from_state};
+use crate::background_snapshot::{self, BackgroundSnapshotError, BackgroundSnapshotState};
 use crate::builder::{self, BuildMicrovmFromSnapshotError};
-use crate::cpu_config::templates::StaticCpuTemplate;
+#[cfg(target_arch = "aarch64")]
+use crate::cpu_config::aarch64::custom_cpu_template::VcpuFeatures;
+#[cfg(target_arch = "aarch64")]
+use crate::cpu_config::templates::RegisterValueFilter;
+use crate::cpu_config::templates::{CpuConfiguration, CustomCpuTemplate, StaticCpuTemplate};
 #[cfg(target_arch = "x86_64")]
 use crate::cpu_config::x86_64::cpuid::common::get_vendor_id_from_host;
 #[cfg(target_arch = "x86_64")]
-use crate::cpu_config::x86_64::cpuid::CpuidTrait;
+use crate::cpu_config::x86_64::cpuid::{Cpuid, CpuidTrait};
 use crate::device_manager::persist::{ACPIDeviceManagerState, DevicePersistError, DeviceStates};
 use crate::logger::{info, warn};
+use crate::rate_limiter::persist::SharedRateLimiterState;
 use crate::resources::VmResources;
-use crate::snapshot::Snapshot;
-use crate::utils::u64_to_usize;
+use crate::snapshot::encryption::{
+    encrypted_len, DecryptingReader, EncryptedFile, EncryptingWriter, SnapshotEncryptionError,
+    SnapshotKey,
+};
+use crate::snapshot::migration::Migration;
+use crate::snapshot::storage::{
+    open_storage, SnapshotStorage, SnapshotStorageError, VolatileReader, VolatileWriter,
+};
+use crate::snapshot::{Snapshot, SnapshotError, SnapshotLabels};
+use crate::utils::{host_kernel_version, u64_to_usize};
 use crate::vmm_config::boot_source::BootSourceConfig;
 use crate::vmm_config::instance_info::InstanceInfo;
-use crate::vmm_config::machine_config::{HugePageConfig, MachineConfigUpdate, VmConfigError};
+use crate::vmm_config::lifecycle_hooks::LifecycleEvent;
+use crate::vmm_config::machine_config::{
+    HugePageConfig, LegacyDevice, MachineConfigUpdate, ReservedMemoryRegion, VmConfig,
+    VmConfigError,
+};
+use crate::vmm_config::shared_memory::SharedMemoryConfig;
 use crate::vmm_config::snapshot::{
-    CreateSnapshotParams, LoadSnapshotParams, MemBackendType, SnapshotType,
+    CheckSnapshotParams, CreateSnapshotParams, LoadSnapshotParams, MemBackendType, SnapshotType,
 };
 use crate::vstate::memory::{
-    GuestMemory, GuestMemoryExtension, GuestMemoryMmap, GuestMemoryState, MemoryError,
+    GuestMemory, GuestMemoryExtension, GuestMemoryMmap, GuestMemoryRegion, GuestMemoryState,
+    MemoryError,
 };
 use crate::vstate::vcpu::{VcpuSendEventError, VcpuState};
-use crate::vstate::vm::VmState;
-use crate::{mem_size_mib, vstate, EventManager, Vmm, VmmError};
+use crate::vstate::vm::{Vm, VmState};
+use crate::{mem_size_mib, migrations, vstate, EventManager, Vmm, VmmError};
 
 /// Holds information related to the VM that is not part of VmState.
 #[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
@@ -56,6 +78,14 @@ pub struct VmInfo {
     pub boot_source: BootSourceConfig,
     /// Huge page configuration
     pub huge_pages: HugePageConfig,
+    /// Guest memory ranges reserved for specific uses
+    pub reserved_memory: Vec<ReservedMemoryRegion>,
+    /// Read-only memory segments shared with other microVMs
+    pub shared_memory: Vec<SharedMemoryConfig>,
+    /// Legacy devices left out of the microVM
+    pub disabled_legacy_devices: Vec<LegacyDevice>,
+    /// Release of the host kernel on which the snapshot was created
+    pub kernel_version: String,
 }
 
 impl From<&VmResources> for VmInfo {
@@ -66,6 +96,10 @@ impl From<&VmResources> for VmInfo {
             cpu_template: StaticCpuTemplate::from(&value.vm_config.cpu_template),
             boot_source: value.boot_source.config.clone(),
             huge_pages: value.vm_config.huge_pages,
+            reserved_memory: value.vm_config.reserved_memory.clone(),
+            shared_memory: value.shared_memory.configs(),
+            disabled_legacy_devices: value.vm_config.disabled_legacy_devices.clone(),
+            kernel_version: host_kernel_version().unwrap_or_default(),
         }
     }
 }
@@ -85,6 +119,8 @@ pub struct MicrovmState {
     pub device_states: DeviceStates,
     /// ACPI devices state.
     pub acpi_dev_state: ACPIDeviceManagerState,
+    /// States of the rate limiters shared by devices.
+    pub shared_rate_limiters: Vec<SharedRateLimiterState>,
 }
 
 /// This describes the mapping between Firecracker base virtual address and
@@ -119,10 +155,14 @@ pub enum MicrovmStateError {
     NotAllowed(String),
     /// Cannot restore devices: {0}
     RestoreDevices(DevicePersistError),
+    /// Cannot restore the shared rate limiters: {0}
+    RestoreSharedRateLimiters(io::Error),
     /// Cannot restore Vcpu state: {0}
     RestoreVcpuState(vstate::vcpu::VcpuError),
     /// Cannot restore Vm state: {0}
     RestoreVmState(vstate::vm::VmError),
+    /// Cannot restore KVM Vm state: {0}
+    RestoreKvmVmState(vstate::vm::RestoreStateError),
     /// Cannot save Vcpu state: {0}
     SaveVcpuState(vstate::vcpu::VcpuError),
     /// Cannot save Vm state: {0}
@@ -144,6 +184,10 @@ pub enum CreateSnapshotError {
     UnsupportedVersion,
     /// Cannot write memory file: {0}
     Memory(MemoryError),
+    /// Diff snapshots can only be written to local memory files.
+    RemoteDiffSnapshot,
+    /// Cannot select the snapshot storage: {0}
+    Storage(#[from] SnapshotStorageError),
     /// Cannot perform {0} on the memory backing file: {1}
     MemoryBackingFile(&'static str, io::Error),
     /// Cannot save the microVM state: {0}
@@ -154,10 +198,59 @@ pub enum CreateSnapshotError {
     SnapshotBackingFile(&'static str, io::Error),
     /// Size mismatch when writing diff snapshot on top of base layer: base layer size is {0} but diff layer is size {1}.
     SnapshotBackingFileLengthMismatch(u64, u64),
+    /// Too many snapshot labels: {0} (max 64)
+    TooManyLabels(usize),
+    /// Invalid snapshot label key: {0}
+    InvalidLabelKey(String),
+    /// Value of snapshot label {0} is longer than 1024 bytes
+    LabelValueTooLong(String),
+    /// Snapshot encryption error: {0}
+    Encryption(#[from] SnapshotEncryptionError),
+    /// Diff snapshots cannot be encrypted.
+    EncryptedDiffSnapshot,
+    /// The guest memory cannot be encrypted into the memory file it is mapped from.
+    EncryptedMemoryFileInUse,
+    /// Background snapshot error: {0}
+    BackgroundSnapshot(#[from] BackgroundSnapshotError),
 }
 
 /// Snapshot version
-pub const SNAPSHOT_VERSION: Version = Version::new(5, 0, 0);
+pub const SNAPSHOT_VERSION: Version = Version::new(6, 0, 0);
+
+/// Changes of the microVM state format, used to rewrite snapshots between [`SNAPSHOT_VERSION`]
+/// and the versions before it. Changing the format requires bumping [`SNAPSHOT_VERSION`] and
+/// registering the migration from the previous version here, whenever its fields can be
+/// converted back and forth.
+pub const SNAPSHOT_MIGRATIONS: &[Migration] = &[migrations::V5_TO_V6];
+
+/// Maximum number of labels that can be attached to a snapshot.
+pub const MAX_SNAPSHOT_LABELS: usize = 64;
+/// Maximum length of a snapshot label key.
+pub const MAX_SNAPSHOT_LABEL_KEY_LEN: usize = 128;
+/// Maximum length of a snapshot label value.
+pub const MAX_SNAPSHOT_LABEL_VALUE_LEN: usize = 1024;
+
+/// Checks that the snapshot labels are within the limits and that the keys
+/// only contain alphanumeric characters, '-', '_', '.' and '/'.
+fn validate_snapshot_labels(labels: &SnapshotLabels) -> Result<(), CreateSnapshotError> {
+    if labels.len() > MAX_SNAPSHOT_LABELS {
+        return Err(CreateSnapshotError::TooManyLabels(labels.len()));
+    }
+    for (key, value) in labels {
+        if key.is_empty()
+            || key.len() > MAX_SNAPSHOT_LABEL_KEY_LEN
+            || !key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '/'))
+        {
+            return Err(CreateSnapshotError::InvalidLabelKey(key.clone()));
+        }
+        if value.len() > MAX_SNAPSHOT_LABEL_VALUE_LEN {
+            return Err(CreateSnapshotError::LabelValueTooLong(key.clone()));
+        }
+    }
+    Ok(())
+}
 
 /// Creates a Microvm snapshot.
 pub fn create_snapshot(
@@ -165,39 +258,179 @@ pub fn create_snapshot(
     vm_info: &VmInfo,
     params: &CreateSnapshotParams,
 ) -> Result<(), CreateSnapshotError> {
+    validate_snapshot_labels(&params.labels)?;
+
+    // The memory file of a background snapshot may still be written, and guest memory is
+    // write-protected until then.
+    if vmm.background_snapshot_status().state == BackgroundSnapshotState::InProgress {
+        return Err(BackgroundSnapshotError::InProgress.into());
+    }
+    if params.background {
+        check_background_snapshot(vmm, params)?;
+    }
+
+    let key = match &params.encryption {
+        Some(encryption) => {
+            if params.snapshot_type == SnapshotType::Diff {
+                return Err(CreateSnapshotError::EncryptedDiffSnapshot);
+            }
+            Some(SnapshotKey::from_fd(encryption.key_fd)?)
+        }
+        None => None,
+    };
+
+    vmm.run_lifecycle_hooks(LifecycleEvent::PreSnapshot);
     let microvm_state = vmm
         .save_state(vm_info)
         .map_err(CreateSnapshotError::MicrovmState)?;
 
-    snapshot_state_to_file(&microvm_state, &params.snapshot_path)?;
+    let snapshot_storage = open_storage(&params.snapshot_path)?;
+    let mem_storage = open_storage(&params.mem_file_path)?;
+
+    // Encrypted memory files are streamed like remote ones instead of being updated in place, so
+    // they cannot replace the file guest memory is mapped from.
+    if key.is_some()
+        && mem_storage
+            .local_path()
+            .is_some_and(|path| memory_mapped_from(vmm, path))
+    {
+        return Err(CreateSnapshotError::EncryptedMemoryFileInUse);
+    }
+
+    if params.background && mem_storage.local_path().is_none() {
+        return Err(BackgroundSnapshotError::RemoteStorage.into());
+    }
+
+    snapshot_state_to_storage(
+        &microvm_state,
+        snapshot_storage.as_ref(),
+        &params.labels,
+        key.as_ref(),
+    )?;
 
-    snapshot_memory_to_file(vmm, &params.mem_file_path, params.snapshot_type)?;
+    match (mem_storage.local_path(), &key) {
+        (Some(mem_file_path), None) if params.background => {
+            snapshot_memory_in_background(vmm, mem_file_path, vm_info.huge_pages)?
+        }
+        (Some(mem_file_path), None) => {
+            snapshot_memory_to_file(vmm, mem_file_path, params.snapshot_type)?
+        }
+        _ => snapshot_memory_to_storage(
+            vmm,
+            mem_storage.as_ref(),
+            params.snapshot_type,
+            key.as_ref(),
+        )?,
+    }
 
     Ok(())
 }
 
-fn snapshot_state_to_file(
-    microvm_state: &MicrovmState,
-    snapshot_path: &Path,
+/// Checks that a background snapshot of the given [`Vmm`] can be created with `params`, before
+/// any snapshot file is written.
+fn check_background_snapshot(
+    vmm: &Vmm,
+    params: &CreateSnapshotParams,
+) -> Result<(), BackgroundSnapshotError> {
+    if params.snapshot_type == SnapshotType::Diff {
+        return Err(BackgroundSnapshotError::DiffSnapshot);
+    }
+    if params.encryption.is_some() {
+        return Err(BackgroundSnapshotError::Encrypted);
+    }
+    // The guest memory is already registered with the userfaultfd of the page fault handler.
+    if vmm.uffd.is_some() {
+        return Err(BackgroundSnapshotError::Uffd);
+    }
+    // This also rules out truncating the memory file if guest memory is mapped from it.
+    background_snapshot::check_guest_memory(vmm.guest_memory())?;
+    if vmm.snapshot_writer.is_none() {
+        return Err(BackgroundSnapshotError::NoWriter);
+    }
+    Ok(())
+}
+
+/// Write-protects the guest memory of the given [`Vmm`] and hands it over to the snapshot
+/// writer thread, which writes it to `mem_file_path` while the microVM runs.
+fn snapshot_memory_in_background(
+    vmm: &Vmm,
+    mem_file_path: &Path,
+    huge_pages: HugePageConfig,
 ) -> Result<(), CreateSnapshotError> {
     use self::CreateSnapshotError::*;
-    let mut snapshot_file = OpenOptions::new()
-        .create(true)
+
+    let writer = vmm
+        .snapshot_writer
+        .as_ref()
+        .ok_or(BackgroundSnapshotError::NoWriter)?;
+
+    // The file is emptied, so that the pages holding only zeros do not need to be written.
+    let file = OpenOptions::new()
         .write(true)
+        .create(true)
         .truncate(true)
-        .open(snapshot_path)
-        .map_err(|err| SnapshotBackingFile("open", err))?;
+        .open(mem_file_path)
+        .map_err(|err| MemoryBackingFile("open", err))?;
+    file.set_len(mem_size_mib(vmm.guest_memory()) * 1024 * 1024)
+        .map_err(|err| MemoryBackingFile("set_length", err))?;
 
-    let snapshot = Snapshot::new(SNAPSHOT_VERSION);
+    writer.start(vmm.guest_memory(), huge_pages, file, mem_file_path)?;
+
+    vmm.reset_dirty_bitmap();
+    vmm.guest_memory().reset_dirty();
+    mark_queue_memory_dirty(vmm);
+    Ok(())
+}
+
+/// Whether guest memory is mapped from the file at `path`, which then cannot be truncated.
+fn memory_mapped_from(vmm: &Vmm, path: &Path) -> bool {
+    let Ok(metadata) = std::fs::metadata(path) else {
+        return false;
+    };
+    vmm.guest_memory().iter().any(|region| {
+        region
+            .file_offset()
+            .and_then(|file_offset| file_offset.file().metadata().ok())
+            .is_some_and(|region_metadata| {
+                region_metadata.dev() == metadata.dev() && region_metadata.ino() == metadata.ino()
+            })
+    })
+}
+
+fn snapshot_state_to_storage(
+    microvm_state: &MicrovmState,
+    storage: &dyn SnapshotStorage,
+    labels: &SnapshotLabels,
+    key: Option<&SnapshotKey>,
+) -> Result<(), CreateSnapshotError> {
+    use self::CreateSnapshotError::*;
+
+    // The state is serialized upfront, as remote storages need its size before receiving it.
+    let mut state = Vec::new();
+    let snapshot = Snapshot::new(SNAPSHOT_VERSION).with_labels(labels.clone());
     snapshot
-        .save(&mut snapshot_file, microvm_state)
+        .save(&mut state, microvm_state)
         .map_err(SerializeMicrovmState)?;
-    snapshot_file
-        .flush()
-        .map_err(|err| SnapshotBackingFile("flush", err))?;
-    snapshot_file
-        .sync_all()
-        .map_err(|err| SnapshotBackingFile("sync_all", err))
+    if let Some(key) = key {
+        let mut writer = EncryptingWriter::new(key, EncryptedFile::State, Vec::new())
+            .map_err(|err| SnapshotBackingFile("encrypt", err))?;
+        writer
+            .write_all(&state)
+            .map_err(|err| SnapshotBackingFile("encrypt", err))?;
+        state = writer
+            .finish()
+            .map_err(|err| SnapshotBackingFile("encrypt", err))?;
+    }
+
+    let mut snapshot_writer = storage
+        .create(state.len() as u64)
+        .map_err(|err| SnapshotBackingFile("open", err))?;
+    snapshot_writer
+        .write_all(&state)
+        .map_err(|err| SnapshotBackingFile("write", err))?;
+    snapshot_writer
+        .finish()
+        .map_err(|err| SnapshotBackingFile("finish", err))
 }
 
 /// Takes a snapshot of the virtual machine running inside the given [`Vmm`] and saves it to
@@ -258,7 +491,7 @@ fn snapshot_memory_to_file(
                 .map_err(Memory)
         }
         SnapshotType::Full => {
-            let dump_res = vmm.guest_memory().dump(&mut file).map_err(Memory);
+            let dump_res = vmm.guest_memory().dump_sparse(&mut file).map_err(Memory);
             if dump_res.is_ok() {
                 vmm.reset_dirty_bitmap();
                 vmm.guest_memory().reset_dirty();
@@ -267,6 +500,63 @@ fn snapshot_memory_to_file(
             dump_res
         }
     }?;
+    mark_queue_memory_dirty(vmm);
+
+    file.flush()
+        .map_err(|err| MemoryBackingFile("flush", err))?;
+    file.sync_all()
+        .map_err(|err| MemoryBackingFile("sync_all", err))
+}
+
+/// Takes a full snapshot of the guest memory of the given [`Vmm`] and streams it to a remote
+/// `storage`, which cannot be updated in place by diff snapshots, encrypting it with `key` if
+/// given.
+fn snapshot_memory_to_storage(
+    vmm: &Vmm,
+    storage: &dyn SnapshotStorage,
+    snapshot_type: SnapshotType,
+    key: Option<&SnapshotKey>,
+) -> Result<(), CreateSnapshotError> {
+    use self::CreateSnapshotError::*;
+
+    if snapshot_type == SnapshotType::Diff {
+        return Err(RemoteDiffSnapshot);
+    }
+
+    let expected_size = mem_size_mib(vmm.guest_memory()) * 1024 * 1024;
+    let file_size = match key {
+        Some(_) => encrypted_len(expected_size),
+        None => expected_size,
+    };
+    let mut writer = storage
+        .create(file_size)
+        .map_err(|err| MemoryBackingFile("open", err))?;
+    match key {
+        Some(key) => {
+            let mut encrypting_writer = EncryptingWriter::new(key, EncryptedFile::Memory, writer)
+                .map_err(|err| MemoryBackingFile("encrypt", err))?;
+            vmm.guest_memory()
+                .dump(&mut VolatileWriter(&mut encrypting_writer))
+                .map_err(Memory)?;
+            writer = encrypting_writer
+                .finish()
+                .map_err(|err| MemoryBackingFile("encrypt", err))?;
+        }
+        None => vmm
+            .guest_memory()
+            .dump(&mut VolatileWriter(&mut writer))
+            .map_err(Memory)?,
+    }
+    vmm.reset_dirty_bitmap();
+    vmm.guest_memory().reset_dirty();
+    mark_queue_memory_dirty(vmm);
+
+    writer
+        .finish()
+        .map_err(|err| MemoryBackingFile("finish", err))
+}
+
+fn mark_queue_memory_dirty(vmm: &Vmm) {
     // We need to mark queues as dirty again for all activated devices. The reason we
     // do it here is because we don't mark pages as dirty during runtime
     // for queue objects.
@@ -283,11 +573,6 @@ fn snapshot_memory_to_file(
             }
         })
         .unwrap();
-
-    file.flush()
-        .map_err(|err| MemoryBackingFile("flush", err))?;
-    file.sync_all()
-        .map_err(|err| MemoryBackingFile("sync_all", err))
 }
 
 /// Validates that snapshot CPU vendor matches the host CPU vendor.
@@ -372,7 +657,7 @@ pub fn snapshot_state_sanity_check(
 ) -> Result<(), SnapShotStateSanityCheckError> {
     // Check if the snapshot contains at least 1 mem region.
     // Upper bound check will be done when creating guest memory by comparing against
-    // KVM max supported value kvm_context.max_memslots().
+    // KVM max supported value of memory slots.
     if microvm_state.memory_state.regions.is_empty() {
         return Err(SnapShotStateSanityCheckError::NoMemory);
     }
@@ -385,6 +670,239 @@ pub fn snapshot_state_sanity_check(
     Ok(())
 }
 
+/// Kind of problem preventing a snapshot from being restored on the host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SnapshotCompatIssueKind {
+    /// The snapshot format version is not supported by this Firecracker version.
+    SnapshotVersion,
+    /// The snapshot was created on a host with a CPU from another vendor.
+    CpuVendor,
+    /// The vCPUs use CPU features that are not available on the host.
+    CpuFeatures,
+    /// The vCPUs use a register that is not supported by KVM on the host.
+    UnsupportedRegister,
+    /// The microVM needs a KVM capability that is not supported on the host.
+    KvmCapability,
+    /// The snapshot was created on a host with a newer kernel.
+    KernelVersion,
+}
+
+/// Problem preventing a snapshot from being restored on the host.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SnapshotCompatIssue {
+    /// Kind of the problem.
+    pub kind: SnapshotCompatIssueKind,
+    /// Human-readable description of the problem.
+    pub description: String,
+}
+
+/// Result of checking whether a snapshot can be restored on the host, without restoring it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SnapshotCompatReport {
+    /// Whether the snapshot can be restored on the host.
+    pub compatible: bool,
+    /// Format version of the snapshot.
+    pub snapshot_version: String,
+    /// Release of the host kernel on which the snapshot was created, if recorded.
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub snapshot_kernel_version: String,
+    /// Release of the host kernel.
+    pub host_kernel_version: String,
+    /// Problems preventing the snapshot from being restored.
+    pub issues: Vec<SnapshotCompatIssue>,
+}
+
+impl SnapshotCompatReport {
+    fn push(&mut self, kind: SnapshotCompatIssueKind, description: String) {
+        self.issues.push(SnapshotCompatIssue { kind, description });
+    }
+}
+
+/// Error type for [`check_snapshot_compatibility`].
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum CheckSnapshotError {
+    /// Failed to read the snapshot file: {0}
+    SnapshotStateFromFile(#[from] SnapshotStateFromFileError),
+    /// Snapshot encryption error: {0}
+    Encryption(SnapshotEncryptionError),
+    /// Failed to get the host kernel version: {0}
+    KernelVersion(io::Error),
+    /// Failed to check the KVM capabilities of the host: {0}
+    KvmCapabilities(vstate::vm::VmError),
+    /// Failed to get the CPU configuration of the host: {0}
+    HostCpuConfig(#[from] builder::StartMicrovmError),
+    #[cfg(target_arch = "x86_64")]
+    /// Failed to read the CPUID of the snapshot: {0}
+    SnapshotCpuid(crate::cpu_config::x86_64::cpuid::CpuidTryFromKvmCpuid),
+}
+
+/// Parses the major and minor numbers of a kernel release, e.g. `(6, 1)` for `6.1.102-foo`.
+fn kernel_major_minor(release: &str) -> Option<(u32, u32)> {
+    let mut numbers = release.split(|c: char| !c.is_ascii_digit()).map(str::parse);
+    Some((numbers.next()?.ok()?, numbers.next()?.ok()?))
+}
+
+/// Checks whether the snapshot at `params.snapshot_path` can be restored on the host: its format
+/// version, the KVM capabilities it needs, the CPU vendor and features of its vCPUs and the
+/// kernel it was created on. Only the microVM state is read, and nothing is restored.
+pub fn check_snapshot_compatibility(
+    params: &CheckSnapshotParams,
+) -> Result<SnapshotCompatReport, CheckSnapshotError> {
+    use SnapshotCompatIssueKind::*;
+
+    let mut report = SnapshotCompatReport {
+        host_kernel_version: host_kernel_version().map_err(CheckSnapshotError::KernelVersion)?,
+        ..Default::default()
+    };
+
+    let key = params
+        .encryption
+        .map(|encryption| SnapshotKey::from_fd(encryption.key_fd))
+        .transpose()
+        .map_err(CheckSnapshotError::Encryption)?;
+    let microvm_state = match snapshot_state_from_file(&params.snapshot_path, key.as_ref()) {
+        Ok(microvm_state) => microvm_state,
+        Err(SnapshotStateFromFileError::Load(SnapshotError::InvalidFormatVersion(version))) => {
+            report.snapshot_version = version.to_string();
+            report.push(
+                SnapshotVersion,
+                format!("Snapshot version {version} is not supported, expected {SNAPSHOT_VERSION}"),
+            );
+            return Ok(report);
+        }
+        Err(err) => return Err(err.into()),
+    };
+    report.snapshot_version = SNAPSHOT_VERSION.to_string();
+    report.snapshot_kernel_version = microvm_state.vm_info.kernel_version.clone();
+
+    if let (Some(snapshot_kernel), Some(host_kernel)) = (
+        kernel_major_minor(&report.snapshot_kernel_version),
+        kernel_major_minor(&report.host_kernel_version),
+    ) {
+        if host_kernel < snapshot_kernel {
+            report.push(
+                KernelVersion,
+                format!(
+                    "The snapshot was created on kernel {}, newer than the host kernel {}",
+                    report.snapshot_kernel_version, report.host_kernel_version
+                ),
+            );
+        }
+    }
+
+    let kvm_cap_modifiers = &microvm_state.vm_state.kvm_cap_modifiers;
+    let unsupported_caps = Vm::unsupported_capabilities(kvm_cap_modifiers)
+        .map_err(CheckSnapshotError::KvmCapabilities)?;
+    for cap in &unsupported_caps {
+        report.push(
+            KvmCapability,
+            format!("KVM capability {cap} is not supported on this host"),
+        );
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    let same_vendor = match (
+        get_vendor_id_from_host(),
+        microvm_state.vcpu_states[0].cpuid.vendor_id(),
+    ) {
+        (Ok(host_id), Some(snapshot_id)) => host_id == snapshot_id,
+        _ => false,
+    };
+    #[cfg(target_arch = "aarch64")]
+    let same_vendor = match (
+        get_manufacturer_id_from_host(),
+        get_manufacturer_id_from_state(&microvm_state.vcpu_states[0].regs),
+    ) {
+        (Ok(host_id), Ok(snapshot_id)) => host_id == snapshot_id,
+        _ => false,
+    };
+    if !same_vendor {
+        report.push(
+            CpuVendor,
+            "The snapshot was created on a CPU from another vendor, or the vendor is unknown"
+                .to_string(),
+        );
+    }
+
+    // The configuration of the host vCPUs can only be compared if a VM can be created with the
+    // capabilities of the snapshot.
+    if same_vendor && unsupported_caps.is_empty() {
+        let (host_config, snapshot_config) = cpu_configs_for_snapshot(&microvm_state)?;
+        for (register, bits) in host_config.missing_features(&snapshot_config) {
+            report.push(
+                CpuFeatures,
+                format!("The features {bits:#x} of {register} are not available on this host"),
+            );
+        }
+        #[cfg(target_arch = "x86_64")]
+        for addr in snapshot_config.msrs.keys() {
+            if !host_config.msrs.contains_key(addr) {
+                report.push(
+                    UnsupportedRegister,
+                    format!("Register msr:{addr:#x} is not supported by KVM on this host"),
+                );
+            }
+        }
+    }
+
+    report.compatible = report.issues.is_empty();
+    Ok(report)
+}
+
+/// Returns the CPU configuration that the host gives to the vCPUs of the microVM saved in
+/// `microvm_state`, with the same KVM capabilities and vCPU features, and the configuration of the
+/// first saved vCPU.
+fn cpu_configs_for_snapshot(
+    microvm_state: &MicrovmState,
+) -> Result<(CpuConfiguration, CpuConfiguration), CheckSnapshotError> {
+    let vcpu_state = &microvm_state.vcpu_states[0];
+    #[allow(unused_mut)]
+    let mut cpu_template = CustomCpuTemplate {
+        kvm_capabilities: microvm_state.vm_state.kvm_cap_modifiers.clone(),
+        ..Default::default()
+    };
+    #[cfg(target_arch = "aarch64")]
+    cpu_template.vcpu_features.push(VcpuFeatures {
+        index: 0,
+        bitmap: RegisterValueFilter {
+            filter: u32::MAX,
+            value: vcpu_state.kvi.features[0],
+        },
+    });
+
+    let mut scratch_resources = VmResources {
+        vm_config: VmConfig {
+            vcpu_count: u8::try_from(microvm_state.vcpu_states.len()).unwrap_or(u8::MAX),
+            smt: microvm_state.vm_info.smt,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    scratch_resources
+        .vm_config
+        .set_custom_cpu_template(cpu_template);
+    let host_config = builder::dump_cpu_config(&scratch_resources)?;
+
+    #[cfg(target_arch = "x86_64")]
+    let snapshot_config = CpuConfiguration {
+        cpuid: Cpuid::try_from(vcpu_state.cpuid.clone())
+            .map_err(CheckSnapshotError::SnapshotCpuid)?,
+        msrs: vcpu_state
+            .saved_msrs
+            .iter()
+            .flat_map(|msrs| msrs.as_slice())
+            .map(|entry| (entry.index, entry.data))
+            .collect(),
+    };
+    #[cfg(target_arch = "aarch64")]
+    let snapshot_config = CpuConfiguration {
+        regs: vcpu_state.regs.clone(),
+    };
+
+    Ok((host_config, snapshot_config))
+}
+
 /// Error type for [`restore_from_snapshot`].
 #[derive(Debug, thiserror::Error, displaydoc::Display)]
 pub enum RestoreFromSnapshotError {
@@ -396,6 +914,12 @@ pub enum RestoreFromSnapshotError {
     GuestMemory(#[from] RestoreFromSnapshotGuestMemoryError),
     /// Failed to build microVM from snapshot: {0}
     Build(#[from] BuildMicrovmFromSnapshotError),
+    /// Snapshot encryption error: {0}
+    Encryption(#[from] SnapshotEncryptionError),
+    /// Encrypted snapshots cannot be loaded with the Uffd memory backend.
+    EncryptedUffd,
+    /// Guest memory cannot be pre-faulted with the Uffd memory backend.
+    PrefaultUffd,
 }
 /// Sub-Error type for [`restore_from_snapshot`] to contain either [`GuestMemoryFromFileError`] or
 /// [`GuestMemoryFromUffdError`] within [`RestoreFromSnapshotError`].
@@ -405,6 +929,8 @@ pub enum RestoreFromSnapshotGuestMemoryError {
     File(#[from] GuestMemoryFromFileError),
     /// Error creating guest memory from uffd: {0}
     Uffd(#[from] GuestMemoryFromUffdError),
+    /// Error pre-faulting guest memory: {0}
+    Prefault(MemoryError),
 }
 
 /// Loads a Microvm snapshot producing a 'paused' Microvm.
@@ -415,7 +941,21 @@ pub fn restore_from_snapshot(
     params: &LoadSnapshotParams,
     vm_resources: &mut VmResources,
 ) -> Result<Arc<Mutex<Vmm>>, RestoreFromSnapshotError> {
-    let microvm_state = snapshot_state_from_file(&params.snapshot_path)?;
+    let key = match params.encryption {
+        Some(encryption) => {
+            // The page fault handler only serves the plaintext contents of memory files.
+            if params.mem_backend.backend_type == MemBackendType::Uffd {
+                return Err(RestoreFromSnapshotError::EncryptedUffd);
+            }
+            Some(SnapshotKey::from_fd(encryption.key_fd)?)
+        }
+        None => None,
+    };
+    // The pages are populated by the page fault handler, on its own terms.
+    if params.prefault && params.mem_backend.backend_type == MemBackendType::Uffd {
+        return Err(RestoreFromSnapshotError::PrefaultUffd);
+    }
+    let microvm_state = snapshot_state_from_file(&params.snapshot_path, key.as_ref())?;
     let track_dirty_pages = params.enable_diff_snapshots;
 
     let vcpu_count = microvm_state
@@ -425,6 +965,12 @@ pub fn restore_from_snapshot(
         .map_err(|_| VmConfigError::InvalidVcpuCount)
         .map_err(BuildMicrovmFromSnapshotError::VmUpdateConfig)?;
 
+    // The PMU is a vCPU feature on aarch64, kept in the vCPU states.
+    #[cfg(target_arch = "aarch64")]
+    let pmu = microvm_state.vcpu_states.iter().any(VcpuState::has_pmu);
+    #[cfg(target_arch = "x86_64")]
+    let pmu = false;
+
     vm_resources
         .update_vm_config(&MachineConfigUpdate {
             vcpu_count: Some(vcpu_count),
@@ -433,6 +979,13 @@ pub fn restore_from_snapshot(
             cpu_template: Some(microvm_state.vm_info.cpu_template),
             track_dirty_pages: Some(track_dirty_pages),
             huge_pages: Some(microvm_state.vm_info.huge_pages),
+            pmu: Some(pmu),
+            nested_virt: None,
+            memory_tiers: None,
+            disabled_legacy_devices: Some(microvm_state.vm_info.disabled_legacy_devices.clone()),
+            reserved_memory: Some(microvm_state.vm_info.reserved_memory.clone()),
+            memory_backend: None,
+            prefault: None,
             #[cfg(feature = "gdb")]
             gdb_socket_path: None,
         })
@@ -451,6 +1004,7 @@ pub fn restore_from_snapshot(
                 mem_state,
                 track_dirty_pages,
                 vm_resources.vm_config.huge_pages,
+                key.as_ref(),
             )
             .map_err(RestoreFromSnapshotGuestMemoryError::File)?,
             None,
@@ -466,6 +1020,11 @@ pub fn restore_from_snapshot(
         )
         .map_err(RestoreFromSnapshotGuestMemoryError::Uffd)?,
     };
+    if params.prefault {
+        guest_memory
+            .prefault()
+            .map_err(RestoreFromSnapshotGuestMemoryError::Prefault)?;
+    }
     builder::build_microvm_from_snapshot(
         instance_info,
         event_manager,
@@ -487,19 +1046,35 @@ pub enum SnapshotStateFromFileError {
     Meta(std::io::Error),
     /// Failed to load snapshot state from file: {0}
     Load(#[from] crate::snapshot::SnapshotError),
+    /// Failed to select the snapshot storage: {0}
+    Storage(#[from] SnapshotStorageError),
+    /// Failed to decrypt snapshot file: {0}
+    Decrypt(std::io::Error),
 }
 
 fn snapshot_state_from_file(
     snapshot_path: &Path,
+    key: Option<&SnapshotKey>,
 ) -> Result<MicrovmState, SnapshotStateFromFileError> {
     let snapshot = Snapshot::new(SNAPSHOT_VERSION);
-    let mut snapshot_reader =
-        File::open(snapshot_path).map_err(SnapshotStateFromFileError::Open)?;
-    let metadata = std::fs::metadata(snapshot_path).map_err(SnapshotStateFromFileError::Meta)?;
-    let snapshot_len = u64_to_usize(metadata.len());
-    let state: MicrovmState = snapshot
-        .load_with_version_check(&mut snapshot_reader, snapshot_len)
-        .map_err(SnapshotStateFromFileError::Load)?;
+    let storage = open_storage(snapshot_path)?;
+    let snapshot_size = storage.size().map_err(SnapshotStateFromFileError::Meta)?;
+    let mut snapshot_reader = storage
+        .read_range(0, snapshot_size)
+        .map_err(SnapshotStateFromFileError::Open)?;
+    let snapshot_len = u64_to_usize(snapshot_size);
+    let state: MicrovmState = match key {
+        Some(key) => {
+            let mut decrypted = Vec::with_capacity(snapshot_len);
+            DecryptingReader::new(key, EncryptedFile::State, snapshot_reader)
+                .and_then(|mut reader| reader.read_to_end(&mut decrypted))
+                .map_err(SnapshotStateFromFileError::Decrypt)?;
+            let decrypted_len = decrypted.len();
+            snapshot.load_with_version_check(&mut decrypted.as_slice(), decrypted_len)
+        }
+        None => snapshot.load_with_version_check(&mut snapshot_reader, snapshot_len),
+    }
+    .map_err(SnapshotStateFromFileError::Load)?;
     Ok(state)
 }
 
@@ -510,6 +1085,10 @@ pub enum GuestMemoryFromFileError {
     File(#[from] std::io::Error),
     /// Failed to restore guest memory: {0}
     Restore(#[from] MemoryError),
+    /// Failed to select the snapshot storage: {0}
+    Storage(#[from] SnapshotStorageError),
+    /// Failed to fetch guest memory: {0}
+    Fetch(#[from] GuestMemoryError),
 }
 
 fn guest_memory_from_file(
@@ -517,13 +1096,92 @@ fn guest_memory_from_file(
     mem_state: &GuestMemoryState,
     track_dirty_pages: bool,
     huge_pages: HugePageConfig,
+    key: Option<&SnapshotKey>,
 ) -> Result<GuestMemoryMmap, GuestMemoryFromFileError> {
+    let storage = open_storage(mem_file_path)?;
+    if let Some(key) = key {
+        return guest_memory_from_encrypted_storage(
+            storage.as_ref(),
+            key,
+            mem_state,
+            track_dirty_pages,
+            huge_pages,
+        );
+    }
+    let Some(mem_file_path) = storage.local_path() else {
+        return guest_memory_from_storage(
+            storage.as_ref(),
+            mem_state,
+            track_dirty_pages,
+            huge_pages,
+        );
+    };
     let mem_file = File::open(mem_file_path)?;
     let guest_mem =
         GuestMemoryMmap::from_state(Some(&mem_file), mem_state, track_dirty_pages, huge_pages)?;
     Ok(guest_mem)
 }
 
+/// Creates anonymous guest memory and fills each of its regions by streaming the matching range
+/// of the memory file from a remote `storage`.
+fn guest_memory_from_storage(
+    storage: &dyn SnapshotStorage,
+    mem_state: &GuestMemoryState,
+    track_dirty_pages: bool,
+    huge_pages: HugePageConfig,
+) -> Result<GuestMemoryMmap, GuestMemoryFromFileError> {
+    let guest_mem = GuestMemoryMmap::from_state(None, mem_state, track_dirty_pages, huge_pages)?;
+    for (mem_region, state_region) in guest_mem.iter().zip(mem_state.regions.iter()) {
+        let mut slice = mem_region.as_volatile_slice()?;
+        let mut reader =
+            VolatileReader(storage.read_range(state_region.offset, slice.len() as u64)?);
+        reader
+            .read_exact_volatile(&mut slice)
+            .map_err(GuestMemoryError::from)?;
+    }
+    Ok(guest_mem)
+}
+
+/// Creates anonymous guest memory and fills it with the decrypted contents of an encrypted memory
+/// file, in which the regions are stored one after the other.
+fn guest_memory_from_encrypted_storage(
+    storage: &dyn SnapshotStorage,
+    key: &SnapshotKey,
+    mem_state: &GuestMemoryState,
+    track_dirty_pages: bool,
+    huge_pages: HugePageConfig,
+) -> Result<GuestMemoryMmap, GuestMemoryFromFileError> {
+    let guest_mem = GuestMemoryMmap::from_state(None, mem_state, track_dirty_pages, huge_pages)?;
+    let file_size = storage.size()?;
+    let mut reader = DecryptingReader::new(
+        key,
+        EncryptedFile::Memory,
+        storage.read_range(0, file_size)?,
+    )?;
+    let mut offset = 0;
+    for (mem_region, state_region) in guest_mem.iter().zip(mem_state.regions.iter()) {
+        let gap = state_region.offset.checked_sub(offset).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "overlapping memory regions")
+        })?;
+        io::copy(&mut (&mut reader).take(gap), &mut io::sink())?;
+        let mut slice = mem_region.as_volatile_slice()?;
+        VolatileReader(&mut reader)
+            .read_exact_volatile(&mut slice)
+            .map_err(GuestMemoryError::from)?;
+        offset = state_region.offset + slice.len() as u64;
+    }
+    // Reading up to the end authenticates the last chunk, which guarantees that the file is
+    // complete.
+    if io::copy(&mut reader, &mut io::sink())? != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "memory file larger than guest memory",
+        )
+        .into());
+    }
+    Ok(guest_mem)
+}
+
 /// Error type for [`guest_memory_from_uffd`]
 #[derive(Debug, thiserror::Error, displaydoc::Display)]
 pub enum GuestMemoryFromUffdError {
@@ -648,15 +1306,16 @@ mod tests {
     use vmm_sys_util::tempfile::TempFile;
 
     use super::*;
-    #[cfg(target_arch = "x86_64")]
-    use crate::builder::tests::insert_vmgenid_device;
     use crate::builder::tests::{
         default_kernel_cmdline, default_vmm, insert_balloon_device, insert_block_devices,
         insert_net_device, insert_vsock_device, CustomBlockConfig,
     };
+    #[cfg(target_arch = "x86_64")]
+    use crate::builder::tests::{insert_power_button_device, insert_vmgenid_device};
     #[cfg(target_arch = "aarch64")]
     use crate::construct_kvm_mpidrs;
     use crate::devices::virtio::block::CacheType;
+    use crate::snapshot::migration::{migrate, migration_targets, MigrationError};
     use crate::snapshot::Persist;
     use crate::vmm_config::balloon::BalloonDeviceConfig;
     use crate::vmm_config::net::NetworkInterfaceConfig;
@@ -674,6 +1333,8 @@ mod tests {
             amount_mib: 0,
             deflate_on_oom: false,
             stats_polling_interval_s: 0,
+            stats_push: None,
+            auto_target: None,
         };
         insert_balloon_device(&mut vmm, &mut cmdline, &mut event_manager, balloon_config);
 
@@ -695,6 +1356,16 @@ mod tests {
             guest_mac: None,
             rx_rate_limiter: None,
             tx_rate_limiter: None,
+            flow_accounting: None,
+            pause_responder: None,
+            rx_coalescing: None,
+            mtu: None,
+            mmds_only: None,
+            num_queues: None,
+            offloads: None,
+            enforce_mac: None,
+            enforce_arp: None,
+            shared_rate_limiter: None,
         };
         insert_net_device(
             &mut vmm,
@@ -712,29 +1383,19 @@ mod tests {
 
         #[cfg(target_arch = "x86_64")]
         insert_vmgenid_device(&mut vmm);
+        #[cfg(target_arch = "x86_64")]
+        insert_power_button_device(&mut vmm);
 
         vmm
     }
 
-    #[test]
-    fn test_microvm_state_snapshot() {
-        let vmm = default_vmm_with_devices();
-        let states = vmm.mmio_device_manager.save();
-
-        // Only checking that all devices are saved, actual device state
-        // is tested by that device's tests.
-        assert_eq!(states.block_devices.len(), 1);
-        assert_eq!(states.net_devices.len(), 1);
-        assert!(states.vsock_device.is_some());
-        assert!(states.balloon_device.is_some());
-
-        let memory_state = vmm.guest_memory().describe();
+    fn default_microvm_state(vmm: &Vmm) -> MicrovmState {
         let vcpu_states = vec![VcpuState::default()];
         #[cfg(target_arch = "aarch64")]
         let mpidrs = construct_kvm_mpidrs(&vcpu_states);
-        let microvm_state = MicrovmState {
-            device_states: states,
-            memory_state,
+        MicrovmState {
+            device_states: vmm.mmio_device_manager.save(),
+            memory_state: vmm.guest_memory().describe(),
             vcpu_states,
             vm_info: VmInfo {
                 mem_size_mib: 1u64,
@@ -745,7 +1406,22 @@ mod tests {
             #[cfg(target_arch = "x86_64")]
             vm_state: vmm.vm.save_state().unwrap(),
             acpi_dev_state: vmm.acpi_device_manager.save(),
-        };
+            shared_rate_limiters: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_microvm_state_snapshot() {
+        let vmm = default_vmm_with_devices();
+        let microvm_state = default_microvm_state(&vmm);
+        let states = &microvm_state.device_states;
+
+        // Only checking that all devices are saved, actual device state
+        // is tested by that device's tests.
+        assert_eq!(states.block_devices.len(), 1);
+        assert_eq!(states.net_devices.len(), 1);
+        assert!(states.vsock_device.is_some());
+        assert!(states.balloon_device.is_some());
 
         let mut buf = vec![0; 10000];
         Snapshot::serialize(&mut buf.as_mut_slice(), &microvm_state).unwrap();
@@ -760,6 +1436,204 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_migrate_v5_snapshot() {
+        let v5 = Version::new(5, 0, 0);
+        assert_eq!(
+            migration_targets(SNAPSHOT_MIGRATIONS, &SNAPSHOT_VERSION),
+            vec![v5.clone(), SNAPSHOT_VERSION]
+        );
+
+        let vmm = default_vmm_with_devices();
+        let microvm_state = default_microvm_state(&vmm);
+        let mut snapshot_data = Vec::new();
+        Snapshot::new(SNAPSHOT_VERSION)
+            .save(&mut snapshot_data, &microvm_state)
+            .unwrap();
+
+        // Rewrite the snapshot in version 5.0.0 of the format, in which the new fields of the
+        // device states are gone.
+        let mut v5_data = Vec::new();
+        migrate(
+            SNAPSHOT_MIGRATIONS,
+            &mut snapshot_data.as_slice(),
+            snapshot_data.len(),
+            &mut v5_data,
+            &v5,
+        )
+        .unwrap();
+        assert_eq!(
+            Snapshot::get_format_version(&mut v5_data.as_slice()).unwrap(),
+            v5
+        );
+        assert!(v5_data.len() < snapshot_data.len());
+        // The state doesn't load as it is in the current version of the format.
+        Snapshot::new(SNAPSHOT_VERSION)
+            .load_with_version_check::<_, MicrovmState>(&mut v5_data.as_slice(), v5_data.len())
+            .unwrap_err();
+
+        // Rewrite the version 5.0.0 snapshot in the current version of the format.
+        let mut v6_data = Vec::new();
+        let from = migrate(
+            SNAPSHOT_MIGRATIONS,
+            &mut v5_data.as_slice(),
+            v5_data.len(),
+            &mut v6_data,
+            &SNAPSHOT_VERSION,
+        )
+        .unwrap();
+        assert_eq!(from, v5);
+        let restored_state: MicrovmState = Snapshot::new(SNAPSHOT_VERSION)
+            .load_with_version_check(&mut v6_data.as_slice(), v6_data.len())
+            .unwrap();
+        assert_eq!(restored_state.vm_info, microvm_state.vm_info);
+        assert_eq!(restored_state.device_states, microvm_state.device_states);
+
+        // Shared rate limiters, labels and the fields holding values that are not the defaults
+        // cannot be represented in version 5.0.0.
+        let mut microvm_state = default_microvm_state(&vmm);
+        microvm_state.vm_info.disabled_legacy_devices = vec![LegacyDevice::I8042];
+        let mut snapshot_data = Vec::new();
+        Snapshot::new(SNAPSHOT_VERSION)
+            .save(&mut snapshot_data, &microvm_state)
+            .unwrap();
+        assert_eq!(
+            migrate(
+                SNAPSHOT_MIGRATIONS,
+                &mut snapshot_data.as_slice(),
+                snapshot_data.len(),
+                &mut Vec::new(),
+                &v5,
+            )
+            .unwrap_err(),
+            MigrationError::Unrepresentable("Disabled legacy devices".to_string(), v5.clone())
+        );
+
+        let labels = SnapshotLabels::from([("creator".to_string(), "test".to_string())]);
+        let mut snapshot_data = Vec::new();
+        Snapshot::new(SNAPSHOT_VERSION)
+            .with_labels(labels)
+            .save(&mut snapshot_data, &default_microvm_state(&vmm))
+            .unwrap();
+        assert_eq!(
+            migrate(
+                SNAPSHOT_MIGRATIONS,
+                &mut snapshot_data.as_slice(),
+                snapshot_data.len(),
+                &mut Vec::new(),
+                &v5,
+            )
+            .unwrap_err(),
+            MigrationError::Unrepresentable("Snapshot labels".to_string(), v5)
+        );
+    }
+
+    #[test]
+    fn test_validate_snapshot_labels() {
+        let mut labels = SnapshotLabels::from([
+            ("creator".to_string(), "test".to_string()),
+            ("example.com/git-sha".to_string(), "0123abcd".to_string()),
+        ]);
+        validate_snapshot_labels(&labels).unwrap();
+
+        labels.insert("tenant id".to_string(), "42".to_string());
+        assert!(matches!(
+            validate_snapshot_labels(&labels),
+            Err(CreateSnapshotError::InvalidLabelKey(key)) if key == "tenant id"
+        ));
+
+        let mut labels = SnapshotLabels::from([(String::new(), "empty".to_string())]);
+        assert!(matches!(
+            validate_snapshot_labels(&labels),
+            Err(CreateSnapshotError::InvalidLabelKey(_))
+        ));
+
+        labels.clear();
+        labels.insert(
+            "image".to_string(),
+            "a".repeat(MAX_SNAPSHOT_LABEL_VALUE_LEN + 1),
+        );
+        assert!(matches!(
+            validate_snapshot_labels(&labels),
+            Err(CreateSnapshotError::LabelValueTooLong(key)) if key == "image"
+        ));
+
+        let labels = (0..=MAX_SNAPSHOT_LABELS)
+            .map(|i| (format!("key{i}"), String::new()))
+            .collect();
+        assert!(matches!(
+            validate_snapshot_labels(&labels),
+            Err(CreateSnapshotError::TooManyLabels(len)) if len == MAX_SNAPSHOT_LABELS + 1
+        ));
+    }
+
+    #[test]
+    fn test_check_background_snapshot() {
+        let mut vmm = default_vmm();
+        let mut params = CreateSnapshotParams {
+            snapshot_type: SnapshotType::Diff,
+            snapshot_path: "vmstate".into(),
+            mem_file_path: "mem".into(),
+            labels: Default::default(),
+            encryption: None,
+            background: true,
+        };
+        assert!(matches!(
+            check_background_snapshot(&vmm, &params),
+            Err(BackgroundSnapshotError::DiffSnapshot)
+        ));
+
+        params.snapshot_type = SnapshotType::Full;
+        assert!(matches!(
+            check_background_snapshot(&vmm, &params),
+            Err(BackgroundSnapshotError::NoWriter)
+        ));
+
+        vmm.start_snapshot_writer(Arc::new(Vec::new())).unwrap();
+        check_background_snapshot(&vmm, &params).unwrap();
+    }
+
+    #[test]
+    fn test_kernel_major_minor() {
+        assert_eq!(
+            kernel_major_minor("6.1.102-111.182.amzn2023.x86_64"),
+            Some((6, 1))
+        );
+        assert_eq!(kernel_major_minor("5.10"), Some((5, 10)));
+        assert_eq!(kernel_major_minor("6"), None);
+        assert_eq!(kernel_major_minor(""), None);
+    }
+
+    #[test]
+    fn test_check_snapshot_compatibility_version() {
+        let snapshot_file = TempFile::new().unwrap();
+        Snapshot::new(Version::new(1, 0, 0))
+            .save(&mut snapshot_file.as_file(), &0u8)
+            .unwrap();
+
+        let report = check_snapshot_compatibility(&CheckSnapshotParams {
+            snapshot_path: snapshot_file.as_path().to_path_buf(),
+            encryption: None,
+        })
+        .unwrap();
+        assert!(!report.compatible);
+        assert_eq!(report.snapshot_version, "1.0.0");
+        assert_eq!(report.issues.len(), 1);
+        assert_eq!(
+            report.issues[0].kind,
+            SnapshotCompatIssueKind::SnapshotVersion
+        );
+
+        let missing_file = TempFile::new().unwrap();
+        let missing_path = missing_file.as_path().to_path_buf();
+        drop(missing_file);
+        check_snapshot_compatibility(&CheckSnapshotParams {
+            snapshot_path: missing_path,
+            encryption: None,
+        })
+        .unwrap_err();
+    }
+
     #[test]
     fn test_create_guest_memory() {
         let mem_state = GuestMemoryState {
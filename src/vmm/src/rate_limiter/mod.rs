@@ -2,12 +2,15 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 use std::{fmt, io};
 
+use shared::SharedRateLimiter;
 use timerfd::{ClockId, SetTimeFlags, TimerFd, TimerState};
 
 pub mod persist;
+pub mod shared;
 
 #[derive(Debug, thiserror::Error, displaydoc::Display)]
 /// Describes the errors that may occur while handling rate limiter events.
@@ -20,6 +23,9 @@ pub enum RateLimiterError {
 const REFILL_TIMER_INTERVAL_MS: u64 = 100;
 const TIMER_REFILL_STATE: TimerState =
     TimerState::Oneshot(Duration::from_millis(REFILL_TIMER_INTERVAL_MS));
+// Shortest interval at which the refill timer runs in smoothing mode, to bound the rate of timer
+// events when the missing tokens replenish almost right away.
+const MIN_SMOOTHING_TIMER_INTERVAL: Duration = Duration::from_micros(50);
 
 const NANOSEC_IN_ONE_MILLISEC: u64 = 1_000_000;
 
@@ -260,10 +266,25 @@ impl TokenBucket {
     pub fn initial_one_time_burst(&self) -> u64 {
         self.initial_one_time_burst
     }
+
+    /// Returns the time it takes for the bucket to replenish up to a budget of `tokens`, which
+    /// is zero if the budget is already large enough.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn time_to_budget(&self, tokens: u64) -> Duration {
+        let missing = u128::from(std::cmp::min(tokens, self.size).saturating_sub(self.budget));
+        let processed_capacity = u128::from(self.processed_capacity);
+        let processed_refill_time = u128::from(self.processed_refill_time);
+        // Round up, so that the tokens are replenished once the time elapsed.
+        let refill_time_ns = (missing * processed_refill_time).div_ceil(processed_capacity);
+        // The tokens replenish from the last update, and refilling at most `size` tokens takes
+        // `refill_time` milliseconds, which fits into a u64 as checked in the constructor.
+        (self.last_update + Duration::from_nanos(refill_time_ns as u64))
+            .saturating_duration_since(Instant::now())
+    }
 }
 
 /// Enum that describes the type of token used.
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum TokenType {
     /// Token type used for bandwidth limiting.
     Bytes,
@@ -297,9 +318,28 @@ pub enum BucketUpdate {
 /// RateLimiters will generate events on the FDs provided by their `AsRawFd` trait
 /// implementation. These events are meant to be consumed by the user of this struct.
 /// On each such event, the user must call the `event_handler()` method.
+///
+/// A RateLimiter can also be allowed to borrow the unused budget of a peer limiter, e.g. so that
+/// the RX and TX limiters of a network interface share a combined budget. See
+/// `consume_or_borrow()`.
+///
+/// A RateLimiter can also reference a shared rate limiter, whose budget is consumed along with
+/// its own, e.g. so that all the drives of a microVM share one aggregate bandwidth budget. See
+/// `set_shared()`.
+///
+/// In smoothing mode, a blocked RateLimiter arms its timer for the time the missing tokens take
+/// to replenish, with a sub-millisecond resolution, rather than for a fixed interval. This avoids
+/// stalling the device for up to the whole interval when its budget runs out. See
+/// `set_smoothing()`.
 pub struct RateLimiter {
     bandwidth: Option<TokenBucket>,
     ops: Option<TokenBucket>,
+    // Whether this limiter may borrow tokens from a peer limiter when it runs out of budget.
+    borrow: bool,
+    // Shared rate limiter whose budget is consumed along with the budget of this limiter.
+    shared: Option<Arc<Mutex<SharedRateLimiter>>>,
+    // Whether the timer is armed for the time the missing tokens take to replenish.
+    smoothing: bool,
 
     timer_fd: TimerFd,
     // Internal flag that quickly determines timer state.
@@ -308,7 +348,11 @@ pub struct RateLimiter {
 
 impl PartialEq for RateLimiter {
     fn eq(&self, other: &RateLimiter) -> bool {
-        self.bandwidth == other.bandwidth && self.ops == other.ops
+        self.bandwidth == other.bandwidth
+            && self.ops == other.ops
+            && self.borrow == other.borrow
+            && self.shared_id() == other.shared_id()
+            && self.smoothing == other.smoothing
     }
 }
 
@@ -372,6 +416,9 @@ impl RateLimiter {
         Ok(RateLimiter {
             bandwidth: bytes_token_bucket,
             ops: ops_token_bucket,
+            borrow: false,
+            shared: None,
+            smoothing: false,
             timer_fd,
             timer_active: false,
         })
@@ -388,11 +435,81 @@ impl RateLimiter {
     ///
     /// If rate limiting is disabled on provided `token_type`, this function will always succeed.
     pub fn consume(&mut self, tokens: u64, token_type: TokenType) -> bool {
+        self.consume_from(tokens, token_type, None)
+    }
+
+    /// Attempts to consume tokens and returns whether that is possible, borrowing them from
+    /// `peer` if borrowing is enabled and there is not enough budget left in this limiter.
+    ///
+    /// Borrowed tokens are taken out of the unused budget of `peer`, without ever blocking it.
+    /// This limiter only blocks if neither of the two limiters can provide the tokens.
+    pub fn consume_or_borrow(
+        &mut self,
+        tokens: u64,
+        token_type: TokenType,
+        peer: &mut RateLimiter,
+    ) -> bool {
+        if self.borrow {
+            self.consume_from(tokens, token_type, Some(peer))
+        } else {
+            self.consume_from(tokens, token_type, None)
+        }
+    }
+
+    fn consume_from(
+        &mut self,
+        tokens: u64,
+        token_type: TokenType,
+        lender: Option<&mut RateLimiter>,
+    ) -> bool {
         // If the timer is active, we can't consume tokens from any bucket and the function fails.
         if self.timer_active {
             return false;
         }
 
+        // Take the tokens out of the shared budget first, so that a limiter blocked by the shared
+        // budget keeps its own. The shared rate limiter has no timer, so this limiter polls it
+        // with its own timer.
+        let shared_reduction = self.shared.as_ref().and_then(|shared| {
+            shared
+                .lock()
+                .expect("Poisoned lock")
+                .reduce(tokens, token_type)
+        });
+        if let Some((BucketReduction::Failure, _)) = shared_reduction {
+            let wait = self
+                .smoothing
+                .then(|| {
+                    self.shared
+                        .as_ref()?
+                        .lock()
+                        .expect("Poisoned lock")
+                        .time_to_budget(tokens, token_type)
+                })
+                .flatten();
+            self.activate_refill_timer(wait);
+            return false;
+        }
+
+        if !self.consume_own(tokens, token_type, lender) {
+            // Give the tokens back to the shared budget, as they are not used.
+            self.replenish_shared(tokens, token_type);
+            return false;
+        }
+        if let Some((BucketReduction::OverConsumption(ratio), refill_time)) = shared_reduction {
+            if !self.timer_active {
+                self.activate_overconsumption_timer(ratio, refill_time);
+            }
+        }
+        true
+    }
+
+    fn consume_own(
+        &mut self,
+        tokens: u64,
+        token_type: TokenType,
+        lender: Option<&mut RateLimiter>,
+    ) -> bool {
         // Identify the required token bucket.
         let token_bucket = match token_type {
             TokenType::Bytes => self.bandwidth.as_mut(),
@@ -406,8 +523,12 @@ impl RateLimiter {
                 // register a timer to replenish the bucket and resume processing;
                 // make sure there is only one running timer for this limiter.
                 BucketReduction::Failure => {
+                    let wait = self.smoothing.then(|| bucket.time_to_budget(tokens));
+                    if lender.is_some_and(|lender| lender.lend(tokens, token_type)) {
+                        return true;
+                    }
                     if !self.timer_active {
-                        self.activate_timer(TIMER_REFILL_STATE);
+                        self.activate_refill_timer(wait);
                     }
                     false
                 }
@@ -422,11 +543,7 @@ impl RateLimiter {
                     // order to enforce the bandwidth limit we need to prevent
                     // further calls to the rate limiter for
                     // `ratio * refill_time` milliseconds.
-                    // The conversion should be safe because the ratio is positive.
-                    #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
-                    self.activate_timer(TimerState::Oneshot(Duration::from_millis(
-                        (ratio * refill_time as f64) as u64,
-                    )));
+                    self.activate_overconsumption_timer(ratio, refill_time);
                     true
                 }
             }
@@ -437,6 +554,67 @@ impl RateLimiter {
         }
     }
 
+    // Arm the timer after running out of budget: for `wait` in smoothing mode, and for the
+    // fixed refill interval otherwise.
+    fn activate_refill_timer(&mut self, wait: Option<Duration>) {
+        match wait {
+            Some(wait) => {
+                self.activate_timer(TimerState::Oneshot(wait.max(MIN_SMOOTHING_TIMER_INTERVAL)))
+            }
+            None => self.activate_timer(TIMER_REFILL_STATE),
+        }
+    }
+
+    // Arm the timer for `ratio * refill_time` milliseconds, after over-consuming a bucket.
+    fn activate_overconsumption_timer(&mut self, ratio: f64, refill_time: u64) {
+        // The conversions should be safe because the ratio is positive.
+        if self.smoothing {
+            // Keep the sub-millisecond part of the delay.
+            #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
+            let wait = Duration::from_nanos(
+                (ratio * (refill_time * NANOSEC_IN_ONE_MILLISEC) as f64) as u64,
+            );
+            self.activate_timer(TimerState::Oneshot(wait.max(MIN_SMOOTHING_TIMER_INTERVAL)));
+            return;
+        }
+        #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
+        self.activate_timer(TimerState::Oneshot(Duration::from_millis(
+            (ratio * refill_time as f64) as u64,
+        )));
+    }
+
+    // Gives `tokens` back to the shared rate limiter, if any.
+    fn replenish_shared(&self, tokens: u64, token_type: TokenType) {
+        if let Some(shared) = self.shared.as_ref() {
+            shared
+                .lock()
+                .expect("Poisoned lock")
+                .replenish(tokens, token_type);
+        }
+    }
+
+    // Takes `tokens` out of the unused budget of this limiter on behalf of a peer limiter.
+    //
+    // Unlike `consume()`, a failure does not block this limiter, and a limiter which does not
+    // limit `token_type` has no budget to lend.
+    fn lend(&mut self, tokens: u64, token_type: TokenType) -> bool {
+        if self.timer_active {
+            return false;
+        }
+
+        let token_bucket = match token_type {
+            TokenType::Bytes => self.bandwidth.as_mut(),
+            TokenType::Ops => self.ops.as_mut(),
+        };
+        match token_bucket {
+            // Lending more than the bucket size would over-consume and block this limiter.
+            Some(bucket) if tokens <= bucket.capacity() => {
+                bucket.reduce(tokens) == BucketReduction::Success
+            }
+            _ => false,
+        }
+    }
+
     /// Adds tokens of `token_type` to their respective bucket.
     ///
     /// Can be used to *manually* add tokens to a bucket. Useful for reverting a
@@ -451,6 +629,7 @@ impl RateLimiter {
         if let Some(bucket) = token_bucket {
             bucket.force_replenish(tokens);
         }
+        self.replenish_shared(tokens, token_type);
     }
 
     /// Returns whether this rate limiter is blocked.
@@ -495,6 +674,40 @@ impl RateLimiter {
         };
     }
 
+    /// Returns whether this limiter may borrow tokens from a peer limiter.
+    pub fn borrow(&self) -> bool {
+        self.borrow
+    }
+
+    /// Allows or forbids this limiter to borrow tokens from a peer limiter.
+    pub fn set_borrow(&mut self, borrow: bool) {
+        self.borrow = borrow;
+    }
+
+    /// Returns whether the timer of this limiter is armed for the time the missing tokens take to
+    /// replenish.
+    pub fn smoothing(&self) -> bool {
+        self.smoothing
+    }
+
+    /// Enables or disables the smoothing mode of this limiter.
+    pub fn set_smoothing(&mut self, smoothing: bool) {
+        self.smoothing = smoothing;
+    }
+
+    /// Makes this limiter consume the budget of the given shared rate limiter along with its own,
+    /// or stop consuming a shared budget if `None`.
+    pub fn set_shared(&mut self, shared: Option<Arc<Mutex<SharedRateLimiter>>>) {
+        self.shared = shared;
+    }
+
+    /// Returns the id of the shared rate limiter this limiter consumes the budget of, if any.
+    pub fn shared_id(&self) -> Option<String> {
+        self.shared
+            .as_ref()
+            .map(|shared| shared.lock().expect("Poisoned lock").id().to_string())
+    }
+
     /// Returns an immutable view of the inner bandwidth token bucket.
     pub fn bandwidth(&self) -> Option<&TokenBucket> {
         self.bandwidth.as_ref()
@@ -1148,6 +1361,145 @@ pub(crate) mod tests {
         assert!(l.consume(100, TokenType::Bytes));
     }
 
+    #[test]
+    fn test_rate_limiter_borrow() {
+        // Two limiters of 1000 bytes per 100s, so that they don't refill during the test.
+        let mut l = RateLimiter::new(1000, 0, 100_000, 0, 0, 0).unwrap();
+        let mut peer = RateLimiter::new(1000, 0, 100_000, 0, 0, 0).unwrap();
+
+        // Borrowing is disabled by default.
+        assert!(l.consume_or_borrow(1000, TokenType::Bytes, &mut peer));
+        assert!(!l.consume_or_borrow(100, TokenType::Bytes, &mut peer));
+        assert!(l.is_blocked());
+        assert_eq!(peer.bandwidth().unwrap().budget(), 1000);
+
+        let mut l = RateLimiter::new(1000, 0, 100_000, 0, 0, 0).unwrap();
+        l.set_borrow(true);
+        assert!(l.borrow());
+
+        // The own budget is used first.
+        assert!(l.consume_or_borrow(800, TokenType::Bytes, &mut peer));
+        assert_eq!(peer.bandwidth().unwrap().budget(), 1000);
+
+        // Then the unused budget of the peer, without blocking either of them.
+        assert!(l.consume_or_borrow(600, TokenType::Bytes, &mut peer));
+        assert!(!l.is_blocked());
+        assert_eq!(l.bandwidth().unwrap().budget(), 200);
+        assert_eq!(peer.bandwidth().unwrap().budget(), 400);
+
+        // When the peer can not lend the tokens either, only the borrower blocks.
+        assert!(!l.consume_or_borrow(600, TokenType::Bytes, &mut peer));
+        assert!(l.is_blocked());
+        assert!(!peer.is_blocked());
+        assert_eq!(peer.bandwidth().unwrap().budget(), 400);
+
+        // A peer which does not limit a token type has nothing to lend.
+        let mut l = RateLimiter::new(0, 0, 0, 10, 0, 100_000).unwrap();
+        l.set_borrow(true);
+        assert!(l.consume_or_borrow(10, TokenType::Ops, &mut peer));
+        assert!(!l.consume_or_borrow(1, TokenType::Ops, &mut peer));
+
+        // A blocked peer does not lend.
+        let mut l = RateLimiter::new(1000, 0, 100_000, 0, 0, 0).unwrap();
+        l.set_borrow(true);
+        assert!(!peer.consume(1000, TokenType::Bytes));
+        assert!(peer.is_blocked());
+        assert!(l.consume_or_borrow(1000, TokenType::Bytes, &mut peer));
+        assert!(!l.consume_or_borrow(1, TokenType::Bytes, &mut peer));
+    }
+
+    #[test]
+    fn test_rate_limiter_shared() {
+        // A shared budget of 1000 bytes per 100s, so that it does not refill during the test.
+        shared::insert(
+            "test_rate_limiter_shared",
+            TokenBucket::new(1000, 0, 100_000),
+            None,
+        );
+        let mut l = RateLimiter::new(1000, 0, 100_000, 0, 0, 0).unwrap();
+        let mut other = RateLimiter::default();
+        l.set_shared(shared::get("test_rate_limiter_shared"));
+        other.set_shared(shared::get("test_rate_limiter_shared"));
+        assert_eq!(l.shared_id().as_deref(), Some("test_rate_limiter_shared"));
+
+        // The tokens are taken out of both the own and the shared budgets.
+        assert!(l.consume(400, TokenType::Bytes));
+        assert!(other.consume(400, TokenType::Bytes));
+        assert_eq!(l.bandwidth().unwrap().budget(), 600);
+        let shared = shared::get("test_rate_limiter_shared").unwrap();
+        assert_eq!(shared.lock().unwrap().bandwidth().unwrap().budget(), 200);
+
+        // Token types which the shared rate limiter does not limit are not shared.
+        assert!(other.consume(u64::MAX, TokenType::Ops));
+
+        // Running out of the shared budget blocks the limiter, without consuming its own budget.
+        assert!(!other.consume(400, TokenType::Bytes));
+        assert!(other.is_blocked());
+        assert!(!l.is_blocked());
+        assert_eq!(shared.lock().unwrap().bandwidth().unwrap().budget(), 200);
+
+        // Running out of the own budget gives the tokens back to the shared budget.
+        let mut l2 = RateLimiter::new(100, 0, 100_000, 0, 0, 0).unwrap();
+        l2.set_shared(shared::get("test_rate_limiter_shared"));
+        assert!(l2.consume(100, TokenType::Bytes));
+        assert!(!l2.consume(50, TokenType::Bytes));
+        assert!(l2.is_blocked());
+        assert_eq!(shared.lock().unwrap().bandwidth().unwrap().budget(), 100);
+
+        // Manually replenished tokens are also given back to the shared budget.
+        l.manual_replenish(100, TokenType::Bytes);
+        assert_eq!(l.bandwidth().unwrap().budget(), 700);
+        assert_eq!(shared.lock().unwrap().bandwidth().unwrap().budget(), 200);
+
+        l.set_shared(None);
+        assert!(l.shared_id().is_none());
+        assert!(l.consume(700, TokenType::Bytes));
+        assert_eq!(shared.lock().unwrap().bandwidth().unwrap().budget(), 200);
+    }
+
+    #[test]
+    fn test_token_bucket_time_to_budget() {
+        // A bucket replenishing one token per millisecond.
+        let mut tb = TokenBucket::new(1000, 0, 1000).unwrap();
+        assert_eq!(tb.time_to_budget(1000), Duration::ZERO);
+
+        assert_eq!(tb.reduce(1000), BucketReduction::Success);
+        let wait = tb.time_to_budget(500);
+        assert!(wait <= Duration::from_millis(500));
+        assert!(wait > Duration::from_millis(400));
+        // The budget never grows past the size of the bucket.
+        assert!(tb.time_to_budget(u64::MAX) <= Duration::from_millis(1000));
+        assert_eq!(tb.time_to_budget(0), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_rate_limiter_smoothing() {
+        // A limiter replenishing one byte per millisecond.
+        let mut l = RateLimiter::new(1000, 0, 1000, 0, 0, 0).unwrap();
+        l.set_smoothing(true);
+        assert!(l.smoothing());
+
+        // Once blocked, the limiter resumes as soon as the missing 10 bytes are replenished,
+        // rather than after the refill timer interval.
+        assert!(l.consume(1000, TokenType::Bytes));
+        assert!(!l.consume(10, TokenType::Bytes));
+        assert!(l.is_blocked());
+        thread::sleep(Duration::from_millis(30));
+        l.event_handler().unwrap();
+        assert!(!l.is_blocked());
+        assert!(l.consume(10, TokenType::Bytes));
+
+        // The over-consumption timer keeps its sub-millisecond part: consuming 1.5x the size of a
+        // bucket refilling in 1ms blocks the limiter for 0.5ms.
+        let mut l = RateLimiter::new(1000, 0, 1, 0, 0, 0).unwrap();
+        l.set_smoothing(true);
+        assert!(l.consume(1500, TokenType::Bytes));
+        assert!(l.is_blocked());
+        thread::sleep(Duration::from_millis(5));
+        l.event_handler().unwrap();
+        assert!(!l.is_blocked());
+    }
+
     #[test]
     fn test_update_buckets() {
         let mut x = RateLimiter::new(1000, 2000, 1000, 10, 20, 1000).unwrap();
@@ -56,6 +56,9 @@ impl Persist<'_> for TokenBucket {
 pub struct RateLimiterState {
     ops: Option<TokenBucketState>,
     bandwidth: Option<TokenBucketState>,
+    borrow: bool,
+    shared: Option<String>,
+    smoothing: bool,
 }
 
 impl Persist<'_> for RateLimiter {
@@ -67,6 +70,9 @@ impl Persist<'_> for RateLimiter {
         RateLimiterState {
             ops: self.ops.as_ref().map(|ops| ops.save()),
             bandwidth: self.bandwidth.as_ref().map(|bw| bw.save()),
+            borrow: self.borrow,
+            shared: self.shared_id(),
+            smoothing: self.smoothing,
         }
     }
 
@@ -82,6 +88,18 @@ impl Persist<'_> for RateLimiter {
             } else {
                 None
             },
+            borrow: state.borrow,
+            // The shared rate limiters are restored before the devices referencing them.
+            shared: match state.shared.as_deref() {
+                Some(id) => Some(shared::get(id).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("Shared rate limiter {id} not found"),
+                    )
+                })?),
+                None => None,
+            },
+            smoothing: state.smoothing,
             timer_fd: TimerFd::new_custom(ClockId::Monotonic, true, true)?,
             timer_active: false,
         };
@@ -90,6 +108,48 @@ impl Persist<'_> for RateLimiter {
     }
 }
 
+/// State for saving a shared rate limiter.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SharedRateLimiterState {
+    id: String,
+    ops: Option<TokenBucketState>,
+    bandwidth: Option<TokenBucketState>,
+}
+
+/// Saves the states of all the shared rate limiters.
+pub fn save_shared_rate_limiters() -> Vec<SharedRateLimiterState> {
+    shared::all()
+        .iter()
+        .map(|limiter| {
+            let limiter = limiter.lock().expect("Poisoned lock");
+            SharedRateLimiterState {
+                id: limiter.id().to_string(),
+                ops: limiter.ops().map(|ops| ops.save()),
+                bandwidth: limiter.bandwidth().map(|bw| bw.save()),
+            }
+        })
+        .collect()
+}
+
+/// Restores the shared rate limiters from their states, replacing the buckets of the ones
+/// already registered with the same ids.
+pub fn restore_shared_rate_limiters(states: &[SharedRateLimiterState]) -> io::Result<()> {
+    for state in states {
+        let ops = state
+            .ops
+            .as_ref()
+            .map(|ops| TokenBucket::restore((), ops))
+            .transpose()?;
+        let bandwidth = state
+            .bandwidth
+            .as_ref()
+            .map(|bw| TokenBucket::restore((), bw))
+            .transpose()?;
+        shared::insert(&state.id, bandwidth, ops);
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -178,6 +238,18 @@ mod tests {
             .unwrap()
             .partial_eq(restored_rate_limiter.bandwidth().unwrap()));
 
+        // Check that the borrowing flag is restored.
+        rate_limiter.set_borrow(true);
+        let restored_rate_limiter =
+            RateLimiter::restore((), &rate_limiter.save()).expect("Unable to restore rate limiter");
+        assert!(restored_rate_limiter.borrow());
+
+        // Check that the smoothing mode is restored.
+        rate_limiter.set_smoothing(true);
+        let restored_rate_limiter =
+            RateLimiter::restore((), &rate_limiter.save()).expect("Unable to restore rate limiter");
+        assert!(restored_rate_limiter.smoothing());
+
         // Test serialization.
         let mut mem = vec![0; 4096];
         Snapshot::serialize(&mut mem.as_mut_slice(), &rate_limiter.save()).unwrap();
@@ -192,5 +264,37 @@ mod tests {
             .bandwidth()
             .unwrap()
             .partial_eq(restored_rate_limiter.bandwidth().unwrap()));
+        assert!(restored_rate_limiter.borrow());
+    }
+
+    #[test]
+    fn test_shared_rate_limiter_persistence() {
+        shared::insert("test_persistence", TokenBucket::new(1000, 0, 100_000), None);
+        let mut rate_limiter = RateLimiter::default();
+        rate_limiter.set_shared(shared::get("test_persistence"));
+        assert!(rate_limiter.consume(100, TokenType::Bytes));
+
+        let states = save_shared_rate_limiters();
+        let rate_limiter_state = rate_limiter.save();
+
+        // Consume more of the shared budget, which is then restored from the saved state.
+        assert!(rate_limiter.consume(100, TokenType::Bytes));
+        restore_shared_rate_limiters(&states).unwrap();
+        let restored_rate_limiter = RateLimiter::restore((), &rate_limiter_state).unwrap();
+        assert_eq!(
+            restored_rate_limiter.shared_id().as_deref(),
+            Some("test_persistence")
+        );
+        let shared = shared::get("test_persistence").unwrap();
+        assert_eq!(shared.lock().unwrap().bandwidth().unwrap().budget(), 900);
+        assert!(shared.lock().unwrap().ops().is_none());
+
+        // Restoring a limiter referencing a missing shared rate limiter fails.
+        let mut state = rate_limiter_state;
+        state.shared = Some("test_persistence_missing".to_string());
+        assert_eq!(
+            RateLimiter::restore((), &state).unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
     }
 }
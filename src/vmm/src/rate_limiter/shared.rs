@@ -0,0 +1,266 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Rate limiters shared by several devices, e.g. to enforce one aggregate bandwidth budget
+//! across all the drives of the microVM.
+//!
+//! The shared rate limiters are registered by id, and referenced by the rate limiters of the
+//! devices: the tokens consumed by a device are taken out of both its own buckets and the buckets
+//! of the shared rate limiter. The shared rate limiter has no timer: a device it blocked polls it
+//! with the timer of its own rate limiter.
+//!
+//! # Metrics format
+//! The fill levels of the shared rate limiters are reported with the metrics, as:
+//! ```json
+//! {
+//!  "rate_limiter_drives": {
+//!     "bandwidth_budget": 1048576,
+//!     "ops_budget": 0,
+//!     "throttled_events": "SharedIncMetric"
+//!  }
+//! }
+//! ```
+//! where `drives` is the id of the shared rate limiter, and the budgets of the buckets it does not
+//! have are 0.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::ser::SerializeMap;
+use serde::{Serialize, Serializer};
+
+use super::{BucketReduction, BucketUpdate, TokenBucket, TokenType};
+use crate::logger::{IncMetric, SharedIncMetric};
+
+/// Token buckets shared by the rate limiters of several devices.
+#[derive(Debug)]
+pub struct SharedRateLimiter {
+    id: String,
+    bandwidth: Option<TokenBucket>,
+    ops: Option<TokenBucket>,
+    /// Number of times a device was blocked because the shared budget ran out.
+    throttled_events: SharedIncMetric,
+}
+
+impl SharedRateLimiter {
+    /// Creates a shared rate limiter with the given buckets, which are disabled if `None`.
+    pub fn new(id: String, bandwidth: Option<TokenBucket>, ops: Option<TokenBucket>) -> Self {
+        SharedRateLimiter {
+            id,
+            bandwidth,
+            ops,
+            throttled_events: SharedIncMetric::default(),
+        }
+    }
+
+    /// Returns the id of the shared rate limiter.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Returns an immutable view of the bandwidth token bucket.
+    pub fn bandwidth(&self) -> Option<&TokenBucket> {
+        self.bandwidth.as_ref()
+    }
+
+    /// Returns an immutable view of the ops token bucket.
+    pub fn ops(&self) -> Option<&TokenBucket> {
+        self.ops.as_ref()
+    }
+
+    /// Replenishes the buckets based on the elapsed time, so that their budgets are up to date.
+    pub fn refresh(&mut self) {
+        for bucket in [self.bandwidth.as_mut(), self.ops.as_mut()]
+            .into_iter()
+            .flatten()
+        {
+            bucket.auto_replenish();
+        }
+    }
+
+    /// Updates the parameters of the token buckets.
+    pub fn update_buckets(&mut self, bytes: BucketUpdate, ops: BucketUpdate) {
+        match bytes {
+            BucketUpdate::Disabled => self.bandwidth = None,
+            BucketUpdate::Update(tb) => self.bandwidth = Some(tb),
+            BucketUpdate::None => (),
+        };
+        match ops {
+            BucketUpdate::Disabled => self.ops = None,
+            BucketUpdate::Update(tb) => self.ops = Some(tb),
+            BucketUpdate::None => (),
+        };
+    }
+
+    fn bucket_mut(&mut self, token_type: TokenType) -> Option<&mut TokenBucket> {
+        match token_type {
+            TokenType::Bytes => self.bandwidth.as_mut(),
+            TokenType::Ops => self.ops.as_mut(),
+        }
+    }
+
+    // Takes `tokens` out of the bucket of `token_type`, returning the outcome along with the
+    // refill time of the bucket, or `None` if this limiter does not limit `token_type`.
+    pub(super) fn reduce(
+        &mut self,
+        tokens: u64,
+        token_type: TokenType,
+    ) -> Option<(BucketReduction, u64)> {
+        let bucket = self.bucket_mut(token_type)?;
+        let refill_time = bucket.refill_time_ms();
+        let reduction = bucket.reduce(tokens);
+        if reduction == BucketReduction::Failure {
+            self.throttled_events.inc();
+        }
+        Some((reduction, refill_time))
+    }
+
+    // Returns the time it takes for the bucket of `token_type` to replenish up to `tokens`, or
+    // `None` if this limiter does not limit `token_type`.
+    pub(super) fn time_to_budget(&self, tokens: u64, token_type: TokenType) -> Option<Duration> {
+        let bucket = match token_type {
+            TokenType::Bytes => self.bandwidth.as_ref(),
+            TokenType::Ops => self.ops.as_ref(),
+        };
+        bucket.map(|bucket| bucket.time_to_budget(tokens))
+    }
+
+    // Gives `tokens` back to the bucket of `token_type`.
+    pub(super) fn replenish(&mut self, tokens: u64, token_type: TokenType) {
+        if let Some(bucket) = self.bucket_mut(token_type) {
+            bucket.force_replenish(tokens);
+        }
+    }
+}
+
+/// The shared rate limiters, by id.
+static SHARED_RATE_LIMITERS: Mutex<BTreeMap<String, Arc<Mutex<SharedRateLimiter>>>> =
+    Mutex::new(BTreeMap::new());
+
+/// Registers a shared rate limiter with the given buckets. If one is already registered with
+/// the same id, its buckets are updated instead, so that the devices referencing it are
+/// limited by the new buckets.
+pub fn insert(id: &str, bandwidth: Option<TokenBucket>, ops: Option<TokenBucket>) {
+    let mut limiters = SHARED_RATE_LIMITERS.lock().expect("Poisoned lock");
+    match limiters.get(id) {
+        Some(limiter) => {
+            let bucket_update = |bucket: Option<TokenBucket>| {
+                bucket.map_or(BucketUpdate::Disabled, BucketUpdate::Update)
+            };
+            limiter
+                .lock()
+                .expect("Poisoned lock")
+                .update_buckets(bucket_update(bandwidth), bucket_update(ops));
+        }
+        None => {
+            limiters.insert(
+                id.to_string(),
+                Arc::new(Mutex::new(SharedRateLimiter::new(
+                    id.to_string(),
+                    bandwidth,
+                    ops,
+                ))),
+            );
+        }
+    }
+}
+
+/// Returns the shared rate limiter registered with the given id, if any.
+pub fn get(id: &str) -> Option<Arc<Mutex<SharedRateLimiter>>> {
+    SHARED_RATE_LIMITERS
+        .lock()
+        .expect("Poisoned lock")
+        .get(id)
+        .cloned()
+}
+
+/// Returns all the shared rate limiters, sorted by id.
+pub fn all() -> Vec<Arc<Mutex<SharedRateLimiter>>> {
+    SHARED_RATE_LIMITERS
+        .lock()
+        .expect("Poisoned lock")
+        .values()
+        .cloned()
+        .collect()
+}
+
+#[derive(Serialize)]
+struct SharedRateLimiterMetrics<'a> {
+    bandwidth_budget: u64,
+    ops_budget: u64,
+    throttled_events: &'a SharedIncMetric,
+}
+
+/// Serializes the current fill levels of the shared rate limiters, along with their metrics.
+pub fn flush_metrics<S: Serializer>(serializer: S) -> Result<S::Ok, S::Error> {
+    let limiters = all();
+    let mut seq = serializer.serialize_map(Some(limiters.len()))?;
+    for limiter in limiters {
+        let mut limiter = limiter.lock().expect("Poisoned lock");
+        limiter.refresh();
+        let metrics = SharedRateLimiterMetrics {
+            bandwidth_budget: limiter.bandwidth().map_or(0, TokenBucket::budget),
+            ops_budget: limiter.ops().map_or(0, TokenBucket::budget),
+            throttled_events: &limiter.throttled_events,
+        };
+        seq.serialize_entry(&format!("rate_limiter_{}", limiter.id()), &metrics)?;
+    }
+    seq.end()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shared_rate_limiter_registry() {
+        assert!(get("test_registry").is_none());
+
+        insert(
+            "test_registry",
+            TokenBucket::new(1000, 0, 1000),
+            TokenBucket::new(10, 0, 1000),
+        );
+        let limiter = get("test_registry").unwrap();
+        assert_eq!(limiter.lock().unwrap().id(), "test_registry");
+        assert_eq!(
+            limiter.lock().unwrap().bandwidth().unwrap().capacity(),
+            1000
+        );
+
+        // Inserting it again updates the limiter in place.
+        insert("test_registry", None, TokenBucket::new(20, 0, 1000));
+        assert!(Arc::ptr_eq(&limiter, &get("test_registry").unwrap()));
+        assert!(limiter.lock().unwrap().bandwidth().is_none());
+        assert_eq!(limiter.lock().unwrap().ops().unwrap().capacity(), 20);
+        assert!(all()
+            .iter()
+            .any(|limiter| limiter.lock().unwrap().id() == "test_registry"));
+    }
+
+    #[test]
+    fn test_shared_rate_limiter_reduce() {
+        let mut limiter = SharedRateLimiter::new(
+            "test_reduce".to_string(),
+            TokenBucket::new(1000, 0, 1000),
+            None,
+        );
+
+        // Token types which are not limited are not reduced.
+        assert!(limiter.reduce(1, TokenType::Ops).is_none());
+
+        assert_eq!(
+            limiter.reduce(600, TokenType::Bytes),
+            Some((BucketReduction::Success, 1000))
+        );
+        assert_eq!(
+            limiter.reduce(600, TokenType::Bytes),
+            Some((BucketReduction::Failure, 1000))
+        );
+        assert_eq!(limiter.throttled_events.count(), 1);
+
+        limiter.replenish(600, TokenType::Bytes);
+        assert_eq!(limiter.bandwidth().unwrap().budget(), 1000);
+    }
+}
@@ -3,32 +3,47 @@
 
 use std::convert::From;
 use std::path::PathBuf;
+use std::sync::atomic::Ordering;
 use std::sync::{Arc, Mutex, MutexGuard};
 
 use serde::{Deserialize, Serialize};
 
 use crate::cpu_config::templates::CustomCpuTemplate;
 use crate::device_manager::persist::SharedDeviceType;
+use crate::devices::legacy::serial::SerialLog;
 use crate::logger::{info, log_dev_preview_warning};
 use crate::mmds;
 use crate::mmds::data_store::{Mmds, MmdsVersion};
 use crate::mmds::ns::MmdsNetworkStack;
+use crate::snapshot::storage::HTTP_SNAPSHOT_STORAGE;
 use crate::utils::net::ipv4addr::is_link_local_valid;
 use crate::vmm_config::balloon::*;
 use crate::vmm_config::boot_source::{
     BootConfig, BootSource, BootSourceConfig, BootSourceConfigError,
 };
+use crate::vmm_config::cpu_limiter::{CpuLimiterConfig, CpuLimiterConfigError};
 use crate::vmm_config::drive::*;
 use crate::vmm_config::entropy::*;
 use crate::vmm_config::instance_info::InstanceInfo;
+use crate::vmm_config::lifecycle_hooks::{LifecycleHooksConfig, LifecycleHooksConfigError};
 use crate::vmm_config::machine_config::{
-    HugePageConfig, MachineConfig, MachineConfigUpdate, VmConfig, VmConfigError,
+    HugePageConfig, MachineConfig, MachineConfigUpdate, MemoryBackend, VmConfig, VmConfigError,
 };
 use crate::vmm_config::metrics::{init_metrics, MetricsConfig, MetricsConfigError};
-use crate::vmm_config::mmds::{MmdsConfig, MmdsConfigError};
+use crate::vmm_config::mmds::{MmdsConfig, MmdsConfigError, MAX_BACKEND_TIMEOUT_MS};
 use crate::vmm_config::net::*;
+use crate::vmm_config::pvpanic::PvPanicConfig;
+use crate::vmm_config::rate_limiters::{
+    insert_shared_rate_limiter, SharedRateLimiterConfig, SharedRateLimiterConfigError,
+};
+use crate::vmm_config::serial::{SerialConfig, SerialConfigError};
+use crate::vmm_config::shared_memory::{
+    SharedMemoryBuilder, SharedMemoryConfig, SharedMemoryConfigError,
+};
+use crate::vmm_config::smbios::{SmbiosConfig, SmbiosConfigError};
+use crate::vmm_config::tpm::TpmConfig;
 use crate::vmm_config::vsock::*;
-use crate::vstate::memory::{GuestMemoryExtension, GuestMemoryMmap, MemoryError};
+use crate::vstate::memory::{GuestAddress, GuestMemoryExtension, GuestMemoryMmap, MemoryError};
 
 /// Errors encountered when configuring microVM resources.
 #[derive(Debug, thiserror::Error, displaydoc::Display)]
@@ -39,10 +54,14 @@ pub enum ResourcesError {
     BlockDevice(#[from] DriveError),
     /// Boot source error: {0}
     BootSource(#[from] BootSourceConfigError),
+    /// CPU limiter config error: {0}
+    CpuLimiter(#[from] CpuLimiterConfigError),
     /// File operation error: {0}
     File(#[from] std::io::Error),
     /// Invalid JSON: {0}
     InvalidJson(#[from] serde_json::Error),
+    /// Lifecycle hooks config error: {0}
+    LifecycleHooks(#[from] LifecycleHooksConfigError),
     /// Logger error: {0}
     Logger(#[from] crate::logger::LoggerUpdateError),
     /// Metrics error: {0}
@@ -53,6 +72,14 @@ pub enum ResourcesError {
     MmdsConfig(#[from] MmdsConfigError),
     /// Network device error: {0}
     NetDevice(#[from] NetworkInterfaceError),
+    /// Shared rate limiter config error: {0}
+    SharedRateLimiter(#[from] SharedRateLimiterConfigError),
+    /// Serial console config error: {0}
+    SerialConfig(#[from] SerialConfigError),
+    /// Shared memory config error: {0}
+    SharedMemory(#[from] SharedMemoryConfigError),
+    /// SMBIOS config error: {0}
+    Smbios(#[from] SmbiosConfigError),
     /// VM config error: {0}
     VmConfig(#[from] VmConfigError),
     /// Vsock device error: {0}
@@ -72,6 +99,10 @@ pub struct VmmConfig {
     boot_source: BootSourceConfig,
     #[serde(rename = "cpu-config")]
     cpu_config: Option<PathBuf>,
+    #[serde(rename = "cpu-limiter")]
+    cpu_limiter: Option<CpuLimiterConfig>,
+    #[serde(rename = "lifecycle-hooks", default)]
+    lifecycle_hooks: LifecycleHooksConfig,
     #[serde(rename = "logger")]
     logger: Option<crate::logger::LoggerConfig>,
     #[serde(rename = "machine-config")]
@@ -82,6 +113,18 @@ pub struct VmmConfig {
     mmds_config: Option<MmdsConfig>,
     #[serde(rename = "network-interfaces", default)]
     net_devices: Vec<NetworkInterfaceConfig>,
+    #[serde(rename = "pvpanic")]
+    pvpanic: Option<PvPanicConfig>,
+    #[serde(rename = "rate-limiters", default)]
+    shared_rate_limiters: Vec<SharedRateLimiterConfig>,
+    #[serde(rename = "serial", default)]
+    serial: SerialConfig,
+    #[serde(rename = "shared-memory", default)]
+    shared_memory: Vec<SharedMemoryConfig>,
+    #[serde(rename = "smbios")]
+    smbios: Option<SmbiosConfig>,
+    #[serde(rename = "tpm")]
+    tpm: Option<TpmConfig>,
     #[serde(rename = "vsock")]
     vsock_device: Option<VsockDeviceConfig>,
     #[serde(rename = "entropy")]
@@ -114,6 +157,24 @@ pub struct VmResources {
     pub mmds_size_limit: usize,
     /// Whether or not to load boot timer device.
     pub boot_timer: bool,
+    /// The host side to which the serial console is attached.
+    pub serial: SerialConfig,
+    /// The buffer capturing the serial console output, if enabled.
+    pub serial_log: Option<SerialLog>,
+    /// The read-only memory segments shared with other microVMs.
+    pub shared_memory: SharedMemoryBuilder,
+    /// The configurations of the rate limiters shared by several devices.
+    pub shared_rate_limiters: Vec<SharedRateLimiterConfig>,
+    /// The SMBIOS configuration, if the SMBIOS tables are exposed to the guest.
+    pub smbios: Option<SmbiosConfig>,
+    /// The TPM configuration, if a TPM device is attached to the microVM.
+    pub tpm: Option<TpmConfig>,
+    /// The pvpanic configuration, if a pvpanic device is attached to the microVM.
+    pub pvpanic: Option<PvPanicConfig>,
+    /// The CPU limiter configuration, if the vCPUs are throttled to a CPU allocation.
+    pub cpu_limiter: Option<CpuLimiterConfig>,
+    /// The hooks run at the lifecycle events of the microVM.
+    pub lifecycle_hooks: LifecycleHooksConfig,
 }
 
 impl VmResources {
@@ -152,6 +213,11 @@ impl VmResources {
 
         resources.build_boot_source(vmm_config.boot_source)?;
 
+        // The shared rate limiters are referenced by the devices, so they are configured first.
+        for rate_limiter_config in vmm_config.shared_rate_limiters.into_iter() {
+            resources.set_shared_rate_limiter(rate_limiter_config)?;
+        }
+
         for drive_config in vmm_config.block_devices.into_iter() {
             resources.set_block_device(drive_config)?;
         }
@@ -184,6 +250,30 @@ impl VmResources {
             resources.build_entropy_device(entropy_device_config)?;
         }
 
+        resources.set_serial_config(vmm_config.serial)?;
+
+        for shared_memory_config in vmm_config.shared_memory.into_iter() {
+            resources.set_shared_memory(shared_memory_config)?;
+        }
+
+        if let Some(smbios_config) = vmm_config.smbios {
+            resources.set_smbios_config(smbios_config)?;
+        }
+
+        if let Some(tpm_config) = vmm_config.tpm {
+            resources.set_tpm_config(tpm_config);
+        }
+
+        if let Some(pvpanic_config) = vmm_config.pvpanic {
+            resources.set_pvpanic_config(pvpanic_config);
+        }
+
+        if let Some(cpu_limiter_config) = vmm_config.cpu_limiter {
+            resources.set_cpu_limiter(cpu_limiter_config)?;
+        }
+
+        resources.set_lifecycle_hooks(vmm_config.lifecycle_hooks)?;
+
         Ok(resources)
     }
 
@@ -266,9 +356,11 @@ impl VmResources {
             return Err(VmConfigError::BalloonAndHugePages);
         }
 
-        if self.boot_source.config.initrd_path.is_some()
-            && updated.huge_pages != HugePageConfig::None
-        {
+        if self.balloon.get().is_some() && updated.memory_backend == MemoryBackend::GuestMemfd {
+            return Err(VmConfigError::BalloonAndGuestMemfd);
+        }
+
+        if self.boot_source.config.has_initrd() && updated.huge_pages != HugePageConfig::None {
             return Err(VmConfigError::InitrdAndHugePages);
         }
 
@@ -296,11 +388,21 @@ impl VmResources {
                 version: mmds.lock().expect("Poisoned lock").version(),
                 network_interfaces: vec![],
                 ipv4_address: None,
+                guest_data_limit: mmds.lock().expect("Poisoned lock").guest_data_limit(),
+                path_acl: None,
+                backend: mmds.lock().expect("Poisoned lock").backend_config(),
             };
 
             for net_dev in net_devs_with_mmds {
                 let net = net_dev.lock().unwrap();
                 inner_mmds_config.network_interfaces.push(net.id().clone());
+                // Safe to unwrap the mmds_ns as the filter() explicitly checks for its existence.
+                if let Some(allowed_paths) = net.mmds_ns().unwrap().allowed_paths() {
+                    inner_mmds_config
+                        .path_acl
+                        .get_or_insert_with(Default::default)
+                        .insert(net.id().clone(), allowed_paths.to_vec());
+                }
                 // Only need to get one ip address, as they will all be equal.
                 if inner_mmds_config.ipv4_address.is_none() {
                     // Safe to unwrap the mmds_ns as the filter() explicitly checks for
@@ -322,7 +424,11 @@ impl VmResources {
     ) -> Result<(), BalloonConfigError> {
         // The balloon cannot have a target size greater than the size of
         // the guest memory.
-        if config.amount_mib as usize > self.vm_config.mem_size_mib {
+        if config.amount_mib as usize > self.vm_config.mem_size_mib
+            || config.auto_target.as_ref().is_some_and(|auto_target| {
+                auto_target.max_mib as usize > self.vm_config.mem_size_mib
+            })
+        {
             return Err(BalloonConfigError::TooManyPagesRequested);
         }
 
@@ -330,6 +436,10 @@ impl VmResources {
             return Err(BalloonConfigError::HugePages);
         }
 
+        if self.vm_config.memory_backend == MemoryBackend::GuestMemfd {
+            return Err(BalloonConfigError::GuestMemfd);
+        }
+
         self.balloon.set(config)
     }
 
@@ -338,9 +448,7 @@ impl VmResources {
         &mut self,
         boot_source_cfg: BootSourceConfig,
     ) -> Result<(), BootSourceConfigError> {
-        if boot_source_cfg.initrd_path.is_some()
-            && self.vm_config.huge_pages != HugePageConfig::None
-        {
+        if boot_source_cfg.has_initrd() && self.vm_config.huge_pages != HugePageConfig::None {
             return Err(BootSourceConfigError::HugePagesAndInitRd);
         }
 
@@ -384,14 +492,111 @@ impl VmResources {
         self.entropy.insert(body)
     }
 
+    /// Sets the host side to which the serial console is attached when the VM starts.
+    pub fn set_serial_config(&mut self, config: SerialConfig) -> Result<(), SerialConfigError> {
+        config.validate()?;
+        self.serial_log = config.log_buffer_size.map(SerialLog::new);
+        self.serial = config;
+        Ok(())
+    }
+
+    /// Adds a read-only memory segment shared with other microVMs, or updates the one with the
+    /// same id.
+    pub fn set_shared_memory(
+        &mut self,
+        config: SharedMemoryConfig,
+    ) -> Result<(), SharedMemoryConfigError> {
+        self.shared_memory.insert(config)
+    }
+
+    /// Adds a rate limiter shared by several devices, or updates the one with the same id.
+    pub fn set_shared_rate_limiter(
+        &mut self,
+        config: SharedRateLimiterConfig,
+    ) -> Result<(), SharedRateLimiterConfigError> {
+        insert_shared_rate_limiter(config.clone())?;
+        match self
+            .shared_rate_limiters
+            .iter_mut()
+            .find(|existing| existing.id == config.id)
+        {
+            Some(existing) => *existing = config,
+            None => self.shared_rate_limiters.push(config),
+        }
+        Ok(())
+    }
+
+    /// Sets the SMBIOS configuration exposed to the guest when the VM starts.
+    pub fn set_smbios_config(&mut self, config: SmbiosConfig) -> Result<(), SmbiosConfigError> {
+        config.validate()?;
+        self.smbios = Some(config);
+        Ok(())
+    }
+
+    /// Sets the TPM device attached to the microVM when it starts.
+    pub fn set_tpm_config(&mut self, config: TpmConfig) {
+        self.tpm = Some(config);
+    }
+
+    /// Sets the pvpanic device attached to the microVM when it starts.
+    pub fn set_pvpanic_config(&mut self, config: PvPanicConfig) {
+        self.pvpanic = Some(config);
+    }
+
+    /// Sets the CPU allocation the vCPUs are throttled to once the microVM starts.
+    pub fn set_cpu_limiter(
+        &mut self,
+        config: CpuLimiterConfig,
+    ) -> Result<(), CpuLimiterConfigError> {
+        config.validate()?;
+        self.cpu_limiter = Some(config);
+        Ok(())
+    }
+
+    /// Updates the CPU allocation the vCPUs are throttled to, which must have been set before.
+    pub fn update_cpu_limiter(
+        &mut self,
+        config: CpuLimiterConfig,
+    ) -> Result<(), CpuLimiterConfigError> {
+        config.validate()?;
+        let cpu_limiter = self
+            .cpu_limiter
+            .as_mut()
+            .ok_or(CpuLimiterConfigError::NotConfigured)?;
+        *cpu_limiter = config;
+        Ok(())
+    }
+
+    /// Sets the hooks run at the lifecycle events of the microVM.
+    pub fn set_lifecycle_hooks(
+        &mut self,
+        config: LifecycleHooksConfig,
+    ) -> Result<(), LifecycleHooksConfigError> {
+        config.validate()?;
+        self.lifecycle_hooks = config;
+        Ok(())
+    }
+
     /// Setter for mmds config.
     pub fn set_mmds_config(
         &mut self,
         config: MmdsConfig,
         instance_id: &str,
     ) -> Result<(), MmdsConfigError> {
+        if config.guest_data_limit() == Some(0) {
+            return Err(MmdsConfigError::InvalidGuestDataLimit);
+        }
+        if let Some(backend) = config.backend.as_ref() {
+            if !(1..=MAX_BACKEND_TIMEOUT_MS).contains(&backend.timeout_ms) {
+                return Err(MmdsConfigError::InvalidBackendTimeout(backend.timeout_ms));
+            }
+        }
+
         self.set_mmds_network_stack_config(&config)?;
         self.set_mmds_version(config.version, instance_id)?;
+        let mut mmds = self.locked_mmds_or_default();
+        mmds.set_guest_data_limit(config.guest_data_limit());
+        mmds.set_backend(config.backend.as_ref());
 
         Ok(())
     }
@@ -440,6 +645,16 @@ impl VmResources {
             return Err(MmdsConfigError::InvalidNetworkInterfaceId);
         }
 
+        // Ensure the path ACL only restricts the interfaces forwarding MMDS requests.
+        for (iface_id, prefixes) in config.path_acl.iter().flatten() {
+            if !network_interfaces.contains(iface_id) {
+                return Err(MmdsConfigError::InvalidPathAclIface(iface_id.clone()));
+            }
+            if let Some(prefix) = prefixes.iter().find(|prefix| !prefix.starts_with('/')) {
+                return Err(MmdsConfigError::InvalidPathAclPrefix(prefix.clone()));
+            }
+        }
+
         // Safe to unwrap because we've just made sure that it's initialised.
         let mmds = self.mmds_or_default().clone();
 
@@ -449,7 +664,9 @@ impl VmResources {
         for net_device in self.net_builder.iter_mut() {
             let mut net_device_lock = net_device.lock().expect("Poisoned lock");
             if network_interfaces.contains(net_device_lock.id()) {
+                let allowed_paths = config.allowed_paths(net_device_lock.id());
                 net_device_lock.configure_mmds_network_stack(ipv4_addr, mmds.clone());
+                net_device_lock.set_mmds_allowed_paths(allowed_paths);
             } else {
                 net_device_lock.disable_mmds_network_stack();
             }
@@ -458,16 +675,45 @@ impl VmResources {
         Ok(())
     }
 
+    /// Returns whether the VMM thread connects to TCP servers once the microVM runs: for the NBD
+    /// drives served over TCP, the remote drives with an `http://` chunk store, the vsock ports
+    /// forwarded to TCP, and the snapshots created on `http://` URLs when they are allowed.
+    pub fn connects_over_tcp(&self) -> bool {
+        let drives = self.block.configs().into_iter().any(|config| {
+            let nbd_tcp = config.backend == Some(DriveBackend::Nbd)
+                && config
+                    .path_on_host
+                    .is_some_and(|path| path.starts_with("nbd://"));
+            let remote_http = config
+                .remote
+                .is_some_and(|remote| remote.chunk_store.starts_with("http://"));
+            nbd_tcp || remote_http
+        });
+        let vsock = self
+            .vsock
+            .config()
+            .is_some_and(|config| !config.tcp_forwards.is_empty());
+        drives || vsock || HTTP_SNAPSHOT_STORAGE.load(Ordering::Relaxed)
+    }
+
+    /// Returns the guest physical address and size of the guest memory regions.
+    pub fn guest_memory_regions(&self) -> Vec<(GuestAddress, usize)> {
+        // The memory tiers are mapped past the guest memory, from their own backing files.
+        self.vm_config.guest_memory_layout().swap_remove(0)
+    }
+
     /// Allocates guest memory in a configuration most appropriate for these [`VmResources`].
     ///
     /// If vhost-user-blk devices are in use, allocates memfd-backed shared memory, otherwise
-    /// prefers anonymous memory for performance reasons.
+    /// prefers anonymous memory for performance reasons. Guest memory backed by a guest_memfd is
+    /// allocated by the builder instead, as the guest_memfd is created from the VM.
     pub fn allocate_guest_memory(&self) -> Result<GuestMemoryMmap, MemoryError> {
         let vhost_user_device_used = self
             .block
             .devices
             .iter()
             .any(|b| b.lock().expect("Poisoned lock").is_vhost_user());
+        let regions = self.guest_memory_regions();
 
         // Page faults are more expensive for shared memory mapping, including  memfd.
         // For this reason, we only back guest memory with a memfd
@@ -480,12 +726,11 @@ impl VmResources {
         // that would not be worth the effort.
         if vhost_user_device_used {
             GuestMemoryMmap::memfd_backed(
-                self.vm_config.mem_size_mib,
+                &regions,
                 self.vm_config.track_dirty_pages,
                 self.vm_config.huge_pages,
             )
         } else {
-            let regions = crate::arch::arch_memory_regions(self.vm_config.mem_size_mib << 20);
             GuestMemoryMmap::from_raw_regions(
                 &regions,
                 self.vm_config.track_dirty_pages,
@@ -502,11 +747,19 @@ impl From<&VmResources> for VmmConfig {
             block_devices: resources.block.configs(),
             boot_source: resources.boot_source.config.clone(),
             cpu_config: None,
+            cpu_limiter: resources.cpu_limiter.clone(),
+            lifecycle_hooks: resources.lifecycle_hooks.clone(),
             logger: None,
             machine_config: Some(MachineConfig::from(&resources.vm_config)),
             metrics: None,
             mmds_config: resources.mmds_config(),
             net_devices: resources.net_builder.configs(),
+            pvpanic: resources.pvpanic.clone(),
+            shared_rate_limiters: resources.shared_rate_limiters.clone(),
+            serial: resources.serial.clone(),
+            shared_memory: resources.shared_memory.configs(),
+            smbios: resources.smbios.clone(),
+            tpm: resources.tpm.clone(),
             vsock_device: resources.vsock.config(),
             entropy_device: resources.entropy.config(),
         }
@@ -535,10 +788,17 @@ mod tests {
         BootConfig, BootSource, BootSourceConfig, DEFAULT_KERNEL_CMDLINE,
     };
     use crate::vmm_config::drive::{BlockBuilder, BlockDeviceConfig};
-    use crate::vmm_config::machine_config::{HugePageConfig, MachineConfig, VmConfigError};
+    use crate::vmm_config::lifecycle_hooks::{LifecycleEvent, LifecycleHookConfig};
+    use crate::vmm_config::machine_config::{
+        HugePageConfig, LegacyDevice, MachineConfig, MemoryTier, ReservedMemoryRegion,
+        VmConfigError, MAX_MEMORY_TIERS,
+    };
+    use crate::vmm_config::mmds::{MmdsBackendConfig, DEFAULT_BACKEND_TIMEOUT_MS};
     use crate::vmm_config::net::{NetBuilder, NetworkInterfaceConfig};
+    use crate::vmm_config::pvpanic::PanicSnapshotConfig;
+    use crate::vmm_config::serial::SerialMode;
     use crate::vmm_config::vsock::tests::default_config;
-    use crate::vmm_config::RateLimiterConfig;
+    use crate::vmm_config::{RateLimiterConfig, TokenBucketConfig};
     use crate::HTTP_MAX_PAYLOAD_SIZE;
 
     fn default_net_cfg() -> NetworkInterfaceConfig {
@@ -555,6 +815,16 @@ mod tests {
             guest_mac: Some(MacAddr::from_str("01:23:45:67:89:0a").unwrap()),
             rx_rate_limiter: Some(RateLimiterConfig::default()),
             tx_rate_limiter: Some(RateLimiterConfig::default()),
+            flow_accounting: None,
+            pause_responder: None,
+            rx_coalescing: None,
+            mtu: None,
+            mmds_only: None,
+            num_queues: None,
+            offloads: None,
+            enforce_mac: None,
+            enforce_arp: None,
+            shared_rate_limiter: None,
         }
     }
 
@@ -578,6 +848,11 @@ mod tests {
                 path_on_host: Some(tmp_file.as_path().to_str().unwrap().to_string()),
                 rate_limiter: Some(RateLimiterConfig::default()),
                 file_engine_type: None,
+                remote: None,
+                direct_io: None,
+                backend: None,
+                latency_injection: None,
+                shared_rate_limiter: None,
 
                 socket: None,
             },
@@ -600,8 +875,11 @@ mod tests {
             config: BootSourceConfig::default(),
             builder: Some(BootConfig {
                 cmdline: kernel_cmdline,
-                kernel_file: File::open(tmp_file.as_path()).unwrap(),
-                initrd_file: Some(File::open(tmp_file.as_path()).unwrap()),
+                kernel_file: Some(File::open(tmp_file.as_path()).unwrap()),
+                initrd_files: vec![File::open(tmp_file.as_path()).unwrap()],
+                firmware_file: None,
+                kernel_digest: None,
+                initrd_digest: None,
             }),
         }
     }
@@ -618,28 +896,37 @@ mod tests {
             boot_timer: false,
             mmds_size_limit: HTTP_MAX_PAYLOAD_SIZE,
             entropy: Default::default(),
+            serial: Default::default(),
+            serial_log: None,
+            shared_memory: Default::default(),
+            shared_rate_limiters: Vec::new(),
+            smbios: None,
+            tpm: None,
+            pvpanic: None,
+            cpu_limiter: None,
+            lifecycle_hooks: Default::default(),
         }
     }
 
     impl PartialEq for BootConfig {
         fn eq(&self, other: &Self) -> bool {
             self.cmdline.eq(&other.cmdline)
-                && self.kernel_file.metadata().unwrap().st_ino()
-                    == other.kernel_file.metadata().unwrap().st_ino()
                 && self
-                    .initrd_file
+                    .kernel_file
                     .as_ref()
                     .unwrap()
                     .metadata()
                     .unwrap()
                     .st_ino()
                     == other
-                        .initrd_file
+                        .kernel_file
                         .as_ref()
                         .unwrap()
                         .metadata()
                         .unwrap()
                         .st_ino()
+                && self.initrd_files[0].metadata().unwrap().st_ino()
+                    == other.initrd_files[0].metadata().unwrap().st_ino()
         }
     }
 
@@ -1318,7 +1605,11 @@ mod tests {
                     }},
                     "mmds-config": {{
                         "network_interfaces": ["netif1", "netif2"],
-                        "ipv4_address": "169.254.1.1"
+                        "ipv4_address": "169.254.1.1",
+                        "guest_data_limit": 1024,
+                        "path_acl": {{
+                            "netif2": ["/latest/meta-data"]
+                        }}
                     }}
             }}"#,
                 kernel_file.as_path().to_str().unwrap(),
@@ -1338,6 +1629,77 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_set_mmds_path_acl() {
+        let mut vm_resources = default_vm_resources();
+        let mut config = MmdsConfig {
+            version: MmdsVersion::V2,
+            network_interfaces: vec!["net_if1".to_string()],
+            ipv4_address: None,
+            guest_data_limit: None,
+            backend: None,
+            path_acl: Some(
+                [("net_if2".to_string(), vec!["/latest".to_string()])]
+                    .into_iter()
+                    .collect(),
+            ),
+        };
+        assert!(matches!(
+            vm_resources.set_mmds_config(config.clone(), ""),
+            Err(MmdsConfigError::InvalidPathAclIface(iface_id)) if iface_id == "net_if2"
+        ));
+
+        config.path_acl = Some(
+            [("net_if1".to_string(), vec!["latest".to_string()])]
+                .into_iter()
+                .collect(),
+        );
+        assert!(matches!(
+            vm_resources.set_mmds_config(config.clone(), ""),
+            Err(MmdsConfigError::InvalidPathAclPrefix(prefix)) if prefix == "latest"
+        ));
+
+        config.path_acl = Some(
+            [("net_if1".to_string(), vec!["/latest".to_string()])]
+                .into_iter()
+                .collect(),
+        );
+        vm_resources.set_mmds_config(config.clone(), "").unwrap();
+        let net = vm_resources.net_builder.iter().next().unwrap();
+        assert_eq!(
+            net.lock().unwrap().mmds_ns().unwrap().allowed_paths(),
+            Some(&["/latest".to_string()][..])
+        );
+        assert_eq!(
+            vm_resources.mmds_config().unwrap().path_acl,
+            config.path_acl
+        );
+    }
+
+    #[test]
+    fn test_set_mmds_backend() {
+        let mut vm_resources = default_vm_resources();
+        let mut config = MmdsConfig {
+            version: MmdsVersion::V2,
+            network_interfaces: vec!["net_if1".to_string()],
+            ipv4_address: None,
+            guest_data_limit: None,
+            path_acl: None,
+            backend: Some(MmdsBackendConfig {
+                socket_path: PathBuf::from("/tmp/mmds.sock"),
+                timeout_ms: 0,
+            }),
+        };
+        assert!(matches!(
+            vm_resources.set_mmds_config(config.clone(), ""),
+            Err(MmdsConfigError::InvalidBackendTimeout(0))
+        ));
+
+        config.backend.as_mut().unwrap().timeout_ms = DEFAULT_BACKEND_TIMEOUT_MS;
+        vm_resources.set_mmds_config(config.clone(), "").unwrap();
+        assert_eq!(vm_resources.mmds_config().unwrap().backend, config.backend);
+    }
+
     #[test]
     fn test_update_vm_config() {
         let mut vm_resources = default_vm_resources();
@@ -1351,6 +1713,13 @@ mod tests {
             cpu_template: Some(StaticCpuTemplate::V1N1),
             track_dirty_pages: Some(false),
             huge_pages: Some(HugePageConfig::None),
+            pmu: Some(false),
+            nested_virt: Some(false),
+            memory_tiers: Some(vec![]),
+            disabled_legacy_devices: Some(vec![]),
+            reserved_memory: Some(vec![]),
+            memory_backend: Some(MemoryBackend::Anonymous),
+            prefault: Some(false),
         };
 
         assert_ne!(
@@ -1394,6 +1763,193 @@ mod tests {
         vm_resources.update_vm_config(&aux_vm_config).unwrap();
         aux_vm_config.smt = Some(false);
 
+        // Check that PMU is only supported on aarch64.
+        aux_vm_config.pmu = Some(true);
+        #[cfg(target_arch = "x86_64")]
+        assert_eq!(
+            vm_resources.update_vm_config(&aux_vm_config),
+            Err(VmConfigError::PmuNotSupported)
+        );
+        #[cfg(target_arch = "aarch64")]
+        vm_resources.update_vm_config(&aux_vm_config).unwrap();
+        aux_vm_config.pmu = Some(false);
+
+        // Check that nested virtualization is only supported on x86_64.
+        aux_vm_config.nested_virt = Some(true);
+        #[cfg(target_arch = "aarch64")]
+        assert_eq!(
+            vm_resources.update_vm_config(&aux_vm_config),
+            Err(VmConfigError::NestedVirtNotSupported)
+        );
+        #[cfg(target_arch = "x86_64")]
+        vm_resources.update_vm_config(&aux_vm_config).unwrap();
+        aux_vm_config.nested_virt = Some(false);
+
+        // Check that only the legacy devices of the architecture can be disabled.
+        aux_vm_config.disabled_legacy_devices = Some(vec![LegacyDevice::Serial]);
+        vm_resources.update_vm_config(&aux_vm_config).unwrap();
+        assert!(!vm_resources
+            .vm_config
+            .has_legacy_device(LegacyDevice::Serial));
+        #[cfg(target_arch = "x86_64")]
+        let unsupported = LegacyDevice::Rtc;
+        #[cfg(target_arch = "aarch64")]
+        let unsupported = LegacyDevice::I8042;
+        aux_vm_config.disabled_legacy_devices = Some(vec![LegacyDevice::Serial, unsupported]);
+        assert_eq!(
+            vm_resources.update_vm_config(&aux_vm_config),
+            Err(VmConfigError::UnsupportedLegacyDevice(unsupported))
+        );
+        aux_vm_config.disabled_legacy_devices = Some(vec![]);
+
+        // Reserved memory regions must have valid, unique names, be page aligned, lie within
+        // guest memory and not overlap each other.
+        let region = |name: &str, guest_addr: u64, size: u64| ReservedMemoryRegion {
+            name: name.to_string(),
+            guest_addr: crate::arch::SYSTEM_MEM_START + crate::arch::SYSTEM_MEM_SIZE + guest_addr,
+            size,
+        };
+        aux_vm_config.reserved_memory = Some(vec![region("shmem", 0, 0x1000)]);
+        vm_resources.update_vm_config(&aux_vm_config).unwrap();
+        assert_eq!(
+            vm_resources.vm_config.reserved_memory,
+            [region("shmem", 0, 0x1000)]
+        );
+        for name in ["", "shmem@0", &"a".repeat(32)] {
+            aux_vm_config.reserved_memory = Some(vec![region(name, 0, 0x1000)]);
+            assert_eq!(
+                vm_resources.update_vm_config(&aux_vm_config),
+                Err(VmConfigError::InvalidReservedMemoryName(name.to_string()))
+            );
+        }
+        aux_vm_config.reserved_memory = Some(vec![
+            region("shmem", 0, 0x1000),
+            region("shmem", 0x1000, 0x1000),
+        ]);
+        assert_eq!(
+            vm_resources.update_vm_config(&aux_vm_config),
+            Err(VmConfigError::InvalidReservedMemoryName(
+                "shmem".to_string()
+            ))
+        );
+        for invalid in [
+            region("shmem", 0, 0),
+            region("shmem", 0x800, 0x1000),
+            region("shmem", 0, 0x800),
+            region("shmem", 0, 512 << 20),
+        ] {
+            aux_vm_config.reserved_memory = Some(vec![invalid]);
+            assert_eq!(
+                vm_resources.update_vm_config(&aux_vm_config),
+                Err(VmConfigError::InvalidReservedMemoryRegion(
+                    "shmem".to_string()
+                ))
+            );
+        }
+        aux_vm_config.reserved_memory = Some(vec![
+            region("first", 0, 0x2000),
+            region("second", 0x1000, 0x1000),
+        ]);
+        assert_eq!(
+            vm_resources.update_vm_config(&aux_vm_config),
+            Err(VmConfigError::InvalidReservedMemoryRegion(
+                "second".to_string()
+            ))
+        );
+        aux_vm_config.reserved_memory = Some(vec![]);
+
+        // Memory tiers must have valid, unique ids and non-zero sizes, and are laid out after the
+        // guest memory.
+        let tier = |tier_id: &str, size_mib: usize| MemoryTier {
+            tier_id: tier_id.to_string(),
+            path_on_host: "/dev/dax0.0".to_string(),
+            size_mib,
+        };
+        aux_vm_config.memory_tiers = Some(vec![tier("cxl0", 256), tier("cxl1", 128)]);
+        vm_resources.update_vm_config(&aux_vm_config).unwrap();
+        let layout = vm_resources.vm_config.guest_memory_layout();
+        assert_eq!(layout.len(), 3);
+        let node_size = |regions: &Vec<(GuestAddress, usize)>| {
+            regions.iter().map(|(_, size)| size).sum::<usize>()
+        };
+        assert_eq!(node_size(&layout[0]), 512 << 20);
+        assert_eq!(node_size(&layout[1]), 256 << 20);
+        assert_eq!(node_size(&layout[2]), 128 << 20);
+        assert_eq!(vm_resources.guest_memory_regions(), layout[0]);
+        for invalid in [
+            vec![tier("", 128)],
+            vec![tier("cxl0@0", 128)],
+            vec![tier("cxl0", 0)],
+            vec![tier("cxl0", 128), tier("cxl0", 128)],
+        ] {
+            aux_vm_config.memory_tiers = Some(invalid);
+            assert!(matches!(
+                vm_resources.update_vm_config(&aux_vm_config),
+                Err(VmConfigError::InvalidMemoryTier(_))
+            ));
+        }
+        aux_vm_config.memory_tiers = Some(
+            (0..=MAX_MEMORY_TIERS)
+                .map(|i| tier(&format!("cxl{i}"), 1))
+                .collect(),
+        );
+        assert_eq!(
+            vm_resources.update_vm_config(&aux_vm_config),
+            Err(VmConfigError::InvalidMemoryTier(format!(
+                "cxl{MAX_MEMORY_TIERS}"
+            )))
+        );
+        aux_vm_config.memory_tiers = Some(vec![tier("cxl0", 128)]);
+        aux_vm_config.memory_backend = Some(MemoryBackend::GuestMemfd);
+        assert_eq!(
+            vm_resources.update_vm_config(&aux_vm_config),
+            Err(VmConfigError::GuestMemfdAndMemoryTiers)
+        );
+        aux_vm_config.memory_backend = Some(MemoryBackend::Anonymous);
+        aux_vm_config.memory_tiers = Some(vec![]);
+
+        // The memory tiers can't hold the allocation tags of the Memory Tagging Extension.
+        #[cfg(target_arch = "aarch64")]
+        {
+            let cpu_template = vm_resources.vm_config.cpu_template.clone();
+            vm_resources.set_custom_cpu_template(CustomCpuTemplate {
+                mte: true,
+                ..Default::default()
+            });
+            let update = MachineConfigUpdate {
+                memory_tiers: Some(vec![tier("cxl0", 128)]),
+                ..Default::default()
+            };
+            assert_eq!(
+                vm_resources.update_vm_config(&update),
+                Err(VmConfigError::MteAndMemoryTiers)
+            );
+            vm_resources.vm_config.cpu_template = cpu_template;
+        }
+
+        // Guest memory backed by guest_memfd is incompatible with dirty page tracking, huge pages
+        // and memory ballooning.
+        aux_vm_config.memory_backend = Some(MemoryBackend::GuestMemfd);
+        aux_vm_config.track_dirty_pages = Some(true);
+        assert_eq!(
+            vm_resources.update_vm_config(&aux_vm_config),
+            Err(VmConfigError::GuestMemfdAndDirtyPages)
+        );
+        aux_vm_config.track_dirty_pages = Some(false);
+        aux_vm_config.huge_pages = Some(HugePageConfig::Hugetlbfs2M);
+        assert_eq!(
+            vm_resources.update_vm_config(&aux_vm_config),
+            Err(VmConfigError::GuestMemfdAndHugePages)
+        );
+        aux_vm_config.huge_pages = Some(HugePageConfig::None);
+        vm_resources.update_vm_config(&aux_vm_config).unwrap();
+        assert_eq!(
+            vm_resources.vm_config.memory_backend,
+            MemoryBackend::GuestMemfd
+        );
+        aux_vm_config.memory_backend = Some(MemoryBackend::Anonymous);
+        vm_resources.update_vm_config(&aux_vm_config).unwrap();
+
         // Invalid mem_size_mib.
         aux_vm_config.mem_size_mib = Some(0);
         assert_eq!(
@@ -1408,6 +1964,8 @@ mod tests {
                 amount_mib: 100,
                 deflate_on_oom: false,
                 stats_polling_interval_s: 0,
+                stats_push: None,
+                auto_target: None,
             })
             .unwrap();
         aux_vm_config.mem_size_mib = Some(90);
@@ -1420,6 +1978,14 @@ mod tests {
         aux_vm_config.mem_size_mib = Some(256);
         vm_resources.update_vm_config(&aux_vm_config).unwrap();
 
+        // Ballooning incompatible with guest_memfd backed memory.
+        aux_vm_config.memory_backend = Some(MemoryBackend::GuestMemfd);
+        assert_eq!(
+            vm_resources.update_vm_config(&aux_vm_config),
+            Err(VmConfigError::BalloonAndGuestMemfd)
+        );
+        aux_vm_config.memory_backend = Some(MemoryBackend::Anonymous);
+
         // mem_size_mib incompatible with huge pages configuration
         aux_vm_config.mem_size_mib = Some(129);
         aux_vm_config.huge_pages = Some(HugePageConfig::Hugetlbfs2M);
@@ -1444,6 +2010,8 @@ mod tests {
             amount_mib: 100,
             deflate_on_oom: false,
             stats_polling_interval_s: 0,
+            stats_push: None,
+            auto_target: None,
         };
         assert!(vm_resources.balloon.get().is_none());
         vm_resources
@@ -1465,8 +2033,17 @@ mod tests {
         vm_resources.balloon = BalloonBuilder::new();
         new_balloon_cfg.amount_mib = 256;
         vm_resources
-            .set_balloon_device(new_balloon_cfg)
+            .set_balloon_device(new_balloon_cfg.clone())
             .unwrap_err();
+
+        let mut vm_resources = default_vm_resources();
+        vm_resources.balloon = BalloonBuilder::new();
+        vm_resources.vm_config.memory_backend = MemoryBackend::GuestMemfd;
+        new_balloon_cfg.amount_mib = 100;
+        assert!(matches!(
+            vm_resources.set_balloon_device(new_balloon_cfg),
+            Err(BalloonConfigError::GuestMemfd)
+        ));
     }
 
     #[test]
@@ -1509,14 +2086,222 @@ mod tests {
         assert_eq!(actual_entropy_cfg, entropy_device_cfg);
     }
 
+    #[test]
+    fn test_set_shared_memory() {
+        let mut vm_resources = default_vm_resources();
+        let file = TempFile::new().unwrap();
+        file.as_file().set_len(0x1000).unwrap();
+        let shared_memory_cfg = SharedMemoryConfig {
+            segment_id: "heap".to_string(),
+            path_on_host: file.as_path().to_str().unwrap().to_string(),
+            guest_addr: 0x1_0000_0000,
+        };
+
+        vm_resources
+            .set_shared_memory(shared_memory_cfg.clone())
+            .unwrap();
+        assert_eq!(
+            VmmConfig::from(&vm_resources).shared_memory,
+            [shared_memory_cfg.clone()]
+        );
+
+        let invalid_cfg = SharedMemoryConfig {
+            segment_id: "other".to_string(),
+            ..shared_memory_cfg
+        };
+        vm_resources.set_shared_memory(invalid_cfg).unwrap_err();
+        assert_eq!(vm_resources.shared_memory.configs().len(), 1);
+    }
+
+    #[test]
+    fn test_set_shared_rate_limiter() {
+        let mut vm_resources = default_vm_resources();
+        let mut rate_limiter_cfg = SharedRateLimiterConfig {
+            id: "test_resources".to_string(),
+            bandwidth: None,
+            ops: Some(TokenBucketConfig {
+                size: 1000,
+                one_time_burst: None,
+                refill_time: 1000,
+            }),
+        };
+
+        vm_resources
+            .set_shared_rate_limiter(rate_limiter_cfg.clone())
+            .unwrap();
+        // Updating the shared rate limiter replaces its configuration.
+        rate_limiter_cfg.ops = None;
+        vm_resources
+            .set_shared_rate_limiter(rate_limiter_cfg.clone())
+            .unwrap();
+        assert_eq!(
+            VmmConfig::from(&vm_resources).shared_rate_limiters,
+            [rate_limiter_cfg.clone()]
+        );
+
+        let invalid_cfg = SharedRateLimiterConfig {
+            id: "invalid-id".to_string(),
+            ..rate_limiter_cfg
+        };
+        vm_resources
+            .set_shared_rate_limiter(invalid_cfg)
+            .unwrap_err();
+        assert_eq!(vm_resources.shared_rate_limiters.len(), 1);
+    }
+
+    #[test]
+    fn test_set_serial_config() {
+        let mut vm_resources = default_vm_resources();
+        let serial_cfg = SerialConfig {
+            mode: SerialMode::UnixSocket,
+            path: Some("/tmp/console.sock".to_string()),
+            log_buffer_size: Some(4096),
+            replay_log: true,
+            ..Default::default()
+        };
+        vm_resources.set_serial_config(serial_cfg.clone()).unwrap();
+        assert_eq!(vm_resources.serial, serial_cfg);
+        assert!(vm_resources.serial_log.is_some());
+        assert_eq!(VmmConfig::from(&vm_resources).serial, serial_cfg);
+
+        let invalid_cfg = SerialConfig {
+            mode: SerialMode::Pty,
+            ..Default::default()
+        };
+        assert_eq!(
+            vm_resources.set_serial_config(invalid_cfg),
+            Err(SerialConfigError::MissingPath)
+        );
+        assert_eq!(vm_resources.serial, serial_cfg);
+    }
+
+    #[test]
+    fn test_set_tpm_config() {
+        let mut vm_resources = default_vm_resources();
+        let tpm_cfg = TpmConfig {
+            socket: PathBuf::from("/tmp/swtpm.sock"),
+        };
+        vm_resources.set_tpm_config(tpm_cfg.clone());
+        assert_eq!(vm_resources.tpm, Some(tpm_cfg));
+        assert_eq!(VmmConfig::from(&vm_resources).tpm, vm_resources.tpm);
+    }
+
+    #[test]
+    fn test_set_pvpanic_config() {
+        let mut vm_resources = default_vm_resources();
+        let pvpanic_cfg = PvPanicConfig {
+            snapshot: Some(PanicSnapshotConfig {
+                snapshot_path: PathBuf::from("/tmp/panic.snap"),
+                mem_file_path: PathBuf::from("/tmp/panic.mem"),
+            }),
+        };
+        vm_resources.set_pvpanic_config(pvpanic_cfg.clone());
+        assert_eq!(vm_resources.pvpanic, Some(pvpanic_cfg));
+        assert_eq!(VmmConfig::from(&vm_resources).pvpanic, vm_resources.pvpanic);
+    }
+
+    #[test]
+    fn test_set_cpu_limiter() {
+        let mut vm_resources = default_vm_resources();
+        let cpu_limiter_cfg = CpuLimiterConfig {
+            millicpus: 1500,
+            burst_ms: 10,
+        };
+        assert_eq!(
+            vm_resources.update_cpu_limiter(cpu_limiter_cfg.clone()),
+            Err(CpuLimiterConfigError::NotConfigured)
+        );
+        assert_eq!(
+            vm_resources.set_cpu_limiter(CpuLimiterConfig {
+                millicpus: 0,
+                burst_ms: 0,
+            }),
+            Err(CpuLimiterConfigError::ZeroMillicpus)
+        );
+        assert_eq!(vm_resources.cpu_limiter, None);
+
+        vm_resources.set_cpu_limiter(cpu_limiter_cfg).unwrap();
+        let cpu_limiter_cfg = CpuLimiterConfig {
+            millicpus: 500,
+            burst_ms: 0,
+        };
+        vm_resources
+            .update_cpu_limiter(cpu_limiter_cfg.clone())
+            .unwrap();
+        assert_eq!(vm_resources.cpu_limiter, Some(cpu_limiter_cfg));
+        assert_eq!(
+            VmmConfig::from(&vm_resources).cpu_limiter,
+            vm_resources.cpu_limiter
+        );
+    }
+
+    #[test]
+    fn test_set_lifecycle_hooks() {
+        let mut vm_resources = default_vm_resources();
+        let mut hooks_cfg = LifecycleHooksConfig {
+            hooks: vec![LifecycleHookConfig {
+                event: LifecycleEvent::PreSnapshot,
+                command: Some(vec!["/usr/bin/sync".to_string()]),
+                socket_path: None,
+                timeout_ms: 1000,
+            }],
+        };
+        vm_resources.set_lifecycle_hooks(hooks_cfg.clone()).unwrap();
+        assert_eq!(vm_resources.lifecycle_hooks, hooks_cfg);
+        assert_eq!(VmmConfig::from(&vm_resources).lifecycle_hooks, hooks_cfg);
+
+        hooks_cfg.hooks[0].timeout_ms = 0;
+        assert_eq!(
+            vm_resources.set_lifecycle_hooks(hooks_cfg),
+            Err(LifecycleHooksConfigError::InvalidTimeout(0))
+        );
+        assert_eq!(vm_resources.lifecycle_hooks.hooks[0].timeout_ms, 1000);
+    }
+
+    #[test]
+    fn test_set_smbios_config() {
+        let mut vm_resources = default_vm_resources();
+        let smbios_cfg = SmbiosConfig {
+            system_serial_number: Some("serial".to_string()),
+            ..Default::default()
+        };
+
+        #[cfg(target_arch = "x86_64")]
+        {
+            vm_resources.set_smbios_config(smbios_cfg.clone()).unwrap();
+            assert_eq!(vm_resources.smbios, Some(smbios_cfg));
+            assert_eq!(VmmConfig::from(&vm_resources).smbios, vm_resources.smbios);
+
+            let invalid_cfg = SmbiosConfig {
+                system_uuid: Some("invalid".to_string()),
+                ..Default::default()
+            };
+            vm_resources.set_smbios_config(invalid_cfg).unwrap_err();
+            assert!(vm_resources.smbios.is_some());
+        }
+        #[cfg(target_arch = "aarch64")]
+        {
+            assert_eq!(
+                vm_resources.set_smbios_config(smbios_cfg),
+                Err(SmbiosConfigError::Unsupported)
+            );
+            assert!(vm_resources.smbios.is_none());
+        }
+    }
+
     #[test]
     fn test_set_boot_source() {
         let tmp_file = TempFile::new().unwrap();
         let cmdline = "reboot=k panic=1 pci=off nomodule 8250.nr_uarts=0";
         let expected_boot_cfg = BootSourceConfig {
             kernel_image_path: String::from(tmp_file.as_path().to_str().unwrap()),
-            initrd_path: Some(String::from(tmp_file.as_path().to_str().unwrap())),
-            boot_args: Some(cmdline.to_string()),
+            initrd_path: Some(String::from(tmp_file.as_path().to_str().unwrap()).into()),
+            boot_args: Some(cmdline.to_string().into()),
+            kernel_image_fd: None,
+            initrd_fd: None,
+            firmware_path: None,
+            kernel_digest: None,
+            initrd_digest: None,
         };
 
         let mut vm_resources = default_vm_resources();
@@ -1531,13 +2316,9 @@ mod tests {
                 .as_bytes_with_nul(),
             [cmdline.as_bytes(), b"\0"].concat()
         );
-        assert_ne!(
-            boot_builder.kernel_file.metadata().unwrap().st_ino(),
-            tmp_ino
-        );
         assert_ne!(
             boot_builder
-                .initrd_file
+                .kernel_file
                 .as_ref()
                 .unwrap()
                 .metadata()
@@ -1545,6 +2326,10 @@ mod tests {
                 .st_ino(),
             tmp_ino
         );
+        assert_ne!(
+            boot_builder.initrd_files[0].metadata().unwrap().st_ino(),
+            tmp_ino
+        );
 
         vm_resources.build_boot_source(expected_boot_cfg).unwrap();
         let boot_source_builder = vm_resources.boot_source.builder.unwrap();
@@ -1556,13 +2341,9 @@ mod tests {
                 .as_bytes_with_nul(),
             [cmdline.as_bytes(), b"\0"].concat()
         );
-        assert_eq!(
-            boot_source_builder.kernel_file.metadata().unwrap().st_ino(),
-            tmp_ino
-        );
         assert_eq!(
             boot_source_builder
-                .initrd_file
+                .kernel_file
                 .as_ref()
                 .unwrap()
                 .metadata()
@@ -1570,6 +2351,13 @@ mod tests {
                 .st_ino(),
             tmp_ino
         );
+        assert_eq!(
+            boot_source_builder.initrd_files[0]
+                .metadata()
+                .unwrap()
+                .st_ino(),
+            tmp_ino
+        );
     }
 
     #[test]
@@ -1596,6 +2384,25 @@ mod tests {
         assert_eq!(actual_vsock_cfg.lock().unwrap().id(), VSOCK_DEV_ID);
     }
 
+    #[test]
+    fn test_connects_over_tcp() {
+        let mut vm_resources = default_vm_resources();
+        let mut tmp_sock_file = TempFile::new().unwrap();
+        tmp_sock_file.remove().unwrap();
+        vm_resources
+            .set_vsock_device(default_config(&tmp_sock_file))
+            .unwrap();
+        assert!(!vm_resources.connects_over_tcp());
+
+        let mut vsock_cfg = default_config(&tmp_sock_file);
+        vsock_cfg.tcp_forwards = vec![VsockTcpForwardConfig {
+            port: 8080,
+            addr: "127.0.0.1:8080".parse().unwrap(),
+        }];
+        vm_resources.set_vsock_device(vsock_cfg).unwrap();
+        assert!(vm_resources.connects_over_tcp());
+    }
+
     #[test]
     fn test_set_net_device() {
         let mut vm_resources = default_vm_resources();
@@ -4,7 +4,7 @@
 use std::fmt::{self, Debug};
 use std::sync::{Arc, Mutex, MutexGuard};
 
-use seccompiler::BpfThreadMap;
+use seccompiler::{BpfProgram, BpfThreadMap};
 use serde_json::Value;
 use utils::time::{get_time_us, ClockType};
 
@@ -12,29 +12,55 @@ use super::builder::build_and_boot_microvm;
 use super::persist::{create_snapshot, restore_from_snapshot};
 use super::resources::VmResources;
 use super::{Vmm, VmmError};
+use crate::background_snapshot::BackgroundSnapshotStatus;
+use crate::boot_timings::{BootTimingsInfo, BOOT_TIMINGS};
 use crate::builder::StartMicrovmError;
-use crate::cpu_config::templates::{CustomCpuTemplate, GuestConfigError};
+use crate::cpu_config::templates::{CpuTemplateReport, CustomCpuTemplate, GuestConfigError};
+use crate::devices::legacy::serial::SerialLogContent;
+use crate::devices::virtio::net::flows::NetFlows;
 use crate::logger::{info, warn, LoggerConfig, *};
-use crate::mmds::data_store::{self, Mmds};
-use crate::persist::{CreateSnapshotError, RestoreFromSnapshotError, VmInfo};
+use crate::mmds::data_store::{self, Mmds, MmdsSession};
+use crate::persist::{
+    check_snapshot_compatibility, CheckSnapshotError, CreateSnapshotError,
+    RestoreFromSnapshotError, SnapshotCompatReport, VmInfo,
+};
 use crate::resources::VmmConfig;
+use crate::state_dir::STATE_DIR;
 use crate::vmm_config::balloon::{
     BalloonConfigError, BalloonDeviceConfig, BalloonStats, BalloonUpdateConfig,
     BalloonUpdateStatsConfig,
 };
 use crate::vmm_config::boot_source::{BootSourceConfig, BootSourceConfigError};
+use crate::vmm_config::cpu_limiter::{CpuLimiterConfig, CpuLimiterConfigError};
 use crate::vmm_config::drive::{BlockDeviceConfig, BlockDeviceUpdateConfig, DriveError};
 use crate::vmm_config::entropy::{EntropyDeviceConfig, EntropyDeviceError};
-use crate::vmm_config::instance_info::InstanceInfo;
+use crate::vmm_config::instance_info::{InstanceInfo, ProcessInfo, VmState, PROCESS_INFO};
+use crate::vmm_config::lifecycle_hooks::{LifecycleHooksConfig, LifecycleHooksConfigError};
 use crate::vmm_config::machine_config::{MachineConfig, MachineConfigUpdate, VmConfigError};
+use crate::vmm_config::memory_target::{MemoryTargetConfig, MemoryTargetError, MemoryTargetStatus};
 use crate::vmm_config::metrics::{MetricsConfig, MetricsConfigError};
-use crate::vmm_config::mmds::{MmdsConfig, MmdsConfigError};
+use crate::vmm_config::mmds::{MmdsConfig, MmdsConfigError, MmdsSessionRevocation};
 use crate::vmm_config::net::{
-    NetworkInterfaceConfig, NetworkInterfaceError, NetworkInterfaceUpdateConfig,
+    NetworkCaptureConfig, NetworkInterfaceConfig, NetworkInterfaceError,
+    NetworkInterfaceUpdateConfig,
+};
+use crate::vmm_config::pvpanic::PvPanicConfig;
+use crate::vmm_config::rate_limiters::{
+    shared_rate_limiter_statuses, SharedRateLimiterConfig, SharedRateLimiterConfigError,
+    SharedRateLimiterStatus,
 };
-use crate::vmm_config::snapshot::{CreateSnapshotParams, LoadSnapshotParams, SnapshotType};
+use crate::vmm_config::seccomp::{SeccompFiltersConfig, SeccompFiltersError};
+use crate::vmm_config::serial::{SerialConfig, SerialConfigError};
+use crate::vmm_config::shared_memory::{SharedMemoryConfig, SharedMemoryConfigError};
+use crate::vmm_config::shutdown::{ShutdownConfig, ShutdownError};
+use crate::vmm_config::smbios::{SmbiosConfig, SmbiosConfigError};
+use crate::vmm_config::snapshot::{
+    CheckSnapshotParams, CreateSnapshotParams, LoadSnapshotParams, SnapshotType,
+};
+use crate::vmm_config::tpm::TpmConfig;
 use crate::vmm_config::vsock::{VsockConfigError, VsockDeviceConfig};
-use crate::vmm_config::{self, RateLimiterUpdate};
+use crate::vmm_config::{self, DeviceQueuesState, RateLimiterUpdate};
+use crate::vstate::memory::MemorySlotsUsage;
 use crate::EventManager;
 
 /// This enum represents the public interface of the VMM. Each action contains various
@@ -50,6 +76,9 @@ pub enum VmmAction {
     /// Configure the metrics using as input the `MetricsConfig`. This action can only be called
     /// before the microVM has booted.
     ConfigureMetrics(MetricsConfig),
+    /// Check whether the snapshot described by `CheckSnapshotParams` can be restored on the host,
+    /// without restoring it. This action can only be called before the microVM has booted.
+    CheckSnapshotCompatibility(CheckSnapshotParams),
     /// Create a snapshot using as input the `CreateSnapshotParams`. This action can only be called
     /// after the microVM has booted and only when the microVM is in `Paused` state.
     CreateSnapshot(CreateSnapshotParams),
@@ -57,16 +86,37 @@ pub enum VmmAction {
     GetBalloonConfig,
     /// Get the ballon device latest statistics.
     GetBalloonStats,
+    /// Get the timestamps of the boot phases of the microVM.
+    GetBootTimings,
     /// Get complete microVM configuration in JSON format.
     GetFullVmConfig,
     /// Get MMDS contents.
     GetMMDS,
+    /// Get the usage of the KVM memory slots, after microVM start.
+    GetMemorySlots,
+    /// Get the host process details of the microVM.
+    GetProcessInfo,
+    /// Get the progress of the memory available to the guest towards its target size, after
+    /// microVM start.
+    GetMemoryTarget,
+    /// Get the MMDS key/value pairs written by the guest.
+    GetMmdsGuestData,
+    /// Get the outstanding MMDS sessions opened by the guest.
+    GetMmdsSessions,
     /// Get the machine configuration of the microVM.
     GetVmMachineConfig,
     /// Get microVM instance information.
     GetVmInstanceInfo,
     /// Get microVM version.
     GetVmmVersion,
+    /// Get the per-flow traffic accounting of a network interface, after microVM start.
+    GetNetworkFlows(String),
+    /// Get the most recent output of the serial console.
+    GetSerialLog,
+    /// Get the current state of the rate limiters shared by several devices.
+    GetSharedRateLimiters,
+    /// Get the progress of the last background snapshot, after microVM start.
+    GetSnapshotStatus,
     /// Flush the metrics. This action can only be called after the logger has been configured.
     FlushMetrics,
     /// Add a new block device or update one that already exists using the `BlockDeviceConfig` as
@@ -76,6 +126,17 @@ pub enum VmmAction {
     /// `NetworkInterfaceConfig` as input. This action can only be called before the microVM has
     /// booted.
     InsertNetworkDevice(NetworkInterfaceConfig),
+    /// Add a new read-only memory segment shared with other microVMs or update one that already
+    /// exists using the `SharedMemoryConfig` as input. This action can only be called before the
+    /// microVM has booted.
+    InsertSharedMemory(SharedMemoryConfig),
+    /// Add a new rate limiter shared by several devices or update one that already exists using
+    /// the `SharedRateLimiterConfig` as input. Updating a shared rate limiter after the microVM
+    /// has booted applies its new buckets to the devices referencing it.
+    InsertSharedRateLimiter(SharedRateLimiterConfig),
+    /// Install additional seccomp filters on the threads of the microVM, on top of their current
+    /// filters, after microVM start.
+    InstallSeccompFilters(SeccompFiltersConfig),
     /// Load the microVM state using as input the `LoadSnapshotParams`. This action can only be
     /// called before the microVM has booted. If this action is successful, the loaded microVM will
     /// be in `Paused` state. Should change this state to `Resumed` for the microVM to run.
@@ -90,12 +151,32 @@ pub enum VmmAction {
     PutCpuConfiguration(CustomCpuTemplate),
     /// Resume the guest, by resuming the microVM VCPUs.
     Resume,
+    /// Revoke an outstanding MMDS session, invalidating its token.
+    RevokeMmdsSession(MmdsSessionRevocation),
     /// Set the balloon device or update the one that already exists using the
     /// `BalloonDeviceConfig` as input. This action can only be called before the microVM
     /// has booted.
     SetBalloonDevice(BalloonDeviceConfig),
+    /// Set the CPU allocation the vCPUs are throttled to. This action can only be called before
+    /// the microVM has booted or has been loaded from a snapshot.
+    SetCpuLimiter(CpuLimiterConfig),
+    /// Set the hooks run at the lifecycle events of the microVM. This action can only be called
+    /// before the microVM has booted or has been loaded from a snapshot.
+    SetLifecycleHooks(LifecycleHooksConfig),
     /// Set the MMDS configuration.
     SetMmdsConfiguration(MmdsConfig),
+    /// Set the host side to which the serial console is attached. This action can only be called
+    /// before the microVM has booted or has been loaded from a snapshot.
+    SetSerialConfiguration(SerialConfig),
+    /// Set the SMBIOS configuration exposed to the guest. This action can only be called before
+    /// the microVM has booted.
+    SetSmbiosConfiguration(SmbiosConfig),
+    /// Set the TPM device, backed by an swtpm TPM emulator. This action can only be called before
+    /// the microVM has booted.
+    SetTpmDevice(TpmConfig),
+    /// Set the pvpanic device, with which the guest reports its kernel panics. This action can
+    /// only be called before the microVM has booted.
+    SetPvPanicDevice(PvPanicConfig),
     /// Set the vsock device or update the one that already exists using the
     /// `VsockDeviceConfig` as input. This action can only be called before the microVM has
     /// booted.
@@ -109,18 +190,32 @@ pub enum VmmAction {
     /// driver is listening on the guest end, this can be used to shut down the microVM gracefully.
     #[cfg(target_arch = "x86_64")]
     SendCtrlAltDel,
+    /// Press the ACPI power button of the microVM, so that the guest shuts down gracefully, and
+    /// stop the microVM if the guest did not shut down within the optional timeout.
+    SendShutdown(ShutdownConfig),
     /// Update the balloon size, after microVM start.
     UpdateBalloon(BalloonUpdateConfig),
     /// Update the balloon statistics polling interval, after microVM start.
     UpdateBalloonStatistics(BalloonUpdateStatsConfig),
     /// Update existing block device properties such as `path_on_host` or `rate_limiter`.
     UpdateBlockDevice(BlockDeviceUpdateConfig),
+    /// Update the CPU allocation the vCPUs are throttled to, after microVM start.
+    UpdateCpuLimiter(CpuLimiterConfig),
+    /// Resize the memory available to the guest through the available mechanism, after microVM
+    /// start.
+    UpdateMemoryTarget(MemoryTargetConfig),
+    /// Start or stop the packet capture of the network interface with the given id, after microVM
+    /// start.
+    UpdateNetworkCapture(String, NetworkCaptureConfig),
     /// Update a network interface, after microVM start. Currently, the only updatable properties
     /// are the RX and TX rate limiters.
     UpdateNetworkInterface(NetworkInterfaceUpdateConfig),
     /// Update the microVM configuration (memory & vcpu) using `VmUpdateConfig` as input. This
     /// action can only be called before the microVM has booted.
     UpdateVmConfiguration(MachineConfigUpdate),
+    /// Validate a custom CPU template against the host without applying it. This action can only
+    /// be called before the microVM has booted.
+    ValidateCpuConfiguration(CustomCpuTemplate),
 }
 
 /// Wrapper for all errors associated with VMM actions.
@@ -130,22 +225,30 @@ pub enum VmmActionError {
     BalloonConfig(#[from] BalloonConfigError),
     /// Boot source error: {0}
     BootSource(#[from] BootSourceConfigError),
+    /// Check snapshot error: {0}
+    CheckSnapshot(#[from] CheckSnapshotError),
     /// Create snapshot error: {0}
     CreateSnapshot(#[from] CreateSnapshotError),
     /// Configure CPU error: {0}
     ConfigureCpu(#[from] GuestConfigError),
+    /// CPU limiter config error: {0}
+    CpuLimiter(#[from] CpuLimiterConfigError),
     /// Drive config error: {0}
     DriveConfig(#[from] DriveError),
     /// Entropy device error: {0}
     EntropyDevice(#[from] EntropyDeviceError),
     /// Internal VMM error: {0}
     InternalVmm(#[from] VmmError),
+    /// Lifecycle hooks config error: {0}
+    LifecycleHooks(#[from] LifecycleHooksConfigError),
     /// Load snapshot error: {0}
     LoadSnapshot(#[from] LoadSnapshotError),
     /// Logger error: {0}
     Logger(#[from] crate::logger::LoggerUpdateError),
     /// Machine config error: {0}
     MachineConfig(#[from] VmConfigError),
+    /// Memory target error: {0}
+    MemoryTarget(#[from] MemoryTargetError),
     /// Metrics error: {0}
     Metrics(#[from] MetricsConfigError),
     #[from(ignore)]
@@ -158,6 +261,20 @@ pub enum VmmActionError {
     MmdsLimitExceeded(data_store::MmdsDatastoreError),
     /// Network config error: {0}
     NetworkConfig(#[from] NetworkInterfaceError),
+    /// Seccomp filters error: {0}
+    SeccompFilters(#[from] SeccompFiltersError),
+    /// Serial console config error: {0}
+    SerialConfig(#[from] SerialConfigError),
+    /// Shared memory config error: {0}
+    SharedMemoryConfig(#[from] SharedMemoryConfigError),
+    /// Shared rate limiter config error: {0}
+    SharedRateLimiterConfig(#[from] SharedRateLimiterConfigError),
+    /// Graceful shutdown error: {0}
+    Shutdown(#[from] ShutdownError),
+    /// The serial console output is not captured.
+    SerialLogDisabled,
+    /// SMBIOS config error: {0}
+    SmbiosConfig(#[from] SmbiosConfigError),
     /// The requested operation is not supported: {0}
     NotSupported(String),
     /// The requested operation is not supported after starting the microVM.
@@ -175,24 +292,58 @@ pub enum VmmActionError {
 #[allow(clippy::large_enum_variant)]
 #[derive(Debug, PartialEq, Eq)]
 pub enum VmmData {
+    /// The seccomp filter for the API thread to install on itself, which is empty if its filters
+    /// are left unchanged.
+    ApiSeccompFilter(Arc<BpfProgram>),
     /// The balloon device configuration.
     BalloonConfig(BalloonDeviceConfig),
+    /// The result of the validation of a custom CPU template.
+    CpuTemplateReport(CpuTemplateReport),
+    /// The result of the check of a snapshot against the host.
+    SnapshotCompatReport(SnapshotCompatReport),
     /// The latest balloon device statistics.
     BalloonStats(BalloonStats),
+    /// The timestamps of the boot phases of the microVM.
+    BootTimings(BootTimingsInfo),
     /// No data is sent on the channel.
     Empty,
     /// The complete microVM configuration in JSON format.
     FullVmConfig(VmmConfig),
     /// The microVM configuration represented by `VmConfig`.
     MachineConfiguration(MachineConfig),
+    /// The usage of the KVM memory slots.
+    MemorySlots(MemorySlotsUsage),
+    /// The progress of the memory available to the guest towards its target size.
+    MemoryTarget(MemoryTargetStatus),
+    /// The host process details of the microVM.
+    ProcessInfo(ProcessInfo),
+    /// The outstanding MMDS sessions.
+    MmdsSessions(Vec<MmdsSession>),
     /// Mmds contents.
     MmdsValue(serde_json::Value),
+    /// The per-flow traffic accounting of a network interface.
+    NetworkFlows(NetFlows),
+    /// The most recent output of the serial console.
+    SerialLog(SerialLogContent),
+    /// The current state of the rate limiters shared by several devices.
+    SharedRateLimiters(Vec<SharedRateLimiterStatus>),
+    /// The progress of the last background snapshot.
+    BackgroundSnapshotStatus(BackgroundSnapshotStatus),
     /// The microVM instance information.
     InstanceInformation(InstanceInfo),
     /// The microVM version.
     VmmVersion(String),
 }
 
+/// Returns the captured output of the serial console, before or after microVM start.
+fn get_serial_log(vm_resources: &VmResources) -> Result<VmmData, VmmActionError> {
+    vm_resources
+        .serial_log
+        .as_ref()
+        .map(|log| VmmData::SerialLog(log.content()))
+        .ok_or(VmmActionError::SerialLogDisabled)
+}
+
 /// Trait used for deduplicating the MMDS request handling across the two ApiControllers.
 /// The methods get a mutable reference to self because the methods should initialise the data
 /// store with the defaults if it's not already initialised.
@@ -203,6 +354,24 @@ trait MmdsRequestHandler {
         Ok(VmmData::MmdsValue(self.mmds().data_store_value()))
     }
 
+    fn get_mmds_guest_data(&mut self) -> Result<VmmData, VmmActionError> {
+        Ok(VmmData::MmdsValue(self.mmds().guest_data_value()))
+    }
+
+    fn get_mmds_sessions(&mut self) -> Result<VmmData, VmmActionError> {
+        Ok(VmmData::MmdsSessions(self.mmds().sessions()))
+    }
+
+    fn revoke_mmds_session(
+        &mut self,
+        revocation: MmdsSessionRevocation,
+    ) -> Result<VmmData, VmmActionError> {
+        self.mmds()
+            .revoke_session(&revocation.session_id)
+            .map(|()| VmmData::Empty)
+            .map_err(VmmActionError::Mmds)
+    }
+
     fn patch_mmds(&mut self, value: serde_json::Value) -> Result<VmmData, VmmActionError> {
         self.mmds()
             .patch_data(value)
@@ -280,8 +449,30 @@ pub enum LoadSnapshotError {
     ResumeMicrovm(#[from] VmmError),
 }
 
-/// Shorthand type for a request containing a boxed VmmAction.
-pub type ApiRequest = Box<VmmAction>;
+/// A request containing a boxed VmmAction, along with the time at which it was queued.
+#[derive(Debug)]
+pub struct ApiRequest {
+    action: Box<VmmAction>,
+    queued_at_us: u64,
+}
+
+impl ApiRequest {
+    /// Creates a request for `action`, queued now.
+    pub fn new(action: Box<VmmAction>) -> Self {
+        ApiRequest {
+            action,
+            queued_at_us: get_time_us(ClockType::Monotonic),
+        }
+    }
+
+    /// Takes the action out of the request, recording how long it waited in the queue.
+    pub fn dequeue(self) -> VmmAction {
+        let wait_us = get_time_us(ClockType::Monotonic).saturating_sub(self.queued_at_us);
+        METRICS.vmm.api_action_queue_wait_agg.record(wait_us);
+        *self.action
+    }
+}
+
 /// Shorthand type for a response containing a boxed Result.
 pub type ApiResponse = Box<std::result::Result<VmmData, VmmActionError>>;
 
@@ -372,7 +563,7 @@ impl<'a> PrebootApiController<'a> {
                 .expect("VMM: Failed to read the API event_fd");
 
             // Process the request.
-            let res = preboot_controller.handle_preboot_request(*req);
+            let res = preboot_controller.handle_preboot_request(req.dequeue());
 
             // Send back the response.
             to_api.send(Box::new(res)).expect("one-shot channel closed");
@@ -396,7 +587,11 @@ impl<'a> PrebootApiController<'a> {
     ) -> Result<VmmData, VmmActionError> {
         use self::VmmAction::*;
 
-        match request {
+        METRICS.vmm.api_actions.inc();
+        let _metric = METRICS.vmm.api_action_handling_agg.record_latency_metrics();
+        let recorded = STATE_DIR.start_action(&request);
+
+        let result = match request {
             // Supported operations allowed pre-boot.
             ConfigureBootSource(config) => self.set_boot_source(config),
             ConfigureLogger(logger_cfg) => crate::logger::LOGGER
@@ -407,6 +602,7 @@ impl<'a> PrebootApiController<'a> {
                 .map(|()| VmmData::Empty)
                 .map_err(VmmActionError::Metrics),
             GetBalloonConfig => self.balloon_config(),
+            GetBootTimings => Ok(VmmData::BootTimings(BOOT_TIMINGS.info())),
             GetFullVmConfig => {
                 warn!(
                     "If the VM was restored from snapshot, boot-source, machine-config.smt, and \
@@ -415,6 +611,18 @@ impl<'a> PrebootApiController<'a> {
                 Ok(VmmData::FullVmConfig((&*self.vm_resources).into()))
             }
             GetMMDS => self.get_mmds(),
+            GetMmdsGuestData => self.get_mmds_guest_data(),
+            GetMmdsSessions => self.get_mmds_sessions(),
+            GetProcessInfo => Ok(VmmData::ProcessInfo(
+                PROCESS_INFO.get().cloned().unwrap_or_default(),
+            )),
+            GetSerialLog => get_serial_log(self.vm_resources),
+            GetSharedRateLimiters => {
+                Ok(VmmData::SharedRateLimiters(shared_rate_limiter_statuses()))
+            }
+            CheckSnapshotCompatibility(params) => check_snapshot_compatibility(&params)
+                .map(VmmData::SnapshotCompatReport)
+                .map_err(VmmActionError::CheckSnapshot),
             GetVmMachineConfig => Ok(VmmData::MachineConfiguration(MachineConfig::from(
                 &self.vm_resources.vm_config,
             ))),
@@ -422,6 +630,8 @@ impl<'a> PrebootApiController<'a> {
             GetVmmVersion => Ok(VmmData::VmmVersion(self.instance_info.vmm_version.clone())),
             InsertBlockDevice(config) => self.insert_block_device(config),
             InsertNetworkDevice(config) => self.insert_net_device(config),
+            InsertSharedMemory(config) => self.insert_shared_memory(config),
+            InsertSharedRateLimiter(config) => self.insert_shared_rate_limiter(config),
             LoadSnapshot(config) => self
                 .load_snapshot(&config)
                 .map_err(VmmActionError::LoadSnapshot),
@@ -430,11 +640,21 @@ impl<'a> PrebootApiController<'a> {
                 self.set_custom_cpu_template(custom_cpu_template)
             }
             PutMMDS(value) => self.put_mmds(value),
+            RevokeMmdsSession(revocation) => self.revoke_mmds_session(revocation),
             SetBalloonDevice(config) => self.set_balloon_device(config),
+            SetCpuLimiter(config) => self.set_cpu_limiter(config),
+            SetLifecycleHooks(config) => self.set_lifecycle_hooks(config),
             SetVsockDevice(config) => self.set_vsock_device(config),
             SetMmdsConfiguration(config) => self.set_mmds_config(config),
+            SetSerialConfiguration(config) => self.set_serial_config(config),
+            SetSmbiosConfiguration(config) => self.set_smbios_config(config),
+            SetTpmDevice(config) => self.set_tpm_device(config),
+            SetPvPanicDevice(config) => self.set_pvpanic_device(config),
             StartMicroVm => self.start_microvm(),
             UpdateVmConfiguration(config) => self.update_vm_config(config),
+            ValidateCpuConfiguration(custom_cpu_template) => {
+                self.validate_custom_cpu_template(&custom_cpu_template)
+            }
             SetEntropyDevice(config) => self.set_entropy_device(config),
             // Operations not allowed pre-boot.
             CreateSnapshot(_)
@@ -442,13 +662,34 @@ impl<'a> PrebootApiController<'a> {
             | Pause
             | Resume
             | GetBalloonStats
+            | GetMemorySlots
+            | GetMemoryTarget
+            | GetNetworkFlows(_)
+            | GetSnapshotStatus
+            | InstallSeccompFilters(_)
+            | SendShutdown(_)
             | UpdateBalloon(_)
             | UpdateBalloonStatistics(_)
             | UpdateBlockDevice(_)
+            | UpdateCpuLimiter(_)
+            | UpdateMemoryTarget(_)
+            | UpdateNetworkCapture(..)
             | UpdateNetworkInterface(_) => Err(VmmActionError::OperationNotSupportedPreBoot),
             #[cfg(target_arch = "x86_64")]
             SendCtrlAltDel => Err(VmmActionError::OperationNotSupportedPreBoot),
+        };
+
+        if recorded {
+            let vm_state = self.built_vmm.as_ref().map_or(VmState::NotStarted, |vmm| {
+                vmm.lock().expect("Poisoned lock").instance_info().state
+            });
+            STATE_DIR.finish_action(
+                result.as_ref().err().map(ToString::to_string),
+                &vm_state,
+                self.vm_resources,
+            );
         }
+        result
     }
 
     fn balloon_config(&mut self) -> Result<VmmData, VmmActionError> {
@@ -478,6 +719,21 @@ impl<'a> PrebootApiController<'a> {
             .map_err(VmmActionError::NetworkConfig)
     }
 
+    fn insert_shared_memory(&mut self, cfg: SharedMemoryConfig) -> Result<VmmData, VmmActionError> {
+        self.boot_path = true;
+        self.vm_resources.set_shared_memory(cfg)?;
+        Ok(VmmData::Empty)
+    }
+
+    fn insert_shared_rate_limiter(
+        &mut self,
+        cfg: SharedRateLimiterConfig,
+    ) -> Result<VmmData, VmmActionError> {
+        self.boot_path = true;
+        self.vm_resources.set_shared_rate_limiter(cfg)?;
+        Ok(VmmData::Empty)
+    }
+
     fn set_balloon_device(&mut self, cfg: BalloonDeviceConfig) -> Result<VmmData, VmmActionError> {
         self.boot_path = true;
         self.vm_resources
@@ -502,6 +758,45 @@ impl<'a> PrebootApiController<'a> {
             .map_err(VmmActionError::MmdsConfig)
     }
 
+    // The serial console is also attached when loading a snapshot, so this does not set the boot
+    // path.
+    fn set_serial_config(&mut self, cfg: SerialConfig) -> Result<VmmData, VmmActionError> {
+        self.vm_resources.set_serial_config(cfg)?;
+        Ok(VmmData::Empty)
+    }
+
+    // The hooks are also run when loading a snapshot, so this does not set the boot path.
+    fn set_cpu_limiter(&mut self, cfg: CpuLimiterConfig) -> Result<VmmData, VmmActionError> {
+        self.vm_resources.set_cpu_limiter(cfg)?;
+        Ok(VmmData::Empty)
+    }
+
+    fn set_lifecycle_hooks(
+        &mut self,
+        cfg: LifecycleHooksConfig,
+    ) -> Result<VmmData, VmmActionError> {
+        self.vm_resources.set_lifecycle_hooks(cfg)?;
+        Ok(VmmData::Empty)
+    }
+
+    fn set_smbios_config(&mut self, cfg: SmbiosConfig) -> Result<VmmData, VmmActionError> {
+        self.boot_path = true;
+        self.vm_resources.set_smbios_config(cfg)?;
+        Ok(VmmData::Empty)
+    }
+
+    fn set_tpm_device(&mut self, cfg: TpmConfig) -> Result<VmmData, VmmActionError> {
+        self.boot_path = true;
+        self.vm_resources.set_tpm_config(cfg);
+        Ok(VmmData::Empty)
+    }
+
+    fn set_pvpanic_device(&mut self, cfg: PvPanicConfig) -> Result<VmmData, VmmActionError> {
+        self.boot_path = true;
+        self.vm_resources.set_pvpanic_config(cfg);
+        Ok(VmmData::Empty)
+    }
+
     fn update_vm_config(&mut self, cfg: MachineConfigUpdate) -> Result<VmmData, VmmActionError> {
         self.boot_path = true;
         self.vm_resources
@@ -518,6 +813,15 @@ impl<'a> PrebootApiController<'a> {
         Ok(VmmData::Empty)
     }
 
+    fn validate_custom_cpu_template(
+        &self,
+        cpu_template: &CustomCpuTemplate,
+    ) -> Result<VmmData, VmmActionError> {
+        crate::builder::validate_cpu_template(self.vm_resources, cpu_template)
+            .map(VmmData::CpuTemplateReport)
+            .map_err(VmmActionError::StartMicrovm)
+    }
+
     fn set_vsock_device(&mut self, cfg: VsockDeviceConfig) -> Result<VmmData, VmmActionError> {
         self.boot_path = true;
         self.vm_resources
@@ -621,7 +925,12 @@ impl RuntimeApiController {
     /// Handles the incoming runtime `VmmAction` request and provides a response for it.
     pub fn handle_request(&mut self, request: VmmAction) -> Result<VmmData, VmmActionError> {
         use self::VmmAction::*;
-        match request {
+
+        METRICS.vmm.api_actions.inc();
+        let _metric = METRICS.vmm.api_action_handling_agg.record_latency_metrics();
+        let recorded = STATE_DIR.start_action(&request);
+
+        let result = match request {
             // Supported operations allowed post-boot.
             CreateSnapshot(snapshot_create_cfg) => self.create_snapshot(&snapshot_create_cfg),
             FlushMetrics => self.flush_metrics(),
@@ -639,8 +948,35 @@ impl RuntimeApiController {
                 .latest_balloon_stats()
                 .map(VmmData::BalloonStats)
                 .map_err(|err| VmmActionError::BalloonConfig(BalloonConfigError::from(err))),
+            GetBootTimings => Ok(VmmData::BootTimings(BOOT_TIMINGS.info())),
             GetFullVmConfig => Ok(VmmData::FullVmConfig((&self.vm_resources).into())),
+            GetMemorySlots => Ok(VmmData::MemorySlots(
+                self.vmm.lock().expect("Poisoned lock").memory_slots(),
+            )),
+            GetMemoryTarget => self
+                .vmm
+                .lock()
+                .expect("Poisoned lock")
+                .memory_target()
+                .map(VmmData::MemoryTarget)
+                .map_err(VmmActionError::MemoryTarget),
             GetMMDS => self.get_mmds(),
+            GetMmdsGuestData => self.get_mmds_guest_data(),
+            GetMmdsSessions => self.get_mmds_sessions(),
+            GetNetworkFlows(iface_id) => self.get_net_flows(&iface_id),
+            GetProcessInfo => Ok(VmmData::ProcessInfo(
+                self.vmm.lock().expect("Poisoned lock").process_info(),
+            )),
+            GetSerialLog => get_serial_log(&self.vm_resources),
+            GetSharedRateLimiters => {
+                Ok(VmmData::SharedRateLimiters(shared_rate_limiter_statuses()))
+            }
+            GetSnapshotStatus => Ok(VmmData::BackgroundSnapshotStatus(
+                self.vmm
+                    .lock()
+                    .expect("Poisoned lock")
+                    .background_snapshot_status(),
+            )),
             GetVmMachineConfig => Ok(VmmData::MachineConfiguration(MachineConfig::from(
                 &self.vm_resources.vm_config,
             ))),
@@ -650,12 +986,21 @@ impl RuntimeApiController {
             GetVmmVersion => Ok(VmmData::VmmVersion(
                 self.vmm.lock().expect("Poisoned lock").version(),
             )),
+            InstallSeccompFilters(config) => self.install_seccomp_filters(&config),
             PatchMMDS(value) => self.patch_mmds(value),
             Pause => self.pause(),
             PutMMDS(value) => self.put_mmds(value),
             Resume => self.resume(),
+            RevokeMmdsSession(revocation) => self.revoke_mmds_session(revocation),
             #[cfg(target_arch = "x86_64")]
             SendCtrlAltDel => self.send_ctrl_alt_del(),
+            SendShutdown(config) => self
+                .vmm
+                .lock()
+                .expect("Poisoned lock")
+                .send_shutdown(&config)
+                .map(|()| VmmData::Empty)
+                .map_err(VmmActionError::Shutdown),
             UpdateBalloon(balloon_update) => self
                 .vmm
                 .lock()
@@ -667,27 +1012,69 @@ impl RuntimeApiController {
                 .vmm
                 .lock()
                 .expect("Poisoned lock")
-                .update_balloon_stats_config(balloon_stats_update.stats_polling_interval_s)
+                .update_balloon_stats_config(
+                    balloon_stats_update.stats_polling_interval_s,
+                    balloon_stats_update.stats_push,
+                    balloon_stats_update.auto_target,
+                )
                 .map(|_| VmmData::Empty)
                 .map_err(|err| VmmActionError::BalloonConfig(BalloonConfigError::from(err))),
+            InsertSharedRateLimiter(config) => self
+                .vm_resources
+                .set_shared_rate_limiter(config)
+                .map(|()| VmmData::Empty)
+                .map_err(VmmActionError::SharedRateLimiterConfig),
             UpdateBlockDevice(new_cfg) => self.update_block_device(new_cfg),
-            UpdateNetworkInterface(netif_update) => self.update_net_rate_limiters(netif_update),
+            UpdateCpuLimiter(config) => self.update_cpu_limiter(config),
+            UpdateMemoryTarget(memory_target) => self
+                .vmm
+                .lock()
+                .expect("Poisoned lock")
+                .set_memory_target(memory_target.target_mib)
+                .map(|_| VmmData::Empty)
+                .map_err(VmmActionError::MemoryTarget),
+            UpdateNetworkCapture(iface_id, capture) => self.update_net_capture(&iface_id, capture),
+            UpdateNetworkInterface(netif_update) => self.update_network_interface(netif_update),
 
             // Operations not allowed post-boot.
-            ConfigureBootSource(_)
+            CheckSnapshotCompatibility(_)
+            | ConfigureBootSource(_)
             | ConfigureLogger(_)
             | ConfigureMetrics(_)
             | InsertBlockDevice(_)
             | InsertNetworkDevice(_)
+            | InsertSharedMemory(_)
             | LoadSnapshot(_)
             | PutCpuConfiguration(_)
             | SetBalloonDevice(_)
+            | SetCpuLimiter(_)
             | SetVsockDevice(_)
             | SetMmdsConfiguration(_)
+            | SetSerialConfiguration(_)
+            | SetSmbiosConfiguration(_)
+            | SetTpmDevice(_)
+            | SetPvPanicDevice(_)
+            | SetLifecycleHooks(_)
             | SetEntropyDevice(_)
             | StartMicroVm
-            | UpdateVmConfiguration(_) => Err(VmmActionError::OperationNotSupportedPostBoot),
+            | UpdateVmConfiguration(_)
+            | ValidateCpuConfiguration(_) => Err(VmmActionError::OperationNotSupportedPostBoot),
+        };
+
+        if recorded {
+            let vm_state = self
+                .vmm
+                .lock()
+                .expect("Poisoned lock")
+                .instance_info()
+                .state;
+            STATE_DIR.finish_action(
+                result.as_ref().err().map(ToString::to_string),
+                &vm_state,
+                &self.vm_resources,
+            );
         }
+        result
     }
 
     /// Creates a new `RuntimeApiController`.
@@ -761,6 +1148,19 @@ impl RuntimeApiController {
             ));
         }
 
+        if self.vm_resources.vm_config.nested_virt {
+            return Err(VmmActionError::NotSupported(
+                "Snapshots are not allowed on uVMs with nested virtualization enabled.".to_string(),
+            ));
+        }
+
+        // The memory tiers are backed by host files which are not part of the snapshot.
+        if !self.vm_resources.vm_config.memory_tiers.is_empty() {
+            return Err(VmmActionError::NotSupported(
+                "Snapshots are not allowed on uVMs with memory tiers.".to_string(),
+            ));
+        }
+
         let mut locked_vmm = self.vmm.lock().unwrap();
         let vm_info = VmInfo::from(&self.vm_resources);
         let create_start_us = get_time_us(ClockType::Monotonic);
@@ -792,6 +1192,17 @@ impl RuntimeApiController {
         Ok(VmmData::Empty)
     }
 
+    /// Updates the CPU allocation of the vCPUs, both in the running microVM and in its
+    /// configuration.
+    fn update_cpu_limiter(&mut self, cfg: CpuLimiterConfig) -> Result<VmmData, VmmActionError> {
+        self.vmm
+            .lock()
+            .expect("Poisoned lock")
+            .update_cpu_limiter(cfg.clone())?;
+        self.vm_resources.update_cpu_limiter(cfg)?;
+        Ok(VmmData::Empty)
+    }
+
     /// Updates block device properties:
     ///  - path of the host file backing the emulated block device, update the disk image on the
     ///    device and its virtio configuration
@@ -802,9 +1213,18 @@ impl RuntimeApiController {
     ) -> Result<VmmData, VmmActionError> {
         let mut vmm = self.vmm.lock().expect("Poisoned lock");
 
-        // vhost-user-block updates
-        if new_cfg.path_on_host.is_none() && new_cfg.rate_limiter.is_none() {
-            vmm.update_vhost_user_block_config(&new_cfg.drive_id)
+        // The queue is paused before the other updates, e.g. the swap of the backing file.
+        if new_cfg.state == Some(DeviceQueuesState::Paused) {
+            vmm.update_block_device_state(&new_cfg.drive_id, DeviceQueuesState::Paused)
+                .map_err(DriveError::DeviceUpdate)?;
+        }
+
+        // Updates of the config from the backend, e.g. after the backing file was resized.
+        if new_cfg.path_on_host.is_none()
+            && new_cfg.rate_limiter.is_none()
+            && new_cfg.state.is_none()
+        {
+            vmm.update_block_device_config(&new_cfg.drive_id)
                 .map(|()| VmmData::Empty)
                 .map_err(DriveError::DeviceUpdate)?;
         }
@@ -824,28 +1244,86 @@ impl RuntimeApiController {
             .map(|()| VmmData::Empty)
             .map_err(DriveError::DeviceUpdate)?;
         }
+
+        // The queue is resumed once the other updates are applied.
+        if new_cfg.state == Some(DeviceQueuesState::Resumed) {
+            vmm.update_block_device_state(&new_cfg.drive_id, DeviceQueuesState::Resumed)
+                .map_err(DriveError::DeviceUpdate)?;
+        }
         Ok(VmmData::Empty)
     }
 
     /// Updates configuration for an emulated net device as described in `new_cfg`.
-    fn update_net_rate_limiters(
+    fn update_network_interface(
         &mut self,
         new_cfg: NetworkInterfaceUpdateConfig,
     ) -> Result<VmmData, VmmActionError> {
+        let mut vmm = self.vmm.lock().expect("Poisoned lock");
+
+        if new_cfg.state == Some(DeviceQueuesState::Paused) {
+            vmm.update_net_state(&new_cfg.iface_id, DeviceQueuesState::Paused)
+                .map_err(NetworkInterfaceError::DeviceUpdate)?;
+        }
+        vmm.update_net_rate_limiters(
+            &new_cfg.iface_id,
+            RateLimiterUpdate::from(new_cfg.rx_rate_limiter),
+            RateLimiterUpdate::from(new_cfg.tx_rate_limiter),
+        )
+        .map_err(NetworkInterfaceError::DeviceUpdate)?;
+        if new_cfg.state == Some(DeviceQueuesState::Resumed) {
+            vmm.update_net_state(&new_cfg.iface_id, DeviceQueuesState::Resumed)
+                .map_err(NetworkInterfaceError::DeviceUpdate)?;
+        }
+        Ok(VmmData::Empty)
+    }
+
+    /// Installs additional seccomp filters on the VMM and vCPU threads, and hands the filter of
+    /// the API thread back to it.
+    fn install_seccomp_filters(
+        &mut self,
+        config: &SeccompFiltersConfig,
+    ) -> Result<VmmData, VmmActionError> {
+        let filters = config.load()?;
         self.vmm
             .lock()
             .expect("Poisoned lock")
-            .update_net_rate_limiters(
-                &new_cfg.iface_id,
-                RateLimiterUpdate::from(new_cfg.rx_rate_limiter).bandwidth,
-                RateLimiterUpdate::from(new_cfg.rx_rate_limiter).ops,
-                RateLimiterUpdate::from(new_cfg.tx_rate_limiter).bandwidth,
-                RateLimiterUpdate::from(new_cfg.tx_rate_limiter).ops,
-            )
+            .install_seccomp_filters(&filters)?;
+        Ok(VmmData::ApiSeccompFilter(
+            filters.get("api").cloned().unwrap_or_default(),
+        ))
+    }
+
+    /// Starts or stops the packet capture of a network interface.
+    fn update_net_capture(
+        &mut self,
+        iface_id: &str,
+        capture: NetworkCaptureConfig,
+    ) -> Result<VmmData, VmmActionError> {
+        if capture.enabled && capture.path.is_none() {
+            return Err(VmmActionError::NetworkConfig(
+                NetworkInterfaceError::CapturePathMissing,
+            ));
+        }
+        self.vmm
+            .lock()
+            .expect("Poisoned lock")
+            .update_net_capture(iface_id, &capture)
             .map(|()| VmmData::Empty)
             .map_err(NetworkInterfaceError::DeviceUpdate)
             .map_err(VmmActionError::NetworkConfig)
     }
+
+    /// Returns the per-flow traffic accounting of a network interface.
+    fn get_net_flows(&mut self, iface_id: &str) -> Result<VmmData, VmmActionError> {
+        self.vmm
+            .lock()
+            .expect("Poisoned lock")
+            .net_flows(iface_id)
+            .map_err(NetworkInterfaceError::DeviceQuery)?
+            .map(VmmData::NetworkFlows)
+            .ok_or_else(|| NetworkInterfaceError::FlowAccountingDisabled(iface_id.to_string()))
+            .map_err(VmmActionError::NetworkConfig)
+    }
 }
 
 #[cfg(test)]
@@ -901,6 +1379,52 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_preboot_get_serial_log() {
+        assert!(matches!(
+            preboot_request(VmmAction::GetSerialLog),
+            Err(VmmActionError::SerialLogDisabled)
+        ));
+
+        let mut vm_resources = VmResources::default();
+        vm_resources
+            .set_serial_config(SerialConfig {
+                log_buffer_size: Some(4),
+                ..Default::default()
+            })
+            .unwrap();
+        vm_resources.serial_log.as_ref().unwrap().append(b"booted");
+        let mut evmgr = EventManager::new().unwrap();
+        let seccomp_filters = BpfThreadMap::new();
+        let mut preboot = default_preboot(&mut vm_resources, &mut evmgr, &seccomp_filters);
+        assert_eq!(
+            preboot
+                .handle_preboot_request(VmmAction::GetSerialLog)
+                .unwrap(),
+            VmmData::SerialLog(SerialLogContent {
+                log: "oted".to_string(),
+                dropped_bytes: 2,
+            })
+        );
+    }
+
+    #[test]
+    fn test_preboot_get_boot_timings() {
+        assert!(matches!(
+            preboot_request(VmmAction::GetBootTimings),
+            Ok(VmmData::BootTimings(_))
+        ));
+    }
+
+    #[test]
+    fn test_preboot_get_process_info() {
+        // There are no vCPU threads before microVM start.
+        assert_eq!(
+            preboot_request(VmmAction::GetProcessInfo).unwrap(),
+            VmmData::ProcessInfo(ProcessInfo::default())
+        );
+    }
+
     #[test]
     fn test_preboot_get_mmds() {
         assert_eq!(
@@ -909,6 +1433,57 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_runtime_get_mmds_guest_data() {
+        let mmds = Arc::new(Mutex::new(Mmds::default()));
+        assert_eq!(
+            runtime_request_with_mmds(VmmAction::GetMmdsGuestData, mmds.clone()).unwrap(),
+            VmmData::MmdsValue(serde_json::json!({}))
+        );
+
+        {
+            let mut mmds = mmds.lock().unwrap();
+            mmds.set_guest_data_limit(Some(1024));
+            mmds.put_guest_data("ready", "true".to_string()).unwrap();
+        }
+        assert_eq!(
+            runtime_request_with_mmds(VmmAction::GetMmdsGuestData, mmds).unwrap(),
+            VmmData::MmdsValue(serde_json::json!({"ready": "true"}))
+        );
+    }
+
+    #[test]
+    fn test_runtime_mmds_sessions() {
+        let mmds = Arc::new(Mutex::new(Mmds::default()));
+        mmds.lock().unwrap().set_version(MmdsVersion::V2).unwrap();
+        let token = mmds.lock().unwrap().generate_token(60).unwrap();
+        let sessions =
+            match runtime_request_with_mmds(VmmAction::GetMmdsSessions, mmds.clone()).unwrap() {
+                VmmData::MmdsSessions(sessions) => sessions,
+                data => panic!("Unexpected data: {:?}", data),
+            };
+        assert_eq!(sessions.len(), 1);
+
+        let revocation = MmdsSessionRevocation {
+            session_id: sessions[0].session_id.clone(),
+        };
+        assert_eq!(
+            runtime_request_with_mmds(
+                VmmAction::RevokeMmdsSession(revocation.clone()),
+                mmds.clone()
+            )
+            .unwrap(),
+            VmmData::Empty
+        );
+        assert!(!mmds.lock().unwrap().is_valid_token(&token).unwrap());
+        assert!(matches!(
+            runtime_request_with_mmds(VmmAction::RevokeMmdsSession(revocation), mmds),
+            Err(VmmActionError::Mmds(
+                data_store::MmdsDatastoreError::SessionNotFound(_)
+            ))
+        ));
+    }
+
     #[test]
     fn test_runtime_get_mmds() {
         assert_eq!(
@@ -1132,6 +1707,8 @@ mod tests {
         check_unsupported(preboot_request(VmmAction::UpdateBalloonStatistics(
             BalloonUpdateStatsConfig {
                 stats_polling_interval_s: 0,
+                stats_push: None,
+                auto_target: None,
             },
         )));
         check_unsupported(preboot_request(VmmAction::UpdateBlockDevice(
@@ -1142,17 +1719,51 @@ mod tests {
                 iface_id: String::new(),
                 rx_rate_limiter: None,
                 tx_rate_limiter: None,
+                state: None,
+            },
+        )));
+        check_unsupported(preboot_request(VmmAction::GetNetworkFlows(String::new())));
+        check_unsupported(preboot_request(VmmAction::UpdateNetworkCapture(
+            String::new(),
+            NetworkCaptureConfig {
+                enabled: false,
+                path: None,
+                snaplen: 0,
+                ring_size: 0,
             },
         )));
+        check_unsupported(preboot_request(VmmAction::GetMemorySlots));
+        check_unsupported(preboot_request(VmmAction::GetMemoryTarget));
+        check_unsupported(preboot_request(VmmAction::UpdateMemoryTarget(
+            MemoryTargetConfig { target_mib: 0 },
+        )));
+        check_unsupported(preboot_request(VmmAction::UpdateCpuLimiter(
+            CpuLimiterConfig {
+                millicpus: 1000,
+                burst_ms: 0,
+            },
+        )));
+        check_unsupported(preboot_request(VmmAction::GetSnapshotStatus));
         check_unsupported(preboot_request(VmmAction::CreateSnapshot(
             CreateSnapshotParams {
                 snapshot_type: SnapshotType::Full,
                 snapshot_path: PathBuf::new(),
                 mem_file_path: PathBuf::new(),
+                labels: Default::default(),
+                encryption: None,
+                background: false,
             },
         )));
         #[cfg(target_arch = "x86_64")]
         check_unsupported(preboot_request(VmmAction::SendCtrlAltDel));
+        check_unsupported(preboot_request(VmmAction::SendShutdown(
+            ShutdownConfig::default(),
+        )));
+        check_unsupported(preboot_request(VmmAction::InstallSeccompFilters(
+            SeccompFiltersConfig {
+                filter_path: PathBuf::new(),
+            },
+        )));
     }
 
     fn runtime_request(request: VmmAction) -> Result<VmmData, VmmActionError> {
@@ -1182,6 +1793,68 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_runtime_get_boot_timings() {
+        assert!(matches!(
+            runtime_request(VmmAction::GetBootTimings),
+            Ok(VmmData::BootTimings(_))
+        ));
+    }
+
+    #[test]
+    fn test_runtime_get_process_info() {
+        assert_eq!(
+            runtime_request(VmmAction::GetProcessInfo).unwrap(),
+            VmmData::ProcessInfo(ProcessInfo::default())
+        );
+    }
+
+    #[test]
+    fn test_runtime_update_cpu_limiter() {
+        // The default test microVM is not throttled.
+        let res = runtime_request(VmmAction::UpdateCpuLimiter(CpuLimiterConfig {
+            millicpus: 1000,
+            burst_ms: 0,
+        }));
+        assert!(
+            matches!(
+                res,
+                Err(VmmActionError::CpuLimiter(
+                    CpuLimiterConfigError::NotConfigured
+                ))
+            ),
+            "{:?}",
+            res
+        );
+    }
+
+    #[test]
+    fn test_runtime_send_shutdown() {
+        // The timeout is validated before pressing the power button.
+        let res = runtime_request(VmmAction::SendShutdown(ShutdownConfig {
+            shutdown_timeout_ms: Some(0),
+        }));
+        assert!(
+            matches!(
+                res,
+                Err(VmmActionError::Shutdown(ShutdownError::InvalidTimeout(0)))
+            ),
+            "{:?}",
+            res
+        );
+
+        // The default test microVM has no power button.
+        let res = runtime_request(VmmAction::SendShutdown(ShutdownConfig::default()));
+        assert!(
+            matches!(
+                res,
+                Err(VmmActionError::Shutdown(ShutdownError::NoPowerButton))
+            ),
+            "{:?}",
+            res
+        );
+    }
+
     #[test]
     fn test_runtime_disallowed() {
         fn check_unsupported(res: Result<VmmData, VmmActionError>) {
@@ -1218,6 +1891,11 @@ mod tests {
                 path_on_host: Some(String::new()),
                 rate_limiter: None,
                 file_engine_type: None,
+                remote: None,
+                direct_io: None,
+                backend: None,
+                latency_injection: None,
+                shared_rate_limiter: None,
 
                 socket: None,
             },
@@ -1229,6 +1907,16 @@ mod tests {
                 guest_mac: None,
                 rx_rate_limiter: None,
                 tx_rate_limiter: None,
+                flow_accounting: None,
+                pause_responder: None,
+                rx_coalescing: None,
+                mtu: None,
+                mmds_only: None,
+                num_queues: None,
+                offloads: None,
+                enforce_mac: None,
+                enforce_arp: None,
+                shared_rate_limiter: None,
             },
         )));
         check_unsupported(runtime_request(VmmAction::SetVsockDevice(
@@ -1236,6 +1924,10 @@ mod tests {
                 vsock_id: Some(String::new()),
                 guest_cid: 0,
                 uds_path: String::new(),
+                port_rate_limiters: Vec::new(),
+                tcp_forwards: Vec::new(),
+                seqpacket: false,
+                dgram: false,
             },
         )));
         check_unsupported(runtime_request(VmmAction::SetBalloonDevice(
@@ -1246,6 +1938,10 @@ mod tests {
                 vsock_id: Some(String::new()),
                 guest_cid: 0,
                 uds_path: String::new(),
+                port_rate_limiters: Vec::new(),
+                tcp_forwards: Vec::new(),
+                seqpacket: false,
+                dgram: false,
             },
         )));
         check_unsupported(runtime_request(VmmAction::SetMmdsConfiguration(
@@ -1253,11 +1949,23 @@ mod tests {
                 ipv4_address: None,
                 version: MmdsVersion::default(),
                 network_interfaces: Vec::new(),
+                guest_data_limit: None,
+                path_acl: None,
+                backend: None,
             },
         )));
         check_unsupported(runtime_request(VmmAction::UpdateVmConfiguration(
             MachineConfigUpdate::from(MachineConfig::default()),
         )));
+        check_unsupported(runtime_request(VmmAction::ValidateCpuConfiguration(
+            CustomCpuTemplate::default(),
+        )));
+        check_unsupported(runtime_request(VmmAction::CheckSnapshotCompatibility(
+            CheckSnapshotParams {
+                snapshot_path: PathBuf::new(),
+                encryption: None,
+            },
+        )));
         check_unsupported(runtime_request(VmmAction::LoadSnapshot(
             LoadSnapshotParams {
                 snapshot_path: PathBuf::new(),
@@ -1267,10 +1975,40 @@ mod tests {
                 },
                 enable_diff_snapshots: false,
                 resume_vm: false,
+                encryption: None,
+                prefault: false,
             },
         )));
         check_unsupported(runtime_request(VmmAction::SetEntropyDevice(
             EntropyDeviceConfig::default(),
         )));
+        check_unsupported(runtime_request(VmmAction::SetSerialConfiguration(
+            SerialConfig::default(),
+        )));
+        check_unsupported(runtime_request(VmmAction::SetSmbiosConfiguration(
+            SmbiosConfig::default(),
+        )));
+        check_unsupported(runtime_request(VmmAction::SetTpmDevice(TpmConfig {
+            socket: PathBuf::new(),
+        })));
+        check_unsupported(runtime_request(VmmAction::SetPvPanicDevice(
+            PvPanicConfig::default(),
+        )));
+        check_unsupported(runtime_request(VmmAction::SetCpuLimiter(
+            CpuLimiterConfig {
+                millicpus: 1000,
+                burst_ms: 0,
+            },
+        )));
+        check_unsupported(runtime_request(VmmAction::SetLifecycleHooks(
+            LifecycleHooksConfig::default(),
+        )));
+        check_unsupported(runtime_request(VmmAction::InsertSharedMemory(
+            SharedMemoryConfig {
+                segment_id: String::new(),
+                path_on_host: String::new(),
+                guest_addr: 0,
+            },
+        )));
     }
 }
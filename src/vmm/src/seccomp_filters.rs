@@ -1,8 +1,12 @@
 // Copyright 2018 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
+use std::collections::BTreeMap;
 use std::sync::Arc;
 
-use seccompiler::BpfThreadMap;
+use seccompiler::backend::{
+    SeccompAction, SeccompCmpArgLen, SeccompCmpOp, SeccompCondition, SeccompFilter, SeccompRule,
+};
+use seccompiler::{BpfProgram, BpfThreadMap};
 
 /// Retrieve empty seccomp filters.
 pub fn get_empty_filters() -> BpfThreadMap {
@@ -12,3 +16,116 @@ pub fn get_empty_filters() -> BpfThreadMap {
     map.insert("vcpu".to_string(), Arc::new(vec![]));
     map
 }
+
+/// Retrieve the filter installed on top of the VMM filter when no feature of the microVM
+/// connects to TCP servers, which fails the creation of IPv4 and IPv6 sockets with `EACCES`.
+pub fn get_tcp_denying_filter() -> BpfProgram {
+    let rules = [libc::AF_INET, libc::AF_INET6]
+        .into_iter()
+        .map(|family| {
+            let condition = SeccompCondition::new(
+                0,
+                SeccompCmpArgLen::Dword,
+                SeccompCmpOp::Eq,
+                u64::try_from(family).unwrap(),
+            )
+            .unwrap();
+            SeccompRule::new(
+                vec![condition],
+                SeccompAction::Errno(u32::try_from(libc::EACCES).unwrap()),
+            )
+        })
+        .collect();
+    SeccompFilter::new(
+        BTreeMap::from([(libc::SYS_socket, rules)]),
+        SeccompAction::Allow,
+        std::env::consts::ARCH,
+    )
+    .and_then(TryInto::try_into)
+    .expect("Invalid TCP denying seccomp filter")
+}
+
+// `ioctl` requests registering the ioeventfds and irqfds of the devices, which are the same on
+// x86_64 and aarch64.
+const KVM_IOEVENTFD: u64 = 0x4040_ae79;
+const KVM_IRQFD: u64 = 0x4020_ae76;
+
+/// Retrieve the filter of the thread registering the KVM events of the deferred devices, which
+/// only allows the registration itself, and the syscalls needed to log, exit, and unwind its stack
+/// for diagnostic reports.
+pub fn get_deferred_devices_filter() -> BpfProgram {
+    let ioctls = [KVM_IOEVENTFD, KVM_IRQFD]
+        .into_iter()
+        .map(|request| {
+            let condition =
+                SeccompCondition::new(1, SeccompCmpArgLen::Dword, SeccompCmpOp::Eq, request)
+                    .unwrap();
+            SeccompRule::new(vec![condition], SeccompAction::Allow)
+        })
+        .collect();
+    let mut rules = BTreeMap::from([(libc::SYS_ioctl, ioctls)]);
+    rules.extend(
+        [
+            libc::SYS_brk,
+            libc::SYS_close,
+            libc::SYS_exit,
+            libc::SYS_futex,
+            libc::SYS_madvise,
+            libc::SYS_mmap,
+            libc::SYS_munmap,
+            libc::SYS_rt_sigprocmask,
+            libc::SYS_rt_sigreturn,
+            libc::SYS_sigaltstack,
+            libc::SYS_write,
+        ]
+        .map(|syscall| {
+            (
+                syscall,
+                vec![SeccompRule::new(vec![], SeccompAction::Allow)],
+            )
+        }),
+    );
+    SeccompFilter::new(rules, SeccompAction::Trap, std::env::consts::ARCH)
+        .and_then(TryInto::try_into)
+        .expect("Invalid deferred devices seccomp filter")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::os::unix::net::UnixDatagram;
+    use std::thread;
+
+    use vmm_sys_util::eventfd::EventFd;
+
+    use super::*;
+
+    #[test]
+    fn test_tcp_denying_filter() {
+        thread::spawn(|| {
+            seccompiler::apply_filter(&get_tcp_denying_filter()).unwrap();
+            assert_eq!(
+                TcpListener::bind("127.0.0.1:0").unwrap_err().raw_os_error(),
+                Some(libc::EACCES)
+            );
+            assert_eq!(
+                TcpListener::bind("[::1]:0").unwrap_err().raw_os_error(),
+                Some(libc::EACCES)
+            );
+            UnixDatagram::unbound().unwrap();
+        })
+        .join()
+        .unwrap();
+    }
+
+    #[test]
+    fn test_deferred_devices_filter() {
+        let evt = EventFd::new(0).unwrap();
+        thread::spawn(move || {
+            seccompiler::apply_filter(&get_deferred_devices_filter()).unwrap();
+            evt.write(1).unwrap();
+        })
+        .join()
+        .unwrap();
+    }
+}
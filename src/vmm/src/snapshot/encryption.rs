@@ -0,0 +1,402 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Encrypts snapshot files with AES-256-GCM.
+//!
+//! An encrypted file starts with a header made of a magic value and a random nonce prefix. It is
+//! followed by the plaintext, split in chunks of [`CHUNK_SIZE`] bytes, each sealed with its own
+//! nonce and authentication tag, so that files larger than memory can be streamed. The nonce of
+//! a chunk is made of the prefix and the index of the chunk, and the additional authenticated data
+//! holds the kind of file and whether the chunk is the last one. Chunks thus cannot be reordered,
+//! moved between files or dropped from the end of a file without failing the decryption.
+
+use std::fmt::{self, Debug};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::fd::RawFd;
+use std::os::unix::fs::FileExt;
+
+use aws_lc_rs::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+
+use crate::utils::file_from_fd;
+
+/// Length of the snapshot encryption key, in bytes.
+pub const KEY_LEN: usize = 32;
+/// Length of the plaintext sealed in each chunk, except for the last one which can be shorter.
+pub const CHUNK_SIZE: usize = 1024 * 1024;
+const MAGIC: [u8; 8] = *b"FCENCv1\0";
+const NONCE_PREFIX_LEN: usize = NONCE_LEN - std::mem::size_of::<u32>();
+const HEADER_LEN: usize = MAGIC.len() + NONCE_PREFIX_LEN;
+const TAG_LEN: usize = 16;
+
+/// Errors associated with the snapshot encryption key.
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum SnapshotEncryptionError {
+    /// Cannot read the snapshot encryption key: {0}
+    ReadKey(io::Error),
+    /// The snapshot encryption key must be {KEY_LEN} bytes long.
+    KeyLength,
+}
+
+/// Kind of an encrypted snapshot file, authenticated with each of its chunks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncryptedFile {
+    /// File holding the microVM state.
+    State = 1,
+    /// File holding the guest memory.
+    Memory = 2,
+}
+
+/// AES-256-GCM key encrypting snapshot files.
+#[derive(Debug)]
+pub struct SnapshotKey(LessSafeKey);
+
+impl SnapshotKey {
+    /// Reads the key from the file descriptor `fd`, inherited by the process. The key is read
+    /// from the start of the file, so the same descriptor can be used for several snapshots.
+    pub fn from_fd(fd: RawFd) -> Result<Self, SnapshotEncryptionError> {
+        let file = file_from_fd(fd).map_err(SnapshotEncryptionError::ReadKey)?;
+        Self::from_file(&file)
+    }
+
+    fn from_file(file: &File) -> Result<Self, SnapshotEncryptionError> {
+        // One byte more than the key is read, to reject longer files.
+        let mut key = [0u8; KEY_LEN + 1];
+        let mut len = 0;
+        while len < key.len() {
+            match file.read_at(&mut key[len..], len as u64) {
+                Ok(0) => break,
+                Ok(read) => len += read,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(SnapshotEncryptionError::ReadKey(err)),
+            }
+        }
+        let unbound_key = UnboundKey::new(&AES_256_GCM, &key[..len]);
+        key.fill(0);
+        let unbound_key = unbound_key.map_err(|_| SnapshotEncryptionError::KeyLength)?;
+        Ok(SnapshotKey(LessSafeKey::new(unbound_key)))
+    }
+
+    #[cfg(test)]
+    pub(crate) fn from_bytes(key: &[u8; KEY_LEN]) -> Self {
+        SnapshotKey(LessSafeKey::new(
+            UnboundKey::new(&AES_256_GCM, key).unwrap(),
+        ))
+    }
+}
+
+/// Returns the length of the encrypted file holding `len` bytes of plaintext.
+pub fn encrypted_len(len: u64) -> u64 {
+    let chunks = len.div_ceil(CHUNK_SIZE as u64).max(1);
+    HEADER_LEN as u64 + len + chunks * TAG_LEN as u64
+}
+
+fn chunk_nonce(prefix: &[u8; NONCE_PREFIX_LEN], index: u32) -> Nonce {
+    let mut nonce = [0u8; NONCE_LEN];
+    nonce[..NONCE_PREFIX_LEN].copy_from_slice(prefix);
+    nonce[NONCE_PREFIX_LEN..].copy_from_slice(&index.to_be_bytes());
+    Nonce::assume_unique_for_key(nonce)
+}
+
+fn chunk_aad(file: EncryptedFile, last: bool) -> Aad<[u8; 2]> {
+    Aad::from([file as u8, u8::from(last)])
+}
+
+fn next_index(index: u32) -> io::Result<u32> {
+    index
+        .checked_add(1)
+        .ok_or_else(|| io::Error::other("snapshot file too large to be encrypted"))
+}
+
+/// Stream encrypting a snapshot file before writing it to `W`.
+///
+/// [`EncryptingWriter::finish`] must be called once all the plaintext is written, to seal the
+/// last chunk.
+pub struct EncryptingWriter<'a, W: Write> {
+    key: &'a SnapshotKey,
+    file: EncryptedFile,
+    inner: W,
+    nonce_prefix: [u8; NONCE_PREFIX_LEN],
+    index: u32,
+    chunk: Vec<u8>,
+}
+
+impl<W: Write> Debug for EncryptingWriter<'_, W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EncryptingWriter")
+            .field("file", &self.file)
+            .field("index", &self.index)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'a, W: Write> EncryptingWriter<'a, W> {
+    /// Writes the header of the encrypted file to `inner`, with a new random nonce prefix.
+    pub fn new(key: &'a SnapshotKey, file: EncryptedFile, mut inner: W) -> io::Result<Self> {
+        let mut nonce_prefix = [0u8; NONCE_PREFIX_LEN];
+        aws_lc_rs::rand::fill(&mut nonce_prefix)
+            .map_err(|_| io::Error::other("cannot generate a random nonce"))?;
+        inner.write_all(&MAGIC)?;
+        inner.write_all(&nonce_prefix)?;
+        Ok(EncryptingWriter {
+            key,
+            file,
+            inner,
+            nonce_prefix,
+            index: 0,
+            chunk: Vec::with_capacity(CHUNK_SIZE + TAG_LEN),
+        })
+    }
+
+    fn seal_chunk(&mut self, last: bool) -> io::Result<()> {
+        self.key
+            .0
+            .seal_in_place_append_tag(
+                chunk_nonce(&self.nonce_prefix, self.index),
+                chunk_aad(self.file, last),
+                &mut self.chunk,
+            )
+            .map_err(|_| io::Error::other("cannot encrypt snapshot chunk"))?;
+        self.inner.write_all(&self.chunk)?;
+        self.chunk.clear();
+        self.index = next_index(self.index)?;
+        Ok(())
+    }
+
+    /// Seals the last chunk, and returns the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.seal_chunk(true)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for EncryptingWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        // A full chunk is only sealed once more plaintext comes, as the last chunk is sealed
+        // differently.
+        if self.chunk.len() == CHUNK_SIZE {
+            self.seal_chunk(false)?;
+        }
+        let len = buf.len().min(CHUNK_SIZE - self.chunk.len());
+        self.chunk.extend_from_slice(&buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Stream decrypting a snapshot file read from `R`.
+///
+/// Each chunk is authenticated before any of its plaintext is returned. Reaching the end of the
+/// stream guarantees that the file was not truncated.
+pub struct DecryptingReader<'a, R: Read> {
+    key: &'a SnapshotKey,
+    file: EncryptedFile,
+    inner: BufReader<R>,
+    nonce_prefix: [u8; NONCE_PREFIX_LEN],
+    index: u32,
+    chunk: Vec<u8>,
+    pos: usize,
+    last: bool,
+}
+
+impl<R: Read> Debug for DecryptingReader<'_, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DecryptingReader")
+            .field("file", &self.file)
+            .field("index", &self.index)
+            .finish_non_exhaustive()
+    }
+}
+
+fn decryption_error() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "cannot decrypt snapshot file: wrong key, or corrupted or truncated file",
+    )
+}
+
+impl<'a, R: Read> DecryptingReader<'a, R> {
+    /// Reads the header of the encrypted file from `inner`.
+    pub fn new(key: &'a SnapshotKey, file: EncryptedFile, inner: R) -> io::Result<Self> {
+        let mut inner = BufReader::with_capacity(CHUNK_SIZE + TAG_LEN, inner);
+        let mut header = [0u8; HEADER_LEN];
+        inner
+            .read_exact(&mut header)
+            .map_err(|err| match err.kind() {
+                io::ErrorKind::UnexpectedEof => decryption_error(),
+                _ => err,
+            })?;
+        if header[..MAGIC.len()] != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not an encrypted snapshot file",
+            ));
+        }
+        let mut nonce_prefix = [0u8; NONCE_PREFIX_LEN];
+        nonce_prefix.copy_from_slice(&header[MAGIC.len()..]);
+        Ok(DecryptingReader {
+            key,
+            file,
+            inner,
+            nonce_prefix,
+            index: 0,
+            chunk: Vec::with_capacity(CHUNK_SIZE + TAG_LEN),
+            pos: 0,
+            last: false,
+        })
+    }
+
+    fn open_chunk(&mut self) -> io::Result<()> {
+        self.chunk.clear();
+        self.pos = 0;
+        (&mut self.inner)
+            .take((CHUNK_SIZE + TAG_LEN) as u64)
+            .read_to_end(&mut self.chunk)?;
+        let last = self.inner.fill_buf()?.is_empty();
+        let len = self
+            .key
+            .0
+            .open_in_place(
+                chunk_nonce(&self.nonce_prefix, self.index),
+                chunk_aad(self.file, last),
+                &mut self.chunk,
+            )
+            .map_err(|_| decryption_error())?
+            .len();
+        self.chunk.truncate(len);
+        self.index = next_index(self.index)?;
+        self.last = last;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for DecryptingReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.chunk.len() {
+            if self.last {
+                return Ok(0);
+            }
+            self.open_chunk()?;
+        }
+        let len = buf.len().min(self.chunk.len() - self.pos);
+        buf[..len].copy_from_slice(&self.chunk[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use vmm_sys_util::tempfile::TempFile;
+
+    use super::*;
+
+    fn encrypt(key: &SnapshotKey, file: EncryptedFile, plaintext: &[u8]) -> Vec<u8> {
+        let mut writer = EncryptingWriter::new(key, file, Vec::new()).unwrap();
+        writer.write_all(plaintext).unwrap();
+        writer.finish().unwrap()
+    }
+
+    fn decrypt(key: &SnapshotKey, file: EncryptedFile, encrypted: &[u8]) -> io::Result<Vec<u8>> {
+        let mut plaintext = Vec::new();
+        DecryptingReader::new(key, file, encrypted)?.read_to_end(&mut plaintext)?;
+        Ok(plaintext)
+    }
+
+    #[test]
+    fn test_encryption_round_trip() {
+        let key = SnapshotKey::from_bytes(&[7; KEY_LEN]);
+        for len in [0, 1, CHUNK_SIZE, CHUNK_SIZE * 2 + 3] {
+            let plaintext: Vec<u8> = (0..len).map(|i| u8::try_from(i % 251).unwrap()).collect();
+            let encrypted = encrypt(&key, EncryptedFile::Memory, &plaintext);
+            assert_eq!(encrypted.len() as u64, encrypted_len(len as u64));
+            assert_eq!(
+                decrypt(&key, EncryptedFile::Memory, &encrypted).unwrap(),
+                plaintext
+            );
+        }
+
+        // The same plaintext is encrypted differently each time.
+        assert_ne!(
+            encrypt(&key, EncryptedFile::State, b"state"),
+            encrypt(&key, EncryptedFile::State, b"state")
+        );
+    }
+
+    #[test]
+    fn test_decryption_errors() {
+        let key = SnapshotKey::from_bytes(&[7; KEY_LEN]);
+        let plaintext = vec![0xa5; CHUNK_SIZE * 2];
+        let encrypted = encrypt(&key, EncryptedFile::Memory, &plaintext);
+
+        // Wrong key.
+        let other_key = SnapshotKey::from_bytes(&[8; KEY_LEN]);
+        decrypt(&other_key, EncryptedFile::Memory, &encrypted).unwrap_err();
+        // Wrong kind of file.
+        decrypt(&key, EncryptedFile::State, &encrypted).unwrap_err();
+        // Truncated at a chunk boundary, or in the middle of a chunk.
+        let chunk_len = CHUNK_SIZE + TAG_LEN;
+        decrypt(
+            &key,
+            EncryptedFile::Memory,
+            &encrypted[..HEADER_LEN + chunk_len],
+        )
+        .unwrap_err();
+        decrypt(
+            &key,
+            EncryptedFile::Memory,
+            &encrypted[..encrypted.len() - 1],
+        )
+        .unwrap_err();
+        decrypt(&key, EncryptedFile::Memory, &encrypted[..HEADER_LEN]).unwrap_err();
+        // Corrupted.
+        let mut corrupted = encrypted.clone();
+        corrupted[HEADER_LEN + chunk_len + 1] ^= 1;
+        decrypt(&key, EncryptedFile::Memory, &corrupted).unwrap_err();
+        // Swapped chunks.
+        let mut swapped = encrypted[..HEADER_LEN].to_vec();
+        swapped.extend_from_slice(&encrypted[HEADER_LEN + chunk_len..]);
+        swapped.extend_from_slice(&encrypted[HEADER_LEN..HEADER_LEN + chunk_len]);
+        decrypt(&key, EncryptedFile::Memory, &swapped).unwrap_err();
+        // Not encrypted.
+        let err = decrypt(&key, EncryptedFile::Memory, &plaintext).unwrap_err();
+        assert_eq!(err.to_string(), "not an encrypted snapshot file");
+    }
+
+    #[test]
+    fn test_key_from_file() {
+        let key_file = TempFile::new().unwrap();
+        key_file.as_file().write_all(&[7; KEY_LEN]).unwrap();
+        let key = SnapshotKey::from_file(key_file.as_file()).unwrap();
+        let encrypted = encrypt(&key, EncryptedFile::State, b"state");
+        let reference_key = SnapshotKey::from_bytes(&[7; KEY_LEN]);
+        assert_eq!(
+            decrypt(&reference_key, EncryptedFile::State, &encrypted).unwrap(),
+            b"state"
+        );
+        // The key is read again from the start of the file.
+        SnapshotKey::from_file(key_file.as_file()).unwrap();
+
+        key_file.as_file().write_all(&[7]).unwrap();
+        assert!(matches!(
+            SnapshotKey::from_file(key_file.as_file()),
+            Err(SnapshotEncryptionError::KeyLength)
+        ));
+        key_file.as_file().set_len(KEY_LEN as u64 - 1).unwrap();
+        assert!(matches!(
+            SnapshotKey::from_file(key_file.as_file()),
+            Err(SnapshotEncryptionError::KeyLength)
+        ));
+        assert!(matches!(
+            SnapshotKey::from_fd(-1),
+            Err(SnapshotEncryptionError::ReadKey(_))
+        ));
+    }
+}
@@ -0,0 +1,306 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Rewrites snapshots from one version of their format to another.
+//!
+//! Each change of the format is described by a [`Migration`] between two consecutive versions,
+//! converting the serialized state in both directions. A snapshot is migrated by applying the
+//! chain of migrations between its version and the target one, so that snapshots can be moved
+//! between Firecracker versions on both sides of a format change during a rollout. Downgrading
+//! is only possible while the fields missing from the older format hold values it can
+//! represent, typically their defaults.
+
+use std::fmt::Debug;
+use std::io::{Read, Write};
+
+use semver::Version;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use super::{Snapshot, SnapshotError};
+
+/// Function converting a serialized state from one version of the format to another.
+pub type ConvertFn = fn(&[u8]) -> Result<Vec<u8>, MigrationError>;
+
+/// Change of the snapshot format between two consecutive versions.
+#[derive(Debug, Clone)]
+pub struct Migration {
+    /// Version of the format before the change.
+    pub from: Version,
+    /// Version of the format after the change.
+    pub to: Version,
+    /// Converts a state serialized in version `from` to version `to`.
+    pub upgrade: ConvertFn,
+    /// Converts a state serialized in version `to` to version `from`.
+    pub downgrade: ConvertFn,
+}
+
+/// Errors associated with snapshot migrations.
+#[derive(Debug, thiserror::Error, displaydoc::Display, PartialEq)]
+pub enum MigrationError {
+    /// Snapshot error: {0}
+    Snapshot(#[from] SnapshotError),
+    /// No migration from version {0} to version {1} of the snapshot format.
+    NoMigrationPath(Version, Version),
+    /// {0} cannot be represented in version {1} of the snapshot format.
+    Unrepresentable(String, Version),
+}
+
+/// Returns the migrations leading from version `from` to version `to`, in the order in which
+/// they are applied. They are applied as downgrades if `to` is older than `from`.
+fn migration_path<'a>(
+    migrations: &'a [Migration],
+    from: &Version,
+    to: &Version,
+) -> Result<Vec<&'a Migration>, MigrationError> {
+    let no_path = || MigrationError::NoMigrationPath(from.clone(), to.clone());
+    let upgrade = from < to;
+    let mut path = Vec::new();
+    let mut current = from;
+    while current != to {
+        // Each migration is used at most once, which stops cycles in the registry.
+        if path.len() == migrations.len() {
+            return Err(no_path());
+        }
+        let migration = migrations
+            .iter()
+            .find(|m| (if upgrade { &m.from } else { &m.to }) == current)
+            .ok_or_else(no_path)?;
+        current = if upgrade {
+            &migration.to
+        } else {
+            &migration.from
+        };
+        path.push(migration);
+    }
+    Ok(path)
+}
+
+/// Returns the versions of the format to which a snapshot of version `from` can be migrated,
+/// including `from` itself, from the oldest to the newest.
+pub fn migration_targets(migrations: &[Migration], from: &Version) -> Vec<Version> {
+    let mut targets: Vec<Version> = migrations
+        .iter()
+        .flat_map(|m| [m.from.clone(), m.to.clone()])
+        .chain(std::iter::once(from.clone()))
+        .filter(|to| migration_path(migrations, from, to).is_ok())
+        .collect();
+    targets.sort();
+    targets.dedup();
+    targets
+}
+
+/// Converts `state`, serialized in version `from` of the format, to version `to`.
+pub fn migrate_state(
+    migrations: &[Migration],
+    state: &[u8],
+    from: &Version,
+    to: &Version,
+) -> Result<Vec<u8>, MigrationError> {
+    let upgrade = from < to;
+    let mut state = state.to_vec();
+    for migration in migration_path(migrations, from, to)? {
+        let convert = if upgrade {
+            migration.upgrade
+        } else {
+            migration.downgrade
+        };
+        state = convert(&state)?;
+    }
+    Ok(state)
+}
+
+/// Reads the snapshot of `snapshot_len` bytes from `reader`, and writes it to `writer` in version
+/// `to` of the format, with the same labels, which older versions of the format can't carry.
+/// Returns the version the snapshot was read in.
+pub fn migrate<R, W>(
+    migrations: &[Migration],
+    reader: &mut R,
+    snapshot_len: usize,
+    writer: &mut W,
+    to: &Version,
+) -> Result<Version, MigrationError>
+where
+    R: Read + Debug,
+    W: Write + Debug,
+{
+    let (state, from, labels) = Snapshot::load_raw(reader, snapshot_len)?;
+    if !labels.is_empty() && !Snapshot::has_labels(to) {
+        return Err(MigrationError::Unrepresentable(
+            "Snapshot labels".to_string(),
+            to.clone(),
+        ));
+    }
+    let state = migrate_state(migrations, &state, &from, to)?;
+    Snapshot::new(to.clone())
+        .with_labels(labels)
+        .save_raw(writer, &state)?;
+    Ok(from)
+}
+
+/// Helper for writing the conversions of a [`Migration`]: deserializes `state` as `Old`, converts
+/// it with `f` and serializes the result. Fields of the state after the ones of `Old` are kept
+/// as they are, so `Old` only needs to cover the state up to the changed field.
+pub fn convert<Old, New>(
+    state: &[u8],
+    f: impl FnOnce(Old) -> Result<New, MigrationError>,
+) -> Result<Vec<u8>, MigrationError>
+where
+    Old: DeserializeOwned + Debug,
+    New: Serialize + Debug,
+{
+    let mut reader = state;
+    let old: Old = Snapshot::deserialize(&mut reader)?;
+    let mut converted = Vec::with_capacity(state.len());
+    Snapshot::serialize(&mut converted, &f(old)?)?;
+    converted.extend_from_slice(reader);
+    Ok(converted)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+
+    // State of a toy format in which version 1.1.0 added a field with a default value, followed
+    // by a field which is left as is.
+    #[derive(Debug, Serialize, Deserialize)]
+    struct InfoV1_0 {
+        mem_size_mib: u64,
+    }
+
+    #[derive(Debug, Default, Serialize, Deserialize)]
+    struct InfoV1_1 {
+        mem_size_mib: u64,
+        smt: bool,
+    }
+
+    fn upgrade_v1_1(state: &[u8]) -> Result<Vec<u8>, MigrationError> {
+        convert(state, |old: InfoV1_0| {
+            Ok(InfoV1_1 {
+                mem_size_mib: old.mem_size_mib,
+                ..Default::default()
+            })
+        })
+    }
+
+    fn downgrade_v1_1(state: &[u8]) -> Result<Vec<u8>, MigrationError> {
+        convert(state, |new: InfoV1_1| {
+            if new.smt {
+                return Err(MigrationError::Unrepresentable(
+                    "smt".to_string(),
+                    Version::new(1, 0, 0),
+                ));
+            }
+            Ok(InfoV1_0 {
+                mem_size_mib: new.mem_size_mib,
+            })
+        })
+    }
+
+    fn migrations() -> Vec<Migration> {
+        vec![Migration {
+            from: Version::new(1, 0, 0),
+            to: Version::new(1, 1, 0),
+            upgrade: upgrade_v1_1,
+            downgrade: downgrade_v1_1,
+        }]
+    }
+
+    fn snapshot_data<O: Serialize + Debug>(version: Version, state: &O) -> Vec<u8> {
+        let mut data = Vec::new();
+        Snapshot::new(version).save(&mut data, state).unwrap();
+        data
+    }
+
+    #[test]
+    fn test_migration_path() {
+        let migrations = migrations();
+        let (v1_0, v1_1, v2_0) = (
+            Version::new(1, 0, 0),
+            Version::new(1, 1, 0),
+            Version::new(2, 0, 0),
+        );
+        assert!(migration_path(&migrations, &v1_0, &v1_0)
+            .unwrap()
+            .is_empty());
+        assert_eq!(migration_path(&migrations, &v1_0, &v1_1).unwrap().len(), 1);
+        assert_eq!(migration_path(&migrations, &v1_1, &v1_0).unwrap().len(), 1);
+        assert_eq!(
+            migration_path(&migrations, &v1_1, &v2_0).unwrap_err(),
+            MigrationError::NoMigrationPath(v1_1.clone(), v2_0.clone())
+        );
+        assert_eq!(
+            migration_targets(&migrations, &v1_1),
+            vec![v1_0.clone(), v1_1.clone()]
+        );
+        assert_eq!(migration_targets(&migrations, &v2_0), vec![v2_0.clone()]);
+
+        // A registry with a cycle does not loop forever.
+        let mut cycle = migrations.clone();
+        cycle.push(Migration {
+            from: v1_1.clone(),
+            to: v1_0.clone(),
+            ..migrations[0].clone()
+        });
+        migration_path(&cycle, &v1_0, &v2_0).unwrap_err();
+    }
+
+    #[test]
+    fn test_migrate() {
+        let migrations = migrations();
+        let v1_0 = Version::new(1, 0, 0);
+        let v1_1 = Version::new(1, 1, 0);
+
+        // The field following the changed struct is kept.
+        let data = snapshot_data(v1_0.clone(), &(InfoV1_0 { mem_size_mib: 128 }, 42u32));
+        let mut upgraded = Vec::new();
+        let from = migrate(
+            &migrations,
+            &mut data.as_slice(),
+            data.len(),
+            &mut upgraded,
+            &v1_1,
+        )
+        .unwrap();
+        assert_eq!(from, v1_0);
+        let ((info, tail), version) =
+            Snapshot::load::<_, (InfoV1_1, u32)>(&mut upgraded.as_slice(), upgraded.len()).unwrap();
+        assert_eq!(version, v1_1);
+        assert_eq!(info.mem_size_mib, 128);
+        assert!(!info.smt);
+        assert_eq!(tail, 42);
+
+        let mut downgraded = Vec::new();
+        migrate(
+            &migrations,
+            &mut upgraded.as_slice(),
+            upgraded.len(),
+            &mut downgraded,
+            &v1_0,
+        )
+        .unwrap();
+        assert_eq!(downgraded, data);
+
+        // Downgrading fails if a new field does not hold its default value.
+        let data = snapshot_data(
+            v1_1.clone(),
+            &InfoV1_1 {
+                mem_size_mib: 128,
+                smt: true,
+            },
+        );
+        assert_eq!(
+            migrate(
+                &migrations,
+                &mut data.as_slice(),
+                data.len(),
+                &mut Vec::new(),
+                &v1_0,
+            )
+            .unwrap_err(),
+            MigrationError::Unrepresentable("smt".to_string(), v1_0)
+        );
+    }
+}
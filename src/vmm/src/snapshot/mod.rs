@@ -15,6 +15,8 @@
 //!  |-----------------------------|
 //!  |       version string        |
 //!  |-----------------------------|
+//!  |    labels (since 6.0.0)     |
+//!  |-----------------------------|
 //!  |            State            |
 //!  |-----------------------------|
 //!  |        optional CRC64       |
@@ -23,15 +25,22 @@
 //!
 //! The snapshot format uses a version value in the form of `MAJOR.MINOR.PATCH`. The version is
 //! provided by the library clients (it is not tied to this crate).
+//!
+//! Labels are arbitrary key/value string pairs attached to the snapshot by its creator
+//! (e.g. the image id or the git sha of the tooling that created it).
 pub mod crc;
+pub mod encryption;
+pub mod migration;
 mod persist;
+pub mod storage;
+use std::collections::BTreeMap;
 use std::fmt::Debug;
 use std::io::{Read, Write};
 
 use bincode::Options;
 use semver::Version;
 use serde::de::DeserializeOwned;
-use serde::{Deserialize, Serialize};
+use serde::Serialize;
 
 use crate::snapshot::crc::{CRC64Reader, CRC64Writer};
 pub use crate::snapshot::persist::Persist;
@@ -62,22 +71,73 @@ pub enum SnapshotError {
     Serde(String),
 }
 
+/// Key/value labels stored in the snapshot header.
+pub type SnapshotLabels = BTreeMap<String, String>;
+
+/// First version of the snapshot format whose header carries labels.
+const LABELS_VERSION: Version = Version::new(6, 0, 0);
+
 /// Firecracker snapshot header
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug)]
 struct SnapshotHdr {
     /// magic value
     magic: u64,
     /// Snapshot data version
     version: Version,
+    /// Labels attached to the snapshot
+    labels: SnapshotLabels,
 }
 
 impl SnapshotHdr {
-    fn new(version: Version) -> Self {
+    fn new(version: Version, labels: SnapshotLabels) -> Self {
         Self {
             magic: SNAPSHOT_MAGIC_ID,
             version,
+            labels,
         }
     }
+
+    /// Reads the header from a reader and validates the magic value.
+    ///
+    /// The magic value and the version are read before the labels, which are only
+    /// present in the header of snapshots since [`LABELS_VERSION`].
+    fn read<T>(reader: &mut T, expected_version: Option<&Version>) -> Result<Self, SnapshotError>
+    where
+        T: Read,
+    {
+        let (magic, version): (u64, Version) = Snapshot::deserialize(reader)?;
+        if magic != SNAPSHOT_MAGIC_ID {
+            return Err(SnapshotError::InvalidMagic(magic));
+        }
+        if let Some(expected) = expected_version {
+            if version.major != expected.major || version.minor > expected.minor {
+                return Err(SnapshotError::InvalidFormatVersion(version));
+            }
+        }
+
+        let labels: SnapshotLabels = if Snapshot::has_labels(&version) {
+            Snapshot::deserialize(reader)?
+        } else {
+            SnapshotLabels::new()
+        };
+        Ok(Self {
+            magic,
+            version,
+            labels,
+        })
+    }
+
+    /// Writes the header, without the labels for versions before [`LABELS_VERSION`].
+    fn write<T>(&self, writer: &mut T) -> Result<(), SnapshotError>
+    where
+        T: Write,
+    {
+        Snapshot::serialize(writer, &(self.magic, &self.version))?;
+        if Snapshot::has_labels(&self.version) {
+            Snapshot::serialize(writer, &self.labels)?;
+        }
+        Ok(())
+    }
 }
 
 /// Firecracker snapshot type
@@ -87,12 +147,28 @@ impl SnapshotHdr {
 pub struct Snapshot {
     // The snapshot version we can handle
     version: Version,
+    // Labels written in the header of saved snapshots
+    labels: SnapshotLabels,
 }
 
 impl Snapshot {
     /// Creates a new instance which can only be used to save a new snapshot.
     pub fn new(version: Version) -> Snapshot {
-        Snapshot { version }
+        Snapshot {
+            version,
+            labels: SnapshotLabels::new(),
+        }
+    }
+
+    /// Sets the labels that will be stored in the header of saved snapshots.
+    pub fn with_labels(mut self, labels: SnapshotLabels) -> Snapshot {
+        self.labels = labels;
+        self
+    }
+
+    /// Returns whether the header of snapshots of version `version` carries labels.
+    pub fn has_labels(version: &Version) -> bool {
+        *version >= LABELS_VERSION
     }
 
     /// Fetches snapshot data version.
@@ -100,8 +176,19 @@ impl Snapshot {
     where
         T: Read + Debug,
     {
-        let hdr: SnapshotHdr = Self::deserialize(reader)?;
-        Ok(hdr.version)
+        // Only the magic value and the version are read, so that the version of
+        // snapshots using older header formats can be reported as well.
+        let (_, version): (u64, Version) = Self::deserialize(reader)?;
+        Ok(version)
+    }
+
+    /// Fetches the labels stored in the snapshot header.
+    pub fn get_labels<T>(reader: &mut T) -> Result<SnapshotLabels, SnapshotError>
+    where
+        T: Read + Debug,
+    {
+        let hdr = SnapshotHdr::read(reader, None)?;
+        Ok(hdr.labels)
     }
 
     /// Helper function to deserialize an object from a reader
@@ -132,16 +219,15 @@ impl Snapshot {
     /// Attempts to load an existing snapshot without performing CRC or version validation.
     ///
     /// This will check that the snapshot magic value is correct.
-    fn unchecked_load<T, O>(reader: &mut T) -> Result<(O, Version), SnapshotError>
+    fn unchecked_load<T, O>(
+        reader: &mut T,
+        expected_version: Option<&Version>,
+    ) -> Result<(O, Version), SnapshotError>
     where
         T: Read + Debug,
         O: DeserializeOwned + Debug,
     {
-        let hdr: SnapshotHdr = Self::deserialize(reader)?;
-        if hdr.magic != SNAPSHOT_MAGIC_ID {
-            return Err(SnapshotError::InvalidMagic(hdr.magic));
-        }
-
+        let hdr = SnapshotHdr::read(reader, expected_version)?;
         let data: O = Self::deserialize(reader)?;
         Ok((data, hdr.version))
     }
@@ -151,6 +237,43 @@ impl Snapshot {
     where
         T: Read + Debug,
         O: DeserializeOwned + Debug,
+    {
+        Self::load_inner(reader, snapshot_len, None)
+    }
+
+    fn load_inner<T, O>(
+        reader: &mut T,
+        snapshot_len: usize,
+        expected_version: Option<&Version>,
+    ) -> Result<(O, Version), SnapshotError>
+    where
+        T: Read + Debug,
+        O: DeserializeOwned + Debug,
+    {
+        let snapshot = Self::read_with_crc_check(reader, snapshot_len)?;
+        let mut snapshot_slice: &[u8] = snapshot.as_slice();
+        Snapshot::unchecked_load::<_, O>(&mut snapshot_slice, expected_version)
+    }
+
+    /// Loads the version, the labels and the serialized state of a snapshot, validating its CRC
+    /// but neither its version nor its state, e.g. to rewrite it in another version of the format.
+    pub fn load_raw<T>(
+        reader: &mut T,
+        snapshot_len: usize,
+    ) -> Result<(Vec<u8>, Version, SnapshotLabels), SnapshotError>
+    where
+        T: Read + Debug,
+    {
+        let snapshot = Self::read_with_crc_check(reader, snapshot_len)?;
+        let mut snapshot_slice: &[u8] = snapshot.as_slice();
+        let hdr = SnapshotHdr::read(&mut snapshot_slice, None)?;
+        Ok((snapshot_slice.to_vec(), hdr.version, hdr.labels))
+    }
+
+    /// Reads a whole snapshot, apart from its CRC, which is validated.
+    fn read_with_crc_check<T>(reader: &mut T, snapshot_len: usize) -> Result<Vec<u8>, SnapshotError>
+    where
+        T: Read + Debug,
     {
         let mut crc_reader = CRC64Reader::new(reader);
 
@@ -173,9 +296,7 @@ impl Snapshot {
         if computed_checksum != stored_checksum {
             return Err(SnapshotError::Crc64(computed_checksum));
         }
-
-        let mut snapshot_slice: &[u8] = snapshot.as_mut_slice();
-        Snapshot::unchecked_load::<_, O>(&mut snapshot_slice)
+        Ok(snapshot)
     }
 
     /// Load a snapshot from a reader object and perform a snapshot version check
@@ -188,12 +309,8 @@ impl Snapshot {
         T: Read + Debug,
         O: DeserializeOwned + Debug,
     {
-        let (data, version) = Snapshot::load::<_, O>(reader, snapshot_len)?;
-        if version.major != self.version.major || version.minor > self.version.minor {
-            Err(SnapshotError::InvalidFormatVersion(version))
-        } else {
-            Ok(data)
-        }
+        let (data, _) = Snapshot::load_inner::<_, O>(reader, snapshot_len, Some(&self.version))?;
+        Ok(data)
     }
 
     /// Saves a snapshot and include a CRC64 checksum.
@@ -210,6 +327,22 @@ impl Snapshot {
         Self::serialize(&mut crc_writer, &checksum)
     }
 
+    /// Saves a snapshot whose state is already serialized, e.g. by [`Snapshot::load_raw`], and
+    /// includes a CRC64 checksum.
+    pub fn save_raw<T>(&self, writer: &mut T, state: &[u8]) -> Result<(), SnapshotError>
+    where
+        T: Write + Debug,
+    {
+        let mut crc_writer = CRC64Writer::new(writer);
+        SnapshotHdr::new(self.version.clone(), self.labels.clone()).write(&mut crc_writer)?;
+        crc_writer
+            .write_all(state)
+            .map_err(|ref err| SnapshotError::Io(err.raw_os_error().unwrap_or(libc::EINVAL)))?;
+
+        let checksum = crc_writer.checksum();
+        Self::serialize(&mut crc_writer, &checksum)
+    }
+
     /// Save a snapshot with no CRC64 checksum included.
     pub fn save_without_crc<T, O>(
         &self,
@@ -220,8 +353,8 @@ impl Snapshot {
         T: Write,
         O: Serialize + Debug,
     {
-        // Write magic value and snapshot version
-        Self::serialize(&mut writer, &SnapshotHdr::new(self.version.clone()))?;
+        // Write magic value, snapshot version and labels
+        SnapshotHdr::new(self.version.clone(), self.labels.clone()).write(&mut writer)?;
         // Write data
         Self::serialize(&mut writer, object)
     }
@@ -248,6 +381,101 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_labels() {
+        let labels = SnapshotLabels::from([
+            ("creator".to_string(), "test".to_string()),
+            ("image_id".to_string(), "ami-42".to_string()),
+        ]);
+        let snapshot = Snapshot::new(Version::new(6, 0, 42)).with_labels(labels.clone());
+
+        let mut snapshot_data = vec![0u8; 200];
+        snapshot
+            .save(&mut snapshot_data.as_mut_slice(), &42u8)
+            .unwrap();
+
+        assert_eq!(
+            Snapshot::get_labels(&mut snapshot_data.as_slice()).unwrap(),
+            labels
+        );
+        assert_eq!(
+            Snapshot::get_format_version(&mut snapshot_data.as_slice()).unwrap(),
+            Version::new(6, 0, 42)
+        );
+        let (data, version) =
+            Snapshot::load::<_, u8>(&mut snapshot_data.as_slice(), snapshot_data.len()).unwrap();
+        assert_eq!(data, 42);
+        assert_eq!(version, Version::new(6, 0, 42));
+
+        // Snapshots without labels have an empty label set.
+        let snapshot = Snapshot::new(Version::new(6, 0, 42));
+        snapshot
+            .save(&mut snapshot_data.as_mut_slice(), &42u8)
+            .unwrap();
+        assert!(Snapshot::get_labels(&mut snapshot_data.as_slice())
+            .unwrap()
+            .is_empty());
+
+        // The header of older versions of the format doesn't carry labels.
+        let mut snapshot_data = Vec::new();
+        Snapshot::new(Version::new(5, 0, 0))
+            .with_labels(labels)
+            .save(&mut snapshot_data, &42u8)
+            .unwrap();
+        let mut expected_data = Vec::new();
+        Snapshot::serialize(
+            &mut expected_data,
+            &(SNAPSHOT_MAGIC_ID, Version::new(5, 0, 0), 42u8),
+        )
+        .unwrap();
+        assert_eq!(
+            &snapshot_data[..expected_data.len()],
+            expected_data.as_slice()
+        );
+        assert!(Snapshot::get_labels(&mut snapshot_data.as_slice())
+            .unwrap()
+            .is_empty());
+        let (data, version) =
+            Snapshot::load::<_, u8>(&mut snapshot_data.as_slice(), snapshot_data.len()).unwrap();
+        assert_eq!(data, 42);
+        assert_eq!(version, Version::new(5, 0, 0));
+    }
+
+    #[test]
+    fn test_raw_state() {
+        let labels = SnapshotLabels::from([("creator".to_string(), "test".to_string())]);
+        let mut snapshot_data = Vec::new();
+        Snapshot::new(Version::new(6, 0, 42))
+            .with_labels(labels.clone())
+            .save(&mut snapshot_data, &(42u8, 7u64))
+            .unwrap();
+
+        let (state, version, raw_labels) =
+            Snapshot::load_raw(&mut snapshot_data.as_slice(), snapshot_data.len()).unwrap();
+        assert_eq!(state.len(), 9);
+        assert_eq!(version, Version::new(6, 0, 42));
+        assert_eq!(raw_labels, labels);
+
+        let mut resaved_data = Vec::new();
+        Snapshot::new(Version::new(6, 1, 0))
+            .with_labels(raw_labels)
+            .save_raw(&mut resaved_data, &state)
+            .unwrap();
+        let (data, version) =
+            Snapshot::load::<_, (u8, u64)>(&mut resaved_data.as_slice(), resaved_data.len())
+                .unwrap();
+        assert_eq!(data, (42, 7));
+        assert_eq!(version, Version::new(6, 1, 0));
+
+        // The CRC is still validated.
+        let len = snapshot_data.len();
+        snapshot_data[len - 9] ^= 0xff;
+        assert!(matches!(
+            Snapshot::load_raw(&mut snapshot_data.as_slice(), len),
+            Err(SnapshotError::Crc64(_))
+        ));
+    }
+
     #[test]
     fn test_bad_snapshot_size() {
         let snapshot_data = vec![0u8; 1];
@@ -300,7 +528,7 @@ mod tests {
         data[6] = 0x44;
         data[7] = 0x45;
         assert!(matches!(
-            Snapshot::unchecked_load::<_, u8>(&mut data.as_slice()),
+            Snapshot::unchecked_load::<_, u8>(&mut data.as_slice(), None),
             Err(SnapshotError::InvalidMagic(0x4544_4342_0403_0201u64))
         ));
     }
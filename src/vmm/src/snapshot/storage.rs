@@ -0,0 +1,521 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Defines where snapshot files are stored.
+//!
+//! Snapshot files are read and written through a [`SnapshotStorage`], selected from the path
+//! given to the snapshot API: `http://` URLs are stored on an HTTP server, with range `GET` and
+//! `PUT` requests, e.g. an object storage endpoint, while all the other paths are local files.
+//!
+//! Only plain HTTP is spoken, to servers addressed by IP: HTTPS, host names and the request
+//! signing of S3 are not supported, so object storage is reached through presigned URLs or a
+//! local proxy. Remote memory files are fetched entirely when a snapshot is loaded.
+
+use std::fmt::Debug;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::net::{IpAddr, SocketAddr, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use std::time::Duration;
+
+use vm_memory::bitmap::BitmapSlice;
+use vm_memory::io::{ReadVolatile, WriteVolatile};
+use vm_memory::{VolatileMemoryError, VolatileSlice};
+
+/// Whether snapshots may be created on `http://` URLs, which requires the VMM thread to open TCP
+/// connections once the microVM runs. Set with `--http-snapshot-storage`.
+pub static HTTP_SNAPSHOT_STORAGE: AtomicBool = AtomicBool::new(false);
+
+/// Timeout of the socket operations of the HTTP storage.
+pub const HTTP_STORAGE_TIMEOUT: Duration = Duration::from_secs(30);
+const HTTP_SCHEME: &str = "http://";
+const HTTP_DEFAULT_PORT: u16 = 80;
+// Size of the chunks copied between guest memory and the snapshot file streams.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Errors associated with selecting the storage of a snapshot file.
+#[derive(Debug, PartialEq, Eq, thiserror::Error, displaydoc::Display)]
+pub enum SnapshotStorageError {
+    /// Unsupported snapshot storage {0:?}: only local paths and http:// URLs are supported.
+    UnsupportedScheme(String),
+    /// Invalid snapshot storage URL {0:?}, expected `http://<ip>[:<port>]/<path>`.
+    InvalidUrl(String),
+}
+
+/// Storage of a snapshot file.
+pub trait SnapshotStorage: Debug {
+    /// Returns the path of the snapshot file, if it is stored on the local filesystem.
+    fn local_path(&self) -> Option<&Path>;
+    /// Returns the size of the snapshot file.
+    fn size(&self) -> io::Result<u64>;
+    /// Opens a stream reading `len` bytes of the snapshot file, starting at `offset`.
+    fn read_range(&self, offset: u64, len: u64) -> io::Result<Box<dyn Read>>;
+    /// Opens a stream replacing the snapshot file with `len` bytes.
+    fn create(&self, len: u64) -> io::Result<Box<dyn SnapshotWriter>>;
+}
+
+/// Stream replacing a snapshot file.
+pub trait SnapshotWriter: Write {
+    /// Persists the snapshot file, once all of its bytes are written.
+    fn finish(self: Box<Self>) -> io::Result<()>;
+}
+
+/// Returns the storage of the snapshot file at `path`, which is either a local path or an
+/// `http://` URL.
+pub fn open_storage(path: &Path) -> Result<Box<dyn SnapshotStorage>, SnapshotStorageError> {
+    let Some(url) = path.to_str().filter(|path| path.contains("://")) else {
+        return Ok(Box::new(FileStorage {
+            path: path.to_path_buf(),
+        }));
+    };
+    match url.strip_prefix(HTTP_SCHEME) {
+        Some(location) => HttpStorage::parse(location)
+            .map(|storage| Box::new(storage) as Box<dyn SnapshotStorage>)
+            .ok_or_else(|| SnapshotStorageError::InvalidUrl(url.to_string())),
+        None => Err(SnapshotStorageError::UnsupportedScheme(url.to_string())),
+    }
+}
+
+/// Snapshot file stored on the local filesystem.
+#[derive(Debug)]
+pub struct FileStorage {
+    path: PathBuf,
+}
+
+impl SnapshotStorage for FileStorage {
+    fn local_path(&self) -> Option<&Path> {
+        Some(&self.path)
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(std::fs::metadata(&self.path)?.len())
+    }
+
+    fn read_range(&self, offset: u64, len: u64) -> io::Result<Box<dyn Read>> {
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(offset))?;
+        Ok(Box::new(file.take(len)))
+    }
+
+    fn create(&self, _len: u64) -> io::Result<Box<dyn SnapshotWriter>> {
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&self.path)?;
+        Ok(Box::new(file))
+    }
+}
+
+impl SnapshotWriter for File {
+    fn finish(mut self: Box<Self>) -> io::Result<()> {
+        self.flush()?;
+        self.sync_all()
+    }
+}
+
+/// Snapshot file stored on an HTTP server, read with range `GET` requests and written with a
+/// streaming `PUT` request.
+///
+/// The server is addressed by IP, as host names cannot be resolved once the VMM thread is
+/// sandboxed, and connections are not encrypted.
+#[derive(Debug)]
+pub struct HttpStorage {
+    addr: SocketAddr,
+    authority: String,
+    target: String,
+}
+
+impl HttpStorage {
+    /// Parses the location of an `http://` URL, i.e. `<ip>[:<port>]/<path>`.
+    fn parse(location: &str) -> Option<Self> {
+        let (authority, target) = location.split_at(location.find('/')?);
+        if target.len() < 2 {
+            return None;
+        }
+        let addr = authority.parse::<SocketAddr>().ok().or_else(|| {
+            let host = authority
+                .strip_prefix('[')
+                .and_then(|host| host.strip_suffix(']'))
+                .unwrap_or(authority);
+            host.parse::<IpAddr>()
+                .ok()
+                .map(|ip| SocketAddr::new(ip, HTTP_DEFAULT_PORT))
+        })?;
+        Some(HttpStorage {
+            addr,
+            authority: authority.to_string(),
+            target: target.to_string(),
+        })
+    }
+
+    /// Sends a request, without body, and returns the connection to the server.
+    fn send_request(&self, method: &str, headers: &str) -> io::Result<TcpStream> {
+        let mut stream = TcpStream::connect(self.addr).map_err(|err| {
+            // The seccomp filters of the VMM thread deny TCP sockets unless a feature needs them.
+            if err.kind() == io::ErrorKind::PermissionDenied {
+                io::Error::new(
+                    err.kind(),
+                    "TCP connections are not allowed, see --http-snapshot-storage",
+                )
+            } else {
+                err
+            }
+        })?;
+        stream.set_read_timeout(Some(HTTP_STORAGE_TIMEOUT))?;
+        stream.set_write_timeout(Some(HTTP_STORAGE_TIMEOUT))?;
+        let request = format!(
+            "{} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n{}\r\n",
+            method, self.target, self.authority, headers
+        );
+        stream.write_all(request.as_bytes())?;
+        Ok(stream)
+    }
+}
+
+impl SnapshotStorage for HttpStorage {
+    fn local_path(&self) -> Option<&Path> {
+        None
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        let mut reader = BufReader::new(self.send_request("HEAD", "")?);
+        let head = ResponseHead::read(&mut reader)?;
+        head.check_status(&[200])?;
+        head.content_length
+            .ok_or_else(|| invalid_response("missing Content-Length".to_string()))
+    }
+
+    fn read_range(&self, offset: u64, len: u64) -> io::Result<Box<dyn Read>> {
+        if len == 0 {
+            return Ok(Box::new(io::empty()));
+        }
+        let range = format!("Range: bytes={}-{}\r\n", offset, offset + len - 1);
+        let mut reader = BufReader::new(self.send_request("GET", &range)?);
+        let head = ResponseHead::read(&mut reader)?;
+        // Servers ignoring the range answer with the whole file, which only starts with the
+        // requested range if the range starts at the beginning of the file.
+        if !(head.status == 206 || (head.status == 200 && offset == 0)) {
+            head.check_status(&[206])?;
+        }
+        if let Some(content_len) = head.content_length.filter(|content_len| *content_len < len) {
+            return Err(invalid_response(format!(
+                "{} bytes received for a range of {} bytes",
+                content_len, len
+            )));
+        }
+        Ok(Box::new(reader.take(len)))
+    }
+
+    fn create(&self, len: u64) -> io::Result<Box<dyn SnapshotWriter>> {
+        let headers = format!(
+            "Content-Type: application/octet-stream\r\nContent-Length: {}\r\n",
+            len
+        );
+        let stream = self.send_request("PUT", &headers)?;
+        Ok(Box::new(HttpUpload {
+            stream: BufWriter::with_capacity(CHUNK_SIZE, stream),
+            remaining: len,
+        }))
+    }
+}
+
+/// Body of a `PUT` request replacing a snapshot file on an HTTP server.
+#[derive(Debug)]
+struct HttpUpload {
+    stream: BufWriter<TcpStream>,
+    remaining: u64,
+}
+
+impl Write for HttpUpload {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.len() as u64 > self.remaining {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "write past the announced snapshot file size",
+            ));
+        }
+        let written = self.stream.write(buf)?;
+        self.remaining -= written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+impl SnapshotWriter for HttpUpload {
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        if self.remaining != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("{} bytes of the snapshot file not written", self.remaining),
+            ));
+        }
+        let stream = self.stream.into_inner().map_err(|err| err.into_error())?;
+        let mut reader = BufReader::new(stream);
+        ResponseHead::read(&mut reader)?.check_status(&[200, 201, 204])
+    }
+}
+
+/// Status line and headers of an HTTP response.
+#[derive(Debug, PartialEq, Eq)]
+struct ResponseHead {
+    status: u16,
+    content_length: Option<u64>,
+}
+
+impl ResponseHead {
+    fn read<R: BufRead>(reader: &mut R) -> io::Result<Self> {
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let status = line
+            .strip_prefix("HTTP/1.")
+            .and_then(|line| line.split_whitespace().nth(1))
+            .and_then(|status| status.parse::<u16>().ok())
+            .ok_or_else(|| invalid_response(format!("status line {:?}", line.trim_end())))?;
+
+        let mut content_length = None;
+        loop {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            let header = line.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                if name.trim().eq_ignore_ascii_case("content-length") {
+                    content_length = Some(value.trim().parse::<u64>().map_err(|_| {
+                        invalid_response(format!("Content-Length {:?}", value.trim()))
+                    })?);
+                }
+            }
+        }
+        Ok(ResponseHead {
+            status,
+            content_length,
+        })
+    }
+
+    fn check_status(&self, expected: &[u16]) -> io::Result<()> {
+        if expected.contains(&self.status) {
+            Ok(())
+        } else {
+            Err(io::Error::other(format!(
+                "HTTP snapshot storage answered with status {}",
+                self.status
+            )))
+        }
+    }
+}
+
+fn invalid_response(what: String) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Invalid HTTP snapshot storage response: {}", what),
+    )
+}
+
+/// Reads guest memory from a snapshot file stream.
+#[derive(Debug)]
+pub struct VolatileReader<R>(pub R);
+
+impl<R: Read> ReadVolatile for VolatileReader<R> {
+    fn read_volatile<B: BitmapSlice>(
+        &mut self,
+        buf: &mut VolatileSlice<B>,
+    ) -> Result<usize, VolatileMemoryError> {
+        let mut chunk = [0u8; CHUNK_SIZE];
+        let len = buf.len().min(CHUNK_SIZE);
+        let read = self
+            .0
+            .read(&mut chunk[..len])
+            .map_err(VolatileMemoryError::IOError)?;
+        buf.copy_from(&chunk[..read]);
+        Ok(read)
+    }
+}
+
+/// Writes guest memory to a snapshot file stream.
+#[derive(Debug)]
+pub struct VolatileWriter<W>(pub W);
+
+impl<W: Write> WriteVolatile for VolatileWriter<W> {
+    fn write_volatile<B: BitmapSlice>(
+        &mut self,
+        buf: &VolatileSlice<B>,
+    ) -> Result<usize, VolatileMemoryError> {
+        let mut chunk = [0u8; CHUNK_SIZE];
+        let len = buf.copy_to(&mut chunk[..buf.len().min(CHUNK_SIZE)]);
+        self.0
+            .write_all(&chunk[..len])
+            .map_err(VolatileMemoryError::IOError)?;
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::thread;
+
+    use vmm_sys_util::tempfile::TempFile;
+
+    use super::*;
+
+    // Serves a single request with `response`, and returns the request.
+    fn serve_once(response: &'static [u8]) -> (String, thread::JoinHandle<Vec<u8>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/bucket/snapshot", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut request = Vec::new();
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if let Some(len) = line.strip_prefix("Content-Length: ") {
+                    content_length = len.trim().parse().unwrap();
+                }
+                request.extend_from_slice(line.as_bytes());
+                if line == "\r\n" {
+                    break;
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+            request.extend_from_slice(&body);
+            reader.get_mut().write_all(response).unwrap();
+            request
+        });
+        (url, server)
+    }
+
+    #[test]
+    fn test_open_storage() {
+        let storage = open_storage(Path::new("/tmp/snapshot")).unwrap();
+        assert_eq!(storage.local_path(), Some(Path::new("/tmp/snapshot")));
+
+        for url in [
+            "http://10.0.0.1/snapshot",
+            "http://10.0.0.1:9000/bucket/snapshot?X-Amz-Signature=0",
+            "http://[::1]:9000/snapshot",
+            "http://[::1]/snapshot",
+        ] {
+            assert!(open_storage(Path::new(url)).unwrap().local_path().is_none());
+        }
+        for url in [
+            "http://10.0.0.1",
+            "http://10.0.0.1/",
+            "http://storage.local/snapshot",
+            "http://10.0.0.1:port/snapshot",
+        ] {
+            assert_eq!(
+                open_storage(Path::new(url)).unwrap_err(),
+                SnapshotStorageError::InvalidUrl(url.to_string())
+            );
+        }
+        assert_eq!(
+            open_storage(Path::new("https://10.0.0.1/snapshot")).unwrap_err(),
+            SnapshotStorageError::UnsupportedScheme("https://10.0.0.1/snapshot".to_string())
+        );
+    }
+
+    #[test]
+    fn test_file_storage() {
+        let file = TempFile::new().unwrap();
+        let storage = open_storage(file.as_path()).unwrap();
+
+        let mut writer = storage.create(8).unwrap();
+        writer.write_all(b"snapshot").unwrap();
+        writer.finish().unwrap();
+        assert_eq!(storage.size().unwrap(), 8);
+
+        let mut content = String::new();
+        storage
+            .read_range(4, 3)
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "sho");
+    }
+
+    #[test]
+    fn test_http_storage_read() {
+        let (url, server) = serve_once(b"HTTP/1.1 200 OK\r\nContent-Length: 8\r\n\r\n");
+        let storage = open_storage(Path::new(&url)).unwrap();
+        assert_eq!(storage.size().unwrap(), 8);
+        let request = String::from_utf8(server.join().unwrap()).unwrap();
+        assert!(request.starts_with("HEAD /bucket/snapshot HTTP/1.1\r\n"));
+
+        let (url, server) =
+            serve_once(b"HTTP/1.1 206 Partial Content\r\ncontent-length: 3\r\n\r\nsho");
+        let storage = open_storage(Path::new(&url)).unwrap();
+        let mut content = String::new();
+        storage
+            .read_range(4, 3)
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "sho");
+        let request = String::from_utf8(server.join().unwrap()).unwrap();
+        assert!(request.starts_with("GET /bucket/snapshot HTTP/1.1\r\n"));
+        assert!(request.contains("Range: bytes=4-6\r\n"));
+
+        // Servers ignoring the range of a request not starting at the beginning of the file.
+        let (url, server) = serve_once(b"HTTP/1.1 200 OK\r\nContent-Length: 8\r\n\r\nsnapshot");
+        let storage = open_storage(Path::new(&url)).unwrap();
+        storage.read_range(4, 3).err().unwrap();
+        server.join().unwrap();
+
+        let (url, server) = serve_once(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n");
+        let storage = open_storage(Path::new(&url)).unwrap();
+        storage.size().unwrap_err();
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_http_storage_write() {
+        let (url, server) = serve_once(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n");
+        let storage = open_storage(Path::new(&url)).unwrap();
+        let mut writer = storage.create(8).unwrap();
+        writer.write_all(b"snap").unwrap();
+        writer.write_all(b"shots").unwrap_err();
+        writer.write_all(b"shot").unwrap();
+        writer.finish().unwrap();
+        let request = String::from_utf8(server.join().unwrap()).unwrap();
+        assert!(request.starts_with("PUT /bucket/snapshot HTTP/1.1\r\n"));
+        assert!(request.ends_with("Content-Length: 8\r\n\r\nsnapshot"));
+
+        // The upload fails without waiting for the server if the file is incomplete.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/snapshot", listener.local_addr().unwrap());
+        let storage = open_storage(Path::new(&url)).unwrap();
+        let mut writer = storage.create(8).unwrap();
+        writer.write_all(b"snap").unwrap();
+        writer.finish().unwrap_err();
+    }
+
+    #[test]
+    fn test_volatile_adapters() {
+        let mut memory = vec![0u8; 2 * CHUNK_SIZE + 1];
+        let content: Vec<u8> = (0..memory.len()).map(|i| i as u8).collect();
+
+        let mut reader = VolatileReader(content.as_slice());
+        reader
+            .read_exact_volatile(&mut VolatileSlice::from(memory.as_mut_slice()))
+            .unwrap();
+        assert_eq!(memory, content);
+
+        let mut writer = VolatileWriter(Vec::new());
+        writer
+            .write_all_volatile(&VolatileSlice::from(memory.as_mut_slice()))
+            .unwrap();
+        assert_eq!(writer.0, content);
+    }
+}
@@ -0,0 +1,641 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Directory in which Firecracker records the configuration, the device backends and the
+//! lifecycle state of its microVM, so that the host can find out what a crashed instance was
+//! doing and clean up after it.
+//!
+//! Each file of the directory is replaced atomically: it is written to a temporary file which is
+//! synced and then renamed over the previous version. A crash therefore leaves either the old or
+//! the new version of a file, never a partially written one.
+
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+use serde::{Deserialize, Serialize};
+use utils::time::{get_time_us, ClockType};
+
+use crate::logger::warn;
+use crate::resources::{VmResources, VmmConfig};
+use crate::rpc_interface::VmmAction;
+use crate::vmm_config::instance_info::{InstanceInfo, VmState};
+
+/// Name of the file holding the [`InstanceState`].
+pub const STATE_FILE: &str = "state.json";
+/// Name of the file holding the microVM configuration, in the format of `--config-file`.
+pub const CONFIG_FILE: &str = "config.json";
+
+/// Global state directory of the process, disabled unless initialized.
+pub static STATE_DIR: StateDir = StateDir::new();
+
+/// Errors associated with the state directory.
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum StateDirError {
+    /// The state directory is already initialized.
+    AlreadyInitialized,
+    /// Failed to create the state directory: {0}
+    CreateDir(io::Error),
+    /// Failed to write {0}: {1}
+    Write(&'static str, io::Error),
+    /// Failed to read the state file: {0}
+    Read(io::Error),
+    /// Invalid state file: {0}
+    Parse(serde_json::Error),
+}
+
+/// Mutating action being handled when the state was recorded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingAction {
+    /// Name of the action.
+    pub action: String,
+    /// Host files written by the action, which may be left incomplete if it was interrupted.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<PathBuf>,
+    /// Wall clock time at which the action started, in microseconds.
+    pub started_at_us: u64,
+}
+
+/// Result of the last completed mutating action.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ActionOutcome {
+    /// Name of the action.
+    pub action: String,
+    /// Error returned by the action, if it failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Wall clock time at which the action completed, in microseconds.
+    pub finished_at_us: u64,
+}
+
+/// Kind of host resource backing a device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackendKind {
+    /// Backing file of a block device.
+    DriveFile,
+    /// Socket of a vhost-user block device.
+    VhostUserSocket,
+    /// Tap device of a network interface.
+    Tap,
+    /// Unix socket of the vsock device.
+    VsockSocket,
+    /// Socket of the TPM emulator.
+    TpmSocket,
+    /// File to which the serial console is written.
+    SerialFile,
+    /// File backing a shared memory region.
+    SharedMemoryFile,
+}
+
+/// Host resource backing a device of the microVM.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceBackend {
+    /// Id of the device, if it has one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device_id: Option<String>,
+    /// Kind of the resource.
+    pub kind: BackendKind,
+    /// Path or name of the resource on the host.
+    pub path: String,
+}
+
+/// Returns the host resources backing the devices configured in `resources`.
+pub fn device_backends(resources: &VmResources) -> Vec<DeviceBackend> {
+    let backend = |device_id: Option<&str>, kind, path: &str| DeviceBackend {
+        device_id: device_id.map(str::to_string),
+        kind,
+        path: path.to_string(),
+    };
+    let mut backends = Vec::new();
+    for drive in resources.block.configs() {
+        if let Some(path) = &drive.path_on_host {
+            backends.push(backend(Some(&drive.drive_id), BackendKind::DriveFile, path));
+        }
+        if let Some(socket) = &drive.socket {
+            backends.push(backend(
+                Some(&drive.drive_id),
+                BackendKind::VhostUserSocket,
+                socket,
+            ));
+        }
+    }
+    for iface in resources.net_builder.configs() {
+        backends.push(backend(
+            Some(&iface.iface_id),
+            BackendKind::Tap,
+            &iface.host_dev_name,
+        ));
+    }
+    if let Some(vsock) = resources.vsock.config() {
+        backends.push(backend(
+            vsock.vsock_id.as_deref(),
+            BackendKind::VsockSocket,
+            &vsock.uds_path,
+        ));
+    }
+    if let Some(tpm) = &resources.tpm {
+        backends.push(backend(
+            None,
+            BackendKind::TpmSocket,
+            &tpm.socket.to_string_lossy(),
+        ));
+    }
+    if let Some(path) = &resources.serial.path {
+        backends.push(backend(None, BackendKind::SerialFile, path));
+    }
+    for segment in resources.shared_memory.configs() {
+        backends.push(backend(
+            Some(&segment.segment_id),
+            BackendKind::SharedMemoryFile,
+            &segment.path_on_host,
+        ));
+    }
+    backends
+}
+
+/// Returns the action recorded as pending while `action` is handled, or `None` if `action` does
+/// not change the microVM.
+fn pending_action(action: &VmmAction) -> Option<PendingAction> {
+    use VmmAction::*;
+
+    let (name, files) = match action {
+        CheckSnapshotCompatibility(_)
+        | FlushMetrics
+        | GetBalloonConfig
+        | GetBalloonStats
+        | GetBootTimings
+        | GetFullVmConfig
+        | GetMMDS
+        | GetMemorySlots
+        | GetMemoryTarget
+        | GetMmdsGuestData
+        | GetMmdsSessions
+        | GetNetworkFlows(_)
+        | GetProcessInfo
+        | GetSerialLog
+        | GetSharedRateLimiters
+        | GetSnapshotStatus
+        | GetVmInstanceInfo
+        | GetVmMachineConfig
+        | GetVmmVersion
+        | ValidateCpuConfiguration(_) => return None,
+        ConfigureBootSource(_) => ("ConfigureBootSource", vec![]),
+        ConfigureLogger(_) => ("ConfigureLogger", vec![]),
+        ConfigureMetrics(_) => ("ConfigureMetrics", vec![]),
+        CreateSnapshot(params) => (
+            "CreateSnapshot",
+            vec![params.snapshot_path.clone(), params.mem_file_path.clone()],
+        ),
+        InsertBlockDevice(_) => ("InsertBlockDevice", vec![]),
+        InsertNetworkDevice(_) => ("InsertNetworkDevice", vec![]),
+        InsertSharedMemory(_) => ("InsertSharedMemory", vec![]),
+        InsertSharedRateLimiter(_) => ("InsertSharedRateLimiter", vec![]),
+        InstallSeccompFilters(_) => ("InstallSeccompFilters", vec![]),
+        LoadSnapshot(_) => ("LoadSnapshot", vec![]),
+        PatchMMDS(_) => ("PatchMMDS", vec![]),
+        Pause => ("Pause", vec![]),
+        PutMMDS(_) => ("PutMMDS", vec![]),
+        PutCpuConfiguration(_) => ("PutCpuConfiguration", vec![]),
+        Resume => ("Resume", vec![]),
+        RevokeMmdsSession(_) => ("RevokeMmdsSession", vec![]),
+        SetBalloonDevice(_) => ("SetBalloonDevice", vec![]),
+        SetCpuLimiter(_) => ("SetCpuLimiter", vec![]),
+        SetLifecycleHooks(_) => ("SetLifecycleHooks", vec![]),
+        SetMmdsConfiguration(_) => ("SetMmdsConfiguration", vec![]),
+        SetSerialConfiguration(_) => ("SetSerialConfiguration", vec![]),
+        SetSmbiosConfiguration(_) => ("SetSmbiosConfiguration", vec![]),
+        SetTpmDevice(_) => ("SetTpmDevice", vec![]),
+        SetPvPanicDevice(_) => ("SetPvPanicDevice", vec![]),
+        SetVsockDevice(_) => ("SetVsockDevice", vec![]),
+        SetEntropyDevice(_) => ("SetEntropyDevice", vec![]),
+        StartMicroVm => ("StartMicroVm", vec![]),
+        #[cfg(target_arch = "x86_64")]
+        SendCtrlAltDel => ("SendCtrlAltDel", vec![]),
+        SendShutdown(_) => ("SendShutdown", vec![]),
+        UpdateBalloon(_) => ("UpdateBalloon", vec![]),
+        UpdateBalloonStatistics(_) => ("UpdateBalloonStatistics", vec![]),
+        UpdateBlockDevice(_) => ("UpdateBlockDevice", vec![]),
+        UpdateCpuLimiter(_) => ("UpdateCpuLimiter", vec![]),
+        UpdateMemoryTarget(_) => ("UpdateMemoryTarget", vec![]),
+        UpdateNetworkCapture(..) => ("UpdateNetworkCapture", vec![]),
+        UpdateNetworkInterface(_) => ("UpdateNetworkInterface", vec![]),
+        UpdateVmConfiguration(_) => ("UpdateVmConfiguration", vec![]),
+    };
+    Some(PendingAction {
+        action: name.to_string(),
+        files,
+        started_at_us: get_time_us(ClockType::Real),
+    })
+}
+
+/// Last known state of a Firecracker process and of its microVM.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InstanceState {
+    /// Id of the microVM.
+    pub id: String,
+    /// Version of Firecracker.
+    pub vmm_version: String,
+    /// Pid of the Firecracker process.
+    pub pid: u32,
+    /// Path of the API socket, if the API server is enabled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_socket: Option<PathBuf>,
+    /// Lifecycle state of the microVM.
+    pub state: String,
+    /// Mutating action being handled, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pending_action: Option<PendingAction>,
+    /// Last completed mutating action, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_action: Option<ActionOutcome>,
+    /// Host resources backing the devices of the microVM.
+    #[serde(default)]
+    pub backends: Vec<DeviceBackend>,
+    /// Exit code of the process, if it exited.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<u8>,
+    /// Number of times the state was recorded.
+    pub generation: u64,
+    /// Wall clock time at which the process started, in microseconds.
+    pub started_at_us: u64,
+    /// Wall clock time at which the state was recorded, in microseconds.
+    pub updated_at_us: u64,
+}
+
+/// Writes `contents` to the file `name` of `dir`, replacing it atomically.
+fn write_atomically(dir: &Path, name: &str, contents: &[u8]) -> io::Result<()> {
+    let tmp_path = dir.join(format!("{name}.tmp"));
+    let mut file = File::create(&tmp_path)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    fs::rename(&tmp_path, dir.join(name))?;
+    // The rename itself is only durable once the directory is synced.
+    File::open(dir)?.sync_all()
+}
+
+/// Records the state of the process in a state directory.
+#[derive(Debug)]
+pub struct StateRecorder {
+    path: PathBuf,
+    state: InstanceState,
+}
+
+impl StateRecorder {
+    /// Creates the state directory `path` if needed, and records the initial state of the
+    /// process in it. The configuration recorded by a previous process is removed.
+    pub fn new(
+        path: PathBuf,
+        instance_info: &InstanceInfo,
+        api_socket: Option<PathBuf>,
+    ) -> Result<Self, StateDirError> {
+        fs::create_dir_all(&path).map_err(StateDirError::CreateDir)?;
+        match fs::remove_file(path.join(CONFIG_FILE)) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                return Err(StateDirError::Write(CONFIG_FILE, err));
+            }
+            _ => (),
+        }
+        let now = get_time_us(ClockType::Real);
+        let mut recorder = StateRecorder {
+            path,
+            state: InstanceState {
+                id: instance_info.id.clone(),
+                vmm_version: instance_info.vmm_version.clone(),
+                pid: std::process::id(),
+                api_socket,
+                state: instance_info.state.to_string(),
+                pending_action: None,
+                last_action: None,
+                backends: Vec::new(),
+                exit_code: None,
+                generation: 0,
+                started_at_us: now,
+                updated_at_us: now,
+            },
+        };
+        recorder.write_state()?;
+        Ok(recorder)
+    }
+
+    /// Returns the recorded state.
+    pub fn state(&self) -> &InstanceState {
+        &self.state
+    }
+
+    fn write_state(&mut self) -> Result<(), StateDirError> {
+        self.state.generation += 1;
+        self.state.updated_at_us = get_time_us(ClockType::Real);
+        // Serializing plain data to JSON can not fail.
+        let contents = serde_json::to_vec_pretty(&self.state).unwrap();
+        write_atomically(&self.path, STATE_FILE, &contents)
+            .map_err(|err| StateDirError::Write(STATE_FILE, err))
+    }
+
+    fn write_config(&self, resources: &VmResources) -> Result<(), StateDirError> {
+        let contents = serde_json::to_vec_pretty(&VmmConfig::from(resources)).unwrap();
+        write_atomically(&self.path, CONFIG_FILE, &contents)
+            .map_err(|err| StateDirError::Write(CONFIG_FILE, err))
+    }
+
+    /// Records that `action` is being handled, unless it does not change the microVM.
+    pub fn start_action(&mut self, action: &VmmAction) -> Result<(), StateDirError> {
+        match pending_action(action) {
+            Some(pending) => {
+                self.state.pending_action = Some(pending);
+                self.write_state()
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Records the completion of the pending action, along with the resulting state and
+    /// configuration of the microVM.
+    pub fn finish_action(
+        &mut self,
+        error: Option<String>,
+        vm_state: &VmState,
+        resources: &VmResources,
+    ) -> Result<(), StateDirError> {
+        let Some(pending) = self.state.pending_action.take() else {
+            return Ok(());
+        };
+        self.state.last_action = Some(ActionOutcome {
+            action: pending.action,
+            error,
+            finished_at_us: get_time_us(ClockType::Real),
+        });
+        self.record_vm(vm_state, resources)
+    }
+
+    /// Records the state and configuration of the microVM.
+    pub fn record_vm(
+        &mut self,
+        vm_state: &VmState,
+        resources: &VmResources,
+    ) -> Result<(), StateDirError> {
+        // The configuration is written first, so that the state never refers to backends which
+        // are missing from it.
+        self.write_config(resources)?;
+        self.state.state = vm_state.to_string();
+        self.state.backends = device_backends(resources);
+        self.write_state()
+    }
+
+    /// Records the exit code of the process.
+    pub fn record_exit(&mut self, exit_code: u8) -> Result<(), StateDirError> {
+        self.state.exit_code = Some(exit_code);
+        self.write_state()
+    }
+}
+
+/// Process-wide [`StateRecorder`], which does nothing until initialized.
+///
+/// Failures to record the state are logged, as they must not fail the operation being recorded.
+#[derive(Debug)]
+pub struct StateDir {
+    recorder: OnceLock<Mutex<StateRecorder>>,
+}
+
+impl StateDir {
+    /// Creates an uninitialized state directory.
+    pub const fn new() -> Self {
+        StateDir {
+            recorder: OnceLock::new(),
+        }
+    }
+
+    /// Starts recording the state of the process in the directory `path`.
+    pub fn init(
+        &self,
+        path: PathBuf,
+        instance_info: &InstanceInfo,
+        api_socket: Option<PathBuf>,
+    ) -> Result<(), StateDirError> {
+        let recorder = StateRecorder::new(path, instance_info, api_socket)?;
+        self.recorder
+            .set(Mutex::new(recorder))
+            .map_err(|_| StateDirError::AlreadyInitialized)
+    }
+
+    /// Returns the path of the state directory, if it is initialized.
+    pub fn path(&self) -> Option<PathBuf> {
+        self.recorder
+            .get()
+            .map(|recorder| recorder.lock().expect("Poisoned lock").path.clone())
+    }
+
+    fn record(&self, f: impl FnOnce(&mut StateRecorder) -> Result<(), StateDirError>) {
+        if let Some(recorder) = self.recorder.get() {
+            if let Err(err) = f(&mut recorder.lock().expect("Poisoned lock")) {
+                warn!("Failed to record the state of the microVM: {err}");
+            }
+        }
+    }
+
+    /// See [`StateRecorder::start_action`]. Returns whether the action is recorded, in which case
+    /// its completion must be recorded with [`StateDir::finish_action`].
+    pub fn start_action(&self, action: &VmmAction) -> bool {
+        let recorded = self.recorder.get().is_some() && pending_action(action).is_some();
+        if recorded {
+            self.record(|recorder| recorder.start_action(action));
+        }
+        recorded
+    }
+
+    /// See [`StateRecorder::finish_action`].
+    pub fn finish_action(
+        &self,
+        error: Option<String>,
+        vm_state: &VmState,
+        resources: &VmResources,
+    ) {
+        self.record(|recorder| recorder.finish_action(error, vm_state, resources));
+    }
+
+    /// See [`StateRecorder::record_vm`].
+    pub fn record_vm(&self, vm_state: &VmState, resources: &VmResources) {
+        self.record(|recorder| recorder.record_vm(vm_state, resources));
+    }
+
+    /// See [`StateRecorder::record_exit`].
+    pub fn record_exit(&self, exit_code: u8) {
+        self.record(|recorder| recorder.record_exit(exit_code));
+    }
+}
+
+impl Default for StateDir {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// What became of the process which recorded a state directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProcessOutcome {
+    /// The process is still running.
+    Running,
+    /// The process exited with the recorded exit code.
+    Exited,
+    /// The process stopped without recording an exit code.
+    Crashed,
+}
+
+/// Report on the process which recorded a state directory, printed by `--recover`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RecoveryReport {
+    /// What became of the process.
+    pub outcome: ProcessOutcome,
+    /// Action interrupted by the end of the process, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interrupted_action: Option<PendingAction>,
+    /// Whether the configuration of the microVM was recorded.
+    pub config_recorded: bool,
+    /// Last recorded state.
+    #[serde(flatten)]
+    pub state: InstanceState,
+}
+
+/// Returns whether a process with the given pid exists.
+fn process_alive(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    // SAFETY: Sending signal 0 only checks whether the process exists and can be signaled.
+    let ret = unsafe { libc::kill(pid, 0) };
+    ret == 0 || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// Reads the state directory `path` and reports what the process which recorded it was doing.
+///
+/// The process is considered running while a process with the recorded pid exists, which may be
+/// wrong once the pid is reused.
+pub fn recover(path: &Path) -> Result<RecoveryReport, StateDirError> {
+    let contents = fs::read(path.join(STATE_FILE)).map_err(StateDirError::Read)?;
+    let state: InstanceState = serde_json::from_slice(&contents).map_err(StateDirError::Parse)?;
+    let outcome = if state.exit_code.is_some() {
+        ProcessOutcome::Exited
+    } else if process_alive(state.pid) {
+        ProcessOutcome::Running
+    } else {
+        ProcessOutcome::Crashed
+    };
+    Ok(RecoveryReport {
+        outcome,
+        interrupted_action: match outcome {
+            ProcessOutcome::Running => None,
+            _ => state.pending_action.clone(),
+        },
+        config_recorded: path.join(CONFIG_FILE).exists(),
+        state,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use vmm_sys_util::tempdir::TempDir;
+
+    use super::*;
+    use crate::vmm_config::drive::BlockDeviceConfig;
+
+    fn instance_info() -> InstanceInfo {
+        InstanceInfo {
+            id: "test-vm".to_string(),
+            vmm_version: "1.0.0".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_record_actions() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.as_path().join("state");
+        let mut recorder = StateRecorder::new(path.clone(), &instance_info(), None).unwrap();
+        assert_eq!(recorder.state().generation, 1);
+
+        // Read-only actions are not recorded.
+        recorder
+            .start_action(&VmmAction::GetVmInstanceInfo)
+            .unwrap();
+        assert_eq!(recorder.state().generation, 1);
+
+        recorder
+            .start_action(&VmmAction::InsertBlockDevice(BlockDeviceConfig::default()))
+            .unwrap();
+        // The process stops while handling the action, which was not recorded as finished.
+        let report = recover(&path).unwrap();
+        assert_eq!(report.outcome, ProcessOutcome::Running);
+        assert!(report.interrupted_action.is_none());
+        assert_eq!(
+            report.state.pending_action.unwrap().action,
+            "InsertBlockDevice"
+        );
+        assert!(!report.config_recorded);
+
+        let mut resources = VmResources::default();
+        resources.serial.path = Some("/tmp/serial.log".to_string());
+        recorder
+            .finish_action(Some("error".to_string()), &VmState::NotStarted, &resources)
+            .unwrap();
+        let report = recover(&path).unwrap();
+        assert!(report.state.pending_action.is_none());
+        let last_action = report.state.last_action.unwrap();
+        assert_eq!(last_action.action, "InsertBlockDevice");
+        assert_eq!(last_action.error.as_deref(), Some("error"));
+        assert_eq!(
+            report.state.backends,
+            vec![DeviceBackend {
+                device_id: None,
+                kind: BackendKind::SerialFile,
+                path: "/tmp/serial.log".to_string(),
+            }]
+        );
+        assert!(report.config_recorded);
+        // The configuration can be given back to Firecracker.
+        serde_json::from_slice::<VmmConfig>(&fs::read(path.join(CONFIG_FILE)).unwrap()).unwrap();
+
+        recorder.record_exit(0).unwrap();
+        let report = recover(&path).unwrap();
+        assert_eq!(report.outcome, ProcessOutcome::Exited);
+        assert_eq!(report.state.exit_code, Some(0));
+        assert_eq!(report.state.generation, 4);
+
+        // No temporary file is left behind.
+        let mut files: Vec<_> = fs::read_dir(&path)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        files.sort();
+        assert_eq!(files, [CONFIG_FILE, STATE_FILE]);
+
+        // A new process starts from a clean state.
+        StateRecorder::new(path.clone(), &instance_info(), None).unwrap();
+        let report = recover(&path).unwrap();
+        assert!(report.state.last_action.is_none());
+        assert!(!report.config_recorded);
+    }
+
+    #[test]
+    fn test_recover_crashed() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.as_path().to_path_buf();
+        let mut recorder = StateRecorder::new(path.clone(), &instance_info(), None).unwrap();
+        recorder.start_action(&VmmAction::Pause).unwrap();
+
+        // Pretend that the state was recorded by a process which no longer exists.
+        let mut state = recorder.state().clone();
+        state.pid = u32::try_from(libc::pid_t::MAX).unwrap();
+        fs::write(path.join(STATE_FILE), serde_json::to_vec(&state).unwrap()).unwrap();
+        let report = recover(&path).unwrap();
+        assert_eq!(report.outcome, ProcessOutcome::Crashed);
+        assert_eq!(report.interrupted_action.unwrap().action, "Pause");
+
+        fs::write(path.join(STATE_FILE), b"{").unwrap();
+        assert!(matches!(
+            recover(&path).unwrap_err(),
+            StateDirError::Parse(_)
+        ));
+    }
+}
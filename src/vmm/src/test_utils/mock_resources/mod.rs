@@ -45,11 +45,16 @@ impl MockBootSourceConfig {
             kernel_image_path: kernel_image_path(None),
             initrd_path: None,
             boot_args: None,
+            kernel_image_fd: None,
+            initrd_fd: None,
+            firmware_path: None,
+            kernel_digest: None,
+            initrd_digest: None,
         })
     }
 
     pub fn with_default_boot_args(mut self) -> Self {
-        self.0.boot_args = Some(DEFAULT_BOOT_ARGS.to_string());
+        self.0.boot_args = Some(DEFAULT_BOOT_ARGS.to_string().into());
         self
     }
 
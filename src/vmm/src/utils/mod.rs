@@ -10,9 +10,13 @@ pub mod signal;
 /// Module with state machine
 pub mod sm;
 
+use std::fs::File;
 use std::num::Wrapping;
+use std::os::fd::{FromRawFd, RawFd};
 use std::result::Result;
 
+use vmm_sys_util::syscall::SyscallReturnCode;
+
 /// Return the default page size of the platform, in bytes.
 pub fn get_page_size() -> Result<usize, vmm_sys_util::errno::Error> {
     // SAFETY: Safe because the parameters are valid.
@@ -40,6 +44,28 @@ pub const fn usize_to_u64(num: usize) -> u64 {
     num as u64
 }
 
+/// Opens a file passed as a file descriptor inherited by the process. The descriptor is
+/// duplicated, so that it stays open, and can be used again, e.g. if the boot source or device
+/// using it is reconfigured.
+pub fn file_from_fd(fd: RawFd) -> std::io::Result<File> {
+    // SAFETY: `fcntl` does not access memory, and fails with `EBADF` if `fd` is not open.
+    let dup_fd =
+        SyscallReturnCode(unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 0) }).into_result()?;
+    // SAFETY: `dup_fd` is a valid file descriptor, not owned by anything else.
+    Ok(unsafe { File::from_raw_fd(dup_fd) })
+}
+
+/// Returns the release of the host kernel, e.g. `6.1.102`.
+pub fn host_kernel_version() -> std::io::Result<String> {
+    // SAFETY: An all-zeroed value for `libc::utsname` is valid.
+    let mut name: libc::utsname = unsafe { std::mem::zeroed() };
+    // SAFETY: The passed arg is a valid mutable reference of `libc::utsname`.
+    SyscallReturnCode(unsafe { libc::uname(&mut name) }).into_empty_result()?;
+    // SAFETY: The fields of `libc::utsname` are terminated by a null byte.
+    let release = unsafe { std::ffi::CStr::from_ptr(name.release.as_ptr()) };
+    Ok(release.to_string_lossy().into_owned())
+}
+
 /// Converts a usize into a wrapping u32.
 #[inline]
 pub const fn wrap_usize_to_u32(num: usize) -> Wrapping<u32> {
@@ -5,7 +5,9 @@ use std::sync::{Arc, Mutex};
 
 use serde::{Deserialize, Serialize};
 
-pub use crate::devices::virtio::balloon::device::BalloonStats;
+pub use crate::devices::virtio::balloon::device::{
+    BalloonAutoTargetConfig, BalloonStats, BalloonStatsPushConfig,
+};
 pub use crate::devices::virtio::balloon::BALLOON_DEV_ID;
 use crate::devices::virtio::balloon::{Balloon, BalloonConfig};
 
@@ -24,12 +26,16 @@ pub enum BalloonConfigError {
     TooManyPagesRequested,
     /// Statistics for the balloon device are not enabled
     StatsNotFound,
+    /// Statistics cannot be pushed when they are not enabled.
+    StatsPushDisabled,
     /// Error creating the balloon device: {0}
     CreateFailure(crate::devices::virtio::balloon::BalloonError),
     /// Error updating the balloon device configuration: {0}
     UpdateFailure(std::io::Error),
     /// Firecracker's huge pages support is incompatible with memory ballooning.
     HugePages,
+    /// Guest memory backed by guest_memfd is incompatible with memory ballooning.
+    GuestMemfd,
 }
 
 /// This struct represents the strongly typed equivalent of the json body
@@ -44,6 +50,12 @@ pub struct BalloonDeviceConfig {
     /// Interval in seconds between refreshing statistics.
     #[serde(default)]
     pub stats_polling_interval_s: u16,
+    /// Destinations to which the statistics are pushed after each refresh.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stats_push: Option<BalloonStatsPushConfig>,
+    /// Bounds within which the target size is adjusted after each refresh.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auto_target: Option<BalloonAutoTargetConfig>,
 }
 
 impl From<BalloonConfig> for BalloonDeviceConfig {
@@ -52,6 +64,8 @@ impl From<BalloonConfig> for BalloonDeviceConfig {
             amount_mib: state.amount_mib,
             deflate_on_oom: state.deflate_on_oom,
             stats_polling_interval_s: state.stats_polling_interval_s,
+            stats_push: state.stats_push,
+            auto_target: state.auto_target,
         }
     }
 }
@@ -74,6 +88,14 @@ pub struct BalloonUpdateConfig {
 pub struct BalloonUpdateStatsConfig {
     /// Interval in seconds between refreshing statistics.
     pub stats_polling_interval_s: u16,
+    /// Destinations to which the statistics are pushed after each refresh, replacing the
+    /// previous ones if given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stats_push: Option<BalloonStatsPushConfig>,
+    /// Bounds within which the target size is adjusted after each refresh, replacing the
+    /// previous ones if given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auto_target: Option<BalloonAutoTargetConfig>,
 }
 
 /// A builder for `Balloon` devices from 'BalloonDeviceConfig'.
@@ -92,14 +114,20 @@ impl BalloonBuilder {
     /// Inserts a Balloon device in the store.
     /// If an entry already exists, it will overwrite it.
     pub fn set(&mut self, cfg: BalloonDeviceConfig) -> Result<(), BalloonConfigError> {
-        self.inner = Some(Arc::new(Mutex::new(Balloon::new(
+        if cfg.stats_push.is_some() && cfg.stats_polling_interval_s == 0 {
+            return Err(BalloonConfigError::StatsPushDisabled);
+        }
+        let mut balloon = Balloon::new(
             cfg.amount_mib,
             cfg.deflate_on_oom,
             cfg.stats_polling_interval_s,
             // `restored` flag is false because this code path
             // is never called by snapshot restore functionality.
             false,
-        )?)));
+        )?;
+        balloon.set_stats_push(cfg.stats_push);
+        balloon.set_auto_target(cfg.auto_target)?;
+        self.inner = Some(Arc::new(Mutex::new(balloon)));
 
         Ok(())
     }
@@ -135,12 +163,15 @@ impl Default for BalloonBuilder {
 #[cfg(test)]
 pub(crate) mod tests {
     use super::*;
+    use crate::devices::virtio::balloon::BalloonError;
 
     pub(crate) fn default_config() -> BalloonDeviceConfig {
         BalloonDeviceConfig {
             amount_mib: 0,
             deflate_on_oom: false,
             stats_polling_interval_s: 0,
+            stats_push: None,
+            auto_target: None,
         }
     }
 
@@ -151,6 +182,8 @@ pub(crate) mod tests {
             amount_mib: 0,
             deflate_on_oom: false,
             stats_polling_interval_s: 0,
+            stats_push: None,
+            auto_target: None,
         };
         assert_eq!(default_balloon_config, balloon_config);
         let mut builder = BalloonBuilder::new();
@@ -160,24 +193,58 @@ pub(crate) mod tests {
         assert_eq!(builder.get().unwrap().lock().unwrap().num_pages(), 0);
         assert_eq!(builder.get_config().unwrap(), default_balloon_config);
 
+        let push_config = BalloonDeviceConfig {
+            stats_push: Some(BalloonStatsPushConfig::default()),
+            ..default_config()
+        };
+        assert!(matches!(
+            builder.set(push_config),
+            Err(BalloonConfigError::StatsPushDisabled)
+        ));
+        let auto_target_config = BalloonDeviceConfig {
+            auto_target: Some(BalloonAutoTargetConfig::default()),
+            ..default_config()
+        };
+        assert!(matches!(
+            builder.set(auto_target_config),
+            Err(BalloonConfigError::CreateFailure(
+                BalloonError::StatisticsDisabled
+            ))
+        ));
+
         let _update_config = BalloonUpdateConfig { amount_mib: 5 };
         let _stats_update_config = BalloonUpdateStatsConfig {
             stats_polling_interval_s: 5,
+            stats_push: None,
+            auto_target: None,
         };
     }
 
     #[test]
     fn test_from_balloon_state() {
+        let stats_push = BalloonStatsPushConfig {
+            metrics: true,
+            socket_path: Some("/tmp/balloon.sock".into()),
+        };
+        let auto_target = BalloonAutoTargetConfig {
+            min_mib: 0,
+            max_mib: 64,
+            available_mib: 32,
+        };
         let expected_balloon_config = BalloonDeviceConfig {
             amount_mib: 5,
             deflate_on_oom: false,
             stats_polling_interval_s: 3,
+            stats_push: Some(stats_push.clone()),
+            auto_target: Some(auto_target.clone()),
         };
 
         let actual_balloon_config = BalloonDeviceConfig::from(BalloonConfig {
             amount_mib: 5,
             deflate_on_oom: false,
             stats_polling_interval_s: 3,
+            stats_push: Some(stats_push),
+            auto_target: Some(auto_target),
         });
 
         assert_eq!(expected_balloon_config, actual_balloon_config);
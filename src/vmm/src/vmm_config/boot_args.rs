@@ -0,0 +1,536 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Kernel command line given either as a string, or as typed fields from which the command line
+//! is assembled.
+
+use std::fmt;
+use std::net::Ipv4Addr;
+
+use serde::de::value::MapAccessDeserializer;
+use serde::de::{MapAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use super::boot_source::DEFAULT_KERNEL_CMDLINE;
+
+/// Parameter of [`DEFAULT_KERNEL_CMDLINE`] disabling the 8250 serial ports, left out when the
+/// console is a serial port.
+const NO_SERIAL_PARAM: &str = "8250.nr_uarts=0";
+/// Maximum number of name servers in the `ip` parameter.
+const MAX_NAMESERVERS: usize = 2;
+
+/// Errors associated with the typed fields of the kernel command line.
+#[derive(Debug, thiserror::Error, displaydoc::Display, PartialEq, Eq)]
+pub enum BootArgsError {
+    /// Invalid console `{0}`: expected a device name, optionally followed by its options, e.g.
+    /// `ttyS0,115200`.
+    InvalidConsole(String),
+    /// Invalid root device `{0}`: it must not be empty nor contain whitespace.
+    InvalidRootDevice(String),
+    /// Invalid root file system type `{0}`.
+    InvalidFsType(String),
+    /// Invalid hostname `{0}`.
+    InvalidHostname(String),
+    /// Invalid network interface name `{0}`.
+    InvalidInterface(String),
+    /// At most {MAX_NAMESERVERS} name servers can be configured.
+    TooManyNameservers,
+    /// Invalid init `{0}`: it must be an absolute path without whitespace.
+    InvalidInit(String),
+    /// Invalid extra parameter `{0}`: it must not be empty nor contain whitespace or quotes.
+    InvalidParam(String),
+    /// The extra parameter `{0}` is already set by a typed field.
+    DuplicateParam(String),
+}
+
+/// Root file system of the guest.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct RootArgs {
+    /// Root device, e.g. `/dev/vda`, or `PARTUUID=<uuid>`.
+    pub device: String,
+    /// Type of the root file system, e.g. `ext4`, probed by the kernel if not set.
+    #[serde(default)]
+    pub fstype: Option<String>,
+    /// Whether the root file system is mounted read-only.
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+/// Static IPv4 configuration of a guest network interface, done by the kernel.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct IpArgs {
+    /// Address of the interface.
+    pub address: Ipv4Addr,
+    /// Netmask of the interface.
+    pub netmask: Ipv4Addr,
+    /// Default gateway.
+    #[serde(default)]
+    pub gateway: Option<Ipv4Addr>,
+    /// Hostname of the guest.
+    #[serde(default)]
+    pub hostname: Option<String>,
+    /// Name of the interface in the guest, e.g. `eth0`. The kernel picks the first interface if
+    /// not set.
+    #[serde(default)]
+    pub interface: Option<String>,
+    /// Name servers, at most 2.
+    #[serde(default)]
+    pub nameservers: Vec<Ipv4Addr>,
+}
+
+/// Typed fields from which the kernel command line is assembled.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct StructuredBootArgs {
+    /// Whether the command line starts with the default parameters of Firecracker.
+    #[serde(default = "default_true")]
+    pub defaults: bool,
+    /// Console of the guest, e.g. `ttyS0` or `hvc0`.
+    #[serde(default)]
+    pub console: Option<String>,
+    /// Root file system of the guest.
+    #[serde(default)]
+    pub root: Option<RootArgs>,
+    /// Static IPv4 configuration of a network interface.
+    #[serde(default)]
+    pub ip: Option<IpArgs>,
+    /// Path of the init program in the guest.
+    #[serde(default)]
+    pub init: Option<String>,
+    /// Other parameters, appended as they are, e.g. `quiet` or `loglevel=4`.
+    #[serde(default)]
+    pub extra: Vec<String>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Default for StructuredBootArgs {
+    fn default() -> Self {
+        StructuredBootArgs {
+            defaults: true,
+            console: None,
+            root: None,
+            ip: None,
+            init: None,
+            extra: Vec::new(),
+        }
+    }
+}
+
+fn is_valid_name(name: &str, extra_chars: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || extra_chars.contains(c))
+}
+
+fn is_valid_param(param: &str) -> bool {
+    !param.is_empty() && !param.chars().any(|c| c.is_whitespace() || c == '"')
+}
+
+impl StructuredBootArgs {
+    /// Validates the fields and assembles them into a kernel command line.
+    pub fn to_cmdline(&self) -> Result<String, BootArgsError> {
+        let mut params: Vec<String> = Vec::new();
+
+        let serial_console = self
+            .console
+            .as_deref()
+            .is_some_and(|console| console.starts_with("ttyS"));
+        if self.defaults {
+            params.extend(
+                DEFAULT_KERNEL_CMDLINE
+                    .split_whitespace()
+                    .filter(|param| !(serial_console && *param == NO_SERIAL_PARAM))
+                    .map(str::to_string),
+            );
+        }
+
+        if let Some(console) = &self.console {
+            let valid = match console.split_once(',') {
+                Some((device, options)) => is_valid_name(device, "_") && is_valid_name(options, ""),
+                None => is_valid_name(console, "_"),
+            };
+            if !valid {
+                return Err(BootArgsError::InvalidConsole(console.clone()));
+            }
+            params.push(format!("console={console}"));
+        }
+
+        if let Some(root) = &self.root {
+            if !is_valid_param(&root.device) {
+                return Err(BootArgsError::InvalidRootDevice(root.device.clone()));
+            }
+            params.push(format!("root={}", root.device));
+            if let Some(fstype) = &root.fstype {
+                if !is_valid_name(fstype, "_") {
+                    return Err(BootArgsError::InvalidFsType(fstype.clone()));
+                }
+                params.push(format!("rootfstype={fstype}"));
+            }
+            params.push(if root.read_only { "ro" } else { "rw" }.to_string());
+        }
+
+        if let Some(ip) = &self.ip {
+            params.push(ip.to_param()?);
+        }
+
+        if let Some(init) = &self.init {
+            if !init.starts_with('/') || !is_valid_param(init) {
+                return Err(BootArgsError::InvalidInit(init.clone()));
+            }
+            params.push(format!("init={init}"));
+        }
+
+        for param in &self.extra {
+            if !is_valid_param(param) {
+                return Err(BootArgsError::InvalidParam(param.clone()));
+            }
+            // Parameters generated from a typed field which is set cannot be given again.
+            let key = param.split_once('=').map_or(param.as_str(), |(key, _)| key);
+            let typed_field_set = match key {
+                "console" => self.console.is_some(),
+                "root" | "rootfstype" | "ro" | "rw" => self.root.is_some(),
+                "ip" => self.ip.is_some(),
+                "init" => self.init.is_some(),
+                _ => false,
+            };
+            if typed_field_set {
+                return Err(BootArgsError::DuplicateParam(param.clone()));
+            }
+            params.push(param.clone());
+        }
+
+        Ok(params.join(" "))
+    }
+}
+
+impl IpArgs {
+    /// Returns the `ip` parameter, in the
+    /// `ip=<client-ip>:<server-ip>:<gw-ip>:<netmask>:<hostname>:<device>:<autoconf>:<dns0-ip>:
+    /// <dns1-ip>` form understood by the kernel.
+    fn to_param(&self) -> Result<String, BootArgsError> {
+        let hostname = self.hostname.as_deref().unwrap_or_default();
+        if hostname.len() > 64 || !(hostname.is_empty() || is_valid_name(hostname, "-.")) {
+            return Err(BootArgsError::InvalidHostname(hostname.to_string()));
+        }
+        let interface = self.interface.as_deref().unwrap_or_default();
+        if !(interface.is_empty() || is_valid_name(interface, "-_.")) {
+            return Err(BootArgsError::InvalidInterface(interface.to_string()));
+        }
+        if self.nameservers.len() > MAX_NAMESERVERS {
+            return Err(BootArgsError::TooManyNameservers);
+        }
+
+        let gateway = self.gateway.map(|gw| gw.to_string()).unwrap_or_default();
+        let mut param = format!(
+            "ip={}::{gateway}:{}:{hostname}:{interface}:off",
+            self.address, self.netmask
+        );
+        for nameserver in &self.nameservers {
+            param.push_str(&format!(":{nameserver}"));
+        }
+        Ok(param)
+    }
+}
+
+/// Kernel command line, given either as a string or as typed fields.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum BootArgs {
+    /// Command line passed to the kernel as it is.
+    Raw(String),
+    /// Typed fields, validated and assembled into the command line.
+    Structured(StructuredBootArgs),
+}
+
+impl From<String> for BootArgs {
+    fn from(cmdline: String) -> Self {
+        BootArgs::Raw(cmdline)
+    }
+}
+
+impl BootArgs {
+    /// Returns the kernel command line, assembled from the typed fields if needed.
+    pub fn to_cmdline(&self) -> Result<String, BootArgsError> {
+        match self {
+            BootArgs::Raw(cmdline) => Ok(cmdline.clone()),
+            BootArgs::Structured(args) => args.to_cmdline(),
+        }
+    }
+}
+
+// Snapshots are not self-describing, so the form of the command line is stored there as an
+// explicit variant.
+#[derive(Serialize)]
+enum TaggedBootArgsRef<'a> {
+    Raw(&'a str),
+    Structured(&'a StructuredBootArgs),
+}
+
+#[derive(Deserialize)]
+enum TaggedBootArgs {
+    Raw(String),
+    Structured(StructuredBootArgs),
+}
+
+impl Serialize for BootArgs {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match (self, serializer.is_human_readable()) {
+            (BootArgs::Raw(cmdline), true) => cmdline.serialize(serializer),
+            (BootArgs::Structured(args), true) => args.serialize(serializer),
+            (BootArgs::Raw(cmdline), false) => {
+                TaggedBootArgsRef::Raw(cmdline).serialize(serializer)
+            }
+            (BootArgs::Structured(args), false) => {
+                TaggedBootArgsRef::Structured(args).serialize(serializer)
+            }
+        }
+    }
+}
+
+struct BootArgsVisitor;
+
+impl<'de> Visitor<'de> for BootArgsVisitor {
+    type Value = BootArgs;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a kernel command line string or an object of boot arguments")
+    }
+
+    fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<Self::Value, E> {
+        Ok(BootArgs::Raw(value.to_string()))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+        // Deserializing the object directly, rather than through an untagged enum, keeps the
+        // errors about its fields, e.g. a misspelled one.
+        StructuredBootArgs::deserialize(MapAccessDeserializer::new(map)).map(BootArgs::Structured)
+    }
+}
+
+impl<'de> Deserialize<'de> for BootArgs {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(BootArgsVisitor)
+        } else {
+            Ok(match TaggedBootArgs::deserialize(deserializer)? {
+                TaggedBootArgs::Raw(cmdline) => BootArgs::Raw(cmdline),
+                TaggedBootArgs::Structured(args) => BootArgs::Structured(args),
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshot::Snapshot;
+
+    #[test]
+    fn test_structured_cmdline() {
+        let args = StructuredBootArgs {
+            defaults: false,
+            ..Default::default()
+        };
+        assert_eq!(args.to_cmdline().unwrap(), "");
+        assert_eq!(
+            StructuredBootArgs::default().to_cmdline().unwrap(),
+            DEFAULT_KERNEL_CMDLINE
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+        );
+
+        let args = StructuredBootArgs {
+            defaults: false,
+            console: Some("ttyS0,115200".to_string()),
+            root: Some(RootArgs {
+                device: "/dev/vda".to_string(),
+                fstype: Some("ext4".to_string()),
+                read_only: true,
+            }),
+            ip: Some(IpArgs {
+                address: Ipv4Addr::new(172, 16, 0, 2),
+                netmask: Ipv4Addr::new(255, 255, 255, 252),
+                gateway: Some(Ipv4Addr::new(172, 16, 0, 1)),
+                hostname: Some("guest".to_string()),
+                interface: Some("eth0".to_string()),
+                nameservers: vec![Ipv4Addr::new(1, 1, 1, 1)],
+            }),
+            init: Some("/sbin/init".to_string()),
+            extra: vec!["quiet".to_string(), "loglevel=4".to_string()],
+        };
+        assert_eq!(
+            args.to_cmdline().unwrap(),
+            "console=ttyS0,115200 root=/dev/vda rootfstype=ext4 ro \
+             ip=172.16.0.2::172.16.0.1:255.255.255.252:guest:eth0:off:1.1.1.1 init=/sbin/init \
+             quiet loglevel=4"
+        );
+
+        // The serial ports are not disabled when the console is a serial port.
+        let args = StructuredBootArgs {
+            console: Some("ttyS0".to_string()),
+            ..Default::default()
+        };
+        let cmdline = args.to_cmdline().unwrap();
+        assert!(!cmdline.contains(NO_SERIAL_PARAM));
+        assert!(cmdline.starts_with("reboot=k panic=1"));
+        assert!(cmdline.ends_with("console=ttyS0"));
+        let args = StructuredBootArgs {
+            console: Some("hvc0".to_string()),
+            ..Default::default()
+        };
+        assert!(args.to_cmdline().unwrap().contains(NO_SERIAL_PARAM));
+    }
+
+    #[test]
+    fn test_structured_cmdline_errors() {
+        let invalid = |args: StructuredBootArgs| args.to_cmdline().unwrap_err();
+
+        for console in ["", "tty S0", "ttyS0,115200 quiet", "ttyS0,"] {
+            assert_eq!(
+                invalid(StructuredBootArgs {
+                    console: Some(console.to_string()),
+                    ..Default::default()
+                }),
+                BootArgsError::InvalidConsole(console.to_string())
+            );
+        }
+        let root = RootArgs {
+            device: "/dev/vda rw".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            invalid(StructuredBootArgs {
+                root: Some(root.clone()),
+                ..Default::default()
+            }),
+            BootArgsError::InvalidRootDevice(root.device)
+        );
+        let root = RootArgs {
+            device: "/dev/vda".to_string(),
+            fstype: Some("ext4 ".to_string()),
+            read_only: false,
+        };
+        assert_eq!(
+            invalid(StructuredBootArgs {
+                root: Some(root),
+                ..Default::default()
+            }),
+            BootArgsError::InvalidFsType("ext4 ".to_string())
+        );
+
+        let ip = IpArgs {
+            address: Ipv4Addr::new(10, 0, 0, 2),
+            netmask: Ipv4Addr::new(255, 255, 255, 0),
+            gateway: None,
+            hostname: Some("guest:1".to_string()),
+            interface: None,
+            nameservers: Vec::new(),
+        };
+        assert_eq!(
+            invalid(StructuredBootArgs {
+                ip: Some(ip.clone()),
+                ..Default::default()
+            }),
+            BootArgsError::InvalidHostname("guest:1".to_string())
+        );
+        let bad_interface = IpArgs {
+            hostname: None,
+            interface: Some("eth0:".to_string()),
+            ..ip.clone()
+        };
+        assert_eq!(
+            invalid(StructuredBootArgs {
+                ip: Some(bad_interface),
+                ..Default::default()
+            }),
+            BootArgsError::InvalidInterface("eth0:".to_string())
+        );
+        let too_many_nameservers = IpArgs {
+            hostname: None,
+            nameservers: vec![Ipv4Addr::new(10, 0, 0, 1); 3],
+            ..ip
+        };
+        assert_eq!(
+            invalid(StructuredBootArgs {
+                ip: Some(too_many_nameservers),
+                ..Default::default()
+            }),
+            BootArgsError::TooManyNameservers
+        );
+
+        assert_eq!(
+            invalid(StructuredBootArgs {
+                init: Some("sbin/init".to_string()),
+                ..Default::default()
+            }),
+            BootArgsError::InvalidInit("sbin/init".to_string())
+        );
+        for param in ["", "quiet loglevel=4", "foo=\"bar\""] {
+            assert_eq!(
+                invalid(StructuredBootArgs {
+                    extra: vec![param.to_string()],
+                    ..Default::default()
+                }),
+                BootArgsError::InvalidParam(param.to_string())
+            );
+        }
+        assert_eq!(
+            invalid(StructuredBootArgs {
+                console: Some("ttyS0".to_string()),
+                extra: vec!["console=hvc0".to_string()],
+                ..Default::default()
+            }),
+            BootArgsError::DuplicateParam("console=hvc0".to_string())
+        );
+        // Extra parameters are only duplicates of typed fields which are set.
+        StructuredBootArgs {
+            extra: vec!["console=hvc0".to_string(), "rw".to_string()],
+            ..Default::default()
+        }
+        .to_cmdline()
+        .unwrap();
+    }
+
+    #[test]
+    fn test_boot_args_serde() {
+        let args: BootArgs = serde_json::from_str(r#""console=ttyS0 reboot=k""#).unwrap();
+        assert_eq!(args, BootArgs::Raw("console=ttyS0 reboot=k".to_string()));
+        assert_eq!(
+            serde_json::to_string(&args).unwrap(),
+            r#""console=ttyS0 reboot=k""#
+        );
+
+        let json = r#"{"console":"ttyS0","root":{"device":"/dev/vda","read_only":false}}"#;
+        let args: BootArgs = serde_json::from_str(json).unwrap();
+        let BootArgs::Structured(structured) = &args else {
+            panic!("unexpected boot args {args:?}");
+        };
+        assert!(structured.defaults);
+        assert_eq!(structured.root.as_ref().unwrap().device, "/dev/vda");
+        let json = r#"{"defaults":true,"console":"ttyS0","root":{"device":"/dev/vda","fstype":null,"read_only":false},"ip":null,"init":null,"extra":[]}"#;
+        assert_eq!(serde_json::to_string(&args).unwrap(), json);
+
+        // Misspelled fields and invalid addresses are rejected.
+        let err = serde_json::from_str::<BootArgs>(r#"{"consol":"ttyS0"}"#).unwrap_err();
+        assert!(err.to_string().contains("unknown field `consol`"), "{err}");
+        serde_json::from_str::<BootArgs>(
+            r#"{"ip":{"address":"172.16.0.256","netmask":"255.255.255.0"}}"#,
+        )
+        .unwrap_err();
+        serde_json::from_str::<BootArgs>("42").unwrap_err();
+
+        let mut snapshot_data = vec![0u8; 1000];
+        for args in [args, BootArgs::Raw("panic=1".to_string())] {
+            Snapshot::serialize(&mut snapshot_data.as_mut_slice(), &args).unwrap();
+            let restored_args: BootArgs =
+                Snapshot::deserialize(&mut snapshot_data.as_slice()).unwrap();
+            assert_eq!(restored_args, args);
+        }
+    }
+}
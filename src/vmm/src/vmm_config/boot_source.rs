@@ -1,10 +1,17 @@
 // Copyright 2018 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
+use std::fmt::Write as _;
 use std::fs::File;
-use std::io;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::os::fd::RawFd;
 
-use serde::{Deserialize, Serialize};
+use aws_lc_rs::digest;
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use super::boot_args::{BootArgs, BootArgsError};
+use crate::utils::file_from_fd;
 
 /// Default guest kernel command line:
 /// - `reboot=k` shut down the guest on reboot, instead of well... rebooting;
@@ -24,13 +31,74 @@ pub const DEFAULT_KERNEL_CMDLINE: &str = "reboot=k panic=1 pci=off nomodule 8250
 #[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct BootSourceConfig {
-    /// Path of the kernel image.
+    /// Path of the kernel image. Must be left empty when booting from firmware or when the
+    /// kernel image is passed as a file descriptor.
+    #[serde(default)]
     pub kernel_image_path: String,
-    /// Path of the initrd, if there is one.
-    pub initrd_path: Option<String>,
-    /// The boot arguments to pass to the kernel. If this field is uninitialized,
-    /// DEFAULT_KERNEL_CMDLINE is used.
-    pub boot_args: Option<String>,
+    /// Path of the initrd, or paths of the initrd images to concatenate, if there is one.
+    pub initrd_path: Option<InitrdPaths>,
+    /// File descriptor of the kernel image, inherited by the process, to use instead of
+    /// `kernel_image_path`.
+    pub kernel_image_fd: Option<RawFd>,
+    /// File descriptor of the initrd, inherited by the process, to use instead of `initrd_path`.
+    pub initrd_fd: Option<RawFd>,
+    /// The boot arguments to pass to the kernel, either as a command line or as typed fields
+    /// from which it is assembled. If this field is uninitialized, DEFAULT_KERNEL_CMDLINE is used.
+    pub boot_args: Option<BootArgs>,
+    /// Path of a UEFI firmware image to boot instead of the kernel (aarch64 only).
+    pub firmware_path: Option<String>,
+    /// Expected digest of the kernel image, formatted as `sha256:<hex>` or `sha384:<hex>`.
+    pub kernel_digest: Option<String>,
+    /// Expected digest of the initrd, formatted as `sha256:<hex>` or `sha384:<hex>`.
+    pub initrd_digest: Option<String>,
+}
+
+/// Paths of the initrd images, configured either as a single path or as a list of paths.
+///
+/// The images are loaded one after the other in guest memory and passed to the guest as a single
+/// initrd, so that the kernel unpacks them as concatenated initramfs archives.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct InitrdPaths(pub Vec<String>);
+
+impl From<String> for InitrdPaths {
+    fn from(path: String) -> Self {
+        InitrdPaths(vec![path])
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrManyPaths {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Serialize for InitrdPaths {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        // A single path is kept as a string in the API, as it was before lists were supported.
+        match self.0.as_slice() {
+            [path] if serializer.is_human_readable() => path.serialize(serializer),
+            paths => paths.serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for InitrdPaths {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // Snapshots are not self-describing, so the paths are always stored there as a list.
+        let paths = if deserializer.is_human_readable() {
+            match OneOrManyPaths::deserialize(deserializer)? {
+                OneOrManyPaths::One(path) => vec![path],
+                OneOrManyPaths::Many(paths) => paths,
+            }
+        } else {
+            Vec::<String>::deserialize(deserializer)?
+        };
+        if paths.is_empty() {
+            return Err(D::Error::invalid_length(0, &"at least one initrd path"));
+        }
+        Ok(InitrdPaths(paths))
+    }
 }
 
 /// Errors associated with actions on `BootSourceConfig`.
@@ -42,8 +110,151 @@ pub enum BootSourceConfigError {
     InvalidInitrdPath(io::Error),
     /// The kernel command line is invalid: {0}
     InvalidKernelCommandLine(String),
+    /// Invalid boot arguments: {0}
+    InvalidBootArgs(BootArgsError),
     /// Firecracker's huge pages support is incompatible with initrds.
     HugePagesAndInitRd,
+    /// The firmware file cannot be opened: {0}
+    InvalidFirmwarePath(io::Error),
+    /// A kernel image, an initrd or their digests cannot be used when booting from firmware.
+    FirmwareAndKernel,
+    /// Booting from firmware is only supported on aarch64.
+    FirmwareNotSupported,
+    /// Invalid kernel digest: {0}
+    InvalidKernelDigest(ImageDigestError),
+    /// Invalid initrd digest: {0}
+    InvalidInitrdDigest(ImageDigestError),
+    /// The kernel image file descriptor cannot be used: {0}
+    InvalidKernelFd(io::Error),
+    /// The initrd file descriptor cannot be used: {0}
+    InvalidInitrdFd(io::Error),
+    /// A boot image cannot be given both as a path and as a file descriptor.
+    PathAndFd,
+}
+
+/// Errors associated with the digests of boot images.
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum ImageDigestError {
+    /// The digest must be formatted as `<algorithm>:<hex value>`.
+    Format,
+    /// Unsupported digest algorithm `{0}`, expected `sha256` or `sha384`.
+    UnsupportedAlgorithm(String),
+    /// The digest value must be {0} hexadecimal characters long.
+    Length(usize),
+    /// Cannot read the image: {0}
+    Read(#[from] io::Error),
+    /// The image digest {actual} does not match the expected digest {expected}.
+    Mismatch {
+        /// The digest from the boot source configuration.
+        expected: String,
+        /// The digest of the image.
+        actual: String,
+    },
+}
+
+/// Expected digest of a boot image, checked before the image is loaded in guest memory.
+#[derive(Clone, Debug)]
+pub struct ImageDigest {
+    name: &'static str,
+    algorithm: &'static digest::Algorithm,
+    value: Vec<u8>,
+}
+
+impl ImageDigest {
+    /// Parses a digest formatted as `<algorithm>:<hex value>`.
+    pub fn parse(digest: &str) -> Result<Self, ImageDigestError> {
+        let (name, hex) = digest.split_once(':').ok_or(ImageDigestError::Format)?;
+        let (name, algorithm, len) = match name {
+            "sha256" => ("sha256", &digest::SHA256, digest::SHA256_OUTPUT_LEN),
+            "sha384" => ("sha384", &digest::SHA384, digest::SHA384_OUTPUT_LEN),
+            _ => return Err(ImageDigestError::UnsupportedAlgorithm(name.to_string())),
+        };
+
+        let expected_len = len * 2;
+        if hex.len() != expected_len {
+            return Err(ImageDigestError::Length(expected_len));
+        }
+        if !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            return Err(ImageDigestError::Format);
+        }
+        let value = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+            .collect::<Option<Vec<u8>>>()
+            .ok_or(ImageDigestError::Format)?;
+
+        Ok(ImageDigest {
+            name,
+            algorithm,
+            value,
+        })
+    }
+
+    /// Computes the digest of the whole `image` and compares it with the expected one.
+    ///
+    /// The image is rewound before and after reading it.
+    pub fn verify(&self, image: &mut File) -> Result<(), ImageDigestError> {
+        self.verify_concatenated(std::slice::from_mut(image))
+    }
+
+    /// Computes the digest of the concatenation of `images` and compares it with the expected
+    /// one.
+    ///
+    /// The images are rewound before and after reading them.
+    pub fn verify_concatenated(&self, images: &mut [File]) -> Result<(), ImageDigestError> {
+        let mut context = digest::Context::new(self.algorithm);
+        let mut buf = vec![0u8; 64 * 1024];
+
+        for image in images {
+            image.seek(SeekFrom::Start(0))?;
+            loop {
+                match image.read(&mut buf) {
+                    Ok(0) => break,
+                    Ok(len) => context.update(&buf[..len]),
+                    Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(err) => return Err(err.into()),
+                }
+            }
+            image.seek(SeekFrom::Start(0))?;
+        }
+
+        self.check(context.finish().as_ref())
+    }
+
+    /// Computes the digest of `data` and compares it with the expected one.
+    pub fn verify_bytes(&self, data: &[u8]) -> Result<(), ImageDigestError> {
+        self.check(digest::digest(self.algorithm, data).as_ref())
+    }
+
+    fn check(&self, actual: &[u8]) -> Result<(), ImageDigestError> {
+        if actual != self.value.as_slice() {
+            return Err(ImageDigestError::Mismatch {
+                expected: self.to_string(),
+                actual: Self::format(self.name, actual),
+            });
+        }
+        Ok(())
+    }
+
+    fn format(name: &str, value: &[u8]) -> String {
+        value.iter().fold(format!("{name}:"), |mut acc, byte| {
+            let _ = write!(acc, "{byte:02x}");
+            acc
+        })
+    }
+}
+
+impl std::fmt::Display for ImageDigest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&Self::format(self.name, &self.value))
+    }
+}
+
+impl BootSourceConfig {
+    /// Returns `true` if an initrd is configured, either as a path or as a file descriptor.
+    pub fn has_initrd(&self) -> bool {
+        self.initrd_path.is_some() || self.initrd_fd.is_some()
+    }
 }
 
 /// Holds the kernel specification (both configuration as well as runtime details).
@@ -61,38 +272,94 @@ pub struct BootSource {
 pub struct BootConfig {
     /// The commandline validated against correctness.
     pub cmdline: linux_loader::cmdline::Cmdline,
-    /// The descriptor to the kernel file.
-    pub kernel_file: File,
-    /// The descriptor to the initrd file, if there is one.
-    pub initrd_file: Option<File>,
+    /// The descriptor to the kernel file, unless booting from firmware.
+    pub kernel_file: Option<File>,
+    /// The descriptors to the initrd files, loaded one after the other, if there is an initrd.
+    pub initrd_files: Vec<File>,
+    /// The descriptor to the firmware file, when booting from firmware.
+    pub firmware_file: Option<File>,
+    /// The expected digest of the kernel image, if any.
+    pub kernel_digest: Option<ImageDigest>,
+    /// The expected digest of the initrd, if any.
+    pub initrd_digest: Option<ImageDigest>,
 }
 
 impl BootConfig {
     /// Creates the BootConfig based on a given configuration.
     pub fn new(cfg: &BootSourceConfig) -> Result<Self, BootSourceConfigError> {
         use self::BootSourceConfigError::{
-            InvalidInitrdPath, InvalidKernelCommandLine, InvalidKernelPath,
+            InvalidInitrdDigest, InvalidInitrdFd, InvalidInitrdPath, InvalidKernelCommandLine,
+            InvalidKernelDigest, InvalidKernelFd, InvalidKernelPath, PathAndFd,
         };
 
+        if (!cfg.kernel_image_path.is_empty() && cfg.kernel_image_fd.is_some())
+            || (cfg.initrd_path.is_some() && cfg.initrd_fd.is_some())
+        {
+            return Err(PathAndFd);
+        }
+
         // Validate boot source config.
-        let kernel_file = File::open(&cfg.kernel_image_path).map_err(InvalidKernelPath)?;
-        let initrd_file: Option<File> = match &cfg.initrd_path {
-            Some(path) => Some(File::open(path).map_err(InvalidInitrdPath)?),
+        let firmware_file: Option<File> = match &cfg.firmware_path {
+            #[cfg(target_arch = "x86_64")]
+            Some(_) => return Err(BootSourceConfigError::FirmwareNotSupported),
+            #[cfg(target_arch = "aarch64")]
+            Some(path) => {
+                // The firmware loads the guest OS from its disk, so there is nothing else to load.
+                if !cfg.kernel_image_path.is_empty()
+                    || cfg.kernel_image_fd.is_some()
+                    || cfg.has_initrd()
+                    || cfg.kernel_digest.is_some()
+                    || cfg.initrd_digest.is_some()
+                {
+                    return Err(BootSourceConfigError::FirmwareAndKernel);
+                }
+                Some(File::open(path).map_err(BootSourceConfigError::InvalidFirmwarePath)?)
+            }
             None => None,
         };
+        let kernel_file: Option<File> = match (&firmware_file, cfg.kernel_image_fd) {
+            (Some(_), _) => None,
+            (None, Some(fd)) => Some(file_from_fd(fd).map_err(InvalidKernelFd)?),
+            (None, None) => Some(File::open(&cfg.kernel_image_path).map_err(InvalidKernelPath)?),
+        };
+        let initrd_files: Vec<File> = match (&cfg.initrd_path, cfg.initrd_fd) {
+            (Some(paths), _) => paths
+                .0
+                .iter()
+                .map(|path| File::open(path).map_err(InvalidInitrdPath))
+                .collect::<Result<_, _>>()?,
+            (None, Some(fd)) => vec![file_from_fd(fd).map_err(InvalidInitrdFd)?],
+            (None, None) => Vec::new(),
+        };
+
+        let kernel_digest = cfg
+            .kernel_digest
+            .as_deref()
+            .map(ImageDigest::parse)
+            .transpose()
+            .map_err(InvalidKernelDigest)?;
+        let initrd_digest = cfg
+            .initrd_digest
+            .as_deref()
+            .map(ImageDigest::parse)
+            .transpose()
+            .map_err(InvalidInitrdDigest)?;
 
         let cmdline_str = match cfg.boot_args.as_ref() {
-            None => DEFAULT_KERNEL_CMDLINE,
-            Some(str) => str.as_str(),
+            None => DEFAULT_KERNEL_CMDLINE.to_string(),
+            Some(args) => args.to_cmdline().map_err(InvalidBootArgs)?,
         };
         let cmdline =
-            linux_loader::cmdline::Cmdline::try_from(cmdline_str, crate::arch::CMDLINE_MAX_SIZE)
+            linux_loader::cmdline::Cmdline::try_from(&cmdline_str, crate::arch::CMDLINE_MAX_SIZE)
                 .map_err(|err| InvalidKernelCommandLine(err.to_string()))?;
 
         Ok(BootConfig {
             cmdline,
             kernel_file,
-            initrd_file,
+            initrd_files,
+            firmware_file,
+            kernel_digest,
+            initrd_digest,
         })
     }
 }
@@ -103,6 +370,10 @@ pub(crate) mod tests {
 
     use super::*;
     use crate::snapshot::Snapshot;
+    use crate::vmm_config::boot_args::StructuredBootArgs;
+
+    const SHA256_ABC: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+    const SHA384_ABC: &str = "cb00753f45a35e8bb5a03d699ac65007272c32ab0eded1631a8b605a43ff5bed8086072ba1e7cc2358baeca134c825a7";
 
     #[test]
     fn test_boot_config() {
@@ -113,27 +384,318 @@ pub(crate) mod tests {
             boot_args: None,
             initrd_path: None,
             kernel_image_path: kernel_path,
+            kernel_image_fd: None,
+            initrd_fd: None,
+            firmware_path: None,
+            kernel_digest: None,
+            initrd_digest: None,
         };
 
         let boot_cfg = BootConfig::new(&boot_src_cfg).unwrap();
-        assert!(boot_cfg.initrd_file.is_none());
+        assert!(boot_cfg.kernel_file.is_some());
+        assert!(boot_cfg.initrd_files.is_empty());
+        assert!(boot_cfg.firmware_file.is_none());
         assert_eq!(
             boot_cfg.cmdline.as_cstring().unwrap().as_bytes_with_nul(),
             [DEFAULT_KERNEL_CMDLINE.as_bytes(), b"\0"].concat()
         );
     }
 
+    #[test]
+    fn test_boot_config_structured_args() {
+        let kernel_file = TempFile::new().unwrap();
+        let boot_src_cfg = BootSourceConfig {
+            kernel_image_path: kernel_file.as_path().to_str().unwrap().to_string(),
+            boot_args: Some(BootArgs::Structured(StructuredBootArgs {
+                defaults: false,
+                console: Some("ttyS0".to_string()),
+                extra: vec!["quiet".to_string()],
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        let boot_cfg = BootConfig::new(&boot_src_cfg).unwrap();
+        assert_eq!(
+            boot_cfg.cmdline.as_cstring().unwrap().as_bytes_with_nul(),
+            b"console=ttyS0 quiet\0"
+        );
+
+        let boot_src_cfg = BootSourceConfig {
+            boot_args: Some(BootArgs::Structured(StructuredBootArgs {
+                extra: vec!["console=hvc0".to_string()],
+                console: Some("ttyS0".to_string()),
+                ..Default::default()
+            })),
+            ..boot_src_cfg
+        };
+        assert!(matches!(
+            BootConfig::new(&boot_src_cfg),
+            Err(BootSourceConfigError::InvalidBootArgs(
+                BootArgsError::DuplicateParam(_)
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_boot_config_firmware() {
+        let firmware_file = TempFile::new().unwrap();
+        let firmware_path = firmware_file.as_path().to_str().unwrap().to_string();
+
+        let boot_src_cfg = BootSourceConfig {
+            firmware_path: Some(firmware_path.clone()),
+            ..Default::default()
+        };
+
+        #[cfg(target_arch = "x86_64")]
+        assert!(matches!(
+            BootConfig::new(&boot_src_cfg),
+            Err(BootSourceConfigError::FirmwareNotSupported)
+        ));
+
+        #[cfg(target_arch = "aarch64")]
+        {
+            let boot_cfg = BootConfig::new(&boot_src_cfg).unwrap();
+            assert!(boot_cfg.kernel_file.is_none());
+            assert!(boot_cfg.firmware_file.is_some());
+
+            let mut boot_src_cfg = boot_src_cfg;
+            boot_src_cfg.firmware_path = Some("/invalid/path".to_string());
+            assert!(matches!(
+                BootConfig::new(&boot_src_cfg),
+                Err(BootSourceConfigError::InvalidFirmwarePath(_))
+            ));
+
+            boot_src_cfg.firmware_path = Some(firmware_path.clone());
+            boot_src_cfg.kernel_image_path = firmware_path.clone();
+            assert!(matches!(
+                BootConfig::new(&boot_src_cfg),
+                Err(BootSourceConfigError::FirmwareAndKernel)
+            ));
+
+            boot_src_cfg.kernel_image_path = String::new();
+            boot_src_cfg.initrd_path = Some(firmware_path.into());
+            assert!(matches!(
+                BootConfig::new(&boot_src_cfg),
+                Err(BootSourceConfigError::FirmwareAndKernel)
+            ));
+
+            boot_src_cfg.initrd_path = None;
+            boot_src_cfg.kernel_digest = Some(format!("sha256:{SHA256_ABC}"));
+            assert!(matches!(
+                BootConfig::new(&boot_src_cfg),
+                Err(BootSourceConfigError::FirmwareAndKernel)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_boot_config_fd() {
+        use std::io::Write;
+        use std::os::fd::AsRawFd;
+
+        let kernel_file = TempFile::new().unwrap();
+        kernel_file.as_file().write_all(b"kernel").unwrap();
+        let kernel_fd = kernel_file.as_file().as_raw_fd();
+        let initrd_file = TempFile::new().unwrap();
+        let initrd_fd = initrd_file.as_file().as_raw_fd();
+
+        let boot_src_cfg = BootSourceConfig {
+            kernel_image_fd: Some(kernel_fd),
+            initrd_fd: Some(initrd_fd),
+            ..Default::default()
+        };
+        let boot_cfg = BootConfig::new(&boot_src_cfg).unwrap();
+        let mut kernel_image = boot_cfg.kernel_file.unwrap();
+        let mut kernel = String::new();
+        kernel_image.seek(SeekFrom::Start(0)).unwrap();
+        kernel_image.read_to_string(&mut kernel).unwrap();
+        assert_eq!(kernel, "kernel");
+        assert_eq!(boot_cfg.initrd_files.len(), 1);
+        // The inherited descriptors are duplicated, so they can be used again.
+        BootConfig::new(&boot_src_cfg).unwrap();
+
+        let path = kernel_file.as_path().to_str().unwrap().to_string();
+        let boot_src_cfg = BootSourceConfig {
+            kernel_image_path: path.clone(),
+            ..boot_src_cfg
+        };
+        assert!(matches!(
+            BootConfig::new(&boot_src_cfg),
+            Err(BootSourceConfigError::PathAndFd)
+        ));
+
+        let boot_src_cfg = BootSourceConfig {
+            kernel_image_fd: None,
+            initrd_path: Some(path.into()),
+            ..boot_src_cfg
+        };
+        assert!(matches!(
+            BootConfig::new(&boot_src_cfg),
+            Err(BootSourceConfigError::PathAndFd)
+        ));
+
+        let boot_src_cfg = BootSourceConfig {
+            kernel_image_fd: Some(-1),
+            ..Default::default()
+        };
+        assert!(matches!(
+            BootConfig::new(&boot_src_cfg),
+            Err(BootSourceConfigError::InvalidKernelFd(_))
+        ));
+        let boot_src_cfg = BootSourceConfig {
+            kernel_image_fd: Some(kernel_fd),
+            initrd_fd: Some(-1),
+            ..Default::default()
+        };
+        assert!(matches!(
+            BootConfig::new(&boot_src_cfg),
+            Err(BootSourceConfigError::InvalidInitrdFd(_))
+        ));
+    }
+
+    #[test]
+    fn test_image_digest() {
+        use std::io::Write;
+
+        let sha256 = ImageDigest::parse(&format!("sha256:{SHA256_ABC}")).unwrap();
+        assert_eq!(sha256.to_string(), format!("sha256:{SHA256_ABC}"));
+        let sha384 = ImageDigest::parse(&format!("sha384:{}", SHA384_ABC.to_uppercase())).unwrap();
+        assert_eq!(sha384.to_string(), format!("sha384:{SHA384_ABC}"));
+
+        assert!(matches!(
+            ImageDigest::parse(SHA256_ABC),
+            Err(ImageDigestError::Format)
+        ));
+        assert!(matches!(
+            ImageDigest::parse(&format!("md5:{SHA256_ABC}")),
+            Err(ImageDigestError::UnsupportedAlgorithm(name)) if name == "md5"
+        ));
+        assert!(matches!(
+            ImageDigest::parse(&format!("sha384:{SHA256_ABC}")),
+            Err(ImageDigestError::Length(96))
+        ));
+        assert!(matches!(
+            ImageDigest::parse(&format!("sha256:+{}", &SHA256_ABC[1..])),
+            Err(ImageDigestError::Format)
+        ));
+
+        let image = TempFile::new().unwrap();
+        let mut file = image.into_file();
+        file.write_all(b"abc").unwrap();
+
+        sha256.verify(&mut file).unwrap();
+        assert_eq!(file.stream_position().unwrap(), 0);
+        sha384.verify(&mut file).unwrap();
+
+        // The digest of concatenated images covers their concatenated content.
+        let mut parts =
+            [TempFile::new().unwrap(), TempFile::new().unwrap()].map(|part| part.into_file());
+        parts[0].write_all(b"a").unwrap();
+        parts[1].write_all(b"bc").unwrap();
+        sha256.verify_concatenated(&mut parts).unwrap();
+        sha256.verify_bytes(b"abc").unwrap();
+        sha256.verify_bytes(b"abd").unwrap_err();
+
+        file.write_all(b"d").unwrap();
+        let err = sha256.verify(&mut file).unwrap_err();
+        assert!(
+            matches!(&err, ImageDigestError::Mismatch { expected, actual }
+                if *expected == sha256.to_string() && *actual != *expected)
+        );
+        assert_eq!(file.stream_position().unwrap(), 0);
+
+        // Invalid digests are rejected when configuring the boot source.
+        let kernel_file = TempFile::new().unwrap();
+        let boot_src_cfg = BootSourceConfig {
+            kernel_image_path: kernel_file.as_path().to_str().unwrap().to_string(),
+            kernel_digest: Some("sha256:".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            BootConfig::new(&boot_src_cfg),
+            Err(BootSourceConfigError::InvalidKernelDigest(_))
+        ));
+        let boot_src_cfg = BootSourceConfig {
+            kernel_digest: None,
+            initrd_digest: Some("sha1:".to_string()),
+            ..boot_src_cfg
+        };
+        assert!(matches!(
+            BootConfig::new(&boot_src_cfg),
+            Err(BootSourceConfigError::InvalidInitrdDigest(_))
+        ));
+    }
+
     #[test]
     fn test_serde() {
         let boot_src_cfg = BootSourceConfig {
-            boot_args: Some(DEFAULT_KERNEL_CMDLINE.to_string()),
-            initrd_path: Some("/tmp/initrd".to_string()),
+            boot_args: Some(DEFAULT_KERNEL_CMDLINE.to_string().into()),
+            initrd_path: Some(InitrdPaths(vec![
+                "/tmp/initrd".to_string(),
+                "/tmp/config.cpio".to_string(),
+            ])),
             kernel_image_path: "./vmlinux.bin".to_string(),
+            kernel_image_fd: Some(3),
+            initrd_fd: Some(4),
+            firmware_path: Some("./firmware.fd".to_string()),
+            kernel_digest: Some(format!("sha256:{SHA256_ABC}")),
+            initrd_digest: Some(format!("sha384:{SHA384_ABC}")),
         };
 
         let mut snapshot_data = vec![0u8; 1000];
         Snapshot::serialize(&mut snapshot_data.as_mut_slice(), &boot_src_cfg).unwrap();
         let restored_boot_cfg = Snapshot::deserialize(&mut snapshot_data.as_slice()).unwrap();
         assert_eq!(boot_src_cfg, restored_boot_cfg);
+
+        let boot_src_cfg = BootSourceConfig {
+            initrd_path: Some("/tmp/initrd".to_string().into()),
+            ..boot_src_cfg
+        };
+        Snapshot::serialize(&mut snapshot_data.as_mut_slice(), &boot_src_cfg).unwrap();
+        let restored_boot_cfg = Snapshot::deserialize(&mut snapshot_data.as_slice()).unwrap();
+        assert_eq!(boot_src_cfg, restored_boot_cfg);
+    }
+
+    #[test]
+    fn test_initrd_paths_json() {
+        let paths: InitrdPaths = serde_json::from_str(r#""/tmp/initrd""#).unwrap();
+        assert_eq!(paths, InitrdPaths::from("/tmp/initrd".to_string()));
+        assert_eq!(serde_json::to_string(&paths).unwrap(), r#""/tmp/initrd""#);
+
+        let json = r#"["/tmp/initrd","/tmp/config.cpio"]"#;
+        let paths: InitrdPaths = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            paths.0,
+            ["/tmp/initrd".to_string(), "/tmp/config.cpio".to_string()]
+        );
+        assert_eq!(serde_json::to_string(&paths).unwrap(), json);
+
+        serde_json::from_str::<InitrdPaths>("[]").unwrap_err();
+        serde_json::from_str::<InitrdPaths>("[1]").unwrap_err();
+    }
+
+    #[test]
+    fn test_boot_config_initrd_paths() {
+        let kernel_file = TempFile::new().unwrap();
+        let base_file = TempFile::new().unwrap();
+        let config_file = TempFile::new().unwrap();
+        let path = |file: &TempFile| file.as_path().to_str().unwrap().to_string();
+
+        let mut boot_src_cfg = BootSourceConfig {
+            kernel_image_path: path(&kernel_file),
+            initrd_path: Some(InitrdPaths(vec![path(&base_file), path(&config_file)])),
+            ..Default::default()
+        };
+        let boot_cfg = BootConfig::new(&boot_src_cfg).unwrap();
+        assert_eq!(boot_cfg.initrd_files.len(), 2);
+
+        boot_src_cfg.initrd_path = Some(InitrdPaths(vec![
+            path(&base_file),
+            "/invalid/path".to_string(),
+        ]));
+        assert!(matches!(
+            BootConfig::new(&boot_src_cfg),
+            Err(BootSourceConfigError::InvalidInitrdPath(_))
+        ));
     }
 }
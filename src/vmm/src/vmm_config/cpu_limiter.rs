@@ -0,0 +1,68 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Auxiliary module for configuring the CPU limiter throttling the vCPUs of the microVM.
+
+use serde::{Deserialize, Serialize};
+
+/// The data fed into a CPU limiter configuration or update request.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct CpuLimiterConfig {
+    /// Host CPU time the vCPUs may use, in thousandths of a CPU: 1500 lets the vCPUs use one and
+    /// a half host CPUs altogether.
+    pub millicpus: u32,
+    /// CPU time in milliseconds, counted at the allocated rate, which the vCPUs may use ahead of
+    /// their allocation after leaving it unused.
+    #[serde(default)]
+    pub burst_ms: u32,
+}
+
+impl CpuLimiterConfig {
+    /// Checks that the configuration lets the vCPUs run.
+    pub fn validate(&self) -> Result<(), CpuLimiterConfigError> {
+        if self.millicpus == 0 {
+            return Err(CpuLimiterConfigError::ZeroMillicpus);
+        }
+        Ok(())
+    }
+}
+
+/// Errors associated with the CPU limiter.
+#[derive(Debug, thiserror::Error, displaydoc::Display, PartialEq, Eq)]
+pub enum CpuLimiterConfigError {
+    /// The CPU allocation of the vCPUs must be greater than 0 millicpus.
+    ZeroMillicpus,
+    /// The CPU limiter can only be updated if it was configured before the microVM started.
+    NotConfigured,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cpu_limiter_config() {
+        let config: CpuLimiterConfig = serde_json::from_str(r#"{"millicpus": 500}"#).unwrap();
+        assert_eq!(
+            config,
+            CpuLimiterConfig {
+                millicpus: 500,
+                burst_ms: 0,
+            }
+        );
+        config.validate().unwrap();
+
+        let config = CpuLimiterConfig {
+            millicpus: 0,
+            burst_ms: 10,
+        };
+        assert_eq!(
+            config.validate().unwrap_err(),
+            CpuLimiterConfigError::ZeroMillicpus
+        );
+
+        serde_json::from_str::<CpuLimiterConfig>(r#"{"millicpus": 500, "quota_us": 1}"#)
+            .unwrap_err();
+    }
+}
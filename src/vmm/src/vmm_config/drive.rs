@@ -7,9 +7,9 @@ use std::sync::{Arc, Mutex};
 
 use serde::{Deserialize, Serialize};
 
-use super::RateLimiterConfig;
+use super::{DeviceQueuesState, RateLimiterConfig};
 use crate::devices::virtio::block::device::Block;
-pub use crate::devices::virtio::block::virtio::device::FileEngineType;
+pub use crate::devices::virtio::block::virtio::device::{DriveBackend, FileEngineType};
 use crate::devices::virtio::block::{BlockError, CacheType};
 use crate::VmmError;
 
@@ -39,8 +39,8 @@ pub struct BlockDeviceConfig {
     /// Setting this flag to true will mount the block device in the
     /// guest under /dev/vda unless the partuuid is present.
     pub is_root_device: bool,
-    /// If set to true, the drive will ignore flush requests coming from
-    /// the guest driver.
+    /// Caching strategy of the drive. With `Unsafe`, the drive ignores flush
+    /// requests coming from the guest driver.
     #[serde(default)]
     pub cache_type: CacheType,
 
@@ -48,7 +48,7 @@ pub struct BlockDeviceConfig {
     /// If set to true, the drive is opened in read-only mode. Otherwise, the
     /// drive is opened as read-write.
     pub is_read_only: Option<bool>,
-    /// Path of the drive.
+    /// Path of the drive, or NBD URI of its export with the `nbd` backend.
     pub path_on_host: Option<String>,
     /// Rate Limiter for I/O operations.
     pub rate_limiter: Option<RateLimiterConfig>,
@@ -58,15 +58,61 @@ pub struct BlockDeviceConfig {
     // pub file_engine_type: FileEngineType,
     #[serde(rename = "io_engine")]
     pub file_engine_type: Option<FileEngineType>,
+    /// Remote image lazily fetched in the file at `path_on_host`, used as a cache.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote: Option<RemoteDriveConfig>,
+    /// If set to true, the drive is opened with `O_DIRECT`, bypassing the host page cache.
+    /// Always the case with the `Directsync` cache type.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub direct_io: Option<bool>,
+    /// The backend from which the image of the drive is read, `file` by default. With `nbd`,
+    /// `path_on_host` is the NBD URI of the export.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backend: Option<DriveBackend>,
+    /// Latency and errors injected in the requests of the drive, for fault testing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency_injection: Option<LatencyInjectionConfig>,
+    /// Id of a shared rate limiter whose budget the drive consumes along with the budget of its
+    /// own rate limiter.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shared_rate_limiter: Option<String>,
 
     // VhostUserBlock specific fields
     /// Path to the vhost-user socket.
     pub socket: Option<String>,
 }
 
+/// Configuration of a drive image fetched chunk by chunk, on first access, from a
+/// content-addressed chunk store.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct RemoteDriveConfig {
+    /// Path of the manifest listing the size of the image and the digests of its chunks.
+    pub manifest_path: String,
+    /// Location of the chunks, either a local directory or an `http://` URL.
+    pub chunk_store: String,
+}
+
+/// Latency and errors injected in the requests of a drive, to test how the guest storage stack
+/// copes with a slow or failing disk.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct LatencyInjectionConfig {
+    /// Delay, in microseconds, added to the completion of every request.
+    #[serde(default)]
+    pub delay_us: u64,
+    /// Maximum random delay, in microseconds, added on top of `delay_us`.
+    #[serde(default)]
+    pub jitter_us: u64,
+    /// Share of the requests, in parts per million, failed with an I/O error instead of being
+    /// executed.
+    #[serde(default)]
+    pub error_rate_ppm: u32,
+}
+
 /// Only provided fields will be updated. I.e. if any optional fields
 /// are missing, they will not be updated.
-#[derive(Debug, Default, PartialEq, Eq, Deserialize)]
+#[derive(Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct BlockDeviceUpdateConfig {
     /// The drive ID, as provided by the user at creation time.
@@ -77,6 +123,9 @@ pub struct BlockDeviceUpdateConfig {
     pub path_on_host: Option<String>,
     /// New rate limiter config.
     pub rate_limiter: Option<RateLimiterConfig>,
+    /// New state of the queue. The device is paused before, and resumed after, the other
+    /// updates are applied.
+    pub state: Option<DeviceQueuesState>,
 }
 
 /// Wrapper for the collection that holds all the Block Devices
@@ -202,6 +251,11 @@ mod tests {
                 path_on_host: self.path_on_host.clone(),
                 rate_limiter: self.rate_limiter,
                 file_engine_type: self.file_engine_type,
+                remote: self.remote.clone(),
+                direct_io: self.direct_io,
+                backend: self.backend,
+                latency_injection: self.latency_injection,
+                shared_rate_limiter: self.shared_rate_limiter.clone(),
 
                 socket: self.socket.clone(),
             }
@@ -229,6 +283,11 @@ mod tests {
             path_on_host: Some(dummy_path),
             rate_limiter: None,
             file_engine_type: None,
+            remote: None,
+            direct_io: None,
+            backend: None,
+            latency_injection: None,
+            shared_rate_limiter: None,
 
             socket: None,
         };
@@ -261,6 +320,11 @@ mod tests {
             path_on_host: Some(dummy_path),
             rate_limiter: None,
             file_engine_type: None,
+            remote: None,
+            direct_io: None,
+            backend: None,
+            latency_injection: None,
+            shared_rate_limiter: None,
 
             socket: None,
         };
@@ -290,6 +354,11 @@ mod tests {
             path_on_host: Some(dummy_path_1),
             rate_limiter: None,
             file_engine_type: None,
+            remote: None,
+            direct_io: None,
+            backend: None,
+            latency_injection: None,
+            shared_rate_limiter: None,
 
             socket: None,
         };
@@ -306,6 +375,11 @@ mod tests {
             path_on_host: Some(dummy_path_2),
             rate_limiter: None,
             file_engine_type: None,
+            remote: None,
+            direct_io: None,
+            backend: None,
+            latency_injection: None,
+            shared_rate_limiter: None,
 
             socket: None,
         };
@@ -333,6 +407,11 @@ mod tests {
             path_on_host: Some(dummy_path_1),
             rate_limiter: None,
             file_engine_type: None,
+            remote: None,
+            direct_io: None,
+            backend: None,
+            latency_injection: None,
+            shared_rate_limiter: None,
 
             socket: None,
         };
@@ -349,6 +428,11 @@ mod tests {
             path_on_host: Some(dummy_path_2),
             rate_limiter: None,
             file_engine_type: None,
+            remote: None,
+            direct_io: None,
+            backend: None,
+            latency_injection: None,
+            shared_rate_limiter: None,
 
             socket: None,
         };
@@ -365,6 +449,11 @@ mod tests {
             path_on_host: Some(dummy_path_3),
             rate_limiter: None,
             file_engine_type: None,
+            remote: None,
+            direct_io: None,
+            backend: None,
+            latency_injection: None,
+            shared_rate_limiter: None,
 
             socket: None,
         };
@@ -406,6 +495,11 @@ mod tests {
             path_on_host: Some(dummy_path_1),
             rate_limiter: None,
             file_engine_type: None,
+            remote: None,
+            direct_io: None,
+            backend: None,
+            latency_injection: None,
+            shared_rate_limiter: None,
 
             socket: None,
         };
@@ -422,6 +516,11 @@ mod tests {
             path_on_host: Some(dummy_path_2),
             rate_limiter: None,
             file_engine_type: None,
+            remote: None,
+            direct_io: None,
+            backend: None,
+            latency_injection: None,
+            shared_rate_limiter: None,
 
             socket: None,
         };
@@ -438,6 +537,11 @@ mod tests {
             path_on_host: Some(dummy_path_3),
             rate_limiter: None,
             file_engine_type: None,
+            remote: None,
+            direct_io: None,
+            backend: None,
+            latency_injection: None,
+            shared_rate_limiter: None,
 
             socket: None,
         };
@@ -480,6 +584,11 @@ mod tests {
             path_on_host: Some(dummy_path_1.clone()),
             rate_limiter: None,
             file_engine_type: None,
+            remote: None,
+            direct_io: None,
+            backend: None,
+            latency_injection: None,
+            shared_rate_limiter: None,
 
             socket: None,
         };
@@ -496,6 +605,11 @@ mod tests {
             path_on_host: Some(dummy_path_2.clone()),
             rate_limiter: None,
             file_engine_type: None,
+            remote: None,
+            direct_io: None,
+            backend: None,
+            latency_injection: None,
+            shared_rate_limiter: None,
 
             socket: None,
         };
@@ -560,6 +674,11 @@ mod tests {
             path_on_host: Some(dummy_path_1),
             rate_limiter: None,
             file_engine_type: None,
+            remote: None,
+            direct_io: None,
+            backend: None,
+            latency_injection: None,
+            shared_rate_limiter: None,
 
             socket: None,
         };
@@ -576,6 +695,11 @@ mod tests {
             path_on_host: Some(dummy_path_2),
             rate_limiter: None,
             file_engine_type: None,
+            remote: None,
+            direct_io: None,
+            backend: None,
+            latency_injection: None,
+            shared_rate_limiter: None,
 
             socket: None,
         };
@@ -602,6 +726,11 @@ mod tests {
             path_on_host: Some(dummy_file.as_path().to_str().unwrap().to_string()),
             rate_limiter: None,
             file_engine_type: Some(FileEngineType::Sync),
+            remote: None,
+            direct_io: None,
+            backend: None,
+            latency_injection: None,
+            shared_rate_limiter: None,
 
             socket: None,
         };
@@ -630,6 +759,11 @@ mod tests {
             path_on_host: Some(backing_file.as_path().to_str().unwrap().to_string()),
             rate_limiter: None,
             file_engine_type: None,
+            remote: None,
+            direct_io: None,
+            backend: None,
+            latency_injection: None,
+            shared_rate_limiter: None,
 
             socket: None,
         };
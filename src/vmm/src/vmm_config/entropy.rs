@@ -7,7 +7,7 @@ use std::sync::{Arc, Mutex};
 use serde::{Deserialize, Serialize};
 
 use super::RateLimiterConfig;
-use crate::devices::virtio::rng::{Entropy, EntropyError};
+use crate::devices::virtio::rng::{Entropy, EntropyError, EntropySourceType};
 
 /// This struct represents the strongly typed equivalent of the json body from entropy device
 /// related requests.
@@ -16,6 +16,9 @@ use crate::devices::virtio::rng::{Entropy, EntropyError};
 pub struct EntropyDeviceConfig {
     /// Configuration for RateLimiter of Entropy device
     pub rate_limiter: Option<RateLimiterConfig>,
+    /// Host source of the random bytes provided to the guest.
+    #[serde(default)]
+    pub source: EntropySourceType,
 }
 
 impl From<&Entropy> for EntropyDeviceConfig {
@@ -23,6 +26,7 @@ impl From<&Entropy> for EntropyDeviceConfig {
         let rate_limiter: RateLimiterConfig = dev.rate_limiter().into();
         EntropyDeviceConfig {
             rate_limiter: rate_limiter.into_option(),
+            source: dev.source_type(),
         }
     }
 }
@@ -56,7 +60,10 @@ impl EntropyDeviceBuilder {
             .rate_limiter
             .map(RateLimiterConfig::try_into)
             .transpose()?;
-        let dev = Arc::new(Mutex::new(Entropy::new(rate_limiter.unwrap_or_default())?));
+        let dev = Arc::new(Mutex::new(Entropy::new(
+            rate_limiter.unwrap_or_default(),
+            config.source,
+        )?));
         self.0 = Some(dev.clone());
 
         Ok(dev)
@@ -102,10 +109,30 @@ mod tests {
         assert_eq!(builder.config().unwrap(), config);
     }
 
+    #[test]
+    fn test_entropy_source_config() {
+        let config: EntropyDeviceConfig = serde_json::from_str("{}").unwrap();
+        assert_eq!(config.source, EntropySourceType::Drbg);
+        let config: EntropyDeviceConfig =
+            serde_json::from_str(r#"{"source": "getrandom"}"#).unwrap();
+        assert_eq!(config.source, EntropySourceType::Getrandom);
+        let config: EntropyDeviceConfig = serde_json::from_str(r#"{"source": {"fd": 3}}"#).unwrap();
+        assert_eq!(config.source, EntropySourceType::Fd(3));
+        serde_json::from_str::<EntropyDeviceConfig>(r#"{"source": "random"}"#).unwrap_err();
+
+        let mut builder = EntropyDeviceBuilder::new();
+        let config = EntropyDeviceConfig {
+            source: EntropySourceType::Urandom,
+            ..Default::default()
+        };
+        builder.insert(config.clone()).unwrap();
+        assert_eq!(builder.config().unwrap(), config);
+    }
+
     #[test]
     fn test_set_device() {
         let mut builder = EntropyDeviceBuilder::new();
-        let device = Entropy::new(RateLimiter::default()).unwrap();
+        let device = Entropy::new(RateLimiter::default(), EntropySourceType::default()).unwrap();
         assert!(builder.0.is_none());
         builder.set_device(Arc::new(Mutex::new(device)));
         assert!(builder.0.is_some());
@@ -1,8 +1,10 @@
 // Copyright 2018 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 use std::fmt::{self, Display, Formatter};
+use std::path::PathBuf;
+use std::sync::OnceLock;
 
-use serde::{ser, Serialize};
+use serde::{de, ser, Deserialize, Serialize};
 
 /// Enumerates microVM runtime states.
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
@@ -35,8 +37,25 @@ impl ser::Serialize for VmState {
     }
 }
 
+impl<'de> de::Deserialize<'de> for VmState {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        match String::deserialize(deserializer)?.as_str() {
+            "Not started" => Ok(VmState::NotStarted),
+            "Paused" => Ok(VmState::Paused),
+            "Running" => Ok(VmState::Running),
+            other => Err(de::Error::unknown_variant(
+                other,
+                &["Not started", "Paused", "Running"],
+            )),
+        }
+    }
+}
+
 /// Serializable struct that contains general information about the microVM.
-#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
 pub struct InstanceInfo {
     /// The ID of the microVM.
     pub id: String,
@@ -47,3 +66,19 @@ pub struct InstanceInfo {
     /// The name of the application that runs the microVM.
     pub app_name: String,
 }
+
+/// Host process details of the VMM, set once at startup since the seccomp filters do not allow
+/// querying them afterwards. The vCPU thread IDs are filled in when they are requested.
+pub static PROCESS_INFO: OnceLock<ProcessInfo> = OnceLock::new();
+
+/// Serializable struct that contains the host process details of the microVM.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ProcessInfo {
+    /// The PID of the VMM process.
+    pub pid: u32,
+    /// The thread IDs of the vCPU threads, ordered by vCPU index. Empty before microVM start.
+    pub vcpu_tids: Vec<i32>,
+    /// The host path of the jail root directory, if the VMM is started by the jailer.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chroot_dir: Option<PathBuf>,
+}
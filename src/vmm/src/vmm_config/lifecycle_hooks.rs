@@ -0,0 +1,166 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Auxiliary module for configuring the hooks run at the lifecycle events of the microVM.
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+/// Default time a hook is given to complete, in milliseconds.
+pub const DEFAULT_HOOK_TIMEOUT_MS: u64 = 5000;
+/// Maximum time a hook can be given to complete, in milliseconds.
+pub const MAX_HOOK_TIMEOUT_MS: u64 = 60_000;
+
+/// Points of the lifecycle of the microVM at which hooks are run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LifecycleEvent {
+    /// The microVM is built, and its vCPUs are about to run for the first time.
+    PreBoot,
+    /// The vCPUs of the microVM were started.
+    PostBoot,
+    /// A snapshot of the microVM is about to be created.
+    PreSnapshot,
+    /// The microVM was restored from a snapshot, and was not resumed yet.
+    PostRestore,
+    /// The VMM is about to stop the microVM and exit.
+    PreShutdown,
+}
+
+impl LifecycleEvent {
+    /// Returns the name of the event, as used in the configuration.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LifecycleEvent::PreBoot => "pre_boot",
+            LifecycleEvent::PostBoot => "post_boot",
+            LifecycleEvent::PreSnapshot => "pre_snapshot",
+            LifecycleEvent::PostRestore => "post_restore",
+            LifecycleEvent::PreShutdown => "pre_shutdown",
+        }
+    }
+}
+
+/// A hook run at a lifecycle event: either a command, or a notification written to a Unix
+/// socket.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct LifecycleHookConfig {
+    /// Lifecycle event at which the hook is run.
+    pub event: LifecycleEvent,
+    /// Program to run and its arguments.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<Vec<String>>,
+    /// Path of the Unix socket to which the notification is written.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub socket_path: Option<PathBuf>,
+    /// Time the hook is given to complete, in milliseconds.
+    #[serde(default = "default_hook_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+fn default_hook_timeout_ms() -> u64 {
+    DEFAULT_HOOK_TIMEOUT_MS
+}
+
+/// Configuration of the hooks run at the lifecycle events of the microVM.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct LifecycleHooksConfig {
+    /// Hooks, run in order at each of their events.
+    pub hooks: Vec<LifecycleHookConfig>,
+}
+
+/// Errors associated with the lifecycle hooks configuration.
+#[rustfmt::skip]
+#[derive(Debug, thiserror::Error, displaydoc::Display, PartialEq, Eq)]
+pub enum LifecycleHooksConfigError {
+    /// Lifecycle hook {0} must set exactly one of command and socket_path.
+    InvalidAction(usize),
+    /// Lifecycle hook {0} has an empty command.
+    EmptyCommand(usize),
+    /// Lifecycle hook {0}: the timeout must be between 1 and {MAX_HOOK_TIMEOUT_MS:} ms.
+    InvalidTimeout(usize),
+}
+
+impl LifecycleHooksConfig {
+    /// Checks that each hook either runs a non empty command or notifies a socket, and that its
+    /// timeout is within bounds.
+    pub fn validate(&self) -> Result<(), LifecycleHooksConfigError> {
+        for (i, hook) in self.hooks.iter().enumerate() {
+            match (&hook.command, &hook.socket_path) {
+                (Some(command), None) if command.is_empty() => {
+                    return Err(LifecycleHooksConfigError::EmptyCommand(i));
+                }
+                (Some(_), None) | (None, Some(_)) => (),
+                _ => return Err(LifecycleHooksConfigError::InvalidAction(i)),
+            }
+            if hook.timeout_ms == 0 || hook.timeout_ms > MAX_HOOK_TIMEOUT_MS {
+                return Err(LifecycleHooksConfigError::InvalidTimeout(i));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns whether any hook runs a command.
+    pub fn has_commands(&self) -> bool {
+        self.hooks.iter().any(|hook| hook.command.is_some())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lifecycle_hooks_config() {
+        let config: LifecycleHooksConfig = serde_json::from_str(
+            r#"{"hooks": [
+                {"event": "pre_snapshot", "command": ["/usr/bin/sync"]},
+                {"event": "post_restore", "socket_path": "/tmp/hooks.sock", "timeout_ms": 100}
+            ]}"#,
+        )
+        .unwrap();
+        config.validate().unwrap();
+        assert!(config.has_commands());
+        assert_eq!(config.hooks[0].event, LifecycleEvent::PreSnapshot);
+        assert_eq!(config.hooks[0].timeout_ms, DEFAULT_HOOK_TIMEOUT_MS);
+        assert_eq!(config.hooks[1].event.as_str(), "post_restore");
+        assert_eq!(config.hooks[1].timeout_ms, 100);
+
+        serde_json::from_str::<LifecycleHooksConfig>(r#"{"hooks": [{"event": "pre_pause"}]}"#)
+            .unwrap_err();
+
+        let mut config = LifecycleHooksConfig {
+            hooks: vec![LifecycleHookConfig {
+                event: LifecycleEvent::PreBoot,
+                command: None,
+                socket_path: None,
+                timeout_ms: DEFAULT_HOOK_TIMEOUT_MS,
+            }],
+        };
+        assert_eq!(
+            config.validate(),
+            Err(LifecycleHooksConfigError::InvalidAction(0))
+        );
+        config.hooks[0].command = Some(vec![]);
+        assert_eq!(
+            config.validate(),
+            Err(LifecycleHooksConfigError::EmptyCommand(0))
+        );
+        config.hooks[0].socket_path = Some(PathBuf::from("/tmp/hooks.sock"));
+        assert_eq!(
+            config.validate(),
+            Err(LifecycleHooksConfigError::InvalidAction(0))
+        );
+        config.hooks[0].command = None;
+        config.validate().unwrap();
+        assert!(!config.has_commands());
+        for timeout_ms in [0, MAX_HOOK_TIMEOUT_MS + 1] {
+            config.hooks[0].timeout_ms = timeout_ms;
+            assert_eq!(
+                config.validate(),
+                Err(LifecycleHooksConfigError::InvalidTimeout(0))
+            );
+        }
+    }
+}
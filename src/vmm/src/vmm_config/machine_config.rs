@@ -4,13 +4,21 @@ use std::fmt::Debug;
 
 use serde::{Deserialize, Serialize};
 
+use crate::arch::{arch_memory_regions, PAGE_SIZE, SYSTEM_MEM_SIZE, SYSTEM_MEM_START};
 use crate::cpu_config::templates::{CpuTemplateType, CustomCpuTemplate, StaticCpuTemplate};
+use crate::utils::usize_to_u64;
+use crate::vstate::memory::{Address, GuestAddress};
 
 /// The default memory size of the VM, in MiB.
 pub const DEFAULT_MEM_SIZE_MIB: usize = 128;
 /// Firecracker aims to support small scale workloads only, so limit the maximum
 /// vCPUs supported.
 pub const MAX_SUPPORTED_VCPUS: u8 = 32;
+/// Maximum length of the name of a reserved memory region, as imposed by the device tree
+/// specification on node names.
+pub const MAX_RESERVED_MEMORY_NAME_LEN: usize = 31;
+/// Maximum number of memory tiers, each exposed to the guest as a NUMA node.
+pub const MAX_MEMORY_TIERS: usize = 8;
 
 /// Errors associated with configuring the microVM.
 #[rustfmt::skip]
@@ -27,12 +35,37 @@ pub enum VmConfigError {
     /// Enabling simultaneous multithreading is not supported on aarch64.
     #[cfg(target_arch = "aarch64")]
     SmtNotSupported,
+    /// Enabling PMU virtualization is only supported on aarch64.
+    #[cfg(target_arch = "x86_64")]
+    PmuNotSupported,
+    /// Enabling nested virtualization is only supported on x86_64.
+    #[cfg(target_arch = "aarch64")]
+    NestedVirtNotSupported,
     /// Could not determine host kernel version when checking hugetlbfs compatibility
     KernelVersion,
     /// Firecracker's huge pages support is incompatible with memory ballooning.
     BalloonAndHugePages,
     /// Firecracker's huge pages support is incompatible with initrds.
     InitrdAndHugePages,
+    /// Invalid reserved memory region name {0:?}: names must be unique, at most {MAX_RESERVED_MEMORY_NAME_LEN:} characters long and only contain alphanumeric characters, ',', '.', '_', '+' or '-'.
+    InvalidReservedMemoryName(String),
+    /// Reserved memory region {0:?} must be non-empty, page aligned, within guest memory and must not overlap other reserved memory regions.
+    InvalidReservedMemoryRegion(String),
+    /// Guest memory backed by guest_memfd is incompatible with dirty page tracking.
+    GuestMemfdAndDirtyPages,
+    /// Guest memory backed by guest_memfd is incompatible with huge pages.
+    GuestMemfdAndHugePages,
+    /// Guest memory backed by guest_memfd is incompatible with memory ballooning.
+    BalloonAndGuestMemfd,
+    /// Invalid memory tier {0:?}: ids must be unique and valid memory region names, sizes must be non-zero, at most {MAX_MEMORY_TIERS:} tiers can be configured and they must fit in the guest physical address space.
+    InvalidMemoryTier(String),
+    /// Guest memory backed by guest_memfd is incompatible with memory tiers.
+    GuestMemfdAndMemoryTiers,
+    /// Enabling the Memory Tagging Extension is incompatible with memory tiers, which are backed by host files.
+    #[cfg(target_arch = "aarch64")]
+    MteAndMemoryTiers,
+    /// The legacy device {0:?} does not exist on this architecture.
+    UnsupportedLegacyDevice(LegacyDevice),
 }
 
 /// Describes the possible (huge)page configurations for a microVM's memory.
@@ -91,6 +124,154 @@ impl From<HugePageConfig> for Option<memfd::HugetlbSize> {
     }
 }
 
+/// Describes what backs the memory of a microVM.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MemoryBackend {
+    /// Back guest memory by memory mapped by Firecracker: anonymous memory, or a memfd if a
+    /// vhost-user device is configured.
+    #[default]
+    Anonymous,
+    /// Back guest memory by a KVM guest_memfd, from which KVM maps guest memory instead of going
+    /// through the address space of Firecracker.
+    GuestMemfd,
+}
+
+/// Legacy devices which can be left out of the microVM when the guest does not use them, e.g. to
+/// avoid reserving their interrupts and IO ports.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LegacyDevice {
+    /// The serial console.
+    Serial,
+    /// The i8042 keyboard controller (x86_64 only).
+    I8042,
+    /// The PL031 real time clock (aarch64 only).
+    Rtc,
+}
+
+impl LegacyDevice {
+    /// Returns `true` iff the device exists on the architecture Firecracker runs on.
+    fn is_supported(&self) -> bool {
+        match self {
+            LegacyDevice::Serial => true,
+            LegacyDevice::I8042 => cfg!(target_arch = "x86_64"),
+            LegacyDevice::Rtc => cfg!(target_arch = "aarch64"),
+        }
+    }
+}
+
+/// Named range of guest physical memory reserved for a specific use, e.g. a shared memory
+/// device or firmware. The range stays backed by guest memory, but it is reported to the guest as
+/// reserved in its memory map, so that the kernel does not use it as regular RAM.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReservedMemoryRegion {
+    /// Name of the region.
+    pub name: String,
+    /// Guest physical address at which the region starts.
+    pub guest_addr: u64,
+    /// Size of the region in bytes.
+    pub size: u64,
+}
+
+impl ReservedMemoryRegion {
+    /// Returns the first guest physical address past the end of the region.
+    pub fn end(&self) -> u64 {
+        self.guest_addr.saturating_add(self.size)
+    }
+}
+
+/// Additional guest memory backed by a host file or device, e.g. a file on a CXL or persistent
+/// memory mount or a DAX device. Each tier is mapped past the guest memory and exposed to the
+/// guest as a separate, CPU-less NUMA node.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct MemoryTier {
+    /// Unique identifier of the tier.
+    pub tier_id: String,
+    /// Path of the host file or device backing the tier.
+    pub path_on_host: String,
+    /// Size of the tier in MiB.
+    pub size_mib: usize,
+}
+
+/// Checks that the memory tiers have valid and unique ids and non-zero sizes, and that they fit
+/// with the guest memory in the guest physical address space.
+fn validate_memory_tiers(tiers: &[MemoryTier], mem_size_mib: usize) -> Result<(), VmConfigError> {
+    if let Some(tier) = tiers.get(MAX_MEMORY_TIERS) {
+        return Err(VmConfigError::InvalidMemoryTier(tier.tier_id.clone()));
+    }
+
+    let mut total_size_mib = mem_size_mib;
+    for (index, tier) in tiers.iter().enumerate() {
+        total_size_mib += tier.size_mib;
+        let layout_size: usize = arch_memory_regions(total_size_mib << 20)
+            .iter()
+            .map(|(_, size)| size)
+            .sum();
+        if !is_valid_region_name(&tier.tier_id)
+            || tiers[..index].iter().any(|t| t.tier_id == tier.tier_id)
+            || tier.size_mib == 0
+            || layout_size != total_size_mib << 20
+        {
+            return Err(VmConfigError::InvalidMemoryTier(tier.tier_id.clone()));
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that `name` can name a memory region in the guest memory map, i.e. that it is a valid
+/// device tree node name.
+pub(crate) fn is_valid_region_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_RESERVED_MEMORY_NAME_LEN
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || ",._+-".contains(c))
+}
+
+/// Checks that the reserved memory regions have valid and unique names, and that they are page
+/// aligned, do not overlap each other and lie within the guest memory available to the kernel.
+fn validate_reserved_memory(
+    regions: &[ReservedMemoryRegion],
+    mem_size_mib: usize,
+) -> Result<(), VmConfigError> {
+    let page_size = PAGE_SIZE as u64;
+    let guest_memory = arch_memory_regions(mem_size_mib << 20);
+    let system_mem_end = SYSTEM_MEM_START + SYSTEM_MEM_SIZE;
+
+    for (index, region) in regions.iter().enumerate() {
+        if !is_valid_region_name(&region.name)
+            || regions[..index].iter().any(|r| r.name == region.name)
+        {
+            return Err(VmConfigError::InvalidReservedMemoryName(
+                region.name.clone(),
+            ));
+        }
+
+        let in_guest_memory = guest_memory.iter().any(|(start, size)| {
+            start.0 <= region.guest_addr && region.end() <= start.0 + *size as u64
+        });
+        if region.size == 0
+            || region.guest_addr % page_size != 0
+            || region.size % page_size != 0
+            || region.guest_addr < system_mem_end
+            || !in_guest_memory
+            || regions[..index]
+                .iter()
+                .any(|r| r.guest_addr < region.end() && region.guest_addr < r.end())
+        {
+            return Err(VmConfigError::InvalidReservedMemoryRegion(
+                region.name.clone(),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
 /// Struct used in PUT `/machine-config` API call.
 #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
@@ -111,6 +292,27 @@ pub struct MachineConfig {
     /// Configures what page size Firecracker should use to back guest memory.
     #[serde(default)]
     pub huge_pages: HugePageConfig,
+    /// Enables the virtual PMU (aarch64 only).
+    #[serde(default)]
+    pub pmu: bool,
+    /// Exposes the hardware virtualization extensions to the guest (x86_64 only).
+    #[serde(default)]
+    pub nested_virt: bool,
+    /// Named guest physical memory ranges reported as reserved to the guest.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reserved_memory: Vec<ReservedMemoryRegion>,
+    /// Configures what backs guest memory.
+    #[serde(default)]
+    pub memory_backend: MemoryBackend,
+    /// Faults in the guest memory before the microVM starts.
+    #[serde(default)]
+    pub prefault: bool,
+    /// Additional guest memory backed by host files or devices, exposed as NUMA nodes.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub memory_tiers: Vec<MemoryTier>,
+    /// Legacy devices left out of the microVM.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub disabled_legacy_devices: Vec<LegacyDevice>,
     /// GDB socket address.
     #[cfg(feature = "gdb")]
     #[serde(default, skip_serializing_if = "Option::is_none")]
@@ -150,6 +352,27 @@ pub struct MachineConfigUpdate {
     /// Configures what page size Firecracker should use to back guest memory.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub huge_pages: Option<HugePageConfig>,
+    /// Enables the virtual PMU (aarch64 only).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pmu: Option<bool>,
+    /// Exposes the hardware virtualization extensions to the guest (x86_64 only).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nested_virt: Option<bool>,
+    /// Named guest physical memory ranges reported as reserved to the guest.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reserved_memory: Option<Vec<ReservedMemoryRegion>>,
+    /// Configures what backs guest memory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_backend: Option<MemoryBackend>,
+    /// Faults in the guest memory before the microVM starts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prefault: Option<bool>,
+    /// Additional guest memory backed by host files or devices, exposed as NUMA nodes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_tiers: Option<Vec<MemoryTier>>,
+    /// Legacy devices left out of the microVM.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disabled_legacy_devices: Option<Vec<LegacyDevice>>,
     /// GDB socket address.
     #[cfg(feature = "gdb")]
     #[serde(default, skip_serializing_if = "Option::is_none")]
@@ -174,6 +397,13 @@ impl From<MachineConfig> for MachineConfigUpdate {
             cpu_template: cfg.cpu_template,
             track_dirty_pages: Some(cfg.track_dirty_pages),
             huge_pages: Some(cfg.huge_pages),
+            pmu: Some(cfg.pmu),
+            nested_virt: Some(cfg.nested_virt),
+            reserved_memory: Some(cfg.reserved_memory),
+            memory_backend: Some(cfg.memory_backend),
+            prefault: Some(cfg.prefault),
+            memory_tiers: Some(cfg.memory_tiers),
+            disabled_legacy_devices: Some(cfg.disabled_legacy_devices),
             #[cfg(feature = "gdb")]
             gdb_socket_path: cfg.gdb_socket_path,
         }
@@ -195,12 +425,62 @@ pub struct VmConfig {
     pub track_dirty_pages: bool,
     /// Configures what page size Firecracker should use to back guest memory.
     pub huge_pages: HugePageConfig,
+    /// Enables the virtual PMU (aarch64 only).
+    pub pmu: bool,
+    /// Exposes the hardware virtualization extensions to the guest (x86_64 only).
+    pub nested_virt: bool,
+    /// Named guest physical memory ranges reported as reserved to the guest.
+    pub reserved_memory: Vec<ReservedMemoryRegion>,
+    /// Configures what backs guest memory.
+    pub memory_backend: MemoryBackend,
+    /// Faults in the guest memory before the microVM starts.
+    pub prefault: bool,
+    /// Additional guest memory backed by host files or devices, exposed as NUMA nodes.
+    pub memory_tiers: Vec<MemoryTier>,
+    /// Legacy devices left out of the microVM.
+    pub disabled_legacy_devices: Vec<LegacyDevice>,
     /// GDB socket address.
     #[cfg(feature = "gdb")]
     pub gdb_socket_path: Option<String>,
 }
 
 impl VmConfig {
+    /// Lays out the guest memory followed by the memory tiers in the guest physical address
+    /// space. The first element holds the regions of the guest memory and the following ones the
+    /// regions of each memory tier, so that the index of an element is its NUMA node.
+    pub fn guest_memory_layout(&self) -> Vec<Vec<(GuestAddress, usize)>> {
+        let sizes: Vec<usize> = std::iter::once(self.mem_size_mib)
+            .chain(self.memory_tiers.iter().map(|tier| tier.size_mib))
+            .map(|size_mib| size_mib << 20)
+            .collect();
+        let mut regions = arch_memory_regions(sizes.iter().sum()).into_iter();
+        let mut next_region = regions.next();
+
+        sizes
+            .iter()
+            .map(|size| {
+                let mut node_regions = Vec::new();
+                let mut remaining = *size;
+                while let Some((addr, region_size)) = next_region.filter(|_| remaining > 0) {
+                    let len = remaining.min(region_size);
+                    node_regions.push((addr, len));
+                    remaining -= len;
+                    next_region = if len == region_size {
+                        regions.next()
+                    } else {
+                        Some((addr.unchecked_add(usize_to_u64(len)), region_size - len))
+                    };
+                }
+                node_regions
+            })
+            .collect()
+    }
+
+    /// Returns `true` iff the legacy device is part of the microVM.
+    pub fn has_legacy_device(&self, device: LegacyDevice) -> bool {
+        device.is_supported() && !self.disabled_legacy_devices.contains(&device)
+    }
+
     /// Sets cpu tempalte field to `CpuTemplateType::Custom(cpu_template)`.
     pub fn set_custom_cpu_template(&mut self, cpu_template: CustomCpuTemplate) {
         self.cpu_template = Some(CpuTemplateType::Custom(cpu_template));
@@ -238,19 +518,80 @@ impl VmConfig {
             return Err(VmConfigError::InvalidMemorySize);
         }
 
+        let track_dirty_pages = update.track_dirty_pages.unwrap_or(self.track_dirty_pages);
+        let memory_backend = update.memory_backend.unwrap_or(self.memory_backend);
+
+        if memory_backend == MemoryBackend::GuestMemfd {
+            if track_dirty_pages {
+                return Err(VmConfigError::GuestMemfdAndDirtyPages);
+            }
+            if page_config.is_hugetlbfs() {
+                return Err(VmConfigError::GuestMemfdAndHugePages);
+            }
+        }
+
+        let pmu = update.pmu.unwrap_or(self.pmu);
+
+        #[cfg(target_arch = "x86_64")]
+        if pmu {
+            return Err(VmConfigError::PmuNotSupported);
+        }
+
+        let nested_virt = update.nested_virt.unwrap_or(self.nested_virt);
+
+        #[cfg(target_arch = "aarch64")]
+        if nested_virt {
+            return Err(VmConfigError::NestedVirtNotSupported);
+        }
+
+        let reserved_memory = update
+            .reserved_memory
+            .as_ref()
+            .unwrap_or(&self.reserved_memory);
+        validate_reserved_memory(reserved_memory, mem_size_mib)?;
+
+        let memory_tiers = update.memory_tiers.as_ref().unwrap_or(&self.memory_tiers);
+        validate_memory_tiers(memory_tiers, mem_size_mib)?;
+        if memory_backend == MemoryBackend::GuestMemfd && !memory_tiers.is_empty() {
+            return Err(VmConfigError::GuestMemfdAndMemoryTiers);
+        }
+
+        let disabled_legacy_devices = update
+            .disabled_legacy_devices
+            .as_ref()
+            .unwrap_or(&self.disabled_legacy_devices);
+        if let Some(device) = disabled_legacy_devices.iter().find(|d| !d.is_supported()) {
+            return Err(VmConfigError::UnsupportedLegacyDevice(*device));
+        }
+
         let cpu_template = match update.cpu_template {
             None => self.cpu_template.clone(),
             Some(StaticCpuTemplate::None) => None,
             Some(other) => Some(CpuTemplateType::Static(other)),
         };
 
+        // KVM can't store allocation tags for memory mapped from regular host files.
+        #[cfg(target_arch = "aarch64")]
+        if !memory_tiers.is_empty()
+            && matches!(&cpu_template, Some(CpuTemplateType::Custom(template)) if template.mte)
+        {
+            return Err(VmConfigError::MteAndMemoryTiers);
+        }
+
         Ok(VmConfig {
             vcpu_count,
             mem_size_mib,
             smt,
             cpu_template,
-            track_dirty_pages: update.track_dirty_pages.unwrap_or(self.track_dirty_pages),
+            track_dirty_pages,
             huge_pages: page_config,
+            pmu,
+            nested_virt,
+            reserved_memory: reserved_memory.clone(),
+            memory_backend,
+            prefault: update.prefault.unwrap_or(self.prefault),
+            memory_tiers: memory_tiers.clone(),
+            disabled_legacy_devices: disabled_legacy_devices.clone(),
             #[cfg(feature = "gdb")]
             gdb_socket_path: update.gdb_socket_path.clone(),
         })
@@ -266,6 +607,13 @@ impl Default for VmConfig {
             cpu_template: None,
             track_dirty_pages: false,
             huge_pages: HugePageConfig::None,
+            pmu: false,
+            nested_virt: false,
+            reserved_memory: Vec::new(),
+            memory_backend: MemoryBackend::Anonymous,
+            prefault: false,
+            memory_tiers: Vec::new(),
+            disabled_legacy_devices: Vec::new(),
             #[cfg(feature = "gdb")]
             gdb_socket_path: None,
         }
@@ -281,6 +629,13 @@ impl From<&VmConfig> for MachineConfig {
             cpu_template: value.cpu_template.as_ref().map(|template| template.into()),
             track_dirty_pages: value.track_dirty_pages,
             huge_pages: value.huge_pages,
+            pmu: value.pmu,
+            nested_virt: value.nested_virt,
+            reserved_memory: value.reserved_memory.clone(),
+            memory_backend: value.memory_backend,
+            prefault: value.prefault,
+            memory_tiers: value.memory_tiers.clone(),
+            disabled_legacy_devices: value.disabled_legacy_devices.clone(),
             #[cfg(feature = "gdb")]
             gdb_socket_path: value.gdb_socket_path.clone(),
         }
@@ -0,0 +1,139 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Auxiliary module for resizing the memory available to the guest, whatever the mechanism used.
+
+use serde::{Deserialize, Serialize};
+
+use crate::devices::virtio::balloon::BalloonError;
+
+/// The data fed into a memory target update request.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct MemoryTargetConfig {
+    /// Size in MiB of the memory the guest should be left with.
+    pub target_mib: u32,
+}
+
+/// Mechanisms through which the memory available to the guest is resized.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MemoryTargetMechanism {
+    /// The balloon device, inflated to reclaim memory and deflated to return it.
+    Balloon,
+}
+
+/// Progress of the memory available to the guest towards its target size.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct MemoryTargetStatus {
+    /// Mechanism through which the memory is resized.
+    pub mechanism: MemoryTargetMechanism,
+    /// Size in MiB of the guest memory, when none of it is reclaimed.
+    pub size_mib: u32,
+    /// Size in MiB of the memory the guest should be left with.
+    pub target_mib: u32,
+    /// Size in MiB of the memory the guest is currently left with.
+    pub actual_mib: u32,
+    /// Whether the memory available to the guest reached its target size.
+    pub reached: bool,
+}
+
+impl MemoryTargetStatus {
+    /// Builds the status of a guest with `size_mib` of memory, from the target size of the balloon
+    /// and the size of the memory it currently holds.
+    pub fn from_balloon(size_mib: u32, balloon_target_mib: u32, balloon_actual_mib: u32) -> Self {
+        let target_mib = size_mib.saturating_sub(balloon_target_mib);
+        let actual_mib = size_mib.saturating_sub(balloon_actual_mib);
+        MemoryTargetStatus {
+            mechanism: MemoryTargetMechanism::Balloon,
+            size_mib,
+            target_mib,
+            actual_mib,
+            reached: target_mib == actual_mib,
+        }
+    }
+}
+
+/// Errors associated with the memory target of the guest.
+#[rustfmt::skip]
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum MemoryTargetError {
+    /// No mechanism can resize the memory of the guest: a balloon device is required.
+    NoMechanism,
+    /// The memory target cannot be greater than the guest memory size of {0} MiB.
+    TooLarge(u32),
+    /// The balloon target size is adjusted automatically, and would override the memory target.
+    AutoTarget,
+    /// Balloon error: {0}
+    Balloon(BalloonError),
+}
+
+impl From<BalloonError> for MemoryTargetError {
+    fn from(err: BalloonError) -> Self {
+        match err {
+            BalloonError::DeviceNotFound => MemoryTargetError::NoMechanism,
+            err => MemoryTargetError::Balloon(err),
+        }
+    }
+}
+
+/// Returns the target size of the balloon leaving `target_mib` of the `size_mib` of guest memory
+/// to the guest.
+pub fn balloon_target_mib(size_mib: u32, target_mib: u32) -> Result<u32, MemoryTargetError> {
+    size_mib
+        .checked_sub(target_mib)
+        .ok_or(MemoryTargetError::TooLarge(size_mib))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_balloon_target_mib() {
+        assert_eq!(balloon_target_mib(1024, 256).unwrap(), 768);
+        assert_eq!(balloon_target_mib(1024, 1024).unwrap(), 0);
+        assert_eq!(
+            balloon_target_mib(1024, 1025).unwrap_err().to_string(),
+            "The memory target cannot be greater than the guest memory size of 1024 MiB."
+        );
+    }
+
+    #[test]
+    fn test_status_from_balloon() {
+        let status = MemoryTargetStatus::from_balloon(1024, 768, 512);
+        assert_eq!(
+            status,
+            MemoryTargetStatus {
+                mechanism: MemoryTargetMechanism::Balloon,
+                size_mib: 1024,
+                target_mib: 256,
+                actual_mib: 512,
+                reached: false,
+            }
+        );
+        assert!(MemoryTargetStatus::from_balloon(1024, 768, 768).reached);
+        assert_eq!(
+            serde_json::to_value(&status).unwrap(),
+            serde_json::json!({
+                "mechanism": "balloon",
+                "size_mib": 1024,
+                "target_mib": 256,
+                "actual_mib": 512,
+                "reached": false
+            })
+        );
+    }
+
+    #[test]
+    fn test_from_balloon_error() {
+        assert!(matches!(
+            MemoryTargetError::from(BalloonError::DeviceNotFound),
+            MemoryTargetError::NoMechanism
+        ));
+        assert!(matches!(
+            MemoryTargetError::from(BalloonError::DeviceNotActive),
+            MemoryTargetError::Balloon(BalloonError::DeviceNotActive)
+        ));
+    }
+}
@@ -1,12 +1,34 @@
 // Copyright 2020 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
+use std::collections::BTreeMap;
 use std::net::Ipv4Addr;
+use std::path::PathBuf;
 
 use serde::{Deserialize, Serialize};
 
 use crate::mmds::data_store;
 use crate::mmds::data_store::MmdsVersion;
 
+/// Default time the MMDS backend is given to answer a request, in milliseconds.
+pub const DEFAULT_BACKEND_TIMEOUT_MS: u64 = 100;
+/// Maximum time the MMDS backend can be given to answer a request, in milliseconds.
+pub const MAX_BACKEND_TIMEOUT_MS: u64 = 5000;
+
+/// Unix socket serving the MMDS content retrieved by the guest, in place of the data store.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct MmdsBackendConfig {
+    /// Path of the Unix socket to which the requests are written.
+    pub socket_path: PathBuf,
+    /// Time the backend is given to answer a request, in milliseconds.
+    #[serde(default = "default_backend_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+fn default_backend_timeout_ms() -> u64 {
+    DEFAULT_BACKEND_TIMEOUT_MS
+}
+
 /// Keeps the MMDS configuration.
 #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
@@ -18,6 +40,17 @@ pub struct MmdsConfig {
     pub network_interfaces: Vec<String>,
     /// MMDS IPv4 configured address.
     pub ipv4_address: Option<Ipv4Addr>,
+    /// Size limit, in bytes, of the key/value pairs the guest is allowed to write.
+    /// Guest writes are disabled when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub guest_data_limit: Option<usize>,
+    /// Path prefixes of the MMDS content the guest can retrieve through each network interface.
+    /// The content is not restricted on the network interfaces not listed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path_acl: Option<BTreeMap<String, Vec<String>>>,
+    /// Unix socket serving the MMDS content retrieved by the guest, in place of the data store.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backend: Option<MmdsBackendConfig>,
 }
 
 impl MmdsConfig {
@@ -36,6 +69,29 @@ impl MmdsConfig {
     pub fn ipv4_addr(&self) -> Option<Ipv4Addr> {
         self.ipv4_address
     }
+
+    /// Returns the size limit of the guest writable data if guest writes were enabled.
+    /// Otherwise returns None.
+    pub fn guest_data_limit(&self) -> Option<usize> {
+        self.guest_data_limit
+    }
+
+    /// Returns the path prefixes of the MMDS content which can be retrieved through the network
+    /// interface `iface_id`, if the content is restricted on it.
+    pub fn allowed_paths(&self, iface_id: &str) -> Option<Vec<String>> {
+        self.path_acl
+            .as_ref()
+            .and_then(|acl| acl.get(iface_id))
+            .cloned()
+    }
+}
+
+/// The data fed into an MMDS session revocation request.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct MmdsSessionRevocation {
+    /// Identifier of the session to revoke, as listed by the host, or its token.
+    pub session_id: String,
 }
 
 /// MMDS configuration related errors.
@@ -50,4 +106,12 @@ pub enum MmdsConfigError {
     InvalidNetworkInterfaceId,
     /// The MMDS could not be configured to version {0}: {1}
     MmdsVersion(MmdsVersion, data_store::MmdsDatastoreError),
+    /// The size limit of the guest writable data cannot be 0.
+    InvalidGuestDataLimit,
+    /// The MMDS path ACL references the network interface {0}, which does not forward MMDS requests.
+    InvalidPathAclIface(String),
+    /// Invalid MMDS path ACL prefix {0}: it must start with '/'.
+    InvalidPathAclPrefix(String),
+    /// Invalid MMDS backend timeout of {0} ms: it must be between 1 and 5000 ms.
+    InvalidBackendTimeout(u64),
 }
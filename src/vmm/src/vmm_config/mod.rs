@@ -14,24 +14,48 @@ use crate::rate_limiter::{BucketUpdate, RateLimiter, TokenBucket};
 
 /// Wrapper for configuring the balloon device.
 pub mod balloon;
+/// Wrapper for the typed fields of the kernel command line.
+pub mod boot_args;
 /// Wrapper for configuring the microVM boot source.
 pub mod boot_source;
+/// Wrapper for configuring the CPU limiter throttling the vCPUs.
+pub mod cpu_limiter;
 /// Wrapper for configuring the block devices.
 pub mod drive;
 /// Wrapper for configuring the entropy device attached to the microVM.
 pub mod entropy;
 /// Wrapper over the microVM general information attached to the microVM.
 pub mod instance_info;
+/// Wrapper for configuring the hooks run at the lifecycle events of the microVM.
+pub mod lifecycle_hooks;
 /// Wrapper for configuring the memory and CPU of the microVM.
 pub mod machine_config;
+/// Wrapper for resizing the memory available to the guest.
+pub mod memory_target;
 /// Wrapper for configuring the metrics.
 pub mod metrics;
 /// Wrapper for configuring the MMDS.
 pub mod mmds;
 /// Wrapper for configuring the network devices attached to the microVM.
 pub mod net;
+/// Wrapper for configuring the pvpanic device attached to the microVM.
+pub mod pvpanic;
+/// Wrapper for configuring the rate limiters shared by several devices.
+pub mod rate_limiters;
+/// Wrapper for tightening the seccomp filters of the threads of a running microVM.
+pub mod seccomp;
+/// Wrapper for configuring the host side of the serial console.
+pub mod serial;
+/// Wrapper for configuring the read-only memory segments shared between microVMs.
+pub mod shared_memory;
+/// Wrapper for asking the guest to shut down gracefully.
+pub mod shutdown;
+/// Wrapper for configuring the SMBIOS tables exposed to the microVM.
+pub mod smbios;
 /// Wrapper for configuring microVM snapshots and the microVM state.
 pub mod snapshot;
+/// Wrapper for configuring the TPM device attached to the microVM.
+pub mod tpm;
 /// Wrapper for configuring the vsock devices attached to the microVM.
 pub mod vsock;
 
@@ -70,6 +94,17 @@ impl From<&TokenBucket> for TokenBucketConfig {
     }
 }
 
+/// State of the queues of a device, which can be paused for maintenance operations without
+/// pausing the whole microVM.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceQueuesState {
+    /// The queues are not processed, the requests of the guest are left in them.
+    Paused,
+    /// The queues are processed.
+    Resumed,
+}
+
 /// A public-facing, stateless structure, holding all the data we need to create a RateLimiter
 /// (live) object.
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
@@ -79,6 +114,14 @@ pub struct RateLimiterConfig {
     pub bandwidth: Option<TokenBucketConfig>,
     /// Data used to initialize the RateLimiter::ops bucket.
     pub ops: Option<TokenBucketConfig>,
+    /// Whether the RateLimiter may borrow the unused budget of its peer limiter. Only network
+    /// interfaces have peer limiters: the RX and TX limiters of the same interface.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub borrow: Option<bool>,
+    /// Whether the RateLimiter, once blocked, resumes as soon as the missing tokens are
+    /// replenished rather than after a fixed interval.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub smoothing: Option<bool>,
 }
 
 /// A public-facing, stateless structure, specifying RateLimiter properties updates.
@@ -88,6 +131,10 @@ pub struct RateLimiterUpdate {
     pub bandwidth: BucketUpdate,
     /// Possible update to the RateLimiter::ops bucket.
     pub ops: BucketUpdate,
+    /// Possible update to the RateLimiter borrowing flag.
+    pub borrow: Option<bool>,
+    /// Possible update to the RateLimiter smoothing mode.
+    pub smoothing: Option<bool>,
 }
 
 fn get_bucket_update(tb_cfg: &Option<TokenBucketConfig>) -> BucketUpdate {
@@ -115,12 +162,16 @@ impl From<Option<RateLimiterConfig>> for RateLimiterUpdate {
             RateLimiterUpdate {
                 bandwidth: get_bucket_update(&cfg.bandwidth),
                 ops: get_bucket_update(&cfg.ops),
+                borrow: cfg.borrow,
+                smoothing: cfg.smoothing,
             }
         } else {
             // No update to the rate-limiter.
             RateLimiterUpdate {
                 bandwidth: BucketUpdate::None,
                 ops: BucketUpdate::None,
+                borrow: None,
+                smoothing: None,
             }
         }
     }
@@ -132,14 +183,17 @@ impl TryInto<RateLimiter> for RateLimiterConfig {
     fn try_into(self) -> Result<RateLimiter, Self::Error> {
         let bw = self.bandwidth.unwrap_or_default();
         let ops = self.ops.unwrap_or_default();
-        RateLimiter::new(
+        let mut rate_limiter = RateLimiter::new(
             bw.size,
             bw.one_time_burst.unwrap_or(0),
             bw.refill_time,
             ops.size,
             ops.one_time_burst.unwrap_or(0),
             ops.refill_time,
-        )
+        )?;
+        rate_limiter.set_borrow(self.borrow.unwrap_or(false));
+        rate_limiter.set_smoothing(self.smoothing.unwrap_or(false));
+        Ok(rate_limiter)
     }
 }
 
@@ -148,6 +202,8 @@ impl From<&RateLimiter> for RateLimiterConfig {
         RateLimiterConfig {
             bandwidth: rl.bandwidth().map(TokenBucketConfig::from),
             ops: rl.ops().map(TokenBucketConfig::from),
+            borrow: rl.borrow().then_some(true),
+            smoothing: rl.smoothing().then_some(true),
         }
     }
 }
@@ -156,7 +212,11 @@ impl RateLimiterConfig {
     /// [`Option<T>`] already implements [`From<T>`] so we have to use a custom
     /// one.
     pub fn into_option(self) -> Option<RateLimiterConfig> {
-        if self.bandwidth.is_some() || self.ops.is_some() {
+        if self.bandwidth.is_some()
+            || self.ops.is_some()
+            || self.borrow.is_some()
+            || self.smoothing.is_some()
+        {
             Some(self)
         } else {
             None
@@ -197,8 +257,11 @@ mod tests {
                 one_time_burst: None,
                 refill_time: REFILL_TIME * 2,
             }),
+            borrow: None,
+            smoothing: None,
         };
         let rl: RateLimiter = rlconf.try_into().unwrap();
+        assert!(!rl.borrow());
         assert_eq!(rl.bandwidth().unwrap().capacity(), SIZE);
         assert_eq!(rl.bandwidth().unwrap().one_time_burst(), ONE_TIME_BURST);
         assert_eq!(rl.bandwidth().unwrap().refill_time_ms(), REFILL_TIME);
@@ -221,10 +284,34 @@ mod tests {
         let rl_conf = RateLimiterConfig {
             bandwidth: Some(bw_tb_cfg),
             ops: None,
+            borrow: None,
+            smoothing: None,
         };
         let rl: RateLimiter = rl_conf.try_into().unwrap();
         let generated_rl_conf = RateLimiterConfig::from(&rl);
         assert_eq!(generated_rl_conf, rl_conf);
         assert_eq!(generated_rl_conf.into_option(), Some(rl_conf));
+
+        let rl_conf = RateLimiterConfig {
+            bandwidth: Some(bw_tb_cfg),
+            ops: None,
+            borrow: Some(true),
+            smoothing: None,
+        };
+        let rl: RateLimiter = rl_conf.try_into().unwrap();
+        assert!(rl.borrow());
+        let generated_rl_conf = RateLimiterConfig::from(&rl);
+        assert_eq!(generated_rl_conf, rl_conf);
+
+        let rl_conf = RateLimiterConfig {
+            bandwidth: Some(bw_tb_cfg),
+            ops: None,
+            borrow: None,
+            smoothing: Some(true),
+        };
+        let rl: RateLimiter = rl_conf.try_into().unwrap();
+        assert!(rl.smoothing());
+        let generated_rl_conf = RateLimiterConfig::from(&rl);
+        assert_eq!(generated_rl_conf, rl_conf);
     }
 }
@@ -3,12 +3,23 @@
 
 use std::convert::TryInto;
 use std::ops::Deref;
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 
 use serde::{Deserialize, Serialize};
 
-use super::RateLimiterConfig;
-use crate::devices::virtio::net::{Net, TapError};
+use super::{DeviceQueuesState, RateLimiterConfig};
+use crate::devices::virtio::gen::virtio_net::{
+    VIRTIO_NET_F_CSUM, VIRTIO_NET_F_GUEST_CSUM, VIRTIO_NET_F_GUEST_TSO4, VIRTIO_NET_F_GUEST_TSO6,
+    VIRTIO_NET_F_GUEST_UFO, VIRTIO_NET_F_HOST_TSO4, VIRTIO_NET_F_HOST_TSO6, VIRTIO_NET_F_HOST_UFO,
+};
+use crate::devices::virtio::net::capture::{CaptureError, DEFAULT_RING_SIZE, DEFAULT_SNAPLEN};
+use crate::devices::virtio::net::coalesce::MAX_COALESCED_SEGMENTS;
+use crate::devices::virtio::net::device::{MacFilter, NET_OFFLOAD_FEATURES};
+use crate::devices::virtio::net::flows::MAX_FLOW_TABLE_SIZE;
+use crate::devices::virtio::net::pause_responder::MAX_TRACKED_CONNECTIONS;
+use crate::devices::virtio::net::{Net, TapError, MAX_QUEUE_PAIRS, MIN_MTU};
+use crate::rate_limiter::{shared, RateLimiter};
 use crate::utils::net::mac::MacAddr;
 use crate::VmmError;
 
@@ -19,7 +30,8 @@ use crate::VmmError;
 pub struct NetworkInterfaceConfig {
     /// ID of the guest network interface.
     pub iface_id: String,
-    /// Host level path for the guest network interface.
+    /// Host level path for the guest network interface. Empty for MMDS-only interfaces.
+    #[serde(default)]
     pub host_dev_name: String,
     /// Guest MAC address.
     pub guest_mac: Option<MacAddr>,
@@ -27,6 +39,151 @@ pub struct NetworkInterfaceConfig {
     pub rx_rate_limiter: Option<RateLimiterConfig>,
     /// Rate Limiter for transmitted packages.
     pub tx_rate_limiter: Option<RateLimiterConfig>,
+    /// Per-flow accounting of the traffic.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flow_accounting: Option<FlowAccountingConfig>,
+    /// Answers the TCP peers of the guest on its behalf while the microVM is paused.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pause_responder: Option<PauseResponderConfig>,
+    /// Merges consecutive TCP segments of the same flow received for the guest.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rx_coalescing: Option<RxCoalescingConfig>,
+    /// MTU of the tap, also advertised to the guest.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mtu: Option<u16>,
+    /// Whether the interface is not connected to any tap, and only exchanges frames with the
+    /// MMDS.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mmds_only: Option<bool>,
+    /// Number of RX/TX queue pairs, each backed by a queue of the tap, which then has to be a
+    /// multi-queue tap if there is more than one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub num_queues: Option<u16>,
+    /// Offloads offered to the guest, all of them if not specified.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub offloads: Option<OffloadsConfig>,
+    /// Whether the frames sent by the guest from a source MAC address other than `guest_mac` are
+    /// dropped.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enforce_mac: Option<bool>,
+    /// Whether the ARP frames sent by the guest with a sender hardware address other than
+    /// `guest_mac` are dropped.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enforce_arp: Option<bool>,
+    /// Id of a shared rate limiter whose budget the RX and TX rate limiters of the interface
+    /// consume along with their own.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shared_rate_limiter: Option<String>,
+}
+
+/// Configuration of the per-flow accounting of a network interface.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct FlowAccountingConfig {
+    /// Maximum number of flows tracked at the same time.
+    pub max_flows: usize,
+}
+
+/// Configuration of the TCP pause responder of a network interface.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct PauseResponderConfig {
+    /// Maximum number of TCP connections tracked at the same time.
+    pub max_connections: usize,
+}
+
+/// Configuration of the coalescing of the TCP segments received on a network interface.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct RxCoalescingConfig {
+    /// Maximum number of segments merged into a single frame.
+    pub max_segments: usize,
+}
+
+/// Configuration of the offloads offered to the guest on a network interface. The offloads not
+/// specified are enabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct OffloadsConfig {
+    /// Checksum offload of the frames received by the guest.
+    #[serde(default = "default_true")]
+    pub guest_csum: bool,
+    /// TCP segmentation offload over IPv4 of the frames received by the guest.
+    #[serde(default = "default_true")]
+    pub guest_tso4: bool,
+    /// TCP segmentation offload over IPv6 of the frames received by the guest.
+    #[serde(default = "default_true")]
+    pub guest_tso6: bool,
+    /// UDP fragmentation offload of the frames received by the guest.
+    #[serde(default = "default_true")]
+    pub guest_ufo: bool,
+    /// Checksum offload of the frames sent by the guest.
+    #[serde(default = "default_true")]
+    pub host_csum: bool,
+    /// TCP segmentation offload over IPv4 of the frames sent by the guest.
+    #[serde(default = "default_true")]
+    pub host_tso4: bool,
+    /// TCP segmentation offload over IPv6 of the frames sent by the guest.
+    #[serde(default = "default_true")]
+    pub host_tso6: bool,
+    /// UDP fragmentation offload of the frames sent by the guest.
+    #[serde(default = "default_true")]
+    pub host_ufo: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl OffloadsConfig {
+    // Pairs each offload with its virtio feature bit.
+    fn offloads(&self) -> [(bool, u32); 8] {
+        [
+            (self.guest_csum, VIRTIO_NET_F_GUEST_CSUM),
+            (self.guest_tso4, VIRTIO_NET_F_GUEST_TSO4),
+            (self.guest_tso6, VIRTIO_NET_F_GUEST_TSO6),
+            (self.guest_ufo, VIRTIO_NET_F_GUEST_UFO),
+            (self.host_csum, VIRTIO_NET_F_CSUM),
+            (self.host_tso4, VIRTIO_NET_F_HOST_TSO4),
+            (self.host_tso6, VIRTIO_NET_F_HOST_TSO6),
+            (self.host_ufo, VIRTIO_NET_F_HOST_UFO),
+        ]
+    }
+
+    /// Returns the virtio features of the enabled offloads.
+    pub fn features(&self) -> u64 {
+        self.offloads()
+            .iter()
+            .filter(|(enabled, _)| *enabled)
+            .fold(0, |features, (_, bit)| features | 1 << bit)
+    }
+
+    /// Builds the configuration of the offloads from their virtio features.
+    pub fn from_features(features: u64) -> Self {
+        let enabled = |bit: u32| features & (1 << bit) != 0;
+        OffloadsConfig {
+            guest_csum: enabled(VIRTIO_NET_F_GUEST_CSUM),
+            guest_tso4: enabled(VIRTIO_NET_F_GUEST_TSO4),
+            guest_tso6: enabled(VIRTIO_NET_F_GUEST_TSO6),
+            guest_ufo: enabled(VIRTIO_NET_F_GUEST_UFO),
+            host_csum: enabled(VIRTIO_NET_F_CSUM),
+            host_tso4: enabled(VIRTIO_NET_F_HOST_TSO4),
+            host_tso6: enabled(VIRTIO_NET_F_HOST_TSO6),
+            host_ufo: enabled(VIRTIO_NET_F_HOST_UFO),
+        }
+    }
+
+    // The segmentation offloads of a direction depend on its checksum offload, as the segments
+    // are only checksummed once split.
+    fn validate(&self) -> Result<(), NetworkInterfaceError> {
+        if !self.guest_csum && (self.guest_tso4 || self.guest_tso6 || self.guest_ufo) {
+            return Err(NetworkInterfaceError::OffloadWithoutChecksum("guest"));
+        }
+        if !self.host_csum && (self.host_tso4 || self.host_tso6 || self.host_ufo) {
+            return Err(NetworkInterfaceError::OffloadWithoutChecksum("host"));
+        }
+        Ok(())
+    }
 }
 
 impl From<&Net> for NetworkInterfaceConfig {
@@ -39,13 +196,30 @@ impl From<&Net> for NetworkInterfaceConfig {
             guest_mac: net.guest_mac().copied(),
             rx_rate_limiter: rx_rl.into_option(),
             tx_rate_limiter: tx_rl.into_option(),
+            flow_accounting: net.flow_table().map(|table| FlowAccountingConfig {
+                max_flows: table.max_flows(),
+            }),
+            pause_responder: net.pause_responder().map(|responder| PauseResponderConfig {
+                max_connections: responder.max_connections(),
+            }),
+            rx_coalescing: net.rx_coalescer().map(|coalescer| RxCoalescingConfig {
+                max_segments: coalescer.max_segments(),
+            }),
+            mtu: net.mtu(),
+            mmds_only: net.is_mmds_only().then_some(true),
+            num_queues: (net.num_queue_pairs() > 1).then_some(net.num_queue_pairs()),
+            offloads: (net.offload_features() != NET_OFFLOAD_FEATURES)
+                .then(|| OffloadsConfig::from_features(net.offload_features())),
+            enforce_mac: net.mac_filter().enforce_mac.then_some(true),
+            enforce_arp: net.mac_filter().enforce_arp.then_some(true),
+            shared_rate_limiter: net.rx_rate_limiter().shared_id(),
         }
     }
 }
 
 /// The data fed into a network iface update request. Currently, only the RX and TX rate limiters
-/// can be updated.
-#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+/// and the state of the queues can be updated.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct NetworkInterfaceUpdateConfig {
     /// The net iface ID, as provided by the user at iface creation time.
@@ -56,21 +230,81 @@ pub struct NetworkInterfaceUpdateConfig {
     /// New TX rate limiter config. Only provided data will be updated. I.e. if any optional data
     /// is missing, it will not be nullified, but left unchanged.
     pub tx_rate_limiter: Option<RateLimiterConfig>,
+    /// New state of the RX and TX queues. The device is paused before, and resumed after, the
+    /// rate limiters are updated.
+    pub state: Option<DeviceQueuesState>,
+}
+
+/// The data fed into a packet capture update request of a network iface.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct NetworkCaptureConfig {
+    /// Whether the frames exchanged by the guest are captured. Disabling the capture closes its
+    /// file.
+    pub enabled: bool,
+    /// Path of the file or fifo the capture is written to, in the pcap format. Required to enable
+    /// the capture.
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+    /// Maximum number of bytes captured of each frame.
+    #[serde(default = "default_capture_snaplen")]
+    pub snaplen: u32,
+    /// Maximum number of bytes of the capture buffered while its file cannot be written. The
+    /// frames which do not fit are dropped from the capture.
+    #[serde(default = "default_capture_ring_size")]
+    pub ring_size: usize,
+}
+
+fn default_capture_snaplen() -> u32 {
+    DEFAULT_SNAPLEN
+}
+
+fn default_capture_ring_size() -> usize {
+    DEFAULT_RING_SIZE
 }
 
 /// Errors associated with the operations allowed on a net device.
+#[rustfmt::skip]
 #[derive(Debug, thiserror::Error, displaydoc::Display)]
 pub enum NetworkInterfaceError {
+    /// Cannot start the packet capture: {0}
+    Capture(#[from] CaptureError),
+    /// The path of the packet capture is required to enable it.
+    CapturePathMissing,
     /// Could not create the network device: {0}
     CreateNetworkDevice(#[from] crate::devices::virtio::net::NetError),
     /// Cannot create the rate limiter: {0}
     CreateRateLimiter(#[from] std::io::Error),
+    /// Unable to query the net device: {0}
+    DeviceQuery(VmmError),
     /// Unable to update the net device: {0}
     DeviceUpdate(#[from] VmmError),
+    /// Flow accounting is not enabled on network interface {0}.
+    FlowAccountingDisabled(String),
+    /// Enforcing the source MAC address of the frames sent by the guest requires a guest MAC address.
+    EnforceMacWithoutGuestMac,
     /// The MAC address is already in use: {0}
     GuestMacAddressInUse(String),
+    /// The maximum number of accounted flows must be between 1 and 65536, got {0}.
+    InvalidMaxFlows(usize),
+    /// The maximum number of TCP connections tracked by the pause responder must be between 1 and 65536, got {0}.
+    InvalidMaxConnections(usize),
+    /// The maximum number of coalesced TCP segments must be between 2 and 64, got {0}.
+    InvalidMaxSegments(usize),
+    /// The MTU must be between 68 and 65535, got {0}.
+    InvalidMtu(u16),
+    /// MMDS-only network interfaces cannot be connected to the tap {0}.
+    MmdsOnlyWithTap(String),
+    /// MMDS-only network interfaces have a single queue pair, got {0}.
+    MmdsOnlyWithQueues(u16),
+    /// The number of queue pairs must be between 1 and 16, got {0}.
+    InvalidNumQueues(u16),
+    /// The {0} segmentation offloads require the {0} checksum offload.
+    OffloadWithoutChecksum(&'static str),
     /// Cannot open/create the tap device: {0}
     OpenTap(#[from] TapError),
+    /// Shared rate limiter {0} not found.
+    SharedRateLimiterNotFound(String),
 }
 
 /// Builder for a list of network devices.
@@ -143,26 +377,111 @@ impl NetBuilder {
 
     /// Creates a Net device from a NetworkInterfaceConfig.
     pub fn create_net(cfg: NetworkInterfaceConfig) -> Result<Net, NetworkInterfaceError> {
-        let rx_rate_limiter = cfg
+        if let Some(flow_accounting) = cfg.flow_accounting {
+            if flow_accounting.max_flows == 0 || flow_accounting.max_flows > MAX_FLOW_TABLE_SIZE {
+                return Err(NetworkInterfaceError::InvalidMaxFlows(
+                    flow_accounting.max_flows,
+                ));
+            }
+        }
+        if let Some(pause_responder) = cfg.pause_responder {
+            if pause_responder.max_connections == 0
+                || pause_responder.max_connections > MAX_TRACKED_CONNECTIONS
+            {
+                return Err(NetworkInterfaceError::InvalidMaxConnections(
+                    pause_responder.max_connections,
+                ));
+            }
+        }
+        if let Some(rx_coalescing) = cfg.rx_coalescing {
+            if rx_coalescing.max_segments < 2 || rx_coalescing.max_segments > MAX_COALESCED_SEGMENTS
+            {
+                return Err(NetworkInterfaceError::InvalidMaxSegments(
+                    rx_coalescing.max_segments,
+                ));
+            }
+        }
+        if let Some(mtu) = cfg.mtu {
+            if mtu < MIN_MTU {
+                return Err(NetworkInterfaceError::InvalidMtu(mtu));
+            }
+        }
+        if let Some(offloads) = cfg.offloads {
+            offloads.validate()?;
+        }
+        let num_queues = cfg.num_queues.unwrap_or(1);
+        if num_queues == 0 || num_queues > MAX_QUEUE_PAIRS {
+            return Err(NetworkInterfaceError::InvalidNumQueues(num_queues));
+        }
+        let mac_filter = MacFilter {
+            enforce_mac: cfg.enforce_mac.unwrap_or(false),
+            enforce_arp: cfg.enforce_arp.unwrap_or(false),
+        };
+        if mac_filter != MacFilter::default() && cfg.guest_mac.is_none() {
+            return Err(NetworkInterfaceError::EnforceMacWithoutGuestMac);
+        }
+        let mmds_only = cfg.mmds_only.unwrap_or(false);
+        if mmds_only && !cfg.host_dev_name.is_empty() {
+            return Err(NetworkInterfaceError::MmdsOnlyWithTap(cfg.host_dev_name));
+        }
+        if mmds_only && num_queues > 1 {
+            return Err(NetworkInterfaceError::MmdsOnlyWithQueues(num_queues));
+        }
+        let mut rx_rate_limiter: RateLimiter = cfg
             .rx_rate_limiter
             .map(super::RateLimiterConfig::try_into)
             .transpose()
-            .map_err(NetworkInterfaceError::CreateRateLimiter)?;
-        let tx_rate_limiter = cfg
+            .map_err(NetworkInterfaceError::CreateRateLimiter)?
+            .unwrap_or_default();
+        let mut tx_rate_limiter: RateLimiter = cfg
             .tx_rate_limiter
             .map(super::RateLimiterConfig::try_into)
             .transpose()
-            .map_err(NetworkInterfaceError::CreateRateLimiter)?;
+            .map_err(NetworkInterfaceError::CreateRateLimiter)?
+            .unwrap_or_default();
+        if let Some(id) = cfg.shared_rate_limiter {
+            let shared =
+                shared::get(&id).ok_or(NetworkInterfaceError::SharedRateLimiterNotFound(id))?;
+            rx_rate_limiter.set_shared(Some(shared.clone()));
+            tx_rate_limiter.set_shared(Some(shared));
+        }
 
         // Create and return the Net device
-        crate::devices::virtio::net::Net::new(
-            cfg.iface_id,
-            &cfg.host_dev_name,
-            cfg.guest_mac,
-            rx_rate_limiter.unwrap_or_default(),
-            tx_rate_limiter.unwrap_or_default(),
-        )
-        .map_err(NetworkInterfaceError::CreateNetworkDevice)
+        let mut net = if mmds_only {
+            Net::new_mmds_only(
+                cfg.iface_id,
+                cfg.guest_mac,
+                rx_rate_limiter,
+                tx_rate_limiter,
+            )
+        } else {
+            Net::new_with_queues(
+                cfg.iface_id,
+                &cfg.host_dev_name,
+                num_queues,
+                cfg.guest_mac,
+                rx_rate_limiter,
+                tx_rate_limiter,
+            )
+        }
+        .map_err(NetworkInterfaceError::CreateNetworkDevice)?;
+        if let Some(flow_accounting) = cfg.flow_accounting {
+            net.enable_flow_accounting(flow_accounting.max_flows);
+        }
+        if let Some(pause_responder) = cfg.pause_responder {
+            net.enable_pause_responder(pause_responder.max_connections);
+        }
+        if let Some(rx_coalescing) = cfg.rx_coalescing {
+            net.enable_rx_coalescing(rx_coalescing.max_segments);
+        }
+        if let Some(mtu) = cfg.mtu {
+            net.set_mtu(mtu)?;
+        }
+        if let Some(offloads) = cfg.offloads {
+            net.set_offload_features(offloads.features());
+        }
+        net.set_mac_filter(mac_filter);
+        Ok(net)
     }
 
     /// Returns a vec with the structures used to configure the net devices.
@@ -180,7 +499,6 @@ mod tests {
     use std::str::FromStr;
 
     use super::*;
-    use crate::rate_limiter::RateLimiter;
 
     impl NetBuilder {
         pub(crate) fn len(&self) -> usize {
@@ -195,6 +513,16 @@ mod tests {
             guest_mac: Some(MacAddr::from_str(mac).unwrap()),
             rx_rate_limiter: RateLimiterConfig::default().into_option(),
             tx_rate_limiter: RateLimiterConfig::default().into_option(),
+            flow_accounting: None,
+            pause_responder: None,
+            rx_coalescing: None,
+            mtu: None,
+            mmds_only: None,
+            num_queues: None,
+            offloads: None,
+            enforce_mac: None,
+            enforce_arp: None,
+            shared_rate_limiter: None,
         }
     }
 
@@ -206,6 +534,16 @@ mod tests {
                 guest_mac: self.guest_mac,
                 rx_rate_limiter: None,
                 tx_rate_limiter: None,
+                flow_accounting: self.flow_accounting,
+                pause_responder: self.pause_responder,
+                rx_coalescing: self.rx_coalescing,
+                mtu: self.mtu,
+                mmds_only: self.mmds_only,
+                num_queues: self.num_queues,
+                offloads: self.offloads,
+                enforce_mac: self.enforce_mac,
+                enforce_arp: self.enforce_arp,
+                shared_rate_limiter: self.shared_rate_limiter.clone(),
             }
         }
     }
@@ -325,6 +663,247 @@ mod tests {
         assert_eq!(configs.first().unwrap(), &net_if_cfg);
     }
 
+    #[test]
+    fn test_flow_accounting_config() {
+        let mut net_builder = NetBuilder::new();
+        let mut net_if_cfg = create_netif("id", "dev", "01:23:45:67:89:0c");
+
+        for max_flows in [0, MAX_FLOW_TABLE_SIZE + 1] {
+            net_if_cfg.flow_accounting = Some(FlowAccountingConfig { max_flows });
+            assert_eq!(
+                net_builder
+                    .build(net_if_cfg.clone())
+                    .unwrap_err()
+                    .to_string(),
+                NetworkInterfaceError::InvalidMaxFlows(max_flows).to_string()
+            );
+        }
+
+        net_if_cfg.flow_accounting = Some(FlowAccountingConfig { max_flows: 128 });
+        let net = net_builder.build(net_if_cfg.clone()).unwrap();
+        assert_eq!(net.lock().unwrap().flow_table().unwrap().max_flows(), 128);
+        assert_eq!(net_builder.configs(), vec![net_if_cfg]);
+    }
+
+    #[test]
+    fn test_pause_responder_config() {
+        let mut net_builder = NetBuilder::new();
+        let mut net_if_cfg = create_netif("id", "dev", "01:23:45:67:89:0d");
+
+        for max_connections in [0, MAX_TRACKED_CONNECTIONS + 1] {
+            net_if_cfg.pause_responder = Some(PauseResponderConfig { max_connections });
+            assert_eq!(
+                net_builder
+                    .build(net_if_cfg.clone())
+                    .unwrap_err()
+                    .to_string(),
+                NetworkInterfaceError::InvalidMaxConnections(max_connections).to_string()
+            );
+        }
+
+        net_if_cfg.pause_responder = Some(PauseResponderConfig {
+            max_connections: 256,
+        });
+        let net = net_builder.build(net_if_cfg.clone()).unwrap();
+        assert_eq!(
+            net.lock()
+                .unwrap()
+                .pause_responder()
+                .unwrap()
+                .max_connections(),
+            256
+        );
+        assert_eq!(net_builder.configs(), vec![net_if_cfg]);
+    }
+
+    #[test]
+    fn test_rx_coalescing_config() {
+        let mut net_builder = NetBuilder::new();
+        let mut net_if_cfg = create_netif("id", "dev", "01:23:45:67:89:0e");
+
+        for max_segments in [1, MAX_COALESCED_SEGMENTS + 1] {
+            net_if_cfg.rx_coalescing = Some(RxCoalescingConfig { max_segments });
+            assert_eq!(
+                net_builder
+                    .build(net_if_cfg.clone())
+                    .unwrap_err()
+                    .to_string(),
+                NetworkInterfaceError::InvalidMaxSegments(max_segments).to_string()
+            );
+        }
+
+        net_if_cfg.rx_coalescing = Some(RxCoalescingConfig { max_segments: 16 });
+        let net = net_builder.build(net_if_cfg.clone()).unwrap();
+        assert_eq!(
+            net.lock().unwrap().rx_coalescer().unwrap().max_segments(),
+            16
+        );
+        assert_eq!(net_builder.configs(), vec![net_if_cfg]);
+    }
+
+    #[test]
+    fn test_mtu_config() {
+        let mut net_builder = NetBuilder::new();
+        let mut net_if_cfg = create_netif("id", "dev", "01:23:45:67:89:0f");
+
+        net_if_cfg.mtu = Some(MIN_MTU - 1);
+        assert_eq!(
+            net_builder
+                .build(net_if_cfg.clone())
+                .unwrap_err()
+                .to_string(),
+            NetworkInterfaceError::InvalidMtu(MIN_MTU - 1).to_string()
+        );
+
+        net_if_cfg.mtu = Some(9000);
+        let net = net_builder.build(net_if_cfg.clone()).unwrap();
+        assert_eq!(net.lock().unwrap().mtu(), Some(9000));
+        assert_eq!(net_builder.configs(), vec![net_if_cfg]);
+    }
+
+    #[test]
+    fn test_mmds_only_config() {
+        let mut net_builder = NetBuilder::new();
+        let mut net_if_cfg = create_netif("id", "dev", "01:23:45:67:89:0f");
+
+        net_if_cfg.mmds_only = Some(true);
+        assert_eq!(
+            net_builder
+                .build(net_if_cfg.clone())
+                .unwrap_err()
+                .to_string(),
+            NetworkInterfaceError::MmdsOnlyWithTap("dev".to_string()).to_string()
+        );
+
+        net_if_cfg.host_dev_name = String::new();
+        let net = net_builder.build(net_if_cfg.clone()).unwrap();
+        assert!(net.lock().unwrap().is_mmds_only());
+        assert_eq!(net_builder.configs(), vec![net_if_cfg]);
+
+        // The host device name can be omitted.
+        let net_if_cfg: NetworkInterfaceConfig =
+            serde_json::from_str(r#"{"iface_id": "id", "mmds_only": true}"#).unwrap();
+        assert!(net_if_cfg.host_dev_name.is_empty());
+    }
+
+    #[test]
+    fn test_num_queues_config() {
+        let mut net_builder = NetBuilder::new();
+        let mut net_if_cfg = create_netif("id", "mqdev%d", "01:23:45:67:89:0f");
+
+        for invalid in [0, MAX_QUEUE_PAIRS + 1] {
+            net_if_cfg.num_queues = Some(invalid);
+            assert_eq!(
+                net_builder
+                    .build(net_if_cfg.clone())
+                    .unwrap_err()
+                    .to_string(),
+                NetworkInterfaceError::InvalidNumQueues(invalid).to_string()
+            );
+        }
+
+        let mut mmds_if_cfg = create_netif("mmds", "", "01:23:45:67:89:0e");
+        mmds_if_cfg.mmds_only = Some(true);
+        mmds_if_cfg.num_queues = Some(2);
+        assert_eq!(
+            net_builder.build(mmds_if_cfg).unwrap_err().to_string(),
+            NetworkInterfaceError::MmdsOnlyWithQueues(2).to_string()
+        );
+
+        net_if_cfg.num_queues = Some(4);
+        let net = net_builder.build(net_if_cfg).unwrap();
+        let net = net.lock().unwrap();
+        assert_eq!(net.num_queue_pairs(), 4);
+        assert_eq!(net.taps.len(), 4);
+        assert_eq!(
+            NetworkInterfaceConfig::from(net.deref()).num_queues,
+            Some(4)
+        );
+    }
+
+    #[test]
+    fn test_offloads_config() {
+        let mut net_builder = NetBuilder::new();
+        let mut net_if_cfg = create_netif("id", "dev", "01:23:45:67:89:0f");
+
+        // The offloads not specified are enabled.
+        let offloads: OffloadsConfig =
+            serde_json::from_str(r#"{"guest_tso4": false, "host_ufo": false}"#).unwrap();
+        assert_eq!(
+            offloads.features(),
+            NET_OFFLOAD_FEATURES & !(1 << VIRTIO_NET_F_GUEST_TSO4 | 1 << VIRTIO_NET_F_HOST_UFO)
+        );
+        assert_eq!(OffloadsConfig::from_features(offloads.features()), offloads);
+
+        let mut no_csum = offloads;
+        no_csum.host_csum = false;
+        net_if_cfg.offloads = Some(no_csum);
+        assert_eq!(
+            net_builder
+                .build(net_if_cfg.clone())
+                .unwrap_err()
+                .to_string(),
+            NetworkInterfaceError::OffloadWithoutChecksum("host").to_string()
+        );
+
+        net_if_cfg.offloads = Some(offloads);
+        let net = net_builder.build(net_if_cfg.clone()).unwrap();
+        assert_eq!(net.lock().unwrap().offload_features(), offloads.features());
+        assert_eq!(net_builder.configs(), vec![net_if_cfg]);
+
+        // Enabling all the offloads is the same as not specifying them.
+        let mut net_if_cfg = create_netif("id2", "dev2", "01:23:45:67:89:0e");
+        net_if_cfg.offloads = Some(OffloadsConfig::from_features(NET_OFFLOAD_FEATURES));
+        net_builder.build(net_if_cfg).unwrap();
+        assert_eq!(net_builder.configs()[1].offloads, None);
+    }
+
+    #[test]
+    fn test_mac_filter_config() {
+        let mut net_builder = NetBuilder::new();
+        let mut net_if_cfg = create_netif("id", "dev", "01:23:45:67:89:0d");
+        net_if_cfg.guest_mac = None;
+        net_if_cfg.enforce_arp = Some(true);
+        assert_eq!(
+            net_builder
+                .build(net_if_cfg.clone())
+                .unwrap_err()
+                .to_string(),
+            NetworkInterfaceError::EnforceMacWithoutGuestMac.to_string()
+        );
+
+        let mut net_if_cfg = create_netif("id", "dev", "01:23:45:67:89:0d");
+        net_if_cfg.enforce_mac = Some(true);
+        let net = net_builder.build(net_if_cfg.clone()).unwrap();
+        assert_eq!(
+            net.lock().unwrap().mac_filter(),
+            MacFilter {
+                enforce_mac: true,
+                enforce_arp: false,
+            }
+        );
+        assert_eq!(net_builder.configs(), vec![net_if_cfg]);
+    }
+
+    #[test]
+    fn test_capture_config() {
+        let config: NetworkCaptureConfig =
+            serde_json::from_str(r#"{"enabled": true, "path": "/tmp/net0.pcap"}"#).unwrap();
+        assert_eq!(
+            config,
+            NetworkCaptureConfig {
+                enabled: true,
+                path: Some(PathBuf::from("/tmp/net0.pcap")),
+                snaplen: DEFAULT_SNAPLEN,
+                ring_size: DEFAULT_RING_SIZE,
+            }
+        );
+
+        let config: NetworkCaptureConfig = serde_json::from_str(r#"{"enabled": false}"#).unwrap();
+        assert_eq!(config.path, None);
+        serde_json::from_str::<NetworkCaptureConfig>(r#"{"path": "/tmp/net0.pcap"}"#).unwrap_err();
+    }
+
     #[test]
     fn test_add_device() {
         let mut net_builder = NetBuilder::new();
@@ -0,0 +1,27 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Auxiliary module for configuring the pvpanic device.
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+/// Strongly typed structure describing the pvpanic device, with which the guest reports its
+/// kernel panics.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct PvPanicConfig {
+    /// Full snapshot created the first time the guest panics, to debug the panic offline.
+    #[serde(default)]
+    pub snapshot: Option<PanicSnapshotConfig>,
+}
+
+/// Files of the full snapshot created when the guest panics.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct PanicSnapshotConfig {
+    /// Path to the file that will contain the microVM state.
+    pub snapshot_path: PathBuf,
+    /// Path to the file that will contain the guest memory.
+    pub mem_file_path: PathBuf,
+}
@@ -0,0 +1,184 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Auxiliary module for configuring the rate limiters shared by several devices.
+use serde::{Deserialize, Serialize};
+
+use super::TokenBucketConfig;
+use crate::rate_limiter::{shared, TokenBucket};
+
+/// Configuration of a rate limiter shared by several devices, which consume its budget along
+/// with the budget of their own rate limiters.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SharedRateLimiterConfig {
+    /// Unique identifier of the shared rate limiter, referenced by the devices.
+    pub id: String,
+    /// Token bucket limiting the bandwidth, in bytes per second, of all the devices.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bandwidth: Option<TokenBucketConfig>,
+    /// Token bucket limiting the operations per second of all the devices.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ops: Option<TokenBucketConfig>,
+}
+
+/// Errors associated with the shared rate limiters configuration.
+#[rustfmt::skip]
+#[derive(Debug, thiserror::Error, displaydoc::Display, PartialEq, Eq)]
+pub enum SharedRateLimiterConfigError {
+    /// Invalid shared rate limiter id {0:?}: ids must be non-empty and only contain alphanumeric characters or '_'.
+    InvalidId(String),
+}
+
+/// Current state of a token bucket of a shared rate limiter.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct TokenBucketStatus {
+    /// Configuration of the token bucket.
+    #[serde(flatten)]
+    pub config: TokenBucketConfig,
+    /// Tokens currently available, one time burst notwithstanding.
+    pub budget: u64,
+    /// Remaining one time burst tokens.
+    pub remaining_one_time_burst: u64,
+}
+
+impl From<&TokenBucket> for TokenBucketStatus {
+    fn from(tb: &TokenBucket) -> Self {
+        TokenBucketStatus {
+            config: TokenBucketConfig::from(tb),
+            budget: tb.budget(),
+            remaining_one_time_burst: tb.one_time_burst(),
+        }
+    }
+}
+
+/// Current state of a shared rate limiter, as reported by `GET /rate-limiters`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct SharedRateLimiterStatus {
+    /// Identifier of the shared rate limiter.
+    pub id: String,
+    /// State of the bandwidth token bucket, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bandwidth: Option<TokenBucketStatus>,
+    /// State of the ops token bucket, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ops: Option<TokenBucketStatus>,
+}
+
+fn token_bucket(config: Option<TokenBucketConfig>) -> Option<TokenBucket> {
+    config.and_then(|config| {
+        TokenBucket::new(
+            config.size,
+            config.one_time_burst.unwrap_or(0),
+            config.refill_time,
+        )
+    })
+}
+
+/// Registers a shared rate limiter, or updates the buckets of the one registered with the same
+/// id. Devices referencing an updated shared rate limiter are limited by its new buckets right
+/// away.
+pub fn insert_shared_rate_limiter(
+    config: SharedRateLimiterConfig,
+) -> Result<(), SharedRateLimiterConfigError> {
+    if config.id.is_empty() || !config.id.chars().all(|c| c == '_' || c.is_alphanumeric()) {
+        return Err(SharedRateLimiterConfigError::InvalidId(config.id));
+    }
+    shared::insert(
+        &config.id,
+        token_bucket(config.bandwidth),
+        token_bucket(config.ops),
+    );
+    Ok(())
+}
+
+/// Returns the configurations of the shared rate limiters.
+pub fn shared_rate_limiter_configs() -> Vec<SharedRateLimiterConfig> {
+    shared::all()
+        .iter()
+        .map(|limiter| {
+            let limiter = limiter.lock().expect("Poisoned lock");
+            SharedRateLimiterConfig {
+                id: limiter.id().to_string(),
+                bandwidth: limiter.bandwidth().map(TokenBucketConfig::from),
+                ops: limiter.ops().map(TokenBucketConfig::from),
+            }
+        })
+        .collect()
+}
+
+/// Returns the current state of the shared rate limiters.
+pub fn shared_rate_limiter_statuses() -> Vec<SharedRateLimiterStatus> {
+    shared::all()
+        .iter()
+        .map(|limiter| {
+            let mut limiter = limiter.lock().expect("Poisoned lock");
+            limiter.refresh();
+            SharedRateLimiterStatus {
+                id: limiter.id().to_string(),
+                bandwidth: limiter.bandwidth().map(TokenBucketStatus::from),
+                ops: limiter.ops().map(TokenBucketStatus::from),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_shared_rate_limiter() {
+        let config = SharedRateLimiterConfig {
+            id: "test_config".to_string(),
+            bandwidth: Some(TokenBucketConfig {
+                size: 1000,
+                one_time_burst: Some(500),
+                refill_time: 100_000,
+            }),
+            ops: None,
+        };
+        insert_shared_rate_limiter(config.clone()).unwrap();
+        assert!(shared_rate_limiter_configs().contains(&config));
+
+        let status = shared_rate_limiter_statuses()
+            .into_iter()
+            .find(|status| status.id == "test_config")
+            .unwrap();
+        assert_eq!(
+            status.bandwidth,
+            Some(TokenBucketStatus {
+                config: config.bandwidth.unwrap(),
+                budget: 1000,
+                remaining_one_time_burst: 500,
+            })
+        );
+        assert!(status.ops.is_none());
+
+        // Zero sized buckets do not limit the devices.
+        let config = SharedRateLimiterConfig {
+            id: "test_config".to_string(),
+            bandwidth: Some(TokenBucketConfig::default()),
+            ops: None,
+        };
+        insert_shared_rate_limiter(config).unwrap();
+        assert!(
+            shared_rate_limiter_configs().contains(&SharedRateLimiterConfig {
+                id: "test_config".to_string(),
+                bandwidth: None,
+                ops: None,
+            })
+        );
+
+        for id in ["", "with-dash"] {
+            assert_eq!(
+                insert_shared_rate_limiter(SharedRateLimiterConfig {
+                    id: id.to_string(),
+                    bandwidth: None,
+                    ops: None,
+                }),
+                Err(SharedRateLimiterConfigError::InvalidId(id.to_string()))
+            );
+        }
+    }
+}
@@ -0,0 +1,170 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Auxiliary module for tightening the seccomp filters of the threads of a running microVM.
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+
+use seccompiler::{deserialize_binary, BpfThreadMap, DeserializationError};
+use serde::{Deserialize, Serialize};
+
+use crate::vstate::vcpu::{VcpuError, VcpuSendEventError};
+
+/// Categories of the threads on which the additional filters can be installed.
+pub const SECCOMP_THREAD_CATEGORIES: [&str; 3] = ["vmm", "api", "vcpu"];
+
+// Guards against a memory allocation DOS caused by filter files that are too large, as for the
+// filters passed with `--seccomp-filter`.
+const DESERIALIZATION_BYTES_LIMIT: Option<u64> = Some(100_000);
+
+// Maximum number of instructions of a BPF program accepted by the kernel (`BPF_MAXINSNS`).
+const BPF_MAX_LEN: usize = 4096;
+
+/// Additional seccomp filters, installed on top of the filters the threads already run with.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SeccompFiltersConfig {
+    /// Path of the filters compiled by seccompiler-bin, by thread category. The categories which
+    /// have no filter keep their current filters.
+    pub filter_path: PathBuf,
+}
+
+/// Errors associated with the installation of additional seccomp filters.
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum SeccompFiltersError {
+    /// Cannot open the seccomp filters file: {0}
+    Open(std::io::Error),
+    /// Cannot deserialize the seccomp filters: {0}
+    Deserialization(DeserializationError),
+    /// Invalid thread categories: {0}
+    ThreadCategories(String),
+    /// The seccomp filter of the {0} threads is empty or too long.
+    FilterLength(String),
+    /// Cannot install the seccomp filter of the VMM thread: {0}
+    Install(seccompiler::InstallationError),
+    /// Failed to send event to vcpu thread: {0}
+    SendEvent(VcpuSendEventError),
+    /// Got unexpected response from vcpu thread.
+    UnexpectedResponse,
+    /// Cannot install the seccomp filter of a vCPU thread: {0}
+    Vcpu(VcpuError),
+    /// Cannot install the seccomp filter of the API thread: {0}
+    ApiThread(seccompiler::InstallationError),
+}
+
+impl SeccompFiltersConfig {
+    /// Reads the filters from the file, checking that they only apply to known thread
+    /// categories and that the kernel accepts their length.
+    pub fn load(&self) -> Result<BpfThreadMap, SeccompFiltersError> {
+        let file = File::open(&self.filter_path).map_err(SeccompFiltersError::Open)?;
+        let filters = deserialize_binary(BufReader::new(file), DESERIALIZATION_BYTES_LIMIT)
+            .map_err(SeccompFiltersError::Deserialization)?;
+
+        let mut invalid_categories: Vec<_> = filters
+            .keys()
+            .filter(|category| !SECCOMP_THREAD_CATEGORIES.contains(&category.as_str()))
+            .map(String::as_str)
+            .collect();
+        if !invalid_categories.is_empty() {
+            invalid_categories.sort_unstable();
+            return Err(SeccompFiltersError::ThreadCategories(
+                invalid_categories.join(","),
+            ));
+        }
+        if let Some((category, _)) = filters
+            .iter()
+            .find(|(_, filter)| filter.is_empty() || filter.len() > BPF_MAX_LEN)
+        {
+            return Err(SeccompFiltersError::FilterLength(category.clone()));
+        }
+        Ok(filters)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::io::Write;
+
+    use seccompiler::{sock_filter, BpfProgram};
+    use vmm_sys_util::tempfile::TempFile;
+
+    use super::*;
+
+    fn filters_file_with_len(categories: &[&str], len: usize) -> TempFile {
+        let filter = vec![
+            sock_filter {
+                code: 6,
+                jt: 0,
+                jf: 0,
+                k: 0x7fff_0000,
+            };
+            len
+        ];
+        let filters: HashMap<String, BpfProgram> = categories
+            .iter()
+            .map(|category| (category.to_string(), filter.clone()))
+            .collect();
+        let file = TempFile::new().unwrap();
+        file.as_file()
+            .write_all(&bincode::serialize(&filters).unwrap())
+            .unwrap();
+        file
+    }
+
+    fn filters_file(categories: &[&str]) -> TempFile {
+        filters_file_with_len(categories, 1)
+    }
+
+    #[test]
+    fn test_load_seccomp_filters() {
+        let config = SeccompFiltersConfig {
+            filter_path: PathBuf::from("/invalid/path"),
+        };
+        assert!(matches!(config.load(), Err(SeccompFiltersError::Open(_))));
+
+        let file = TempFile::new().unwrap();
+        let config = SeccompFiltersConfig {
+            filter_path: file.as_path().to_path_buf(),
+        };
+        assert!(matches!(
+            config.load(),
+            Err(SeccompFiltersError::Deserialization(_))
+        ));
+
+        let file = filters_file(&["vcpu", "thread2", "thread1"]);
+        let config = SeccompFiltersConfig {
+            filter_path: file.as_path().to_path_buf(),
+        };
+        match config.load() {
+            Err(SeccompFiltersError::ThreadCategories(categories)) => {
+                assert_eq!(categories, "thread1,thread2")
+            }
+            res => panic!("Expected ThreadCategories error, got {res:?}."),
+        }
+
+        // The filters can apply to only some of the thread categories.
+        let file = filters_file(&["vcpu", "api"]);
+        let config = SeccompFiltersConfig {
+            filter_path: file.as_path().to_path_buf(),
+        };
+        let filters = config.load().unwrap();
+        assert_eq!(filters.len(), 2);
+        assert_eq!(filters["vcpu"].len(), 1);
+        assert!(!filters.contains_key("vmm"));
+
+        // Filters the kernel would reject are caught before any of them is installed.
+        for len in [0, BPF_MAX_LEN + 1] {
+            let file = filters_file_with_len(&["vmm"], len);
+            let config = SeccompFiltersConfig {
+                filter_path: file.as_path().to_path_buf(),
+            };
+            match config.load() {
+                Err(SeccompFiltersError::FilterLength(category)) => assert_eq!(category, "vmm"),
+                res => panic!("Expected FilterLength error, got {res:?}."),
+            }
+        }
+    }
+}
@@ -0,0 +1,326 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Auxiliary module for configuring the host side of the serial console.
+use serde::{Deserialize, Serialize};
+
+/// Maximum size of the buffer capturing the serial console output.
+pub const MAX_SERIAL_LOG_BUFFER_SIZE: usize = 16 << 20;
+
+/// Host side to which the serial console of the microVM is attached.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SerialMode {
+    /// The standard input and output of Firecracker.
+    #[default]
+    Stdio,
+    /// A pseudo terminal, whose secondary side is linked at the configured path.
+    Pty,
+    /// A Unix socket listening at the configured path, to which a single client is attached at
+    /// a time.
+    UnixSocket,
+    /// A file at the configured path, created if needed, to which the output is appended. The
+    /// serial console has no input.
+    File,
+    /// A Unix socket listening at the configured path, to which a single client is attached at
+    /// a time with a WebSocket upgrade request on `/vm/console/ws`.
+    #[serde(rename = "websocket")]
+    WebSocket,
+}
+
+/// Configuration of the host side of the serial console.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SerialConfig {
+    /// Host side to which the serial console is attached.
+    #[serde(default)]
+    pub mode: SerialMode,
+    /// Path of the Unix socket, or of the link to the pseudo terminal.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// Size of the buffer capturing the most recent serial console output, in bytes. The output
+    /// is not captured if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log_buffer_size: Option<usize>,
+    /// Whether the captured output is written to each client attaching to the Unix socket, so
+    /// that the output written while no client was attached, e.g. by the early boot console, is
+    /// not lost.
+    #[serde(default)]
+    pub replay_log: bool,
+    /// Host sides of the additional serial ports, COM2 to COM4, which are not connected to
+    /// anything if not configured. x86_64 only.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extra_ports: Vec<SerialPortConfig>,
+    /// Users allowed to attach to the console in the WebSocket mode, besides the user running
+    /// Firecracker.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub websocket: Option<WebSocketConsoleConfig>,
+}
+
+/// Users allowed to attach to the serial console with the WebSocket protocol, identified by the
+/// credentials of the process connecting to the socket.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebSocketConsoleConfig {
+    /// Users allowed to read the output and write the input of the console.
+    #[serde(default)]
+    pub read_write_uids: Vec<u32>,
+    /// Users only allowed to read the output of the console.
+    #[serde(default)]
+    pub read_only_uids: Vec<u32>,
+}
+
+/// Configuration of the host side of one of the additional serial ports.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SerialPortConfig {
+    /// Number of the port, from 2 for COM2 (`ttyS1` in the guest) to 4 for COM4 (`ttyS3`).
+    pub port: u8,
+    /// Host side to which the port is attached. The standard input and output are reserved to
+    /// the serial console.
+    pub mode: SerialMode,
+    /// Path of the Unix socket, of the link to the pseudo terminal or of the file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+}
+
+impl SerialPortConfig {
+    /// Returns the configuration of the host side of the port, in the form used for the serial
+    /// console.
+    pub fn host_config(&self) -> SerialConfig {
+        SerialConfig {
+            mode: self.mode,
+            path: self.path.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Errors associated with the serial console configuration.
+#[rustfmt::skip]
+#[derive(Debug, thiserror::Error, displaydoc::Display, PartialEq, Eq)]
+pub enum SerialConfigError {
+    /// A path is required to attach the serial console to a pseudo terminal, a Unix socket or a file.
+    MissingPath,
+    /// A path can only be set when attaching the serial console to a pseudo terminal, a Unix socket or a file.
+    UnexpectedPath,
+    /// The serial log buffer size must be between 1 and {MAX_SERIAL_LOG_BUFFER_SIZE:} bytes.
+    InvalidLogBufferSize,
+    /// The serial console output can only be replayed to Unix socket and WebSocket clients, when it is captured.
+    InvalidReplayLog,
+    /// The WebSocket console users can only be set in the websocket mode.
+    UnexpectedWebSocketConfig,
+    /// Additional serial ports are only supported on x86_64.
+    UnsupportedExtraPorts,
+    /// Invalid serial port {0}: the additional serial ports are COM2 to COM4.
+    InvalidPort(u8),
+    /// Serial port COM{0} is configured more than once.
+    DuplicatePort(u8),
+    /// Serial port COM{0} cannot be attached to the standard input and output, which are reserved to the serial console.
+    StdioPort(u8),
+    /// Serial port COM{0} cannot be attached to a WebSocket console, which is reserved to the serial console.
+    WebSocketPort(u8),
+    /// Serial port COM{0}: {1}
+    Port(u8, Box<SerialConfigError>),
+}
+
+impl SerialConfig {
+    /// Checks that a path is set if, and only if, the mode requires one, that the log buffer
+    /// size is within bounds, that the captured output can be replayed, if requested, and that
+    /// the additional ports are valid.
+    pub fn validate(&self) -> Result<(), SerialConfigError> {
+        self.validate_extra_ports()?;
+        if self
+            .log_buffer_size
+            .is_some_and(|size| size == 0 || size > MAX_SERIAL_LOG_BUFFER_SIZE)
+        {
+            return Err(SerialConfigError::InvalidLogBufferSize);
+        }
+        let socket = matches!(self.mode, SerialMode::UnixSocket | SerialMode::WebSocket);
+        if self.replay_log && (!socket || self.log_buffer_size.is_none()) {
+            return Err(SerialConfigError::InvalidReplayLog);
+        }
+        if self.websocket.is_some() && self.mode != SerialMode::WebSocket {
+            return Err(SerialConfigError::UnexpectedWebSocketConfig);
+        }
+        match (self.mode, &self.path) {
+            (SerialMode::Stdio, Some(_)) => Err(SerialConfigError::UnexpectedPath),
+            (
+                SerialMode::Pty | SerialMode::UnixSocket | SerialMode::File | SerialMode::WebSocket,
+                None,
+            ) => Err(SerialConfigError::MissingPath),
+            _ => Ok(()),
+        }
+    }
+
+    fn validate_extra_ports(&self) -> Result<(), SerialConfigError> {
+        if !self.extra_ports.is_empty() && cfg!(not(target_arch = "x86_64")) {
+            return Err(SerialConfigError::UnsupportedExtraPorts);
+        }
+        for (i, port_config) in self.extra_ports.iter().enumerate() {
+            let port = port_config.port;
+            if !(2..=4).contains(&port) {
+                return Err(SerialConfigError::InvalidPort(port));
+            }
+            if self.extra_ports[..i].iter().any(|other| other.port == port) {
+                return Err(SerialConfigError::DuplicatePort(port));
+            }
+            match port_config.mode {
+                SerialMode::Stdio => return Err(SerialConfigError::StdioPort(port)),
+                SerialMode::WebSocket => return Err(SerialConfigError::WebSocketPort(port)),
+                _ => (),
+            }
+            port_config
+                .host_config()
+                .validate()
+                .map_err(|err| SerialConfigError::Port(port, Box::new(err)))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serial_config() {
+        let config: SerialConfig = serde_json::from_str("{}").unwrap();
+        assert_eq!(config, SerialConfig::default());
+        config.validate().unwrap();
+
+        let config: SerialConfig =
+            serde_json::from_str(r#"{"mode": "unix_socket", "path": "/tmp/console.sock"}"#)
+                .unwrap();
+        assert_eq!(config.mode, SerialMode::UnixSocket);
+        config.validate().unwrap();
+
+        let config = SerialConfig {
+            mode: SerialMode::Pty,
+            ..Default::default()
+        };
+        assert_eq!(config.validate(), Err(SerialConfigError::MissingPath));
+        let config = SerialConfig {
+            path: Some("/tmp/console".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(config.validate(), Err(SerialConfigError::UnexpectedPath));
+
+        let mut config = SerialConfig {
+            log_buffer_size: Some(MAX_SERIAL_LOG_BUFFER_SIZE),
+            ..Default::default()
+        };
+        config.validate().unwrap();
+        for size in [0, MAX_SERIAL_LOG_BUFFER_SIZE + 1] {
+            config.log_buffer_size = Some(size);
+            assert_eq!(
+                config.validate(),
+                Err(SerialConfigError::InvalidLogBufferSize)
+            );
+        }
+
+        let mut config = SerialConfig {
+            mode: SerialMode::UnixSocket,
+            path: Some("/tmp/console.sock".to_string()),
+            log_buffer_size: Some(4096),
+            replay_log: true,
+            ..Default::default()
+        };
+        config.validate().unwrap();
+        config.log_buffer_size = None;
+        assert_eq!(config.validate(), Err(SerialConfigError::InvalidReplayLog));
+        config.log_buffer_size = Some(4096);
+        config.mode = SerialMode::Pty;
+        assert_eq!(config.validate(), Err(SerialConfigError::InvalidReplayLog));
+
+        let config: SerialConfig =
+            serde_json::from_str(r#"{"mode": "file", "path": "/tmp/console.log"}"#).unwrap();
+        assert_eq!(config.mode, SerialMode::File);
+        config.validate().unwrap();
+        let config = SerialConfig {
+            mode: SerialMode::File,
+            ..Default::default()
+        };
+        assert_eq!(config.validate(), Err(SerialConfigError::MissingPath));
+
+        let mut config: SerialConfig = serde_json::from_str(
+            r#"{
+                "mode": "websocket",
+                "path": "/tmp/console.sock",
+                "websocket": {"read_only_uids": [1000]}
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(config.mode, SerialMode::WebSocket);
+        assert_eq!(
+            config.websocket,
+            Some(WebSocketConsoleConfig {
+                read_write_uids: vec![],
+                read_only_uids: vec![1000],
+            })
+        );
+        config.validate().unwrap();
+        config.mode = SerialMode::UnixSocket;
+        assert_eq!(
+            config.validate(),
+            Err(SerialConfigError::UnexpectedWebSocketConfig)
+        );
+
+        serde_json::from_str::<SerialConfig>(r#"{"mode": "fifo"}"#).unwrap_err();
+        serde_json::from_str::<SerialConfig>(r#"{"extra_ports": [{"port": 2}]}"#).unwrap_err();
+        serde_json::from_str::<SerialConfig>(r#"{"mode": "pty", "pth": "/tmp/pty"}"#).unwrap_err();
+    }
+    #[test]
+    fn test_serial_extra_ports() {
+        let mut config: SerialConfig = serde_json::from_str(
+            r#"{
+                "extra_ports": [
+                    {"port": 2, "mode": "file", "path": "/tmp/app.log"},
+                    {"port": 4, "mode": "unix_socket", "path": "/tmp/shell.sock"}
+                ]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(config.mode, SerialMode::Stdio);
+        assert_eq!(
+            config.extra_ports[1],
+            SerialPortConfig {
+                port: 4,
+                mode: SerialMode::UnixSocket,
+                path: Some("/tmp/shell.sock".to_string()),
+            }
+        );
+        if cfg!(not(target_arch = "x86_64")) {
+            assert_eq!(
+                config.validate(),
+                Err(SerialConfigError::UnsupportedExtraPorts)
+            );
+            return;
+        }
+        config.validate().unwrap();
+
+        config.extra_ports[1].port = 2;
+        assert_eq!(config.validate(), Err(SerialConfigError::DuplicatePort(2)));
+        for port in [0, 1, 5] {
+            config.extra_ports[1].port = port;
+            assert_eq!(config.validate(), Err(SerialConfigError::InvalidPort(port)));
+        }
+
+        config.extra_ports[1] = SerialPortConfig {
+            port: 3,
+            mode: SerialMode::Stdio,
+            path: None,
+        };
+        assert_eq!(config.validate(), Err(SerialConfigError::StdioPort(3)));
+        config.extra_ports[1].mode = SerialMode::WebSocket;
+        assert_eq!(config.validate(), Err(SerialConfigError::WebSocketPort(3)));
+        config.extra_ports[1].mode = SerialMode::Pty;
+        assert_eq!(
+            config.validate(),
+            Err(SerialConfigError::Port(
+                3,
+                Box::new(SerialConfigError::MissingPath)
+            ))
+        );
+    }
+}
@@ -0,0 +1,238 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Auxiliary module for configuring the read-only memory segments shared between microVMs.
+use std::fs::File;
+use std::io;
+
+use serde::{Deserialize, Serialize};
+use vm_memory::mmap::MmapRegionError;
+
+use crate::arch::PAGE_SIZE;
+use crate::utils::u64_to_usize;
+use crate::vmm_config::machine_config::{is_valid_region_name, MAX_RESERVED_MEMORY_NAME_LEN};
+use crate::vstate::memory::{FileOffset, MmapRegion, MmapRegionBuilder};
+
+/// Configuration of a read-only memory segment, backed by a host file and mapped in the guest
+/// physical address space. As the file is mapped shared, its pages are shared between all the
+/// microVMs using it.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SharedMemoryConfig {
+    /// Unique identifier of the segment, also naming it in the guest memory map.
+    pub segment_id: String,
+    /// Path of the host file backing the segment.
+    pub path_on_host: String,
+    /// Guest physical address at which the segment is mapped.
+    pub guest_addr: u64,
+}
+
+/// Errors associated with the shared memory segments configuration.
+#[rustfmt::skip]
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum SharedMemoryConfigError {
+    /// Invalid shared memory segment id {0:?}: ids must be at most {MAX_RESERVED_MEMORY_NAME_LEN:} characters long and only contain alphanumeric characters, ',', '.', '_', '+' or '-'.
+    InvalidId(String),
+    /// The guest address of shared memory segment {0:?} must be page aligned.
+    UnalignedAddress(String),
+    /// Cannot open the file backing the shared memory segment: {0}
+    OpenFile(io::Error),
+    /// The size of the file backing shared memory segment {0:?} must be a non-zero multiple of the page size.
+    InvalidSize(String),
+    /// Shared memory segment {0:?} overlaps shared memory segment {1:?}.
+    Overlap(String, String),
+}
+
+/// A read-only memory segment shared between microVMs, with its backing file opened.
+#[derive(Debug)]
+pub struct SharedMemorySegment {
+    config: SharedMemoryConfig,
+    file: File,
+    size: u64,
+}
+
+impl SharedMemorySegment {
+    /// Opens the file backing the segment described by `config`.
+    pub fn new(config: SharedMemoryConfig) -> Result<Self, SharedMemoryConfigError> {
+        if !is_valid_region_name(&config.segment_id) {
+            return Err(SharedMemoryConfigError::InvalidId(config.segment_id));
+        }
+        let page_size = PAGE_SIZE as u64;
+        if config.guest_addr % page_size != 0 {
+            return Err(SharedMemoryConfigError::UnalignedAddress(config.segment_id));
+        }
+
+        let file = File::open(&config.path_on_host).map_err(SharedMemoryConfigError::OpenFile)?;
+        let size = file
+            .metadata()
+            .map_err(SharedMemoryConfigError::OpenFile)?
+            .len();
+        if size == 0 || size % page_size != 0 {
+            return Err(SharedMemoryConfigError::InvalidSize(config.segment_id));
+        }
+
+        Ok(SharedMemorySegment { config, file, size })
+    }
+
+    /// Returns the configuration of the segment.
+    pub fn config(&self) -> &SharedMemoryConfig {
+        &self.config
+    }
+
+    /// Returns the id of the segment.
+    pub fn id(&self) -> &str {
+        &self.config.segment_id
+    }
+
+    /// Returns the guest physical address at which the segment is mapped.
+    pub fn guest_addr(&self) -> u64 {
+        self.config.guest_addr
+    }
+
+    /// Returns the size of the segment, i.e. the size of its file.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    fn overlaps(&self, other: &SharedMemorySegment) -> bool {
+        self.guest_addr() < other.guest_addr() + other.size()
+            && other.guest_addr() < self.guest_addr() + self.size()
+    }
+
+    /// Maps the file backing the segment read-only and shared in the address space of the
+    /// process.
+    pub fn map(&self) -> Result<MmapRegion, MmapRegionError> {
+        let file = self.file.try_clone().map_err(MmapRegionError::Mmap)?;
+        MmapRegionBuilder::new(u64_to_usize(self.size))
+            .with_mmap_prot(libc::PROT_READ)
+            .with_mmap_flags(libc::MAP_SHARED | libc::MAP_NORESERVE)
+            .with_file_offset(FileOffset::new(file, 0))
+            .build()
+    }
+}
+
+/// Holds the read-only memory segments shared with other microVMs.
+#[derive(Debug, Default)]
+pub struct SharedMemoryBuilder {
+    segments: Vec<SharedMemorySegment>,
+}
+
+impl SharedMemoryBuilder {
+    /// Adds a segment, or replaces the segment with the same id.
+    pub fn insert(&mut self, config: SharedMemoryConfig) -> Result<(), SharedMemoryConfigError> {
+        let segment = SharedMemorySegment::new(config)?;
+        if let Some(other) = self
+            .segments
+            .iter()
+            .find(|other| other.id() != segment.id() && other.overlaps(&segment))
+        {
+            return Err(SharedMemoryConfigError::Overlap(
+                segment.id().to_string(),
+                other.id().to_string(),
+            ));
+        }
+
+        match self.segments.iter_mut().find(|s| s.id() == segment.id()) {
+            Some(existing) => *existing = segment,
+            None => self.segments.push(segment),
+        }
+        Ok(())
+    }
+
+    /// Returns an iterator over the segments.
+    pub fn iter(&self) -> impl Iterator<Item = &SharedMemorySegment> {
+        self.segments.iter()
+    }
+
+    /// Returns the configurations of the segments.
+    pub fn configs(&self) -> Vec<SharedMemoryConfig> {
+        self.segments.iter().map(|s| s.config().clone()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use vmm_sys_util::tempfile::TempFile;
+
+    use super::*;
+
+    fn config(id: &str, file: &TempFile, guest_addr: u64) -> SharedMemoryConfig {
+        SharedMemoryConfig {
+            segment_id: id.to_string(),
+            path_on_host: file.as_path().to_str().unwrap().to_string(),
+            guest_addr,
+        }
+    }
+
+    #[test]
+    fn test_shared_memory_builder() {
+        let file = TempFile::new().unwrap();
+        let mut builder = SharedMemoryBuilder::default();
+
+        assert!(matches!(
+            builder.insert(config("heap", &file, 0x1_0000_0000)),
+            Err(SharedMemoryConfigError::InvalidSize(id)) if id == "heap"
+        ));
+        file.as_file().write_all(&[0xaa; 0x1001]).unwrap();
+        assert!(matches!(
+            builder.insert(config("heap", &file, 0x1_0000_0000)),
+            Err(SharedMemoryConfigError::InvalidSize(id)) if id == "heap"
+        ));
+
+        file.as_file().set_len(0x2000).unwrap();
+        builder
+            .insert(config("heap", &file, 0x1_0000_0000))
+            .unwrap();
+        assert_eq!(builder.iter().next().unwrap().size(), 0x2000);
+
+        assert!(matches!(
+            builder.insert(config("heap@0", &file, 0x1_0000_0000)),
+            Err(SharedMemoryConfigError::InvalidId(_))
+        ));
+        assert!(matches!(
+            builder.insert(config("other", &file, 0x1_0000_0800)),
+            Err(SharedMemoryConfigError::UnalignedAddress(_))
+        ));
+        assert!(matches!(
+            builder.insert(config("other", &file, 0x1_0000_1000)),
+            Err(SharedMemoryConfigError::Overlap(id, other)) if id == "other" && other == "heap"
+        ));
+        let mut invalid_path = config("other", &file, 0x1_0000_2000);
+        invalid_path.path_on_host = "/invalid/path".to_string();
+        assert!(matches!(
+            builder.insert(invalid_path),
+            Err(SharedMemoryConfigError::OpenFile(_))
+        ));
+
+        // Segments can be moved, and replaced by their id.
+        builder
+            .insert(config("heap", &file, 0x1_0000_1000))
+            .unwrap();
+        builder
+            .insert(config("other", &file, 0x1_0000_3000))
+            .unwrap();
+        assert_eq!(
+            builder.configs(),
+            [
+                config("heap", &file, 0x1_0000_1000),
+                config("other", &file, 0x1_0000_3000)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_shared_memory_map() {
+        let file = TempFile::new().unwrap();
+        file.as_file().write_all(b"runtime").unwrap();
+        file.as_file().set_len(PAGE_SIZE as u64).unwrap();
+        let segment = SharedMemorySegment::new(config("heap", &file, 0x1_0000_0000)).unwrap();
+
+        let region = segment.map().unwrap();
+        assert_eq!(region.size(), PAGE_SIZE);
+        // SAFETY: The region is mapped and readable, and one page long.
+        let content = unsafe { std::slice::from_raw_parts(region.as_ptr(), 7) };
+        assert_eq!(content, b"runtime");
+    }
+}
@@ -0,0 +1,71 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Auxiliary module for asking the guest to shut down gracefully.
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// Maximum time the guest can be given to shut down, in milliseconds.
+pub const MAX_SHUTDOWN_TIMEOUT_MS: u64 = 3_600_000;
+
+/// The data fed into a graceful shutdown request.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ShutdownConfig {
+    /// Time in milliseconds after which the microVM is stopped if the guest did not shut down.
+    /// When not set, the microVM is left running until the guest shuts down.
+    pub shutdown_timeout_ms: Option<u64>,
+}
+
+impl ShutdownConfig {
+    /// Returns the time after which the microVM is stopped, if any.
+    pub fn timeout(&self) -> Result<Option<Duration>, ShutdownError> {
+        match self.shutdown_timeout_ms {
+            None => Ok(None),
+            Some(timeout_ms @ 1..=MAX_SHUTDOWN_TIMEOUT_MS) => {
+                Ok(Some(Duration::from_millis(timeout_ms)))
+            }
+            Some(timeout_ms) => Err(ShutdownError::InvalidTimeout(timeout_ms)),
+        }
+    }
+}
+
+/// Errors associated with the graceful shutdown of the guest.
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum ShutdownError {
+    /// The shutdown timeout must be between 1 and 3600000 ms, got {0} ms.
+    InvalidTimeout(u64),
+    /// The microVM has no power button.
+    NoPowerButton,
+    /// Cannot press the power button: {0}
+    PowerButton(std::io::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shutdown_timeout() {
+        assert_eq!(ShutdownConfig::default().timeout().unwrap(), None);
+        let config = ShutdownConfig {
+            shutdown_timeout_ms: Some(MAX_SHUTDOWN_TIMEOUT_MS),
+        };
+        assert_eq!(
+            config.timeout().unwrap(),
+            Some(Duration::from_millis(MAX_SHUTDOWN_TIMEOUT_MS))
+        );
+
+        for timeout_ms in [0, MAX_SHUTDOWN_TIMEOUT_MS + 1] {
+            let config = ShutdownConfig {
+                shutdown_timeout_ms: Some(timeout_ms),
+            };
+            assert!(matches!(
+                config.timeout(),
+                Err(ShutdownError::InvalidTimeout(ms)) if ms == timeout_ms
+            ));
+        }
+    }
+}
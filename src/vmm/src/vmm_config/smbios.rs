@@ -0,0 +1,176 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Auxiliary module for configuring the SMBIOS tables exposed to the guest.
+use serde::{Deserialize, Serialize};
+
+/// Maximum number of OEM strings, as their count is stored on a single byte.
+pub const MAX_OEM_STRINGS: usize = 255;
+
+/// Strongly typed structure describing the per-microVM identity exposed to the guest through
+/// the SMBIOS tables. Unset strings are reported as "Not Specified".
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SmbiosConfig {
+    /// Manufacturer of the system (type 1). Defaults to "Firecracker".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_manufacturer: Option<String>,
+    /// Product name of the system (type 1). Defaults to "Firecracker".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_product_name: Option<String>,
+    /// Serial number of the system (type 1).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_serial_number: Option<String>,
+    /// UUID of the system (type 1), formatted as `xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_uuid: Option<String>,
+    /// Serial number of the chassis (type 3).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chassis_serial_number: Option<String>,
+    /// Asset tag of the chassis (type 3).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chassis_asset_tag: Option<String>,
+    /// Free-form OEM strings (type 11).
+    #[serde(default)]
+    pub oem_strings: Vec<String>,
+}
+
+/// Errors associated with the SMBIOS configuration.
+#[derive(Debug, thiserror::Error, displaydoc::Display, PartialEq, Eq)]
+pub enum SmbiosConfigError {
+    /// SMBIOS tables are only supported on x86_64.
+    Unsupported,
+    /// SMBIOS strings can not contain NUL characters.
+    InvalidString,
+    /// At most 255 OEM strings are supported.
+    TooManyOemStrings,
+    /// Invalid system UUID: {0}
+    InvalidUuid(String),
+}
+
+impl SmbiosConfig {
+    /// Checks that the configuration can be encoded in the SMBIOS tables.
+    pub fn validate(&self) -> Result<(), SmbiosConfigError> {
+        if cfg!(not(target_arch = "x86_64")) {
+            return Err(SmbiosConfigError::Unsupported);
+        }
+
+        let strings = [
+            &self.system_manufacturer,
+            &self.system_product_name,
+            &self.system_serial_number,
+            &self.chassis_serial_number,
+            &self.chassis_asset_tag,
+        ];
+        if strings
+            .into_iter()
+            .flatten()
+            .chain(&self.oem_strings)
+            .any(|string| string.contains('\0'))
+        {
+            return Err(SmbiosConfigError::InvalidString);
+        }
+        if self.oem_strings.len() > MAX_OEM_STRINGS {
+            return Err(SmbiosConfigError::TooManyOemStrings);
+        }
+        self.uuid()?;
+
+        Ok(())
+    }
+
+    /// Returns the bytes of the system UUID, in their textual order, or all zeros if it is unset.
+    pub fn uuid(&self) -> Result<[u8; 16], SmbiosConfigError> {
+        let mut bytes = [0u8; 16];
+        let Some(uuid) = &self.system_uuid else {
+            return Ok(bytes);
+        };
+
+        let invalid = || SmbiosConfigError::InvalidUuid(uuid.clone());
+        let groups: Vec<&str> = uuid.split('-').collect();
+        if groups.iter().map(|group| group.len()).collect::<Vec<_>>() != [8, 4, 4, 4, 12] {
+            return Err(invalid());
+        }
+        let hex = groups.concat();
+        if !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).map_err(|_| invalid())?;
+        }
+
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_smbios_config_uuid() {
+        let mut config = SmbiosConfig::default();
+        assert_eq!(config.uuid().unwrap(), [0u8; 16]);
+
+        config.system_uuid = Some("00112233-4455-6677-8899-AABBCCDDEEFF".to_string());
+        assert_eq!(
+            config.uuid().unwrap(),
+            [
+                0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd,
+                0xee, 0xff
+            ]
+        );
+
+        for uuid in [
+            "",
+            "00112233445566778899aabbccddeeff",
+            "0011223-34455-6677-8899-aabbccddeeff",
+            "00112233-4455-6677-8899-aabbccddeeff-",
+            "00112233-4455-6677-8899-aabbccddeefg",
+            "+0112233-4455-6677-8899-aabbccddeeff",
+        ] {
+            config.system_uuid = Some(uuid.to_string());
+            assert_eq!(
+                config.uuid(),
+                Err(SmbiosConfigError::InvalidUuid(uuid.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn test_smbios_config_validate() {
+        let mut config = SmbiosConfig {
+            system_serial_number: Some("serial".to_string()),
+            chassis_asset_tag: Some("tag".to_string()),
+            oem_strings: vec!["oem".to_string(); MAX_OEM_STRINGS],
+            ..Default::default()
+        };
+        config.validate().unwrap();
+
+        config.oem_strings.push("oem".to_string());
+        assert_eq!(config.validate(), Err(SmbiosConfigError::TooManyOemStrings));
+
+        config.oem_strings = vec!["o\0em".to_string()];
+        assert_eq!(config.validate(), Err(SmbiosConfigError::InvalidString));
+
+        config.oem_strings.clear();
+        config.chassis_asset_tag = Some("t\0ag".to_string());
+        assert_eq!(config.validate(), Err(SmbiosConfigError::InvalidString));
+
+        config.chassis_asset_tag = None;
+        config.system_uuid = Some("uuid".to_string());
+        assert_eq!(
+            config.validate(),
+            Err(SmbiosConfigError::InvalidUuid("uuid".to_string()))
+        );
+    }
+
+    #[test]
+    #[cfg(target_arch = "aarch64")]
+    fn test_smbios_config_validate() {
+        assert_eq!(
+            SmbiosConfig::default().validate(),
+            Err(SmbiosConfigError::Unsupported)
+        );
+    }
+}
@@ -3,12 +3,15 @@
 
 //! Configurations used in the snapshotting context.
 
+use std::os::fd::RawFd;
 use std::path::PathBuf;
 
 /// For crates that depend on `vmm` we export.
 pub use semver::Version;
 use serde::{Deserialize, Serialize};
 
+use crate::snapshot::SnapshotLabels;
+
 /// The snapshot type options that are available when
 /// creating a new snapshot.
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
@@ -25,7 +28,7 @@ pub enum SnapshotType {
 /// 1) A file that contains the guest memory to be loaded,
 /// 2) An UDS where a custom page-fault handler process is listening for the UFFD set up by
 ///    Firecracker to handle its guest memory page faults.
-#[derive(Debug, PartialEq, Eq, Deserialize)]
+#[derive(Debug, PartialEq, Eq, Deserialize, Serialize)]
 pub enum MemBackendType {
     /// Guest memory contents will be loaded from a file.
     File,
@@ -34,7 +37,7 @@ pub enum MemBackendType {
 }
 
 /// Stores the configuration that will be used for creating a snapshot.
-#[derive(Debug, PartialEq, Eq, Deserialize)]
+#[derive(Debug, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct CreateSnapshotParams {
     /// This marks the type of snapshot we want to create.
@@ -45,6 +48,24 @@ pub struct CreateSnapshotParams {
     pub snapshot_path: PathBuf,
     /// Path to the file that will contain the guest memory.
     pub mem_file_path: PathBuf,
+    /// Key/value labels stored in the snapshot header.
+    #[serde(default)]
+    pub labels: SnapshotLabels,
+    /// Encrypts the microVM state and guest memory files.
+    #[serde(default)]
+    pub encryption: Option<SnapshotEncryptionConfig>,
+    /// Writes the guest memory file in the background, once the guest memory is
+    /// write-protected, so that the microVM can be resumed right away.
+    #[serde(default)]
+    pub background: bool,
+}
+
+/// Stores the configuration used for encrypting or decrypting snapshot files with AES-256-GCM.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SnapshotEncryptionConfig {
+    /// File descriptor, inherited by the process, of the file holding the 32 bytes of the key.
+    pub key_fd: RawFd,
 }
 
 /// Stores the configuration that will be used for loading a snapshot.
@@ -60,10 +81,14 @@ pub struct LoadSnapshotParams {
     /// When set to true, the vm is also resumed if the snapshot load
     /// is successful.
     pub resume_vm: bool,
+    /// Decrypts the microVM state and guest memory files.
+    pub encryption: Option<SnapshotEncryptionConfig>,
+    /// Faults in the guest memory before the microVM is resumed.
+    pub prefault: bool,
 }
 
 /// Stores the configuration for loading a snapshot that is provided by the user.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct LoadSnapshotConfig {
     /// Path to the file that contains the microVM state to be loaded.
@@ -82,10 +107,16 @@ pub struct LoadSnapshotConfig {
     /// Whether or not to resume the vm post snapshot load.
     #[serde(default)]
     pub resume_vm: bool,
+    /// Decrypts the microVM state and guest memory files.
+    #[serde(default)]
+    pub encryption: Option<SnapshotEncryptionConfig>,
+    /// Whether or not to fault in the guest memory before the microVM is resumed.
+    #[serde(default)]
+    pub prefault: bool,
 }
 
 /// Stores the configuration used for managing snapshot memory.
-#[derive(Debug, PartialEq, Eq, Deserialize)]
+#[derive(Debug, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct MemBackendConfig {
     /// Path to the backend used to handle the guest memory.
@@ -94,6 +125,17 @@ pub struct MemBackendConfig {
     pub backend_type: MemBackendType,
 }
 
+/// Stores the configuration used for checking whether a snapshot can be restored on the host.
+#[derive(Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct CheckSnapshotParams {
+    /// Path to the file that contains the microVM state to be checked.
+    pub snapshot_path: PathBuf,
+    /// Decrypts the microVM state file.
+    #[serde(default)]
+    pub encryption: Option<SnapshotEncryptionConfig>,
+}
+
 /// The microVM state options.
 #[derive(Debug, Deserialize, Serialize)]
 pub enum VmState {
@@ -0,0 +1,15 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Auxiliary module for configuring the TPM device.
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+/// Strongly typed structure describing the TPM device, backed by an swtpm TPM emulator.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct TpmConfig {
+    /// Path of the control socket of swtpm, started with `--ctrl type=unixio,path=<socket>`.
+    pub socket: PathBuf,
+}
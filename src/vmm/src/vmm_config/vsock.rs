@@ -1,12 +1,16 @@
 // Copyright 2018 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
+use std::collections::BTreeMap;
 use std::convert::TryFrom;
+use std::net::SocketAddr;
 use std::sync::{Arc, Mutex};
 
 use serde::{Deserialize, Serialize};
 
+use super::RateLimiterConfig;
 use crate::devices::virtio::vsock::{Vsock, VsockError, VsockUnixBackend, VsockUnixBackendError};
+use crate::rate_limiter::RateLimiter;
 
 type MutexVsockUnix = Arc<Mutex<Vsock<VsockUnixBackend>>>;
 
@@ -17,6 +21,40 @@ pub enum VsockConfigError {
     CreateVsockBackend(VsockUnixBackendError),
     /// Cannot create vsock device: {0}
     CreateVsockDevice(VsockError),
+    /// Cannot create a rate limiter of a vsock port: {0}
+    CreateRateLimiter(std::io::Error),
+    /// The vsock port {0} has more than one rate limiter configuration.
+    #[from(ignore)]
+    DuplicatePortRateLimiter(u32),
+    /// The vsock port {0} is forwarded to more than one TCP address.
+    #[from(ignore)]
+    DuplicateTcpForward(u32),
+}
+
+/// The rate limiters of the connections to a vsock port: the guest-initiated connections to the
+/// host port `port`, and the host-initiated connections to the guest port `port`.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct VsockPortRateLimiterConfig {
+    /// The rate limited vsock port.
+    pub port: u32,
+    /// Rate limiter for the data sent to the guest.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rx_rate_limiter: Option<RateLimiterConfig>,
+    /// Rate limiter for the data sent by the guest.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tx_rate_limiter: Option<RateLimiterConfig>,
+}
+
+/// The forwarding of the guest-initiated stream connections to the host port `port` to a host
+/// TCP address, instead of the Unix socket of the port.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct VsockTcpForwardConfig {
+    /// The forwarded vsock port.
+    pub port: u32,
+    /// The TCP address to which the connections are forwarded, e.g. `127.0.0.1:8080`.
+    pub addr: SocketAddr,
 }
 
 /// This struct represents the strongly typed equivalent of the json body
@@ -32,6 +70,20 @@ pub struct VsockDeviceConfig {
     pub guest_cid: u32,
     /// Path to local unix socket.
     pub uds_path: String,
+    /// Rate limiters of the connections to vsock ports. The connections to these ports also get
+    /// their own metrics.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub port_rate_limiters: Vec<VsockPortRateLimiterConfig>,
+    /// Host TCP addresses to which the guest-initiated connections to vsock ports are forwarded.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tcp_forwards: Vec<VsockTcpForwardConfig>,
+    /// Whether the guest can connect seqpacket sockets.
+    #[serde(default)]
+    pub seqpacket: bool,
+    /// Whether the guest can use datagram sockets, exchanged with the host through a datagram
+    /// Unix socket bound at `<uds_path>_dgram`.
+    #[serde(default)]
+    pub dgram: bool,
 }
 
 #[derive(Debug)]
@@ -43,10 +95,33 @@ struct VsockAndUnixPath {
 impl From<&VsockAndUnixPath> for VsockDeviceConfig {
     fn from(vsock: &VsockAndUnixPath) -> Self {
         let vsock_lock = vsock.vsock.lock().unwrap();
+        let port_rate_limiters = vsock_lock
+            .backend()
+            .port_limiters()
+            .iter()
+            .map(|(port, limiter)| VsockPortRateLimiterConfig {
+                port: *port,
+                rx_rate_limiter: RateLimiterConfig::from(&limiter.rx).into_option(),
+                tx_rate_limiter: RateLimiterConfig::from(&limiter.tx).into_option(),
+            })
+            .collect();
+        let tcp_forwards = vsock_lock
+            .backend()
+            .tcp_forwards()
+            .iter()
+            .map(|(port, addr)| VsockTcpForwardConfig {
+                port: *port,
+                addr: *addr,
+            })
+            .collect();
         VsockDeviceConfig {
             vsock_id: None,
             guest_cid: u32::try_from(vsock_lock.cid()).unwrap(),
             uds_path: vsock.uds_path.clone(),
+            port_rate_limiters,
+            tcp_forwards,
+            seqpacket: vsock_lock.backend().seqpacket_enabled(),
+            dgram: vsock_lock.backend().dgram_enabled(),
         }
     }
 }
@@ -99,7 +174,50 @@ impl VsockBuilder {
     pub fn create_unixsock_vsock(
         cfg: VsockDeviceConfig,
     ) -> Result<Vsock<VsockUnixBackend>, VsockConfigError> {
-        let backend = VsockUnixBackend::new(u64::from(cfg.guest_cid), cfg.uds_path)?;
+        // Build the rate limiters and the forwards first, so that no socket is left behind on
+        // failure.
+        let mut port_limiters = BTreeMap::new();
+        for port_cfg in cfg.port_rate_limiters {
+            let rx: RateLimiter = port_cfg
+                .rx_rate_limiter
+                .map(RateLimiterConfig::try_into)
+                .transpose()
+                .map_err(VsockConfigError::CreateRateLimiter)?
+                .unwrap_or_default();
+            let tx: RateLimiter = port_cfg
+                .tx_rate_limiter
+                .map(RateLimiterConfig::try_into)
+                .transpose()
+                .map_err(VsockConfigError::CreateRateLimiter)?
+                .unwrap_or_default();
+            if port_limiters.insert(port_cfg.port, (rx, tx)).is_some() {
+                return Err(VsockConfigError::DuplicatePortRateLimiter(port_cfg.port));
+            }
+        }
+
+        let mut tcp_forwards = BTreeMap::new();
+        for forward_cfg in cfg.tcp_forwards {
+            if tcp_forwards
+                .insert(forward_cfg.port, forward_cfg.addr)
+                .is_some()
+            {
+                return Err(VsockConfigError::DuplicateTcpForward(forward_cfg.port));
+            }
+        }
+
+        let mut backend = VsockUnixBackend::new(u64::from(cfg.guest_cid), cfg.uds_path)?;
+        for (port, (rx, tx)) in port_limiters {
+            backend.add_port_limiter(port, rx, tx)?;
+        }
+        for (port, addr) in tcp_forwards {
+            backend.add_tcp_forward(port, addr)?;
+        }
+        if cfg.seqpacket {
+            backend.enable_seqpacket();
+        }
+        if cfg.dgram {
+            backend.enable_dgram()?;
+        }
 
         Vsock::new(u64::from(cfg.guest_cid), backend).map_err(VsockConfigError::CreateVsockDevice)
     }
@@ -116,12 +234,17 @@ pub(crate) mod tests {
 
     use super::*;
     use crate::devices::virtio::vsock::VSOCK_DEV_ID;
+    use crate::vmm_config::TokenBucketConfig;
 
     pub(crate) fn default_config(tmp_sock_file: &TempFile) -> VsockDeviceConfig {
         VsockDeviceConfig {
             vsock_id: None,
             guest_cid: 3,
             uds_path: tmp_sock_file.as_path().to_str().unwrap().to_string(),
+            port_rate_limiters: Vec::new(),
+            tcp_forwards: Vec::new(),
+            seqpacket: false,
+            dgram: false,
         }
     }
 
@@ -164,6 +287,60 @@ pub(crate) mod tests {
         assert_eq!(config.unwrap(), vsock_config);
     }
 
+    #[test]
+    fn test_vsock_port_rate_limiters() {
+        let mut vsock_builder = VsockBuilder::new();
+        let mut tmp_sock_file = TempFile::new().unwrap();
+        tmp_sock_file.remove().unwrap();
+        let mut vsock_config = default_config(&tmp_sock_file);
+        let port_config = VsockPortRateLimiterConfig {
+            port: 52,
+            rx_rate_limiter: None,
+            tx_rate_limiter: Some(RateLimiterConfig {
+                bandwidth: Some(TokenBucketConfig {
+                    size: 1000,
+                    one_time_burst: None,
+                    refill_time: 100,
+                }),
+                ..Default::default()
+            }),
+        };
+        vsock_config.port_rate_limiters = vec![port_config.clone(), port_config];
+        assert!(matches!(
+            vsock_builder.insert(vsock_config.clone()),
+            Err(VsockConfigError::DuplicatePortRateLimiter(52))
+        ));
+
+        vsock_config.port_rate_limiters.pop();
+        vsock_builder.insert(vsock_config.clone()).unwrap();
+        assert_eq!(vsock_builder.config().unwrap(), vsock_config);
+    }
+
+    #[test]
+    fn test_vsock_tcp_forwards() {
+        let mut vsock_builder = VsockBuilder::new();
+        let mut tmp_sock_file = TempFile::new().unwrap();
+        tmp_sock_file.remove().unwrap();
+        let mut vsock_config = default_config(&tmp_sock_file);
+        let forward_config = VsockTcpForwardConfig {
+            port: 5000,
+            addr: "127.0.0.1:8080".parse().unwrap(),
+        };
+        vsock_config.tcp_forwards = vec![forward_config.clone(), forward_config];
+        assert!(matches!(
+            vsock_builder.insert(vsock_config.clone()),
+            Err(VsockConfigError::DuplicateTcpForward(5000))
+        ));
+
+        vsock_config.tcp_forwards.pop();
+        vsock_builder.insert(vsock_config.clone()).unwrap();
+        assert_eq!(vsock_builder.config().unwrap(), vsock_config);
+
+        let json = r#"{"guest_cid": 3, "uds_path": "/v.sock", "tcp_forwards": [{"port": 5000, "addr": "127.0.0.1:8080"}]}"#;
+        let config: VsockDeviceConfig = serde_json::from_str(json).unwrap();
+        assert_eq!(config.tcp_forwards, vsock_config.tcp_forwards);
+    }
+
     #[test]
     fn test_set_device() {
         let mut vsock_builder = VsockBuilder::new();
@@ -5,8 +5,11 @@
 // Use of this source code is governed by a BSD-style license that can be
 // found in the THIRD-PARTY file.
 
+use std::collections::BTreeSet;
 use std::fs::File;
-use std::io::SeekFrom;
+use std::io::{self, Seek, SeekFrom, Write};
+use std::os::unix::io::AsRawFd;
+use std::sync::Arc;
 
 use serde::{Deserialize, Serialize};
 pub use vm_memory::bitmap::{AtomicBitmap, Bitmap, BitmapSlice, BS};
@@ -53,6 +56,10 @@ pub enum MemoryError {
     MemfdSetLen(std::io::Error),
     /// Cannot restore hugetlbfs backed snapshot by mapping the memory file. Please use uffd.
     HugetlbfsSnapshot,
+    /// Cannot pre-fault memory: {0}
+    Prefault(std::io::Error),
+    /// Cannot exclude memory from forked processes: {0}
+    DontFork(std::io::Error),
 }
 
 /// Defines the interface for snapshotting memory.
@@ -60,13 +67,20 @@ pub trait GuestMemoryExtension
 where
     Self: Sized,
 {
-    /// Creates a GuestMemoryMmap with `size` in MiB backed by a memfd.
+    /// Creates a GuestMemoryMmap from raw regions backed by a single memfd.
     fn memfd_backed(
-        mem_size_mib: usize,
+        regions: &[(GuestAddress, usize)],
         track_dirty_pages: bool,
         huge_pages: HugePageConfig,
     ) -> Result<Self, MemoryError>;
 
+    /// Creates a GuestMemoryMmap from raw regions backed by a single guest_memfd, created with
+    /// [`crate::vstate::vm::Vm::create_guest_memfd`].
+    fn guest_memfd_backed(
+        guest_memfd: File,
+        regions: &[(GuestAddress, usize)],
+    ) -> Result<Self, MemoryError>;
+
     /// Creates a GuestMemoryMmap from raw regions.
     fn from_raw_regions(
         regions: &[(GuestAddress, usize)],
@@ -81,6 +95,15 @@ where
         shared: bool,
     ) -> Result<Self, MemoryError>;
 
+    /// Adds raw regions laid out one after the other in `file`, mapped shared, to the guest
+    /// memory.
+    fn with_file_regions(
+        self,
+        file: &File,
+        regions: &[(GuestAddress, usize)],
+        track_dirty_pages: bool,
+    ) -> Result<Self, MemoryError>;
+
     /// Creates a GuestMemoryMmap given a `file` containing the data
     /// and a `state` containing mapping information.
     fn from_state(
@@ -106,6 +129,18 @@ where
         dirty_bitmap: &DirtyBitmap,
     ) -> Result<(), MemoryError>;
 
+    /// Dumps all contents of GuestMemoryMmap to `file`, punching holes in place of the pages
+    /// holding only zeros instead of writing them.
+    fn dump_sparse(&self, file: &mut File) -> Result<(), MemoryError>;
+
+    /// Populates the page tables of all the regions for write access, so that the guest does not
+    /// fault on its first access to a page.
+    fn prefault(&self) -> Result<(), MemoryError>;
+
+    /// Leaves all the regions out of the processes forked by Firecracker, which would otherwise
+    /// share the resident pages copy-on-write, and copy them as the guest writes to them.
+    fn exclude_from_fork(&self) -> Result<(), MemoryError>;
+
     /// Resets all the memory region bitmaps
     fn reset_dirty(&self);
 
@@ -133,29 +168,116 @@ pub struct GuestMemoryState {
     pub regions: Vec<GuestMemoryRegionState>,
 }
 
+/// Usage of the KVM memory slots of a VM.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemorySlotsUsage {
+    /// Number of memory slots supported by KVM for the VM.
+    pub max_slots: u32,
+    /// Number of memory slots in use.
+    pub used_slots: u32,
+    /// Number of memory slots in use by guest memory.
+    pub guest_memory_slots: u32,
+    /// Highest memory slot in use, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub highest_slot: Option<u32>,
+}
+
+/// Allocator of the KVM memory slots of a VM.
+///
+/// KVM only accepts slots below the limit reported by `KVM_CAP_NR_MEMSLOTS`, so the slots of
+/// memory regions that are removed are reused, lowest first, instead of handing out ever higher
+/// slots. This keeps the slots in use compact, however many regions are added and removed over
+/// the lifetime of the VM.
+#[derive(Debug)]
+pub struct MemorySlots {
+    max_slots: u32,
+    guest_memory_slots: u32,
+    used: BTreeSet<u32>,
+}
+
+impl MemorySlots {
+    /// Creates an allocator of `max_slots` memory slots.
+    pub fn new(max_slots: u32) -> Self {
+        MemorySlots {
+            max_slots,
+            guest_memory_slots: 0,
+            used: BTreeSet::new(),
+        }
+    }
+
+    /// Reserves the slots of the `num_regions` guest memory regions. Guest memory regions use
+    /// the slot matching their index, which dirty page tracking relies on, so they are reserved
+    /// first. Returns `None` if there are not enough free slots.
+    pub fn reserve_guest_memory(&mut self, num_regions: u32) -> Option<()> {
+        if num_regions > self.max_slots || (0..num_regions).any(|slot| self.used.contains(&slot)) {
+            return None;
+        }
+        self.used.extend(0..num_regions);
+        self.guest_memory_slots = num_regions;
+        Some(())
+    }
+
+    /// Allocates the lowest free slot, or returns `None` if all the slots are in use.
+    pub fn allocate(&mut self) -> Option<u32> {
+        // The used slots are sorted, so the first gap is the lowest free slot.
+        let mut slot = 0;
+        for used in self.used.iter() {
+            if *used != slot {
+                break;
+            }
+            slot += 1;
+        }
+        if slot >= self.max_slots {
+            return None;
+        }
+        self.used.insert(slot);
+        Some(slot)
+    }
+
+    /// Releases `slot`, so that it can be allocated again. Returns `false` if the slot was not
+    /// in use.
+    pub fn release(&mut self, slot: u32) -> bool {
+        self.used.remove(&slot)
+    }
+
+    /// Returns the current usage of the memory slots.
+    pub fn usage(&self) -> MemorySlotsUsage {
+        MemorySlotsUsage {
+            max_slots: self.max_slots,
+            // There are no more used slots than `max_slots`, which is an u32.
+            used_slots: u32::try_from(self.used.len()).unwrap(),
+            guest_memory_slots: self.guest_memory_slots,
+            highest_slot: self.used.last().copied(),
+        }
+    }
+}
+
 impl GuestMemoryExtension for GuestMemoryMmap {
-    /// Creates a GuestMemoryMmap with `size` in MiB backed by a memfd.
+    /// Creates a GuestMemoryMmap from raw regions backed by a single memfd.
     fn memfd_backed(
-        mem_size_mib: usize,
+        regions: &[(GuestAddress, usize)],
         track_dirty_pages: bool,
         huge_pages: HugePageConfig,
     ) -> Result<Self, MemoryError> {
+        let mem_size_mib = regions.iter().map(|(_, size)| size).sum::<usize>() >> 20;
         let memfd_file = create_memfd(mem_size_mib, huge_pages.into())?.into_file();
 
-        let mut offset: u64 = 0;
-        let regions = crate::arch::arch_memory_regions(mem_size_mib << 20)
-            .iter()
-            .map(|(guest_address, region_size)| {
-                let file_clone = memfd_file.try_clone().map_err(MemoryError::FileError)?;
-                let file_offset = FileOffset::new(file_clone, offset);
-                offset += *region_size as u64;
-                Ok((file_offset, *guest_address, *region_size))
-            })
-            .collect::<Result<Vec<_>, MemoryError>>()?;
-
+        let regions = file_regions(&memfd_file, regions)?;
         Self::from_raw_regions_file(regions, track_dirty_pages, true)
     }
 
+    /// Creates a GuestMemoryMmap from raw regions backed by a single guest_memfd.
+    fn guest_memfd_backed(
+        guest_memfd: File,
+        regions: &[(GuestAddress, usize)],
+    ) -> Result<Self, MemoryError> {
+        // The guest_memfd stays mapped for the life of the VM, since the devices access guest
+        // memory through these mappings, even though KVM doesn't use them.
+        // KVM does not log the pages dirtied in guest_memfd backed memory slots.
+        let regions = file_regions(&guest_memfd, regions)?;
+        Self::from_raw_regions_file(regions, false, true)
+    }
+
     /// Creates a GuestMemoryMmap from raw regions backed by anonymous memory.
     fn from_raw_regions(
         regions: &[(GuestAddress, usize)],
@@ -197,33 +319,25 @@ impl GuestMemoryExtension for GuestMemoryMmap {
         track_dirty_pages: bool,
         shared: bool,
     ) -> Result<Self, MemoryError> {
-        let prot = libc::PROT_READ | libc::PROT_WRITE;
-        let flags = if shared {
-            libc::MAP_NORESERVE | libc::MAP_SHARED
-        } else {
-            libc::MAP_NORESERVE | libc::MAP_PRIVATE
-        };
-        let regions = regions
-            .into_iter()
-            .map(|(file_offset, guest_address, region_size)| {
-                let bitmap = match track_dirty_pages {
-                    true => Some(AtomicBitmap::with_len(region_size)),
-                    false => None,
-                };
-                let region = MmapRegionBuilder::new_with_bitmap(region_size, bitmap)
-                    .with_mmap_prot(prot)
-                    .with_mmap_flags(flags)
-                    .with_file_offset(file_offset)
-                    .build()
-                    .map_err(MemoryError::MmapRegionError)?;
-
-                GuestRegionMmap::new(region, guest_address).map_err(MemoryError::VmMemoryError)
-            })
-            .collect::<Result<Vec<_>, MemoryError>>()?;
-
+        let regions = file_backed_regions(regions, track_dirty_pages, shared)?;
         GuestMemoryMmap::from_regions(regions).map_err(MemoryError::VmMemoryError)
     }
 
+    /// Adds raw regions backed by a shared file to the guest memory.
+    fn with_file_regions(
+        self,
+        file: &File,
+        regions: &[(GuestAddress, usize)],
+        track_dirty_pages: bool,
+    ) -> Result<Self, MemoryError> {
+        let regions = file_backed_regions(file_regions(file, regions)?, track_dirty_pages, true)?;
+        regions.into_iter().try_fold(self, |guest_memory, region| {
+            guest_memory
+                .insert_region(Arc::new(region))
+                .map_err(MemoryError::VmMemoryError)
+        })
+    }
+
     /// Creates a GuestMemoryMmap backed by a `file` if present, otherwise backed
     /// by anonymous memory. Memory layout and ranges are described in `state` param.
     fn from_state(
@@ -358,6 +472,103 @@ impl GuestMemoryExtension for GuestMemoryMmap {
         write_result.map_err(MemoryError::WriteMemory)
     }
 
+    /// Dumps all contents of GuestMemoryMmap to `file`, punching holes in place of the pages
+    /// holding only zeros instead of writing them.
+    fn dump_sparse(&self, file: &mut File) -> Result<(), MemoryError> {
+        let page_size = get_page_size().map_err(MemoryError::PageSize)?;
+        let mut buf = vec![0u8; SPARSE_DUMP_CHUNK_PAGES * page_size];
+        let mut region_file_offset = 0u64;
+
+        for region in self.iter() {
+            let region_len = u64_to_usize(region.len());
+            // Start of the run of zero pages not punched yet, which may span several chunks.
+            let mut hole_start: Option<u64> = None;
+            let mut chunk_offset = 0;
+
+            while chunk_offset < region_len {
+                let chunk = &mut buf
+                    [..(region_len - chunk_offset).min(SPARSE_DUMP_CHUNK_PAGES * page_size)];
+                region
+                    .read_slice(chunk, MemoryRegionAddress(chunk_offset as u64))
+                    .map_err(MemoryError::WriteMemory)?;
+
+                // Start of the run of non-zero pages of the chunk not written yet.
+                let mut data_start: Option<usize> = None;
+                for (index, page) in chunk.chunks(page_size).enumerate() {
+                    let page_start = index * page_size;
+                    let file_offset = region_file_offset + (chunk_offset + page_start) as u64;
+                    if page.iter().all(|&byte| byte == 0) {
+                        if let Some(start) = data_start.take() {
+                            write_at(
+                                file,
+                                file_offset - (page_start - start) as u64,
+                                &chunk[start..page_start],
+                            )?;
+                        }
+                        hole_start.get_or_insert(file_offset);
+                    } else {
+                        if let Some(start) = hole_start.take() {
+                            punch_hole(file, start, file_offset - start)?;
+                        }
+                        data_start.get_or_insert(page_start);
+                    }
+                }
+                if let Some(start) = data_start {
+                    let file_offset = region_file_offset + (chunk_offset + start) as u64;
+                    write_at(file, file_offset, &chunk[start..])?;
+                }
+                chunk_offset += chunk.len();
+            }
+
+            region_file_offset += region.len();
+            if let Some(start) = hole_start {
+                punch_hole(file, start, region_file_offset - start)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn prefault(&self) -> Result<(), MemoryError> {
+        let page_size = get_page_size().map_err(MemoryError::PageSize)?;
+        for region in self.iter() {
+            // SAFETY: The address and length are the ones of the mapping of the region.
+            let ret = unsafe {
+                libc::madvise(
+                    region.as_ptr().cast(),
+                    u64_to_usize(region.len()),
+                    libc::MADV_POPULATE_WRITE,
+                )
+            };
+            if ret == 0 {
+                continue;
+            }
+            let err = io::Error::last_os_error();
+            // `MADV_POPULATE_WRITE` is only known from Linux 5.14.
+            if err.raw_os_error() != Some(libc::EINVAL) {
+                return Err(MemoryError::Prefault(err));
+            }
+            touch_pages(region, page_size);
+        }
+        Ok(())
+    }
+
+    fn exclude_from_fork(&self) -> Result<(), MemoryError> {
+        for region in self.iter() {
+            // SAFETY: The address and length are the ones of the mapping of the region.
+            let ret = unsafe {
+                libc::madvise(
+                    region.as_ptr().cast(),
+                    u64_to_usize(region.len()),
+                    libc::MADV_DONTFORK,
+                )
+            };
+            if ret != 0 {
+                return Err(MemoryError::DontFork(io::Error::last_os_error()));
+            }
+        }
+        Ok(())
+    }
+
     /// Resets all the memory region bitmaps
     fn reset_dirty(&self) {
         self.iter().for_each(|region| {
@@ -388,6 +599,111 @@ impl GuestMemoryExtension for GuestMemoryMmap {
     }
 }
 
+/// Faults in the pages of `region` for write access by writing back the first byte of each page.
+/// Only to be used before the vCPUs run, as the guest could otherwise write the pages meanwhile.
+fn touch_pages(region: &GuestRegionMmap, page_size: usize) {
+    for offset in (0..u64_to_usize(region.len())).step_by(page_size) {
+        // SAFETY: The offset is within the mapping of the region.
+        unsafe {
+            let byte = region.as_ptr().add(offset);
+            byte.write_volatile(byte.read_volatile());
+        }
+    }
+}
+
+/// Maps the file backed `regions`.
+fn file_backed_regions(
+    regions: Vec<(FileOffset, GuestAddress, usize)>,
+    track_dirty_pages: bool,
+    shared: bool,
+) -> Result<Vec<GuestRegionMmap>, MemoryError> {
+    let prot = libc::PROT_READ | libc::PROT_WRITE;
+    let flags = if shared {
+        libc::MAP_NORESERVE | libc::MAP_SHARED
+    } else {
+        libc::MAP_NORESERVE | libc::MAP_PRIVATE
+    };
+    regions
+        .into_iter()
+        .map(|(file_offset, guest_address, region_size)| {
+            let bitmap = match track_dirty_pages {
+                true => Some(AtomicBitmap::with_len(region_size)),
+                false => None,
+            };
+            let region = MmapRegionBuilder::new_with_bitmap(region_size, bitmap)
+                .with_mmap_prot(prot)
+                .with_mmap_flags(flags)
+                .with_file_offset(file_offset)
+                .build()
+                .map_err(MemoryError::MmapRegionError)?;
+
+            GuestRegionMmap::new(region, guest_address).map_err(MemoryError::VmMemoryError)
+        })
+        .collect()
+}
+
+/// Lays out `regions` one after the other in `file`.
+fn file_regions(
+    file: &File,
+    regions: &[(GuestAddress, usize)],
+) -> Result<Vec<(FileOffset, GuestAddress, usize)>, MemoryError> {
+    let mut offset: u64 = 0;
+    regions
+        .iter()
+        .map(|(guest_address, region_size)| {
+            let file_clone = file.try_clone().map_err(MemoryError::FileError)?;
+            let file_offset = FileOffset::new(file_clone, offset);
+            offset += *region_size as u64;
+            Ok((file_offset, *guest_address, *region_size))
+        })
+        .collect()
+}
+
+/// Number of pages read from guest memory at once by [`GuestMemoryExtension::dump_sparse`].
+const SPARSE_DUMP_CHUNK_PAGES: usize = 256;
+
+fn write_at(file: &mut File, offset: u64, buf: &[u8]) -> Result<(), MemoryError> {
+    file.seek(SeekFrom::Start(offset))
+        .and_then(|_| file.write_all(buf))
+        .map_err(MemoryError::FileError)
+}
+
+/// Deallocates `len` bytes of `file` from `offset`, so that they read as zeros. Zeros are written
+/// instead if the file system does not support punching holes.
+fn punch_hole(file: &mut File, offset: u64, len: u64) -> Result<(), MemoryError> {
+    let (Ok(raw_offset), Ok(raw_len)) = (i64::try_from(offset), i64::try_from(len)) else {
+        return Err(MemoryError::FileError(io::Error::from(
+            io::ErrorKind::InvalidInput,
+        )));
+    };
+    // SAFETY: `fallocate` only changes the allocation of the file, whose fd is valid as long as
+    // `file` is borrowed.
+    let ret = unsafe {
+        libc::fallocate(
+            file.as_raw_fd(),
+            libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE,
+            raw_offset,
+            raw_len,
+        )
+    };
+    if ret == 0 {
+        return Ok(());
+    }
+    let err = io::Error::last_os_error();
+    if err.raw_os_error() != Some(libc::EOPNOTSUPP) {
+        return Err(MemoryError::FileError(err));
+    }
+
+    let zeros = vec![0u8; u64_to_usize(len.min(1 << 20))];
+    let mut written = 0;
+    while written < len {
+        let chunk = &zeros[..u64_to_usize((len - written).min(zeros.len() as u64))];
+        write_at(file, offset + written, chunk)?;
+        written += chunk.len() as u64;
+    }
+    Ok(())
+}
+
 fn create_memfd(
     size: usize,
     hugetlb_size: Option<memfd::HugetlbSize>,
@@ -425,6 +741,7 @@ mod tests {
 
     use std::collections::HashMap;
     use std::io::{Read, Seek};
+    use std::os::unix::fs::FileExt;
 
     use vmm_sys_util::tempfile::TempFile;
 
@@ -432,6 +749,51 @@ mod tests {
     use crate::snapshot::Snapshot;
     use crate::utils::get_page_size;
 
+    #[test]
+    fn test_memory_slots() {
+        let mut slots = MemorySlots::new(4);
+        assert_eq!(
+            slots.usage(),
+            MemorySlotsUsage {
+                max_slots: 4,
+                ..Default::default()
+            }
+        );
+
+        assert!(slots.reserve_guest_memory(5).is_none());
+        slots.reserve_guest_memory(2).unwrap();
+        assert!(slots.reserve_guest_memory(1).is_none());
+        assert_eq!(slots.allocate(), Some(2));
+        assert_eq!(slots.allocate(), Some(3));
+        assert_eq!(slots.allocate(), None);
+        assert_eq!(
+            slots.usage(),
+            MemorySlotsUsage {
+                max_slots: 4,
+                used_slots: 4,
+                guest_memory_slots: 2,
+                highest_slot: Some(3),
+            }
+        );
+
+        // Released slots are reused, lowest first.
+        assert!(slots.release(2));
+        assert!(!slots.release(2));
+        assert_eq!(slots.usage().used_slots, 3);
+        assert_eq!(slots.allocate(), Some(2));
+        assert!(slots.release(3));
+        assert!(slots.release(2));
+        assert_eq!(slots.usage().highest_slot, Some(1));
+
+        // Adding and removing regions repeatedly does not exhaust the slots.
+        for _ in 0..100 {
+            let slot = slots.allocate().unwrap();
+            assert_eq!(slot, 2);
+            assert!(slots.release(slot));
+        }
+        assert_eq!(slots.usage().used_slots, 2);
+    }
+
     #[test]
     fn test_from_raw_regions() {
         // Check dirty page tracking is off.
@@ -530,6 +892,52 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_with_file_regions() {
+        let region_size = 0x10000;
+        let guest_memory = GuestMemoryMmap::from_raw_regions(
+            &[(GuestAddress(0), region_size)],
+            false,
+            HugePageConfig::None,
+        )
+        .unwrap();
+
+        let file = TempFile::new().unwrap().into_file();
+        file.set_len(2 * region_size as u64).unwrap();
+        let guest_memory = guest_memory
+            .with_file_regions(
+                &file,
+                &[
+                    (GuestAddress(0x10000), region_size),
+                    (GuestAddress(0x40000), region_size),
+                ],
+                true,
+            )
+            .unwrap();
+        assert_eq!(guest_memory.num_regions(), 3);
+        assert!(guest_memory
+            .find_region(GuestAddress(0))
+            .unwrap()
+            .file_offset()
+            .is_none());
+        let region = guest_memory.find_region(GuestAddress(0x40000)).unwrap();
+        assert_eq!(region.file_offset().unwrap().start(), 0x10000);
+        assert!(region.bitmap().is_some());
+
+        // The regions are shared with the file.
+        guest_memory
+            .write_obj(0xdead_beef_u32, GuestAddress(0x40000))
+            .unwrap();
+        let mut data = [0u8; 4];
+        file.read_exact_at(&mut data, 0x10000).unwrap();
+        assert_eq!(u32::from_le_bytes(data), 0xdead_beef);
+
+        // The regions must not overlap the guest memory.
+        guest_memory
+            .with_file_regions(&file, &[(GuestAddress(0), region_size)], false)
+            .unwrap_err();
+    }
+
     #[test]
     fn test_from_state() {
         let state = GuestMemoryState {
@@ -749,6 +1157,121 @@ mod tests {
         assert_eq!(second_region, restored_region);
     }
 
+    #[test]
+    fn test_dump_sparse() {
+        let page_size = get_page_size().unwrap();
+
+        // Two regions, the first one spanning several chunks, with a one page gap between them.
+        let region_1_address = GuestAddress(0);
+        let region_1_size = page_size * (SPARSE_DUMP_CHUNK_PAGES + 2);
+        let region_2_address = GuestAddress((region_1_size + page_size) as u64);
+        let region_2_size = page_size * 3;
+        let mem_regions = [
+            (region_1_address, region_1_size),
+            (region_2_address, region_2_size),
+        ];
+        let guest_memory =
+            GuestMemoryMmap::from_raw_regions(&mem_regions, false, HugePageConfig::None).unwrap();
+
+        // Data pages at the start of the first region, around the end of its first chunk, and in
+        // the middle of the second region. The other pages hold zeros.
+        let data = vec![1u8; page_size];
+        for page in [0, SPARSE_DUMP_CHUNK_PAGES - 1, SPARSE_DUMP_CHUNK_PAGES] {
+            guest_memory
+                .write(
+                    &data,
+                    region_1_address.unchecked_add((page * page_size) as u64),
+                )
+                .unwrap();
+        }
+        guest_memory
+            .write(&data, region_2_address.unchecked_add(page_size as u64))
+            .unwrap();
+
+        // The file holds a previous snapshot, which is overwritten.
+        let mut memory_file = TempFile::new().unwrap().into_file();
+        let file_size = region_1_size + region_2_size;
+        memory_file.write_all(&vec![0xffu8; file_size]).unwrap();
+        guest_memory.dump_sparse(&mut memory_file).unwrap();
+        assert_eq!(memory_file.metadata().unwrap().len(), file_size as u64);
+
+        let mut dumped = vec![0u8; file_size];
+        memory_file.read_exact_at(&mut dumped, 0).unwrap();
+        let mut expected = vec![0u8; region_1_size];
+        guest_memory.read(&mut expected, region_1_address).unwrap();
+        let mut region_2 = vec![0u8; region_2_size];
+        guest_memory.read(&mut region_2, region_2_address).unwrap();
+        expected.extend_from_slice(&region_2);
+        assert_eq!(dumped, expected);
+    }
+
+    #[test]
+    fn test_prefault() {
+        let page_size = get_page_size().unwrap();
+        let region_size = page_size * 4;
+        let mut memory_file = TempFile::new().unwrap().into_file();
+        memory_file.write_all(&vec![1u8; region_size]).unwrap();
+        let mem_state = GuestMemoryState {
+            regions: vec![GuestMemoryRegionState {
+                base_address: 0,
+                size: region_size,
+                offset: 0,
+            }],
+        };
+        let guest_memory = GuestMemoryMmap::from_state(
+            Some(&memory_file),
+            &mem_state,
+            false,
+            HugePageConfig::None,
+        )
+        .unwrap();
+
+        // All the pages are resident, and still hold the contents of the file, both when they are
+        // populated by the kernel and when they are touched on older kernels.
+        let region = guest_memory.iter().next().unwrap();
+        let resident_pages = || {
+            let mut residency = vec![0u8; region_size / page_size];
+            // SAFETY: The address and length are the ones of the mapping of the region, and the
+            // vector holds one byte per page.
+            let ret = unsafe {
+                libc::mincore(region.as_ptr().cast(), region_size, residency.as_mut_ptr())
+            };
+            assert_eq!(ret, 0);
+            residency.iter().filter(|page| *page & 1 == 1).count()
+        };
+        let mut contents = vec![0u8; region_size];
+
+        touch_pages(region, page_size);
+        assert_eq!(resident_pages(), region_size / page_size);
+        guest_memory.read(&mut contents, GuestAddress(0)).unwrap();
+        assert_eq!(contents, vec![1u8; region_size]);
+
+        guest_memory.prefault().unwrap();
+        assert_eq!(resident_pages(), region_size / page_size);
+        guest_memory.read(&mut contents, GuestAddress(0)).unwrap();
+        assert_eq!(contents, vec![1u8; region_size]);
+    }
+
+    #[test]
+    fn test_exclude_from_fork() {
+        let page_size = get_page_size().unwrap();
+        let regions = [(GuestAddress(0), page_size)];
+        let guest_memory =
+            GuestMemoryMmap::from_raw_regions(&regions, false, HugePageConfig::None).unwrap();
+        guest_memory.exclude_from_fork().unwrap();
+
+        // The mapping of the region is flagged as not copied on fork, i.e. `dc`.
+        let region = guest_memory.iter().next().unwrap();
+        let start = format!("{:x}-", region.as_ptr() as usize);
+        let smaps = std::fs::read_to_string("/proc/self/smaps").unwrap();
+        let flags = smaps
+            .lines()
+            .skip_while(|line| !line.starts_with(&start))
+            .find(|line| line.starts_with("VmFlags:"))
+            .unwrap();
+        assert!(flags.split_whitespace().any(|flag| flag == "dc"));
+    }
+
     #[test]
     fn test_dump_dirty() {
         let page_size = get_page_size().unwrap();
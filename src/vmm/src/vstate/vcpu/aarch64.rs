@@ -8,12 +8,18 @@
 use std::fmt::{Debug, Write};
 
 use kvm_bindings::{
-    kvm_mp_state, kvm_vcpu_init, KVM_ARM_VCPU_POWER_OFF, KVM_ARM_VCPU_PSCI_0_2, KVM_ARM_VCPU_SVE,
+    kvm_device_attr, kvm_mp_state, kvm_vcpu_init, KVM_ARM_VCPU_PMU_V3, KVM_ARM_VCPU_PMU_V3_CTRL,
+    KVM_ARM_VCPU_PMU_V3_INIT, KVM_ARM_VCPU_PMU_V3_IRQ, KVM_ARM_VCPU_POWER_OFF,
+    KVM_ARM_VCPU_PSCI_0_2, KVM_ARM_VCPU_SVE,
 };
 use kvm_ioctls::*;
 use serde::{Deserialize, Serialize};
 
-use crate::arch::aarch64::regs::{Aarch64RegisterVec, KVM_REG_ARM64_SVE_VLS};
+use crate::arch::aarch64::layout::{PMU_PPI, PPI_BASE};
+use crate::arch::aarch64::regs::{
+    is_sve_reg, sve_vls_limit, Aarch64RegisterRef, Aarch64RegisterVec, KVM_REG_ARM64_SVE_VLS,
+    SVE_VLS_WORDS,
+};
 use crate::arch::aarch64::vcpu::{
     get_all_registers, get_all_registers_ids, get_mpidr, get_mpstate, get_registers, set_mpstate,
     set_register, setup_boot_regs, VcpuError as ArchError,
@@ -37,14 +43,28 @@ pub enum KvmVcpuError {
     DumpCpuConfig(ArchError),
     /// Error getting the vcpu preferred target: {0}
     GetPreferredTarget(kvm_ioctls::Error),
+    /// Error finalizing the vcpu: {0}
+    Finalize(kvm_ioctls::Error),
     /// Error initializing the vcpu: {0}
     Init(kvm_ioctls::Error),
+    /// Error initializing the vcpu PMU: {0}
+    InitPmu(kvm_ioctls::Error),
+    /// PMU is not supported by the host: {0}
+    PmuNotSupported(kvm_ioctls::Error),
     /// Error applying template: {0}
     ApplyCpuTemplate(ArchError),
     /// Failed to restore the state of the vcpu: {0}
     RestoreState(ArchError),
     /// Failed to save the state of the vcpu: {0}
     SaveState(ArchError),
+    /// Failed to configure SVE vector lengths: {0}
+    SveVectorLength(ArchError),
+    /// SVE vector length was requested but SVE is not enabled for the vcpu
+    SveNotEnabled,
+    /// SVE vector length of {0} bits is not supported by the host
+    UnsupportedSveVectorLength(u16),
+    /// The vcpu state contains SVE registers but SVE is not enabled for the vcpu
+    SveStateMismatch,
 }
 
 /// Error type for [`KvmVcpu::configure`].
@@ -139,15 +159,25 @@ impl KvmVcpu {
     ///
     /// # Arguments
     ///
-    /// * `vm_fd` - The kvm `VmFd` for this microvm.
-    pub fn init(&mut self, vcpu_features: &[VcpuFeatures]) -> Result<(), KvmVcpuError> {
+    /// * `vcpu_features` - Modifiers of the vcpu features.
+    /// * `sve_vector_length` - Maximum SVE vector length in bits the guest is allowed to use. If
+    ///   `None`, all vector lengths supported by the host are exposed.
+    pub fn init(
+        &mut self,
+        vcpu_features: &[VcpuFeatures],
+        sve_vector_length: Option<u16>,
+    ) -> Result<(), KvmVcpuError> {
         for feature in vcpu_features.iter() {
             let index = feature.index as usize;
             self.kvi.features[index] = feature.bitmap.apply(self.kvi.features[index]);
         }
 
         self.init_vcpu()?;
+        if let Some(max_len) = sve_vector_length {
+            self.set_sve_vector_length(max_len)?;
+        }
         self.finalize_vcpu()?;
+        self.init_pmu()?;
 
         Ok(())
     }
@@ -186,6 +216,12 @@ impl KvmVcpu {
 
     /// Use provided state to populate KVM internal state.
     pub fn restore_state(&mut self, state: &VcpuState) -> Result<(), KvmVcpuError> {
+        // SVE registers can only be restored if the vcpu is initialized with SVE
+        // enabled, otherwise KVM would reject them after half of the state is set.
+        if !Self::sve_enabled(&state.kvi) && state.regs.iter().any(|reg| is_sve_reg(reg.id)) {
+            return Err(KvmVcpuError::SveStateMismatch);
+        }
+
         self.kvi = state.kvi;
 
         self.init_vcpu()?;
@@ -201,6 +237,9 @@ impl KvmVcpu {
         }
 
         self.finalize_vcpu()?;
+        // The PMU interrupt is not part of the registers, so it has to be
+        // configured again if the feature was enabled on the snapshotted vcpu.
+        self.init_pmu()?;
 
         // KVM_REG_ARM64_SVE_VLS needs to be skipped after vcpu is finalized.
         // If it is present it is handled in the code above.
@@ -230,14 +269,83 @@ impl KvmVcpu {
         Ok(())
     }
 
+    /// Checks if SVE is enabled in the vcpu features.
+    fn sve_enabled(kvi: &kvm_vcpu_init) -> bool {
+        (kvi.features[0] & (1 << KVM_ARM_VCPU_SVE)) != 0
+    }
+
+    /// Restricts the SVE vector lengths available to the guest to the ones
+    /// not longer than `max_len` bits. Must be called before the vcpu is finalized.
+    fn set_sve_vector_length(&self, max_len: u16) -> Result<(), KvmVcpuError> {
+        if !Self::sve_enabled(&self.kvi) {
+            return Err(KvmVcpuError::SveNotEnabled);
+        }
+
+        let mut bytes = [0u8; SVE_VLS_WORDS * 8];
+        self.fd
+            .get_one_reg(KVM_REG_ARM64_SVE_VLS, &mut bytes)
+            .map_err(|err| {
+                KvmVcpuError::SveVectorLength(ArchError::GetOneReg(KVM_REG_ARM64_SVE_VLS, err))
+            })?;
+
+        let mut host_vls = [0u64; SVE_VLS_WORDS];
+        for (word, chunk) in host_vls.iter_mut().zip(bytes.chunks_exact(8)) {
+            // Safe to unwrap because the chunks are exactly 8 bytes long.
+            *word = u64::from_le_bytes(chunk.try_into().unwrap());
+        }
+        let vls = sve_vls_limit(&host_vls, max_len)
+            .ok_or(KvmVcpuError::UnsupportedSveVectorLength(max_len))?;
+        for (word, chunk) in vls.iter().zip(bytes.chunks_exact_mut(8)) {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+
+        set_register(
+            &self.fd,
+            Aarch64RegisterRef::new(KVM_REG_ARM64_SVE_VLS, &bytes),
+        )
+        .map_err(KvmVcpuError::SveVectorLength)
+    }
+
+    /// Checks for PMU feature and, if it is enabled, sets the PMU overflow
+    /// interrupt and initializes the PMU. The in-kernel irqchip needs to be
+    /// initialized before calling this.
+    fn init_pmu(&self) -> Result<(), KvmVcpuError> {
+        if (self.kvi.features[0] & (1 << KVM_ARM_VCPU_PMU_V3)) == 0 {
+            return Ok(());
+        }
+
+        let irq: u32 = PPI_BASE + PMU_PPI;
+        let mut attr = kvm_device_attr {
+            group: KVM_ARM_VCPU_PMU_V3_CTRL,
+            attr: u64::from(KVM_ARM_VCPU_PMU_V3_IRQ),
+            addr: &irq as *const u32 as u64,
+            flags: 0,
+        };
+        self.fd
+            .has_device_attr(&attr)
+            .map_err(KvmVcpuError::PmuNotSupported)?;
+        self.fd
+            .set_device_attr(&attr)
+            .map_err(KvmVcpuError::InitPmu)?;
+
+        attr.attr = u64::from(KVM_ARM_VCPU_PMU_V3_INIT);
+        attr.addr = 0;
+        self.fd
+            .set_device_attr(&attr)
+            .map_err(KvmVcpuError::InitPmu)?;
+        Ok(())
+    }
+
     /// Checks for SVE feature and calls `vcpu_finalize` if
     /// it is enabled.
     fn finalize_vcpu(&self) -> Result<(), KvmVcpuError> {
-        if (self.kvi.features[0] & (1 << KVM_ARM_VCPU_SVE)) != 0 {
+        if Self::sve_enabled(&self.kvi) {
             // KVM_ARM_VCPU_SVE has value 4 so casting to i32 is safe.
             #[allow(clippy::cast_possible_wrap)]
             let feature = KVM_ARM_VCPU_SVE as i32;
-            self.fd.vcpu_finalize(&feature).unwrap();
+            self.fd
+                .vcpu_finalize(&feature)
+                .map_err(KvmVcpuError::Finalize)?;
         }
         Ok(())
     }
@@ -293,6 +401,13 @@ impl Debug for VcpuState {
     }
 }
 
+impl VcpuState {
+    /// Whether the vCPU was initialized with the PMUv3 feature.
+    pub fn has_pmu(&self) -> bool {
+        (self.kvi.features[0] & (1 << KVM_ARM_VCPU_PMU_V3)) != 0
+    }
+}
+
 #[cfg(test)]
 mod tests {
     #![allow(clippy::undocumented_unsafe_blocks)]
@@ -301,7 +416,6 @@ mod tests {
     use kvm_bindings::{KVM_ARM_VCPU_PSCI_0_2, KVM_REG_SIZE_U64};
 
     use super::*;
-    use crate::arch::aarch64::regs::Aarch64RegisterRef;
     use crate::cpu_config::aarch64::CpuConfiguration;
     use crate::cpu_config::templates::RegisterValueFilter;
     use crate::vcpu::VcpuConfig;
@@ -312,7 +426,7 @@ mod tests {
     fn setup_vcpu(mem_size: usize) -> (Vm, KvmVcpu, GuestMemoryMmap) {
         let (mut vm, vm_mem) = setup_vm(mem_size);
         let mut vcpu = KvmVcpu::new(0, &vm).unwrap();
-        vcpu.init(&[]).unwrap();
+        vcpu.init(&[], None).unwrap();
         vm.setup_irqchip(1).unwrap();
 
         (vm, vcpu, vm_mem)
@@ -384,10 +498,62 @@ mod tests {
                 value: 0,
             },
         }];
-        vcpu.init(&vcpu_features).unwrap();
+        vcpu.init(&vcpu_features, None).unwrap();
         assert!((vcpu.kvi.features[0] & (1 << KVM_ARM_VCPU_PSCI_0_2)) == 0)
     }
 
+    #[test]
+    fn test_init_vcpu_pmu() {
+        let (mut vm, _vm_mem) = setup_vm(0x1000);
+        let mut vcpu = KvmVcpu::new(0, &vm).unwrap();
+        vm.setup_irqchip(1).unwrap();
+
+        let vcpu_features = vec![VcpuFeatures {
+            index: 0,
+            bitmap: RegisterValueFilter {
+                filter: 1 << KVM_ARM_VCPU_PMU_V3,
+                value: 1 << KVM_ARM_VCPU_PMU_V3,
+            },
+        }];
+        vcpu.init(&vcpu_features, None).unwrap();
+        assert!((vcpu.kvi.features[0] & (1 << KVM_ARM_VCPU_PMU_V3)) != 0);
+
+        // PMU feature is kept in the saved state.
+        let state = vcpu.save_state().unwrap();
+        assert!(state.has_pmu());
+        assert!(!VcpuState::default().has_pmu());
+    }
+
+    #[test]
+    fn test_init_vcpu_sve_not_enabled() {
+        let (mut vm, _vm_mem) = setup_vm(0x1000);
+        let mut vcpu = KvmVcpu::new(0, &vm).unwrap();
+        vm.setup_irqchip(1).unwrap();
+
+        // SVE is not enabled by default, so vector length can not be set.
+        assert_eq!(
+            vcpu.init(&[], Some(128)).unwrap_err(),
+            KvmVcpuError::SveNotEnabled
+        );
+    }
+
+    #[test]
+    fn test_restore_sve_state_mismatch() {
+        let (mut vm, _vm_mem) = setup_vm(0x1000);
+        let mut vcpu = KvmVcpu::new(0, &vm).unwrap();
+        vm.setup_irqchip(1).unwrap();
+
+        let mut state = VcpuState::default();
+        state.regs.push(Aarch64RegisterRef::new(
+            KVM_REG_ARM64_SVE_VLS,
+            &[0u8; SVE_VLS_WORDS * 8],
+        ));
+        assert_eq!(
+            vcpu.restore_state(&state).unwrap_err(),
+            KvmVcpuError::SveStateMismatch
+        );
+    }
+
     #[test]
     fn test_vcpu_save_restore_state() {
         let (mut vm, _vm_mem) = setup_vm(0x1000);
@@ -421,7 +587,7 @@ mod tests {
             KvmVcpuError::RestoreState(ArchError::SetOneReg(0, _))
         ));
 
-        vcpu.init(&[]).unwrap();
+        vcpu.init(&[], None).unwrap();
         let state = vcpu.save_state().expect("Cannot save state of vcpu");
         assert!(!state.regs.is_empty());
         vcpu.restore_state(&state)
@@ -447,7 +613,7 @@ mod tests {
         let (mut vm, _vm_mem) = setup_vm(0x1000);
         let mut vcpu = KvmVcpu::new(0, &vm).unwrap();
         vm.setup_irqchip(1).unwrap();
-        vcpu.init(&[]).unwrap();
+        vcpu.init(&[], None).unwrap();
 
         vcpu.dump_cpu_config().unwrap();
     }
@@ -456,9 +622,9 @@ mod tests {
     fn test_setup_non_boot_vcpu() {
         let (vm, _) = setup_vm(0x1000);
         let mut vcpu1 = KvmVcpu::new(0, &vm).unwrap();
-        vcpu1.init(&[]).unwrap();
+        vcpu1.init(&[], None).unwrap();
         let mut vcpu2 = KvmVcpu::new(1, &vm).unwrap();
-        vcpu2.init(&[]).unwrap();
+        vcpu2.init(&[], None).unwrap();
     }
 
     #[test]
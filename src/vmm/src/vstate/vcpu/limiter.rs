@@ -0,0 +1,249 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use std::{io, ptr, thread};
+
+use utils::time::{get_time_ns, ClockType, NANOS_PER_MILLISECOND, NANOS_PER_SECOND};
+
+use crate::logger::{IncMetric, METRICS};
+use crate::vmm_config::cpu_limiter::CpuLimiterConfig;
+
+/// Host CPU time a vCPU thread uses between two charges of the CPU limiter.
+pub const LIMITER_TICK_NS: u64 = NANOS_PER_MILLISECOND;
+// Longest a vCPU thread sleeps at once, so that it keeps handling the events sent to it while it
+// waits out a large debt.
+const MAX_THROTTLE_NS: u64 = 100 * NANOS_PER_MILLISECOND;
+
+/// Limits the host CPU time used by all the vCPUs of a microVM to a fraction of the host CPUs.
+///
+/// The vCPU threads charge the CPU time they use as they go, and sleep as soon as they get ahead
+/// of the allocation of the microVM, instead of running until the quota of a cgroup period runs
+/// out and then stopping for the rest of the period. The limiter tracks the theoretical arrival
+/// time of the charges (GCRA), which advances by the CPU time used divided by the allocation and
+/// may trail the current time by at most the burst.
+#[derive(Debug)]
+pub struct CpuLimiter {
+    state: Mutex<LimiterState>,
+}
+
+#[derive(Debug)]
+struct LimiterState {
+    config: CpuLimiterConfig,
+    // Monotonic time in nanoseconds until which the CPU time already used is paid for.
+    tat_ns: u64,
+}
+
+impl CpuLimiter {
+    /// Creates a limiter with the given configuration, which must be valid.
+    pub fn new(config: CpuLimiterConfig) -> Self {
+        CpuLimiter {
+            state: Mutex::new(LimiterState { config, tat_ns: 0 }),
+        }
+    }
+
+    /// Replaces the configuration of the limiter, which must be valid. The CPU time already
+    /// charged is kept.
+    pub fn update(&self, config: CpuLimiterConfig) {
+        self.state.lock().expect("Poisoned lock").config = config;
+    }
+
+    /// Charges `used_ns` of CPU time used by a vCPU thread at the monotonic time `now_ns`, and
+    /// returns how long in nanoseconds the thread should sleep to stay within the allocation.
+    pub fn charge(&self, now_ns: u64, used_ns: u64) -> u64 {
+        let mut state = self.state.lock().expect("Poisoned lock");
+        let burst_ns = u64::from(state.config.burst_ms) * NANOS_PER_MILLISECOND;
+        let cost_ns = u128::from(used_ns) * 1000 / u128::from(state.config.millicpus);
+        state.tat_ns = state
+            .tat_ns
+            .max(now_ns.saturating_sub(burst_ns))
+            .saturating_add(u64::try_from(cost_ns).unwrap_or(u64::MAX));
+        state.tat_ns.saturating_sub(now_ns)
+    }
+}
+
+/// Side of the CPU limiter owned by a vCPU thread.
+#[derive(Debug)]
+pub struct VcpuLimiter {
+    limiter: Arc<CpuLimiter>,
+    // CPU time of the thread when it last charged the limiter.
+    charged_ns: u64,
+    // Whether the thread still owes CPU time to the limiter after its last sleep.
+    in_debt: bool,
+}
+
+impl VcpuLimiter {
+    /// Creates the side of `limiter` owned by a vCPU thread.
+    pub fn new(limiter: Arc<CpuLimiter>) -> Self {
+        VcpuLimiter {
+            limiter,
+            charged_ns: 0,
+            in_debt: false,
+        }
+    }
+
+    /// Whether the thread has to wait out a debt before running the vCPU again.
+    pub fn in_debt(&self) -> bool {
+        self.in_debt
+    }
+
+    /// Charges the CPU time the calling thread used since its previous charge, and sleeps for as
+    /// long as the limiter requires, or at most `MAX_THROTTLE_NS`. Returns whether the thread is
+    /// still in debt.
+    pub fn throttle(&mut self) -> bool {
+        let cpu_ns = get_time_ns(ClockType::ThreadCpu);
+        let used_ns = cpu_ns.saturating_sub(self.charged_ns);
+        self.charged_ns = cpu_ns;
+
+        let debt_ns = self
+            .limiter
+            .charge(get_time_ns(ClockType::Monotonic), used_ns);
+        let sleep_ns = debt_ns.min(MAX_THROTTLE_NS);
+        if sleep_ns > 0 {
+            METRICS.vcpu.throttled.inc();
+            METRICS.vcpu.throttled_us.add(sleep_ns / 1000);
+            thread::sleep(Duration::from_nanos(sleep_ns));
+        }
+        self.in_debt = debt_ns > sleep_ns;
+        self.in_debt
+    }
+}
+
+/// Timer sending a signal to the thread which created it every time the thread used a given
+/// amount of CPU time.
+#[derive(Debug)]
+pub struct CpuTimeTimer(libc::timer_t);
+
+impl CpuTimeTimer {
+    /// Creates a timer sending `signum` to the calling thread every `period_ns` of CPU time it
+    /// uses.
+    pub fn new(signum: libc::c_int, period_ns: u64) -> io::Result<Self> {
+        // SAFETY: `sigevent` is a C struct for which all zeroes is a valid value.
+        let mut sigevent: libc::sigevent = unsafe { std::mem::zeroed() };
+        sigevent.sigev_notify = libc::SIGEV_THREAD_ID;
+        sigevent.sigev_signo = signum;
+        // SAFETY: Safe because gettid takes no argument and always succeeds.
+        sigevent.sigev_notify_thread_id = unsafe { libc::gettid() };
+
+        let mut timer: libc::timer_t = ptr::null_mut();
+        // SAFETY: Safe because the arguments are valid and the return value is checked.
+        if unsafe { libc::timer_create(libc::CLOCK_THREAD_CPUTIME_ID, &mut sigevent, &mut timer) }
+            < 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+        let timer = CpuTimeTimer(timer);
+
+        let period = libc::timespec {
+            tv_sec: libc::time_t::try_from(period_ns / NANOS_PER_SECOND)
+                .map_err(|_| io::Error::from_raw_os_error(libc::EINVAL))?,
+            tv_nsec: libc::c_long::try_from(period_ns % NANOS_PER_SECOND)
+                .map_err(|_| io::Error::from_raw_os_error(libc::EINVAL))?,
+        };
+        let spec = libc::itimerspec {
+            it_interval: period,
+            it_value: period,
+        };
+        // SAFETY: Safe because the timer was just created, `spec` is valid and the return value
+        // is checked.
+        if unsafe { libc::timer_settime(timer.0, 0, &spec, ptr::null_mut()) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(timer)
+    }
+}
+
+impl Drop for CpuTimeTimer {
+    fn drop(&mut self) {
+        // SAFETY: Safe because the timer is valid and deleted only once.
+        unsafe { libc::timer_delete(self.0) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_limiter(millicpus: u32, burst_ms: u32) -> CpuLimiter {
+        CpuLimiter::new(CpuLimiterConfig {
+            millicpus,
+            burst_ms,
+        })
+    }
+
+    #[test]
+    fn test_charge() {
+        let now = 10 * NANOS_PER_SECOND;
+        let limiter = new_limiter(500, 0);
+        // Half a CPU pays 1ms of CPU time in 2ms.
+        assert_eq!(
+            limiter.charge(now, NANOS_PER_MILLISECOND),
+            2 * NANOS_PER_MILLISECOND
+        );
+        assert_eq!(
+            limiter.charge(now + NANOS_PER_MILLISECOND, NANOS_PER_MILLISECOND),
+            3 * NANOS_PER_MILLISECOND
+        );
+        // Once the debt is paid, the unused allocation is lost without a burst.
+        assert_eq!(limiter.charge(now + NANOS_PER_SECOND, 0), 0);
+        assert_eq!(
+            limiter.charge(now + NANOS_PER_SECOND, NANOS_PER_MILLISECOND),
+            2 * NANOS_PER_MILLISECOND
+        );
+
+        // Two CPUs pay 1ms of CPU time in 0.5ms.
+        let limiter = new_limiter(2000, 0);
+        assert_eq!(
+            limiter.charge(now, NANOS_PER_MILLISECOND),
+            NANOS_PER_MILLISECOND / 2
+        );
+    }
+
+    #[test]
+    fn test_charge_burst() {
+        let now = 10 * NANOS_PER_SECOND;
+        let limiter = new_limiter(1000, 5);
+        // The vCPUs may use 5ms of CPU time ahead of their allocation.
+        for _ in 0..5 {
+            assert_eq!(limiter.charge(now, NANOS_PER_MILLISECOND), 0);
+        }
+        assert_eq!(
+            limiter.charge(now, NANOS_PER_MILLISECOND),
+            NANOS_PER_MILLISECOND
+        );
+    }
+
+    #[test]
+    fn test_update() {
+        let now = 10 * NANOS_PER_SECOND;
+        let limiter = new_limiter(1000, 0);
+        assert_eq!(
+            limiter.charge(now, NANOS_PER_MILLISECOND),
+            NANOS_PER_MILLISECOND
+        );
+        limiter.update(CpuLimiterConfig {
+            millicpus: 250,
+            burst_ms: 0,
+        });
+        // The debt already charged is kept.
+        assert_eq!(
+            limiter.charge(now, NANOS_PER_MILLISECOND),
+            5 * NANOS_PER_MILLISECOND
+        );
+    }
+
+    #[test]
+    fn test_vcpu_limiter() {
+        let mut vcpu_limiter = VcpuLimiter::new(Arc::new(new_limiter(u32::MAX, 0)));
+        assert!(!vcpu_limiter.in_debt());
+        assert!(!vcpu_limiter.throttle());
+        assert!(!vcpu_limiter.in_debt());
+    }
+
+    #[test]
+    fn test_cpu_time_timer() {
+        // The period is long enough for the signal, which would kill the test, to never be sent.
+        CpuTimeTimer::new(libc::SIGRTMIN(), 3600 * NANOS_PER_SECOND).unwrap();
+    }
+}
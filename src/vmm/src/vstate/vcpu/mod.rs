@@ -23,18 +23,23 @@ use seccompiler::{BpfProgram, BpfProgramRef};
 use vmm_sys_util::errno;
 use vmm_sys_util::eventfd::EventFd;
 
+use crate::boot_timings::{BootPhase, BOOT_TIMINGS};
 use crate::cpu_config::templates::{CpuConfiguration, GuestConfigError};
+use crate::event_stream::{self, VmEvent};
 #[cfg(feature = "gdb")]
 use crate::gdb::target::{get_raw_tid, GdbTargetError};
 use crate::logger::{IncMetric, METRICS};
 use crate::utils::signal::{register_signal_handler, sigrtmin, Killable};
 use crate::utils::sm::StateMachine;
+use crate::vstate::vcpu::limiter::{CpuLimiter, CpuTimeTimer, VcpuLimiter, LIMITER_TICK_NS};
 use crate::vstate::vm::Vm;
 use crate::FcExitCode;
 
 /// Module with aarch64 vCPU implementation.
 #[cfg(target_arch = "aarch64")]
 pub mod aarch64;
+/// Module with the CPU limiter throttling the vCPU threads.
+pub mod limiter;
 /// Module with x86_64 vCPU implementation.
 #[cfg(target_arch = "x86_64")]
 pub mod x86_64;
@@ -62,10 +67,14 @@ pub enum VcpuError {
     VcpuResponse(KvmVcpuError),
     /// Cannot spawn a new vCPU thread: {0}
     VcpuSpawn(io::Error),
+    /// Cannot create the CPU limiter timer: {0}
+    CpuTimer(io::Error),
     /// Cannot clean init vcpu TLS
     VcpuTlsInit,
     /// Vcpu not present in TLS
     VcpuTlsNotPresent,
+    /// Cannot install the seccomp filter: {0}
+    SeccompFilter(seccompiler::InstallationError),
     /// Error with gdb request sent
     #[cfg(feature = "gdb")]
     GdbRequest(GdbTargetError),
@@ -86,9 +95,13 @@ pub struct VcpuConfig {
 type VcpuCell = Cell<Option<*mut Vcpu>>;
 
 /// Error type for [`Vcpu::start_threaded`].
-#[derive(Debug, derive_more::From, thiserror::Error)]
-#[error("Failed to spawn vCPU thread: {0}")]
-pub struct StartThreadedError(std::io::Error);
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum StartThreadedError {
+    /// Failed to spawn vCPU thread: {0}
+    Spawn(#[from] std::io::Error),
+    /// Failed to set up the vCPU thread: {0}
+    Setup(VcpuError),
+}
 
 /// Error type for [`Vcpu::copy_kvm_vcpu_fd`].
 #[cfg(feature = "gdb")]
@@ -120,6 +133,8 @@ pub struct Vcpu {
     response_receiver: Option<Receiver<VcpuResponse>>,
     /// The transmitting end of the responses channel owned by the vcpu side.
     response_sender: Sender<VcpuResponse>,
+    /// The CPU limiter throttling this vcpu, if any.
+    cpu_limiter: Option<VcpuLimiter>,
 }
 
 impl Vcpu {
@@ -226,6 +241,7 @@ impl Vcpu {
             #[cfg(feature = "gdb")]
             gdb_event: None,
             kvm_vcpu,
+            cpu_limiter: None,
         })
     }
 
@@ -234,6 +250,11 @@ impl Vcpu {
         self.kvm_vcpu.peripherals.mmio_bus = Some(mmio_bus);
     }
 
+    /// Sets the CPU limiter throttling this vcpu.
+    pub fn set_cpu_limiter(&mut self, cpu_limiter: Arc<CpuLimiter>) {
+        self.cpu_limiter = Some(VcpuLimiter::new(cpu_limiter));
+    }
+
     /// Attaches the fields required for debugging
     #[cfg(feature = "gdb")]
     pub fn attach_debug_info(&mut self, gdb_event: Sender<usize>) {
@@ -261,9 +282,30 @@ impl Vcpu {
     ) -> Result<VcpuHandle, StartThreadedError> {
         let event_sender = self.event_sender.take().expect("vCPU already started");
         let response_receiver = self.response_receiver.take().unwrap();
+        let (tid_sender, tid_receiver) = channel();
         let vcpu_thread = thread::Builder::new()
             .name(format!("fc_vcpu {}", self.kvm_vcpu.index))
             .spawn(move || {
+                // Kicks the vcpu every time its thread uses a tick of CPU time, for it to charge
+                // the CPU limiter. The timer is created before the seccomp filters are applied.
+                let _cpu_timer = match self
+                    .cpu_limiter
+                    .is_some()
+                    .then(|| CpuTimeTimer::new(sigrtmin() + VCPU_RTSIG_OFFSET, LIMITER_TICK_NS))
+                    .transpose()
+                {
+                    Ok(cpu_timer) => cpu_timer,
+                    Err(err) => {
+                        tid_sender
+                            .send(Err(VcpuError::CpuTimer(err)))
+                            .expect("Cannot report the vcpu thread setup error.");
+                        return;
+                    }
+                };
+                // SAFETY: Safe because gettid takes no argument and always succeeds.
+                tid_sender
+                    .send(Ok(unsafe { libc::gettid() }))
+                    .expect("Cannot report the vcpu thread ID.");
                 let filter = &*seccomp_filter;
                 self.init_thread_local_data()
                     .expect("Cannot cleanly initialize vcpu TLS.");
@@ -271,11 +313,16 @@ impl Vcpu {
                 barrier.wait();
                 self.run(filter);
             })?;
+        let tid = tid_receiver
+            .recv()
+            .expect("vcpu thread exited before reporting its ID.")
+            .map_err(StartThreadedError::Setup)?;
 
         Ok(VcpuHandle::new(
             event_sender,
             response_receiver,
             vcpu_thread,
+            tid,
         ))
     }
 
@@ -299,28 +346,55 @@ impl Vcpu {
         StateMachine::run(self, Self::paused);
     }
 
+    // Installs a seccomp filter on this thread, on top of its current filters, and reports the
+    // outcome. The filters can be tightened in any state.
+    fn install_seccomp_filter(&self, seccomp_filter: BpfProgramRef) {
+        let response = match seccompiler::apply_filter(seccomp_filter) {
+            Ok(()) => VcpuResponse::InstalledSeccompFilter,
+            Err(err) => VcpuResponse::Error(VcpuError::SeccompFilter(err)),
+        };
+        self.response_sender
+            .send(response)
+            .expect("vcpu channel unexpectedly closed");
+    }
+
     // This is the main loop of the `Running` state.
     fn running(&mut self) -> StateMachine<Self> {
         // This loop is here just for optimizing the emulation path.
         // No point in ticking the state machine if there are no external events.
         loop {
+            // Wait out the debt to the CPU limiter before running the vcpu again, checking the
+            // external events between the sleeps.
+            if let Some(cpu_limiter) = self.cpu_limiter.as_mut() {
+                if cpu_limiter.in_debt() && cpu_limiter.throttle() {
+                    break;
+                }
+            }
             match self.run_emulation() {
                 // Emulation ran successfully, continue.
                 Ok(VcpuEmulation::Handled) => (),
-                // Emulation was interrupted, check external events.
-                Ok(VcpuEmulation::Interrupted) => break,
+                // Emulation was interrupted, charge the CPU limiter and check external events.
+                Ok(VcpuEmulation::Interrupted) => {
+                    if let Some(cpu_limiter) = self.cpu_limiter.as_mut() {
+                        cpu_limiter.throttle();
+                    }
+                    break;
+                }
                 // If the guest was rebooted or halted:
                 // - vCPU0 will always exit out of `KVM_RUN` with KVM_EXIT_SHUTDOWN or KVM_EXIT_HLT.
                 // - the other vCPUs won't ever exit out of `KVM_RUN`, but they won't consume CPU.
                 // So we pause vCPU0 and send a signal to the emulation thread to stop the VMM.
-                Ok(VcpuEmulation::Stopped) => return self.exit(FcExitCode::Ok),
+                Ok(VcpuEmulation::Stopped) => return self.exit(FcExitCode::Ok, false),
+                // A reset of the guest, e.g. after a triple fault, stops the microVM as well, but
+                // is reported as such so that the VMM can snapshot the failure.
+                Ok(VcpuEmulation::Reset) => return self.exit(FcExitCode::Ok, true),
                 // If the emulation requests a pause lets do this
                 #[cfg(feature = "gdb")]
                 Ok(VcpuEmulation::Paused) => {
                     return StateMachine::next(Self::paused);
                 }
                 // Emulation errors lead to vCPU exit.
-                Err(_) => return self.exit(FcExitCode::GenericError),
+                Err(_) => return self.exit(FcExitCode::GenericError, false),
             }
         }
 
@@ -354,8 +428,8 @@ impl Vcpu {
                     .send(VcpuResponse::Resumed)
                     .expect("vcpu channel unexpectedly closed");
             }
-            // SaveState cannot be performed on a running Vcpu.
-            Ok(VcpuEvent::SaveState) => {
+            // SaveState and RestoreState cannot be performed on a running Vcpu.
+            Ok(VcpuEvent::SaveState) | Ok(VcpuEvent::RestoreState(_)) => {
                 self.response_sender
                     .send(VcpuResponse::NotAllowed(String::from(
                         "save/restore unavailable while running",
@@ -370,11 +444,14 @@ impl Vcpu {
                     )))
                     .expect("vcpu channel unexpectedly closed");
             }
+            Ok(VcpuEvent::InstallSeccompFilter(seccomp_filter)) => {
+                self.install_seccomp_filter(&seccomp_filter);
+            }
             Ok(VcpuEvent::Finish) => return StateMachine::finish(),
             // Unhandled exit of the other end.
             Err(TryRecvError::Disconnected) => {
                 // Move to 'exited' state.
-                state = self.exit(FcExitCode::GenericError);
+                state = self.exit(FcExitCode::GenericError, false);
             }
             // All other events or lack thereof have no effect on current 'running' state.
             Err(TryRecvError::Empty) => (),
@@ -399,6 +476,7 @@ impl Vcpu {
                 self.response_sender
                     .send(VcpuResponse::Resumed)
                     .expect("vcpu channel unexpectedly closed");
+                BOOT_TIMINGS.record(BootPhase::FirstVcpuRun);
                 // Move to 'running' state.
                 StateMachine::next(Self::running)
             }
@@ -409,19 +487,17 @@ impl Vcpu {
                 StateMachine::next(Self::paused)
             }
             Ok(VcpuEvent::SaveState) => {
-                // Save vcpu state.
-                self.kvm_vcpu
-                    .save_state()
-                    .map(|vcpu_state| {
-                        self.response_sender
-                            .send(VcpuResponse::SavedState(Box::new(vcpu_state)))
-                            .expect("vcpu channel unexpectedly closed");
-                    })
-                    .unwrap_or_else(|err| {
-                        self.response_sender
-                            .send(VcpuResponse::Error(VcpuError::VcpuResponse(err)))
-                            .expect("vcpu channel unexpectedly closed");
-                    });
+                self.send_saved_state();
+                StateMachine::next(Self::paused)
+            }
+            Ok(VcpuEvent::RestoreState(vcpu_state)) => {
+                let response = match self.kvm_vcpu.restore_state(&vcpu_state) {
+                    Ok(()) => VcpuResponse::RestoredState,
+                    Err(err) => VcpuResponse::Error(VcpuError::VcpuResponse(err)),
+                };
+                self.response_sender
+                    .send(response)
+                    .expect("vcpu channel unexpectedly closed");
 
                 StateMachine::next(Self::paused)
             }
@@ -441,17 +517,33 @@ impl Vcpu {
 
                 StateMachine::next(Self::paused)
             }
+            Ok(VcpuEvent::InstallSeccompFilter(seccomp_filter)) => {
+                self.install_seccomp_filter(&seccomp_filter);
+                StateMachine::next(Self::paused)
+            }
             Ok(VcpuEvent::Finish) => StateMachine::finish(),
             // Unhandled exit of the other end.
             Err(_) => {
                 // Move to 'exited' state.
-                self.exit(FcExitCode::GenericError)
+                self.exit(FcExitCode::GenericError, false)
             }
         }
     }
 
-    // Transition to the exited state and finish on command.
-    fn exit(&mut self, exit_code: FcExitCode) -> StateMachine<Self> {
+    // Saves the vcpu state and sends it, or the error, as response.
+    fn send_saved_state(&self) {
+        let response = match self.kvm_vcpu.save_state() {
+            Ok(vcpu_state) => VcpuResponse::SavedState(Box::new(vcpu_state)),
+            Err(err) => VcpuResponse::Error(VcpuError::VcpuResponse(err)),
+        };
+        self.response_sender
+            .send(response)
+            .expect("vcpu channel unexpectedly closed");
+    }
+
+    // Transition to the exited state and finish on command. `guest_reset` tells whether the
+    // guest reset the vCPU.
+    fn exit(&mut self, exit_code: FcExitCode, guest_reset: bool) -> StateMachine<Self> {
         // To avoid cycles, all teardown paths take the following route:
         //   +------------------------+----------------------------+------------------------+
         //   |        Vmm             |           Action           |           Vcpu         |
@@ -469,6 +561,10 @@ impl Vcpu {
         // Vmm initiated teardown starts from `pub fn Vmm::stop()` (step 4).
         // Once `vmm.shutdown_exit_code` becomes `Some(exit_code)`, it is the upper layer's
         // responsibility to break main event loop and propagate the exit code value.
+        event_stream::emit(VmEvent::VcpuExit {
+            vcpu: self.kvm_vcpu.index,
+            exit_code: exit_code as u8,
+        });
         // Signal Vmm of Vcpu exit.
         if let Err(err) = self.exit_evt.write(1) {
             METRICS.vcpu.failures.inc();
@@ -477,11 +573,14 @@ impl Vcpu {
         // From this state we only accept going to finished.
         loop {
             self.response_sender
-                .send(VcpuResponse::Exited(exit_code))
+                .send(VcpuResponse::Exited(exit_code, guest_reset))
                 .expect("vcpu channel unexpectedly closed");
-            // Wait for and only accept 'VcpuEvent::Finish'.
-            if let Ok(VcpuEvent::Finish) = self.event_receiver.recv() {
-                break;
+            // Wait for 'VcpuEvent::Finish', still saving the vcpu state for the snapshots of
+            // the failures.
+            match self.event_receiver.recv() {
+                Ok(VcpuEvent::Finish) => break,
+                Ok(VcpuEvent::SaveState) => self.send_saved_state(),
+                _ => (),
             }
         }
         StateMachine::finish()
@@ -492,7 +591,12 @@ impl Vcpu {
     /// Returns error or enum specifying whether emulation was handled or interrupted.
     pub fn run_emulation(&mut self) -> Result<VcpuEmulation, VcpuError> {
         if self.kvm_vcpu.fd.get_kvm_run().immediate_exit == 1u8 {
-            warn!("Requested a vCPU run with immediate_exit enabled. The operation was skipped");
+            // The ticks of the CPU limiter routinely land outside of `KVM_RUN`.
+            if self.cpu_limiter.is_none() {
+                warn!(
+                    "Requested a vCPU run with immediate_exit enabled. The operation was skipped"
+                );
+            }
             self.kvm_vcpu.fd.set_kvm_immediate_exit(0);
             return Ok(VcpuEmulation::Interrupted);
         }
@@ -547,7 +651,7 @@ fn handle_kvm_exit(
             }
             VcpuExit::Shutdown => {
                 info!("Received KVM_EXIT_SHUTDOWN signal");
-                Ok(VcpuEmulation::Stopped)
+                Ok(VcpuEmulation::Reset)
             }
             // Documentation specifies that below kvm exits are considered
             // errors.
@@ -573,7 +677,14 @@ fn handle_kvm_exit(
                 )))
             }
             VcpuExit::SystemEvent(event_type, event_flags) => match event_type {
-                KVM_SYSTEM_EVENT_RESET | KVM_SYSTEM_EVENT_SHUTDOWN => {
+                KVM_SYSTEM_EVENT_RESET => {
+                    info!(
+                        "Received KVM_SYSTEM_EVENT: type: {}, event: {:?}",
+                        event_type, event_flags
+                    );
+                    Ok(VcpuEmulation::Reset)
+                }
+                KVM_SYSTEM_EVENT_SHUTDOWN => {
                     info!(
                         "Received KVM_SYSTEM_EVENT: type: {}, event: {:?}",
                         event_type, event_flags
@@ -635,16 +746,20 @@ pub enum VcpuEvent {
     Resume,
     /// Event to save the state of a paused Vcpu.
     SaveState,
+    /// Event to restore a previously saved state into a paused Vcpu.
+    RestoreState(Box<VcpuState>),
     /// Event to dump CPU configuration of a paused Vcpu.
     DumpCpuConfig,
+    /// Event to install a seccomp filter on the vCPU thread, on top of its current filters.
+    InstallSeccompFilter(Arc<BpfProgram>),
 }
 
 /// List of responses that the Vcpu reports.
 pub enum VcpuResponse {
     /// Requested action encountered an error.
     Error(VcpuError),
-    /// Vcpu is stopped.
-    Exited(FcExitCode),
+    /// Vcpu is stopped, with its exit code and whether the guest reset it.
+    Exited(FcExitCode, bool),
     /// Requested action not allowed.
     NotAllowed(String),
     /// Vcpu is paused.
@@ -653,8 +768,12 @@ pub enum VcpuResponse {
     Resumed,
     /// Vcpu state is saved.
     SavedState(Box<VcpuState>),
+    /// Vcpu state is restored.
+    RestoredState,
     /// Vcpu is in the state where CPU config is dumped.
     DumpedCpuConfig(Box<CpuConfiguration>),
+    /// The seccomp filter is installed on the vCPU thread.
+    InstalledSeccompFilter,
 }
 
 impl fmt::Debug for VcpuResponse {
@@ -663,11 +782,15 @@ impl fmt::Debug for VcpuResponse {
         match self {
             Paused => write!(f, "VcpuResponse::Paused"),
             Resumed => write!(f, "VcpuResponse::Resumed"),
-            Exited(code) => write!(f, "VcpuResponse::Exited({:?})", code),
+            Exited(code, guest_reset) => {
+                write!(f, "VcpuResponse::Exited({:?}, {})", code, guest_reset)
+            }
             SavedState(_) => write!(f, "VcpuResponse::SavedState"),
+            RestoredState => write!(f, "VcpuResponse::RestoredState"),
             Error(ref err) => write!(f, "VcpuResponse::Error({:?})", err),
             NotAllowed(ref reason) => write!(f, "VcpuResponse::NotAllowed({})", reason),
             DumpedCpuConfig(_) => write!(f, "VcpuResponse::DumpedCpuConfig"),
+            InstalledSeccompFilter => write!(f, "VcpuResponse::InstalledSeccompFilter"),
         }
     }
 }
@@ -680,6 +803,8 @@ pub struct VcpuHandle {
     // Rust JoinHandles have to be wrapped in Option if you ever plan on 'join()'ing them.
     // We want to be able to join these threads in tests.
     vcpu_thread: Option<thread::JoinHandle<()>>,
+    // ID of the vcpu thread, as seen by the host.
+    tid: libc::pid_t,
 }
 
 /// Error type for [`VcpuHandle::send_event`].
@@ -694,17 +819,25 @@ impl VcpuHandle {
     /// + `event_sender`: [`Sender`] to communicate [`VcpuEvent`] to control the vcpu.
     /// + `response_received`: [`Received`] from which the vcpu's responses can be read.
     /// + `vcpu_thread`: A [`JoinHandle`] for the vcpu thread.
+    /// + `tid`: The ID of the vcpu thread.
     pub fn new(
         event_sender: Sender<VcpuEvent>,
         response_receiver: Receiver<VcpuResponse>,
         vcpu_thread: thread::JoinHandle<()>,
+        tid: libc::pid_t,
     ) -> Self {
         Self {
             event_sender,
             response_receiver,
             vcpu_thread: Some(vcpu_thread),
+            tid,
         }
     }
+
+    /// Returns the ID of the vcpu thread.
+    pub fn tid(&self) -> libc::pid_t {
+        self.tid
+    }
     /// Sends event to vCPU.
     ///
     /// # Errors
@@ -753,6 +886,8 @@ pub enum VcpuEmulation {
     Interrupted,
     /// Stopped.
     Stopped,
+    /// Reset by the guest.
+    Reset,
     /// Pause request
     #[cfg(feature = "gdb")]
     Paused,
@@ -788,7 +923,7 @@ pub(crate) mod tests {
         assert_eq!(res.unwrap(), VcpuEmulation::Stopped);
 
         let res = handle_kvm_exit(&mut vcpu.kvm_vcpu.peripherals, Ok(VcpuExit::Shutdown));
-        assert_eq!(res.unwrap(), VcpuEmulation::Stopped);
+        assert_eq!(res.unwrap(), VcpuEmulation::Reset);
 
         let res = handle_kvm_exit(
             &mut vcpu.kvm_vcpu.peripherals,
@@ -815,7 +950,7 @@ pub(crate) mod tests {
             &mut vcpu.kvm_vcpu.peripherals,
             Ok(VcpuExit::SystemEvent(2, &[])),
         );
-        assert_eq!(res.unwrap(), VcpuEmulation::Stopped);
+        assert_eq!(res.unwrap(), VcpuEmulation::Reset);
 
         let res = handle_kvm_exit(
             &mut vcpu.kvm_vcpu.peripherals,
@@ -899,12 +1034,14 @@ pub(crate) mod tests {
             use crate::VcpuResponse::*;
             // Guard match with no wildcard to make sure we catch new enum variants.
             match self {
-                Paused | Resumed | Exited(_) => (),
+                Paused | Resumed | Exited(..) | RestoredState => (),
                 Error(_) | NotAllowed(_) | SavedState(_) | DumpedCpuConfig(_) => (),
             };
             match (self, other) {
-                (Paused, Paused) | (Resumed, Resumed) => true,
-                (Exited(code), Exited(other_code)) => code == other_code,
+                (Paused, Paused) | (Resumed, Resumed) | (RestoredState, RestoredState) => true,
+                (Exited(code, guest_reset), Exited(other_code, other_guest_reset)) => {
+                    code == other_code && guest_reset == other_guest_reset
+                }
                 (NotAllowed(_), NotAllowed(_))
                 | (SavedState(_), SavedState(_))
                 | (DumpedCpuConfig(_), DumpedCpuConfig(_)) => true,
@@ -926,7 +1063,7 @@ pub(crate) mod tests {
         #[cfg(target_arch = "aarch64")]
         let vcpu = {
             let mut vcpu = Vcpu::new(1, &vm, exit_evt).unwrap();
-            vcpu.kvm_vcpu.init(&[]).unwrap();
+            vcpu.kvm_vcpu.init(&[], None).unwrap();
             vm.setup_irqchip(1).unwrap();
             vcpu
         };
@@ -1202,15 +1339,22 @@ pub(crate) mod tests {
         vcpu_handle
             .send_event(VcpuEvent::SaveState)
             .expect("failed to send event to vcpu");
-        match vcpu_handle
+        let vcpu_state = match vcpu_handle
             .response_receiver()
             .recv_timeout(RECV_TIMEOUT_SEC)
             .expect("did not receive event response from vcpu")
         {
-            VcpuResponse::SavedState(_) => {}
+            VcpuResponse::SavedState(vcpu_state) => vcpu_state,
             _ => panic!("unexpected response"),
         };
 
+        // Queue a RestoreState event with the saved state, expect a response.
+        queue_event_expect_response(
+            &vcpu_handle,
+            VcpuEvent::RestoreState(vcpu_state),
+            VcpuResponse::RestoredState,
+        );
+
         vcpu_handle.send_event(VcpuEvent::Finish).unwrap();
     }
 
@@ -5,8 +5,12 @@
 // Use of this source code is governed by a BSD-style license that can be
 // found in the THIRD-PARTY file.
 
+use std::collections::BTreeMap;
 #[cfg(target_arch = "x86_64")]
 use std::fmt;
+use std::fs::File;
+use std::os::fd::{AsRawFd, FromRawFd};
+use std::sync::Arc;
 
 #[cfg(target_arch = "x86_64")]
 use kvm_bindings::{
@@ -14,7 +18,10 @@ use kvm_bindings::{
     KVM_CLOCK_TSC_STABLE, KVM_IRQCHIP_IOAPIC, KVM_IRQCHIP_PIC_MASTER, KVM_IRQCHIP_PIC_SLAVE,
     KVM_MAX_CPUID_ENTRIES, KVM_PIT_SPEAKER_DUMMY,
 };
-use kvm_bindings::{kvm_userspace_memory_region, KVM_API_VERSION, KVM_MEM_LOG_DIRTY_PAGES};
+use kvm_bindings::{
+    kvm_create_guest_memfd, kvm_userspace_memory_region, kvm_userspace_memory_region2,
+    KVM_API_VERSION, KVM_MEM_GUEST_MEMFD, KVM_MEM_LOG_DIRTY_PAGES, KVM_MEM_READONLY,
+};
 use kvm_ioctls::{Kvm, VmFd};
 use serde::{Deserialize, Serialize};
 
@@ -25,7 +32,17 @@ use crate::arch::aarch64::gic::GicState;
 use crate::cpu_config::templates::KvmCapability;
 #[cfg(target_arch = "x86_64")]
 use crate::utils::u64_to_usize;
-use crate::vstate::memory::{Address, GuestMemory, GuestMemoryMmap, GuestMemoryRegion};
+use crate::vstate::memory::{
+    Address, GuestAddress, GuestMemory, GuestMemoryMmap, GuestMemoryRegion, MemorySlots,
+    MemorySlotsUsage, MmapRegion,
+};
+
+// Flags of `KVM_CREATE_GUEST_MEMFD`, which are not in kvm-bindings yet: the guest_memfd can be
+// mapped by the VMM, and its memory is initially shared with the VMM.
+const GUEST_MEMFD_FLAG_MMAP: u64 = 1 << 0;
+const GUEST_MEMFD_FLAG_INIT_SHARED: u64 = 1 << 1;
+// Capability reporting the flags supported by `KVM_CREATE_GUEST_MEMFD`.
+const KVM_CAP_GUEST_MEMFD_FLAGS: u64 = 244;
 
 /// Errors associated with the wrappers over KVM ioctls.
 /// Needs `rustfmt::skip` to make multiline comments work
@@ -44,11 +61,25 @@ pub enum VmError {
     GetMsrsToSave(#[from] crate::arch::x86_64::msr::MsrError),
     /// The number of configured slots is bigger than the maximum reported by KVM
     NotEnoughMemorySlots,
+    /// Memory slot {0} is not used by a read-only memory region
+    UnknownMemorySlot(u32),
+    /// The host kernel does not support guest memory backed by a mappable guest_memfd
+    GuestMemfdNotSupported,
+    /// Cannot create the guest_memfd: {0}
+    CreateGuestMemfd(kvm_ioctls::Error),
+    /// Snapshots are not supported for VMs with guest memory backed by a guest_memfd
+    GuestMemfdSnapshotNotSupported,
     /// Cannot set the memory regions: {0}
     SetUserMemoryRegion(kvm_ioctls::Error),
     #[cfg(target_arch = "aarch64")]
     /// Error creating the global interrupt controller: {0}
     VmCreateGIC(crate::arch::aarch64::gic::GicError),
+    #[cfg(target_arch = "aarch64")]
+    /// Failed to enable the Memory Tagging Extension: {0}
+    EnableMte(kvm_ioctls::Error),
+    #[cfg(target_arch = "aarch64")]
+    /// Snapshots are not supported for VMs with the Memory Tagging Extension enabled
+    MteSnapshotNotSupported,
     /// Cannot open the VM file descriptor: {0}
     VmFd(kvm_ioctls::Error),
     #[cfg(target_arch = "x86_64")]
@@ -111,8 +142,13 @@ pub enum RestoreStateError {
 /// A wrapper around creating and using a VM.
 #[derive(Debug)]
 pub struct Vm {
-    fd: VmFd,
-    max_memslots: usize,
+    fd: Arc<VmFd>,
+    memory_slots: MemorySlots,
+    // Read-only memory regions mapped in the guest outside of guest memory, by memory slot. They
+    // need to stay mapped as long as they are registered with KVM.
+    readonly_regions: BTreeMap<u32, MmapRegion>,
+    // Whether guest memory is backed by a guest_memfd created with `create_guest_memfd`.
+    guest_memfd: bool,
 
     /// Additional capabilities that were specified in cpu template.
     pub kvm_cap_modifiers: Vec<KvmCapability>,
@@ -146,15 +182,27 @@ impl Vm {
         // Check that all desired capabilities are supported.
         Self::check_capabilities(&kvm, &total_caps).map_err(VmError::Capabilities)?;
 
-        let max_memslots = kvm.get_nr_memslots();
+        let memory_slots =
+            MemorySlots::new(u32::try_from(kvm.get_nr_memslots()).unwrap_or(u32::MAX));
         // Create fd for interacting with kvm-vm specific functions.
         let vm_fd = kvm.create_vm().map_err(VmError::VmFd)?;
 
         #[cfg(target_arch = "aarch64")]
         {
+            // MTE has to be enabled on the VM before any vcpu is created.
+            if total_caps.contains(&kvm_bindings::KVM_CAP_ARM_MTE) {
+                let cap = kvm_bindings::kvm_enable_cap {
+                    cap: kvm_bindings::KVM_CAP_ARM_MTE,
+                    ..Default::default()
+                };
+                vm_fd.enable_cap(&cap).map_err(VmError::EnableMte)?;
+            }
+
             Ok(Vm {
-                fd: vm_fd,
-                max_memslots,
+                fd: Arc::new(vm_fd),
+                memory_slots,
+                readonly_regions: BTreeMap::new(),
+                guest_memfd: false,
                 kvm_cap_modifiers,
                 irqchip_handle: None,
             })
@@ -168,8 +216,10 @@ impl Vm {
             let msrs_to_save = crate::arch::x86_64::msr::get_msrs_to_save(&kvm)?;
 
             Ok(Vm {
-                fd: vm_fd,
-                max_memslots,
+                fd: Arc::new(vm_fd),
+                memory_slots,
+                readonly_regions: BTreeMap::new(),
+                guest_memfd: false,
                 kvm_cap_modifiers,
                 supported_cpuid,
                 msrs_to_save,
@@ -196,6 +246,18 @@ impl Vm {
         total_caps
     }
 
+    /// Returns the capabilities needed by a VM with `kvm_cap_modifiers` that KVM does not support
+    /// on this host.
+    pub fn unsupported_capabilities(
+        kvm_cap_modifiers: &[KvmCapability],
+    ) -> Result<Vec<u32>, VmError> {
+        let kvm = Kvm::new().map_err(VmError::Kvm)?;
+        Ok(Self::combine_capabilities(kvm_cap_modifiers)
+            .into_iter()
+            .filter(|cap| kvm.check_extension_raw(u64::from(*cap)) == 0)
+            .collect())
+    }
+
     fn check_capabilities(kvm: &Kvm, capabilities: &[u32]) -> Result<(), u32> {
         for cap in capabilities {
             // If capability is not supported kernel will return 0.
@@ -208,13 +270,14 @@ impl Vm {
 
     /// Initializes the guest memory.
     pub fn memory_init(
-        &self,
+        &mut self,
         guest_mem: &GuestMemoryMmap,
         track_dirty_pages: bool,
     ) -> Result<(), VmError> {
-        if guest_mem.num_regions() > self.max_memslots {
-            return Err(VmError::NotEnoughMemorySlots);
-        }
+        u32::try_from(guest_mem.num_regions())
+            .ok()
+            .and_then(|num_regions| self.memory_slots.reserve_guest_memory(num_regions))
+            .ok_or(VmError::NotEnoughMemorySlots)?;
         self.set_kvm_memory_regions(guest_mem, track_dirty_pages)?;
         #[cfg(target_arch = "x86_64")]
         self.fd
@@ -233,30 +296,150 @@ impl Vm {
         if track_dirty_pages {
             flags |= KVM_MEM_LOG_DIRTY_PAGES;
         }
-        guest_mem
-            .iter()
-            .zip(0u32..)
-            .try_for_each(|(region, slot)| {
-                let memory_region = kvm_userspace_memory_region {
-                    slot,
-                    guest_phys_addr: region.start_addr().raw_value(),
-                    memory_size: region.len(),
-                    // It's safe to unwrap because the guest address is valid.
-                    userspace_addr: guest_mem.get_host_address(region.start_addr()).unwrap() as u64,
-                    flags,
-                };
+        for (region, slot) in guest_mem.iter().zip(0u32..) {
+            let guest_phys_addr = region.start_addr().raw_value();
+            // It's safe to unwrap because the guest address is valid.
+            let userspace_addr = guest_mem.get_host_address(region.start_addr()).unwrap() as u64;
+
+            match region.file_offset().filter(|_| self.guest_memfd) {
+                // KVM maps the guest memory from the guest_memfd, the userspace mapping is only
+                // used by the VMM.
+                Some(file_offset) => {
+                    let memory_region = kvm_userspace_memory_region2 {
+                        slot,
+                        flags: flags | KVM_MEM_GUEST_MEMFD,
+                        guest_phys_addr,
+                        memory_size: region.len(),
+                        userspace_addr,
+                        guest_memfd_offset: file_offset.start(),
+                        // File descriptors are never negative.
+                        guest_memfd: u32::try_from(file_offset.file().as_raw_fd()).unwrap(),
+                        ..Default::default()
+                    };
+
+                    // SAFETY: Safe because the fd is a valid KVM file descriptor, and the
+                    // guest_memfd is kept open by the guest memory region.
+                    unsafe { self.fd.set_user_memory_region2(memory_region) }
+                }
+                None => {
+                    let memory_region = kvm_userspace_memory_region {
+                        slot,
+                        guest_phys_addr,
+                        memory_size: region.len(),
+                        userspace_addr,
+                        flags,
+                    };
+
+                    // SAFETY: Safe because the fd is a valid KVM file descriptor.
+                    unsafe { self.fd.set_user_memory_region(memory_region) }
+                }
+            }
+            .map_err(VmError::SetUserMemoryRegion)?;
+        }
+        Ok(())
+    }
 
-                // SAFETY: Safe because the fd is a valid KVM file descriptor.
-                unsafe { self.fd.set_user_memory_region(memory_region) }
-            })
+    /// Creates a guest_memfd of `size` bytes, to back guest memory. The guest_memfd can be mapped
+    /// by the VMM, and its memory is shared with the VMM. It has to be created before guest memory
+    /// is registered with [`Vm::memory_init`].
+    pub fn create_guest_memfd(&mut self, size: u64) -> Result<File, VmError> {
+        let flags = GUEST_MEMFD_FLAG_MMAP | GUEST_MEMFD_FLAG_INIT_SHARED;
+        // The capability reports the supported flags, or 0 if guest_memfd is not supported.
+        let supported =
+            u64::try_from(self.fd.check_extension_raw(KVM_CAP_GUEST_MEMFD_FLAGS)).unwrap_or(0);
+        if supported & flags != flags {
+            return Err(VmError::GuestMemfdNotSupported);
+        }
+
+        let guest_memfd = kvm_create_guest_memfd {
+            size,
+            flags,
+            ..Default::default()
+        };
+        let fd = self
+            .fd
+            .create_guest_memfd(guest_memfd)
+            .map_err(VmError::CreateGuestMemfd)?;
+        self.guest_memfd = true;
+        // SAFETY: Safe because the fd was just created by KVM, and is owned by the returned file.
+        Ok(unsafe { File::from_raw_fd(fd) })
+    }
+
+    /// Returns `true` if guest memory is backed by a guest_memfd.
+    pub fn guest_memfd(&self) -> bool {
+        self.guest_memfd
+    }
+
+    /// Maps `region` read-only in the guest physical address space at `guest_addr`, using the
+    /// lowest free memory slot. The region is kept mapped until it is removed with
+    /// [`Vm::remove_readonly_memory_region`]. Returns the memory slot of the region.
+    pub fn add_readonly_memory_region(
+        &mut self,
+        guest_addr: GuestAddress,
+        region: MmapRegion,
+    ) -> Result<u32, VmError> {
+        let slot = self
+            .memory_slots
+            .allocate()
+            .ok_or(VmError::NotEnoughMemorySlots)?;
+        let memory_region = kvm_userspace_memory_region {
+            slot,
+            guest_phys_addr: guest_addr.raw_value(),
+            memory_size: region.size() as u64,
+            userspace_addr: region.as_ptr() as u64,
+            flags: KVM_MEM_READONLY,
+        };
+
+        // SAFETY: Safe because the fd is a valid KVM file descriptor, and the region stays mapped
+        // as long as it is registered with KVM.
+        if let Err(err) = unsafe { self.fd.set_user_memory_region(memory_region) } {
+            self.memory_slots.release(slot);
+            return Err(VmError::SetUserMemoryRegion(err));
+        }
+        self.readonly_regions.insert(slot, region);
+        Ok(slot)
+    }
+
+    /// Removes the read-only memory region using memory `slot` from the guest physical address
+    /// space, and frees the slot so that it is reused by the next region added.
+    pub fn remove_readonly_memory_region(&mut self, slot: u32) -> Result<(), VmError> {
+        let region = self
+            .readonly_regions
+            .get(&slot)
+            .ok_or(VmError::UnknownMemorySlot(slot))?;
+        // A memory region of size 0 deletes the slot.
+        let memory_region = kvm_userspace_memory_region {
+            slot,
+            guest_phys_addr: 0,
+            memory_size: 0,
+            userspace_addr: region.as_ptr() as u64,
+            flags: KVM_MEM_READONLY,
+        };
+
+        // SAFETY: Safe because the fd is a valid KVM file descriptor.
+        unsafe { self.fd.set_user_memory_region(memory_region) }
             .map_err(VmError::SetUserMemoryRegion)?;
+        // The region is only unmapped once KVM does not use it anymore.
+        self.readonly_regions.remove(&slot);
+        self.memory_slots.release(slot);
         Ok(())
     }
 
+    /// Returns the current usage of the KVM memory slots of the VM.
+    pub fn memory_slots(&self) -> MemorySlotsUsage {
+        self.memory_slots.usage()
+    }
+
     /// Gets a reference to the kvm file descriptor owned by this VM.
     pub fn fd(&self) -> &VmFd {
         &self.fd
     }
+
+    /// Gets a shared reference to the kvm file descriptor, for the threads which use it
+    /// without access to the VM.
+    pub fn shared_fd(&self) -> Arc<VmFd> {
+        self.fd.clone()
+    }
 }
 
 #[cfg(target_arch = "aarch64")]
@@ -285,8 +468,22 @@ impl Vm {
         self.irqchip_handle.as_ref().expect("IRQ chip not set")
     }
 
+    /// Returns `true` if the Memory Tagging Extension is enabled for this VM.
+    pub fn mte_enabled(&self) -> bool {
+        Self::combine_capabilities(&self.kvm_cap_modifiers).contains(&kvm_bindings::KVM_CAP_ARM_MTE)
+    }
+
     /// Saves and returns the Kvm Vm state.
     pub fn save_state(&self, mpidrs: &[u64]) -> Result<VmState, VmError> {
+        // MTE allocation tags live outside of the guest memory and are not
+        // saved, so restoring such a snapshot would silently lose them.
+        if self.mte_enabled() {
+            return Err(VmError::MteSnapshotNotSupported);
+        }
+        // Restoring the snapshot would silently back guest memory by anonymous memory instead.
+        if self.guest_memfd {
+            return Err(VmError::GuestMemfdSnapshotNotSupported);
+        }
         Ok(VmState {
             gic: self
                 .get_irqchip()
@@ -395,6 +592,11 @@ impl Vm {
 
     /// Saves and returns the Kvm Vm state.
     pub fn save_state(&self) -> Result<VmState, VmError> {
+        // Restoring the snapshot would silently back guest memory by anonymous memory instead.
+        if self.guest_memfd {
+            return Err(VmError::GuestMemfdSnapshotNotSupported);
+        }
+
         let pitstate = self.fd.get_pit2().map_err(VmError::VmGetPit2)?;
 
         let mut clock = self.fd.get_clock().map_err(VmError::VmGetClock)?;
@@ -471,13 +673,13 @@ pub(crate) mod tests {
     #[cfg(target_arch = "x86_64")]
     use crate::snapshot::Snapshot;
     use crate::test_utils::single_region_mem;
-    use crate::vstate::memory::GuestMemoryMmap;
+    use crate::vstate::memory::{Bytes, GuestMemoryExtension, GuestMemoryMmap};
 
     // Auxiliary function being used throughout the tests.
     pub(crate) fn setup_vm(mem_size: usize) -> (Vm, GuestMemoryMmap) {
         let gm = single_region_mem(mem_size);
 
-        let vm = Vm::new(vec![]).expect("Cannot create new vm");
+        let mut vm = Vm::new(vec![]).expect("Cannot create new vm");
         vm.memory_init(&gm, false).unwrap();
 
         (vm, gm)
@@ -507,13 +709,53 @@ pub(crate) mod tests {
             .any(|c| *c == kvm_bindings::KVM_CAP_IOEVENTFD));
     }
 
+    #[test]
+    fn test_unsupported_capabilities() {
+        assert!(Vm::unsupported_capabilities(&[]).unwrap().is_empty());
+        // No KVM capability has this number.
+        let unknown_cap = 0xffff;
+        assert_eq!(
+            Vm::unsupported_capabilities(&[KvmCapability::Add(unknown_cap)]).unwrap(),
+            vec![unknown_cap]
+        );
+    }
+
     #[test]
     fn test_vm_memory_init() {
-        let vm = Vm::new(vec![]).expect("Cannot create new vm");
+        let mut vm = Vm::new(vec![]).expect("Cannot create new vm");
 
         // Create valid memory region and test that the initialization is successful.
         let gm = single_region_mem(0x1000);
         vm.memory_init(&gm, true).unwrap();
+        assert_eq!(vm.memory_slots().used_slots, 1);
+        assert_eq!(vm.memory_slots().guest_memory_slots, 1);
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    #[test]
+    fn test_vm_mte() {
+        use kvm_bindings::KVM_CAP_ARM_MTE;
+
+        let vm = Vm::new(vec![]).unwrap();
+        assert!(!vm.mte_enabled());
+
+        let caps = vec![KvmCapability::Add(KVM_CAP_ARM_MTE)];
+        let kvm = Kvm::new().unwrap();
+        if kvm.check_extension_raw(u64::from(KVM_CAP_ARM_MTE)) == 0 {
+            assert_eq!(
+                Vm::new(caps).unwrap_err(),
+                VmError::Capabilities(KVM_CAP_ARM_MTE)
+            );
+            return;
+        }
+
+        let vm = Vm::new(caps).unwrap();
+        assert!(vm.mte_enabled());
+        // MTE tags are not part of the snapshot.
+        assert_eq!(
+            vm.save_state(&[]).unwrap_err(),
+            VmError::MteSnapshotNotSupported
+        );
     }
 
     #[cfg(target_arch = "x86_64")]
@@ -602,4 +844,74 @@ pub(crate) mod tests {
             "Cannot set the memory regions: Invalid argument (os error 22)"
         );
     }
+
+    #[test]
+    fn test_guest_memfd() {
+        let mut vm = Vm::new(vec![]).expect("Cannot create new vm");
+        let guest_memfd = match vm.create_guest_memfd(0x10_0000) {
+            Ok(guest_memfd) => guest_memfd,
+            // Mappable guest_memfds are only supported by recent host kernels.
+            Err(VmError::GuestMemfdNotSupported) => return,
+            Err(err) => panic!("{err}"),
+        };
+        assert!(vm.guest_memfd());
+
+        let regions = [(GuestAddress(0), 0x10_0000)];
+        let gm = GuestMemoryMmap::guest_memfd_backed(guest_memfd, &regions).unwrap();
+        vm.memory_init(&gm, false).unwrap();
+        gm.write_obj(0xdead_beef_u32, GuestAddress(0x1000)).unwrap();
+        assert_eq!(
+            gm.read_obj::<u32>(GuestAddress(0x1000)).unwrap(),
+            0xdead_beef
+        );
+
+        #[cfg(target_arch = "x86_64")]
+        let res = vm.save_state();
+        #[cfg(target_arch = "aarch64")]
+        let res = vm.save_state(&[]);
+        assert_eq!(res.unwrap_err(), VmError::GuestMemfdSnapshotNotSupported);
+    }
+
+    #[test]
+    fn test_add_readonly_memory_region() {
+        let mut vm = Vm::new(vec![]).expect("Cannot create new vm");
+        vm.memory_slots = MemorySlots::new(3);
+        let gm = single_region_mem(0x1000);
+        vm.memory_init(&gm, false).unwrap();
+
+        let region = MmapRegion::new(0x1000).unwrap();
+        let slot = vm
+            .add_readonly_memory_region(GuestAddress(0x10_0000), region)
+            .unwrap();
+        assert_eq!(slot, 1);
+        assert_eq!(vm.readonly_regions.len(), 1);
+
+        let region = MmapRegion::new(0x1000).unwrap();
+        assert_eq!(
+            vm.add_readonly_memory_region(GuestAddress(0x20_0000), region)
+                .unwrap(),
+            2
+        );
+        let region = MmapRegion::new(0x1000).unwrap();
+        assert!(matches!(
+            vm.add_readonly_memory_region(GuestAddress(0x30_0000), region),
+            Err(VmError::NotEnoughMemorySlots)
+        ));
+        assert_eq!(vm.memory_slots().used_slots, 3);
+
+        // The slot of a removed region is reused.
+        vm.remove_readonly_memory_region(1).unwrap();
+        assert_eq!(
+            vm.remove_readonly_memory_region(1),
+            Err(VmError::UnknownMemorySlot(1))
+        );
+        assert_eq!(vm.memory_slots().used_slots, 2);
+        let region = MmapRegion::new(0x1000).unwrap();
+        assert_eq!(
+            vm.add_readonly_memory_region(GuestAddress(0x30_0000), region)
+                .unwrap(),
+            1
+        );
+        assert_eq!(vm.memory_slots().highest_slot, Some(2));
+    }
 }
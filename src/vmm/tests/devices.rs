@@ -29,6 +29,7 @@ fn create_serial(
             SerialOut::Stdout(std::io::stdout()),
         ),
         input: Some(Box::new(serial_in)),
+        listener: None,
     }))
 }
 
@@ -1,7 +1,8 @@
 // Copyright 2020 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
-use std::io::{Seek, SeekFrom};
+use std::io::{Seek, SeekFrom, Write};
+use std::os::fd::AsRawFd;
 use std::thread;
 use std::time::Duration;
 
@@ -11,6 +12,7 @@ use vmm::persist::{snapshot_state_sanity_check, MicrovmState, MicrovmStateError,
 use vmm::resources::VmResources;
 use vmm::rpc_interface::{
     LoadSnapshotError, PrebootApiController, RuntimeApiController, VmmAction, VmmActionError,
+    VmmData,
 };
 use vmm::seccomp_filters::get_empty_filters;
 use vmm::snapshot::Snapshot;
@@ -25,9 +27,11 @@ use vmm::vmm_config::instance_info::{InstanceInfo, VmState};
 use vmm::vmm_config::machine_config::{MachineConfig, MachineConfigUpdate, VmConfig};
 use vmm::vmm_config::net::NetworkInterfaceConfig;
 use vmm::vmm_config::snapshot::{
-    CreateSnapshotParams, LoadSnapshotParams, MemBackendConfig, MemBackendType, SnapshotType,
+    CreateSnapshotParams, LoadSnapshotParams, MemBackendConfig, MemBackendType,
+    SnapshotEncryptionConfig, SnapshotType,
 };
 use vmm::vmm_config::vsock::VsockDeviceConfig;
+use vmm::vstate::memory::GuestMemory;
 use vmm::{DumpCpuConfigError, EventManager, FcExitCode};
 use vmm_sys_util::tempfile::TempFile;
 
@@ -102,6 +106,25 @@ fn test_pause_resume_microvm() {
     vmm.lock().unwrap().stop(FcExitCode::Ok);
 }
 
+#[test]
+fn test_get_memory_slots() {
+    let (vmm, _) = default_vmm(None);
+    let mut api_controller = RuntimeApiController::new(VmResources::default(), vmm.clone());
+
+    let VmmData::MemorySlots(usage) = api_controller
+        .handle_request(VmmAction::GetMemorySlots)
+        .unwrap()
+    else {
+        panic!("unexpected response");
+    };
+    let num_regions = u32::try_from(vmm.lock().unwrap().guest_memory().num_regions()).unwrap();
+    assert_eq!(usage.guest_memory_slots, num_regions);
+    assert_eq!(usage.used_slots, num_regions);
+    assert!(usage.max_slots >= num_regions);
+
+    vmm.lock().unwrap().stop(FcExitCode::Ok);
+}
+
 #[test]
 fn test_dirty_bitmap_error() {
     // Error case: dirty tracking disabled.
@@ -213,6 +236,9 @@ fn verify_create_snapshot(is_diff: bool) -> (TempFile, TempFile) {
         snapshot_type,
         snapshot_path: snapshot_file.as_path().to_path_buf(),
         mem_file_path: memory_file.as_path().to_path_buf(),
+        labels: Default::default(),
+        encryption: None,
+        background: false,
     };
 
     controller
@@ -240,7 +266,11 @@ fn verify_create_snapshot(is_diff: bool) -> (TempFile, TempFile) {
     (snapshot_file, memory_file)
 }
 
-fn verify_load_snapshot(snapshot_file: TempFile, memory_file: TempFile) {
+fn verify_load_snapshot(
+    snapshot_file: TempFile,
+    memory_file: TempFile,
+    encryption: Option<SnapshotEncryptionConfig>,
+) {
     let mut event_manager = EventManager::new().unwrap();
     let empty_seccomp_filters = get_empty_filters();
     let mut vm_resources = VmResources::default();
@@ -261,6 +291,8 @@ fn verify_load_snapshot(snapshot_file: TempFile, memory_file: TempFile) {
             },
             enable_diff_snapshots: false,
             resume_vm: true,
+            encryption,
+            prefault: false,
         }))
         .unwrap();
 
@@ -278,7 +310,7 @@ fn test_create_and_load_snapshot() {
     // that a microVM can be built with no errors from given snapshot.
     // It does _not_ verify that the guest is actually restored properly. We're using
     // python integration tests for that.
-    verify_load_snapshot(snapshot_file, memory_file);
+    verify_load_snapshot(snapshot_file, memory_file, None);
 
     // Create full snapshot.
     let (snapshot_file, memory_file) = verify_create_snapshot(false);
@@ -286,7 +318,50 @@ fn test_create_and_load_snapshot() {
     // that a microVM can be built with no errors from given snapshot.
     // It does _not_ verify that the guest is actually restored properly. We're using
     // python integration tests for that.
-    verify_load_snapshot(snapshot_file, memory_file);
+    verify_load_snapshot(snapshot_file, memory_file, None);
+}
+
+#[test]
+fn test_create_and_load_encrypted_snapshot() {
+    let key_file = TempFile::new().unwrap();
+    key_file.as_file().write_all(&[0x5a; 32]).unwrap();
+    let encryption = Some(SnapshotEncryptionConfig {
+        key_fd: key_file.as_file().as_raw_fd(),
+    });
+    let snapshot_file = TempFile::new().unwrap();
+    let memory_file = TempFile::new().unwrap();
+
+    let (vmm, _) = create_vmm(Some(NOISY_KERNEL_IMAGE), false, true);
+    let mut controller = RuntimeApiController::new(VmResources::default(), vmm.clone());
+    thread::sleep(Duration::from_millis(200));
+    controller.handle_request(VmmAction::Pause).unwrap();
+
+    let snapshot_params = |snapshot_type| CreateSnapshotParams {
+        snapshot_type,
+        snapshot_path: snapshot_file.as_path().to_path_buf(),
+        mem_file_path: memory_file.as_path().to_path_buf(),
+        labels: Default::default(),
+        encryption,
+        background: false,
+    };
+    // Diff snapshots cannot be encrypted.
+    controller
+        .handle_request(VmmAction::CreateSnapshot(snapshot_params(
+            SnapshotType::Diff,
+        )))
+        .unwrap_err();
+    controller
+        .handle_request(VmmAction::CreateSnapshot(snapshot_params(
+            SnapshotType::Full,
+        )))
+        .unwrap();
+    vmm.lock().unwrap().stop(FcExitCode::Ok);
+
+    // The microVM state cannot be read without the key.
+    let snapshot_len = snapshot_file.as_file().metadata().unwrap().len() as usize;
+    Snapshot::load::<_, MicrovmState>(&mut snapshot_file.as_file(), snapshot_len).unwrap_err();
+
+    verify_load_snapshot(snapshot_file, memory_file, encryption);
 }
 
 #[test]
@@ -344,6 +419,8 @@ fn verify_load_snap_disallowed_after_boot_resources(res: VmmAction, res_name: &s
         },
         enable_diff_snapshots: false,
         resume_vm: false,
+        encryption: None,
+        prefault: false,
     });
     let err = preboot_api_controller.handle_preboot_request(req);
     assert!(
@@ -377,6 +454,11 @@ fn test_preboot_load_snap_disallowed_after_boot_resources() {
         path_on_host: Some(tmp_file),
         rate_limiter: None,
         file_engine_type: None,
+        remote: None,
+        direct_io: None,
+        backend: None,
+        latency_injection: None,
+        shared_rate_limiter: None,
 
         socket: None,
     };
@@ -390,6 +472,16 @@ fn test_preboot_load_snap_disallowed_after_boot_resources() {
         guest_mac: None,
         rx_rate_limiter: None,
         tx_rate_limiter: None,
+        flow_accounting: None,
+        pause_responder: None,
+        rx_coalescing: None,
+        mtu: None,
+        mmds_only: None,
+        num_queues: None,
+        offloads: None,
+        enforce_mac: None,
+        enforce_arp: None,
+        shared_rate_limiter: None,
     });
     verify_load_snap_disallowed_after_boot_resources(req, "InsertNetworkDevice");
 
@@ -400,6 +492,10 @@ fn test_preboot_load_snap_disallowed_after_boot_resources() {
         vsock_id: Some(String::new()),
         guest_cid: 0,
         uds_path: String::new(),
+        port_rate_limiters: Vec::new(),
+        tcp_forwards: Vec::new(),
+        seqpacket: false,
+        dgram: false,
     });
     verify_load_snap_disallowed_after_boot_resources(req, "SetVsockDevice");
 
@@ -73,6 +73,12 @@ def validate_fc_metrics(metrics):
         "max_us",
         "sum_us",
     ]
+    device_error_metrics = [
+        "guest_errors",
+        "backend_errors",
+        "vmm_errors",
+    ]
+
     block_metrics = [
         "activate_fails",
         "cfg_fails",
@@ -92,6 +98,10 @@ def validate_fc_metrics(metrics):
         "rate_limiter_throttled_events",
         "io_engine_throttled_events",
         "remaining_reqs_count",
+        "remote_fetch_bytes",
+        "remote_fetch_fails",
+        "injected_errors",
+        "injected_delays",
         {"read_agg": latency_agg_metrics_fields},
         {"write_agg": latency_agg_metrics_fields},
     ]
@@ -123,7 +133,17 @@ def validate_fc_metrics(metrics):
         "tx_rate_limiter_event_count",
         "tx_rate_limiter_throttled",
         "tx_spoofed_mac_count",
+        "tx_spoofed_dropped_frames",
         "tx_remaining_reqs_count",
+        "paused_rx_dropped_frames",
+        "pause_responder_acks",
+        "rx_coalesced_segments",
+        "tx_dropped_frames",
+        "ctrl_queue_event_count",
+        "ctrl_fails",
+        "capture_frames",
+        "capture_dropped_frames",
+        "capture_fails",
         {"tap_write_agg": latency_agg_metrics_fields},
     ]
     firecracker_metrics = {
@@ -133,6 +153,18 @@ def validate_fc_metrics(metrics):
             "process_startup_time_cpu_us",
             "sync_response_fails",
             "sync_vmm_send_timeout_count",
+            "socket_rebinds",
+            "socket_rebind_fails",
+            "vmm_busy_rejections",
+            "idempotent_replays",
+            "guest_rejections",
+        ],
+        "boot_timings": [
+            "config_done_us",
+            "kernel_loaded_us",
+            "devices_created_us",
+            "first_vcpu_run_us",
+            "guest_boot_complete_us",
         ],
         "balloon": [
             "activate_fails",
@@ -141,12 +173,34 @@ def validate_fc_metrics(metrics):
             "stats_update_fails",
             "deflate_count",
             "event_fails",
+            "stats_push_fails",
+            "auto_target_updates",
+            "target_mib",
+            "actual_mib",
+            "free_memory",
+            "available_memory",
+            "total_memory",
+            "disk_caches",
+            "major_faults",
+            "minor_faults",
+            "swap_in",
+            "swap_out",
         ],
         "block": block_metrics,
         "deprecated_api": [
             "deprecated_http_api_calls",
             "deprecated_cmd_line_api_calls",
         ],
+        "device_errors": device_error_metrics,
+        "device_exits": [
+            "i8042",
+            "rtc",
+            "boot_timer",
+            "pvpanic",
+            "tpm",
+            "serial",
+            "virtio_mmio",
+        ],
         "get_api_requests": [
             "instance_info_count",
             "machine_cfg_count",
@@ -191,6 +245,12 @@ def validate_fc_metrics(metrics):
             "tx_frames",
             "connections_created",
             "connections_destroyed",
+            "guest_data_writes",
+            "guest_data_write_fails",
+            "not_modified",
+            "acl_denied",
+            "backend_requests",
+            "backend_fails",
         ],
         "net": net_metrics,
         "patch_api_requests": [
@@ -235,6 +295,8 @@ def validate_fc_metrics(metrics):
             "exit_mmio_write",
             "failures",
             "kvmclock_ctrl_fails",
+            "throttled",
+            "throttled_us",
             {"exit_io_in_agg": latency_agg_metrics_fields},
             {"exit_io_out_agg": latency_agg_metrics_fields},
             {"exit_mmio_read_agg": latency_agg_metrics_fields},
@@ -243,6 +305,22 @@ def validate_fc_metrics(metrics):
         "vmm": [
             "device_events",
             "panic_count",
+            "api_actions",
+            {"api_action_handling_agg": latency_agg_metrics_fields},
+            {"api_action_queue_wait_agg": latency_agg_metrics_fields},
+            "event_loop_samples",
+            {"event_loop_dispatch_agg": latency_agg_metrics_fields},
+            "chaos_cycles",
+            "chaos_cycle_fails",
+            "hook_fails",
+            "events_dropped",
+            "event_clients_dropped",
+            "forced_shutdowns",
+            "guest_panics",
+            "panic_snapshot_fails",
+            "coredump_snapshots",
+            "coredump_snapshot_fails",
+            "deferred_device_fails",
         ],
         "uart": [
             "error_count",
@@ -278,10 +356,13 @@ def validate_fc_metrics(metrics):
             "conns_added",
             "conns_killed",
             "conns_removed",
+            "rx_rst_count",
+            "tx_rst_count",
             "killq_resync",
             "tx_flush_fails",
             "tx_write_fails",
             "rx_read_fails",
+            "dgram_drops",
         ],
         "entropy": [
             "activate_fails",
@@ -323,10 +404,29 @@ def validate_fc_metrics(metrics):
                 "config_change_time_us",
             ]
             vhost_user_devices.append(metrics_name)
+        if metrics_name.startswith("device_errors_"):
+            firecracker_metrics[metrics_name] = device_error_metrics
         if metrics_name.startswith("block_"):
             firecracker_metrics[metrics_name] = block_metrics
         if metrics_name.startswith("net_"):
             firecracker_metrics[metrics_name] = net_metrics
+        if metrics_name.startswith("rate_limiter_"):
+            firecracker_metrics[metrics_name] = [
+                "bandwidth_budget",
+                "ops_budget",
+                "throttled_events",
+            ]
+        if metrics_name.startswith("vsock_port_"):
+            firecracker_metrics[metrics_name] = [
+                "conns_open",
+                "conns_added",
+                "rx_bytes_count",
+                "tx_bytes_count",
+                "rx_rst_count",
+                "tx_rst_count",
+                "rx_rate_limiter_throttled",
+                "tx_rate_limiter_throttled",
+            ]
 
     firecracker_metrics_schema = create_metrics_schema_objects(firecracker_metrics)
 
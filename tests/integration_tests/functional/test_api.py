@@ -1096,6 +1096,9 @@ def test_get_full_config_after_restoring_snapshot(microvm_factory, uvm_nano):
         "smt": True,
         "track_dirty_pages": False,
         "huge_pages": "None",
+        "pmu": False,
+        "nested_virt": False,
+        "memory_backend": "anonymous",
     }
 
     if cpu_vendor == utils_cpuid.CpuVendor.ARM:
@@ -1173,7 +1176,12 @@ def test_get_full_config_after_restoring_snapshot(microvm_factory, uvm_nano):
     expected_cfg["boot-source"] = {
         "kernel_image_path": uvm_nano.get_jailed_resource(uvm_nano.kernel_file),
         "initrd_path": None,
+        "kernel_image_fd": None,
+        "initrd_fd": None,
         "boot_args": None,
+        "firmware_path": None,
+        "kernel_digest": None,
+        "initrd_digest": None,
     }
 
     # no ipv4 specified during PUT /mmds/config so we expect the default
@@ -1186,6 +1194,25 @@ def test_get_full_config_after_restoring_snapshot(microvm_factory, uvm_nano):
     # We should expect a null entropy device
     expected_cfg["entropy"] = None
 
+    # The serial console is attached to stdio by default
+    expected_cfg["serial"] = {"mode": "stdio"}
+
+    # No shared memory segment was configured
+    expected_cfg["shared-memory"] = []
+
+    # No lifecycle hook was configured
+    expected_cfg["lifecycle-hooks"] = {"hooks": []}
+
+    # SMBIOS was not configured
+    expected_cfg["smbios"] = None
+
+    # No TPM device was configured
+    expected_cfg["tpm"] = None
+    expected_cfg["pvpanic"] = None
+
+    # The vCPUs were not throttled
+    expected_cfg["cpu-limiter"] = None
+
     # Validate full vm configuration post-restore.
     response = uvm2.api.vm_config.get().json()
     assert response != setup_cfg
@@ -1209,12 +1236,20 @@ def test_get_full_config(uvm_plain):
         "smt": False,
         "track_dirty_pages": False,
         "huge_pages": "None",
+        "pmu": False,
+        "nested_virt": False,
+        "memory_backend": "anonymous",
     }
     expected_cfg["cpu-config"] = None
     expected_cfg["boot-source"] = {
         "boot_args": "",
         "kernel_image_path": f"/{test_microvm.kernel_file.name}",
         "initrd_path": None,
+        "kernel_image_fd": None,
+        "initrd_fd": None,
+        "firmware_path": None,
+        "kernel_digest": None,
+        "initrd_digest": None,
     }
     expected_cfg["drives"] = [
         {
@@ -1286,6 +1321,25 @@ def test_get_full_config(uvm_plain):
     # We should expect a null entropy device
     expected_cfg["entropy"] = None
 
+    # The serial console is attached to stdio by default
+    expected_cfg["serial"] = {"mode": "stdio"}
+
+    # No shared memory segment was configured
+    expected_cfg["shared-memory"] = []
+
+    # No lifecycle hook was configured
+    expected_cfg["lifecycle-hooks"] = {"hooks": []}
+
+    # SMBIOS was not configured
+    expected_cfg["smbios"] = None
+
+    # No TPM device was configured
+    expected_cfg["tpm"] = None
+    expected_cfg["pvpanic"] = None
+
+    # The vCPUs were not throttled
+    expected_cfg["cpu-limiter"] = None
+
     # Getting full vm configuration should be available pre-boot.
     response = test_microvm.api.vm_config.get()
     assert response.json() == expected_cfg
@@ -2,6 +2,8 @@
 # SPDX-License-Identifier: Apache-2.0
 """Tests that ensure the correctness of the command line parameters."""
 
+import json
+import platform
 import subprocess
 from pathlib import Path
 
@@ -116,3 +118,19 @@ def test_cli_no_params(microvm_factory):
     except subprocess.TimeoutExpired:
         # The good case
         process.kill()
+
+
+def test_cli_dump_cpu_config(microvm_factory):
+    """
+    Test `--dump-cpu-config` prints the CPU configuration as a custom CPU template.
+    """
+
+    fc_binary = microvm_factory.fc_binary_path
+    _, stdout, stderr = check_output([fc_binary, "--dump-cpu-config"])
+    assert stderr == ""
+    cpu_template = json.loads(stdout)
+    if platform.machine() == "x86_64":
+        assert cpu_template["cpuid_modifiers"]
+        assert cpu_template["msr_modifiers"]
+    else:
+        assert cpu_template["reg_modifiers"]
@@ -0,0 +1,39 @@
+# Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+# SPDX-License-Identifier: Apache-2.0
+"""Tests booting aarch64 microVMs from UEFI firmware."""
+
+import pytest
+
+from framework.defs import ARTIFACT_DIR
+from framework.microvm import Serial
+from framework.properties import global_props
+
+# edk2 image built from `ArmVirtPkg/ArmVirtKvmTool.dsc`, see `docs/firmware-boot.md`.
+FIRMWARE = ARTIFACT_DIR / "KVMTOOL_EFI.fd"
+
+pytestmark = [
+    pytest.mark.skipif(
+        global_props.cpu_architecture != "aarch64", reason="Only run in aarch64"
+    ),
+    pytest.mark.skipif(not FIRMWARE.exists(), reason="No firmware artifact"),
+]
+
+
+def test_firmware_boot(microvm_factory):
+    """
+    Test that the edk2 firmware starts and prints its banner on the serial
+    console.
+    """
+    vm = microvm_factory.build()
+    vm.help.enable_console()
+    vm.spawn()
+    # The firmware doesn't boot a guest OS, so there is no memory to monitor.
+    vm.memory_monitor = None
+
+    vm.api.machine_config.put(vcpu_count=1, mem_size_mib=256)
+    vm.api.boot.put(firmware_path=vm.create_jailed_resource(FIRMWARE))
+    vm.start()
+
+    serial = Serial(vm)
+    serial.open()
+    serial.rx("UEFI firmware (version")